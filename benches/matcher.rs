@@ -0,0 +1,63 @@
+use divan::AllocProfiler;
+use rama::http::matcher::{DomainMatcher, DomainSetMatcher, PathMatcher, PathTrie};
+use rama::http::{Body, Request};
+use rama::matcher::Matcher;
+use rama::net::address::Domain;
+use rama::Context;
+
+#[global_allocator]
+static ALLOC: AllocProfiler = AllocProfiler::system();
+
+fn main() {
+    // Run registered benchmarks.
+    divan::main();
+}
+
+const RULES: &[usize] = &[10, 100, 1000, 10000];
+
+fn domains(n: usize) -> impl Iterator<Item = Domain> {
+    (0..n).map(|i| format!("host-{i}.example.com").parse().unwrap())
+}
+
+fn paths(n: usize) -> impl Iterator<Item = String> {
+    (0..n).map(|i| format!("/api/v{}/resource-{i}/:id", i % 3))
+}
+
+fn request(uri: &str) -> Request {
+    Request::builder().uri(uri).body(Body::empty()).unwrap()
+}
+
+#[divan::bench(args = RULES)]
+fn domain_matcher_list(bencher: divan::Bencher, n: usize) {
+    let matchers: Vec<_> = domains(n).map(DomainMatcher::sub).collect();
+    let ctx = Context::default();
+    let req = request("http://www.unknown.example.com/");
+    bencher.bench_local(|| matchers.iter().any(|m| m.matches(None, &ctx, &req)));
+}
+
+#[divan::bench(args = RULES)]
+fn domain_set_matcher(bencher: divan::Bencher, n: usize) {
+    let mut matcher = DomainSetMatcher::new();
+    for domain in domains(n) {
+        matcher.set_sub(domain);
+    }
+    let ctx = Context::default();
+    let req = request("http://www.unknown.example.com/");
+    bencher.bench_local(|| matcher.matches(None, &ctx, &req));
+}
+
+#[divan::bench(args = RULES)]
+fn path_matcher_list(bencher: divan::Bencher, n: usize) {
+    let matchers: Vec<_> = paths(n).map(PathMatcher::new).collect();
+    let ctx = Context::default();
+    let req = request("/api/v1/unknown/42");
+    bencher.bench_local(|| matchers.iter().any(|m| m.matches(None, &ctx, &req)));
+}
+
+#[divan::bench(args = RULES)]
+fn path_trie(bencher: divan::Bencher, n: usize) {
+    let trie: PathTrie<()> = paths(n).map(|path| (path, ())).collect();
+    let ctx = Context::default();
+    let req = request("/api/v1/unknown/42");
+    bencher.bench_local(|| trie.matches(None, &ctx, &req));
+}
@@ -0,0 +1,195 @@
+//! rama certificate generation
+
+use clap::Args;
+use rama::{
+    error::{BoxError, ErrorContext, OpaqueError},
+    net::{address::Host, tls::server::SelfSignedData},
+    tls::boring::{
+        dep::boring::{
+            pkey::{PKey, Private},
+            x509::X509,
+        },
+        server::{SelfSignedGenerator, SelfSignedKeyType},
+    },
+};
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
+use tokio::io::AsyncWriteExt;
+
+#[derive(Debug, Args)]
+/// rama cert generator (self-signed CA and server certificates as PEM files)
+pub struct CliCommandCert {
+    #[arg(long, default_value = "localhost")]
+    /// the common name (CN) of the certificate
+    cn: Host,
+
+    #[arg(long)]
+    /// the organisation name of the certificate
+    org: Option<String>,
+
+    #[arg(long)]
+    /// subject alternative name (SAN) to add to the certificate, can be repeated
+    san: Vec<String>,
+
+    #[arg(long, short = 'd', default_value_t = 90)]
+    /// the number of days the certificate(s) remain valid
+    days: u64,
+
+    #[arg(long, default_value_t = SelfSignedKeyType::Rsa4096)]
+    /// the type of private key to generate
+    ///
+    /// Supported: rsa2048, rsa4096, ecdsa-p256, ecdsa-p384
+    key_type: SelfSignedKeyType,
+
+    #[arg(long)]
+    /// only generate a (self-signed) CA, e.g. to be used by a MITM proxy
+    ca: bool,
+
+    #[arg(long, requires = "ca_key", conflicts_with = "ca")]
+    /// path to an existing CA certificate (PEM) used to sign the server certificate
+    ///
+    /// A new CA is generated (and written) if not defined.
+    ca_cert: Option<PathBuf>,
+
+    #[arg(long, requires = "ca_cert", conflicts_with = "ca")]
+    /// path to the private key (PEM) of the existing CA certificate
+    ca_key: Option<PathBuf>,
+
+    #[arg(long, short = 'o', default_value = ".")]
+    /// the directory to write the PEM files to
+    out: PathBuf,
+
+    #[arg(long, short = 'n', default_value = "rama")]
+    /// the name used as prefix for the written files
+    ///
+    /// e.g. `rama.crt.pem` and `rama.key.pem`,
+    /// and `rama-ca.crt.pem` and `rama-ca.key.pem` for a generated CA
+    name: String,
+
+    #[arg(long, short = 'f')]
+    /// overwrite existing files
+    force: bool,
+}
+
+/// Run the rama cert command.
+pub async fn run(cfg: CliCommandCert) -> Result<(), BoxError> {
+    let generator = SelfSignedGenerator::new(SelfSignedData {
+        organisation_name: cfg.org.clone(),
+        common_name: Some(cfg.cn.clone()),
+        subject_alternative_names: (!cfg.san.is_empty()).then(|| cfg.san.clone()),
+    })
+    .key_type(cfg.key_type)
+    .validity(Duration::from_secs(cfg.days * 24 * 60 * 60));
+
+    // generate everything before writing any file,
+    // such that a failure does not leave a partial set of files behind
+    let mut pairs = Vec::with_capacity(2);
+
+    let (ca_cert, ca_key) = match (cfg.ca_cert.as_deref(), cfg.ca_key.as_deref()) {
+        (Some(cert_path), Some(key_path)) => load_ca(cert_path, key_path).await?,
+        _ => {
+            let (ca_cert, ca_key) = generator.generate_ca().context("generate CA")?;
+            let name = if cfg.ca {
+                cfg.name.clone()
+            } else {
+                format!("{}-ca", cfg.name)
+            };
+            pairs.push((name, ca_cert.clone(), ca_key.clone()));
+            (ca_cert, ca_key)
+        }
+    };
+
+    if !cfg.ca {
+        let (cert, key) = generator
+            .generate_cert(&ca_cert, &ca_key)
+            .context("generate server certificate")?;
+        pairs.push((cfg.name.clone(), cert, key));
+    }
+
+    tokio::fs::create_dir_all(&cfg.out)
+        .await
+        .context("create output directory")?;
+
+    let mut written = Vec::new();
+    for (name, cert, key) in &pairs {
+        written.extend(write_pair(&cfg, name, cert, key).await?);
+    }
+
+    let mut output = String::new();
+    for path in written {
+        output.push_str(&format!("{}\n", path.display()));
+    }
+
+    let mut stdout = tokio::io::stdout();
+    stdout.write_all(output.as_bytes()).await?;
+    stdout.flush().await?;
+
+    Ok(())
+}
+
+async fn load_ca(cert_path: &Path, key_path: &Path) -> Result<(X509, PKey<Private>), OpaqueError> {
+    let cert_pem = tokio::fs::read(cert_path)
+        .await
+        .context("read CA certificate file")?;
+    let key_pem = tokio::fs::read(key_path)
+        .await
+        .context("read CA private key file")?;
+    let cert = X509::from_pem(&cert_pem).context("parse CA certificate from PEM")?;
+    let key = PKey::private_key_from_pem(&key_pem).context("parse CA private key from PEM")?;
+    Ok((cert, key))
+}
+
+async fn write_pair(
+    cfg: &CliCommandCert,
+    name: &str,
+    cert: &X509,
+    key: &PKey<Private>,
+) -> Result<[PathBuf; 2], OpaqueError> {
+    let cert_path = cfg.out.join(format!("{name}.crt.pem"));
+    let key_path = cfg.out.join(format!("{name}.key.pem"));
+
+    let cert_pem = cert.to_pem().context("encode certificate as PEM")?;
+    let key_pem = key
+        .private_key_to_pem_pkcs8()
+        .context("encode private key as PEM")?;
+
+    write_file(&cert_path, &cert_pem, cfg.force, false).await?;
+    write_file(&key_path, &key_pem, cfg.force, true).await?;
+
+    Ok([cert_path, key_path])
+}
+
+async fn write_file(
+    path: &Path,
+    data: &[u8],
+    force: bool,
+    private: bool,
+) -> Result<(), OpaqueError> {
+    let mut options = tokio::fs::OpenOptions::new();
+    options.write(true);
+    if force {
+        options.create(true).truncate(true);
+    } else {
+        options.create_new(true);
+    }
+    #[cfg(unix)]
+    if private {
+        options.mode(0o600);
+    }
+    #[cfg(not(unix))]
+    let _ = private;
+
+    let mut file = options
+        .open(path)
+        .await
+        .with_context(|| format!("open file '{}' (use --force to overwrite)", path.display()))?;
+    file.write_all(data)
+        .await
+        .with_context(|| format!("write file '{}'", path.display()))?;
+    file.flush()
+        .await
+        .with_context(|| format!("flush file '{}'", path.display()))?;
+    Ok(())
+}
@@ -126,6 +126,13 @@ pub(super) struct RequestInfo {
     pub(super) path: String,
     pub(super) uri: String,
     pub(super) peer_addr: Option<String>,
+    pub(super) client_port: Option<u16>,
+    pub(super) tcp_info: Option<TcpDisplayInfo>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(super) struct TcpDisplayInfo {
+    pub(super) mss: Option<u32>,
 }
 
 pub(super) async fn get_user_agent_info(ctx: &Context<Arc<State>>) -> UserAgentInfo {
@@ -176,6 +183,14 @@ pub(super) async fn get_request_info(
                     .or_else(|| f.client_ip().map(|ip| ip.to_string()))
             })
             .or_else(|| ctx.get::<SocketInfo>().map(|v| v.peer_addr().to_string())),
+        client_port: ctx
+            .get::<Forwarded>()
+            .and_then(|f| f.client_socket_addr().map(|addr| addr.port()))
+            .or_else(|| ctx.get::<SocketInfo>().map(|v| v.peer_addr().port())),
+        tcp_info: ctx
+            .get::<SocketInfo>()
+            .and_then(|v| v.tcp_info())
+            .map(|info| TcpDisplayInfo { mss: info.mss() }),
     })
 }
 
@@ -1,7 +1,7 @@
 use super::{
     data::{
         get_http_info, get_request_info, get_tls_display_info, get_user_agent_info, DataSource,
-        FetchMode, Initiator, RequestInfo, ResourceType, TlsDisplayInfo, UserAgentInfo,
+        FetchMode, HttpInfo, Initiator, RequestInfo, ResourceType, TlsDisplayInfo, UserAgentInfo,
     },
     State,
 };
@@ -97,6 +97,17 @@ pub(super) async fn get_report(
 
     let head = r#"<script src="/assets/script.js"></script>"#.to_owned();
 
+    let tls_info = get_tls_display_info(&ctx);
+
+    let profile_id = store_profile(
+        &ctx,
+        &user_agent_info,
+        &request_info,
+        &http_info,
+        tls_info.as_ref(),
+    )
+    .await;
+
     let mut tables = vec![
         ctx.state().data_source.clone().into(),
         user_agent_info.into(),
@@ -107,7 +118,10 @@ pub(super) async fn get_report(
         },
     ];
 
-    let tls_info = get_tls_display_info(&ctx);
+    if let Some(profile_id) = profile_id {
+        tables.insert(1, profile_table(profile_id));
+    }
+
     if let Some(tls_info) = tls_info {
         let mut tls_tables = tls_info.into();
         tables.append(&mut tls_tables);
@@ -330,6 +344,17 @@ pub(super) async fn form(mut ctx: Context<Arc<State>>, req: Request) -> Result<H
         );
     }
 
+    let tls_info = get_tls_display_info(&ctx);
+
+    let profile_id = store_profile(
+        &ctx,
+        &user_agent_info,
+        &request_info,
+        &http_info,
+        tls_info.as_ref(),
+    )
+    .await;
+
     let mut tables = vec![
         ctx.state().data_source.clone().into(),
         user_agent_info.into(),
@@ -340,7 +365,10 @@ pub(super) async fn form(mut ctx: Context<Arc<State>>, req: Request) -> Result<H
         },
     ];
 
-    let tls_info = get_tls_display_info(&ctx);
+    if let Some(profile_id) = profile_id {
+        tables.insert(1, profile_table(profile_id));
+    }
+
     if let Some(tls_info) = tls_info {
         let mut tls_tables = tls_info.into();
         tables.append(&mut tls_tables);
@@ -354,6 +382,83 @@ pub(super) async fn form(mut ctx: Context<Arc<State>>, req: Request) -> Result<H
     ))
 }
 
+//------------------------------------------
+// endpoints: storage
+//------------------------------------------
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(super) struct ProfileParams {
+    id: String,
+}
+
+pub(super) async fn get_api_profile(
+    Path(params): Path<ProfileParams>,
+    ctx: Context<Arc<State>>,
+) -> Response {
+    let storage = match ctx.state().storage.as_ref() {
+        Some(storage) => storage,
+        None => {
+            return (
+                StatusCode::NOT_FOUND,
+                Json(json!({"error": "fingerprint storage is not enabled"})),
+            )
+                .into_response()
+        }
+    };
+
+    match storage.load_profile(&params.id).await {
+        Ok(Some(profile)) => Json(profile).into_response(),
+        Ok(None) => (
+            StatusCode::NOT_FOUND,
+            Json(json!({"error": "fingerprint profile not found"})),
+        )
+            .into_response(),
+        Err(err) => {
+            tracing::error!(error = %err, id = %params.id, "failed to load fingerprint profile");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": "failed to load fingerprint profile"})),
+            )
+                .into_response()
+        }
+    }
+}
+
+/// Store the fingerprint profile if storage is enabled,
+/// returning the id of the stored profile on success.
+async fn store_profile(
+    ctx: &Context<Arc<State>>,
+    user_agent_info: &UserAgentInfo,
+    request_info: &RequestInfo,
+    http_info: &HttpInfo,
+    tls_info: Option<&TlsDisplayInfo>,
+) -> Option<String> {
+    let storage = ctx.state().storage.as_ref()?;
+    match storage
+        .store_profile(user_agent_info, request_info, http_info, tls_info)
+        .await
+    {
+        Ok(id) => Some(id),
+        Err(err) => {
+            tracing::error!(error = %err, "failed to store fingerprint profile");
+            None
+        }
+    }
+}
+
+fn profile_table(profile_id: String) -> Table {
+    Table {
+        title: "🗄️ Stored Profile".to_owned(),
+        rows: vec![
+            ("ID".to_owned(), profile_id.clone()),
+            (
+                "JSON".to_owned(),
+                format!(r##"<a href="/api/profile/{profile_id}">/api/profile/{profile_id}</a>"##),
+            ),
+        ],
+    }
+}
+
 //------------------------------------------
 // endpoints: assets
 //------------------------------------------
@@ -522,11 +627,23 @@ impl From<RequestInfo> for Table {
                     "Socket Address".to_owned(),
                     info.peer_addr.unwrap_or_default(),
                 ),
+                (
+                    "Client Port".to_owned(),
+                    info.client_port.map(|v| v.to_string()).unwrap_or_default(),
+                ),
+                (
+                    "TCP MSS".to_owned(),
+                    tcp_value(info.tcp_info.as_ref().and_then(|v| v.mss)),
+                ),
             ],
         }
     }
 }
 
+fn tcp_value(value: Option<impl ToString>) -> String {
+    value.map(|v| v.to_string()).unwrap_or_default()
+}
+
 impl From<DataSource> for Table {
     fn from(data_source: DataSource) -> Self {
         Self {
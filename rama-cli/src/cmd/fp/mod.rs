@@ -45,6 +45,7 @@ use tracing_subscriber::{fmt, layer::SubscriberExt, util::SubscriberInitExt, Env
 mod data;
 mod endpoints;
 mod state;
+mod storage;
 
 #[doc(inline)]
 use state::State;
@@ -95,6 +96,12 @@ pub struct CliCommandFingerprint {
     #[arg(long, short = 's')]
     /// run echo service in secure mode (enable TLS)
     secure: bool,
+
+    #[arg(long)]
+    /// optional SQLite database url used to persist fingerprints (e.g. `sqlite://fp.db`)
+    ///
+    /// Stored fingerprints can be fetched as JSON via `/api/profile/:id`.
+    storage: Option<String>,
 }
 
 /// run the rama FP service
@@ -198,6 +205,11 @@ pub async fn run(cfg: CliCommandFingerprint) -> Result<(), BoxError> {
         Some(cfg) => Some(cfg.try_into()?),
     };
 
+    let storage = match cfg.storage.as_deref() {
+        None => None,
+        Some(url) => Some(storage::Storage::connect(url).await?),
+    };
+
     let address = format!("{}:{}", cfg.interface, cfg.port);
     let ch_headers = [
         "Width",
@@ -275,6 +287,8 @@ pub async fn run(cfg: CliCommandFingerprint) -> Result<(), BoxError> {
                     // Assets
                     HttpMatcher::get("/assets/style.css") => endpoints::get_assets_style,
                     HttpMatcher::get("/assets/script.js") => endpoints::get_assets_script,
+                    // Storage
+                    HttpMatcher::get("/api/profile/:id") => endpoints::get_api_profile,
                     // Fingerprinting Endpoints
                     _ => inner_http_service,
                 })
@@ -295,7 +309,7 @@ pub async fn run(cfg: CliCommandFingerprint) -> Result<(), BoxError> {
             })
         );
 
-        let tcp_listener = TcpListener::build_with_state(Arc::new(State::new(acme_data)))
+        let tcp_listener = TcpListener::build_with_state(Arc::new(State::new(acme_data, storage)))
             .bind(&address)
             .await
             .expect("bind TCP Listener");
@@ -1,6 +1,6 @@
 use std::{collections::HashMap, sync::atomic::AtomicUsize};
 
-use super::data::DataSource;
+use super::{data::DataSource, storage::Storage};
 
 #[derive(Debug)]
 #[non_exhaustive]
@@ -8,15 +8,17 @@ pub(super) struct State {
     pub(super) data_source: DataSource,
     pub(super) counter: AtomicUsize,
     pub(super) acme: ACMEData,
+    pub(super) storage: Option<Storage>,
 }
 
 impl State {
     /// Create a new instance of [`State`].
-    pub(super) fn new(acme: ACMEData) -> Self {
+    pub(super) fn new(acme: ACMEData, storage: Option<Storage>) -> Self {
         State {
             data_source: DataSource::default(),
             counter: AtomicUsize::new(0),
             acme,
+            storage,
         }
     }
 }
@@ -0,0 +1,136 @@
+use super::data::{HttpInfo, RequestInfo, TlsDisplayInfo, UserAgentInfo};
+use rama::error::{BoxError, ErrorContext};
+use serde::Serialize;
+use sqlx::{
+    sqlite::{SqliteConnectOptions, SqlitePoolOptions},
+    Row, SqlitePool,
+};
+use std::{
+    str::FromStr,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+const CREATE_TABLE_SQL: &str = r##"CREATE TABLE IF NOT EXISTS fingerprints (
+    id TEXT PRIMARY KEY NOT NULL,
+    created_at INTEGER NOT NULL,
+    http_version TEXT NOT NULL,
+    user_agent TEXT NOT NULL,
+    user_agent_info TEXT NOT NULL,
+    request_info TEXT NOT NULL,
+    http_info TEXT NOT NULL,
+    tls_info TEXT
+)"##;
+
+/// Optional storage backend used to persist fingerprints collected by the FP service.
+#[derive(Debug, Clone)]
+pub(super) struct Storage {
+    pool: SqlitePool,
+}
+
+/// A fingerprint profile as stored for a single visit.
+#[derive(Debug, Clone, Serialize)]
+pub(super) struct StoredProfile {
+    pub(super) id: String,
+    pub(super) created_at: u64,
+    pub(super) user_agent_info: serde_json::Value,
+    pub(super) request_info: serde_json::Value,
+    pub(super) http_info: serde_json::Value,
+    pub(super) tls_info: Option<serde_json::Value>,
+}
+
+impl Storage {
+    /// Connect to the SQLite database found at the given url,
+    /// creating the database and its schema if they do not exist yet.
+    pub(super) async fn connect(url: &str) -> Result<Self, BoxError> {
+        let options = SqliteConnectOptions::from_str(url)
+            .context("parse sqlite storage url")?
+            .create_if_missing(true);
+        let pool = SqlitePoolOptions::new()
+            .max_connections(8)
+            .connect_with(options)
+            .await
+            .context("connect to sqlite storage")?;
+        sqlx::query(CREATE_TABLE_SQL)
+            .execute(&pool)
+            .await
+            .context("create fingerprints table")?;
+        Ok(Self { pool })
+    }
+
+    /// Store the fingerprint of a single visit,
+    /// returning the generated id by which it can be queried.
+    pub(super) async fn store_profile(
+        &self,
+        user_agent_info: &UserAgentInfo,
+        request_info: &RequestInfo,
+        http_info: &HttpInfo,
+        tls_info: Option<&TlsDisplayInfo>,
+    ) -> Result<String, BoxError> {
+        let id = uuid::Uuid::new_v4().to_string();
+        let created_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+
+        let tls_info = tls_info
+            .map(serde_json::to_string)
+            .transpose()
+            .context("serialize tls info")?;
+
+        sqlx::query(
+            r##"INSERT INTO fingerprints
+                (id, created_at, http_version, user_agent, user_agent_info, request_info, http_info, tls_info)
+                VALUES (?, ?, ?, ?, ?, ?, ?, ?)"##,
+        )
+        .bind(&id)
+        .bind(created_at as i64)
+        .bind(&request_info.version)
+        .bind(&user_agent_info.user_agent)
+        .bind(serde_json::to_string(user_agent_info).context("serialize user agent info")?)
+        .bind(serde_json::to_string(request_info).context("serialize request info")?)
+        .bind(serde_json::to_string(http_info).context("serialize http info")?)
+        .bind(tls_info)
+        .execute(&self.pool)
+        .await
+        .context("insert fingerprint")?;
+
+        Ok(id)
+    }
+
+    /// Load a previously stored fingerprint profile by its id.
+    pub(super) async fn load_profile(&self, id: &str) -> Result<Option<StoredProfile>, BoxError> {
+        let row = sqlx::query(
+            r##"SELECT id, created_at, user_agent_info, request_info, http_info, tls_info
+                FROM fingerprints WHERE id = ?"##,
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await
+        .context("select fingerprint")?;
+
+        let row = match row {
+            Some(row) => row,
+            None => return Ok(None),
+        };
+
+        let parse = |column: &str| -> Result<serde_json::Value, BoxError> {
+            let raw: String = row.try_get(column).context("get fingerprint column")?;
+            Ok(serde_json::from_str(&raw).context("parse fingerprint column")?)
+        };
+
+        let tls_info: Option<String> = row.try_get("tls_info").context("get tls_info column")?;
+        let created_at: i64 = row.try_get("created_at").context("get created_at column")?;
+
+        Ok(Some(StoredProfile {
+            id: row.try_get("id").context("get id column")?,
+            created_at: created_at as u64,
+            user_agent_info: parse("user_agent_info")?,
+            request_info: parse("request_info")?,
+            http_info: parse("http_info")?,
+            tls_info: tls_info
+                .map(|raw| serde_json::from_str(&raw))
+                .transpose()
+                .context("parse tls_info column")?,
+        }))
+    }
+}
@@ -2,7 +2,7 @@
 
 use clap::Args;
 use rama::{
-    cli::args::RequestArgsBuilder,
+    cli::args::{RequestArgsBuilder, UrlMode},
     error::{error, BoxError, ErrorContext, OpaqueError},
     graceful::{self, Shutdown, ShutdownGuard},
     http::{
@@ -16,9 +16,9 @@ use rama::{
             follow_redirect::{policy::Limited, FollowRedirectLayer},
             required_header::AddRequiredRequestHeadersLayer,
             timeout::TimeoutLayer,
-            traffic_writer::WriterMode,
+            traffic_writer::{DoNotWriteRequest, DoNotWriteResponse, WriterMode},
         },
-        IntoResponse, Request, Response, StatusCode,
+        Request, Response, Version,
     },
     layer::{HijackLayer, MapResultLayer},
     net::{
@@ -46,7 +46,7 @@ mod writer;
 #[derive(Args, Debug, Clone)]
 /// rama http client
 pub struct CliCommandHttp {
-    #[arg(short = 'j', long)]
+    #[arg(short = 'j', long, conflicts_with = "form")]
     /// data items from the command line are serialized as a JSON object.
     /// The `Content-Type` and `Accept headers` are set to `application/json`
     /// (if not specified)
@@ -89,6 +89,10 @@ pub struct CliCommandHttp {
     /// skip Tls certificate verification
     insecure: bool,
 
+    #[arg(long, conflicts_with = "proxy")]
+    /// send the request over HTTP/3 (QUIC), which requires an https url
+    http3: bool,
+
     #[arg(long)]
     /// the desired tls version to use (automatically defined by default, choices are: 1.2, 1.3)
     tls: Option<String>,
@@ -105,7 +109,7 @@ pub struct CliCommandHttp {
     /// fail if status code is not 2xx (4 if 4xx and 5 if 5xx)
     check_status: bool,
 
-    #[arg(long, short = 'p')]
+    #[arg(long, short = 'p', conflicts_with_all = ["body", "headers"])]
     /// define what the output should contain ('h'/'H' for headers, 'b'/'B' for body (response/request)
     print: Option<String>,
 
@@ -117,7 +121,7 @@ pub struct CliCommandHttp {
     /// print the response headers (short for --print h)
     headers: bool,
 
-    #[arg(short = 'v', long)]
+    #[arg(short = 'v', long, conflicts_with_all = ["print", "body", "headers"])]
     /// print verbose output, alias for --all --print hHbB (not used in offline mode)
     verbose: bool,
 
@@ -125,14 +129,21 @@ pub struct CliCommandHttp {
     /// show output for all requests/responses (including redirects)
     all: bool,
 
-    #[arg(long)]
+    #[arg(long, conflicts_with_all = ["verbose", "print", "body", "headers", "all"])]
     /// print the request instead of executing it
+    ///
+    /// The request is printed in a canonical format (see `rama::http::io::write_canonical_http_request`),
+    /// after all client layers are applied, such that the output can be diffed.
     offline: bool,
 
     #[arg(long, short = 'o')]
     /// write output to file instead of stdout
     output: Option<String>,
 
+    #[arg(long)]
+    /// require the URL to be an absolute RFC 3986 URI, used as-is
+    strict_url: bool,
+
     #[arg(long)]
     /// print debug info
     debug: bool,
@@ -165,6 +176,10 @@ pub struct CliCommandHttp {
     ///
     ///    $ rama http :/foo    # => http://localhost/foo
     ///
+    /// International domain names are converted to their punycode form,
+    /// and spaces in the path and query are percent-encoded,
+    /// unless `--strict-url` is used.
+    ///
     /// # REQUEST_ITEM
     ///
     /// Optional key-value pairs to be included in the request. The separator used
@@ -258,16 +273,29 @@ async fn run_inner(guard: ShutdownGuard, cfg: CliCommandHttp) -> Result<(), BoxE
     } else {
         RequestArgsBuilder::new()
     };
+    if cfg.strict_url {
+        request_args_builder.set_url_mode(UrlMode::Strict);
+    }
 
     for arg in cfg.args.clone() {
         request_args_builder.parse_arg(arg);
     }
 
-    let request = request_args_builder.build()?;
+    let mut request = request_args_builder.build()?;
+    if cfg.http3 {
+        *request.version_mut() = Version::HTTP_3;
+    }
 
     let client = create_client(guard, cfg.clone()).await?;
 
-    let response = client.serve(Context::default(), request).await?;
+    let mut ctx = Context::default();
+    if cfg.offline {
+        // the final request is written by the offline service instead
+        ctx.insert(DoNotWriteRequest::new());
+        ctx.insert(DoNotWriteResponse::new());
+    }
+
+    let response = client.serve(ctx, request).await?;
 
     if cfg.check_status {
         let status = response.status();
@@ -297,7 +325,7 @@ where
     S: Clone + Send + Sync + 'static,
 {
     let (request_writer_mode, response_writer_mode) = if cfg.offline {
-        (Some(WriterMode::All), None)
+        (None, None)
     } else if cfg.verbose {
         cfg.all = true;
         (Some(WriterMode::All), Some(WriterMode::All))
@@ -332,7 +360,7 @@ where
     let executor = Executor::graceful(guard);
     let (request_writer, response_writer) = writer::create_traffic_writers(
         &executor,
-        writer_kind,
+        writer_kind.clone(),
         cfg.all,
         request_writer_mode,
         response_writer_mode,
@@ -414,7 +442,10 @@ where
             }
         },
         SetProxyAuthHttpHeaderLayer::default(),
-        HijackLayer::new(cfg.offline, service_fn(dummy_response)),
+        HijackLayer::new(
+            cfg.offline,
+            service_fn(move |req: Request| writer::write_offline_request(writer_kind.clone(), req)),
+        ),
     );
 
     Ok(client_builder.layer(inner_client))
@@ -469,10 +500,6 @@ fn parse_print_mode(mode: &str) -> Result<(Option<WriterMode>, Option<WriterMode
     Ok((request_mode, response_mode))
 }
 
-async fn dummy_response<S, Request>(_ctx: Context<S>, _req: Request) -> Result<Response, BoxError> {
-    Ok(StatusCode::OK.into_response())
-}
-
 fn map_internal_client_error<E, Body>(
     result: Result<Response<Body>, E>,
 ) -> Result<Response, BoxError>
@@ -1,14 +1,22 @@
 use rama::{
     combinators::Either,
     error::BoxError,
-    http::layer::traffic_writer::{
-        BidirectionalMessage, BidirectionalWriter, RequestWriterLayer, ResponseWriterLayer,
-        WriterMode,
+    http::{
+        io::write_canonical_http_request,
+        layer::traffic_writer::{
+            BidirectionalMessage, BidirectionalWriter, RequestWriterLayer, ResponseWriterLayer,
+            WriterMode,
+        },
+        IntoResponse, Request, Response, StatusCode,
     },
     rt::Executor,
 };
 use std::path::PathBuf;
-use tokio::{fs::OpenOptions, io::stdout, sync::mpsc::Sender};
+use tokio::{
+    fs::OpenOptions,
+    io::{stdout, AsyncWriteExt},
+    sync::mpsc::Sender,
+};
 
 #[derive(Debug, Clone)]
 pub(super) enum WriterKind {
@@ -16,6 +24,32 @@ pub(super) enum WriterKind {
     File(PathBuf),
 }
 
+async fn open_writer(
+    kind: WriterKind,
+) -> Result<Either<tokio::io::Stdout, tokio::fs::File>, BoxError> {
+    Ok(match kind {
+        WriterKind::Stdout => Either::A(stdout()),
+        WriterKind::File(path) => Either::B(
+            OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .await?,
+        ),
+    })
+}
+
+/// Write the (final) request in canonical format, in place of sending it.
+pub(super) async fn write_offline_request(
+    kind: WriterKind,
+    req: Request,
+) -> Result<Response, BoxError> {
+    let mut writer = open_writer(kind).await?;
+    write_canonical_http_request(&mut writer, req).await?;
+    writer.flush().await?;
+    Ok(StatusCode::OK.into_response())
+}
+
 pub(super) async fn create_traffic_writers(
     executor: &Executor,
     kind: WriterKind,
@@ -29,16 +63,7 @@ pub(super) async fn create_traffic_writers(
     ),
     BoxError,
 > {
-    let writer = match kind {
-        WriterKind::Stdout => Either::A(stdout()),
-        WriterKind::File(path) => Either::B(
-            OpenOptions::new()
-                .create(true)
-                .append(true)
-                .open(path)
-                .await?,
-        ),
-    };
+    let writer = open_writer(kind).await?;
 
     let bidirectional_writer = if all {
         BidirectionalWriter::new(executor, writer, 32, request_mode, response_mode)
@@ -12,6 +12,8 @@ use std::time::Duration;
 use tracing::level_filters::LevelFilter;
 use tracing_subscriber::{fmt, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
 
+mod query;
+
 #[derive(Debug, Args)]
 /// rama ip service (returns the ip address of the client)
 pub struct CliCommandIp {
@@ -30,7 +32,7 @@ pub struct CliCommandIp {
     concurrent: usize,
 
     #[arg(long, short = 't', default_value = "8")]
-    /// the timeout in seconds for each connection
+    /// the timeout in seconds for each connection (or the query in query mode)
     ///
     /// (0 = default timeout of 30s)
     timeout: u64,
@@ -56,6 +58,31 @@ pub struct CliCommandIp {
     #[arg(long, short = 'T')]
     /// operate the IP service on transport layer (tcp)
     transport: bool,
+
+    #[arg(long, short = 'q', conflicts_with_all = ["ha_proxy", "forward", "transport"])]
+    /// report the public IP and local interface addresses of this machine,
+    /// instead of running the ip service
+    query: bool,
+
+    #[arg(long, default_value = "https://icanhazip.com")]
+    /// the ip echo endpoint used to query the public IP (only used in query mode)
+    ///
+    /// It is expected to respond with the IP of the client as plain text,
+    /// such as the rama ip service does.
+    endpoint: String,
+
+    #[arg(long, short = 'P', requires = "query")]
+    /// upstream proxy to query the public IP through, to verify its egress IP
+    /// (only used in query mode, can also be specified using PROXY env variable)
+    proxy: Option<String>,
+
+    #[arg(long, short = 'U', requires = "query")]
+    /// upstream proxy user credentials to use (or overwrite)
+    proxy_user: Option<String>,
+
+    #[arg(short = 'k', long, requires = "query")]
+    /// skip Tls certificate verification (only used in query mode)
+    insecure: bool,
 }
 
 /// run the rama ip service
@@ -64,11 +91,22 @@ pub async fn run(cfg: CliCommandIp) -> Result<(), BoxError> {
         .with(fmt::layer())
         .with(
             EnvFilter::builder()
-                .with_default_directive(LevelFilter::INFO.into())
+                .with_default_directive(
+                    if cfg.query {
+                        LevelFilter::ERROR
+                    } else {
+                        LevelFilter::INFO
+                    }
+                    .into(),
+                )
                 .from_env_lossy(),
         )
         .init();
 
+    if cfg.query {
+        return query::run(cfg).await;
+    }
+
     let graceful = rama::graceful::Shutdown::default();
 
     let tcp_service = if cfg.transport {
@@ -0,0 +1,121 @@
+use super::CliCommandIp;
+use rama::{
+    error::{BoxError, ErrorContext, OpaqueError},
+    http::{
+        client::{
+            proxy::layer::{HttpProxyAddressLayer, SetProxyAuthHttpHeaderLayer},
+            HttpClient,
+        },
+        layer::{required_header::AddRequiredRequestHeadersLayer, timeout::TimeoutLayer},
+        Body, BodyExtractExt, Request,
+    },
+    net::{
+        address::ProxyAddress,
+        tls::client::{ClientConfig, ServerVerifyMode},
+        user::ProxyCredential,
+    },
+    Context, Layer, Service,
+};
+use std::{net::IpAddr, time::Duration};
+use tokio::io::AsyncWriteExt;
+
+/// Report the public IP of this machine (as seen by the echo endpoint),
+/// optionally egressing via a proxy, together with the local interface addresses.
+pub(super) async fn run(cfg: CliCommandIp) -> Result<(), BoxError> {
+    let public_ip = query_public_ip(&cfg).await?;
+
+    let mut output = format!("public ip: {public_ip}\n");
+    if let Some(proxy) = cfg.proxy.as_deref() {
+        output.push_str(&format!("proxy: {proxy}\n"));
+    }
+
+    output.push_str("local addresses:\n");
+    let mut interfaces = if_addrs::get_if_addrs().context("list local network interfaces")?;
+    interfaces.sort_by(|a, b| a.name.cmp(&b.name).then_with(|| a.ip().cmp(&b.ip())));
+    for interface in interfaces {
+        output.push_str(&format!(
+            "  {}: {}{}\n",
+            interface.name,
+            interface.ip(),
+            if interface.is_loopback() {
+                " (loopback)"
+            } else {
+                ""
+            },
+        ));
+    }
+
+    let mut stdout = tokio::io::stdout();
+    stdout.write_all(output.as_bytes()).await?;
+    stdout.flush().await?;
+
+    Ok(())
+}
+
+async fn query_public_ip(cfg: &CliCommandIp) -> Result<IpAddr, BoxError> {
+    let mut inner_client = HttpClient::default();
+
+    if cfg.insecure {
+        inner_client.set_tls_config(ClientConfig {
+            server_verify_mode: Some(ServerVerifyMode::Disable),
+            ..Default::default()
+        });
+        inner_client.set_proxy_tls_config(ClientConfig {
+            server_verify_mode: Some(ServerVerifyMode::Disable),
+            ..Default::default()
+        });
+    }
+
+    let client = (
+        TimeoutLayer::new(if cfg.timeout > 0 {
+            Duration::from_secs(cfg.timeout)
+        } else {
+            Duration::from_secs(30)
+        }),
+        AddRequiredRequestHeadersLayer::default(),
+        match cfg.proxy.as_deref() {
+            None => HttpProxyAddressLayer::try_from_env_default()?,
+            Some(proxy) => {
+                let mut proxy_address: ProxyAddress =
+                    proxy.parse().context("parse proxy address")?;
+                if let Some(proxy_user) = cfg.proxy_user.clone() {
+                    let credential = ProxyCredential::try_from_clear_str(proxy_user)
+                        .context("parse proxy credentials")?;
+                    proxy_address.credential = Some(credential);
+                }
+                HttpProxyAddressLayer::maybe(Some(proxy_address))
+            }
+        },
+        SetProxyAuthHttpHeaderLayer::default(),
+    )
+        .layer(inner_client);
+
+    let request = Request::builder()
+        .uri(cfg.endpoint.as_str())
+        .body(Body::empty())
+        .context("build ip echo request")?;
+
+    let response = client
+        .serve(Context::default(), request)
+        .await
+        .map_err(OpaqueError::from_boxed)
+        .context("query public ip")?;
+
+    if !response.status().is_success() {
+        return Err(OpaqueError::from_display(format!(
+            "unexpected status from ip echo endpoint: {}",
+            response.status()
+        ))
+        .into());
+    }
+
+    let body = response
+        .try_into_string()
+        .await
+        .context("read ip echo response")?;
+    let ip = body
+        .trim()
+        .parse()
+        .with_context(|| format!("parse ip echo response as ip address: '{}'", body.trim()))?;
+    Ok(ip)
+}
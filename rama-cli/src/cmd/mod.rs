@@ -1,7 +1,12 @@
 //! rama cli subcommands
 
+pub mod cert;
 pub mod echo;
 pub mod fp;
 pub mod http;
 pub mod ip;
 pub mod proxy;
+pub mod reverse_proxy;
+pub mod speed;
+pub mod tcp;
+pub mod top;
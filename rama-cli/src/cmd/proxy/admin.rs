@@ -0,0 +1,160 @@
+//! admin api of the rama proxy, as monitored by `rama top`
+
+use rama::{
+    http::{
+        layer::traffic_stats::{HostTrafficStats, TrafficStats},
+        response::Json,
+        IntoResponse, Request, Response,
+    },
+    Context, Layer, Service,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    convert::Infallible,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Instant,
+};
+
+/// Path on which the [`AdminStats`] are served.
+pub const STATS_PATH: &str = "/stats";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Live statistics of a running rama proxy.
+pub struct AdminStats {
+    /// time since the proxy was started
+    pub uptime_secs: u64,
+    /// amount of (tcp) connections currently being served
+    pub active_connections: u64,
+    /// amount of (tcp) connections served since the proxy was started
+    pub total_connections: u64,
+    /// amount of http requests served since the proxy was started
+    pub total_requests: u64,
+    /// traffic per destination host, within the sliding window of the last hour
+    pub hosts: Vec<HostTrafficStats>,
+}
+
+#[derive(Debug, Clone)]
+/// State shared between the proxy and its admin api.
+pub(super) struct AdminState {
+    started: Instant,
+    connections: Counter,
+    requests: Counter,
+    traffic: TrafficStats,
+}
+
+impl AdminState {
+    pub(super) fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            connections: Counter::default(),
+            requests: Counter::default(),
+            traffic: TrafficStats::default(),
+        }
+    }
+
+    pub(super) fn traffic(&self) -> &TrafficStats {
+        &self.traffic
+    }
+
+    /// layer counting the (tcp) connections
+    pub(super) fn connections_layer(&self) -> CountLayer {
+        CountLayer(self.connections.clone())
+    }
+
+    /// layer counting the http requests
+    pub(super) fn requests_layer(&self) -> CountLayer {
+        CountLayer(self.requests.clone())
+    }
+
+    fn stats(&self) -> AdminStats {
+        AdminStats {
+            uptime_secs: self.started.elapsed().as_secs(),
+            active_connections: self.connections.0.active.load(Ordering::Relaxed),
+            total_connections: self.connections.0.total.load(Ordering::Relaxed),
+            total_requests: self.requests.0.total.load(Ordering::Relaxed),
+            hosts: self.traffic.snapshot(),
+        }
+    }
+}
+
+impl<State> Service<State, Request> for AdminState
+where
+    State: Clone + Send + Sync + 'static,
+{
+    type Response = Response;
+    type Error = Infallible;
+
+    async fn serve(
+        &self,
+        _ctx: Context<State>,
+        _req: Request,
+    ) -> Result<Self::Response, Self::Error> {
+        Ok(Json(self.stats()).into_response())
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct Counter(Arc<CounterInner>);
+
+#[derive(Debug, Default)]
+struct CounterInner {
+    active: AtomicU64,
+    total: AtomicU64,
+}
+
+/// Decrements the active count once dropped.
+struct CounterGuard(Counter);
+
+impl Counter {
+    fn start(&self) -> CounterGuard {
+        self.0.active.fetch_add(1, Ordering::Relaxed);
+        self.0.total.fetch_add(1, Ordering::Relaxed);
+        CounterGuard(self.clone())
+    }
+}
+
+impl Drop for CounterGuard {
+    fn drop(&mut self) {
+        self.0 .0.active.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+#[derive(Debug, Clone)]
+/// Layer counting the inputs being served (e.g. connections or requests).
+pub(super) struct CountLayer(Counter);
+
+impl<S> Layer<S> for CountLayer {
+    type Service = CountService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        CountService {
+            inner,
+            counter: self.0.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+/// Service counting the inputs being served (e.g. connections or requests).
+pub(super) struct CountService<S> {
+    inner: S,
+    counter: Counter,
+}
+
+impl<State, Input, S> Service<State, Input> for CountService<S>
+where
+    State: Clone + Send + Sync + 'static,
+    Input: Send + 'static,
+    S: Service<State, Input>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+
+    async fn serve(&self, ctx: Context<State>, input: Input) -> Result<S::Response, S::Error> {
+        let _guard = self.counter.start();
+        self.inner.serve(ctx, input).await
+    }
+}
@@ -4,14 +4,18 @@ use clap::Args;
 use rama::{
     error::BoxError,
     http::{
-        client::HttpClient,
+        client::{proxy::proxy_status_for_error, HttpClient},
+        headers::{HeaderMapExt, ProxyStatusEntry},
         layer::{
+            map_request_body::MapRequestBodyLayer,
             remove_header::{RemoveRequestHeaderLayer, RemoveResponseHeaderLayer},
             trace::TraceLayer,
+            traffic_stats::{TrafficStats, TrafficStatsLayer},
             upgrade::{UpgradeLayer, Upgraded},
         },
         matcher::MethodMatcher,
         server::HttpServer,
+        service::web::WebService,
         Body, IntoResponse, Request, Response, StatusCode,
     },
     layer::{limit::policy::ConcurrentPolicy, LimitLayer, TimeoutLayer},
@@ -26,6 +30,9 @@ use std::{convert::Infallible, time::Duration};
 use tracing::level_filters::LevelFilter;
 use tracing_subscriber::{fmt, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
 
+pub mod admin;
+use admin::AdminState;
+
 #[derive(Debug, Args)]
 /// rama proxy server
 pub struct CliCommandProxy {
@@ -44,6 +51,11 @@ pub struct CliCommandProxy {
     #[arg(long, short = 't', default_value_t = 8)]
     /// the timeout in seconds for each connection (0 = no timeout)
     timeout: u64,
+
+    #[arg(long)]
+    /// the address (e.g. 127.0.0.1:8081) to serve the admin api on,
+    /// as used by `rama top` to monitor the proxy (disabled by default)
+    admin: Option<String>,
 }
 
 /// run the rama proxy service
@@ -59,6 +71,23 @@ pub async fn run(cfg: CliCommandProxy) -> Result<(), BoxError> {
 
     let graceful = rama::graceful::Shutdown::default();
 
+    let admin_state = AdminState::new();
+
+    if let Some(admin_address) = cfg.admin {
+        tracing::info!("starting proxy admin api on: {}", admin_address);
+        let admin_state = admin_state.clone();
+        graceful.spawn_task_fn(move |guard| async move {
+            let exec = Executor::graceful(guard);
+            HttpServer::auto(exec)
+                .listen(
+                    admin_address,
+                    WebService::default().get(admin::STATS_PATH, admin_state),
+                )
+                .await
+                .expect("serve proxy admin api");
+        });
+    }
+
     let address = format!("{}:{}", cfg.interface, cfg.port);
     tracing::info!("starting proxy on: {}", address);
 
@@ -69,13 +98,19 @@ pub async fn run(cfg: CliCommandProxy) -> Result<(), BoxError> {
             .expect("bind proxy to 127.0.0.1:62001");
 
         let exec = Executor::graceful(guard.clone());
+        let traffic = admin_state.traffic().clone();
         let http_service = HttpServer::auto(exec).service(
             (
                 TraceLayer::new_for_http(),
+                admin_state.requests_layer(),
+                TrafficStatsLayer::new(traffic.clone()),
+                MapRequestBodyLayer::new(Body::new),
                 UpgradeLayer::new(
                     MethodMatcher::CONNECT,
                     service_fn(http_connect_accept),
-                    service_fn(http_connect_proxy),
+                    service_fn(move |ctx, upgraded| {
+                        http_connect_proxy(ctx, upgraded, traffic.clone())
+                    }),
                 ),
                 RemoveResponseHeaderLayer::hop_by_hop(),
                 RemoveRequestHeaderLayer::hop_by_hop(),
@@ -84,6 +119,7 @@ pub async fn run(cfg: CliCommandProxy) -> Result<(), BoxError> {
         );
 
         let tcp_service_builder = (
+            admin_state.connections_layer(),
             // protect the http proxy from too large bodies, both from request and response end
             BodyLimitLayer::symmetric(2 * 1024 * 1024),
             (cfg.concurrent > 0).then(|| LimitLayer::new(ConcurrentPolicy::max(cfg.concurrent))),
@@ -120,7 +156,11 @@ where
     Ok((StatusCode::OK.into_response(), ctx, req))
 }
 
-async fn http_connect_proxy<S>(ctx: Context<S>, mut upgraded: Upgraded) -> Result<(), Infallible>
+async fn http_connect_proxy<S>(
+    ctx: Context<S>,
+    mut upgraded: Upgraded,
+    traffic: TrafficStats,
+) -> Result<(), Infallible>
 where
     S: Clone + Send + Sync + 'static,
 {
@@ -130,16 +170,21 @@ where
         .authority
         .clone();
     tracing::info!("CONNECT to {authority}");
-    let (mut stream, _) = match default_tcp_connect(&ctx, authority).await {
+    let (mut stream, _) = match default_tcp_connect(&ctx, authority.clone()).await {
         Ok(stream) => stream,
         Err(err) => {
             tracing::error!(error = %err, "error connecting to host");
             return Ok(());
         }
     };
-    if let Err(err) = tokio::io::copy_bidirectional(&mut upgraded, &mut stream).await {
-        if !is_connection_error(&err) {
-            tracing::error!(error = %err, "error copying data");
+    match tokio::io::copy_bidirectional(&mut upgraded, &mut stream).await {
+        Ok((bytes_sent, bytes_received)) => {
+            traffic.record_bytes(&authority.host().to_string(), bytes_sent, bytes_received);
+        }
+        Err(err) => {
+            if !is_connection_error(&err) {
+                tracing::error!(error = %err, "error copying data");
+            }
         }
     }
     Ok(())
@@ -154,10 +199,11 @@ where
         Ok(resp) => Ok(resp),
         Err(err) => {
             tracing::error!(error = %err, "error in client request");
-            Ok(Response::builder()
-                .status(StatusCode::INTERNAL_SERVER_ERROR)
-                .body(Body::empty())
-                .unwrap())
+            let proxy_status =
+                proxy_status_for_error(ProxyStatusEntry::new(rama::utils::info::NAME), &err);
+            let mut resp = StatusCode::BAD_GATEWAY.into_response();
+            resp.headers_mut().typed_insert(proxy_status);
+            Ok(resp)
         }
     }
 }
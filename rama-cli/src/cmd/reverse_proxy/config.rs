@@ -0,0 +1,269 @@
+//! configuration file format of the rama reverse proxy
+
+use rama::{
+    error::{BoxError, ErrorContext, OpaqueError},
+    http::Uri,
+    net::address::{Domain, Host},
+};
+use serde::Deserialize;
+use std::{collections::HashMap, net::SocketAddr, path::Path, path::PathBuf};
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+/// Root of the reverse proxy configuration file.
+pub(super) struct Config {
+    /// the addresses to listen on, optionally terminating TLS
+    pub(super) listeners: Vec<ListenerConfig>,
+
+    /// routes matched in order, forwarding requests to the named upstream
+    pub(super) routes: Vec<RouteConfig>,
+
+    /// upstreams by name
+    pub(super) upstreams: HashMap<String, UpstreamConfig>,
+
+    #[serde(default)]
+    /// the number of concurrent connections to allow per listener (0 = no limit)
+    pub(super) concurrent: usize,
+
+    #[serde(default = "default_timeout")]
+    /// the timeout in seconds for each connection (0 = no timeout)
+    pub(super) timeout: u64,
+
+    #[serde(default = "default_reload_interval")]
+    /// the interval in seconds to check the config and tls files for changes,
+    /// and to re-resolve the endpoints of headless upstreams (0 = never)
+    pub(super) reload_interval: u64,
+}
+
+fn default_timeout() -> u64 {
+    60
+}
+
+fn default_reload_interval() -> u64 {
+    10
+}
+
+#[derive(Debug, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+/// A single address to listen on.
+pub(super) struct ListenerConfig {
+    /// the socket address to bind to, e.g. `0.0.0.0:8080`
+    pub(super) address: SocketAddr,
+
+    #[serde(default)]
+    /// terminate TLS on this listener
+    pub(super) tls: Option<TlsConfig>,
+}
+
+#[derive(Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+/// TLS configuration of a listener.
+///
+/// A self-signed certificate is generated if no cert and key are defined.
+pub(super) struct TlsConfig {
+    /// path to the (PEM-encoded) certificate chain
+    pub(super) cert: Option<PathBuf>,
+
+    /// path to the (PEM-encoded) private key
+    pub(super) key: Option<PathBuf>,
+
+    /// path to a mounted kubernetes tls secret,
+    /// a directory containing the `tls.crt` and `tls.key` files
+    pub(super) secret: Option<PathBuf>,
+}
+
+impl TlsConfig {
+    /// The paths of the certificate chain and private key files, if any.
+    pub(super) fn files(&self) -> Option<(PathBuf, PathBuf)> {
+        match (&self.secret, &self.cert, &self.key) {
+            (Some(secret), _, _) => Some((secret.join("tls.crt"), secret.join("tls.key"))),
+            (None, Some(cert), Some(key)) => Some((cert.clone(), key.clone())),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+/// A route forwarding matching requests to an upstream.
+///
+/// A route without host and path matches all requests.
+pub(super) struct RouteConfig {
+    #[serde(default)]
+    /// the host to match, use `*.example.com` to also match all its subdomains
+    pub(super) host: Option<String>,
+
+    #[serde(default)]
+    /// the path to match, e.g. `/api/*` or `/users/:id`
+    pub(super) path: Option<String>,
+
+    /// the name of the upstream to forward requests to
+    pub(super) upstream: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+/// An upstream (origin) server.
+pub(super) struct UpstreamConfig {
+    #[serde(with = "uri_serde")]
+    /// the base url of the upstream, e.g. `http://127.0.0.1:3000`
+    pub(super) url: Uri,
+
+    #[serde(default)]
+    /// skip Tls certificate verification of the upstream
+    pub(super) insecure: bool,
+
+    #[serde(default)]
+    /// forward the original Host header instead of the upstream authority
+    pub(super) preserve_host: bool,
+
+    #[serde(default)]
+    /// the http version used to connect to the upstream
+    pub(super) version: UpstreamVersion,
+
+    #[serde(default)]
+    /// balance requests over all addresses the upstream host resolves to
+    /// (e.g. the endpoints of a kubernetes headless service),
+    /// which are re-resolved every reload interval
+    pub(super) headless: bool,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+/// The http version used to connect to an upstream.
+pub(super) enum UpstreamVersion {
+    #[default]
+    /// h2 if negotiated (ALPN) with a tls upstream, http/1.1 otherwise
+    Auto,
+    /// always http/1.1
+    Http1,
+    /// always h2, using prior knowledge for plain text upstreams
+    H2,
+    /// always h3 (QUIC), for tls upstreams only
+    H3,
+}
+
+mod uri_serde {
+    use rama::http::Uri;
+    use serde::{de::Error, Deserialize, Deserializer};
+
+    pub(super) fn deserialize<'de, D>(deserializer: D) -> Result<Uri, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(D::Error::custom)
+    }
+}
+
+/// Host matcher parsed from the [`RouteConfig`] host.
+pub(super) enum HostPattern {
+    Exact(Domain),
+    Sub(Domain),
+}
+
+impl Config {
+    /// Read the raw content of the config file.
+    pub(super) async fn read(path: &Path) -> Result<String, BoxError> {
+        let raw = tokio::fs::read_to_string(path)
+            .await
+            .with_context(|| format!("read config file '{}'", path.display()))?;
+        Ok(raw)
+    }
+
+    /// Parse and validate the raw content of the config file,
+    /// using the file extension to detect the format (TOML or YAML).
+    pub(super) fn parse(path: &Path, raw: &str) -> Result<Self, BoxError> {
+        let cfg: Self = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("toml") => toml::from_str(raw).context("parse TOML config")?,
+            Some("yaml" | "yml") => serde_yaml::from_str(raw).context("parse YAML config")?,
+            _ => {
+                return Err(OpaqueError::from_display(
+                    "unknown config format: use a .toml, .yaml or .yml file",
+                )
+                .into_boxed())
+            }
+        };
+
+        cfg.validate()?;
+        Ok(cfg)
+    }
+
+    fn validate(&self) -> Result<(), OpaqueError> {
+        if self.listeners.is_empty() {
+            return Err(OpaqueError::from_display("no listeners defined"));
+        }
+        for listener in &self.listeners {
+            if let Some(tls) = &listener.tls {
+                if tls.cert.is_some() != tls.key.is_some() {
+                    return Err(OpaqueError::from_display(format!(
+                        "listener {}: tls cert and key have to be defined together",
+                        listener.address
+                    )));
+                }
+                if tls.secret.is_some() && tls.cert.is_some() {
+                    return Err(OpaqueError::from_display(format!(
+                        "listener {}: tls secret cannot be combined with a tls cert and key",
+                        listener.address
+                    )));
+                }
+            }
+        }
+        if self.routes.is_empty() {
+            return Err(OpaqueError::from_display("no routes defined"));
+        }
+        for route in &self.routes {
+            if !self.upstreams.contains_key(&route.upstream) {
+                return Err(OpaqueError::from_display(format!(
+                    "route refers to unknown upstream '{}'",
+                    route.upstream
+                )));
+            }
+            route.host_pattern()?;
+        }
+        for (name, upstream) in &self.upstreams {
+            match upstream.url.scheme_str() {
+                Some("http" | "https") => (),
+                _ => {
+                    return Err(OpaqueError::from_display(format!(
+                        "upstream '{name}': url has to start with http:// or https://"
+                    )))
+                }
+            }
+            if upstream.url.authority().is_none() {
+                return Err(OpaqueError::from_display(format!(
+                    "upstream '{name}': url is missing a host"
+                )));
+            }
+            if upstream.headless && upstream.domain().is_none() {
+                return Err(OpaqueError::from_display(format!(
+                    "upstream '{name}': headless upstream url requires a domain as host"
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl UpstreamConfig {
+    /// The domain of the upstream url, if its host is not an IP address.
+    pub(super) fn domain(&self) -> Option<Domain> {
+        match self.url.host()?.parse().ok()? {
+            Host::Name(domain) => Some(domain),
+            Host::Address(_) => None,
+        }
+    }
+}
+
+impl RouteConfig {
+    pub(super) fn host_pattern(&self) -> Result<Option<HostPattern>, OpaqueError> {
+        let Some(host) = self.host.as_deref() else {
+            return Ok(None);
+        };
+        let pattern = match host.strip_prefix("*.") {
+            Some(domain) => HostPattern::Sub(domain.parse().context("parse route host")?),
+            None => HostPattern::Exact(host.parse().context("parse route host")?),
+        };
+        Ok(Some(pattern))
+    }
+}
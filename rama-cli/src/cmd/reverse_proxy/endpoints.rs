@@ -0,0 +1,69 @@
+//! endpoints of headless upstreams (e.g. a kubernetes headless service)
+
+use arc_swap::ArcSwap;
+use rama::{
+    dns::{DnsResolver, HickoryDns},
+    net::address::Domain,
+};
+use std::{
+    net::IpAddr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+/// The addresses a headless upstream resolves to,
+/// which are used in a round-robin fashion.
+pub(super) struct Endpoints {
+    domain: Domain,
+    addresses: ArcSwap<Vec<IpAddr>>,
+    next: AtomicUsize,
+}
+
+impl Endpoints {
+    pub(super) fn new(domain: Domain) -> Self {
+        Self {
+            domain,
+            addresses: ArcSwap::from_pointee(Vec::new()),
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    /// The address to use for the next request,
+    /// `None` in case the endpoints were not resolved (yet).
+    pub(super) fn next(&self) -> Option<IpAddr> {
+        let addresses = self.addresses.load();
+        if addresses.is_empty() {
+            return None;
+        }
+        let index = self.next.fetch_add(1, Ordering::Relaxed) % addresses.len();
+        Some(addresses[index])
+    }
+
+    /// Re-resolve the endpoints, keeping the previous
+    /// endpoints in case the domain could not be resolved.
+    pub(super) async fn resolve(&self, dns: &HickoryDns) {
+        let (ipv4, ipv6) = tokio::join!(
+            dns.ipv4_lookup(self.domain.clone()),
+            dns.ipv6_lookup(self.domain.clone()),
+        );
+        let mut addresses: Vec<IpAddr> = ipv4
+            .into_iter()
+            .flatten()
+            .map(IpAddr::V4)
+            .chain(ipv6.into_iter().flatten().map(IpAddr::V6))
+            .collect();
+
+        if addresses.is_empty() {
+            tracing::warn!(domain = %self.domain, "failed to resolve headless upstream endpoints");
+            return;
+        }
+
+        addresses.sort_unstable();
+        if **self.addresses.load() != addresses {
+            tracing::info!(domain = %self.domain, ?addresses, "headless upstream endpoints updated");
+            self.addresses.store(Arc::new(addresses));
+        }
+    }
+}
@@ -0,0 +1,442 @@
+//! rama reverse proxy service, driven by a config file
+//!
+//! Example config (TOML):
+//!
+//! ```toml
+//! [[listeners]]
+//! address = "0.0.0.0:8080"
+//!
+//! [[listeners]]
+//! address = "0.0.0.0:8443"
+//! tls = { cert = "rama.crt.pem", key = "rama.key.pem" }
+//!
+//! [[routes]]
+//! host = "*.example.com"
+//! path = "/api/*"
+//! upstream = "api"
+//!
+//! [[routes]]
+//! upstream = "web"
+//!
+//! [upstreams.api]
+//! url = "http://127.0.0.1:3000"
+//!
+//! [upstreams.web]
+//! url = "https://127.0.0.1:4000"
+//! insecure = true
+//! version = "h2" # auto (default), http1, h2 or h3 (tls upstreams only)
+//! ```
+//!
+//! By default h2 is used for tls upstreams which negotiate it (ALPN),
+//! falling back to http/1.1 otherwise. Version specific semantics, such as
+//! connection headers and cookie headers, are translated by the http client,
+//! such that clients and upstreams can use different http versions.
+//!
+//! # Kubernetes
+//!
+//! The config file and tls files are checked for changes every `reload_interval`
+//! seconds (10 by default), such that the config can be mounted from a ConfigMap
+//! and the certificate from a (tls) Secret, both updated in place by the kubelet.
+//! Routes and upstreams are reloaded without restart, as well as the certificates.
+//!
+//! Headless upstreams are balanced over all addresses their host resolves to,
+//! as is the case for the endpoints of a headless service, re-resolved every reload interval:
+//!
+//! ```toml
+//! reload_interval = 10
+//!
+//! [[listeners]]
+//! address = "0.0.0.0:8443"
+//! tls = { secret = "/etc/rama/tls" } # containing tls.crt and tls.key
+//!
+//! [[routes]]
+//! upstream = "api"
+//!
+//! [upstreams.api]
+//! url = "http://api.default.svc.cluster.local:3000"
+//! headless = true
+//! ```
+
+use arc_swap::ArcSwap;
+use clap::Args;
+use rama::{
+    error::{BoxError, ErrorContext, OpaqueError},
+    http::{
+        client::HttpClient,
+        header::HOST,
+        layer::{
+            forwarded::SetForwardedHeadersLayer,
+            remove_header::{RemoveRequestHeaderLayer, RemoveResponseHeaderLayer},
+            trace::TraceLayer,
+        },
+        matcher::HttpMatcher,
+        server::HttpServer,
+        Body, HeaderValue, IntoResponse, Request, Response, Scheme, StatusCode, Uri, Version,
+    },
+    layer::{limit::policy::ConcurrentPolicy, ConsumeErrLayer, LimitLayer, TimeoutLayer},
+    matcher::Matcher,
+    net::{
+        address::Host,
+        http::RequestContext,
+        tls::{
+            client::{ClientConfig, ClientHelloExtension, ServerNameIndication, ServerVerifyMode},
+            ApplicationProtocol,
+        },
+    },
+    rt::Executor,
+    tcp::server::TcpListener,
+    Context, Layer, Service,
+};
+use std::{
+    convert::Infallible,
+    net::{IpAddr, SocketAddr},
+    path::PathBuf,
+    sync::Arc,
+    time::Duration,
+};
+use tokio::io::AsyncWriteExt;
+use tracing::level_filters::LevelFilter;
+use tracing_subscriber::{fmt, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
+
+mod config;
+use config::{Config, HostPattern, UpstreamConfig, UpstreamVersion};
+
+mod endpoints;
+use endpoints::Endpoints;
+
+mod reload;
+use reload::{ListenerTls, ListenerTlsLayer};
+
+#[derive(Debug, Args)]
+/// rama reverse proxy (listeners, tls, routes and upstreams defined in a TOML or YAML file)
+pub struct CliCommandReverseProxy {
+    #[arg(long)]
+    /// only validate the config file, without running the reverse proxy
+    check: bool,
+
+    /// path to the config file (.toml, .yaml or .yml)
+    config: PathBuf,
+}
+
+/// run the rama reverse proxy service
+pub async fn run(cfg: CliCommandReverseProxy) -> Result<(), BoxError> {
+    let raw_config = Config::read(&cfg.config).await?;
+    let config = Config::parse(&cfg.config, &raw_config)?;
+    let proxy = ReverseProxy::try_from_config(&config)?;
+
+    if cfg.check {
+        let mut stdout = tokio::io::stdout();
+        stdout
+            .write_all(format!("config '{}' is valid\n", cfg.config.display()).as_bytes())
+            .await?;
+        stdout.flush().await?;
+        return Ok(());
+    }
+
+    tracing_subscriber::registry()
+        .with(fmt::layer())
+        .with(
+            EnvFilter::builder()
+                .with_default_directive(LevelFilter::INFO.into())
+                .from_env_lossy(),
+        )
+        .init();
+
+    let graceful = rama::graceful::Shutdown::default();
+
+    let mut listeners_tls = Vec::new();
+    for listener in &config.listeners {
+        let tls = listener
+            .tls
+            .as_ref()
+            .map(|tls| ListenerTls::try_new(tls.files()).map(Arc::new))
+            .transpose()
+            .with_context(|| format!("create tls acceptor data for {}", listener.address))?;
+        listeners_tls.extend(tls.clone());
+
+        let tcp_listener = TcpListener::bind(listener.address)
+            .await
+            .with_context(|| format!("bind reverse proxy to {}", listener.address))?;
+        tracing::info!(
+            "reverse proxy listening on: {} (tls: {})",
+            listener.address,
+            tls.is_some()
+        );
+
+        let proxy = proxy.clone();
+        let concurrent = config.concurrent;
+        let timeout = config.timeout;
+
+        graceful.spawn_task_fn(move |guard| async move {
+            let exec = Executor::graceful(guard.clone());
+            let http_service = HttpServer::auto(exec).service(
+                (
+                    TraceLayer::new_for_http(),
+                    ConsumeErrLayer::trace(tracing::Level::WARN),
+                    RemoveResponseHeaderLayer::hop_by_hop(),
+                    RemoveRequestHeaderLayer::hop_by_hop(),
+                    SetForwardedHeadersLayer::x_forwarded_for(),
+                )
+                    .layer(proxy),
+            );
+
+            let tcp_service_builder = (
+                ConsumeErrLayer::trace(tracing::Level::DEBUG),
+                (concurrent > 0).then(|| LimitLayer::new(ConcurrentPolicy::max(concurrent))),
+                (timeout > 0).then(|| TimeoutLayer::new(Duration::from_secs(timeout))),
+                tls.map(ListenerTlsLayer),
+            );
+
+            tcp_listener
+                .serve_graceful(guard, tcp_service_builder.layer(http_service))
+                .await;
+        });
+    }
+
+    if config.reload_interval > 0 {
+        let interval = Duration::from_secs(config.reload_interval);
+        let path = cfg.config.clone();
+        graceful.spawn_task_fn(move |guard| {
+            reload::watch(guard, interval, path, raw_config, proxy, listeners_tls)
+        });
+    }
+
+    graceful
+        .shutdown_with_limit(Duration::from_secs(30))
+        .await?;
+
+    Ok(())
+}
+
+#[derive(Clone)]
+struct ReverseProxy {
+    routes: Arc<ArcSwap<Vec<Route>>>,
+}
+
+struct Route {
+    matcher: Option<HttpMatcher<(), Body>>,
+    upstream: Arc<Upstream>,
+}
+
+struct Upstream {
+    name: String,
+    url: Uri,
+    preserve_host: bool,
+    version: UpstreamVersion,
+    client: HttpClient,
+    endpoints: Option<Arc<Endpoints>>,
+}
+
+impl ReverseProxy {
+    fn try_from_config(cfg: &Config) -> Result<Self, OpaqueError> {
+        Ok(Self {
+            routes: Arc::new(ArcSwap::from_pointee(Self::routes(cfg)?)),
+        })
+    }
+
+    /// Replace the routes and upstreams by the ones defined in the given config.
+    fn reload(&self, cfg: &Config) -> Result<(), OpaqueError> {
+        self.routes.store(Arc::new(Self::routes(cfg)?));
+        Ok(())
+    }
+
+    fn routes(cfg: &Config) -> Result<Vec<Route>, OpaqueError> {
+        let upstreams = cfg
+            .upstreams
+            .iter()
+            .map(|(name, upstream)| (name.as_str(), Arc::new(Upstream::new(name, upstream))))
+            .collect::<std::collections::HashMap<_, _>>();
+
+        let routes = cfg
+            .routes
+            .iter()
+            .map(|route| {
+                let host_matcher = route.host_pattern()?.map(|pattern| match pattern {
+                    HostPattern::Exact(domain) => HttpMatcher::domain(domain),
+                    HostPattern::Sub(domain) => HttpMatcher::subdomain(domain),
+                });
+                let matcher = match (host_matcher, route.path.as_deref()) {
+                    (Some(matcher), Some(path)) => Some(matcher.and_path(path)),
+                    (Some(matcher), None) => Some(matcher),
+                    (None, Some(path)) => Some(HttpMatcher::path(path)),
+                    (None, None) => None,
+                };
+                Ok(Route {
+                    matcher,
+                    // existence validated when loading the config
+                    upstream: upstreams[route.upstream.as_str()].clone(),
+                })
+            })
+            .collect::<Result<Vec<_>, OpaqueError>>()?;
+
+        Ok(routes)
+    }
+
+    /// The endpoints of all headless upstreams in use.
+    fn headless_endpoints(&self) -> Vec<Arc<Endpoints>> {
+        let mut endpoints: Vec<Arc<Endpoints>> = Vec::new();
+        for route in self.routes.load().iter() {
+            if let Some(upstream_endpoints) = &route.upstream.endpoints {
+                if !endpoints.iter().any(|e| Arc::ptr_eq(e, upstream_endpoints)) {
+                    endpoints.push(upstream_endpoints.clone());
+                }
+            }
+        }
+        endpoints
+    }
+
+    fn route(&self, ctx: &Context<()>, req: &Request) -> Option<Arc<Upstream>> {
+        self.routes
+            .load()
+            .iter()
+            .find(|route| {
+                route
+                    .matcher
+                    .as_ref()
+                    .map(|matcher| matcher.matches(None, ctx, req))
+                    .unwrap_or(true)
+            })
+            .map(|route| route.upstream.clone())
+    }
+}
+
+impl Upstream {
+    fn new(name: &str, cfg: &UpstreamConfig) -> Self {
+        let alpn = match cfg.version {
+            UpstreamVersion::Auto => {
+                vec![ApplicationProtocol::HTTP_2, ApplicationProtocol::HTTP_11]
+            }
+            UpstreamVersion::Http1 => vec![ApplicationProtocol::HTTP_11],
+            UpstreamVersion::H2 => vec![ApplicationProtocol::HTTP_2],
+            UpstreamVersion::H3 => vec![ApplicationProtocol::HTTP_3],
+        };
+        let endpoints = cfg
+            .headless
+            .then(|| cfg.domain())
+            .flatten()
+            .map(|domain| Arc::new(Endpoints::new(domain)));
+        let client = HttpClient::default().with_tls_config(ClientConfig {
+            // requests to a headless upstream target the ip address of an endpoint,
+            // while the server (cert) is still expected to match the upstream domain
+            server_name: endpoints
+                .as_ref()
+                .and(cfg.domain())
+                .map(|domain| ServerNameIndication::Custom(Host::Name(domain))),
+            server_verify_mode: cfg.insecure.then_some(ServerVerifyMode::Disable),
+            extensions: Some(vec![
+                ClientHelloExtension::ApplicationLayerProtocolNegotiation(alpn),
+            ]),
+            ..Default::default()
+        });
+        Self {
+            name: name.to_owned(),
+            url: cfg.url.clone(),
+            preserve_host: cfg.preserve_host,
+            version: cfg.version,
+            client,
+            endpoints,
+        }
+    }
+
+    /// Rewrite the request such that it targets this upstream.
+    fn rewrite(&self, mut req: Request) -> Result<Request, OpaqueError> {
+        let base_path = self.url.path().trim_end_matches('/');
+        let path_and_query = req
+            .uri()
+            .path_and_query()
+            .map(|pq| pq.as_str())
+            .unwrap_or("/");
+
+        let mut parts = self.url.clone().into_parts();
+        parts.path_and_query = Some(
+            format!("{base_path}{path_and_query}")
+                .parse()
+                .context("create upstream path")?,
+        );
+        let uri = Uri::from_parts(parts).context("create upstream uri")?;
+
+        let original_host = req.headers().get(HOST).cloned().or_else(|| {
+            req.uri()
+                .authority()
+                .and_then(|authority| HeaderValue::from_str(authority.as_str()).ok())
+        });
+        let host = match original_host {
+            Some(host) if self.preserve_host => host,
+            _ => HeaderValue::from_str(uri.authority().map(|a| a.as_str()).unwrap_or_default())
+                .context("create upstream host header")?,
+        };
+        req.headers_mut().insert(HOST, host);
+
+        // for tls upstreams the version is overwritten by the negotiated (ALPN) version,
+        // plain text upstreams are not expected to support h2 (prior knowledge) unless configured
+        *req.version_mut() = match self.version {
+            UpstreamVersion::Auto | UpstreamVersion::Http1 => Version::HTTP_11,
+            UpstreamVersion::H2 => Version::HTTP_2,
+            UpstreamVersion::H3 => Version::HTTP_3,
+        };
+        *req.uri_mut() = match self.endpoints.as_ref().and_then(|e| e.next()) {
+            Some(ip) => endpoint_uri(uri, ip)?,
+            None => uri,
+        };
+
+        Ok(req)
+    }
+}
+
+/// Replace the host of the uri by the ip address of a (headless upstream) endpoint.
+fn endpoint_uri(uri: Uri, ip: IpAddr) -> Result<Uri, OpaqueError> {
+    let port = uri
+        .port_u16()
+        .unwrap_or(if uri.scheme() == Some(&Scheme::HTTPS) {
+            443
+        } else {
+            80
+        });
+    let mut parts = uri.into_parts();
+    parts.authority = Some(
+        SocketAddr::new(ip, port)
+            .to_string()
+            .parse()
+            .context("create endpoint authority")?,
+    );
+    Uri::from_parts(parts).context("create endpoint uri")
+}
+
+impl Service<(), Request> for ReverseProxy {
+    type Response = Response;
+    type Error = Infallible;
+
+    async fn serve(
+        &self,
+        mut ctx: Context<()>,
+        req: Request,
+    ) -> Result<Self::Response, Self::Error> {
+        let Some(upstream) = self.route(&ctx, &req) else {
+            tracing::debug!(uri = %req.uri(), "no route found");
+            return Ok(StatusCode::NOT_FOUND.into_response());
+        };
+
+        let version = req.version();
+        let req = match upstream.rewrite(req) {
+            Ok(req) => req,
+            Err(err) => {
+                tracing::error!(error = %err, upstream = %upstream.name, "rewrite request");
+                return Ok(StatusCode::BAD_REQUEST.into_response());
+            }
+        };
+
+        // the request context of the incoming request does not apply to the upstream
+        ctx.remove::<RequestContext>();
+
+        match upstream.client.serve(ctx, req).await {
+            Ok(mut resp) => {
+                *resp.version_mut() = version;
+                Ok(resp)
+            }
+            Err(err) => {
+                tracing::error!(error = %err, upstream = %upstream.name, "upstream request failed");
+                Ok(StatusCode::BAD_GATEWAY.into_response())
+            }
+        }
+    }
+}
@@ -0,0 +1,198 @@
+//! reload of the config and tls files on change,
+//! such as mounted kubernetes configmaps and secrets,
+//! which are updated in place by the kubelet
+
+use super::{config::Config, ReverseProxy};
+use arc_swap::ArcSwap;
+use rama::{
+    dns::HickoryDns,
+    error::{ErrorContext, OpaqueError},
+    graceful::ShutdownGuard,
+    net::tls::{
+        server::{SelfSignedData, ServerAuth, ServerAuthData, ServerConfig},
+        ApplicationProtocol, DataEncoding,
+    },
+    tls::std::server::{TlsAcceptorData, TlsAcceptorService},
+    Context, Layer, Service,
+};
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+/// The tls acceptor data of a listener, reloaded when its cert or key file changes.
+pub(super) struct ListenerTls {
+    files: Option<(PathBuf, PathBuf)>,
+    contents: Mutex<Option<(String, String)>>,
+    data: ArcSwap<TlsAcceptorData>,
+}
+
+impl ListenerTls {
+    /// Create the tls acceptor data using the given cert and key files,
+    /// or a self-signed certificate in case no files are given.
+    pub(super) fn try_new(files: Option<(PathBuf, PathBuf)>) -> Result<Self, OpaqueError> {
+        let contents = files
+            .as_ref()
+            .map(|(cert, key)| read_tls_files(cert, key))
+            .transpose()?;
+        let data = tls_acceptor_data(contents.clone())?;
+        Ok(Self {
+            files,
+            contents: Mutex::new(contents),
+            data: ArcSwap::from_pointee(data),
+        })
+    }
+
+    /// Reload the tls acceptor data in case the cert or key file changed.
+    fn reload(&self) -> Result<(), OpaqueError> {
+        let Some((cert, key)) = &self.files else {
+            return Ok(());
+        };
+        let contents = read_tls_files(cert, key)?;
+        let mut current = self.contents.lock().unwrap_or_else(|err| err.into_inner());
+        if current.as_ref() == Some(&contents) {
+            return Ok(());
+        }
+        let data = tls_acceptor_data(Some(contents.clone()))?;
+        self.data.store(Arc::new(data));
+        *current = Some(contents);
+        tracing::info!(cert = %cert.display(), "tls certificate reloaded");
+        Ok(())
+    }
+}
+
+fn read_tls_files(cert: &Path, key: &Path) -> Result<(String, String), OpaqueError> {
+    let cert_chain = std::fs::read_to_string(cert)
+        .with_context(|| format!("read tls cert file '{}'", cert.display()))?;
+    let private_key = std::fs::read_to_string(key)
+        .with_context(|| format!("read tls key file '{}'", key.display()))?;
+    Ok((cert_chain, private_key))
+}
+
+fn tls_acceptor_data(contents: Option<(String, String)>) -> Result<TlsAcceptorData, OpaqueError> {
+    let server_auth = match contents {
+        Some((cert_chain, private_key)) => ServerAuth::Single(ServerAuthData {
+            private_key: DataEncoding::Pem(
+                private_key.try_into().context("tls key file is empty")?,
+            ),
+            cert_chain: DataEncoding::Pem(cert_chain.try_into().context("tls cert file is empty")?),
+            ocsp: None,
+        }),
+        None => ServerAuth::SelfSigned(SelfSignedData::default()),
+    };
+    ServerConfig {
+        application_layer_protocol_negotiation: Some(vec![
+            ApplicationProtocol::HTTP_2,
+            ApplicationProtocol::HTTP_11,
+        ]),
+        ..ServerConfig::new(server_auth)
+    }
+    .try_into()
+}
+
+/// Layer accepting tls connections using the current data of a [`ListenerTls`].
+pub(super) struct ListenerTlsLayer(pub(super) Arc<ListenerTls>);
+
+impl<S> Layer<S> for ListenerTlsLayer {
+    type Service = ListenerTlsService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        ListenerTlsService {
+            tls: self.0.clone(),
+            inner: Arc::new(inner),
+        }
+    }
+}
+
+/// Service accepting tls connections using the current data of a [`ListenerTls`].
+pub(super) struct ListenerTlsService<S> {
+    tls: Arc<ListenerTls>,
+    inner: Arc<S>,
+}
+
+impl<S, IO> Service<(), IO> for ListenerTlsService<S>
+where
+    S: Send + Sync + 'static,
+    IO: Send + 'static,
+    TlsAcceptorService<Arc<S>>: Service<(), IO>,
+{
+    type Response = <TlsAcceptorService<Arc<S>> as Service<(), IO>>::Response;
+    type Error = <TlsAcceptorService<Arc<S>> as Service<(), IO>>::Error;
+
+    async fn serve(&self, ctx: Context<()>, stream: IO) -> Result<Self::Response, Self::Error> {
+        let data = self.tls.data.load().as_ref().clone();
+        TlsAcceptorService::new(data, self.inner.clone(), false)
+            .serve(ctx, stream)
+            .await
+    }
+}
+
+/// Watch the config and tls files for changes,
+/// and re-resolve the endpoints of headless upstreams,
+/// until the graceful shutdown is initiated.
+pub(super) async fn watch(
+    guard: ShutdownGuard,
+    interval: Duration,
+    path: PathBuf,
+    mut raw: String,
+    proxy: ReverseProxy,
+    listeners: Vec<Arc<ListenerTls>>,
+) {
+    let dns = HickoryDns::default();
+    let mut current = match Config::parse(&path, &raw) {
+        Ok(cfg) => cfg,
+        Err(err) => {
+            tracing::error!(error = %err, "parse config file to watch");
+            return;
+        }
+    };
+
+    let mut ticker = tokio::time::interval(interval);
+    let mut cancelled = std::pin::pin!(guard.cancelled());
+    loop {
+        tokio::select! {
+            _ = cancelled.as_mut() => return,
+            _ = ticker.tick() => (),
+        }
+
+        match Config::read(&path).await {
+            Ok(new_raw) if new_raw != raw => {
+                match Config::parse(&path, &new_raw).and_then(|cfg| {
+                    proxy.reload(&cfg)?;
+                    Ok(cfg)
+                }) {
+                    Ok(cfg) => {
+                        tracing::info!(path = %path.display(), "config reloaded");
+                        if cfg.listeners != current.listeners
+                            || cfg.concurrent != current.concurrent
+                            || cfg.timeout != current.timeout
+                            || cfg.reload_interval != current.reload_interval
+                        {
+                            tracing::warn!(
+                                "changes to listeners, concurrent, timeout and reload_interval are only applied after a restart"
+                            );
+                        }
+                        current = cfg;
+                    }
+                    Err(err) => {
+                        tracing::error!(error = %err, "invalid config file, keeping previous config")
+                    }
+                }
+                raw = new_raw;
+            }
+            Ok(_) => (),
+            Err(err) => tracing::error!(error = %err, "read config file"),
+        }
+
+        for tls in &listeners {
+            if let Err(err) = tls.reload() {
+                tracing::error!(error = %err, "reload tls certificate, keeping previous certificate");
+            }
+        }
+
+        for endpoints in proxy.headless_endpoints() {
+            endpoints.resolve(&dns).await;
+        }
+    }
+}
@@ -0,0 +1,465 @@
+//! rama speed test client
+
+use clap::Args;
+use rama::{
+    error::{BoxError, ErrorContext, OpaqueError},
+    http::{
+        client::{
+            proxy::layer::{HttpProxyAddressLayer, SetProxyAuthHttpHeaderLayer},
+            HttpClient,
+        },
+        dep::http_body_util::BodyExt,
+        header::USER_AGENT,
+        layer::{
+            required_header::AddRequiredRequestHeadersLayer, set_header::SetRequestHeaderLayer,
+            timeout::TimeoutLayer, ua::UserAgentClassifierLayer,
+        },
+        Body, HeaderValue, Method, Request, Response,
+    },
+    layer::MapResultLayer,
+    net::{
+        address::ProxyAddress,
+        tls::{
+            client::{ClientConfig, ClientHelloExtension, ServerVerifyMode},
+            ApplicationProtocol,
+        },
+    },
+    proxy::ProxyCsvRowReader,
+    Context, Layer, Service,
+};
+use serde::Serialize;
+use std::time::{Duration, Instant};
+use tokio::io::AsyncWriteExt;
+use tracing::level_filters::LevelFilter;
+use tracing_subscriber::{fmt, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
+
+#[derive(Args, Debug, Clone)]
+/// rama speed test (measure throughput, latency and jitter, optionally per proxy)
+pub struct CliCommandSpeed {
+    #[arg(long, short = 'P')]
+    /// upstream proxy to test, can be repeated
+    ///
+    /// A direct connection is tested if no proxy is given
+    /// (using the PROXY env variable if defined).
+    proxy: Vec<String>,
+
+    #[arg(long)]
+    /// path to a ProxyDB CSV file, all proxies within it are tested
+    proxy_csv: Option<String>,
+
+    #[arg(long, short = 'n', default_value_t = 5)]
+    /// the number of download (and upload) rounds to run per proxy
+    rounds: usize,
+
+    #[arg(long, short = 'u')]
+    /// url to upload the test payload to (using a POST request)
+    ///
+    /// Upload is not tested if not defined.
+    upload: Option<String>,
+
+    #[arg(long, default_value_t = 1024 * 1024)]
+    /// the size in bytes of the test payload to upload
+    upload_size: usize,
+
+    #[arg(short = 'k', long)]
+    /// skip Tls certificate verification
+    insecure: bool,
+
+    #[arg(long, short = 't', default_value = "30")]
+    /// the timeout in seconds for each round trip (0 = no timeout)
+    timeout: u64,
+
+    #[arg(long)]
+    /// the User-Agent to emulate (rama's own User-Agent by default)
+    ///
+    /// It is sent as the User-Agent header of all requests,
+    /// and classified such that the client stack can emulate its http and tls agent.
+    user_agent: Option<String>,
+
+    #[arg(long)]
+    /// print the report as JSON instead of a table
+    json: bool,
+
+    #[arg(long)]
+    /// print debug info
+    debug: bool,
+
+    /// url of the test payload to download
+    url: String,
+}
+
+/// Run the speed test command.
+pub async fn run(cfg: CliCommandSpeed) -> Result<(), BoxError> {
+    tracing_subscriber::registry()
+        .with(fmt::layer())
+        .with(
+            EnvFilter::builder()
+                .with_default_directive(
+                    if cfg.debug {
+                        LevelFilter::DEBUG
+                    } else {
+                        LevelFilter::ERROR
+                    }
+                    .into(),
+                )
+                .from_env_lossy(),
+        )
+        .init();
+
+    let mut reports = Vec::new();
+    for target in collect_targets(&cfg).await? {
+        tracing::debug!(proxy = %target.name, "run speed test");
+        let client = create_client(&cfg, target.proxy)?;
+        reports.push(measure(&cfg, target.name, &client).await);
+    }
+
+    // rank the fastest proxies first
+    reports.sort_by(|a, b| b.download_mbps.total_cmp(&a.download_mbps));
+
+    let output = if cfg.json {
+        let mut output = serde_json::to_string_pretty(&reports).context("serialize report")?;
+        output.push('\n');
+        output
+    } else {
+        render_table(&reports)
+    };
+
+    let mut stdout = tokio::io::stdout();
+    stdout.write_all(output.as_bytes()).await?;
+    stdout.flush().await?;
+
+    Ok(())
+}
+
+#[derive(Debug)]
+struct Target {
+    name: String,
+    proxy: Option<ProxyAddress>,
+}
+
+async fn collect_targets(cfg: &CliCommandSpeed) -> Result<Vec<Target>, BoxError> {
+    let mut targets = Vec::new();
+
+    for proxy in cfg.proxy.iter() {
+        let address: ProxyAddress = proxy.parse().context("parse proxy address")?;
+        targets.push(Target {
+            name: address.authority.to_string(),
+            proxy: Some(address),
+        });
+    }
+
+    if let Some(path) = cfg.proxy_csv.as_deref() {
+        let mut reader = ProxyCsvRowReader::open(path)
+            .await
+            .context("open proxy csv file")?;
+        while let Some(proxy) = reader.next().await.context("read proxy csv row")? {
+            targets.push(Target {
+                name: proxy.id.to_string(),
+                proxy: Some(proxy.address),
+            });
+        }
+    }
+
+    if targets.is_empty() {
+        targets.push(Target {
+            name: "direct".to_owned(),
+            proxy: None,
+        });
+    }
+
+    Ok(targets)
+}
+
+fn create_client<S>(
+    cfg: &CliCommandSpeed,
+    proxy: Option<ProxyAddress>,
+) -> Result<impl Service<S, Request, Response = Response, Error = BoxError>, BoxError>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    let mut inner_client = HttpClient::default();
+
+    let server_verify_mode = if cfg.insecure {
+        Some(ServerVerifyMode::Disable)
+    } else {
+        None
+    };
+
+    inner_client.set_tls_config(ClientConfig {
+        server_verify_mode,
+        extensions: Some(vec![
+            ClientHelloExtension::ApplicationLayerProtocolNegotiation(vec![
+                ApplicationProtocol::HTTP_2,
+                ApplicationProtocol::HTTP_11,
+            ]),
+        ]),
+        ..Default::default()
+    });
+
+    inner_client.set_proxy_tls_config(ClientConfig {
+        server_verify_mode,
+        ..Default::default()
+    });
+
+    let user_agent = cfg
+        .user_agent
+        .as_deref()
+        .map(HeaderValue::from_str)
+        .transpose()
+        .context("parse user agent")?;
+
+    let client_builder = (
+        MapResultLayer::new(map_internal_client_error),
+        (cfg.timeout > 0).then(|| TimeoutLayer::new(Duration::from_secs(cfg.timeout))),
+        user_agent.map(|ua| SetRequestHeaderLayer::overriding(USER_AGENT, ua)),
+        AddRequiredRequestHeadersLayer::default(),
+        UserAgentClassifierLayer::new(),
+        match proxy {
+            None => HttpProxyAddressLayer::try_from_env_default()?,
+            Some(proxy) => HttpProxyAddressLayer::maybe(Some(proxy)),
+        },
+        SetProxyAuthHttpHeaderLayer::default(),
+    );
+
+    Ok(client_builder.layer(inner_client))
+}
+
+#[derive(Debug, Serialize)]
+struct SpeedReport {
+    name: String,
+    rounds: usize,
+    errors: usize,
+    latency_ms: Option<f64>,
+    jitter_ms: Option<f64>,
+    download_mbps: f64,
+    upload_mbps: Option<f64>,
+}
+
+async fn measure<S>(cfg: &CliCommandSpeed, name: String, client: &S) -> SpeedReport
+where
+    S: Service<(), Request, Response = Response, Error = BoxError>,
+{
+    let mut latencies = Vec::with_capacity(cfg.rounds);
+    let mut downloads = Vec::with_capacity(cfg.rounds);
+    let mut uploads = Vec::with_capacity(cfg.rounds);
+    let mut errors = 0;
+
+    for round in 0..cfg.rounds {
+        match download_round(client, &cfg.url).await {
+            Ok((latency, bytes_per_sec)) => {
+                latencies.push(latency);
+                downloads.push(bytes_per_sec);
+            }
+            Err(err) => {
+                tracing::debug!(proxy = %name, round, error = %err, "download round failed");
+                errors += 1;
+            }
+        }
+
+        if let Some(url) = cfg.upload.as_deref() {
+            match upload_round(client, url, cfg.upload_size).await {
+                Ok(bytes_per_sec) => uploads.push(bytes_per_sec),
+                Err(err) => {
+                    tracing::debug!(proxy = %name, round, error = %err, "upload round failed");
+                    errors += 1;
+                }
+            }
+        }
+    }
+
+    let latencies_ms: Vec<_> = latencies
+        .iter()
+        .map(|latency| latency.as_secs_f64() * 1000.)
+        .collect();
+
+    SpeedReport {
+        name,
+        rounds: cfg.rounds,
+        errors,
+        latency_ms: mean(&latencies_ms),
+        jitter_ms: jitter(&latencies_ms),
+        download_mbps: mean(&downloads).map(to_mbps).unwrap_or_default(),
+        upload_mbps: cfg
+            .upload
+            .as_ref()
+            .map(|_| mean(&uploads).map(to_mbps).unwrap_or_default()),
+    }
+}
+
+/// Download the test payload, returning the time to first byte
+/// and the throughput in bytes per second.
+async fn download_round<S>(client: &S, url: &str) -> Result<(Duration, f64), BoxError>
+where
+    S: Service<(), Request, Response = Response, Error = BoxError>,
+{
+    let request = Request::builder()
+        .method(Method::GET)
+        .uri(url)
+        .body(Body::empty())
+        .context("build download request")?;
+
+    let start = Instant::now();
+    let response = client.serve(Context::default(), request).await?;
+    let latency = start.elapsed();
+
+    if !response.status().is_success() {
+        return Err(OpaqueError::from_display(format!(
+            "unexpected download status: {}",
+            response.status()
+        ))
+        .into());
+    }
+
+    let mut body = response.into_body();
+    let mut size = 0;
+    while let Some(frame) = body.frame().await {
+        if let Some(data) = frame.context("read download body frame")?.data_ref() {
+            size += data.len();
+        }
+    }
+
+    Ok((latency, bytes_per_sec(size, start.elapsed())))
+}
+
+/// Upload a test payload, returning the throughput in bytes per second.
+async fn upload_round<S>(client: &S, url: &str, size: usize) -> Result<f64, BoxError>
+where
+    S: Service<(), Request, Response = Response, Error = BoxError>,
+{
+    let request = Request::builder()
+        .method(Method::POST)
+        .uri(url)
+        .header("content-type", "application/octet-stream")
+        .body(Body::from(vec![0u8; size]))
+        .context("build upload request")?;
+
+    let start = Instant::now();
+    let response = client.serve(Context::default(), request).await?;
+
+    if !response.status().is_success() {
+        return Err(OpaqueError::from_display(format!(
+            "unexpected upload status: {}",
+            response.status()
+        ))
+        .into());
+    }
+
+    // consume the (usually empty) response to include the full round trip
+    response
+        .into_body()
+        .collect()
+        .await
+        .context("read upload response body")?;
+
+    Ok(bytes_per_sec(size, start.elapsed()))
+}
+
+fn bytes_per_sec(size: usize, elapsed: Duration) -> f64 {
+    size as f64 / elapsed.as_secs_f64()
+}
+
+fn mean(values: &[f64]) -> Option<f64> {
+    if values.is_empty() {
+        None
+    } else {
+        Some(values.iter().sum::<f64>() / values.len() as f64)
+    }
+}
+
+/// Jitter as the mean deviation between consecutive latency samples.
+fn jitter(latencies: &[f64]) -> Option<f64> {
+    if latencies.len() < 2 {
+        return None;
+    }
+    let deviations: Vec<_> = latencies.windows(2).map(|w| (w[1] - w[0]).abs()).collect();
+    mean(&deviations)
+}
+
+fn to_mbps(bytes_per_sec: f64) -> f64 {
+    bytes_per_sec * 8. / 1_000_000.
+}
+
+fn render_table(reports: &[SpeedReport]) -> String {
+    let name_width = reports
+        .iter()
+        .map(|report| report.name.len())
+        .max()
+        .unwrap_or_default()
+        .max(5);
+
+    let fmt_opt = |value: Option<f64>, unit: &str| match value {
+        Some(value) => format!("{value:.2} {unit}"),
+        None => "-".to_owned(),
+    };
+
+    let mut output = format!(
+        "{:<name_width$}  {:>12}  {:>12}  {:>14}  {:>14}  {:>6}\n",
+        "PROXY", "LATENCY", "JITTER", "DOWNLOAD", "UPLOAD", "ERRORS"
+    );
+    for report in reports {
+        output.push_str(&format!(
+            "{:<name_width$}  {:>12}  {:>12}  {:>14}  {:>14}  {:>6}\n",
+            report.name,
+            fmt_opt(report.latency_ms, "ms"),
+            fmt_opt(report.jitter_ms, "ms"),
+            fmt_opt(Some(report.download_mbps), "Mbps"),
+            fmt_opt(report.upload_mbps, "Mbps"),
+            report.errors,
+        ));
+    }
+    output
+}
+
+fn map_internal_client_error<E, Body>(
+    result: Result<Response<Body>, E>,
+) -> Result<Response, BoxError>
+where
+    E: Into<BoxError>,
+    Body: rama::http::dep::http_body::Body<Data = bytes::Bytes, Error: Into<BoxError>>
+        + Send
+        + Sync
+        + 'static,
+{
+    match result {
+        Ok(response) => Ok(response.map(rama::http::Body::new)),
+        Err(err) => Err(err.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bytes_per_sec() {
+        assert_eq!(bytes_per_sec(0, Duration::from_secs(1)), 0.);
+        assert_eq!(bytes_per_sec(1000, Duration::from_millis(500)), 2000.);
+        assert_eq!(
+            to_mbps(bytes_per_sec(1_250_000, Duration::from_secs(1))),
+            10.
+        );
+    }
+
+    #[test]
+    fn test_mean() {
+        assert_eq!(mean(&[]), None);
+        assert_eq!(mean(&[2.]), Some(2.));
+        assert_eq!(mean(&[1., 2., 6.]), Some(3.));
+    }
+
+    #[test]
+    fn test_jitter() {
+        assert_eq!(jitter(&[]), None);
+        assert_eq!(jitter(&[10.]), None);
+        assert_eq!(jitter(&[10., 10., 10.]), Some(0.));
+        // deviations: 10, 20, 0
+        assert_eq!(jitter(&[10., 20., 0., 0.]), Some(10.));
+    }
+
+    #[test]
+    fn test_to_mbps() {
+        assert_eq!(to_mbps(0.), 0.);
+        assert_eq!(to_mbps(125_000.), 1.);
+        assert_eq!(to_mbps(12_500_000.), 100.);
+    }
+}
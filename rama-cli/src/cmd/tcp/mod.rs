@@ -0,0 +1,195 @@
+//! rama tcp client
+
+use clap::Args;
+use rama::{
+    error::{BoxError, ErrorContext, OpaqueError},
+    http::client::proxy::layer::HttpProxyConnector,
+    net::{
+        address::{Authority, ProxyAddress},
+        client::{ConnectorService, EstablishedClientConnection},
+        tls::client::{ClientConfig, ServerVerifyMode},
+        user::ProxyCredential,
+    },
+    tcp::client::{service::TcpConnector, Request as TcpRequest},
+    tls::std::client::{TlsConnector, TlsConnectorData},
+    Context,
+};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
+use tracing::level_filters::LevelFilter;
+use tracing_subscriber::{fmt, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
+
+#[derive(Debug, Args)]
+/// rama tcp client (netcat-like, pipes stdin and stdout over a raw tcp connection)
+pub struct CliCommandTcp {
+    #[arg(long, short = 's')]
+    /// establish a TLS connection on top of the tcp connection
+    secure: bool,
+
+    #[arg(short = 'k', long, requires = "secure")]
+    /// skip Tls certificate verification
+    insecure: bool,
+
+    #[arg(long, short = 'P')]
+    /// upstream (http) proxy to use, tunneling via the CONNECT method
+    ///
+    /// The PROXY env variable is used if defined and this option is not set.
+    proxy: Option<String>,
+
+    #[arg(long, short = 'U')]
+    /// upstream proxy user credentials to use (or overwrite)
+    proxy_user: Option<String>,
+
+    #[arg(long, short = 't', default_value_t = 30)]
+    /// the timeout in seconds to establish the connection (0 = no timeout)
+    timeout: u64,
+
+    #[arg(long)]
+    /// print debug info
+    debug: bool,
+
+    /// the address to connect to (e.g. `example.com:80`)
+    address: Authority,
+}
+
+/// Run the rama tcp command.
+pub async fn run(cfg: CliCommandTcp) -> Result<(), BoxError> {
+    tracing_subscriber::registry()
+        .with(fmt::layer().with_writer(std::io::stderr))
+        .with(
+            EnvFilter::builder()
+                .with_default_directive(
+                    if cfg.debug {
+                        LevelFilter::DEBUG
+                    } else {
+                        LevelFilter::ERROR
+                    }
+                    .into(),
+                )
+                .from_env_lossy(),
+        )
+        .init();
+
+    let mut ctx = Context::default();
+    if let Some(proxy_address) = proxy_address(&cfg)? {
+        tracing::debug!(proxy = %proxy_address.authority, "connect via upstream proxy");
+        ctx.insert(proxy_address);
+    }
+
+    let server_verify_mode = cfg.insecure.then_some(ServerVerifyMode::Disable);
+    let proxy_tls_connector_data: TlsConnectorData = ClientConfig {
+        server_verify_mode,
+        ..Default::default()
+    }
+    .try_into()
+    .context("create proxy tls connector data")?;
+    let transport_connector = HttpProxyConnector::optional(
+        TlsConnector::tunnel(TcpConnector::new(), None)
+            .with_connector_data(proxy_tls_connector_data),
+    );
+
+    let req = TcpRequest::new(cfg.address.clone());
+
+    if cfg.secure {
+        let tls_connector_data: TlsConnectorData = ClientConfig {
+            server_verify_mode,
+            ..Default::default()
+        }
+        .try_into()
+        .context("create tls connector data")?;
+        let connector =
+            TlsConnector::secure(transport_connector).with_connector_data(tls_connector_data);
+        let conn = connect(&cfg, &connector, ctx, req).await?;
+        pipe(conn, false).await
+    } else {
+        let conn = connect(&cfg, &transport_connector, ctx, req).await?;
+        pipe(conn, true).await
+    }
+}
+
+fn proxy_address(cfg: &CliCommandTcp) -> Result<Option<ProxyAddress>, OpaqueError> {
+    let proxy = match cfg.proxy.clone() {
+        Some(proxy) => proxy,
+        None => match std::env::var("PROXY") {
+            Ok(proxy) if !proxy.is_empty() => proxy,
+            _ => return Ok(None),
+        },
+    };
+    let mut proxy_address: ProxyAddress = proxy.parse().context("parse proxy address")?;
+    if let Some(proxy_user) = cfg.proxy_user.clone() {
+        let credential =
+            ProxyCredential::try_from_clear_str(proxy_user).context("parse proxy credentials")?;
+        proxy_address.credential = Some(credential);
+    }
+    Ok(Some(proxy_address))
+}
+
+async fn connect<C>(
+    cfg: &CliCommandTcp,
+    connector: &C,
+    ctx: Context<()>,
+    req: TcpRequest,
+) -> Result<C::Connection, BoxError>
+where
+    C: ConnectorService<(), TcpRequest, Error: Into<BoxError>>,
+{
+    let connect = connector.connect(ctx, req);
+    let result = if cfg.timeout > 0 {
+        tokio::time::timeout(Duration::from_secs(cfg.timeout), connect)
+            .await
+            .map_err(|_| OpaqueError::from_display("connect timeout"))
+            .with_context(|| format!("connect to {}", cfg.address))?
+    } else {
+        connect.await
+    };
+    let EstablishedClientConnection { conn, .. } = result
+        .map_err(|err| OpaqueError::from_boxed(err.into()))
+        .with_context(|| format!("connect to {}", cfg.address))?;
+    tracing::debug!(address = %cfg.address, "connection established");
+    Ok(conn)
+}
+
+/// Pipe stdin to the connection and the connection to stdout,
+/// until the connection is closed by the peer.
+///
+/// The write side of plain tcp connections is shut down when stdin reaches EOF,
+/// such that the peer knows no more data will follow. For TLS connections this
+/// is not done as a `close_notify` alert usually results in the peer closing
+/// the connection entirely, possibly before it responded.
+async fn pipe<S>(conn: S, half_close: bool) -> Result<(), BoxError>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let (mut reader, mut writer) = tokio::io::split(conn);
+
+    let send = async {
+        let sent = tokio::io::copy(&mut tokio::io::stdin(), &mut writer).await?;
+        writer.flush().await?;
+        if half_close {
+            writer.shutdown().await?;
+        }
+        tracing::debug!(sent, "stdin reached EOF");
+        // keep receiving until the peer closes the connection
+        std::future::pending::<std::io::Result<()>>().await
+    };
+
+    let receive = async {
+        let mut stdout = tokio::io::stdout();
+        let received = match tokio::io::copy(&mut reader, &mut stdout).await {
+            Ok(received) => received,
+            // peers often close TLS connections without a close_notify alert
+            Err(err) if err.kind() == std::io::ErrorKind::UnexpectedEof => 0,
+            Err(err) => return Err(err),
+        };
+        stdout.flush().await?;
+        tracing::debug!(received, "connection closed by peer");
+        Ok(())
+    };
+
+    tokio::select! {
+        result = send => result,
+        result = receive => result,
+    }
+    .context("pipe stdin/stdout over tcp connection")?;
+    Ok(())
+}
@@ -0,0 +1,180 @@
+//! rama top: live monitoring of a running rama proxy
+
+use crate::cmd::proxy::admin::{AdminStats, STATS_PATH};
+use clap::Args;
+use rama::{
+    error::{BoxError, ErrorContext, OpaqueError},
+    http::{client::HttpClient, service::client::HttpClientExt, BodyExtractExt, Request, Response},
+    Context, Service,
+};
+use std::time::{Duration, Instant};
+use tokio::io::AsyncWriteExt;
+
+/// clear the screen and move the cursor to the top left
+const CLEAR_SCREEN: &str = "\x1b[2J\x1b[H";
+const BOLD: &str = "\x1b[1m";
+const RED: &str = "\x1b[31m";
+const GREEN: &str = "\x1b[32m";
+const RESET: &str = "\x1b[0m";
+
+#[derive(Args, Debug, Clone)]
+/// rama top (live dashboard of a rama proxy, served with `rama proxy --admin <ADDRESS>`)
+pub struct CliCommandTop {
+    #[arg(default_value = "127.0.0.1:8081")]
+    /// the address of the admin api of the rama proxy to monitor
+    admin: String,
+
+    #[arg(long, short = 'n', default_value_t = 1)]
+    /// the refresh interval in seconds
+    interval: u64,
+
+    #[arg(long, short = 'l', default_value_t = 20)]
+    /// the maximum amount of hosts to show
+    limit: usize,
+}
+
+/// Run the top command.
+pub async fn run(cfg: CliCommandTop) -> Result<(), BoxError> {
+    let url = format!("http://{}{STATS_PATH}", cfg.admin);
+    let client = HttpClient::default();
+    let mut interval = tokio::time::interval(Duration::from_secs(cfg.interval.max(1)));
+    let mut stdout = tokio::io::stdout();
+    let mut previous: Option<(Instant, u64)> = None;
+
+    loop {
+        tokio::select! {
+            _ = interval.tick() => (),
+            _ = tokio::signal::ctrl_c() => return Ok(()),
+        }
+
+        let screen = match fetch_stats(&client, &url).await {
+            Ok(stats) => {
+                let now = Instant::now();
+                // rps is only known from the second sample onwards
+                let rps = previous.and_then(|(at, requests)| {
+                    let elapsed = now.duration_since(at).as_secs_f64();
+                    let delta = stats.total_requests.checked_sub(requests)?;
+                    (elapsed > 0.).then(|| delta as f64 / elapsed)
+                });
+                previous = Some((now, stats.total_requests));
+                render(&cfg.admin, &stats, rps, cfg.limit)
+            }
+            Err(err) => {
+                previous = None;
+                render_down(&cfg.admin, &err)
+            }
+        };
+
+        stdout.write_all(CLEAR_SCREEN.as_bytes()).await?;
+        stdout.write_all(screen.as_bytes()).await?;
+        stdout.flush().await?;
+    }
+}
+
+async fn fetch_stats<S>(client: &S, url: &str) -> Result<AdminStats, OpaqueError>
+where
+    S: Service<(), Request, Response = Response, Error = OpaqueError>,
+{
+    let resp = client
+        .get(url)
+        .send(Context::default())
+        .await
+        .context("fetch proxy stats")?;
+    if !resp.status().is_success() {
+        return Err(OpaqueError::from_display(format!(
+            "unexpected admin api status: {}",
+            resp.status()
+        )));
+    }
+    resp.try_into_json()
+        .await
+        .context("decode proxy stats as json")
+}
+
+fn render(admin: &str, stats: &AdminStats, rps: Option<f64>, limit: usize) -> String {
+    let requests: u64 = stats.hosts.iter().map(|host| host.requests).sum();
+    let errors: u64 = stats.hosts.iter().map(|host| host.errors).sum();
+    let error_rate = if requests > 0 {
+        errors as f64 / requests as f64 * 100.
+    } else {
+        0.
+    };
+
+    let mut output = format!(
+        "{BOLD}rama top{RESET} — {admin} — {GREEN}UP{RESET} {}\n\n",
+        format_duration(stats.uptime_secs)
+    );
+    output.push_str(&format!(
+        "connections: {} active, {} total    requests: {} total, {} rps    errors (1h): {:.1}%\n\n",
+        stats.active_connections,
+        stats.total_connections,
+        stats.total_requests,
+        rps.map(|rps| format!("{rps:.1}"))
+            .unwrap_or_else(|| "-".to_owned()),
+        error_rate,
+    ));
+
+    let host_width = stats
+        .hosts
+        .iter()
+        .take(limit)
+        .map(|host| host.host.len())
+        .max()
+        .unwrap_or_default()
+        .max(4);
+    output.push_str(&format!(
+        "{BOLD}{:<host_width$}  {:>10}  {:>8}  {:>7}  {:>10}  {:>10}  {:>10}{RESET}\n",
+        "HOST", "REQUESTS", "ERRORS", "ERR%", "SENT", "RECEIVED", "TTFB"
+    ));
+    for host in stats.hosts.iter().take(limit) {
+        let line = format!(
+            "{:<host_width$}  {:>10}  {:>8}  {:>6.1}%  {:>10}  {:>10}  {:>8.1}ms",
+            host.host,
+            host.requests,
+            host.errors,
+            host.error_rate * 100.,
+            format_bytes(host.bytes_sent),
+            format_bytes(host.bytes_received),
+            host.mean_ttfb_ms,
+        );
+        if host.errors > 0 {
+            output.push_str(&format!("{RED}{line}{RESET}\n"));
+        } else {
+            output.push_str(&line);
+            output.push('\n');
+        }
+    }
+    if stats.hosts.len() > limit {
+        output.push_str(&format!("... and {} more\n", stats.hosts.len() - limit));
+    }
+    output
+}
+
+fn render_down(admin: &str, err: &OpaqueError) -> String {
+    format!("{BOLD}rama top{RESET} — {admin} — {RED}DOWN{RESET}\n\n{err}\n")
+}
+
+fn format_duration(secs: u64) -> String {
+    format!(
+        "{}d {:02}:{:02}:{:02}",
+        secs / 86400,
+        secs % 86400 / 3600,
+        secs % 3600 / 60,
+        secs % 60
+    )
+}
+
+fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024. && unit < UNITS.len() - 1 {
+        value /= 1024.;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{bytes} B")
+    } else {
+        format!("{value:.1} {}", UNITS[unit])
+    }
+}
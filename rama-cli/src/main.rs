@@ -8,7 +8,7 @@ use clap::{Parser, Subcommand};
 use rama::error::BoxError;
 
 pub mod cmd;
-use cmd::{echo, fp, http, ip, proxy};
+use cmd::{cert, echo, fp, http, ip, proxy, reverse_proxy, speed, tcp, top};
 
 pub mod error;
 
@@ -29,6 +29,11 @@ enum CliCommands {
     Echo(echo::CliCommandEcho),
     Ip(ip::CliCommandIp),
     Fp(fp::CliCommandFingerprint),
+    Speed(speed::CliCommandSpeed),
+    Cert(cert::CliCommandCert),
+    Tcp(tcp::CliCommandTcp),
+    ReverseProxy(reverse_proxy::CliCommandReverseProxy),
+    Top(top::CliCommandTop),
 }
 
 #[tokio::main]
@@ -42,6 +47,11 @@ async fn main() -> Result<(), BoxError> {
         CliCommands::Echo(cfg) => echo::run(cfg).await,
         CliCommands::Ip(cfg) => ip::run(cfg).await,
         CliCommands::Fp(cfg) => fp::run(cfg).await,
+        CliCommands::Speed(cfg) => speed::run(cfg).await,
+        CliCommands::Cert(cfg) => cert::run(cfg).await,
+        CliCommands::Tcp(cfg) => tcp::run(cfg).await,
+        CliCommands::ReverseProxy(cfg) => reverse_proxy::run(cfg).await,
+        CliCommands::Top(cfg) => top::run(cfg).await,
     } {
         Ok(()) => Ok(()),
         Err(err) => {
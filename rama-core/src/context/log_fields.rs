@@ -0,0 +1,188 @@
+use super::Extensions;
+use std::any::TypeId;
+use std::fmt;
+use std::sync::{PoisonError, RwLock};
+
+/// An extension which can be exported as structured log fields.
+///
+/// Extensions implementing this trait can be registered using [`register_log_fields`],
+/// after which their fields are included in the [`LogFieldSet`] collected
+/// by [`Extensions::log_fields`] (and thus [`Context::log_fields`]),
+/// as used by the tracing and access log layers.
+///
+/// [`Context::log_fields`]: crate::Context::log_fields
+pub trait LogFields: Send + Sync + 'static {
+    /// Record the fields of this extension in the given [`LogFieldSet`].
+    fn log_fields(&self, fields: &mut LogFieldSet);
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+/// An ordered set of structured log fields, as recorded by [`LogFields`] extensions.
+///
+/// Formatted using [`fmt::Display`] it renders the fields in the logfmt style,
+/// e.g. `request_id=42 user=john`, quoting values where needed.
+pub struct LogFieldSet {
+    fields: Vec<(&'static str, String)>,
+}
+
+impl LogFieldSet {
+    /// Create a new empty [`LogFieldSet`].
+    pub const fn new() -> Self {
+        Self { fields: Vec::new() }
+    }
+
+    /// Record a field, overwriting the value of a previously recorded field with the same name.
+    pub fn record(&mut self, name: &'static str, value: impl fmt::Display) -> &mut Self {
+        let value = value.to_string();
+        match self.fields.iter_mut().find(|(n, _)| *n == name) {
+            Some((_, v)) => *v = value,
+            None => self.fields.push((name, value)),
+        }
+        self
+    }
+
+    /// Get the value of the field with the given name, if recorded.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.fields
+            .iter()
+            .find_map(|(n, v)| (*n == name).then_some(v.as_str()))
+    }
+
+    /// Iterate over the recorded fields, in the order they were recorded.
+    pub fn iter(&self) -> impl Iterator<Item = (&'static str, &str)> {
+        self.fields.iter().map(|(n, v)| (*n, v.as_str()))
+    }
+
+    /// Returns the amount of recorded fields.
+    pub fn len(&self) -> usize {
+        self.fields.len()
+    }
+
+    /// Returns `true` in case no fields were recorded.
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+}
+
+impl fmt::Display for LogFieldSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (index, (name, value)) in self.fields.iter().enumerate() {
+            if index > 0 {
+                f.write_str(" ")?;
+            }
+            if value.is_empty()
+                || value
+                    .chars()
+                    .any(|c| c.is_whitespace() || c == '"' || c == '=')
+            {
+                write!(f, "{name}={value:?}")?;
+            } else {
+                write!(f, "{name}={value}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+type Collector = fn(&Extensions, &mut LogFieldSet);
+
+static REGISTRY: RwLock<Vec<(TypeId, Collector)>> = RwLock::new(Vec::new());
+
+/// Register the extension `T` such that its [`LogFields`]
+/// are collected by [`Extensions::log_fields`].
+///
+/// Registering the same type multiple times has no effect,
+/// so it is fine to (also) call this from the constructor of a layer
+/// which inserts the extension.
+pub fn register_log_fields<T: LogFields>() {
+    let type_id = TypeId::of::<T>();
+    let is_registered =
+        |registry: &[(TypeId, Collector)]| registry.iter().any(|(id, _)| *id == type_id);
+
+    if is_registered(&REGISTRY.read().unwrap_or_else(PoisonError::into_inner)) {
+        return;
+    }
+    let mut registry = REGISTRY.write().unwrap_or_else(PoisonError::into_inner);
+    if !is_registered(&registry) {
+        registry.push((type_id, |ext, fields| {
+            if let Some(value) = ext.get::<T>() {
+                value.log_fields(fields);
+            }
+        }));
+    }
+}
+
+impl Extensions {
+    /// Collect the [`LogFields`] of all registered extensions found in these [`Extensions`].
+    ///
+    /// See [`register_log_fields`] for more information.
+    pub fn log_fields(&self) -> LogFieldSet {
+        let mut fields = LogFieldSet::new();
+        for (_, collect) in REGISTRY
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .iter()
+        {
+            collect(self, &mut fields);
+        }
+        fields
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Clone)]
+    struct RequestNumber(u64);
+
+    impl LogFields for RequestNumber {
+        fn log_fields(&self, fields: &mut LogFieldSet) {
+            fields.record("request_number", self.0);
+        }
+    }
+
+    #[derive(Debug, Clone)]
+    struct Peer(&'static str);
+
+    impl LogFields for Peer {
+        fn log_fields(&self, fields: &mut LogFieldSet) {
+            fields.record("peer", self.0).record("peer_known", true);
+        }
+    }
+
+    #[test]
+    fn test_log_fields_registered_only() {
+        let mut ext = Extensions::new();
+        ext.insert(RequestNumber(42));
+        ext.insert(Peer("home sweet home"));
+
+        register_log_fields::<RequestNumber>();
+        register_log_fields::<RequestNumber>();
+        let fields = ext.log_fields();
+        assert_eq!(fields.len(), 1);
+        assert_eq!(fields.get("request_number"), Some("42"));
+        assert_eq!(fields.to_string(), "request_number=42");
+
+        register_log_fields::<Peer>();
+        let fields = ext.log_fields();
+        assert_eq!(fields.get("peer"), Some("home sweet home"));
+        assert_eq!(
+            fields.to_string(),
+            r#"request_number=42 peer="home sweet home" peer_known=true"#
+        );
+
+        assert!(Extensions::new().log_fields().is_empty());
+    }
+
+    #[test]
+    fn test_log_field_set_record_overwrites() {
+        let mut fields = LogFieldSet::new();
+        fields.record("a", 1).record("b", "").record("a", 2);
+        assert_eq!(
+            fields.iter().collect::<Vec<_>>(),
+            vec![("a", "2"), ("b", "")]
+        );
+        assert_eq!(fields.to_string(), r#"a=2 b="""#);
+    }
+}
@@ -89,6 +89,33 @@
 //!   depend on a specific state type, but instead only require a reference (mutable or not)
 //!   to specific properties they need, which can be useful in case that service
 //!   is used in multiple branches, each with their own concrete _state_ type.
+//!
+//! ## Log Fields
+//!
+//! Extensions can opt into being exported as structured log fields by implementing
+//! [`LogFields`] and getting registered using [`register_log_fields`]. Layers such as
+//! the tracing and access log layers of `rama-http` include the fields of all registered
+//! extensions found in the [`Context`] automatically.
+//!
+//! ```
+//! use rama_core::Context;
+//! use rama_core::context::{register_log_fields, LogFieldSet, LogFields};
+//!
+//! #[derive(Debug, Clone)]
+//! struct TenantId(u32);
+//!
+//! impl LogFields for TenantId {
+//!     fn log_fields(&self, fields: &mut LogFieldSet) {
+//!         fields.record("tenant_id", self.0);
+//!     }
+//! }
+//!
+//! register_log_fields::<TenantId>();
+//!
+//! let mut ctx = Context::default();
+//! ctx.insert(TenantId(7));
+//! assert_eq!(ctx.log_fields().to_string(), "tenant_id=7");
+//! ```
 
 use crate::graceful::ShutdownGuard;
 use crate::rt::Executor;
@@ -99,6 +126,10 @@ mod extensions;
 #[doc(inline)]
 pub use extensions::Extensions;
 
+mod log_fields;
+#[doc(inline)]
+pub use log_fields::{register_log_fields, LogFieldSet, LogFields};
+
 /// Context passed to and between services as input.
 ///
 /// See [`crate::context`] for more information.
@@ -452,6 +483,13 @@ impl<S> Context<S> {
         &self.extensions
     }
 
+    /// Collect the [`LogFields`] of all registered extensions found in the [`Context`].
+    ///
+    /// See [`Extensions::log_fields`] and [`register_log_fields`] for more information.
+    pub fn log_fields(&self) -> LogFieldSet {
+        self.extensions.log_fields()
+    }
+
     /// Return the entire dynamic state of the [`Context`] by mutable reference.
     ///
     /// Useful only in case you have a function which works with [`Extensions`] rather
@@ -1,4 +1,4 @@
-//! A middleware that limits the number of in-flight requests.
+//! A middleware that limits the number of in-flight requests, or the rate at which they are made.
 //!
 //! See [`Limit`].
 
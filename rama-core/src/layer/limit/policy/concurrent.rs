@@ -1,6 +1,7 @@
 //! A [`Policy`] that limits the number of concurrent requests.
 //!
-//! See [`ConcurrentPolicy`].
+//! See [`ConcurrentPolicy`], and [`KeyedConcurrentPolicy`]
+//! to limit the number of concurrent requests per key (e.g. per client ip) instead.
 //!
 //! # Examples
 //!
@@ -23,11 +24,13 @@
 //! # }
 //! ```
 
-use super::{Policy, PolicyOutput, PolicyResult};
+use super::{Policy, PolicyOutput, PolicyResult, RateLimitKey};
 use crate::Context;
 use parking_lot::Mutex;
 use rama_utils::backoff::Backoff;
+use std::collections::HashMap;
 use std::fmt;
+use std::hash::Hash;
 use std::sync::Arc;
 
 /// A [`Policy`] that limits the number of concurrent requests.
@@ -214,6 +217,160 @@ impl Drop for ConcurrentCounterGuard {
     }
 }
 
+/// A [`Policy`] that limits the number of concurrent requests per key,
+/// as produced by a [`RateLimitKey`] (e.g. the client ip or the proxy user).
+///
+/// Requests for which no key is produced are not limited.
+/// When the limit is reached for a key, the request is aborted with [`LimitReached`],
+/// unless a [`Backoff`] is used, in which case the request is retried for as long
+/// as the backoff allows it.
+///
+/// Cloning the policy is cheap, and all clones share the same state.
+pub struct KeyedConcurrentPolicy<K, Key, B = ()> {
+    max: usize,
+    key: K,
+    backoff: B,
+    current: Arc<Mutex<HashMap<Key, usize>>>,
+}
+
+impl<K: fmt::Debug, Key, B: fmt::Debug> fmt::Debug for KeyedConcurrentPolicy<K, Key, B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("KeyedConcurrentPolicy")
+            .field("max", &self.max)
+            .field("key", &self.key)
+            .field("backoff", &self.backoff)
+            .finish()
+    }
+}
+
+impl<K: Clone, Key, B: Clone> Clone for KeyedConcurrentPolicy<K, Key, B> {
+    fn clone(&self) -> Self {
+        Self {
+            max: self.max,
+            key: self.key.clone(),
+            backoff: self.backoff.clone(),
+            current: self.current.clone(),
+        }
+    }
+}
+
+impl<K, Key> KeyedConcurrentPolicy<K, Key, ()> {
+    /// Create a new [`KeyedConcurrentPolicy`], allowing at most `max` concurrent
+    /// requests per key, as produced by the given [`RateLimitKey`].
+    pub fn new(max: usize, key: K) -> Self {
+        Self::with_backoff(max, key, ())
+    }
+}
+
+impl<K, Key, B> KeyedConcurrentPolicy<K, Key, B> {
+    /// Create a new [`KeyedConcurrentPolicy`], allowing at most `max` concurrent
+    /// requests per key, as produced by the given [`RateLimitKey`],
+    /// and which backs off if the limit is reached, using the given backoff policy.
+    pub fn with_backoff(max: usize, key: K, backoff: B) -> Self {
+        Self {
+            max,
+            key,
+            backoff,
+            current: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+}
+
+impl<K, Key: Hash + Eq + Clone, B> KeyedConcurrentPolicy<K, Key, B> {
+    fn try_access(&self, key: Key) -> Result<KeyedConcurrentGuard<Key>, LimitReached> {
+        let mut current = self.current.lock();
+        let count = current.entry(key.clone()).or_default();
+        if *count < self.max {
+            *count += 1;
+            Ok(KeyedConcurrentGuard {
+                key: Some(key),
+                current: self.current.clone(),
+            })
+        } else {
+            if *count == 0 {
+                // only possible for a max of 0, do not keep track of unused keys
+                current.remove(&key);
+            }
+            Err(LimitReached)
+        }
+    }
+}
+
+impl<K, B, State, Request> Policy<State, Request> for KeyedConcurrentPolicy<K, K::Key, B>
+where
+    K: RateLimitKey<State, Request, Key: Clone>,
+    B: Backoff,
+    State: Clone + Send + Sync + 'static,
+    Request: Send + 'static,
+{
+    type Guard = KeyedConcurrentGuard<K::Key>;
+    type Error = LimitReached;
+
+    async fn check(
+        &self,
+        ctx: Context<State>,
+        request: Request,
+    ) -> PolicyResult<State, Request, Self::Guard, Self::Error> {
+        let Some(key) = self.key.key(&ctx, &request) else {
+            return PolicyResult {
+                ctx,
+                request,
+                output: PolicyOutput::Ready(KeyedConcurrentGuard {
+                    key: None,
+                    current: self.current.clone(),
+                }),
+            };
+        };
+
+        let output = match self.try_access(key) {
+            Ok(guard) => PolicyOutput::Ready(guard),
+            Err(err) => {
+                if self.backoff.next_backoff().await {
+                    PolicyOutput::Retry
+                } else {
+                    PolicyOutput::Abort(err)
+                }
+            }
+        };
+
+        PolicyResult {
+            ctx,
+            request,
+            output,
+        }
+    }
+}
+
+/// The guard for [`KeyedConcurrentPolicy`] that releases
+/// the concurrent request limit of its key when dropped.
+pub struct KeyedConcurrentGuard<Key: Hash + Eq> {
+    key: Option<Key>,
+    current: Arc<Mutex<HashMap<Key, usize>>>,
+}
+
+impl<Key: Hash + Eq + fmt::Debug> fmt::Debug for KeyedConcurrentGuard<Key> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("KeyedConcurrentGuard")
+            .field("key", &self.key)
+            .finish()
+    }
+}
+
+impl<Key: Hash + Eq> Drop for KeyedConcurrentGuard<Key> {
+    fn drop(&mut self) {
+        let Some(key) = self.key.take() else {
+            return;
+        };
+        let mut current = self.current.lock();
+        if let Some(count) = current.get_mut(&key) {
+            *count -= 1;
+            if *count == 0 {
+                current.remove(&key);
+            }
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -272,4 +429,33 @@ mod tests {
         drop(guard_1);
         assert_ready(policy.check(Context::default(), ()).await);
     }
+
+    #[tokio::test]
+    async fn keyed_concurrent_policy() {
+        let policy = KeyedConcurrentPolicy::new(1, |_: &Context<()>, req: &Option<u8>| *req);
+
+        let guard_1 = assert_ready(policy.check(Context::default(), Some(1)).await);
+        assert_abort(policy.check(Context::default(), Some(1)).await);
+
+        // other keys have their own limit
+        let guard_2 = assert_ready(policy.check(Context::default(), Some(2)).await);
+
+        // requests without a key are not limited
+        let _guard_3 = assert_ready(policy.check(Context::default(), None).await);
+        let _guard_4 = assert_ready(policy.check(Context::default(), None).await);
+
+        drop(guard_1);
+        let _guard_5 = assert_ready(policy.check(Context::default(), Some(1)).await);
+        assert_abort(policy.check(Context::default(), Some(1)).await);
+
+        drop(guard_2);
+        assert_eq!(policy.current.lock().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn keyed_concurrent_policy_zero() {
+        let policy = KeyedConcurrentPolicy::new(0, ());
+        assert_abort(policy.check(Context::default(), ()).await);
+        assert!(policy.current.lock().is_empty());
+    }
 }
@@ -0,0 +1,284 @@
+//! A [`Policy`] that sheds load when the service is overloaded.
+//!
+//! See [`LoadShedPolicy`].
+//!
+//! # Examples
+//!
+//! ```
+//! use rama_core::layer::limit::{Limit, policy::LoadShedPolicy};
+//! use rama_core::service::service_fn;
+//! use rama_core::{Context, Service};
+//! use std::time::Duration;
+//! # use std::convert::Infallible;
+//!
+//! # #[tokio::main]
+//! # async fn main() {
+//!
+//! let service = service_fn(|_, _| async {
+//!     Ok::<_, Infallible>(())
+//! });
+//! // start shedding load once requests take longer than 250ms on average,
+//! // or once more than 512 requests are in flight
+//! let policy = LoadShedPolicy::new(Duration::from_millis(250)).max_in_flight(512);
+//! let service = Limit::new(service, policy);
+//!
+//! let response = service.serve(Context::default(), ()).await;
+//! assert!(response.is_ok());
+//! # }
+//! ```
+
+use super::{Policy, PolicyOutput, PolicyResult};
+use crate::time::Clock;
+use crate::Context;
+use parking_lot::Mutex;
+use std::fmt;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Weight of a new latency sample in the moving average.
+const LATENCY_SMOOTHING: f64 = 0.2;
+
+/// Default for the max ratio of requests that is shed.
+const DEFAULT_MAX_SHED_RATIO: f64 = 0.95;
+
+rama_utils::macros::error::static_str_error! {
+    #[doc = "request shed due to an overloaded service"]
+    pub struct LoadShed;
+}
+
+/// A [`Policy`] that rejects a fraction of the requests with [`LoadShed`]
+/// when the service is overloaded, such that it can degrade gracefully
+/// instead of falling over.
+///
+/// The load is measured by:
+///
+/// - the (exponential moving) average latency of the requests,
+///   compared to the target latency;
+/// - the amount of requests in flight (the queue depth),
+///   compared to the max in flight, if defined.
+///
+/// Once one of these exceeds its target, the policy starts shedding
+/// the excess fraction of the requests (e.g. half of the requests
+/// when the average latency is twice the target latency), up to the
+/// [max shed ratio](LoadShedPolicy::max_shed_ratio), such that some requests
+/// keep going through and the load can be measured while recovering.
+///
+/// Latency is measured from the moment a request is allowed
+/// until its guard is dropped, which usually is when the response is returned.
+///
+/// Cloning the policy is cheap, and all clones share the same state.
+#[derive(Debug, Clone)]
+pub struct LoadShedPolicy {
+    target_latency: Duration,
+    max_in_flight: Option<usize>,
+    max_shed_ratio: f64,
+    state: Arc<Mutex<LoadShedState>>,
+}
+
+#[derive(Debug, Default)]
+struct LoadShedState {
+    /// moving average of the latency, in seconds
+    latency: f64,
+    in_flight: usize,
+    /// accumulated shed ratio, a request is shed each time it reaches 1
+    shed_credit: f64,
+}
+
+impl LoadShedPolicy {
+    /// Create a new [`LoadShedPolicy`], shedding load once
+    /// the average latency of requests exceeds the given target latency.
+    pub fn new(target_latency: Duration) -> Self {
+        Self {
+            target_latency,
+            max_in_flight: None,
+            max_shed_ratio: DEFAULT_MAX_SHED_RATIO,
+            state: Default::default(),
+        }
+    }
+
+    /// Also shed load once more than `max` requests are in flight.
+    pub fn max_in_flight(mut self, max: usize) -> Self {
+        self.max_in_flight = Some(max);
+        self
+    }
+
+    /// Also shed load once more than `max` requests are in flight.
+    pub fn set_max_in_flight(&mut self, max: usize) -> &mut Self {
+        self.max_in_flight = Some(max);
+        self
+    }
+
+    /// Define the max ratio (between `0` and `1`) of requests which are shed.
+    ///
+    /// Defaults to `0.95`.
+    pub fn max_shed_ratio(mut self, ratio: f64) -> Self {
+        self.max_shed_ratio = ratio.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Define the max ratio (between `0` and `1`) of requests which are shed.
+    ///
+    /// Defaults to `0.95`.
+    pub fn set_max_shed_ratio(&mut self, ratio: f64) -> &mut Self {
+        self.max_shed_ratio = ratio.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Returns the ratio of requests which are currently shed.
+    pub fn shed_ratio(&self) -> f64 {
+        let state = self.state.lock();
+        self.compute_shed_ratio(&state)
+    }
+
+    fn compute_shed_ratio(&self, state: &LoadShedState) -> f64 {
+        let mut load = state.latency / self.target_latency.as_secs_f64().max(f64::EPSILON);
+        if let Some(max) = self.max_in_flight {
+            load = load.max(state.in_flight as f64 / max.max(1) as f64);
+        }
+        if load <= 1.0 {
+            return 0.0;
+        }
+        (1.0 - 1.0 / load).min(self.max_shed_ratio)
+    }
+
+    /// Returns `true` in case the next request has to be shed,
+    /// spreading the shed requests evenly according to the current shed ratio.
+    fn should_shed(&self, state: &mut LoadShedState) -> bool {
+        let ratio = self.compute_shed_ratio(state);
+        if ratio > 0.0 {
+            state.shed_credit += ratio;
+            if state.shed_credit >= 1.0 {
+                state.shed_credit -= 1.0;
+                return true;
+            }
+        } else {
+            state.shed_credit = 0.0;
+        }
+        false
+    }
+
+    fn try_admit(&self, clock: Clock) -> Result<LoadShedGuard, LoadShed> {
+        let mut state = self.state.lock();
+        if self.should_shed(&mut state) {
+            return Err(LoadShed);
+        }
+        state.in_flight += 1;
+        Ok(LoadShedGuard {
+            start: clock.now(),
+            clock,
+            state: self.state.clone(),
+        })
+    }
+}
+
+impl LoadShedState {
+    fn record(&mut self, latency: Duration) {
+        let sample = latency.as_secs_f64();
+        self.latency = if self.latency == 0.0 {
+            sample
+        } else {
+            self.latency
+                .mul_add(1.0 - LATENCY_SMOOTHING, sample * LATENCY_SMOOTHING)
+        };
+    }
+}
+
+impl<State, Request> Policy<State, Request> for LoadShedPolicy
+where
+    State: Clone + Send + Sync + 'static,
+    Request: Send + 'static,
+{
+    type Guard = LoadShedGuard;
+    type Error = LoadShed;
+
+    async fn check(
+        &self,
+        ctx: Context<State>,
+        request: Request,
+    ) -> PolicyResult<State, Request, Self::Guard, Self::Error> {
+        let output = match self.try_admit(ctx.clock()) {
+            Ok(guard) => PolicyOutput::Ready(guard),
+            Err(err) => PolicyOutput::Abort(err),
+        };
+        PolicyResult {
+            ctx,
+            request,
+            output,
+        }
+    }
+}
+
+/// The guard for [`LoadShedPolicy`] that records the latency
+/// of the request and releases it from the in flight requests when dropped.
+pub struct LoadShedGuard {
+    start: Instant,
+    clock: Clock,
+    state: Arc<Mutex<LoadShedState>>,
+}
+
+impl fmt::Debug for LoadShedGuard {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LoadShedGuard")
+            .field("start", &self.start)
+            .finish()
+    }
+}
+
+impl Drop for LoadShedGuard {
+    fn drop(&mut self) {
+        let mut state = self.state.lock();
+        state.in_flight -= 1;
+        state.record(self.clock.now().saturating_duration_since(self.start));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shed_count(policy: &LoadShedPolicy, requests: usize) -> usize {
+        // admitted requests are not tracked, such that their latency is not recorded
+        (0..requests)
+            .filter(|_| policy.should_shed(&mut policy.state.lock()))
+            .count()
+    }
+
+    #[test]
+    fn load_shed_latency() {
+        let policy = LoadShedPolicy::new(Duration::from_millis(100));
+        assert_eq!(shed_count(&policy, 100), 0);
+
+        policy.state.lock().record(Duration::from_millis(200));
+        assert!((policy.shed_ratio() - 0.5).abs() < 1e-9);
+        assert!((49..=51).contains(&shed_count(&policy, 100)));
+
+        // recovers as latency goes down
+        for _ in 0..50 {
+            policy.state.lock().record(Duration::from_millis(10));
+        }
+        assert!(policy.shed_ratio() == 0.0);
+        assert_eq!(shed_count(&policy, 100), 0);
+    }
+
+    #[test]
+    fn load_shed_max_ratio() {
+        let policy = LoadShedPolicy::new(Duration::from_millis(1)).max_shed_ratio(0.9);
+        policy.state.lock().record(Duration::from_secs(10));
+        assert!((policy.shed_ratio() - 0.9).abs() < 1e-9);
+        assert!((89..=91).contains(&shed_count(&policy, 100)));
+    }
+
+    #[test]
+    fn load_shed_in_flight() {
+        let policy = LoadShedPolicy::new(Duration::from_secs(60)).max_in_flight(2);
+        let guards: Vec<_> = (0..4)
+            .map(|_| policy.try_admit(Clock::system()).unwrap())
+            .collect();
+        assert!((policy.shed_ratio() - 0.5).abs() < 1e-9);
+        assert_eq!(shed_count(&policy, 10), 5);
+
+        drop(guards);
+        assert_eq!(policy.state.lock().in_flight, 0);
+        assert!(policy.shed_ratio() == 0.0);
+    }
+}
@@ -33,10 +33,21 @@ use std::{convert::Infallible, fmt, sync::Arc};
 
 mod concurrent;
 #[doc(inline)]
-pub use concurrent::{ConcurrentCounter, ConcurrentPolicy, ConcurrentTracker, LimitReached};
+pub use concurrent::{
+    ConcurrentCounter, ConcurrentPolicy, ConcurrentTracker, KeyedConcurrentGuard,
+    KeyedConcurrentPolicy, LimitReached,
+};
+
+mod load_shed;
+#[doc(inline)]
+pub use load_shed::{LoadShed, LoadShedGuard, LoadShedPolicy};
 
 mod matcher;
 
+mod rate;
+#[doc(inline)]
+pub use rate::{Rate, RateLimitKey, RateLimitPolicy, RateLimited};
+
 /// The full result of a limit policy.
 pub struct PolicyResult<State, Request, Guard, Error> {
     /// The input context
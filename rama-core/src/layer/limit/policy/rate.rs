@@ -0,0 +1,372 @@
+//! A [`Policy`] that limits the rate of requests per key.
+//!
+//! See [`RateLimitPolicy`].
+//!
+//! # Examples
+//!
+//! ```
+//! use rama_core::layer::limit::{Limit, policy::{Rate, RateLimitPolicy}};
+//! use rama_core::service::service_fn;
+//! use rama_core::{Context, Service};
+//! use std::time::Duration;
+//! # use std::convert::Infallible;
+//!
+//! # #[tokio::main]
+//! # async fn main() {
+//!
+//! let service = service_fn(|_, _| async {
+//!     Ok::<_, Infallible>(())
+//! });
+//! // allow 2 requests per minute, keyed by the (u8) request
+//! let policy = RateLimitPolicy::new(
+//!     Rate::new(2, Duration::from_secs(60)),
+//!     |_ctx: &Context<()>, req: &u8| Some(*req),
+//! );
+//! let service = Limit::new(service, policy);
+//!
+//! assert!(service.serve(Context::default(), 1).await.is_ok());
+//! assert!(service.serve(Context::default(), 1).await.is_ok());
+//! assert!(service.serve(Context::default(), 1).await.is_err());
+//! assert!(service.serve(Context::default(), 2).await.is_ok());
+//! # }
+//! ```
+
+use super::{Policy, PolicyOutput, PolicyResult};
+use crate::Context;
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::Hash;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// The rate at which requests are allowed by a [`RateLimitPolicy`].
+///
+/// Requests are spread evenly over the period (e.g. `Rate::new(10, 1s)` allows
+/// a request every 100ms), while a burst of requests (defaulting to the `limit`)
+/// is allowed in case no requests were made for a while.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rate {
+    emission_interval: Duration,
+    burst: u32,
+}
+
+impl Rate {
+    /// Create a new [`Rate`] allowing `limit` requests per `period`.
+    ///
+    /// A `limit` of `0` is treated as `1`.
+    pub fn new(limit: u32, period: Duration) -> Self {
+        let limit = limit.max(1);
+        Self {
+            emission_interval: period / limit,
+            burst: limit,
+        }
+    }
+
+    /// Create a new [`Rate`] allowing `limit` requests per second.
+    pub fn per_second(limit: u32) -> Self {
+        Self::new(limit, Duration::from_secs(1))
+    }
+
+    /// Create a new [`Rate`] allowing `limit` requests per minute.
+    pub fn per_minute(limit: u32) -> Self {
+        Self::new(limit, Duration::from_secs(60))
+    }
+
+    /// Define the maximum amount of requests allowed at once (the bucket size).
+    ///
+    /// Defaults to the `limit` of this rate. A `burst` of `0` is treated as `1`.
+    pub fn burst(mut self, burst: u32) -> Self {
+        self.burst = burst.max(1);
+        self
+    }
+
+    /// Define the maximum amount of requests allowed at once (the bucket size).
+    ///
+    /// Defaults to the `limit` of this rate. A `burst` of `0` is treated as `1`.
+    pub fn set_burst(&mut self, burst: u32) -> &mut Self {
+        self.burst = burst.max(1);
+        self
+    }
+}
+
+/// Error returned by the [`RateLimitPolicy`] when the rate limit is exceeded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimited {
+    retry_after: Duration,
+}
+
+impl RateLimited {
+    /// Create a new [`RateLimited`] error, e.g. for custom rate limit policies.
+    pub const fn new(retry_after: Duration) -> Self {
+        Self { retry_after }
+    }
+
+    /// The minimum duration to wait before the request is allowed.
+    pub fn retry_after(&self) -> Duration {
+        self.retry_after
+    }
+}
+
+impl fmt::Display for RateLimited {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "request aborted due to exceeded rate limit (retry after {:?})",
+            self.retry_after
+        )
+    }
+}
+
+impl std::error::Error for RateLimited {}
+
+/// Produces the key by which requests are limited by a [`RateLimitPolicy`]
+/// or a [`KeyedConcurrentPolicy`], e.g. the client ip address or the authenticated user.
+///
+/// Requests for which no key is produced are not limited.
+///
+/// Implemented for `()`, which limits all requests using a single key,
+/// and for functions `Fn(&Context<State>, &Request) -> Option<Key>`.
+///
+/// [`KeyedConcurrentPolicy`]: super::KeyedConcurrentPolicy
+pub trait RateLimitKey<State, Request>: Send + Sync + 'static {
+    /// The key by which requests are rate limited.
+    type Key: Hash + Eq + Send + Sync + 'static;
+
+    /// Produce the key for the given request, if any.
+    fn key(&self, ctx: &Context<State>, req: &Request) -> Option<Self::Key>;
+}
+
+impl<State, Request> RateLimitKey<State, Request> for () {
+    type Key = ();
+
+    fn key(&self, _ctx: &Context<State>, _req: &Request) -> Option<Self::Key> {
+        Some(())
+    }
+}
+
+impl<F, K, State, Request> RateLimitKey<State, Request> for F
+where
+    F: Fn(&Context<State>, &Request) -> Option<K> + Send + Sync + 'static,
+    K: Hash + Eq + Send + Sync + 'static,
+{
+    type Key = K;
+
+    fn key(&self, ctx: &Context<State>, req: &Request) -> Option<Self::Key> {
+        (self)(ctx, req)
+    }
+}
+
+/// A [`Policy`] that limits the rate of requests per key,
+/// using the Generic Cell Rate Algorithm (GCRA), a token bucket variant
+/// which only has to store a single timestamp per key.
+///
+/// Different limits for different classes of keys (e.g. internal vs external clients)
+/// can be achieved by combining multiple policies using a matcher [`Policy`] map,
+/// see the [module docs](super) for more information.
+///
+/// Cloning the policy is cheap, and all clones share the same state.
+pub struct RateLimitPolicy<K, Key> {
+    rate: Rate,
+    key: K,
+    state: Arc<Mutex<RateLimitState<Key>>>,
+}
+
+struct RateLimitState<Key> {
+    /// theoretical arrival time per key
+    tat: HashMap<Key, Instant>,
+    /// size of the map at which expired keys are cleaned up
+    cleanup_threshold: usize,
+}
+
+const MIN_CLEANUP_THRESHOLD: usize = 1024;
+
+impl<K: fmt::Debug, Key> fmt::Debug for RateLimitPolicy<K, Key> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RateLimitPolicy")
+            .field("rate", &self.rate)
+            .field("key", &self.key)
+            .finish()
+    }
+}
+
+impl<K: Clone, Key> Clone for RateLimitPolicy<K, Key> {
+    fn clone(&self) -> Self {
+        Self {
+            rate: self.rate,
+            key: self.key.clone(),
+            state: self.state.clone(),
+        }
+    }
+}
+
+impl<K, Key> RateLimitPolicy<K, Key> {
+    /// Create a new [`RateLimitPolicy`], limiting requests per key,
+    /// as produced by the given [`RateLimitKey`], to the given [`Rate`].
+    pub fn new(rate: Rate, key: K) -> Self {
+        Self {
+            rate,
+            key,
+            state: Arc::new(Mutex::new(RateLimitState {
+                tat: HashMap::new(),
+                cleanup_threshold: MIN_CLEANUP_THRESHOLD,
+            })),
+        }
+    }
+}
+
+impl RateLimitPolicy<(), ()> {
+    /// Create a new [`RateLimitPolicy`], limiting all requests together to the given [`Rate`].
+    pub fn global(rate: Rate) -> Self {
+        Self::new(rate, ())
+    }
+}
+
+impl<K, Key: Hash + Eq> RateLimitPolicy<K, Key> {
+    fn try_acquire(&self, key: Key, now: Instant) -> Result<(), RateLimited> {
+        let interval = self.rate.emission_interval;
+        let tolerance = interval * self.rate.burst;
+
+        let mut state = self.state.lock();
+        let tat = state.tat.get(&key).copied().unwrap_or(now).max(now);
+        let new_tat = tat + interval;
+        let wait = new_tat.saturating_duration_since(now);
+        if wait > tolerance {
+            return Err(RateLimited {
+                retry_after: wait - tolerance,
+            });
+        }
+        state.tat.insert(key, new_tat);
+
+        if state.tat.len() >= state.cleanup_threshold {
+            // keys of which the theoretical arrival time has passed are equal to unknown keys
+            state.tat.retain(|_, tat| *tat > now);
+            state.cleanup_threshold = (state.tat.len() * 2).max(MIN_CLEANUP_THRESHOLD);
+        }
+
+        Ok(())
+    }
+}
+
+impl<K, State, Request> Policy<State, Request> for RateLimitPolicy<K, K::Key>
+where
+    K: RateLimitKey<State, Request>,
+    State: Clone + Send + Sync + 'static,
+    Request: Send + 'static,
+{
+    type Guard = ();
+    type Error = RateLimited;
+
+    async fn check(
+        &self,
+        ctx: Context<State>,
+        request: Request,
+    ) -> PolicyResult<State, Request, Self::Guard, Self::Error> {
+        let output = match self.key.key(&ctx, &request) {
+            Some(key) => match self.try_acquire(key, ctx.clock().now()) {
+                Ok(()) => PolicyOutput::Ready(()),
+                Err(err) => PolicyOutput::Abort(err),
+            },
+            None => PolicyOutput::Ready(()),
+        };
+        PolicyResult {
+            ctx,
+            request,
+            output,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::time::Clock;
+
+    #[test]
+    fn rate_limit_burst_and_refill() {
+        let policy = RateLimitPolicy::global(Rate::new(2, Duration::from_secs(1)));
+        let now = Instant::now();
+
+        assert!(policy.try_acquire((), now).is_ok());
+        assert!(policy.try_acquire((), now).is_ok());
+        let err = policy.try_acquire((), now).unwrap_err();
+        assert_eq!(err.retry_after(), Duration::from_millis(500));
+
+        let later = now + Duration::from_millis(500);
+        assert!(policy.try_acquire((), later).is_ok());
+        assert!(policy.try_acquire((), later).is_err());
+
+        // fully refilled after a full period of inactivity
+        let much_later = later + Duration::from_secs(5);
+        assert!(policy.try_acquire((), much_later).is_ok());
+        assert!(policy.try_acquire((), much_later).is_ok());
+        assert!(policy.try_acquire((), much_later).is_err());
+    }
+
+    #[test]
+    fn rate_limit_custom_burst() {
+        let policy = RateLimitPolicy::global(Rate::per_second(10).burst(1));
+        let now = Instant::now();
+
+        assert!(policy.try_acquire((), now).is_ok());
+        assert_eq!(
+            policy.try_acquire((), now).unwrap_err().retry_after(),
+            Duration::from_millis(100)
+        );
+        assert!(policy
+            .try_acquire((), now + Duration::from_millis(100))
+            .is_ok());
+    }
+
+    #[test]
+    fn rate_limit_cleanup() {
+        let policy = RateLimitPolicy::new(Rate::per_second(1), |_: &Context<()>, req: &usize| {
+            Some(*req)
+        });
+        let now = Instant::now();
+        for i in 0..MIN_CLEANUP_THRESHOLD - 1 {
+            assert!(policy.try_acquire(i, now).is_ok());
+        }
+        assert_eq!(policy.state.lock().tat.len(), MIN_CLEANUP_THRESHOLD - 1);
+
+        let later = now + Duration::from_secs(2);
+        assert!(policy.try_acquire(MIN_CLEANUP_THRESHOLD, later).is_ok());
+        assert_eq!(policy.state.lock().tat.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn rate_limit_policy_per_key() {
+        let policy = RateLimitPolicy::new(Rate::per_minute(1), |_: &Context<()>, req: &u8| {
+            (*req != 0).then_some(*req)
+        });
+
+        for req in [1, 2] {
+            let result = policy.check(Context::default(), req).await;
+            assert!(matches!(result.output, PolicyOutput::Ready(())));
+            let result = policy.check(Context::default(), req).await;
+            assert!(matches!(result.output, PolicyOutput::Abort(_)));
+        }
+
+        // requests without key are not limited
+        for _ in 0..3 {
+            let result = policy.check(Context::default(), 0).await;
+            assert!(matches!(result.output, PolicyOutput::Ready(())));
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn rate_limit_policy_with_clock() {
+        let policy = RateLimitPolicy::global(Rate::per_minute(1));
+        let mut ctx = Context::default();
+        ctx.insert(Clock::tokio());
+
+        let result = policy.check(ctx.clone(), ()).await;
+        assert!(matches!(result.output, PolicyOutput::Ready(())));
+        let result = policy.check(ctx.clone(), ()).await;
+        assert!(matches!(result.output, PolicyOutput::Abort(_)));
+
+        tokio::time::advance(Duration::from_secs(60)).await;
+        let result = policy.check(ctx, ()).await;
+        assert!(matches!(result.output, PolicyOutput::Ready(())));
+    }
+}
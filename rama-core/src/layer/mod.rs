@@ -725,6 +725,8 @@ pub use add_extension::{AddExtension, AddExtensionLayer};
 pub mod get_extension;
 pub use get_extension::{GetExtension, GetExtensionLayer};
 
+pub mod order;
+
 macro_rules! impl_layer_either {
     ($id:ident, $($param:ident),+ $(,)?) => {
         impl<$($param),+, S> Layer<S> for crate::combinators::$id<$($param),+>
@@ -0,0 +1,449 @@
+//! Validation of the order in which layers are stacked.
+//!
+//! Some middleware only behaves correctly when it wraps (or is wrapped by)
+//! other middleware. A traffic writer that is wrapped by a decompression
+//! layer for example sees the compressed bytes, and an authorization layer
+//! wrapped by a [`HijackService`] can be bypassed by every hijacked request.
+//! Such stacks compile just fine, which is why [`LayerOrderRules`] can be used
+//! to validate a stack once it is built, and fail (or warn) on known-bad orderings.
+//!
+//! The validation is done on the (fully qualified) type name of the built service.
+//! As is the convention for all rama middleware, the inner service
+//! is expected to be the first generic parameter of a middleware service.
+//!
+//! # Limitations
+//!
+//! This validation is a best-effort diagnostic, it is not a guarantee that a stack is correct:
+//!
+//! - the output of [`std::any::type_name`] is not specified and may change
+//!   between compiler versions, in which case violations can be missed;
+//! - services which are type-erased (e.g. boxed) or which hide their inner service
+//!   in another generic parameter than the first one cannot be inspected;
+//! - only the orderings described by the rules are checked.
+//!
+//! Use it as a safety net (e.g. in tests or at startup), not as a security boundary.
+//!
+//! # Example
+//!
+//! ```
+//! use rama_core::layer::{
+//!     order::{LayerOrderRule, LayerOrderRules},
+//!     HijackLayer, HijackService, Layer, TimeoutLayer,
+//! };
+//! use rama_core::service::service_fn;
+//! use std::{convert::Infallible, time::Duration};
+//!
+//! # #[derive(Debug, Clone)]
+//! # struct Guard<S>(S);
+//! let rules = LayerOrderRules::new().with_rule(
+//!     LayerOrderRule::must_wrap::<Guard<()>, HijackService<(), (), ()>>(
+//!         "hijacked requests would bypass the guard",
+//!     ),
+//! );
+//!
+//! let inner = service_fn(|_: ()| async { Ok::<_, Infallible>(()) });
+//! let hijack = service_fn(|_: ()| async { Ok::<_, Infallible>(()) });
+//!
+//! let good = Guard(HijackLayer::new(true, hijack.clone()).layer(inner.clone()));
+//! assert!(rules.validate(&good).is_ok());
+//!
+//! let bad = HijackLayer::new(true, hijack)
+//!     .layer(TimeoutLayer::new(Duration::from_secs(1)).layer(Guard(inner)));
+//! let err = rules.validate(&bad).unwrap_err();
+//! assert_eq!(err.violations().len(), 1);
+//! ```
+//!
+//! [`HijackService`]: super::HijackService
+
+use std::fmt;
+
+#[derive(Debug, Clone)]
+/// A rule defining that the `outer` middleware service has to wrap
+/// the `inner` middleware service when both are part of the same stack.
+pub struct LayerOrderRule {
+    outer: &'static str,
+    inner: &'static str,
+    reason: &'static str,
+}
+
+impl LayerOrderRule {
+    /// Create a new [`LayerOrderRule`] defining that `Outer` has to wrap `Inner`,
+    /// with the given reason used in the reported violation.
+    ///
+    /// The generic parameters of `Outer` and `Inner` are ignored,
+    /// and can thus be filled in with any type (e.g. `()`).
+    pub fn must_wrap<Outer: ?Sized, Inner: ?Sized>(reason: &'static str) -> Self {
+        Self {
+            outer: type_path::<Outer>(),
+            inner: type_path::<Inner>(),
+            reason,
+        }
+    }
+
+    /// The (fully qualified) type path of the middleware that has to be the outer one.
+    pub fn outer(&self) -> &'static str {
+        self.outer
+    }
+
+    /// The (fully qualified) type path of the middleware that has to be the inner one.
+    pub fn inner(&self) -> &'static str {
+        self.inner
+    }
+
+    /// The reason why the order of this rule matters.
+    pub fn reason(&self) -> &'static str {
+        self.reason
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+/// A set of [`LayerOrderRule`]s used to validate a built service stack.
+pub struct LayerOrderRules {
+    rules: Vec<LayerOrderRule>,
+}
+
+impl LayerOrderRules {
+    /// Create a new empty set of [`LayerOrderRules`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a [`LayerOrderRule`] to this set.
+    pub fn with_rule(mut self, rule: LayerOrderRule) -> Self {
+        self.rules.push(rule);
+        self
+    }
+
+    /// Add a [`LayerOrderRule`] to this set.
+    pub fn set_rule(&mut self, rule: LayerOrderRule) -> &mut Self {
+        self.rules.push(rule);
+        self
+    }
+
+    /// Iterate over the [`LayerOrderRule`]s of this set.
+    pub fn rules(&self) -> impl Iterator<Item = &LayerOrderRule> {
+        self.rules.iter()
+    }
+
+    /// Validate the order of the layers of the given service,
+    /// returning all violated rules as a [`LayerOrderError`].
+    ///
+    /// This is a best-effort check, see the [module docs](self#limitations)
+    /// for the cases in which violations can go undetected.
+    pub fn validate<S>(&self, _service: &S) -> Result<(), LayerOrderError> {
+        self.validate_type_name(std::any::type_name::<S>())
+    }
+
+    /// Same as [`LayerOrderRules::validate`], but logging
+    /// each violated rule as a warning instead.
+    ///
+    /// Returns `true` in case no rule was violated.
+    pub fn warn<S>(&self, service: &S) -> bool {
+        match self.validate(service) {
+            Ok(()) => true,
+            Err(err) => {
+                for violation in err.violations() {
+                    tracing::warn!(
+                        outer = violation.outer,
+                        inner = violation.inner,
+                        "invalid layer order: {violation}"
+                    );
+                }
+                false
+            }
+        }
+    }
+
+    fn validate_type_name(&self, type_name: &str) -> Result<(), LayerOrderError> {
+        let root = TypeNode::parse(type_name);
+        let violations: Vec<_> = self
+            .rules
+            .iter()
+            .filter(|rule| root.is_wrapped_by(rule.outer, rule.inner))
+            .map(|rule| LayerOrderViolation {
+                outer: rule.outer,
+                inner: rule.inner,
+                reason: rule.reason,
+            })
+            .collect();
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(LayerOrderError { violations })
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// A [`LayerOrderRule`] violated by a service stack.
+pub struct LayerOrderViolation {
+    outer: &'static str,
+    inner: &'static str,
+    reason: &'static str,
+}
+
+impl LayerOrderViolation {
+    /// The (fully qualified) type path of the middleware that should have been the outer one.
+    pub fn outer(&self) -> &'static str {
+        self.outer
+    }
+
+    /// The (fully qualified) type path of the middleware that should have been the inner one.
+    pub fn inner(&self) -> &'static str {
+        self.inner
+    }
+
+    /// The reason why the order of the violated rule matters.
+    pub fn reason(&self) -> &'static str {
+        self.reason
+    }
+}
+
+impl fmt::Display for LayerOrderViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} is wrapped by {} while it should wrap it: {}",
+            short_name(self.outer),
+            short_name(self.inner),
+            self.reason
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
+/// Error returned by [`LayerOrderRules::validate`]
+/// in case one or more rules are violated.
+pub struct LayerOrderError {
+    violations: Vec<LayerOrderViolation>,
+}
+
+impl LayerOrderError {
+    /// The violated rules, never empty.
+    pub fn violations(&self) -> &[LayerOrderViolation] {
+        &self.violations
+    }
+}
+
+impl fmt::Display for LayerOrderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("invalid layer order: ")?;
+        for (index, violation) in self.violations.iter().enumerate() {
+            if index > 0 {
+                f.write_str("; ")?;
+            }
+            violation.fmt(f)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for LayerOrderError {}
+
+/// The type name of `T` without its generic parameters.
+fn type_path<T: ?Sized>() -> &'static str {
+    let name = std::any::type_name::<T>();
+    name.split_once('<').map(|(path, _)| path).unwrap_or(name)
+}
+
+fn short_name(path: &str) -> &str {
+    path.rsplit("::").next().unwrap_or(path)
+}
+
+/// A type (path) with its generic parameters, parsed from a type name.
+#[derive(Debug, Default)]
+struct TypeNode<'a> {
+    path: &'a str,
+    args: Vec<TypeNode<'a>>,
+}
+
+impl<'a> TypeNode<'a> {
+    /// Parse the given type name into an (anonymous) root node,
+    /// which has the parsed type as its only argument.
+    ///
+    /// Parsing is lenient, as it is only used to find type paths
+    /// and the first generic parameter of those.
+    fn parse(type_name: &'a str) -> Self {
+        let mut stack = vec![TypeNode::default()];
+        let mut start = None;
+        let mut chars = type_name.char_indices().peekable();
+
+        while let Some((index, c)) = chars.next() {
+            let is_arrow = c == '-' && chars.peek().is_some_and(|(_, next)| *next == '>');
+            let is_path_char = !is_arrow
+                && !c.is_whitespace()
+                && !matches!(
+                    c,
+                    '<' | '>' | '(' | ')' | '[' | ']' | ',' | ';' | '=' | '+' | '&' | '*'
+                );
+            if is_path_char {
+                start.get_or_insert(index);
+                continue;
+            }
+            if let Some(start) = start.take() {
+                push_path(&mut stack, &type_name[start..index]);
+            }
+
+            match c {
+                '<' => {
+                    let node = stack
+                        .last_mut()
+                        .and_then(|parent| parent.args.pop())
+                        .unwrap_or_default();
+                    stack.push(node);
+                }
+                '(' | '[' => stack.push(TypeNode::default()),
+                '>' | ')' | ']' => pop_node(&mut stack),
+                // skip the '>' of a function pointer's return arrow
+                '-' => {
+                    chars.next();
+                }
+                _ => (),
+            }
+        }
+        if let Some(start) = start {
+            push_path(&mut stack, &type_name[start..]);
+        }
+        while stack.len() > 1 {
+            pop_node(&mut stack);
+        }
+        stack.pop().unwrap_or_default()
+    }
+
+    /// Returns `true` in case a node with the `inner` path
+    /// wraps (directly or indirectly) a node with the `outer` path.
+    fn is_wrapped_by(&self, outer: &str, inner: &str) -> bool {
+        if self.path == inner
+            && self
+                .args
+                .first()
+                .is_some_and(|wrapped| wrapped.contains(outer))
+        {
+            return true;
+        }
+        self.args.iter().any(|arg| arg.is_wrapped_by(outer, inner))
+    }
+
+    fn contains(&self, path: &str) -> bool {
+        self.path == path || self.args.iter().any(|arg| arg.contains(path))
+    }
+}
+
+fn push_path<'a>(stack: &mut [TypeNode<'a>], path: &'a str) {
+    // lifetimes and keywords are not types
+    if path.starts_with('\'') || matches!(path, "dyn" | "mut" | "const" | "impl") {
+        return;
+    }
+    if let Some(parent) = stack.last_mut() {
+        parent.args.push(TypeNode {
+            path,
+            args: Vec::new(),
+        });
+    }
+}
+
+fn pop_node(stack: &mut Vec<TypeNode<'_>>) {
+    if stack.len() > 1 {
+        let node = stack.pop().unwrap_or_default();
+        if let Some(parent) = stack.last_mut() {
+            parent.args.push(node);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Writer<S>(S);
+    struct Decompress<S>(S);
+    struct Guard<S>(S);
+    struct Hijack<S, H>(S, H);
+    struct Wrapper<A, S>(A, S);
+
+    fn rules() -> LayerOrderRules {
+        LayerOrderRules::new()
+            .with_rule(LayerOrderRule::must_wrap::<Writer<()>, Decompress<()>>(
+                "writer should see decompressed bodies",
+            ))
+            .with_rule(LayerOrderRule::must_wrap::<Guard<()>, Hijack<(), ()>>(
+                "hijacked requests bypass the guard",
+            ))
+    }
+
+    fn violations<S>(service: &S) -> Vec<&'static str> {
+        match rules().validate(service) {
+            Ok(()) => Vec::new(),
+            Err(err) => err
+                .violations()
+                .iter()
+                .map(|violation| short_name(violation.inner()))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_type_path() {
+        assert_eq!(
+            type_path::<Writer<Vec<u8>>>(),
+            concat!(module_path!(), "::Writer")
+        );
+        assert_eq!(type_path::<u8>(), "u8");
+    }
+
+    #[test]
+    fn test_valid_order() {
+        assert!(violations(&()).is_empty());
+        assert!(violations(&Writer(Decompress(()))).is_empty());
+        assert!(violations(&Writer(Wrapper((), Decompress(())))).is_empty());
+        assert!(violations(&Guard(Hijack((), ()))).is_empty());
+        assert!(violations(&Wrapper(Guard(()), Hijack((), ()))).is_empty());
+        // a guard in the hijack service itself is fine
+        assert!(violations(&Hijack((), Guard(()))).is_empty());
+        assert!(violations(&(Decompress(()), Writer(()))).is_empty());
+    }
+
+    #[test]
+    fn test_invalid_order() {
+        assert_eq!(violations(&Decompress(Writer(()))), ["Decompress"]);
+        assert_eq!(
+            violations(&Decompress(Wrapper((), Writer(())))),
+            ["Decompress"]
+        );
+        assert_eq!(violations(&Hijack(Guard(()), ())), ["Hijack"]);
+        assert_eq!(
+            violations(&Wrapper(
+                Hijack(Decompress(Box::new(Writer(()))), ()),
+                Guard(())
+            )),
+            ["Decompress"]
+        );
+        assert_eq!(
+            violations(&Hijack(Decompress(Guard(Writer(()))), ())),
+            ["Decompress", "Hijack"]
+        );
+    }
+
+    #[test]
+    fn test_parse_type_name() {
+        let root = TypeNode::parse(
+            "a::Hijack<&'static dyn a::Trait<Output = u8> + Send, fn(u8) -> a::Guard<()>>",
+        );
+        assert_eq!(root.args.len(), 1);
+        let hijack = &root.args[0];
+        assert_eq!(hijack.path, "a::Hijack");
+        assert_eq!(hijack.args[0].path, "a::Trait");
+        assert_eq!(hijack.args[0].args[0].path, "Output");
+        assert!(hijack.contains("a::Guard"));
+        assert!(!root.is_wrapped_by("a::Guard", "a::Hijack"));
+    }
+
+    #[test]
+    fn test_error_display() {
+        let err = rules().validate(&Hijack(Guard(()), ())).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "invalid layer order: Guard is wrapped by Hijack while it should wrap it: hijacked requests bypass the guard"
+        );
+    }
+}
@@ -43,5 +43,7 @@ pub mod matcher;
 
 pub mod username;
 
+pub mod time;
+
 #[cfg(feature = "telemetry")]
 pub mod telemetry;
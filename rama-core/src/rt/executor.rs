@@ -1,4 +1,6 @@
+use super::Schedule;
 use crate::graceful::ShutdownGuard;
+use std::future::Future;
 
 /// Future executor that utilises `tokio` threads.
 #[derive(Default, Debug, Clone)]
@@ -32,6 +34,23 @@ impl Executor {
         }
     }
 
+    /// Spawn a job running periodically according to the given [`Schedule`],
+    /// e.g. to clean up a cache or to flush metrics.
+    ///
+    /// A panic of a single run is logged, without ending the schedule.
+    /// In case a shutdown guard has been registered, the schedule ends
+    /// once the graceful shutdown is initiated, with an in-flight run being awaited.
+    pub fn spawn_scheduled<F, Fut>(&self, schedule: Schedule, job: F) -> tokio::task::JoinHandle<()>
+    where
+        F: FnMut() -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        match &self.guard {
+            Some(guard) => guard.spawn_task_fn(move |guard| schedule.run(Some(guard), job)),
+            None => tokio::spawn(schedule.run(None, job)),
+        }
+    }
+
     /// Get a reference to the shutdown guard,
     /// if and only if the executor was created with [`Self::graceful`].
     pub fn guard(&self) -> Option<&ShutdownGuard> {
@@ -6,8 +6,33 @@
 //!
 //! See the [`Executor`] for more information on how to use it.
 //!
+//! Periodic jobs (e.g. cache cleanups) can be spawned on the [`Executor`]
+//! using [`Executor::spawn_scheduled`], according to a [`Schedule`].
+//!
 //! [`Executor`]: crate::rt::Executor
+//!
+//! # Example
+//!
+//! ```
+//! use rama_core::rt::{Executor, Schedule};
+//! use std::time::Duration;
+//!
+//! # #[tokio::main]
+//! # async fn main() {
+//! let handle = Executor::default().spawn_scheduled(
+//!     Schedule::every(Duration::from_secs(60)).with_jitter(Duration::from_secs(5)),
+//!     || async {
+//!         // e.g. remove expired entries from a cache
+//!     },
+//! );
+//! # handle.abort();
+//! # }
+//! ```
 
 mod executor;
 #[doc(inline)]
 pub use executor::Executor;
+
+mod schedule;
+#[doc(inline)]
+pub use schedule::Schedule;
@@ -0,0 +1,187 @@
+use crate::graceful::ShutdownGuard;
+use std::{future::Future, time::Duration};
+
+#[derive(Debug, Clone)]
+/// Schedule of a periodic job, spawned using [`Executor::spawn_scheduled`].
+///
+/// Runs of the job never overlap: the next run is scheduled once the previous
+/// run is finished, after the configured interval (and random jitter).
+///
+/// [`Executor::spawn_scheduled`]: super::Executor::spawn_scheduled
+pub struct Schedule {
+    interval: Duration,
+    jitter: Duration,
+    run_immediately: bool,
+}
+
+impl Schedule {
+    /// Create a [`Schedule`] running a job every `interval`.
+    pub const fn every(interval: Duration) -> Self {
+        Self {
+            interval,
+            jitter: Duration::ZERO,
+            run_immediately: false,
+        }
+    }
+
+    /// Delay each run by a random duration up to the given jitter,
+    /// such that jobs scheduled at the same time do not all run at once.
+    ///
+    /// No jitter is applied by default.
+    pub fn with_jitter(mut self, jitter: Duration) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Delay each run by a random duration up to the given jitter,
+    /// such that jobs scheduled at the same time do not all run at once.
+    ///
+    /// No jitter is applied by default.
+    pub fn set_jitter(&mut self, jitter: Duration) -> &mut Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Run the job right away (plus jitter) instead of after the first interval.
+    ///
+    /// Disabled by default.
+    pub fn with_run_immediately(mut self, run_immediately: bool) -> Self {
+        self.run_immediately = run_immediately;
+        self
+    }
+
+    /// Run the job right away (plus jitter) instead of after the first interval.
+    ///
+    /// Disabled by default.
+    pub fn set_run_immediately(&mut self, run_immediately: bool) -> &mut Self {
+        self.run_immediately = run_immediately;
+        self
+    }
+
+    /// The interval between two runs of the job.
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    pub(super) async fn run<F, Fut>(self, guard: Option<ShutdownGuard>, mut job: F)
+    where
+        F: FnMut() -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let mut delay = if self.run_immediately {
+            Duration::ZERO
+        } else {
+            self.interval
+        };
+        loop {
+            let sleep = tokio::time::sleep(delay + random_duration(self.jitter));
+            match &guard {
+                Some(guard) => {
+                    tokio::select! {
+                        _ = guard.cancelled() => return,
+                        _ = sleep => (),
+                    }
+                }
+                None => sleep.await,
+            }
+
+            // each run is spawned as a task of its own,
+            // such that a panicking run does not end the schedule
+            if let Err(err) = tokio::spawn(job()).await {
+                if err.is_panic() {
+                    tracing::error!(error = %err, "scheduled job panicked");
+                }
+            }
+            delay = self.interval;
+        }
+    }
+}
+
+/// A random duration up to (and including) `max`, which only has to spread
+/// the runs of scheduled jobs, and thus does not need a proper random generator.
+fn random_duration(max: Duration) -> Duration {
+    use std::hash::{BuildHasher, Hasher};
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+
+    let max = u64::try_from(max.as_nanos()).unwrap_or(u64::MAX);
+    if max == 0 {
+        return Duration::ZERO;
+    }
+    let mut hasher = std::collections::hash_map::RandomState::new().build_hasher();
+    hasher.write_u64(COUNTER.fetch_add(1, Ordering::Relaxed));
+    Duration::from_nanos(hasher.finish() % max.saturating_add(1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{graceful::Shutdown, rt::Executor};
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    #[test]
+    fn test_random_duration() {
+        assert_eq!(random_duration(Duration::ZERO), Duration::ZERO);
+        for _ in 0..100 {
+            assert!(random_duration(Duration::from_secs(1)) <= Duration::from_secs(1));
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_spawn_scheduled() {
+        let runs = Arc::new(AtomicUsize::new(0));
+        let job_runs = runs.clone();
+        Executor::default().spawn_scheduled(
+            Schedule::every(Duration::from_secs(10)).with_jitter(Duration::from_secs(1)),
+            move || {
+                let runs = job_runs.clone();
+                async move {
+                    if runs.fetch_add(1, Ordering::SeqCst) == 0 {
+                        panic!("first run fails");
+                    }
+                }
+            },
+        );
+
+        tokio::time::sleep(Duration::from_secs(5)).await;
+        assert_eq!(runs.load(Ordering::SeqCst), 0);
+        // runs continue after the first (panicking) run
+        tokio::time::sleep(Duration::from_secs(30)).await;
+        assert_eq!(runs.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_spawn_scheduled_graceful() {
+        let (tx, rx) = tokio::sync::oneshot::channel::<()>();
+        let shutdown = Shutdown::new(async move {
+            let _ = rx.await;
+        });
+
+        let runs = Arc::new(AtomicUsize::new(0));
+        let job_runs = runs.clone();
+        let handle = Executor::graceful(shutdown.guard()).spawn_scheduled(
+            Schedule::every(Duration::from_secs(10)).with_run_immediately(true),
+            move || {
+                let runs = job_runs.clone();
+                async move {
+                    runs.fetch_add(1, Ordering::SeqCst);
+                }
+            },
+        );
+
+        tokio::time::sleep(Duration::from_secs(15)).await;
+        assert_eq!(runs.load(Ordering::SeqCst), 2);
+
+        tx.send(()).unwrap();
+        shutdown
+            .shutdown_with_limit(Duration::from_secs(1))
+            .await
+            .unwrap();
+        handle.await.unwrap();
+        assert_eq!(runs.load(Ordering::SeqCst), 2);
+    }
+}
@@ -0,0 +1,56 @@
+use parking_lot::Mutex;
+use std::sync::Arc;
+
+/// Session affinity of a request, pinning it to an [`Upstream`].
+///
+/// Insert it in the [`Context`] before the request is served by a [`Balance`]
+/// service, such that the pinned upstream is used, for as long as it is healthy.
+/// Otherwise the upstream is picked by the [`BalancePolicy`] as usual.
+/// The [`Balance`] service records the upstream used, such that it can be pinned
+/// for the next requests of the same session, e.g. using a cookie.
+///
+/// Upstreams are pinned by their [`Upstream::affinity_key`],
+/// so the id of the upstream is never exposed to the client.
+///
+/// [`Upstream`]: super::Upstream
+/// [`Upstream::affinity_key`]: super::Upstream::affinity_key
+/// [`Context`]: crate::Context
+/// [`Balance`]: super::Balance
+/// [`BalancePolicy`]: super::BalancePolicy
+#[derive(Debug, Clone, Default)]
+pub struct UpstreamAffinity {
+    pinned: Option<String>,
+    selected: Arc<Mutex<Option<String>>>,
+}
+
+impl UpstreamAffinity {
+    /// Create a new [`UpstreamAffinity`] without any upstream pinned.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a new [`UpstreamAffinity`] pinned to the upstream with the given affinity key.
+    pub fn pinned(key: impl Into<String>) -> Self {
+        Self {
+            pinned: Some(key.into()),
+            selected: Default::default(),
+        }
+    }
+
+    /// The affinity key of the pinned upstream, if any.
+    pub fn pinned_key(&self) -> Option<&str> {
+        self.pinned.as_deref()
+    }
+
+    /// The affinity key of the upstream selected to serve the request,
+    /// available once the request was served by the [`Balance`] service.
+    ///
+    /// [`Balance`]: super::Balance
+    pub fn selected_key(&self) -> Option<String> {
+        self.selected.lock().clone()
+    }
+
+    pub(super) fn select(&self, key: &str) {
+        *self.selected.lock() = Some(key.to_owned());
+    }
+}
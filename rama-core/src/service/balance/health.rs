@@ -0,0 +1,374 @@
+use super::{random_u64, Upstream, UpstreamPool};
+use crate::{error::BoxError, rt::Executor};
+use std::{fmt, future::Future, sync::Arc, time::Duration};
+
+/// The default interval between two health probes of an [`Upstream`].
+const DEFAULT_INTERVAL: Duration = Duration::from_secs(10);
+
+/// The default time a health probe is given to succeed.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// The default maximum (random) delay added to the probe interval.
+const DEFAULT_JITTER: Duration = Duration::from_secs(1);
+
+/// A probe checking the health of an [`Upstream`],
+/// e.g. by connecting to it or by making a request to a health endpoint.
+pub trait HealthProbe<S>: Send + Sync + 'static {
+    /// Probe the given upstream, returning an error in case it is unhealthy.
+    fn probe(
+        &self,
+        upstream: &Upstream<S>,
+    ) -> impl Future<Output = Result<(), BoxError>> + Send + '_;
+}
+
+impl<S, F, Fut> HealthProbe<S> for F
+where
+    F: Fn(&Upstream<S>) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Result<(), BoxError>> + Send + 'static,
+{
+    fn probe(
+        &self,
+        upstream: &Upstream<S>,
+    ) -> impl Future<Output = Result<(), BoxError>> + Send + '_ {
+        (self)(upstream)
+    }
+}
+
+/// The health of an [`Upstream`], as observed by the [`HealthChecker`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UpstreamHealth {
+    /// The id of the upstream.
+    pub id: String,
+    /// `true` in case the upstream is marked as up.
+    pub healthy: bool,
+    /// The amount of requests currently in flight for the upstream.
+    pub in_flight: usize,
+    /// The amount of consecutive successful probes.
+    pub consecutive_successes: u32,
+    /// The amount of consecutive failed probes.
+    pub consecutive_failures: u32,
+    /// The error of the last failed probe, if any.
+    pub last_error: Option<String>,
+}
+
+#[derive(Debug, Default)]
+pub(super) struct ProbeState {
+    pub(super) consecutive_successes: u32,
+    pub(super) consecutive_failures: u32,
+    pub(super) last_error: Option<String>,
+}
+
+/// Active health checker, probing each [`Upstream`] of an [`UpstreamPool`]
+/// in the background and marking it as up or down accordingly.
+///
+/// An upstream is marked as down after `unhealthy_threshold` consecutive
+/// failed probes, and as up again after `healthy_threshold` consecutive
+/// successful probes. Each upstream is probed every `interval`, extended by
+/// a random delay of at most `jitter`, such that the probes are spread over time.
+///
+/// # Example
+///
+/// ```
+/// use rama_core::service::balance::{Balance, HealthChecker, Upstream, UpstreamPool};
+/// use rama_core::error::BoxError;
+/// use rama_core::rt::Executor;
+/// use std::time::Duration;
+///
+/// # #[tokio::main]
+/// # async fn main() {
+/// let pool = UpstreamPool::try_new([Upstream::new("a", ()), Upstream::new("b", ())]).unwrap();
+///
+/// HealthChecker::new(pool.clone(), |upstream: &Upstream<()>| {
+///     let healthy = upstream.id() == "a";
+///     async move {
+///         if healthy {
+///             Ok(())
+///         } else {
+///             Err(BoxError::from("connection refused"))
+///         }
+///     }
+/// })
+/// .interval(Duration::from_millis(10))
+/// .jitter(Duration::from_millis(5))
+/// .unhealthy_threshold(1)
+/// .spawn(&Executor::default());
+///
+/// tokio::time::sleep(Duration::from_millis(100)).await;
+/// assert!(pool.upstreams()[0].is_healthy());
+/// assert!(!pool.upstreams()[1].is_healthy());
+///
+/// // the balancer only uses the healthy upstreams
+/// let svc = Balance::round_robin(pool);
+/// # }
+/// ```
+pub struct HealthChecker<S, P> {
+    pool: UpstreamPool<S>,
+    probe: Arc<P>,
+    interval: Duration,
+    timeout: Duration,
+    jitter: Duration,
+    healthy_threshold: u32,
+    unhealthy_threshold: u32,
+}
+
+impl<S, P> HealthChecker<S, P> {
+    /// Create a new [`HealthChecker`] for the given pool, using the given probe.
+    pub fn new(pool: UpstreamPool<S>, probe: P) -> Self {
+        Self {
+            pool,
+            probe: Arc::new(probe),
+            interval: DEFAULT_INTERVAL,
+            timeout: DEFAULT_TIMEOUT,
+            jitter: DEFAULT_JITTER,
+            healthy_threshold: 2,
+            unhealthy_threshold: 3,
+        }
+    }
+
+    /// Set the interval between two probes of an upstream.
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Set the interval between two probes of an upstream.
+    pub fn set_interval(&mut self, interval: Duration) -> &mut Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Set the time a probe is given to succeed, before it is considered failed.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Set the time a probe is given to succeed, before it is considered failed.
+    pub fn set_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Set the maximum random delay added to the interval between two probes.
+    pub fn jitter(mut self, jitter: Duration) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Set the maximum random delay added to the interval between two probes.
+    pub fn set_jitter(&mut self, jitter: Duration) -> &mut Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Set the amount of consecutive successful probes
+    /// required to mark a down upstream as up again.
+    pub fn healthy_threshold(mut self, threshold: u32) -> Self {
+        self.healthy_threshold = threshold.max(1);
+        self
+    }
+
+    /// Set the amount of consecutive successful probes
+    /// required to mark a down upstream as up again.
+    pub fn set_healthy_threshold(&mut self, threshold: u32) -> &mut Self {
+        self.healthy_threshold = threshold.max(1);
+        self
+    }
+
+    /// Set the amount of consecutive failed probes
+    /// required to mark an upstream as down.
+    pub fn unhealthy_threshold(mut self, threshold: u32) -> Self {
+        self.unhealthy_threshold = threshold.max(1);
+        self
+    }
+
+    /// Set the amount of consecutive failed probes
+    /// required to mark an upstream as down.
+    pub fn set_unhealthy_threshold(&mut self, threshold: u32) -> &mut Self {
+        self.unhealthy_threshold = threshold.max(1);
+        self
+    }
+}
+
+impl<S, P> HealthChecker<S, P>
+where
+    S: Send + Sync + 'static,
+    P: HealthProbe<S>,
+{
+    /// Spawn a background task per upstream using the given [`Executor`],
+    /// probing the upstream until the executor's shutdown guard is cancelled.
+    pub fn spawn(self, executor: &Executor) {
+        for index in 0..self.pool.len() {
+            let checker = self.clone_for_task();
+            let guard = executor.guard().cloned();
+            executor.spawn_task(async move {
+                let probe = checker.run(index);
+                match guard {
+                    Some(guard) => {
+                        tokio::select! {
+                            _ = probe => (),
+                            _ = guard.cancelled() => (),
+                        }
+                    }
+                    None => probe.await,
+                }
+            });
+        }
+    }
+
+    /// Probe all upstreams once, one after the other,
+    /// and mark them as up or down accordingly.
+    pub async fn check(&self) {
+        for index in 0..self.pool.len() {
+            self.check_upstream(index).await;
+        }
+    }
+
+    async fn run(self, index: usize) {
+        loop {
+            let jitter = match self.jitter.as_nanos() as u64 {
+                0 => Duration::ZERO,
+                max => Duration::from_nanos(random_u64() % max),
+            };
+            tokio::time::sleep(self.interval + jitter).await;
+            self.check_upstream(index).await;
+        }
+    }
+
+    async fn check_upstream(&self, index: usize) {
+        let upstream = &self.pool.upstreams()[index];
+        let result = match tokio::time::timeout(self.timeout, self.probe.probe(upstream)).await {
+            Ok(result) => result,
+            Err(_) => Err(BoxError::from("health probe timed out")),
+        };
+
+        let mut state = upstream.probe_state.lock();
+        match result {
+            Ok(()) => {
+                state.consecutive_failures = 0;
+                state.consecutive_successes = state.consecutive_successes.saturating_add(1);
+                if !upstream.is_healthy() && state.consecutive_successes >= self.healthy_threshold {
+                    tracing::info!(upstream = %upstream.id(), "upstream marked as up");
+                    upstream.set_healthy(true);
+                }
+            }
+            Err(err) => {
+                state.consecutive_successes = 0;
+                state.consecutive_failures = state.consecutive_failures.saturating_add(1);
+                state.last_error = Some(err.to_string());
+                if upstream.is_healthy() && state.consecutive_failures >= self.unhealthy_threshold {
+                    tracing::warn!(upstream = %upstream.id(), error = %err, "upstream marked as down");
+                    upstream.set_healthy(false);
+                }
+            }
+        }
+    }
+
+    fn clone_for_task(&self) -> Self {
+        Self {
+            pool: self.pool.clone(),
+            probe: self.probe.clone(),
+            interval: self.interval,
+            timeout: self.timeout,
+            jitter: self.jitter,
+            healthy_threshold: self.healthy_threshold,
+            unhealthy_threshold: self.unhealthy_threshold,
+        }
+    }
+}
+
+impl<S: fmt::Debug, P> fmt::Debug for HealthChecker<S, P> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HealthChecker")
+            .field("pool", &self.pool)
+            .field("interval", &self.interval)
+            .field("timeout", &self.timeout)
+            .field("jitter", &self.jitter)
+            .field("healthy_threshold", &self.healthy_threshold)
+            .field("unhealthy_threshold", &self.unhealthy_threshold)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    #[tokio::test]
+    async fn test_thresholds() {
+        let pool = UpstreamPool::try_new([Upstream::new("a", ())]).unwrap();
+        let up = Arc::new(AtomicBool::new(false));
+        let checker = HealthChecker::new(pool.clone(), {
+            let up = up.clone();
+            move |_: &Upstream<()>| {
+                let up = up.load(Ordering::SeqCst);
+                async move {
+                    if up {
+                        Ok(())
+                    } else {
+                        Err(BoxError::from("down"))
+                    }
+                }
+            }
+        })
+        .healthy_threshold(2)
+        .unhealthy_threshold(3);
+
+        let upstream = &pool.upstreams()[0];
+        for _ in 0..2 {
+            checker.check().await;
+            assert!(upstream.is_healthy());
+        }
+        checker.check().await;
+        assert!(!upstream.is_healthy());
+
+        let health = upstream.health();
+        assert_eq!(health.consecutive_failures, 3);
+        assert_eq!(health.last_error.as_deref(), Some("down"));
+
+        up.store(true, Ordering::SeqCst);
+        checker.check().await;
+        assert!(!upstream.is_healthy());
+        checker.check().await;
+        assert!(upstream.is_healthy());
+        assert_eq!(pool.health()[0].consecutive_successes, 2);
+    }
+
+    #[tokio::test]
+    async fn test_timeout() {
+        let pool = UpstreamPool::try_new([Upstream::new("a", ())]).unwrap();
+        let checker = HealthChecker::new(pool.clone(), |_: &Upstream<()>| async {
+            tokio::time::sleep(Duration::from_secs(60)).await;
+            Ok(())
+        })
+        .timeout(Duration::from_millis(10))
+        .unhealthy_threshold(1);
+
+        checker.check().await;
+        assert!(!pool.upstreams()[0].is_healthy());
+        assert_eq!(
+            pool.health()[0].last_error.as_deref(),
+            Some("health probe timed out")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_spawn_graceful() {
+        let shutdown = crate::graceful::Shutdown::new(std::future::ready(()));
+        let pool = UpstreamPool::try_new([Upstream::new("a", ())]).unwrap();
+        HealthChecker::new(pool.clone(), |_: &Upstream<()>| async {
+            Err(BoxError::from("down"))
+        })
+        .interval(Duration::from_millis(1))
+        .jitter(Duration::ZERO)
+        .unhealthy_threshold(1)
+        .spawn(&Executor::graceful(shutdown.guard()));
+
+        // the probe tasks stop once shutdown is triggered
+        shutdown
+            .shutdown_with_limit(Duration::from_secs(5))
+            .await
+            .unwrap();
+    }
+}
@@ -0,0 +1,670 @@
+//! Load balancing of requests over a pool of upstream services.
+//!
+//! The [`Balance`] service serves each request using one of the
+//! upstreams of its [`UpstreamPool`], picked by its [`BalancePolicy`]:
+//!
+//! - [`RoundRobin`]: each upstream in turn;
+//! - [`LeastConnections`]: the upstream with the least requests in flight;
+//! - [`PowerOfTwoChoices`]: the upstream with the least requests in flight
+//!   out of two randomly picked ones, which avoids the herd behaviour of
+//!   [`LeastConnections`] without having to compare all upstreams;
+//! - [`ConsistentHash`]: the upstream selected by rendezvous hashing
+//!   of a key extracted from the request, such that requests with the
+//!   same key end up at the same upstream, even as upstreams are added or removed.
+//!
+//! The upstreams can be any [`Service`], e.g. a reverse proxy service
+//! per origin server, or a connector per upstream (CONNECT) proxy, given a
+//! [`Balance`] of connectors is a connector in its own right.
+//!
+//! Requests are counted as in flight until their upstream returned a response,
+//! which for streamed (http) responses is once the response head is received.
+//!
+//! Upstreams marked as down, e.g. by a [`HealthChecker`], are avoided
+//! for as long as at least one healthy upstream remains.
+//!
+//! Requests can be pinned to a (healthy) upstream using an [`UpstreamAffinity`],
+//! e.g. to keep the requests of a session on the same upstream.
+//!
+//! # Example
+//!
+//! ```
+//! use rama_core::service::{
+//!     balance::{Balance, Upstream, UpstreamPool},
+//!     service_fn,
+//! };
+//! use rama_core::{Context, Service};
+//! use std::convert::Infallible;
+//!
+//! # #[tokio::main]
+//! # async fn main() {
+//! let pool = UpstreamPool::try_new(["a", "b"].map(|name| {
+//!     Upstream::new(
+//!         name,
+//!         service_fn(move |_: ()| async move { Ok::<_, Infallible>(name) }),
+//!     )
+//! }))
+//! .unwrap();
+//!
+//! let svc = Balance::round_robin(pool);
+//! assert_eq!(svc.serve(Context::default(), ()).await.unwrap(), "a");
+//! assert_eq!(svc.serve(Context::default(), ()).await.unwrap(), "b");
+//! assert_eq!(svc.serve(Context::default(), ()).await.unwrap(), "a");
+//! # }
+//! ```
+
+use crate::{error::OpaqueError, Context, Service};
+use parking_lot::Mutex;
+use std::{
+    collections::hash_map::{DefaultHasher, RandomState},
+    fmt,
+    hash::{BuildHasher, Hash, Hasher},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+mod affinity;
+#[doc(inline)]
+pub use affinity::UpstreamAffinity;
+
+mod health;
+#[doc(inline)]
+pub use health::{HealthChecker, HealthProbe, UpstreamHealth};
+
+/// An upstream [`Service`] of an [`UpstreamPool`].
+pub struct Upstream<S> {
+    id: String,
+    affinity_key: String,
+    service: S,
+    in_flight: AtomicUsize,
+    healthy: AtomicBool,
+    probe_state: Mutex<health::ProbeState>,
+}
+
+impl<S> Upstream<S> {
+    /// Create a new [`Upstream`] with the given (unique) id, e.g. its address.
+    ///
+    /// The id is used by the [`ConsistentHash`] policy,
+    /// and should thus be stable across restarts.
+    pub fn new(id: impl Into<String>, service: S) -> Self {
+        let id = id.into();
+        // hashed using fixed keys, such that it is stable across restarts
+        let mut hasher = DefaultHasher::new();
+        id.hash(&mut hasher);
+        Self {
+            affinity_key: format!("{:016x}", hasher.finish()),
+            id,
+            service,
+            in_flight: AtomicUsize::new(0),
+            healthy: AtomicBool::new(true),
+            probe_state: Mutex::new(health::ProbeState::default()),
+        }
+    }
+
+    /// The id of this [`Upstream`].
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// The key used to pin requests to this [`Upstream`] using an [`UpstreamAffinity`],
+    /// derived from its id.
+    pub fn affinity_key(&self) -> &str {
+        &self.affinity_key
+    }
+
+    /// Reference to the [`Service`] of this [`Upstream`].
+    pub fn service(&self) -> &S {
+        &self.service
+    }
+
+    /// The amount of requests currently in flight for this [`Upstream`].
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.load(Ordering::Relaxed)
+    }
+
+    /// Returns `true` in case this [`Upstream`] is marked as up,
+    /// which is the case until marked otherwise.
+    pub fn is_healthy(&self) -> bool {
+        self.healthy.load(Ordering::Relaxed)
+    }
+
+    /// Mark this [`Upstream`] as up or down.
+    ///
+    /// This is done by the [`HealthChecker`], but can also be used
+    /// to mark upstreams manually, e.g. based on passive health checks.
+    pub fn set_healthy(&self, healthy: bool) {
+        self.healthy.store(healthy, Ordering::Relaxed);
+    }
+
+    /// The current [`UpstreamHealth`] of this [`Upstream`].
+    pub fn health(&self) -> UpstreamHealth {
+        let state = self.probe_state.lock();
+        UpstreamHealth {
+            id: self.id.clone(),
+            healthy: self.is_healthy(),
+            in_flight: self.in_flight(),
+            consecutive_successes: state.consecutive_successes,
+            consecutive_failures: state.consecutive_failures,
+            last_error: state.last_error.clone(),
+        }
+    }
+
+    fn start(&self) -> InFlightGuard<'_> {
+        self.in_flight.fetch_add(1, Ordering::Relaxed);
+        InFlightGuard(&self.in_flight)
+    }
+}
+
+impl<S: fmt::Debug> fmt::Debug for Upstream<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Upstream")
+            .field("id", &self.id)
+            .field("service", &self.service)
+            .field("in_flight", &self.in_flight())
+            .field("healthy", &self.is_healthy())
+            .finish()
+    }
+}
+
+/// Decrements the in flight count of an [`Upstream`] once dropped.
+struct InFlightGuard<'a>(&'a AtomicUsize);
+
+impl Drop for InFlightGuard<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// A non-empty pool of [`Upstream`]s, shared by all clones of the pool.
+pub struct UpstreamPool<S> {
+    upstreams: Arc<[Upstream<S>]>,
+}
+
+impl<S> UpstreamPool<S> {
+    /// Create a new [`UpstreamPool`] from the given [`Upstream`]s.
+    ///
+    /// Fails in case no upstreams are given.
+    pub fn try_new(upstreams: impl IntoIterator<Item = Upstream<S>>) -> Result<Self, OpaqueError> {
+        let upstreams: Arc<[Upstream<S>]> = upstreams.into_iter().collect();
+        if upstreams.is_empty() {
+            return Err(OpaqueError::from_display(
+                "upstream pool requires at least one upstream",
+            ));
+        }
+        Ok(Self { upstreams })
+    }
+
+    /// The [`Upstream`]s of this pool.
+    pub fn upstreams(&self) -> &[Upstream<S>] {
+        &self.upstreams
+    }
+
+    /// The amount of [`Upstream`]s in this pool, never zero.
+    pub fn len(&self) -> usize {
+        self.upstreams.len()
+    }
+
+    /// Always `false`, as an [`UpstreamPool`] cannot be empty.
+    pub fn is_empty(&self) -> bool {
+        false
+    }
+
+    /// The current [`UpstreamHealth`] of all [`Upstream`]s in this pool.
+    pub fn health(&self) -> Vec<UpstreamHealth> {
+        self.upstreams.iter().map(Upstream::health).collect()
+    }
+}
+
+impl<S> Clone for UpstreamPool<S> {
+    fn clone(&self) -> Self {
+        Self {
+            upstreams: self.upstreams.clone(),
+        }
+    }
+}
+
+impl<S: fmt::Debug> fmt::Debug for UpstreamPool<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.upstreams.iter()).finish()
+    }
+}
+
+/// A policy picking the [`Upstream`] to serve a request with.
+pub trait BalancePolicy<State, Request>: Send + Sync + 'static {
+    /// Pick the index of the upstream to serve the given request with,
+    /// out of the given (non-empty) upstreams.
+    ///
+    /// Policies should avoid unhealthy upstreams, but in case an unhealthy
+    /// upstream is picked anyway, [`Balance`] serves the request using
+    /// the next healthy upstream instead.
+    fn pick<S>(&self, upstreams: &[Upstream<S>], ctx: &Context<State>, req: &Request) -> usize;
+}
+
+#[derive(Debug, Default)]
+/// [`BalancePolicy`] picking each (healthy) upstream in turn.
+pub struct RoundRobin {
+    next: AtomicUsize,
+}
+
+impl RoundRobin {
+    /// Create a new [`RoundRobin`] policy.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl<State, Request> BalancePolicy<State, Request> for RoundRobin {
+    fn pick<S>(&self, upstreams: &[Upstream<S>], _ctx: &Context<State>, _req: &Request) -> usize {
+        let mut index = 0;
+        for _ in 0..upstreams.len() {
+            index = self.next.fetch_add(1, Ordering::Relaxed) % upstreams.len();
+            if upstreams[index].is_healthy() {
+                break;
+            }
+        }
+        index
+    }
+}
+
+#[derive(Debug, Default)]
+/// [`BalancePolicy`] picking the (healthy) upstream with the least requests in flight,
+/// rotating between the upstreams with an equal amount of requests in flight.
+pub struct LeastConnections {
+    offset: AtomicUsize,
+}
+
+impl LeastConnections {
+    /// Create a new [`LeastConnections`] policy.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl<State, Request> BalancePolicy<State, Request> for LeastConnections {
+    fn pick<S>(&self, upstreams: &[Upstream<S>], _ctx: &Context<State>, _req: &Request) -> usize {
+        let offset = self.offset.fetch_add(1, Ordering::Relaxed);
+        (0..upstreams.len())
+            .map(|i| (i + offset) % upstreams.len())
+            .min_by_key(|index| {
+                (
+                    !upstreams[*index].is_healthy(),
+                    upstreams[*index].in_flight(),
+                )
+            })
+            .unwrap_or_default()
+    }
+}
+
+#[derive(Debug, Default)]
+#[non_exhaustive]
+/// [`BalancePolicy`] picking two upstreams at random,
+/// and using the one with the least requests in flight.
+pub struct PowerOfTwoChoices;
+
+impl PowerOfTwoChoices {
+    /// Create a new [`PowerOfTwoChoices`] policy.
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl<State, Request> BalancePolicy<State, Request> for PowerOfTwoChoices {
+    fn pick<S>(&self, upstreams: &[Upstream<S>], _ctx: &Context<State>, _req: &Request) -> usize {
+        let len = upstreams.len();
+        if len == 1 {
+            return 0;
+        }
+        let first = random_u64() as usize % len;
+        // pick another upstream than the first
+        let second = (first + 1 + random_u64() as usize % (len - 1)) % len;
+        let load = |index: usize| (!upstreams[index].is_healthy(), upstreams[index].in_flight());
+        if load(second) < load(first) {
+            second
+        } else {
+            first
+        }
+    }
+}
+
+/// [`BalancePolicy`] picking the (healthy) upstream by rendezvous hashing
+/// of a key extracted from the request, e.g. the client ip or a session cookie.
+///
+/// Requests without key are served in a round-robin fashion.
+pub struct ConsistentHash<F> {
+    key: F,
+    fallback: RoundRobin,
+}
+
+impl<F> ConsistentHash<F> {
+    /// Create a new [`ConsistentHash`] policy,
+    /// using the given function to extract the key of a request.
+    pub fn new(key: F) -> Self {
+        Self {
+            key,
+            fallback: RoundRobin::new(),
+        }
+    }
+}
+
+impl<F> fmt::Debug for ConsistentHash<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ConsistentHash").finish()
+    }
+}
+
+impl<State, Request, F, K> BalancePolicy<State, Request> for ConsistentHash<F>
+where
+    F: Fn(&Context<State>, &Request) -> Option<K> + Send + Sync + 'static,
+    K: Hash,
+{
+    fn pick<S>(&self, upstreams: &[Upstream<S>], ctx: &Context<State>, req: &Request) -> usize {
+        let Some(key) = (self.key)(ctx, req) else {
+            return self.fallback.pick(upstreams, ctx, req);
+        };
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        let key = hasher.finish();
+
+        upstreams
+            .iter()
+            .enumerate()
+            .max_by_key(|(_, upstream)| {
+                let mut hasher = DefaultHasher::new();
+                key.hash(&mut hasher);
+                upstream.id.hash(&mut hasher);
+                (upstream.is_healthy(), hasher.finish())
+            })
+            .map(|(index, _)| index)
+            .unwrap_or_default()
+    }
+}
+
+/// A [`Service`] serving each request using one of the upstreams
+/// of its [`UpstreamPool`], picked by its [`BalancePolicy`].
+///
+/// See [the module docs](self) for more information.
+pub struct Balance<S, P> {
+    pool: UpstreamPool<S>,
+    policy: Arc<P>,
+}
+
+impl<S, P> Balance<S, P> {
+    /// Create a new [`Balance`] service for the given pool and policy.
+    pub fn new(pool: UpstreamPool<S>, policy: P) -> Self {
+        Self {
+            pool,
+            policy: Arc::new(policy),
+        }
+    }
+
+    /// Reference to the [`UpstreamPool`] of this [`Balance`] service.
+    pub fn pool(&self) -> &UpstreamPool<S> {
+        &self.pool
+    }
+}
+
+impl<S> Balance<S, RoundRobin> {
+    /// Create a new [`Balance`] service using the [`RoundRobin`] policy.
+    pub fn round_robin(pool: UpstreamPool<S>) -> Self {
+        Self::new(pool, RoundRobin::new())
+    }
+}
+
+impl<S> Balance<S, LeastConnections> {
+    /// Create a new [`Balance`] service using the [`LeastConnections`] policy.
+    pub fn least_connections(pool: UpstreamPool<S>) -> Self {
+        Self::new(pool, LeastConnections::new())
+    }
+}
+
+impl<S> Balance<S, PowerOfTwoChoices> {
+    /// Create a new [`Balance`] service using the [`PowerOfTwoChoices`] policy.
+    pub fn power_of_two_choices(pool: UpstreamPool<S>) -> Self {
+        Self::new(pool, PowerOfTwoChoices::new())
+    }
+}
+
+impl<S, F> Balance<S, ConsistentHash<F>> {
+    /// Create a new [`Balance`] service using the [`ConsistentHash`] policy,
+    /// using the given function to extract the key of a request.
+    pub fn consistent_hash(pool: UpstreamPool<S>, key: F) -> Self {
+        Self::new(pool, ConsistentHash::new(key))
+    }
+}
+
+impl<S, P> Clone for Balance<S, P> {
+    fn clone(&self) -> Self {
+        Self {
+            pool: self.pool.clone(),
+            policy: self.policy.clone(),
+        }
+    }
+}
+
+impl<S: fmt::Debug, P: fmt::Debug> fmt::Debug for Balance<S, P> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Balance")
+            .field("pool", &self.pool)
+            .field("policy", &self.policy)
+            .finish()
+    }
+}
+
+impl<S, P, State, Request> Service<State, Request> for Balance<S, P>
+where
+    S: Service<State, Request>,
+    P: BalancePolicy<State, Request>,
+    State: Clone + Send + Sync + 'static,
+    Request: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+
+    async fn serve(
+        &self,
+        ctx: Context<State>,
+        req: Request,
+    ) -> Result<Self::Response, Self::Error> {
+        let upstreams = self.pool.upstreams();
+        let affinity = ctx.get::<UpstreamAffinity>().cloned();
+
+        let pinned = affinity
+            .as_ref()
+            .and_then(UpstreamAffinity::pinned_key)
+            .and_then(|key| {
+                upstreams
+                    .iter()
+                    .position(|upstream| upstream.affinity_key == key && upstream.is_healthy())
+            });
+        let index = match pinned {
+            Some(index) => index,
+            None => {
+                let index = self.policy.pick(upstreams, &ctx, &req) % upstreams.len();
+                // fail open in case none of the upstreams are healthy
+                (index..upstreams.len())
+                    .chain(0..index)
+                    .find(|index| upstreams[*index].is_healthy())
+                    .unwrap_or(index)
+            }
+        };
+
+        let upstream = &upstreams[index];
+        if let Some(affinity) = affinity {
+            affinity.select(&upstream.affinity_key);
+        }
+        let _guard = upstream.start();
+        upstream.service.serve(ctx, req).await
+    }
+}
+
+/// A random number, good enough to spread load, but not cryptographically secure.
+fn random_u64() -> u64 {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u64(COUNTER.fetch_add(1, Ordering::Relaxed));
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::service::service_fn;
+    use std::{convert::Infallible, time::Duration};
+
+    type EchoService = crate::service::BoxService<(), u64, String, Infallible>;
+
+    fn pool(ids: &[&'static str]) -> UpstreamPool<EchoService> {
+        UpstreamPool::try_new(ids.iter().map(|id| {
+            let id = *id;
+            Upstream::new(
+                id,
+                service_fn(move |delay: u64| async move {
+                    tokio::time::sleep(Duration::from_millis(delay)).await;
+                    Ok::<_, Infallible>(id.to_owned())
+                })
+                .boxed(),
+            )
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_empty_pool() {
+        assert!(UpstreamPool::<()>::try_new([]).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_round_robin() {
+        let svc = Balance::round_robin(pool(&["a", "b", "c"]));
+        let mut picked = Vec::new();
+        for _ in 0..6 {
+            picked.push(svc.serve(Context::default(), 0).await.unwrap());
+        }
+        assert_eq!(picked, ["a", "b", "c", "a", "b", "c"]);
+    }
+
+    #[tokio::test]
+    async fn test_skip_unhealthy() {
+        let pool = pool(&["a", "b", "c"]);
+        pool.upstreams()[1].set_healthy(false);
+
+        let svc = Balance::round_robin(pool.clone());
+        let mut picked = Vec::new();
+        for _ in 0..4 {
+            picked.push(svc.serve(Context::default(), 0).await.unwrap());
+        }
+        assert_eq!(picked, ["a", "c", "a", "c"]);
+
+        let svc = Balance::power_of_two_choices(pool.clone());
+        for _ in 0..10 {
+            assert_ne!(svc.serve(Context::default(), 0).await.unwrap(), "b");
+        }
+
+        // fail open in case all upstreams are down
+        for upstream in pool.upstreams() {
+            upstream.set_healthy(false);
+        }
+        let svc = Balance::least_connections(pool.clone());
+        assert!(svc.serve(Context::default(), 0).await.is_ok());
+        assert!(pool.health().iter().all(|health| !health.healthy));
+    }
+
+    #[tokio::test]
+    async fn test_affinity() {
+        let pool = pool(&["a", "b", "c"]);
+        let svc = Balance::round_robin(pool.clone());
+        let key = pool.upstreams()[2].affinity_key().to_owned();
+        assert_ne!(key, pool.upstreams()[1].affinity_key());
+
+        for _ in 0..3 {
+            let affinity = UpstreamAffinity::pinned(key.clone());
+            let mut ctx = Context::default();
+            ctx.insert(affinity.clone());
+            assert_eq!(svc.serve(ctx, 0).await.unwrap(), "c");
+            assert_eq!(affinity.selected_key().as_deref(), Some(key.as_str()));
+        }
+
+        // failover in case the pinned upstream is down
+        pool.upstreams()[2].set_healthy(false);
+        let affinity = UpstreamAffinity::pinned(key.clone());
+        let mut ctx = Context::default();
+        ctx.insert(affinity.clone());
+        assert_ne!(svc.serve(ctx, 0).await.unwrap(), "c");
+        assert_ne!(affinity.selected_key(), Some(key));
+
+        // unknown keys are ignored
+        let affinity = UpstreamAffinity::pinned("unknown");
+        let mut ctx = Context::default();
+        ctx.insert(affinity.clone());
+        assert!(svc.serve(ctx, 0).await.is_ok());
+        assert!(affinity.selected_key().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_least_connections() {
+        let svc = Balance::least_connections(pool(&["a", "b"]));
+        let slow = tokio::spawn({
+            let svc = svc.clone();
+            async move { svc.serve(Context::default(), 100).await.unwrap() }
+        });
+        while svc.pool().upstreams().iter().all(|u| u.in_flight() == 0) {
+            tokio::task::yield_now().await;
+        }
+        let busy = if svc.pool().upstreams()[0].in_flight() == 1 {
+            "a"
+        } else {
+            "b"
+        };
+        for _ in 0..3 {
+            assert_ne!(svc.serve(Context::default(), 0).await.unwrap(), busy);
+        }
+        assert_eq!(slow.await.unwrap(), busy);
+        assert!(svc.pool().upstreams().iter().all(|u| u.in_flight() == 0));
+    }
+
+    #[test]
+    fn test_power_of_two_choices() {
+        let pool = pool(&["a", "b", "c"]);
+        let _guards: Vec<_> = pool.upstreams()[..2].iter().map(Upstream::start).collect();
+        let ctx = Context::default();
+
+        let mut picked = [0; 3];
+        for _ in 0..100 {
+            picked[PowerOfTwoChoices.pick(pool.upstreams(), &ctx, &0)] += 1;
+        }
+        // the idle upstream wins whenever it is one of the two choices
+        assert!(picked[2] > picked[0] && picked[2] > picked[1], "{picked:?}");
+
+        let single = self::pool(&["a"]);
+        assert_eq!(PowerOfTwoChoices.pick(single.upstreams(), &ctx, &0), 0);
+    }
+
+    #[test]
+    fn test_consistent_hash() {
+        let policy = ConsistentHash::new(|_: &Context<()>, key: &u64| (*key > 0).then_some(*key));
+        let ctx = Context::default();
+
+        let large = pool(&["a", "b", "c", "d"]);
+        let small = pool(&["a", "b", "c"]);
+        let mut moved = 0;
+        for key in 1..100 {
+            let index = policy.pick(large.upstreams(), &ctx, &key);
+            assert_eq!(index, policy.pick(large.upstreams(), &ctx, &key));
+            // only keys of the removed upstream move to another upstream
+            let small_index = policy.pick(small.upstreams(), &ctx, &key);
+            if index == 3 {
+                moved += 1;
+            } else {
+                assert_eq!(index, small_index);
+            }
+        }
+        assert!(moved > 0 && moved < 99);
+
+        // requests without key are served round-robin
+        let picked: Vec<_> = (0..3)
+            .map(|_| policy.pick(small.upstreams(), &ctx, &0))
+            .collect();
+        assert_eq!(picked, [0, 1, 2]);
+    }
+}
@@ -0,0 +1,147 @@
+//! Time source abstraction, such that time can be controlled in tests.
+//!
+//! Layers which need the current time (e.g. to rate limit requests or to compute
+//! the age of a cached response) get it from the [`Clock`] found in the [`Context`],
+//! using [`Context::clock`]. By default this is the [`Clock::system`] clock.
+//!
+//! Tests can insert the [`Clock::tokio`] clock instead, which follows
+//! the time of the tokio runtime, such that time can be advanced
+//! deterministically when the runtime's time is paused,
+//! rather than sleeping for real.
+//!
+//! Sleeping (e.g. for timeouts and backoffs) is done using [`tokio::time::sleep`],
+//! which follows the time of the tokio runtime already.
+//!
+//! # Example
+//!
+//! ```
+//! use rama_core::time::Clock;
+//! use rama_core::Context;
+//! use std::time::Duration;
+//!
+//! # #[tokio::main(flavor = "current_thread", start_paused = true)]
+//! # async fn main() {
+//! let mut ctx = Context::default();
+//! ctx.insert(Clock::tokio());
+//!
+//! let start = ctx.clock().now();
+//! tokio::time::advance(Duration::from_secs(60)).await;
+//! assert_eq!(ctx.clock().now() - start, Duration::from_secs(60));
+//! # }
+//! ```
+
+use crate::Context;
+use std::{
+    fmt,
+    sync::Arc,
+    time::{Instant, SystemTime},
+};
+
+/// A source of the current time, used by a [`Clock`].
+pub trait TimeSource: fmt::Debug + Send + Sync + 'static {
+    /// Returns the current (monotonic) time.
+    fn now(&self) -> Instant;
+
+    /// Returns the current system (wall clock) time.
+    fn system_now(&self) -> SystemTime;
+}
+
+#[derive(Debug, Clone, Default)]
+/// A clock providing the current time, inserted in the [`Context`]
+/// to control the time used by services and layers.
+///
+/// See the [module docs](self) for more details.
+pub struct Clock {
+    source: Option<Arc<dyn TimeSource>>,
+}
+
+impl Clock {
+    /// Create a new [`Clock`] using the given [`TimeSource`].
+    pub fn new(source: impl TimeSource) -> Self {
+        Self {
+            source: Some(Arc::new(source)),
+        }
+    }
+
+    /// The clock of the operating system, used by default.
+    pub fn system() -> Self {
+        Self { source: None }
+    }
+
+    /// A clock following the time of the tokio runtime,
+    /// which can be paused and advanced in tests.
+    ///
+    /// The system time is derived from the system time at the moment
+    /// this clock was created, advanced by the time of the tokio runtime.
+    pub fn tokio() -> Self {
+        Self::new(TokioTimeSource {
+            start: tokio::time::Instant::now(),
+            system_start: SystemTime::now(),
+        })
+    }
+
+    /// Returns the current (monotonic) time.
+    pub fn now(&self) -> Instant {
+        match &self.source {
+            Some(source) => source.now(),
+            None => Instant::now(),
+        }
+    }
+
+    /// Returns the current system (wall clock) time.
+    pub fn system_now(&self) -> SystemTime {
+        match &self.source {
+            Some(source) => source.system_now(),
+            None => SystemTime::now(),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct TokioTimeSource {
+    start: tokio::time::Instant,
+    system_start: SystemTime,
+}
+
+impl TimeSource for TokioTimeSource {
+    fn now(&self) -> Instant {
+        tokio::time::Instant::now().into_std()
+    }
+
+    fn system_now(&self) -> SystemTime {
+        self.system_start + self.start.elapsed()
+    }
+}
+
+impl<S> Context<S> {
+    /// Returns the [`Clock`] to be used for this [`Context`],
+    /// which is the [`Clock::system`] clock unless another one was inserted.
+    pub fn clock(&self) -> Clock {
+        self.get::<Clock>().cloned().unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test(start_paused = true)]
+    async fn test_tokio_clock() {
+        let clock = Clock::tokio();
+        let (now, system_now) = (clock.now(), clock.system_now());
+
+        tokio::time::advance(Duration::from_secs(3600)).await;
+        assert_eq!(clock.now() - now, Duration::from_secs(3600));
+        assert_eq!(
+            clock.system_now().duration_since(system_now).unwrap(),
+            Duration::from_secs(3600)
+        );
+    }
+
+    #[test]
+    fn test_context_clock_default() {
+        let ctx = Context::default();
+        assert!(ctx.clock().source.is_none());
+    }
+}
@@ -1,12 +1,21 @@
 use super::{svc::SendRequest, HttpClientService};
 use crate::executor::HyperExecutor;
+use bytes::Bytes;
 use hyper_util::rt::TokioIo;
 use rama_core::{
     error::{BoxError, OpaqueError},
     Context, Layer, Service,
 };
-use rama_http_types::{dep::http_body, Request, Version};
+use rama_http_types::{
+    dep::{
+        http_body,
+        http_body_util::{BodyExt, Empty},
+    },
+    header::{self, HeaderName, HeaderValue},
+    Request, StatusCode, Version,
+};
 use rama_net::{
+    address::ProxyAddress,
     client::{ConnectorService, EstablishedClientConnection},
     stream::Stream,
 };
@@ -19,15 +28,63 @@ use tracing::trace;
 #[cfg(any(feature = "rustls", feature = "boring"))]
 use rama_net::tls::{client::NegotiatedTlsParameters, ApplicationProtocol};
 
+#[derive(Debug, Clone, Default)]
+#[non_exhaustive]
+/// Marker which can be inserted in the [`Context`] of a request,
+/// such that the [`HttpConnector`] speaks h2 over a cleartext (non-tls) connection
+/// without any negotiation, also known as "h2c with prior knowledge".
+///
+/// Useful to talk to internal h2 (e.g. gRPC) services which are served without tls.
+/// Requests made over tls, or via a proxy, are not affected, as for the former
+/// the http version is negotiated using ALPN.
+///
+/// See [`Http2Upgrade`] to only use h2 in case the server agrees to it.
+pub struct Http2PriorKnowledge;
+
+impl Http2PriorKnowledge {
+    /// Create a new [`Http2PriorKnowledge`] marker.
+    pub const fn new() -> Self {
+        Self
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+#[non_exhaustive]
+/// Marker which can be inserted in the [`Context`] of a request,
+/// such that the [`HttpConnector`] negotiates h2 over a cleartext (non-tls) connection
+/// using the HTTP/1.1 `Upgrade: h2c` mechanism.
+///
+/// The upgrade is offered using an `OPTIONS *` request, prior to sending the actual request.
+/// Servers which do not accept the upgrade keep on serving the connection using HTTP/1.1,
+/// while for servers which accept it a new connection is established, speaking h2 right away,
+/// as the underlying h2 client cannot take over the stream of the upgraded request.
+///
+/// Requests made over tls, or via a proxy, are not affected.
+/// [`Http2PriorKnowledge`] takes priority over this marker.
+pub struct Http2Upgrade;
+
+impl Http2Upgrade {
+    /// Create a new [`Http2Upgrade`] marker.
+    pub const fn new() -> Self {
+        Self
+    }
+}
+
 /// A [`Service`] which establishes an HTTP Connection.
 pub struct HttpConnector<S> {
     inner: S,
+    preserve_header_case: bool,
+    http2_prior_knowledge: bool,
+    http2_upgrade: bool,
 }
 
 impl<S: fmt::Debug> fmt::Debug for HttpConnector<S> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("HttpConnector")
             .field("inner", &self.inner)
+            .field("preserve_header_case", &self.preserve_header_case)
+            .field("http2_prior_knowledge", &self.http2_prior_knowledge)
+            .field("http2_upgrade", &self.http2_upgrade)
             .finish()
     }
 }
@@ -35,7 +92,75 @@ impl<S: fmt::Debug> fmt::Debug for HttpConnector<S> {
 impl<S> HttpConnector<S> {
     /// Create a new [`HttpConnector`].
     pub const fn new(inner: S) -> Self {
-        Self { inner }
+        Self {
+            inner,
+            preserve_header_case: false,
+            http2_prior_knowledge: false,
+            http2_upgrade: false,
+        }
+    }
+
+    /// Set whether http/1 connections preserve the original casing of header names.
+    ///
+    /// When enabled, the header names of requests received by an [`HttpServer`]
+    /// configured to preserve header cases are sent using their original casing
+    /// (e.g. `X-Custom-HeaDer`), instead of in lowercase. Likewise the original
+    /// casing of the response header names is recorded, such that the server
+    /// can forward them as-is, which is what a proxy typically wants.
+    ///
+    /// Default is `false`. This setting does not affect h2 connections.
+    ///
+    /// [`HttpServer`]: crate::server::HttpServer
+    pub fn set_preserve_header_case(&mut self, enabled: bool) -> &mut Self {
+        self.preserve_header_case = enabled;
+        self
+    }
+
+    /// Replace this [`HttpConnector`] with whether http/1 connections
+    /// preserve the original casing of header names.
+    ///
+    /// See [`Self::set_preserve_header_case`] for more information.
+    pub fn with_preserve_header_case(mut self, enabled: bool) -> Self {
+        self.preserve_header_case = enabled;
+        self
+    }
+
+    /// Set whether h2 is spoken over all cleartext (non-tls) connections,
+    /// without any negotiation.
+    ///
+    /// Default is `false`, in which case it can still be enabled for individual
+    /// requests by inserting the [`Http2PriorKnowledge`] marker in their [`Context`].
+    pub fn set_http2_prior_knowledge(&mut self, enabled: bool) -> &mut Self {
+        self.http2_prior_knowledge = enabled;
+        self
+    }
+
+    /// Replace this [`HttpConnector`] with whether h2 is spoken
+    /// over all cleartext (non-tls) connections, without any negotiation.
+    ///
+    /// See [`Self::set_http2_prior_knowledge`] for more information.
+    pub fn with_http2_prior_knowledge(mut self, enabled: bool) -> Self {
+        self.http2_prior_knowledge = enabled;
+        self
+    }
+
+    /// Set whether an upgrade to h2 is offered over all cleartext (non-tls) connections,
+    /// using the HTTP/1.1 `Upgrade: h2c` mechanism.
+    ///
+    /// Default is `false`, in which case it can still be enabled for individual
+    /// requests by inserting the [`Http2Upgrade`] marker in their [`Context`].
+    pub fn set_http2_upgrade(&mut self, enabled: bool) -> &mut Self {
+        self.http2_upgrade = enabled;
+        self
+    }
+
+    /// Replace this [`HttpConnector`] with whether an upgrade to h2 is offered
+    /// over all cleartext (non-tls) connections, using the HTTP/1.1 `Upgrade: h2c` mechanism.
+    ///
+    /// See [`Self::set_http2_upgrade`] for more information.
+    pub fn with_http2_upgrade(mut self, enabled: bool) -> Self {
+        self.http2_upgrade = enabled;
+        self
     }
 
     define_inner_service_accessors!();
@@ -48,6 +173,9 @@ where
     fn clone(&self) -> Self {
         Self {
             inner: self.inner.clone(),
+            preserve_header_case: self.preserve_header_case,
+            http2_prior_knowledge: self.http2_prior_knowledge,
+            http2_upgrade: self.http2_upgrade,
         }
     }
 }
@@ -68,10 +196,7 @@ where
     ) -> Result<Self::Response, Self::Error> {
         let EstablishedClientConnection {
             ctx,
-            #[cfg(any(feature = "rustls", feature = "boring"))]
             mut req,
-            #[cfg(not(any(feature = "rustls", feature = "boring")))]
-            req,
             conn,
             addr,
         } = self.inner.connect(ctx, req).await.map_err(Into::into)?;
@@ -102,7 +227,49 @@ where
             *req.version_mut() = new_version;
         }
 
-        let io = TokioIo::new(Box::pin(conn));
+        // h2c is only spoken directly to the server, over cleartext connections
+        let h2c = matches!(req.uri().scheme_str(), None | Some("http"))
+            && req.version() != Version::HTTP_2
+            && !ctx.contains::<ProxyAddress>();
+
+        let mut io = TokioIo::new(Box::pin(conn));
+        let mut ctx = ctx;
+        let mut addr = addr;
+
+        if h2c && (self.http2_prior_knowledge || ctx.contains::<Http2PriorKnowledge>()) {
+            trace!(
+                "setting request version to HTTP/2 based on prior knowledge (was: {:?})",
+                req.version(),
+            );
+            *req.version_mut() = Version::HTTP_2;
+        } else if h2c
+            && req.version() == Version::HTTP_11
+            && (self.http2_upgrade || ctx.contains::<Http2Upgrade>())
+        {
+            let host = req.headers().get(header::HOST).cloned().or_else(|| {
+                req.uri()
+                    .authority()
+                    .and_then(|authority| HeaderValue::from_str(authority.as_str()).ok())
+            });
+            match http2_upgrade(io, host).await? {
+                Http2UpgradeOutcome::Declined(Some(reusable_io)) => {
+                    trace!(uri = %req.uri(), "h2c upgrade declined: continue using HTTP/1.1");
+                    io = reusable_io;
+                }
+                outcome => {
+                    let upgraded = matches!(outcome, Http2UpgradeOutcome::Upgraded);
+                    trace!(uri = %req.uri(), upgraded, "h2c upgrade probed: establish new connection");
+                    let established = self.inner.connect(ctx, req).await.map_err(Into::into)?;
+                    ctx = established.ctx;
+                    req = established.req;
+                    addr = established.addr;
+                    io = TokioIo::new(Box::pin(established.conn));
+                    if upgraded {
+                        *req.version_mut() = Version::HTTP_2;
+                    }
+                }
+            }
+        }
 
         match req.version() {
             Version::HTTP_2 => {
@@ -127,10 +294,14 @@ where
             }
             Version::HTTP_11 | Version::HTTP_10 | Version::HTTP_09 => {
                 trace!(uri = %req.uri(), "create ~h1 client executor");
-                let (sender, conn) = hyper::client::conn::http1::handshake(io).await?;
+                let (sender, conn) = hyper::client::conn::http1::Builder::new()
+                    .preserve_header_case(self.preserve_header_case)
+                    .handshake(io)
+                    .await?;
 
                 ctx.spawn(async move {
-                    if let Err(err) = conn.await {
+                    // upgrades allow the response to be used for e.g. websocket traffic
+                    if let Err(err) = conn.with_upgrades().await {
                         tracing::debug!("connection failed: {:?}", err);
                     }
                 });
@@ -153,6 +324,70 @@ where
     }
 }
 
+/// Settings sent along with the h2c upgrade: `SETTINGS_ENABLE_PUSH = 0`,
+/// encoded as base64url (without padding) as required for the `HTTP2-Settings` header.
+const HTTP2_SETTINGS: &str = "AAIAAAAA";
+
+/// Outcome of offering a server to upgrade the connection to h2.
+enum Http2UpgradeOutcome<IO> {
+    /// The server accepted the upgrade.
+    Upgraded,
+    /// The server declined the upgrade, returning the
+    /// connection in case it can still be used for HTTP/1.1.
+    Declined(Option<IO>),
+}
+
+/// Offer the server to upgrade the connection to h2 using an `OPTIONS *` request.
+async fn http2_upgrade<IO>(
+    io: IO,
+    host: Option<HeaderValue>,
+) -> Result<Http2UpgradeOutcome<IO>, BoxError>
+where
+    IO: hyper::rt::Read + hyper::rt::Write + Unpin + Send + 'static,
+{
+    let (mut sender, conn) = hyper::client::conn::http1::handshake::<_, Empty<Bytes>>(io).await?;
+
+    let mut builder = Request::options("*")
+        .header(header::CONNECTION, "Upgrade, HTTP2-Settings")
+        .header(header::UPGRADE, "h2c")
+        .header(
+            HeaderName::from_static("http2-settings"),
+            HeaderValue::from_static(HTTP2_SETTINGS),
+        );
+    if let Some(host) = host {
+        builder = builder.header(header::HOST, host);
+    }
+    let probe_req = builder.body(Empty::new())?;
+
+    let probe = async move {
+        let resp = sender.send_request(probe_req).await?;
+        if resp.status() == StatusCode::SWITCHING_PROTOCOLS {
+            return Ok::<_, BoxError>(None);
+        }
+        let keep_alive = resp.version() == Version::HTTP_11
+            && !resp
+                .headers()
+                .get(header::CONNECTION)
+                .and_then(|value| value.to_str().ok())
+                .is_some_and(|value| value.to_ascii_lowercase().contains("close"));
+        // the body has to be consumed, prior to reusing the connection
+        resp.into_body().collect().await?;
+        Ok(Some(keep_alive))
+        // dropping the sender makes the connection return its io
+    };
+    let (probe, parts) = tokio::join!(probe, conn.without_shutdown());
+
+    Ok(match probe? {
+        None => Http2UpgradeOutcome::Upgraded,
+        Some(keep_alive) => {
+            let parts = parts?;
+            Http2UpgradeOutcome::Declined(
+                (keep_alive && parts.read_buf.is_empty()).then_some(parts.io),
+            )
+        }
+    })
+}
+
 /// A [`Layer`] that produces an [`HttpConnector`].
 #[derive(Debug, Clone)]
 #[non_exhaustive]
@@ -175,6 +410,172 @@ impl<S> Layer<S> for HttpConnectorLayer {
     type Service = HttpConnector<S>;
 
     fn layer(&self, inner: S) -> Self::Service {
-        HttpConnector { inner }
+        HttpConnector::new(inner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{client::HttpClient, server::HttpServer};
+    use rama_core::{rt::Executor, service::service_fn};
+    use rama_http_types::{Body, BodyExtractExt, Response};
+    use std::convert::Infallible;
+
+    #[tokio::test]
+    async fn test_http2_prior_knowledge() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let service =
+            HttpServer::h2(Executor::default()).service(service_fn(|req: Request| async move {
+                Ok::<_, Infallible>(Response::new(Body::from(format!("{:?}", req.version()))))
+            }));
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                let service = service.clone();
+                tokio::spawn(async move { service.serve(Context::default(), stream).await });
+            }
+        });
+
+        let request = || {
+            Request::get(format!("http://{addr}/"))
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        let mut ctx = Context::default();
+        ctx.insert(Http2PriorKnowledge::new());
+        let resp = HttpClient::new().serve(ctx, request()).await.unwrap();
+        // the response version is normalized to the version of the original request
+        assert_eq!(resp.version(), Version::HTTP_11);
+        assert_eq!(
+            resp.into_body().try_into_string().await.unwrap(),
+            "HTTP/2.0"
+        );
+
+        let resp = HttpClient::new()
+            .with_http2_prior_knowledge(true)
+            .serve(Context::default(), request())
+            .await
+            .unwrap();
+        assert_eq!(
+            resp.into_body().try_into_string().await.unwrap(),
+            "HTTP/2.0"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_http2_prior_knowledge_via_proxy() {
+        let connector = HttpConnector::new(service_fn(
+            |ctx: Context<()>, req: Request<Body>| async move {
+                let (conn, _) = tokio::io::duplex(1024);
+                Ok::<_, Infallible>(EstablishedClientConnection {
+                    ctx,
+                    req,
+                    conn,
+                    addr: ([127, 0, 0, 1], 8080).into(),
+                })
+            },
+        ))
+        .with_http2_prior_knowledge(true);
+
+        let mut ctx = Context::default();
+        ctx.insert(ProxyAddress::try_from("http://127.0.0.1:8080").unwrap());
+        let req = Request::get("http://example.com/")
+            .body(Body::empty())
+            .unwrap();
+        let established = connector.serve(ctx, req).await.unwrap();
+        assert_eq!(established.req.version(), Version::HTTP_11);
+    }
+
+    #[tokio::test]
+    async fn test_http2_upgrade() {
+        use std::sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        };
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        // server which accepts the h2c upgrade, serving h2 (with prior knowledge) afterwards
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let service =
+            HttpServer::h2(Executor::default()).service(service_fn(|req: Request| async move {
+                Ok::<_, Infallible>(Response::new(Body::from(format!("{:?}", req.version()))))
+            }));
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let service = service.clone();
+                tokio::spawn(async move {
+                    let mut preface = [0; 14];
+                    stream.peek(&mut preface).await.unwrap();
+                    if &preface == b"PRI * HTTP/2.0" {
+                        let _ = service.serve(Context::default(), stream).await;
+                        return;
+                    }
+
+                    let mut head = Vec::new();
+                    while !head.ends_with(b"\r\n\r\n") {
+                        head.push(stream.read_u8().await.unwrap());
+                    }
+                    let head = String::from_utf8(head).unwrap().to_ascii_lowercase();
+                    assert!(head.starts_with("options * http/1.1\r\n"), "{head}");
+                    assert!(head.contains("upgrade: h2c\r\n"), "{head}");
+                    assert!(head.contains("http2-settings: "), "{head}");
+                    stream
+                        .write_all(
+                            b"HTTP/1.1 101 Switching Protocols\r\nConnection: Upgrade\r\nUpgrade: h2c\r\n\r\n",
+                        )
+                        .await
+                        .unwrap();
+                });
+            }
+        });
+
+        let mut ctx = Context::default();
+        ctx.insert(Http2Upgrade::new());
+        let req = Request::get(format!("http://{addr}/"))
+            .body(Body::empty())
+            .unwrap();
+        let resp = HttpClient::new().serve(ctx, req).await.unwrap();
+        assert_eq!(
+            resp.into_body().try_into_string().await.unwrap(),
+            "HTTP/2.0"
+        );
+
+        // server which does not support h2c, reusing the connection of the declined upgrade
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let connections = Arc::new(AtomicUsize::new(0));
+        let service = HttpServer::http1().service(service_fn(|req: Request| async move {
+            Ok::<_, Infallible>(Response::new(Body::from(format!("{:?}", req.version()))))
+        }));
+        tokio::spawn({
+            let connections = connections.clone();
+            async move {
+                loop {
+                    let (stream, _) = listener.accept().await.unwrap();
+                    connections.fetch_add(1, Ordering::SeqCst);
+                    let service = service.clone();
+                    tokio::spawn(async move { service.serve(Context::default(), stream).await });
+                }
+            }
+        });
+
+        let req = Request::get(format!("http://{addr}/"))
+            .body(Body::empty())
+            .unwrap();
+        let resp = HttpClient::new()
+            .with_http2_upgrade(true)
+            .serve(Context::default(), req)
+            .await
+            .unwrap();
+        assert_eq!(
+            resp.into_body().try_into_string().await.unwrap(),
+            "HTTP/1.1"
+        );
+        assert_eq!(connections.load(Ordering::SeqCst), 1);
     }
 }
@@ -0,0 +1,242 @@
+use rama_http_types::{
+    dep::http_body::{self, Frame, SizeHint},
+    header::EXPECT,
+    HeaderValue, Request, Version,
+};
+use std::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context as TaskContext, Poll},
+    time::Duration,
+};
+use tokio::{sync::oneshot, time::Sleep};
+
+/// The default time the [`HttpClient`] waits for a `100 Continue`
+/// prior to sending the body of an `Expect: 100-continue` request.
+///
+/// [`HttpClient`]: super::HttpClient
+pub(super) const DEFAULT_EXPECT_CONTINUE_TIMEOUT: Duration = Duration::from_secs(1);
+
+#[derive(Clone)]
+/// Gate opened once a `100 Continue` is received for the request,
+/// inserted in the request extensions by the [`HttpClient`].
+///
+/// [`HttpClient`]: super::HttpClient
+pub(crate) struct ContinueGate(Arc<parking_lot::Mutex<Option<oneshot::Sender<()>>>>);
+
+impl ContinueGate {
+    fn new() -> (Self, oneshot::Receiver<()>) {
+        let (tx, rx) = oneshot::channel();
+        (Self(Arc::new(parking_lot::Mutex::new(Some(tx)))), rx)
+    }
+
+    /// Open the gate, allowing the request body to be sent.
+    pub(crate) fn open(&self) {
+        if let Some(tx) = self.0.lock().take() {
+            let _ = tx.send(());
+        }
+    }
+}
+
+impl fmt::Debug for ContinueGate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("ContinueGate").finish()
+    }
+}
+
+/// Returns `true` in case the request expects a `100 Continue`
+/// prior to sending its body, adding the `Expect` header in case
+/// the body is at least as large as the given threshold.
+pub(super) fn expects_continue<B: http_body::Body>(
+    req: &mut Request<B>,
+    threshold: Option<u64>,
+) -> bool {
+    if req.version() != Version::HTTP_11 || req.body().is_end_stream() {
+        return false;
+    }
+    if let Some(expect) = req.headers().get(EXPECT) {
+        return expect.as_bytes().eq_ignore_ascii_case(b"100-continue");
+    }
+    let size = req.body().size_hint();
+    match threshold {
+        Some(threshold) if size.exact().map_or(true, |size| size >= threshold) => {
+            req.headers_mut()
+                .insert(EXPECT, HeaderValue::from_static("100-continue"));
+            true
+        }
+        _ => false,
+    }
+}
+
+struct Waiting {
+    continued: oneshot::Receiver<()>,
+    timeout: Duration,
+    sleep: Option<Pin<Box<Sleep>>>,
+}
+
+/// Request body which is held back until a `100 Continue` is received
+/// for an `Expect: 100-continue` request, or until the timeout expires.
+pub(super) struct ExpectContinueBody<B> {
+    inner: B,
+    waiting: Option<Waiting>,
+}
+
+impl<B> ExpectContinueBody<B> {
+    /// Create a body which is sent right away.
+    pub(super) fn new(inner: B) -> Self {
+        Self {
+            inner,
+            waiting: None,
+        }
+    }
+
+    /// Hold back the body until the returned gate is opened,
+    /// or until the timeout expires, starting from when the body is first polled.
+    pub(super) fn wait_for_continue(&mut self, timeout: Duration) -> ContinueGate {
+        let (gate, continued) = ContinueGate::new();
+        self.waiting = Some(Waiting {
+            continued,
+            timeout,
+            sleep: None,
+        });
+        gate
+    }
+
+    fn poll_continue(&mut self, cx: &mut TaskContext<'_>) -> Poll<()> {
+        let Some(waiting) = &mut self.waiting else {
+            return Poll::Ready(());
+        };
+        if Pin::new(&mut waiting.continued).poll(cx).is_ready() {
+            tracing::trace!("http client: received 100 continue, send body");
+        } else {
+            let sleep = waiting
+                .sleep
+                .get_or_insert_with(|| Box::pin(tokio::time::sleep(waiting.timeout)));
+            if sleep.as_mut().poll(cx).is_pending() {
+                return Poll::Pending;
+            }
+            tracing::trace!("http client: no 100 continue received in time, send body");
+        }
+        self.waiting = None;
+        Poll::Ready(())
+    }
+}
+
+impl<B> http_body::Body for ExpectContinueBody<B>
+where
+    B: http_body::Body + Unpin,
+{
+    type Data = B::Data;
+    type Error = B::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut TaskContext<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        if self.poll_continue(cx).is_pending() {
+            return Poll::Pending;
+        }
+        Pin::new(&mut self.inner).poll_frame(cx)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rama_http_types::{dep::http_body_util::BodyExt, Body};
+
+    #[test]
+    fn test_expects_continue() {
+        let request = |expect: Option<&str>, body: &'static str| {
+            let mut builder = Request::post("/");
+            if let Some(expect) = expect {
+                builder = builder.header(EXPECT, expect);
+            }
+            builder.body(Body::from(body)).unwrap()
+        };
+
+        assert!(expects_continue(
+            &mut request(Some("100-continue"), "data"),
+            None
+        ));
+        assert!(!expects_continue(
+            &mut request(Some("100-continue"), ""),
+            None
+        ));
+        assert!(!expects_continue(&mut request(None, "data"), None));
+
+        let mut req = request(None, "data");
+        assert!(expects_continue(&mut req, Some(4)));
+        assert_eq!(req.headers()[EXPECT], "100-continue");
+        assert!(!expects_continue(&mut request(None, "data"), Some(5)));
+    }
+
+    #[tokio::test]
+    async fn test_expect_continue_body() {
+        let mut body = ExpectContinueBody::new(Body::from("data"));
+        let gate = body.wait_for_continue(Duration::from_secs(60));
+        let handle = tokio::spawn(async move { body.collect().await.unwrap().to_bytes() });
+        tokio::task::yield_now().await;
+        assert!(!handle.is_finished());
+        gate.open();
+        assert_eq!(handle.await.unwrap(), "data");
+
+        let mut body = ExpectContinueBody::new(Body::from("data"));
+        let _gate = body.wait_for_continue(Duration::from_millis(10));
+        assert_eq!(body.collect().await.unwrap().to_bytes(), "data");
+    }
+
+    #[tokio::test]
+    async fn test_http_client_waits_for_continue() {
+        use crate::client::HttpClient;
+        use rama_core::{Context, Service};
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut buf = Vec::new();
+            let mut chunk = [0; 1024];
+            while !buf.windows(4).any(|w| w == b"\r\n\r\n") {
+                let n = stream.read(&mut chunk).await.unwrap();
+                buf.extend_from_slice(&chunk[..n]);
+            }
+            // only the head is sent prior to the 100 continue
+            assert!(buf.ends_with(b"\r\n\r\n"));
+            assert!(String::from_utf8_lossy(&buf)
+                .to_lowercase()
+                .contains("expect: 100-continue"));
+            stream
+                .write_all(b"HTTP/1.1 100 Continue\r\n\r\n")
+                .await
+                .unwrap();
+            let mut body = [0; 4];
+            stream.read_exact(&mut body).await.unwrap();
+            assert_eq!(&body, b"data");
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n")
+                .await
+                .unwrap();
+        });
+
+        let client = HttpClient::new()
+            .with_expect_continue_threshold(0)
+            .with_expect_continue_timeout(Duration::from_secs(60));
+        let req = Request::post(format!("http://{addr}/upload"))
+            .body(Body::from("data"))
+            .unwrap();
+        let resp = client.serve(Context::default(), req).await.unwrap();
+        assert_eq!(resp.status(), rama_http_types::StatusCode::OK);
+    }
+}
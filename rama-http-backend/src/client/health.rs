@@ -0,0 +1,176 @@
+use super::HttpClient;
+use rama_core::{
+    error::{BoxError, ErrorContext, OpaqueError},
+    service::balance::{HealthProbe, Upstream},
+    Context, Service,
+};
+use rama_http_types::{Body, Method, Request, StatusCode};
+use std::{fmt, future::Future};
+
+/// A [`HealthProbe`] which considers an [`Upstream`] healthy
+/// if a `GET` request to its health endpoint returns a successful (2xx) status.
+///
+/// The id of the upstream is used as the base uri of the health endpoint,
+/// e.g. `http://example.com:8080` or `example.com:8080` (using `http`).
+pub struct HttpHealthProbe {
+    client: HttpClient,
+    path: String,
+    expected_status: Option<StatusCode>,
+}
+
+impl HttpHealthProbe {
+    /// Create a new [`HttpHealthProbe`] for the health endpoint at the given path.
+    pub fn new(path: impl Into<String>) -> Self {
+        Self {
+            client: HttpClient::default(),
+            path: path.into(),
+            expected_status: None,
+        }
+    }
+
+    /// Set the [`HttpClient`] used to make the health requests,
+    /// e.g. to configure the tls config used for `https` upstreams.
+    pub fn client(mut self, client: HttpClient) -> Self {
+        self.client = client;
+        self
+    }
+
+    /// Set the [`HttpClient`] used to make the health requests,
+    /// e.g. to configure the tls config used for `https` upstreams.
+    pub fn set_client(&mut self, client: HttpClient) -> &mut Self {
+        self.client = client;
+        self
+    }
+
+    /// Expect the given status instead of any successful (2xx) status.
+    pub fn expected_status(mut self, status: StatusCode) -> Self {
+        self.expected_status = Some(status);
+        self
+    }
+
+    /// Expect the given status instead of any successful (2xx) status.
+    pub fn set_expected_status(&mut self, status: StatusCode) -> &mut Self {
+        self.expected_status = Some(status);
+        self
+    }
+
+    fn uri(&self, id: &str) -> String {
+        let base = id.trim_end_matches('/');
+        let sep = if self.path.starts_with('/') { "" } else { "/" };
+        if base.contains("://") {
+            format!("{base}{sep}{}", self.path)
+        } else {
+            format!("http://{base}{sep}{}", self.path)
+        }
+    }
+}
+
+impl fmt::Debug for HttpHealthProbe {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HttpHealthProbe")
+            .field("client", &self.client)
+            .field("path", &self.path)
+            .field("expected_status", &self.expected_status)
+            .finish()
+    }
+}
+
+impl<S> HealthProbe<S> for HttpHealthProbe
+where
+    S: Send + Sync + 'static,
+{
+    fn probe(
+        &self,
+        upstream: &Upstream<S>,
+    ) -> impl Future<Output = Result<(), BoxError>> + Send + '_ {
+        let req = Request::builder()
+            .method(Method::GET)
+            .uri(self.uri(upstream.id()))
+            .body(Body::empty())
+            .context("create health request");
+        async move {
+            let resp = self.client.serve(Context::default(), req?).await?;
+            let healthy = match self.expected_status {
+                Some(status) => resp.status() == status,
+                None => resp.status().is_success(),
+            };
+            if !healthy {
+                return Err(OpaqueError::from_display(format!(
+                    "unexpected health status: {}",
+                    resp.status()
+                ))
+                .into_boxed());
+            }
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::HttpServer;
+    use rama_core::service::{
+        balance::{HealthChecker, UpstreamPool},
+        service_fn,
+    };
+    use rama_http_types::Response;
+    use rama_tcp::server::TcpListener;
+    use std::convert::Infallible;
+
+    #[test]
+    fn test_probe_uri() {
+        let probe = HttpHealthProbe::new("/health");
+        assert_eq!(probe.uri("127.0.0.1:80"), "http://127.0.0.1:80/health");
+        assert_eq!(
+            probe.uri("https://example.com/"),
+            "https://example.com/health"
+        );
+        assert_eq!(
+            HttpHealthProbe::new("ready").uri("example.com"),
+            "http://example.com/ready"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_http_health_probe() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(
+            listener.serve(HttpServer::auto(Default::default()).service(service_fn(
+                |req: Request| async move {
+                    let status = if req.uri().path() == "/health" {
+                        StatusCode::OK
+                    } else {
+                        StatusCode::SERVICE_UNAVAILABLE
+                    };
+                    Ok::<_, Infallible>(
+                        Response::builder()
+                            .status(status)
+                            .body(Body::empty())
+                            .unwrap(),
+                    )
+                },
+            ))),
+        );
+
+        let pool = UpstreamPool::try_new([Upstream::new(addr.to_string(), ())]).unwrap();
+        let upstream = &pool.upstreams()[0];
+
+        HealthChecker::new(pool.clone(), HttpHealthProbe::new("/health"))
+            .unhealthy_threshold(1)
+            .check()
+            .await;
+        assert!(upstream.is_healthy());
+
+        HealthChecker::new(pool.clone(), HttpHealthProbe::new("/other"))
+            .unhealthy_threshold(1)
+            .check()
+            .await;
+        assert!(!upstream.is_healthy());
+        assert_eq!(
+            upstream.health().last_error.as_deref(),
+            Some("unexpected health status: 503 Service Unavailable")
+        );
+    }
+}
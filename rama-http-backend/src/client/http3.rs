@@ -0,0 +1,677 @@
+use super::{svc::SendRequest, HttpClientService};
+use bytes::{Buf, Bytes};
+use rama_core::{
+    error::{BoxError, ErrorContext, ErrorExt, OpaqueError},
+    Context, Service,
+};
+use rama_dns::{DnsResolver, HickoryDns};
+use rama_http_types::{
+    dep::{
+        http_body::{self, Frame},
+        http_body_util::BodyExt,
+    },
+    header::ALT_SVC,
+    HeaderMap, HeaderValue, Request, Response, Version,
+};
+use rama_net::{
+    address::{Authority, Host, ProxyAddress},
+    client::EstablishedClientConnection,
+    http::RequestContext,
+    stream::{ClientSocketInfo, SocketInfo},
+    tls::{client::NegotiatedTlsParameters, ApplicationProtocol, ProtocolVersion},
+    transport::TransportProtocol,
+};
+use rama_tls::rustls::client::TlsConnectorData;
+use std::{
+    collections::HashMap,
+    fmt,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    pin::Pin,
+    sync::Arc,
+    task::{ready, Context as TaskContext, Poll},
+    time::{Duration, Instant},
+};
+use tracing::trace;
+
+#[derive(Debug, Clone)]
+/// A [`Service`] which establishes an HTTP/3 connection, over QUIC.
+///
+/// Unlike the [`HttpConnector`], which speaks http over the (tls) stream
+/// established by its inner connector, the [`Http3Connector`] establishes
+/// the QUIC (udp) transport itself, as tls is an integral part of QUIC.
+///
+/// The server is connected to directly, as proxies are not supported for HTTP/3.
+/// The [`TlsConnectorData`] found in the [`Context`] (if any) takes priority
+/// over the one configured in this connector, while the negotiated application
+/// protocol is always `h3`.
+///
+/// All connections are established using the same (lazily bound) QUIC endpoint
+/// per address family, which is shared by the clones of this connector.
+///
+/// [`HttpConnector`]: super::HttpConnector
+pub struct Http3Connector<Dns = HickoryDns> {
+    dns: Dns,
+    tls_connector_data: Option<TlsConnectorData>,
+    endpoints: QuicEndpoints,
+}
+
+impl Http3Connector {
+    /// Create a new [`Http3Connector`].
+    pub fn new() -> Self {
+        Self {
+            dns: HickoryDns::default(),
+            tls_connector_data: None,
+            endpoints: QuicEndpoints::default(),
+        }
+    }
+}
+
+impl Default for Http3Connector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<Dns> Http3Connector<Dns> {
+    /// Consume `self` to attach the given `dns` (a [`DnsResolver`]) as a new [`Http3Connector`].
+    pub fn with_dns<OtherDns>(self, dns: OtherDns) -> Http3Connector<OtherDns>
+    where
+        OtherDns: DnsResolver<Error: Into<BoxError>> + Clone,
+    {
+        Http3Connector {
+            dns,
+            tls_connector_data: self.tls_connector_data,
+            endpoints: self.endpoints,
+        }
+    }
+
+    /// Set the [`TlsConnectorData`] used to secure the QUIC connections.
+    pub fn set_tls_connector_data(&mut self, data: TlsConnectorData) -> &mut Self {
+        self.tls_connector_data = Some(data);
+        self
+    }
+
+    /// Replace this [`Http3Connector`] with the [`TlsConnectorData`]
+    /// used to secure the QUIC connections.
+    pub fn with_tls_connector_data(mut self, data: TlsConnectorData) -> Self {
+        self.tls_connector_data = Some(data);
+        self
+    }
+}
+
+impl<Dns> Http3Connector<Dns>
+where
+    Dns: DnsResolver<Error: Into<BoxError>> + Clone,
+{
+    /// Establish an HTTP/3 connection to the given [`Authority`],
+    /// inserting the [`ClientSocketInfo`] and [`NegotiatedTlsParameters`] in the [`Context`].
+    pub(super) async fn connect<State, Body>(
+        &self,
+        ctx: &mut Context<State>,
+        authority: Authority,
+    ) -> Result<(HttpClientService<Body>, SocketAddr), OpaqueError>
+    where
+        State: Clone + Send + Sync + 'static,
+    {
+        let (host, port) = authority.into_parts();
+
+        let connector_data = match ctx
+            .get::<TlsConnectorData>()
+            .or(self.tls_connector_data.as_ref())
+        {
+            Some(data) => data.clone(),
+            None => TlsConnectorData::new()?,
+        };
+        let server_host = connector_data
+            .server_name()
+            .cloned()
+            .unwrap_or_else(|| host.clone());
+        let server_name = match &server_host {
+            Host::Name(domain) => domain.to_string(),
+            Host::Address(ip) => ip.to_string(),
+        };
+
+        let mut tls_config = connector_data.try_to_build_client_config()?;
+        tls_config.alpn_protocols = vec![ApplicationProtocol::HTTP_3.as_bytes().to_vec()];
+        let crypto = quinn::crypto::rustls::QuicClientConfig::try_from(tls_config)
+            .context("Http3Connector: create quic client config")?;
+        let client_config = quinn::ClientConfig::new(Arc::new(crypto));
+
+        let mut last_err = None;
+        for addr in self.resolve(host, port).await? {
+            trace!(%addr, server.name = %server_name, "Http3Connector: quic connect attempt");
+            let (conn, local_addr) = match self
+                .endpoints
+                .connect(&client_config, addr, &server_name)
+                .await
+            {
+                Ok(tuple) => tuple,
+                Err(err) => {
+                    trace!(%addr, %err, "Http3Connector: quic connect attempt failed");
+                    last_err = Some(err);
+                    continue;
+                }
+            };
+
+            let application_layer_protocol = conn
+                .handshake_data()
+                .and_then(|data| data.downcast::<quinn::crypto::rustls::HandshakeData>().ok())
+                .and_then(|data| data.protocol)
+                .map(ApplicationProtocol::from);
+
+            let (mut driver, sender) = h3::client::new(h3_quinn::Connection::new(conn.clone()))
+                .await
+                .context("Http3Connector: h3 handshake")?;
+            ctx.spawn(async move {
+                let err = driver.wait_idle().await;
+                trace!(%err, "Http3Connector: h3 connection closed");
+            });
+
+            ctx.insert(ClientSocketInfo(
+                SocketInfo::new(local_addr, addr).with_protocol(TransportProtocol::Udp),
+            ));
+            ctx.insert(NegotiatedTlsParameters {
+                protocol_version: ProtocolVersion::TLSv1_3,
+                application_layer_protocol,
+            });
+
+            let svc = HttpClientService(SendRequest::Http3(H3SendRequest { sender, conn }));
+            return Ok((svc, addr));
+        }
+
+        Err(last_err
+            .unwrap_or_else(|| OpaqueError::from_display("no address to connect to"))
+            .context(format!(
+                "Http3Connector: connect to {server_host} (port {port})"
+            )))
+    }
+
+    async fn resolve(&self, host: Host, port: u16) -> Result<Vec<SocketAddr>, OpaqueError> {
+        let domain = match host {
+            Host::Name(domain) => domain,
+            Host::Address(ip) => return Ok(vec![(ip, port).into()]),
+        };
+
+        let (ipv4, ipv6) = tokio::join!(
+            async {
+                self.dns
+                    .ipv4_lookup(domain.clone())
+                    .await
+                    .unwrap_or_else(|err| {
+                        let err = OpaqueError::from_boxed(err.into());
+                        trace!(%err, "Http3Connector: failed to resolve domain to IPv4 addresses");
+                        Vec::new()
+                    })
+            },
+            async {
+                self.dns
+                    .ipv6_lookup(domain.clone())
+                    .await
+                    .unwrap_or_else(|err| {
+                        let err = OpaqueError::from_boxed(err.into());
+                        trace!(%err, "Http3Connector: failed to resolve domain to IPv6 addresses");
+                        Vec::new()
+                    })
+            },
+        );
+        let addrs: Vec<SocketAddr> = ipv4
+            .into_iter()
+            .map(IpAddr::V4)
+            .chain(ipv6.into_iter().map(IpAddr::V6))
+            .map(|ip| (ip, port).into())
+            .collect();
+
+        if addrs.is_empty() {
+            return Err(OpaqueError::from_display(format!(
+                "Http3Connector: failed to resolve {domain} to any IP address"
+            )));
+        }
+        Ok(addrs)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+/// The QUIC client endpoints shared by the clones of an [`Http3Connector`],
+/// bound to the unspecified address of their family on first use.
+struct QuicEndpoints(Arc<QuicEndpointsInner>);
+
+#[derive(Debug, Default)]
+struct QuicEndpointsInner {
+    ipv4: tokio::sync::OnceCell<quinn::Endpoint>,
+    ipv6: tokio::sync::OnceCell<quinn::Endpoint>,
+}
+
+impl QuicEndpoints {
+    /// Establish a QUIC connection to the given address,
+    /// using the client endpoint of the same family.
+    async fn connect(
+        &self,
+        config: &quinn::ClientConfig,
+        addr: SocketAddr,
+        server_name: &str,
+    ) -> Result<(quinn::Connection, Option<SocketAddr>), OpaqueError> {
+        let (endpoint, bind_addr): (_, SocketAddr) = match addr {
+            SocketAddr::V4(_) => (&self.0.ipv4, (Ipv4Addr::UNSPECIFIED, 0).into()),
+            SocketAddr::V6(_) => (&self.0.ipv6, (Ipv6Addr::UNSPECIFIED, 0).into()),
+        };
+        let endpoint = endpoint
+            .get_or_try_init(|| async {
+                trace!(%bind_addr, "Http3Connector: bind quic endpoint");
+                quinn::Endpoint::client(bind_addr).context("Http3Connector: bind quic endpoint")
+            })
+            .await?;
+        let conn = endpoint
+            .connect_with(config.clone(), addr, server_name)
+            .context("Http3Connector: start quic connection")?
+            .await
+            .context("Http3Connector: establish quic connection")?;
+        Ok((conn, endpoint.local_addr().ok()))
+    }
+}
+
+impl<State, Body, Dns> Service<State, Request<Body>> for Http3Connector<Dns>
+where
+    State: Clone + Send + Sync + 'static,
+    Body: http_body::Body<Data: Send + 'static, Error: Into<BoxError>> + Unpin + Send + 'static,
+    Dns: DnsResolver<Error: Into<BoxError>> + Clone,
+{
+    type Response = EstablishedClientConnection<HttpClientService<Body>, State, Request<Body>>;
+    type Error = BoxError;
+
+    async fn serve(
+        &self,
+        mut ctx: Context<State>,
+        mut req: Request<Body>,
+    ) -> Result<Self::Response, Self::Error> {
+        if ctx.contains::<ProxyAddress>() {
+            return Err(OpaqueError::from_display(
+                "Http3Connector: HTTP/3 cannot be used via a proxy",
+            )
+            .into());
+        }
+
+        let authority = ctx
+            .get_or_try_insert_with_ctx::<RequestContext, _>(|ctx| (ctx, &req).try_into())
+            .context("Http3Connector: compute request context to get authority")?
+            .authority
+            .clone();
+
+        let (conn, addr) = self.connect(&mut ctx, authority).await?;
+        *req.version_mut() = Version::HTTP_3;
+
+        Ok(EstablishedClientConnection {
+            ctx,
+            req,
+            conn,
+            addr,
+        })
+    }
+}
+
+/// Sender of the requests over an HTTP/3 connection.
+pub(super) struct H3SendRequest {
+    sender: h3::client::SendRequest<h3_quinn::OpenStreams, Bytes>,
+    conn: quinn::Connection,
+}
+
+impl fmt::Debug for H3SendRequest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("H3SendRequest")
+            .field("remote_address", &self.conn.remote_address())
+            .finish()
+    }
+}
+
+impl H3SendRequest {
+    /// Returns `true` in case the connection is closed.
+    pub(super) fn is_closed(&self) -> bool {
+        self.conn.close_reason().is_some()
+    }
+
+    /// Send the request, including its body and trailers (if any),
+    /// prior to waiting for the response.
+    pub(super) async fn send_request<Body>(
+        &self,
+        req: Request<Body>,
+    ) -> Result<Response<H3ResponseBody>, BoxError>
+    where
+        Body: http_body::Body<Data: Send + 'static, Error: Into<BoxError>> + Unpin + Send + 'static,
+    {
+        let (parts, mut body) = req.into_parts();
+        let mut stream = self
+            .sender
+            .clone()
+            .send_request(Request::from_parts(parts, ()))
+            .await?;
+
+        loop {
+            let frame = match body.frame().await {
+                Some(frame) => frame.map_err(Into::into)?,
+                None => break,
+            };
+            match frame.into_data() {
+                Ok(mut data) => {
+                    stream
+                        .send_data(data.copy_to_bytes(data.remaining()))
+                        .await?;
+                }
+                Err(frame) => {
+                    if let Ok(trailers) = frame.into_trailers() {
+                        stream.send_trailers(trailers).await?;
+                    }
+                }
+            }
+        }
+        stream.finish().await?;
+
+        let resp = stream.recv_response().await?;
+        Ok(resp.map(|()| H3ResponseBody {
+            stream,
+            data_done: false,
+            done: false,
+        }))
+    }
+}
+
+/// Body of a response received over an HTTP/3 connection.
+pub(super) struct H3ResponseBody {
+    stream: h3::client::RequestStream<h3_quinn::BidiStream<Bytes>, Bytes>,
+    data_done: bool,
+    done: bool,
+}
+
+impl http_body::Body for H3ResponseBody {
+    type Data = Bytes;
+    type Error = BoxError;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut TaskContext<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let this = self.get_mut();
+        if this.done {
+            return Poll::Ready(None);
+        }
+
+        if !this.data_done {
+            match ready!(this.stream.poll_recv_data(cx)) {
+                Ok(Some(mut data)) => {
+                    return Poll::Ready(Some(Ok(Frame::data(
+                        data.copy_to_bytes(data.remaining()),
+                    ))));
+                }
+                Ok(None) => this.data_done = true,
+                Err(err) => {
+                    this.done = true;
+                    return Poll::Ready(Some(Err(err.into())));
+                }
+            }
+        }
+
+        let result = ready!(this.stream.poll_recv_trailers(cx));
+        this.done = true;
+        match result {
+            Ok(Some(trailers)) => Poll::Ready(Some(Ok(Frame::trailers(trailers)))),
+            Ok(None) => Poll::Ready(None),
+            Err(err) => Poll::Ready(Some(Err(err.into()))),
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.done
+    }
+}
+
+/// The max age of an alternative service which does not define one,
+/// as defined in <https://datatracker.ietf.org/doc/html/rfc7838#section-3.1>.
+const DEFAULT_ALT_SVC_MAX_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
+#[derive(Debug, Clone, Default)]
+/// Cache of the HTTP/3 alternative services advertised by origins
+/// using the `Alt-Svc` header, as defined in <https://datatracker.ietf.org/doc/html/rfc7838>.
+///
+/// Only alternatives on the same host as the origin are used,
+/// such that the tls server name remains the same.
+pub(super) struct AltSvcCache(Arc<parking_lot::Mutex<HashMap<Authority, (u16, Instant)>>>);
+
+impl AltSvcCache {
+    /// Returns the [`Authority`] of the HTTP/3 alternative of the given origin, if any.
+    pub(super) fn get(&self, origin: &Authority) -> Option<Authority> {
+        let mut alternatives = self.0.lock();
+        match alternatives.get(origin) {
+            Some((port, expires)) if *expires > Instant::now() => {
+                Some(Authority::new(origin.host().clone(), *port))
+            }
+            Some(_) => {
+                alternatives.remove(origin);
+                None
+            }
+            None => None,
+        }
+    }
+
+    /// Forget the HTTP/3 alternative of the given origin,
+    /// e.g. because it could not be connected to.
+    pub(super) fn remove(&self, origin: &Authority) {
+        self.0.lock().remove(origin);
+    }
+
+    /// Update the alternative of the given origin
+    /// using the `Alt-Svc` headers of its response.
+    pub(super) fn update(&self, origin: &Authority, headers: &HeaderMap) {
+        for value in headers.get_all(ALT_SVC) {
+            match parse_h3_alt_svc(value, origin.host()) {
+                Some(AltSvc::Clear) => {
+                    trace!(%origin, "alt-svc: clear alternatives");
+                    self.remove(origin);
+                }
+                Some(AltSvc::H3 { port, max_age }) => {
+                    trace!(%origin, port, ?max_age, "alt-svc: h3 alternative advertised");
+                    self.0
+                        .lock()
+                        .insert(origin.clone(), (port, Instant::now() + max_age));
+                }
+                None => continue,
+            }
+            return;
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum AltSvc {
+    Clear,
+    H3 { port: u16, max_age: Duration },
+}
+
+/// Parse the first (same host) `h3` alternative service of an `Alt-Svc` header value.
+fn parse_h3_alt_svc(value: &HeaderValue, host: &Host) -> Option<AltSvc> {
+    let value = value.to_str().ok()?.trim();
+    if value == "clear" {
+        return Some(AltSvc::Clear);
+    }
+
+    value.split(',').find_map(|alternative| {
+        let mut params = alternative.split(';').map(str::trim);
+        let (protocol, alt_authority) = params.next()?.split_once('=')?;
+        if protocol.trim() != "h3" {
+            return None;
+        }
+        let (alt_host, port) = alt_authority.trim().trim_matches('"').rsplit_once(':')?;
+        if !alt_host.is_empty() && alt_host != *host {
+            return None;
+        }
+        let port = port.parse().ok()?;
+
+        let max_age = params
+            .filter_map(|param| param.split_once('='))
+            .find(|(name, _)| name.trim() == "ma")
+            .and_then(|(_, value)| value.trim().trim_matches('"').parse().ok())
+            .map(Duration::from_secs)
+            .unwrap_or(DEFAULT_ALT_SVC_MAX_AGE);
+
+        Some(AltSvc::H3 { port, max_age })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::HttpClient;
+    use rama_http_types::{Body, BodyExtractExt};
+    use rama_net::tls::{
+        client::{ClientConfig, ServerVerifyMode},
+        server::SelfSignedData,
+    };
+    use rama_tls::rustls::{dep::rustls, test_certs::TestCerts};
+
+    #[test]
+    fn test_parse_h3_alt_svc() {
+        let host: Host = "example.com".parse().unwrap();
+        let parse = |value: &'static str| parse_h3_alt_svc(&HeaderValue::from_static(value), &host);
+
+        assert_eq!(parse("clear"), Some(AltSvc::Clear));
+        assert_eq!(
+            parse(r#"h3=":443"; ma=3600, h2=":443""#),
+            Some(AltSvc::H3 {
+                port: 443,
+                max_age: Duration::from_secs(3600)
+            })
+        );
+        assert_eq!(
+            parse(r#"h2=":443", h3="example.com:8443""#),
+            Some(AltSvc::H3 {
+                port: 8443,
+                max_age: DEFAULT_ALT_SVC_MAX_AGE
+            })
+        );
+        assert_eq!(parse(r#"h3="alt.example.com:443""#), None);
+        assert_eq!(parse(r#"h3-29=":443""#), None);
+        assert_eq!(parse(r#"h3=":not-a-port""#), None);
+    }
+
+    #[test]
+    fn test_alt_svc_cache() {
+        let origin: Authority = "example.com:443".parse().unwrap();
+        let cache = AltSvcCache::default();
+        assert_eq!(cache.get(&origin), None);
+
+        let mut headers = HeaderMap::new();
+        headers.insert(ALT_SVC, HeaderValue::from_static(r#"h3=":8443""#));
+        cache.update(&origin, &headers);
+        assert_eq!(
+            cache.get(&origin),
+            Some("example.com:8443".parse().unwrap())
+        );
+
+        headers.insert(ALT_SVC, HeaderValue::from_static(r#"h3=":8443"; ma=0"#));
+        cache.update(&origin, &headers);
+        assert_eq!(cache.get(&origin), None);
+
+        headers.insert(ALT_SVC, HeaderValue::from_static(r#"h3=":8443""#));
+        cache.update(&origin, &headers);
+        headers.insert(ALT_SVC, HeaderValue::from_static("clear"));
+        cache.update(&origin, &headers);
+        assert_eq!(cache.get(&origin), None);
+    }
+
+    #[tokio::test]
+    async fn test_http3_client() {
+        let certs = TestCerts::generate(
+            b"http3-client",
+            SelfSignedData {
+                subject_alternative_names: Some(vec!["127.0.0.1".to_owned()]),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let mut tls_config = rustls::ServerConfig::builder()
+            .with_no_client_auth()
+            .with_single_cert(certs.server_cert_chain(), certs.server_private_key())
+            .unwrap();
+        tls_config.alpn_protocols = vec![b"h3".to_vec()];
+        let server_config = quinn::ServerConfig::with_crypto(Arc::new(
+            quinn::crypto::rustls::QuicServerConfig::try_from(tls_config).unwrap(),
+        ));
+        let endpoint =
+            quinn::Endpoint::server(server_config, "127.0.0.1:0".parse().unwrap()).unwrap();
+        let addr = endpoint.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            while let Some(incoming) = endpoint.accept().await {
+                tokio::spawn(async move {
+                    let conn = incoming.await.unwrap();
+                    let mut conn =
+                        h3::server::Connection::<_, Bytes>::new(h3_quinn::Connection::new(conn))
+                            .await
+                            .unwrap();
+                    while let Ok(Some(resolver)) = conn.accept().await {
+                        let (req, mut stream) = resolver.resolve_request().await.unwrap();
+                        let mut body = Vec::new();
+                        while let Some(mut data) = stream.recv_data().await.unwrap() {
+                            body.extend_from_slice(&data.copy_to_bytes(data.remaining()));
+                        }
+                        let resp = Response::builder()
+                            .header("x-authority", req.uri().authority().unwrap().as_str())
+                            .body(())
+                            .unwrap();
+                        stream.send_response(resp).await.unwrap();
+                        stream
+                            .send_data(Bytes::from(format!(
+                                "{} {}",
+                                req.method(),
+                                String::from_utf8(body).unwrap()
+                            )))
+                            .await
+                            .unwrap();
+                        stream.finish().await.unwrap();
+                    }
+                });
+            }
+        });
+
+        let client = HttpClient::new().with_tls_config(ClientConfig {
+            server_verify_mode: Some(ServerVerifyMode::Disable),
+            ..Default::default()
+        });
+        let req = Request::post(format!("https://{addr}/echo"))
+            .version(Version::HTTP_3)
+            .body(Body::from("hello"))
+            .unwrap();
+        let resp = client.serve(Context::default(), req).await.unwrap();
+        assert_eq!(resp.version(), Version::HTTP_3);
+        assert_eq!(resp.headers()["x-authority"], addr.to_string());
+        assert_eq!(
+            resp.into_body().try_into_string().await.unwrap(),
+            "POST hello"
+        );
+
+        // HTTP/3 is only available over a secure scheme
+        let req = Request::get(format!("http://{addr}/echo"))
+            .version(Version::HTTP_3)
+            .body(Body::empty())
+            .unwrap();
+        assert!(client.serve(Context::default(), req).await.is_err());
+
+        // connections share the same quic endpoint
+        let connector = Http3Connector::new().with_tls_connector_data(
+            ClientConfig {
+                server_verify_mode: Some(ServerVerifyMode::Disable),
+                ..Default::default()
+            }
+            .try_into()
+            .unwrap(),
+        );
+        let mut local_addrs = Vec::new();
+        for _ in 0..2 {
+            let mut ctx = Context::default();
+            let _ = connector
+                .connect::<(), Body>(&mut ctx, addr.into())
+                .await
+                .unwrap();
+            let info = ctx.get::<ClientSocketInfo>().unwrap();
+            local_addrs.push(*info.local_addr().unwrap());
+        }
+        assert_eq!(local_addrs[0], local_addrs[1]);
+    }
+}
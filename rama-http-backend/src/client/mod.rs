@@ -8,7 +8,16 @@ use rama_core::{
 };
 use rama_http_types::{dep::http_body, Request, Response};
 use rama_net::client::{ConnectorService, EstablishedClientConnection};
+
+#[cfg(feature = "http3")]
+use rama_http_types::Version;
+#[cfg(feature = "http3")]
+use rama_net::{
+    address::{Authority, ProxyAddress},
+    http::RequestContext,
+};
 use rama_tcp::client::service::TcpConnector;
+use std::time::Duration;
 
 #[cfg(any(feature = "rustls", feature = "boring"))]
 use rama_tls::std::client::{TlsConnector, TlsConnectorData};
@@ -20,14 +29,40 @@ use rama_net::tls::client::ClientConfig;
 use rama_core::error::ErrorContext;
 
 mod svc;
+pub(crate) use svc::te_contains_trailers;
 #[doc(inline)]
 pub use svc::HttpClientService;
 
 mod conn;
 #[doc(inline)]
-pub use conn::{HttpConnector, HttpConnectorLayer};
+pub use conn::{Http2PriorKnowledge, Http2Upgrade, HttpConnector, HttpConnectorLayer};
 use tracing::trace;
 
+#[cfg(feature = "http3")]
+mod http3;
+#[cfg(feature = "http3")]
+use http3::AltSvcCache;
+#[cfg(feature = "http3")]
+#[doc(inline)]
+pub use http3::Http3Connector;
+
+mod progress;
+#[doc(inline)]
+pub use progress::{ProgressEvent, ProgressObserver};
+use progress::{RequestProgressBody, ResponseProgressBody, SharedProgressObserver};
+
+mod reuse;
+#[doc(inline)]
+pub use reuse::{ConnectionReuse, ConnectionReuseMetrics, CONNECTION_REUSED};
+
+mod health;
+#[doc(inline)]
+pub use health::HttpHealthProbe;
+
+mod expect_continue;
+pub(crate) use expect_continue::ContinueGate;
+use expect_continue::{expects_continue, ExpectContinueBody, DEFAULT_EXPECT_CONTINUE_TIMEOUT};
+
 pub mod proxy;
 
 #[derive(Debug, Clone, Default)]
@@ -44,6 +79,16 @@ pub struct HttpClient {
     tls_config: Option<ClientConfig>,
     #[cfg(any(feature = "rustls", feature = "boring"))]
     proxy_tls_config: Option<ClientConfig>,
+    progress_observer: Option<SharedProgressObserver>,
+    expect_continue_timeout: Option<Duration>,
+    expect_continue_threshold: Option<u64>,
+    preserve_header_case: bool,
+    http2_prior_knowledge: bool,
+    http2_upgrade: bool,
+    #[cfg(feature = "http3")]
+    alt_svc: Option<AltSvcCache>,
+    #[cfg(feature = "http3")]
+    http3_connector: Http3Connector,
 }
 
 impl HttpClient {
@@ -93,26 +138,272 @@ impl HttpClient {
         self.proxy_tls_config = cfg;
         self
     }
-}
 
-impl<State, Body> Service<State, Request<Body>> for HttpClient
-where
-    State: Clone + Send + Sync + 'static,
-    Body: http_body::Body<Data: Send + 'static, Error: Into<BoxError>> + Unpin + Send + 'static,
-{
-    type Response = Response;
-    type Error = OpaqueError;
+    /// Set the [`ProgressObserver`] of this [`HttpClient`],
+    /// which is notified of the [`ProgressEvent`]s of the requests it serves.
+    pub fn set_progress_observer(&mut self, observer: impl ProgressObserver) -> &mut Self {
+        self.progress_observer = Some(SharedProgressObserver::new(observer));
+        self
+    }
 
-    async fn serve(
+    /// Replace this [`HttpClient`] with the [`ProgressObserver`] set,
+    /// which is notified of the [`ProgressEvent`]s of the requests it serves.
+    pub fn with_progress_observer(mut self, observer: impl ProgressObserver) -> Self {
+        self.progress_observer = Some(SharedProgressObserver::new(observer));
+        self
+    }
+
+    /// Set the time to wait for a `100 Continue` response
+    /// prior to sending the body of an `Expect: 100-continue` (http/1.1) request anyway.
+    ///
+    /// The body is sent as soon as the `100 Continue` is received,
+    /// and never sent in case the server responds with a final response first.
+    ///
+    /// Default is 1 second.
+    pub fn set_expect_continue_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.expect_continue_timeout = Some(timeout);
+        self
+    }
+
+    /// Replace this [`HttpClient`] with the time to wait for a `100 Continue` response
+    /// prior to sending the body of an `Expect: 100-continue` (http/1.1) request anyway.
+    ///
+    /// See [`Self::set_expect_continue_timeout`] for more information.
+    pub fn with_expect_continue_timeout(mut self, timeout: Duration) -> Self {
+        self.expect_continue_timeout = Some(timeout);
+        self
+    }
+
+    /// Add an `Expect: 100-continue` header to http/1.1 requests of which the body
+    /// is at least the given amount of bytes (or of unknown size), such that the server
+    /// can reject them prior to their body being sent.
+    ///
+    /// Disabled by default, in which case only requests which already have
+    /// such header wait for a `100 Continue`.
+    pub fn set_expect_continue_threshold(&mut self, threshold: u64) -> &mut Self {
+        self.expect_continue_threshold = Some(threshold);
+        self
+    }
+
+    /// Replace this [`HttpClient`] with the minimum body size of http/1.1 requests
+    /// to which an `Expect: 100-continue` header is added.
+    ///
+    /// See [`Self::set_expect_continue_threshold`] for more information.
+    pub fn with_expect_continue_threshold(mut self, threshold: u64) -> Self {
+        self.expect_continue_threshold = Some(threshold);
+        self
+    }
+
+    /// Set whether http/1 connections preserve the original casing of header names,
+    /// e.g. to forward the requests received by an [`HttpServer`] (configured to
+    /// preserve header cases as well) with their header names as-is.
+    ///
+    /// See [`HttpConnector::set_preserve_header_case`] for more information.
+    ///
+    /// Default is `false`.
+    ///
+    /// [`HttpServer`]: crate::server::HttpServer
+    pub fn set_preserve_header_case(&mut self, enabled: bool) -> &mut Self {
+        self.preserve_header_case = enabled;
+        self
+    }
+
+    /// Replace this [`HttpClient`] with whether http/1 connections
+    /// preserve the original casing of header names.
+    ///
+    /// See [`Self::set_preserve_header_case`] for more information.
+    pub fn with_preserve_header_case(mut self, enabled: bool) -> Self {
+        self.preserve_header_case = enabled;
+        self
+    }
+
+    /// Set whether h2 is spoken over all cleartext (non-tls) connections,
+    /// without any negotiation ("h2c with prior knowledge").
+    ///
+    /// See [`HttpConnector::set_http2_prior_knowledge`] for more information.
+    ///
+    /// Default is `false`, in which case it can still be enabled for individual
+    /// requests by inserting the [`Http2PriorKnowledge`] marker in their [`Context`].
+    pub fn set_http2_prior_knowledge(&mut self, enabled: bool) -> &mut Self {
+        self.http2_prior_knowledge = enabled;
+        self
+    }
+
+    /// Replace this [`HttpClient`] with whether h2 is spoken
+    /// over all cleartext (non-tls) connections, without any negotiation.
+    ///
+    /// See [`Self::set_http2_prior_knowledge`] for more information.
+    pub fn with_http2_prior_knowledge(mut self, enabled: bool) -> Self {
+        self.http2_prior_knowledge = enabled;
+        self
+    }
+
+    /// Set whether an upgrade to h2 is offered over all cleartext (non-tls) connections,
+    /// using the HTTP/1.1 `Upgrade: h2c` mechanism.
+    ///
+    /// See [`HttpConnector::set_http2_upgrade`] for more information.
+    ///
+    /// Default is `false`, in which case it can still be enabled for individual
+    /// requests by inserting the [`Http2Upgrade`] marker in their [`Context`].
+    pub fn set_http2_upgrade(&mut self, enabled: bool) -> &mut Self {
+        self.http2_upgrade = enabled;
+        self
+    }
+
+    /// Replace this [`HttpClient`] with whether an upgrade to h2 is offered
+    /// over all cleartext (non-tls) connections, using the HTTP/1.1 `Upgrade: h2c` mechanism.
+    ///
+    /// See [`Self::set_http2_upgrade`] for more information.
+    pub fn with_http2_upgrade(mut self, enabled: bool) -> Self {
+        self.http2_upgrade = enabled;
+        self
+    }
+
+    #[cfg(feature = "http3")]
+    /// Set whether HTTP/3 alternative services, as advertised by https origins
+    /// using the `Alt-Svc` header, are used for subsequent requests to these origins.
+    ///
+    /// The alternatives are shared between the clones of this [`HttpClient`].
+    /// In case such an alternative cannot be connected to, it is forgotten
+    /// and the request is sent over tcp instead. Requests with version
+    /// [`Version::HTTP_3`] are always sent over HTTP/3, regardless of this setting.
+    ///
+    /// Default is `false`.
+    pub fn set_alt_svc(&mut self, enabled: bool) -> &mut Self {
+        self.alt_svc = enabled.then(AltSvcCache::default);
+        self
+    }
+
+    #[cfg(feature = "http3")]
+    /// Replace this [`HttpClient`] with whether HTTP/3 alternative services,
+    /// as advertised by https origins, are used.
+    ///
+    /// See [`Self::set_alt_svc`] for more information.
+    pub fn with_alt_svc(mut self, enabled: bool) -> Self {
+        self.set_alt_svc(enabled);
+        self
+    }
+
+    #[cfg(feature = "http3")]
+    /// Returns the origin of the request and the [`Authority`] to connect to using HTTP/3,
+    /// in case it is requested explicitly or an alternative service is known for its origin.
+    fn http3_authority<State, Body>(
+        &self,
+        ctx: &mut Context<State>,
+        req: &Request<Body>,
+    ) -> Result<Option<(Authority, Authority)>, OpaqueError> {
+        let explicit = req.version() == Version::HTTP_3;
+        if !explicit && self.alt_svc.is_none() {
+            return Ok(None);
+        }
+        if ctx.contains::<ProxyAddress>() {
+            if explicit {
+                return Err(OpaqueError::from_display(
+                    "HttpClient: HTTP/3 cannot be used via a proxy",
+                ));
+            }
+            return Ok(None);
+        }
+
+        let request_ctx = ctx
+            .get_or_try_insert_with_ctx::<RequestContext, _>(|ctx| (ctx, req).try_into())
+            .context("HttpClient: compute request context")?;
+        if !request_ctx.protocol.is_secure() {
+            if explicit {
+                return Err(OpaqueError::from_display(
+                    "HttpClient: HTTP/3 requires a secure scheme (https)",
+                ));
+            }
+            return Ok(None);
+        }
+        let origin = request_ctx.authority.clone();
+        if explicit {
+            return Ok(Some((origin.clone(), origin)));
+        }
+        Ok(self
+            .alt_svc
+            .as_ref()
+            .and_then(|cache| cache.get(&origin))
+            .map(|authority| (origin, authority)))
+    }
+
+    #[cfg(feature = "http3")]
+    /// Establish an HTTP/3 connection to the given [`Authority`],
+    /// returning `None` in case it concerns an alternative service of the origin
+    /// which cannot be connected to, such that tcp is used instead.
+    async fn connect_http3<State, Body>(
+        &self,
+        ctx: &mut Context<State>,
+        origin: Authority,
+        authority: Authority,
+        explicit: bool,
+    ) -> Result<Option<(HttpClientService<Body>, std::net::SocketAddr)>, OpaqueError>
+    where
+        State: Clone + Send + Sync + 'static,
+    {
+        let tls_connector_data = match &self.tls_config {
+            Some(tls_config) => tls_config
+                .clone()
+                .try_into()
+                .context("HttpClient: create h3 tls connector data from tls config")?,
+            None => rama_tls::rustls::client::TlsConnectorData::new()
+                .context("HttpClient: create h3 tls connector data")?,
+        };
+        // clones of the connector share their QUIC endpoints
+        let connector = self
+            .http3_connector
+            .clone()
+            .with_tls_connector_data(tls_connector_data);
+
+        match connector.connect(ctx, authority).await {
+            Ok(established) => Ok(Some(established)),
+            Err(err) if explicit => Err(err),
+            Err(err) => {
+                tracing::debug!(%origin, %err, "HttpClient: h3 alternative failed, fall back to tcp");
+                if let Some(cache) = &self.alt_svc {
+                    cache.remove(&origin);
+                }
+                Ok(None)
+            }
+        }
+    }
+
+    /// Establish a connection for the request, using HTTP/3
+    /// in case it is requested or advertised, and tcp otherwise.
+    async fn connect<State, Body>(
         &self,
         ctx: Context<State>,
         req: Request<Body>,
-    ) -> Result<Self::Response, Self::Error> {
-        let uri = req.uri().clone();
-
-        // record original req version,
-        // so we can put the response back
-        let original_req_version = req.version();
+    ) -> Result<
+        EstablishedClientConnection<HttpClientService<Body>, State, Request<Body>>,
+        OpaqueError,
+    >
+    where
+        State: Clone + Send + Sync + 'static,
+        Body: http_body::Body<Data: Send + 'static, Error: Into<BoxError>> + Unpin + Send + 'static,
+    {
+        #[cfg(feature = "http3")]
+        let mut ctx = ctx;
+        #[cfg(feature = "http3")]
+        {
+            let http3_authority = self.http3_authority(&mut ctx, &req)?;
+            let explicit = req.version() == Version::HTTP_3;
+            if let Some((origin, authority)) = http3_authority {
+                if let Some((conn, addr)) = self
+                    .connect_http3(&mut ctx, origin, authority, explicit)
+                    .await?
+                {
+                    let mut req = req;
+                    *req.version_mut() = Version::HTTP_3;
+                    return Ok(EstablishedClientConnection {
+                        ctx,
+                        req,
+                        conn,
+                        addr,
+                    });
+                }
+            }
+        }
 
         let tcp_connector = TcpConnector::new();
 
@@ -155,26 +446,109 @@ where
             HttpConnector::new(
                 TlsConnector::auto(transport_connector).with_connector_data(tls_connector_data),
             )
+            .with_preserve_header_case(self.preserve_header_case)
+            .with_http2_prior_knowledge(self.http2_prior_knowledge)
+            .with_http2_upgrade(self.http2_upgrade)
         };
         #[cfg(not(any(feature = "rustls", feature = "boring")))]
-        let connector = HttpConnector::new(HttpProxyConnector::optional(tcp_connector));
+        let connector = HttpConnector::new(HttpProxyConnector::optional(tcp_connector))
+            .with_preserve_header_case(self.preserve_header_case)
+            .with_http2_prior_knowledge(self.http2_prior_knowledge)
+            .with_http2_upgrade(self.http2_upgrade);
+
+        connector
+            .connect(ctx, req)
+            .await
+            .map_err(OpaqueError::from_boxed)
+    }
+}
+
+impl<State, Body> Service<State, Request<Body>> for HttpClient
+where
+    State: Clone + Send + Sync + 'static,
+    Body: http_body::Body<Data: Send + 'static, Error: Into<BoxError>> + Unpin + Send + 'static,
+{
+    type Response = Response;
+    type Error = OpaqueError;
+
+    async fn serve(
+        &self,
+        ctx: Context<State>,
+        req: Request<Body>,
+    ) -> Result<Self::Response, Self::Error> {
+        let uri = req.uri().clone();
+
+        // record original req version,
+        // so we can put the response back
+        let original_req_version = req.version();
+
+        let observer = self.progress_observer.clone();
+        let req = req
+            .map(|body| RequestProgressBody::new(ExpectContinueBody::new(body), observer.clone()));
+
+        #[cfg(feature = "http3")]
+        let alt_svc_origin = self
+            .alt_svc
+            .as_ref()
+            .filter(|_| !ctx.contains::<ProxyAddress>())
+            .and_then(|_| RequestContext::try_from((&ctx, &req)).ok())
+            .filter(|request_ctx| request_ctx.protocol.is_secure())
+            .map(|request_ctx| request_ctx.authority);
 
         // NOTE: stack might change request version based on connector data,
         // such as ALPN (tls), as such it is important to reset it back below,
         // so that the other end can read it... This might however give issues in
         // case switching http versions requires more work than version. If so,
         // your first place will be to check here and/or in the [`HttpConnector`].
-        let EstablishedClientConnection { ctx, req, conn, .. } = connector
+        let EstablishedClientConnection {
+            ctx,
+            mut req,
+            conn,
+            addr,
+        } = self
             .connect(ctx, req)
             .await
-            .map_err(|err| OpaqueError::from_boxed(err).with_context(|| uri.to_string()))?;
+            .map_err(|err| err.with_context(|| uri.to_string()))?;
+
+        if let Some(observer) = &observer {
+            observer.notify(ProgressEvent::ConnectionEstablished { addr });
+            #[cfg(any(feature = "rustls", feature = "boring"))]
+            if ctx.contains::<rama_net::tls::client::NegotiatedTlsParameters>() {
+                observer.notify(ProgressEvent::TlsHandshakeDone);
+            }
+        }
+
+        if expects_continue(&mut req, self.expect_continue_threshold) {
+            let timeout = self
+                .expect_continue_timeout
+                .unwrap_or(DEFAULT_EXPECT_CONTINUE_TIMEOUT);
+            trace!(uri = %uri, ?timeout, "wait for 100 continue prior to sending the body");
+            let gate = req.body_mut().inner_mut().wait_for_continue(timeout);
+            req.extensions_mut().insert(gate);
+        }
 
         trace!(uri = %uri, "send http req to connector stack");
+        req.body_mut().start();
         let mut resp = conn.serve(ctx, req).await.map_err(|err| {
             OpaqueError::from_boxed(err)
                 .with_context(|| format!("http request failure for uri: {uri}"))
         })?;
         trace!(uri = %uri, "response received from connector stack");
+        #[cfg(feature = "http3")]
+        if let (Some(cache), Some(origin)) = (&self.alt_svc, alt_svc_origin) {
+            cache.update(&origin, resp.headers());
+        }
+        // this client does not pool connections, so each request uses a new one
+        resp.extensions_mut()
+            .insert(ConnectionReuse::new_connection(1));
+
+        if let Some(observer) = observer {
+            observer.notify(ProgressEvent::ResponseReceived {
+                status: resp.status(),
+            });
+            resp = resp
+                .map(|body| rama_http_types::Body::new(ResponseProgressBody::new(body, observer)));
+        }
 
         trace!(
             "incoming response version {:?}, normalizing to {:?}",
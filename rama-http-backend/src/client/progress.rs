@@ -0,0 +1,305 @@
+use rama_http_types::{
+    dep::http_body::{self, Frame, SizeHint},
+    Body, StatusCode,
+};
+use std::{
+    fmt,
+    net::SocketAddr,
+    pin::Pin,
+    sync::Arc,
+    task::{ready, Context as TaskContext, Poll},
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+/// A progress event of a request served by the [`HttpClient`],
+/// reported to its [`ProgressObserver`] (if any).
+///
+/// Events are reported in the order they are defined in,
+/// with all but [`ProgressEvent::ConnectionEstablished`] and
+/// [`ProgressEvent::ResponseReceived`] being optional.
+///
+/// The connection events are reported once the connection
+/// is ready to be used, including its tls and http handshakes.
+///
+/// [`HttpClient`]: super::HttpClient
+pub enum ProgressEvent {
+    /// A (transport) connection was established with the given address,
+    /// which is the address of the proxy in case one is used.
+    ConnectionEstablished {
+        /// The address connected to.
+        addr: SocketAddr,
+    },
+    /// The tls handshake with the server was completed.
+    ///
+    /// Not reported for plain text (http) requests.
+    TlsHandshakeDone,
+    /// The request, including its body, was sent to the server.
+    RequestSent,
+    /// The head of the response was received (the "first byte").
+    ResponseReceived {
+        /// The status of the response.
+        status: StatusCode,
+    },
+    /// A chunk of the response body was received.
+    BodyProgress {
+        /// The amount of body bytes received so far.
+        received: u64,
+        /// The total size of the body, if known upfront.
+        total: Option<u64>,
+    },
+    /// The response body was received completely.
+    BodyDone {
+        /// The amount of body bytes received.
+        received: u64,
+    },
+}
+
+/// An observer of the [`ProgressEvent`]s of the requests served by the [`HttpClient`],
+/// e.g. to display progress in a (T)UI without having to parse tracing logs.
+///
+/// Implemented for any `Fn(&ProgressEvent)` closure.
+///
+/// [`HttpClient`]: super::HttpClient
+pub trait ProgressObserver: Send + Sync + 'static {
+    /// Called for each [`ProgressEvent`] of a request.
+    ///
+    /// Called from within the request (body) futures, and should thus not block.
+    fn on_progress(&self, event: &ProgressEvent);
+}
+
+impl<F> ProgressObserver for F
+where
+    F: Fn(&ProgressEvent) + Send + Sync + 'static,
+{
+    fn on_progress(&self, event: &ProgressEvent) {
+        (self)(event)
+    }
+}
+
+#[derive(Clone)]
+pub(super) struct SharedProgressObserver(Arc<dyn ProgressObserver>);
+
+impl SharedProgressObserver {
+    pub(super) fn new(observer: impl ProgressObserver) -> Self {
+        Self(Arc::new(observer))
+    }
+
+    pub(super) fn notify(&self, event: ProgressEvent) {
+        self.0.on_progress(&event)
+    }
+}
+
+impl fmt::Debug for SharedProgressObserver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("SharedProgressObserver").finish()
+    }
+}
+
+/// Request body which reports [`ProgressEvent::RequestSent`] once it is fully sent.
+pub(super) struct RequestProgressBody<B> {
+    inner: B,
+    observer: Option<SharedProgressObserver>,
+}
+
+impl<B: http_body::Body> RequestProgressBody<B> {
+    pub(super) fn new(inner: B, observer: Option<SharedProgressObserver>) -> Self {
+        Self { inner, observer }
+    }
+
+    pub(super) fn inner_mut(&mut self) -> &mut B {
+        &mut self.inner
+    }
+
+    /// Mark the request as about to be sent, reporting empty bodies
+    /// as sent already, given these might never be polled.
+    pub(super) fn start(&mut self) {
+        if self.inner.is_end_stream() {
+            self.notify_sent();
+        }
+    }
+
+    fn notify_sent(&mut self) {
+        if let Some(observer) = self.observer.take() {
+            observer.notify(ProgressEvent::RequestSent);
+        }
+    }
+}
+
+impl<B> http_body::Body for RequestProgressBody<B>
+where
+    B: http_body::Body + Unpin,
+{
+    type Data = B::Data;
+    type Error = B::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut TaskContext<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let frame = ready!(Pin::new(&mut self.inner).poll_frame(cx));
+        if frame.is_none() || self.inner.is_end_stream() {
+            self.notify_sent();
+        }
+        Poll::Ready(frame)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+/// Response body which reports the [`ProgressEvent::BodyProgress`]
+/// and [`ProgressEvent::BodyDone`] events.
+pub(super) struct ResponseProgressBody {
+    inner: Body,
+    observer: Option<SharedProgressObserver>,
+    received: u64,
+    total: Option<u64>,
+}
+
+impl ResponseProgressBody {
+    pub(super) fn new(inner: Body, observer: SharedProgressObserver) -> Self {
+        let total = http_body::Body::size_hint(&inner).exact();
+        Self {
+            inner,
+            observer: Some(observer),
+            received: 0,
+            total,
+        }
+    }
+
+    fn notify_done(&mut self) {
+        if let Some(observer) = self.observer.take() {
+            observer.notify(ProgressEvent::BodyDone {
+                received: self.received,
+            });
+        }
+    }
+}
+
+impl http_body::Body for ResponseProgressBody {
+    type Data = <Body as http_body::Body>::Data;
+    type Error = <Body as http_body::Body>::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut TaskContext<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let frame = ready!(Pin::new(&mut self.inner).poll_frame(cx));
+        match &frame {
+            Some(Ok(frame)) => {
+                if let Some(data) = frame.data_ref() {
+                    self.received += data.len() as u64;
+                    if let Some(observer) = &self.observer {
+                        observer.notify(ProgressEvent::BodyProgress {
+                            received: self.received,
+                            total: self.total,
+                        });
+                    }
+                }
+                if self.inner.is_end_stream() {
+                    self.notify_done();
+                }
+            }
+            Some(Err(_)) => {
+                // no longer report progress for a failed body
+                self.observer = None;
+            }
+            None => self.notify_done(),
+        }
+        Poll::Ready(frame)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rama_core::error::BoxError;
+    use rama_http_types::BodyExtractExt;
+    use std::sync::Mutex;
+
+    fn recorder() -> (SharedProgressObserver, Arc<Mutex<Vec<ProgressEvent>>>) {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let observer = SharedProgressObserver::new({
+            let events = events.clone();
+            move |event: &ProgressEvent| events.lock().unwrap().push(event.clone())
+        });
+        (observer, events)
+    }
+
+    #[tokio::test]
+    async fn test_request_progress_body() {
+        let (observer, events) = recorder();
+        let mut body = RequestProgressBody::new(Body::empty(), Some(observer));
+        assert!(events.lock().unwrap().is_empty());
+        body.start();
+        assert_eq!(*events.lock().unwrap(), [ProgressEvent::RequestSent]);
+
+        let (observer, events) = recorder();
+        let mut body = RequestProgressBody::new(
+            Body::from_stream(futures_lite::stream::iter([
+                Ok::<_, BoxError>("a"),
+                Ok("b"),
+            ])),
+            Some(observer),
+        );
+        body.start();
+        assert!(events.lock().unwrap().is_empty());
+        Body::new(body).try_into_string().await.unwrap();
+        assert_eq!(*events.lock().unwrap(), [ProgressEvent::RequestSent]);
+    }
+
+    #[tokio::test]
+    async fn test_response_progress_body() {
+        let (observer, events) = recorder();
+        let body = ResponseProgressBody::new(
+            Body::from_stream(futures_lite::stream::iter([
+                Ok::<_, BoxError>("foo"),
+                Ok("ba"),
+            ])),
+            observer,
+        );
+        assert_eq!(Body::new(body).try_into_string().await.unwrap(), "fooba");
+        assert_eq!(
+            *events.lock().unwrap(),
+            [
+                ProgressEvent::BodyProgress {
+                    received: 3,
+                    total: None
+                },
+                ProgressEvent::BodyProgress {
+                    received: 5,
+                    total: None
+                },
+                ProgressEvent::BodyDone { received: 5 },
+            ]
+        );
+
+        let (observer, events) = recorder();
+        let body = ResponseProgressBody::new(Body::from("hello"), observer);
+        assert_eq!(Body::new(body).try_into_string().await.unwrap(), "hello");
+        assert_eq!(
+            *events.lock().unwrap(),
+            [
+                ProgressEvent::BodyProgress {
+                    received: 5,
+                    total: Some(5)
+                },
+                ProgressEvent::BodyDone { received: 5 },
+            ]
+        );
+    }
+}
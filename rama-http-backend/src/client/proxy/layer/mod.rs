@@ -8,4 +8,6 @@ pub use proxy_auth_header::{SetProxyAuthHttpHeaderLayer, SetProxyAuthHttpHeaderS
 
 mod proxy_connector;
 #[doc(inline)]
-pub use proxy_connector::{HttpProxyConnector, HttpProxyConnectorLayer, HttpProxyError};
+pub use proxy_connector::{
+    HttpProxyConnectResponse, HttpProxyConnector, HttpProxyConnectorLayer, HttpProxyError,
+};
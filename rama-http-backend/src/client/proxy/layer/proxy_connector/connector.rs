@@ -6,12 +6,12 @@ use std::borrow::Cow;
 
 use rama_http_types::{
     headers::{Header, HeaderMapExt},
-    HeaderMap, HeaderName, HeaderValue,
+    HeaderMap, HeaderName, HeaderValue, StatusCode,
 };
 use rama_net::{address::Authority, stream::Stream};
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 
-use super::HttpProxyError;
+use super::{HttpProxyConnectResponse, HttpProxyError};
 
 #[derive(Debug, Clone)]
 /// Connector for HTTP proxies.
@@ -108,33 +108,118 @@ impl InnerHttpProxyConnector {
             pos += n;
 
             let recvd = &buf[..pos];
-            if recvd.starts_with(b"HTTP/1.1 200") || recvd.starts_with(b"HTTP/1.0 200") {
-                if recvd.ends_with(b"\r\n\r\n") {
-                    return Ok(stream);
-                }
-                if pos == buf.len() {
-                    return Err(std::io::Error::new(
-                        std::io::ErrorKind::InvalidData,
-                        "http conn handshake response too large",
-                    )
-                    .into());
-                }
-            // else read more
-            } else if recvd.starts_with(b"HTTP/1.1 407") {
-                return Err(HttpProxyError::AuthRequired);
-            } else if recvd.starts_with(b"HTTP/1.1 503") {
-                return Err(HttpProxyError::Unavailable);
-            } else {
-                let input = String::from_utf8_lossy(recvd);
-                return Err(HttpProxyError::Other(format!(
-                    "invalid http conn handshake start: [{}]",
-                    if let Some((line, _)) = input.split_once("\r\n") {
-                        Cow::Borrowed(line)
-                    } else {
-                        input
+            if !(recvd.starts_with(b"HTTP/1.1 ") || recvd.starts_with(b"HTTP/1.0 "))
+                && recvd.len() >= 9
+            {
+                return Err(invalid_handshake(recvd));
+            }
+            if recvd.ends_with(b"\r\n\r\n") {
+                let response =
+                    parse_response_head(recvd).ok_or_else(|| invalid_handshake(recvd))?;
+                return match response.status() {
+                    status if status.is_success() => Ok(stream),
+                    StatusCode::PROXY_AUTHENTICATION_REQUIRED => {
+                        Err(HttpProxyError::AuthRequired(response))
                     }
-                )));
+                    StatusCode::SERVICE_UNAVAILABLE => Err(HttpProxyError::Unavailable(response)),
+                    _ => Err(HttpProxyError::Refused(response)),
+                };
             }
+            if pos == buf.len() {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    "http conn handshake response too large",
+                )
+                .into());
+            }
+            // else read more
         }
     }
 }
+
+/// Parse the status code and headers of the response to the CONNECT request.
+fn parse_response_head(head: &[u8]) -> Option<HttpProxyConnectResponse> {
+    let head = std::str::from_utf8(head).ok()?;
+    let mut lines = head.split("\r\n").filter(|line| !line.is_empty());
+
+    let status_line = lines.next()?;
+    let status = status_line.split(' ').nth(1)?;
+    let status = StatusCode::from_bytes(status.as_bytes()).ok()?;
+
+    let mut headers = HeaderMap::new();
+    for line in lines {
+        let (name, value) = line.split_once(':')?;
+        headers.append(
+            HeaderName::from_bytes(name.trim().as_bytes()).ok()?,
+            HeaderValue::from_str(value.trim()).ok()?,
+        );
+    }
+
+    Some(HttpProxyConnectResponse::new(status, headers))
+}
+
+fn invalid_handshake(recvd: &[u8]) -> HttpProxyError {
+    let input = String::from_utf8_lossy(recvd);
+    HttpProxyError::Other(format!(
+        "invalid http conn handshake start: [{}]",
+        if let Some((line, _)) = input.split_once("\r\n") {
+            Cow::Borrowed(line)
+        } else {
+            input
+        }
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn handshake(response: &'static [u8]) -> Result<(), HttpProxyError> {
+        let (client, mut server) = tokio::io::duplex(1024);
+        tokio::spawn(async move {
+            let mut buf = [0; 1024];
+            let _ = server.read(&mut buf).await.unwrap();
+            server.write_all(response).await.unwrap();
+        });
+        InnerHttpProxyConnector::new("example.com:443".parse().unwrap())
+            .handshake(client)
+            .await
+            .map(|_| ())
+    }
+
+    #[tokio::test]
+    async fn test_handshake_response() {
+        handshake(b"HTTP/1.1 200 Connection Established\r\n\r\n")
+            .await
+            .unwrap();
+        handshake(b"HTTP/1.0 200 OK\r\nVia: 1.1 proxy\r\n\r\n")
+            .await
+            .unwrap();
+
+        let err = handshake(
+            b"HTTP/1.1 407 Proxy Authentication Required\r\nProxy-Authenticate: Basic\r\n\r\n",
+        )
+        .await
+        .unwrap_err();
+        assert!(matches!(err, HttpProxyError::AuthRequired(_)));
+        assert_eq!(
+            err.response().unwrap().headers()["proxy-authenticate"],
+            "Basic"
+        );
+
+        let err = handshake(
+            b"HTTP/1.1 502 Bad Gateway\r\nProxy-Status: proxy; error=dns_timeout\r\n\r\n",
+        )
+        .await
+        .unwrap_err();
+        let response = err.response().unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_GATEWAY);
+        assert_eq!(
+            response.proxy_status().unwrap().entries()[0].proxy(),
+            "proxy"
+        );
+
+        let err = handshake(b"SSH-2.0-OpenSSH\r\n\r\n").await.unwrap_err();
+        assert!(matches!(err, HttpProxyError::Other(_)));
+    }
+}
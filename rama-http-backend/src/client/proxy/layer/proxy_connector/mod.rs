@@ -4,7 +4,7 @@ use connector::InnerHttpProxyConnector;
 
 mod proxy_error;
 #[doc(inline)]
-pub use proxy_error::HttpProxyError;
+pub use proxy_error::{HttpProxyConnectResponse, HttpProxyError};
 
 mod layer;
 #[doc(inline)]
@@ -1,5 +1,41 @@
+use rama_http_types::{
+    headers::{HeaderMapExt, ProxyStatus},
+    HeaderMap, StatusCode,
+};
 use std::fmt;
 
+#[derive(Debug, Clone)]
+/// The (non-successful) response head returned by a http proxy
+/// in response to a CONNECT request.
+///
+/// Can be used to inspect the details of why a proxy refused
+/// to establish a connection, e.g. by checking its [`ProxyStatus`] header.
+pub struct HttpProxyConnectResponse {
+    status: StatusCode,
+    headers: HeaderMap,
+}
+
+impl HttpProxyConnectResponse {
+    pub(crate) fn new(status: StatusCode, headers: HeaderMap) -> Self {
+        Self { status, headers }
+    }
+
+    /// The status code returned by the proxy.
+    pub fn status(&self) -> StatusCode {
+        self.status
+    }
+
+    /// The headers returned by the proxy.
+    pub fn headers(&self) -> &HeaderMap {
+        &self.headers
+    }
+
+    /// The [`ProxyStatus`] header returned by the proxy, if any (valid) one.
+    pub fn proxy_status(&self) -> Option<ProxyStatus> {
+        self.headers.typed_get()
+    }
+}
+
 #[derive(Debug)]
 /// error that can be returned in case a http proxy
 /// did not manage to establish a connection
@@ -7,11 +43,15 @@ pub enum HttpProxyError {
     /// Proxy Authentication Required
     ///
     /// (Proxy returned HTTP 407)
-    AuthRequired,
+    AuthRequired(HttpProxyConnectResponse),
     /// Proxy is Unavailable
     ///
     /// (Proxy returned HTTP 503)
-    Unavailable,
+    Unavailable(HttpProxyConnectResponse),
+    /// Proxy refused to establish the connection
+    ///
+    /// (Proxy returned a non-successful HTTP status other than 407 or 503)
+    Refused(HttpProxyConnectResponse),
     /// I/O error happened as part of HTTP Proxy Connection Establishment
     ///
     /// (e.g. some kind of TCP error)
@@ -22,15 +62,35 @@ pub enum HttpProxyError {
     Other(String),
 }
 
+impl HttpProxyError {
+    /// The response returned by the proxy, in case
+    /// it refused to establish the connection.
+    pub fn response(&self) -> Option<&HttpProxyConnectResponse> {
+        match self {
+            HttpProxyError::AuthRequired(response)
+            | HttpProxyError::Unavailable(response)
+            | HttpProxyError::Refused(response) => Some(response),
+            HttpProxyError::Transport(_) | HttpProxyError::Other(_) => None,
+        }
+    }
+}
+
 impl fmt::Display for HttpProxyError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            HttpProxyError::AuthRequired => {
+            HttpProxyError::AuthRequired(_) => {
                 write!(f, "http proxy error: proxy auth required (http 407)")
             }
-            HttpProxyError::Unavailable => {
+            HttpProxyError::Unavailable(_) => {
                 write!(f, "http proxy error: proxy unavailable (http 503)")
             }
+            HttpProxyError::Refused(response) => {
+                write!(
+                    f,
+                    "http proxy error: proxy refused connection (http {})",
+                    response.status.as_u16()
+                )
+            }
             HttpProxyError::Transport(error) => {
                 write!(f, "http proxy error: transport error: I/O [{}]", error)
             }
@@ -50,8 +110,9 @@ impl From<std::io::Error> for HttpProxyError {
 impl std::error::Error for HttpProxyError {
     fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
         match self {
-            HttpProxyError::AuthRequired => None,
-            HttpProxyError::Unavailable => None,
+            HttpProxyError::AuthRequired(_) => None,
+            HttpProxyError::Unavailable(_) => None,
+            HttpProxyError::Refused(_) => None,
             HttpProxyError::Transport(err) => Some(err),
             HttpProxyError::Other(_) => None,
         }
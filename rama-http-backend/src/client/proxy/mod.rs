@@ -1,3 +1,7 @@
 //! Client Http Proxy Connector Support.
 
 pub mod layer;
+
+mod status;
+#[doc(inline)]
+pub use status::proxy_status_for_error;
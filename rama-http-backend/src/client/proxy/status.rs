@@ -0,0 +1,139 @@
+//! Classify (proxy) connection errors as a [`ProxyStatus`] header,
+//! as defined in [RFC 9209](https://www.rfc-editor.org/rfc/rfc9209).
+
+use super::layer::HttpProxyError;
+use rama_http_types::headers::{ProxyErrorType, ProxyStatus, ProxyStatusEntry};
+use std::{error::Error, io};
+
+/// Create the [`ProxyStatus`] to be returned by a proxy
+/// which failed to forward a request because of the given error.
+///
+/// The error (chain) is classified into a [`ProxyErrorType`] which is set on the given `entry`,
+/// unless the entry already has an error type defined.
+/// In case the error was caused by an upstream http proxy refusing
+/// the connection, the status code it returned is set as the received status,
+/// and the [`ProxyStatus`] entries it returned (if any) precede the given entry.
+///
+/// # Example
+///
+/// ```
+/// use rama_http_backend::client::proxy::proxy_status_for_error;
+/// use rama_http_types::headers::{ProxyErrorType, ProxyStatusEntry};
+///
+/// let error = std::io::Error::from(std::io::ErrorKind::ConnectionRefused);
+/// let status = proxy_status_for_error(ProxyStatusEntry::new("rama"), &error);
+/// assert_eq!(
+///     status.entries()[0].error(),
+///     Some(&ProxyErrorType::ConnectionRefused),
+/// );
+/// ```
+pub fn proxy_status_for_error(
+    mut entry: ProxyStatusEntry,
+    error: &(dyn Error + 'static),
+) -> ProxyStatus {
+    let mut upstream = None;
+    let mut error_type = ProxyErrorType::DestinationUnavailable;
+
+    let mut source = Some(error);
+    while let Some(err) = source {
+        if let Some(err) = err.downcast_ref::<HttpProxyError>() {
+            match err {
+                HttpProxyError::Transport(err) => {
+                    if let Some(kind) = io_error_type(err) {
+                        error_type = kind;
+                    }
+                }
+                HttpProxyError::Other(_) => error_type = ProxyErrorType::HttpProtocolError,
+                HttpProxyError::AuthRequired(response)
+                | HttpProxyError::Unavailable(response)
+                | HttpProxyError::Refused(response) => {
+                    if entry.received_status().is_none() {
+                        entry.set_received_status(response.status().as_u16());
+                    }
+                    upstream = response.proxy_status();
+                }
+            }
+            break;
+        }
+        if let Some(kind) = err.downcast_ref::<io::Error>().and_then(io_error_type) {
+            error_type = kind;
+            break;
+        }
+        source = err.source();
+    }
+
+    if entry.error().is_none() {
+        entry.set_error(error_type);
+    }
+
+    match upstream {
+        Some(mut status) => {
+            status.push(entry);
+            status
+        }
+        None => ProxyStatus::new(entry),
+    }
+}
+
+fn io_error_type(err: &io::Error) -> Option<ProxyErrorType> {
+    match err.kind() {
+        io::ErrorKind::ConnectionRefused => Some(ProxyErrorType::ConnectionRefused),
+        io::ErrorKind::TimedOut => Some(ProxyErrorType::ConnectionTimeout),
+        io::ErrorKind::ConnectionReset
+        | io::ErrorKind::ConnectionAborted
+        | io::ErrorKind::BrokenPipe
+        | io::ErrorKind::UnexpectedEof => Some(ProxyErrorType::ConnectionTerminated),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rama_core::error::{ErrorExt, OpaqueError};
+    use rama_http_types::{header::PROXY_STATUS, headers::HeaderMapExt, HeaderMap, StatusCode};
+
+    fn encode(status: ProxyStatus) -> String {
+        let mut headers = HeaderMap::new();
+        headers.typed_insert(status);
+        headers[&PROXY_STATUS].to_str().unwrap().to_owned()
+    }
+
+    #[test]
+    fn test_proxy_status_for_error() {
+        let error = OpaqueError::from_std(io::Error::from(io::ErrorKind::TimedOut))
+            .context("connect to upstream");
+        let status = proxy_status_for_error(
+            ProxyStatusEntry::new("rama").with_next_hop("example.com"),
+            &error,
+        );
+        assert_eq!(
+            encode(status),
+            r#"rama; error=connection_timeout; next-hop="example.com""#
+        );
+
+        let error = OpaqueError::from_display("oops");
+        let status = proxy_status_for_error(ProxyStatusEntry::new("rama"), &error);
+        assert_eq!(encode(status), "rama; error=destination_unavailable");
+    }
+
+    #[test]
+    fn test_proxy_status_for_refused_connect() {
+        let mut headers = HeaderMap::new();
+        headers.typed_insert(ProxyStatus::new(
+            ProxyStatusEntry::new("upstream").with_error(ProxyErrorType::DnsTimeout),
+        ));
+        let response = crate::client::proxy::layer::HttpProxyConnectResponse::new(
+            StatusCode::GATEWAY_TIMEOUT,
+            headers,
+        );
+        let error = OpaqueError::from_std(HttpProxyError::Refused(response))
+            .context("http proxy handshake");
+
+        let status = proxy_status_for_error(ProxyStatusEntry::new("rama"), &error);
+        assert_eq!(
+            encode(status),
+            "upstream; error=dns_timeout, rama; error=destination_unavailable; received-status=504"
+        );
+    }
+}
@@ -0,0 +1,154 @@
+use rama_http_types::{HeaderName, HeaderValue};
+use std::{
+    fmt,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+/// Name of the debug response header describing the [`ConnectionReuse`] of a request,
+/// e.g. `connection-reused: ?1;attempts=1;handshakes=0`.
+pub const CONNECTION_REUSED: HeaderName = HeaderName::from_static("connection-reused");
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// How the (upstream) connection used to serve a request was obtained.
+///
+/// Inserted as an extension in the responses of the [`HttpClient`] and [`ReverseProxyService`],
+/// e.g. to troubleshoot the latency of requests sent through a proxy.
+///
+/// [`HttpClient`]: super::HttpClient
+/// [`ReverseProxyService`]: crate::reverse_proxy::ReverseProxyService
+pub struct ConnectionReuse {
+    reused: bool,
+    attempts: u32,
+    handshakes: u32,
+}
+
+impl ConnectionReuse {
+    /// A request served over a newly established connection,
+    /// after the given amount of (pooled) connections were tried.
+    pub const fn new_connection(attempts: u32) -> Self {
+        Self {
+            reused: false,
+            attempts,
+            handshakes: 1,
+        }
+    }
+
+    /// A request served over a pooled connection,
+    /// after the given amount of (pooled) connections were tried.
+    pub const fn reused_connection(attempts: u32) -> Self {
+        Self {
+            reused: true,
+            attempts,
+            handshakes: 0,
+        }
+    }
+
+    /// Returns `true` in case a pooled connection was reused.
+    pub const fn reused(&self) -> bool {
+        self.reused
+    }
+
+    /// The amount of connections tried, pooled or new, including the one used.
+    pub const fn attempts(&self) -> u32 {
+        self.attempts
+    }
+
+    /// The amount of connections established (handshakes) to serve the request.
+    pub const fn handshakes(&self) -> u32 {
+        self.handshakes
+    }
+
+    /// The value of the [`CONNECTION_REUSED`] debug header, a structured field
+    /// boolean with the attempts and handshakes as parameters.
+    pub fn header_value(&self) -> HeaderValue {
+        let value = format!(
+            "?{};attempts={};handshakes={}",
+            u8::from(self.reused),
+            self.attempts,
+            self.handshakes
+        );
+        HeaderValue::try_from(value).expect("connection reuse header value is valid")
+    }
+}
+
+#[derive(Default)]
+/// Counters of the [`ConnectionReuse`] of all requests served by a service,
+/// e.g. to export as metrics.
+pub struct ConnectionReuseMetrics {
+    requests: AtomicU64,
+    reused: AtomicU64,
+    attempts: AtomicU64,
+    handshakes: AtomicU64,
+}
+
+impl ConnectionReuseMetrics {
+    /// Create new [`ConnectionReuseMetrics`], with all counters at zero.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record the [`ConnectionReuse`] of a request.
+    pub fn record(&self, reuse: &ConnectionReuse) {
+        self.requests.fetch_add(1, Ordering::Relaxed);
+        if reuse.reused {
+            self.reused.fetch_add(1, Ordering::Relaxed);
+        }
+        self.attempts
+            .fetch_add(reuse.attempts as u64, Ordering::Relaxed);
+        self.handshakes
+            .fetch_add(reuse.handshakes as u64, Ordering::Relaxed);
+    }
+
+    /// The amount of requests recorded.
+    pub fn requests(&self) -> u64 {
+        self.requests.load(Ordering::Relaxed)
+    }
+
+    /// The amount of requests which reused a pooled connection.
+    pub fn reused(&self) -> u64 {
+        self.reused.load(Ordering::Relaxed)
+    }
+
+    /// The total amount of connections tried for all requests.
+    pub fn attempts(&self) -> u64 {
+        self.attempts.load(Ordering::Relaxed)
+    }
+
+    /// The total amount of connections established for all requests.
+    pub fn handshakes(&self) -> u64 {
+        self.handshakes.load(Ordering::Relaxed)
+    }
+}
+
+impl fmt::Debug for ConnectionReuseMetrics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ConnectionReuseMetrics")
+            .field("requests", &self.requests())
+            .field("reused", &self.reused())
+            .field("attempts", &self.attempts())
+            .field("handshakes", &self.handshakes())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_connection_reuse() {
+        let metrics = ConnectionReuseMetrics::new();
+        let new = ConnectionReuse::new_connection(2);
+        let reused = ConnectionReuse::reused_connection(1);
+        assert_eq!(new.header_value(), "?0;attempts=2;handshakes=1");
+        assert_eq!(reused.header_value(), "?1;attempts=1;handshakes=0");
+
+        metrics.record(&new);
+        metrics.record(&reused);
+        metrics.record(&reused);
+        assert_eq!(metrics.requests(), 3);
+        assert_eq!(metrics.reused(), 2);
+        assert_eq!(metrics.attempts(), 4);
+        assert_eq!(metrics.handshakes(), 1);
+    }
+}
@@ -1,4 +1,5 @@
-use hyper::header::{CONNECTION, TRANSFER_ENCODING, UPGRADE};
+use super::ContinueGate;
+use hyper::header::{CONNECTION, COOKIE, TE, TRANSFER_ENCODING, UPGRADE};
 use rama_core::{
     error::{BoxError, ErrorContext, OpaqueError},
     Context, Service,
@@ -7,9 +8,11 @@ use rama_http_types::{
     dep::{http::uri::PathAndQuery, http_body},
     header::{HOST, KEEP_ALIVE, PROXY_CONNECTION},
     headers::HeaderMapExt,
-    Method, Request, Response, Version,
+    EarlyHintsSender, HeaderMap, HeaderValue, InformationalResponse, InformationalResponses,
+    Method, OriginalHeaderOrder, Request, Response, Version,
 };
 use rama_net::{address::ProxyAddress, http::RequestContext};
+use std::sync::Arc;
 use tokio::sync::Mutex;
 
 #[derive(Debug)]
@@ -18,12 +21,37 @@ use tokio::sync::Mutex;
 pub(super) enum SendRequest<Body> {
     Http1(Mutex<hyper::client::conn::http1::SendRequest<Body>>),
     Http2(Mutex<hyper::client::conn::http2::SendRequest<Body>>),
+    #[cfg(feature = "http3")]
+    Http3(super::http3::H3SendRequest),
 }
 
 #[derive(Debug)]
 /// Internal http sender used to send the actual requests.
 pub struct HttpClientService<Body>(pub(super) SendRequest<Body>);
 
+impl<Body> HttpClientService<Body> {
+    /// Wait until the connection is ready to send a new request,
+    /// returning `false` in case the connection is closed instead.
+    pub(crate) async fn ready(&self) -> bool {
+        match &self.0 {
+            SendRequest::Http1(sender) => sender.lock().await.ready().await.is_ok(),
+            SendRequest::Http2(sender) => sender.lock().await.ready().await.is_ok(),
+            #[cfg(feature = "http3")]
+            SendRequest::Http3(sender) => !sender.is_closed(),
+        }
+    }
+
+    /// Returns `true` in case the connection is closed.
+    pub(crate) fn is_closed(&self) -> bool {
+        match &self.0 {
+            SendRequest::Http1(sender) => sender.try_lock().is_ok_and(|sender| sender.is_closed()),
+            SendRequest::Http2(sender) => sender.try_lock().is_ok_and(|sender| sender.is_closed()),
+            #[cfg(feature = "http3")]
+            SendRequest::Http3(sender) => sender.is_closed(),
+        }
+    }
+}
+
 impl<State, Body> Service<State, Request<Body>> for HttpClientService<Body>
 where
     State: Clone + Send + Sync + 'static,
@@ -45,17 +73,73 @@ where
         //
         // TODO: fix this in hyper fork (embedded in rama http core)
         // directly instead of here...
-        let req = sanitize_client_req_header(&mut ctx, req)?;
+        let mut req = sanitize_client_req_header(&mut ctx, req)?;
+        apply_original_header_order(&mut req);
+        let interim = on_informational(&mut req);
 
-        let resp = match &self.0 {
+        let mut resp = match &self.0 {
             SendRequest::Http1(sender) => sender.lock().await.send_request(req).await,
             SendRequest::Http2(sender) => sender.lock().await.send_request(req).await,
+            #[cfg(feature = "http3")]
+            SendRequest::Http3(sender) => {
+                return Ok(sender
+                    .send_request(req)
+                    .await?
+                    .map(rama_http_types::Body::new));
+            }
         }?;
 
+        let interim = std::mem::take(&mut *interim.lock());
+        if !interim.is_empty() {
+            resp.extensions_mut()
+                .insert(InformationalResponses::new(interim));
+        }
+
         Ok(resp.map(rama_http_types::Body::new))
     }
 }
 
+/// Collect the informational (`1xx`) interim responses received for the request,
+/// forwarding `103 Early Hints` to the [`EarlyHintsSender`] of the request, if any,
+/// and opening the [`ContinueGate`] of the request (if any) once a `100 Continue` is received.
+///
+/// Interim responses are currently only surfaced by hyper for http/1 connections.
+fn on_informational<B>(
+    req: &mut Request<B>,
+) -> Arc<parking_lot::Mutex<Vec<InformationalResponse>>> {
+    let interim = Arc::new(parking_lot::Mutex::new(Vec::new()));
+    let sender = req.extensions().get::<EarlyHintsSender>().cloned();
+    let gate = req.extensions().get::<ContinueGate>().cloned();
+    hyper::ext::on_informational(req, {
+        let interim = interim.clone();
+        move |res| {
+            let status = res.status();
+            let headers = res.headers().clone();
+            tracing::trace!(%status, "http client: received informational response");
+            match (status.as_u16(), &sender, &gate) {
+                (100, _, Some(gate)) => gate.open(),
+                (103, Some(sender), _) => sender.send(headers.clone()),
+                _ => (),
+            }
+            interim
+                .lock()
+                .push(InformationalResponse::new(status, headers));
+        }
+    });
+    interim
+}
+
+/// Send the headers of an http/1 request in the order in which they were
+/// originally received, in case the request has an [`OriginalHeaderOrder`].
+fn apply_original_header_order<B>(req: &mut Request<B>) {
+    if req.version() > Version::HTTP_11 {
+        return;
+    }
+    if let Some(order) = req.extensions().get::<OriginalHeaderOrder>().cloned() {
+        order.apply_to(req.headers_mut());
+    }
+}
+
 fn sanitize_client_req_header<S, B>(
     ctx: &mut Context<S>,
     req: Request<B>,
@@ -68,6 +152,11 @@ fn sanitize_client_req_header<S, B>(
     // logic specific to http versions
     Ok(match req.version() {
         Version::HTTP_09 | Version::HTTP_10 | Version::HTTP_11 => {
+            let mut req = req;
+            // h2 allows cookies to be split over multiple headers,
+            // while http/1.1 requires a single cookie header
+            merge_cookie_headers(req.headers_mut());
+
             // remove authority and scheme for non-connect requests
             // cfr: <https://datatracker.ietf.org/doc/html/rfc2616#section-5.1.2>
             if !ctx.contains::<ProxyAddress>() && req.uri().host().is_some() {
@@ -116,7 +205,6 @@ fn sanitize_client_req_header<S, B>(
                         )
                     })?
                     .clone();
-                let mut req = req;
                 req.headers_mut()
                     .typed_insert(rama_http_types::headers::Host::from(authority));
                 req
@@ -124,9 +212,9 @@ fn sanitize_client_req_header<S, B>(
                 req
             }
         }
-        Version::HTTP_2 => {
+        Version::HTTP_2 | Version::HTTP_3 => {
             // set scheme/host if not defined as otherwise pseudo
-            // headers won't be possible to be set in the h2 crate
+            // headers won't be possible to be set in the h2 (or h3) crate
             let mut req = if req.uri().host().is_none() {
                 let request_ctx = ctx.get::<RequestContext>().ok_or_else(|| {
                     OpaqueError::from_display("[h2+] add scheme/host: missing RequestCtx")
@@ -166,6 +254,36 @@ fn sanitize_client_req_header<S, B>(
                 req
             };
 
+            // remove the headers nominated by the connection header,
+            // as these are specific to the (http/1.1) connection
+            if let Some(connection) = req.headers().get(CONNECTION).cloned() {
+                for name in connection
+                    .to_str()
+                    .unwrap_or_default()
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|name| !name.is_empty())
+                {
+                    if let Some(header) = req.headers_mut().remove(name) {
+                        tracing::trace!(
+                            ?header,
+                            "removed connection specific header from h2+ request"
+                        );
+                    }
+                }
+            }
+
+            // only `TE: trailers` is allowed in h2 (and h3) requests,
+            // which is kept as it is required by e.g. gRPC
+            if let Some(te) = req.headers_mut().remove(TE) {
+                if te_contains_trailers(&te) {
+                    req.headers_mut()
+                        .insert(TE, HeaderValue::from_static("trailers"));
+                } else {
+                    tracing::trace!(header = ?te, "removed illegal TE header from h2+ request");
+                }
+            }
+
             // remove illegal headers
             for illegal_h2_header in [
                 &CONNECTION,
@@ -176,19 +294,12 @@ fn sanitize_client_req_header<S, B>(
                 &HOST,
             ] {
                 if let Some(header) = req.headers_mut().remove(illegal_h2_header) {
-                    tracing::trace!(?header, "removed illegal (~http1) header from h2 request");
+                    tracing::trace!(?header, "removed illegal (~http1) header from h2+ request");
                 }
             }
 
             req
         }
-        Version::HTTP_3 => {
-            tracing::debug!(
-                uri = %req.uri(),
-                "h3 request detected, but sanitize_client_req_header does not yet support this",
-            );
-            req
-        }
         _ => {
             tracing::warn!(
                 uri = %req.uri(),
@@ -199,3 +310,131 @@ fn sanitize_client_req_header<S, B>(
         }
     })
 }
+
+/// Returns `true` in case the `TE` header value lists `trailers`,
+/// signaling that the client is willing to accept trailer fields.
+pub(crate) fn te_contains_trailers(te: &HeaderValue) -> bool {
+    te.to_str()
+        .unwrap_or_default()
+        .split(',')
+        .any(|value| value.trim().eq_ignore_ascii_case("trailers"))
+}
+
+/// Merge multiple cookie headers into a single one,
+/// as defined in <https://datatracker.ietf.org/doc/html/rfc9113#section-8.2.3>.
+fn merge_cookie_headers(headers: &mut HeaderMap) {
+    if headers.get_all(COOKIE).iter().nth(1).is_none() {
+        return;
+    }
+    let mut cookie = Vec::new();
+    for value in headers.get_all(COOKIE) {
+        if !cookie.is_empty() {
+            cookie.extend_from_slice(b"; ");
+        }
+        cookie.extend_from_slice(value.as_bytes());
+    }
+    match HeaderValue::from_bytes(&cookie) {
+        Ok(value) => {
+            tracing::trace!("merged multiple cookie headers into a single one");
+            headers.insert(COOKIE, value);
+        }
+        Err(err) => {
+            tracing::debug!(%err, "failed to merge multiple cookie headers, keep them as-is");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rama_http_types::Body;
+
+    #[test]
+    fn test_sanitize_h1_merges_cookie_headers() {
+        let req = Request::builder()
+            .uri("/")
+            .header(HOST, "example.com")
+            .header(COOKIE, "a=1")
+            .header(COOKIE, "b=2")
+            .body(Body::empty())
+            .unwrap();
+        let req = sanitize_client_req_header(&mut Context::default(), req).unwrap();
+        let cookies: Vec<_> = req.headers().get_all(COOKIE).iter().collect();
+        assert_eq!(cookies, ["a=1; b=2"]);
+    }
+
+    #[test]
+    fn test_sanitize_h2_removes_connection_headers() {
+        let req = Request::builder()
+            .uri("https://example.com/")
+            .version(Version::HTTP_2)
+            .header(CONNECTION, "keep-alive, x-hop")
+            .header("x-hop", "1")
+            .header("x-end", "1")
+            .header(TE, "gzip")
+            .header(HOST, "example.com")
+            .body(Body::empty())
+            .unwrap();
+        let req = sanitize_client_req_header(&mut Context::default(), req).unwrap();
+        for name in [CONNECTION.as_str(), "x-hop", TE.as_str(), HOST.as_str()] {
+            assert!(!req.headers().contains_key(name), "{name}");
+        }
+        assert!(req.headers().contains_key("x-end"));
+
+        for te in ["trailers", "gzip, trailers"] {
+            let req = Request::builder()
+                .uri("https://example.com/")
+                .version(Version::HTTP_2)
+                .header(TE, te)
+                .body(Body::empty())
+                .unwrap();
+            let req = sanitize_client_req_header(&mut Context::default(), req).unwrap();
+            assert_eq!(req.headers()[TE], "trailers");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_informational_responses() {
+        use hyper_util::rt::TokioIo;
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let (client_io, mut server_io) = tokio::io::duplex(4096);
+        tokio::spawn(async move {
+            let mut buf = [0; 1024];
+            let _ = server_io.read(&mut buf).await.unwrap();
+            server_io
+                .write_all(
+                    b"HTTP/1.1 103 Early Hints\r\nlink: </style.css>; rel=preload; as=style\r\n\r\n\
+                    HTTP/1.1 200 OK\r\ncontent-length: 2\r\n\r\nok",
+                )
+                .await
+                .unwrap();
+        });
+
+        let (sender, conn) = hyper::client::conn::http1::handshake(TokioIo::new(client_io))
+            .await
+            .unwrap();
+        tokio::spawn(conn);
+        let client = HttpClientService(SendRequest::Http1(Mutex::new(sender)));
+
+        let hints = Arc::new(parking_lot::Mutex::new(Vec::new()));
+        let mut req = Request::builder()
+            .uri("/")
+            .header(HOST, "example.com")
+            .body(Body::empty())
+            .unwrap();
+        req.extensions_mut().insert(EarlyHintsSender::new({
+            let hints = hints.clone();
+            move |headers| hints.lock().push(headers)
+        }));
+
+        let resp = client.serve(Context::default(), req).await.unwrap();
+        let interim = resp.extensions().get::<InformationalResponses>().unwrap();
+        assert_eq!(interim.early_hints().count(), 1);
+        assert_eq!(
+            interim.iter().next().unwrap().headers()["link"],
+            "</style.css>; rel=preload; as=style"
+        );
+        assert_eq!(hints.lock().len(), 1);
+    }
+}
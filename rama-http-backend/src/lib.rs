@@ -16,6 +16,8 @@
 #![cfg_attr(not(test), warn(clippy::print_stdout, clippy::dbg_macro))]
 
 pub mod client;
+pub mod reverse_proxy;
 pub mod server;
+pub mod websocket;
 
 mod executor;
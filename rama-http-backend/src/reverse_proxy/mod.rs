@@ -0,0 +1,1110 @@
+//! Reverse proxy [`Service`], forwarding requests to an upstream (origin) server.
+//!
+//! The [`ReverseProxyService`] takes care of what is otherwise hand-rolled
+//! on top of the [`HttpClient`] for each reverse proxy:
+//!
+//! - the request target is rewritten to the configured upstream,
+//!   with the upstream (base) path prefixed to the original path;
+//! - hop-by-hop headers are removed from both the request and the response,
+//!   including the headers nominated by the `Connection` header;
+//! - the `Forwarded` and `X-Forwarded-For`, `X-Forwarded-Host` and `X-Forwarded-Proto`
+//!   headers are (re)set, extending the forwarded information found in the [`Context`]
+//!   (e.g. by a `GetForwardedHeadersLayer` of a trusted proxy in front of this one);
+//! - request and response bodies are streamed in both directions, including trailers,
+//!   with gRPC requests forwarded over h2 (using prior knowledge for plain text upstreams);
+//! - http/1.1 upgrades (e.g. WebSocket) are forwarded over a dedicated upstream connection,
+//!   after which the upgraded client and upstream connections are spliced together;
+//! - upstream connections are kept in a pool, such that they can be reused.
+//!
+//! Whether a request reused a pooled connection is recorded in the [`ConnectionReuse`]
+//! response extension and the [`ConnectionReuseMetrics`] of the service, and can optionally
+//! be exposed to the client in the `connection-reused` debug response header.
+//!
+//! When reconfiguring the upstream, the connections of the old [`ReverseProxyService`]
+//! can be drained using [`ReverseProxyService::drain_connections`], such that
+//! in-flight requests can finish instead of being aborted. Connections established
+//! with a [`ProxyCredentialWatch`] in the [`Context`] are drained in the same way
+//! once that watch becomes stale, i.e. when the proxy credentials are rotated
+//! (requires the `proxy` feature).
+//!
+//! Upstream failures are reported as a `502 Bad Gateway` response,
+//! with a `Proxy-Status` header describing the kind of failure.
+//!
+//! [`HttpClient`]: crate::client::HttpClient
+//! [`ProxyCredentialWatch`]: rama_proxy::ProxyCredentialWatch
+//!
+//! # Example
+//!
+//! ```no_run
+//! use rama_core::rt::Executor;
+//! use rama_http_backend::{reverse_proxy::ReverseProxyService, server::HttpServer};
+//! use rama_tcp::server::TcpListener;
+//!
+//! # #[tokio::main]
+//! # async fn main() {
+//! let proxy = ReverseProxyService::try_new("http://127.0.0.1:3000/app".parse().unwrap())
+//!     .unwrap()
+//!     .preserve_host(true);
+//!
+//! TcpListener::bind("127.0.0.1:8080")
+//!     .await
+//!     .unwrap()
+//!     .serve(HttpServer::auto(Executor::default()).service(proxy))
+//!     .await;
+//! # }
+//! ```
+
+use crate::{
+    client::{
+        proxy::proxy_status_for_error, te_contains_trailers, ConnectionReuse,
+        ConnectionReuseMetrics, HttpConnector, CONNECTION_REUSED,
+    },
+    server::layer::upgrade::Upgraded,
+};
+use hyper::upgrade::OnUpgrade;
+use rama_core::{
+    error::{ErrorContext, ErrorExt, OpaqueError},
+    Context, Service,
+};
+use rama_http_types::{
+    header::{
+        CONNECTION, CONTENT_TYPE, FORWARDED, HOST, KEEP_ALIVE, PROXY_AUTHENTICATE,
+        PROXY_AUTHORIZATION, PROXY_CONNECTION, TE, TRANSFER_ENCODING, UPGRADE, X_FORWARDED_FOR,
+        X_FORWARDED_HOST, X_FORWARDED_PROTO,
+    },
+    headers::{HeaderMapExt, ProxyStatusEntry},
+    Body, HeaderMap, HeaderName, HeaderValue, IntoResponse, Request, Response, StatusCode, Uri,
+    Version,
+};
+use rama_net::{
+    client::{ConnectorService, EstablishedClientConnection},
+    forwarded::{Forwarded, ForwardedAuthority, ForwardedElement, ForwardedProtocol},
+    http::RequestContext,
+    stream::SocketInfo,
+};
+use rama_tcp::client::service::TcpConnector;
+use std::{convert::Infallible, sync::Arc};
+
+#[cfg(feature = "proxy")]
+use rama_proxy::ProxyCredentialWatch;
+
+#[cfg(any(feature = "rustls", feature = "boring"))]
+use rama_net::tls::client::ClientConfig;
+#[cfg(any(feature = "rustls", feature = "boring"))]
+use rama_tls::std::client::{TlsConnector, TlsConnectorData};
+
+mod pool;
+#[doc(inline)]
+pub use pool::ConnectionDrain;
+use pool::{ConnectionPool, PooledBody};
+
+const DEFAULT_MAX_IDLE_CONNECTIONS: usize = 32;
+
+#[derive(Debug, Clone)]
+/// A [`Service`] forwarding requests to an upstream (origin) server.
+///
+/// See [the module docs](self) for more information.
+pub struct ReverseProxyService {
+    upstream: Uri,
+    preserve_host: bool,
+    forwarded_headers: bool,
+    max_idle_connections: usize,
+    pool: Arc<ConnectionPool>,
+    reuse_metrics: Arc<ConnectionReuseMetrics>,
+    connection_reused_header: bool,
+    preserve_header_case: bool,
+    #[cfg(any(feature = "rustls", feature = "boring"))]
+    tls_config: Option<ClientConfig>,
+}
+
+impl ReverseProxyService {
+    /// Create a new [`ReverseProxyService`] forwarding to the given upstream url,
+    /// e.g. `http://127.0.0.1:3000` or `https://example.com/api`.
+    ///
+    /// Fails in case the url is not an absolute http(s) url.
+    pub fn try_new(upstream: Uri) -> Result<Self, OpaqueError> {
+        match upstream.scheme_str() {
+            #[cfg(any(feature = "rustls", feature = "boring"))]
+            Some("http" | "https") => (),
+            #[cfg(not(any(feature = "rustls", feature = "boring")))]
+            Some("http") => (),
+            _ => {
+                return Err(OpaqueError::from_display(format!(
+                    "unsupported upstream url scheme: {upstream}"
+                )))
+            }
+        }
+        if upstream.authority().is_none() {
+            return Err(OpaqueError::from_display(format!(
+                "upstream url is missing a host: {upstream}"
+            )));
+        }
+        Ok(Self {
+            upstream,
+            preserve_host: false,
+            forwarded_headers: true,
+            max_idle_connections: DEFAULT_MAX_IDLE_CONNECTIONS,
+            pool: Arc::default(),
+            reuse_metrics: Arc::default(),
+            connection_reused_header: false,
+            preserve_header_case: false,
+            #[cfg(any(feature = "rustls", feature = "boring"))]
+            tls_config: None,
+        })
+    }
+
+    /// Forward the original `Host` header instead of the upstream authority.
+    ///
+    /// Disabled by default.
+    pub fn preserve_host(mut self, preserve: bool) -> Self {
+        self.preserve_host = preserve;
+        self
+    }
+
+    /// Forward the original `Host` header instead of the upstream authority.
+    ///
+    /// Disabled by default.
+    pub fn set_preserve_host(&mut self, preserve: bool) -> &mut Self {
+        self.preserve_host = preserve;
+        self
+    }
+
+    /// Set the `Forwarded` and `X-Forwarded-*` headers.
+    ///
+    /// Enabled by default. When disabled these headers are still removed
+    /// from the incoming request, as they cannot be trusted.
+    pub fn forwarded_headers(mut self, enabled: bool) -> Self {
+        self.forwarded_headers = enabled;
+        self
+    }
+
+    /// Set the `Forwarded` and `X-Forwarded-*` headers.
+    ///
+    /// Enabled by default. When disabled these headers are still removed
+    /// from the incoming request, as they cannot be trusted.
+    pub fn set_forwarded_headers(&mut self, enabled: bool) -> &mut Self {
+        self.forwarded_headers = enabled;
+        self
+    }
+
+    /// Set the maximum amount of idle upstream connections kept for reuse.
+    ///
+    /// Defaults to `32`, use `0` to disable connection reuse.
+    pub fn max_idle_connections(mut self, max: usize) -> Self {
+        self.max_idle_connections = max;
+        self
+    }
+
+    /// Set the maximum amount of idle upstream connections kept for reuse.
+    ///
+    /// Defaults to `32`, use `0` to disable connection reuse.
+    pub fn set_max_idle_connections(&mut self, max: usize) -> &mut Self {
+        self.max_idle_connections = max;
+        self
+    }
+
+    /// Add the `connection-reused` debug header to the responses,
+    /// describing the [`ConnectionReuse`] of the request.
+    ///
+    /// Disabled by default, as it exposes details of the upstream connections.
+    pub fn connection_reused_header(mut self, enabled: bool) -> Self {
+        self.connection_reused_header = enabled;
+        self
+    }
+
+    /// Add the `connection-reused` debug header to the responses,
+    /// describing the [`ConnectionReuse`] of the request.
+    ///
+    /// Disabled by default, as it exposes details of the upstream connections.
+    pub fn set_connection_reused_header(&mut self, enabled: bool) -> &mut Self {
+        self.connection_reused_header = enabled;
+        self
+    }
+
+    /// Forward the header names of http/1 requests and responses
+    /// using their original casing (e.g. `X-Custom-HeaDer`), instead of in lowercase.
+    ///
+    /// Requires the [`HttpServer`] to be configured to preserve header cases as well,
+    /// as it is the one recording the original casing of the request header names.
+    ///
+    /// Disabled by default.
+    ///
+    /// [`HttpServer`]: crate::server::HttpServer
+    pub fn preserve_header_case(mut self, enabled: bool) -> Self {
+        self.preserve_header_case = enabled;
+        self
+    }
+
+    /// Forward the header names of http/1 requests and responses
+    /// using their original casing (e.g. `X-Custom-HeaDer`), instead of in lowercase.
+    ///
+    /// See [`Self::preserve_header_case`] for more information.
+    pub fn set_preserve_header_case(&mut self, enabled: bool) -> &mut Self {
+        self.preserve_header_case = enabled;
+        self
+    }
+
+    /// The [`ConnectionReuseMetrics`] of all requests forwarded
+    /// by this service (and its clones).
+    pub fn connection_reuse_metrics(&self) -> &ConnectionReuseMetrics {
+        &self.reuse_metrics
+    }
+
+    #[cfg(any(feature = "rustls", feature = "boring"))]
+    /// Set the [`ClientConfig`] used to connect to a https upstream.
+    pub fn set_tls_config(&mut self, cfg: ClientConfig) -> &mut Self {
+        self.tls_config = Some(cfg);
+        self
+    }
+
+    #[cfg(any(feature = "rustls", feature = "boring"))]
+    /// Replace this [`ReverseProxyService`] with the [`ClientConfig`]
+    /// used to connect to a https upstream set.
+    pub fn with_tls_config(mut self, cfg: ClientConfig) -> Self {
+        self.tls_config = Some(cfg);
+        self
+    }
+
+    /// Rewrite the request such that it targets the upstream.
+    /// Gracefully close the current upstream connections of this service
+    /// (and its clones), e.g. when the upstream settings are reconfigured.
+    ///
+    /// The connections are no longer used for new requests, while their
+    /// in-flight requests are allowed to finish. Each connection is closed
+    /// once its last request is finished, which for h2 connections means
+    /// a `GOAWAY` frame is sent. New requests use new connections.
+    ///
+    /// Await [`ConnectionDrain::finished`] to wait until all
+    /// drained connections finished their in-flight requests.
+    pub fn drain_connections(&self) -> ConnectionDrain {
+        self.pool.drain()
+    }
+
+    fn rewrite_request<State>(
+        &self,
+        ctx: &mut Context<State>,
+        mut req: Request,
+    ) -> Result<Request, OpaqueError> {
+        remove_request_hop_by_hop_headers(req.headers_mut());
+
+        let original_host = req.headers().get(HOST).cloned().or_else(|| {
+            req.uri()
+                .authority()
+                .and_then(|authority| HeaderValue::from_str(authority.as_str()).ok())
+        });
+
+        if self.forwarded_headers {
+            set_forwarded_headers(ctx, &mut req, original_host.as_ref())?;
+        }
+        // the request context of the incoming request does not apply to the upstream
+        ctx.remove::<RequestContext>();
+
+        let base_path = self.upstream.path().trim_end_matches('/');
+        let path_and_query = req
+            .uri()
+            .path_and_query()
+            .map(|pq| pq.as_str())
+            .unwrap_or("/");
+        let mut parts = self.upstream.clone().into_parts();
+        parts.path_and_query = Some(
+            format!("{base_path}{path_and_query}")
+                .parse()
+                .context("create upstream path")?,
+        );
+        let uri = Uri::from_parts(parts).context("create upstream uri")?;
+
+        let host = match original_host {
+            Some(host) if self.preserve_host => host,
+            _ => HeaderValue::from_str(uri.authority().map(|a| a.as_str()).unwrap_or_default())
+                .context("create upstream host header")?,
+        };
+        req.headers_mut().insert(HOST, host);
+
+        // h2 is only used for tls upstreams which negotiate it (ALPN),
+        // except for gRPC which requires h2 (prior knowledge for plain text upstreams)
+        *req.version_mut() = if is_grpc_request(&req) {
+            Version::HTTP_2
+        } else {
+            Version::HTTP_11
+        };
+        *req.uri_mut() = uri;
+
+        Ok(req)
+    }
+
+    async fn forward<State>(
+        &self,
+        ctx: Context<State>,
+        mut req: Request,
+    ) -> Result<Response, OpaqueError>
+    where
+        State: Clone + Send + Sync + 'static,
+    {
+        let mut attempts = 0;
+        let pooled = match self.pool.checkout(req.version() == Version::HTTP_2) {
+            Some(conn) => {
+                attempts += 1;
+                conn.service().ready().await.then_some(conn)
+            }
+            None => None,
+        };
+        let (ctx, req, conn, reuse) = match pooled {
+            Some(conn) => {
+                tracing::trace!(upstream = %self.upstream, "reuse pooled upstream connection");
+                *req.version_mut() = conn.version;
+                (ctx, req, conn, ConnectionReuse::reused_connection(attempts))
+            }
+            None => {
+                let reuse = ConnectionReuse::new_connection(attempts + 1);
+                let EstablishedClientConnection { ctx, req, conn, .. } =
+                    match self.connector(false)?.connect(ctx, req).await {
+                        Ok(established) => established,
+                        Err(err) => {
+                            self.reuse_metrics.record(&reuse);
+                            return Err(OpaqueError::from_boxed(err).context("connect to upstream"));
+                        }
+                    };
+                let version = req.version();
+                #[cfg(feature = "proxy")]
+                let conn =
+                    self.pool
+                        .connection(conn, version, ctx.get::<ProxyCredentialWatch>().cloned());
+                #[cfg(not(feature = "proxy"))]
+                let conn = self.pool.connection(conn, version);
+                (ctx, req, conn, reuse)
+            }
+        };
+        self.reuse_metrics.record(&reuse);
+
+        let mut resp = conn
+            .service()
+            .serve(ctx, req)
+            .await
+            .map_err(OpaqueError::from_boxed)
+            .context("send request to upstream")?;
+        resp.extensions_mut().insert(reuse);
+
+        if conn.is_multiplexed() {
+            self.pool.checkin(conn.clone(), self.max_idle_connections);
+            Ok(resp.map(|body| Body::new(PooledBody::in_use(body, conn))))
+        } else {
+            let pool = self.pool.clone();
+            let max_idle = self.max_idle_connections;
+            Ok(resp.map(|body| Body::new(PooledBody::new(body, pool, conn, max_idle))))
+        }
+    }
+
+    /// Forward a http/1.1 upgrade request over a dedicated upstream connection,
+    /// splicing the upgraded client and upstream connections together
+    /// in case the upstream switches protocols.
+    async fn forward_upgrade<State>(
+        &self,
+        ctx: Context<State>,
+        req: Request,
+        client_upgrade: OnUpgrade,
+    ) -> Result<Response, OpaqueError>
+    where
+        State: Clone + Send + Sync + 'static,
+    {
+        let executor = ctx.executor().clone();
+        let EstablishedClientConnection { ctx, req, conn, .. } = self
+            .connector(true)?
+            .connect(ctx, req)
+            .await
+            .map_err(OpaqueError::from_boxed)
+            .context("connect to upstream")?;
+
+        let reuse = ConnectionReuse::new_connection(1);
+        self.reuse_metrics.record(&reuse);
+
+        let mut resp = conn
+            .serve(ctx, req)
+            .await
+            .map_err(OpaqueError::from_boxed)
+            .context("send upgrade request to upstream")?;
+        resp.extensions_mut().insert(reuse);
+        if resp.status() != StatusCode::SWITCHING_PROTOCOLS {
+            return Ok(resp);
+        }
+
+        let upstream_upgrade = hyper::upgrade::on(&mut resp);
+        executor.spawn_task(async move {
+            let (mut client, mut upstream) =
+                match tokio::try_join!(client_upgrade, upstream_upgrade) {
+                    Ok((client, upstream)) => (Upgraded::new(client), Upgraded::new(upstream)),
+                    Err(err) => {
+                        tracing::debug!(error = %err, "upgrade proxied connection");
+                        return;
+                    }
+                };
+            match tokio::io::copy_bidirectional(&mut client, &mut upstream).await {
+                Ok((sent, received)) => {
+                    tracing::trace!(sent, received, "upgraded proxied connection closed")
+                }
+                Err(err) => tracing::debug!(error = %err, "upgraded proxied connection failed"),
+            }
+        });
+
+        Ok(resp)
+    }
+
+    #[cfg(any(feature = "rustls", feature = "boring"))]
+    fn connector(
+        &self,
+        http1_only: bool,
+    ) -> Result<HttpConnector<TlsConnector<TcpConnector>>, OpaqueError> {
+        let mut tls_connector_data = match &self.tls_config {
+            Some(tls_config) => tls_config
+                .clone()
+                .try_into()
+                .context("ReverseProxyService: create tls connector data from tls config")?,
+            None => TlsConnectorData::new_http_auto()
+                .context("ReverseProxyService: create tls connector data for http (auto)")?,
+        };
+        if http1_only {
+            // upgrades are only supported by http/1.1 connections
+            tls_connector_data = tls_connector_data.merge(
+                &TlsConnectorData::new_http_1()
+                    .context("ReverseProxyService: create tls connector data for http/1.1")?,
+            );
+        }
+        Ok(HttpConnector::new(
+            TlsConnector::auto(TcpConnector::new()).with_connector_data(tls_connector_data),
+        )
+        .with_preserve_header_case(self.preserve_header_case))
+    }
+
+    #[cfg(not(any(feature = "rustls", feature = "boring")))]
+    fn connector(&self, _http1_only: bool) -> Result<HttpConnector<TcpConnector>, OpaqueError> {
+        Ok(HttpConnector::new(TcpConnector::new())
+            .with_preserve_header_case(self.preserve_header_case))
+    }
+}
+
+impl<State> Service<State, Request> for ReverseProxyService
+where
+    State: Clone + Send + Sync + 'static,
+{
+    type Response = Response;
+    type Error = Infallible;
+
+    async fn serve(
+        &self,
+        mut ctx: Context<State>,
+        mut req: Request,
+    ) -> Result<Self::Response, Self::Error> {
+        let version = req.version();
+        let upgrade = take_upgrade(&mut req);
+        let mut req = match self.rewrite_request(&mut ctx, req) {
+            Ok(req) => req,
+            Err(err) => {
+                tracing::debug!(error = %err, upstream = %self.upstream, "rewrite request for upstream");
+                return Ok(StatusCode::BAD_REQUEST.into_response());
+            }
+        };
+
+        let result = match upgrade {
+            Some((protocol, client_upgrade)) => {
+                set_upgrade_headers(req.headers_mut(), protocol);
+                self.forward_upgrade(ctx, req, client_upgrade).await
+            }
+            None => self.forward(ctx, req).await,
+        };
+
+        match result {
+            Ok(mut resp) => {
+                let protocol = match resp.status() {
+                    StatusCode::SWITCHING_PROTOCOLS => resp.headers().get(UPGRADE).cloned(),
+                    _ => None,
+                };
+                remove_response_hop_by_hop_headers(resp.headers_mut());
+                if let Some(protocol) = protocol {
+                    set_upgrade_headers(resp.headers_mut(), protocol);
+                }
+                if self.connection_reused_header {
+                    if let Some(reuse) = resp.extensions().get::<ConnectionReuse>() {
+                        let value = reuse.header_value();
+                        resp.headers_mut().insert(CONNECTION_REUSED, value);
+                    }
+                }
+                *resp.version_mut() = version;
+                Ok(resp)
+            }
+            Err(err) => {
+                tracing::debug!(error = %err, upstream = %self.upstream, "forward request to upstream");
+                let mut entry = ProxyStatusEntry::new(rama_utils::info::NAME);
+                if let Some(authority) = self.upstream.authority() {
+                    entry.set_next_hop(authority.as_str());
+                }
+                let mut resp = StatusCode::BAD_GATEWAY.into_response();
+                resp.headers_mut()
+                    .typed_insert(proxy_status_for_error(entry, &err));
+                Ok(resp)
+            }
+        }
+    }
+}
+
+/// Take the requested protocol and the pending client upgrade
+/// of a http/1.1 upgrade request (e.g. a WebSocket handshake).
+fn take_upgrade(req: &mut Request) -> Option<(HeaderValue, OnUpgrade)> {
+    if req.version() != Version::HTTP_11 {
+        return None;
+    }
+    let upgrade_requested = req
+        .headers()
+        .get_all(CONNECTION)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|value| value.trim().eq_ignore_ascii_case("upgrade"));
+    if !upgrade_requested {
+        return None;
+    }
+    let protocol = req.headers().get(UPGRADE)?.clone();
+    Some((protocol, hyper::upgrade::on(req)))
+}
+
+/// (Re)set the hop-by-hop headers of an upgrade request or response.
+fn set_upgrade_headers(headers: &mut HeaderMap, protocol: HeaderValue) {
+    headers.insert(CONNECTION, HeaderValue::from_static("upgrade"));
+    headers.insert(UPGRADE, protocol);
+}
+
+/// Remove the hop-by-hop request headers, as well as the forwarded headers,
+/// which are (re)set by the proxy itself.
+///
+/// A `TE` header accepting trailers is forwarded as `TE: trailers`,
+/// as trailers are forwarded end-to-end (required by e.g. gRPC).
+fn remove_request_hop_by_hop_headers(headers: &mut HeaderMap) {
+    let accepts_trailers = headers.get(TE).is_some_and(te_contains_trailers);
+    remove_connection_headers(headers);
+    for header in [
+        &CONNECTION,
+        &KEEP_ALIVE,
+        &PROXY_CONNECTION,
+        &PROXY_AUTHORIZATION,
+        &TE,
+        &TRANSFER_ENCODING,
+        &UPGRADE,
+        &FORWARDED,
+        &X_FORWARDED_FOR,
+        &X_FORWARDED_HOST,
+        &X_FORWARDED_PROTO,
+    ] {
+        headers.remove(header);
+    }
+    if accepts_trailers {
+        headers.insert(TE, HeaderValue::from_static("trailers"));
+    }
+}
+
+/// Returns `true` in case the request is a gRPC request, which requires h2.
+fn is_grpc_request(req: &Request) -> bool {
+    req.headers()
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("application/grpc"))
+}
+
+fn remove_response_hop_by_hop_headers(headers: &mut HeaderMap) {
+    remove_connection_headers(headers);
+    for header in [
+        &CONNECTION,
+        &KEEP_ALIVE,
+        &PROXY_CONNECTION,
+        &PROXY_AUTHENTICATE,
+        &TRANSFER_ENCODING,
+        &UPGRADE,
+    ] {
+        headers.remove(header);
+    }
+}
+
+/// Remove the headers nominated by the `Connection` header.
+fn remove_connection_headers(headers: &mut HeaderMap) {
+    let nominated: Vec<_> = headers
+        .get_all(CONNECTION)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .filter_map(|name| HeaderName::from_bytes(name.trim().as_bytes()).ok())
+        .collect();
+    for name in nominated {
+        headers.remove(name);
+    }
+}
+
+/// Set the forwarded headers, extending the forwarded information
+/// already known with the information of the incoming request.
+fn set_forwarded_headers<State>(
+    ctx: &Context<State>,
+    req: &mut Request,
+    original_host: Option<&HeaderValue>,
+) -> Result<(), OpaqueError> {
+    let request_ctx = match ctx.get::<RequestContext>() {
+        Some(request_ctx) => request_ctx.clone(),
+        None => RequestContext::try_from((ctx, &*req)).context("create request context")?,
+    };
+    let proto = ForwardedProtocol::try_from(&request_ctx.protocol).ok();
+
+    // prefer the host as requested, which omits the default port
+    let host = original_host
+        .and_then(|host| host.to_str().ok()?.parse::<ForwardedAuthority>().ok())
+        .unwrap_or_else(|| request_ctx.authority.into());
+
+    let mut element = ForwardedElement::forwarded_host(host);
+    if let Some(peer_addr) = ctx.get::<SocketInfo>().map(|socket| *socket.peer_addr()) {
+        element.set_forwarded_for(peer_addr);
+    }
+    if let Some(proto) = proto.clone() {
+        element.set_forwarded_proto(proto);
+    }
+    let forwarded = match ctx.get::<Forwarded>().cloned() {
+        Some(mut forwarded) => {
+            forwarded.append(element);
+            forwarded
+        }
+        None => Forwarded::new(element),
+    };
+
+    let headers = req.headers_mut();
+    headers.insert(
+        FORWARDED,
+        HeaderValue::from_str(&forwarded.to_string()).context("create forwarded header")?,
+    );
+    let forwarded_for: Vec<_> = forwarded
+        .iter()
+        .filter_map(|element| element.ref_forwarded_for()?.ip())
+        .map(|ip| ip.to_string())
+        .collect();
+    if !forwarded_for.is_empty() {
+        headers.insert(
+            &X_FORWARDED_FOR,
+            HeaderValue::from_str(&forwarded_for.join(", "))
+                .context("create x-forwarded-for header")?,
+        );
+    }
+    if let Some(host) = original_host {
+        headers.insert(&X_FORWARDED_HOST, host.clone());
+    }
+    if let Some(proto) = proto {
+        headers.insert(
+            &X_FORWARDED_PROTO,
+            HeaderValue::from_str(proto.as_str()).context("create x-forwarded-proto header")?,
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::HttpServer;
+    use rama_core::service::service_fn;
+    use rama_http_types::BodyExtractExt;
+    use rama_tcp::server::TcpListener;
+    use std::{
+        net::SocketAddr,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    fn proxy(upstream: &str) -> ReverseProxyService {
+        ReverseProxyService::try_new(upstream.parse().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_try_new() {
+        assert!(ReverseProxyService::try_new("http://127.0.0.1:3000".parse().unwrap()).is_ok());
+        assert!(ReverseProxyService::try_new("/foo".parse().unwrap()).is_err());
+        assert!(ReverseProxyService::try_new("ftp://example.com".parse().unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_rewrite_request() {
+        let mut ctx = Context::default();
+        ctx.insert(SocketInfo::new(None, "10.0.0.1:4242".parse().unwrap()));
+        let req = Request::builder()
+            .uri("/foo?bar=baz")
+            .version(Version::HTTP_2)
+            .header(HOST, "example.com")
+            .header(CONNECTION, "keep-alive, x-hop")
+            .header("x-hop", "1")
+            .header("x-end", "1")
+            .header(TE, "gzip, trailers")
+            .header(&X_FORWARDED_FOR, "6.6.6.6")
+            .body(Body::empty())
+            .unwrap();
+
+        let req = proxy("http://127.0.0.1:3000/app/")
+            .rewrite_request(&mut ctx, req)
+            .unwrap();
+        assert_eq!(req.headers()[TE], "trailers");
+        assert_eq!(req.uri(), "http://127.0.0.1:3000/app/foo?bar=baz");
+        assert_eq!(req.version(), Version::HTTP_11);
+        assert_eq!(req.headers()[HOST], "127.0.0.1:3000");
+        for name in [CONNECTION.as_str(), "x-hop"] {
+            assert!(!req.headers().contains_key(name), "{name}");
+        }
+        assert_eq!(req.headers()["x-end"], "1");
+        assert_eq!(
+            req.headers()[FORWARDED],
+            r#"for="10.0.0.1:4242";host=example.com;proto=http"#
+        );
+        assert_eq!(req.headers()[&X_FORWARDED_FOR], "10.0.0.1");
+        assert_eq!(req.headers()[&X_FORWARDED_HOST], "example.com");
+        assert_eq!(req.headers()[&X_FORWARDED_PROTO], "http");
+        assert!(!ctx.contains::<RequestContext>());
+    }
+
+    #[test]
+    fn test_rewrite_request_preserve_host_without_forwarded() {
+        let mut ctx = Context::<()>::default();
+        let req = Request::builder()
+            .uri("http://example.com/")
+            .header(&X_FORWARDED_FOR, "6.6.6.6")
+            .body(Body::empty())
+            .unwrap();
+
+        let req = proxy("http://127.0.0.1:3000")
+            .preserve_host(true)
+            .forwarded_headers(false)
+            .rewrite_request(&mut ctx, req)
+            .unwrap();
+        assert_eq!(req.uri(), "http://127.0.0.1:3000/");
+        assert_eq!(req.headers()[HOST], "example.com");
+        for name in [
+            &FORWARDED,
+            &X_FORWARDED_FOR,
+            &X_FORWARDED_HOST,
+            &X_FORWARDED_PROTO,
+        ] {
+            assert!(!req.headers().contains_key(name), "{name}");
+        }
+    }
+
+    async fn spawn_upstream(connections: Arc<AtomicUsize>) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = HttpServer::http1().on_connection(move |_| {
+            connections.fetch_add(1, Ordering::SeqCst);
+        });
+        tokio::spawn(
+            listener.serve(server.service(service_fn(|req: Request| async move {
+                let path = req.uri().path().to_owned();
+                let body = req.into_body().try_into_string().await.unwrap();
+                Ok::<_, Infallible>(
+                    Response::builder()
+                        .header(CONNECTION, "x-hop")
+                        .header("x-hop", "1")
+                        .body(Body::from(format!("{path}:{body}")))
+                        .unwrap(),
+                )
+            }))),
+        );
+        addr
+    }
+
+    #[tokio::test]
+    async fn test_forward_reuses_connections() {
+        let connections = Arc::new(AtomicUsize::new(0));
+        let addr = spawn_upstream(connections.clone()).await;
+        let proxy = proxy(&format!("http://{addr}/api")).connection_reused_header(true);
+
+        for i in 0..3 {
+            let req = Request::builder()
+                .method("POST")
+                .uri(format!("http://example.com/item/{i}"))
+                .body(Body::from("hello"))
+                .unwrap();
+            let resp = proxy.serve(Context::default(), req).await.unwrap();
+            assert_eq!(resp.status(), StatusCode::OK);
+            assert!(!resp.headers().contains_key("x-hop"));
+            let expected = if i == 0 {
+                "?0;attempts=1;handshakes=1"
+            } else {
+                "?1;attempts=1;handshakes=0"
+            };
+            assert_eq!(resp.headers()[CONNECTION_REUSED], expected);
+            let body = resp.into_body().try_into_string().await.unwrap();
+            assert_eq!(body, format!("/api/item/{i}:hello"));
+        }
+        assert_eq!(connections.load(Ordering::SeqCst), 1);
+        assert_eq!(proxy.pool.len(), 1);
+
+        let metrics = proxy.connection_reuse_metrics();
+        assert_eq!(metrics.requests(), 3);
+        assert_eq!(metrics.reused(), 2);
+        assert_eq!(metrics.handshakes(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_drain_connections() {
+        let connections = Arc::new(AtomicUsize::new(0));
+        let addr = spawn_upstream(connections.clone()).await;
+        let proxy = proxy(&format!("http://{addr}"));
+        let request = || {
+            Request::builder()
+                .uri("http://example.com/")
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        let resp = proxy.serve(Context::default(), request()).await.unwrap();
+        resp.into_body().try_into_string().await.unwrap();
+        assert_eq!(proxy.pool.len(), 1);
+
+        // idle connections are closed right away
+        let drain = proxy.drain_connections();
+        assert_eq!(proxy.pool.len(), 0);
+        assert_eq!(drain.remaining(), 0);
+        drain.finished().await;
+
+        // in-flight requests are allowed to finish
+        let resp = proxy.serve(Context::default(), request()).await.unwrap();
+        assert_eq!(connections.load(Ordering::SeqCst), 2);
+        let drain = proxy.drain_connections();
+        assert_eq!(drain.remaining(), 1);
+        let body = tokio::spawn(resp.into_body().try_into_string());
+        tokio::time::timeout(std::time::Duration::from_secs(5), drain.finished())
+            .await
+            .unwrap();
+        assert_eq!(body.await.unwrap().unwrap(), "/:");
+
+        // drained connections are not returned to the pool
+        assert_eq!(proxy.pool.len(), 0);
+        let resp = proxy.serve(Context::default(), request()).await.unwrap();
+        resp.into_body().try_into_string().await.unwrap();
+        assert_eq!(connections.load(Ordering::SeqCst), 3);
+    }
+
+    #[cfg(feature = "proxy")]
+    #[tokio::test]
+    async fn test_drain_connections_on_credential_rotation() {
+        use rama_net::user::{Basic, ProxyCredential};
+        use rama_proxy::{ProxyCredentialRotation, ProxyID};
+        use rama_utils::str::NonEmptyString;
+
+        let connections = Arc::new(AtomicUsize::new(0));
+        let addr = spawn_upstream(connections.clone()).await;
+        let proxy = proxy(&format!("http://{addr}"));
+        let rotation = ProxyCredentialRotation::new();
+        let id = ProxyID::from(NonEmptyString::from_static("1"));
+        let credential = ProxyCredential::Basic(Basic::new("john", "secret"));
+
+        let serve = |watch: ProxyCredentialWatch| {
+            let mut ctx = Context::default();
+            ctx.insert(watch);
+            let req = Request::builder()
+                .uri("http://example.com/")
+                .body(Body::empty())
+                .unwrap();
+            let proxy = proxy.clone();
+            async move {
+                let resp = proxy.serve(ctx, req).await.unwrap();
+                resp.into_body().try_into_string().await.unwrap();
+            }
+        };
+
+        serve(rotation.observe(&id, Some(&credential))).await;
+        serve(rotation.observe(&id, Some(&credential))).await;
+        assert_eq!(connections.load(Ordering::SeqCst), 1);
+        assert_eq!(proxy.pool.len(), 1);
+
+        // connections established with the old credential are no longer reused
+        rotation.rotate(&id);
+        serve(rotation.observe(&id, Some(&credential))).await;
+        assert_eq!(connections.load(Ordering::SeqCst), 2);
+        assert_eq!(proxy.pool.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_forward_preserve_header_case() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let upstream = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut head = Vec::new();
+            let mut chunk = [0; 1024];
+            while !head.ends_with(b"\r\n\r\n") {
+                let n = stream.read(&mut chunk).await.unwrap();
+                head.extend_from_slice(&chunk[..n]);
+            }
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\nX-Upstream-CaSe: 1\r\ncontent-length: 0\r\n\r\n")
+                .await
+                .unwrap();
+            String::from_utf8(head).unwrap()
+        });
+
+        let mut server = HttpServer::http1();
+        server.http1_mut().preserve_header_case(true);
+        let service = server.service(
+            proxy(&format!("http://{addr}"))
+                .preserve_header_case(true)
+                .max_idle_connections(0),
+        );
+        let (mut client_io, server_io) = tokio::io::duplex(4096);
+        tokio::spawn(async move { service.serve(Context::default(), server_io).await });
+
+        client_io
+            .write_all(
+                b"GET / HTTP/1.1\r\nHost: example.com\r\nX-Custom-HeaDer: 1\r\nConnection: close\r\n\r\n",
+            )
+            .await
+            .unwrap();
+        let mut output = String::new();
+        client_io.read_to_string(&mut output).await.unwrap();
+        assert!(output.contains("\r\nX-Upstream-CaSe: 1\r\n"), "{output}");
+
+        let head = upstream.await.unwrap();
+        assert!(head.contains("\r\nX-Custom-HeaDer: 1\r\n"), "{head}");
+    }
+
+    #[tokio::test]
+    async fn test_forward_upstream_down() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        drop(listener);
+
+        let proxy = proxy(&format!("http://{addr}"));
+        let req = Request::builder()
+            .uri("http://example.com/")
+            .body(Body::empty())
+            .unwrap();
+        let resp = proxy.serve(Context::<()>::default(), req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::BAD_GATEWAY);
+        assert_eq!(
+            resp.headers()["proxy-status"],
+            format!(r#"rama; error=connection_refused; next-hop="{addr}""#),
+        );
+    }
+
+    #[tokio::test]
+    async fn test_forward_grpc_trailers() {
+        use rama_core::rt::Executor;
+        use rama_http_types::dep::{
+            http_body::Frame,
+            http_body_util::{BodyExt, StreamBody},
+        };
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = HttpServer::h2(Executor::default());
+        tokio::spawn(
+            listener.serve(server.service(service_fn(|req: Request| async move {
+                let mut trailers = HeaderMap::new();
+                trailers.insert("grpc-status", HeaderValue::from_static("0"));
+                let frames = [
+                    Ok::<_, Infallible>(Frame::data(bytes::Bytes::from_static(b"\0\0\0\0\0"))),
+                    Ok(Frame::trailers(trailers)),
+                ];
+                Ok::<_, Infallible>(
+                    Response::builder()
+                        .header(CONTENT_TYPE, "application/grpc")
+                        .header("x-version", format!("{:?}", req.version()))
+                        .header(
+                            "x-te",
+                            req.headers()
+                                .get(TE)
+                                .cloned()
+                                .unwrap_or(HeaderValue::from_static("")),
+                        )
+                        .body(Body::new(StreamBody::new(futures_lite::stream::iter(
+                            frames,
+                        ))))
+                        .unwrap(),
+                )
+            }))),
+        );
+
+        let proxy = proxy(&format!("http://{addr}"));
+        let req = Request::builder()
+            .method("POST")
+            .uri("http://example.com/helloworld.Greeter/SayHello")
+            .version(Version::HTTP_2)
+            .header(CONTENT_TYPE, "application/grpc")
+            .header(TE, "trailers")
+            .body(Body::from(&b"\0\0\0\0\0"[..]))
+            .unwrap();
+        let resp = proxy.serve(Context::<()>::default(), req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.version(), Version::HTTP_2);
+        assert_eq!(resp.headers()["x-version"], "HTTP/2.0");
+        assert_eq!(resp.headers()["x-te"], "trailers");
+
+        let body = resp.into_body().collect().await.unwrap();
+        assert_eq!(body.trailers().unwrap()["grpc-status"], "0");
+        assert_eq!(body.to_bytes(), &b"\0\0\0\0\0"[..]);
+    }
+
+    #[tokio::test]
+    async fn test_forward_websocket_upgrade() {
+        use crate::{
+            server::layer::upgrade::UpgradeLayer,
+            websocket::{
+                server::{WebSocketAcceptor, WebSocketMatcher, WebSocketService},
+                Message, Role, WebSocket,
+            },
+        };
+        use rama_core::Layer;
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let upstream_addr = listener.local_addr().unwrap();
+        let echo = service_fn(|mut socket: WebSocket| async move {
+            while let Some(msg) = socket.recv().await {
+                if let msg @ Message::Text(_) = msg? {
+                    socket.send(msg).await?;
+                }
+            }
+            Ok::<_, OpaqueError>(())
+        });
+        let upstream = UpgradeLayer::new(
+            WebSocketMatcher::new(),
+            WebSocketAcceptor::new(),
+            WebSocketService::new(echo),
+        )
+        .layer(service_fn(|_req: Request| async {
+            Ok::<_, Infallible>(StatusCode::NOT_FOUND.into_response())
+        }));
+        tokio::spawn(listener.serve(HttpServer::http1().service(upstream)));
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let proxy_addr = listener.local_addr().unwrap();
+        let proxy = proxy(&format!("http://{upstream_addr}"));
+        tokio::spawn(listener.serve(HttpServer::http1().service(proxy)));
+
+        let mut stream = tokio::net::TcpStream::connect(proxy_addr).await.unwrap();
+        stream
+            .write_all(
+                b"GET /chat HTTP/1.1\r\n\
+                  host: example.com\r\n\
+                  connection: upgrade\r\n\
+                  upgrade: websocket\r\n\
+                  sec-websocket-version: 13\r\n\
+                  sec-websocket-key: dGhlIHNhbXBsZSBub25jZQ==\r\n\r\n",
+            )
+            .await
+            .unwrap();
+        let mut head = Vec::new();
+        while !head.ends_with(b"\r\n\r\n") {
+            head.push(stream.read_u8().await.unwrap());
+        }
+        let head = String::from_utf8(head).unwrap().to_lowercase();
+        assert!(head.starts_with("http/1.1 101"), "{head}");
+        assert!(head.contains("upgrade: websocket"), "{head}");
+        assert!(
+            head.contains("sec-websocket-accept: s3pplmbitxaq9kygzzhzrbk+xoo="),
+            "{head}"
+        );
+
+        let mut socket = WebSocket::from_raw(stream, Role::Client);
+        for text in ["hello", "world"] {
+            socket.send(text).await.unwrap();
+            assert_eq!(socket.recv().await.unwrap().unwrap(), Message::from(text));
+        }
+    }
+}
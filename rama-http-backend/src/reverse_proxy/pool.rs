@@ -0,0 +1,301 @@
+use crate::client::HttpClientService;
+use parking_lot::Mutex;
+use rama_http_types::{
+    dep::http_body::{self, Frame, SizeHint},
+    Body, Version,
+};
+use std::{
+    fmt,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Weak,
+    },
+    task::{ready, Context as TaskContext, Poll},
+};
+use tokio::sync::Notify;
+
+#[cfg(feature = "proxy")]
+use rama_proxy::ProxyCredentialWatch;
+
+/// An (upstream) connection which can be reused for multiple requests.
+#[derive(Clone)]
+pub(super) struct PooledConnection {
+    inner: Arc<ConnectionInner>,
+    pub(super) version: Version,
+}
+
+struct ConnectionInner {
+    service: HttpClientService<Body>,
+    generation: u64,
+    #[cfg(feature = "proxy")]
+    credential: Option<ProxyCredentialWatch>,
+    released: Arc<Notify>,
+}
+
+impl Drop for ConnectionInner {
+    fn drop(&mut self) {
+        // dropping the last handle of a connection closes it gracefully,
+        // which is what a connection drain waits for
+        self.released.notify_waiters();
+    }
+}
+
+impl PooledConnection {
+    pub(super) fn service(&self) -> &HttpClientService<Body> {
+        &self.inner.service
+    }
+
+    /// Returns `false` in case the connection is closed, or the credential
+    /// of the upstream proxy used to establish the connection has been rotated since.
+    fn is_reusable(&self) -> bool {
+        #[cfg(feature = "proxy")]
+        if self
+            .inner
+            .credential
+            .as_ref()
+            .is_some_and(ProxyCredentialWatch::is_stale)
+        {
+            return false;
+        }
+        !self.service().is_closed()
+    }
+
+    /// Returns `true` in case the connection can be shared by concurrent requests.
+    pub(super) fn is_multiplexed(&self) -> bool {
+        self.version == Version::HTTP_2
+    }
+}
+
+/// Pool of the idle connections to a single upstream.
+///
+/// Http/1 connections are used for one request at a time, and are thus
+/// taken out of the pool until the response body is received. H2 connections
+/// remain in the pool, as they can be shared by concurrent requests.
+#[derive(Default)]
+pub(super) struct ConnectionPool {
+    idle: Mutex<Vec<PooledConnection>>,
+    live: Mutex<Vec<Weak<ConnectionInner>>>,
+    generation: AtomicU64,
+    released: Arc<Notify>,
+}
+
+impl ConnectionPool {
+    /// Create a [`PooledConnection`] for a newly established connection.
+    ///
+    /// The connection is no longer reused once the given [`ProxyCredentialWatch`],
+    /// if any, becomes stale.
+    ///
+    /// [`ProxyCredentialWatch`]: rama_proxy::ProxyCredentialWatch
+    pub(super) fn connection(
+        &self,
+        service: HttpClientService<Body>,
+        version: Version,
+        #[cfg(feature = "proxy")] credential: Option<ProxyCredentialWatch>,
+    ) -> PooledConnection {
+        let inner = Arc::new(ConnectionInner {
+            service,
+            generation: self.generation.load(Ordering::Acquire),
+            #[cfg(feature = "proxy")]
+            credential,
+            released: self.released.clone(),
+        });
+        let mut live = self.live.lock();
+        live.retain(|conn| conn.strong_count() > 0);
+        live.push(Arc::downgrade(&inner));
+        drop(live);
+        PooledConnection { inner, version }
+    }
+
+    /// Get a pooled connection, if any,
+    /// limited to h2 connections in case `h2_only` is `true`.
+    pub(super) fn checkout(&self, h2_only: bool) -> Option<PooledConnection> {
+        let mut idle = self.idle.lock();
+        // connections established with rotated proxy credentials are drained,
+        // and thus closed as soon as their in-flight requests are finished
+        idle.retain(PooledConnection::is_reusable);
+        let index = idle.iter().position(PooledConnection::is_multiplexed);
+        match index {
+            Some(index) => Some(idle[index].clone()),
+            None if h2_only => None,
+            None => idle.pop(),
+        }
+    }
+
+    /// Return a connection to the pool, such that it can be reused,
+    /// unless the pool already contains `max_idle` connections,
+    /// or the connection was drained since it was established,
+    /// either explicitly or because its proxy credentials were rotated.
+    pub(super) fn checkin(&self, conn: PooledConnection, max_idle: usize) {
+        let mut idle = self.idle.lock();
+        if idle.len() >= max_idle
+            || conn.inner.generation != self.generation.load(Ordering::Acquire)
+            || !conn.is_reusable()
+            || (conn.is_multiplexed()
+                && idle
+                    .iter()
+                    .any(|other| Arc::ptr_eq(&other.inner, &conn.inner)))
+        {
+            return;
+        }
+        idle.push(conn);
+    }
+
+    /// Stop using all current connections for new requests,
+    /// such that they are closed as soon as their in-flight requests are finished.
+    pub(super) fn drain(&self) -> ConnectionDrain {
+        let mut idle = self.idle.lock();
+        self.generation.fetch_add(1, Ordering::AcqRel);
+        let drained = std::mem::take(&mut *idle);
+        drop(idle);
+        drop(drained);
+
+        let live = std::mem::take(&mut *self.live.lock());
+        ConnectionDrain {
+            connections: live
+                .into_iter()
+                .filter(|conn| conn.strong_count() > 0)
+                .collect(),
+            released: self.released.clone(),
+        }
+    }
+
+    pub(super) fn len(&self) -> usize {
+        self.idle.lock().len()
+    }
+}
+
+impl fmt::Debug for ConnectionPool {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ConnectionPool")
+            .field("idle", &self.len())
+            .field("generation", &self.generation.load(Ordering::Relaxed))
+            .finish()
+    }
+}
+
+/// Upstream connections being drained by
+/// [`ReverseProxyService::drain_connections`].
+///
+/// No new requests are sent over these connections, and each connection
+/// is closed gracefully once its in-flight requests are finished,
+/// which for h2 connections means a `GOAWAY` frame is sent.
+///
+/// [`ReverseProxyService::drain_connections`]: super::ReverseProxyService::drain_connections
+pub struct ConnectionDrain {
+    connections: Vec<Weak<ConnectionInner>>,
+    released: Arc<Notify>,
+}
+
+impl ConnectionDrain {
+    /// The amount of drained connections which still have requests in flight.
+    pub fn remaining(&self) -> usize {
+        self.connections
+            .iter()
+            .filter(|conn| conn.strong_count() > 0)
+            .count()
+    }
+
+    /// Wait until all drained connections finished their in-flight requests.
+    pub async fn finished(&self) {
+        loop {
+            // created before checking, such that no release can be missed
+            let released = self.released.notified();
+            if self.remaining() == 0 {
+                return;
+            }
+            released.await;
+        }
+    }
+}
+
+impl fmt::Debug for ConnectionDrain {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ConnectionDrain")
+            .field("remaining", &self.remaining())
+            .finish()
+    }
+}
+
+/// Response body which keeps its connection in use until the body
+/// is received completely, returning (http/1) connections to the pool.
+///
+/// Connections of bodies dropped early are not returned,
+/// as these cannot be reused.
+pub(super) struct PooledBody {
+    inner: Body,
+    conn: Option<PooledConnection>,
+    checkin: Option<(Arc<ConnectionPool>, usize)>,
+}
+
+impl PooledBody {
+    /// Create a body returning its connection to the pool once received.
+    pub(super) fn new(
+        inner: Body,
+        pool: Arc<ConnectionPool>,
+        conn: PooledConnection,
+        max_idle: usize,
+    ) -> Self {
+        let mut body = Self {
+            inner,
+            conn: Some(conn),
+            checkin: Some((pool, max_idle)),
+        };
+        // empty bodies might never be polled
+        if http_body::Body::is_end_stream(&body.inner) {
+            body.release();
+        }
+        body
+    }
+
+    /// Create a body which only keeps its (shared) connection in use until received.
+    pub(super) fn in_use(inner: Body, conn: PooledConnection) -> Self {
+        let mut body = Self {
+            inner,
+            conn: Some(conn),
+            checkin: None,
+        };
+        if http_body::Body::is_end_stream(&body.inner) {
+            body.release();
+        }
+        body
+    }
+
+    fn release(&mut self) {
+        let conn = self.conn.take();
+        if let (Some((pool, max_idle)), Some(conn)) = (self.checkin.take(), conn) {
+            pool.checkin(conn, max_idle);
+        }
+    }
+}
+
+impl http_body::Body for PooledBody {
+    type Data = <Body as http_body::Body>::Data;
+    type Error = <Body as http_body::Body>::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut TaskContext<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let frame = ready!(Pin::new(&mut self.inner).poll_frame(cx));
+        match &frame {
+            Some(Ok(_)) if self.inner.is_end_stream() => self.release(),
+            Some(Ok(_)) => (),
+            // a failed connection cannot be reused
+            Some(Err(_)) => {
+                self.checkin = None;
+                self.release();
+            }
+            None => self.release(),
+        }
+        Poll::Ready(frame)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
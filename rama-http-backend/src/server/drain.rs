@@ -0,0 +1,151 @@
+use rama_core::graceful::ShutdownGuard;
+use std::{
+    pin::pin,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    },
+};
+use tokio::sync::Notify;
+
+#[derive(Debug, Default)]
+struct TrackerState {
+    active: AtomicUsize,
+    drained: Notify,
+}
+
+#[derive(Debug, Clone, Default)]
+/// Tracks the connections which are being served by one or more [`HttpServer`]s.
+///
+/// Useful to observe how many connections are still open while draining,
+/// e.g. to report the progress of a graceful shutdown.
+///
+/// Each tracked connection holds on to the [`ShutdownGuard`] of the server (if any),
+/// such that [`Shutdown::shutdown_with_limit`] only resolves early
+/// once [`ConnectionTracker::active`] dropped to zero.
+///
+/// [`Shutdown::shutdown_with_limit`]: rama_core::graceful::Shutdown::shutdown_with_limit
+///
+/// [`HttpServer`]: super::HttpServer
+pub struct ConnectionTracker(Arc<TrackerState>);
+
+impl ConnectionTracker {
+    /// Create a new [`ConnectionTracker`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the amount of connections which are currently being served.
+    pub fn active(&self) -> usize {
+        self.0.active.load(Ordering::Acquire)
+    }
+
+    /// Wait until no connections are being served anymore.
+    ///
+    /// Resolves immediately in case there are no active connections.
+    pub async fn drained(&self) {
+        loop {
+            let mut notified = pin!(self.0.drained.notified());
+            notified.as_mut().enable();
+            if self.active() == 0 {
+                return;
+            }
+            notified.await;
+        }
+    }
+
+    /// Track a connection until the returned guard is dropped.
+    ///
+    /// The given [`ShutdownGuard`] is kept alive for as long as the connection is tracked.
+    pub(crate) fn track(&self, guard: Option<&ShutdownGuard>) -> TrackedConnection {
+        self.0.active.fetch_add(1, Ordering::AcqRel);
+        TrackedConnection {
+            state: self.0.clone(),
+            _guard: guard.cloned(),
+        }
+    }
+}
+
+/// Guard of a connection tracked by a [`ConnectionTracker`].
+pub(crate) struct TrackedConnection {
+    state: Arc<TrackerState>,
+    _guard: Option<ShutdownGuard>,
+}
+
+impl Drop for TrackedConnection {
+    fn drop(&mut self) {
+        if self.state.active.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.state.drained.notify_waiters();
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+/// Flag shared between a connection and its requests,
+/// set once the connection started draining.
+pub(crate) struct Draining(Arc<AtomicBool>);
+
+impl Draining {
+    /// Mark the connection as draining.
+    pub(crate) fn start(&self) {
+        self.0.store(true, Ordering::Release);
+    }
+
+    /// Returns `true` in case the connection is draining.
+    pub(crate) fn is_draining(&self) -> bool {
+        self.0.load(Ordering::Acquire)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_connection_tracker() {
+        let tracker = ConnectionTracker::new();
+        tracker.drained().await;
+
+        let first = tracker.track(None);
+        let second = tracker.track(None);
+        assert_eq!(tracker.active(), 2);
+
+        let drained = tokio::spawn({
+            let tracker = tracker.clone();
+            async move { tracker.drained().await }
+        });
+        drop(first);
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert!(!drained.is_finished());
+
+        drop(second);
+        tokio::time::timeout(Duration::from_secs(1), drained)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(tracker.active(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_connection_tracker_shutdown_with_limit() {
+        let shutdown = rama_core::graceful::Shutdown::new(async {});
+        let tracker = ConnectionTracker::new();
+
+        let guard = shutdown.guard();
+        let connection = tracker.track(Some(&guard));
+        drop(guard);
+
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            drop(connection);
+        });
+
+        let elapsed = shutdown
+            .shutdown_with_limit(Duration::from_secs(1))
+            .await
+            .unwrap();
+        assert!(elapsed >= Duration::from_millis(50), "{elapsed:?}");
+        assert_eq!(tracker.active(), 0);
+    }
+}
@@ -0,0 +1,144 @@
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+/// Tuning settings for H2 server connections,
+/// which can be applied to an h2 or auto [`HttpServer`].
+///
+/// Settings which are not set keep the default used by hyper,
+/// which makes it possible to only tune the settings that matter
+/// for your workload (e.g. a proxy with a high fan-in of clients).
+///
+/// # Example
+///
+/// ```
+/// use rama_core::rt::Executor;
+/// use rama_http_backend::server::{H2Settings, HttpServer};
+/// use std::time::Duration;
+///
+/// let settings = H2Settings::new()
+///     .with_max_concurrent_streams(256)
+///     .with_initial_stream_window_size(1024 * 1024)
+///     .with_initial_connection_window_size(8 * 1024 * 1024)
+///     .with_keep_alive_interval(Duration::from_secs(30))
+///     .with_keep_alive_timeout(Duration::from_secs(10));
+///
+/// let server = HttpServer::auto(Executor::default()).with_h2_settings(settings);
+/// ```
+///
+/// [`HttpServer`]: super::HttpServer
+pub struct H2Settings {
+    max_concurrent_streams: Option<u32>,
+    initial_stream_window_size: Option<u32>,
+    initial_connection_window_size: Option<u32>,
+    adaptive_window: Option<bool>,
+    max_frame_size: Option<u32>,
+    max_header_list_size: Option<u32>,
+    max_send_buf_size: Option<usize>,
+    keep_alive_interval: Option<Duration>,
+    keep_alive_timeout: Option<Duration>,
+}
+
+macro_rules! h2_setting {
+    ($(#[$doc:meta])* $field:ident: $ty:ty, $with:ident, $set:ident) => {
+        $(#[$doc])*
+        pub fn $with(mut self, value: $ty) -> Self {
+            self.$field = Some(value);
+            self
+        }
+
+        $(#[$doc])*
+        pub fn $set(&mut self, value: $ty) -> &mut Self {
+            self.$field = Some(value);
+            self
+        }
+
+        /// Returns the configured value, if any.
+        pub fn $field(&self) -> Option<$ty> {
+            self.$field
+        }
+    };
+}
+
+impl H2Settings {
+    /// Create new [`H2Settings`], using the hyper defaults for all settings.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    h2_setting!(
+        /// Set the [`SETTINGS_MAX_CONCURRENT_STREAMS`][spec] advertised to clients,
+        /// limiting the amount of requests a single client can have in flight.
+        ///
+        /// [spec]: https://httpwg.org/specs/rfc9113.html#SETTINGS_MAX_CONCURRENT_STREAMS
+        max_concurrent_streams: u32,
+        with_max_concurrent_streams,
+        set_max_concurrent_streams
+    );
+
+    h2_setting!(
+        /// Set the [`SETTINGS_INITIAL_WINDOW_SIZE`][spec] used for stream-level flow control.
+        ///
+        /// [spec]: https://httpwg.org/specs/rfc9113.html#SETTINGS_INITIAL_WINDOW_SIZE
+        initial_stream_window_size: u32,
+        with_initial_stream_window_size,
+        set_initial_stream_window_size
+    );
+
+    h2_setting!(
+        /// Set the initial window size used for connection-level flow control.
+        initial_connection_window_size: u32,
+        with_initial_connection_window_size,
+        set_initial_connection_window_size
+    );
+
+    h2_setting!(
+        /// Set whether to use an adaptive flow control,
+        /// overriding the configured initial window sizes when enabled.
+        adaptive_window: bool,
+        with_adaptive_window,
+        set_adaptive_window
+    );
+
+    h2_setting!(
+        /// Set the [`SETTINGS_MAX_FRAME_SIZE`][spec] advertised to clients.
+        ///
+        /// [spec]: https://httpwg.org/specs/rfc9113.html#SETTINGS_MAX_FRAME_SIZE
+        max_frame_size: u32,
+        with_max_frame_size,
+        set_max_frame_size
+    );
+
+    h2_setting!(
+        /// Set the maximum size of received header frames.
+        max_header_list_size: u32,
+        with_max_header_list_size,
+        set_max_header_list_size
+    );
+
+    h2_setting!(
+        /// Set the maximum write buffer size for each stream.
+        ///
+        /// The value must be no larger than `u32::MAX`.
+        max_send_buf_size: usize,
+        with_max_send_buf_size,
+        set_max_send_buf_size
+    );
+
+    h2_setting!(
+        /// Set the interval at which ping frames are sent to keep a connection alive,
+        /// using the tokio timer, unless another timer is set afterwards.
+        keep_alive_interval: Duration,
+        with_keep_alive_interval,
+        set_keep_alive_interval
+    );
+
+    h2_setting!(
+        /// Set the timeout for receiving the acknowledgement of a keep-alive ping,
+        /// after which the connection is closed.
+        ///
+        /// Does nothing if no keep-alive interval is set.
+        keep_alive_timeout: Duration,
+        with_keep_alive_timeout,
+        set_keep_alive_timeout
+    );
+}
@@ -0,0 +1,401 @@
+use bytes::Bytes;
+use pin_project_lite::pin_project;
+use rama_http_types::{OriginalHeaderOrder, Request, Uri};
+use std::{
+    collections::VecDeque,
+    fmt, io,
+    pin::Pin,
+    sync::Arc,
+    task::{ready, Context as TaskContext, Poll},
+};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+/// The maximum size of a request head (or chunk line) buffered by the recorder,
+/// beyond which it stops recording for the connection.
+///
+/// Aligned with the default (read) buffer size of the hyper http/1 server.
+const MAX_HEAD_SIZE: usize = 400 * 1024;
+
+/// A request head recorded by [`RecordHeaderOrder`].
+struct RecordedHead {
+    method: Bytes,
+    target: Bytes,
+    order: OriginalHeaderOrder,
+}
+
+#[derive(Clone, Default)]
+/// The request heads recorded for a connection, in the order they were received,
+/// inserted in the connection [`Context`] such that the hyper service can
+/// add the [`OriginalHeaderOrder`] to the requests it serves.
+///
+/// [`Context`]: rama_core::Context
+pub(crate) struct HeaderOrderRecorder(Arc<parking_lot::Mutex<VecDeque<RecordedHead>>>);
+
+impl HeaderOrderRecorder {
+    fn push(&self, head: RecordedHead) {
+        self.0.lock().push_back(head);
+    }
+
+    /// Take the [`OriginalHeaderOrder`] recorded for the given request, if any.
+    ///
+    /// Requests are served by hyper in the order they are received,
+    /// which is the order in which their heads are recorded.
+    pub(crate) fn take<B>(&self, req: &Request<B>) -> Option<OriginalHeaderOrder> {
+        let head = self.0.lock().pop_front()?;
+        let matches = head.method == req.method().as_str().as_bytes()
+            && Uri::try_from(head.target.as_ref()).is_ok_and(|uri| &uri == req.uri());
+        if !matches {
+            tracing::debug!(
+                uri = %req.uri(),
+                "http server: recorded header order does not match request, ignore it",
+            );
+            return None;
+        }
+        Some(head.order)
+    }
+}
+
+impl fmt::Debug for HeaderOrderRecorder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("HeaderOrderRecorder").finish()
+    }
+}
+
+pin_project! {
+    /// Stream which records the header order of the http/1 requests read from it.
+    pub(crate) struct RecordHeaderOrder<IO> {
+        #[pin]
+        inner: IO,
+        parser: HeadParser,
+        recorder: HeaderOrderRecorder,
+    }
+}
+
+impl<IO> RecordHeaderOrder<IO> {
+    pub(crate) fn new(inner: IO, recorder: HeaderOrderRecorder) -> Self {
+        Self {
+            inner,
+            parser: HeadParser::default(),
+            recorder,
+        }
+    }
+}
+
+impl<IO: AsyncRead> AsyncRead for RecordHeaderOrder<IO> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut TaskContext<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.project();
+        let offset = buf.filled().len();
+        ready!(this.inner.poll_read(cx, buf))?;
+        let recorder = this.recorder;
+        this.parser
+            .feed(&buf.filled()[offset..], &mut |head| recorder.push(head));
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<IO: AsyncWrite> AsyncWrite for RecordHeaderOrder<IO> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut TaskContext<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        self.project().inner.poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<io::Result<()>> {
+        self.project().inner.poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<io::Result<()>> {
+        self.project().inner.poll_shutdown(cx)
+    }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut TaskContext<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        self.project().inner.poll_write_vectored(cx, bufs)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.inner.is_write_vectored()
+    }
+}
+
+#[derive(Debug)]
+enum State {
+    /// Reading a request head.
+    Head(Vec<u8>),
+    /// Reading a body with a content length.
+    Body(u64),
+    /// Reading the size line of a chunk.
+    ChunkSize(Vec<u8>),
+    /// Reading the data of a chunk.
+    ChunkData(u64),
+    /// Reading the CRLF which ends the data of a chunk.
+    ChunkDataEnd(u64),
+    /// Reading a trailer line, following the last chunk.
+    Trailer(Vec<u8>),
+    /// Stopped recording, e.g. because the connection got upgraded,
+    /// or it turned out not to be an http/1 connection.
+    Done,
+}
+
+/// Minimal http/1 request parser, which only understands as much of the protocol
+/// as required to find the request heads within the stream of a connection.
+///
+/// It stops recording as soon as it finds something it doesn't expect,
+/// leaving it up to hyper to deal with the (invalid) request.
+#[derive(Debug)]
+struct HeadParser {
+    state: State,
+}
+
+impl Default for HeadParser {
+    fn default() -> Self {
+        Self {
+            state: State::Head(Vec::new()),
+        }
+    }
+}
+
+impl HeadParser {
+    fn feed(&mut self, mut data: &[u8], on_head: &mut impl FnMut(RecordedHead)) {
+        while !data.is_empty() {
+            match &mut self.state {
+                State::Done => return,
+                State::Head(buf) => {
+                    if buf.is_empty() {
+                        // empty lines preceding a request line are to be ignored
+                        let skip = data
+                            .iter()
+                            .take_while(|b| matches!(b, b'\r' | b'\n'))
+                            .count();
+                        data = &data[skip..];
+                        if data.is_empty() {
+                            return;
+                        }
+                    }
+                    let start = buf.len().saturating_sub(3);
+                    let buffered = buf.len();
+                    buf.extend_from_slice(data);
+                    match find(&buf[start..], b"\r\n\r\n") {
+                        Some(pos) => {
+                            let end = start + pos + 4;
+                            data = &data[end - buffered..];
+                            let buf = std::mem::take(buf);
+                            self.state = parse_head(&buf[..end], on_head);
+                        }
+                        None => {
+                            if buf.len() > MAX_HEAD_SIZE {
+                                self.state = State::Done;
+                            }
+                            return;
+                        }
+                    }
+                }
+                State::Body(remaining) | State::ChunkData(remaining) => {
+                    skip(&mut data, remaining);
+                    if *remaining == 0 {
+                        self.state = match self.state {
+                            State::Body(_) => State::Head(Vec::new()),
+                            _ => State::ChunkDataEnd(2),
+                        };
+                    }
+                }
+                State::ChunkDataEnd(remaining) => {
+                    skip(&mut data, remaining);
+                    if *remaining == 0 {
+                        self.state = State::ChunkSize(Vec::new());
+                    }
+                }
+                State::ChunkSize(line) => match read_line(line, &mut data) {
+                    Some(line) => {
+                        self.state = match parse_chunk_size(&line) {
+                            Some(0) => State::Trailer(Vec::new()),
+                            Some(size) => State::ChunkData(size),
+                            None => State::Done,
+                        }
+                    }
+                    None if line.len() > MAX_HEAD_SIZE => self.state = State::Done,
+                    None => (),
+                },
+                State::Trailer(line) => match read_line(line, &mut data) {
+                    Some(line) if line.trim_ascii().is_empty() => {
+                        self.state = State::Head(Vec::new())
+                    }
+                    None if line.len() > MAX_HEAD_SIZE => self.state = State::Done,
+                    _ => (),
+                },
+            }
+        }
+    }
+}
+
+/// Read (the remainder of) a line, returning the line once it is complete.
+fn read_line(line: &mut Vec<u8>, data: &mut &[u8]) -> Option<Vec<u8>> {
+    match data.iter().position(|b| *b == b'\n') {
+        Some(pos) => {
+            line.extend_from_slice(&data[..pos]);
+            *data = &data[pos + 1..];
+            Some(std::mem::take(line))
+        }
+        None => {
+            line.extend_from_slice(data);
+            *data = &[];
+            None
+        }
+    }
+}
+
+/// Skip up to `remaining` bytes of the data.
+fn skip(data: &mut &[u8], remaining: &mut u64) {
+    let n = (*remaining).min(data.len() as u64) as usize;
+    *data = &data[n..];
+    *remaining -= n as u64;
+}
+
+/// Parse a request head, recording its header order,
+/// returning the state to continue with after the head.
+fn parse_head(head: &[u8], on_head: &mut impl FnMut(RecordedHead)) -> State {
+    let mut lines = head.split(|b| *b == b'\n').map(<[u8]>::trim_ascii);
+
+    let mut request_line = lines.next().unwrap_or_default().split(|b| *b == b' ');
+    let (Some(method), Some(target)) = (request_line.next(), request_line.next()) else {
+        return State::Done;
+    };
+    if method == b"PRI" {
+        // http/2 connection preface (prior knowledge)
+        return State::Done;
+    }
+
+    let mut order = OriginalHeaderOrder::new();
+    let mut content_length = None;
+    let mut chunked = None;
+    let mut upgrade = method == b"CONNECT";
+    for line in lines.take_while(|line| !line.is_empty()) {
+        let Some(pos) = line.iter().position(|b| *b == b':') else {
+            return State::Done;
+        };
+        let (name, value) = (&line[..pos], line[pos + 1..].trim_ascii());
+        order.push(Bytes::copy_from_slice(name));
+        if name.eq_ignore_ascii_case(b"content-length") {
+            content_length = std::str::from_utf8(value)
+                .ok()
+                .and_then(|value| value.parse::<u64>().ok());
+        } else if name.eq_ignore_ascii_case(b"transfer-encoding") {
+            // only the last transfer coding matters for the framing
+            chunked = Some(
+                value
+                    .rsplit(|b| *b == b',')
+                    .next()
+                    .is_some_and(|coding| coding.trim_ascii().eq_ignore_ascii_case(b"chunked")),
+            );
+        } else if name.eq_ignore_ascii_case(b"upgrade") {
+            upgrade = true;
+        }
+    }
+
+    on_head(RecordedHead {
+        method: Bytes::copy_from_slice(method),
+        target: Bytes::copy_from_slice(target),
+        order,
+    });
+
+    match (upgrade, chunked, content_length) {
+        // the remainder of the connection might no longer be http/1
+        (true, _, _) => State::Done,
+        (_, Some(true), _) => State::ChunkSize(Vec::new()),
+        // rejected by hyper
+        (_, Some(false), _) => State::Done,
+        (_, None, Some(len)) if len > 0 => State::Body(len),
+        _ => State::Head(Vec::new()),
+    }
+}
+
+fn parse_chunk_size(line: &[u8]) -> Option<u64> {
+    let size = line.split(|b| *b == b';').next()?;
+    let size = std::str::from_utf8(size.trim_ascii()).ok()?;
+    u64::from_str_radix(size, 16).ok()
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(chunks: &[&[u8]]) -> Vec<(String, Vec<String>)> {
+        let mut parser = HeadParser::default();
+        let mut heads = Vec::new();
+        for chunk in chunks {
+            parser.feed(chunk, &mut |head| heads.push(head));
+        }
+        heads
+            .into_iter()
+            .map(|head| {
+                (
+                    String::from_utf8(head.target.to_vec()).unwrap(),
+                    head.order
+                        .iter()
+                        .map(|name| String::from_utf8(name.to_vec()).unwrap())
+                        .collect(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_head_parser_pipelined_requests() {
+        let data: &[u8] =
+            b"POST /a HTTP/1.1\r\nHost: example.com\r\nContent-Length: 24\r\nX-Foo: bar\r\n\r\n\
+            GET /fake HTTP/1.1\r\n\r\n\
+            POST /b HTTP/1.1\r\nTransfer-Encoding: chunked\r\nhost: example.com\r\n\r\n\
+            5;ext=1\r\nGET /\r\n0\r\nTrailer: x\r\n\r\n\
+            \r\nGET /c HTTP/1.1\r\nUser-Agent: rama\r\nAccept: */*\r\nHost: example.com\r\n\r\n";
+        let expected = vec![
+            (
+                "/a".to_owned(),
+                vec!["Host".into(), "Content-Length".into(), "X-Foo".into()],
+            ),
+            (
+                "/b".to_owned(),
+                vec!["Transfer-Encoding".into(), "host".into()],
+            ),
+            (
+                "/c".to_owned(),
+                vec!["User-Agent".into(), "Accept".into(), "Host".into()],
+            ),
+        ];
+
+        assert_eq!(parse(&[data]), expected);
+        // same result regardless of how the data is read
+        let chunks: Vec<_> = data.chunks(1).collect();
+        assert_eq!(parse(&chunks), expected);
+        let chunks: Vec<_> = data.chunks(7).collect();
+        assert_eq!(parse(&chunks), expected);
+    }
+
+    #[test]
+    fn test_head_parser_stops_on_upgrade_and_h2() {
+        assert_eq!(
+            parse(&[
+                b"GET /ws HTTP/1.1\r\nUpgrade: websocket\r\n\r\nGET /x HTTP/1.1\r\n\r\n".as_slice()
+            ]),
+            vec![("/ws".to_owned(), vec!["Upgrade".to_owned()])],
+        );
+        assert!(
+            parse(&[b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\nGET / HTTP/1.1\r\n\r\n".as_slice()])
+                .is_empty()
+        );
+    }
+}
@@ -111,6 +111,7 @@ mod private {
             let stream = TokioIo::new(Box::pin(io));
             let guard = ctx.guard().cloned();
             let service = HyperService::new(ctx, service);
+            let draining = service.draining();
 
             let mut conn = pin!(self.serve_connection(stream, service).with_upgrades());
 
@@ -120,6 +121,7 @@ mod private {
                 select! {
                     _ = cancelled_fut.as_mut() => {
                         tracing::trace!("signal received: initiate graceful shutdown");
+                        draining.start();
                         conn.as_mut().graceful_shutdown();
                     }
                     result = conn.as_mut() => {
@@ -154,6 +156,7 @@ mod private {
             let stream = TokioIo::new(Box::pin(io));
             let guard = ctx.guard().cloned();
             let service = HyperService::new(ctx, service);
+            let draining = service.draining();
 
             let mut conn = pin!(self.serve_connection(stream, service));
 
@@ -163,6 +166,7 @@ mod private {
                 select! {
                     _ = cancelled_fut.as_mut() => {
                         tracing::trace!("signal received: initiate graceful shutdown");
+                        draining.start();
                         conn.as_mut().graceful_shutdown();
                     }
                     result = conn.as_mut() => {
@@ -197,6 +201,7 @@ mod private {
             let stream = TokioIo::new(Box::pin(io));
             let guard = ctx.guard().cloned();
             let service = HyperService::new(ctx, service);
+            let draining = service.draining();
 
             let mut conn = pin!(self.serve_connection_with_upgrades(stream, service));
 
@@ -205,7 +210,8 @@ mod private {
 
                 select! {
                     _ = cancelled_fut.as_mut() => {
-                        tracing::trace!("signal received: nop: graceful shutdown not supported for auto builder");
+                        tracing::trace!("signal received: initiate graceful shutdown");
+                        draining.start();
                         conn.as_mut().graceful_shutdown();
                     }
                     result = conn.as_mut() => {
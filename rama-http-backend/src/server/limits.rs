@@ -0,0 +1,110 @@
+use rama_core::{context::Extensions, error::BoxError};
+use rama_http_types::{Request, Uri, Version};
+use std::{error::Error as _, fmt, sync::Arc};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+/// A limit of the [`HttpServer`] exceeded by a client,
+/// reported to the hook set using [`HttpServer::on_limit_exceeded`].
+///
+/// [`HttpServer`]: super::HttpServer
+/// [`HttpServer::on_limit_exceeded`]: super::HttpServer::on_limit_exceeded
+pub enum LimitExceeded {
+    /// The request headers were not received within the header read timeout,
+    /// after which the connection is closed (e.g. a slowloris client).
+    HeaderReadTimeout,
+    /// The request head exceeded the maximum amount of headers or the (read) buffer size,
+    /// answered with `431 Request Header Fields Too Large`.
+    HeadersTooLarge,
+    /// The request line exceeded the maximum request line length,
+    /// answered with `414 URI Too Long` once the entire request head is received.
+    RequestLineTooLong,
+}
+
+impl fmt::Display for LimitExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::HeaderReadTimeout => f.write_str("header read timeout"),
+            Self::HeadersTooLarge => f.write_str("headers too large"),
+            Self::RequestLineTooLong => f.write_str("request line too long"),
+        }
+    }
+}
+
+type OnLimitExceeded = dyn Fn(LimitExceeded, &Extensions) + Send + Sync + 'static;
+
+#[derive(Clone, Default)]
+/// The limits enforced by the [`HttpServer`] on top of the ones of the hyper builder,
+/// inserted in the connection [`Context`] such that the hyper service can enforce them.
+///
+/// [`HttpServer`]: super::HttpServer
+/// [`Context`]: rama_core::Context
+pub(crate) struct ServerLimits {
+    pub(crate) max_request_line_length: Option<usize>,
+    pub(crate) on_limit_exceeded: Option<Arc<OnLimitExceeded>>,
+}
+
+impl ServerLimits {
+    pub(crate) fn is_empty(&self) -> bool {
+        self.max_request_line_length.is_none() && self.on_limit_exceeded.is_none()
+    }
+
+    /// Returns `true` in case the request line of the (http/1) request is too long.
+    ///
+    /// Only called once hyper parsed the entire request head.
+    pub(crate) fn request_line_too_long<B>(&self, req: &Request<B>) -> bool {
+        match self.max_request_line_length {
+            Some(max) if req.version() <= Version::HTTP_11 => {
+                // method SP request-target SP HTTP-version
+                req.method().as_str().len() + request_target_len(req.uri()) + 10 > max
+            }
+            _ => false,
+        }
+    }
+
+    pub(crate) fn report(&self, limit: LimitExceeded, extensions: &Extensions) {
+        tracing::debug!(%limit, "http server: client exceeded limit");
+        if let Some(hook) = &self.on_limit_exceeded {
+            hook(limit, extensions);
+        }
+    }
+
+    /// Report the limit exceeded by a client which caused the connection to fail, if any.
+    pub(crate) fn report_connection_error(&self, err: &BoxError, extensions: &Extensions) {
+        let Some(err) = err.downcast_ref::<hyper::Error>() else {
+            return;
+        };
+        let limit = if err.is_parse_too_large() {
+            LimitExceeded::HeadersTooLarge
+        } else if err.is_timeout() {
+            LimitExceeded::HeaderReadTimeout
+        } else {
+            return;
+        };
+        tracing::trace!(error = ?err.source(), "http server: connection closed due to limit");
+        self.report(limit, extensions);
+    }
+}
+
+fn request_target_len(uri: &Uri) -> usize {
+    let path_and_query = uri.path_and_query().map(|pq| pq.as_str().len());
+    match (uri.scheme_str(), uri.authority()) {
+        // absolute-form
+        (Some(scheme), Some(authority)) => {
+            scheme.len() + 3 + authority.as_str().len() + path_and_query.unwrap_or_default()
+        }
+        // authority-form
+        (None, Some(authority)) => authority.as_str().len(),
+        // origin-form or asterisk-form
+        _ => path_and_query.unwrap_or(1),
+    }
+}
+
+impl fmt::Debug for ServerLimits {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ServerLimits")
+            .field("max_request_line_length", &self.max_request_line_length)
+            .field("on_limit_exceeded", &self.on_limit_exceeded.is_some())
+            .finish()
+    }
+}
@@ -7,6 +7,16 @@ pub type HttpServeResult = Result<(), rama_core::error::BoxError>;
 pub mod service;
 pub use service::HttpServer;
 
+mod limits;
+pub use limits::LimitExceeded;
+
+mod drain;
+pub use drain::ConnectionTracker;
+
+mod h2_settings;
+pub use h2_settings::H2Settings;
+
+mod header_order;
 mod hyper_conn;
 mod svc_hyper;
 
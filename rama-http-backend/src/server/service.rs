@@ -1,13 +1,18 @@
 //! Rama HTTP server module.
 
+use super::drain::ConnectionTracker;
+use super::header_order::{HeaderOrderRecorder, RecordHeaderOrder};
 use super::hyper_conn::HyperConnServer;
-use super::HttpServeResult;
+use super::limits::{LimitExceeded, ServerLimits};
+use super::{H2Settings, HttpServeResult};
 use crate::executor::HyperExecutor;
 use hyper::server::conn::http2::Builder as H2ConnBuilder;
 use hyper::{rt::Timer, server::conn::http1::Builder as Http1ConnBuilder};
+use hyper_util::rt::TokioTimer;
 use hyper_util::server::conn::auto::Builder as AutoConnBuilder;
 use hyper_util::server::conn::auto::Http1Builder as InnerAutoHttp1Builder;
 use hyper_util::server::conn::auto::Http2Builder as InnerAutoHttp2Builder;
+use rama_core::context::Extensions;
 use rama_core::graceful::ShutdownGuard;
 use rama_core::rt::Executor;
 use rama_core::{Context, Service};
@@ -29,6 +34,21 @@ use tokio::net::ToSocketAddrs;
 pub struct HttpServer<B> {
     builder: B,
     guard: Option<ShutdownGuard>,
+    on_connection: Option<OnConnection>,
+    limits: ServerLimits,
+    preserve_header_order: bool,
+    connection_tracker: Option<ConnectionTracker>,
+}
+
+#[derive(Clone)]
+/// Hook which is called once per accepted connection, prior to serving
+/// any of its requests, see [`HttpServer::on_connection`].
+struct OnConnection(Arc<dyn Fn(&mut Extensions) + Send + Sync + 'static>);
+
+impl fmt::Debug for OnConnection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("OnConnection").finish()
+    }
 }
 
 impl<B> fmt::Debug for HttpServer<B>
@@ -38,6 +58,10 @@ where
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("HttpServer")
             .field("builder", &self.builder)
+            .field("on_connection", &self.on_connection)
+            .field("limits", &self.limits)
+            .field("preserve_header_order", &self.preserve_header_order)
+            .field("connection_tracker", &self.connection_tracker)
             .finish()
     }
 }
@@ -50,6 +74,10 @@ where
         Self {
             builder: self.builder.clone(),
             guard: self.guard.clone(),
+            on_connection: self.on_connection.clone(),
+            limits: self.limits.clone(),
+            preserve_header_order: self.preserve_header_order,
+            connection_tracker: self.connection_tracker.clone(),
         }
     }
 }
@@ -60,6 +88,10 @@ impl HttpServer<Http1ConnBuilder> {
         Self {
             builder: Http1ConnBuilder::new(),
             guard: None,
+            on_connection: None,
+            limits: ServerLimits::default(),
+            preserve_header_order: false,
+            connection_tracker: None,
         }
     }
 
@@ -152,14 +184,34 @@ impl<'a> Http1Config<'a> {
     }
 
     /// Set a timeout for reading client request headers. If a client does not
-    /// transmit the entire header within this time, the connection is closed.
+    /// transmit the entire header within this time, the connection is closed,
+    /// protecting the server against slowloris-style clients.
+    ///
+    /// Hyper requires a timer to enforce this timeout, hence this installs
+    /// a [`TokioTimer`] as the timer used in background tasks, replacing
+    /// any timer set earlier. Use [`Self::timer`] afterwards to use another timer.
     ///
     /// Default is None.
+    ///
+    /// [`TokioTimer`]: hyper_util::rt::TokioTimer
     pub fn header_read_timeout(&mut self, read_timeout: Duration) -> &mut Self {
+        self.inner.timer(TokioTimer::new());
         self.inner.header_read_timeout(read_timeout);
         self
     }
 
+    /// Set the maximum amount of headers of a request.
+    ///
+    /// Requests with more headers are answered with
+    /// `431 Request Header Fields Too Large`, after which the connection is closed.
+    /// The total size of the request head is limited by [`Self::max_buf_size`].
+    ///
+    /// Default is 100.
+    pub fn max_headers(&mut self, max: usize) -> &mut Self {
+        self.inner.max_headers(max);
+        self
+    }
+
     /// Set whether HTTP/1 connections should try to use vectored writes,
     /// or always flatten into a single buffer.
     ///
@@ -216,6 +268,10 @@ impl HttpServer<H2ConnBuilder<HyperExecutor>> {
         Self {
             builder: H2ConnBuilder::new(HyperExecutor(exec)),
             guard,
+            on_connection: None,
+            limits: ServerLimits::default(),
+            preserve_header_order: false,
+            connection_tracker: None,
         }
     }
 }
@@ -227,6 +283,18 @@ impl<E> HttpServer<H2ConnBuilder<E>> {
             inner: &mut self.builder,
         }
     }
+
+    /// Apply the given [`H2Settings`] to the H2 configuration.
+    pub fn with_h2_settings(mut self, settings: H2Settings) -> Self {
+        self.h2_mut().apply(settings);
+        self
+    }
+
+    /// Apply the given [`H2Settings`] to the H2 configuration.
+    pub fn set_h2_settings(&mut self, settings: H2Settings) -> &mut Self {
+        self.h2_mut().apply(settings);
+        self
+    }
 }
 
 /// A configuration builder for H2 server connections.
@@ -295,11 +363,15 @@ impl<'a, E> H2Config<'a, E> {
     ///
     /// Pass `None` to disable HTTP2 keep-alive.
     ///
-    /// Default is currently disabled.
-    ///
-    /// # Cargo Feature
+    /// Enabling it sets the tokio timer as the timer used in background tasks,
+    /// unless another timer is set using [`Self::timer`] afterwards.
     ///
+    /// Default is currently disabled.
     pub fn keep_alive_interval(&mut self, interval: impl Into<Option<Duration>>) -> &mut Self {
+        let interval = interval.into();
+        if interval.is_some() {
+            self.inner.timer(TokioTimer::new());
+        }
         self.inner.keep_alive_interval(interval);
         self
     }
@@ -310,9 +382,6 @@ impl<'a, E> H2Config<'a, E> {
     /// be closed. Does nothing if `http2_keep_alive_interval` is disabled.
     ///
     /// Default is 20 seconds.
-    ///
-    /// # Cargo Feature
-    ///
     pub fn keep_alive_timeout(&mut self, timeout: Duration) -> &mut Self {
         self.inner.keep_alive_timeout(timeout);
         self
@@ -346,6 +415,38 @@ impl<'a, E> H2Config<'a, E> {
         self
     }
 
+    /// Apply the given [`H2Settings`], leaving the settings which are not set untouched.
+    pub fn apply(&mut self, settings: H2Settings) -> &mut Self {
+        if let Some(max) = settings.max_concurrent_streams() {
+            self.max_concurrent_streams(max);
+        }
+        if let Some(sz) = settings.initial_stream_window_size() {
+            self.initial_stream_window_size(sz);
+        }
+        if let Some(sz) = settings.initial_connection_window_size() {
+            self.initial_connection_window_size(sz);
+        }
+        if let Some(enabled) = settings.adaptive_window() {
+            self.adaptive_window(enabled);
+        }
+        if let Some(sz) = settings.max_frame_size() {
+            self.max_frame_size(sz);
+        }
+        if let Some(max) = settings.max_header_list_size() {
+            self.max_header_list_size(max);
+        }
+        if let Some(max) = settings.max_send_buf_size() {
+            self.max_send_buf_size(max);
+        }
+        if let Some(interval) = settings.keep_alive_interval() {
+            self.keep_alive_interval(interval);
+        }
+        if let Some(timeout) = settings.keep_alive_timeout() {
+            self.keep_alive_timeout(timeout);
+        }
+        self
+    }
+
     /// Set the timer used in background tasks.
     pub fn timer<M>(&mut self, timer: M) -> &mut Self
     where
@@ -363,6 +464,10 @@ impl HttpServer<AutoConnBuilder<HyperExecutor>> {
         Self {
             builder: AutoConnBuilder::new(HyperExecutor(exec)),
             guard,
+            on_connection: None,
+            limits: ServerLimits::default(),
+            preserve_header_order: false,
+            connection_tracker: None,
         }
     }
 }
@@ -381,6 +486,18 @@ impl<E> HttpServer<AutoConnBuilder<E>> {
             inner: self.builder.http2(),
         }
     }
+
+    /// Apply the given [`H2Settings`] to the H2 configuration.
+    pub fn with_h2_settings(mut self, settings: H2Settings) -> Self {
+        self.h2_mut().apply(settings);
+        self
+    }
+
+    /// Apply the given [`H2Settings`] to the H2 configuration.
+    pub fn set_h2_settings(&mut self, settings: H2Settings) -> &mut Self {
+        self.h2_mut().apply(settings);
+        self
+    }
 }
 
 /// A configuration builder for HTTP/1 server connections in auto mode.
@@ -446,14 +563,34 @@ impl<'a, E> AutoHttp1Config<'a, E> {
     }
 
     /// Set a timeout for reading client request headers. If a client does not
-    /// transmit the entire header within this time, the connection is closed.
+    /// transmit the entire header within this time, the connection is closed,
+    /// protecting the server against slowloris-style clients.
+    ///
+    /// Hyper requires a timer to enforce this timeout, hence this installs
+    /// a [`TokioTimer`] as the timer used in background tasks, replacing
+    /// any timer set earlier. Use [`Self::timer`] afterwards to use another timer.
     ///
     /// Default is None.
+    ///
+    /// [`TokioTimer`]: hyper_util::rt::TokioTimer
     pub fn header_read_timeout(&mut self, read_timeout: Duration) -> &mut Self {
+        self.inner.timer(TokioTimer::new());
         self.inner.header_read_timeout(read_timeout);
         self
     }
 
+    /// Set the maximum amount of headers of a request.
+    ///
+    /// Requests with more headers are answered with
+    /// `431 Request Header Fields Too Large`, after which the connection is closed.
+    /// The total size of the request head is limited by [`Self::max_buf_size`].
+    ///
+    /// Default is 100.
+    pub fn max_headers(&mut self, max: usize) -> &mut Self {
+        self.inner.max_headers(max);
+        self
+    }
+
     /// Set whether HTTP/1 connections should try to use vectored writes,
     /// or always flatten into a single buffer.
     ///
@@ -574,11 +711,15 @@ impl<'a, E> AutoH2Config<'a, E> {
     ///
     /// Pass `None` to disable HTTP2 keep-alive.
     ///
-    /// Default is currently disabled.
-    ///
-    /// # Cargo Feature
+    /// Enabling it sets the tokio timer as the timer used in background tasks,
+    /// unless another timer is set using [`Self::timer`] afterwards.
     ///
+    /// Default is currently disabled.
     pub fn keep_alive_interval(&mut self, interval: impl Into<Option<Duration>>) -> &mut Self {
+        let interval = interval.into();
+        if interval.is_some() {
+            self.inner.timer(TokioTimer::new());
+        }
         self.inner.keep_alive_interval(interval);
         self
     }
@@ -589,9 +730,6 @@ impl<'a, E> AutoH2Config<'a, E> {
     /// be closed. Does nothing if `http2_keep_alive_interval` is disabled.
     ///
     /// Default is 20 seconds.
-    ///
-    /// # Cargo Feature
-    ///
     pub fn keep_alive_timeout(&mut self, timeout: Duration) -> &mut Self {
         self.inner.keep_alive_timeout(timeout);
         self
@@ -625,6 +763,38 @@ impl<'a, E> AutoH2Config<'a, E> {
         self
     }
 
+    /// Apply the given [`H2Settings`], leaving the settings which are not set untouched.
+    pub fn apply(&mut self, settings: H2Settings) -> &mut Self {
+        if let Some(max) = settings.max_concurrent_streams() {
+            self.max_concurrent_streams(max);
+        }
+        if let Some(sz) = settings.initial_stream_window_size() {
+            self.initial_stream_window_size(sz);
+        }
+        if let Some(sz) = settings.initial_connection_window_size() {
+            self.initial_connection_window_size(sz);
+        }
+        if let Some(enabled) = settings.adaptive_window() {
+            self.adaptive_window(enabled);
+        }
+        if let Some(sz) = settings.max_frame_size() {
+            self.max_frame_size(sz);
+        }
+        if let Some(max) = settings.max_header_list_size() {
+            self.max_header_list_size(max);
+        }
+        if let Some(max) = settings.max_send_buf_size() {
+            self.max_send_buf_size(max);
+        }
+        if let Some(interval) = settings.keep_alive_interval() {
+            self.keep_alive_interval(interval);
+        }
+        if let Some(timeout) = settings.keep_alive_timeout() {
+            self.keep_alive_timeout(timeout);
+        }
+        self
+    }
+
     /// Set the timer used in background tasks.
     pub fn timer<M>(&mut self, timer: M) -> &mut Self
     where
@@ -635,6 +805,141 @@ impl<'a, E> AutoH2Config<'a, E> {
     }
 }
 
+impl<B> HttpServer<B> {
+    /// Set a hook which is called once per accepted connection,
+    /// prior to serving any of its requests.
+    ///
+    /// The hook gets mutable access to the [`Extensions`] of the connection's [`Context`],
+    /// which already contain the transport information (e.g. the [`SocketInfo`]
+    /// of a tcp connection). Anything inserted by the hook is available
+    /// in the [`Context`] of all requests served on that connection.
+    ///
+    /// [`SocketInfo`]: rama_net::stream::SocketInfo
+    pub fn on_connection<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(&mut Extensions) + Send + Sync + 'static,
+    {
+        self.on_connection = Some(OnConnection(Arc::new(hook)));
+        self
+    }
+
+    /// Set a hook which is called once per accepted connection,
+    /// prior to serving any of its requests.
+    ///
+    /// See [`Self::on_connection`] for more information.
+    pub fn set_on_connection<F>(&mut self, hook: F) -> &mut Self
+    where
+        F: Fn(&mut Extensions) + Send + Sync + 'static,
+    {
+        self.on_connection = Some(OnConnection(Arc::new(hook)));
+        self
+    }
+
+    /// Set the maximum length of the request line (method, request target and version)
+    /// of http/1 requests.
+    ///
+    /// Requests with a longer request line are answered with `414 URI Too Long`,
+    /// prior to being served by the service (and thus prior to reading their body).
+    ///
+    /// The limit is checked once the entire request head is received and parsed,
+    /// and thus does not limit the size of the head buffered by the server,
+    /// nor the time spent receiving it. Use the `max_buf_size` and `header_read_timeout`
+    /// of the http/1 configuration to protect against large or slow (e.g. slowloris) request heads.
+    ///
+    /// No limit by default, other than the one implied by the `max_buf_size`
+    /// of the http/1 configuration.
+    pub fn with_max_request_line_length(mut self, max: usize) -> Self {
+        self.limits.max_request_line_length = Some(max);
+        self
+    }
+
+    /// Set the maximum length of the request line (method, request target and version)
+    /// of http/1 requests.
+    ///
+    /// See [`Self::with_max_request_line_length`] for more information.
+    pub fn set_max_request_line_length(&mut self, max: usize) -> &mut Self {
+        self.limits.max_request_line_length = Some(max);
+        self
+    }
+
+    /// Set a hook which is called each time a client exceeds one of the limits
+    /// of the server, e.g. to record it as a metric or to ban the client.
+    ///
+    /// The hook gets access to the [`Extensions`] of the connection's [`Context`],
+    /// which contain the transport information (e.g. the [`SocketInfo`]).
+    /// Exceeded limits are logged at the debug level regardless of this hook.
+    ///
+    /// [`SocketInfo`]: rama_net::stream::SocketInfo
+    pub fn on_limit_exceeded<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(LimitExceeded, &Extensions) + Send + Sync + 'static,
+    {
+        self.limits.on_limit_exceeded = Some(Arc::new(hook));
+        self
+    }
+
+    /// Set a hook which is called each time a client exceeds one of the limits
+    /// of the server.
+    ///
+    /// See [`Self::on_limit_exceeded`] for more information.
+    pub fn set_on_limit_exceeded<F>(&mut self, hook: F) -> &mut Self
+    where
+        F: Fn(LimitExceeded, &Extensions) + Send + Sync + 'static,
+    {
+        self.limits.on_limit_exceeded = Some(Arc::new(hook));
+        self
+    }
+
+    /// Record the order (and casing) in which the headers of http/1 requests
+    /// are received, inserting it as an [`OriginalHeaderOrder`] in the request extensions.
+    ///
+    /// The [`HttpClient`] sends the headers of http/1 requests with this extension
+    /// in the recorded order, such that a proxy can forward the headers
+    /// in the exact order they were sent by its client.
+    ///
+    /// Disabled by default. Recording has no effect on h2 connections.
+    ///
+    /// [`OriginalHeaderOrder`]: rama_http_types::OriginalHeaderOrder
+    /// [`HttpClient`]: crate::client::HttpClient
+    pub fn preserve_header_order(mut self, enabled: bool) -> Self {
+        self.preserve_header_order = enabled;
+        self
+    }
+
+    /// Record the order (and casing) in which the headers of http/1 requests
+    /// are received.
+    ///
+    /// See [`Self::preserve_header_order`] for more information.
+    pub fn set_preserve_header_order(&mut self, enabled: bool) -> &mut Self {
+        self.preserve_header_order = enabled;
+        self
+    }
+
+    /// Track the connections served by this [`HttpServer`]
+    /// using the given [`ConnectionTracker`].
+    ///
+    /// Combined with a graceful shutdown, the tracker reflects how many connections
+    /// are still draining: once the shutdown is initiated no new connections are accepted,
+    /// http/1 responses get a `Connection: close` header and h2 connections receive a `GOAWAY`.
+    /// Tracked connections keep the graceful shutdown pending, such that
+    /// [`Shutdown::shutdown_with_limit`] waits (up to its limit) for them to be drained.
+    ///
+    /// [`Shutdown::shutdown_with_limit`]: rama_core::graceful::Shutdown::shutdown_with_limit
+    pub fn connection_tracker(mut self, tracker: ConnectionTracker) -> Self {
+        self.connection_tracker = Some(tracker);
+        self
+    }
+
+    /// Track the connections served by this [`HttpServer`]
+    /// using the given [`ConnectionTracker`].
+    ///
+    /// See [`Self::connection_tracker`] for more information.
+    pub fn set_connection_tracker(&mut self, tracker: ConnectionTracker) -> &mut Self {
+        self.connection_tracker = Some(tracker);
+        self
+    }
+}
+
 impl<B> HttpServer<B>
 where
     B: HyperConnServer,
@@ -642,13 +947,20 @@ where
     /// Turn this `HttpServer` into a [`Service`] that can be used to serve
     /// IO Byte streams (e.g. a TCP Stream) as HTTP.
     pub fn service<S>(self, service: S) -> HttpService<B, S> {
-        HttpService::new(self.builder, service)
+        HttpService::new(
+            self.builder,
+            service,
+            self.on_connection,
+            self.limits,
+            self.preserve_header_order,
+            self.connection_tracker,
+        )
     }
 
     /// Serve a single IO Byte Stream (e.g. a TCP Stream) as HTTP.
     pub async fn serve<State, S, Response, IO>(
         &self,
-        ctx: Context<State>,
+        mut ctx: Context<State>,
         stream: IO,
         service: S,
     ) -> HttpServeResult
@@ -658,9 +970,19 @@ where
         Response: IntoResponse + Send + 'static,
         IO: Stream,
     {
-        self.builder
-            .hyper_serve_connection(ctx, stream, service)
-            .await
+        if let Some(on_connection) = &self.on_connection {
+            (on_connection.0)(ctx.extensions_mut());
+        }
+        serve_connection(
+            &self.builder,
+            &self.limits,
+            self.preserve_header_order,
+            self.connection_tracker.as_ref(),
+            ctx,
+            stream,
+            service,
+        )
+        .await
     }
 
     /// Listen for connections on the given address, serving HTTP connections.
@@ -673,7 +995,14 @@ where
         A: ToSocketAddrs,
     {
         let tcp = TcpListener::bind(addr).await?;
-        let service = HttpService::new(self.builder, service);
+        let service = HttpService::new(
+            self.builder,
+            service,
+            self.on_connection,
+            self.limits,
+            self.preserve_header_order,
+            self.connection_tracker,
+        );
         match self.guard {
             Some(guard) => tcp.serve_graceful(guard, service).await,
             None => tcp.serve(service).await,
@@ -700,7 +1029,14 @@ where
         A: ToSocketAddrs,
     {
         let tcp = TcpListener::build_with_state(state).bind(addr).await?;
-        let service = HttpService::new(self.builder, service);
+        let service = HttpService::new(
+            self.builder,
+            service,
+            self.on_connection,
+            self.limits,
+            self.preserve_header_order,
+            self.connection_tracker,
+        );
         match self.guard {
             Some(guard) => tcp.serve_graceful(guard, service).await,
             None => tcp.serve(service).await,
@@ -713,6 +1049,10 @@ where
 pub struct HttpService<B, S> {
     builder: Arc<B>,
     service: Arc<S>,
+    on_connection: Option<OnConnection>,
+    limits: ServerLimits,
+    preserve_header_order: bool,
+    connection_tracker: Option<ConnectionTracker>,
 }
 
 impl<B, S> std::fmt::Debug for HttpService<B, S>
@@ -724,15 +1064,30 @@ where
         f.debug_struct("HttpService")
             .field("builder", &self.builder)
             .field("service", &self.service)
+            .field("on_connection", &self.on_connection)
+            .field("limits", &self.limits)
+            .field("preserve_header_order", &self.preserve_header_order)
+            .field("connection_tracker", &self.connection_tracker)
             .finish()
     }
 }
 
 impl<B, S> HttpService<B, S> {
-    fn new(builder: B, service: S) -> Self {
+    fn new(
+        builder: B,
+        service: S,
+        on_connection: Option<OnConnection>,
+        limits: ServerLimits,
+        preserve_header_order: bool,
+        connection_tracker: Option<ConnectionTracker>,
+    ) -> Self {
         Self {
             builder: Arc::new(builder),
             service: Arc::new(service),
+            on_connection,
+            limits,
+            preserve_header_order,
+            connection_tracker,
         }
     }
 }
@@ -742,6 +1097,10 @@ impl<B, S> Clone for HttpService<B, S> {
         Self {
             builder: self.builder.clone(),
             service: self.service.clone(),
+            on_connection: self.on_connection.clone(),
+            limits: self.limits.clone(),
+            preserve_header_order: self.preserve_header_order,
+            connection_tracker: self.connection_tracker.clone(),
         }
     }
 }
@@ -759,10 +1118,319 @@ where
 
     fn serve(
         &self,
-        ctx: Context<State>,
+        mut ctx: Context<State>,
         stream: IO,
     ) -> impl Future<Output = Result<Self::Response, Self::Error>> + Send + '_ {
+        if let Some(on_connection) = &self.on_connection {
+            (on_connection.0)(ctx.extensions_mut());
+        }
         let service = self.service.clone();
-        self.builder.hyper_serve_connection(ctx, stream, service)
+        serve_connection(
+            &*self.builder,
+            &self.limits,
+            self.preserve_header_order,
+            self.connection_tracker.as_ref(),
+            ctx,
+            stream,
+            service,
+        )
+    }
+}
+
+async fn serve_connection<B, State, S, Response, IO>(
+    builder: &B,
+    limits: &ServerLimits,
+    preserve_header_order: bool,
+    connection_tracker: Option<&ConnectionTracker>,
+    mut ctx: Context<State>,
+    stream: IO,
+    service: S,
+) -> HttpServeResult
+where
+    B: HyperConnServer,
+    State: Clone + Send + Sync + 'static,
+    S: Service<State, Request, Response = Response, Error = Infallible>,
+    Response: IntoResponse + Send + 'static,
+    IO: Stream,
+{
+    let _connection = connection_tracker.map(|tracker| tracker.track(ctx.guard()));
+
+    if !preserve_header_order {
+        return serve_connection_with_limits(builder, limits, ctx, stream, service).await;
+    }
+
+    let recorder = HeaderOrderRecorder::default();
+    ctx.insert(recorder.clone());
+    let stream = RecordHeaderOrder::new(stream, recorder);
+    serve_connection_with_limits(builder, limits, ctx, stream, service).await
+}
+
+async fn serve_connection_with_limits<B, State, S, Response, IO>(
+    builder: &B,
+    limits: &ServerLimits,
+    mut ctx: Context<State>,
+    stream: IO,
+    service: S,
+) -> HttpServeResult
+where
+    B: HyperConnServer,
+    State: Clone + Send + Sync + 'static,
+    S: Service<State, Request, Response = Response, Error = Infallible>,
+    Response: IntoResponse + Send + 'static,
+    IO: Stream,
+{
+    if limits.is_empty() {
+        return builder.hyper_serve_connection(ctx, stream, service).await;
+    }
+
+    ctx.insert(limits.clone());
+    let extensions = limits
+        .on_limit_exceeded
+        .as_ref()
+        .map(|_| ctx.extensions().clone());
+
+    let result = builder.hyper_serve_connection(ctx, stream, service).await;
+    if let Err(err) = &result {
+        limits.report_connection_error(err, extensions.as_ref().unwrap_or(&Extensions::new()));
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper_util::rt::TokioIo;
+    use rama_core::service::service_fn;
+    use rama_http_types::{Body, BodyExtractExt, Response};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[derive(Debug, Clone)]
+    struct ConnectionId(usize);
+
+    #[tokio::test]
+    async fn test_on_connection() {
+        let connections = Arc::new(AtomicUsize::new(0));
+        let server = HttpServer::http1().on_connection({
+            let connections = connections.clone();
+            move |extensions| {
+                let id = connections.fetch_add(1, Ordering::SeqCst);
+                extensions.insert(ConnectionId(id));
+            }
+        });
+        let service = server.service(service_fn(|ctx: Context<()>, _req: Request| async move {
+            let id = ctx.get::<ConnectionId>().unwrap().0;
+            Ok::<_, Infallible>(Response::new(Body::from(id.to_string())))
+        }));
+
+        for expected_id in 0..2 {
+            let (client_io, server_io) = tokio::io::duplex(1024);
+            tokio::spawn({
+                let service = service.clone();
+                async move { service.serve(Context::default(), server_io).await }
+            });
+
+            let (mut sender, conn) = hyper::client::conn::http1::handshake(TokioIo::new(client_io))
+                .await
+                .unwrap();
+            tokio::spawn(conn);
+
+            // all requests of a connection share the same connection state
+            for _ in 0..2 {
+                let resp = sender
+                    .send_request(Request::new(Body::empty()))
+                    .await
+                    .unwrap();
+                let body = Body::new(resp.into_body()).try_into_string().await.unwrap();
+                assert_eq!(body, expected_id.to_string());
+            }
+        }
+        assert_eq!(connections.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_request_line_limit() {
+        let exceeded = Arc::new(AtomicUsize::new(0));
+        let server = HttpServer::http1()
+            .with_max_request_line_length(64)
+            .on_limit_exceeded({
+                let exceeded = exceeded.clone();
+                move |limit, _extensions| {
+                    assert_eq!(limit, LimitExceeded::RequestLineTooLong);
+                    exceeded.fetch_add(1, Ordering::SeqCst);
+                }
+            });
+        let service = server.service(service_fn(|_ctx: Context<()>, _req: Request| async move {
+            Ok::<_, Infallible>(Response::new(Body::empty()))
+        }));
+
+        let (client_io, server_io) = tokio::io::duplex(4096);
+        tokio::spawn(async move { service.serve(Context::default(), server_io).await });
+
+        let (mut sender, conn) = hyper::client::conn::http1::handshake(TokioIo::new(client_io))
+            .await
+            .unwrap();
+        tokio::spawn(conn);
+
+        let resp = sender
+            .send_request(
+                Request::builder()
+                    .uri("/short")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), rama_http_types::StatusCode::OK);
+
+        let resp = sender
+            .send_request(
+                Request::builder()
+                    .uri(format!("/{}", "a".repeat(64)))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), rama_http_types::StatusCode::URI_TOO_LONG);
+        assert_eq!(exceeded.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_graceful_drain() {
+        use rama_core::graceful::Shutdown;
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let (signal_tx, signal_rx) = tokio::sync::oneshot::channel::<()>();
+        let shutdown = Shutdown::new(async move {
+            let _ = signal_rx.await;
+        });
+
+        let (entered_tx, entered_rx) = tokio::sync::oneshot::channel::<()>();
+        let entered_tx = Arc::new(parking_lot::Mutex::new(Some(entered_tx)));
+        let release = Arc::new(tokio::sync::Notify::new());
+
+        let tracker = ConnectionTracker::new();
+        let server = HttpServer::http1().connection_tracker(tracker.clone());
+        let service = server.service(service_fn({
+            let release = release.clone();
+            move |_ctx: Context<()>, _req: Request| {
+                let entered_tx = entered_tx.lock().take();
+                let release = release.clone();
+                async move {
+                    if let Some(entered_tx) = entered_tx {
+                        let _ = entered_tx.send(());
+                    }
+                    release.notified().await;
+                    Ok::<_, Infallible>(Response::new(Body::from("ok")))
+                }
+            }
+        }));
+
+        let (mut client_io, server_io) = tokio::io::duplex(4096);
+        let ctx = Context::new((), Executor::graceful(shutdown.guard()));
+        tokio::spawn(async move { service.serve(ctx, server_io).await });
+
+        client_io
+            .write_all(b"GET / HTTP/1.1\r\nHost: example.com\r\n\r\n")
+            .await
+            .unwrap();
+        entered_rx.await.unwrap();
+        assert_eq!(tracker.active(), 1);
+
+        signal_tx.send(()).unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        release.notify_one();
+
+        let mut output = String::new();
+        client_io.read_to_string(&mut output).await.unwrap();
+        assert!(output.starts_with("HTTP/1.1 200 OK\r\n"), "{output}");
+        assert!(output.contains("connection: close\r\n"), "{output}");
+        assert!(output.ends_with("\r\n\r\nok"), "{output}");
+
+        shutdown
+            .shutdown_with_limit(Duration::from_secs(1))
+            .await
+            .unwrap();
+        assert_eq!(tracker.active(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_preserve_header_order() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let server = HttpServer::http1().preserve_header_order(true);
+        let service = server.service(service_fn(|_ctx: Context<()>, req: Request| async move {
+            let order = req
+                .extensions()
+                .get::<rama_http_types::OriginalHeaderOrder>()
+                .map(|order| {
+                    order
+                        .iter()
+                        .map(String::from_utf8_lossy)
+                        .collect::<Vec<_>>()
+                        .join(",")
+                })
+                .unwrap_or_default();
+            Ok::<_, Infallible>(Response::new(Body::from(order)))
+        }));
+
+        let (mut client_io, server_io) = tokio::io::duplex(4096);
+        tokio::spawn(async move { service.serve(Context::default(), server_io).await });
+
+        client_io
+            .write_all(
+                b"POST /a HTTP/1.1\r\nX-Foo: 1\r\nHost: example.com\r\nContent-Length: 4\r\n\r\ndata\
+                GET /b HTTP/1.1\r\nuser-agent: rama\r\nHOST: example.com\r\nConnection: close\r\n\r\n",
+            )
+            .await
+            .unwrap();
+        let mut output = String::new();
+        client_io.read_to_string(&mut output).await.unwrap();
+        assert!(
+            output.contains("\r\n\r\nX-Foo,Host,Content-Length"),
+            "{output}"
+        );
+        assert!(
+            output.ends_with("\r\n\r\nuser-agent,HOST,Connection"),
+            "{output}"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_h2_settings() {
+        let server = HttpServer::h2(Executor::default()).with_h2_settings(
+            H2Settings::new()
+                .with_max_concurrent_streams(8)
+                .with_initial_stream_window_size(128 * 1024)
+                .with_max_frame_size(32 * 1024)
+                .with_keep_alive_interval(Duration::from_secs(30))
+                .with_keep_alive_timeout(Duration::from_secs(5)),
+        );
+        let service = server.service(service_fn(|_ctx: Context<()>, _req: Request| async move {
+            Ok::<_, Infallible>(Response::new(Body::from("ok")))
+        }));
+
+        let (client_io, server_io) = tokio::io::duplex(64 * 1024);
+        tokio::spawn(async move { service.serve(Context::default(), server_io).await });
+
+        let (mut sender, conn) = hyper::client::conn::http2::handshake(
+            hyper_util::rt::TokioExecutor::new(),
+            TokioIo::new(client_io),
+        )
+        .await
+        .unwrap();
+        tokio::spawn(conn);
+
+        let resp = sender
+            .send_request(
+                Request::builder()
+                    .uri("http://example.com/")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let body = Body::new(resp.into_body()).try_into_string().await.unwrap();
+        assert_eq!(body, "ok");
     }
 }
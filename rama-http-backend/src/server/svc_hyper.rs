@@ -1,5 +1,8 @@
+use super::drain::Draining;
+use super::header_order::HeaderOrderRecorder;
+use super::limits::{LimitExceeded, ServerLimits};
 use rama_core::{Context, Service};
-use rama_http_types::{BodyLimit, IntoResponse, Request};
+use rama_http_types::{header, BodyLimit, HeaderValue, IntoResponse, Request, StatusCode, Version};
 use std::{convert::Infallible, fmt, future::Future, pin::Pin, sync::Arc};
 
 /// Wrapper service that implements [`hyper::service::Service`].
@@ -12,6 +15,7 @@ use std::{convert::Infallible, fmt, future::Future, pin::Pin, sync::Arc};
 pub(crate) struct HyperService<S, T> {
     ctx: Context<S>,
     inner: Arc<T>,
+    draining: Draining,
 }
 
 impl<S, T> HyperService<S, T> {
@@ -20,8 +24,14 @@ impl<S, T> HyperService<S, T> {
         Self {
             ctx,
             inner: Arc::new(inner),
+            draining: Draining::default(),
         }
     }
+
+    /// The flag to set once the connection served by this service starts draining.
+    pub(crate) fn draining(&self) -> Draining {
+        self.draining.clone()
+    }
 }
 
 impl<S, T, Response> hyper::service::Service<HyperRequest> for HyperService<S, T>
@@ -39,7 +49,26 @@ where
         let ctx = self.ctx.clone();
         let inner = self.inner.clone();
 
+        if let Some(limits) = ctx.get::<ServerLimits>() {
+            if limits.request_line_too_long(&req) {
+                limits.report(LimitExceeded::RequestLineTooLong, ctx.extensions());
+                return Box::pin(std::future::ready(Ok(
+                    StatusCode::URI_TOO_LONG.into_response()
+                )));
+            }
+        }
+
+        let mut req = req;
+        if let Some(order) = ctx
+            .get::<HeaderOrderRecorder>()
+            .and_then(|recorder| recorder.take(&req))
+        {
+            req.extensions_mut().insert(order);
+        }
+
         let body_limit = ctx.get::<BodyLimit>().cloned();
+        let version = req.version();
+        let draining = self.draining.clone();
 
         let req = match body_limit.and_then(|limit| limit.request()) {
             Some(limit) => req.map(|body| rama_http_types::Body::with_limit(body, limit)),
@@ -47,7 +76,17 @@ where
         };
 
         Box::pin(async move {
-            let resp = inner.serve(ctx, req).await.into_response();
+            let mut resp = inner.serve(ctx, req).await.into_response();
+            if draining.is_draining()
+                && matches!(version, Version::HTTP_10 | Version::HTTP_11)
+                && !resp.status().is_informational()
+            {
+                // let the client know it has to open a new connection for its next request,
+                // informational responses (e.g. `101 Switching Protocols`) are left untouched
+                // as the connection is (to be) upgraded
+                resp.headers_mut()
+                    .insert(header::CONNECTION, HeaderValue::from_static("close"));
+            }
             Ok(match body_limit.and_then(|limit| limit.response()) {
                 Some(limit) => resp.map(|body| rama_http_types::Body::with_limit(body, limit)),
                 // If there is no limit, we can just return the response as is.
@@ -66,6 +105,7 @@ where
         f.debug_struct("HyperService")
             .field("ctx", &self.ctx)
             .field("inner", &self.inner)
+            .field("draining", &self.draining)
             .finish()
     }
 }
@@ -79,6 +119,7 @@ where
         Self {
             ctx: self.ctx.clone(),
             inner: self.inner.clone(),
+            draining: self.draining.clone(),
         }
     }
 }
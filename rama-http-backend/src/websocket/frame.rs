@@ -0,0 +1,263 @@
+//! WebSocket frame encoding and decoding,
+//! as defined in [RFC 6455 section 5](https://www.rfc-editor.org/rfc/rfc6455#section-5).
+
+use super::Role;
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum OpCode {
+    Continuation,
+    Text,
+    Binary,
+    Close,
+    Ping,
+    Pong,
+}
+
+impl OpCode {
+    fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0x0 => Some(Self::Continuation),
+            0x1 => Some(Self::Text),
+            0x2 => Some(Self::Binary),
+            0x8 => Some(Self::Close),
+            0x9 => Some(Self::Ping),
+            0xA => Some(Self::Pong),
+            _ => None,
+        }
+    }
+
+    fn as_u8(self) -> u8 {
+        match self {
+            Self::Continuation => 0x0,
+            Self::Text => 0x1,
+            Self::Binary => 0x2,
+            Self::Close => 0x8,
+            Self::Ping => 0x9,
+            Self::Pong => 0xA,
+        }
+    }
+
+    pub(super) fn is_control(self) -> bool {
+        matches!(self, Self::Close | Self::Ping | Self::Pong)
+    }
+}
+
+#[derive(Debug)]
+pub(super) struct Frame {
+    pub(super) fin: bool,
+    pub(super) opcode: OpCode,
+    pub(super) payload: Bytes,
+}
+
+/// Error returned in case a received frame is invalid.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum DecodeError {
+    /// The frame violates the websocket protocol.
+    Protocol(&'static str),
+    /// The frame exceeds the maximum message size.
+    TooLarge,
+}
+
+/// Try to decode a single frame from the start of the buffer,
+/// returning `None` in case the buffer does not contain a complete frame yet.
+pub(super) fn decode(
+    buf: &mut BytesMut,
+    role: Role,
+    max_size: usize,
+) -> Result<Option<Frame>, DecodeError> {
+    if buf.len() < 2 {
+        return Ok(None);
+    }
+    let (b0, b1) = (buf[0], buf[1]);
+
+    if b0 & 0x70 != 0 {
+        return Err(DecodeError::Protocol("reserved bits set"));
+    }
+    let fin = b0 & 0x80 != 0;
+    let opcode = OpCode::from_u8(b0 & 0x0F).ok_or(DecodeError::Protocol("unknown opcode"))?;
+
+    let masked = b1 & 0x80 != 0;
+    match role {
+        Role::Server if !masked => return Err(DecodeError::Protocol("client frame is not masked")),
+        Role::Client if masked => return Err(DecodeError::Protocol("server frame is masked")),
+        _ => (),
+    }
+
+    let (len, mut offset) = match b1 & 0x7F {
+        126 => {
+            if buf.len() < 4 {
+                return Ok(None);
+            }
+            (u64::from(u16::from_be_bytes([buf[2], buf[3]])), 4)
+        }
+        127 => {
+            if buf.len() < 10 {
+                return Ok(None);
+            }
+            let mut len = [0; 8];
+            len.copy_from_slice(&buf[2..10]);
+            (u64::from_be_bytes(len), 10)
+        }
+        len => (u64::from(len), 2),
+    };
+
+    if opcode.is_control() && (!fin || len > 125) {
+        return Err(DecodeError::Protocol("invalid control frame"));
+    }
+    if len > max_size as u64 {
+        return Err(DecodeError::TooLarge);
+    }
+    let len = len as usize;
+
+    let mask = if masked {
+        if buf.len() < offset + 4 {
+            return Ok(None);
+        }
+        let mut mask = [0; 4];
+        mask.copy_from_slice(&buf[offset..offset + 4]);
+        offset += 4;
+        Some(mask)
+    } else {
+        None
+    };
+
+    if buf.len() < offset + len {
+        buf.reserve(offset + len - buf.len());
+        return Ok(None);
+    }
+
+    buf.advance(offset);
+    let mut payload = buf.split_to(len);
+    if let Some(mask) = mask {
+        apply_mask(&mut payload, mask);
+    }
+
+    Ok(Some(Frame {
+        fin,
+        opcode,
+        payload: payload.freeze(),
+    }))
+}
+
+/// Encode a single (final) frame, masking it in case it is sent by a client.
+pub(super) fn encode(buf: &mut BytesMut, opcode: OpCode, payload: &[u8], role: Role) {
+    buf.put_u8(0x80 | opcode.as_u8());
+
+    let mask_bit = match role {
+        Role::Client => 0x80,
+        Role::Server => 0,
+    };
+    match payload.len() {
+        len @ 0..=125 => buf.put_u8(mask_bit | len as u8),
+        len @ 126..=0xFFFF => {
+            buf.put_u8(mask_bit | 126);
+            buf.put_u16(len as u16);
+        }
+        len => {
+            buf.put_u8(mask_bit | 127);
+            buf.put_u64(len as u64);
+        }
+    }
+
+    match role {
+        Role::Client => {
+            let mask = mask_key();
+            buf.put_slice(&mask);
+            let start = buf.len();
+            buf.put_slice(payload);
+            apply_mask(&mut buf[start..], mask);
+        }
+        Role::Server => buf.put_slice(payload),
+    }
+}
+
+/// Encode and write a single (final) frame.
+pub(super) async fn write<IO>(
+    io: &mut IO,
+    opcode: OpCode,
+    payload: &[u8],
+    role: Role,
+) -> std::io::Result<()>
+where
+    IO: AsyncWrite + Unpin,
+{
+    let mut buf = BytesMut::with_capacity(payload.len() + 14);
+    encode(&mut buf, opcode, payload, role);
+    io.write_all(&buf).await?;
+    io.flush().await
+}
+
+fn apply_mask(data: &mut [u8], mask: [u8; 4]) {
+    for (i, byte) in data.iter_mut().enumerate() {
+        *byte ^= mask[i % 4];
+    }
+}
+
+/// Masking key for a client frame, which only has to be unpredictable
+/// for the (intermediaries of the) connection, not cryptographically secure.
+fn mask_key() -> [u8; 4] {
+    use std::hash::{BuildHasher, Hasher};
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+
+    let mut hasher = std::collections::hash_map::RandomState::new().build_hasher();
+    hasher.write_u64(COUNTER.fetch_add(1, Ordering::Relaxed));
+    (hasher.finish() as u32).to_ne_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frame_roundtrip() {
+        for len in [0, 5, 125, 126, 1024, 0x10000] {
+            let payload = vec![0x42; len];
+
+            let mut buf = BytesMut::new();
+            encode(&mut buf, OpCode::Binary, &payload, Role::Client);
+            // a client frame is decoded by the server
+            let frame = decode(&mut buf, Role::Server, usize::MAX).unwrap().unwrap();
+            assert!(frame.fin);
+            assert_eq!(frame.opcode, OpCode::Binary);
+            assert_eq!(frame.payload, payload);
+            assert!(buf.is_empty());
+
+            encode(&mut buf, OpCode::Text, &payload, Role::Server);
+            let frame = decode(&mut buf, Role::Client, usize::MAX).unwrap().unwrap();
+            assert_eq!(frame.opcode, OpCode::Text);
+            assert_eq!(frame.payload, payload);
+        }
+    }
+
+    #[test]
+    fn test_frame_decode_partial_and_invalid() {
+        // masked "Hello" example from RFC 6455 section 5.7
+        let raw = [
+            0x81, 0x85, 0x37, 0xfa, 0x21, 0x3d, 0x7f, 0x9f, 0x4d, 0x51, 0x58,
+        ];
+        let mut buf = BytesMut::new();
+        for byte in &raw[..raw.len() - 1] {
+            buf.put_u8(*byte);
+            assert!(decode(&mut buf, Role::Server, 1024).unwrap().is_none());
+        }
+        buf.put_u8(raw[raw.len() - 1]);
+        let frame = decode(&mut buf, Role::Server, 1024).unwrap().unwrap();
+        assert_eq!(frame.payload, "Hello");
+
+        // unmasked frame sent by a client
+        let mut buf = BytesMut::from(&[0x81, 0x05, b'H', b'e', b'l', b'l', b'o'][..]);
+        assert!(decode(&mut buf, Role::Server, 1024).is_err());
+
+        // too large frame
+        let mut buf = BytesMut::from(&[0x82, 0x05, b'H', b'e', b'l', b'l', b'o'][..]);
+        assert!(decode(&mut buf, Role::Client, 4).is_err());
+
+        // fragmented control frame
+        let mut buf = BytesMut::from(&[0x09, 0x00][..]);
+        assert!(decode(&mut buf, Role::Client, 1024).is_err());
+    }
+}
@@ -0,0 +1,139 @@
+//! WebSocket support, as defined in [RFC 6455](https://www.rfc-editor.org/rfc/rfc6455).
+//!
+//! A [`WebSocket`] is a message stream on top of an upgraded (http) connection.
+//! Use the [`server`] utilities in combination with the [`UpgradeLayer`]
+//! to accept websocket connections on rama's http servers.
+//!
+//! Both the http/1.1 upgrade handshake and the http/2
+//! [extended CONNECT](https://www.rfc-editor.org/rfc/rfc8441) handshake are supported,
+//! the latter requiring the extended CONNECT protocol to be enabled on the h2 server.
+//!
+//! [`UpgradeLayer`]: crate::server::layer::upgrade::UpgradeLayer
+//!
+//! # Example
+//!
+//! ```
+//! use rama_core::{service::service_fn, Context, Layer};
+//! use rama_http_backend::server::layer::upgrade::UpgradeLayer;
+//! use rama_http_backend::websocket::{
+//!     server::{WebSocketAcceptor, WebSocketMatcher, WebSocketService},
+//!     Message, WebSocket,
+//! };
+//! use rama_http_types::{IntoResponse, Request, StatusCode};
+//! use rama_core::error::OpaqueError;
+//! use std::convert::Infallible;
+//!
+//! async fn echo(_ctx: Context<()>, mut socket: WebSocket) -> Result<(), OpaqueError> {
+//!     while let Some(msg) = socket.recv().await {
+//!         match msg? {
+//!             msg @ (Message::Text(_) | Message::Binary(_)) => socket.send(msg).await?,
+//!             _ => (),
+//!         }
+//!     }
+//!     Ok(())
+//! }
+//!
+//! let service = UpgradeLayer::new(
+//!     WebSocketMatcher::new(),
+//!     WebSocketAcceptor::new(),
+//!     WebSocketService::new(service_fn(echo)),
+//! )
+//! .layer(service_fn(|_req: Request| async {
+//!     Ok::<_, Infallible>(StatusCode::NOT_FOUND.into_response())
+//! }));
+//! # let _ = service;
+//! ```
+
+mod frame;
+
+mod socket;
+#[doc(inline)]
+pub use socket::WebSocket;
+
+pub mod server;
+
+use bytes::Bytes;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+/// The side of the websocket connection,
+/// which determines how frames are masked.
+pub enum Role {
+    /// The server side, accepting the websocket connection.
+    Server,
+    /// The client side, which initiated the websocket connection.
+    Client,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// A message sent or received over a [`WebSocket`].
+pub enum Message {
+    /// A UTF-8 encoded text message.
+    Text(String),
+    /// A binary message.
+    Binary(Bytes),
+    /// A ping control message, answered automatically
+    /// with a pong message when received.
+    Ping(Bytes),
+    /// A pong control message.
+    Pong(Bytes),
+    /// A close control message, with an optional close code and reason.
+    ///
+    /// A received close message is answered automatically,
+    /// after which the connection is closed.
+    Close(Option<CloseFrame>),
+}
+
+impl From<String> for Message {
+    fn from(value: String) -> Self {
+        Self::Text(value)
+    }
+}
+
+impl From<&str> for Message {
+    fn from(value: &str) -> Self {
+        Self::Text(value.to_owned())
+    }
+}
+
+impl From<Bytes> for Message {
+    fn from(value: Bytes) -> Self {
+        Self::Binary(value)
+    }
+}
+
+impl From<Vec<u8>> for Message {
+    fn from(value: Vec<u8>) -> Self {
+        Self::Binary(value.into())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// The code and reason of a [`Message::Close`].
+pub struct CloseFrame {
+    /// The close code, as defined in
+    /// [RFC 6455 section 7.4](https://www.rfc-editor.org/rfc/rfc6455#section-7.4).
+    pub code: u16,
+    /// The (UTF-8 encoded) reason for closing the connection.
+    pub reason: String,
+}
+
+impl CloseFrame {
+    /// Normal closure.
+    pub const NORMAL: u16 = 1000;
+    /// The endpoint is going away, e.g. a server shutting down.
+    pub const GOING_AWAY: u16 = 1001;
+    /// The endpoint received a frame violating the protocol.
+    pub const PROTOCOL_ERROR: u16 = 1002;
+    /// The endpoint received a text message which is not valid UTF-8.
+    pub const INVALID_PAYLOAD: u16 = 1007;
+    /// The endpoint received a message too large to process.
+    pub const MESSAGE_TOO_BIG: u16 = 1009;
+
+    /// Create a new [`CloseFrame`].
+    pub fn new(code: u16, reason: impl Into<String>) -> Self {
+        Self {
+            code,
+            reason: reason.into(),
+        }
+    }
+}
@@ -0,0 +1,353 @@
+//! WebSocket server utilities, to be used in combination with the
+//! [`UpgradeLayer`](crate::server::layer::upgrade::UpgradeLayer).
+//!
+//! See the [module level docs](super) for an example.
+
+use super::{Role, WebSocket};
+use crate::server::layer::upgrade::Upgraded;
+use base64::Engine as _;
+use rama_core::{
+    context::Extensions,
+    error::{BoxError, OpaqueError},
+    matcher::Matcher,
+    Context, Service,
+};
+use rama_http_types::{
+    header::{
+        CONNECTION, SEC_WEBSOCKET_ACCEPT, SEC_WEBSOCKET_KEY, SEC_WEBSOCKET_PROTOCOL,
+        SEC_WEBSOCKET_VERSION, UPGRADE,
+    },
+    Body, HeaderMap, HeaderValue, Method, Request, Response, StatusCode, Version,
+};
+use rama_utils::macros::define_inner_service_accessors;
+use sha1::{Digest, Sha1};
+use std::{convert::Infallible, fmt};
+
+/// The GUID used to compute the `Sec-WebSocket-Accept` header value,
+/// as defined in [RFC 6455 section 1.3](https://www.rfc-editor.org/rfc/rfc6455#section-1.3).
+const WEBSOCKET_GUID: &[u8] = b"258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+/// Compute the `Sec-WebSocket-Accept` header value for a `Sec-WebSocket-Key` header value.
+pub fn accept_key(key: &[u8]) -> HeaderValue {
+    let mut hasher = Sha1::new();
+    hasher.update(key);
+    hasher.update(WEBSOCKET_GUID);
+    let accept = base64::engine::general_purpose::STANDARD.encode(hasher.finalize());
+    HeaderValue::try_from(accept).expect("base64 is a valid header value")
+}
+
+#[derive(Debug, Clone, Default)]
+#[non_exhaustive]
+/// [`Matcher`] matching websocket handshake requests,
+/// both http/1.1 upgrade requests and http/2 extended CONNECT requests.
+pub struct WebSocketMatcher;
+
+impl WebSocketMatcher {
+    /// Create a new [`WebSocketMatcher`].
+    pub const fn new() -> Self {
+        Self
+    }
+}
+
+impl<State> Matcher<State, Request> for WebSocketMatcher
+where
+    State: Clone + Send + Sync + 'static,
+{
+    fn matches(&self, _ext: Option<&mut Extensions>, _ctx: &Context<State>, req: &Request) -> bool {
+        match req.version() {
+            Version::HTTP_10 | Version::HTTP_11 => {
+                req.method() == Method::GET
+                    && header_contains_token(req.headers(), &CONNECTION, "upgrade")
+                    && header_contains_token(req.headers(), &UPGRADE, "websocket")
+            }
+            Version::HTTP_2 => {
+                req.method() == Method::CONNECT
+                    && req
+                        .extensions()
+                        .get::<hyper::ext::Protocol>()
+                        .map(|protocol| protocol.as_str().eq_ignore_ascii_case("websocket"))
+                        .unwrap_or_default()
+            }
+            _ => false,
+        }
+    }
+}
+
+fn header_contains_token(
+    headers: &HeaderMap,
+    name: &rama_http_types::HeaderName,
+    token: &str,
+) -> bool {
+    headers
+        .get_all(name)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|value| value.trim().eq_ignore_ascii_case(token))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// The subprotocol accepted during the websocket handshake,
+/// inserted in the [`Context`] by the [`WebSocketAcceptor`].
+pub struct AcceptedSubProtocol(String);
+
+impl AcceptedSubProtocol {
+    /// The name of the accepted subprotocol.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+/// Responder answering websocket handshake requests,
+/// to be used as the responder of an [`UpgradeLayer`].
+///
+/// Requests which are not a valid websocket handshake are rejected
+/// with a `400 Bad Request` (or `426 Upgrade Required` for an unsupported version).
+///
+/// [`UpgradeLayer`]: crate::server::layer::upgrade::UpgradeLayer
+pub struct WebSocketAcceptor {
+    protocols: Vec<String>,
+}
+
+impl WebSocketAcceptor {
+    /// Create a new [`WebSocketAcceptor`].
+    pub const fn new() -> Self {
+        Self {
+            protocols: Vec::new(),
+        }
+    }
+
+    /// Set the subprotocols supported by the server, in order of preference.
+    ///
+    /// The first supported protocol requested by the client is accepted,
+    /// and inserted as [`AcceptedSubProtocol`] in the [`Context`].
+    /// Requests not offering any of the supported protocols are accepted
+    /// without a subprotocol.
+    pub fn with_protocols<I, P>(mut self, protocols: I) -> Self
+    where
+        I: IntoIterator<Item = P>,
+        P: Into<String>,
+    {
+        self.protocols = protocols.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Set the subprotocols supported by the server, in order of preference.
+    ///
+    /// See [`Self::with_protocols`] for more details.
+    pub fn set_protocols<I, P>(&mut self, protocols: I) -> &mut Self
+    where
+        I: IntoIterator<Item = P>,
+        P: Into<String>,
+    {
+        self.protocols = protocols.into_iter().map(Into::into).collect();
+        self
+    }
+
+    fn select_protocol(&self, headers: &HeaderMap) -> Option<&str> {
+        self.protocols
+            .iter()
+            .find(|protocol| header_contains_token(headers, &SEC_WEBSOCKET_PROTOCOL, protocol))
+            .map(String::as_str)
+    }
+}
+
+impl<State> Service<State, Request> for WebSocketAcceptor
+where
+    State: Clone + Send + Sync + 'static,
+{
+    type Response = (Response, Context<State>, Request);
+    type Error = Response;
+
+    async fn serve(
+        &self,
+        mut ctx: Context<State>,
+        req: Request,
+    ) -> Result<Self::Response, Self::Error> {
+        if req
+            .headers()
+            .get(SEC_WEBSOCKET_VERSION)
+            .map(|v| v.as_bytes())
+            != Some(b"13")
+        {
+            tracing::debug!("websocket handshake: unsupported version");
+            return Err(Response::builder()
+                .status(StatusCode::UPGRADE_REQUIRED)
+                .header(SEC_WEBSOCKET_VERSION, "13")
+                .body(Body::empty())
+                .expect("build websocket version response"));
+        }
+
+        let mut builder = match req.version() {
+            Version::HTTP_2 => Response::builder().status(StatusCode::OK),
+            _ => {
+                let Some(key) = req.headers().get(SEC_WEBSOCKET_KEY) else {
+                    tracing::debug!("websocket handshake: missing key");
+                    return Err(bad_request());
+                };
+                Response::builder()
+                    .status(StatusCode::SWITCHING_PROTOCOLS)
+                    .header(CONNECTION, "upgrade")
+                    .header(UPGRADE, "websocket")
+                    .header(SEC_WEBSOCKET_ACCEPT, accept_key(key.as_bytes()))
+            }
+        };
+
+        if let Some(protocol) = self.select_protocol(req.headers()) {
+            builder = builder.header(SEC_WEBSOCKET_PROTOCOL, protocol);
+            ctx.insert(AcceptedSubProtocol(protocol.to_owned()));
+        }
+
+        match builder.body(Body::empty()) {
+            Ok(resp) => Ok((resp, ctx, req)),
+            Err(err) => {
+                tracing::debug!(error = %err, "websocket handshake: build response");
+                Err(bad_request())
+            }
+        }
+    }
+}
+
+fn bad_request() -> Response {
+    Response::builder()
+        .status(StatusCode::BAD_REQUEST)
+        .body(Body::empty())
+        .expect("build bad request response")
+}
+
+/// Service handling the upgraded connection of a websocket handshake,
+/// by serving the inner service with a server [`WebSocket`].
+pub struct WebSocketService<S> {
+    inner: S,
+    max_message_size: usize,
+}
+
+impl<S> WebSocketService<S> {
+    /// Create a new [`WebSocketService`].
+    pub const fn new(inner: S) -> Self {
+        Self {
+            inner,
+            max_message_size: WebSocket::<Upgraded>::DEFAULT_MAX_MESSAGE_SIZE,
+        }
+    }
+
+    /// Set the maximum size of a message received by the [`WebSocket`].
+    pub fn with_max_message_size(mut self, size: usize) -> Self {
+        self.max_message_size = size;
+        self
+    }
+
+    /// Set the maximum size of a message received by the [`WebSocket`].
+    pub fn set_max_message_size(&mut self, size: usize) -> &mut Self {
+        self.max_message_size = size;
+        self
+    }
+
+    define_inner_service_accessors!();
+}
+
+impl<S: fmt::Debug> fmt::Debug for WebSocketService<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WebSocketService")
+            .field("inner", &self.inner)
+            .field("max_message_size", &self.max_message_size)
+            .finish()
+    }
+}
+
+impl<S: Clone> Clone for WebSocketService<S> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            max_message_size: self.max_message_size,
+        }
+    }
+}
+
+impl<State, S> Service<State, Upgraded> for WebSocketService<S>
+where
+    State: Clone + Send + Sync + 'static,
+    S: Service<State, WebSocket, Response = (), Error: Into<BoxError>>,
+{
+    type Response = ();
+    type Error = Infallible;
+
+    async fn serve(
+        &self,
+        ctx: Context<State>,
+        io: Upgraded,
+    ) -> Result<Self::Response, Self::Error> {
+        let socket =
+            WebSocket::from_raw(io, Role::Server).with_max_message_size(self.max_message_size);
+        if let Err(err) = self.inner.serve(ctx, socket).await {
+            let err = OpaqueError::from_boxed(err.into());
+            tracing::debug!(error = %err, "websocket service error");
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_accept_key() {
+        // example from RFC 6455 section 1.3
+        assert_eq!(
+            accept_key(b"dGhlIHNhbXBsZSBub25jZQ=="),
+            "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+        );
+    }
+
+    #[tokio::test]
+    async fn test_websocket_handshake() {
+        let req = Request::builder()
+            .method(Method::GET)
+            .uri("http://example.com/chat")
+            .header(CONNECTION, "keep-alive, Upgrade")
+            .header(UPGRADE, "websocket")
+            .header(SEC_WEBSOCKET_KEY, "dGhlIHNhbXBsZSBub25jZQ==")
+            .header(SEC_WEBSOCKET_VERSION, "13")
+            .header(SEC_WEBSOCKET_PROTOCOL, "chat, superchat")
+            .body(Body::empty())
+            .unwrap();
+        let ctx = Context::default();
+        assert!(WebSocketMatcher::new().matches(None, &ctx, &req));
+
+        let acceptor = WebSocketAcceptor::new().with_protocols(["superchat", "chat"]);
+        let (resp, ctx, _) = acceptor.serve(ctx, req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::SWITCHING_PROTOCOLS);
+        assert_eq!(
+            resp.headers().get(SEC_WEBSOCKET_ACCEPT).unwrap(),
+            "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+        );
+        assert_eq!(
+            resp.headers().get(SEC_WEBSOCKET_PROTOCOL).unwrap(),
+            "superchat"
+        );
+        assert_eq!(
+            ctx.get::<AcceptedSubProtocol>().unwrap().as_str(),
+            "superchat"
+        );
+
+        let req = Request::builder()
+            .method(Method::GET)
+            .header(CONNECTION, "upgrade")
+            .header(UPGRADE, "websocket")
+            .header(SEC_WEBSOCKET_KEY, "dGhlIHNhbXBsZSBub25jZQ==")
+            .header(SEC_WEBSOCKET_VERSION, "8")
+            .body(Body::empty())
+            .unwrap();
+        let resp = acceptor.serve(Context::default(), req).await.unwrap_err();
+        assert_eq!(resp.status(), StatusCode::UPGRADE_REQUIRED);
+        assert_eq!(resp.headers().get(SEC_WEBSOCKET_VERSION).unwrap(), "13");
+
+        let req = Request::builder()
+            .method(Method::GET)
+            .body(Body::empty())
+            .unwrap();
+        assert!(!WebSocketMatcher::new().matches(None, &Context::default(), &req));
+    }
+}
@@ -0,0 +1,342 @@
+use super::{
+    frame::{self, DecodeError, OpCode},
+    CloseFrame, Message, Role,
+};
+use crate::server::layer::upgrade::Upgraded;
+use bytes::{BufMut, Bytes, BytesMut};
+use rama_core::error::{ErrorContext, ErrorExt, OpaqueError};
+use std::fmt;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite};
+
+/// A WebSocket connection, sending and receiving [`Message`]s.
+///
+/// Ping messages are answered automatically, as is the close handshake.
+pub struct WebSocket<IO = Upgraded> {
+    io: IO,
+    role: Role,
+    max_message_size: usize,
+    read_buf: BytesMut,
+    fragments: Option<(OpCode, BytesMut)>,
+    close_sent: bool,
+    close_received: bool,
+}
+
+impl<IO> fmt::Debug for WebSocket<IO> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WebSocket")
+            .field("role", &self.role)
+            .field("max_message_size", &self.max_message_size)
+            .field("close_sent", &self.close_sent)
+            .field("close_received", &self.close_received)
+            .finish()
+    }
+}
+
+impl<IO> WebSocket<IO> {
+    /// The default maximum size of a received message (16 MiB).
+    pub const DEFAULT_MAX_MESSAGE_SIZE: usize = 16 * 1024 * 1024;
+
+    /// Create a [`WebSocket`] from an io stream on which the handshake already happened.
+    pub fn from_raw(io: IO, role: Role) -> Self {
+        Self {
+            io,
+            role,
+            max_message_size: Self::DEFAULT_MAX_MESSAGE_SIZE,
+            read_buf: BytesMut::new(),
+            fragments: None,
+            close_sent: false,
+            close_received: false,
+        }
+    }
+
+    /// Set the maximum size of a received message,
+    /// the connection is closed in case a larger message is received.
+    pub fn with_max_message_size(mut self, size: usize) -> Self {
+        self.max_message_size = size;
+        self
+    }
+
+    /// Set the maximum size of a received message,
+    /// the connection is closed in case a larger message is received.
+    pub fn set_max_message_size(&mut self, size: usize) -> &mut Self {
+        self.max_message_size = size;
+        self
+    }
+
+    /// The [`Role`] of this side of the connection.
+    pub fn role(&self) -> Role {
+        self.role
+    }
+
+    /// Consume the [`WebSocket`] into the underlying io stream.
+    pub fn into_inner(self) -> IO {
+        self.io
+    }
+}
+
+impl<IO> WebSocket<IO>
+where
+    IO: AsyncRead + AsyncWrite + Unpin,
+{
+    /// Receive the next message.
+    ///
+    /// Returns `None` once the connection is closed.
+    pub async fn recv(&mut self) -> Option<Result<Message, OpaqueError>> {
+        if self.close_received {
+            return None;
+        }
+        loop {
+            match frame::decode(&mut self.read_buf, self.role, self.max_message_size) {
+                Ok(Some(frame)) => match self.on_frame(frame).await {
+                    Ok(Some(msg)) => return Some(Ok(msg)),
+                    Ok(None) => continue,
+                    Err(err) => return Some(Err(err)),
+                },
+                Ok(None) => (),
+                Err(DecodeError::Protocol(reason)) => {
+                    return Some(Err(self.fail(CloseFrame::PROTOCOL_ERROR, reason).await))
+                }
+                Err(DecodeError::TooLarge) => {
+                    return Some(Err(self
+                        .fail(CloseFrame::MESSAGE_TOO_BIG, "message too big")
+                        .await))
+                }
+            }
+
+            match self.io.read_buf(&mut self.read_buf).await {
+                Ok(0) => {
+                    self.close_received = true;
+                    return None;
+                }
+                Ok(_) => (),
+                Err(err) => return Some(Err(err.context("read websocket frame"))),
+            }
+        }
+    }
+
+    /// Send a message.
+    ///
+    /// Sending a [`Message::Close`] initiates the close handshake,
+    /// after which no more messages can be sent.
+    pub async fn send(&mut self, msg: impl Into<Message>) -> Result<(), OpaqueError> {
+        if self.close_sent {
+            return Err(OpaqueError::from_display("websocket: connection is closed"));
+        }
+        let (opcode, payload) = match msg.into() {
+            Message::Text(text) => (OpCode::Text, Bytes::from(text)),
+            Message::Binary(data) => (OpCode::Binary, data),
+            Message::Ping(data) => (OpCode::Ping, data),
+            Message::Pong(data) => (OpCode::Pong, data),
+            Message::Close(frame) => {
+                self.close_sent = true;
+                (OpCode::Close, close_payload(frame))
+            }
+        };
+        frame::write(&mut self.io, opcode, &payload, self.role)
+            .await
+            .context("write websocket frame")
+    }
+
+    /// Initiate the close handshake.
+    pub async fn close(&mut self, frame: Option<CloseFrame>) -> Result<(), OpaqueError> {
+        self.send(Message::Close(frame)).await
+    }
+
+    async fn on_frame(&mut self, frame: frame::Frame) -> Result<Option<Message>, OpaqueError> {
+        let (opcode, data) = match frame.opcode {
+            OpCode::Ping => {
+                if !self.close_sent {
+                    frame::write(&mut self.io, OpCode::Pong, &frame.payload, self.role)
+                        .await
+                        .context("write websocket pong")?;
+                }
+                return Ok(Some(Message::Ping(frame.payload)));
+            }
+            OpCode::Pong => return Ok(Some(Message::Pong(frame.payload))),
+            OpCode::Close => {
+                self.close_received = true;
+                let close = match parse_close_payload(&frame.payload) {
+                    Ok(close) => close,
+                    Err(reason) => return Err(self.fail(CloseFrame::PROTOCOL_ERROR, reason).await),
+                };
+                if !self.close_sent {
+                    // echo the close code, as part of the close handshake
+                    let echo = close
+                        .as_ref()
+                        .map(|close| CloseFrame::new(close.code, String::new()));
+                    self.send(Message::Close(echo)).await?;
+                }
+                return Ok(Some(Message::Close(close)));
+            }
+            OpCode::Text | OpCode::Binary => {
+                if self.fragments.is_some() {
+                    return Err(self
+                        .fail(CloseFrame::PROTOCOL_ERROR, "expected continuation frame")
+                        .await);
+                }
+                if !frame.fin {
+                    self.fragments = Some((frame.opcode, BytesMut::from(&frame.payload[..])));
+                    return Ok(None);
+                }
+                (frame.opcode, frame.payload)
+            }
+            OpCode::Continuation => {
+                let Some((_, data)) = self.fragments.as_mut() else {
+                    return Err(self
+                        .fail(CloseFrame::PROTOCOL_ERROR, "unexpected continuation frame")
+                        .await);
+                };
+                if data.len() + frame.payload.len() > self.max_message_size {
+                    return Err(self
+                        .fail(CloseFrame::MESSAGE_TOO_BIG, "message too big")
+                        .await);
+                }
+                data.put_slice(&frame.payload);
+                if !frame.fin {
+                    return Ok(None);
+                }
+                let (opcode, data) = self.fragments.take().expect("fragments");
+                (opcode, data.freeze())
+            }
+        };
+
+        if opcode == OpCode::Binary {
+            return Ok(Some(Message::Binary(data)));
+        }
+        match String::from_utf8(data.into()) {
+            Ok(text) => Ok(Some(Message::Text(text))),
+            Err(_) => Err(self
+                .fail(
+                    CloseFrame::INVALID_PAYLOAD,
+                    "text message is not valid utf-8",
+                )
+                .await),
+        }
+    }
+
+    /// Close the connection (best effort) because of a protocol violation.
+    async fn fail(&mut self, code: u16, reason: &'static str) -> OpaqueError {
+        self.close_received = true;
+        if !self.close_sent {
+            self.close_sent = true;
+            let payload = close_payload(Some(CloseFrame::new(code, reason)));
+            if let Err(err) = frame::write(&mut self.io, OpCode::Close, &payload, self.role).await {
+                tracing::debug!(error = %err, "websocket: failed to send close frame");
+            }
+        }
+        OpaqueError::from_display(format!("websocket protocol error: {reason}"))
+    }
+}
+
+fn close_payload(frame: Option<CloseFrame>) -> Bytes {
+    let Some(frame) = frame else {
+        return Bytes::new();
+    };
+    let mut payload = BytesMut::with_capacity(2 + frame.reason.len());
+    payload.put_u16(frame.code);
+    // control frames are limited to 125 bytes
+    let mut len = frame.reason.len().min(123);
+    while !frame.reason.is_char_boundary(len) {
+        len -= 1;
+    }
+    payload.put_slice(&frame.reason.as_bytes()[..len]);
+    payload.freeze()
+}
+
+fn parse_close_payload(payload: &[u8]) -> Result<Option<CloseFrame>, &'static str> {
+    match payload {
+        [] => Ok(None),
+        [_] => Err("invalid close frame"),
+        [hi, lo, reason @ ..] => {
+            let reason = std::str::from_utf8(reason).map_err(|_| "invalid close reason")?;
+            Ok(Some(CloseFrame::new(
+                u16::from_be_bytes([*hi, *lo]),
+                reason,
+            )))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_websocket_messages() {
+        let (client, server) = tokio::io::duplex(1024);
+        let mut client = WebSocket::from_raw(client, Role::Client);
+        let mut server = WebSocket::from_raw(server, Role::Server).with_max_message_size(64);
+
+        client.send("hello").await.unwrap();
+        client
+            .send(Message::Ping(Bytes::from("ping")))
+            .await
+            .unwrap();
+        assert_eq!(
+            server.recv().await.unwrap().unwrap(),
+            Message::from("hello")
+        );
+        assert_eq!(
+            server.recv().await.unwrap().unwrap(),
+            Message::Ping(Bytes::from("ping"))
+        );
+        // pong is sent automatically
+        assert_eq!(
+            client.recv().await.unwrap().unwrap(),
+            Message::Pong(Bytes::from("ping"))
+        );
+
+        server.send(vec![1, 2, 3]).await.unwrap();
+        assert_eq!(
+            client.recv().await.unwrap().unwrap(),
+            Message::Binary(Bytes::from_static(&[1, 2, 3]))
+        );
+
+        client
+            .close(Some(CloseFrame::new(CloseFrame::NORMAL, "bye")))
+            .await
+            .unwrap();
+        assert_eq!(
+            server.recv().await.unwrap().unwrap(),
+            Message::Close(Some(CloseFrame::new(CloseFrame::NORMAL, "bye")))
+        );
+        assert!(server.recv().await.is_none());
+        assert!(server.send("too late").await.is_err());
+
+        // close handshake is answered by the server
+        assert_eq!(
+            client.recv().await.unwrap().unwrap(),
+            Message::Close(Some(CloseFrame::new(CloseFrame::NORMAL, "")))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_websocket_fragmented_and_too_big() {
+        let (mut client, server) = tokio::io::duplex(1024);
+        let mut server = WebSocket::from_raw(server, Role::Server).with_max_message_size(8);
+
+        // "Hel" + "lo" as fragmented text message, masked with an all-zero key
+        let mut raw = vec![0x01, 0x83, 0, 0, 0, 0, b'H', b'e', b'l'];
+        raw.extend([0x80, 0x82, 0, 0, 0, 0, b'l', b'o']);
+        // binary message exceeding the max size
+        raw.extend([0x82, 0x89, 0, 0, 0, 0]);
+        raw.extend([0; 9]);
+        tokio::io::AsyncWriteExt::write_all(&mut client, &raw)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            server.recv().await.unwrap().unwrap(),
+            Message::from("Hello")
+        );
+        assert!(server.recv().await.unwrap().is_err());
+        assert!(server.recv().await.is_none());
+
+        // server closed the connection with "message too big"
+        let mut buf = [0; 4];
+        tokio::io::AsyncReadExt::read_exact(&mut client, &mut buf)
+            .await
+            .unwrap();
+        assert_eq!(buf, [0x88, 17, 0x03, 0xF1]);
+    }
+}
@@ -0,0 +1,112 @@
+use crate::{HeaderMap, HeaderName};
+use bytes::Bytes;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+/// The header names of an http/1 request, in the order
+/// (and casing) in which they were received.
+///
+/// Inserted in the request extensions by the http server in case it
+/// is configured to preserve the header order. The http client sends the
+/// headers of http/1 requests which have this extension in the recorded order,
+/// such that a proxy forwards the headers as they were sent by its client.
+pub struct OriginalHeaderOrder(Vec<Bytes>);
+
+impl OriginalHeaderOrder {
+    /// Create a new empty [`OriginalHeaderOrder`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Append a (raw) header name to the order.
+    pub fn push(&mut self, name: impl Into<Bytes>) {
+        self.0.push(name.into());
+    }
+
+    /// Iterate over the (raw) header names, in the order they were received.
+    ///
+    /// Names of headers received multiple times are yielded multiple times.
+    pub fn iter(&self) -> impl Iterator<Item = &[u8]> {
+        self.0.iter().map(|name| name.as_ref())
+    }
+
+    /// Returns the amount of recorded header names.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Returns `true` in case no header names were recorded.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Reorder the given headers in the recorded order.
+    ///
+    /// Headers which were not recorded (e.g. added by a proxy) are kept
+    /// in their current order, after the recorded ones. All values of a header
+    /// are kept together, at the position where that header was first recorded.
+    pub fn apply_to(&self, headers: &mut HeaderMap) {
+        let original = std::mem::take(headers);
+        for name in self.iter() {
+            let Ok(name) = HeaderName::from_bytes(name) else {
+                continue;
+            };
+            if headers.contains_key(&name) {
+                continue;
+            }
+            for value in original.get_all(&name) {
+                headers.append(name.clone(), value.clone());
+            }
+        }
+
+        let mut remaining = HeaderMap::new();
+        for (name, value) in original.iter() {
+            if !headers.contains_key(name) {
+                remaining.append(name.clone(), value.clone());
+            }
+        }
+        for (name, value) in remaining.iter() {
+            headers.append(name.clone(), value.clone());
+        }
+    }
+}
+
+impl<N: Into<Bytes>> FromIterator<N> for OriginalHeaderOrder {
+    fn from_iter<T: IntoIterator<Item = N>>(iter: T) -> Self {
+        Self(iter.into_iter().map(Into::into).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_apply_to() {
+        let order: OriginalHeaderOrder = ["User-Agent", "Accept", "X-Foo", "accept", "Host"]
+            .into_iter()
+            .collect();
+
+        let mut headers = HeaderMap::new();
+        headers.insert("host", "example.com".parse().unwrap());
+        headers.insert("via", "1.1 rama".parse().unwrap());
+        headers.append("accept", "text/html".parse().unwrap());
+        headers.append("accept", "*/*".parse().unwrap());
+        headers.insert("user-agent", "rama".parse().unwrap());
+
+        order.apply_to(&mut headers);
+        let headers: Vec<_> = headers
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.to_str().unwrap()))
+            .collect();
+        assert_eq!(
+            headers,
+            [
+                ("user-agent", "rama"),
+                ("accept", "text/html"),
+                ("accept", "*/*"),
+                ("host", "example.com"),
+                ("via", "1.1 rama"),
+            ]
+        );
+    }
+}
@@ -90,3 +90,7 @@ pub mod authorization {
 mod ext;
 #[doc(inline)]
 pub use ext::HeaderExt;
+
+mod proxy_status;
+#[doc(inline)]
+pub use proxy_status::{ProxyErrorType, ProxyStatus, ProxyStatusEntry};
@@ -0,0 +1,456 @@
+use super::{Error, Header};
+use crate::{header::PROXY_STATUS, HeaderName, HeaderValue};
+use std::fmt;
+
+/// The `Proxy-Status` response header, as defined in [RFC 9209].
+///
+/// Each intermediary which handled the response (e.g. a forward or reverse proxy)
+/// can append a [`ProxyStatusEntry`], describing how it handled the request,
+/// and in case of failure, what kind of [`ProxyErrorType`] occurred.
+/// The entry of the intermediary closest to the origin comes first.
+///
+/// [RFC 9209]: https://www.rfc-editor.org/rfc/rfc9209.html
+///
+/// # Example values
+///
+/// * `rama; error=connection_refused`
+/// * `SomeCDN; received-status=200, "internal proxy"; error=http_response_timeout`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProxyStatus(Vec<ProxyStatusEntry>);
+
+impl ProxyStatus {
+    /// Create a new [`ProxyStatus`] header with a single entry.
+    pub fn new(entry: ProxyStatusEntry) -> Self {
+        Self(vec![entry])
+    }
+
+    /// The entries of this header, starting with the one closest to the origin.
+    pub fn entries(&self) -> &[ProxyStatusEntry] {
+        &self.0
+    }
+
+    /// Append an entry to this header, as the intermediary closest to the client.
+    pub fn push(&mut self, entry: ProxyStatusEntry) -> &mut Self {
+        self.0.push(entry);
+        self
+    }
+}
+
+impl FromIterator<ProxyStatusEntry> for ProxyStatus {
+    fn from_iter<T: IntoIterator<Item = ProxyStatusEntry>>(iter: T) -> Self {
+        Self(iter.into_iter().collect())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// A single entry of the [`ProxyStatus`] header,
+/// describing how a single intermediary handled the request.
+pub struct ProxyStatusEntry {
+    proxy: String,
+    error: Option<ProxyErrorType>,
+    received_status: Option<u16>,
+    next_hop: Option<String>,
+    details: Option<String>,
+}
+
+impl ProxyStatusEntry {
+    /// Create a new [`ProxyStatusEntry`] for the intermediary with the given name.
+    pub fn new(proxy: impl Into<String>) -> Self {
+        Self {
+            proxy: proxy.into(),
+            error: None,
+            received_status: None,
+            next_hop: None,
+            details: None,
+        }
+    }
+
+    /// The name of the intermediary.
+    pub fn proxy(&self) -> &str {
+        &self.proxy
+    }
+
+    /// The error which occurred while handling the request, if any.
+    pub fn error(&self) -> Option<&ProxyErrorType> {
+        self.error.as_ref()
+    }
+
+    /// Set the error which occurred while handling the request.
+    pub fn with_error(mut self, error: ProxyErrorType) -> Self {
+        self.error = Some(error);
+        self
+    }
+
+    /// Set the error which occurred while handling the request.
+    pub fn set_error(&mut self, error: ProxyErrorType) -> &mut Self {
+        self.error = Some(error);
+        self
+    }
+
+    /// The status code received from the next hop, if any.
+    pub fn received_status(&self) -> Option<u16> {
+        self.received_status
+    }
+
+    /// Set the status code received from the next hop.
+    pub fn with_received_status(mut self, status: u16) -> Self {
+        self.received_status = Some(status);
+        self
+    }
+
+    /// Set the status code received from the next hop.
+    pub fn set_received_status(&mut self, status: u16) -> &mut Self {
+        self.received_status = Some(status);
+        self
+    }
+
+    /// The (host) name of the next hop, if known.
+    pub fn next_hop(&self) -> Option<&str> {
+        self.next_hop.as_deref()
+    }
+
+    /// Set the (host) name of the next hop.
+    pub fn with_next_hop(mut self, next_hop: impl Into<String>) -> Self {
+        self.next_hop = Some(next_hop.into());
+        self
+    }
+
+    /// Set the (host) name of the next hop.
+    pub fn set_next_hop(&mut self, next_hop: impl Into<String>) -> &mut Self {
+        self.next_hop = Some(next_hop.into());
+        self
+    }
+
+    /// Additional (human readable) details about the error, if any.
+    pub fn details(&self) -> Option<&str> {
+        self.details.as_deref()
+    }
+
+    /// Set additional (human readable) details about the error.
+    pub fn with_details(mut self, details: impl Into<String>) -> Self {
+        self.details = Some(details.into());
+        self
+    }
+
+    /// Set additional (human readable) details about the error.
+    pub fn set_details(&mut self, details: impl Into<String>) -> &mut Self {
+        self.details = Some(details.into());
+        self
+    }
+}
+
+macro_rules! proxy_error_types {
+    ($($(#[$doc:meta])* $variant:ident => $name:literal,)+) => {
+        #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+        /// The error types of the [`ProxyStatus`] header,
+        /// as registered in the [HTTP Proxy-Status Parameters registry].
+        ///
+        /// [HTTP Proxy-Status Parameters registry]: https://www.iana.org/assignments/http-proxy-status
+        pub enum ProxyErrorType {
+            $($(#[$doc])* $variant,)+
+            /// An error type which is not (yet) known to rama.
+            Other(String),
+        }
+
+        impl ProxyErrorType {
+            /// The name of the error type, as used in the header.
+            pub fn as_str(&self) -> &str {
+                match self {
+                    $(Self::$variant => $name,)+
+                    Self::Other(name) => name,
+                }
+            }
+        }
+
+        impl From<&str> for ProxyErrorType {
+            fn from(name: &str) -> Self {
+                match name {
+                    $($name => Self::$variant,)+
+                    name => Self::Other(name.to_owned()),
+                }
+            }
+        }
+    };
+}
+
+proxy_error_types! {
+    /// `dns_timeout`: the DNS lookup for the next hop timed out.
+    DnsTimeout => "dns_timeout",
+    /// `dns_error`: the DNS lookup for the next hop failed.
+    DnsError => "dns_error",
+    /// `destination_not_found`: the next hop could not be determined.
+    DestinationNotFound => "destination_not_found",
+    /// `destination_unavailable`: the next hop is considered unavailable.
+    DestinationUnavailable => "destination_unavailable",
+    /// `destination_ip_prohibited`: the ip address of the next hop is not allowed.
+    DestinationIpProhibited => "destination_ip_prohibited",
+    /// `destination_ip_unroutable`: the ip address of the next hop is unroutable.
+    DestinationIpUnroutable => "destination_ip_unroutable",
+    /// `connection_refused`: the connection to the next hop was refused.
+    ConnectionRefused => "connection_refused",
+    /// `connection_terminated`: the connection to the next hop was closed before a complete response was received.
+    ConnectionTerminated => "connection_terminated",
+    /// `connection_timeout`: connecting to the next hop timed out.
+    ConnectionTimeout => "connection_timeout",
+    /// `connection_read_timeout`: reading from the next hop timed out.
+    ConnectionReadTimeout => "connection_read_timeout",
+    /// `connection_write_timeout`: writing to the next hop timed out.
+    ConnectionWriteTimeout => "connection_write_timeout",
+    /// `connection_limit_reached`: the connection limit for the next hop was reached.
+    ConnectionLimitReached => "connection_limit_reached",
+    /// `tls_protocol_error`: the tls handshake with the next hop failed.
+    TlsProtocolError => "tls_protocol_error",
+    /// `tls_certificate_error`: the certificate of the next hop could not be verified.
+    TlsCertificateError => "tls_certificate_error",
+    /// `tls_alert_received`: a tls alert was received from the next hop.
+    TlsAlertReceived => "tls_alert_received",
+    /// `http_request_error`: the request is invalid.
+    HttpRequestError => "http_request_error",
+    /// `http_request_denied`: the request was denied by the intermediary.
+    HttpRequestDenied => "http_request_denied",
+    /// `http_response_incomplete`: the response of the next hop was incomplete.
+    HttpResponseIncomplete => "http_response_incomplete",
+    /// `http_response_header_section_size`: the response header section of the next hop was too large.
+    HttpResponseHeaderSectionSize => "http_response_header_section_size",
+    /// `http_response_header_size`: a response header of the next hop was too large.
+    HttpResponseHeaderSize => "http_response_header_size",
+    /// `http_response_body_size`: the response body of the next hop was too large.
+    HttpResponseBodySize => "http_response_body_size",
+    /// `http_response_trailer_section_size`: the response trailer section of the next hop was too large.
+    HttpResponseTrailerSectionSize => "http_response_trailer_section_size",
+    /// `http_response_trailer_size`: a response trailer of the next hop was too large.
+    HttpResponseTrailerSize => "http_response_trailer_size",
+    /// `http_response_transfer_coding`: the transfer coding of the response of the next hop could not be handled.
+    HttpResponseTransferCoding => "http_response_transfer_coding",
+    /// `http_response_content_coding`: the content coding of the response of the next hop could not be handled.
+    HttpResponseContentCoding => "http_response_content_coding",
+    /// `http_response_timeout`: waiting for the response of the next hop timed out.
+    HttpResponseTimeout => "http_response_timeout",
+    /// `http_upgrade_failed`: the protocol upgrade with the next hop failed.
+    HttpUpgradeFailed => "http_upgrade_failed",
+    /// `http_protocol_error`: the next hop violated the http protocol.
+    HttpProtocolError => "http_protocol_error",
+    /// `proxy_internal_response`: the response was generated by the intermediary itself.
+    ProxyInternalResponse => "proxy_internal_response",
+    /// `proxy_internal_error`: an internal error occurred in the intermediary.
+    ProxyInternalError => "proxy_internal_error",
+    /// `proxy_configuration_error`: the intermediary is misconfigured.
+    ProxyConfigurationError => "proxy_configuration_error",
+    /// `proxy_loop_detected`: the request is looping through the intermediary.
+    ProxyLoopDetected => "proxy_loop_detected",
+}
+
+impl fmt::Display for ProxyErrorType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl Header for ProxyStatus {
+    fn name() -> &'static HeaderName {
+        &PROXY_STATUS
+    }
+
+    fn decode<'i, I: Iterator<Item = &'i HeaderValue>>(values: &mut I) -> Result<Self, Error> {
+        let mut entries = Vec::new();
+        for value in values {
+            let value = value.to_str().map_err(|_| Error::invalid())?;
+            for member in split_unquoted(value, ',') {
+                entries.push(parse_entry(member).ok_or_else(Error::invalid)?);
+            }
+        }
+        if entries.is_empty() {
+            return Err(Error::invalid());
+        }
+        Ok(Self(entries))
+    }
+
+    fn encode<E: Extend<HeaderValue>>(&self, values: &mut E) {
+        let value = self
+            .0
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(", ");
+        values.extend(Some(
+            HeaderValue::from_str(&value).expect("proxy status to be a valid header value"),
+        ))
+    }
+}
+
+impl fmt::Display for ProxyStatusEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if is_token(&self.proxy) {
+            f.write_str(&self.proxy)?;
+        } else {
+            write_sf_string(f, &self.proxy)?;
+        }
+        if let Some(error) = &self.error {
+            if is_token(error.as_str()) {
+                write!(f, "; error={error}")?;
+            } else {
+                f.write_str("; error=")?;
+                write_sf_string(f, error.as_str())?;
+            }
+        }
+        if let Some(next_hop) = &self.next_hop {
+            f.write_str("; next-hop=")?;
+            write_sf_string(f, next_hop)?;
+        }
+        if let Some(status) = self.received_status {
+            write!(f, "; received-status={status}")?;
+        }
+        if let Some(details) = &self.details {
+            f.write_str("; details=")?;
+            write_sf_string(f, details)?;
+        }
+        Ok(())
+    }
+}
+
+/// Split the input on the given separator, ignoring separators in quoted strings.
+fn split_unquoted(input: &str, separator: char) -> impl Iterator<Item = &str> {
+    let mut parts = Vec::new();
+    let (mut start, mut quoted, mut escaped) = (0, false, false);
+    for (index, c) in input.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if quoted => escaped = true,
+            '"' => quoted = !quoted,
+            c if c == separator && !quoted => {
+                parts.push(&input[start..index]);
+                start = index + 1;
+            }
+            _ => (),
+        }
+    }
+    parts.push(&input[start..]);
+    parts
+        .into_iter()
+        .map(str::trim)
+        .filter(|part| !part.is_empty())
+}
+
+fn parse_entry(member: &str) -> Option<ProxyStatusEntry> {
+    let mut parts = split_unquoted(member, ';');
+    let mut entry = ProxyStatusEntry::new(parse_bare_item(parts.next()?)?);
+    for param in parts {
+        let (key, value) = match param.split_once('=') {
+            Some((key, value)) => (key.trim(), parse_bare_item(value.trim())?),
+            // boolean parameter, none of which are known
+            None => continue,
+        };
+        match key {
+            "error" => entry.error = Some(ProxyErrorType::from(value.as_str())),
+            "next-hop" => entry.next_hop = Some(value),
+            "received-status" => entry.received_status = Some(value.parse().ok()?),
+            "details" => entry.details = Some(value),
+            _ => (),
+        }
+    }
+    Some(entry)
+}
+
+/// Parse a token, integer or (unescaped) string item.
+fn parse_bare_item(item: &str) -> Option<String> {
+    match item.strip_prefix('"') {
+        Some(rest) => {
+            let rest = rest.strip_suffix('"')?;
+            let mut value = String::with_capacity(rest.len());
+            let mut chars = rest.chars();
+            while let Some(c) = chars.next() {
+                match c {
+                    '\\' => value.push(chars.next().filter(|c| matches!(c, '"' | '\\'))?),
+                    '"' => return None,
+                    c => value.push(c),
+                }
+            }
+            Some(value)
+        }
+        None if !item.is_empty() => Some(item.to_owned()),
+        None => None,
+    }
+}
+
+/// Whether the value can be written as a structured field token.
+fn is_token(value: &str) -> bool {
+    let mut chars = value.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '*')
+        && chars.all(|c| c.is_ascii_alphanumeric() || "!#$%&'*+-.^_`|~:/".contains(c))
+}
+
+/// Write the value as a structured field string,
+/// replacing characters which cannot be represented.
+fn write_sf_string(f: &mut fmt::Formatter<'_>, value: &str) -> fmt::Result {
+    f.write_str("\"")?;
+    for c in value.chars() {
+        match c {
+            '"' | '\\' => write!(f, "\\{c}")?,
+            ' '..='~' => write!(f, "{c}")?,
+            _ => f.write_str("?")?,
+        }
+    }
+    f.write_str("\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::headers::HeaderMapExt;
+    use crate::HeaderMap;
+
+    #[test]
+    fn test_proxy_status_decode() {
+        let mut headers = HeaderMap::new();
+        headers.append(
+            &PROXY_STATUS,
+            HeaderValue::from_static(r#"origin-proxy; received-status=503, "my, proxy"; error=connection_refused; details="oh \"no\""; foo"#),
+        );
+        headers.append(
+            &PROXY_STATUS,
+            HeaderValue::from_static("edge; error=new_error"),
+        );
+
+        let header: ProxyStatus = headers.typed_get().unwrap();
+        let entries = header.entries();
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0].proxy(), "origin-proxy");
+        assert_eq!(entries[0].received_status(), Some(503));
+        assert_eq!(entries[0].error(), None);
+        assert_eq!(entries[1].proxy(), "my, proxy");
+        assert_eq!(entries[1].error(), Some(&ProxyErrorType::ConnectionRefused));
+        assert_eq!(entries[1].details(), Some(r#"oh "no""#));
+        assert_eq!(
+            entries[2].error(),
+            Some(&ProxyErrorType::Other("new_error".to_owned()))
+        );
+
+        for invalid in ["", "a; received-status=abc", r#""unterminated"#] {
+            let mut headers = HeaderMap::new();
+            headers.insert(&PROXY_STATUS, HeaderValue::from_static(invalid));
+            assert!(headers.typed_get::<ProxyStatus>().is_none(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn test_proxy_status_encode() {
+        let mut header =
+            ProxyStatus::new(ProxyStatusEntry::new("origin").with_received_status(502));
+        header.push(
+            ProxyStatusEntry::new("rama proxy")
+                .with_error(ProxyErrorType::HttpResponseTimeout)
+                .with_next_hop("example.com:443")
+                .with_details("waited \"too\" long"),
+        );
+
+        let mut headers = HeaderMap::new();
+        headers.typed_insert(header.clone());
+        assert_eq!(
+            headers[&PROXY_STATUS],
+            r#"origin; received-status=502, "rama proxy"; error=http_response_timeout; next-hop="example.com:443"; details="waited \"too\" long""#
+        );
+        assert_eq!(headers.typed_get::<ProxyStatus>(), Some(header));
+    }
+}
@@ -0,0 +1,108 @@
+use crate::{header, HeaderMap, HeaderValue, StatusCode};
+use std::{fmt, sync::Arc};
+
+#[derive(Clone)]
+/// Sends the headers of a `103 Early Hints` interim response,
+/// ahead of the final response.
+///
+/// Inserted in the request extensions by a transport (or service)
+/// which supports sending interim responses. Handlers (and middleware)
+/// use it to emit early hints while they are still preparing the final response.
+///
+/// The http client forwards the `103 Early Hints` received from the upstream
+/// to the [`EarlyHintsSender`] found in the request extensions, if any,
+/// such that a proxy can pass them on to its own client.
+pub struct EarlyHintsSender(Arc<dyn Fn(HeaderMap) + Send + Sync + 'static>);
+
+impl EarlyHintsSender {
+    /// Create a new [`EarlyHintsSender`] from the given function,
+    /// which is called with the headers of each `103 Early Hints` response to be sent.
+    pub fn new(f: impl Fn(HeaderMap) + Send + Sync + 'static) -> Self {
+        Self(Arc::new(f))
+    }
+
+    /// Send a `103 Early Hints` response with the given headers.
+    pub fn send(&self, headers: HeaderMap) {
+        (self.0)(headers)
+    }
+
+    /// Send a `103 Early Hints` response with the given `Link` values
+    /// (e.g. `</style.css>; rel=preload; as=style`).
+    ///
+    /// Nothing is sent in case no links are given.
+    pub fn send_links(&self, links: impl IntoIterator<Item = HeaderValue>) {
+        let mut headers = HeaderMap::new();
+        for link in links {
+            headers.append(header::LINK, link);
+        }
+        if !headers.is_empty() {
+            self.send(headers);
+        }
+    }
+}
+
+impl fmt::Debug for EarlyHintsSender {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("EarlyHintsSender").finish()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// An informational (`1xx`) interim response,
+/// received ahead of the final response.
+pub struct InformationalResponse {
+    status: StatusCode,
+    headers: HeaderMap,
+}
+
+impl InformationalResponse {
+    /// Create a new [`InformationalResponse`].
+    pub fn new(status: StatusCode, headers: HeaderMap) -> Self {
+        Self { status, headers }
+    }
+
+    /// The status of the interim response (e.g. `103 Early Hints`).
+    pub fn status(&self) -> StatusCode {
+        self.status
+    }
+
+    /// The headers of the interim response.
+    pub fn headers(&self) -> &HeaderMap {
+        &self.headers
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+/// The informational (`1xx`) interim responses received ahead of a final response,
+/// in the order they were received.
+///
+/// Inserted in the response extensions by the http client,
+/// in case the upstream sent any interim responses.
+pub struct InformationalResponses(Vec<InformationalResponse>);
+
+impl InformationalResponses {
+    /// Create a new [`InformationalResponses`] from the given responses.
+    pub fn new(responses: Vec<InformationalResponse>) -> Self {
+        Self(responses)
+    }
+
+    /// Iterate over the interim responses, in the order they were received.
+    pub fn iter(&self) -> impl Iterator<Item = &InformationalResponse> {
+        self.0.iter()
+    }
+
+    /// Iterate over the `103 Early Hints` interim responses.
+    pub fn early_hints(&self) -> impl Iterator<Item = &InformationalResponse> {
+        self.0.iter().filter(|res| res.status.as_u16() == 103)
+    }
+
+    /// Returns the amount of interim responses.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Returns `true` in case there are no interim responses.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
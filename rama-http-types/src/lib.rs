@@ -26,6 +26,12 @@ pub use body_limit::BodyLimit;
 mod body_ext;
 pub use body_ext::BodyExtractExt;
 
+mod header_order;
+pub use header_order::OriginalHeaderOrder;
+
+mod interim;
+pub use interim::{EarlyHintsSender, InformationalResponse, InformationalResponses};
+
 /// Type alias for [`http::Request`] whose body type
 /// defaults to [`Body`], the most common body type used with rama.
 pub type Request<T = Body> = http::Request<T>;
@@ -118,7 +124,7 @@ pub mod header {
     static_header!["x-forwarded-host", "x-forwarded-for", "x-forwarded-proto",];
 
     // standard
-    static_header!["keep-alive", "proxy-connection", "via",];
+    static_header!["keep-alive", "proxy-connection", "proxy-status", "via",];
 
     // non-std client ip forward headers
     static_header![
@@ -78,6 +78,9 @@ pub use rama_http_types::headers::{
     StrictTransportSecurity, Te, TransferEncoding, Upgrade, UserAgent, Vary,
 };
 
+#[doc(inline)]
+pub use rama_http_types::headers::{ProxyErrorType, ProxyStatus, ProxyStatusEntry};
+
 mod common;
 #[doc(inline)]
 pub use common::Accept;
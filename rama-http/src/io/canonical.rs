@@ -0,0 +1,162 @@
+use crate::{
+    dep::{http::request::Parts, http_body, http_body_util::BodyExt},
+    Body, Request,
+};
+use bytes::Bytes;
+use rama_core::error::BoxError;
+use std::fmt::Write as _;
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+/// Write an HTTP request to a writer in a canonical and stable format,
+/// such that the output of equivalent requests can be compared (e.g. in snapshot tests).
+///
+/// Compared to [`write_http_request`] the output differs as follows:
+///
+/// - the request line contains the full uri, with the default scheme (`http`)
+///   and port made explicit, and the authority taken from the `Host` header if needed;
+/// - query parameters are sorted by name (keeping the order of repeated parameters);
+/// - headers are sorted by name (keeping the order of repeated headers),
+///   with non-utf-8 values written escaped;
+/// - lines end with `\n` instead of `\r\n`, and the output always ends with a newline.
+///
+/// [`write_http_request`]: super::write_http_request
+pub async fn write_canonical_http_request<W, B>(
+    w: &mut W,
+    req: Request<B>,
+) -> Result<Request, BoxError>
+where
+    W: AsyncWrite + Unpin + Send + Sync + 'static,
+    B: http_body::Body<Data = Bytes, Error: Into<BoxError>> + Send + Sync + 'static,
+{
+    let (parts, body) = req.into_parts();
+    let body = body.collect().await.map_err(Into::into)?.to_bytes();
+
+    w.write_all(canonical_http_request_head(&parts).as_bytes())
+        .await?;
+    w.write_all(b"\n").await?;
+    if !body.is_empty() {
+        w.write_all(body.as_ref()).await?;
+        if !body.ends_with(b"\n") {
+            w.write_all(b"\n").await?;
+        }
+    }
+
+    Ok(Request::from_parts(parts, Body::from(body)))
+}
+
+fn canonical_http_request_head(parts: &Parts) -> String {
+    let mut head = String::new();
+
+    let scheme = parts.uri.scheme_str().unwrap_or("http");
+    let (host, port) = match parts.uri.authority() {
+        Some(authority) => (Some(authority.host()), authority.port_u16()),
+        None => match parts
+            .headers
+            .get(crate::header::HOST)
+            .and_then(|host| host.to_str().ok())
+        {
+            Some(host) => match host
+                .rsplit_once(':')
+                .and_then(|(name, port)| Some((name, port.parse::<u16>().ok()?)))
+            {
+                Some((name, port)) => (Some(name), Some(port)),
+                None => (Some(host), None),
+            },
+            None => (None, None),
+        },
+    };
+    let _ = write!(head, "{} ", parts.method);
+    if let Some(host) = host {
+        let port = port.unwrap_or(match scheme {
+            "https" | "wss" => 443,
+            _ => 80,
+        });
+        let _ = write!(head, "{scheme}://{host}:{port}");
+    }
+
+    head.push_str(parts.uri.path());
+    if let Some(query) = parts.uri.query().filter(|query| !query.is_empty()) {
+        let mut params: Vec<_> = query.split('&').collect();
+        // stable sort, such that the order of repeated parameters is preserved
+        params.sort_by_key(|param| param.split_once('=').map_or(*param, |(name, _)| name));
+        head.push('?');
+        head.push_str(&params.join("&"));
+    }
+    let _ = writeln!(head, " {:?}", parts.version);
+
+    let mut names: Vec<_> = parts.headers.keys().collect();
+    names.sort_by(|a, b| a.as_str().cmp(b.as_str()));
+    for name in names {
+        for value in parts.headers.get_all(name) {
+            match value.to_str() {
+                Ok(value) => {
+                    let _ = writeln!(head, "{name}: {value}");
+                }
+                Err(_) => {
+                    let _ = writeln!(head, "{name}: {value:?}");
+                }
+            }
+        }
+    }
+
+    head
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn canonical(req: Request) -> String {
+        let mut buf = Vec::new();
+        write_canonical_http_request(&mut buf, req).await.unwrap();
+        String::from_utf8(buf).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_write_canonical_http_request() {
+        let req = Request::builder()
+            .method("POST")
+            .uri("http://example.com/foo?b=2&a=1&b=1")
+            .header("user-agent", "test/0")
+            .header("accept", "*/*")
+            .header("x-foo", "1")
+            .header("x-foo", "0")
+            .body(Body::from("hello"))
+            .unwrap();
+        assert_eq!(
+            canonical(req).await,
+            "POST http://example.com:80/foo?a=1&b=2&b=1 HTTP/1.1\n\
+             accept: */*\n\
+             user-agent: test/0\n\
+             x-foo: 1\n\
+             x-foo: 0\n\
+             \n\
+             hello\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_write_canonical_http_request_defaults() {
+        let a = Request::builder()
+            .uri("https://example.com?q=rama&lang=rust")
+            .header("a", "1")
+            .header("b", "2")
+            .body(Body::empty())
+            .unwrap();
+        let b = Request::builder()
+            .uri("/?lang=rust&q=rama")
+            .header("b", "2")
+            .header("host", "example.com:443")
+            .header("a", "1")
+            .body(Body::empty())
+            .unwrap();
+        assert_eq!(
+            canonical(a).await,
+            "GET https://example.com:443/?lang=rust&q=rama HTTP/1.1\na: 1\nb: 2\n\n"
+        );
+        assert_eq!(
+            canonical(b).await,
+            "GET http://example.com:443/?lang=rust&q=rama HTTP/1.1\na: 1\nb: 2\nhost: example.com:443\n\n"
+        );
+    }
+}
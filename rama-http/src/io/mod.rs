@@ -1,9 +1,14 @@
-//! http I/O utilities, e.g. writing http requests/responses in std http format.
+//! http I/O utilities, e.g. writing http requests/responses in std http format,
+//! or requests in a canonical format.
 
 mod request;
 #[doc(inline)]
 pub use request::write_http_request;
 
+mod canonical;
+#[doc(inline)]
+pub use canonical::write_canonical_http_request;
+
 mod response;
 #[doc(inline)]
 pub use response::write_http_response;
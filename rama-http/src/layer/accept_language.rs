@@ -0,0 +1,428 @@
+//! Middleware to negotiate the language of a response,
+//! based on the `Accept-Language` request header.
+//!
+//! The [`AcceptLanguageLayer`] picks the best match between the language ranges
+//! accepted by the client and the languages supported by the service,
+//! falling back to the default (first) language if there is no match.
+//! The negotiated [`Language`] is inserted in the [`Context`], such that it can be used
+//! by handlers and templates (e.g. error pages) further down the stack.
+//!
+//! Optionally the `Content-Language` response header is set to the negotiated language,
+//! in which case `Accept-Language` is added to the `Vary` response header as well.
+//!
+//! The [`negotiate_language`] function can be used directly
+//! in case you want to negotiate the language without this middleware.
+//!
+//! See [RFC 9110, section 12.5.4] and [RFC 4647] for more information.
+//!
+//! [RFC 9110, section 12.5.4]: https://www.rfc-editor.org/rfc/rfc9110.html#section-12.5.4
+//! [RFC 4647]: https://www.rfc-editor.org/rfc/rfc4647.html
+//!
+//! # Example
+//!
+//! ```
+//! use rama_http::layer::accept_language::{AcceptLanguageLayer, Language};
+//! use rama_http::{Body, Request, Response};
+//! use rama_core::service::service_fn;
+//! use rama_core::{Context, Service, Layer};
+//! use rama_core::error::BoxError;
+//! use std::convert::Infallible;
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), BoxError> {
+//! let service = AcceptLanguageLayer::new("en")
+//!     .with_language("nl")
+//!     .with_language("fr-BE")
+//!     .content_language(true)
+//!     .layer(service_fn(|ctx: Context<()>, _req: Request| async move {
+//!         let greeting = match ctx.get::<Language>().unwrap().as_str() {
+//!             "nl" => "Hallo",
+//!             "fr-BE" => "Bonjour",
+//!             _ => "Hello",
+//!         };
+//!         Ok::<_, Infallible>(Response::new(Body::from(greeting)))
+//!     }));
+//!
+//! let req = Request::builder()
+//!     .header("accept-language", "fr;q=0.9, nl-BE;q=0.5")
+//!     .body(Body::empty())
+//!     .unwrap();
+//! let resp = service.serve(Context::default(), req).await?;
+//! assert_eq!(resp.headers()["content-language"], "fr-BE");
+//! assert_eq!(resp.headers()["vary"], "accept-language");
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{
+    header::{ACCEPT_LANGUAGE, CONTENT_LANGUAGE, VARY},
+    layer::util::quality::quality_values,
+    HeaderMap, HeaderValue, Request, Response,
+};
+use rama_core::{Context, Layer, Service};
+use rama_utils::macros::define_inner_service_accessors;
+use std::{fmt, sync::Arc};
+
+/// A language tag (e.g. `en` or `nl-BE`), as negotiated by the [`AcceptLanguageService`].
+///
+/// Inserted in the [`Context`] by the [`AcceptLanguageService`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Language(Arc<str>);
+
+impl Language {
+    /// Create a new [`Language`] from the given language tag.
+    pub fn new(tag: impl AsRef<str>) -> Self {
+        Self(tag.as_ref().into())
+    }
+
+    /// Returns the language tag as a string slice.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Returns the primary language subtag, e.g. `nl` for `nl-BE`.
+    pub fn primary(&self) -> &str {
+        self.0.split('-').next().unwrap_or_default()
+    }
+}
+
+impl AsRef<str> for Language {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for Language {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl From<&str> for Language {
+    fn from(tag: &str) -> Self {
+        Self::new(tag)
+    }
+}
+
+impl From<String> for Language {
+    fn from(tag: String) -> Self {
+        Self(tag.into())
+    }
+}
+
+/// Negotiate the best matching language out of the `supported` languages,
+/// based on the `Accept-Language` header(s) found in the given headers.
+///
+/// Language ranges are tried in order of preference (q-value), where each range is matched:
+///
+/// 1. exactly (case-insensitive);
+/// 2. as a prefix of a supported language (e.g. `en` matches `en-US`);
+/// 3. by truncating the range (e.g. `en-US` matches `en`).
+///
+/// The wildcard range (`*`) matches the first supported language, while (non-wildcard)
+/// ranges with a q-value of `0` exclude the languages they match.
+///
+/// Returns `None` if no supported language is accepted.
+pub fn negotiate_language<'a, L: AsRef<str>>(
+    headers: &HeaderMap,
+    supported: &'a [L],
+) -> Option<&'a L> {
+    let mut ranges: Vec<_> = quality_values(headers, &ACCEPT_LANGUAGE).collect();
+    // stable sort, such that ranges with equal q-values keep their order
+    ranges.sort_by(|(_, a), (_, b)| b.cmp(a));
+
+    let excluded = |language: &str| {
+        ranges.iter().any(|(range, qvalue)| {
+            qvalue.is_zero() && *range != "*" && range_matches_prefix(range, language)
+        })
+    };
+    let candidates = || {
+        supported
+            .iter()
+            .filter(|language| !excluded(language.as_ref()))
+    };
+
+    ranges
+        .iter()
+        .filter(|(_, qvalue)| !qvalue.is_zero())
+        .find_map(|(range, _)| {
+            if *range == "*" {
+                return candidates().next();
+            }
+            candidates()
+                .find(|language| language.as_ref().eq_ignore_ascii_case(range))
+                .or_else(|| {
+                    candidates().find(|language| range_matches_prefix(range, language.as_ref()))
+                })
+                .or_else(|| {
+                    candidates().find(|language| range_matches_prefix(language.as_ref(), range))
+                })
+        })
+}
+
+/// Returns `true` if `tag` equals `prefix` or starts with `prefix` followed by a `-`,
+/// comparing case-insensitively.
+fn range_matches_prefix(prefix: &str, tag: &str) -> bool {
+    match tag.get(..prefix.len()) {
+        Some(head) if head.eq_ignore_ascii_case(prefix) => {
+            matches!(tag.as_bytes().get(prefix.len()), None | Some(b'-'))
+        }
+        _ => false,
+    }
+}
+
+/// Layer that applies the [`AcceptLanguageService`] middleware.
+///
+/// See the [module docs](self) for more details.
+#[derive(Debug, Clone)]
+pub struct AcceptLanguageLayer {
+    languages: Vec<Language>,
+    content_language: bool,
+}
+
+impl AcceptLanguageLayer {
+    /// Create a new [`AcceptLanguageLayer`], using the given language
+    /// as the default (and so far only) supported language.
+    pub fn new(default: impl Into<Language>) -> Self {
+        Self {
+            languages: vec![default.into()],
+            content_language: false,
+        }
+    }
+
+    /// Add a supported language.
+    ///
+    /// Languages added first are preferred in case of a tie.
+    pub fn with_language(mut self, language: impl Into<Language>) -> Self {
+        self.languages.push(language.into());
+        self
+    }
+
+    /// Add a supported language.
+    ///
+    /// Languages added first are preferred in case of a tie.
+    pub fn set_language(&mut self, language: impl Into<Language>) -> &mut Self {
+        self.languages.push(language.into());
+        self
+    }
+
+    /// Set the `Content-Language` header of responses to the negotiated language,
+    /// unless already set by the inner service.
+    ///
+    /// Disabled by default.
+    pub fn content_language(mut self, enabled: bool) -> Self {
+        self.content_language = enabled;
+        self
+    }
+
+    /// Set the `Content-Language` header of responses to the negotiated language,
+    /// unless already set by the inner service.
+    ///
+    /// Disabled by default.
+    pub fn set_content_language(&mut self, enabled: bool) -> &mut Self {
+        self.content_language = enabled;
+        self
+    }
+}
+
+impl<S> Layer<S> for AcceptLanguageLayer {
+    type Service = AcceptLanguageService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        AcceptLanguageService {
+            inner,
+            languages: self.languages.clone().into(),
+            content_language: self.content_language,
+        }
+    }
+}
+
+/// Middleware which negotiates the response [`Language`]
+/// and inserts it in the [`Context`].
+///
+/// See the [module docs](self) for more details.
+pub struct AcceptLanguageService<S> {
+    inner: S,
+    languages: Arc<[Language]>,
+    content_language: bool,
+}
+
+impl<S> AcceptLanguageService<S> {
+    /// Create a new [`AcceptLanguageService`], using the given language
+    /// as the default (and so far only) supported language.
+    pub fn new(inner: S, default: impl Into<Language>) -> Self {
+        AcceptLanguageLayer::new(default).layer(inner)
+    }
+
+    define_inner_service_accessors!();
+}
+
+impl<S: fmt::Debug> fmt::Debug for AcceptLanguageService<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AcceptLanguageService")
+            .field("inner", &self.inner)
+            .field("languages", &self.languages)
+            .field("content_language", &self.content_language)
+            .finish()
+    }
+}
+
+impl<S: Clone> Clone for AcceptLanguageService<S> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            languages: self.languages.clone(),
+            content_language: self.content_language,
+        }
+    }
+}
+
+impl<State, S, ReqBody, ResBody> Service<State, Request<ReqBody>> for AcceptLanguageService<S>
+where
+    State: Clone + Send + Sync + 'static,
+    S: Service<State, Request<ReqBody>, Response = Response<ResBody>>,
+    ReqBody: Send + 'static,
+    ResBody: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+
+    async fn serve(
+        &self,
+        mut ctx: Context<State>,
+        req: Request<ReqBody>,
+    ) -> Result<Self::Response, Self::Error> {
+        let language = negotiate_language(req.headers(), &self.languages)
+            .unwrap_or(&self.languages[0])
+            .clone();
+        ctx.insert(language.clone());
+
+        let mut res = self.inner.serve(ctx, req).await?;
+
+        if self.content_language {
+            let headers = res.headers_mut();
+            if !headers.contains_key(CONTENT_LANGUAGE) {
+                if let Ok(value) = HeaderValue::from_str(language.as_str()) {
+                    headers.insert(CONTENT_LANGUAGE, value);
+                }
+            }
+            headers.append(VARY, HeaderValue::from_static("accept-language"));
+        }
+
+        Ok(res)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dep::http_body_util::BodyExt;
+    use crate::Body;
+    use rama_core::service::service_fn;
+    use std::convert::Infallible;
+
+    fn accept_language(value: &'static str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(ACCEPT_LANGUAGE, HeaderValue::from_static(value));
+        headers
+    }
+
+    #[test]
+    fn test_negotiate_language() {
+        let supported = ["en", "nl-BE", "fr", "de-DE"];
+        let test_cases = [
+            ("", None),
+            ("en", Some("en")),
+            ("EN-us", Some("en")),
+            ("nl", Some("nl-BE")),
+            ("fr-CA, nl;q=0.9", Some("fr")),
+            ("es, de;q=0.1, nl;q=0.2", Some("nl-BE")),
+            ("es, it", None),
+            ("*", Some("en")),
+            ("*, en;q=0", Some("nl-BE")),
+            ("fr;q=0.5, nl-be;q=0.5", Some("fr")),
+            ("nl-BE;q=0.001, de-de;q=1.0", Some("de-DE")),
+            ("nl-BE;q=2", None),
+            ("de, *;q=0", Some("de-DE")),
+        ];
+        for (header, expected) in test_cases {
+            let headers = if header.is_empty() {
+                HeaderMap::new()
+            } else {
+                accept_language(header)
+            };
+            assert_eq!(
+                negotiate_language(&headers, &supported).copied(),
+                expected,
+                "header: {header}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_language() {
+        let language = Language::from("nl-BE");
+        assert_eq!(language.as_str(), "nl-BE");
+        assert_eq!(language.primary(), "nl");
+        assert_eq!(language.to_string(), "nl-BE");
+        assert_eq!(Language::from("en").primary(), "en");
+    }
+
+    #[tokio::test]
+    async fn test_accept_language_service() {
+        let service = AcceptLanguageLayer::new("en")
+            .with_language("nl")
+            .layer(service_fn(|ctx: Context<()>, _req: Request| async move {
+                let language = ctx.get::<Language>().unwrap().to_string();
+                Ok::<_, Infallible>(Response::new(Body::from(language)))
+            }));
+
+        for (header, expected) in [(None, "en"), (Some("es"), "en"), (Some("nl-NL"), "nl")] {
+            let mut req = Request::builder();
+            if let Some(header) = header {
+                req = req.header(ACCEPT_LANGUAGE, header);
+            }
+            let resp = service
+                .serve(Context::default(), req.body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+            assert!(!resp.headers().contains_key(CONTENT_LANGUAGE));
+            let body = resp.into_body().collect().await.unwrap().to_bytes();
+            assert_eq!(body, expected);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_accept_language_service_content_language() {
+        let service = AcceptLanguageLayer::new("en")
+            .with_language("nl")
+            .content_language(true)
+            .layer(service_fn(|req: Request| async move {
+                let mut resp = Response::new(Body::empty());
+                if req.uri().path() == "/de" {
+                    resp.headers_mut()
+                        .insert(CONTENT_LANGUAGE, HeaderValue::from_static("de"));
+                }
+                resp.headers_mut()
+                    .insert(VARY, HeaderValue::from_static("accept-encoding"));
+                Ok::<_, Infallible>(resp)
+            }));
+
+        let req = Request::builder()
+            .header(ACCEPT_LANGUAGE, "nl")
+            .body(Body::empty())
+            .unwrap();
+        let resp = service.serve(Context::default(), req).await.unwrap();
+        assert_eq!(resp.headers()[CONTENT_LANGUAGE], "nl");
+        let vary: Vec<_> = resp.headers().get_all(VARY).iter().collect();
+        assert_eq!(vary, ["accept-encoding", "accept-language"]);
+
+        let req = Request::builder()
+            .uri("/de")
+            .header(ACCEPT_LANGUAGE, "nl")
+            .body(Body::empty())
+            .unwrap();
+        let resp = service.serve(Context::default(), req).await.unwrap();
+        assert_eq!(resp.headers()[CONTENT_LANGUAGE], "de");
+    }
+}
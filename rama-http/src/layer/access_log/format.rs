@@ -0,0 +1,481 @@
+use crate::HeaderName;
+use rama_core::context::LogFieldSet;
+use rama_core::error::OpaqueError;
+use serde_json::{Number, Value};
+use std::{fmt, str::FromStr};
+
+/// Template of the [Common Log Format](https://en.wikipedia.org/wiki/Common_Log_Format).
+const COMMON_TEMPLATE: &str =
+    r#"{remote_addr} - {user} [{time}] "{request_line}" {status} {bytes}"#;
+
+/// Template of the Combined Log Format, the Common Log Format with the referer and user agent.
+const COMBINED_TEMPLATE: &str = r#"{remote_addr} - {user} [{time}] "{request_line}" {status} {bytes} "{referer}" "{user_agent}""#;
+
+/// Fields logged by [`AccessLogFormat::json`].
+const JSON_FIELDS: &[AccessLogField] = &[
+    AccessLogField::Timestamp,
+    AccessLogField::RemoteAddr,
+    AccessLogField::User,
+    AccessLogField::Method,
+    AccessLogField::Uri,
+    AccessLogField::Version,
+    AccessLogField::Status,
+    AccessLogField::Bytes,
+    AccessLogField::Latency,
+    AccessLogField::Host,
+    AccessLogField::TlsSni,
+    AccessLogField::Referer,
+    AccessLogField::UserAgent,
+    AccessLogField::Extensions,
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// A field which can be logged by the [`AccessLogLayer`].
+///
+/// Fields of which the value is not known are logged as `-`
+/// (or `null` in the json format).
+///
+/// [`AccessLogLayer`]: super::AccessLogLayer
+pub enum AccessLogField {
+    /// `remote_addr`: the address of the peer, as found in the [`SocketInfo`].
+    ///
+    /// [`SocketInfo`]: rama_net::stream::SocketInfo
+    RemoteAddr,
+    /// `interface`: the name of the network interface the peer connected on,
+    /// as found in the [`SocketInfo`].
+    ///
+    /// [`SocketInfo`]: rama_net::stream::SocketInfo
+    Interface,
+    /// `transport`: the transport protocol of the connection (`tcp` or `udp`),
+    /// as found in the [`SocketInfo`].
+    ///
+    /// [`SocketInfo`]: rama_net::stream::SocketInfo
+    Transport,
+    /// `user`: the username of the authenticated (proxy) user, as found in the [`UserId`].
+    ///
+    /// [`UserId`]: rama_net::user::UserId
+    User,
+    /// `time`: the time the request was received, in the Common Log Format,
+    /// e.g. `10/Oct/2000:13:55:36 +0000`.
+    Time,
+    /// `timestamp`: the time the request was received, in seconds since the unix epoch.
+    Timestamp,
+    /// `method`: the method of the request.
+    Method,
+    /// `uri`: the uri of the request.
+    Uri,
+    /// `path`: the path of the request uri.
+    Path,
+    /// `version`: the http version of the request, e.g. `HTTP/1.1`.
+    Version,
+    /// `request_line`: the method, uri and version of the request, e.g. `GET / HTTP/1.1`.
+    RequestLine,
+    /// `status`: the status code of the response.
+    Status,
+    /// `bytes`: the amount of bytes of the response body which were sent.
+    Bytes,
+    /// `request_bytes`: the content length of the request body.
+    RequestBytes,
+    /// `latency_ms`: the time between receiving the request
+    /// and sending the last byte of the response, in milliseconds.
+    Latency,
+    /// `ttfb_ms`: the time between receiving the request
+    /// and returning the response head, in milliseconds.
+    Ttfb,
+    /// `host`: the host of the request, as found in the [`RequestContext`].
+    ///
+    /// [`RequestContext`]: rama_net::http::RequestContext
+    Host,
+    /// `upstream`: the authority of the upstream proxy (as found in the [`ProxyAddress`])
+    /// or otherwise the authority the request is sent to (as found in the [`RequestContext`]).
+    ///
+    /// [`ProxyAddress`]: rama_net::address::ProxyAddress
+    /// [`RequestContext`]: rama_net::http::RequestContext
+    Upstream,
+    /// `tls_sni`: the server name indicated by the client in its tls client hello,
+    /// only available with the `tls` feature enabled
+    /// and if the `SecureTransport` contains the client hello.
+    TlsSni,
+    /// `referer`: the `Referer` header of the request.
+    Referer,
+    /// `user_agent`: the `User-Agent` header of the request.
+    UserAgent,
+    /// `extensions`: the [`LogFields`] of all registered extensions found in the [`Context`],
+    /// e.g. the [`RequestId`] and [`UserId`], rendered in the logfmt style
+    /// (or as an object in the json format).
+    ///
+    /// [`LogFields`]: rama_core::context::LogFields
+    /// [`Context`]: rama_core::Context
+    /// [`RequestId`]: crate::layer::request_id::RequestId
+    /// [`UserId`]: rama_net::user::UserId
+    Extensions,
+    /// `ext:<name>`: the given field of the [`LogFields`] of the registered extensions.
+    ///
+    /// [`LogFields`]: rama_core::context::LogFields
+    Extension(String),
+    /// `req_header:<name>`: the given header of the request.
+    RequestHeader(HeaderName),
+    /// `resp_header:<name>`: the given header of the response.
+    ResponseHeader(HeaderName),
+}
+
+impl fmt::Display for AccessLogField {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::RemoteAddr => f.write_str("remote_addr"),
+            Self::Interface => f.write_str("interface"),
+            Self::Transport => f.write_str("transport"),
+            Self::User => f.write_str("user"),
+            Self::Time => f.write_str("time"),
+            Self::Timestamp => f.write_str("timestamp"),
+            Self::Method => f.write_str("method"),
+            Self::Uri => f.write_str("uri"),
+            Self::Path => f.write_str("path"),
+            Self::Version => f.write_str("version"),
+            Self::RequestLine => f.write_str("request_line"),
+            Self::Status => f.write_str("status"),
+            Self::Bytes => f.write_str("bytes"),
+            Self::RequestBytes => f.write_str("request_bytes"),
+            Self::Latency => f.write_str("latency_ms"),
+            Self::Ttfb => f.write_str("ttfb_ms"),
+            Self::Host => f.write_str("host"),
+            Self::Upstream => f.write_str("upstream"),
+            Self::TlsSni => f.write_str("tls_sni"),
+            Self::Referer => f.write_str("referer"),
+            Self::UserAgent => f.write_str("user_agent"),
+            Self::Extensions => f.write_str("extensions"),
+            Self::Extension(name) => write!(f, "ext:{name}"),
+            Self::RequestHeader(name) => write!(f, "req_header:{name}"),
+            Self::ResponseHeader(name) => write!(f, "resp_header:{name}"),
+        }
+    }
+}
+
+impl FromStr for AccessLogField {
+    type Err = OpaqueError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let field = match s.trim() {
+            "remote_addr" => Self::RemoteAddr,
+            "interface" => Self::Interface,
+            "transport" => Self::Transport,
+            "user" => Self::User,
+            "time" => Self::Time,
+            "timestamp" => Self::Timestamp,
+            "method" => Self::Method,
+            "uri" => Self::Uri,
+            "path" => Self::Path,
+            "version" => Self::Version,
+            "request_line" => Self::RequestLine,
+            "status" => Self::Status,
+            "bytes" => Self::Bytes,
+            "request_bytes" => Self::RequestBytes,
+            "latency_ms" => Self::Latency,
+            "ttfb_ms" => Self::Ttfb,
+            "host" => Self::Host,
+            "upstream" => Self::Upstream,
+            "tls_sni" => Self::TlsSni,
+            "referer" => Self::Referer,
+            "user_agent" => Self::UserAgent,
+            "extensions" => Self::Extensions,
+            s if s.starts_with("ext:") => {
+                let name = s["ext:".len()..].trim();
+                if name.is_empty() {
+                    return Err(OpaqueError::from_display(format!(
+                        "missing extension field name in access log field: '{s}'"
+                    )));
+                }
+                Self::Extension(name.to_owned())
+            }
+            s => {
+                let (header_field, name): (fn(HeaderName) -> Self, _) =
+                    if let Some(name) = s.strip_prefix("req_header:") {
+                        (Self::RequestHeader, name)
+                    } else if let Some(name) = s.strip_prefix("resp_header:") {
+                        (Self::ResponseHeader, name)
+                    } else {
+                        return Err(OpaqueError::from_display(format!(
+                            "unknown access log field: '{s}'"
+                        )));
+                    };
+                let name = HeaderName::from_str(name.trim()).map_err(|_| {
+                    OpaqueError::from_display(format!(
+                        "invalid header name in access log field: '{s}'"
+                    ))
+                })?;
+                header_field(name)
+            }
+        };
+        Ok(field)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// The value of an [`AccessLogField`] for a single request.
+pub(super) enum FieldValue {
+    Str(String),
+    Uint(u64),
+    Millis(f64),
+    Fields(LogFieldSet),
+}
+
+impl FieldValue {
+    fn to_json(&self) -> Value {
+        match self {
+            Self::Str(s) => Value::String(s.clone()),
+            Self::Uint(n) => Value::Number((*n).into()),
+            Self::Millis(ms) => Number::from_f64((ms * 1000.).round() / 1000.)
+                .map(Value::Number)
+                .unwrap_or(Value::Null),
+            Self::Fields(fields) => Value::Object(
+                fields
+                    .iter()
+                    .map(|(name, value)| (name.to_owned(), Value::String(value.to_owned())))
+                    .collect(),
+            ),
+        }
+    }
+}
+
+impl fmt::Display for FieldValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Str(s) => f.write_str(s),
+            Self::Uint(n) => write!(f, "{n}"),
+            Self::Millis(ms) => write!(f, "{ms:.3}"),
+            Self::Fields(fields) => fields.fmt(f),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Segment {
+    Literal(String),
+    Field(usize),
+}
+
+#[derive(Debug, Clone)]
+enum Kind {
+    Template(Vec<Segment>),
+    Json,
+}
+
+#[derive(Debug, Clone)]
+/// The format of the lines logged by the [`AccessLogLayer`].
+///
+/// Formats can be created for the Common and Combined Log Format,
+/// json lines, or from a template, in which the fields are
+/// referred to by their name between curly braces, e.g.
+/// `{method} {uri} {status} {latency_ms}ms`. Curly braces
+/// can be escaped by doubling them (`{{` and `}}`).
+///
+/// The same formats can be parsed from a string, which is either
+/// `common`, `combined`, `json` or a template.
+///
+/// See [`AccessLogField`] for the available fields.
+///
+/// [`AccessLogLayer`]: super::AccessLogLayer
+pub struct AccessLogFormat {
+    fields: Vec<AccessLogField>,
+    kind: Kind,
+}
+
+impl Default for AccessLogFormat {
+    fn default() -> Self {
+        Self::combined()
+    }
+}
+
+impl AccessLogFormat {
+    /// The [Common Log Format](https://en.wikipedia.org/wiki/Common_Log_Format).
+    pub fn common() -> Self {
+        Self::template(COMMON_TEMPLATE).expect("valid common log format template")
+    }
+
+    /// The Combined Log Format, which is the Common Log Format
+    /// with the referer and user agent of the request.
+    pub fn combined() -> Self {
+        Self::template(COMBINED_TEMPLATE).expect("valid combined log format template")
+    }
+
+    /// Json lines with the most commonly used fields.
+    pub fn json() -> Self {
+        Self::json_fields(JSON_FIELDS.iter().cloned())
+    }
+
+    /// Json lines with the given fields, using the names of the fields as keys.
+    pub fn json_fields(fields: impl IntoIterator<Item = AccessLogField>) -> Self {
+        Self {
+            fields: fields.into_iter().collect(),
+            kind: Kind::Json,
+        }
+    }
+
+    /// Create a format from the given template, e.g. `{method} {uri} {status}`.
+    pub fn template(template: &str) -> Result<Self, OpaqueError> {
+        let mut fields = Vec::new();
+        let mut segments = Vec::new();
+        let mut literal = String::new();
+
+        let mut chars = template.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.as_str().starts_with('{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.as_str().starts_with('}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '{' => {
+                    let rest = chars.as_str();
+                    let end = rest.find('}').ok_or_else(|| {
+                        OpaqueError::from_display("unclosed field in access log template")
+                    })?;
+                    let field: AccessLogField = rest[..end].parse()?;
+                    chars = rest[end + 1..].chars();
+
+                    if !literal.is_empty() {
+                        segments.push(Segment::Literal(std::mem::take(&mut literal)));
+                    }
+                    let index = fields.iter().position(|f| f == &field).unwrap_or_else(|| {
+                        fields.push(field);
+                        fields.len() - 1
+                    });
+                    segments.push(Segment::Field(index));
+                }
+                '}' => {
+                    return Err(OpaqueError::from_display(
+                        "unexpected '}' in access log template, use '}}' to escape it",
+                    ));
+                }
+                c => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            segments.push(Segment::Literal(literal));
+        }
+
+        Ok(Self {
+            fields,
+            kind: Kind::Template(segments),
+        })
+    }
+
+    /// The fields logged using this format.
+    pub fn fields(&self) -> &[AccessLogField] {
+        &self.fields
+    }
+
+    /// Render a line using the values of the fields, in the same order as [`Self::fields`].
+    pub(super) fn render(&self, values: &[Option<FieldValue>]) -> String {
+        match &self.kind {
+            Kind::Template(segments) => {
+                let mut line = String::new();
+                for segment in segments {
+                    match segment {
+                        Segment::Literal(s) => line.push_str(s),
+                        Segment::Field(index) => match &values[*index] {
+                            Some(value) => {
+                                use std::fmt::Write;
+                                let _ = write!(line, "{value}");
+                            }
+                            None => line.push('-'),
+                        },
+                    }
+                }
+                line
+            }
+            Kind::Json => {
+                // written by hand, such that the fields are kept in order
+                let mut line = String::from("{");
+                for (i, (field, value)) in self.fields.iter().zip(values).enumerate() {
+                    if i > 0 {
+                        line.push(',');
+                    }
+                    line.push_str(&Value::String(field.to_string()).to_string());
+                    line.push(':');
+                    match value {
+                        Some(value) => line.push_str(&value.to_json().to_string()),
+                        None => line.push_str("null"),
+                    }
+                }
+                line.push('}');
+                line
+            }
+        }
+    }
+}
+
+impl FromStr for AccessLogFormat {
+    type Err = OpaqueError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "common" => Ok(Self::common()),
+            "combined" => Ok(Self::combined()),
+            "json" => Ok(Self::json()),
+            template => Self::template(template),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_template_parse_and_render() {
+        let format =
+            AccessLogFormat::template("{{{method}}} {uri} {req_header:x-id} {method}").unwrap();
+        assert_eq!(
+            format.fields(),
+            &[
+                AccessLogField::Method,
+                AccessLogField::Uri,
+                AccessLogField::RequestHeader(HeaderName::from_static("x-id")),
+            ]
+        );
+        let line = format.render(&[Some(FieldValue::Str("GET".to_owned())), None, None]);
+        assert_eq!(line, "{GET} - - GET");
+    }
+
+    #[test]
+    fn test_template_invalid() {
+        for template in ["{method", "{foo}", "method}", "{req_header:}", "{ext:}"] {
+            assert!(AccessLogFormat::template(template).is_err(), "{template}");
+        }
+    }
+
+    #[test]
+    fn test_json_render() {
+        let format = AccessLogFormat::json_fields([
+            AccessLogField::Status,
+            AccessLogField::Latency,
+            AccessLogField::User,
+        ]);
+        let line = format.render(&[
+            Some(FieldValue::Uint(200)),
+            Some(FieldValue::Millis(1.23456)),
+            None,
+        ]);
+        assert_eq!(line, r#"{"status":200,"latency_ms":1.235,"user":null}"#);
+    }
+
+    #[test]
+    fn test_format_from_str() {
+        assert_eq!(
+            "common".parse::<AccessLogFormat>().unwrap().fields(),
+            AccessLogFormat::common().fields()
+        );
+        assert_eq!(
+            "json".parse::<AccessLogFormat>().unwrap().fields(),
+            JSON_FIELDS
+        );
+        assert_eq!(
+            "{status}".parse::<AccessLogFormat>().unwrap().fields(),
+            &[AccessLogField::Status]
+        );
+    }
+}
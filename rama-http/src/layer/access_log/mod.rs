@@ -0,0 +1,607 @@
+//! Middleware that writes an access log line for each request.
+//!
+//! The [`AccessLogLayer`] writes a line for each request once its response
+//! has been sent (or failed), such that the amount of bytes sent and the total
+//! latency are known. The lines are formatted using an [`AccessLogFormat`],
+//! which can be the Common or Combined Log Format, json lines, or a template
+//! of [`AccessLogField`]s, and written to an [`AccessLogSink`], such as
+//! the [`StdoutSink`] or a (rotated) [`FileSink`].
+//!
+//! The time used is the [`Clock`] found in the [`Context`].
+//!
+//! [`Clock`]: rama_core::time::Clock
+//!
+//! # Example
+//!
+//! ```
+//! use rama_http::layer::access_log::{AccessLogBody, AccessLogFormat, AccessLogLayer};
+//! use rama_http::{Body, Request, Response};
+//! use rama_http::dep::http_body_util::BodyExt;
+//! use rama_core::service::service_fn;
+//! use rama_core::{Context, Service, Layer};
+//! use rama_core::error::BoxError;
+//! use std::convert::Infallible;
+//! use std::sync::{Arc, Mutex};
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), BoxError> {
+//! let lines = Arc::new(Mutex::new(Vec::new()));
+//! let sink = {
+//!     let lines = lines.clone();
+//!     move |line: &str| lines.lock().unwrap().push(line.to_owned())
+//! };
+//!
+//! let format = AccessLogFormat::template("{method} {path} {status} {bytes}")?;
+//! let service = AccessLogLayer::new(format, sink).layer(service_fn(|_req: Request| async {
+//!     Ok::<_, Infallible>(Response::new(Body::from("hello")))
+//! }));
+//!
+//! let req = Request::builder()
+//!     .uri("http://example.com/greeting")
+//!     .body(Body::empty())
+//!     .unwrap();
+//! let resp = service.serve(Context::default(), req).await?;
+//! // the line is written once the response body has been sent
+//! resp.into_body().collect().await.unwrap();
+//!
+//! assert_eq!(lines.lock().unwrap().as_slice(), ["GET /greeting 200 5"]);
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::dep::http_body::{Body as HttpBody, Frame, SizeHint};
+use crate::header::{REFERER, USER_AGENT};
+use crate::headers::{ContentLength, HeaderMapExt};
+use crate::layer::util::log_fields::register_default_log_fields;
+use crate::{HeaderMap, Request, Response};
+use futures_lite::ready;
+use pin_project_lite::pin_project;
+use rama_core::time::Clock;
+use rama_core::{Context, Layer, Service};
+use rama_net::address::ProxyAddress;
+use rama_net::http::RequestContext;
+use rama_net::stream::SocketInfo;
+use rama_net::transport::TransportProtocol;
+use rama_net::user::UserId;
+use rama_utils::macros::define_inner_service_accessors;
+use std::{
+    fmt,
+    pin::Pin,
+    sync::Arc,
+    task::{self, Poll},
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+
+mod format;
+use format::FieldValue;
+#[doc(inline)]
+pub use format::{AccessLogField, AccessLogFormat};
+
+mod sink;
+#[doc(inline)]
+pub use sink::{AccessLogSink, FileSink, StdoutSink};
+
+/// Layer that applies the [`AccessLogService`] middleware.
+///
+/// See the [module docs](self) for more details.
+#[derive(Clone)]
+pub struct AccessLogLayer {
+    log: Arc<AccessLog>,
+}
+
+struct AccessLog {
+    format: AccessLogFormat,
+    sink: Box<dyn AccessLogSink>,
+}
+
+impl fmt::Debug for AccessLogLayer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AccessLogLayer")
+            .field("format", &self.log.format)
+            .finish()
+    }
+}
+
+impl AccessLogLayer {
+    /// Create a new [`AccessLogLayer`], writing lines in the given format to the given sink.
+    pub fn new(format: AccessLogFormat, sink: impl AccessLogSink) -> Self {
+        register_default_log_fields();
+        Self {
+            log: Arc::new(AccessLog {
+                format,
+                sink: Box::new(sink),
+            }),
+        }
+    }
+
+    /// Create a new [`AccessLogLayer`], writing lines in the given format to the standard output.
+    pub fn stdout(format: AccessLogFormat) -> Self {
+        Self::new(format, StdoutSink::new())
+    }
+}
+
+impl<S> Layer<S> for AccessLogLayer {
+    type Service = AccessLogService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        AccessLogService {
+            inner,
+            log: self.log.clone(),
+        }
+    }
+}
+
+/// Middleware which writes an access log line for each request.
+///
+/// See the [module docs](self) for more details.
+pub struct AccessLogService<S> {
+    inner: S,
+    log: Arc<AccessLog>,
+}
+
+impl<S> AccessLogService<S> {
+    /// Create a new [`AccessLogService`], writing lines in the given format to the given sink.
+    pub fn new(inner: S, format: AccessLogFormat, sink: impl AccessLogSink) -> Self {
+        AccessLogLayer::new(format, sink).layer(inner)
+    }
+
+    define_inner_service_accessors!();
+}
+
+impl<S: fmt::Debug> fmt::Debug for AccessLogService<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AccessLogService")
+            .field("inner", &self.inner)
+            .field("format", &self.log.format)
+            .finish()
+    }
+}
+
+impl<S: Clone> Clone for AccessLogService<S> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            log: self.log.clone(),
+        }
+    }
+}
+
+impl<State, S, ReqBody, ResBody> Service<State, Request<ReqBody>> for AccessLogService<S>
+where
+    State: Clone + Send + Sync + 'static,
+    S: Service<State, Request<ReqBody>, Response = Response<ResBody>>,
+    ReqBody: Send + 'static,
+    ResBody: Send + 'static,
+{
+    type Response = Response<AccessLogBody<ResBody>>;
+    type Error = S::Error;
+
+    async fn serve(
+        &self,
+        mut ctx: Context<State>,
+        req: Request<ReqBody>,
+    ) -> Result<Self::Response, Self::Error> {
+        let _ = ctx.get_or_try_insert_with_ctx::<RequestContext, _>(|ctx| (ctx, &req).try_into());
+
+        let mut entry = AccessLogEntry::new(self.log.clone(), ctx.clock());
+        entry.record_request(&ctx, &req);
+
+        let resp = self.inner.serve(ctx, req).await?;
+        entry.record_response(&resp);
+
+        Ok(resp.map(|inner| AccessLogBody {
+            inner,
+            entry: Some(entry),
+        }))
+    }
+}
+
+/// The values of a single access log line,
+/// which is written once dropped.
+struct AccessLogEntry {
+    log: Arc<AccessLog>,
+    clock: Clock,
+    start: Instant,
+    values: Vec<Option<FieldValue>>,
+    bytes: u64,
+}
+
+impl AccessLogEntry {
+    fn new(log: Arc<AccessLog>, clock: Clock) -> Self {
+        let values = vec![None; log.format.fields().len()];
+        Self {
+            start: clock.now(),
+            log,
+            clock,
+            values,
+            bytes: 0,
+        }
+    }
+
+    fn record_request<State, Body>(&mut self, ctx: &Context<State>, req: &Request<Body>) {
+        let now = self.clock.system_now();
+        let request_ctx = ctx.get::<RequestContext>();
+        let mut log_fields = None;
+
+        for (field, value) in self.log.format.fields().iter().zip(&mut self.values) {
+            *value = match field {
+                AccessLogField::RemoteAddr => ctx
+                    .get::<SocketInfo>()
+                    .map(|info| FieldValue::Str(info.peer_addr().ip().to_string())),
+                AccessLogField::Interface => ctx
+                    .get::<SocketInfo>()
+                    .and_then(|info| info.interface())
+                    .map(|interface| FieldValue::Str(interface.to_owned())),
+                AccessLogField::Transport => ctx.get::<SocketInfo>().map(|info| {
+                    FieldValue::Str(
+                        match info.protocol() {
+                            TransportProtocol::Tcp => "tcp",
+                            TransportProtocol::Udp => "udp",
+                        }
+                        .to_owned(),
+                    )
+                }),
+                AccessLogField::User => match ctx.get::<UserId>() {
+                    Some(UserId::Username(username)) => Some(FieldValue::Str(username.clone())),
+                    _ => None,
+                },
+                AccessLogField::Time => Some(FieldValue::Str(clf_time(now))),
+                AccessLogField::Timestamp => {
+                    let since_epoch = now.duration_since(UNIX_EPOCH).unwrap_or_default();
+                    Some(FieldValue::Millis(since_epoch.as_secs_f64()))
+                }
+                AccessLogField::Method => Some(FieldValue::Str(req.method().to_string())),
+                AccessLogField::Uri => Some(FieldValue::Str(req.uri().to_string())),
+                AccessLogField::Path => Some(FieldValue::Str(req.uri().path().to_owned())),
+                AccessLogField::Version => Some(FieldValue::Str(format!("{:?}", req.version()))),
+                AccessLogField::RequestLine => Some(FieldValue::Str(format!(
+                    "{} {} {:?}",
+                    req.method(),
+                    req.uri(),
+                    req.version()
+                ))),
+                AccessLogField::RequestBytes => req
+                    .headers()
+                    .typed_get::<ContentLength>()
+                    .map(|ContentLength(length)| FieldValue::Uint(length)),
+                AccessLogField::Host => request_ctx
+                    .map(|request_ctx| FieldValue::Str(request_ctx.authority.host().to_string())),
+                AccessLogField::Upstream => ctx
+                    .get::<ProxyAddress>()
+                    .map(|proxy| proxy.authority.to_string())
+                    .or_else(|| request_ctx.map(|request_ctx| request_ctx.authority.to_string()))
+                    .map(FieldValue::Str),
+                AccessLogField::TlsSni => tls_sni(ctx).map(FieldValue::Str),
+                AccessLogField::Referer => header_value(req.headers(), &REFERER),
+                AccessLogField::UserAgent => header_value(req.headers(), &USER_AGENT),
+                AccessLogField::Extensions => {
+                    let fields = log_fields.get_or_insert_with(|| ctx.log_fields());
+                    (!fields.is_empty()).then(|| FieldValue::Fields(fields.clone()))
+                }
+                AccessLogField::Extension(name) => log_fields
+                    .get_or_insert_with(|| ctx.log_fields())
+                    .get(name)
+                    .map(|value| FieldValue::Str(value.to_owned())),
+                AccessLogField::RequestHeader(name) => header_value(req.headers(), name),
+                AccessLogField::Status
+                | AccessLogField::Bytes
+                | AccessLogField::Latency
+                | AccessLogField::Ttfb
+                | AccessLogField::ResponseHeader(_) => None,
+            };
+        }
+    }
+
+    fn record_response<Body>(&mut self, resp: &Response<Body>) {
+        let ttfb = self.clock.now().saturating_duration_since(self.start);
+
+        for (field, value) in self.log.format.fields().iter().zip(&mut self.values) {
+            match field {
+                AccessLogField::Status => {
+                    *value = Some(FieldValue::Uint(resp.status().as_u16() as u64))
+                }
+                AccessLogField::Ttfb => {
+                    *value = Some(FieldValue::Millis(ttfb.as_secs_f64() * 1000.))
+                }
+                AccessLogField::ResponseHeader(name) => *value = header_value(resp.headers(), name),
+                _ => (),
+            }
+        }
+    }
+}
+
+impl Drop for AccessLogEntry {
+    fn drop(&mut self) {
+        let latency = self.clock.now().saturating_duration_since(self.start);
+
+        for (field, value) in self.log.format.fields().iter().zip(&mut self.values) {
+            match field {
+                AccessLogField::Bytes => *value = Some(FieldValue::Uint(self.bytes)),
+                AccessLogField::Latency => {
+                    *value = Some(FieldValue::Millis(latency.as_secs_f64() * 1000.))
+                }
+                _ => (),
+            }
+        }
+
+        let line = self.log.format.render(&self.values);
+        self.log.sink.write_line(&line);
+    }
+}
+
+fn header_value(headers: &HeaderMap, name: &crate::HeaderName) -> Option<FieldValue> {
+    headers
+        .get(name)
+        .map(|value| FieldValue::Str(String::from_utf8_lossy(value.as_bytes()).into_owned()))
+}
+
+#[cfg(feature = "tls")]
+fn tls_sni<State>(ctx: &Context<State>) -> Option<String> {
+    ctx.get::<rama_net::tls::SecureTransport>()?
+        .client_hello()?
+        .ext_server_name()
+        .map(ToString::to_string)
+}
+
+#[cfg(not(feature = "tls"))]
+fn tls_sni<State>(_ctx: &Context<State>) -> Option<String> {
+    None
+}
+
+/// Format the time as in the Common Log Format, e.g. `10/Oct/2000:13:55:36 +0000`.
+fn clf_time(time: SystemTime) -> String {
+    // e.g. `Tue, 10 Oct 2000 13:55:36 GMT`
+    let date = httpdate::fmt_http_date(time);
+    format!(
+        "{}/{}/{}:{} +0000",
+        &date[5..7],
+        &date[8..11],
+        &date[12..16],
+        &date[17..25]
+    )
+}
+
+pin_project! {
+    /// Response body used by [`AccessLogService`],
+    /// writing the access log line once it has been sent.
+    pub struct AccessLogBody<B> {
+        #[pin]
+        inner: B,
+        entry: Option<AccessLogEntry>,
+    }
+}
+
+impl<B: fmt::Debug> fmt::Debug for AccessLogBody<B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AccessLogBody")
+            .field("inner", &self.inner)
+            .finish()
+    }
+}
+
+impl<B> HttpBody for AccessLogBody<B>
+where
+    B: HttpBody,
+{
+    type Data = B::Data;
+    type Error = B::Error;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut task::Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let mut this = self.project();
+        let result = ready!(this.inner.as_mut().poll_frame(cx));
+        match &result {
+            Some(Ok(frame)) => {
+                if let (Some(entry), Some(data)) = (this.entry.as_mut(), frame.data_ref()) {
+                    entry.bytes += bytes::Buf::remaining(data) as u64;
+                }
+                if this.inner.is_end_stream() {
+                    // write the line
+                    this.entry.take();
+                }
+            }
+            Some(Err(_)) | None => {
+                this.entry.take();
+            }
+        }
+        Poll::Ready(result)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dep::http_body_util::BodyExt;
+    use crate::{Body, StatusCode};
+    use rama_core::service::service_fn;
+    use std::convert::Infallible;
+    use std::sync::Mutex;
+    use std::time::Duration;
+
+    fn collecting_sink() -> (Arc<Mutex<Vec<String>>>, impl AccessLogSink) {
+        let lines = Arc::new(Mutex::new(Vec::new()));
+        let sink_lines = lines.clone();
+        (lines, move |line: &str| {
+            sink_lines.lock().unwrap().push(line.to_owned())
+        })
+    }
+
+    #[test]
+    fn test_clf_time() {
+        let time = UNIX_EPOCH + Duration::from_secs(971_186_136);
+        assert_eq!(clf_time(time), "10/Oct/2000:13:55:36 +0000");
+    }
+
+    #[tokio::test]
+    async fn test_access_log_combined() {
+        let (lines, sink) = collecting_sink();
+        let service = AccessLogLayer::new(AccessLogFormat::combined(), sink).layer(service_fn(
+            |_req: Request| async {
+                Ok::<_, Infallible>(
+                    Response::builder()
+                        .status(StatusCode::NOT_FOUND)
+                        .body(Body::from("not found"))
+                        .unwrap(),
+                )
+            },
+        ));
+
+        let mut ctx = Context::default();
+        ctx.insert(SocketInfo::new(None, "127.0.0.1:8080".parse().unwrap()));
+        ctx.insert(UserId::Username("john".to_owned()));
+        let req = Request::builder()
+            .uri("http://example.com/foo?bar=baz")
+            .header(USER_AGENT, "curl/8.0")
+            .body(Body::empty())
+            .unwrap();
+
+        let resp = service.serve(ctx, req).await.unwrap();
+        assert!(lines.lock().unwrap().is_empty());
+        resp.into_body().collect().await.unwrap();
+
+        let lines = lines.lock().unwrap();
+        assert_eq!(lines.len(), 1);
+        let (start, rest) = lines[0].split_once(" [").unwrap();
+        assert_eq!(start, "127.0.0.1 - john");
+        let (_, rest) = rest.split_once("] ").unwrap();
+        assert_eq!(
+            rest,
+            r#""GET http://example.com/foo?bar=baz HTTP/1.1" 404 9 "-" "curl/8.0""#
+        );
+    }
+
+    #[tokio::test]
+    async fn test_access_log_socket_info() {
+        let (lines, sink) = collecting_sink();
+        let format = AccessLogFormat::template("{remote_addr} {transport} {interface}").unwrap();
+        let service = AccessLogLayer::new(format, sink).layer(service_fn(|_req: Request| async {
+            Ok::<_, Infallible>(Response::new(Body::empty()))
+        }));
+
+        let mut ctx = Context::default();
+        ctx.insert(
+            SocketInfo::new(None, "127.0.0.1:8080".parse().unwrap())
+                .with_interface("eth0")
+                .with_protocol(TransportProtocol::Udp),
+        );
+        drop(
+            service
+                .serve(ctx, Request::new(Body::empty()))
+                .await
+                .unwrap(),
+        );
+
+        let mut ctx = Context::default();
+        ctx.insert(SocketInfo::new(None, "127.0.0.1:8080".parse().unwrap()));
+        drop(
+            service
+                .serve(ctx, Request::new(Body::empty()))
+                .await
+                .unwrap(),
+        );
+
+        assert_eq!(
+            lines.lock().unwrap().as_slice(),
+            ["127.0.0.1 udp eth0", "127.0.0.1 tcp -"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_access_log_extensions() {
+        use crate::layer::request_id::{RequestId, SetRequestIdLayer};
+
+        #[derive(Debug, Clone)]
+        struct NextId;
+
+        impl crate::layer::request_id::MakeRequestId for NextId {
+            fn make_request_id<B>(&self, _request: &Request<B>) -> Option<RequestId> {
+                Some(RequestId::new(crate::HeaderValue::from_static("42")))
+            }
+        }
+
+        let (lines, sink) = collecting_sink();
+        let format =
+            AccessLogFormat::template("{ext:request_id} {ext:user} [{extensions}]").unwrap();
+        let service = (
+            SetRequestIdLayer::x_request_id(NextId),
+            AccessLogLayer::new(format, sink),
+        )
+            .layer(service_fn(|_req: Request| async {
+                Ok::<_, Infallible>(Response::new(Body::empty()))
+            }));
+
+        let mut ctx = Context::default();
+        ctx.insert(UserId::Username("john".to_owned()));
+        let req = Request::builder()
+            .uri("http://example.com/")
+            .body(Body::empty())
+            .unwrap();
+        drop(service.serve(ctx, req).await.unwrap());
+
+        assert_eq!(
+            lines.lock().unwrap().as_slice(),
+            ["42 john [request_id=42 user=john]"]
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_access_log_json_latency() {
+        let (lines, sink) = collecting_sink();
+        let format = AccessLogFormat::json_fields([
+            AccessLogField::Status,
+            AccessLogField::Ttfb,
+            AccessLogField::Latency,
+            AccessLogField::Upstream,
+            AccessLogField::ResponseHeader(crate::header::CONTENT_TYPE),
+        ]);
+        let service = AccessLogLayer::new(format, sink).layer(service_fn(|_req: Request| async {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            Ok::<_, Infallible>(Response::new(Body::from("hello")))
+        }));
+
+        let mut ctx = Context::default();
+        ctx.insert(Clock::tokio());
+        let req = Request::builder()
+            .uri("http://example.com/")
+            .body(Body::empty())
+            .unwrap();
+
+        let resp = service.serve(ctx, req).await.unwrap();
+        tokio::time::advance(Duration::from_millis(10)).await;
+        drop(resp);
+
+        assert_eq!(
+            lines.lock().unwrap().as_slice(),
+            [
+                r#"{"status":200,"ttfb_ms":20.0,"latency_ms":30.0,"upstream":"example.com:80","resp_header:content-type":null}"#
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_access_log_error() {
+        let (lines, sink) = collecting_sink();
+        let format = AccessLogFormat::template("{method} {status} {bytes}").unwrap();
+        let service = AccessLogLayer::new(format, sink).layer(service_fn(|_req: Request| async {
+            Err::<Response, _>(std::io::Error::other("oops"))
+        }));
+
+        let req = Request::builder()
+            .method("POST")
+            .uri("http://example.com/")
+            .body(Body::empty())
+            .unwrap();
+        assert!(service.serve(Context::default(), req).await.is_err());
+        assert_eq!(lines.lock().unwrap().as_slice(), ["POST - 0"]);
+    }
+}
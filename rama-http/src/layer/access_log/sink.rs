@@ -0,0 +1,217 @@
+use std::{
+    fmt,
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+/// Default amount of rotated files kept by the [`FileSink`].
+const DEFAULT_MAX_FILES: usize = 5;
+
+/// A destination for the lines logged by the [`AccessLogLayer`].
+///
+/// Implemented for any `Fn(&str)`, e.g. to forward the lines to a channel.
+///
+/// [`AccessLogLayer`]: super::AccessLogLayer
+pub trait AccessLogSink: Send + Sync + 'static {
+    /// Write a single line, which does not contain a trailing newline.
+    fn write_line(&self, line: &str);
+}
+
+impl<F> AccessLogSink for F
+where
+    F: Fn(&str) + Send + Sync + 'static,
+{
+    fn write_line(&self, line: &str) {
+        self(line)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+#[non_exhaustive]
+/// An [`AccessLogSink`] writing the lines to the standard output.
+pub struct StdoutSink;
+
+impl StdoutSink {
+    /// Create a new [`StdoutSink`].
+    pub const fn new() -> Self {
+        Self
+    }
+}
+
+impl AccessLogSink for StdoutSink {
+    fn write_line(&self, line: &str) {
+        let mut stdout = io::stdout().lock();
+        if let Err(err) = stdout
+            .write_all(line.as_bytes())
+            .and_then(|_| stdout.write_all(b"\n"))
+        {
+            tracing::error!(error = %err, "access log: failed to write line to stdout");
+        }
+    }
+}
+
+/// An [`AccessLogSink`] appending the lines to a file.
+///
+/// Optionally the file is rotated once it would exceed a max size,
+/// in which case the current file is renamed to `<path>.1`, the previous
+/// `<path>.1` to `<path>.2` and so on, keeping at most [`FileSink::max_files`]
+/// rotated files.
+pub struct FileSink {
+    path: PathBuf,
+    max_size: Option<u64>,
+    max_files: usize,
+    state: Mutex<FileState>,
+}
+
+struct FileState {
+    file: File,
+    size: u64,
+}
+
+impl fmt::Debug for FileSink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FileSink")
+            .field("path", &self.path)
+            .field("max_size", &self.max_size)
+            .field("max_files", &self.max_files)
+            .finish()
+    }
+}
+
+impl FileSink {
+    /// Create a new [`FileSink`], appending to the file at the given path,
+    /// which is created if it does not exist yet.
+    ///
+    /// The file is not rotated, unless a max size is defined.
+    pub fn new(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let state = FileState::open(&path)?;
+        Ok(Self {
+            path,
+            max_size: None,
+            max_files: DEFAULT_MAX_FILES,
+            state: Mutex::new(state),
+        })
+    }
+
+    /// Rotate the file once it would exceed the given size (in bytes).
+    pub fn max_size(mut self, size: u64) -> Self {
+        self.max_size = Some(size);
+        self
+    }
+
+    /// Rotate the file once it would exceed the given size (in bytes).
+    pub fn set_max_size(&mut self, size: u64) -> &mut Self {
+        self.max_size = Some(size);
+        self
+    }
+
+    /// Define the max amount of rotated files which are kept.
+    ///
+    /// Defaults to `5`. With `0` the file is truncated instead of rotated.
+    pub fn max_files(mut self, count: usize) -> Self {
+        self.max_files = count;
+        self
+    }
+
+    /// Define the max amount of rotated files which are kept.
+    ///
+    /// Defaults to `5`. With `0` the file is truncated instead of rotated.
+    pub fn set_max_files(&mut self, count: usize) -> &mut Self {
+        self.max_files = count;
+        self
+    }
+
+    fn rotated_path(&self, index: usize) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(format!(".{index}"));
+        path.into()
+    }
+
+    fn rotate(&self, state: &mut FileState) -> io::Result<()> {
+        if self.max_files == 0 {
+            state.file = File::create(&self.path)?;
+            state.size = 0;
+            return Ok(());
+        }
+        for index in (1..self.max_files).rev() {
+            match fs::rename(self.rotated_path(index), self.rotated_path(index + 1)) {
+                Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err),
+                _ => (),
+            }
+        }
+        fs::rename(&self.path, self.rotated_path(1))?;
+        *state = FileState::open(&self.path)?;
+        Ok(())
+    }
+
+    fn try_write_line(&self, line: &str) -> io::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        let len = line.len() as u64 + 1;
+        if let Some(max_size) = self.max_size {
+            if state.size > 0 && state.size + len > max_size {
+                self.rotate(&mut state)?;
+            }
+        }
+
+        let mut buf = Vec::with_capacity(line.len() + 1);
+        buf.extend_from_slice(line.as_bytes());
+        buf.push(b'\n');
+        state.file.write_all(&buf)?;
+        state.size += len;
+        Ok(())
+    }
+}
+
+impl FileState {
+    fn open(path: &Path) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let size = file.metadata()?.len();
+        Ok(Self { file, size })
+    }
+}
+
+impl AccessLogSink for FileSink {
+    fn write_line(&self, line: &str) {
+        if let Err(err) = self.try_write_line(line) {
+            tracing::error!(
+                error = %err,
+                path = %self.path.display(),
+                "access log: failed to write line to file",
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_file_sink_rotation() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("access.log");
+        let sink = FileSink::new(&path).unwrap().max_size(8).max_files(2);
+
+        for line in ["one", "two", "three", "four", "five"] {
+            sink.write_line(line);
+        }
+
+        let read = |path: PathBuf| fs::read_to_string(path).unwrap();
+        assert_eq!(read(path.clone()), "five\n");
+        assert_eq!(read(sink.rotated_path(1)), "four\n");
+        assert_eq!(read(sink.rotated_path(2)), "three\n");
+        assert!(!sink.rotated_path(3).exists());
+    }
+
+    #[test]
+    fn test_file_sink_append() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("access.log");
+        FileSink::new(&path).unwrap().write_line("one");
+        FileSink::new(&path).unwrap().write_line("two");
+        assert_eq!(fs::read_to_string(path).unwrap(), "one\ntwo\n");
+    }
+}
@@ -0,0 +1,416 @@
+//! Authenticate requests using an API key.
+//!
+//! The [`ApiKeyLayer`] reads the API key of a request from a header
+//! (`x-api-key` by default) and/or a query parameter, and looks it up in an [`ApiKeyStore`].
+//! Requests without a known key get a `401 Unauthorized` response,
+//! while for the others the [`ApiKeyInfo`] (e.g. owner and rate tier)
+//! of the key is inserted in the [`Context`], for use by the inner services
+//! (e.g. to select a rate limit).
+//!
+//! # Example
+//!
+//! ```
+//! use rama_http::layer::auth::api_key::{ApiKeyInfo, ApiKeyLayer, StaticApiKeyStore};
+//! use rama_http::{Body, Request, Response, StatusCode};
+//! use rama_core::service::service_fn;
+//! use rama_core::{Context, Layer, Service};
+//! use std::convert::Infallible;
+//!
+//! # #[tokio::main]
+//! # async fn main() {
+//! let store = StaticApiKeyStore::new()
+//!     .with_key("s3cr3t", ApiKeyInfo::new().with_owner("john").with_rate_tier("gold"));
+//! let service = ApiKeyLayer::new(store)
+//!     .query_param("api_key")
+//!     .layer(service_fn(|ctx: Context<()>, _req: Request| async move {
+//!         let info: &ApiKeyInfo = ctx.get().unwrap();
+//!         Ok::<_, Infallible>(Response::new(Body::from(info.rate_tier().unwrap().to_owned())))
+//!     }));
+//!
+//! let req = Request::builder()
+//!     .header("x-api-key", "s3cr3t")
+//!     .body(Body::empty())
+//!     .unwrap();
+//! let resp = service.serve(Context::default(), req).await.unwrap();
+//! assert_eq!(resp.status(), StatusCode::OK);
+//!
+//! let req = Request::builder()
+//!     .uri("/?api_key=wrong")
+//!     .body(Body::empty())
+//!     .unwrap();
+//! let resp = service.serve(Context::default(), req).await.unwrap();
+//! assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+//! # }
+//! ```
+
+use super::credential_store::constant_time_eq;
+use crate::{HeaderName, Request, Response, StatusCode};
+use rama_core::error::OpaqueError;
+use rama_core::{Context, Layer, Service};
+use rama_utils::macros::define_inner_service_accessors;
+use std::{fmt, future::Future, sync::Arc};
+
+/// Default header from which the API key is read.
+pub const DEFAULT_API_KEY_HEADER: HeaderName = HeaderName::from_static("x-api-key");
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+/// Metadata of an API key, inserted in the [`Context`] by the [`ApiKey`] middleware
+/// for requests with a valid key.
+pub struct ApiKeyInfo {
+    owner: Option<String>,
+    rate_tier: Option<String>,
+}
+
+impl ApiKeyInfo {
+    /// Create a new [`ApiKeyInfo`] without metadata.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Define the owner of the key.
+    pub fn with_owner(mut self, owner: impl Into<String>) -> Self {
+        self.owner = Some(owner.into());
+        self
+    }
+
+    /// Define the owner of the key.
+    pub fn set_owner(&mut self, owner: impl Into<String>) -> &mut Self {
+        self.owner = Some(owner.into());
+        self
+    }
+
+    /// Returns the owner of the key, if defined.
+    pub fn owner(&self) -> Option<&str> {
+        self.owner.as_deref()
+    }
+
+    /// Define the rate tier of the key.
+    pub fn with_rate_tier(mut self, tier: impl Into<String>) -> Self {
+        self.rate_tier = Some(tier.into());
+        self
+    }
+
+    /// Define the rate tier of the key.
+    pub fn set_rate_tier(&mut self, tier: impl Into<String>) -> &mut Self {
+        self.rate_tier = Some(tier.into());
+        self
+    }
+
+    /// Returns the rate tier of the key, if defined.
+    pub fn rate_tier(&self) -> Option<&str> {
+        self.rate_tier.as_deref()
+    }
+}
+
+/// A store of API keys, used to look up the [`ApiKeyInfo`] of a key.
+pub trait ApiKeyStore: Send + Sync + 'static {
+    /// Look up the given key, returning its [`ApiKeyInfo`]
+    /// if it is a valid key, or `None` otherwise.
+    fn lookup<'a>(
+        &'a self,
+        key: &'a str,
+    ) -> impl Future<Output = Result<Option<ApiKeyInfo>, OpaqueError>> + Send + 'a;
+}
+
+impl<T: ApiKeyStore> ApiKeyStore for Arc<T> {
+    fn lookup<'a>(
+        &'a self,
+        key: &'a str,
+    ) -> impl Future<Output = Result<Option<ApiKeyInfo>, OpaqueError>> + Send + 'a {
+        (**self).lookup(key)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+/// An [`ApiKeyStore`] with a static set of keys.
+pub struct StaticApiKeyStore {
+    keys: Vec<(String, ApiKeyInfo)>,
+}
+
+impl StaticApiKeyStore {
+    /// Create a new empty [`StaticApiKeyStore`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add the given key with its [`ApiKeyInfo`].
+    pub fn with_key(mut self, key: impl Into<String>, info: ApiKeyInfo) -> Self {
+        self.keys.push((key.into(), info));
+        self
+    }
+
+    /// Add the given key with its [`ApiKeyInfo`].
+    pub fn set_key(&mut self, key: impl Into<String>, info: ApiKeyInfo) -> &mut Self {
+        self.keys.push((key.into(), info));
+        self
+    }
+}
+
+impl ApiKeyStore for StaticApiKeyStore {
+    fn lookup<'a>(
+        &'a self,
+        key: &'a str,
+    ) -> impl Future<Output = Result<Option<ApiKeyInfo>, OpaqueError>> + Send + 'a {
+        let info = self
+            .keys
+            .iter()
+            .find(|(candidate, _)| constant_time_eq(candidate.as_bytes(), key.as_bytes()))
+            .map(|(_, info)| info.clone());
+        std::future::ready(Ok(info))
+    }
+}
+
+/// Layer that applies the [`ApiKey`] middleware,
+/// which authenticates requests using an API key.
+///
+/// See the [module docs](self) for more details.
+pub struct ApiKeyLayer<K> {
+    store: K,
+    header: Option<HeaderName>,
+    query_param: Option<String>,
+}
+
+impl<K: fmt::Debug> fmt::Debug for ApiKeyLayer<K> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ApiKeyLayer")
+            .field("store", &self.store)
+            .field("header", &self.header)
+            .field("query_param", &self.query_param)
+            .finish()
+    }
+}
+
+impl<K: Clone> Clone for ApiKeyLayer<K> {
+    fn clone(&self) -> Self {
+        Self {
+            store: self.store.clone(),
+            header: self.header.clone(),
+            query_param: self.query_param.clone(),
+        }
+    }
+}
+
+impl<K> ApiKeyLayer<K> {
+    /// Create a new [`ApiKeyLayer`], reading the key from the `x-api-key` header.
+    pub fn new(store: K) -> Self {
+        Self {
+            store,
+            header: Some(DEFAULT_API_KEY_HEADER),
+            query_param: None,
+        }
+    }
+
+    /// Read the key from the given header instead of the `x-api-key` header.
+    pub fn header(mut self, name: HeaderName) -> Self {
+        self.header = Some(name);
+        self
+    }
+
+    /// Read the key from the given header instead of the `x-api-key` header.
+    pub fn set_header(&mut self, name: HeaderName) -> &mut Self {
+        self.header = Some(name);
+        self
+    }
+
+    /// Do not read the key from a header, e.g. to only accept it as a query parameter.
+    pub fn without_header(mut self) -> Self {
+        self.header = None;
+        self
+    }
+
+    /// Do not read the key from a header, e.g. to only accept it as a query parameter.
+    pub fn unset_header(&mut self) -> &mut Self {
+        self.header = None;
+        self
+    }
+
+    /// Read the key from the given query parameter,
+    /// in case it is not found in the header.
+    pub fn query_param(mut self, name: impl Into<String>) -> Self {
+        self.query_param = Some(name.into());
+        self
+    }
+
+    /// Read the key from the given query parameter,
+    /// in case it is not found in the header.
+    pub fn set_query_param(&mut self, name: impl Into<String>) -> &mut Self {
+        self.query_param = Some(name.into());
+        self
+    }
+}
+
+impl<S, K: Clone> Layer<S> for ApiKeyLayer<K> {
+    type Service = ApiKey<S, K>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        ApiKey {
+            inner,
+            store: self.store.clone(),
+            header: self.header.clone(),
+            query_param: self.query_param.clone(),
+        }
+    }
+}
+
+/// Middleware which authenticates requests using an API key.
+///
+/// See the [module docs](self) for more details.
+pub struct ApiKey<S, K> {
+    inner: S,
+    store: K,
+    header: Option<HeaderName>,
+    query_param: Option<String>,
+}
+
+impl<S, K> ApiKey<S, K> {
+    /// Create a new [`ApiKey`], reading the key from the `x-api-key` header.
+    pub fn new(inner: S, store: K) -> Self {
+        Self {
+            inner,
+            store,
+            header: Some(DEFAULT_API_KEY_HEADER),
+            query_param: None,
+        }
+    }
+
+    define_inner_service_accessors!();
+
+    fn find_key<'a, B>(&self, req: &'a Request<B>) -> Option<std::borrow::Cow<'a, str>> {
+        if let Some(name) = self.header.as_ref() {
+            if let Some(key) = req
+                .headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+            {
+                return Some(key.into());
+            }
+        }
+
+        let name = self.query_param.as_deref()?;
+        let params: Vec<(String, String)> = serde_html_form::from_str(req.uri().query()?).ok()?;
+        params
+            .into_iter()
+            .find_map(|(param, value)| (param == name).then_some(value.into()))
+    }
+}
+
+impl<S: fmt::Debug, K: fmt::Debug> fmt::Debug for ApiKey<S, K> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ApiKey")
+            .field("inner", &self.inner)
+            .field("store", &self.store)
+            .field("header", &self.header)
+            .field("query_param", &self.query_param)
+            .finish()
+    }
+}
+
+impl<S: Clone, K: Clone> Clone for ApiKey<S, K> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            store: self.store.clone(),
+            header: self.header.clone(),
+            query_param: self.query_param.clone(),
+        }
+    }
+}
+
+impl<State, S, K, ReqBody, ResBody> Service<State, Request<ReqBody>> for ApiKey<S, K>
+where
+    State: Clone + Send + Sync + 'static,
+    S: Service<State, Request<ReqBody>, Response = Response<ResBody>>,
+    K: ApiKeyStore,
+    ReqBody: Send + 'static,
+    ResBody: Default + Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+
+    async fn serve(
+        &self,
+        mut ctx: Context<State>,
+        req: Request<ReqBody>,
+    ) -> Result<Self::Response, Self::Error> {
+        let info = match self.find_key(&req) {
+            Some(key) => self.store.lookup(&key).await,
+            None => Ok(None),
+        };
+
+        match info {
+            Ok(Some(info)) => {
+                ctx.insert(info);
+                self.inner.serve(ctx, req).await
+            }
+            Ok(None) => {
+                tracing::debug!("api key: missing or unknown key");
+                let mut res = Response::new(ResBody::default());
+                *res.status_mut() = StatusCode::UNAUTHORIZED;
+                Ok(res)
+            }
+            Err(err) => {
+                tracing::error!(error = %err, "api key: failed to look up key in store");
+                let mut res = Response::new(ResBody::default());
+                *res.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+                Ok(res)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Body;
+    use rama_core::service::service_fn;
+    use std::convert::Infallible;
+
+    #[tokio::test]
+    async fn test_api_key() {
+        let store = StaticApiKeyStore::new()
+            .with_key("abc", ApiKeyInfo::new().with_owner("john"))
+            .with_key("x y", ApiKeyInfo::new().with_rate_tier("free"));
+        let service = ApiKeyLayer::new(store)
+            .header(HeaderName::from_static("authorization-key"))
+            .query_param("key")
+            .layer(service_fn(|ctx: Context<()>, _req: Request| async move {
+                let info: &ApiKeyInfo = ctx.get().unwrap();
+                Ok::<_, Infallible>(Response::new(Body::from(format!(
+                    "{:?}/{:?}",
+                    info.owner(),
+                    info.rate_tier()
+                ))))
+            }));
+
+        for (header, uri, expected) in [
+            (Some("abc"), "/", Some("Some(\"john\")/None")),
+            (None, "/?key=x+y", Some("None/Some(\"free\")")),
+            (None, "/?a=b&key=x%20y", Some("None/Some(\"free\")")),
+            // the header takes priority over the query parameter
+            (Some("abcd"), "/?key=abc", None),
+            (Some("abc"), "/?key=wrong", Some("Some(\"john\")/None")),
+            (None, "/?api_key=abc", None),
+            (None, "/", None),
+        ] {
+            let mut req = Request::builder().uri(uri);
+            if let Some(header) = header {
+                req = req.header("authorization-key", header);
+            }
+            let res = service
+                .serve(Context::default(), req.body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+            match expected {
+                Some(expected) => {
+                    assert_eq!(res.status(), StatusCode::OK, "{header:?} {uri}");
+                    let body = crate::dep::http_body_util::BodyExt::collect(res.into_body())
+                        .await
+                        .unwrap()
+                        .to_bytes();
+                    assert_eq!(body, expected);
+                }
+                None => assert_eq!(res.status(), StatusCode::UNAUTHORIZED, "{header:?} {uri}"),
+            }
+        }
+    }
+}
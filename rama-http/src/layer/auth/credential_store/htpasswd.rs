@@ -0,0 +1,143 @@
+use super::{constant_time_eq, CredentialStore};
+use base64::Engine as _;
+use rama_core::error::{ErrorContext, OpaqueError};
+use rama_net::user::{ProxyCredential, UserId};
+use ring::digest;
+use std::{collections::HashMap, path::Path};
+
+const BASE64: base64::engine::GeneralPurpose = base64::engine::general_purpose::STANDARD;
+
+#[derive(Debug, Clone, Default)]
+/// A [`CredentialStore`] with the users of an [htpasswd] file,
+/// which only supports [`Basic`] credentials.
+///
+/// Passwords have to be hashed using SHA-1 (`{SHA}`, created using `htpasswd -s`)
+/// or stored in plain text (created using `htpasswd -p`). Other formats
+/// (e.g. bcrypt and MD5) are not supported and result in an error when parsed.
+///
+/// [htpasswd]: https://httpd.apache.org/docs/current/programs/htpasswd.html
+/// [`Basic`]: rama_net::user::Basic
+pub struct HtpasswdCredentialStore {
+    users: HashMap<String, HtpasswdHash>,
+}
+
+#[derive(Debug, Clone)]
+enum HtpasswdHash {
+    Sha1(Vec<u8>),
+    Plain(String),
+}
+
+impl HtpasswdCredentialStore {
+    /// Read the htpasswd file at the given path.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, OpaqueError> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path)
+            .map_err(OpaqueError::from_std)
+            .with_context(|| format!("read htpasswd file {}", path.display()))?;
+        Self::parse(&content)
+    }
+
+    /// Parse the content of a htpasswd file,
+    /// with a `username:hash` pair on each line.
+    pub fn parse(content: &str) -> Result<Self, OpaqueError> {
+        let mut users = HashMap::new();
+        for (index, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (username, hash) = line.split_once(':').ok_or_else(|| {
+                OpaqueError::from_display(format!(
+                    "htpasswd: missing ':' separator on line {}",
+                    index + 1
+                ))
+            })?;
+            let hash = if let Some(encoded) = hash.strip_prefix("{SHA}") {
+                HtpasswdHash::Sha1(
+                    BASE64
+                        .decode(encoded)
+                        .map_err(OpaqueError::from_std)
+                        .with_context(|| {
+                            format!("htpasswd: decode sha1 hash on line {}", index + 1)
+                        })?,
+                )
+            } else if hash.starts_with('$') {
+                return Err(OpaqueError::from_display(format!(
+                    "htpasswd: unsupported hash format on line {} (only sha1 and plain text are supported)",
+                    index + 1
+                )));
+            } else {
+                HtpasswdHash::Plain(hash.to_owned())
+            };
+            users.insert(username.to_owned(), hash);
+        }
+        Ok(Self { users })
+    }
+}
+
+impl CredentialStore for HtpasswdCredentialStore {
+    async fn validate(&self, credential: ProxyCredential) -> Option<UserId> {
+        let ProxyCredential::Basic(basic) = credential else {
+            return None;
+        };
+        let valid = match self.users.get(basic.username())? {
+            HtpasswdHash::Sha1(hash) => {
+                let digest = digest::digest(
+                    &digest::SHA1_FOR_LEGACY_USE_ONLY,
+                    basic.password().as_bytes(),
+                );
+                constant_time_eq(hash, digest.as_ref())
+            }
+            HtpasswdHash::Plain(password) => {
+                constant_time_eq(password.as_bytes(), basic.password().as_bytes())
+            }
+        };
+        valid.then(|| UserId::Username(basic.username().to_owned()))
+    }
+
+    fn supports_bearer(&self) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rama_net::user::{Basic, Bearer};
+
+    #[tokio::test]
+    async fn test_htpasswd_credential_store() {
+        let store = HtpasswdCredentialStore::parse(
+            "# users\njohn:{SHA}5en6G6MezRroT3XKqkdPOmY/BfQ=\n\njane:plain\n",
+        )
+        .unwrap();
+        assert!(!store.supports_bearer());
+
+        assert_eq!(
+            store.validate(Basic::new("john", "secret").into()).await,
+            Some(UserId::Username("john".to_owned()))
+        );
+        assert!(store
+            .validate(Basic::new("john", "{SHA}5en6G6MezRroT3XKqkdPOmY/BfQ=").into())
+            .await
+            .is_none());
+        assert_eq!(
+            store.validate(Basic::new("jane", "plain").into()).await,
+            Some(UserId::Username("jane".to_owned()))
+        );
+        assert!(store
+            .validate(Bearer::try_from_clear_str("plain").unwrap().into())
+            .await
+            .is_none());
+    }
+
+    #[test]
+    fn test_htpasswd_invalid() {
+        for content in ["john", "john:$2y$05$abc", "john:{SHA}!!"] {
+            assert!(
+                HtpasswdCredentialStore::parse(content).is_err(),
+                "{content}"
+            );
+        }
+    }
+}
@@ -0,0 +1,193 @@
+//! Stores of credentials, used to validate the credentials of requests.
+//!
+//! See [`CredentialStore`] and the [`RequireAuthorizationLayer`] which uses it.
+//!
+//! [`RequireAuthorizationLayer`]: super::RequireAuthorizationLayer
+
+use rama_net::user::{ProxyCredential, UserId};
+use std::{collections::HashMap, future::Future, sync::Arc};
+
+#[cfg(feature = "htpasswd")]
+mod htpasswd;
+#[cfg(feature = "htpasswd")]
+#[doc(inline)]
+pub use htpasswd::HtpasswdCredentialStore;
+
+/// A store of credentials, used to validate [`ProxyCredential`]s,
+/// either [`Basic`] (username and password) or [`Bearer`] (token) credentials.
+///
+/// [`Basic`]: rama_net::user::Basic
+/// [`Bearer`]: rama_net::user::Bearer
+pub trait CredentialStore: Send + Sync + 'static {
+    /// Validate the given credential, returning the [`UserId`]
+    /// of the user it belongs to if valid, or `None` otherwise.
+    fn validate(
+        &self,
+        credential: ProxyCredential,
+    ) -> impl Future<Output = Option<UserId>> + Send + '_;
+
+    /// Returns `true` if this store can validate [`Basic`] credentials,
+    /// used to decide which challenges to respond with.
+    ///
+    /// [`Basic`]: rama_net::user::Basic
+    fn supports_basic(&self) -> bool {
+        true
+    }
+
+    /// Returns `true` if this store can validate [`Bearer`] credentials,
+    /// used to decide which challenges to respond with.
+    ///
+    /// [`Bearer`]: rama_net::user::Bearer
+    fn supports_bearer(&self) -> bool {
+        true
+    }
+}
+
+impl<T: CredentialStore> CredentialStore for Arc<T> {
+    fn validate(
+        &self,
+        credential: ProxyCredential,
+    ) -> impl Future<Output = Option<UserId>> + Send + '_ {
+        (**self).validate(credential)
+    }
+
+    fn supports_basic(&self) -> bool {
+        (**self).supports_basic()
+    }
+
+    fn supports_bearer(&self) -> bool {
+        (**self).supports_bearer()
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+/// A [`CredentialStore`] with a static set of usernames with their password,
+/// and bearer tokens with the [`UserId`] they belong to.
+pub struct StaticCredentialStore {
+    users: HashMap<String, String>,
+    tokens: HashMap<String, UserId>,
+}
+
+impl StaticCredentialStore {
+    /// Create a new empty [`StaticCredentialStore`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a user with the given username and password.
+    pub fn with_user(mut self, username: impl Into<String>, password: impl Into<String>) -> Self {
+        self.users.insert(username.into(), password.into());
+        self
+    }
+
+    /// Add a user with the given username and password.
+    pub fn set_user(
+        &mut self,
+        username: impl Into<String>,
+        password: impl Into<String>,
+    ) -> &mut Self {
+        self.users.insert(username.into(), password.into());
+        self
+    }
+
+    /// Add a bearer token, identifying the user as the token itself.
+    pub fn with_token(mut self, token: impl Into<String>) -> Self {
+        let token = token.into();
+        let user_id = UserId::Token(token.as_bytes().to_vec());
+        self.tokens.insert(token, user_id);
+        self
+    }
+
+    /// Add a bearer token, identifying the user as the token itself.
+    pub fn set_token(&mut self, token: impl Into<String>) -> &mut Self {
+        let token = token.into();
+        let user_id = UserId::Token(token.as_bytes().to_vec());
+        self.tokens.insert(token, user_id);
+        self
+    }
+
+    /// Add a bearer token belonging to the given user.
+    pub fn with_user_token(mut self, token: impl Into<String>, user_id: UserId) -> Self {
+        self.tokens.insert(token.into(), user_id);
+        self
+    }
+
+    /// Add a bearer token belonging to the given user.
+    pub fn set_user_token(&mut self, token: impl Into<String>, user_id: UserId) -> &mut Self {
+        self.tokens.insert(token.into(), user_id);
+        self
+    }
+}
+
+impl CredentialStore for StaticCredentialStore {
+    async fn validate(&self, credential: ProxyCredential) -> Option<UserId> {
+        match credential {
+            ProxyCredential::Basic(basic) => {
+                let password = self.users.get(basic.username())?;
+                constant_time_eq(password.as_bytes(), basic.password().as_bytes())
+                    .then(|| UserId::Username(basic.username().to_owned()))
+            }
+            ProxyCredential::Bearer(bearer) => self
+                .tokens
+                .iter()
+                .find(|(token, _)| constant_time_eq(token.as_bytes(), bearer.token().as_bytes()))
+                .map(|(_, user_id)| user_id.clone()),
+        }
+    }
+
+    fn supports_basic(&self) -> bool {
+        !self.users.is_empty()
+    }
+
+    fn supports_bearer(&self) -> bool {
+        !self.tokens.is_empty()
+    }
+}
+
+/// Compare the secrets in constant time (for secrets of the same length).
+pub(super) fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rama_net::user::{Basic, Bearer};
+
+    #[tokio::test]
+    async fn test_static_credential_store() {
+        let store = StaticCredentialStore::new()
+            .with_user("john", "secret")
+            .with_token("abc")
+            .with_user_token("xyz", UserId::Username("jane".to_owned()));
+
+        assert_eq!(
+            store.validate(Basic::new("john", "secret").into()).await,
+            Some(UserId::Username("john".to_owned()))
+        );
+        assert!(store
+            .validate(Basic::new("john", "secrets").into())
+            .await
+            .is_none());
+        assert!(store
+            .validate(Basic::new("jane", "secret").into())
+            .await
+            .is_none());
+        assert_eq!(
+            store
+                .validate(Bearer::try_from_clear_str("abc").unwrap().into())
+                .await,
+            Some(UserId::Token(b"abc".to_vec()))
+        );
+        assert_eq!(
+            store
+                .validate(Bearer::try_from_clear_str("xyz").unwrap().into())
+                .await,
+            Some(UserId::Username("jane".to_owned()))
+        );
+        assert!(store
+            .validate(Bearer::try_from_clear_str("abcd").unwrap().into())
+            .await
+            .is_none());
+    }
+}
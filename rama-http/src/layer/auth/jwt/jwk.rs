@@ -0,0 +1,356 @@
+use base64::Engine as _;
+use bytes::Bytes;
+use rama_core::error::{ErrorContext, OpaqueError};
+use ring::{hmac, signature};
+use serde::Deserialize;
+use std::{fmt, str::FromStr};
+
+const BASE64_URL: base64::engine::GeneralPurpose = base64::engine::general_purpose::URL_SAFE_NO_PAD;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+/// The algorithms supported to sign a JWT with.
+pub enum JwtAlgorithm {
+    /// HMAC using SHA-256
+    HS256,
+    /// RSASSA-PKCS1-v1_5 using SHA-256
+    RS256,
+    /// ECDSA using P-256 and SHA-256
+    ES256,
+}
+
+impl JwtAlgorithm {
+    /// Returns the name of the algorithm, as used in the `alg` header and JWK parameter.
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            JwtAlgorithm::HS256 => "HS256",
+            JwtAlgorithm::RS256 => "RS256",
+            JwtAlgorithm::ES256 => "ES256",
+        }
+    }
+}
+
+impl fmt::Display for JwtAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for JwtAlgorithm {
+    type Err = OpaqueError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "HS256" => Ok(JwtAlgorithm::HS256),
+            "RS256" => Ok(JwtAlgorithm::RS256),
+            "ES256" => Ok(JwtAlgorithm::ES256),
+            _ => Err(OpaqueError::from_display(format!(
+                "unsupported jwt algorithm: {s}"
+            ))),
+        }
+    }
+}
+
+#[derive(Clone)]
+/// A JSON Web Key ([RFC 7517]), used to verify the signature of a JWT.
+///
+/// Only the keys of the supported [`JwtAlgorithm`]s can be created,
+/// symmetric (`oct`), `RSA` and `EC` (P-256) keys.
+///
+/// [RFC 7517]: https://datatracker.ietf.org/doc/html/rfc7517
+pub struct Jwk {
+    kid: Option<String>,
+    key: JwkKey,
+}
+
+#[derive(Clone)]
+enum JwkKey {
+    Hmac(Bytes),
+    Rsa { n: Bytes, e: Bytes },
+    EcP256(Bytes),
+}
+
+impl fmt::Debug for Jwk {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // key material is not printed, as hmac keys are secret
+        f.debug_struct("Jwk")
+            .field("kid", &self.kid)
+            .field("algorithm", &self.algorithm())
+            .finish()
+    }
+}
+
+impl Jwk {
+    /// Create a symmetric key, used to verify [`JwtAlgorithm::HS256`] signatures.
+    pub fn hmac(secret: impl Into<Bytes>) -> Self {
+        Self {
+            kid: None,
+            key: JwkKey::Hmac(secret.into()),
+        }
+    }
+
+    /// Create an RSA public key from its (big-endian) modulus and exponent,
+    /// used to verify [`JwtAlgorithm::RS256`] signatures.
+    pub fn rsa(n: impl Into<Bytes>, e: impl Into<Bytes>) -> Self {
+        Self {
+            kid: None,
+            key: JwkKey::Rsa {
+                n: n.into(),
+                e: e.into(),
+            },
+        }
+    }
+
+    /// Create a P-256 public key from its (big-endian) coordinates,
+    /// used to verify [`JwtAlgorithm::ES256`] signatures.
+    pub fn ec_p256(x: &[u8], y: &[u8]) -> Result<Self, OpaqueError> {
+        if x.len() != 32 || y.len() != 32 {
+            return Err(OpaqueError::from_display(
+                "jwk: P-256 coordinates have to be 32 bytes",
+            ));
+        }
+        let mut point = Vec::with_capacity(65);
+        point.push(0x04); // uncompressed
+        point.extend_from_slice(x);
+        point.extend_from_slice(y);
+        Ok(Self {
+            kid: None,
+            key: JwkKey::EcP256(point.into()),
+        })
+    }
+
+    /// Attach the given key id (`kid`) to this key.
+    pub fn with_kid(mut self, kid: impl Into<String>) -> Self {
+        self.kid = Some(kid.into());
+        self
+    }
+
+    /// Attach the given key id (`kid`) to this key.
+    pub fn set_kid(&mut self, kid: impl Into<String>) -> &mut Self {
+        self.kid = Some(kid.into());
+        self
+    }
+
+    /// Returns the key id (`kid`) of this key, if defined.
+    pub fn kid(&self) -> Option<&str> {
+        self.kid.as_deref()
+    }
+
+    /// Returns the [`JwtAlgorithm`] this key is used for.
+    pub fn algorithm(&self) -> JwtAlgorithm {
+        match self.key {
+            JwkKey::Hmac(_) => JwtAlgorithm::HS256,
+            JwkKey::Rsa { .. } => JwtAlgorithm::RS256,
+            JwkKey::EcP256(_) => JwtAlgorithm::ES256,
+        }
+    }
+
+    /// Verify the signature of the given message using this key.
+    pub(super) fn verify(&self, message: &[u8], signature: &[u8]) -> bool {
+        match &self.key {
+            JwkKey::Hmac(secret) => {
+                let key = hmac::Key::new(hmac::HMAC_SHA256, secret);
+                hmac::verify(&key, message, signature).is_ok()
+            }
+            JwkKey::Rsa { n, e } => signature::RsaPublicKeyComponents { n, e }
+                .verify(&signature::RSA_PKCS1_2048_8192_SHA256, message, signature)
+                .is_ok(),
+            JwkKey::EcP256(point) => {
+                signature::UnparsedPublicKey::new(&signature::ECDSA_P256_SHA256_FIXED, point)
+                    .verify(message, signature)
+                    .is_ok()
+            }
+        }
+    }
+
+    fn try_from_raw(raw: RawJwk) -> Result<Self, OpaqueError> {
+        let param = |name: &'static str, value: Option<String>| {
+            let value = value.with_context(|| format!("jwk: missing parameter {name}"))?;
+            BASE64_URL
+                .decode(value)
+                .map_err(OpaqueError::from_std)
+                .with_context(|| format!("jwk: decode parameter {name}"))
+        };
+
+        if let Some(usage) = raw.usage.as_deref() {
+            if usage != "sig" {
+                return Err(OpaqueError::from_display(format!(
+                    "jwk: unsupported key use: {usage}"
+                )));
+            }
+        }
+
+        let jwk = match raw.kty.as_str() {
+            "oct" => Jwk::hmac(param("k", raw.k)?),
+            "RSA" => Jwk::rsa(param("n", raw.n)?, param("e", raw.e)?),
+            "EC" => match raw.crv.as_deref() {
+                Some("P-256") => Jwk::ec_p256(&param("x", raw.x)?, &param("y", raw.y)?)?,
+                crv => {
+                    return Err(OpaqueError::from_display(format!(
+                        "jwk: unsupported curve: {crv:?}"
+                    )))
+                }
+            },
+            kty => {
+                return Err(OpaqueError::from_display(format!(
+                    "jwk: unsupported key type: {kty}"
+                )))
+            }
+        };
+
+        if let Some(alg) = raw.alg.as_deref() {
+            if alg.parse::<JwtAlgorithm>()? != jwk.algorithm() {
+                return Err(OpaqueError::from_display(format!(
+                    "jwk: algorithm {alg} does not match key type {}",
+                    raw.kty
+                )));
+            }
+        }
+
+        Ok(match raw.kid {
+            Some(kid) => jwk.with_kid(kid),
+            None => jwk,
+        })
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct RawJwk {
+    kty: String,
+    kid: Option<String>,
+    alg: Option<String>,
+    #[serde(rename = "use")]
+    usage: Option<String>,
+    k: Option<String>,
+    n: Option<String>,
+    e: Option<String>,
+    crv: Option<String>,
+    x: Option<String>,
+    y: Option<String>,
+}
+
+impl<'de> Deserialize<'de> for Jwk {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let raw = RawJwk::deserialize(deserializer)?;
+        Jwk::try_from_raw(raw).map_err(serde::de::Error::custom)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+/// A JSON Web Key Set ([RFC 7517]), a set of [`Jwk`]s.
+///
+/// When deserialized (e.g. from a JWKS endpoint) keys which
+/// are not supported are ignored, instead of failing the entire set.
+///
+/// [RFC 7517]: https://datatracker.ietf.org/doc/html/rfc7517#section-5
+pub struct JwkSet {
+    keys: Vec<Jwk>,
+}
+
+impl JwkSet {
+    /// Create a new empty [`JwkSet`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parse a [`JwkSet`] from its JSON representation.
+    pub fn parse(json: &[u8]) -> Result<Self, OpaqueError> {
+        serde_json::from_slice(json).context("parse jwk set")
+    }
+
+    /// Add the given key to this set.
+    pub fn with_key(mut self, key: Jwk) -> Self {
+        self.keys.push(key);
+        self
+    }
+
+    /// Add the given key to this set.
+    pub fn set_key(&mut self, key: Jwk) -> &mut Self {
+        self.keys.push(key);
+        self
+    }
+
+    /// Returns the keys in this set.
+    pub fn keys(&self) -> &[Jwk] {
+        &self.keys
+    }
+
+    /// Find the key with the given key id (if any) to be used for the given algorithm.
+    pub fn find(&self, kid: Option<&str>, alg: JwtAlgorithm) -> Option<&Jwk> {
+        self.keys
+            .iter()
+            .find(|key| key.algorithm() == alg && (kid.is_none() || key.kid() == kid))
+    }
+}
+
+impl<'de> Deserialize<'de> for JwkSet {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        struct RawJwkSet {
+            keys: Vec<RawJwk>,
+        }
+
+        let raw = RawJwkSet::deserialize(deserializer)?;
+        let keys = raw
+            .keys
+            .into_iter()
+            .filter_map(|raw| {
+                Jwk::try_from_raw(raw)
+                    .inspect_err(|err| tracing::debug!(error = %err, "jwk set: ignore key"))
+                    .ok()
+            })
+            .collect();
+        Ok(Self { keys })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_jwk_set_parse() {
+        let set = JwkSet::parse(
+            br#"{"keys": [
+                {"kty": "oct", "kid": "a", "k": "c2VjcmV0"},
+                {"kty": "EC", "kid": "b", "crv": "P-256", "alg": "ES256",
+                 "x": "f83OJ3D2xF1Bg8vub9tLe1gHMzV76e8Tus9uPHvRVEU",
+                 "y": "x_FEzRu9m36HLN_tue659LNpXW6pCyStikYjKIWI5a0"},
+                {"kty": "RSA", "kid": "c", "n": "AQAB", "e": "AQAB"},
+                {"kty": "EC", "kid": "d", "crv": "P-384", "x": "AA", "y": "AA"},
+                {"kty": "OKP", "kid": "e", "crv": "Ed25519", "x": "AA"},
+                {"kty": "oct", "kid": "f", "alg": "RS256", "k": "c2VjcmV0"}
+            ]}"#,
+        )
+        .unwrap();
+
+        let kids: Vec<_> = set.keys().iter().map(|key| key.kid().unwrap()).collect();
+        assert_eq!(kids, ["a", "b", "c"]);
+
+        assert_eq!(
+            set.find(Some("b"), JwtAlgorithm::ES256).unwrap().kid(),
+            Some("b")
+        );
+        assert!(set.find(Some("b"), JwtAlgorithm::RS256).is_none());
+        assert_eq!(
+            set.find(None, JwtAlgorithm::RS256).unwrap().kid(),
+            Some("c")
+        );
+        assert!(set.find(Some("x"), JwtAlgorithm::HS256).is_none());
+    }
+
+    #[test]
+    fn test_jwk_hmac_verify() {
+        let jwk = Jwk::hmac("secret");
+        let key = hmac::Key::new(hmac::HMAC_SHA256, b"secret");
+        let tag = hmac::sign(&key, b"message");
+        assert!(jwk.verify(b"message", tag.as_ref()));
+        assert!(!jwk.verify(b"messages", tag.as_ref()));
+        assert!(!Jwk::hmac("other").verify(b"message", tag.as_ref()));
+    }
+}
@@ -0,0 +1,710 @@
+//! Validate the Bearer JSON Web Tokens (JWT) of requests.
+//!
+//! The [`JwtAuthLayer`] requires each request to have a `Authorization: Bearer <jwt>` header,
+//! with a JWT signed using one of the supported [`JwtAlgorithm`]s (`HS256`, `RS256` or `ES256`)
+//! by one of the keys of a [`JwtKeyStore`]. The keys can be static (e.g. a [`JwkSet`])
+//! or be fetched (and cached) from a JWKS url using [`RemoteJwks`].
+//!
+//! The registered claims (`exp`, `nbf`, `iss` and `aud`) are validated
+//! according to the [`JwtValidation`] rules, after which the verified
+//! [`JwtClaims`] are inserted in the [`Context`] for the inner services.
+//!
+//! # Example
+//!
+//! ```
+//! use rama_http::layer::auth::jwt::{Jwk, JwkSet, JwtAuthLayer, JwtClaims, JwtValidation};
+//! use rama_http::{header, Body, Request, Response, StatusCode};
+//! use rama_core::service::service_fn;
+//! use rama_core::{Context, Layer, Service};
+//! use std::convert::Infallible;
+//!
+//! # #[tokio::main]
+//! # async fn main() {
+//! let keys = JwkSet::new().with_key(Jwk::hmac("secret").with_kid("main"));
+//! let service = JwtAuthLayer::new(keys)
+//!     .validation(JwtValidation::new().with_issuer("https://auth.example.com"))
+//!     .layer(service_fn(|ctx: Context<()>, _req: Request| async move {
+//!         let claims: &JwtClaims = ctx.get().unwrap();
+//!         Ok::<_, Infallible>(Response::new(Body::from(
+//!             claims.subject().unwrap_or_default().to_owned(),
+//!         )))
+//!     }));
+//!
+//! let req = Request::builder()
+//!     .header(header::AUTHORIZATION, "Bearer not.a.jwt")
+//!     .body(Body::empty())
+//!     .unwrap();
+//! let resp = service.serve(Context::default(), req).await.unwrap();
+//! assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+//! # }
+//! ```
+
+use crate::headers::{authorization::Bearer, Authorization, HeaderMapExt};
+use crate::{header, HeaderValue, Request, Response, StatusCode};
+use base64::Engine as _;
+use rama_core::error::{ErrorContext, OpaqueError};
+use rama_core::{Context, Layer, Service};
+use rama_utils::macros::define_inner_service_accessors;
+use serde::{de::DeserializeOwned, Deserialize};
+use serde_json::{Map, Value};
+use std::{
+    fmt,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+mod jwk;
+#[doc(inline)]
+pub use jwk::{Jwk, JwkSet, JwtAlgorithm};
+
+mod store;
+#[doc(inline)]
+pub use store::{JwtKeyStore, RemoteJwks};
+
+const BASE64_URL: base64::engine::GeneralPurpose = base64::engine::general_purpose::URL_SAFE_NO_PAD;
+
+/// Default leeway used to validate the time based claims of a JWT.
+const DEFAULT_LEEWAY: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// The (verified) claims of a JWT,
+/// inserted in the [`Context`] by the [`JwtAuth`] middleware.
+pub struct JwtClaims(Map<String, Value>);
+
+impl JwtClaims {
+    /// Returns the claim with the given name, if present.
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        self.0.get(name)
+    }
+
+    /// Returns the subject (`sub`) claim, if present.
+    pub fn subject(&self) -> Option<&str> {
+        self.0.get("sub").and_then(Value::as_str)
+    }
+
+    /// Returns the issuer (`iss`) claim, if present.
+    pub fn issuer(&self) -> Option<&str> {
+        self.0.get("iss").and_then(Value::as_str)
+    }
+
+    /// Returns the audience (`aud`) claim, which can be a single or multiple values.
+    pub fn audience(&self) -> Vec<&str> {
+        match self.0.get("aud") {
+            Some(Value::String(aud)) => vec![aud.as_str()],
+            Some(Value::Array(values)) => values.iter().filter_map(Value::as_str).collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Returns the expiration time (`exp`) claim, if present.
+    pub fn expires_at(&self) -> Option<SystemTime> {
+        self.numeric_date("exp")
+    }
+
+    /// Returns the not before (`nbf`) claim, if present.
+    pub fn not_before(&self) -> Option<SystemTime> {
+        self.numeric_date("nbf")
+    }
+
+    /// Returns the issued at (`iat`) claim, if present.
+    pub fn issued_at(&self) -> Option<SystemTime> {
+        self.numeric_date("iat")
+    }
+
+    /// Returns all claims as a JSON object.
+    pub fn as_map(&self) -> &Map<String, Value> {
+        &self.0
+    }
+
+    /// Deserialize the claims into the given type.
+    pub fn deserialize<T: DeserializeOwned>(&self) -> Result<T, OpaqueError> {
+        T::deserialize(&self.0).context("deserialize jwt claims")
+    }
+
+    fn numeric_date(&self, name: &str) -> Option<SystemTime> {
+        let secs = self.0.get(name)?.as_f64()?;
+        if !secs.is_finite() || secs < 0.0 {
+            return None;
+        }
+        UNIX_EPOCH.checked_add(Duration::from_secs_f64(secs))
+    }
+}
+
+#[derive(Debug, Clone)]
+/// The rules used to validate the claims and algorithm of a JWT.
+///
+/// By default the expiration time (`exp`) is required, the time based claims
+/// are validated with a leeway of 60 seconds and all [`JwtAlgorithm`]s are allowed.
+pub struct JwtValidation {
+    issuers: Vec<String>,
+    audiences: Vec<String>,
+    algorithms: Vec<JwtAlgorithm>,
+    leeway: Duration,
+    require_exp: bool,
+}
+
+impl Default for JwtValidation {
+    fn default() -> Self {
+        Self {
+            issuers: Vec::new(),
+            audiences: Vec::new(),
+            algorithms: vec![
+                JwtAlgorithm::HS256,
+                JwtAlgorithm::RS256,
+                JwtAlgorithm::ES256,
+            ],
+            leeway: DEFAULT_LEEWAY,
+            require_exp: true,
+        }
+    }
+}
+
+impl JwtValidation {
+    /// Create a new [`JwtValidation`] with the default rules.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Allow the given issuer (`iss`).
+    ///
+    /// When at least one issuer is allowed, the issuer claim is required.
+    pub fn with_issuer(mut self, issuer: impl Into<String>) -> Self {
+        self.issuers.push(issuer.into());
+        self
+    }
+
+    /// Allow the given issuer (`iss`).
+    ///
+    /// When at least one issuer is allowed, the issuer claim is required.
+    pub fn set_issuer(&mut self, issuer: impl Into<String>) -> &mut Self {
+        self.issuers.push(issuer.into());
+        self
+    }
+
+    /// Allow the given audience (`aud`).
+    ///
+    /// When at least one audience is allowed, the audience claim is required.
+    pub fn with_audience(mut self, audience: impl Into<String>) -> Self {
+        self.audiences.push(audience.into());
+        self
+    }
+
+    /// Allow the given audience (`aud`).
+    ///
+    /// When at least one audience is allowed, the audience claim is required.
+    pub fn set_audience(&mut self, audience: impl Into<String>) -> &mut Self {
+        self.audiences.push(audience.into());
+        self
+    }
+
+    /// Define the algorithms allowed to sign the JWT with.
+    pub fn algorithms(mut self, algorithms: impl IntoIterator<Item = JwtAlgorithm>) -> Self {
+        self.algorithms = algorithms.into_iter().collect();
+        self
+    }
+
+    /// Define the algorithms allowed to sign the JWT with.
+    pub fn set_algorithms(
+        &mut self,
+        algorithms: impl IntoIterator<Item = JwtAlgorithm>,
+    ) -> &mut Self {
+        self.algorithms = algorithms.into_iter().collect();
+        self
+    }
+
+    /// Define the leeway used to validate the time based claims (`exp` and `nbf`),
+    /// to account for clock skew.
+    pub fn leeway(mut self, leeway: Duration) -> Self {
+        self.leeway = leeway;
+        self
+    }
+
+    /// Define the leeway used to validate the time based claims (`exp` and `nbf`),
+    /// to account for clock skew.
+    pub fn set_leeway(&mut self, leeway: Duration) -> &mut Self {
+        self.leeway = leeway;
+        self
+    }
+
+    /// Define whether or not the expiration time (`exp`) claim is required.
+    pub fn require_exp(mut self, require: bool) -> Self {
+        self.require_exp = require;
+        self
+    }
+
+    /// Define whether or not the expiration time (`exp`) claim is required.
+    pub fn set_require_exp(&mut self, require: bool) -> &mut Self {
+        self.require_exp = require;
+        self
+    }
+
+    fn validate_claims(&self, claims: &JwtClaims, now: SystemTime) -> Result<(), &'static str> {
+        match claims.get("exp") {
+            Some(_) => match claims.expires_at() {
+                Some(exp) if now < exp + self.leeway => (),
+                Some(_) => return Err("token is expired"),
+                None => return Err("invalid exp claim"),
+            },
+            None if self.require_exp => return Err("missing exp claim"),
+            None => (),
+        }
+
+        if claims.get("nbf").is_some() {
+            match claims.not_before() {
+                Some(nbf) if nbf <= now + self.leeway => (),
+                Some(_) => return Err("token is not yet valid"),
+                None => return Err("invalid nbf claim"),
+            }
+        }
+
+        if !self.issuers.is_empty()
+            && !claims
+                .issuer()
+                .map(|iss| self.issuers.iter().any(|allowed| allowed == iss))
+                .unwrap_or_default()
+        {
+            return Err("invalid issuer");
+        }
+
+        if !self.audiences.is_empty()
+            && !claims
+                .audience()
+                .into_iter()
+                .any(|aud| self.audiences.iter().any(|allowed| allowed == aud))
+        {
+            return Err("invalid audience");
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct JwtHeader {
+    alg: String,
+    kid: Option<String>,
+}
+
+/// A JWT which is decoded, but not yet verified.
+struct DecodedJwt<'a> {
+    header: JwtHeader,
+    claims: JwtClaims,
+    signing_input: &'a str,
+    signature: Vec<u8>,
+}
+
+impl<'a> DecodedJwt<'a> {
+    fn decode(token: &'a str) -> Option<Self> {
+        let (signing_input, signature) = token.rsplit_once('.')?;
+        let (header, claims) = signing_input.split_once('.')?;
+        let header = serde_json::from_slice(&BASE64_URL.decode(header).ok()?).ok()?;
+        let claims = serde_json::from_slice(&BASE64_URL.decode(claims).ok()?).ok()?;
+        let signature = BASE64_URL.decode(signature).ok()?;
+        Some(Self {
+            header,
+            claims: JwtClaims(claims),
+            signing_input,
+            signature,
+        })
+    }
+}
+
+/// Error which occurred while validating a JWT.
+enum JwtError {
+    /// The JWT is missing, malformed, not valid or its signature cannot be verified.
+    InvalidToken(&'static str),
+    /// The key store failed to provide the keys.
+    KeyStore(OpaqueError),
+}
+
+/// Layer that applies the [`JwtAuth`] middleware,
+/// which validates the Bearer JWT of requests.
+///
+/// See the [module docs](self) for more details.
+pub struct JwtAuthLayer<K> {
+    store: K,
+    validation: Arc<JwtValidation>,
+}
+
+impl<K: fmt::Debug> fmt::Debug for JwtAuthLayer<K> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("JwtAuthLayer")
+            .field("store", &self.store)
+            .field("validation", &self.validation)
+            .finish()
+    }
+}
+
+impl<K: Clone> Clone for JwtAuthLayer<K> {
+    fn clone(&self) -> Self {
+        Self {
+            store: self.store.clone(),
+            validation: self.validation.clone(),
+        }
+    }
+}
+
+impl<K> JwtAuthLayer<K> {
+    /// Create a new [`JwtAuthLayer`] verifying the JWTs
+    /// using the keys of the given [`JwtKeyStore`].
+    pub fn new(store: K) -> Self {
+        Self {
+            store,
+            validation: Arc::new(JwtValidation::default()),
+        }
+    }
+
+    /// Define the [`JwtValidation`] rules used to validate the JWTs.
+    pub fn validation(mut self, validation: JwtValidation) -> Self {
+        self.validation = Arc::new(validation);
+        self
+    }
+
+    /// Define the [`JwtValidation`] rules used to validate the JWTs.
+    pub fn set_validation(&mut self, validation: JwtValidation) -> &mut Self {
+        self.validation = Arc::new(validation);
+        self
+    }
+}
+
+impl<S, K: Clone> Layer<S> for JwtAuthLayer<K> {
+    type Service = JwtAuth<S, K>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        JwtAuth {
+            inner,
+            store: self.store.clone(),
+            validation: self.validation.clone(),
+        }
+    }
+}
+
+/// Middleware which validates the Bearer JWT of requests.
+///
+/// See the [module docs](self) for more details.
+pub struct JwtAuth<S, K> {
+    inner: S,
+    store: K,
+    validation: Arc<JwtValidation>,
+}
+
+impl<S, K> JwtAuth<S, K> {
+    /// Create a new [`JwtAuth`] verifying the JWTs
+    /// using the keys of the given [`JwtKeyStore`].
+    pub fn new(inner: S, store: K) -> Self {
+        Self {
+            inner,
+            store,
+            validation: Arc::new(JwtValidation::default()),
+        }
+    }
+
+    /// Define the [`JwtValidation`] rules used to validate the JWTs.
+    pub fn validation(mut self, validation: JwtValidation) -> Self {
+        self.validation = Arc::new(validation);
+        self
+    }
+
+    /// Define the [`JwtValidation`] rules used to validate the JWTs.
+    pub fn set_validation(&mut self, validation: JwtValidation) -> &mut Self {
+        self.validation = Arc::new(validation);
+        self
+    }
+
+    define_inner_service_accessors!();
+
+    async fn validate(&self, token: &str, now: SystemTime) -> Result<JwtClaims, JwtError>
+    where
+        K: JwtKeyStore,
+    {
+        let jwt = DecodedJwt::decode(token).ok_or(JwtError::InvalidToken("malformed token"))?;
+
+        let alg: JwtAlgorithm = jwt
+            .header
+            .alg
+            .parse()
+            .map_err(|_| JwtError::InvalidToken("unsupported algorithm"))?;
+        if !self.validation.algorithms.contains(&alg) {
+            return Err(JwtError::InvalidToken("algorithm not allowed"));
+        }
+
+        let key = self
+            .store
+            .find_key(jwt.header.kid.as_deref(), alg)
+            .await
+            .map_err(JwtError::KeyStore)?
+            .ok_or(JwtError::InvalidToken("unknown key"))?;
+        if !key.verify(jwt.signing_input.as_bytes(), &jwt.signature) {
+            return Err(JwtError::InvalidToken("invalid signature"));
+        }
+
+        self.validation
+            .validate_claims(&jwt.claims, now)
+            .map_err(JwtError::InvalidToken)?;
+        Ok(jwt.claims)
+    }
+}
+
+impl<S: fmt::Debug, K: fmt::Debug> fmt::Debug for JwtAuth<S, K> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("JwtAuth")
+            .field("inner", &self.inner)
+            .field("store", &self.store)
+            .field("validation", &self.validation)
+            .finish()
+    }
+}
+
+impl<S: Clone, K: Clone> Clone for JwtAuth<S, K> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            store: self.store.clone(),
+            validation: self.validation.clone(),
+        }
+    }
+}
+
+impl<State, S, K, ReqBody, ResBody> Service<State, Request<ReqBody>> for JwtAuth<S, K>
+where
+    State: Clone + Send + Sync + 'static,
+    S: Service<State, Request<ReqBody>, Response = Response<ResBody>>,
+    K: JwtKeyStore,
+    ReqBody: Send + 'static,
+    ResBody: Default + Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+
+    async fn serve(
+        &self,
+        mut ctx: Context<State>,
+        req: Request<ReqBody>,
+    ) -> Result<Self::Response, Self::Error> {
+        let authorization = req.headers().typed_get::<Authorization<Bearer>>();
+        let Some(token) = authorization.as_ref().map(|auth| auth.token().trim()) else {
+            return Ok(unauthorized(None));
+        };
+
+        match self.validate(token, ctx.clock().system_now()).await {
+            Ok(claims) => {
+                ctx.insert(claims);
+                self.inner.serve(ctx, req).await
+            }
+            Err(JwtError::InvalidToken(reason)) => {
+                tracing::debug!(reason, "jwt auth: invalid token");
+                Ok(unauthorized(Some(reason)))
+            }
+            Err(JwtError::KeyStore(err)) => {
+                tracing::error!(error = %err, "jwt auth: failed to get keys from store");
+                let mut res = Response::new(ResBody::default());
+                *res.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+                Ok(res)
+            }
+        }
+    }
+}
+
+fn unauthorized<ResBody: Default>(reason: Option<&'static str>) -> Response<ResBody> {
+    let mut res = Response::new(ResBody::default());
+    *res.status_mut() = StatusCode::UNAUTHORIZED;
+    let challenge = match reason {
+        Some(reason) => HeaderValue::try_from(format!(
+            "Bearer error=\"invalid_token\", error_description=\"{reason}\""
+        ))
+        .unwrap_or(HeaderValue::from_static("Bearer")),
+        None => HeaderValue::from_static("Bearer"),
+    };
+    res.headers_mut()
+        .insert(header::WWW_AUTHENTICATE, challenge);
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Body;
+    use rama_core::service::service_fn;
+    use rama_core::time::Clock;
+    use ring::{
+        hmac,
+        rand::SystemRandom,
+        signature::{EcdsaKeyPair, KeyPair, ECDSA_P256_SHA256_FIXED_SIGNING},
+    };
+    use std::convert::Infallible;
+
+    const NOW: u64 = 1_700_000_000;
+
+    fn encode(header: &str, claims: &str, sign: impl FnOnce(&[u8]) -> Vec<u8>) -> String {
+        let signing_input = format!(
+            "{}.{}",
+            BASE64_URL.encode(header),
+            BASE64_URL.encode(claims)
+        );
+        let signature = sign(signing_input.as_bytes());
+        format!("{signing_input}.{}", BASE64_URL.encode(signature))
+    }
+
+    fn hs256(claims: &str) -> String {
+        encode(r#"{"alg":"HS256","kid":"main"}"#, claims, |input| {
+            let key = hmac::Key::new(hmac::HMAC_SHA256, b"secret");
+            hmac::sign(&key, input).as_ref().to_vec()
+        })
+    }
+
+    async fn serve<K: JwtKeyStore>(
+        service: &JwtAuth<impl Service<(), Request, Response = Response, Error = Infallible>, K>,
+        token: Option<&str>,
+    ) -> Response {
+        let mut ctx = Context::default();
+        ctx.insert(Clock::new(FixedTime));
+        let mut req = Request::builder();
+        if let Some(token) = token {
+            req = req.header(header::AUTHORIZATION, format!("Bearer {token}"));
+        }
+        service
+            .serve(ctx, req.body(Body::empty()).unwrap())
+            .await
+            .unwrap()
+    }
+
+    #[derive(Debug)]
+    struct FixedTime;
+
+    impl rama_core::time::TimeSource for FixedTime {
+        fn now(&self) -> std::time::Instant {
+            std::time::Instant::now()
+        }
+
+        fn system_now(&self) -> SystemTime {
+            UNIX_EPOCH + Duration::from_secs(NOW)
+        }
+    }
+
+    fn echo_sub() -> impl Service<(), Request, Response = Response, Error = Infallible> {
+        service_fn(|ctx: Context<()>, _req: Request| async move {
+            let claims: &JwtClaims = ctx.get().unwrap();
+            Ok(Response::new(Body::from(
+                claims.subject().unwrap_or_default().to_owned(),
+            )))
+        })
+    }
+
+    #[tokio::test]
+    async fn test_jwt_auth_hs256() {
+        let keys = JwkSet::new().with_key(Jwk::hmac("secret").with_kid("main"));
+        let service = JwtAuthLayer::new(keys)
+            .validation(
+                JwtValidation::new()
+                    .with_issuer("rama")
+                    .with_audience("api"),
+            )
+            .layer(echo_sub());
+
+        let res = serve(&service, None).await;
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(res.headers()[header::WWW_AUTHENTICATE], "Bearer");
+
+        let valid = format!(
+            r#"{{"sub":"john","iss":"rama","aud":["web","api"],"exp":{},"nbf":{}}}"#,
+            NOW + 10,
+            NOW - 10
+        );
+        let res = serve(&service, Some(&hs256(&valid))).await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        for (claims, reason) in [
+            (
+                format!(r#"{{"iss":"rama","aud":"api","exp":{}}}"#, NOW - 61),
+                "token is expired",
+            ),
+            (
+                format!(
+                    r#"{{"iss":"rama","aud":"api","exp":{},"nbf":{}}}"#,
+                    NOW + 120,
+                    NOW + 61
+                ),
+                "token is not yet valid",
+            ),
+            (
+                r#"{"iss":"rama","aud":"api"}"#.to_owned(),
+                "missing exp claim",
+            ),
+            (
+                format!(r#"{{"iss":"other","aud":"api","exp":{}}}"#, NOW + 10),
+                "invalid issuer",
+            ),
+            (
+                format!(r#"{{"iss":"rama","aud":"web","exp":{}}}"#, NOW + 10),
+                "invalid audience",
+            ),
+        ] {
+            let res = serve(&service, Some(&hs256(&claims))).await;
+            assert_eq!(res.status(), StatusCode::UNAUTHORIZED, "{claims}");
+            assert_eq!(
+                res.headers()[header::WWW_AUTHENTICATE],
+                format!("Bearer error=\"invalid_token\", error_description=\"{reason}\""),
+            );
+        }
+
+        // tampered claims
+        let token = hs256(&valid);
+        let (_, signature) = token.rsplit_once('.').unwrap();
+        let tampered = format!(
+            "{}.{}.{signature}",
+            BASE64_URL.encode(r#"{"alg":"HS256","kid":"main"}"#),
+            BASE64_URL.encode(valid.replace("john", "jane")),
+        );
+        let res = serve(&service, Some(&tampered)).await;
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+        // unsigned tokens are never accepted
+        let unsigned = encode(r#"{"alg":"none"}"#, &valid, |_| Vec::new());
+        let res = serve(&service, Some(&unsigned)).await;
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_jwt_auth_es256() {
+        let rng = SystemRandom::new();
+        let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &rng).unwrap();
+        let key_pair =
+            EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, pkcs8.as_ref(), &rng)
+                .unwrap();
+        let point = key_pair.public_key().as_ref();
+        let jwks = format!(
+            r#"{{"keys":[{{"kty":"EC","crv":"P-256","kid":"ec","x":"{}","y":"{}"}}]}}"#,
+            BASE64_URL.encode(&point[1..33]),
+            BASE64_URL.encode(&point[33..]),
+        );
+        let keys = JwkSet::parse(jwks.as_bytes()).unwrap();
+
+        let service = JwtAuthLayer::new(keys).layer(echo_sub());
+
+        let claims = format!(r#"{{"sub":"john","exp":{}}}"#, NOW + 10);
+        let token = encode(r#"{"alg":"ES256","kid":"ec"}"#, &claims, |input| {
+            key_pair.sign(&rng, input).unwrap().as_ref().to_vec()
+        });
+        let res = serve(&service, Some(&token)).await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        // the algorithm has to match the key
+        let token = encode(r#"{"alg":"HS256","kid":"ec"}"#, &claims, |input| {
+            let key = hmac::Key::new(hmac::HMAC_SHA256, point);
+            hmac::sign(&key, input).as_ref().to_vec()
+        });
+        let res = serve(&service, Some(&token)).await;
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+        // only allowed algorithms are accepted
+        let keys = JwkSet::parse(jwks.as_bytes()).unwrap();
+        let service = JwtAuthLayer::new(keys)
+            .validation(JwtValidation::new().algorithms([JwtAlgorithm::RS256]))
+            .layer(echo_sub());
+        let token = encode(r#"{"alg":"ES256","kid":"ec"}"#, &claims, |input| {
+            key_pair.sign(&rng, input).unwrap().as_ref().to_vec()
+        });
+        let res = serve(&service, Some(&token)).await;
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+    }
+}
@@ -0,0 +1,295 @@
+use super::{Jwk, JwkSet, JwtAlgorithm};
+use crate::{dep::http_body, header, BodyExtractExt, Request, Response, Uri};
+use rama_core::error::{BoxError, ErrorContext, OpaqueError};
+use rama_core::{Context, Service};
+use std::{
+    fmt,
+    future::Future,
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+use tokio::time::Instant;
+
+/// Default time the keys fetched by [`RemoteJwks`] are cached.
+const DEFAULT_TTL: Duration = Duration::from_secs(300);
+
+/// Default minimum time between two fetches of the keys by [`RemoteJwks`].
+const DEFAULT_MIN_REFRESH_INTERVAL: Duration = Duration::from_secs(30);
+
+/// A store of [`Jwk`]s, used to find the key to verify a JWT with.
+pub trait JwtKeyStore: Send + Sync + 'static {
+    /// Find the key with the given key id (if any) to be used for the given algorithm,
+    /// returning `None` if there is no such key.
+    fn find_key<'a>(
+        &'a self,
+        kid: Option<&'a str>,
+        alg: JwtAlgorithm,
+    ) -> impl Future<Output = Result<Option<Jwk>, OpaqueError>> + Send + 'a;
+}
+
+impl<T: JwtKeyStore> JwtKeyStore for Arc<T> {
+    fn find_key<'a>(
+        &'a self,
+        kid: Option<&'a str>,
+        alg: JwtAlgorithm,
+    ) -> impl Future<Output = Result<Option<Jwk>, OpaqueError>> + Send + 'a {
+        (**self).find_key(kid, alg)
+    }
+}
+
+impl JwtKeyStore for JwkSet {
+    fn find_key<'a>(
+        &'a self,
+        kid: Option<&'a str>,
+        alg: JwtAlgorithm,
+    ) -> impl Future<Output = Result<Option<Jwk>, OpaqueError>> + Send + 'a {
+        std::future::ready(Ok(self.find(kid, alg).cloned()))
+    }
+}
+
+/// A [`JwtKeyStore`] fetching the [`JwkSet`] from a (JWKS) url.
+///
+/// The fetched keys are cached for a configurable ttl. Keys are fetched
+/// again earlier when a JWT refers to an unknown key id, e.g. because the keys were rotated,
+/// but never more than once within the configured min refresh interval.
+///
+/// When fetching the keys fails the previously fetched keys
+/// (if any) remain in use, until the next fetch.
+pub struct RemoteJwks<C> {
+    client: C,
+    uri: Uri,
+    ttl: Duration,
+    min_refresh_interval: Duration,
+    cache: Arc<JwksCache>,
+}
+
+#[derive(Debug, Default)]
+struct JwksCache {
+    keys: RwLock<Option<CachedJwks>>,
+    refresh: tokio::sync::Mutex<()>,
+}
+
+#[derive(Debug, Clone)]
+struct CachedJwks {
+    keys: Arc<JwkSet>,
+    fetched_at: Instant,
+}
+
+impl<C: fmt::Debug> fmt::Debug for RemoteJwks<C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RemoteJwks")
+            .field("client", &self.client)
+            .field("uri", &self.uri)
+            .field("ttl", &self.ttl)
+            .field("min_refresh_interval", &self.min_refresh_interval)
+            .finish()
+    }
+}
+
+impl<C: Clone> Clone for RemoteJwks<C> {
+    fn clone(&self) -> Self {
+        Self {
+            client: self.client.clone(),
+            uri: self.uri.clone(),
+            ttl: self.ttl,
+            min_refresh_interval: self.min_refresh_interval,
+            cache: self.cache.clone(),
+        }
+    }
+}
+
+impl<C> RemoteJwks<C> {
+    /// Create a new [`RemoteJwks`], fetching the keys from the given url using the given http client.
+    pub fn new(client: C, uri: Uri) -> Self {
+        Self {
+            client,
+            uri,
+            ttl: DEFAULT_TTL,
+            min_refresh_interval: DEFAULT_MIN_REFRESH_INTERVAL,
+            cache: Arc::default(),
+        }
+    }
+
+    /// Define how long the fetched keys are cached.
+    ///
+    /// Defaults to 5 minutes.
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Define how long the fetched keys are cached.
+    ///
+    /// Defaults to 5 minutes.
+    pub fn set_ttl(&mut self, ttl: Duration) -> &mut Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Define the minimum time between two fetches of the keys.
+    ///
+    /// Defaults to 30 seconds.
+    pub fn min_refresh_interval(mut self, interval: Duration) -> Self {
+        self.min_refresh_interval = interval;
+        self
+    }
+
+    /// Define the minimum time between two fetches of the keys.
+    ///
+    /// Defaults to 30 seconds.
+    pub fn set_min_refresh_interval(&mut self, interval: Duration) -> &mut Self {
+        self.min_refresh_interval = interval;
+        self
+    }
+
+    fn cached(&self) -> Option<CachedJwks> {
+        self.cache.keys.read().unwrap().clone()
+    }
+}
+
+impl<C, Body> RemoteJwks<C>
+where
+    C: Service<(), Request, Response = Response<Body>, Error: Into<BoxError>>,
+    Body: http_body::Body<Data: Send + 'static, Error: Into<BoxError>> + Send + 'static,
+{
+    async fn fetch(&self) -> Result<JwkSet, OpaqueError> {
+        let req = Request::get(self.uri.clone())
+            .header(header::ACCEPT, "application/json")
+            .body(crate::Body::empty())
+            .context("jwks: create request")?;
+        let res = self
+            .client
+            .serve(Context::default(), req)
+            .await
+            .map_err(|err| OpaqueError::from_boxed(err.into()))
+            .context("jwks: fetch keys")?;
+        if !res.status().is_success() {
+            return Err(OpaqueError::from_display(format!(
+                "jwks: unexpected response status: {}",
+                res.status()
+            )));
+        }
+        res.try_into_json().await.context("jwks: read keys")
+    }
+
+    async fn refresh(&self, stale: Option<CachedJwks>) -> Result<Arc<JwkSet>, OpaqueError> {
+        let _guard = self.cache.refresh.lock().await;
+
+        // keys might have been refreshed while waiting for the guard
+        let current = self.cached();
+        if let Some(current) = current.as_ref() {
+            if stale
+                .as_ref()
+                .map(|stale| stale.fetched_at < current.fetched_at)
+                .unwrap_or(true)
+            {
+                return Ok(current.keys.clone());
+            }
+        }
+
+        match self.fetch().await {
+            Ok(keys) => {
+                tracing::trace!(uri = %self.uri, "jwks: fetched {} key(s)", keys.keys().len());
+                let keys = Arc::new(keys);
+                *self.cache.keys.write().unwrap() = Some(CachedJwks {
+                    keys: keys.clone(),
+                    fetched_at: Instant::now(),
+                });
+                Ok(keys)
+            }
+            Err(err) => match current {
+                Some(current) => {
+                    tracing::error!(error = %err, uri = %self.uri, "jwks: failed to fetch keys, use previous keys");
+                    // do not try again before the min refresh interval passed
+                    *self.cache.keys.write().unwrap() = Some(CachedJwks {
+                        keys: current.keys.clone(),
+                        fetched_at: Instant::now()
+                            .checked_sub(self.ttl.saturating_sub(self.min_refresh_interval))
+                            .unwrap_or_else(Instant::now),
+                    });
+                    Ok(current.keys)
+                }
+                None => Err(err),
+            },
+        }
+    }
+}
+
+impl<C, Body> JwtKeyStore for RemoteJwks<C>
+where
+    C: Service<(), Request, Response = Response<Body>, Error: Into<BoxError>>,
+    Body: http_body::Body<Data: Send + 'static, Error: Into<BoxError>> + Send + 'static,
+{
+    async fn find_key<'a>(
+        &'a self,
+        kid: Option<&'a str>,
+        alg: JwtAlgorithm,
+    ) -> Result<Option<Jwk>, OpaqueError> {
+        let cached = self.cached();
+        if let Some(cached) = cached.as_ref() {
+            let age = cached.fetched_at.elapsed();
+            if age < self.ttl {
+                if let Some(key) = cached.keys.find(kid, alg) {
+                    return Ok(Some(key.clone()));
+                }
+                if kid.is_none() || age < self.min_refresh_interval {
+                    return Ok(None);
+                }
+            }
+        }
+
+        let keys = self.refresh(cached).await?;
+        Ok(keys.find(kid, alg).cloned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Body;
+    use rama_core::service::service_fn;
+    use std::{
+        convert::Infallible,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    #[tokio::test(start_paused = true)]
+    async fn test_remote_jwks_cache() {
+        let fetches = Arc::new(AtomicUsize::new(0));
+        let client = service_fn({
+            let fetches = fetches.clone();
+            move |req: Request| {
+                let fetches = fetches.clone();
+                async move {
+                    assert_eq!(req.uri(), "https://example.com/jwks.json");
+                    let kid = fetches.fetch_add(1, Ordering::SeqCst);
+                    Ok::<_, Infallible>(Response::new(Body::from(format!(
+                        r#"{{"keys": [{{"kty": "oct", "kid": "{kid}", "k": "c2VjcmV0"}}]}}"#
+                    ))))
+                }
+            }
+        });
+        let store = RemoteJwks::new(client, Uri::from_static("https://example.com/jwks.json"));
+
+        let find = |kid| store.find_key(Some(kid), JwtAlgorithm::HS256);
+
+        assert!(find("0").await.unwrap().is_some());
+        assert!(find("0").await.unwrap().is_some());
+        assert_eq!(fetches.load(Ordering::SeqCst), 1);
+
+        // unknown keys do not refresh within the min refresh interval
+        assert!(find("1").await.unwrap().is_none());
+        assert_eq!(fetches.load(Ordering::SeqCst), 1);
+
+        // but do refresh after it
+        tokio::time::advance(DEFAULT_MIN_REFRESH_INTERVAL).await;
+        assert!(find("1").await.unwrap().is_some());
+        assert!(find("0").await.unwrap().is_none());
+        assert_eq!(fetches.load(Ordering::SeqCst), 2);
+
+        // keys are refreshed once expired
+        tokio::time::advance(DEFAULT_TTL).await;
+        assert!(find("2").await.unwrap().is_some());
+        assert_eq!(fetches.load(Ordering::SeqCst), 3);
+    }
+}
@@ -1,13 +1,29 @@
 //! Authorization related middleware.
 
 pub mod add_authorization;
+pub mod api_key;
 pub mod async_require_authorization;
+pub mod credential_store;
 pub mod require_authorization;
 
+#[cfg(feature = "jwt")]
+pub mod jwt;
+
 #[doc(inline)]
 pub use self::{
     add_authorization::{AddAuthorization, AddAuthorizationLayer},
+    api_key::{ApiKey, ApiKeyLayer},
     async_require_authorization::{
         AsyncAuthorizeRequest, AsyncRequireAuthorization, AsyncRequireAuthorizationLayer,
     },
+    credential_store::{CredentialStore, StaticCredentialStore},
+    require_authorization::{RequireAuthorization, RequireAuthorizationLayer},
 };
+
+#[cfg(feature = "htpasswd")]
+#[doc(inline)]
+pub use self::credential_store::HtpasswdCredentialStore;
+
+#[cfg(feature = "jwt")]
+#[doc(inline)]
+pub use self::jwt::{JwtAuth, JwtAuthLayer};
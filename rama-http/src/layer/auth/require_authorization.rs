@@ -51,10 +51,14 @@
 //! ```
 //!
 //! Custom validation can be made by implementing [`ValidateRequest`].
+//!
+//! To validate credentials against a [`CredentialStore`], e.g. of multiple users
+//! or an htpasswd file, in origin or proxy mode, use the [`RequireAuthorizationLayer`].
 
 use base64::Engine as _;
 use std::{fmt, marker::PhantomData};
 
+use super::CredentialStore;
 use crate::layer::validate_request::{
     ValidateRequest, ValidateRequestHeader, ValidateRequestHeaderLayer,
 };
@@ -62,7 +66,9 @@ use crate::{
     header::{self, HeaderValue},
     Request, Response, StatusCode,
 };
-use rama_core::Context;
+use rama_core::{Context, Layer, Service};
+use rama_net::user::ProxyCredential;
+use rama_utils::macros::define_inner_service_accessors;
 
 const BASE64: base64::engine::GeneralPurpose = base64::engine::general_purpose::STANDARD;
 
@@ -258,16 +264,261 @@ where
     }
 }
 
+/// Layer that applies the [`RequireAuthorization`] middleware,
+/// which validates the credentials of requests using a [`CredentialStore`].
+///
+/// In origin mode ([`RequireAuthorizationLayer::new`]) the credentials are read
+/// from the `Authorization` header, and unauthorized requests get a
+/// `401 Unauthorized` response with a `WWW-Authenticate` challenge.
+///
+/// In proxy mode ([`RequireAuthorizationLayer::proxy`]) the credentials are read
+/// from the `Proxy-Authorization` header (which is removed before the request
+/// is passed on), and unauthorized requests get a `407 Proxy Authentication Required`
+/// response with a `Proxy-Authenticate` challenge.
+///
+/// A challenge is sent for each scheme supported by the store (`Basic` and/or `Bearer`).
+/// The [`UserId`] of authorized requests is inserted in the [`Context`].
+///
+/// [`UserId`]: rama_net::user::UserId
+pub struct RequireAuthorizationLayer<C> {
+    store: C,
+    mode: AuthMode,
+    realm: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum AuthMode {
+    Origin,
+    Proxy,
+}
+
+impl<C: fmt::Debug> fmt::Debug for RequireAuthorizationLayer<C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RequireAuthorizationLayer")
+            .field("store", &self.store)
+            .field("mode", &self.mode)
+            .field("realm", &self.realm)
+            .finish()
+    }
+}
+
+impl<C: Clone> Clone for RequireAuthorizationLayer<C> {
+    fn clone(&self) -> Self {
+        Self {
+            store: self.store.clone(),
+            mode: self.mode,
+            realm: self.realm.clone(),
+        }
+    }
+}
+
+impl<C> RequireAuthorizationLayer<C> {
+    /// Create a new [`RequireAuthorizationLayer`] validating the `Authorization` header.
+    pub const fn new(store: C) -> Self {
+        Self {
+            store,
+            mode: AuthMode::Origin,
+            realm: None,
+        }
+    }
+
+    /// Create a new [`RequireAuthorizationLayer`] validating the `Proxy-Authorization` header.
+    pub const fn proxy(store: C) -> Self {
+        Self {
+            store,
+            mode: AuthMode::Proxy,
+            realm: None,
+        }
+    }
+
+    /// Set the realm advertised in the challenges.
+    pub fn realm(mut self, realm: impl Into<String>) -> Self {
+        self.realm = Some(realm.into());
+        self
+    }
+
+    /// Set the realm advertised in the challenges.
+    pub fn set_realm(&mut self, realm: impl Into<String>) -> &mut Self {
+        self.realm = Some(realm.into());
+        self
+    }
+}
+
+impl<S, C: Clone> Layer<S> for RequireAuthorizationLayer<C> {
+    type Service = RequireAuthorization<S, C>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RequireAuthorization {
+            inner,
+            store: self.store.clone(),
+            mode: self.mode,
+            realm: self.realm.clone(),
+        }
+    }
+}
+
+/// Middleware which validates the credentials of requests using a [`CredentialStore`].
+///
+/// See [`RequireAuthorizationLayer`] for more details.
+pub struct RequireAuthorization<S, C> {
+    inner: S,
+    store: C,
+    mode: AuthMode,
+    realm: Option<String>,
+}
+
+impl<S, C> RequireAuthorization<S, C> {
+    /// Create a new [`RequireAuthorization`] validating the `Authorization` header.
+    pub const fn new(inner: S, store: C) -> Self {
+        Self {
+            inner,
+            store,
+            mode: AuthMode::Origin,
+            realm: None,
+        }
+    }
+
+    /// Create a new [`RequireAuthorization`] validating the `Proxy-Authorization` header.
+    pub const fn proxy(inner: S, store: C) -> Self {
+        Self {
+            inner,
+            store,
+            mode: AuthMode::Proxy,
+            realm: None,
+        }
+    }
+
+    /// Set the realm advertised in the challenges.
+    pub fn realm(mut self, realm: impl Into<String>) -> Self {
+        self.realm = Some(realm.into());
+        self
+    }
+
+    /// Set the realm advertised in the challenges.
+    pub fn set_realm(&mut self, realm: impl Into<String>) -> &mut Self {
+        self.realm = Some(realm.into());
+        self
+    }
+
+    define_inner_service_accessors!();
+
+    fn unauthorized<ResBody: Default>(&self) -> Response<ResBody>
+    where
+        C: CredentialStore,
+    {
+        let (status, challenge_header) = match self.mode {
+            AuthMode::Origin => (StatusCode::UNAUTHORIZED, header::WWW_AUTHENTICATE),
+            AuthMode::Proxy => (
+                StatusCode::PROXY_AUTHENTICATION_REQUIRED,
+                header::PROXY_AUTHENTICATE,
+            ),
+        };
+
+        let mut res = Response::new(ResBody::default());
+        *res.status_mut() = status;
+
+        let realm = self
+            .realm
+            .as_deref()
+            .map(|realm| format!(" realm=\"{}\"", realm.replace(['\\', '"'], "")));
+        let realm = realm.as_deref().unwrap_or_default();
+        let mut challenges = Vec::with_capacity(2);
+        if self.store.supports_basic() {
+            challenges.push(format!("Basic{realm}"));
+        }
+        if self.store.supports_bearer() {
+            challenges.push(format!("Bearer{realm}"));
+        }
+        for challenge in challenges {
+            match HeaderValue::try_from(challenge) {
+                Ok(value) => {
+                    res.headers_mut().append(challenge_header.clone(), value);
+                }
+                Err(err) => {
+                    tracing::debug!(error = %err, "invalid auth challenge: realm is not a valid header value");
+                }
+            }
+        }
+        res
+    }
+}
+
+impl<S: fmt::Debug, C: fmt::Debug> fmt::Debug for RequireAuthorization<S, C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RequireAuthorization")
+            .field("inner", &self.inner)
+            .field("store", &self.store)
+            .field("mode", &self.mode)
+            .field("realm", &self.realm)
+            .finish()
+    }
+}
+
+impl<S: Clone, C: Clone> Clone for RequireAuthorization<S, C> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            store: self.store.clone(),
+            mode: self.mode,
+            realm: self.realm.clone(),
+        }
+    }
+}
+
+impl<State, S, C, ReqBody, ResBody> Service<State, Request<ReqBody>> for RequireAuthorization<S, C>
+where
+    State: Clone + Send + Sync + 'static,
+    S: Service<State, Request<ReqBody>, Response = Response<ResBody>>,
+    C: CredentialStore,
+    ReqBody: Send + 'static,
+    ResBody: Default + Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+
+    async fn serve(
+        &self,
+        mut ctx: Context<State>,
+        mut req: Request<ReqBody>,
+    ) -> Result<Self::Response, Self::Error> {
+        let header_name = match self.mode {
+            AuthMode::Origin => header::AUTHORIZATION,
+            AuthMode::Proxy => header::PROXY_AUTHORIZATION,
+        };
+
+        let credential = req
+            .headers()
+            .get(&header_name)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| ProxyCredential::try_from_header_str(value).ok());
+        let Some(credential) = credential else {
+            return Ok(self.unauthorized());
+        };
+
+        let Some(user_id) = self.store.validate(credential).await else {
+            return Ok(self.unauthorized());
+        };
+
+        if self.mode == AuthMode::Proxy {
+            req.headers_mut().remove(header::PROXY_AUTHORIZATION);
+        }
+        ctx.insert(user_id);
+        self.inner.serve(ctx, req).await
+    }
+}
+
 #[cfg(test)]
 mod tests {
     #[allow(unused_imports)]
     use super::*;
 
+    use crate::layer::auth::StaticCredentialStore;
     use crate::layer::validate_request::ValidateRequestHeaderLayer;
     use crate::{header, Body};
     use rama_core::error::BoxError;
     use rama_core::service::service_fn;
     use rama_core::{Context, Layer, Service};
+    use rama_net::user::UserId;
 
     #[tokio::test]
     async fn valid_basic_token() {
@@ -396,6 +647,91 @@ mod tests {
         assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
     }
 
+    #[tokio::test]
+    async fn require_authorization_origin() {
+        let store = StaticCredentialStore::new()
+            .with_user("john", "secret")
+            .with_token("abc");
+        let service = RequireAuthorizationLayer::new(store)
+            .realm("test")
+            .layer(service_fn(|ctx: Context<()>, _req: Request| async move {
+                let user_id: &UserId = ctx.get().unwrap();
+                Ok::<_, BoxError>(Response::new(Body::from(format!("{user_id:?}"))))
+            }));
+
+        let res = service
+            .serve(Context::default(), Request::new(Body::empty()))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+        let challenges: Vec<_> = res
+            .headers()
+            .get_all(header::WWW_AUTHENTICATE)
+            .iter()
+            .collect();
+        assert_eq!(
+            challenges,
+            ["Basic realm=\"test\"", "Bearer realm=\"test\""]
+        );
+
+        for (value, expected) in [
+            (format!("Basic {}", BASE64.encode("john:secret")), true),
+            (format!("Basic {}", BASE64.encode("john:wrong")), false),
+            ("Bearer abc".to_owned(), true),
+            ("Bearer abcd".to_owned(), false),
+        ] {
+            let request = Request::get("/")
+                .header(header::AUTHORIZATION, &value)
+                .body(Body::empty())
+                .unwrap();
+            let res = service.serve(Context::default(), request).await.unwrap();
+            if expected {
+                assert_eq!(res.status(), StatusCode::OK, "{value}");
+            } else {
+                assert_eq!(res.status(), StatusCode::UNAUTHORIZED, "{value}");
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn require_authorization_proxy() {
+        let store = StaticCredentialStore::new().with_user("john", "secret");
+        let service = RequireAuthorizationLayer::proxy(store).layer(service_fn(
+            |ctx: Context<()>, req: Request| async move {
+                assert!(!req.headers().contains_key(header::PROXY_AUTHORIZATION));
+                assert_eq!(ctx.get::<UserId>().unwrap(), "john");
+                Ok::<_, BoxError>(Response::new(Body::empty()))
+            },
+        ));
+
+        // the origin authorization header is not used in proxy mode
+        let request = Request::get("/")
+            .header(
+                header::AUTHORIZATION,
+                format!("Basic {}", BASE64.encode("john:secret")),
+            )
+            .body(Body::empty())
+            .unwrap();
+        let res = service.serve(Context::default(), request).await.unwrap();
+        assert_eq!(res.status(), StatusCode::PROXY_AUTHENTICATION_REQUIRED);
+        let challenges: Vec<_> = res
+            .headers()
+            .get_all(header::PROXY_AUTHENTICATE)
+            .iter()
+            .collect();
+        assert_eq!(challenges, ["Basic"]);
+
+        let request = Request::get("/")
+            .header(
+                header::PROXY_AUTHORIZATION,
+                format!("Basic {}", BASE64.encode("john:secret")),
+            )
+            .body(Body::empty())
+            .unwrap();
+        let res = service.serve(Context::default(), request).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+
     async fn echo<Body>(req: Request<Body>) -> Result<Response<Body>, BoxError> {
         Ok(Response::new(req.into_body()))
     }
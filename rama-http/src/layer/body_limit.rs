@@ -1,5 +1,13 @@
 //! Apply a limit to the request body.
 //!
+//! Requests which declare (using the `Content-Length` header) a body larger than the limit
+//! are rejected with `413 Payload Too Large`, without calling the inner service.
+//! All other request bodies are wrapped, such that reading (or streaming) more bytes
+//! than the limit results in an error for the consumer of the body. In that case
+//! the response of the inner service is replaced by a `413 Payload Too Large` as well.
+//!
+//! A limit of `0` disables the limit, in which case request bodies are passed through as-is.
+//!
 //! # Example
 //!
 //! ```
@@ -29,15 +37,29 @@
 //! # }
 //! ```
 
-use crate::dep::http_body_util::Limited;
-use crate::Request;
+use crate::dep::http_body_util::{LengthLimitError, Limited};
+use crate::headers::{ContentLength, HeaderMapExt};
+use crate::{Request, Response, StatusCode};
 use bytes::Bytes;
+use http_body::Frame;
+use pin_project_lite::pin_project;
 use rama_core::{error::BoxError, Context, Layer, Service};
 use rama_http_types::Body;
 use rama_utils::macros::define_inner_service_accessors;
-use std::fmt;
+use std::{
+    fmt,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    task::{ready, Context as TaskContext, Poll},
+};
 
-/// Apply a limit to the request body's size.
+/// Apply a limit to the request body's size,
+/// rejecting requests with a larger body with `413 Payload Too Large`.
+///
+/// A size of `0` disables the limit.
 ///
 /// See the [module docs](crate::layer::body_limit) for an example.
 #[derive(Debug, Clone)]
@@ -46,7 +68,9 @@ pub struct BodyLimitLayer {
 }
 
 impl BodyLimitLayer {
-    /// Create a new [`BodyLimitLayer`].
+    /// Create a new [`BodyLimitLayer`], limiting request bodies to `size` bytes.
+    ///
+    /// A `size` of `0` disables the limit, rather than rejecting all non-empty bodies.
     pub const fn new(size: usize) -> Self {
         Self { size }
     }
@@ -60,7 +84,8 @@ impl<S> Layer<S> for BodyLimitLayer {
     }
 }
 
-/// Apply a transformation to the request body.
+/// Apply a limit to the request body's size,
+/// rejecting requests with a larger body with `413 Payload Too Large`.
 ///
 /// See the [module docs](crate::layer::body_limit) for an example.
 #[derive(Clone)]
@@ -70,7 +95,9 @@ pub struct BodyLimitService<S> {
 }
 
 impl<S> BodyLimitService<S> {
-    /// Create a new [`BodyLimitService`].
+    /// Create a new [`BodyLimitService`], limiting request bodies to `size` bytes.
+    ///
+    /// A `size` of `0` disables the limit, rather than rejecting all non-empty bodies.
     pub const fn new(service: S, size: usize) -> Self {
         Self {
             inner: service,
@@ -81,13 +108,14 @@ impl<S> BodyLimitService<S> {
     define_inner_service_accessors!();
 }
 
-impl<S, State, ReqBody> Service<State, Request<ReqBody>> for BodyLimitService<S>
+impl<S, State, ReqBody, ResBody> Service<State, Request<ReqBody>> for BodyLimitService<S>
 where
-    S: Service<State, Request<Body>>,
+    S: Service<State, Request<Body>, Response = Response<ResBody>>,
     State: Clone + Send + Sync + 'static,
     ReqBody: http_body::Body<Data = Bytes, Error: Into<BoxError>> + Send + Sync + 'static,
+    ResBody: http_body::Body<Data = Bytes, Error: Into<BoxError>> + Send + Sync + 'static,
 {
-    type Response = S::Response;
+    type Response = Response;
     type Error = S::Error;
 
     async fn serve(
@@ -95,14 +123,88 @@ where
         ctx: Context<State>,
         req: Request<ReqBody>,
     ) -> Result<Self::Response, Self::Error> {
+        if self.size == 0 {
+            let res = self.inner.serve(ctx, req.map(Body::new)).await?;
+            return Ok(res.map(Body::new));
+        }
+
+        let declared_size = req
+            .headers()
+            .typed_get::<ContentLength>()
+            .map(|ContentLength(length)| length);
+        if declared_size.unwrap_or_default() > self.size as u64
+            || http_body::Body::size_hint(req.body()).lower() > self.size as u64
+        {
+            tracing::debug!(
+                limit = self.size,
+                ?declared_size,
+                "body limit: reject request with too large body"
+            );
+            return Ok(payload_too_large());
+        }
+
+        let exceeded = Arc::new(AtomicBool::new(false));
         let req = req.map(|body| {
-            if self.size == 0 {
-                Body::new(body)
-            } else {
-                Body::new(Limited::new(body, self.size))
-            }
+            Body::new(LimitedBody {
+                inner: Limited::new(body, self.size),
+                exceeded: exceeded.clone(),
+            })
         });
-        self.inner.serve(ctx, req).await
+
+        let result = self.inner.serve(ctx, req).await;
+        if exceeded.load(Ordering::Acquire) {
+            tracing::debug!(
+                limit = self.size,
+                "body limit: streamed request body exceeded limit"
+            );
+            return Ok(payload_too_large());
+        }
+        result.map(|res| res.map(Body::new))
+    }
+}
+
+fn payload_too_large() -> Response {
+    let mut res = Response::new(Body::empty());
+    *res.status_mut() = StatusCode::PAYLOAD_TOO_LARGE;
+    res
+}
+
+pin_project! {
+    /// [`Limited`] body which records whether or not the limit was exceeded.
+    struct LimitedBody<B> {
+        #[pin]
+        inner: Limited<B>,
+        exceeded: Arc<AtomicBool>,
+    }
+}
+
+impl<B> http_body::Body for LimitedBody<B>
+where
+    B: http_body::Body<Data = Bytes, Error: Into<BoxError>>,
+{
+    type Data = Bytes;
+    type Error = BoxError;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut TaskContext<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let this = self.project();
+        let frame = ready!(this.inner.poll_frame(cx));
+        if let Some(Err(err)) = &frame {
+            if err.is::<LengthLimitError>() {
+                this.exceeded.store(true, Ordering::Release);
+            }
+        }
+        Poll::Ready(frame)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> http_body::SizeHint {
+        self.inner.size_hint()
     }
 }
 
@@ -117,3 +219,91 @@ where
             .finish()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dep::http_body_util::BodyExt;
+    use crate::header;
+    use rama_core::service::service_fn;
+    use std::convert::Infallible;
+
+    fn svc(size: usize) -> impl Service<(), Request, Response = Response, Error = Infallible> {
+        BodyLimitLayer::new(size).layer(service_fn(|req: Request| async move {
+            match req.into_body().collect().await {
+                Ok(body) => Ok(Response::new(Body::from(body.to_bytes()))),
+                Err(_) => {
+                    let mut res = Response::new(Body::empty());
+                    *res.status_mut() = StatusCode::BAD_REQUEST;
+                    Ok(res)
+                }
+            }
+        }))
+    }
+
+    #[tokio::test]
+    async fn test_body_limit_within_limit() {
+        let res = svc(8)
+            .serve(Context::default(), Request::new(Body::from("12345678")))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let body = res.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, "12345678");
+    }
+
+    #[tokio::test]
+    async fn test_body_limit_declared_too_large() {
+        let req = Request::builder()
+            .header(header::CONTENT_LENGTH, "9")
+            .body(Body::from("123456789"))
+            .unwrap();
+        let res = svc(8).serve(Context::default(), req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    #[tokio::test]
+    async fn test_body_limit_streamed_too_large() {
+        let stream = futures_lite::stream::iter(
+            ["1234", "5678", "9"].map(|chunk| Ok::<_, Infallible>(Bytes::from(chunk))),
+        );
+        let req = Request::new(Body::from_stream(stream));
+        let res = svc(8).serve(Context::default(), req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    #[tokio::test]
+    async fn test_body_limit_response_body_without_default() {
+        use crate::dep::http_body_util::StreamBody;
+
+        let svc = BodyLimitLayer::new(8).layer(service_fn(|_: Request| async move {
+            let stream = futures_lite::stream::iter([Ok::<_, Infallible>(Frame::data(
+                Bytes::from_static(b"ok"),
+            ))]);
+            Ok::<_, Infallible>(Response::new(StreamBody::new(stream)))
+        }));
+
+        let res = svc
+            .serve(Context::default(), Request::new(Body::from("1234")))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let body = res.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, "ok");
+
+        let res = svc
+            .serve(Context::default(), Request::new(Body::from("123456789")))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    #[tokio::test]
+    async fn test_body_limit_disabled() {
+        let res = svc(0)
+            .serve(Context::default(), Request::new(Body::from("123456789")))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+}
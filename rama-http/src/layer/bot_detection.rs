@@ -0,0 +1,561 @@
+//! Bot detection, scoring requests on how likely they are sent by a bot.
+//!
+//! The [`BotDetectionLayer`] combines several (fingerprint) signals of a request
+//! into a [`BotScore`] between `0` and `100`, which is inserted in the [`Context`]:
+//!
+//! - a missing `User-Agent`, or one of a well-known http library or automation tool;
+//! - a browser `User-Agent`, while missing the headers each browser sends,
+//!   or sending its headers in an order no browser does;
+//! - with the `tls` feature enabled, the [`Ja4`] fingerprint of the [`ClientHello`]
+//!   (if stored by the tls acceptor), matched against a list of known bot fingerprints,
+//!   and checked for consistency with the browser advertised by the `User-Agent`.
+//!
+//! Each [`BotSignal`] adds its weight to the score, which can be configured.
+//! Http/2 (settings) fingerprints are not captured by the http server,
+//! and are therefore not (yet) used as a signal.
+//!
+//! By default requests are only tagged with their score. Optionally requests
+//! with a high enough score can be challenged, by responding with a custom
+//! response (e.g. a javascript or captcha challenge page), or blocked
+//! with a `403 Forbidden` response.
+//!
+//! [`Ja4`]: rama_net::tls::client::Ja4
+//! [`ClientHello`]: rama_net::tls::client::ClientHello
+//!
+//! # Example
+//!
+//! ```
+//! use rama_http::layer::bot_detection::{BotAction, BotDetectionLayer, BotScore, BotSignal};
+//! use rama_http::{Body, Request, Response, StatusCode};
+//! use rama_core::service::service_fn;
+//! use rama_core::{Context, Layer, Service};
+//! use std::convert::Infallible;
+//!
+//! # #[tokio::main]
+//! # async fn main() {
+//! let svc = BotDetectionLayer::new()
+//!     .with_block_threshold(80)
+//!     .layer(service_fn(|ctx: Context<()>, _req: Request| async move {
+//!         let score = ctx.get::<BotScore>().unwrap();
+//!         assert_eq!(score.action(), BotAction::Tag);
+//!         Ok::<_, Infallible>(Response::new(Body::from(score.score().to_string())))
+//!     }));
+//!
+//! // a (weak) signal is only tagged
+//! let req = Request::builder()
+//!     .header("user-agent", "Mozilla/5.0 (Windows NT 10.0; Win64; x64; rv:125.0) Gecko/20100101 Firefox/125.0")
+//!     .header("accept", "text/html")
+//!     .body(Body::empty())
+//!     .unwrap();
+//! let resp = svc.serve(Context::default(), req).await.unwrap();
+//! assert_eq!(resp.status(), StatusCode::OK);
+//!
+//! // while automation tools are blocked
+//! let req = Request::builder()
+//!     .header("user-agent", "python-requests/2.31.0")
+//!     .body(Body::empty())
+//!     .unwrap();
+//! let resp = svc.serve(Context::default(), req).await.unwrap();
+//! assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+//! # }
+//! ```
+
+use crate::{header, HeaderMap, IntoResponse, Request, Response, StatusCode};
+use rama_core::{Context, Layer, Service};
+use rama_ua::{UserAgent, UserAgentKind};
+use rama_utils::macros::define_inner_service_accessors;
+use std::{collections::HashSet, fmt, sync::Arc};
+
+#[cfg(feature = "tls")]
+use rama_net::tls::{
+    client::{ClientHello, Ja4},
+    ApplicationProtocol, SecureTransport,
+};
+
+/// Lowercase fragments of the `User-Agent` of http libraries and automation tools.
+const AUTOMATION_USER_AGENTS: &[&str] = &[
+    "curl/",
+    "wget/",
+    "python",
+    "go-http-client",
+    "java/",
+    "okhttp",
+    "libwww",
+    "httpclient",
+    "scrapy",
+    "headless",
+    "phantomjs",
+    "selenium",
+    "bot",
+    "crawler",
+    "spider",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+/// A signal that a request might be sent by a bot, contributing to its [`BotScore`].
+pub enum BotSignal {
+    /// The request has no `User-Agent` header.
+    MissingUserAgent,
+    /// The `User-Agent` is one of a known http library or automation tool.
+    AutomationUserAgent,
+    /// The `User-Agent` is one of a browser, while the request misses
+    /// headers browsers always send, e.g. `Accept-Language`.
+    MissingBrowserHeaders,
+    /// The `User-Agent` is one of a browser, while the headers
+    /// are sent in an order that browser does not use.
+    HeaderOrderMismatch,
+    /// The [`Ja4`] fingerprint of the [`ClientHello`] is a known bot fingerprint.
+    ///
+    /// [`Ja4`]: rama_net::tls::client::Ja4
+    /// [`ClientHello`]: rama_net::tls::client::ClientHello
+    KnownBotTlsFingerprint,
+    /// The `User-Agent` is one of a browser, while the [`ClientHello`]
+    /// is not one that browser would send, e.g. missing GREASE values or `h2` support.
+    ///
+    /// [`ClientHello`]: rama_net::tls::client::ClientHello
+    TlsUserAgentMismatch,
+}
+
+impl BotSignal {
+    /// The default weight of this signal, added to the [`BotScore`].
+    pub const fn default_weight(&self) -> u8 {
+        match self {
+            Self::MissingUserAgent => 50,
+            Self::AutomationUserAgent => 80,
+            Self::MissingBrowserHeaders => 30,
+            Self::HeaderOrderMismatch => 20,
+            Self::KnownBotTlsFingerprint => 80,
+            Self::TlsUserAgentMismatch => 50,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// The action taken by the [`BotDetection`] service for a request.
+pub enum BotAction {
+    /// The request is served, tagged with its [`BotScore`].
+    Tag,
+    /// The request is answered with the challenge response.
+    Challenge,
+    /// The request is answered with `403 Forbidden`.
+    Block,
+}
+
+#[derive(Debug, Clone)]
+/// The likelihood of a request being sent by a bot, inserted in the [`Context`]
+/// by the [`BotDetection`] service.
+pub struct BotScore {
+    score: u8,
+    signals: Vec<BotSignal>,
+    action: BotAction,
+}
+
+impl BotScore {
+    /// The score, from `0` (no bot signals) to `100` (most likely a bot).
+    pub fn score(&self) -> u8 {
+        self.score
+    }
+
+    /// The signals found, which contributed to the score.
+    pub fn signals(&self) -> &[BotSignal] {
+        &self.signals
+    }
+
+    /// The action taken for the request.
+    pub fn action(&self) -> BotAction {
+        self.action
+    }
+}
+
+type ChallengeResponse = dyn Fn(&BotScore) -> Response + Send + Sync;
+
+#[derive(Clone, Default)]
+struct BotDetectionConfig {
+    weights: Vec<(BotSignal, u8)>,
+    known_bot_ja4: HashSet<String>,
+    challenge: Option<(u8, Arc<ChallengeResponse>)>,
+    block_threshold: Option<u8>,
+}
+
+impl BotDetectionConfig {
+    fn weight(&self, signal: BotSignal) -> u8 {
+        self.weights
+            .iter()
+            .find_map(|(s, weight)| (*s == signal).then_some(*weight))
+            .unwrap_or_else(|| signal.default_weight())
+    }
+
+    fn score<State, Body>(&self, ctx: &Context<State>, req: &Request<Body>) -> BotScore {
+        let mut signals = Vec::new();
+
+        let ua = match ctx.get::<UserAgent>() {
+            Some(ua) => Some(ua.clone()),
+            None => req
+                .headers()
+                .get(header::USER_AGENT)
+                .and_then(|value| value.to_str().ok())
+                .map(UserAgent::new),
+        };
+        let browser = match &ua {
+            None => {
+                signals.push(BotSignal::MissingUserAgent);
+                None
+            }
+            Some(ua) => {
+                let header = ua.header_str().to_ascii_lowercase();
+                if AUTOMATION_USER_AGENTS.iter().any(|s| header.contains(s)) {
+                    signals.push(BotSignal::AutomationUserAgent);
+                }
+                ua.info().map(|info| info.kind)
+            }
+        };
+
+        if let Some(browser) = browser {
+            if !req.headers().contains_key(header::ACCEPT)
+                || !req.headers().contains_key(header::ACCEPT_LANGUAGE)
+            {
+                signals.push(BotSignal::MissingBrowserHeaders);
+            }
+            if !has_browser_header_order(browser, req.headers()) {
+                signals.push(BotSignal::HeaderOrderMismatch);
+            }
+        }
+
+        #[cfg(feature = "tls")]
+        if let Some(hello) = ctx
+            .get::<SecureTransport>()
+            .and_then(|transport| transport.client_hello())
+        {
+            if !self.known_bot_ja4.is_empty()
+                && self
+                    .known_bot_ja4
+                    .contains(&Ja4::compute(hello).to_string())
+            {
+                signals.push(BotSignal::KnownBotTlsFingerprint);
+            }
+            if let Some(browser) = browser {
+                if !is_browser_client_hello(browser, hello) {
+                    signals.push(BotSignal::TlsUserAgentMismatch);
+                }
+            }
+        }
+
+        let score = signals
+            .iter()
+            .fold(0u8, |score, signal| {
+                score.saturating_add(self.weight(*signal))
+            })
+            .min(100);
+        let action = if self.block_threshold.is_some_and(|t| score >= t) {
+            BotAction::Block
+        } else if self.challenge.as_ref().is_some_and(|(t, _)| score >= *t) {
+            BotAction::Challenge
+        } else {
+            BotAction::Tag
+        };
+
+        BotScore {
+            score,
+            signals,
+            action,
+        }
+    }
+}
+
+/// Chromium and Firefox send the `User-Agent` header before the `Accept` header.
+fn has_browser_header_order(browser: UserAgentKind, headers: &HeaderMap) -> bool {
+    if browser == UserAgentKind::Safari {
+        return true;
+    }
+    let ua = headers.keys().position(|name| name == header::USER_AGENT);
+    let accept = headers.keys().position(|name| name == header::ACCEPT);
+    match (ua, accept) {
+        (Some(ua), Some(accept)) => ua < accept,
+        _ => true,
+    }
+}
+
+/// All browsers offer `h2`, and all but Firefox send GREASE cipher suites.
+#[cfg(feature = "tls")]
+fn is_browser_client_hello(browser: UserAgentKind, hello: &ClientHello) -> bool {
+    let offers_h2 = hello
+        .ext_alpn()
+        .is_some_and(|protocols| protocols.contains(&ApplicationProtocol::HTTP_2));
+    let greases = hello.cipher_suites().iter().any(|cs| {
+        let value = u16::from(*cs);
+        value & 0x0f0f == 0x0a0a
+    });
+    offers_h2 && (browser == UserAgentKind::Firefox || greases)
+}
+
+impl fmt::Debug for BotDetectionConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BotDetectionConfig")
+            .field("weights", &self.weights)
+            .field("known_bot_ja4", &self.known_bot_ja4)
+            .field(
+                "challenge_threshold",
+                &self.challenge.as_ref().map(|(t, _)| t),
+            )
+            .field("block_threshold", &self.block_threshold)
+            .finish()
+    }
+}
+
+/// Layer that applies the [`BotDetection`] middleware.
+///
+/// See the [module docs](self) for more details.
+#[derive(Debug, Clone, Default)]
+pub struct BotDetectionLayer {
+    config: BotDetectionConfig,
+}
+
+impl BotDetectionLayer {
+    /// Create a new [`BotDetectionLayer`], which only tags requests with their [`BotScore`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Overwrite the weight added to the score for the given signal.
+    pub fn with_weight(mut self, signal: BotSignal, weight: u8) -> Self {
+        self.set_weight(signal, weight);
+        self
+    }
+
+    /// Overwrite the weight added to the score for the given signal.
+    pub fn set_weight(&mut self, signal: BotSignal, weight: u8) -> &mut Self {
+        self.config.weights.retain(|(s, _)| *s != signal);
+        self.config.weights.push((signal, weight));
+        self
+    }
+
+    /// Add a known bot [`Ja4`] fingerprint, e.g. `t13d1516h2_8daaf6152771_e5627efa2ab1`.
+    ///
+    /// [`Ja4`]: rama_net::tls::client::Ja4
+    pub fn with_known_bot_ja4(mut self, fingerprint: impl Into<String>) -> Self {
+        self.config.known_bot_ja4.insert(fingerprint.into());
+        self
+    }
+
+    /// Add a known bot [`Ja4`] fingerprint, e.g. `t13d1516h2_8daaf6152771_e5627efa2ab1`.
+    ///
+    /// [`Ja4`]: rama_net::tls::client::Ja4
+    pub fn set_known_bot_ja4(&mut self, fingerprint: impl Into<String>) -> &mut Self {
+        self.config.known_bot_ja4.insert(fingerprint.into());
+        self
+    }
+
+    /// Answer requests with a score of at least `threshold`
+    /// with the response created by the given function.
+    pub fn with_challenge(
+        mut self,
+        threshold: u8,
+        response: impl Fn(&BotScore) -> Response + Send + Sync + 'static,
+    ) -> Self {
+        self.config.challenge = Some((threshold, Arc::new(response)));
+        self
+    }
+
+    /// Answer requests with a score of at least `threshold`
+    /// with the response created by the given function.
+    pub fn set_challenge(
+        &mut self,
+        threshold: u8,
+        response: impl Fn(&BotScore) -> Response + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.config.challenge = Some((threshold, Arc::new(response)));
+        self
+    }
+
+    /// Block requests with a score of at least `threshold`.
+    ///
+    /// Blocking takes precedence over challenging a request.
+    pub fn with_block_threshold(mut self, threshold: u8) -> Self {
+        self.config.block_threshold = Some(threshold);
+        self
+    }
+
+    /// Block requests with a score of at least `threshold`.
+    ///
+    /// Blocking takes precedence over challenging a request.
+    pub fn set_block_threshold(&mut self, threshold: u8) -> &mut Self {
+        self.config.block_threshold = Some(threshold);
+        self
+    }
+}
+
+impl<S> Layer<S> for BotDetectionLayer {
+    type Service = BotDetection<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        BotDetection {
+            inner,
+            config: Arc::new(self.config.clone()),
+        }
+    }
+}
+
+/// Middleware scoring requests on how likely they are sent by a bot.
+///
+/// See the [module docs](self) for more details.
+pub struct BotDetection<S> {
+    inner: S,
+    config: Arc<BotDetectionConfig>,
+}
+
+impl<S> BotDetection<S> {
+    /// Create a new [`BotDetection`] service, which only tags requests with their [`BotScore`].
+    pub fn new(inner: S) -> Self {
+        BotDetectionLayer::new().layer(inner)
+    }
+
+    define_inner_service_accessors!();
+}
+
+impl<S: fmt::Debug> fmt::Debug for BotDetection<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BotDetection")
+            .field("inner", &self.inner)
+            .field("config", &self.config)
+            .finish()
+    }
+}
+
+impl<S: Clone> Clone for BotDetection<S> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            config: self.config.clone(),
+        }
+    }
+}
+
+impl<State, S, ReqBody> Service<State, Request<ReqBody>> for BotDetection<S>
+where
+    State: Clone + Send + Sync + 'static,
+    S: Service<State, Request<ReqBody>, Response = Response>,
+    ReqBody: Send + 'static,
+{
+    type Response = Response;
+    type Error = S::Error;
+
+    async fn serve(
+        &self,
+        mut ctx: Context<State>,
+        req: Request<ReqBody>,
+    ) -> Result<Self::Response, Self::Error> {
+        let score = self.config.score(&ctx, &req);
+        match score.action {
+            BotAction::Tag => (),
+            BotAction::Challenge => {
+                tracing::debug!(score = score.score, signals = ?score.signals, "bot detection: challenge request");
+                if let Some((_, response)) = &self.config.challenge {
+                    return Ok(response(&score));
+                }
+            }
+            BotAction::Block => {
+                tracing::debug!(score = score.score, signals = ?score.signals, "bot detection: block request");
+                return Ok(StatusCode::FORBIDDEN.into_response());
+            }
+        }
+        ctx.insert(score);
+        self.inner.serve(ctx, req).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Body;
+    use rama_core::service::service_fn;
+    use std::convert::Infallible;
+
+    const FIREFOX: &str =
+        "Mozilla/5.0 (Windows NT 10.0; Win64; x64; rv:125.0) Gecko/20100101 Firefox/125.0";
+
+    fn compute_score(layer: &BotDetectionLayer, req: &Request) -> BotScore {
+        layer.config.score(&Context::<()>::default(), req)
+    }
+
+    #[test]
+    fn test_bot_score() {
+        let layer = BotDetectionLayer::new();
+
+        let req = Request::builder()
+            .header("user-agent", FIREFOX)
+            .header("accept", "text/html")
+            .header("accept-language", "en-US")
+            .body(Body::empty())
+            .unwrap();
+        let score = compute_score(&layer, &req);
+        assert_eq!(score.score(), 0);
+        assert!(score.signals().is_empty());
+
+        let req = Request::builder()
+            .header("accept", "text/html")
+            .header("user-agent", FIREFOX)
+            .body(Body::empty())
+            .unwrap();
+        let score = compute_score(&layer, &req);
+        assert_eq!(
+            score.signals(),
+            &[
+                BotSignal::MissingBrowserHeaders,
+                BotSignal::HeaderOrderMismatch
+            ]
+        );
+        assert_eq!(score.score(), 50);
+
+        let req = Request::builder()
+            .header("user-agent", "curl/8.5.0")
+            .body(Body::empty())
+            .unwrap();
+        assert_eq!(
+            compute_score(&layer, &req).signals(),
+            &[BotSignal::AutomationUserAgent]
+        );
+
+        let req = Request::new(Body::empty());
+        assert_eq!(
+            compute_score(&layer, &req).signals(),
+            &[BotSignal::MissingUserAgent]
+        );
+        let layer = layer.with_weight(BotSignal::MissingUserAgent, 5);
+        assert_eq!(compute_score(&layer, &req).score(), 5);
+    }
+
+    #[tokio::test]
+    async fn test_bot_detection_actions() {
+        let svc = BotDetectionLayer::new()
+            .with_challenge(40, |score| {
+                (StatusCode::UNAUTHORIZED, score.score().to_string()).into_response()
+            })
+            .with_block_threshold(80)
+            .layer(service_fn(|ctx: Context<()>, _req: Request| async move {
+                let score = ctx.get::<BotScore>().unwrap();
+                Ok::<_, Infallible>(Response::new(Body::from(score.score().to_string())))
+            }));
+
+        let req = Request::builder()
+            .header("user-agent", FIREFOX)
+            .header("accept-language", "en-US")
+            .body(Body::empty())
+            .unwrap();
+        let resp = svc.serve(Context::default(), req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        let resp = svc
+            .serve(Context::default(), Request::new(Body::empty()))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+        let req = Request::builder()
+            .header("user-agent", "python-requests/2.31.0")
+            .body(Body::empty())
+            .unwrap();
+        let resp = svc.serve(Context::default(), req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+    }
+}
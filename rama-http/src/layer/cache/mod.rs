@@ -0,0 +1,945 @@
+//! Middleware that caches responses, as defined in [RFC 9111].
+//!
+//! The [`CacheLayer`] stores responses to `GET` requests in a [`CacheStore`]
+//! (an in-memory LRU [`MemoryCacheStore`] is provided), and serves
+//! `GET` and `HEAD` requests from it for as long as they are fresh,
+//! honoring the `Cache-Control`, `Expires`, `Age` and `Vary` headers.
+//! Stale responses with validators (`ETag` or `Last-Modified`) are revalidated
+//! using a conditional request, and successful unsafe requests (e.g. `POST`)
+//! invalidate the response stored for their target uri.
+//!
+//! The cache can be used as a shared cache (e.g. in a reverse proxy,
+//! using [`CacheLayer::shared`]) or as a private cache (e.g. in a client,
+//! using [`CacheLayer::private`]). A shared cache does not store responses
+//! marked as `private` nor responses to authorized requests unless explicitly allowed,
+//! and prefers the `s-maxage` directive over `max-age`.
+//!
+//! Only responses with a known `Content-Length`, no larger than the configured
+//! maximum body size, are stored. Responses without a `Content-Length`
+//! (e.g. chunked responses) are thus never stored.
+//! The [`CacheStatus`] of each response is inserted into its extensions.
+//!
+//! [RFC 9111]: https://www.rfc-editor.org/rfc/rfc9111.html
+//!
+//! # Example
+//!
+//! ```
+//! use rama_http::layer::cache::{CacheLayer, CacheStatus, MemoryCacheStore};
+//! use rama_http::{header, Body, Request, Response};
+//! use rama_core::service::service_fn;
+//! use rama_core::{Context, Service, Layer};
+//! use rama_core::error::BoxError;
+//! use std::convert::Infallible;
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), BoxError> {
+//! let service = CacheLayer::shared(MemoryCacheStore::default()).layer(service_fn(
+//!     |_req: Request| async {
+//!         Ok::<_, Infallible>(
+//!             Response::builder()
+//!                 .header(header::CACHE_CONTROL, "max-age=60")
+//!                 .header(header::CONTENT_LENGTH, 5)
+//!                 .body(Body::from("hello"))
+//!                 .unwrap(),
+//!         )
+//!     },
+//! ));
+//!
+//! let request = || Request::builder()
+//!     .uri("http://example.com/")
+//!     .body(Body::empty())
+//!     .unwrap();
+//!
+//! let resp = service.serve(Context::default(), request()).await?;
+//! assert_eq!(resp.extensions().get(), Some(&CacheStatus::Miss));
+//!
+//! let resp = service.serve(Context::default(), request()).await?;
+//! assert_eq!(resp.extensions().get(), Some(&CacheStatus::Hit));
+//! assert!(resp.headers().contains_key(header::AGE));
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{
+    dep::{http_body, http_body_util::BodyExt},
+    header::{
+        AUTHORIZATION, CONTENT_LENGTH, ETAG, EXPIRES, HOST, IF_MODIFIED_SINCE, IF_NONE_MATCH,
+        LAST_MODIFIED, VARY,
+    },
+    headers::{Age, ContentLength, ETag, HeaderMapExt, IfModifiedSince, IfNoneMatch, LastModified},
+    Body, HeaderMap, HeaderName, HeaderValue, Method, Request, Response, StatusCode, Version,
+};
+use bytes::Bytes;
+use rama_core::{error::BoxError, Context, Layer, Service};
+use rama_utils::macros::define_inner_service_accessors;
+use std::{
+    fmt,
+    time::{Duration, SystemTime},
+};
+
+mod policy;
+use policy::{freshness_lifetime, header_tokens, initial_age, CacheDirectives, MaxStale};
+
+mod store;
+#[doc(inline)]
+pub use store::{CacheStore, CachedResponse, MemoryCacheStore};
+
+const DEFAULT_MAX_BODY_SIZE: usize = 1024 * 1024;
+
+/// Describes how a response was produced by the [`CacheService`],
+/// inserted in the extensions of each response.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CacheStatus {
+    /// Served from the cache, without contacting the inner service.
+    Hit,
+    /// Served from the cache, after being revalidated by the inner service.
+    Revalidated,
+    /// Served by the inner service, and stored if possible.
+    Miss,
+    /// Served by the inner service, bypassing the cache.
+    Bypass,
+}
+
+/// Layer that applies the [`CacheService`] middleware.
+///
+/// See the [module docs](self) for more details.
+pub struct CacheLayer<C> {
+    store: C,
+    shared: bool,
+    max_body_size: usize,
+}
+
+impl<C: fmt::Debug> fmt::Debug for CacheLayer<C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CacheLayer")
+            .field("store", &self.store)
+            .field("shared", &self.shared)
+            .field("max_body_size", &self.max_body_size)
+            .finish()
+    }
+}
+
+impl<C: Clone> Clone for CacheLayer<C> {
+    fn clone(&self) -> Self {
+        Self {
+            store: self.store.clone(),
+            shared: self.shared,
+            max_body_size: self.max_body_size,
+        }
+    }
+}
+
+impl<C> CacheLayer<C> {
+    /// Create a new [`CacheLayer`] acting as a shared cache,
+    /// e.g. to be used by a server or (reverse) proxy.
+    pub fn shared(store: C) -> Self {
+        Self {
+            store,
+            shared: true,
+            max_body_size: DEFAULT_MAX_BODY_SIZE,
+        }
+    }
+
+    /// Create a new [`CacheLayer`] acting as a private cache,
+    /// e.g. to be used by a client for a single user.
+    pub fn private(store: C) -> Self {
+        Self {
+            store,
+            shared: false,
+            max_body_size: DEFAULT_MAX_BODY_SIZE,
+        }
+    }
+
+    /// Define the maximum size of a response payload to be stored.
+    ///
+    /// Defaults to 1 MiB.
+    pub fn with_max_body_size(mut self, size: usize) -> Self {
+        self.max_body_size = size;
+        self
+    }
+
+    /// Define the maximum size of a response payload to be stored.
+    ///
+    /// Defaults to 1 MiB.
+    pub fn set_max_body_size(&mut self, size: usize) -> &mut Self {
+        self.max_body_size = size;
+        self
+    }
+}
+
+impl<S, C: Clone> Layer<S> for CacheLayer<C> {
+    type Service = CacheService<S, C>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        CacheService {
+            inner,
+            store: self.store.clone(),
+            shared: self.shared,
+            max_body_size: self.max_body_size,
+        }
+    }
+}
+
+/// Middleware which caches responses.
+///
+/// See the [module docs](self) for more details.
+pub struct CacheService<S, C> {
+    inner: S,
+    store: C,
+    shared: bool,
+    max_body_size: usize,
+}
+
+impl<S, C> CacheService<S, C> {
+    /// Create a new [`CacheService`] acting as a shared cache.
+    pub fn shared(inner: S, store: C) -> Self {
+        Self {
+            inner,
+            store,
+            shared: true,
+            max_body_size: DEFAULT_MAX_BODY_SIZE,
+        }
+    }
+
+    /// Create a new [`CacheService`] acting as a private cache.
+    pub fn private(inner: S, store: C) -> Self {
+        Self {
+            inner,
+            store,
+            shared: false,
+            max_body_size: DEFAULT_MAX_BODY_SIZE,
+        }
+    }
+
+    /// Define the maximum size of a response payload to be stored.
+    ///
+    /// Defaults to 1 MiB.
+    pub fn with_max_body_size(mut self, size: usize) -> Self {
+        self.max_body_size = size;
+        self
+    }
+
+    /// Define the maximum size of a response payload to be stored.
+    ///
+    /// Defaults to 1 MiB.
+    pub fn set_max_body_size(&mut self, size: usize) -> &mut Self {
+        self.max_body_size = size;
+        self
+    }
+
+    define_inner_service_accessors!();
+}
+
+impl<S: fmt::Debug, C: fmt::Debug> fmt::Debug for CacheService<S, C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CacheService")
+            .field("inner", &self.inner)
+            .field("store", &self.store)
+            .field("shared", &self.shared)
+            .field("max_body_size", &self.max_body_size)
+            .finish()
+    }
+}
+
+impl<S: Clone, C: Clone> Clone for CacheService<S, C> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            store: self.store.clone(),
+            shared: self.shared,
+            max_body_size: self.max_body_size,
+        }
+    }
+}
+
+impl<S, C> CacheService<S, C> {
+    /// Whether a stored response can be used without validation (RFC 9111, section 4.2).
+    fn is_usable(
+        &self,
+        req_directives: &CacheDirectives,
+        resp_directives: &CacheDirectives,
+        lifetime: Duration,
+        age: Duration,
+    ) -> bool {
+        if req_directives.no_cache || resp_directives.no_cache {
+            return false;
+        }
+        if req_directives.max_age.is_some_and(|max_age| age > max_age) {
+            return false;
+        }
+        let min_fresh = req_directives.min_fresh.unwrap_or_default();
+        if age + min_fresh < lifetime {
+            return true;
+        }
+
+        // stale, only usable if explicitly allowed by the client and not forbidden by the origin
+        if resp_directives.must_revalidate || (self.shared && resp_directives.proxy_revalidate) {
+            return false;
+        }
+        match req_directives.max_stale {
+            Some(MaxStale::Any) => true,
+            Some(MaxStale::Limit(max_stale)) => age.saturating_sub(lifetime) <= max_stale,
+            None => false,
+        }
+    }
+
+    /// Whether a response can be stored (RFC 9111, section 3).
+    fn is_storable(
+        &self,
+        req_headers: &HeaderMap,
+        req_directives: &CacheDirectives,
+        status: StatusCode,
+        resp_headers: &HeaderMap,
+        resp_directives: &CacheDirectives,
+    ) -> bool {
+        if req_directives.no_store || resp_directives.no_store {
+            return false;
+        }
+        if self.shared && resp_directives.private {
+            return false;
+        }
+        if status.is_informational()
+            || status == StatusCode::PARTIAL_CONTENT
+            || status == StatusCode::NOT_MODIFIED
+        {
+            return false;
+        }
+        if header_tokens(resp_headers, &VARY).any(|name| name == "*") {
+            return false;
+        }
+        if self.shared
+            && req_headers.contains_key(AUTHORIZATION)
+            && !(resp_directives.public
+                || resp_directives.must_revalidate
+                || resp_directives.s_maxage.is_some())
+        {
+            return false;
+        }
+
+        resp_directives.public
+            || (!self.shared && resp_directives.private)
+            || resp_directives.max_age.is_some()
+            || (self.shared && resp_directives.s_maxage.is_some())
+            || resp_headers.contains_key(EXPIRES)
+            || policy::is_heuristically_cacheable(status)
+    }
+}
+
+impl<State, S, C, ReqBody, ResBody> Service<State, Request<ReqBody>> for CacheService<S, C>
+where
+    State: Clone + Send + Sync + 'static,
+    S: Service<State, Request<ReqBody>, Response = Response<ResBody>, Error: Into<BoxError>>,
+    C: CacheStore,
+    ReqBody: Send + 'static,
+    ResBody: http_body::Body<Data = Bytes, Error: Into<BoxError>> + Send + Sync + 'static,
+{
+    type Response = Response;
+    type Error = BoxError;
+
+    async fn serve(
+        &self,
+        ctx: Context<State>,
+        mut req: Request<ReqBody>,
+    ) -> Result<Self::Response, Self::Error> {
+        let method = req.method().clone();
+        let key = cache_key(&req);
+
+        if method != Method::GET && method != Method::HEAD {
+            let resp = self.inner.serve(ctx, req).await.map_err(Into::into)?;
+            // unsafe methods invalidate the stored response (RFC 9111, section 4.4)
+            if let Some(key) = key.filter(|_| !method.is_safe()) {
+                if resp.status().is_success() || resp.status().is_redirection() {
+                    self.store.remove(&key).await;
+                }
+            }
+            return Ok(with_status(resp.map(Body::new), CacheStatus::Bypass));
+        }
+
+        let req_directives = CacheDirectives::from_headers(req.headers());
+        let Some(key) = key.filter(|_| !req_directives.no_store) else {
+            let resp = self.inner.serve(ctx, req).await.map_err(Into::into)?;
+            return Ok(with_status(resp.map(Body::new), CacheStatus::Bypass));
+        };
+
+        let version = req.version();
+        let clock = ctx.clock();
+        let cached = self
+            .store
+            .get(&key)
+            .await
+            .filter(|cached| vary_matches(cached, req.headers()));
+
+        let mut revalidating = None;
+        if let Some(cached) = cached {
+            let resp_directives = CacheDirectives::from_headers(&cached.headers);
+            let lifetime = freshness_lifetime(
+                cached.status,
+                &cached.headers,
+                &resp_directives,
+                cached.response_time,
+                self.shared,
+            );
+            let age = current_age(&cached, clock.system_now());
+
+            if self.is_usable(&req_directives, &resp_directives, lifetime, age) {
+                tracing::trace!(%key, ?age, "serve response from cache");
+                return Ok(cached_response(
+                    &method,
+                    version,
+                    req.headers(),
+                    &cached,
+                    age,
+                    CacheStatus::Hit,
+                ));
+            }
+
+            if req_directives.only_if_cached {
+                return Ok(gateway_timeout());
+            }
+
+            // revalidate the stored response, unless the client is validating its own cache
+            let client_conditional = req.headers().contains_key(IF_NONE_MATCH)
+                || req.headers().contains_key(IF_MODIFIED_SINCE);
+            let etag = cached.headers.get(ETAG).cloned();
+            let last_modified = cached.headers.get(LAST_MODIFIED).cloned();
+            if !client_conditional && (etag.is_some() || last_modified.is_some()) {
+                if let Some(etag) = etag {
+                    req.headers_mut().insert(IF_NONE_MATCH, etag);
+                }
+                if let Some(last_modified) = last_modified {
+                    req.headers_mut().insert(IF_MODIFIED_SINCE, last_modified);
+                }
+                tracing::trace!(%key, "revalidate stale response");
+                revalidating = Some(cached);
+            }
+        } else if req_directives.only_if_cached {
+            return Ok(gateway_timeout());
+        }
+
+        let req_headers = req.headers().clone();
+        let request_time = clock.system_now();
+        let resp = self.inner.serve(ctx, req).await.map_err(Into::into)?;
+        let response_time = clock.system_now();
+
+        let resp_directives = CacheDirectives::from_headers(resp.headers());
+
+        if let Some(mut cached) = revalidating {
+            if resp.status() == StatusCode::NOT_MODIFIED {
+                // freshen the stored response (RFC 9111, section 4.3.4)
+                for name in resp.headers().keys() {
+                    if name == CONTENT_LENGTH {
+                        continue;
+                    }
+                    cached.headers.remove(name);
+                    for value in resp.headers().get_all(name) {
+                        cached.headers.append(name.clone(), value.clone());
+                    }
+                }
+                cached.response_time = response_time;
+                cached.initial_age = initial_age(resp.headers(), request_time, response_time);
+
+                let age = cached.initial_age;
+                let resp = cached_response(
+                    &method,
+                    version,
+                    &HeaderMap::new(),
+                    &cached,
+                    age,
+                    CacheStatus::Revalidated,
+                );
+                if resp_directives.no_store {
+                    self.store.remove(&key).await;
+                } else {
+                    self.store.insert(key, cached).await;
+                }
+                return Ok(resp);
+            }
+        }
+
+        let content_length = resp
+            .headers()
+            .typed_get::<ContentLength>()
+            .map(|ContentLength(length)| length);
+
+        if method != Method::GET
+            || !content_length.is_some_and(|length| length <= self.max_body_size as u64)
+            || !self.is_storable(
+                &req_headers,
+                &req_directives,
+                resp.status(),
+                resp.headers(),
+                &resp_directives,
+            )
+        {
+            return Ok(with_status(resp.map(Body::new), CacheStatus::Miss));
+        }
+
+        let (parts, body) = resp.into_parts();
+        let body = body.collect().await.map_err(Into::into)?.to_bytes();
+
+        let vary = header_tokens(&parts.headers, &VARY)
+            .filter_map(|name| name.parse::<HeaderName>().ok())
+            .map(|name| {
+                let value = vary_value(&req_headers, &name);
+                (name, value)
+            })
+            .collect();
+
+        tracing::trace!(%key, "store response in cache");
+        self.store
+            .insert(
+                key,
+                CachedResponse {
+                    status: parts.status,
+                    headers: parts.headers.clone(),
+                    body: body.clone(),
+                    vary,
+                    response_time,
+                    initial_age: initial_age(&parts.headers, request_time, response_time),
+                },
+            )
+            .await;
+
+        Ok(with_status(
+            Response::from_parts(parts, Body::from(body)),
+            CacheStatus::Miss,
+        ))
+    }
+}
+
+/// Compute the key used to store the response of the given request,
+/// which is its absolute target uri.
+fn cache_key<B>(req: &Request<B>) -> Option<String> {
+    let uri = req.uri();
+    if uri.authority().is_some() {
+        return Some(uri.to_string());
+    }
+    let host = req.headers().get(HOST)?.to_str().ok()?;
+    Some(format!(
+        "{}{}",
+        host.to_ascii_lowercase(),
+        uri.path_and_query().map(|pq| pq.as_str()).unwrap_or("/")
+    ))
+}
+
+/// The (combined) value of the given request header, as used for Vary matching.
+fn vary_value(headers: &HeaderMap, name: &HeaderName) -> Option<HeaderValue> {
+    let values: Vec<_> = headers.get_all(name).iter().map(|v| v.as_bytes()).collect();
+    if values.is_empty() {
+        return None;
+    }
+    HeaderValue::from_bytes(&values.join(&b", "[..])).ok()
+}
+
+fn vary_matches(cached: &CachedResponse, headers: &HeaderMap) -> bool {
+    cached
+        .vary
+        .iter()
+        .all(|(name, value)| vary_value(headers, name).as_ref() == value.as_ref())
+}
+
+fn current_age(cached: &CachedResponse, now: SystemTime) -> Duration {
+    cached.initial_age + now.duration_since(cached.response_time).unwrap_or_default()
+}
+
+/// Whether the client conditional request headers match the stored response.
+fn not_modified(req_headers: &HeaderMap, cached_headers: &HeaderMap) -> bool {
+    if let Some(if_none_match) = req_headers.typed_get::<IfNoneMatch>() {
+        return cached_headers
+            .typed_get::<ETag>()
+            .is_some_and(|etag| !if_none_match.precondition_passes(&etag));
+    }
+    if let Some(if_modified_since) = req_headers.typed_get::<IfModifiedSince>() {
+        return cached_headers
+            .typed_get::<LastModified>()
+            .is_some_and(|last_modified| !if_modified_since.is_modified(last_modified.into()));
+    }
+    false
+}
+
+fn cached_response(
+    method: &Method,
+    version: Version,
+    req_headers: &HeaderMap,
+    cached: &CachedResponse,
+    age: Duration,
+    status: CacheStatus,
+) -> Response {
+    let mut headers = cached.headers.clone();
+    headers.typed_insert(Age::from_secs(age.as_secs()));
+
+    let (status_code, body) =
+        if cached.status == StatusCode::OK && not_modified(req_headers, &cached.headers) {
+            headers.remove(CONTENT_LENGTH);
+            (StatusCode::NOT_MODIFIED, Body::empty())
+        } else if method == Method::HEAD {
+            (cached.status, Body::empty())
+        } else {
+            (cached.status, Body::from(cached.body.clone()))
+        };
+
+    let mut resp = Response::new(body);
+    *resp.status_mut() = status_code;
+    *resp.version_mut() = version;
+    *resp.headers_mut() = headers;
+    with_status(resp, status)
+}
+
+fn gateway_timeout() -> Response {
+    let mut resp = Response::new(Body::empty());
+    *resp.status_mut() = StatusCode::GATEWAY_TIMEOUT;
+    with_status(resp, CacheStatus::Miss)
+}
+
+fn with_status(mut resp: Response, status: CacheStatus) -> Response {
+    resp.extensions_mut().insert(status);
+    resp
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rama_core::{service::service_fn, time::Clock};
+    use std::{
+        convert::Infallible,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+    };
+
+    fn origin(
+        headers: &'static [(&'static str, &'static str)],
+    ) -> (
+        Arc<AtomicUsize>,
+        impl Service<(), Request, Response = Response, Error = Infallible>,
+    ) {
+        let counter = Arc::new(AtomicUsize::new(0));
+        let svc_counter = counter.clone();
+        let svc = service_fn(move |req: Request| {
+            let counter = svc_counter.clone();
+            async move {
+                let n = counter.fetch_add(1, Ordering::SeqCst) + 1;
+                let mut builder = Response::builder();
+                for (name, value) in headers {
+                    builder = builder.header(*name, *value);
+                }
+                if req.method() == Method::POST {
+                    return Ok(builder.body(Body::empty()).unwrap());
+                }
+                if req
+                    .headers()
+                    .get(IF_NONE_MATCH)
+                    .is_some_and(|v| v == "\"v1\"")
+                {
+                    return Ok(builder
+                        .status(StatusCode::NOT_MODIFIED)
+                        .header("x-revalidated", "1")
+                        .body(Body::empty())
+                        .unwrap());
+                }
+                let body = format!("response {n}");
+                Ok(builder
+                    .header(CONTENT_LENGTH, body.len())
+                    .body(Body::from(body))
+                    .unwrap())
+            }
+        });
+        (counter, svc)
+    }
+
+    fn request(method: Method, headers: &[(&'static str, &'static str)]) -> Request {
+        let mut builder = Request::builder()
+            .method(method)
+            .uri("/foo")
+            .header(HOST, "example.com");
+        for (name, value) in headers {
+            builder = builder.header(*name, *value);
+        }
+        builder.body(Body::empty()).unwrap()
+    }
+
+    async fn body_string(resp: Response) -> String {
+        let bytes = resp.into_body().collect().await.unwrap().to_bytes();
+        String::from_utf8(bytes.to_vec()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_cache_fresh_hit() {
+        let (counter, svc) = origin(&[("cache-control", "max-age=60")]);
+        let svc = CacheLayer::shared(MemoryCacheStore::default()).layer(svc);
+
+        let resp = svc
+            .serve(Context::default(), request(Method::GET, &[]))
+            .await
+            .unwrap();
+        assert_eq!(resp.extensions().get(), Some(&CacheStatus::Miss));
+        assert_eq!(body_string(resp).await, "response 1");
+
+        let resp = svc
+            .serve(Context::default(), request(Method::GET, &[]))
+            .await
+            .unwrap();
+        assert_eq!(resp.extensions().get(), Some(&CacheStatus::Hit));
+        assert_eq!(resp.headers()["age"], "0");
+        assert_eq!(body_string(resp).await, "response 1");
+
+        let resp = svc
+            .serve(Context::default(), request(Method::HEAD, &[]))
+            .await
+            .unwrap();
+        assert_eq!(resp.extensions().get(), Some(&CacheStatus::Hit));
+        assert_eq!(body_string(resp).await, "");
+
+        // client asks for a validated response
+        let resp = svc
+            .serve(
+                Context::default(),
+                request(Method::GET, &[("cache-control", "no-cache")]),
+            )
+            .await
+            .unwrap();
+        assert_eq!(resp.extensions().get(), Some(&CacheStatus::Miss));
+        assert_eq!(body_string(resp).await, "response 2");
+
+        assert_eq!(counter.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_cache_not_storable() {
+        for headers in [
+            &[("cache-control", "no-store, max-age=60")][..],
+            &[("cache-control", "private, max-age=60")][..],
+            &[("cache-control", "max-age=60"), ("vary", "*")][..],
+        ] {
+            let (counter, svc) = origin(headers);
+            let svc = CacheLayer::shared(MemoryCacheStore::default()).layer(svc);
+            for _ in 0..2 {
+                let resp = svc
+                    .serve(Context::default(), request(Method::GET, &[]))
+                    .await
+                    .unwrap();
+                assert_eq!(resp.extensions().get(), Some(&CacheStatus::Miss));
+            }
+            assert_eq!(counter.load(Ordering::SeqCst), 2, "{headers:?}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_cache_body_size() {
+        // responses larger than the max body size are not stored
+        let (counter, svc) = origin(&[("cache-control", "max-age=60")]);
+        let svc = CacheLayer::shared(MemoryCacheStore::default())
+            .with_max_body_size(5)
+            .layer(svc);
+        for _ in 0..2 {
+            let resp = svc
+                .serve(Context::default(), request(Method::GET, &[]))
+                .await
+                .unwrap();
+            assert_eq!(resp.extensions().get(), Some(&CacheStatus::Miss));
+        }
+        assert_eq!(counter.load(Ordering::SeqCst), 2);
+
+        // responses without a content length (e.g. chunked) are never stored
+        let svc = CacheLayer::shared(MemoryCacheStore::default()).layer(service_fn(
+            |_req: Request| async {
+                Ok::<_, Infallible>(
+                    Response::builder()
+                        .header("cache-control", "max-age=60")
+                        .body(Body::from("hello"))
+                        .unwrap(),
+                )
+            },
+        ));
+        for _ in 0..2 {
+            let resp = svc
+                .serve(Context::default(), request(Method::GET, &[]))
+                .await
+                .unwrap();
+            assert_eq!(resp.extensions().get(), Some(&CacheStatus::Miss));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_cache_private() {
+        let (counter, svc) = origin(&[("cache-control", "private, max-age=60")]);
+        let svc = CacheLayer::private(MemoryCacheStore::default()).layer(svc);
+        for _ in 0..2 {
+            svc.serve(Context::default(), request(Method::GET, &[]))
+                .await
+                .unwrap();
+        }
+        assert_eq!(counter.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_cache_expires_with_clock() {
+        let (counter, svc) = origin(&[("cache-control", "max-age=60")]);
+        let svc = CacheLayer::shared(MemoryCacheStore::default()).layer(svc);
+        let mut ctx = Context::default();
+        ctx.insert(Clock::tokio());
+
+        let resp = svc
+            .serve(ctx.clone(), request(Method::GET, &[]))
+            .await
+            .unwrap();
+        assert_eq!(resp.extensions().get(), Some(&CacheStatus::Miss));
+
+        tokio::time::advance(Duration::from_secs(30)).await;
+        let resp = svc
+            .serve(ctx.clone(), request(Method::GET, &[]))
+            .await
+            .unwrap();
+        assert_eq!(resp.extensions().get(), Some(&CacheStatus::Hit));
+        assert_eq!(resp.headers()["age"], "30");
+
+        tokio::time::advance(Duration::from_secs(31)).await;
+        let resp = svc.serve(ctx, request(Method::GET, &[])).await.unwrap();
+        assert_eq!(resp.extensions().get(), Some(&CacheStatus::Miss));
+        assert_eq!(body_string(resp).await, "response 2");
+
+        assert_eq!(counter.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_cache_authorization_shared() {
+        let (counter, svc) = origin(&[("cache-control", "max-age=60")]);
+        let svc = CacheLayer::shared(MemoryCacheStore::default()).layer(svc);
+        for _ in 0..2 {
+            svc.serve(
+                Context::default(),
+                request(Method::GET, &[("authorization", "Bearer foo")]),
+            )
+            .await
+            .unwrap();
+        }
+        assert_eq!(counter.load(Ordering::SeqCst), 2);
+
+        let (counter, svc) = origin(&[("cache-control", "public, max-age=60")]);
+        let svc = CacheLayer::shared(MemoryCacheStore::default()).layer(svc);
+        for _ in 0..2 {
+            svc.serve(
+                Context::default(),
+                request(Method::GET, &[("authorization", "Bearer foo")]),
+            )
+            .await
+            .unwrap();
+        }
+        assert_eq!(counter.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_cache_revalidate() {
+        let (counter, svc) = origin(&[("cache-control", "max-age=0"), ("etag", "\"v1\"")]);
+        let svc = CacheLayer::shared(MemoryCacheStore::default()).layer(svc);
+
+        let resp = svc
+            .serve(Context::default(), request(Method::GET, &[]))
+            .await
+            .unwrap();
+        assert_eq!(resp.extensions().get(), Some(&CacheStatus::Miss));
+        assert_eq!(body_string(resp).await, "response 1");
+
+        let resp = svc
+            .serve(Context::default(), request(Method::GET, &[]))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.extensions().get(), Some(&CacheStatus::Revalidated));
+        assert_eq!(resp.headers()["x-revalidated"], "1");
+        assert_eq!(body_string(resp).await, "response 1");
+
+        // client validating its own cache gets the 304 of the origin
+        let resp = svc
+            .serve(
+                Context::default(),
+                request(Method::GET, &[("if-none-match", "\"v1\"")]),
+            )
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::NOT_MODIFIED);
+
+        assert_eq!(counter.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_cache_client_conditional_hit() {
+        let (counter, svc) = origin(&[("cache-control", "max-age=60"), ("etag", "\"v1\"")]);
+        let svc = CacheLayer::shared(MemoryCacheStore::default()).layer(svc);
+
+        svc.serve(Context::default(), request(Method::GET, &[]))
+            .await
+            .unwrap();
+        let resp = svc
+            .serve(
+                Context::default(),
+                request(Method::GET, &[("if-none-match", "\"v1\"")]),
+            )
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(resp.extensions().get(), Some(&CacheStatus::Hit));
+        assert_eq!(counter.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_cache_vary() {
+        let (counter, svc) =
+            origin(&[("cache-control", "max-age=60"), ("vary", "Accept-Language")]);
+        let svc = CacheLayer::shared(MemoryCacheStore::default()).layer(svc);
+
+        for (lang, expected) in [
+            ("en", "response 1"),
+            ("en", "response 1"),
+            ("nl", "response 2"),
+        ] {
+            let resp = svc
+                .serve(
+                    Context::default(),
+                    request(Method::GET, &[("accept-language", lang)]),
+                )
+                .await
+                .unwrap();
+            assert_eq!(body_string(resp).await, expected);
+        }
+        assert_eq!(counter.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_cache_invalidate_unsafe() {
+        let (counter, svc) = origin(&[("cache-control", "max-age=60")]);
+        let svc = CacheLayer::shared(MemoryCacheStore::default()).layer(svc);
+
+        svc.serve(Context::default(), request(Method::GET, &[]))
+            .await
+            .unwrap();
+        let resp = svc
+            .serve(Context::default(), request(Method::POST, &[]))
+            .await
+            .unwrap();
+        assert_eq!(resp.extensions().get(), Some(&CacheStatus::Bypass));
+        let resp = svc
+            .serve(Context::default(), request(Method::GET, &[]))
+            .await
+            .unwrap();
+        assert_eq!(body_string(resp).await, "response 3");
+        assert_eq!(counter.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_cache_only_if_cached() {
+        let (counter, svc) = origin(&[("cache-control", "max-age=60")]);
+        let svc = CacheLayer::shared(MemoryCacheStore::default()).layer(svc);
+
+        let resp = svc
+            .serve(
+                Context::default(),
+                request(Method::GET, &[("cache-control", "only-if-cached")]),
+            )
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::GATEWAY_TIMEOUT);
+        assert_eq!(counter.load(Ordering::SeqCst), 0);
+    }
+}
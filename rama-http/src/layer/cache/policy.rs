@@ -0,0 +1,277 @@
+//! Cache-Control directives and freshness calculations,
+//! as defined in [RFC 9111](https://www.rfc-editor.org/rfc/rfc9111.html).
+
+use crate::{
+    header::{CACHE_CONTROL, EXPIRES, PRAGMA},
+    headers::{Age, Date, Expires, HeaderMapExt, LastModified},
+    HeaderMap, StatusCode,
+};
+use std::time::{Duration, SystemTime};
+
+/// Delta-seconds greater than this value are to be treated as this value,
+/// as advised by RFC 9111, section 1.2.2.
+const MAX_DELTA_SECONDS: u64 = 1 << 31;
+
+/// Upper bound of the heuristic freshness lifetime.
+const MAX_HEURISTIC_FRESHNESS: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// The amount of staleness accepted by the client (`max-stale` request directive).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum MaxStale {
+    Any,
+    Limit(Duration),
+}
+
+/// The subset of Cache-Control directives (request and response) understood by the cache.
+#[derive(Debug, Clone, Default)]
+pub(super) struct CacheDirectives {
+    pub(super) no_store: bool,
+    pub(super) no_cache: bool,
+    pub(super) private: bool,
+    pub(super) public: bool,
+    pub(super) must_revalidate: bool,
+    pub(super) proxy_revalidate: bool,
+    pub(super) only_if_cached: bool,
+    pub(super) max_age: Option<Duration>,
+    pub(super) s_maxage: Option<Duration>,
+    pub(super) max_stale: Option<MaxStale>,
+    pub(super) min_fresh: Option<Duration>,
+}
+
+impl CacheDirectives {
+    /// Parse the Cache-Control directives from the given headers,
+    /// falling back to `Pragma: no-cache` in case no Cache-Control header is present.
+    pub(super) fn from_headers(headers: &HeaderMap) -> Self {
+        let mut directives = Self::default();
+        let mut found = false;
+
+        for directive in header_tokens(headers, &CACHE_CONTROL) {
+            found = true;
+            let (name, arg) = match directive.split_once('=') {
+                Some((name, arg)) => (name.trim(), Some(arg.trim().trim_matches('"'))),
+                None => (directive, None),
+            };
+            // invalid delta-seconds for max-age style directives result in stale responses
+            let delta_seconds = || {
+                arg.and_then(|arg| arg.parse::<u64>().ok())
+                    .map(|secs| Duration::from_secs(secs.min(MAX_DELTA_SECONDS)))
+            };
+
+            match name.to_ascii_lowercase().as_str() {
+                "no-store" => directives.no_store = true,
+                "no-cache" => directives.no_cache = true,
+                "private" => directives.private = true,
+                "public" => directives.public = true,
+                "must-revalidate" => directives.must_revalidate = true,
+                "proxy-revalidate" => directives.proxy_revalidate = true,
+                "only-if-cached" => directives.only_if_cached = true,
+                "max-age" => directives.max_age = Some(delta_seconds().unwrap_or_default()),
+                "s-maxage" => directives.s_maxage = Some(delta_seconds().unwrap_or_default()),
+                "max-stale" => {
+                    directives.max_stale = Some(
+                        delta_seconds()
+                            .map(MaxStale::Limit)
+                            .unwrap_or(MaxStale::Any),
+                    )
+                }
+                "min-fresh" => directives.min_fresh = delta_seconds(),
+                _ => (),
+            }
+        }
+
+        if !found {
+            // Pragma is only to be respected in absence of Cache-Control (RFC 9111, section 5.4)
+            directives.no_cache =
+                header_tokens(headers, &PRAGMA).any(|token| token.eq_ignore_ascii_case("no-cache"));
+        }
+
+        directives
+    }
+}
+
+/// Iterate over the trimmed, non-empty, comma separated tokens of the given header.
+pub(super) fn header_tokens<'a>(
+    headers: &'a HeaderMap,
+    name: &'a crate::HeaderName,
+) -> impl Iterator<Item = &'a str> + 'a {
+    headers
+        .get_all(name)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .filter(|token| !token.is_empty())
+}
+
+/// Status codes which are heuristically cacheable (RFC 9110, section 15.1).
+pub(super) fn is_heuristically_cacheable(status: StatusCode) -> bool {
+    matches!(
+        status.as_u16(),
+        200 | 203 | 204 | 300 | 301 | 308 | 404 | 405 | 410 | 414 | 501
+    )
+}
+
+/// Compute the freshness lifetime of a response (RFC 9111, section 4.2.1).
+pub(super) fn freshness_lifetime(
+    status: StatusCode,
+    headers: &HeaderMap,
+    directives: &CacheDirectives,
+    response_time: SystemTime,
+    shared: bool,
+) -> Duration {
+    if shared {
+        if let Some(s_maxage) = directives.s_maxage {
+            return s_maxage;
+        }
+    }
+    if let Some(max_age) = directives.max_age {
+        return max_age;
+    }
+
+    let date = headers
+        .typed_get::<Date>()
+        .map(SystemTime::from)
+        .unwrap_or(response_time);
+
+    if headers.contains_key(EXPIRES) {
+        // invalid Expires values represent a time in the past
+        return headers
+            .typed_get::<Expires>()
+            .and_then(|expires| SystemTime::from(expires).duration_since(date).ok())
+            .unwrap_or_default();
+    }
+
+    if is_heuristically_cacheable(status) {
+        if let Some(last_modified) = headers.typed_get::<LastModified>() {
+            return date
+                .duration_since(SystemTime::from(last_modified))
+                .map(|age| (age / 10).min(MAX_HEURISTIC_FRESHNESS))
+                .unwrap_or_default();
+        }
+    }
+
+    Duration::ZERO
+}
+
+/// Compute the corrected initial age of a response (RFC 9111, section 4.2.3).
+pub(super) fn initial_age(
+    headers: &HeaderMap,
+    request_time: SystemTime,
+    response_time: SystemTime,
+) -> Duration {
+    let apparent_age = headers
+        .typed_get::<Date>()
+        .and_then(|date| response_time.duration_since(SystemTime::from(date)).ok())
+        .unwrap_or_default();
+    let response_delay = response_time
+        .duration_since(request_time)
+        .unwrap_or_default();
+    let age_value = headers
+        .typed_get::<Age>()
+        .map(|age| Duration::from_secs(age.as_secs()))
+        .unwrap_or_default();
+    apparent_age.max(age_value + response_delay)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::HeaderValue;
+
+    fn headers(pairs: &[(&'static str, &'static str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.append(*name, HeaderValue::from_static(value));
+        }
+        headers
+    }
+
+    #[test]
+    fn test_cache_directives_parse() {
+        let directives = CacheDirectives::from_headers(&headers(&[
+            ("cache-control", "Public, max-age=60"),
+            ("cache-control", "s-maxage=\"120\", max-stale, no-cache"),
+        ]));
+        assert!(directives.public);
+        assert!(directives.no_cache);
+        assert!(!directives.no_store);
+        assert_eq!(directives.max_age, Some(Duration::from_secs(60)));
+        assert_eq!(directives.s_maxage, Some(Duration::from_secs(120)));
+        assert_eq!(directives.max_stale, Some(MaxStale::Any));
+
+        let directives = CacheDirectives::from_headers(&headers(&[("cache-control", "max-age=x")]));
+        assert_eq!(directives.max_age, Some(Duration::ZERO));
+
+        let directives = CacheDirectives::from_headers(&headers(&[("pragma", "no-cache")]));
+        assert!(directives.no_cache);
+
+        let directives = CacheDirectives::from_headers(&headers(&[
+            ("cache-control", "max-age=5"),
+            ("pragma", "no-cache"),
+        ]));
+        assert!(!directives.no_cache);
+    }
+
+    #[test]
+    fn test_freshness_lifetime() {
+        let now = SystemTime::now();
+
+        let h = headers(&[("cache-control", "max-age=60, s-maxage=120")]);
+        let directives = CacheDirectives::from_headers(&h);
+        assert_eq!(
+            freshness_lifetime(StatusCode::OK, &h, &directives, now, false),
+            Duration::from_secs(60)
+        );
+        assert_eq!(
+            freshness_lifetime(StatusCode::OK, &h, &directives, now, true),
+            Duration::from_secs(120)
+        );
+
+        let h = headers(&[
+            ("date", "Sun, 06 Nov 1994 08:49:37 GMT"),
+            ("expires", "Sun, 06 Nov 1994 08:59:37 GMT"),
+        ]);
+        let directives = CacheDirectives::from_headers(&h);
+        assert_eq!(
+            freshness_lifetime(StatusCode::OK, &h, &directives, now, true),
+            Duration::from_secs(600)
+        );
+
+        let h = headers(&[("expires", "0")]);
+        let directives = CacheDirectives::from_headers(&h);
+        assert_eq!(
+            freshness_lifetime(StatusCode::OK, &h, &directives, now, true),
+            Duration::ZERO
+        );
+
+        let h = headers(&[
+            ("date", "Sun, 06 Nov 1994 08:49:37 GMT"),
+            ("last-modified", "Sun, 06 Nov 1994 07:49:37 GMT"),
+        ]);
+        let directives = CacheDirectives::from_headers(&h);
+        assert_eq!(
+            freshness_lifetime(StatusCode::OK, &h, &directives, now, true),
+            Duration::from_secs(360)
+        );
+        assert_eq!(
+            freshness_lifetime(StatusCode::CREATED, &h, &directives, now, true),
+            Duration::ZERO
+        );
+    }
+
+    #[test]
+    fn test_initial_age() {
+        let request_time = SystemTime::now();
+        let response_time = request_time + Duration::from_secs(2);
+
+        let h = headers(&[("age", "10")]);
+        assert_eq!(
+            initial_age(&h, request_time, response_time),
+            Duration::from_secs(12)
+        );
+        assert_eq!(
+            initial_age(&HeaderMap::new(), request_time, response_time),
+            Duration::from_secs(2)
+        );
+    }
+}
@@ -0,0 +1,271 @@
+//! Storage of cached responses.
+
+use crate::{HeaderMap, HeaderName, HeaderValue, StatusCode};
+use bytes::Bytes;
+use std::{
+    collections::{BTreeMap, HashMap},
+    future::Future,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+
+/// A response stored by the [`CacheService`].
+///
+/// [`CacheService`]: super::CacheService
+#[derive(Debug, Clone)]
+pub struct CachedResponse {
+    pub(super) status: StatusCode,
+    pub(super) headers: HeaderMap,
+    pub(super) body: Bytes,
+    /// The request header values of the header names listed in the Vary response header.
+    pub(super) vary: Vec<(HeaderName, Option<HeaderValue>)>,
+    pub(super) response_time: SystemTime,
+    pub(super) initial_age: Duration,
+}
+
+impl CachedResponse {
+    /// The status code of the cached response.
+    pub fn status(&self) -> StatusCode {
+        self.status
+    }
+
+    /// The headers of the cached response.
+    pub fn headers(&self) -> &HeaderMap {
+        &self.headers
+    }
+
+    /// The payload of the cached response.
+    pub fn body(&self) -> &Bytes {
+        &self.body
+    }
+
+    /// The time at which the cached response was received
+    /// (or last validated) by the cache.
+    pub fn response_time(&self) -> SystemTime {
+        self.response_time
+    }
+
+    /// Approximate memory size (in bytes) of the cached response,
+    /// e.g. to be used by a [`CacheStore`] to bound its memory usage.
+    pub fn size(&self) -> usize {
+        let headers: usize = self
+            .headers
+            .iter()
+            .map(|(name, value)| name.as_str().len() + value.len())
+            .sum();
+        self.body.len() + headers
+    }
+}
+
+/// Storage used by the [`CacheService`] to store and retrieve cached responses.
+///
+/// Keys are derived from the target uri of the request. Only one response
+/// is stored per key, meaning that a new variant (see the Vary header)
+/// replaces the previously stored one.
+///
+/// [`CacheService`]: super::CacheService
+pub trait CacheStore: Send + Sync + 'static {
+    /// Get the response stored for the given key, if any.
+    fn get(&self, key: &str) -> impl Future<Output = Option<CachedResponse>> + Send;
+
+    /// Store the response for the given key, replacing the previously stored response.
+    fn insert(&self, key: String, response: CachedResponse) -> impl Future<Output = ()> + Send;
+
+    /// Remove the response stored for the given key, if any.
+    fn remove(&self, key: &str) -> impl Future<Output = ()> + Send;
+}
+
+impl<S: CacheStore> CacheStore for Arc<S> {
+    fn get(&self, key: &str) -> impl Future<Output = Option<CachedResponse>> + Send {
+        (**self).get(key)
+    }
+
+    fn insert(&self, key: String, response: CachedResponse) -> impl Future<Output = ()> + Send {
+        (**self).insert(key, response)
+    }
+
+    fn remove(&self, key: &str) -> impl Future<Output = ()> + Send {
+        (**self).remove(key)
+    }
+}
+
+const DEFAULT_MAX_ENTRIES: usize = 1024;
+const DEFAULT_MAX_SIZE: usize = 64 * 1024 * 1024;
+
+/// In-memory [`CacheStore`], evicting the least recently used responses
+/// once the maximum number of entries or total size is exceeded.
+///
+/// Cloning the store is cheap, and all clones share the same storage.
+#[derive(Debug, Clone)]
+pub struct MemoryCacheStore {
+    inner: Arc<Mutex<Lru>>,
+}
+
+#[derive(Debug)]
+struct Lru {
+    entries: HashMap<String, (CachedResponse, u64)>,
+    order: BTreeMap<u64, String>,
+    tick: u64,
+    size: usize,
+    max_entries: usize,
+    max_size: usize,
+}
+
+impl Default for MemoryCacheStore {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_ENTRIES)
+    }
+}
+
+impl MemoryCacheStore {
+    /// Create a new [`MemoryCacheStore`] storing at most `max_entries` responses,
+    /// with a total size of at most 64 MiB.
+    pub fn new(max_entries: usize) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(Lru {
+                entries: HashMap::new(),
+                order: BTreeMap::new(),
+                tick: 0,
+                size: 0,
+                max_entries,
+                max_size: DEFAULT_MAX_SIZE,
+            })),
+        }
+    }
+
+    /// Define the maximum total size (in bytes) of all stored responses.
+    pub fn max_size(self, max_size: usize) -> Self {
+        self.inner.lock().unwrap().max_size = max_size;
+        self
+    }
+
+    /// Define the maximum total size (in bytes) of all stored responses.
+    pub fn set_max_size(&mut self, max_size: usize) -> &mut Self {
+        self.inner.lock().unwrap().max_size = max_size;
+        self
+    }
+
+    /// Returns the number of stored responses.
+    pub fn len(&self) -> usize {
+        self.inner.lock().unwrap().entries.len()
+    }
+
+    /// Returns `true` if no responses are stored.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Remove all stored responses.
+    pub fn clear(&self) {
+        let mut lru = self.inner.lock().unwrap();
+        lru.entries.clear();
+        lru.order.clear();
+        lru.size = 0;
+    }
+}
+
+impl Lru {
+    fn next_tick(&mut self) -> u64 {
+        self.tick += 1;
+        self.tick
+    }
+
+    fn remove(&mut self, key: &str) {
+        if let Some((response, tick)) = self.entries.remove(key) {
+            self.order.remove(&tick);
+            self.size -= response.size();
+        }
+    }
+
+    fn evict(&mut self) {
+        while self.entries.len() > self.max_entries || self.size > self.max_size {
+            let Some((_, key)) = self.order.pop_first() else {
+                return;
+            };
+            if let Some((response, _)) = self.entries.remove(&key) {
+                self.size -= response.size();
+            }
+        }
+    }
+}
+
+impl CacheStore for MemoryCacheStore {
+    async fn get(&self, key: &str) -> Option<CachedResponse> {
+        let mut lru = self.inner.lock().unwrap();
+        let tick = lru.next_tick();
+        let (response, last_used) = lru.entries.get_mut(key)?;
+        let previous = std::mem::replace(last_used, tick);
+        let response = response.clone();
+        lru.order.remove(&previous);
+        lru.order.insert(tick, key.to_owned());
+        Some(response)
+    }
+
+    async fn insert(&self, key: String, response: CachedResponse) {
+        let mut lru = self.inner.lock().unwrap();
+        lru.remove(&key);
+        if response.size() > lru.max_size || lru.max_entries == 0 {
+            return;
+        }
+        let tick = lru.next_tick();
+        lru.size += response.size();
+        lru.order.insert(tick, key.clone());
+        lru.entries.insert(key, (response, tick));
+        lru.evict();
+    }
+
+    async fn remove(&self, key: &str) {
+        self.inner.lock().unwrap().remove(key);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(body: &'static str) -> CachedResponse {
+        CachedResponse {
+            status: StatusCode::OK,
+            headers: HeaderMap::new(),
+            body: Bytes::from_static(body.as_bytes()),
+            vary: Vec::new(),
+            response_time: SystemTime::now(),
+            initial_age: Duration::ZERO,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_memory_store_lru_entries() {
+        let store = MemoryCacheStore::new(2);
+        store.insert("a".to_owned(), response("a")).await;
+        store.insert("b".to_owned(), response("b")).await;
+        // mark `a` as recently used
+        assert_eq!(store.get("a").await.unwrap().body(), "a");
+        store.insert("c".to_owned(), response("c")).await;
+
+        assert_eq!(store.len(), 2);
+        assert!(store.get("a").await.is_some());
+        assert!(store.get("b").await.is_none());
+        assert!(store.get("c").await.is_some());
+
+        store.remove("a").await;
+        assert_eq!(store.len(), 1);
+        store.clear();
+        assert!(store.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_memory_store_lru_size() {
+        let store = MemoryCacheStore::new(10).max_size(10);
+        store.insert("a".to_owned(), response("12345")).await;
+        store.insert("b".to_owned(), response("12345")).await;
+        store.insert("c".to_owned(), response("12345")).await;
+        assert_eq!(store.len(), 2);
+        assert!(store.get("a").await.is_none());
+
+        // too large to be stored at all
+        store.insert("d".to_owned(), response("12345678901")).await;
+        assert!(store.get("d").await.is_none());
+        assert_eq!(store.len(), 2);
+    }
+}
@@ -0,0 +1,484 @@
+//! Middleware that coalesces concurrent identical `GET` requests.
+//!
+//! Also known as "singleflight": while a `GET` request is in flight, identical
+//! requests (with the same coalesce key) wait for it to finish, instead of being
+//! sent to the inner service as well. The single response is then fanned out to all
+//! waiting requests, which greatly reduces the load on the inner service for hot
+//! resources, e.g. when used in a gateway in front of an upstream server.
+//!
+//! The coalesce key consists of the absolute target uri of the request and the
+//! values of the `Authorization` and `Cookie` request headers, as well as the values
+//! of any additional [key headers] (by default `Accept`, `Accept-Encoding`
+//! and `Accept-Language`).
+//!
+//! Only responses of which the body is known to be no larger than the configured
+//! [maximum body size] can be shared. In case the response is larger, or the inner
+//! service fails, the waiting requests are sent to the inner service by themselves.
+//! Requests served with a shared response have the [`Coalesced`] extension
+//! inserted into their response extensions.
+//!
+//! [key headers]: CoalesceLayer::key_header
+//! [maximum body size]: CoalesceLayer::max_body_size
+//!
+//! # Example
+//!
+//! ```
+//! use rama_http::layer::coalesce::{CoalesceLayer, Coalesced};
+//! use rama_http::{Body, Request, Response};
+//! use rama_core::service::service_fn;
+//! use rama_core::{Context, Service, Layer};
+//! use rama_core::error::BoxError;
+//! use std::convert::Infallible;
+//! use std::sync::atomic::{AtomicUsize, Ordering};
+//! use std::sync::Arc;
+//! use std::time::Duration;
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), BoxError> {
+//! let counter = Arc::new(AtomicUsize::new(0));
+//! let service = CoalesceLayer::new().layer(service_fn({
+//!     let counter = counter.clone();
+//!     move |_req: Request| {
+//!         let counter = counter.clone();
+//!         async move {
+//!             counter.fetch_add(1, Ordering::SeqCst);
+//!             tokio::time::sleep(Duration::from_millis(50)).await;
+//!             Ok::<_, Infallible>(Response::new(Body::from("hello")))
+//!         }
+//!     }
+//! }));
+//!
+//! let request = || Request::builder()
+//!     .uri("http://example.com/hot")
+//!     .body(Body::empty())
+//!     .unwrap();
+//!
+//! let (a, b) = tokio::join!(
+//!     service.serve(Context::default(), request()),
+//!     service.serve(Context::default(), request()),
+//! );
+//! assert_eq!(counter.load(Ordering::SeqCst), 1);
+//! assert_eq!(
+//!     a?.extensions().get::<Coalesced>().is_some() as u8
+//!         + b?.extensions().get::<Coalesced>().is_some() as u8,
+//!     1,
+//! );
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{
+    dep::{http_body, http_body_util::BodyExt},
+    header::{ACCEPT, ACCEPT_ENCODING, ACCEPT_LANGUAGE, AUTHORIZATION, COOKIE, HOST},
+    Body, HeaderMap, HeaderName, Method, Request, Response, StatusCode, Version,
+};
+use bytes::Bytes;
+use rama_core::{error::BoxError, Context, Layer, Service};
+use rama_utils::macros::define_inner_service_accessors;
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{Arc, Mutex, PoisonError},
+};
+use tokio::sync::watch;
+
+const DEFAULT_MAX_BODY_SIZE: u64 = 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+/// Inserted in the extensions of a response which was not produced for the request itself,
+/// but shared from an identical request which was in flight at the same time.
+pub struct Coalesced;
+
+#[derive(Debug, Clone)]
+struct SharedResponse {
+    status: StatusCode,
+    version: Version,
+    headers: HeaderMap,
+    body: Bytes,
+}
+
+#[derive(Debug, Clone)]
+enum Flight {
+    Pending,
+    Done(Arc<SharedResponse>),
+    Failed,
+}
+
+#[derive(Debug, Default)]
+struct Flights(Mutex<HashMap<String, watch::Receiver<Flight>>>);
+
+impl Flights {
+    /// Join the flight for the given key, or become its leader if there is none.
+    fn join(&self, key: &str) -> Result<watch::Receiver<Flight>, watch::Sender<Flight>> {
+        let mut flights = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(rx) = flights.get(key) {
+            return Ok(rx.clone());
+        }
+        let (tx, rx) = watch::channel(Flight::Pending);
+        flights.insert(key.to_owned(), rx);
+        Err(tx)
+    }
+
+    fn remove(&self, key: &str, tx: &watch::Sender<Flight>) {
+        let mut flights = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        if flights
+            .get(key)
+            .is_some_and(|rx| rx.same_channel(&tx.subscribe()))
+        {
+            flights.remove(key);
+        }
+    }
+}
+
+/// Ends the flight once the leader is done, even if its future is dropped.
+struct FlightGuard<'a> {
+    flights: &'a Flights,
+    key: &'a str,
+    tx: watch::Sender<Flight>,
+}
+
+impl FlightGuard<'_> {
+    fn finish(self, flight: Flight) {
+        self.flights.remove(self.key, &self.tx);
+        self.tx.send_replace(flight);
+    }
+}
+
+impl Drop for FlightGuard<'_> {
+    fn drop(&mut self) {
+        self.flights.remove(self.key, &self.tx);
+        if matches!(*self.tx.borrow(), Flight::Pending) {
+            self.tx.send_replace(Flight::Failed);
+        }
+    }
+}
+
+/// Layer that applies the [`Coalesce`] middleware.
+///
+/// See the [module docs](self) for more details.
+#[derive(Debug, Clone)]
+pub struct CoalesceLayer {
+    key_headers: Vec<HeaderName>,
+    max_body_size: u64,
+    flights: Arc<Flights>,
+}
+
+impl Default for CoalesceLayer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CoalesceLayer {
+    /// Create a new [`CoalesceLayer`].
+    pub fn new() -> Self {
+        Self {
+            key_headers: vec![ACCEPT, ACCEPT_ENCODING, ACCEPT_LANGUAGE],
+            max_body_size: DEFAULT_MAX_BODY_SIZE,
+            flights: Default::default(),
+        }
+    }
+
+    /// Add a request header of which the value is part of the coalesce key,
+    /// such that only requests with the same value for it are coalesced.
+    pub fn key_header(mut self, name: HeaderName) -> Self {
+        self.key_headers.push(name);
+        self
+    }
+
+    /// Add a request header of which the value is part of the coalesce key,
+    /// such that only requests with the same value for it are coalesced.
+    pub fn set_key_header(&mut self, name: HeaderName) -> &mut Self {
+        self.key_headers.push(name);
+        self
+    }
+
+    /// Define the maximum size of a response payload to be shared.
+    ///
+    /// Defaults to 1 MiB.
+    pub fn max_body_size(mut self, size: u64) -> Self {
+        self.max_body_size = size;
+        self
+    }
+
+    /// Define the maximum size of a response payload to be shared.
+    ///
+    /// Defaults to 1 MiB.
+    pub fn set_max_body_size(&mut self, size: u64) -> &mut Self {
+        self.max_body_size = size;
+        self
+    }
+}
+
+impl<S> Layer<S> for CoalesceLayer {
+    type Service = Coalesce<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Coalesce {
+            inner,
+            key_headers: self.key_headers.clone(),
+            max_body_size: self.max_body_size,
+            flights: self.flights.clone(),
+        }
+    }
+}
+
+/// Middleware which coalesces concurrent identical `GET` requests.
+///
+/// See the [module docs](self) for more details.
+pub struct Coalesce<S> {
+    inner: S,
+    key_headers: Vec<HeaderName>,
+    max_body_size: u64,
+    flights: Arc<Flights>,
+}
+
+impl<S> Coalesce<S> {
+    /// Create a new [`Coalesce`] middleware.
+    pub fn new(inner: S) -> Self {
+        CoalesceLayer::new().layer(inner)
+    }
+
+    define_inner_service_accessors!();
+}
+
+impl<S: fmt::Debug> fmt::Debug for Coalesce<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Coalesce")
+            .field("inner", &self.inner)
+            .field("key_headers", &self.key_headers)
+            .field("max_body_size", &self.max_body_size)
+            .finish()
+    }
+}
+
+impl<S: Clone> Clone for Coalesce<S> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            key_headers: self.key_headers.clone(),
+            max_body_size: self.max_body_size,
+            flights: self.flights.clone(),
+        }
+    }
+}
+
+impl<State, S, ReqBody, ResBody> Service<State, Request<ReqBody>> for Coalesce<S>
+where
+    State: Clone + Send + Sync + 'static,
+    S: Service<State, Request<ReqBody>, Response = Response<ResBody>, Error: Into<BoxError>>,
+    ReqBody: Send + 'static,
+    ResBody: http_body::Body<Data = Bytes, Error: Into<BoxError>> + Send + Sync + 'static,
+{
+    type Response = Response;
+    type Error = BoxError;
+
+    async fn serve(
+        &self,
+        ctx: Context<State>,
+        req: Request<ReqBody>,
+    ) -> Result<Self::Response, Self::Error> {
+        let Some(key) = self.coalesce_key(&req) else {
+            let resp = self.inner.serve(ctx, req).await.map_err(Into::into)?;
+            return Ok(resp.map(Body::new));
+        };
+
+        let tx = match self.flights.join(&key) {
+            Ok(mut rx) => {
+                let flight = rx
+                    .wait_for(|flight| !matches!(flight, Flight::Pending))
+                    .await
+                    .map(|flight| flight.clone())
+                    .unwrap_or(Flight::Failed);
+                if let Flight::Done(shared) = flight {
+                    tracing::trace!(%key, "serve coalesced response");
+                    return Ok(shared_response(&shared));
+                }
+                tracing::trace!(%key, "coalesced request failed: serve request by itself");
+                let resp = self.inner.serve(ctx, req).await.map_err(Into::into)?;
+                return Ok(resp.map(Body::new));
+            }
+            Err(tx) => tx,
+        };
+
+        let guard = FlightGuard {
+            flights: &self.flights,
+            key: &key,
+            tx,
+        };
+        let resp = self.inner.serve(ctx, req).await.map_err(Into::into)?;
+
+        if !resp
+            .body()
+            .size_hint()
+            .upper()
+            .is_some_and(|size| size <= self.max_body_size)
+        {
+            return Ok(resp.map(Body::new));
+        }
+
+        let (parts, body) = resp.into_parts();
+        let body = body.collect().await.map_err(Into::into)?.to_bytes();
+        guard.finish(Flight::Done(Arc::new(SharedResponse {
+            status: parts.status,
+            version: parts.version,
+            headers: parts.headers.clone(),
+            body: body.clone(),
+        })));
+
+        Ok(Response::from_parts(parts, Body::from(body)))
+    }
+}
+
+impl<S> Coalesce<S> {
+    /// Compute the coalesce key of the given request,
+    /// returning `None` if the request cannot be coalesced.
+    fn coalesce_key<B>(&self, req: &Request<B>) -> Option<String> {
+        if req.method() != Method::GET {
+            return None;
+        }
+
+        let uri = req.uri();
+        let mut key = if uri.authority().is_some() {
+            uri.to_string()
+        } else {
+            let host = req.headers().get(HOST)?.to_str().ok()?;
+            format!(
+                "{}{}",
+                host.to_ascii_lowercase(),
+                uri.path_and_query().map(|pq| pq.as_str()).unwrap_or("/")
+            )
+        };
+
+        for name in [&AUTHORIZATION, &COOKIE]
+            .into_iter()
+            .chain(self.key_headers.iter())
+        {
+            for value in req.headers().get_all(name) {
+                key.push('\n');
+                key.push_str(name.as_str());
+                key.push(':');
+                key.push_str(&String::from_utf8_lossy(value.as_bytes()));
+            }
+        }
+
+        Some(key)
+    }
+}
+
+fn shared_response(shared: &SharedResponse) -> Response {
+    let mut resp = Response::new(Body::from(shared.body.clone()));
+    *resp.status_mut() = shared.status;
+    *resp.version_mut() = shared.version;
+    *resp.headers_mut() = shared.headers.clone();
+    resp.extensions_mut().insert(Coalesced);
+    resp
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::BodyExtractExt;
+    use rama_core::service::service_fn;
+    use std::{
+        convert::Infallible,
+        sync::atomic::{AtomicUsize, Ordering},
+        time::Duration,
+    };
+
+    fn request(path: &str, accept: &str) -> Request {
+        Request::builder()
+            .uri(format!("http://example.com{path}"))
+            .header(ACCEPT, accept)
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_coalesce_identical_requests() {
+        let counter = Arc::new(AtomicUsize::new(0));
+        let service = CoalesceLayer::new().layer(service_fn({
+            let counter = counter.clone();
+            move |req: Request| {
+                let counter = counter.clone();
+                async move {
+                    let n = counter.fetch_add(1, Ordering::SeqCst);
+                    tokio::time::sleep(Duration::from_millis(50)).await;
+                    Ok::<_, Infallible>(
+                        Response::builder()
+                            .header("x-n", n)
+                            .body(Body::from(req.uri().path().to_owned()))
+                            .unwrap(),
+                    )
+                }
+            }
+        }));
+
+        let (a, b, c, d) = tokio::join!(
+            service.serve(Context::default(), request("/a", "*/*")),
+            service.serve(Context::default(), request("/a", "*/*")),
+            service.serve(Context::default(), request("/a", "text/html")),
+            service.serve(Context::default(), request("/b", "*/*")),
+        );
+        assert_eq!(counter.load(Ordering::SeqCst), 3);
+
+        let (a, b) = (a.unwrap(), b.unwrap());
+        assert_eq!(a.headers()["x-n"], b.headers()["x-n"]);
+        assert!(a.extensions().get::<Coalesced>().is_none());
+        assert!(b.extensions().get::<Coalesced>().is_some());
+        assert_eq!(b.try_into_string().await.unwrap(), "/a");
+        assert_eq!(a.try_into_string().await.unwrap(), "/a");
+        assert!(c.unwrap().extensions().get::<Coalesced>().is_none());
+        assert_eq!(d.unwrap().try_into_string().await.unwrap(), "/b");
+
+        // flights are not kept around once finished
+        service
+            .serve(Context::default(), request("/a", "*/*"))
+            .await
+            .unwrap();
+        assert_eq!(counter.load(Ordering::SeqCst), 4);
+    }
+
+    #[tokio::test]
+    async fn test_coalesce_not_shareable() {
+        let counter = Arc::new(AtomicUsize::new(0));
+        let service = CoalesceLayer::new().max_body_size(2).layer(service_fn({
+            let counter = counter.clone();
+            move |req: Request| {
+                let counter = counter.clone();
+                async move {
+                    counter.fetch_add(1, Ordering::SeqCst);
+                    tokio::time::sleep(Duration::from_millis(50)).await;
+                    if req.method() == Method::GET {
+                        Ok(Response::new(Body::from("hello")))
+                    } else {
+                        Err(BoxError::from("oops"))
+                    }
+                }
+            }
+        }));
+
+        let (a, b) = tokio::join!(
+            service.serve(Context::default(), request("/", "*/*")),
+            service.serve(Context::default(), request("/", "*/*")),
+        );
+        assert_eq!(counter.load(Ordering::SeqCst), 2);
+        assert_eq!(a.unwrap().try_into_string().await.unwrap(), "hello");
+        assert_eq!(b.unwrap().try_into_string().await.unwrap(), "hello");
+
+        let post = || {
+            Request::builder()
+                .method(Method::POST)
+                .uri("http://example.com/")
+                .body(Body::empty())
+                .unwrap()
+        };
+        let (a, b) = tokio::join!(
+            service.serve(Context::default(), post()),
+            service.serve(Context::default(), post()),
+        );
+        assert_eq!(counter.load(Ordering::SeqCst), 4);
+        assert!(a.is_err() && b.is_err());
+    }
+}
@@ -98,6 +98,30 @@ impl CompressionLayer {
         self
     }
 
+    /// Only compress responses of at least the given size (in bytes),
+    /// instead of the default of 32 bytes.
+    ///
+    /// Only available for the [`DefaultPredicate`], use [`CompressionLayer::compress_when`]
+    /// with a [`SizeAbove`] predicate for custom predicates.
+    ///
+    /// [`SizeAbove`]: super::predicate::SizeAbove
+    pub fn min_size(mut self, min_size_bytes: u16) -> Self {
+        self.predicate.set_min_size(min_size_bytes);
+        self
+    }
+
+    /// Only compress responses of at least the given size (in bytes),
+    /// instead of the default of 32 bytes.
+    ///
+    /// Only available for the [`DefaultPredicate`], use [`CompressionLayer::compress_when`]
+    /// with a [`SizeAbove`] predicate for custom predicates.
+    ///
+    /// [`SizeAbove`]: super::predicate::SizeAbove
+    pub fn set_min_size(&mut self, min_size_bytes: u16) -> &mut Self {
+        self.predicate.set_min_size(min_size_bytes);
+        self
+    }
+
     /// Replace the current compression predicate.
     ///
     /// See [`Compression::compress_when`] for more details.
@@ -118,7 +142,11 @@ mod tests {
     use super::*;
 
     use crate::dep::http_body_util::BodyExt;
-    use crate::{header::ACCEPT_ENCODING, Body, Request, Response};
+    use crate::layer::compression::predicate::ForContentType;
+    use crate::{
+        header::{ACCEPT_ENCODING, CONTENT_TYPE},
+        Body, Request, Response,
+    };
     use rama_core::service::service_fn;
     use rama_core::{Context, Service};
     use std::convert::Infallible;
@@ -191,6 +219,48 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn min_size_and_content_type_filters() -> Result<(), rama_core::error::BoxError> {
+        async fn text(req: Request) -> Result<Response, Infallible> {
+            let size: usize = req.uri().path()[1..].parse().unwrap();
+            Ok(Response::builder()
+                .header(CONTENT_TYPE, "text/plain")
+                .body(Body::from(vec![b'a'; size]))
+                .unwrap())
+        }
+
+        let service = CompressionLayer::new()
+            .min_size(256)
+            .layer(service_fn(text));
+        for (size, expected) in [(128, None), (256, Some("br"))] {
+            let request = Request::builder()
+                .uri(format!("/{size}"))
+                .header(ACCEPT_ENCODING, "gzip;q=0.5, br")
+                .body(Body::empty())?;
+            let response = service.serve(Context::default(), request).await?;
+            assert_eq!(
+                response
+                    .headers()
+                    .get("content-encoding")
+                    .map(|v| v.to_str().unwrap()),
+                expected,
+                "{size}"
+            );
+        }
+
+        let service = CompressionLayer::new()
+            .compress_when(ForContentType::JSON)
+            .layer(service_fn(text));
+        let request = Request::builder()
+            .uri("/1024")
+            .header(ACCEPT_ENCODING, "zstd")
+            .body(Body::empty())?;
+        let response = service.serve(Context::default(), request).await?;
+        assert!(!response.headers().contains_key("content-encoding"));
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn zstd_is_web_safe() -> Result<(), rama_core::error::BoxError> {
         // Test ensuring that zstd compression will not exceed an 8MiB window size; browsers do not
@@ -6,6 +6,7 @@
 //! [`Compression::compress_when`]: super::Compression::compress_when
 //! [`CompressionLayer::compress_when`]: super::CompressionLayer::compress_when
 
+use crate::headers::{ContentLength, HeaderMapExt};
 use http::{header, Extensions, HeaderMap, StatusCode, Version};
 use http_body::Body;
 use std::{fmt, sync::Arc};
@@ -30,6 +31,20 @@ pub trait Predicate: Clone {
             rhs: other,
         }
     }
+
+    /// Combine two predicates into one.
+    ///
+    /// The resulting predicate enables compression if either of the inner predicates does.
+    fn or<Other>(self, other: Other) -> Or<Self, Other>
+    where
+        Self: Sized,
+        Other: Predicate,
+    {
+        Or {
+            lhs: self,
+            rhs: other,
+        }
+    }
 }
 
 impl<F> Predicate for F
@@ -84,6 +99,28 @@ where
     }
 }
 
+/// Two predicates combined into one, enabling compression if either of them does.
+///
+/// Created with [`Predicate::or`]
+#[derive(Debug, Clone, Default, Copy)]
+pub struct Or<Lhs, Rhs> {
+    lhs: Lhs,
+    rhs: Rhs,
+}
+
+impl<Lhs, Rhs> Predicate for Or<Lhs, Rhs>
+where
+    Lhs: Predicate,
+    Rhs: Predicate,
+{
+    fn should_compress<B>(&self, response: &http::Response<B>) -> bool
+    where
+        B: Body,
+    {
+        self.lhs.should_compress(response) || self.rhs.should_compress(response)
+    }
+}
+
 /// The default predicate used by [`Compression`] and [`CompressionLayer`].
 ///
 /// This will compress responses unless:
@@ -95,11 +132,14 @@ where
 ///
 /// # Configuring the defaults
 ///
-/// `DefaultPredicate` doesn't support any configuration. Instead you can build your own predicate
+/// `DefaultPredicate` only allows the minimum size to be configured,
+/// using [`DefaultPredicate::with_min_size`]. For anything else you can build your own predicate
 /// by combining types in this module:
 ///
 /// ```rust
-/// use rama_http::layer::compression::predicate::{SizeAbove, NotForContentType, Predicate};
+/// use rama_http::layer::compression::predicate::{
+///     ForContentType, NotForContentType, Predicate, SizeAbove,
+/// };
 ///
 /// // slightly large min size than the default 32
 /// let predicate = SizeAbove::new(256)
@@ -109,6 +149,11 @@ where
 ///     .and(NotForContentType::IMAGES)
 ///     // also don't compress JSON
 ///     .and(NotForContentType::const_new("application/json"));
+///
+/// // only compress text and javascript responses
+/// let predicate = SizeAbove::new(256).and(
+///     ForContentType::TEXT.or(ForContentType::const_new("application/javascript")),
+/// );
 /// ```
 ///
 /// [`Compression`]: super::Compression
@@ -127,6 +172,24 @@ impl DefaultPredicate {
             .and(NotForContentType::SSE);
         Self(inner)
     }
+
+    /// Only compress responses of at least the given size (in bytes),
+    /// instead of the default of 32 bytes.
+    ///
+    /// See [`SizeAbove`] for more details.
+    pub fn with_min_size(mut self, min_size_bytes: u16) -> Self {
+        self.set_min_size(min_size_bytes);
+        self
+    }
+
+    /// Only compress responses of at least the given size (in bytes),
+    /// instead of the default of 32 bytes.
+    ///
+    /// See [`SizeAbove`] for more details.
+    pub fn set_min_size(&mut self, min_size_bytes: u16) -> &mut Self {
+        self.0.lhs.lhs.lhs = SizeAbove::new(min_size_bytes);
+        self
+    }
 }
 
 impl Default for DefaultPredicate {
@@ -175,9 +238,8 @@ impl Predicate for SizeAbove {
         let content_size = response.body().size_hint().exact().or_else(|| {
             response
                 .headers()
-                .get(header::CONTENT_LENGTH)
-                .and_then(|h| h.to_str().ok())
-                .and_then(|val| val.parse().ok())
+                .typed_get::<ContentLength>()
+                .map(|ContentLength(length)| length)
         });
 
         match content_size {
@@ -239,6 +301,45 @@ impl Predicate for NotForContentType {
     }
 }
 
+/// Predicate that only allows responses with a specific `content-type` to be compressed.
+///
+/// Combine multiple of these using [`Predicate::or`] to allow several content types.
+#[derive(Clone, Debug)]
+pub struct ForContentType {
+    content_type: Str,
+}
+
+impl ForContentType {
+    /// Predicate that only compresses textual responses (`text/*`).
+    pub const TEXT: Self = Self::const_new("text/");
+
+    /// Predicate that only compresses JSON responses.
+    pub const JSON: Self = Self::const_new("application/json");
+
+    /// Create a new `ForContentType`.
+    pub fn new(content_type: &str) -> Self {
+        Self {
+            content_type: Str::Shared(content_type.into()),
+        }
+    }
+
+    /// Create a new `ForContentType` from a static string.
+    pub const fn const_new(content_type: &'static str) -> Self {
+        Self {
+            content_type: Str::Static(content_type),
+        }
+    }
+}
+
+impl Predicate for ForContentType {
+    fn should_compress<B>(&self, response: &http::Response<B>) -> bool
+    where
+        B: Body,
+    {
+        content_type(response).starts_with(self.content_type.as_str())
+    }
+}
+
 #[derive(Clone)]
 enum Str {
     Static(&'static str),
@@ -270,3 +371,36 @@ fn content_type<B>(response: &http::Response<B>) -> &str {
         .and_then(|h| h.to_str().ok())
         .unwrap_or_default()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(content_type: &str, size: usize) -> http::Response<crate::Body> {
+        http::Response::builder()
+            .header(header::CONTENT_TYPE, content_type)
+            .body(crate::Body::from(vec![b'a'; size]))
+            .unwrap()
+    }
+
+    #[test]
+    fn default_predicate_min_size() {
+        let predicate = DefaultPredicate::new();
+        assert!(!predicate.should_compress(&response("text/plain", 31)));
+        assert!(predicate.should_compress(&response("text/plain", 32)));
+
+        let predicate = predicate.with_min_size(1024);
+        assert!(!predicate.should_compress(&response("text/plain", 512)));
+        assert!(predicate.should_compress(&response("text/plain", 1024)));
+        assert!(!predicate.should_compress(&response("image/png", 1024)));
+    }
+
+    #[test]
+    fn for_content_type_or() {
+        let predicate = ForContentType::TEXT.or(ForContentType::JSON);
+        assert!(predicate.should_compress(&response("text/html; charset=utf-8", 64)));
+        assert!(predicate.should_compress(&response("application/json", 64)));
+        assert!(!predicate.should_compress(&response("application/octet-stream", 64)));
+        assert!(!predicate.should_compress(&http::Response::new(crate::Body::empty())));
+    }
+}
@@ -62,6 +62,30 @@ impl<S> Compression<S, DefaultPredicate> {
             quality: CompressionLevel::default(),
         }
     }
+
+    /// Only compress responses of at least the given size (in bytes),
+    /// instead of the default of 32 bytes.
+    ///
+    /// Only available for the [`DefaultPredicate`], use [`Compression::compress_when`]
+    /// with a [`SizeAbove`] predicate for custom predicates.
+    ///
+    /// [`SizeAbove`]: super::predicate::SizeAbove
+    pub fn min_size(mut self, min_size_bytes: u16) -> Self {
+        self.predicate.set_min_size(min_size_bytes);
+        self
+    }
+
+    /// Only compress responses of at least the given size (in bytes),
+    /// instead of the default of 32 bytes.
+    ///
+    /// Only available for the [`DefaultPredicate`], use [`Compression::compress_when`]
+    /// with a [`SizeAbove`] predicate for custom predicates.
+    ///
+    /// [`SizeAbove`]: super::predicate::SizeAbove
+    pub fn set_min_size(&mut self, min_size_bytes: u16) -> &mut Self {
+        self.predicate.set_min_size(min_size_bytes);
+        self
+    }
 }
 
 impl<S, P> Compression<S, P> {
@@ -0,0 +1,410 @@
+//! Middleware that handles conditional requests, as defined in [RFC 9110].
+//!
+//! The [`ConditionalLayer`] computes an `ETag` for successful responses which
+//! do not have one yet (by hashing their body, in case it is small enough and of a known size)
+//! and evaluates the `If-None-Match` and `If-Modified-Since` headers of `GET` and `HEAD`
+//! requests against the `ETag` and `Last-Modified` headers of the response. In case the
+//! response was not modified a `304 Not Modified` response without body is returned instead.
+//!
+//! No `ETag` is computed for responses to `HEAD` requests, given they have no body,
+//! so these only benefit from the validators provided by the inner service.
+//!
+//! It can be used in front of any service, such as the [`ServeDir`] service
+//! or a reverse proxy, and leaves responses which are already conditional untouched.
+//!
+//! [RFC 9110]: https://www.rfc-editor.org/rfc/rfc9110#section-13
+//! [`ServeDir`]: crate::service::fs::ServeDir
+//!
+//! # Example
+//!
+//! ```
+//! use rama_http::layer::conditional::ConditionalLayer;
+//! use rama_http::{header, Body, Request, Response, StatusCode};
+//! use rama_core::service::service_fn;
+//! use rama_core::{Context, Layer, Service};
+//! use std::convert::Infallible;
+//!
+//! # #[tokio::main]
+//! # async fn main() {
+//! let service = ConditionalLayer::new().layer(service_fn(|_req: Request| async {
+//!     Ok::<_, Infallible>(Response::new(Body::from("hello")))
+//! }));
+//!
+//! let resp = service.serve(Context::default(), Request::new(Body::empty())).await.unwrap();
+//! assert_eq!(resp.status(), StatusCode::OK);
+//! let etag = resp.headers()[header::ETAG].clone();
+//!
+//! let req = Request::builder()
+//!     .header(header::IF_NONE_MATCH, etag)
+//!     .body(Body::empty())
+//!     .unwrap();
+//! let resp = service.serve(Context::default(), req).await.unwrap();
+//! assert_eq!(resp.status(), StatusCode::NOT_MODIFIED);
+//! # }
+//! ```
+
+use crate::dep::http_body::Body as HttpBody;
+use crate::dep::http_body_util::BodyExt;
+use crate::header::{
+    CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_RANGE, CONTENT_TYPE, ETAG, TRANSFER_ENCODING,
+};
+use crate::headers::{ETag, HeaderMapExt, IfModifiedSince, IfNoneMatch, LastModified};
+use crate::{Body, HeaderValue, Method, Request, Response, StatusCode};
+use bytes::Bytes;
+use rama_core::error::{BoxError, ErrorContext};
+use rama_core::{Context, Layer, Service};
+use rama_utils::macros::define_inner_service_accessors;
+use ring::digest;
+use std::{fmt, sync::Arc};
+
+/// The default maximum size of a response body which is buffered to compute its `ETag`.
+const DEFAULT_MAX_BODY_SIZE: u64 = 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+/// The kind of `ETag` computed by the [`Conditional`] middleware.
+pub enum ETagMode {
+    #[default]
+    /// A strong `ETag`, promising the body is byte-for-byte identical, e.g. `"3a7f..."`.
+    Strong,
+    /// A weak `ETag`, only promising the body is semantically equivalent, e.g. `W/"3a7f..."`.
+    ///
+    /// Useful in case the responses are (re-)compressed further down the line.
+    Weak,
+}
+
+#[derive(Debug, Clone)]
+struct ConditionalConfig {
+    etag: Option<ETagMode>,
+    max_body_size: u64,
+}
+
+impl Default for ConditionalConfig {
+    fn default() -> Self {
+        Self {
+            etag: Some(ETagMode::default()),
+            max_body_size: DEFAULT_MAX_BODY_SIZE,
+        }
+    }
+}
+
+/// Layer that applies the [`Conditional`] middleware.
+///
+/// See the [module docs](self) for more details.
+#[derive(Debug, Clone, Default)]
+pub struct ConditionalLayer {
+    config: Arc<ConditionalConfig>,
+}
+
+impl ConditionalLayer {
+    /// Create a new [`ConditionalLayer`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the kind of `ETag` computed for responses without one,
+    /// or `None` to only use the validators provided by the inner service.
+    ///
+    /// Defaults to [`ETagMode::Strong`].
+    pub fn etag(mut self, mode: Option<ETagMode>) -> Self {
+        Arc::make_mut(&mut self.config).etag = mode;
+        self
+    }
+
+    /// Set the kind of `ETag` computed for responses without one,
+    /// or `None` to only use the validators provided by the inner service.
+    ///
+    /// Defaults to [`ETagMode::Strong`].
+    pub fn set_etag(&mut self, mode: Option<ETagMode>) -> &mut Self {
+        Arc::make_mut(&mut self.config).etag = mode;
+        self
+    }
+
+    /// Set the maximum size of a response body which is buffered to compute its `ETag`.
+    ///
+    /// Defaults to 1 MiB.
+    pub fn max_body_size(mut self, size: u64) -> Self {
+        Arc::make_mut(&mut self.config).max_body_size = size;
+        self
+    }
+
+    /// Set the maximum size of a response body which is buffered to compute its `ETag`.
+    ///
+    /// Defaults to 1 MiB.
+    pub fn set_max_body_size(&mut self, size: u64) -> &mut Self {
+        Arc::make_mut(&mut self.config).max_body_size = size;
+        self
+    }
+}
+
+impl<S> Layer<S> for ConditionalLayer {
+    type Service = Conditional<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Conditional {
+            inner,
+            config: self.config.clone(),
+        }
+    }
+}
+
+/// Middleware that handles conditional requests.
+///
+/// See the [module docs](self) for more details.
+pub struct Conditional<S> {
+    inner: S,
+    config: Arc<ConditionalConfig>,
+}
+
+impl<S> Conditional<S> {
+    /// Create a new [`Conditional`] with the default configuration.
+    pub fn new(inner: S) -> Self {
+        ConditionalLayer::new().layer(inner)
+    }
+
+    define_inner_service_accessors!();
+}
+
+impl<S: fmt::Debug> fmt::Debug for Conditional<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Conditional")
+            .field("inner", &self.inner)
+            .field("config", &self.config)
+            .finish()
+    }
+}
+
+impl<S: Clone> Clone for Conditional<S> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            config: self.config.clone(),
+        }
+    }
+}
+
+impl<S, State, ReqBody, ResBody> Service<State, Request<ReqBody>> for Conditional<S>
+where
+    S: Service<State, Request<ReqBody>, Response = Response<ResBody>, Error: Into<BoxError>>,
+    State: Clone + Send + Sync + 'static,
+    ReqBody: Send + 'static,
+    ResBody: HttpBody<Data = Bytes, Error: Into<BoxError>> + Send + Sync + 'static,
+{
+    type Response = Response;
+    type Error = BoxError;
+
+    async fn serve(
+        &self,
+        ctx: Context<State>,
+        req: Request<ReqBody>,
+    ) -> Result<Self::Response, Self::Error> {
+        let is_head = req.method() == Method::HEAD;
+        let is_conditional = is_head || req.method() == Method::GET;
+        let if_none_match = req.headers().typed_get::<IfNoneMatch>();
+        let if_modified_since = req.headers().typed_get::<IfModifiedSince>();
+
+        let resp = self.inner.serve(ctx, req).await.map_err(Into::into)?;
+        let (mut parts, body) = resp.into_parts();
+        let mut body = Body::new(body);
+        if parts.status != StatusCode::OK {
+            return Ok(Response::from_parts(parts, body));
+        }
+
+        if let Some(mode) = self.config.etag {
+            let size = body.size_hint().exact();
+            if !is_head
+                && !parts.headers.contains_key(ETAG)
+                && size.is_some_and(|size| size <= self.config.max_body_size)
+            {
+                let bytes = body
+                    .collect()
+                    .await
+                    .context("collect response body to compute etag")?
+                    .to_bytes();
+                parts.headers.insert(ETAG, etag(mode, &bytes));
+                body = Body::from(bytes);
+            }
+        }
+
+        let not_modified = if let Some(if_none_match) = if_none_match {
+            // If-Modified-Since is ignored when If-None-Match is present
+            parts
+                .headers
+                .typed_get::<ETag>()
+                .is_some_and(|etag| !if_none_match.precondition_passes(&etag))
+        } else if let Some(if_modified_since) = if_modified_since {
+            parts
+                .headers
+                .typed_get::<LastModified>()
+                .is_some_and(|last_modified| !if_modified_since.is_modified(last_modified.into()))
+        } else {
+            false
+        };
+
+        if is_conditional && not_modified {
+            parts.status = StatusCode::NOT_MODIFIED;
+            for name in [
+                CONTENT_LENGTH,
+                CONTENT_TYPE,
+                CONTENT_ENCODING,
+                CONTENT_RANGE,
+                TRANSFER_ENCODING,
+            ] {
+                parts.headers.remove(name);
+            }
+            return Ok(Response::from_parts(parts, Body::empty()));
+        }
+
+        Ok(Response::from_parts(parts, body))
+    }
+}
+
+/// Compute the `ETag` of the given body, using (a prefix of) its SHA-256 hash.
+fn etag(mode: ETagMode, body: &[u8]) -> HeaderValue {
+    let hash = digest::digest(&digest::SHA256, body);
+    let tag = hex::encode(&hash.as_ref()[..16]);
+    let value = match mode {
+        ETagMode::Strong => format!("\"{tag}\""),
+        ETagMode::Weak => format!("W/\"{tag}\""),
+    };
+    HeaderValue::from_str(&value).expect("etag to be a valid header value")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::header::{IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED};
+    use rama_core::service::service_fn;
+    use std::convert::Infallible;
+
+    fn request(method: Method, headers: &[(&'static str, &str)]) -> Request {
+        let mut req = Request::builder().method(method);
+        for (name, value) in headers {
+            req = req.header(*name, *value);
+        }
+        req.body(Body::empty()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_conditional_computed_etag() {
+        let svc = ConditionalLayer::new().layer(service_fn(|_req: Request| async {
+            Ok::<_, Infallible>(
+                Response::builder()
+                    .header(CONTENT_TYPE, "text/plain")
+                    .body(Body::from("hello"))
+                    .unwrap(),
+            )
+        }));
+
+        let resp = svc
+            .serve(Context::default(), request(Method::GET, &[]))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let etag = resp.headers()[ETAG].to_str().unwrap().to_owned();
+        assert_eq!(etag, "\"2cf24dba5fb0a30e26e83b2ac5b9e29e\"");
+        let body = resp.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, "hello");
+
+        for (method, header, status) in [
+            (Method::GET, etag.as_str(), StatusCode::NOT_MODIFIED),
+            (
+                Method::GET,
+                "\"foo\", W/\"2cf24dba5fb0a30e26e83b2ac5b9e29e\"",
+                StatusCode::NOT_MODIFIED,
+            ),
+            (Method::GET, "*", StatusCode::NOT_MODIFIED),
+            (Method::GET, "\"foo\"", StatusCode::OK),
+            (Method::POST, etag.as_str(), StatusCode::OK),
+        ] {
+            let resp = svc
+                .serve(
+                    Context::default(),
+                    request(method.clone(), &[("if-none-match", header)]),
+                )
+                .await
+                .unwrap();
+            assert_eq!(resp.status(), status, "{method} {header}");
+            if status == StatusCode::NOT_MODIFIED {
+                assert!(!resp.headers().contains_key(CONTENT_TYPE));
+                assert_eq!(resp.headers()[ETAG], etag.as_str());
+                assert!(resp
+                    .into_body()
+                    .collect()
+                    .await
+                    .unwrap()
+                    .to_bytes()
+                    .is_empty());
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_conditional_last_modified() {
+        let svc = ConditionalLayer::new()
+            .etag(None)
+            .layer(service_fn(|_req: Request| async {
+                Ok::<_, Infallible>(
+                    Response::builder()
+                        .header(LAST_MODIFIED, "Wed, 21 Oct 2015 07:28:00 GMT")
+                        .body(Body::from("hello"))
+                        .unwrap(),
+                )
+            }));
+
+        for (headers, status) in [
+            (
+                &[(IF_MODIFIED_SINCE.as_str(), "Wed, 21 Oct 2015 07:28:00 GMT")][..],
+                StatusCode::NOT_MODIFIED,
+            ),
+            (
+                &[(IF_MODIFIED_SINCE.as_str(), "Tue, 20 Oct 2015 07:28:00 GMT")][..],
+                StatusCode::OK,
+            ),
+            (
+                // If-None-Match takes precedence, and no etag is computed
+                &[
+                    (IF_NONE_MATCH.as_str(), "*"),
+                    (IF_MODIFIED_SINCE.as_str(), "Wed, 21 Oct 2015 07:28:00 GMT"),
+                ][..],
+                StatusCode::OK,
+            ),
+        ] {
+            let resp = svc
+                .serve(Context::default(), request(Method::GET, headers))
+                .await
+                .unwrap();
+            assert_eq!(resp.status(), status, "{headers:?}");
+            assert!(!resp.headers().contains_key(ETAG));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_conditional_weak_etag_and_max_body_size() {
+        let svc = ConditionalLayer::new()
+            .etag(Some(ETagMode::Weak))
+            .max_body_size(5)
+            .layer(service_fn(|req: Request| async move {
+                let body = if req.uri().path() == "/big" {
+                    "hello world"
+                } else {
+                    "hello"
+                };
+                Ok::<_, Infallible>(Response::new(Body::from(body)))
+            }));
+
+        let resp = svc
+            .serve(Context::default(), request(Method::GET, &[]))
+            .await
+            .unwrap();
+        assert_eq!(
+            resp.headers()[ETAG],
+            "W/\"2cf24dba5fb0a30e26e83b2ac5b9e29e\""
+        );
+
+        let req = Request::builder().uri("/big").body(Body::empty()).unwrap();
+        let resp = svc.serve(Context::default(), req).await.unwrap();
+        assert!(!resp.headers().contains_key(ETAG));
+        let body = resp.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, "hello world");
+    }
+}
@@ -0,0 +1,605 @@
+//! Enforce that the length of request and response bodies
+//! matches the length declared by their `Content-Length` header.
+//!
+//! A body that is longer or shorter than declared is a sign of a broken
+//! (or malicious) peer, and forwarding it as-is would make a proxy
+//! desynchronize the connections it forwards to. By default such a body
+//! results in a [`ContentLengthMismatchError`] for the consumer of the body,
+//! but the layer can also be configured to truncate bodies which are too long
+//! (and only log bodies which are too short), using [`OnMismatch::Truncate`].
+//!
+//! All mismatches are counted in the [`ContentLengthStats`] of the layer.
+//!
+//! # Example
+//!
+//! ```
+//! use rama_http::layer::content_length::{ContentLengthBody, EnforceContentLengthLayer};
+//! use rama_http::{header, Body, Request, Response};
+//! use rama_http::dep::http_body_util::BodyExt;
+//! use rama_core::service::service_fn;
+//! use rama_core::{Context, Service, Layer};
+//! use std::convert::Infallible;
+//!
+//! # #[tokio::main]
+//! # async fn main() {
+//! let layer = EnforceContentLengthLayer::new();
+//! let stats = layer.stats().clone();
+//!
+//! let service = layer.layer(service_fn(|req: Request<ContentLengthBody<Body>>| async move {
+//!     // the request body declared to be longer than it is
+//!     assert!(req.into_body().collect().await.is_err());
+//!     Ok::<_, Infallible>(Response::new(Body::empty()))
+//! }));
+//!
+//! let req = Request::builder()
+//!     .header(header::CONTENT_LENGTH, 10)
+//!     .body(Body::from("hello"))
+//!     .unwrap();
+//! service.serve(Context::default(), req).await.unwrap();
+//! assert_eq!(stats.request_too_short(), 1);
+//! # }
+//! ```
+
+use crate::dep::http_body::{Body as HttpBody, Frame, SizeHint};
+use crate::{header::CONTENT_LENGTH, HeaderMap, Method, Request, Response};
+use bytes::{Buf, Bytes};
+use pin_project_lite::pin_project;
+use rama_core::{error::BoxError, Context, Layer, Service};
+use rama_utils::macros::define_inner_service_accessors;
+use std::{
+    fmt,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    task::{ready, Context as TaskContext, Poll},
+};
+
+/// What to do with a body that does not match its declared `Content-Length`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OnMismatch {
+    #[default]
+    /// Fail the body with a [`ContentLengthMismatchError`].
+    Error,
+    /// Truncate bodies which are too long to the declared length,
+    /// and end bodies which are too short, logging the mismatch in both cases.
+    Truncate,
+}
+
+/// Error returned by a [`ContentLengthBody`] which does not
+/// match its declared `Content-Length`, using [`OnMismatch::Error`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContentLengthMismatchError {
+    declared: u64,
+    actual: u64,
+    too_long: bool,
+}
+
+impl ContentLengthMismatchError {
+    /// The length declared by the `Content-Length` header.
+    pub fn declared(&self) -> u64 {
+        self.declared
+    }
+
+    /// The amount of bytes received when the mismatch was detected,
+    /// which for bodies that are too long is not the full length of the body.
+    pub fn actual(&self) -> u64 {
+        self.actual
+    }
+
+    /// Returns `true` in case the body is longer than declared.
+    pub fn is_too_long(&self) -> bool {
+        self.too_long
+    }
+}
+
+impl fmt::Display for ContentLengthMismatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.too_long {
+            write!(
+                f,
+                "body is longer than its content-length of {} bytes (received at least {} bytes)",
+                self.declared, self.actual
+            )
+        } else {
+            write!(
+                f,
+                "body is shorter than its content-length of {} bytes (received {} bytes)",
+                self.declared, self.actual
+            )
+        }
+    }
+}
+
+impl std::error::Error for ContentLengthMismatchError {}
+
+/// Counters of the `Content-Length` mismatches detected by an [`EnforceContentLengthLayer`],
+/// shared by all clones.
+#[derive(Debug, Clone, Default)]
+pub struct ContentLengthStats {
+    inner: Arc<ContentLengthStatsInner>,
+}
+
+#[derive(Debug, Default)]
+struct ContentLengthStatsInner {
+    request_too_long: AtomicU64,
+    request_too_short: AtomicU64,
+    response_too_long: AtomicU64,
+    response_too_short: AtomicU64,
+}
+
+impl ContentLengthStats {
+    /// The amount of request bodies longer than declared.
+    pub fn request_too_long(&self) -> u64 {
+        self.inner.request_too_long.load(Ordering::Relaxed)
+    }
+
+    /// The amount of request bodies shorter than declared.
+    pub fn request_too_short(&self) -> u64 {
+        self.inner.request_too_short.load(Ordering::Relaxed)
+    }
+
+    /// The amount of response bodies longer than declared.
+    pub fn response_too_long(&self) -> u64 {
+        self.inner.response_too_long.load(Ordering::Relaxed)
+    }
+
+    /// The amount of response bodies shorter than declared.
+    pub fn response_too_short(&self) -> u64 {
+        self.inner.response_too_short.load(Ordering::Relaxed)
+    }
+
+    /// The total amount of mismatches detected.
+    pub fn mismatches(&self) -> u64 {
+        self.request_too_long()
+            + self.request_too_short()
+            + self.response_too_long()
+            + self.response_too_short()
+    }
+
+    fn record(&self, direction: Direction, too_long: bool) {
+        let counter = match (direction, too_long) {
+            (Direction::Request, true) => &self.inner.request_too_long,
+            (Direction::Request, false) => &self.inner.request_too_short,
+            (Direction::Response, true) => &self.inner.response_too_long,
+            (Direction::Response, false) => &self.inner.response_too_short,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Direction {
+    Request,
+    Response,
+}
+
+impl Direction {
+    fn as_str(self) -> &'static str {
+        match self {
+            Direction::Request => "request",
+            Direction::Response => "response",
+        }
+    }
+}
+
+/// Layer that applies the [`EnforceContentLength`] middleware.
+///
+/// See the [module docs](self) for more details.
+#[derive(Debug, Clone, Default)]
+pub struct EnforceContentLengthLayer {
+    on_mismatch: OnMismatch,
+    stats: ContentLengthStats,
+}
+
+impl EnforceContentLengthLayer {
+    /// Create a new [`EnforceContentLengthLayer`], failing mismatching bodies.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set what to do with a body that does not match its declared `Content-Length`.
+    pub fn with_on_mismatch(mut self, on_mismatch: OnMismatch) -> Self {
+        self.on_mismatch = on_mismatch;
+        self
+    }
+
+    /// Set what to do with a body that does not match its declared `Content-Length`.
+    pub fn set_on_mismatch(&mut self, on_mismatch: OnMismatch) -> &mut Self {
+        self.on_mismatch = on_mismatch;
+        self
+    }
+
+    /// The [`ContentLengthStats`] of this layer.
+    pub fn stats(&self) -> &ContentLengthStats {
+        &self.stats
+    }
+}
+
+impl<S> Layer<S> for EnforceContentLengthLayer {
+    type Service = EnforceContentLength<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        EnforceContentLength {
+            inner,
+            on_mismatch: self.on_mismatch,
+            stats: self.stats.clone(),
+        }
+    }
+}
+
+/// Middleware which enforces that the length of request and response bodies
+/// matches the length declared by their `Content-Length` header.
+///
+/// See the [module docs](self) for more details.
+pub struct EnforceContentLength<S> {
+    inner: S,
+    on_mismatch: OnMismatch,
+    stats: ContentLengthStats,
+}
+
+impl<S> EnforceContentLength<S> {
+    define_inner_service_accessors!();
+}
+
+impl<S: fmt::Debug> fmt::Debug for EnforceContentLength<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EnforceContentLength")
+            .field("inner", &self.inner)
+            .field("on_mismatch", &self.on_mismatch)
+            .field("stats", &self.stats)
+            .finish()
+    }
+}
+
+impl<S: Clone> Clone for EnforceContentLength<S> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            on_mismatch: self.on_mismatch,
+            stats: self.stats.clone(),
+        }
+    }
+}
+
+impl<State, S, ReqBody, ResBody> Service<State, Request<ReqBody>> for EnforceContentLength<S>
+where
+    State: Clone + Send + Sync + 'static,
+    S: Service<State, Request<ContentLengthBody<ReqBody>>, Response = Response<ResBody>>,
+    ReqBody: Send + 'static,
+    ResBody: Send + 'static,
+{
+    type Response = Response<ContentLengthBody<ResBody>>;
+    type Error = S::Error;
+
+    async fn serve(
+        &self,
+        ctx: Context<State>,
+        req: Request<ReqBody>,
+    ) -> Result<Self::Response, Self::Error> {
+        let head = req.method() == Method::HEAD;
+        let declared = content_length(req.headers());
+        let req = req.map(|body| self.body(body, declared, Direction::Request));
+
+        let res = self.inner.serve(ctx, req).await?;
+
+        // the content-length of these responses does not describe their (empty) body
+        let status = res.status();
+        let declared = if head
+            || status.is_informational()
+            || status == crate::StatusCode::NO_CONTENT
+            || status == crate::StatusCode::NOT_MODIFIED
+        {
+            None
+        } else {
+            content_length(res.headers())
+        };
+        Ok(res.map(|body| self.body(body, declared, Direction::Response)))
+    }
+}
+
+impl<S> EnforceContentLength<S> {
+    fn body<B>(
+        &self,
+        inner: B,
+        declared: Option<u64>,
+        direction: Direction,
+    ) -> ContentLengthBody<B> {
+        ContentLengthBody {
+            inner,
+            declared,
+            received: 0,
+            done: false,
+            direction,
+            on_mismatch: self.on_mismatch,
+            stats: self.stats.clone(),
+        }
+    }
+}
+
+/// The declared content length, if any (and consistent).
+fn content_length(headers: &HeaderMap) -> Option<u64> {
+    let mut values = headers
+        .get_all(CONTENT_LENGTH)
+        .into_iter()
+        .map(|value| value.to_str().ok()?.trim().parse::<u64>().ok());
+    let first = values.next()??;
+    values.all(|value| value == Some(first)).then_some(first)
+}
+
+pin_project! {
+    /// Request and response body used by [`EnforceContentLength`],
+    /// enforcing that its length matches the declared `Content-Length`.
+    pub struct ContentLengthBody<B> {
+        #[pin]
+        inner: B,
+        declared: Option<u64>,
+        received: u64,
+        done: bool,
+        direction: Direction,
+        on_mismatch: OnMismatch,
+        stats: ContentLengthStats,
+    }
+}
+
+impl<B: fmt::Debug> fmt::Debug for ContentLengthBody<B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ContentLengthBody")
+            .field("inner", &self.inner)
+            .field("declared", &self.declared)
+            .field("received", &self.received)
+            .finish()
+    }
+}
+
+impl<B> HttpBody for ContentLengthBody<B>
+where
+    B: HttpBody<Error: Into<BoxError>>,
+{
+    type Data = Bytes;
+    type Error = BoxError;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut TaskContext<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let this = self.project();
+        if *this.done {
+            return Poll::Ready(None);
+        }
+
+        let frame = match ready!(this.inner.poll_frame(cx)) {
+            Some(Ok(frame)) => frame.map_data(|mut data| data.copy_to_bytes(data.remaining())),
+            Some(Err(err)) => return Poll::Ready(Some(Err(err.into()))),
+            None => {
+                *this.done = true;
+                let Some(declared) = *this.declared else {
+                    return Poll::Ready(None);
+                };
+                if *this.received >= declared {
+                    return Poll::Ready(None);
+                }
+                this.stats.record(*this.direction, false);
+                let err = ContentLengthMismatchError {
+                    declared,
+                    actual: *this.received,
+                    too_long: false,
+                };
+                return match this.on_mismatch {
+                    OnMismatch::Error => Poll::Ready(Some(Err(err.into()))),
+                    OnMismatch::Truncate => {
+                        tracing::warn!(direction = this.direction.as_str(), error = %err, "content-length mismatch");
+                        Poll::Ready(None)
+                    }
+                };
+            }
+        };
+
+        let (Some(declared), Some(data)) = (*this.declared, frame.data_ref()) else {
+            return Poll::Ready(Some(Ok(frame)));
+        };
+        *this.received += data.len() as u64;
+        if *this.received <= declared {
+            return Poll::Ready(Some(Ok(frame)));
+        }
+
+        *this.done = true;
+        this.stats.record(*this.direction, true);
+        let err = ContentLengthMismatchError {
+            declared,
+            actual: *this.received,
+            too_long: true,
+        };
+        match this.on_mismatch {
+            OnMismatch::Error => Poll::Ready(Some(Err(err.into()))),
+            OnMismatch::Truncate => {
+                tracing::warn!(direction = this.direction.as_str(), error = %err, "content-length mismatch: truncate body");
+                let excess = (*this.received - declared) as usize;
+                let data = data.slice(..data.len() - excess);
+                *this.received = declared;
+                if data.is_empty() {
+                    Poll::Ready(None)
+                } else {
+                    Poll::Ready(Some(Ok(Frame::data(data))))
+                }
+            }
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        if self.done {
+            return true;
+        }
+        match self.declared {
+            // a body which ends before its declared length still has to be polled,
+            // such that the mismatch is reported
+            Some(declared) if self.received < declared => false,
+            _ => self.inner.is_end_stream(),
+        }
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dep::http_body_util::BodyExt;
+    use crate::{header::CONTENT_LENGTH, Body, StatusCode};
+    use rama_core::service::service_fn;
+    use std::convert::Infallible;
+
+    fn layer(on_mismatch: OnMismatch) -> EnforceContentLengthLayer {
+        EnforceContentLengthLayer::new().with_on_mismatch(on_mismatch)
+    }
+
+    async fn serve_response(
+        layer: &EnforceContentLengthLayer,
+        method: Method,
+        status: StatusCode,
+        content_length: u64,
+        body: &'static str,
+    ) -> Result<Bytes, BoxError> {
+        let svc = layer.layer(service_fn(
+            move |_: Request<ContentLengthBody<Body>>| async move {
+                Ok::<_, Infallible>(
+                    Response::builder()
+                        .status(status)
+                        .header(CONTENT_LENGTH, content_length)
+                        .body(Body::from(body))
+                        .unwrap(),
+                )
+            },
+        ));
+        let req = Request::builder()
+            .method(method)
+            .body(Body::empty())
+            .unwrap();
+        let resp = svc.serve(Context::default(), req).await.unwrap();
+        resp.into_body().collect().await.map(|body| body.to_bytes())
+    }
+
+    #[tokio::test]
+    async fn test_matching_length() {
+        let layer = layer(OnMismatch::Error);
+        let body = serve_response(&layer, Method::GET, StatusCode::OK, 5, "hello")
+            .await
+            .unwrap();
+        assert_eq!(body, "hello");
+        assert_eq!(layer.stats().mismatches(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_mismatch_error() {
+        let layer = layer(OnMismatch::Error);
+
+        let err = serve_response(&layer, Method::GET, StatusCode::OK, 3, "hello")
+            .await
+            .unwrap_err();
+        let err = err.downcast_ref::<ContentLengthMismatchError>().unwrap();
+        assert!(err.is_too_long());
+        assert_eq!(err.declared(), 3);
+
+        let err = serve_response(&layer, Method::GET, StatusCode::OK, 10, "hello")
+            .await
+            .unwrap_err();
+        let err = err.downcast_ref::<ContentLengthMismatchError>().unwrap();
+        assert!(!err.is_too_long());
+        assert_eq!(err.actual(), 5);
+
+        assert_eq!(layer.stats().response_too_long(), 1);
+        assert_eq!(layer.stats().response_too_short(), 1);
+        assert_eq!(layer.stats().mismatches(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_mismatch_truncate() {
+        let layer = layer(OnMismatch::Truncate);
+
+        let body = serve_response(&layer, Method::GET, StatusCode::OK, 3, "hello")
+            .await
+            .unwrap();
+        assert_eq!(body, "hel");
+        let body = serve_response(&layer, Method::GET, StatusCode::OK, 10, "hello")
+            .await
+            .unwrap();
+        assert_eq!(body, "hello");
+        assert_eq!(layer.stats().mismatches(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_head_and_no_content_ignored() {
+        let layer = layer(OnMismatch::Error);
+        serve_response(&layer, Method::HEAD, StatusCode::OK, 10, "")
+            .await
+            .unwrap();
+        serve_response(&layer, Method::GET, StatusCode::NOT_MODIFIED, 10, "")
+            .await
+            .unwrap();
+        assert_eq!(layer.stats().mismatches(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_request_too_long() {
+        let layer = layer(OnMismatch::Error);
+        let svc = layer.layer(service_fn(
+            |req: Request<ContentLengthBody<Body>>| async move {
+                let result = req.into_body().collect().await;
+                Ok::<_, Infallible>(Response::new(Body::from(result.is_err().to_string())))
+            },
+        ));
+        let req = Request::builder()
+            .method(Method::POST)
+            .header(CONTENT_LENGTH, 2)
+            .body(Body::from("hello"))
+            .unwrap();
+        let resp = svc.serve(Context::default(), req).await.unwrap();
+        let body = resp.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, "true");
+        assert_eq!(layer.stats().request_too_long(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_is_end_stream_until_declared_length() {
+        let layer = layer(OnMismatch::Error);
+        let svc = layer.layer(service_fn(
+            |req: Request<ContentLengthBody<Body>>| async move {
+                let body = req.into_body();
+                // the (empty) inner body ended, but not its declared length
+                assert!(!body.is_end_stream());
+                let result = body.collect().await;
+                Ok::<_, Infallible>(Response::new(Body::from(result.is_err().to_string())))
+            },
+        ));
+        let req = Request::builder()
+            .method(Method::POST)
+            .header(CONTENT_LENGTH, 5)
+            .body(Body::empty())
+            .unwrap();
+        let resp = svc.serve(Context::default(), req).await.unwrap();
+        let body = resp.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, "true");
+        assert_eq!(layer.stats().request_too_short(), 1);
+
+        let body = serve_response(&layer, Method::GET, StatusCode::OK, 0, "")
+            .await
+            .unwrap();
+        assert!(body.is_empty());
+        assert_eq!(layer.stats().mismatches(), 1);
+    }
+
+    #[test]
+    fn test_content_length_header() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(content_length(&headers), None);
+        headers.insert(CONTENT_LENGTH, "42".parse().unwrap());
+        assert_eq!(content_length(&headers), Some(42));
+        headers.append(CONTENT_LENGTH, "42".parse().unwrap());
+        assert_eq!(content_length(&headers), Some(42));
+        headers.append(CONTENT_LENGTH, "7".parse().unwrap());
+        assert_eq!(content_length(&headers), None);
+    }
+}
@@ -0,0 +1,591 @@
+//! Cross-Site Request Forgery (CSRF) protection, using the double-submit cookie pattern.
+//!
+//! The [`CsrfLayer`] ensures each client has a (random) token stored in a cookie,
+//! and rejects unsafe requests (e.g. `POST`, `PUT`, `DELETE`) with `403 Forbidden`
+//! unless they submit the same token in a header or in a (urlencoded) form field.
+//! A cross-site attacker can make the browser send the cookie,
+//! but cannot read it and thus cannot submit the token.
+//!
+//! The token of the current request is available as [`CsrfToken`] in the [`Context`],
+//! such that it can be rendered into forms, or bound to a session.
+//! Token generation and validation can be customised by implementing [`CsrfTokens`].
+//!
+//! On top of that the cookie is set with a [`SameSite`] attribute (`Strict` by default),
+//! and unsafe requests which browsers mark as cross-site (`Sec-Fetch-Site: cross-site`)
+//! are rejected regardless of the submitted token.
+//!
+//! # Example
+//!
+//! ```
+//! use rama_http::layer::csrf::{CsrfLayer, CsrfToken};
+//! use rama_http::{Body, Request, Response, StatusCode};
+//! use rama_core::service::service_fn;
+//! use rama_core::{Context, Layer, Service};
+//! use std::convert::Infallible;
+//!
+//! # #[tokio::main]
+//! # async fn main() {
+//! let svc = CsrfLayer::new().layer(service_fn(|ctx: Context<()>, _req: Request| async move {
+//!     let token = ctx.get::<CsrfToken>().unwrap();
+//!     Ok::<_, Infallible>(Response::new(Body::from(format!(
+//!         r#"<form method="post"><input type="hidden" name="csrf_token" value="{}"></form>"#,
+//!         token.as_str(),
+//!     ))))
+//! }));
+//!
+//! // safe requests are allowed, and get a token cookie assigned
+//! let resp = svc.serve(Context::default(), Request::new(Body::empty())).await.unwrap();
+//! assert_eq!(resp.status(), StatusCode::OK);
+//! assert!(resp.headers().contains_key("set-cookie"));
+//!
+//! // unsafe requests without a (matching) token are rejected
+//! let req = Request::builder().method("POST").body(Body::empty()).unwrap();
+//! let resp = svc.serve(Context::default(), req).await.unwrap();
+//! assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+//! # }
+//! ```
+
+use crate::dep::http_body_util::{BodyExt, Limited};
+use crate::dep::mime::{self, Mime};
+use crate::headers::{ContentType, Cookie, HeaderMapExt};
+use crate::utils::SetCookie;
+use crate::{header, Body, HeaderName, HeaderValue, Method, Request, Response, StatusCode};
+use bytes::Bytes;
+use rama_core::{error::BoxError, Context, Layer, Service};
+use rama_utils::macros::define_inner_service_accessors;
+use std::{fmt, sync::Arc};
+use uuid::Uuid;
+
+#[doc(inline)]
+pub use crate::utils::SameSite;
+
+const DEFAULT_COOKIE_NAME: &str = "csrf_token";
+const DEFAULT_HEADER_NAME: &str = "x-csrf-token";
+const DEFAULT_FORM_FIELD: &str = "csrf_token";
+
+/// The maximum size of a urlencoded form body that is buffered to look for the token.
+const MAX_FORM_SIZE: usize = 64 * 1024;
+
+/// Generation and validation of CSRF tokens, used by the [`CsrfService`].
+///
+/// Implement it to e.g. bind tokens to a session,
+/// or sign them using a server secret.
+pub trait CsrfTokens: Send + Sync + 'static {
+    /// Generate a new token, to be stored in the CSRF cookie.
+    ///
+    /// The token has to be a valid cookie value.
+    fn generate_token(&self) -> String;
+
+    /// Returns `true` if the submitted token is valid,
+    /// given the token that was stored in the CSRF cookie.
+    fn validate_token(&self, cookie_token: &str, submitted_token: &str) -> bool;
+}
+
+impl<T: CsrfTokens> CsrfTokens for Arc<T> {
+    fn generate_token(&self) -> String {
+        (**self).generate_token()
+    }
+
+    fn validate_token(&self, cookie_token: &str, submitted_token: &str) -> bool {
+        (**self).validate_token(cookie_token, submitted_token)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+/// The default [`CsrfTokens`]: random (256 bit) tokens,
+/// valid if the submitted token equals the cookie token.
+pub struct RandomCsrfTokens;
+
+impl CsrfTokens for RandomCsrfTokens {
+    fn generate_token(&self) -> String {
+        format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple())
+    }
+
+    fn validate_token(&self, cookie_token: &str, submitted_token: &str) -> bool {
+        constant_time_eq(cookie_token.as_bytes(), submitted_token.as_bytes())
+    }
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// The CSRF token of the current request (and client),
+/// inserted by the [`CsrfService`] in the [`Context`].
+///
+/// Render it in forms (see [`CsrfLayer::form_field`])
+/// or expose it to scripts, to submit it with unsafe requests.
+pub struct CsrfToken(String);
+
+impl CsrfToken {
+    /// Returns the token as a string.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for CsrfToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+#[derive(Debug, Clone)]
+struct CsrfConfig {
+    cookie_name: String,
+    header_name: HeaderName,
+    form_field: Option<String>,
+    same_site: SameSite,
+    secure: bool,
+}
+
+impl Default for CsrfConfig {
+    fn default() -> Self {
+        Self {
+            cookie_name: DEFAULT_COOKIE_NAME.to_owned(),
+            header_name: HeaderName::from_static(DEFAULT_HEADER_NAME),
+            form_field: Some(DEFAULT_FORM_FIELD.to_owned()),
+            same_site: SameSite::default(),
+            secure: true,
+        }
+    }
+}
+
+/// Layer that applies the [`CsrfService`] middleware.
+///
+/// See the [module docs](self) for more details.
+pub struct CsrfLayer<T = RandomCsrfTokens> {
+    config: Arc<CsrfConfig>,
+    tokens: T,
+}
+
+impl<T: fmt::Debug> fmt::Debug for CsrfLayer<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CsrfLayer")
+            .field("config", &self.config)
+            .field("tokens", &self.tokens)
+            .finish()
+    }
+}
+
+impl<T: Clone> Clone for CsrfLayer<T> {
+    fn clone(&self) -> Self {
+        Self {
+            config: self.config.clone(),
+            tokens: self.tokens.clone(),
+        }
+    }
+}
+
+impl CsrfLayer {
+    /// Create a new [`CsrfLayer`], using random tokens.
+    pub fn new() -> Self {
+        Self::with_tokens(RandomCsrfTokens)
+    }
+}
+
+impl Default for CsrfLayer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> CsrfLayer<T> {
+    /// Create a new [`CsrfLayer`], using the given [`CsrfTokens`].
+    pub fn with_tokens(tokens: T) -> Self {
+        Self {
+            config: Arc::new(CsrfConfig::default()),
+            tokens,
+        }
+    }
+
+    /// Set the name of the cookie in which the token is stored.
+    ///
+    /// Defaults to `csrf_token`.
+    pub fn cookie_name(mut self, name: impl Into<String>) -> Self {
+        Arc::make_mut(&mut self.config).cookie_name = name.into();
+        self
+    }
+
+    /// Set the name of the cookie in which the token is stored.
+    ///
+    /// Defaults to `csrf_token`.
+    pub fn set_cookie_name(&mut self, name: impl Into<String>) -> &mut Self {
+        Arc::make_mut(&mut self.config).cookie_name = name.into();
+        self
+    }
+
+    /// Set the name of the header in which the token can be submitted.
+    ///
+    /// Defaults to `x-csrf-token`.
+    pub fn header_name(mut self, name: HeaderName) -> Self {
+        Arc::make_mut(&mut self.config).header_name = name;
+        self
+    }
+
+    /// Set the name of the header in which the token can be submitted.
+    ///
+    /// Defaults to `x-csrf-token`.
+    pub fn set_header_name(&mut self, name: HeaderName) -> &mut Self {
+        Arc::make_mut(&mut self.config).header_name = name;
+        self
+    }
+
+    /// Set the name of the (urlencoded) form field in which the token can be submitted,
+    /// or `None` to only accept the token in the header.
+    ///
+    /// Defaults to `csrf_token`.
+    pub fn form_field(mut self, name: Option<String>) -> Self {
+        Arc::make_mut(&mut self.config).form_field = name;
+        self
+    }
+
+    /// Set the name of the (urlencoded) form field in which the token can be submitted,
+    /// or `None` to only accept the token in the header.
+    ///
+    /// Defaults to `csrf_token`.
+    pub fn set_form_field(&mut self, name: Option<String>) -> &mut Self {
+        Arc::make_mut(&mut self.config).form_field = name;
+        self
+    }
+
+    /// Set the [`SameSite`] attribute of the cookie.
+    ///
+    /// Defaults to [`SameSite::Strict`].
+    pub fn same_site(mut self, same_site: SameSite) -> Self {
+        Arc::make_mut(&mut self.config).same_site = same_site;
+        self
+    }
+
+    /// Set the [`SameSite`] attribute of the cookie.
+    ///
+    /// Defaults to [`SameSite::Strict`].
+    pub fn set_same_site(&mut self, same_site: SameSite) -> &mut Self {
+        Arc::make_mut(&mut self.config).same_site = same_site;
+        self
+    }
+
+    /// Set whether the cookie is only to be sent over secure (https) connections.
+    ///
+    /// Defaults to `true`, only disable it for local development.
+    pub fn secure(mut self, secure: bool) -> Self {
+        Arc::make_mut(&mut self.config).secure = secure;
+        self
+    }
+
+    /// Set whether the cookie is only to be sent over secure (https) connections.
+    ///
+    /// Defaults to `true`, only disable it for local development.
+    pub fn set_secure(&mut self, secure: bool) -> &mut Self {
+        Arc::make_mut(&mut self.config).secure = secure;
+        self
+    }
+}
+
+impl<S, T: Clone> Layer<S> for CsrfLayer<T> {
+    type Service = CsrfService<S, T>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        CsrfService {
+            inner,
+            config: self.config.clone(),
+            tokens: self.tokens.clone(),
+        }
+    }
+}
+
+/// Middleware that protects against Cross-Site Request Forgery (CSRF).
+///
+/// See the [module docs](self) for more details.
+pub struct CsrfService<S, T = RandomCsrfTokens> {
+    inner: S,
+    config: Arc<CsrfConfig>,
+    tokens: T,
+}
+
+impl<S, T> CsrfService<S, T> {
+    define_inner_service_accessors!();
+}
+
+impl<S: fmt::Debug, T: fmt::Debug> fmt::Debug for CsrfService<S, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CsrfService")
+            .field("inner", &self.inner)
+            .field("config", &self.config)
+            .field("tokens", &self.tokens)
+            .finish()
+    }
+}
+
+impl<S: Clone, T: Clone> Clone for CsrfService<S, T> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            config: self.config.clone(),
+            tokens: self.tokens.clone(),
+        }
+    }
+}
+
+impl<S, T, State, ReqBody, ResBody> Service<State, Request<ReqBody>> for CsrfService<S, T>
+where
+    S: Service<State, Request<Body>, Response = Response<ResBody>>,
+    T: CsrfTokens,
+    State: Clone + Send + Sync + 'static,
+    ReqBody: http_body::Body<Data = Bytes, Error: Into<BoxError>> + Send + Sync + 'static,
+    ResBody: Default + Send + 'static,
+{
+    type Response = Response<ResBody>;
+    type Error = S::Error;
+
+    async fn serve(
+        &self,
+        mut ctx: Context<State>,
+        req: Request<ReqBody>,
+    ) -> Result<Self::Response, Self::Error> {
+        let cookie_token = req
+            .headers()
+            .typed_get::<Cookie>()
+            .and_then(|cookie| cookie.get(&self.config.cookie_name).map(ToOwned::to_owned))
+            .filter(|token| !token.is_empty());
+
+        let mut req = req.map(Body::new);
+
+        if !is_safe_method(req.method()) {
+            let Some(cookie_token) = cookie_token.as_deref() else {
+                tracing::debug!("csrf: reject unsafe request without csrf cookie");
+                return Ok(forbidden());
+            };
+            if is_cross_site(&req) {
+                tracing::debug!("csrf: reject unsafe cross-site request");
+                return Ok(forbidden());
+            }
+            let submitted_token;
+            (req, submitted_token) = self.submitted_token(req).await;
+            match submitted_token {
+                Some(submitted) if self.tokens.validate_token(cookie_token, &submitted) => (),
+                _ => {
+                    tracing::debug!("csrf: reject unsafe request without valid csrf token");
+                    return Ok(forbidden());
+                }
+            }
+        }
+
+        let (token, is_new) = match cookie_token {
+            Some(token) => (token, false),
+            None => (self.tokens.generate_token(), true),
+        };
+        ctx.insert(CsrfToken(token.clone()));
+
+        let mut res = self.inner.serve(ctx, req).await?;
+
+        if is_new {
+            match self.cookie_header_value(&token) {
+                Some(value) => {
+                    res.headers_mut().append(header::SET_COOKIE, value);
+                }
+                None => tracing::error!("csrf: generated token is not a valid cookie value"),
+            }
+        }
+
+        Ok(res)
+    }
+}
+
+impl<S, T> CsrfService<S, T> {
+    /// Returns the token submitted in the header or form field,
+    /// together with the request (of which the body might have been buffered).
+    async fn submitted_token(&self, req: Request) -> (Request, Option<String>) {
+        if let Some(token) = req
+            .headers()
+            .get(&self.config.header_name)
+            .and_then(|v| v.to_str().ok())
+        {
+            let token = token.to_owned();
+            return (req, Some(token));
+        }
+
+        let Some(field) = self.config.form_field.as_deref() else {
+            return (req, None);
+        };
+        let is_form = req
+            .headers()
+            .typed_get::<ContentType>()
+            .is_some_and(|content_type| {
+                Mime::from(content_type).essence_str()
+                    == mime::APPLICATION_WWW_FORM_URLENCODED.essence_str()
+            });
+        if !is_form {
+            return (req, None);
+        }
+
+        let (parts, body) = req.into_parts();
+        let bytes = match Limited::new(body, MAX_FORM_SIZE).collect().await {
+            Ok(collected) => collected.to_bytes(),
+            Err(err) => {
+                tracing::debug!(error = %err, "csrf: failed to read form body");
+                return (Request::from_parts(parts, Body::empty()), None);
+            }
+        };
+        let token = serde_html_form::from_bytes::<Vec<(String, String)>>(&bytes)
+            .ok()
+            .and_then(|fields| {
+                fields
+                    .into_iter()
+                    .find_map(|(name, value)| (name == field).then_some(value))
+            });
+        (Request::from_parts(parts, Body::from(bytes)), token)
+    }
+
+    fn cookie_header_value(&self, token: &str) -> Option<HeaderValue> {
+        SetCookie {
+            name: &self.config.cookie_name,
+            value: token,
+            max_age: None,
+            http_only: false,
+            same_site: self.config.same_site,
+            secure: self.config.secure,
+        }
+        .header_value()
+    }
+}
+
+fn is_safe_method(method: &Method) -> bool {
+    matches!(
+        *method,
+        Method::GET | Method::HEAD | Method::OPTIONS | Method::TRACE
+    )
+}
+
+fn is_cross_site<B>(req: &Request<B>) -> bool {
+    req.headers()
+        .get("sec-fetch-site")
+        .is_some_and(|v| v.as_bytes().eq_ignore_ascii_case(b"cross-site"))
+}
+
+fn forbidden<B: Default>() -> Response<B> {
+    let mut res = Response::new(B::default());
+    *res.status_mut() = StatusCode::FORBIDDEN;
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rama_core::service::service_fn;
+    use std::convert::Infallible;
+
+    fn svc() -> impl Service<(), Request, Response = Response, Error = Infallible> {
+        CsrfLayer::new().layer(service_fn(|ctx: Context<()>, req: Request| async move {
+            let token = ctx.get::<CsrfToken>().unwrap().to_string();
+            let body = req.into_body().collect().await.unwrap().to_bytes();
+            assert!(!token.is_empty());
+            Ok(Response::new(Body::from(body)))
+        }))
+    }
+
+    async fn token_cookie(
+        svc: &impl Service<(), Request, Response = Response, Error = Infallible>,
+    ) -> String {
+        let res = svc
+            .serve(Context::default(), Request::new(Body::empty()))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let cookie = res.headers()[header::SET_COOKIE].to_str().unwrap();
+        assert!(cookie.contains("SameSite=Strict"));
+        assert!(cookie.contains("Secure"));
+        cookie
+            .split(';')
+            .next()
+            .unwrap()
+            .strip_prefix("csrf_token=")
+            .unwrap()
+            .to_owned()
+    }
+
+    #[tokio::test]
+    async fn test_csrf_header() {
+        let svc = svc();
+        let token = token_cookie(&svc).await;
+
+        let req = Request::builder()
+            .method(Method::POST)
+            .header(header::COOKIE, format!("csrf_token={token}"))
+            .header("x-csrf-token", &token)
+            .body(Body::from("hello"))
+            .unwrap();
+        let res = svc.serve(Context::default(), req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert!(!res.headers().contains_key(header::SET_COOKIE));
+        let body = res.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, "hello");
+
+        for (cookie, submitted) in [
+            (None, Some(token.as_str())),
+            (Some(token.as_str()), None),
+            (Some(token.as_str()), Some("foo")),
+        ] {
+            let mut req = Request::builder().method(Method::DELETE);
+            if let Some(cookie) = cookie {
+                req = req.header(header::COOKIE, format!("csrf_token={cookie}"));
+            }
+            if let Some(submitted) = submitted {
+                req = req.header("x-csrf-token", submitted);
+            }
+            let res = svc
+                .serve(Context::default(), req.body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+            assert_eq!(
+                res.status(),
+                StatusCode::FORBIDDEN,
+                "{cookie:?} {submitted:?}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_csrf_form_field() {
+        let svc = svc();
+        let token = token_cookie(&svc).await;
+
+        let body = format!("name=john&csrf_token={token}");
+        let req = Request::builder()
+            .method(Method::POST)
+            .header(header::COOKIE, format!("other=1; csrf_token={token}"))
+            .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+            .body(Body::from(body.clone()))
+            .unwrap();
+        let res = svc.serve(Context::default(), req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let res_body = res.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(res_body, body);
+
+        let req = Request::builder()
+            .method(Method::POST)
+            .header(header::COOKIE, format!("csrf_token={token}"))
+            .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+            .body(Body::from("name=john&csrf_token=wrong"))
+            .unwrap();
+        let res = svc.serve(Context::default(), req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn test_csrf_cross_site() {
+        let svc = svc();
+        let token = token_cookie(&svc).await;
+
+        let req = Request::builder()
+            .method(Method::POST)
+            .header(header::COOKIE, format!("csrf_token={token}"))
+            .header("x-csrf-token", &token)
+            .header("sec-fetch-site", "cross-site")
+            .body(Body::empty())
+            .unwrap();
+        let res = svc.serve(Context::default(), req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+    }
+}
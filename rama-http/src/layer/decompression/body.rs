@@ -7,7 +7,9 @@ use crate::layer::util::compression::{
 use crate::HeaderMap;
 use rama_core::error::BoxError;
 
+use crate::dep::http_body_util::combinators::UnsyncBoxBody;
 use async_compression::tokio::bufread::BrotliDecoder;
+use async_compression::tokio::bufread::DeflateDecoder;
 use async_compression::tokio::bufread::GzipDecoder;
 use async_compression::tokio::bufread::ZlibDecoder;
 use async_compression::tokio::bufread::ZstdDecoder;
@@ -16,6 +18,7 @@ use futures_lite::ready;
 use pin_project_lite::pin_project;
 use std::task::Context;
 use std::{io, marker::PhantomData, pin::Pin, task::Poll};
+use sync_wrapper::SyncWrapper;
 use tokio_util::io::StreamReader;
 
 pin_project! {
@@ -81,6 +84,12 @@ pin_project! {
             #[pin]
             inner: ZstdBody<B>,
         },
+        // multiple encodings (e.g. `gzip, zstd`), decoded in reverse order,
+        // or a leniently decoded encoding
+        Chained {
+            #[pin]
+            inner: SyncWrapper<UnsyncBoxBody<Bytes, BoxError>>,
+        },
         Identity {
             #[pin]
             inner: B,
@@ -105,6 +114,12 @@ impl<B: Body> BodyInner<B> {
         Self::Zstd { inner }
     }
 
+    pub(crate) fn chained(inner: UnsyncBoxBody<Bytes, BoxError>) -> Self {
+        Self::Chained {
+            inner: SyncWrapper::new(inner),
+        }
+    }
+
     pub(crate) fn identity(inner: B) -> Self {
         Self::Identity { inner }
     }
@@ -126,6 +141,7 @@ where
             BodyInnerProj::Deflate { inner } => inner.poll_frame(cx),
             BodyInnerProj::Brotli { inner } => inner.poll_frame(cx),
             BodyInnerProj::Zstd { inner } => inner.poll_frame(cx),
+            BodyInnerProj::Chained { inner } => inner.get_pin_mut().poll_frame(cx),
             BodyInnerProj::Identity { inner } => match ready!(inner.poll_frame(cx)) {
                 Some(Ok(frame)) => {
                     let frame = frame.map_data(|mut buf| buf.copy_to_bytes(buf.remaining()));
@@ -179,6 +195,22 @@ where
     }
 }
 
+impl<B> DecorateAsyncRead for DeflateDecoder<B>
+where
+    B: Body,
+{
+    type Input = AsyncReadBody<B>;
+    type Output = DeflateDecoder<Self::Input>;
+
+    fn apply(input: Self::Input, _quality: CompressionLevel) -> Self::Output {
+        DeflateDecoder::new(input)
+    }
+
+    fn get_pin_mut(pinned: Pin<&mut Self::Output>) -> Pin<&mut Self::Input> {
+        pinned.get_pin_mut()
+    }
+}
+
 impl<B> DecorateAsyncRead for BrotliDecoder<B>
 where
     B: Body,
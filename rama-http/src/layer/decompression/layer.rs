@@ -11,6 +11,7 @@ use rama_core::Layer;
 #[derive(Debug, Default, Clone)]
 pub struct DecompressionLayer {
     accept: AcceptEncoding,
+    lenient: bool,
 }
 
 impl<S> Layer<S> for DecompressionLayer {
@@ -20,6 +21,7 @@ impl<S> Layer<S> for DecompressionLayer {
         Decompression {
             inner: service,
             accept: self.accept,
+            lenient: self.lenient,
         }
     }
 }
@@ -77,4 +79,26 @@ impl DecompressionLayer {
         self.accept.set_zstd(enable);
         self
     }
+
+    /// Sets whether to leniently decode response bodies
+    /// which do not strictly follow their `Content-Encoding`,
+    /// as sent by some servers in the wild:
+    ///
+    /// - `deflate` bodies which are a raw deflate stream, missing the zlib header;
+    /// - `gzip` bodies which were (accidentally) gzip encoded twice.
+    ///
+    /// Disabled by default.
+    pub fn lenient(mut self, enable: bool) -> Self {
+        self.lenient = enable;
+        self
+    }
+
+    /// Sets whether to leniently decode response bodies
+    /// which do not strictly follow their `Content-Encoding`.
+    ///
+    /// See [`Self::lenient`] for more details.
+    pub fn set_lenient(&mut self, enable: bool) -> &mut Self {
+        self.lenient = enable;
+        self
+    }
 }
@@ -0,0 +1,173 @@
+//! Lenient decoding of response bodies which do not strictly
+//! follow the encoding advertised in their `Content-Encoding` header.
+
+use super::body::{BodyInner, DecompressionBody};
+use crate::dep::http_body::{Body, Frame, SizeHint};
+use crate::dep::http_body_util::combinators::UnsyncBoxBody;
+use crate::layer::util::compression::{CompressionLevel, WrapBody};
+use async_compression::tokio::bufread::{DeflateDecoder, GzipDecoder, ZlibDecoder};
+use bytes::{Bytes, BytesMut};
+use rama_core::error::BoxError;
+use std::{
+    collections::VecDeque,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+type BoxBody = UnsyncBoxBody<Bytes, BoxError>;
+
+/// Amount of bytes required to recognise a zlib header or gzip magic bytes.
+const SNIFF_LEN: usize = 3;
+
+#[derive(Debug, Clone, Copy)]
+enum Sniff {
+    /// `deflate` encoded body, which is either a zlib stream
+    /// (as it should be) or a raw deflate stream (as sent by some servers).
+    Deflate,
+    /// Already gzip decoded body, which might be gzip encoded once more.
+    Gzip,
+}
+
+/// Body which buffers the first bytes of the inner body,
+/// in order to decide how it has to be decoded.
+pub(super) struct LenientBody {
+    sniffing: Option<(BoxBody, BytesMut)>,
+    sniff: Sniff,
+    decoded: Option<BoxBody>,
+}
+
+impl LenientBody {
+    /// Decode a `deflate` body, tolerating raw deflate streams without zlib header.
+    pub(super) fn deflate(body: BoxBody) -> Self {
+        Self::new(body, Sniff::Deflate)
+    }
+
+    /// Decode a `gzip` body, tolerating bodies which were gzip encoded twice.
+    pub(super) fn gzip(body: BoxBody) -> Self {
+        let body = UnsyncBoxBody::new(WrapBody::<GzipDecoder<BoxBody>>::new(
+            body,
+            CompressionLevel::default(),
+        ));
+        Self::new(body, Sniff::Gzip)
+    }
+
+    fn new(body: BoxBody, sniff: Sniff) -> Self {
+        Self {
+            sniffing: Some((body, BytesMut::new())),
+            sniff,
+            decoded: None,
+        }
+    }
+
+    fn decode(&self, prefix: &[u8], body: PrefixedBody) -> BoxBody {
+        match self.sniff {
+            Sniff::Deflate if prefix.len() >= 2 && !is_zlib_header(prefix) => UnsyncBoxBody::new(
+                WrapBody::<DeflateDecoder<PrefixedBody>>::new(body, CompressionLevel::default()),
+            ),
+            Sniff::Deflate => UnsyncBoxBody::new(WrapBody::<ZlibDecoder<PrefixedBody>>::new(
+                body,
+                CompressionLevel::default(),
+            )),
+            Sniff::Gzip if prefix.starts_with(&[0x1f, 0x8b, 0x08]) => UnsyncBoxBody::new(
+                WrapBody::<GzipDecoder<PrefixedBody>>::new(body, CompressionLevel::default()),
+            ),
+            Sniff::Gzip => UnsyncBoxBody::new(body),
+        }
+    }
+}
+
+impl Body for LenientBody {
+    type Data = Bytes;
+    type Error = BoxError;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let this = &mut *self;
+        loop {
+            if let Some(decoded) = this.decoded.as_mut() {
+                return Pin::new(decoded).poll_frame(cx);
+            }
+
+            let (inner, buf) = this
+                .sniffing
+                .as_mut()
+                .expect("sniffing state as long as not decoded");
+            let mut frames = VecDeque::new();
+            let ended = match std::task::ready!(Pin::new(&mut *inner).poll_frame(cx)) {
+                Some(Ok(frame)) => match frame.into_data() {
+                    Ok(data) => {
+                        buf.extend_from_slice(&data);
+                        if buf.len() < SNIFF_LEN {
+                            continue;
+                        }
+                        false
+                    }
+                    Err(frame) => {
+                        frames.push_back(frame);
+                        false
+                    }
+                },
+                Some(Err(err)) => return Poll::Ready(Some(Err(err))),
+                None => true,
+            };
+
+            let (inner, buf) = this.sniffing.take().expect("sniffing state");
+            let prefix = buf.freeze();
+            if !prefix.is_empty() {
+                frames.push_front(Frame::data(prefix.clone()));
+            }
+            let body = PrefixedBody {
+                frames,
+                inner: (!ended).then_some(inner),
+            };
+            this.decoded = Some(this.decode(&prefix, body));
+        }
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        SizeHint::default()
+    }
+}
+
+/// Returns `true` if the bytes start with a valid zlib header,
+/// as defined in [RFC 1950](https://www.rfc-editor.org/rfc/rfc1950).
+fn is_zlib_header(bytes: &[u8]) -> bool {
+    let (cmf, flg) = (bytes[0], bytes[1]);
+    cmf & 0x0f == 8 && cmf >> 4 <= 7 && ((u16::from(cmf) << 8) | u16::from(flg)) % 31 == 0
+}
+
+/// Body which first yields the frames already read from the inner body.
+struct PrefixedBody {
+    frames: VecDeque<Frame<Bytes>>,
+    inner: Option<BoxBody>,
+}
+
+impl Body for PrefixedBody {
+    type Data = Bytes;
+    type Error = BoxError;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        if let Some(frame) = self.frames.pop_front() {
+            return Poll::Ready(Some(Ok(frame)));
+        }
+        match self.inner.as_mut() {
+            Some(inner) => Pin::new(inner).poll_frame(cx),
+            None => Poll::Ready(None),
+        }
+    }
+}
+
+/// Wrap the body into a [`DecompressionBody`] which
+/// (leniently) decodes it using the given [`LenientBody`] constructor.
+pub(super) fn lenient<B>(body: B, decode: fn(BoxBody) -> LenientBody) -> DecompressionBody<B>
+where
+    B: Body<Data: Send + 'static, Error: Into<BoxError> + Send + 'static> + Send + 'static,
+{
+    let body = UnsyncBoxBody::new(DecompressionBody::new(BodyInner::identity(body)));
+    DecompressionBody::new(BodyInner::chained(UnsyncBoxBody::new(decode(body))))
+}
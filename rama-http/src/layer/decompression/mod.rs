@@ -99,6 +99,7 @@ mod request;
 
 mod body;
 mod layer;
+mod lenient;
 mod service;
 
 #[doc(inline)]
@@ -182,4 +183,149 @@ mod tests {
             .insert("content-encoding", "gzip".parse().unwrap());
         Ok(res)
     }
+
+    #[tokio::test]
+    async fn decompress_zstd() {
+        let client = Decompression::new(service_fn(|_req: Request| async {
+            let body = zstd::encode_all(&b"Hello, World!"[..], 0).unwrap();
+            Ok::<_, Infallible>(
+                Response::builder()
+                    .header("content-encoding", "zstd")
+                    .body(Body::from(body))
+                    .unwrap(),
+            )
+        }));
+
+        let req = Request::builder().body(Body::empty()).unwrap();
+        let res = client.serve(Context::default(), req).await.unwrap();
+        assert!(!res.headers().contains_key("content-encoding"));
+
+        let body = res.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(&body[..], b"Hello, World!");
+    }
+
+    #[tokio::test]
+    async fn decompress_multiple_encodings() {
+        let client = Decompression::new(service_fn(|_req: Request| async {
+            // first gzip, then zstd
+            let mut enc = GzEncoder::new(Vec::new(), Default::default());
+            enc.write_all(b"Hello, World!").unwrap();
+            let body = zstd::encode_all(&enc.finish().unwrap()[..], 0).unwrap();
+            Ok::<_, Infallible>(
+                Response::builder()
+                    .header("content-encoding", "gzip, zstd")
+                    .header("content-length", body.len())
+                    .body(Body::from(body))
+                    .unwrap(),
+            )
+        }));
+
+        let req = Request::builder().body(Body::empty()).unwrap();
+        let res = client.serve(Context::default(), req).await.unwrap();
+        assert!(!res.headers().contains_key("content-encoding"));
+        assert!(!res.headers().contains_key("content-length"));
+
+        let body = res.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(&body[..], b"Hello, World!");
+    }
+
+    #[tokio::test]
+    async fn pass_through_unsupported_encodings() {
+        let client = Decompression::new(service_fn(|_req: Request| async {
+            Ok::<_, Infallible>(
+                Response::builder()
+                    .header("content-encoding", "gzip, unknown")
+                    .body(Body::from("foo"))
+                    .unwrap(),
+            )
+        }))
+        .zstd(false);
+
+        let req = Request::builder().body(Body::empty()).unwrap();
+        let res = client.serve(Context::default(), req).await.unwrap();
+        assert_eq!(res.headers()["content-encoding"], "gzip, unknown");
+
+        let body = res.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(&body[..], b"foo");
+    }
+
+    #[tokio::test]
+    async fn decompress_lenient_raw_deflate() {
+        let client = Decompression::new(service_fn(|_req: Request| async {
+            let mut enc = flate2::write::DeflateEncoder::new(Vec::new(), Default::default());
+            enc.write_all(b"Hello, World!").unwrap();
+            Ok::<_, Infallible>(
+                Response::builder()
+                    .header("content-encoding", "deflate")
+                    .body(Body::from(enc.finish().unwrap()))
+                    .unwrap(),
+            )
+        }));
+
+        let req = Request::builder().body(Body::empty()).unwrap();
+        let res = client.serve(Context::default(), req).await.unwrap();
+        assert!(res.into_body().collect().await.is_err());
+
+        let client = client.lenient(true);
+        for _ in 0..2 {
+            let req = Request::builder().body(Body::empty()).unwrap();
+            let res = client.serve(Context::default(), req).await.unwrap();
+            let body = res.into_body().collect().await.unwrap().to_bytes();
+            assert_eq!(&body[..], b"Hello, World!");
+        }
+    }
+
+    #[tokio::test]
+    async fn decompress_lenient_zlib_deflate() {
+        let client = Decompression::new(service_fn(|_req: Request| async {
+            let mut enc = flate2::write::ZlibEncoder::new(Vec::new(), Default::default());
+            enc.write_all(b"Hello, World!").unwrap();
+            Ok::<_, Infallible>(
+                Response::builder()
+                    .header("content-encoding", "deflate")
+                    .body(Body::from(enc.finish().unwrap()))
+                    .unwrap(),
+            )
+        }))
+        .lenient(true);
+
+        let req = Request::builder().body(Body::empty()).unwrap();
+        let res = client.serve(Context::default(), req).await.unwrap();
+        let body = res.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(&body[..], b"Hello, World!");
+    }
+
+    #[tokio::test]
+    async fn decompress_lenient_double_gzip() {
+        let client = Decompression::new(service_fn(|_req: Request| async {
+            let mut enc = GzEncoder::new(Vec::new(), Default::default());
+            enc.write_all(b"Hello, World!").unwrap();
+            let mut enc2 = GzEncoder::new(Vec::new(), Default::default());
+            enc2.write_all(&enc.finish().unwrap()).unwrap();
+            Ok::<_, Infallible>(
+                Response::builder()
+                    .header("content-encoding", "gzip")
+                    .body(Body::from(enc2.finish().unwrap()))
+                    .unwrap(),
+            )
+        }));
+
+        let req = Request::builder().body(Body::empty()).unwrap();
+        let res = client.serve(Context::default(), req).await.unwrap();
+        let body = res.into_body().collect().await.unwrap().to_bytes();
+        assert_ne!(&body[..], b"Hello, World!");
+
+        let client = client.lenient(true);
+        let req = Request::builder().body(Body::empty()).unwrap();
+        let res = client.serve(Context::default(), req).await.unwrap();
+        let body = res.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(&body[..], b"Hello, World!");
+
+        // single gzip encoded bodies are unaffected
+        let client = Decompression::new(service_fn(handle_multi_gz)).lenient(true);
+        let req = Request::builder().body(Body::empty()).unwrap();
+        let res = client.serve(Context::default(), req).await.unwrap();
+        let body = res.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(&body[..], b"Hello, World!");
+    }
 }
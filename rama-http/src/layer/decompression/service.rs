@@ -1,16 +1,17 @@
 use std::fmt;
 
-use super::{body::BodyInner, DecompressionBody};
+use super::{body::BodyInner, lenient::LenientBody, DecompressionBody};
 use crate::dep::http_body::Body;
+use crate::dep::http_body_util::combinators::UnsyncBoxBody;
 use crate::layer::util::{
     compression::{AcceptEncoding, CompressionLevel, WrapBody},
-    content_encoding::SupportedEncodings,
+    content_encoding::Encoding,
 };
 use crate::{
     header::{self, ACCEPT_ENCODING},
-    Request, Response,
+    HeaderMap, Request, Response,
 };
-use rama_core::{Context, Service};
+use rama_core::{error::BoxError, Context, Service};
 use rama_utils::macros::define_inner_service_accessors;
 
 /// Decompresses response bodies of the underlying service.
@@ -22,6 +23,7 @@ use rama_utils::macros::define_inner_service_accessors;
 pub struct Decompression<S> {
     pub(crate) inner: S,
     pub(crate) accept: AcceptEncoding,
+    pub(crate) lenient: bool,
 }
 
 impl<S> Decompression<S> {
@@ -30,6 +32,7 @@ impl<S> Decompression<S> {
         Self {
             inner: service,
             accept: AcceptEncoding::default(),
+            lenient: false,
         }
     }
 
@@ -82,6 +85,28 @@ impl<S> Decompression<S> {
         self.accept.set_zstd(enable);
         self
     }
+
+    /// Sets whether to leniently decode response bodies
+    /// which do not strictly follow their `Content-Encoding`.
+    ///
+    /// See [`DecompressionLayer::lenient`] for more details.
+    ///
+    /// [`DecompressionLayer::lenient`]: super::DecompressionLayer::lenient
+    pub fn lenient(mut self, enable: bool) -> Self {
+        self.lenient = enable;
+        self
+    }
+
+    /// Sets whether to leniently decode response bodies
+    /// which do not strictly follow their `Content-Encoding`.
+    ///
+    /// See [`DecompressionLayer::lenient`] for more details.
+    ///
+    /// [`DecompressionLayer::lenient`]: super::DecompressionLayer::lenient
+    pub fn set_lenient(&mut self, enable: bool) -> &mut Self {
+        self.lenient = enable;
+        self
+    }
 }
 
 impl<S: fmt::Debug> fmt::Debug for Decompression<S> {
@@ -89,6 +114,7 @@ impl<S: fmt::Debug> fmt::Debug for Decompression<S> {
         f.debug_struct("Decompression")
             .field("inner", &self.inner)
             .field("accept", &self.accept)
+            .field("lenient", &self.lenient)
             .finish()
     }
 }
@@ -98,6 +124,7 @@ impl<S: Clone> Clone for Decompression<S> {
         Decompression {
             inner: self.inner.clone(),
             accept: self.accept,
+            lenient: self.lenient,
         }
     }
 }
@@ -107,7 +134,7 @@ where
     S: Service<State, Request<ReqBody>, Response = Response<ResBody>>,
     State: Clone + Send + Sync + 'static,
     ReqBody: Send + 'static,
-    ResBody: Body<Data: Send + 'static, Error: Send + 'static> + Send + 'static,
+    ResBody: Body<Data: Send + 'static, Error: Into<BoxError> + Send + 'static> + Send + 'static,
 {
     type Response = Response<DecompressionBody<ResBody>>;
     type Error = S::Error;
@@ -127,41 +154,74 @@ where
 
         let (mut parts, body) = res.into_parts();
 
-        let res =
-            if let header::Entry::Occupied(entry) = parts.headers.entry(header::CONTENT_ENCODING) {
-                let body = match entry.get().as_bytes() {
-                    b"gzip" if self.accept.gzip() => DecompressionBody::new(BodyInner::gzip(
-                        WrapBody::new(body, CompressionLevel::default()),
-                    )),
-
-                    b"deflate" if self.accept.deflate() => DecompressionBody::new(
-                        BodyInner::deflate(WrapBody::new(body, CompressionLevel::default())),
-                    ),
-
-                    b"br" if self.accept.br() => DecompressionBody::new(BodyInner::brotli(
-                        WrapBody::new(body, CompressionLevel::default()),
-                    )),
-
-                    b"zstd" if self.accept.zstd() => DecompressionBody::new(BodyInner::zstd(
-                        WrapBody::new(body, CompressionLevel::default()),
-                    )),
-
-                    _ => {
-                        return Ok(Response::from_parts(
-                            parts,
-                            DecompressionBody::new(BodyInner::identity(body)),
-                        ))
-                    }
-                };
-
-                entry.remove();
-                parts.headers.remove(header::CONTENT_LENGTH);
-
-                Response::from_parts(parts, body)
-            } else {
-                Response::from_parts(parts, DecompressionBody::new(BodyInner::identity(body)))
-            };
+        let Some(encodings) = self.response_encodings(&parts.headers) else {
+            // unsupported encoding(s): pass the body through as-is
+            return Ok(Response::from_parts(
+                parts,
+                DecompressionBody::new(BodyInner::identity(body)),
+            ));
+        };
+
+        let body = match encodings.as_slice() {
+            [] => DecompressionBody::new(BodyInner::identity(body)),
+            [encoding] => decompress(body, *encoding, self.lenient),
+            _ => {
+                // encodings are listed in the order they were applied,
+                // so they have to be decoded in reverse order
+                let body = encodings.iter().rev().fold(
+                    UnsyncBoxBody::new(DecompressionBody::new(BodyInner::identity(body))),
+                    |body, encoding| UnsyncBoxBody::new(decompress(body, *encoding, self.lenient)),
+                );
+                DecompressionBody::new(BodyInner::chained(body))
+            }
+        };
+
+        if !encodings.is_empty() {
+            parts.headers.remove(header::CONTENT_ENCODING);
+            parts.headers.remove(header::CONTENT_LENGTH);
+        }
 
+        let res = Response::from_parts(parts, body);
         Ok(res)
     }
 }
+
+impl<S> Decompression<S> {
+    /// Parse the `Content-Encoding` header(s) of a response into the encodings
+    /// in the order they were applied, returning `None` if any of them
+    /// is not supported (or accepted).
+    fn response_encodings(&self, headers: &HeaderMap) -> Option<Vec<Encoding>> {
+        let mut encodings = Vec::new();
+        for value in headers.get_all(header::CONTENT_ENCODING) {
+            for s in value.to_str().ok()?.split(',').map(str::trim) {
+                match Encoding::parse(s, self.accept)? {
+                    Encoding::Identity => (),
+                    encoding => encodings.push(encoding),
+                }
+            }
+        }
+        Some(encodings)
+    }
+}
+
+fn decompress<B>(body: B, encoding: Encoding, lenient: bool) -> DecompressionBody<B>
+where
+    B: Body<Data: Send + 'static, Error: Into<BoxError> + Send + 'static> + Send + 'static,
+{
+    if lenient {
+        match encoding {
+            Encoding::Gzip => return super::lenient::lenient(body, LenientBody::gzip),
+            Encoding::Deflate => return super::lenient::lenient(body, LenientBody::deflate),
+            Encoding::Brotli | Encoding::Zstd | Encoding::Identity => (),
+        }
+    }
+
+    let inner = match encoding {
+        Encoding::Gzip => BodyInner::gzip(WrapBody::new(body, CompressionLevel::default())),
+        Encoding::Deflate => BodyInner::deflate(WrapBody::new(body, CompressionLevel::default())),
+        Encoding::Brotli => BodyInner::brotli(WrapBody::new(body, CompressionLevel::default())),
+        Encoding::Zstd => BodyInner::zstd(WrapBody::new(body, CompressionLevel::default())),
+        Encoding::Identity => BodyInner::identity(body),
+    };
+    DecompressionBody::new(inner)
+}
@@ -0,0 +1,364 @@
+//! Emit `103 Early Hints` with preload links for matching routes.
+//!
+//! The [`EarlyHintsLayer`] allows a client (e.g. a browser) to start preloading
+//! resources (stylesheets, scripts, fonts, ...) while the upstream is still
+//! preparing the final response, which is what HTTP/2 server push was used for.
+//!
+//! For each request matching one of the configured routes, the `Link` values of
+//! that route are sent as a `103 Early Hints` interim response, using the
+//! [`EarlyHintsSender`] found in the request extensions. Such a sender is inserted
+//! by the transport (or any service in front of it) which supports interim responses.
+//!
+//! The same `Link` values are also added to the final (successful) response,
+//! such that a CDN or reverse proxy in front of the server can turn them into
+//! `103 Early Hints` of its own, and clients without support for interim responses
+//! still get to preload. This can be disabled using [`EarlyHintsLayer::link_header`].
+//!
+//! Handlers can also send early hints of their own, using the [`EarlyHintsSender`]
+//! found in the request extensions. The http client on its turn exposes the
+//! interim responses received from the upstream as [`InformationalResponses`]
+//! in the response extensions, and forwards the `103 Early Hints` it receives
+//! to the [`EarlyHintsSender`] of the request, allowing a proxy to pass them on.
+//!
+//! [`InformationalResponses`]: crate::InformationalResponses
+//!
+//! # Example
+//!
+//! ```
+//! use rama_http::layer::early_hints::{EarlyHintsLayer, EarlyHintsSender};
+//! use rama_http::{header, Body, HeaderValue, Request, Response};
+//! use rama_core::service::service_fn;
+//! use rama_core::{Context, Layer, Service};
+//! use std::convert::Infallible;
+//! use std::sync::{Arc, Mutex};
+//!
+//! # #[tokio::main]
+//! # async fn main() {
+//! let svc = EarlyHintsLayer::new()
+//!     .with_hints(
+//!         "/",
+//!         [
+//!             HeaderValue::from_static("</style.css>; rel=preload; as=style"),
+//!             HeaderValue::from_static("</app.js>; rel=preload; as=script"),
+//!         ],
+//!     )
+//!     .layer(service_fn(|_req: Request| async move {
+//!         Ok::<_, Infallible>(Response::new(Body::from("<html>...</html>")))
+//!     }));
+//!
+//! // normally inserted by a transport which supports interim responses
+//! let hints = Arc::new(Mutex::new(Vec::new()));
+//! let sender = EarlyHintsSender::new({
+//!     let hints = hints.clone();
+//!     move |headers| hints.lock().unwrap().push(headers)
+//! });
+//!
+//! let mut req = Request::new(Body::empty());
+//! req.extensions_mut().insert(sender);
+//! let resp = svc.serve(Context::default(), req).await.unwrap();
+//!
+//! assert_eq!(hints.lock().unwrap()[0].get_all(header::LINK).iter().count(), 2);
+//! assert_eq!(resp.headers().get_all(header::LINK).iter().count(), 2);
+//! # }
+//! ```
+
+use crate::matcher::PathMatcher;
+use crate::{header, HeaderValue, Request, Response};
+use rama_core::{Context, Layer, Service};
+use rama_utils::macros::define_inner_service_accessors;
+use std::{fmt, sync::Arc};
+
+#[doc(inline)]
+pub use rama_http_types::EarlyHintsSender;
+
+#[derive(Debug, Clone)]
+struct EarlyHintsRoute {
+    matcher: PathMatcher,
+    links: Vec<HeaderValue>,
+}
+
+#[derive(Debug, Clone)]
+/// Layer that applies the [`EarlyHintsService`] middleware.
+///
+/// See the [module docs](self) for more details.
+pub struct EarlyHintsLayer {
+    routes: Arc<Vec<EarlyHintsRoute>>,
+    link_header: bool,
+}
+
+impl Default for EarlyHintsLayer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EarlyHintsLayer {
+    /// Create a new [`EarlyHintsLayer`] without any routes.
+    pub fn new() -> Self {
+        Self {
+            routes: Default::default(),
+            link_header: true,
+        }
+    }
+
+    /// Add the given `Link` values (e.g. `</style.css>; rel=preload; as=style`)
+    /// as early hints for all requests of which the path matches the given
+    /// path pattern (as used by [`PathMatcher`]).
+    ///
+    /// The hints of all matching routes are combined.
+    pub fn with_hints(
+        mut self,
+        path: impl AsRef<str>,
+        links: impl IntoIterator<Item = HeaderValue>,
+    ) -> Self {
+        self.set_hints(path, links);
+        self
+    }
+
+    /// Add the given `Link` values (e.g. `</style.css>; rel=preload; as=style`)
+    /// as early hints for all requests of which the path matches the given
+    /// path pattern (as used by [`PathMatcher`]).
+    ///
+    /// The hints of all matching routes are combined.
+    pub fn set_hints(
+        &mut self,
+        path: impl AsRef<str>,
+        links: impl IntoIterator<Item = HeaderValue>,
+    ) -> &mut Self {
+        Arc::make_mut(&mut self.routes).push(EarlyHintsRoute {
+            matcher: PathMatcher::new(path),
+            links: links.into_iter().collect(),
+        });
+        self
+    }
+
+    /// Define whether or not the `Link` values are also added
+    /// to the final (successful) response.
+    ///
+    /// Enabled by default.
+    pub fn link_header(mut self, enabled: bool) -> Self {
+        self.link_header = enabled;
+        self
+    }
+
+    /// Define whether or not the `Link` values are also added
+    /// to the final (successful) response.
+    ///
+    /// Enabled by default.
+    pub fn set_link_header(&mut self, enabled: bool) -> &mut Self {
+        self.link_header = enabled;
+        self
+    }
+}
+
+impl<S> Layer<S> for EarlyHintsLayer {
+    type Service = EarlyHintsService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        EarlyHintsService {
+            inner,
+            routes: self.routes.clone(),
+            link_header: self.link_header,
+        }
+    }
+}
+
+/// Middleware which emits `103 Early Hints` with preload links for matching routes.
+///
+/// See the [module docs](self) for more details.
+pub struct EarlyHintsService<S> {
+    inner: S,
+    routes: Arc<Vec<EarlyHintsRoute>>,
+    link_header: bool,
+}
+
+impl<S> EarlyHintsService<S> {
+    define_inner_service_accessors!();
+}
+
+impl<S: fmt::Debug> fmt::Debug for EarlyHintsService<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EarlyHintsService")
+            .field("inner", &self.inner)
+            .field("routes", &self.routes)
+            .field("link_header", &self.link_header)
+            .finish()
+    }
+}
+
+impl<S: Clone> Clone for EarlyHintsService<S> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            routes: self.routes.clone(),
+            link_header: self.link_header,
+        }
+    }
+}
+
+impl<ReqBody, ResBody, S, State> Service<State, Request<ReqBody>> for EarlyHintsService<S>
+where
+    S: Service<State, Request<ReqBody>, Response = Response<ResBody>>,
+    State: Clone + Send + Sync + 'static,
+    ReqBody: Send + 'static,
+    ResBody: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+
+    async fn serve(
+        &self,
+        ctx: Context<State>,
+        req: Request<ReqBody>,
+    ) -> Result<Self::Response, Self::Error> {
+        let links: Vec<HeaderValue> = self
+            .routes
+            .iter()
+            .filter(|route| route.matcher.matches_path(req.uri().path()).is_some())
+            .flat_map(|route| route.links.iter().cloned())
+            .collect();
+
+        if links.is_empty() {
+            return self.inner.serve(ctx, req).await;
+        }
+
+        if let Some(sender) = req.extensions().get::<EarlyHintsSender>() {
+            tracing::trace!(links = links.len(), "early hints: send 103 Early Hints");
+            sender.send_links(links.iter().cloned());
+        }
+
+        let mut res = self.inner.serve(ctx, req).await?;
+
+        if self.link_header && res.status().is_success() {
+            for link in links {
+                if !res
+                    .headers()
+                    .get_all(header::LINK)
+                    .iter()
+                    .any(|v| v == link)
+                {
+                    res.headers_mut().append(header::LINK, link);
+                }
+            }
+        }
+
+        Ok(res)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Body, HeaderMap, StatusCode};
+    use rama_core::service::service_fn;
+    use std::convert::Infallible;
+    use std::sync::Mutex;
+
+    fn svc(layer: EarlyHintsLayer) -> impl Service<(), Request, Response = Response> {
+        layer.layer(service_fn(|req: Request| async move {
+            let mut res = Response::new(Body::empty());
+            if req.uri().path() == "/missing" {
+                *res.status_mut() = StatusCode::NOT_FOUND;
+            }
+            if req.uri().path() == "/assets/page" {
+                res.headers_mut().insert(
+                    header::LINK,
+                    HeaderValue::from_static("</assets/app.js>; rel=preload; as=script"),
+                );
+            }
+            Ok::<_, Infallible>(res)
+        }))
+    }
+
+    async fn serve(
+        svc: &impl Service<(), Request, Response = Response>,
+        path: &str,
+    ) -> (Vec<HeaderMap>, Response) {
+        let hints = Arc::new(Mutex::new(Vec::new()));
+        let sender = EarlyHintsSender::new({
+            let hints = hints.clone();
+            move |headers| hints.lock().unwrap().push(headers)
+        });
+        let mut req = Request::builder().uri(path).body(Body::empty()).unwrap();
+        req.extensions_mut().insert(sender);
+        let res = svc.serve(Context::default(), req).await.ok().unwrap();
+        let hints = hints.lock().unwrap().clone();
+        (hints, res)
+    }
+
+    fn links(headers: &HeaderMap) -> Vec<&str> {
+        headers
+            .get_all(header::LINK)
+            .iter()
+            .map(|v| v.to_str().unwrap())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_early_hints() {
+        let svc = svc(EarlyHintsLayer::new()
+            .with_hints(
+                "/",
+                [HeaderValue::from_static(
+                    "</style.css>; rel=preload; as=style",
+                )],
+            )
+            .with_hints(
+                "/assets/*",
+                [HeaderValue::from_static(
+                    "</assets/app.js>; rel=preload; as=script",
+                )],
+            )
+            .with_hints(
+                "/missing",
+                [HeaderValue::from_static(
+                    "</style.css>; rel=preload; as=style",
+                )],
+            ));
+
+        let (hints, res) = serve(&svc, "/").await;
+        assert_eq!(hints.len(), 1);
+        assert_eq!(links(&hints[0]), ["</style.css>; rel=preload; as=style"]);
+        assert_eq!(
+            links(res.headers()),
+            ["</style.css>; rel=preload; as=style"]
+        );
+
+        // link already set by the inner service is not duplicated
+        let (hints, res) = serve(&svc, "/assets/page").await;
+        assert_eq!(
+            links(&hints[0]),
+            ["</assets/app.js>; rel=preload; as=script"]
+        );
+        assert_eq!(
+            links(res.headers()),
+            ["</assets/app.js>; rel=preload; as=script"]
+        );
+
+        // no links added to unsuccessful responses
+        let (hints, res) = serve(&svc, "/missing").await;
+        assert_eq!(hints.len(), 1);
+        assert!(links(res.headers()).is_empty());
+
+        // no hints for routes which do not match
+        let (hints, res) = serve(&svc, "/other").await;
+        assert!(hints.is_empty());
+        assert!(links(res.headers()).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_early_hints_without_link_header() {
+        let svc = svc(EarlyHintsLayer::new()
+            .with_hints(
+                "/",
+                [HeaderValue::from_static(
+                    "</style.css>; rel=preload; as=style",
+                )],
+            )
+            .link_header(false));
+
+        let (hints, res) = serve(&svc, "/").await;
+        assert_eq!(hints.len(), 1);
+        assert!(links(res.headers()).is_empty());
+    }
+}
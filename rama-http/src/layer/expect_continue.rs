@@ -0,0 +1,332 @@
+//! Middleware to control the handling of `Expect: 100-continue` requests.
+//!
+//! A client sending a request with an `Expect: 100-continue` header waits
+//! for a `100 Continue` interim response (or a timeout) prior to sending the request body,
+//! such that a server can reject (large) uploads without receiving their body.
+//!
+//! The rama http server sends the `100 Continue` response once the body of the request
+//! is first read. As such a handler decides by itself whether or not the client gets
+//! to continue, by reading the body or responding without reading it. The
+//! [`ExpectContinueLayer`] builds on top of that to:
+//!
+//! - reject requests with an expectation other than `100-continue`
+//!   using `417 Expectation Failed`, as required by [RFC 9110];
+//! - reject `100-continue` requests for which the configured predicate fails
+//!   using `417 Expectation Failed`, without reading their body;
+//! - in [`ExpectContinueMode::Auto`] mode: continue the accepted requests
+//!   prior to calling the inner service, by reading the first chunk of their body.
+//!
+//! [RFC 9110]: https://www.rfc-editor.org/rfc/rfc9110#section-10.1.1
+//!
+//! # Example
+//!
+//! ```
+//! use rama_http::layer::expect_continue::{ExpectContinueLayer, ExpectContinueMode};
+//! use rama_http::{header, Body, Request, Response, StatusCode};
+//! use rama_core::service::service_fn;
+//! use rama_core::{Context, Layer, Service};
+//! use std::convert::Infallible;
+//!
+//! # #[tokio::main]
+//! # async fn main() {
+//! let service = ExpectContinueLayer::new(ExpectContinueMode::Handler)
+//!     // only uploads to /upload are allowed to continue
+//!     .with_predicate(|req: &Request| req.uri().path() == "/upload")
+//!     .layer(service_fn(|_req: Request| async {
+//!         Ok::<_, Infallible>(Response::new(Body::empty()))
+//!     }));
+//!
+//! let req = Request::post("/other")
+//!     .header(header::EXPECT, "100-continue")
+//!     .body(Body::from("data"))
+//!     .unwrap();
+//! let resp = service.serve(Context::default(), req).await.unwrap();
+//! assert_eq!(resp.status(), StatusCode::EXPECTATION_FAILED);
+//! # }
+//! ```
+
+use crate::dep::http_body::{Body as HttpBody, Frame, SizeHint};
+use crate::dep::http_body_util::BodyExt;
+use crate::{header, Body, IntoResponse, Request, Response, StatusCode};
+use bytes::Bytes;
+use pin_project_lite::pin_project;
+use rama_core::{error::BoxError, Context, Layer, Service};
+use rama_utils::macros::define_inner_service_accessors;
+use std::{
+    fmt,
+    pin::Pin,
+    sync::Arc,
+    task::{self, Poll},
+};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+/// When the `100 Continue` interim response is sent for accepted requests.
+pub enum ExpectContinueMode {
+    /// Continue prior to calling the inner service,
+    /// such that it receives requests of which the body is already being sent.
+    Auto,
+    #[default]
+    /// Leave it up to the inner service (handler), which continues the request
+    /// by reading its body, or rejects it by responding without reading it.
+    Handler,
+}
+
+type Predicate = dyn Fn(&Request) -> bool + Send + Sync + 'static;
+
+/// Layer that applies the [`ExpectContinue`] middleware.
+///
+/// See the [module docs](self) for more information.
+pub struct ExpectContinueLayer {
+    mode: ExpectContinueMode,
+    predicate: Option<Arc<Predicate>>,
+}
+
+impl fmt::Debug for ExpectContinueLayer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ExpectContinueLayer")
+            .field("mode", &self.mode)
+            .field("predicate", &self.predicate.is_some())
+            .finish()
+    }
+}
+
+impl Clone for ExpectContinueLayer {
+    fn clone(&self) -> Self {
+        Self {
+            mode: self.mode,
+            predicate: self.predicate.clone(),
+        }
+    }
+}
+
+impl ExpectContinueLayer {
+    /// Create a new [`ExpectContinueLayer`] using the given mode.
+    pub fn new(mode: ExpectContinueMode) -> Self {
+        Self {
+            mode,
+            predicate: None,
+        }
+    }
+
+    /// Only continue the `100-continue` requests for which the predicate returns `true`,
+    /// rejecting the others using `417 Expectation Failed` without reading their body.
+    pub fn with_predicate<F>(mut self, predicate: F) -> Self
+    where
+        F: Fn(&Request) -> bool + Send + Sync + 'static,
+    {
+        self.predicate = Some(Arc::new(predicate));
+        self
+    }
+
+    /// Only continue the `100-continue` requests for which the predicate returns `true`,
+    /// rejecting the others using `417 Expectation Failed` without reading their body.
+    pub fn set_predicate<F>(&mut self, predicate: F) -> &mut Self
+    where
+        F: Fn(&Request) -> bool + Send + Sync + 'static,
+    {
+        self.predicate = Some(Arc::new(predicate));
+        self
+    }
+}
+
+impl<S> Layer<S> for ExpectContinueLayer {
+    type Service = ExpectContinue<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        ExpectContinue {
+            inner,
+            mode: self.mode,
+            predicate: self.predicate.clone(),
+        }
+    }
+}
+
+/// Middleware which controls the handling of `Expect: 100-continue` requests.
+///
+/// See the [module docs](self) for more information.
+pub struct ExpectContinue<S> {
+    inner: S,
+    mode: ExpectContinueMode,
+    predicate: Option<Arc<Predicate>>,
+}
+
+impl<S: fmt::Debug> fmt::Debug for ExpectContinue<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ExpectContinue")
+            .field("inner", &self.inner)
+            .field("mode", &self.mode)
+            .field("predicate", &self.predicate.is_some())
+            .finish()
+    }
+}
+
+impl<S: Clone> Clone for ExpectContinue<S> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            mode: self.mode,
+            predicate: self.predicate.clone(),
+        }
+    }
+}
+
+impl<S> ExpectContinue<S> {
+    /// Create a new [`ExpectContinue`] middleware using the given mode.
+    pub fn new(inner: S, mode: ExpectContinueMode) -> Self {
+        Self {
+            inner,
+            mode,
+            predicate: None,
+        }
+    }
+
+    define_inner_service_accessors!();
+}
+
+impl<State, S> Service<State, Request> for ExpectContinue<S>
+where
+    State: Clone + Send + Sync + 'static,
+    S: Service<State, Request, Response: IntoResponse>,
+{
+    type Response = Response;
+    type Error = S::Error;
+
+    async fn serve(
+        &self,
+        ctx: Context<State>,
+        req: Request,
+    ) -> Result<Self::Response, Self::Error> {
+        let Some(expect) = req.headers().get(header::EXPECT) else {
+            return self
+                .inner
+                .serve(ctx, req)
+                .await
+                .map(IntoResponse::into_response);
+        };
+        if !expect.as_bytes().eq_ignore_ascii_case(b"100-continue") {
+            tracing::debug!(?expect, "expect continue: reject unsupported expectation");
+            return Ok(StatusCode::EXPECTATION_FAILED.into_response());
+        }
+        if let Some(predicate) = &self.predicate {
+            if !predicate(&req) {
+                tracing::debug!(uri = %req.uri(), "expect continue: reject request");
+                return Ok(StatusCode::EXPECTATION_FAILED.into_response());
+            }
+        }
+
+        let req = match self.mode {
+            ExpectContinueMode::Handler => req,
+            ExpectContinueMode::Auto => {
+                let (parts, mut body) = req.into_parts();
+                // reading the body is what makes the server send the `100 Continue`
+                let first = match body.frame().await {
+                    Some(Ok(frame)) => Some(frame),
+                    Some(Err(err)) => {
+                        tracing::debug!(error = %err, "expect continue: failed to read body");
+                        return Ok(StatusCode::BAD_REQUEST.into_response());
+                    }
+                    None => None,
+                };
+                Request::from_parts(parts, Body::new(ContinuedBody { first, inner: body }))
+            }
+        };
+        self.inner
+            .serve(ctx, req)
+            .await
+            .map(IntoResponse::into_response)
+    }
+}
+
+pin_project! {
+    /// A body of which the first frame was already read.
+    struct ContinuedBody {
+        first: Option<Frame<Bytes>>,
+        #[pin]
+        inner: Body,
+    }
+}
+
+impl HttpBody for ContinuedBody {
+    type Data = Bytes;
+    type Error = BoxError;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut task::Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let this = self.project();
+        match this.first.take() {
+            Some(frame) => Poll::Ready(Some(Ok(frame))),
+            None => this.inner.poll_frame(cx).map_err(Into::into),
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.first.is_none() && self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        let mut hint = self.inner.size_hint();
+        if let Some(len) = self
+            .first
+            .as_ref()
+            .and_then(Frame::data_ref)
+            .map(Bytes::len)
+        {
+            let len = len as u64;
+            hint.set_lower(hint.lower() + len);
+            if let Some(upper) = hint.upper() {
+                hint.set_upper(upper + len);
+            }
+        }
+        hint
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::BodyExtractExt;
+    use rama_core::service::service_fn;
+    use std::convert::Infallible;
+
+    fn request(expect: Option<&str>, path: &str) -> Request {
+        let mut builder = Request::post(path);
+        if let Some(expect) = expect {
+            builder = builder.header(header::EXPECT, expect);
+        }
+        builder.body(Body::from("hello")).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_expect_continue() {
+        for mode in [ExpectContinueMode::Auto, ExpectContinueMode::Handler] {
+            let service = ExpectContinueLayer::new(mode)
+                .with_predicate(|req: &Request| req.uri().path() != "/rejected")
+                .layer(service_fn(|req: Request| async move {
+                    let body = req.into_body().try_into_string().await.unwrap();
+                    Ok::<_, Infallible>(Response::new(Body::from(body)))
+                }));
+
+            for (expect, path, status) in [
+                (None, "/", StatusCode::OK),
+                (Some("100-Continue"), "/", StatusCode::OK),
+                (
+                    Some("100-continue"),
+                    "/rejected",
+                    StatusCode::EXPECTATION_FAILED,
+                ),
+                (Some("200-ok"), "/", StatusCode::EXPECTATION_FAILED),
+            ] {
+                let resp = service
+                    .serve(Context::default(), request(expect, path))
+                    .await
+                    .unwrap();
+                assert_eq!(resp.status(), status, "{mode:?} {expect:?} {path}");
+                if status == StatusCode::OK {
+                    assert_eq!(resp.into_body().try_into_string().await.unwrap(), "hello");
+                }
+            }
+        }
+    }
+}
@@ -11,6 +11,12 @@
 //! original body is known to be empty by [`Body::size_hint`], the middleware uses `Default`
 //! implementation of the body type to create a new request body. If you know that the body can be
 //! cloned in some way, you can tell the middleware to clone it by configuring a [`policy`].
+//! A [`ReplayBody`] for example can be cloned using [`ReplayBody::try_clone`],
+//! by means of the [`clone_body_fn`] policy.
+//!
+//! [`ReplayBody`]: crate::utils::ReplayBody
+//! [`ReplayBody::try_clone`]: crate::utils::ReplayBody::try_clone
+//! [`clone_body_fn`]: policy::clone_body_fn
 //!
 //! # Examples
 //!
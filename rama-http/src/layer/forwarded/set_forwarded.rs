@@ -85,6 +85,7 @@ use std::marker::PhantomData;
 /// ```
 pub struct SetForwardedHeadersLayer<T = Forwarded> {
     by_node: NodeId,
+    by_interface: bool,
     _headers: PhantomData<fn() -> T>,
 }
 
@@ -92,6 +93,7 @@ impl<T: fmt::Debug> fmt::Debug for SetForwardedHeadersLayer<T> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         f.debug_struct("SetForwardedHeadersLayer")
             .field("by_node", &self.by_node)
+            .field("by_interface", &self.by_interface)
             .field(
                 "_headers",
                 &format_args!("{}", std::any::type_name::<fn() -> T>()),
@@ -104,6 +106,7 @@ impl<T: Clone> Clone for SetForwardedHeadersLayer<T> {
     fn clone(&self) -> Self {
         Self {
             by_node: self.by_node.clone(),
+            by_interface: self.by_interface,
             _headers: PhantomData,
         }
     }
@@ -125,6 +128,26 @@ impl<T> SetForwardedHeadersLayer<T> {
         self.by_node = node_id.into();
         self
     }
+
+    /// Identify this proxy in the "by" property by the network interface
+    /// on which the request was received, as found in the [`SocketInfo`].
+    ///
+    /// The interface is written as an obfuscated identifier (e.g. `_eth0`).
+    /// The node set using [`Self::forward_by`] is used when the interface is not known.
+    pub fn forward_by_interface(mut self, by_interface: bool) -> Self {
+        self.by_interface = by_interface;
+        self
+    }
+
+    /// Identify this proxy in the "by" property by the network interface
+    /// on which the request was received, as found in the [`SocketInfo`].
+    ///
+    /// The interface is written as an obfuscated identifier (e.g. `_eth0`).
+    /// The node set using [`Self::set_forward_by`] is used when the interface is not known.
+    pub fn set_forward_by_interface(&mut self, by_interface: bool) -> &mut Self {
+        self.by_interface = by_interface;
+        self
+    }
 }
 
 impl<T> SetForwardedHeadersLayer<T> {
@@ -132,6 +155,7 @@ impl<T> SetForwardedHeadersLayer<T> {
     pub fn new() -> Self {
         Self {
             by_node: Domain::from_static("rama").into(),
+            by_interface: false,
             _headers: PhantomData,
         }
     }
@@ -190,6 +214,7 @@ impl<H, S> Layer<S> for SetForwardedHeadersLayer<H> {
         Self::Service {
             inner,
             by_node: self.by_node.clone(),
+            by_interface: self.by_interface,
             _headers: PhantomData,
         }
     }
@@ -202,6 +227,7 @@ impl<H, S> Layer<S> for SetForwardedHeadersLayer<H> {
 pub struct SetForwardedHeadersService<S, T = Forwarded> {
     inner: S,
     by_node: NodeId,
+    by_interface: bool,
     _headers: PhantomData<fn() -> T>,
 }
 
@@ -210,6 +236,7 @@ impl<S: fmt::Debug, T> fmt::Debug for SetForwardedHeadersService<S, T> {
         f.debug_struct("SetForwardedHeadersService")
             .field("inner", &self.inner)
             .field("by_node", &self.by_node)
+            .field("by_interface", &self.by_interface)
             .field(
                 "_headers",
                 &format_args!("{}", std::any::type_name::<fn() -> T>()),
@@ -223,6 +250,7 @@ impl<S: Clone, T> Clone for SetForwardedHeadersService<S, T> {
         SetForwardedHeadersService {
             inner: self.inner.clone(),
             by_node: self.by_node.clone(),
+            by_interface: self.by_interface,
             _headers: PhantomData,
         }
     }
@@ -244,6 +272,37 @@ impl<S, T> SetForwardedHeadersService<S, T> {
         self.by_node = node_id.into();
         self
     }
+
+    /// Identify this proxy in the "by" property by the network interface
+    /// on which the request was received, as found in the [`SocketInfo`].
+    ///
+    /// The interface is written as an obfuscated identifier (e.g. `_eth0`).
+    /// The node set using [`Self::forward_by`] is used when the interface is not known.
+    pub fn forward_by_interface(mut self, by_interface: bool) -> Self {
+        self.by_interface = by_interface;
+        self
+    }
+
+    /// Identify this proxy in the "by" property by the network interface
+    /// on which the request was received, as found in the [`SocketInfo`].
+    ///
+    /// The interface is written as an obfuscated identifier (e.g. `_eth0`).
+    /// The node set using [`Self::set_forward_by`] is used when the interface is not known.
+    pub fn set_forward_by_interface(&mut self, by_interface: bool) -> &mut Self {
+        self.by_interface = by_interface;
+        self
+    }
+}
+
+impl<S, T> SetForwardedHeadersService<S, T> {
+    fn by_node<State>(&self, ctx: &Context<State>) -> NodeId {
+        if self.by_interface {
+            if let Some(interface) = ctx.get::<SocketInfo>().and_then(SocketInfo::interface) {
+                return NodeId::from_str_lossy(&format!("_{interface}"));
+            }
+        }
+        self.by_node.clone()
+    }
 }
 
 impl<S, T> SetForwardedHeadersService<S, T> {
@@ -252,6 +311,7 @@ impl<S, T> SetForwardedHeadersService<S, T> {
         Self {
             inner,
             by_node: Domain::from_static("rama").into(),
+            by_interface: false,
             _headers: PhantomData,
         }
     }
@@ -314,7 +374,7 @@ where
     ) -> Result<Self::Response, Self::Error> {
         let forwarded: Option<Forwarded> = ctx.get().cloned();
 
-        let mut forwarded_element = ForwardedElement::forwarded_by(self.by_node.clone());
+        let mut forwarded_element = ForwardedElement::forwarded_by(self.by_node(&ctx));
 
         if let Some(peer_addr) = ctx.get::<SocketInfo>().map(|socket| *socket.peer_addr()) {
             forwarded_element.set_forwarded_for(peer_addr);
@@ -366,7 +426,7 @@ macro_rules! set_forwarded_service_for_tuple {
             ) -> Result<Self::Response, Self::Error> {
                 let forwarded: Option<Forwarded> = ctx.get().cloned();
 
-                let mut forwarded_element = ForwardedElement::forwarded_by(self.by_node.clone());
+                let mut forwarded_element = ForwardedElement::forwarded_by(self.by_node(&ctx));
 
                 if let Some(peer_addr) = ctx.get::<SocketInfo>().map(|socket| *socket.peer_addr()) {
                     forwarded_element.set_forwarded_for(peer_addr);
@@ -540,6 +600,29 @@ mod tests {
         service.serve(ctx, req).await.unwrap();
     }
 
+    #[tokio::test]
+    async fn test_set_forwarded_service_forwarded_by_interface() {
+        async fn svc(request: Request<()>) -> Result<(), Infallible> {
+            assert_eq!(
+                request.headers().get("Forwarded").unwrap(),
+                "by=_eth0;for=\"127.0.0.1:62345\";host=\"www.example.com:443\";proto=https",
+            );
+            Ok(())
+        }
+
+        let service =
+            SetForwardedHeadersService::forwarded(service_fn(svc)).forward_by_interface(true);
+        let req = Request::builder()
+            .uri("https://www.example.com")
+            .body(())
+            .unwrap();
+        let mut ctx = Context::default();
+        ctx.insert(
+            SocketInfo::new(None, "127.0.0.1:62345".parse().unwrap()).with_interface("eth0"),
+        );
+        service.serve(ctx, req).await.unwrap();
+    }
+
     #[tokio::test]
     async fn test_set_forwarded_service_forwarded_fully_defined_with_chain() {
         async fn svc(request: Request<()>) -> Result<(), Infallible> {
@@ -0,0 +1,555 @@
+//! Middleware to insert, append, remove and rename request and response headers,
+//! using [`HeaderTemplate`]s for the values of inserted and appended headers.
+//!
+//! A template is a string which can reference [`Context`] and request data
+//! using `{variable}` placeholders (use `{{` and `}}` for literal braces):
+//!
+//! | variable            | value                                                         |
+//! |---------------------|---------------------------------------------------------------|
+//! | `client_ip`         | the client ip of the [`Forwarded`] information, or the peer ip |
+//! | `peer_addr`         | the peer address of the [`SocketInfo`]                        |
+//! | `sni`               | the server name (SNI) of the tls client hello                 |
+//! | `proxy_username`    | the username of the authorized (proxy) user                   |
+//! | `method`            | the request method                                            |
+//! | `host`              | the host of the request uri, or its `Host` header             |
+//! | `path`              | the request path                                              |
+//! | `param.<name>`      | the uri (route) parameter with the given name                 |
+//! | `header.<name>`     | the (first) request header with the given name                |
+//!
+//! In case a variable has no value for a request,
+//! the header of the template is not inserted or appended.
+//!
+//! Response templates are rendered using the request context and request,
+//! as those are no longer available once the response is returned.
+//!
+//! [`Forwarded`]: rama_net::forwarded::Forwarded
+//! [`SocketInfo`]: rama_net::stream::SocketInfo
+//!
+//! # Example
+//!
+//! ```
+//! use rama_http::layer::header_rewrite::{HeaderRewrite, HeaderRewriteLayer};
+//! use rama_http::{Body, Request, Response};
+//! use rama_http::header::HeaderName;
+//! use rama_core::service::service_fn;
+//! use rama_core::{Context, Service, Layer};
+//! use std::convert::Infallible;
+//!
+//! # #[tokio::main]
+//! # async fn main() {
+//! let layer = HeaderRewriteLayer::new()
+//!     .with_request_rewrite(HeaderRewrite::insert(
+//!         HeaderName::from_static("x-gateway-route"),
+//!         "{method} {path}".parse().unwrap(),
+//!     ))
+//!     .with_request_rewrite(HeaderRewrite::remove(HeaderName::from_static("x-internal")))
+//!     .with_response_rewrite(HeaderRewrite::rename(
+//!         HeaderName::from_static("x-upstream-version"),
+//!         HeaderName::from_static("x-version"),
+//!     ));
+//!
+//! let service = layer.layer(service_fn(|req: Request| async move {
+//!     assert_eq!(req.headers()["x-gateway-route"], "GET /foo");
+//!     assert!(!req.headers().contains_key("x-internal"));
+//!     Ok::<_, Infallible>(
+//!         Response::builder()
+//!             .header("x-upstream-version", "1.2")
+//!             .body(Body::empty())
+//!             .unwrap(),
+//!     )
+//! }));
+//!
+//! let req = Request::builder()
+//!     .uri("/foo")
+//!     .header("x-internal", "secret")
+//!     .body(Body::empty())
+//!     .unwrap();
+//! let resp = service.serve(Context::default(), req).await.unwrap();
+//! assert_eq!(resp.headers()["x-version"], "1.2");
+//! # }
+//! ```
+
+use crate::matcher::UriParams;
+use crate::utils::client_ip;
+use crate::{header, HeaderMap, HeaderName, HeaderValue, Request, Response};
+use rama_core::{
+    error::{ErrorContext, OpaqueError},
+    Context, Layer, Service,
+};
+use rama_net::{stream::SocketInfo, user::UserId};
+use rama_utils::macros::define_inner_service_accessors;
+use std::{fmt, str::FromStr, sync::Arc};
+
+/// A header value template, referencing [`Context`] and request data
+/// using `{variable}` placeholders.
+///
+/// See the [module docs](self) for the supported variables.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HeaderTemplate {
+    segments: Vec<Segment>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Literal(String),
+    Variable(Variable),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Variable {
+    ClientIp,
+    PeerAddr,
+    Sni,
+    ProxyUsername,
+    Method,
+    Host,
+    Path,
+    Param(String),
+    Header(HeaderName),
+}
+
+impl Variable {
+    fn parse(name: &str) -> Result<Self, OpaqueError> {
+        Ok(match name.trim() {
+            "client_ip" => Self::ClientIp,
+            "peer_addr" => Self::PeerAddr,
+            "sni" => Self::Sni,
+            "proxy_username" => Self::ProxyUsername,
+            "method" => Self::Method,
+            "host" => Self::Host,
+            "path" => Self::Path,
+            name => {
+                if let Some(param) = name.strip_prefix("param.") {
+                    Self::Param(param.to_owned())
+                } else if let Some(header) = name.strip_prefix("header.") {
+                    Self::Header(
+                        HeaderName::from_str(header).context("parse template header name")?,
+                    )
+                } else {
+                    return Err(OpaqueError::from_display(format!(
+                        "unknown template variable: {name}"
+                    )));
+                }
+            }
+        })
+    }
+
+    fn render<State, Body>(&self, ctx: &Context<State>, req: &Request<Body>) -> Option<String> {
+        match self {
+            Self::ClientIp => client_ip(ctx, req).map(|ip| ip.to_string()),
+            Self::PeerAddr => ctx
+                .get::<SocketInfo>()
+                .map(|info| info.peer_addr().to_string()),
+            #[cfg(feature = "tls")]
+            Self::Sni => ctx
+                .get::<rama_net::tls::SecureTransport>()
+                .and_then(|transport| transport.client_hello())
+                .and_then(|hello| hello.ext_server_name())
+                .map(|host| host.to_string()),
+            #[cfg(not(feature = "tls"))]
+            Self::Sni => None,
+            Self::ProxyUsername => match ctx.get::<UserId>()? {
+                UserId::Username(username) => Some(username.clone()),
+                UserId::Token(_) => None,
+            },
+            Self::Method => Some(req.method().to_string()),
+            Self::Host => req.uri().host().map(ToOwned::to_owned).or_else(|| {
+                req.headers()
+                    .get(header::HOST)
+                    .and_then(|value| value.to_str().ok())
+                    .map(ToOwned::to_owned)
+            }),
+            Self::Path => Some(req.uri().path().to_owned()),
+            Self::Param(name) => ctx
+                .get::<UriParams>()
+                .and_then(|params| params.get(name))
+                .map(ToOwned::to_owned),
+            Self::Header(name) => req
+                .headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(ToOwned::to_owned),
+        }
+    }
+}
+
+impl HeaderTemplate {
+    /// Try to create a new [`HeaderTemplate`] from the given template string.
+    pub fn try_new(template: impl AsRef<str>) -> Result<Self, OpaqueError> {
+        let mut segments = Vec::new();
+        let mut literal = String::new();
+        let mut chars = template.as_ref().chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '{' => {
+                    let mut name = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => name.push(c),
+                            None => {
+                                return Err(OpaqueError::from_display("unclosed template variable"))
+                            }
+                        }
+                    }
+                    if !literal.is_empty() {
+                        segments.push(Segment::Literal(std::mem::take(&mut literal)));
+                    }
+                    segments.push(Segment::Variable(Variable::parse(&name)?));
+                }
+                '}' => return Err(OpaqueError::from_display("unexpected '}' in template")),
+                c => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            segments.push(Segment::Literal(literal));
+        }
+        Ok(Self { segments })
+    }
+
+    /// Render the template for the given [`Context`] and [`Request`],
+    /// returning `None` in case a variable has no value,
+    /// or the result is not a valid header value.
+    pub fn render<State, Body>(
+        &self,
+        ctx: &Context<State>,
+        req: &Request<Body>,
+    ) -> Option<HeaderValue> {
+        let mut value = String::new();
+        for segment in &self.segments {
+            match segment {
+                Segment::Literal(literal) => value.push_str(literal),
+                Segment::Variable(variable) => value.push_str(&variable.render(ctx, req)?),
+            }
+        }
+        HeaderValue::try_from(value).ok()
+    }
+}
+
+impl FromStr for HeaderTemplate {
+    type Err = OpaqueError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::try_new(s)
+    }
+}
+
+/// A single header rewrite, applied by the [`HeaderRewriteLayer`].
+#[derive(Debug, Clone)]
+pub struct HeaderRewrite {
+    kind: RewriteKind,
+}
+
+#[derive(Debug, Clone)]
+enum RewriteKind {
+    Insert(HeaderName, HeaderTemplate),
+    Append(HeaderName, HeaderTemplate),
+    Remove(HeaderName),
+    Rename(HeaderName, HeaderName),
+}
+
+impl HeaderRewrite {
+    /// Insert the header, overwriting any existing values.
+    pub fn insert(name: HeaderName, template: HeaderTemplate) -> Self {
+        Self {
+            kind: RewriteKind::Insert(name, template),
+        }
+    }
+
+    /// Append the header, keeping any existing values.
+    pub fn append(name: HeaderName, template: HeaderTemplate) -> Self {
+        Self {
+            kind: RewriteKind::Append(name, template),
+        }
+    }
+
+    /// Remove all values of the header.
+    pub fn remove(name: HeaderName) -> Self {
+        Self {
+            kind: RewriteKind::Remove(name),
+        }
+    }
+
+    /// Move all values of the header `from` to the header `to`.
+    pub fn rename(from: HeaderName, to: HeaderName) -> Self {
+        Self {
+            kind: RewriteKind::Rename(from, to),
+        }
+    }
+
+    fn render<State, Body>(
+        &self,
+        ctx: &Context<State>,
+        req: &Request<Body>,
+    ) -> Option<HeaderValue> {
+        match &self.kind {
+            RewriteKind::Insert(_, template) | RewriteKind::Append(_, template) => {
+                template.render(ctx, req)
+            }
+            RewriteKind::Remove(_) | RewriteKind::Rename(..) => None,
+        }
+    }
+
+    fn apply(&self, headers: &mut HeaderMap, value: Option<HeaderValue>) {
+        match &self.kind {
+            RewriteKind::Insert(name, _) => {
+                if let Some(value) = value {
+                    headers.insert(name, value);
+                }
+            }
+            RewriteKind::Append(name, _) => {
+                if let Some(value) = value {
+                    headers.append(name, value);
+                }
+            }
+            RewriteKind::Remove(name) => {
+                headers.remove(name);
+            }
+            RewriteKind::Rename(from, to) => {
+                if let header::Entry::Occupied(entry) = headers.entry(from) {
+                    let values: Vec<_> = entry.remove_entry_mult().1.collect();
+                    for value in values {
+                        headers.append(to, value);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct HeaderRewriteConfig {
+    request: Vec<HeaderRewrite>,
+    response: Vec<HeaderRewrite>,
+}
+
+/// Layer that applies the [`HeaderRewriteService`] middleware.
+///
+/// See the [module docs](self) for more details.
+#[derive(Debug, Clone, Default)]
+pub struct HeaderRewriteLayer {
+    config: Arc<HeaderRewriteConfig>,
+}
+
+impl HeaderRewriteLayer {
+    /// Create a new [`HeaderRewriteLayer`], without any rewrites.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a rewrite of the request headers.
+    ///
+    /// Rewrites are applied in the order they are added.
+    pub fn with_request_rewrite(mut self, rewrite: HeaderRewrite) -> Self {
+        Arc::make_mut(&mut self.config).request.push(rewrite);
+        self
+    }
+
+    /// Add a rewrite of the request headers.
+    ///
+    /// Rewrites are applied in the order they are added.
+    pub fn set_request_rewrite(&mut self, rewrite: HeaderRewrite) -> &mut Self {
+        Arc::make_mut(&mut self.config).request.push(rewrite);
+        self
+    }
+
+    /// Add a rewrite of the response headers.
+    ///
+    /// Rewrites are applied in the order they are added.
+    pub fn with_response_rewrite(mut self, rewrite: HeaderRewrite) -> Self {
+        Arc::make_mut(&mut self.config).response.push(rewrite);
+        self
+    }
+
+    /// Add a rewrite of the response headers.
+    ///
+    /// Rewrites are applied in the order they are added.
+    pub fn set_response_rewrite(&mut self, rewrite: HeaderRewrite) -> &mut Self {
+        Arc::make_mut(&mut self.config).response.push(rewrite);
+        self
+    }
+}
+
+impl<S> Layer<S> for HeaderRewriteLayer {
+    type Service = HeaderRewriteService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        HeaderRewriteService {
+            inner,
+            config: self.config.clone(),
+        }
+    }
+}
+
+/// Middleware which rewrites request and response headers.
+///
+/// See the [module docs](self) for more details.
+pub struct HeaderRewriteService<S> {
+    inner: S,
+    config: Arc<HeaderRewriteConfig>,
+}
+
+impl<S> HeaderRewriteService<S> {
+    define_inner_service_accessors!();
+}
+
+impl<S: fmt::Debug> fmt::Debug for HeaderRewriteService<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HeaderRewriteService")
+            .field("inner", &self.inner)
+            .field("config", &self.config)
+            .finish()
+    }
+}
+
+impl<S: Clone> Clone for HeaderRewriteService<S> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            config: self.config.clone(),
+        }
+    }
+}
+
+impl<State, S, ReqBody, ResBody> Service<State, Request<ReqBody>> for HeaderRewriteService<S>
+where
+    State: Clone + Send + Sync + 'static,
+    S: Service<State, Request<ReqBody>, Response = Response<ResBody>>,
+    ReqBody: Send + 'static,
+    ResBody: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+
+    async fn serve(
+        &self,
+        ctx: Context<State>,
+        mut req: Request<ReqBody>,
+    ) -> Result<Self::Response, Self::Error> {
+        let response_values: Vec<_> = self
+            .config
+            .response
+            .iter()
+            .map(|rewrite| rewrite.render(&ctx, &req))
+            .collect();
+
+        for rewrite in &self.config.request {
+            let value = rewrite.render(&ctx, &req);
+            rewrite.apply(req.headers_mut(), value);
+        }
+
+        let mut res = self.inner.serve(ctx, req).await?;
+
+        for (rewrite, value) in self.config.response.iter().zip(response_values) {
+            rewrite.apply(res.headers_mut(), value);
+        }
+        Ok(res)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Body;
+    use rama_core::service::service_fn;
+    use std::convert::Infallible;
+
+    #[test]
+    fn test_template_parse() {
+        assert!(HeaderTemplate::try_new("plain").is_ok());
+        assert!(HeaderTemplate::try_new("{client_ip}:{param.id}").is_ok());
+        assert!(HeaderTemplate::try_new("{{literal}}").is_ok());
+        assert!(HeaderTemplate::try_new("{unknown}").is_err());
+        assert!(HeaderTemplate::try_new("{client_ip").is_err());
+        assert!(HeaderTemplate::try_new("oops}").is_err());
+        assert!(HeaderTemplate::try_new("{header.in valid}").is_err());
+    }
+
+    #[test]
+    fn test_template_render() {
+        let mut ctx = Context::default();
+        ctx.insert(SocketInfo::new(None, "10.0.0.1:1234".parse().unwrap()));
+        ctx.insert(UserId::Username("john".to_owned()));
+        let req = Request::builder()
+            .method("POST")
+            .uri("http://example.com/foo")
+            .header("x-tenant", "acme")
+            .body(Body::empty())
+            .unwrap();
+
+        let render = |template: &str| {
+            HeaderTemplate::try_new(template)
+                .unwrap()
+                .render(&ctx, &req)
+        };
+        assert_eq!(
+            render("{client_ip} ({peer_addr})").unwrap(),
+            "10.0.0.1 (10.0.0.1:1234)"
+        );
+        assert_eq!(
+            render("{proxy_username}@{header.x-tenant}").unwrap(),
+            "john@acme"
+        );
+        assert_eq!(
+            render("{method} {host}{path} {{ok}}").unwrap(),
+            "POST example.com/foo {ok}"
+        );
+        assert!(render("{param.id}").is_none());
+        assert!(render("{sni}").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_header_rewrite_service() {
+        let layer = HeaderRewriteLayer::new()
+            .with_request_rewrite(HeaderRewrite::append(
+                HeaderName::from_static("x-forwarded-user"),
+                "{proxy_username}".parse().unwrap(),
+            ))
+            .with_request_rewrite(HeaderRewrite::rename(
+                HeaderName::from_static("x-old"),
+                HeaderName::from_static("x-new"),
+            ))
+            .with_response_rewrite(HeaderRewrite::insert(
+                HeaderName::from_static("x-route-id"),
+                "route-{param.id}".parse().unwrap(),
+            ))
+            .with_response_rewrite(HeaderRewrite::remove(header::SERVER));
+
+        let svc = layer.layer(service_fn(|req: Request| async move {
+            assert!(!req.headers().contains_key("x-forwarded-user"));
+            let values: Vec<_> = req.headers().get_all("x-new").iter().collect();
+            assert_eq!(values, ["a", "b"]);
+            Ok::<_, Infallible>(
+                Response::builder()
+                    .header(header::SERVER, "upstream")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+        }));
+
+        let mut ctx = Context::default();
+        ctx.insert(
+            crate::matcher::PathMatcher::new("/items/:id")
+                .matches_path("/items/42")
+                .unwrap(),
+        );
+
+        let req = Request::builder()
+            .uri("/items/42")
+            .header("x-old", "a")
+            .header("x-old", "b")
+            .body(Body::empty())
+            .unwrap();
+        let resp = svc.serve(ctx, req).await.unwrap();
+        assert_eq!(resp.headers()["x-route-id"], "route-42");
+        assert!(!resp.headers().contains_key(header::SERVER));
+    }
+}
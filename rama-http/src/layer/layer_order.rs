@@ -0,0 +1,165 @@
+//! Validation of known-bad orderings of the http middleware.
+//!
+//! See [`rama_core::layer::order`] for how layer order validation works (and its limitations),
+//! and [`http_layer_order_rules`] for the rules that apply to the http middleware.
+//! This validation is best-effort: a stack passing it is not guaranteed to be ordered correctly.
+//!
+//! # Example
+//!
+//! ```
+//! use rama_core::layer::{HijackLayer, Layer};
+//! use rama_core::service::service_fn;
+//! use rama_http::layer::auth::{RequireAuthorizationLayer, StaticCredentialStore};
+//! use rama_http::layer::layer_order::http_layer_order_rules;
+//! use rama_http::{Body, Request, Response};
+//! use std::convert::Infallible;
+//!
+//! let svc = service_fn(|_: Request| async { Ok::<_, Infallible>(Response::<Body>::default()) });
+//! let hijack = svc.clone();
+//!
+//! // the hijacked requests bypass the authorization
+//! let stack = (
+//!     HijackLayer::new(true, hijack),
+//!     RequireAuthorizationLayer::new(StaticCredentialStore::new().with_user("john", "secret")),
+//! )
+//!     .layer(svc);
+//!
+//! let err = http_layer_order_rules().validate(&stack).unwrap_err();
+//! assert_eq!(err.violations().len(), 1);
+//! ```
+
+#[doc(inline)]
+pub use rama_core::layer::order::{
+    LayerOrderError, LayerOrderRule, LayerOrderRules, LayerOrderViolation,
+};
+
+use crate::layer::{
+    auth::{ApiKey, AsyncRequireAuthorization, RequireAuthorization},
+    proxy_auth::ProxyAuthService,
+};
+use rama_core::layer::HijackService;
+
+const AUTH_BYPASS_REASON: &str =
+    "hijacked requests bypass the authorization, make sure to authorize before hijacking";
+
+/// The [`LayerOrderRules`] for known-bad orderings of the rama http middleware:
+///
+/// - the authorization middleware has to wrap the [`HijackService`],
+///   as hijacked requests would otherwise not be authorized;
+/// - the [`ResponseWriterService`] has to wrap the `Decompression` service,
+///   such that it writes the decompressed response bodies;
+/// - the `RequestDecompression` service has to wrap the [`RequestWriterService`],
+///   such that it writes the decompressed request bodies.
+///
+/// Use [`LayerOrderRules::with_rule`] to extend it with your own rules.
+///
+/// [`ResponseWriterService`]: crate::layer::traffic_writer::ResponseWriterService
+/// [`RequestWriterService`]: crate::layer::traffic_writer::RequestWriterService
+pub fn http_layer_order_rules() -> LayerOrderRules {
+    let mut rules = LayerOrderRules::new();
+    rules
+        .set_rule(LayerOrderRule::must_wrap::<
+            RequireAuthorization<(), ()>,
+            HijackService<(), (), ()>,
+        >(AUTH_BYPASS_REASON))
+        .set_rule(LayerOrderRule::must_wrap::<
+            AsyncRequireAuthorization<(), ()>,
+            HijackService<(), (), ()>,
+        >(AUTH_BYPASS_REASON))
+        .set_rule(LayerOrderRule::must_wrap::<
+            ProxyAuthService<(), (), ()>,
+            HijackService<(), (), ()>,
+        >(AUTH_BYPASS_REASON))
+        .set_rule(LayerOrderRule::must_wrap::<
+            ApiKey<(), ()>,
+            HijackService<(), (), ()>,
+        >(AUTH_BYPASS_REASON));
+
+    #[cfg(feature = "jwt")]
+    rules.set_rule(LayerOrderRule::must_wrap::<
+        crate::layer::auth::JwtAuth<(), ()>,
+        HijackService<(), (), ()>,
+    >(AUTH_BYPASS_REASON));
+
+    #[cfg(feature = "compression")]
+    rules
+        .set_rule(LayerOrderRule::must_wrap::<
+            crate::layer::traffic_writer::ResponseWriterService<(), ()>,
+            crate::layer::decompression::Decompression<()>,
+        >(
+            "the response writer would write the compressed response bodies",
+        ))
+        .set_rule(LayerOrderRule::must_wrap::<
+            crate::layer::decompression::RequestDecompression<()>,
+            crate::layer::traffic_writer::RequestWriterService<(), ()>,
+        >(
+            "the request writer would write the compressed request bodies",
+        ));
+
+    rules
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layer::auth::{RequireAuthorizationLayer, StaticCredentialStore};
+    use crate::{Request, Response};
+    use rama_core::{layer::HijackLayer, service::service_fn, Layer};
+    use std::convert::Infallible;
+
+    async fn ok(_: Request) -> Result<Response, Infallible> {
+        Ok(Response::default())
+    }
+
+    #[test]
+    fn test_auth_before_hijack() {
+        let stack = (
+            RequireAuthorizationLayer::new(
+                StaticCredentialStore::new().with_user("john", "secret"),
+            ),
+            HijackLayer::new(true, service_fn(ok)),
+        )
+            .layer(service_fn(ok));
+        assert!(http_layer_order_rules().validate(&stack).is_ok());
+        assert!(http_layer_order_rules().warn(&stack));
+
+        let stack = (
+            HijackLayer::new(true, service_fn(ok)),
+            RequireAuthorizationLayer::new(
+                StaticCredentialStore::new().with_user("john", "secret"),
+            ),
+        )
+            .layer(service_fn(ok));
+        let err = http_layer_order_rules().validate(&stack).unwrap_err();
+        assert_eq!(err.violations().len(), 1);
+        assert_eq!(err.violations()[0].reason(), AUTH_BYPASS_REASON);
+        assert!(!http_layer_order_rules().warn(&stack));
+    }
+
+    #[cfg(feature = "compression")]
+    #[test]
+    fn test_response_writer_wraps_decompression() {
+        use crate::layer::{
+            decompression::DecompressionLayer,
+            traffic_writer::{DoNotWriteResponse, ResponseWriterLayer},
+        };
+
+        let stack = (
+            ResponseWriterLayer::new(DoNotWriteResponse::new()),
+            DecompressionLayer::new(),
+        )
+            .layer(service_fn(ok));
+        assert!(http_layer_order_rules().validate(&stack).is_ok());
+
+        let stack = (
+            DecompressionLayer::new(),
+            ResponseWriterLayer::new(DoNotWriteResponse::new()),
+        )
+            .layer(service_fn(ok));
+        let err = http_layer_order_rules().validate(&stack).unwrap_err();
+        assert_eq!(err.violations().len(), 1);
+        assert!(err
+            .to_string()
+            .contains("ResponseWriterService is wrapped by Decompression while it should wrap it"));
+    }
+}
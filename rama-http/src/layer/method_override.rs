@@ -0,0 +1,372 @@
+//! Middleware to override the method of `POST` requests.
+//!
+//! Some clients (e.g. html forms) or intermediaries only support `GET` and `POST` requests.
+//! The [`MethodOverrideLayer`] allows such clients to submit a `POST` request
+//! with the intended method in the `X-HTTP-Method-Override` header,
+//! or in the `_method` field of an urlencoded form body.
+//!
+//! Only methods in the allow-list of the layer (by default `PUT`, `PATCH` and `DELETE`)
+//! are accepted as override, other overrides are ignored. The original method
+//! is made available to the inner service as an [`OriginalMethod`] in the [`Context`].
+//!
+//! # Example
+//!
+//! ```
+//! use rama_http::layer::method_override::MethodOverrideLayer;
+//! use rama_http::{Body, Method, Request, Response};
+//! use rama_core::service::service_fn;
+//! use rama_core::{Context, Layer, Service};
+//! use std::convert::Infallible;
+//!
+//! # #[tokio::main]
+//! # async fn main() {
+//! let service = MethodOverrideLayer::new().layer(service_fn(|req: Request| async move {
+//!     Ok::<_, Infallible>(Response::new(Body::from(req.method().to_string())))
+//! }));
+//!
+//! let req = Request::builder()
+//!     .method(Method::POST)
+//!     .header("x-http-method-override", "DELETE")
+//!     .body(Body::empty())
+//!     .unwrap();
+//! let resp = service.serve(Context::default(), req).await.unwrap();
+//! # use rama_http::dep::http_body_util::BodyExt;
+//! assert_eq!(resp.into_body().collect().await.unwrap().to_bytes(), "DELETE");
+//! # }
+//! ```
+
+use crate::dep::http_body::Body as HttpBody;
+use crate::dep::http_body_util::{BodyExt, LengthLimitError, Limited};
+use crate::dep::mime::{self, Mime};
+use crate::headers::{ContentType, HeaderMapExt};
+use crate::{Body, HeaderName, Method, Request, Response, StatusCode};
+use bytes::Bytes;
+use rama_core::{error::BoxError, Context, Layer, Service};
+use rama_utils::macros::define_inner_service_accessors;
+use std::{fmt, sync::Arc};
+
+const DEFAULT_HEADER_NAME: &str = "x-http-method-override";
+const DEFAULT_FORM_FIELD: &str = "_method";
+
+/// The maximum size of a urlencoded form body that is buffered to look for the method.
+const MAX_FORM_SIZE: usize = 64 * 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// The method of the request before it was overridden by the [`MethodOverride`] middleware.
+///
+/// Only inserted in the [`Context`] in case the method was overridden.
+pub struct OriginalMethod(pub Method);
+
+#[derive(Debug, Clone)]
+struct MethodOverrideConfig {
+    header_name: Option<HeaderName>,
+    form_field: Option<String>,
+    allowed_methods: Vec<Method>,
+}
+
+impl Default for MethodOverrideConfig {
+    fn default() -> Self {
+        Self {
+            header_name: Some(HeaderName::from_static(DEFAULT_HEADER_NAME)),
+            form_field: Some(DEFAULT_FORM_FIELD.to_owned()),
+            allowed_methods: vec![Method::PUT, Method::PATCH, Method::DELETE],
+        }
+    }
+}
+
+/// Layer that applies the [`MethodOverride`] middleware.
+///
+/// See the [module docs](self) for more details.
+#[derive(Debug, Clone, Default)]
+pub struct MethodOverrideLayer {
+    config: Arc<MethodOverrideConfig>,
+}
+
+impl MethodOverrideLayer {
+    /// Create a new [`MethodOverrideLayer`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the name of the header in which the method override can be submitted,
+    /// or `None` to not look at any header.
+    ///
+    /// Defaults to `x-http-method-override`.
+    pub fn header_name(mut self, name: Option<HeaderName>) -> Self {
+        Arc::make_mut(&mut self.config).header_name = name;
+        self
+    }
+
+    /// Set the name of the header in which the method override can be submitted,
+    /// or `None` to not look at any header.
+    ///
+    /// Defaults to `x-http-method-override`.
+    pub fn set_header_name(&mut self, name: Option<HeaderName>) -> &mut Self {
+        Arc::make_mut(&mut self.config).header_name = name;
+        self
+    }
+
+    /// Set the name of the (urlencoded) form field in which the method override can be submitted,
+    /// or `None` to never buffer the request body.
+    ///
+    /// Defaults to `_method`.
+    pub fn form_field(mut self, name: Option<String>) -> Self {
+        Arc::make_mut(&mut self.config).form_field = name;
+        self
+    }
+
+    /// Set the name of the (urlencoded) form field in which the method override can be submitted,
+    /// or `None` to never buffer the request body.
+    ///
+    /// Defaults to `_method`.
+    pub fn set_form_field(&mut self, name: Option<String>) -> &mut Self {
+        Arc::make_mut(&mut self.config).form_field = name;
+        self
+    }
+
+    /// Set the methods which are allowed as override.
+    ///
+    /// Defaults to `PUT`, `PATCH` and `DELETE`.
+    pub fn allowed_methods(mut self, methods: impl IntoIterator<Item = Method>) -> Self {
+        Arc::make_mut(&mut self.config).allowed_methods = methods.into_iter().collect();
+        self
+    }
+
+    /// Set the methods which are allowed as override.
+    ///
+    /// Defaults to `PUT`, `PATCH` and `DELETE`.
+    pub fn set_allowed_methods(&mut self, methods: impl IntoIterator<Item = Method>) -> &mut Self {
+        Arc::make_mut(&mut self.config).allowed_methods = methods.into_iter().collect();
+        self
+    }
+}
+
+impl<S> Layer<S> for MethodOverrideLayer {
+    type Service = MethodOverride<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        MethodOverride {
+            inner,
+            config: self.config.clone(),
+        }
+    }
+}
+
+/// Middleware to override the method of `POST` requests.
+///
+/// See the [module docs](self) for more details.
+pub struct MethodOverride<S> {
+    inner: S,
+    config: Arc<MethodOverrideConfig>,
+}
+
+impl<S> MethodOverride<S> {
+    /// Create a new [`MethodOverride`] with the default configuration.
+    pub fn new(inner: S) -> Self {
+        MethodOverrideLayer::new().layer(inner)
+    }
+
+    define_inner_service_accessors!();
+}
+
+impl<S: fmt::Debug> fmt::Debug for MethodOverride<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MethodOverride")
+            .field("inner", &self.inner)
+            .field("config", &self.config)
+            .finish()
+    }
+}
+
+impl<S: Clone> Clone for MethodOverride<S> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            config: self.config.clone(),
+        }
+    }
+}
+
+impl<S, State, ReqBody, ResBody> Service<State, Request<ReqBody>> for MethodOverride<S>
+where
+    S: Service<State, Request<Body>, Response = Response<ResBody>>,
+    State: Clone + Send + Sync + 'static,
+    ReqBody: HttpBody<Data = Bytes, Error: Into<BoxError>> + Send + Sync + 'static,
+    ResBody: Default + Send + 'static,
+{
+    type Response = Response<ResBody>;
+    type Error = S::Error;
+
+    async fn serve(
+        &self,
+        mut ctx: Context<State>,
+        req: Request<ReqBody>,
+    ) -> Result<Self::Response, Self::Error> {
+        let mut req = req.map(Body::new);
+        if req.method() != Method::POST {
+            return self.inner.serve(ctx, req).await;
+        }
+
+        let method;
+        (req, method) = match self.requested_method(req).await {
+            Ok(found) => found,
+            Err(status) => {
+                let mut res = Response::new(ResBody::default());
+                *res.status_mut() = status;
+                return Ok(res);
+            }
+        };
+
+        if let Some(method) = method {
+            if self.config.allowed_methods.contains(&method) {
+                tracing::trace!(%method, "method override: override POST request");
+                ctx.insert(OriginalMethod(std::mem::replace(req.method_mut(), method)));
+            } else {
+                tracing::debug!(%method, "method override: ignore disallowed method");
+            }
+        }
+
+        self.inner.serve(ctx, req).await
+    }
+}
+
+impl<S> MethodOverride<S> {
+    /// Returns the method submitted in the header or form field,
+    /// together with the request (of which the body might have been buffered).
+    async fn requested_method(
+        &self,
+        mut req: Request,
+    ) -> Result<(Request, Option<Method>), StatusCode> {
+        if let Some(name) = &self.config.header_name {
+            if let Some(value) = req.headers_mut().remove(name) {
+                let method = Method::from_bytes(value.as_bytes()).ok();
+                return Ok((req, method));
+            }
+        }
+
+        let Some(field) = self.config.form_field.as_deref() else {
+            return Ok((req, None));
+        };
+        let is_form = req
+            .headers()
+            .typed_get::<ContentType>()
+            .is_some_and(|content_type| {
+                Mime::from(content_type).essence_str()
+                    == mime::APPLICATION_WWW_FORM_URLENCODED.essence_str()
+            });
+        if !is_form {
+            return Ok((req, None));
+        }
+
+        let (parts, body) = req.into_parts();
+        let bytes = match Limited::new(body, MAX_FORM_SIZE).collect().await {
+            Ok(collected) => collected.to_bytes(),
+            Err(err) => {
+                tracing::debug!(error = %err, "method override: failed to read form body");
+                return Err(if err.is::<LengthLimitError>() {
+                    StatusCode::PAYLOAD_TOO_LARGE
+                } else {
+                    StatusCode::BAD_REQUEST
+                });
+            }
+        };
+        let method = serde_html_form::from_bytes::<Vec<(String, String)>>(&bytes)
+            .ok()
+            .and_then(|fields| {
+                fields
+                    .into_iter()
+                    .find_map(|(name, value)| (name == field).then_some(value))
+            })
+            .and_then(|value| Method::from_bytes(value.to_ascii_uppercase().as_bytes()).ok());
+        Ok((Request::from_parts(parts, Body::from(bytes)), method))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::header;
+    use rama_core::service::service_fn;
+    use std::convert::Infallible;
+
+    fn svc(
+        layer: MethodOverrideLayer,
+    ) -> impl Service<(), Request, Response = Response, Error = Infallible> {
+        layer.layer(service_fn(|ctx: Context<()>, req: Request| async move {
+            let original = ctx
+                .get::<OriginalMethod>()
+                .map(|OriginalMethod(method)| method.to_string())
+                .unwrap_or_else(|| "-".to_owned());
+            let method = req.method().clone();
+            let has_header = req.headers().contains_key(DEFAULT_HEADER_NAME);
+            let body = req.into_body().collect().await.unwrap().to_bytes();
+            Ok(Response::new(Body::from(format!(
+                "{method} {original} {has_header} {}",
+                String::from_utf8_lossy(&body)
+            ))))
+        }))
+    }
+
+    async fn call(
+        svc: &impl Service<(), Request, Response = Response, Error = Infallible>,
+        req: Request,
+    ) -> (StatusCode, String) {
+        let res = svc.serve(Context::default(), req).await.unwrap();
+        let status = res.status();
+        let body = res.into_body().collect().await.unwrap().to_bytes();
+        (status, String::from_utf8_lossy(&body).into_owned())
+    }
+
+    #[tokio::test]
+    async fn test_method_override_header() {
+        let svc = svc(MethodOverrideLayer::new());
+
+        for (method, value, expected) in [
+            (Method::POST, "PATCH", "PATCH POST false "),
+            (Method::POST, "CONNECT", "POST - false "),
+            (Method::GET, "DELETE", "GET - true "),
+        ] {
+            let req = Request::builder()
+                .method(method)
+                .header(DEFAULT_HEADER_NAME, value)
+                .body(Body::empty())
+                .unwrap();
+            assert_eq!(call(&svc, req).await, (StatusCode::OK, expected.to_owned()));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_method_override_form_field() {
+        let no_form_svc = svc(MethodOverrideLayer::new().form_field(None));
+        let svc = svc(MethodOverrideLayer::new().allowed_methods([Method::PUT]));
+
+        let form = |body: String| {
+            Request::builder()
+                .method(Method::POST)
+                .header(
+                    header::CONTENT_TYPE,
+                    mime::APPLICATION_WWW_FORM_URLENCODED.as_ref(),
+                )
+                .body(Body::from(body))
+                .unwrap()
+        };
+
+        assert_eq!(
+            call(&svc, form("a=1&_method=put".to_owned())).await,
+            (StatusCode::OK, "PUT POST false a=1&_method=put".to_owned())
+        );
+        assert_eq!(
+            call(&svc, form("_method=DELETE".to_owned())).await,
+            (StatusCode::OK, "POST - false _method=DELETE".to_owned())
+        );
+        assert_eq!(
+            call(&svc, form("a".repeat(MAX_FORM_SIZE + 1))).await.0,
+            StatusCode::PAYLOAD_TOO_LARGE
+        );
+
+        assert_eq!(
+            call(&no_form_svc, form("_method=PUT".to_owned())).await,
+            (StatusCode::OK, "POST - false _method=PUT".to_owned())
+        );
+    }
+}
@@ -0,0 +1,440 @@
+//! Middleware that mirrors (shadows) requests to a second service.
+//!
+//! A configurable percentage of the requests is duplicated and sent to the mirror
+//! service in the background, e.g. an http client targeting a new version of an
+//! upstream backend. The response of the mirror service is ignored, and its errors
+//! are only logged, such that the new backend can be tested with production traffic
+//! without affecting the clients.
+//!
+//! The request body has to be buffered in order to be duplicated. Requests of which
+//! the body is not known to be no larger than the configured [maximum body size]
+//! are therefore not mirrored. The mirrored requests do not carry the extensions of
+//! the original request, but have the [`Mirrored`] extension inserted instead.
+//!
+//! [maximum body size]: MirrorLayer::with_max_body_size
+//!
+//! # Example
+//!
+//! ```
+//! use rama_http::layer::mirror::{MirrorLayer, Mirrored};
+//! use rama_http::{Body, BodyExtractExt, Request, Response};
+//! use rama_core::service::service_fn;
+//! use rama_core::{Context, Service, Layer};
+//! use rama_core::error::BoxError;
+//! use std::convert::Infallible;
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), BoxError> {
+//! let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+//! let shadow = service_fn(move |req: Request| {
+//!     let tx = tx.clone();
+//!     async move {
+//!         assert!(req.extensions().get::<Mirrored>().is_some());
+//!         tx.send(req.try_into_string().await?).unwrap();
+//!         Ok::<_, BoxError>(Response::new(Body::from("ignored")))
+//!     }
+//! });
+//!
+//! let service = MirrorLayer::new(shadow)
+//!     .with_percentage(100.0)
+//!     .layer(service_fn(|req: Request| async move {
+//!         let body = req.try_into_string().await?;
+//!         Ok::<_, BoxError>(Response::new(Body::from(format!("hello, {body}"))))
+//!     }));
+//!
+//! let resp = service
+//!     .serve(Context::default(), Request::new(Body::from("rama")))
+//!     .await?;
+//! assert_eq!(resp.try_into_string().await?, "hello, rama");
+//! assert_eq!(rx.recv().await.unwrap(), "rama");
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{
+    dep::{http_body, http_body_util::BodyExt},
+    Body, Request,
+};
+use bytes::Bytes;
+use rama_core::{error::BoxError, Context, Layer, Service};
+use rama_utils::{
+    macros::define_inner_service_accessors,
+    rng::{HasherRng, Rng},
+};
+use std::{
+    fmt,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+const DEFAULT_MAX_BODY_SIZE: u64 = 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+/// Inserted in the extensions of a request sent to the mirror service,
+/// such that it can be recognized as a duplicate of a request.
+pub struct Mirrored;
+
+/// Layer that applies the [`Mirror`] middleware.
+///
+/// See the [module docs](self) for more details.
+pub struct MirrorLayer<M> {
+    mirror: Arc<M>,
+    percentage: f64,
+    max_body_size: u64,
+    max_in_flight: Option<usize>,
+    in_flight: Arc<AtomicUsize>,
+}
+
+impl<M: fmt::Debug> fmt::Debug for MirrorLayer<M> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MirrorLayer")
+            .field("mirror", &self.mirror)
+            .field("percentage", &self.percentage)
+            .field("max_body_size", &self.max_body_size)
+            .field("max_in_flight", &self.max_in_flight)
+            .finish()
+    }
+}
+
+impl<M> Clone for MirrorLayer<M> {
+    fn clone(&self) -> Self {
+        Self {
+            mirror: self.mirror.clone(),
+            percentage: self.percentage,
+            max_body_size: self.max_body_size,
+            max_in_flight: self.max_in_flight,
+            in_flight: self.in_flight.clone(),
+        }
+    }
+}
+
+impl<M> MirrorLayer<M> {
+    /// Create a new [`MirrorLayer`], mirroring all requests to the given service.
+    pub fn new(mirror: M) -> Self {
+        Self {
+            mirror: Arc::new(mirror),
+            percentage: 100.0,
+            max_body_size: DEFAULT_MAX_BODY_SIZE,
+            max_in_flight: None,
+            in_flight: Default::default(),
+        }
+    }
+
+    /// Define the percentage (`0.0` to `100.0`) of requests to be mirrored.
+    ///
+    /// All requests are mirrored by default.
+    pub fn with_percentage(mut self, percentage: f64) -> Self {
+        self.percentage = percentage.clamp(0.0, 100.0);
+        self
+    }
+
+    /// Define the percentage (`0.0` to `100.0`) of requests to be mirrored.
+    ///
+    /// All requests are mirrored by default.
+    pub fn set_percentage(&mut self, percentage: f64) -> &mut Self {
+        self.percentage = percentage.clamp(0.0, 100.0);
+        self
+    }
+
+    /// Define the maximum size of a request payload to be mirrored.
+    ///
+    /// Defaults to 1 MiB.
+    pub fn with_max_body_size(mut self, size: u64) -> Self {
+        self.max_body_size = size;
+        self
+    }
+
+    /// Define the maximum size of a request payload to be mirrored.
+    ///
+    /// Defaults to 1 MiB.
+    pub fn set_max_body_size(&mut self, size: u64) -> &mut Self {
+        self.max_body_size = size;
+        self
+    }
+
+    /// Define the maximum number of mirrored requests in flight,
+    /// beyond which requests are no longer mirrored until some finished.
+    ///
+    /// Unlimited by default.
+    pub fn with_max_in_flight(mut self, max: usize) -> Self {
+        self.max_in_flight = Some(max);
+        self
+    }
+
+    /// Define the maximum number of mirrored requests in flight,
+    /// beyond which requests are no longer mirrored until some finished.
+    ///
+    /// Unlimited by default.
+    pub fn set_max_in_flight(&mut self, max: usize) -> &mut Self {
+        self.max_in_flight = Some(max);
+        self
+    }
+}
+
+impl<S, M> Layer<S> for MirrorLayer<M> {
+    type Service = Mirror<S, M>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Mirror {
+            inner,
+            mirror: self.mirror.clone(),
+            percentage: self.percentage,
+            max_body_size: self.max_body_size,
+            max_in_flight: self.max_in_flight,
+            in_flight: self.in_flight.clone(),
+        }
+    }
+}
+
+/// Middleware which mirrors a percentage of the requests to a second service.
+///
+/// See the [module docs](self) for more details.
+pub struct Mirror<S, M> {
+    inner: S,
+    mirror: Arc<M>,
+    percentage: f64,
+    max_body_size: u64,
+    max_in_flight: Option<usize>,
+    in_flight: Arc<AtomicUsize>,
+}
+
+impl<S, M> Mirror<S, M> {
+    /// Create a new [`Mirror`] middleware, mirroring all requests to the given service.
+    pub fn new(inner: S, mirror: M) -> Self {
+        MirrorLayer::new(mirror).layer(inner)
+    }
+
+    define_inner_service_accessors!();
+}
+
+impl<S: fmt::Debug, M: fmt::Debug> fmt::Debug for Mirror<S, M> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Mirror")
+            .field("inner", &self.inner)
+            .field("mirror", &self.mirror)
+            .field("percentage", &self.percentage)
+            .field("max_body_size", &self.max_body_size)
+            .field("max_in_flight", &self.max_in_flight)
+            .finish()
+    }
+}
+
+impl<S: Clone, M> Clone for Mirror<S, M> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            mirror: self.mirror.clone(),
+            percentage: self.percentage,
+            max_body_size: self.max_body_size,
+            max_in_flight: self.max_in_flight,
+            in_flight: self.in_flight.clone(),
+        }
+    }
+}
+
+impl<State, S, M, ReqBody> Service<State, Request<ReqBody>> for Mirror<S, M>
+where
+    State: Clone + Send + Sync + 'static,
+    S: Service<State, Request, Error: Into<BoxError>>,
+    M: Service<State, Request, Error: Into<BoxError>>,
+    ReqBody: http_body::Body<Data = Bytes, Error: Into<BoxError>> + Send + Sync + 'static,
+{
+    type Response = S::Response;
+    type Error = BoxError;
+
+    async fn serve(
+        &self,
+        ctx: Context<State>,
+        req: Request<ReqBody>,
+    ) -> Result<Self::Response, Self::Error> {
+        let Some(in_flight) = self.try_mirror(&req) else {
+            return self
+                .inner
+                .serve(ctx, req.map(Body::new))
+                .await
+                .map_err(Into::into);
+        };
+
+        let (parts, body) = req.into_parts();
+        let body = body.collect().await.map_err(Into::into)?.to_bytes();
+
+        let mut mirror_req = Request::new(Body::from(body.clone()));
+        *mirror_req.method_mut() = parts.method.clone();
+        *mirror_req.uri_mut() = parts.uri.clone();
+        *mirror_req.version_mut() = parts.version;
+        *mirror_req.headers_mut() = parts.headers.clone();
+        mirror_req.extensions_mut().insert(Mirrored);
+
+        let mirror = self.mirror.clone();
+        let mirror_ctx = ctx.clone();
+        ctx.executor().spawn_task(async move {
+            let _in_flight = in_flight;
+            if let Err(err) = mirror.serve(mirror_ctx, mirror_req).await {
+                let err: BoxError = err.into();
+                tracing::debug!(error = %err, "mirrored request failed");
+            }
+        });
+
+        self.inner
+            .serve(ctx, Request::from_parts(parts, Body::from(body)))
+            .await
+            .map_err(Into::into)
+    }
+}
+
+impl<S, M> Mirror<S, M> {
+    /// Decide whether or not the given request is to be mirrored,
+    /// returning the in-flight guard of the mirrored request if so.
+    fn try_mirror<B: http_body::Body>(&self, req: &Request<B>) -> Option<InFlightGuard> {
+        if !req
+            .body()
+            .size_hint()
+            .upper()
+            .is_some_and(|size| size <= self.max_body_size)
+        {
+            return None;
+        }
+        if self.percentage < 100.0 && HasherRng::new().next_f64() * 100.0 >= self.percentage {
+            return None;
+        }
+
+        let in_flight = self.in_flight.fetch_add(1, Ordering::AcqRel);
+        let guard = InFlightGuard(self.in_flight.clone());
+        if self.max_in_flight.is_some_and(|max| in_flight >= max) {
+            tracing::trace!(in_flight, "max mirrored requests in flight: do not mirror");
+            return None;
+        }
+        Some(guard)
+    }
+}
+
+/// Tracks a mirrored request in flight, for as long as it is alive.
+struct InFlightGuard(Arc<AtomicUsize>);
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BodyExtractExt, Response, StatusCode};
+    use rama_core::service::service_fn;
+    use std::{convert::Infallible, time::Duration};
+    use tokio::sync::mpsc;
+
+    fn echo() -> impl Service<(), Request, Response = Response, Error = BoxError> {
+        service_fn(|req: Request| async move {
+            let body = req.try_into_string().await?;
+            Ok::<_, BoxError>(Response::new(Body::from(body)))
+        })
+    }
+
+    #[tokio::test]
+    async fn test_mirror_request() {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let service = MirrorLayer::new(service_fn(move |req: Request| {
+            let tx = tx.clone();
+            async move {
+                let (parts, body) = req.into_parts();
+                let body = body.collect().await.unwrap().to_bytes();
+                tx.send((parts, body)).unwrap();
+                Ok::<_, Infallible>(StatusCode::INTERNAL_SERVER_ERROR)
+            }
+        }))
+        .layer(echo());
+
+        let req = Request::builder()
+            .method("POST")
+            .uri("http://example.com/foo")
+            .header("x-foo", "bar")
+            .body(Body::from("hello"))
+            .unwrap();
+        let resp = service.serve(Context::default(), req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.try_into_string().await.unwrap(), "hello");
+
+        let (parts, body) = rx.recv().await.unwrap();
+        assert_eq!(parts.method, "POST");
+        assert_eq!(parts.uri, "http://example.com/foo");
+        assert_eq!(parts.headers["x-foo"], "bar");
+        assert!(parts.extensions.get::<Mirrored>().is_some());
+        assert_eq!(body, "hello");
+    }
+
+    #[tokio::test]
+    async fn test_mirror_errors_are_ignored() {
+        let service = MirrorLayer::new(service_fn(|_req: Request| async move {
+            Err::<Response, _>(BoxError::from("shadow upstream is down"))
+        }))
+        .layer(echo());
+
+        let resp = service
+            .serve(Context::default(), Request::new(Body::from("hello")))
+            .await
+            .unwrap();
+        assert_eq!(resp.try_into_string().await.unwrap(), "hello");
+    }
+
+    #[tokio::test]
+    async fn test_mirror_skipped() {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let mirror = service_fn(move |_req: Request| {
+            let tx = tx.clone();
+            async move {
+                tx.send(()).unwrap();
+                Ok::<_, Infallible>(StatusCode::OK)
+            }
+        });
+
+        let service = MirrorLayer::new(mirror.clone())
+            .with_percentage(0.0)
+            .layer(echo());
+        for _ in 0..10 {
+            service
+                .serve(Context::default(), Request::new(Body::from("hello")))
+                .await
+                .unwrap();
+        }
+
+        let service = MirrorLayer::new(mirror).with_max_body_size(4).layer(echo());
+        let resp = service
+            .serve(Context::default(), Request::new(Body::from("hello")))
+            .await
+            .unwrap();
+        assert_eq!(resp.try_into_string().await.unwrap(), "hello");
+
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_mirror_max_in_flight() {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let service = MirrorLayer::new(service_fn(move |_req: Request| {
+            let tx = tx.clone();
+            async move {
+                tx.send(()).unwrap();
+                tokio::time::sleep(Duration::from_millis(200)).await;
+                Ok::<_, Infallible>(StatusCode::OK)
+            }
+        }))
+        .with_max_in_flight(1)
+        .layer(echo());
+
+        for _ in 0..3 {
+            service
+                .serve(Context::default(), Request::new(Body::from("hello")))
+                .await
+                .unwrap();
+        }
+        rx.recv().await.unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(rx.try_recv().is_err());
+    }
+}
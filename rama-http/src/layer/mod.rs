@@ -16,23 +16,41 @@
 //! [`Layer`]: rama_core::Layer
 //! [`Service`]: rama_core::Service
 
+pub mod accept_language;
+pub mod access_log;
 pub mod auth;
 pub mod body_limit;
+pub mod bot_detection;
+pub mod cache;
 pub mod catch_panic;
 pub mod classify;
+pub mod coalesce;
 pub mod collect_body;
+pub mod content_length;
 pub mod cors;
+pub mod csrf;
 pub mod dns;
+pub mod early_hints;
 pub mod error_handling;
+pub mod expect_continue;
 pub mod follow_redirect;
 pub mod forwarded;
 pub mod header_config;
 pub mod header_option_value;
+pub mod header_rewrite;
+pub mod layer_order;
 pub mod map_request_body;
 pub mod map_response_body;
+pub mod method_override;
+pub mod mirror;
 pub mod normalize_path;
+pub mod options_trace;
+pub mod prometheus;
 pub mod propagate_headers;
 pub mod proxy_auth;
+pub mod rate_limit;
+pub mod redact;
+pub mod redirect;
 pub mod remove_header;
 pub mod request_id;
 pub mod required_header;
@@ -40,10 +58,16 @@ pub mod retry;
 pub mod sensitive_headers;
 pub mod set_header;
 pub mod set_status;
+pub mod sticky_session;
 pub mod timeout;
 pub mod trace;
+pub mod trace_context;
+pub mod traffic_size;
+pub mod traffic_stats;
 pub mod traffic_writer;
+pub mod transform_body;
 pub mod ua;
+pub mod uri_rewrite;
 pub mod validate_request;
 
 #[cfg(feature = "telemetry")]
@@ -55,3 +79,10 @@ pub(crate) mod util;
 pub mod compression;
 #[cfg(feature = "compression")]
 pub mod decompression;
+
+#[cfg(feature = "conditional")]
+pub mod conditional;
+#[cfg(feature = "session")]
+pub mod session;
+#[cfg(feature = "webhook")]
+pub mod webhook;
@@ -0,0 +1,495 @@
+//! Middleware to handle `OPTIONS *` and `TRACE` requests as defined in [RFC 9110].
+//!
+//! The [`OptionsTraceLayer`] answers server-wide `OPTIONS *` requests with the
+//! configured `Allow` header, and either echoes `TRACE` requests back
+//! (as `message/http`, excluding credentials and cookies) or rejects them with
+//! `405 Method Not Allowed`. `TRACE` echoing is disabled by default,
+//! as it can leak sensitive information to (malicious) scripts.
+//!
+//! When used in proxy mode the `Max-Forwards` rules of [RFC 9110, section 7.6.2]
+//! apply instead: `OPTIONS` and `TRACE` requests are only answered by the proxy itself
+//! when their `Max-Forwards` header is `0`, otherwise the header is decremented
+//! and the request is forwarded to the inner service.
+//!
+//! All other requests are passed through as-is.
+//!
+//! [RFC 9110]: https://www.rfc-editor.org/rfc/rfc9110.html
+//! [RFC 9110, section 7.6.2]: https://www.rfc-editor.org/rfc/rfc9110.html#section-7.6.2
+//!
+//! # Example
+//!
+//! ```
+//! use rama_http::layer::options_trace::OptionsTraceLayer;
+//! use rama_http::{Body, Method, Request, Response, StatusCode};
+//! use rama_core::service::service_fn;
+//! use rama_core::{Context, Service, Layer};
+//! use rama_core::error::BoxError;
+//! use std::convert::Infallible;
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), BoxError> {
+//! let service = OptionsTraceLayer::new().layer(service_fn(|_req: Request| async {
+//!     Ok::<_, Infallible>(Response::new(Body::empty()))
+//! }));
+//!
+//! let req = Request::builder()
+//!     .method(Method::OPTIONS)
+//!     .uri("*")
+//!     .body(Body::empty())
+//!     .unwrap();
+//! let resp = service.serve(Context::default(), req).await?;
+//! assert_eq!(resp.status(), StatusCode::OK);
+//! assert_eq!(resp.headers()["allow"], "GET, HEAD, POST, PUT, DELETE, PATCH, OPTIONS");
+//!
+//! let req = Request::builder()
+//!     .method(Method::TRACE)
+//!     .uri("/")
+//!     .body(Body::empty())
+//!     .unwrap();
+//! let resp = service.serve(Context::default(), req).await?;
+//! assert_eq!(resp.status(), StatusCode::METHOD_NOT_ALLOWED);
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{
+    header::{
+        ALLOW, AUTHORIZATION, CONTENT_LENGTH, CONTENT_TYPE, COOKIE, MAX_FORWARDS,
+        PROXY_AUTHORIZATION,
+    },
+    Body, HeaderValue, Method, Request, Response, StatusCode,
+};
+use rama_core::{Context, Layer, Service};
+use rama_utils::macros::define_inner_service_accessors;
+use std::fmt;
+
+const DEFAULT_ALLOW: &str = "GET, HEAD, POST, PUT, DELETE, PATCH, OPTIONS";
+const DEFAULT_ALLOW_WITH_TRACE: &str = "GET, HEAD, POST, PUT, DELETE, PATCH, OPTIONS, TRACE";
+
+/// Layer that applies the [`OptionsTraceService`] middleware.
+///
+/// See the [module docs](self) for more details.
+#[derive(Debug, Clone, Default)]
+pub struct OptionsTraceLayer {
+    config: OptionsTraceConfig,
+}
+
+#[derive(Debug, Clone, Default)]
+struct OptionsTraceConfig {
+    allow: Option<HeaderValue>,
+    trace: bool,
+    proxy: bool,
+}
+
+impl OptionsTraceLayer {
+    /// Create a new [`OptionsTraceLayer`] for an origin server,
+    /// with `TRACE` echoing disabled.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a new [`OptionsTraceLayer`] for a proxy,
+    /// applying the `Max-Forwards` rules, with `TRACE` echoing disabled.
+    pub fn proxy() -> Self {
+        Self {
+            config: OptionsTraceConfig {
+                proxy: true,
+                ..Default::default()
+            },
+        }
+    }
+
+    /// Define the `Allow` header value used in responses to `OPTIONS` requests
+    /// answered by this middleware, and `405` responses to `TRACE` requests.
+    ///
+    /// Defaults to the common http methods, including `TRACE` only if enabled.
+    pub fn allow(mut self, allow: HeaderValue) -> Self {
+        self.config.allow = Some(allow);
+        self
+    }
+
+    /// Define the `Allow` header value used in responses to `OPTIONS` requests
+    /// answered by this middleware, and `405` responses to `TRACE` requests.
+    ///
+    /// Defaults to the common http methods, including `TRACE` only if enabled.
+    pub fn set_allow(&mut self, allow: HeaderValue) -> &mut Self {
+        self.config.allow = Some(allow);
+        self
+    }
+
+    /// Enable or disable echoing `TRACE` requests.
+    ///
+    /// Disabled by default, in which case `TRACE` requests are rejected.
+    pub fn trace(mut self, enabled: bool) -> Self {
+        self.config.trace = enabled;
+        self
+    }
+
+    /// Enable or disable echoing `TRACE` requests.
+    ///
+    /// Disabled by default, in which case `TRACE` requests are rejected.
+    pub fn set_trace(&mut self, enabled: bool) -> &mut Self {
+        self.config.trace = enabled;
+        self
+    }
+}
+
+impl<S> Layer<S> for OptionsTraceLayer {
+    type Service = OptionsTraceService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        OptionsTraceService {
+            inner,
+            config: self.config.clone(),
+        }
+    }
+}
+
+/// Middleware which handles `OPTIONS *` and `TRACE` requests.
+///
+/// See the [module docs](self) for more details.
+pub struct OptionsTraceService<S> {
+    inner: S,
+    config: OptionsTraceConfig,
+}
+
+impl<S> OptionsTraceService<S> {
+    /// Create a new [`OptionsTraceService`] for an origin server,
+    /// with `TRACE` echoing disabled.
+    pub fn new(inner: S) -> Self {
+        OptionsTraceLayer::new().layer(inner)
+    }
+
+    /// Create a new [`OptionsTraceService`] for a proxy,
+    /// applying the `Max-Forwards` rules, with `TRACE` echoing disabled.
+    pub fn proxy(inner: S) -> Self {
+        OptionsTraceLayer::proxy().layer(inner)
+    }
+
+    define_inner_service_accessors!();
+}
+
+impl<S: fmt::Debug> fmt::Debug for OptionsTraceService<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OptionsTraceService")
+            .field("inner", &self.inner)
+            .field("config", &self.config)
+            .finish()
+    }
+}
+
+impl<S: Clone> Clone for OptionsTraceService<S> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            config: self.config.clone(),
+        }
+    }
+}
+
+impl<S> OptionsTraceService<S> {
+    fn allow(&self) -> HeaderValue {
+        self.config.allow.clone().unwrap_or_else(|| {
+            HeaderValue::from_static(if self.config.trace {
+                DEFAULT_ALLOW_WITH_TRACE
+            } else {
+                DEFAULT_ALLOW
+            })
+        })
+    }
+
+    fn options_response<ResBody: Default>(&self) -> Response<ResBody> {
+        Response::builder()
+            .status(StatusCode::OK)
+            .header(ALLOW, self.allow())
+            .header(CONTENT_LENGTH, 0)
+            .body(ResBody::default())
+            .unwrap()
+    }
+
+    fn trace_response<ReqBody, ResBody>(&self, req: &Request<ReqBody>) -> Response<ResBody>
+    where
+        ResBody: From<Body> + Default,
+    {
+        if !self.config.trace {
+            return Response::builder()
+                .status(StatusCode::METHOD_NOT_ALLOWED)
+                .header(ALLOW, self.allow())
+                .body(ResBody::default())
+                .unwrap();
+        }
+
+        let message = trace_message(req);
+        Response::builder()
+            .status(StatusCode::OK)
+            .header(CONTENT_TYPE, "message/http")
+            .header(CONTENT_LENGTH, message.len())
+            .body(Body::from(message).into())
+            .unwrap()
+    }
+}
+
+/// Reconstruct the received request message (head only),
+/// excluding the fields likely to contain sensitive data.
+fn trace_message<B>(req: &Request<B>) -> Vec<u8> {
+    let mut message = format!(
+        "{} {} {:?}\r\n",
+        req.method(),
+        req.uri()
+            .path_and_query()
+            .map(|pq| pq.as_str())
+            .unwrap_or("/"),
+        req.version()
+    )
+    .into_bytes();
+    for (name, value) in req.headers() {
+        if name == AUTHORIZATION || name == PROXY_AUTHORIZATION || name == COOKIE {
+            continue;
+        }
+        message.extend_from_slice(name.as_str().as_bytes());
+        message.extend_from_slice(b": ");
+        message.extend_from_slice(value.as_bytes());
+        message.extend_from_slice(b"\r\n");
+    }
+    message.extend_from_slice(b"\r\n");
+    message
+}
+
+/// Result of applying the `Max-Forwards` rules to a request.
+enum MaxForwards {
+    /// No (valid) `Max-Forwards` header is present.
+    Absent,
+    /// The request has to be answered by this recipient.
+    Exhausted,
+    /// The request can be forwarded with the (already decremented) header.
+    Forward,
+}
+
+fn apply_max_forwards<B>(req: &mut Request<B>) -> MaxForwards {
+    let Some(max_forwards) = req
+        .headers()
+        .get(MAX_FORWARDS)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse::<u64>().ok())
+    else {
+        return MaxForwards::Absent;
+    };
+    if max_forwards == 0 {
+        MaxForwards::Exhausted
+    } else {
+        req.headers_mut()
+            .insert(MAX_FORWARDS, HeaderValue::from(max_forwards - 1));
+        MaxForwards::Forward
+    }
+}
+
+impl<State, S, ReqBody, ResBody> Service<State, Request<ReqBody>> for OptionsTraceService<S>
+where
+    State: Clone + Send + Sync + 'static,
+    S: Service<State, Request<ReqBody>, Response = Response<ResBody>>,
+    ReqBody: Send + 'static,
+    ResBody: From<Body> + Default + Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+
+    async fn serve(
+        &self,
+        ctx: Context<State>,
+        mut req: Request<ReqBody>,
+    ) -> Result<Self::Response, Self::Error> {
+        let is_options = req.method() == Method::OPTIONS;
+        let is_trace = req.method() == Method::TRACE;
+        if !is_options && !is_trace {
+            return self.inner.serve(ctx, req).await;
+        }
+
+        if self.config.proxy {
+            if is_trace && !self.config.trace {
+                return Ok(self.trace_response(&req));
+            }
+            return match apply_max_forwards(&mut req) {
+                MaxForwards::Exhausted if is_options => Ok(self.options_response()),
+                MaxForwards::Exhausted => Ok(self.trace_response(&req)),
+                MaxForwards::Absent | MaxForwards::Forward => self.inner.serve(ctx, req).await,
+            };
+        }
+
+        if is_trace {
+            Ok(self.trace_response(&req))
+        } else if req.uri() == "*" {
+            Ok(self.options_response())
+        } else {
+            self.inner.serve(ctx, req).await
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dep::http_body_util::BodyExt;
+    use rama_core::service::service_fn;
+    use std::convert::Infallible;
+
+    async fn echo_max_forwards(req: Request) -> Result<Response, Infallible> {
+        Ok(Response::builder()
+            .header(
+                "x-max-forwards",
+                req.headers()
+                    .get(MAX_FORWARDS)
+                    .cloned()
+                    .unwrap_or(HeaderValue::from_static("none")),
+            )
+            .body(Body::from("inner"))
+            .unwrap())
+    }
+
+    fn request(method: Method, uri: &str, max_forwards: Option<&'static str>) -> Request {
+        let mut builder = Request::builder().method(method).uri(uri);
+        if let Some(max_forwards) = max_forwards {
+            builder = builder.header(MAX_FORWARDS, max_forwards);
+        }
+        builder
+            .header(AUTHORIZATION, "Bearer secret")
+            .header("x-foo", "bar")
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    async fn body_string(resp: Response) -> String {
+        let bytes = resp.into_body().collect().await.unwrap().to_bytes();
+        String::from_utf8(bytes.to_vec()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_options_asterisk() {
+        let service = OptionsTraceLayer::new().layer(service_fn(echo_max_forwards));
+
+        let resp = service
+            .serve(Context::default(), request(Method::OPTIONS, "*", None))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.headers()[ALLOW], DEFAULT_ALLOW);
+        assert_eq!(body_string(resp).await, "");
+
+        // resource specific options are left to the inner service
+        let resp = service
+            .serve(Context::default(), request(Method::OPTIONS, "/foo", None))
+            .await
+            .unwrap();
+        assert_eq!(body_string(resp).await, "inner");
+    }
+
+    #[tokio::test]
+    async fn test_trace_disabled_by_default() {
+        let service = OptionsTraceLayer::new().layer(service_fn(echo_max_forwards));
+
+        let resp = service
+            .serve(Context::default(), request(Method::TRACE, "/foo", None))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::METHOD_NOT_ALLOWED);
+        assert_eq!(resp.headers()[ALLOW], DEFAULT_ALLOW);
+    }
+
+    #[tokio::test]
+    async fn test_trace_echo() {
+        let service = OptionsTraceLayer::new()
+            .trace(true)
+            .layer(service_fn(echo_max_forwards));
+
+        let resp = service
+            .serve(Context::default(), request(Method::TRACE, "/foo?a=b", None))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.headers()[CONTENT_TYPE], "message/http");
+        assert_eq!(
+            body_string(resp).await,
+            "TRACE /foo?a=b HTTP/1.1\r\nx-foo: bar\r\n\r\n"
+        );
+
+        let resp = service
+            .serve(Context::default(), request(Method::OPTIONS, "*", None))
+            .await
+            .unwrap();
+        assert_eq!(resp.headers()[ALLOW], DEFAULT_ALLOW_WITH_TRACE);
+    }
+
+    #[tokio::test]
+    async fn test_proxy_max_forwards() {
+        let service = OptionsTraceLayer::proxy()
+            .trace(true)
+            .layer(service_fn(echo_max_forwards));
+
+        // forwarded as-is without max-forwards
+        let resp = service
+            .serve(
+                Context::default(),
+                request(Method::OPTIONS, "http://example.com", None),
+            )
+            .await
+            .unwrap();
+        assert_eq!(resp.headers()["x-max-forwards"], "none");
+
+        // forwarded with decremented max-forwards
+        let resp = service
+            .serve(
+                Context::default(),
+                request(Method::TRACE, "http://example.com/foo", Some("2")),
+            )
+            .await
+            .unwrap();
+        assert_eq!(resp.headers()["x-max-forwards"], "1");
+
+        // answered by the proxy itself
+        let resp = service
+            .serve(
+                Context::default(),
+                request(Method::OPTIONS, "http://example.com", Some("0")),
+            )
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.headers()[ALLOW], DEFAULT_ALLOW_WITH_TRACE);
+
+        let resp = service
+            .serve(
+                Context::default(),
+                request(Method::TRACE, "http://example.com/foo", Some("0")),
+            )
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(
+            body_string(resp).await,
+            "TRACE /foo HTTP/1.1\r\nmax-forwards: 0\r\nx-foo: bar\r\n\r\n"
+        );
+
+        // other methods are never touched
+        let resp = service
+            .serve(
+                Context::default(),
+                request(Method::GET, "http://example.com/foo", Some("0")),
+            )
+            .await
+            .unwrap();
+        assert_eq!(resp.headers()["x-max-forwards"], "0");
+    }
+
+    #[tokio::test]
+    async fn test_proxy_trace_disabled() {
+        let service = OptionsTraceLayer::proxy().layer(service_fn(echo_max_forwards));
+
+        let resp = service
+            .serve(
+                Context::default(),
+                request(Method::TRACE, "http://example.com/foo", Some("3")),
+            )
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::METHOD_NOT_ALLOWED);
+    }
+}
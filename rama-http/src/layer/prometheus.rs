@@ -0,0 +1,608 @@
+//! Middleware to record http metrics, exposed in the Prometheus text format.
+//!
+//! The [`PrometheusMetricsLayer`] records for each request:
+//!
+//! - `http_requests_total`: the amount of requests served;
+//! - `http_request_duration_seconds`: a histogram of the time it took to respond;
+//! - `http_request_size_bytes_total` and `http_response_size_bytes_total`:
+//!   the body sizes of requests and responses, for bodies of which the size is known upfront;
+//! - `http_requests_in_flight`: the amount of requests currently being served.
+//!
+//! All metrics but the last one are labeled using the configured [`MetricLabel`]s,
+//! by default the request method and the response status class (`2xx`, `5xx`, ...).
+//! Requests for which the inner service failed get `error` as their status (class).
+//!
+//! The [`PrometheusMetrics`] is also a [`Service`] which responds with all
+//! recorded metrics in the Prometheus text exposition format, ready to be
+//! mounted on a `/metrics` endpoint.
+//!
+//! # Example
+//!
+//! ```
+//! use rama_http::layer::prometheus::{MetricLabel, PrometheusMetrics, PrometheusMetricsLayer};
+//! use rama_http::service::web::WebService;
+//! use rama_http::{Body, BodyExtractExt, Request, StatusCode};
+//! use rama_core::{Context, Service, Layer};
+//! use rama_core::error::BoxError;
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), BoxError> {
+//! let metrics = PrometheusMetrics::new()
+//!     .with_labels([MetricLabel::Method, MetricLabel::StatusCode]);
+//!
+//! let service = PrometheusMetricsLayer::new(metrics.clone()).layer(
+//!     WebService::<()>::default()
+//!         .get("/", "hello")
+//!         .get("/metrics", metrics),
+//! );
+//!
+//! let resp = service.serve(Context::default(), Request::builder().uri("/").body(Body::empty())?).await?;
+//! assert_eq!(resp.status(), StatusCode::OK);
+//!
+//! let resp = service.serve(Context::default(), Request::builder().uri("/metrics").body(Body::empty())?).await?;
+//! let text = resp.try_into_string().await?;
+//! assert!(text.contains(r#"http_requests_total{method="GET",status="200"} 1"#));
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::dep::http_body::Body as HttpBody;
+use crate::{header, Body, HeaderName, Request, Response};
+use rama_core::{Context, Layer, Service};
+use rama_net::http::RequestContext;
+use rama_utils::macros::define_inner_service_accessors;
+use std::{
+    collections::BTreeMap,
+    convert::Infallible,
+    fmt::{self, Write as _},
+    sync::{
+        atomic::{AtomicI64, Ordering},
+        Arc, Mutex,
+    },
+    time::Instant,
+};
+
+/// The default buckets (in seconds) of the request duration histogram.
+pub const DEFAULT_DURATION_BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// The default namespace used as prefix for all metric names.
+const DEFAULT_NAMESPACE: &str = "http";
+
+/// Content type of the Prometheus text exposition format.
+const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// Label value used when the value could not be determined.
+const UNKNOWN_VALUE: &str = "unknown";
+
+/// Label value used for the status (class) of requests which failed.
+const ERROR_VALUE: &str = "error";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// A label with which the [`PrometheusMetrics`] are recorded.
+pub enum MetricLabel {
+    /// The request method, e.g. `GET`.
+    Method,
+    /// The status code of the response, e.g. `200`.
+    StatusCode,
+    /// The class of the status code of the response, e.g. `2xx`.
+    StatusClass,
+    /// The (destination) host of the request, as found in the [`RequestContext`].
+    Host,
+    /// The http version of the request, e.g. `HTTP/1.1`.
+    Version,
+    /// The value of the given request header, or `unknown` if not present.
+    ///
+    /// Only use this for headers with a small set of possible values,
+    /// as each distinct value creates a new time series.
+    Header(HeaderName),
+}
+
+impl MetricLabel {
+    fn name(&self) -> String {
+        match self {
+            MetricLabel::Method => "method".to_owned(),
+            MetricLabel::StatusCode => "status".to_owned(),
+            MetricLabel::StatusClass => "status_class".to_owned(),
+            MetricLabel::Host => "host".to_owned(),
+            MetricLabel::Version => "version".to_owned(),
+            MetricLabel::Header(name) => name.as_str().replace('-', "_"),
+        }
+    }
+}
+
+/// Shared http metrics, which can be rendered in the Prometheus text format.
+///
+/// Configure it prior to cloning it, as the configuration is not shared
+/// between clones, while the recorded metrics are.
+///
+/// See the [module docs](self) for more details.
+#[derive(Debug, Clone)]
+pub struct PrometheusMetrics {
+    config: Arc<MetricsConfig>,
+    series: Arc<Mutex<BTreeMap<Vec<String>, Series>>>,
+    in_flight: Arc<AtomicI64>,
+}
+
+#[derive(Debug, Clone)]
+struct MetricsConfig {
+    namespace: String,
+    labels: Vec<MetricLabel>,
+    duration_buckets: Vec<f64>,
+}
+
+#[derive(Debug, Clone, Default)]
+struct Series {
+    requests: u64,
+    /// cumulative count per duration bucket
+    duration_buckets: Vec<u64>,
+    duration_sum: f64,
+    request_bytes: u64,
+    response_bytes: u64,
+}
+
+impl Default for PrometheusMetrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PrometheusMetrics {
+    /// Create a new [`PrometheusMetrics`] with the default configuration.
+    pub fn new() -> Self {
+        Self {
+            config: Arc::new(MetricsConfig {
+                namespace: DEFAULT_NAMESPACE.to_owned(),
+                labels: vec![MetricLabel::Method, MetricLabel::StatusClass],
+                duration_buckets: DEFAULT_DURATION_BUCKETS.to_vec(),
+            }),
+            series: Default::default(),
+            in_flight: Default::default(),
+        }
+    }
+
+    /// Define the namespace used as prefix for all metric names.
+    ///
+    /// Defaults to `http`.
+    pub fn with_namespace(mut self, namespace: impl Into<String>) -> Self {
+        self.set_namespace(namespace);
+        self
+    }
+
+    /// Define the namespace used as prefix for all metric names.
+    ///
+    /// Defaults to `http`.
+    pub fn set_namespace(&mut self, namespace: impl Into<String>) -> &mut Self {
+        Arc::make_mut(&mut self.config).namespace = namespace.into();
+        self
+    }
+
+    /// Define the labels with which the metrics are recorded.
+    ///
+    /// Defaults to [`MetricLabel::Method`] and [`MetricLabel::StatusClass`].
+    pub fn with_labels(mut self, labels: impl IntoIterator<Item = MetricLabel>) -> Self {
+        self.set_labels(labels);
+        self
+    }
+
+    /// Define the labels with which the metrics are recorded.
+    ///
+    /// Defaults to [`MetricLabel::Method`] and [`MetricLabel::StatusClass`].
+    pub fn set_labels(&mut self, labels: impl IntoIterator<Item = MetricLabel>) -> &mut Self {
+        Arc::make_mut(&mut self.config).labels = labels.into_iter().collect();
+        self
+    }
+
+    /// Define the upper bounds (in seconds) of the request duration histogram buckets.
+    ///
+    /// Defaults to [`DEFAULT_DURATION_BUCKETS`].
+    pub fn with_duration_buckets(mut self, buckets: impl IntoIterator<Item = f64>) -> Self {
+        self.set_duration_buckets(buckets);
+        self
+    }
+
+    /// Define the upper bounds (in seconds) of the request duration histogram buckets.
+    ///
+    /// Defaults to [`DEFAULT_DURATION_BUCKETS`].
+    pub fn set_duration_buckets(&mut self, buckets: impl IntoIterator<Item = f64>) -> &mut Self {
+        let mut buckets: Vec<f64> = buckets.into_iter().filter(|b| b.is_finite()).collect();
+        buckets.sort_by(f64::total_cmp);
+        buckets.dedup();
+        Arc::make_mut(&mut self.config).duration_buckets = buckets;
+        self
+    }
+
+    /// Render all recorded metrics in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let config = &self.config;
+        let ns = &config.namespace;
+        let label_names: Vec<String> = config.labels.iter().map(MetricLabel::name).collect();
+        let series = self.series.lock().unwrap();
+
+        let mut out = String::new();
+
+        let _ = writeln!(
+            out,
+            "# HELP {ns}_requests_total The total amount of requests served."
+        );
+        let _ = writeln!(out, "# TYPE {ns}_requests_total counter");
+        for (values, s) in series.iter() {
+            let labels = format_labels(&label_names, values, None);
+            let _ = writeln!(out, "{ns}_requests_total{labels} {}", s.requests);
+        }
+
+        let _ = writeln!(
+            out,
+            "# HELP {ns}_request_duration_seconds The time it took to respond to requests."
+        );
+        let _ = writeln!(out, "# TYPE {ns}_request_duration_seconds histogram");
+        for (values, s) in series.iter() {
+            for (bound, count) in config.duration_buckets.iter().zip(&s.duration_buckets) {
+                let labels = format_labels(&label_names, values, Some(&bound.to_string()));
+                let _ = writeln!(out, "{ns}_request_duration_seconds_bucket{labels} {count}");
+            }
+            let labels = format_labels(&label_names, values, Some("+Inf"));
+            let _ = writeln!(
+                out,
+                "{ns}_request_duration_seconds_bucket{labels} {}",
+                s.requests
+            );
+            let labels = format_labels(&label_names, values, None);
+            let _ = writeln!(
+                out,
+                "{ns}_request_duration_seconds_sum{labels} {}",
+                s.duration_sum
+            );
+            let _ = writeln!(
+                out,
+                "{ns}_request_duration_seconds_count{labels} {}",
+                s.requests
+            );
+        }
+
+        for (name, help, bytes) in [
+            (
+                "request_size_bytes_total",
+                "The total size of request bodies, if known upfront.",
+                (|s: &Series| s.request_bytes) as fn(&Series) -> u64,
+            ),
+            (
+                "response_size_bytes_total",
+                "The total size of response bodies, if known upfront.",
+                |s: &Series| s.response_bytes,
+            ),
+        ] {
+            let _ = writeln!(out, "# HELP {ns}_{name} {help}");
+            let _ = writeln!(out, "# TYPE {ns}_{name} counter");
+            for (values, s) in series.iter() {
+                let labels = format_labels(&label_names, values, None);
+                let _ = writeln!(out, "{ns}_{name}{labels} {}", bytes(s));
+            }
+        }
+
+        let _ = writeln!(
+            out,
+            "# HELP {ns}_requests_in_flight The amount of requests currently being served."
+        );
+        let _ = writeln!(out, "# TYPE {ns}_requests_in_flight gauge");
+        let _ = writeln!(
+            out,
+            "{ns}_requests_in_flight {}",
+            self.in_flight.load(Ordering::Relaxed)
+        );
+
+        out
+    }
+
+    /// Clear all recorded metrics.
+    pub fn clear(&self) {
+        self.series.lock().unwrap().clear();
+    }
+
+    fn record(&self, values: Vec<String>, f: impl FnOnce(&mut Series, &[f64])) {
+        let buckets = &self.config.duration_buckets;
+        let mut series = self.series.lock().unwrap();
+        let s = series.entry(values).or_insert_with(|| Series {
+            duration_buckets: vec![0; buckets.len()],
+            ..Default::default()
+        });
+        f(s, buckets)
+    }
+}
+
+/// Formats the labels of a single sample, including the `le` label of histogram buckets.
+fn format_labels(names: &[String], values: &[String], le: Option<&str>) -> String {
+    if names.is_empty() && le.is_none() {
+        return String::new();
+    }
+    let mut out = String::from("{");
+    let mut first = true;
+    let pairs = names
+        .iter()
+        .map(String::as_str)
+        .zip(values.iter().map(String::as_str))
+        .chain(le.map(|le| ("le", le)));
+    for (name, value) in pairs {
+        if !first {
+            out.push(',');
+        }
+        first = false;
+        out.push_str(name);
+        out.push_str("=\"");
+        for c in value.chars() {
+            match c {
+                '\\' => out.push_str("\\\\"),
+                '"' => out.push_str("\\\""),
+                '\n' => out.push_str("\\n"),
+                c => out.push(c),
+            }
+        }
+        out.push('"');
+    }
+    out.push('}');
+    out
+}
+
+impl<State> Service<State, Request> for PrometheusMetrics
+where
+    State: Clone + Send + Sync + 'static,
+{
+    type Response = Response;
+    type Error = Infallible;
+
+    async fn serve(
+        &self,
+        _ctx: Context<State>,
+        _req: Request,
+    ) -> Result<Self::Response, Self::Error> {
+        Ok(Response::builder()
+            .header(header::CONTENT_TYPE, CONTENT_TYPE)
+            .body(Body::from(self.render()))
+            .unwrap())
+    }
+}
+
+/// Layer that applies the [`PrometheusMetricsService`] middleware.
+///
+/// See the [module docs](self) for more details.
+#[derive(Debug, Clone)]
+pub struct PrometheusMetricsLayer {
+    metrics: PrometheusMetrics,
+}
+
+impl PrometheusMetricsLayer {
+    /// Create a new [`PrometheusMetricsLayer`], recording into the given [`PrometheusMetrics`].
+    pub fn new(metrics: PrometheusMetrics) -> Self {
+        Self { metrics }
+    }
+}
+
+impl<S> Layer<S> for PrometheusMetricsLayer {
+    type Service = PrometheusMetricsService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        PrometheusMetricsService {
+            inner,
+            metrics: self.metrics.clone(),
+        }
+    }
+}
+
+/// Middleware which records http metrics into [`PrometheusMetrics`].
+///
+/// See the [module docs](self) for more details.
+pub struct PrometheusMetricsService<S> {
+    inner: S,
+    metrics: PrometheusMetrics,
+}
+
+impl<S> PrometheusMetricsService<S> {
+    define_inner_service_accessors!();
+}
+
+impl<S: fmt::Debug> fmt::Debug for PrometheusMetricsService<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PrometheusMetricsService")
+            .field("inner", &self.inner)
+            .field("metrics", &self.metrics)
+            .finish()
+    }
+}
+
+impl<S: Clone> Clone for PrometheusMetricsService<S> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            metrics: self.metrics.clone(),
+        }
+    }
+}
+
+/// Decrements the in flight requests when dropped,
+/// such that cancelled requests are accounted for as well.
+struct InFlightGuard(Arc<AtomicI64>);
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl<State, S, ReqBody, ResBody> Service<State, Request<ReqBody>> for PrometheusMetricsService<S>
+where
+    State: Clone + Send + Sync + 'static,
+    S: Service<State, Request<ReqBody>, Response = Response<ResBody>>,
+    ReqBody: HttpBody + Send + 'static,
+    ResBody: HttpBody + Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+
+    async fn serve(
+        &self,
+        mut ctx: Context<State>,
+        req: Request<ReqBody>,
+    ) -> Result<Self::Response, Self::Error> {
+        // status related labels are only known once the response is there
+        let mut values: Vec<Option<String>> = Vec::with_capacity(self.metrics.config.labels.len());
+        for label in self.metrics.config.labels.iter() {
+            values.push(match label {
+                MetricLabel::Method => Some(req.method().as_str().to_owned()),
+                MetricLabel::Version => Some(format!("{:?}", req.version())),
+                MetricLabel::Host => Some(
+                    ctx.get_or_try_insert_with_ctx::<RequestContext, _>(|ctx| {
+                        (ctx, &req).try_into()
+                    })
+                    .map(|request_ctx| request_ctx.authority.host().to_string())
+                    .unwrap_or_else(|_| UNKNOWN_VALUE.to_owned()),
+                ),
+                MetricLabel::Header(name) => Some(
+                    req.headers()
+                        .get(name)
+                        .and_then(|value| value.to_str().ok())
+                        .unwrap_or(UNKNOWN_VALUE)
+                        .to_owned(),
+                ),
+                MetricLabel::StatusCode | MetricLabel::StatusClass => None,
+            });
+        }
+        let request_bytes = req.body().size_hint().exact().unwrap_or_default();
+
+        self.metrics.in_flight.fetch_add(1, Ordering::Relaxed);
+        let in_flight = InFlightGuard(self.metrics.in_flight.clone());
+
+        let start = Instant::now();
+        let result = self.inner.serve(ctx, req).await;
+        let duration = start.elapsed().as_secs_f64();
+        drop(in_flight);
+
+        let status = result.as_ref().ok().map(|resp| resp.status());
+        let values = values
+            .into_iter()
+            .zip(self.metrics.config.labels.iter())
+            .map(|(value, label)| match (value, label, status) {
+                (Some(value), _, _) => value,
+                (None, MetricLabel::StatusCode, Some(status)) => status.as_str().to_owned(),
+                (None, _, Some(status)) => format!("{}xx", status.as_u16() / 100),
+                (None, _, None) => ERROR_VALUE.to_owned(),
+            })
+            .collect();
+        let response_bytes = result
+            .as_ref()
+            .ok()
+            .and_then(|resp| resp.body().size_hint().exact())
+            .unwrap_or_default();
+
+        self.metrics.record(values, |s, buckets| {
+            s.requests += 1;
+            s.duration_sum += duration;
+            for (bound, count) in buckets.iter().zip(s.duration_buckets.iter_mut()) {
+                if duration <= *bound {
+                    *count += 1;
+                }
+            }
+            s.request_bytes += request_bytes;
+            s.response_bytes += response_bytes;
+        });
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::StatusCode;
+    use rama_core::service::service_fn;
+
+    async fn serve(
+        metrics: &PrometheusMetrics,
+        path: &'static str,
+    ) -> Result<Response, &'static str> {
+        let service = PrometheusMetricsLayer::new(metrics.clone()).layer(service_fn(
+            |req: Request| async move {
+                match req.uri().path() {
+                    "/fail" => Err("fail"),
+                    "/missing" => {
+                        let mut resp = Response::new(Body::empty());
+                        *resp.status_mut() = StatusCode::NOT_FOUND;
+                        Ok(resp)
+                    }
+                    _ => Ok(Response::new(Body::from("hello"))),
+                }
+            },
+        ));
+        let req = Request::builder()
+            .method("POST")
+            .uri(path)
+            .header("x-tenant", "acme")
+            .body(Body::from("abc"))
+            .unwrap();
+        service.serve(Context::default(), req).await
+    }
+
+    #[tokio::test]
+    async fn test_prometheus_metrics() {
+        let metrics = PrometheusMetrics::new().with_duration_buckets([60.0]);
+        serve(&metrics, "/").await.unwrap();
+        serve(&metrics, "/").await.unwrap();
+        serve(&metrics, "/missing").await.unwrap();
+        serve(&metrics, "/fail").await.unwrap_err();
+
+        let text = metrics.render();
+        for expected in [
+            "# TYPE http_requests_total counter",
+            r#"http_requests_total{method="POST",status_class="2xx"} 2"#,
+            r#"http_requests_total{method="POST",status_class="4xx"} 1"#,
+            r#"http_requests_total{method="POST",status_class="error"} 1"#,
+            "# TYPE http_request_duration_seconds histogram",
+            r#"http_request_duration_seconds_bucket{method="POST",status_class="2xx",le="60"} 2"#,
+            r#"http_request_duration_seconds_bucket{method="POST",status_class="2xx",le="+Inf"} 2"#,
+            r#"http_request_duration_seconds_count{method="POST",status_class="2xx"} 2"#,
+            r#"http_request_size_bytes_total{method="POST",status_class="2xx"} 6"#,
+            r#"http_response_size_bytes_total{method="POST",status_class="2xx"} 10"#,
+            "http_requests_in_flight 0",
+        ] {
+            assert!(text.contains(expected), "missing {expected:?} in:\n{text}");
+        }
+
+        metrics.clear();
+        assert!(!metrics.render().contains("http_requests_total{"));
+    }
+
+    #[tokio::test]
+    async fn test_prometheus_metrics_custom_labels() {
+        let metrics = PrometheusMetrics::new()
+            .with_namespace("proxy")
+            .with_labels([
+                MetricLabel::StatusCode,
+                MetricLabel::Header(HeaderName::from_static("x-tenant")),
+            ]);
+        serve(&metrics, "/missing").await.unwrap();
+
+        let text = metrics.render();
+        assert!(text.contains(r#"proxy_requests_total{status="404",x_tenant="acme"} 1"#));
+    }
+
+    #[tokio::test]
+    async fn test_prometheus_metrics_endpoint() {
+        let metrics = PrometheusMetrics::new();
+        serve(&metrics, "/").await.unwrap();
+
+        let resp = metrics
+            .serve(Context::<()>::default(), Request::new(Body::empty()))
+            .await
+            .unwrap();
+        assert_eq!(resp.headers()[header::CONTENT_TYPE], CONTENT_TYPE);
+    }
+
+    #[test]
+    fn test_format_labels_escaping() {
+        assert_eq!(format_labels(&[], &[], None), "");
+        assert_eq!(
+            format_labels(&["a".to_owned()], &["x\"y\\z\n".to_owned()], Some("0.5")),
+            r#"{a="x\"y\\z\n",le="0.5"}"#
+        );
+    }
+}
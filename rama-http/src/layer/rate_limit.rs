@@ -0,0 +1,543 @@
+//! Middleware to rate limit http requests, responding with `429 Too Many Requests`
+//! or `503 Service Unavailable`.
+//!
+//! The [`RateLimitLayer`] checks each request against a limit [`Policy`],
+//! usually a [`RateLimitPolicy`] or [`KeyedConcurrentPolicy`]
+//! keyed by one of the keys provided in this module:
+//!
+//! - [`ClientIpKey`]: the ip address of the client;
+//! - [`HeaderKey`]: the value of a request header (e.g. an api key);
+//! - [`UserIdKey`]: the [`UserId`] of the authenticated (proxy) user.
+//!
+//! Requests rejected by a [`RateLimitPolicy`] get a `429 Too Many Requests` response,
+//! with a `Retry-After` header indicating when the client can try again.
+//! Requests rejected because a concurrency limit is reached, e.g. the
+//! in-flight requests per client of a [`KeyedConcurrentPolicy`],
+//! or shed by a [`LoadShedPolicy`] because the service is overloaded,
+//! get a `503 Service Unavailable` response instead.
+//!
+//! Different limits per class of requests can be configured by using
+//! a matcher policy map as the policy, as documented in [`rama_core::layer::limit::policy`].
+//!
+//! # Example
+//!
+//! ```
+//! use rama_http::layer::rate_limit::{ClientIpKey, RateLimitLayer};
+//! use rama_http::{Body, Request, Response, StatusCode};
+//! use rama_core::layer::limit::policy::{Rate, RateLimitPolicy};
+//! use rama_core::service::service_fn;
+//! use rama_core::{Context, Service, Layer};
+//! use rama_core::error::BoxError;
+//! use rama_net::stream::SocketInfo;
+//! use std::convert::Infallible;
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), BoxError> {
+//! let service = RateLimitLayer::new(RateLimitPolicy::new(
+//!     Rate::per_minute(1),
+//!     ClientIpKey::new(),
+//! ))
+//! .layer(service_fn(|_req: Request| async {
+//!     Ok::<_, Infallible>(Response::new(Body::empty()))
+//! }));
+//!
+//! let mut ctx = Context::default();
+//! ctx.insert(SocketInfo::new(None, ([127, 0, 0, 1], 8080).into()));
+//!
+//! let resp = service.serve(ctx.clone(), Request::new(Body::empty())).await?;
+//! assert_eq!(resp.status(), StatusCode::OK);
+//!
+//! let resp = service.serve(ctx, Request::new(Body::empty())).await?;
+//! assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+//! assert_eq!(resp.headers()["retry-after"], "60");
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{header::RETRY_AFTER, HeaderName, HeaderValue, Request, Response, StatusCode};
+use rama_core::{
+    error::BoxError,
+    layer::limit::policy::{
+        LimitReached, LoadShed, Policy, PolicyOutput, RateLimitKey, RateLimited,
+    },
+    Context, Layer, Service,
+};
+use rama_net::{forwarded::Forwarded, stream::SocketInfo, user::UserId};
+use rama_utils::macros::define_inner_service_accessors;
+use std::{fmt, net::IpAddr};
+
+#[cfg(doc)]
+use rama_core::layer::limit::policy::{KeyedConcurrentPolicy, LoadShedPolicy, RateLimitPolicy};
+
+/// Layer that applies the [`RateLimitService`] middleware.
+///
+/// See the [module docs](self) for more details.
+pub struct RateLimitLayer<P> {
+    policy: P,
+}
+
+impl<P: fmt::Debug> fmt::Debug for RateLimitLayer<P> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RateLimitLayer")
+            .field("policy", &self.policy)
+            .finish()
+    }
+}
+
+impl<P: Clone> Clone for RateLimitLayer<P> {
+    fn clone(&self) -> Self {
+        Self {
+            policy: self.policy.clone(),
+        }
+    }
+}
+
+impl<P> RateLimitLayer<P> {
+    /// Create a new [`RateLimitLayer`] using the given limit [`Policy`].
+    pub const fn new(policy: P) -> Self {
+        Self { policy }
+    }
+}
+
+impl<S, P: Clone> Layer<S> for RateLimitLayer<P> {
+    type Service = RateLimitService<S, P>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RateLimitService {
+            inner,
+            policy: self.policy.clone(),
+        }
+    }
+}
+
+/// Middleware which rate limits requests using a limit [`Policy`],
+/// responding with `429 Too Many Requests` to rate limited requests.
+///
+/// See the [module docs](self) for more details.
+pub struct RateLimitService<S, P> {
+    inner: S,
+    policy: P,
+}
+
+impl<S, P> RateLimitService<S, P> {
+    /// Create a new [`RateLimitService`] using the given limit [`Policy`].
+    pub const fn new(inner: S, policy: P) -> Self {
+        Self { inner, policy }
+    }
+
+    define_inner_service_accessors!();
+}
+
+impl<S: fmt::Debug, P: fmt::Debug> fmt::Debug for RateLimitService<S, P> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RateLimitService")
+            .field("inner", &self.inner)
+            .field("policy", &self.policy)
+            .finish()
+    }
+}
+
+impl<S: Clone, P: Clone> Clone for RateLimitService<S, P> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            policy: self.policy.clone(),
+        }
+    }
+}
+
+impl<State, S, P, ReqBody, ResBody> Service<State, Request<ReqBody>> for RateLimitService<S, P>
+where
+    State: Clone + Send + Sync + 'static,
+    S: Service<State, Request<ReqBody>, Response = Response<ResBody>, Error: Into<BoxError>>,
+    P: Policy<State, Request<ReqBody>, Error: Into<BoxError>>,
+    ReqBody: Send + Sync + 'static,
+    ResBody: Default + Send + 'static,
+{
+    type Response = Response<ResBody>;
+    type Error = BoxError;
+
+    async fn serve(
+        &self,
+        mut ctx: Context<State>,
+        mut req: Request<ReqBody>,
+    ) -> Result<Self::Response, Self::Error> {
+        loop {
+            let result = self.policy.check(ctx, req).await;
+            ctx = result.ctx;
+            req = result.request;
+
+            match result.output {
+                PolicyOutput::Ready(guard) => {
+                    let resp = self.inner.serve(ctx, req).await.map_err(Into::into);
+                    drop(guard);
+                    return resp;
+                }
+                PolicyOutput::Abort(err) => {
+                    let err = err.into();
+                    if let Some(rate_limited) = err.downcast_ref::<RateLimited>() {
+                        tracing::debug!(
+                            uri = %req.uri(),
+                            retry_after = ?rate_limited.retry_after(),
+                            "request rate limited",
+                        );
+                        return Ok(too_many_requests(rate_limited));
+                    }
+                    if err.is::<LimitReached>() {
+                        tracing::debug!(uri = %req.uri(), "request concurrency limit reached");
+                        return Ok(service_unavailable());
+                    }
+                    if err.is::<LoadShed>() {
+                        tracing::debug!(uri = %req.uri(), "request shed: service overloaded");
+                        return Ok(service_unavailable());
+                    }
+                    return Err(err);
+                }
+                PolicyOutput::Retry => (),
+            }
+        }
+    }
+}
+
+fn too_many_requests<ResBody: Default>(rate_limited: &RateLimited) -> Response<ResBody> {
+    // Retry-After is expressed in (whole) seconds, round up to not invite early retries
+    let retry_after = rate_limited.retry_after();
+    let secs = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+    Response::builder()
+        .status(StatusCode::TOO_MANY_REQUESTS)
+        .header(RETRY_AFTER, secs)
+        .body(ResBody::default())
+        .unwrap()
+}
+
+fn service_unavailable<ResBody: Default>() -> Response<ResBody> {
+    let mut resp = Response::new(ResBody::default());
+    *resp.status_mut() = StatusCode::SERVICE_UNAVAILABLE;
+    resp
+}
+
+/// A [`RateLimitKey`] which keys requests by the ip address of the client.
+///
+/// By default the peer address of the [`SocketInfo`] is used. Use [`ClientIpKey::forwarded`]
+/// to prefer the client ip of the [`Forwarded`] information instead, which should only
+/// be done when the service is behind a trusted (reverse) proxy, as it can be spoofed otherwise.
+#[derive(Debug, Clone, Default)]
+pub struct ClientIpKey {
+    forwarded: bool,
+}
+
+impl ClientIpKey {
+    /// Create a new [`ClientIpKey`], using the peer address of the [`SocketInfo`].
+    pub const fn new() -> Self {
+        Self { forwarded: false }
+    }
+
+    /// Create a new [`ClientIpKey`], using the client ip of the [`Forwarded`] information,
+    /// falling back to the peer address of the [`SocketInfo`].
+    pub const fn forwarded() -> Self {
+        Self { forwarded: true }
+    }
+}
+
+impl<State, Request> RateLimitKey<State, Request> for ClientIpKey {
+    type Key = IpAddr;
+
+    fn key(&self, ctx: &Context<State>, _req: &Request) -> Option<Self::Key> {
+        self.forwarded
+            .then(|| ctx.get::<Forwarded>().and_then(|f| f.client_ip()))
+            .flatten()
+            .or_else(|| ctx.get::<SocketInfo>().map(|info| info.peer_addr().ip()))
+    }
+}
+
+/// A [`RateLimitKey`] which keys requests by the value of a request header,
+/// e.g. an api key.
+///
+/// Requests without the header are not rate limited by the policy using this key.
+#[derive(Debug, Clone)]
+pub struct HeaderKey {
+    name: HeaderName,
+}
+
+impl HeaderKey {
+    /// Create a new [`HeaderKey`] for the given header name.
+    pub const fn new(name: HeaderName) -> Self {
+        Self { name }
+    }
+}
+
+impl<State, Body> RateLimitKey<State, Request<Body>> for HeaderKey {
+    type Key = HeaderValue;
+
+    fn key(&self, _ctx: &Context<State>, req: &Request<Body>) -> Option<Self::Key> {
+        req.headers().get(&self.name).cloned()
+    }
+}
+
+/// A [`RateLimitKey`] which keys requests by the [`UserId`] of the authenticated user,
+/// as inserted in the [`Context`] by (proxy) authorization layers.
+///
+/// Requests of unauthenticated users are not rate limited by the policy using this key.
+#[derive(Debug, Clone, Default)]
+#[non_exhaustive]
+pub struct UserIdKey;
+
+impl UserIdKey {
+    /// Create a new [`UserIdKey`].
+    pub const fn new() -> Self {
+        Self
+    }
+}
+
+impl<State, Request> RateLimitKey<State, Request> for UserIdKey {
+    type Key = UserId;
+
+    fn key(&self, ctx: &Context<State>, _req: &Request) -> Option<Self::Key> {
+        ctx.get::<UserId>().cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{matcher::HttpMatcher, Body};
+    use rama_core::{
+        layer::limit::policy::{
+            ConcurrentPolicy, KeyedConcurrentPolicy, LoadShedPolicy, Rate, RateLimitPolicy,
+        },
+        service::service_fn,
+    };
+    use rama_net::stream::SocketInfo;
+    use std::{convert::Infallible, sync::Arc, time::Duration};
+
+    async fn ok(_req: Request) -> Result<Response, Infallible> {
+        Ok(Response::new(Body::empty()))
+    }
+
+    fn ctx_with_peer(ip: [u8; 4]) -> Context<()> {
+        let mut ctx = Context::default();
+        ctx.insert(SocketInfo::new(None, (ip, 1234).into()));
+        ctx
+    }
+
+    #[tokio::test]
+    async fn test_rate_limit_client_ip() {
+        let service = RateLimitLayer::new(RateLimitPolicy::new(
+            Rate::new(2, Duration::from_secs(10)),
+            ClientIpKey::new(),
+        ))
+        .layer(service_fn(ok));
+
+        for _ in 0..2 {
+            let resp = service
+                .serve(ctx_with_peer([10, 0, 0, 1]), Request::new(Body::empty()))
+                .await
+                .unwrap();
+            assert_eq!(resp.status(), StatusCode::OK);
+        }
+        let resp = service
+            .serve(ctx_with_peer([10, 0, 0, 1]), Request::new(Body::empty()))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+        let retry_after: u64 = resp.headers()[RETRY_AFTER]
+            .to_str()
+            .unwrap()
+            .parse()
+            .unwrap();
+        assert!((1..=5).contains(&retry_after));
+
+        let resp = service
+            .serve(ctx_with_peer([10, 0, 0, 2]), Request::new(Body::empty()))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        // no key, no limit
+        for _ in 0..3 {
+            let resp = service
+                .serve(Context::default(), Request::new(Body::empty()))
+                .await
+                .unwrap();
+            assert_eq!(resp.status(), StatusCode::OK);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_rate_limit_key_classes() {
+        let service = RateLimitLayer::new(Arc::new((
+            vec![(
+                HttpMatcher::header_exists(HeaderName::from_static("x-api-key")),
+                RateLimitPolicy::new(
+                    Rate::per_minute(2),
+                    HeaderKey::new(HeaderName::from_static("x-api-key")),
+                ),
+            )],
+            RateLimitPolicy::new(
+                Rate::per_minute(1),
+                HeaderKey::new(HeaderName::from_static("x-api-key")),
+            ),
+        )))
+        .layer(service_fn(ok));
+
+        let req = || {
+            Request::builder()
+                .header("x-api-key", "secret")
+                .body(Body::empty())
+                .unwrap()
+        };
+        assert_eq!(
+            service
+                .serve(Context::default(), req())
+                .await
+                .unwrap()
+                .status(),
+            StatusCode::OK
+        );
+        assert_eq!(
+            service
+                .serve(Context::default(), req())
+                .await
+                .unwrap()
+                .status(),
+            StatusCode::OK
+        );
+        assert_eq!(
+            service
+                .serve(Context::default(), req())
+                .await
+                .unwrap()
+                .status(),
+            StatusCode::TOO_MANY_REQUESTS
+        );
+    }
+
+    #[tokio::test]
+    async fn test_rate_limit_user_id() {
+        let service =
+            RateLimitLayer::new(RateLimitPolicy::new(Rate::per_minute(1), UserIdKey::new()))
+                .layer(service_fn(ok));
+
+        let ctx = |user: &str| {
+            let mut ctx = Context::<()>::default();
+            ctx.insert(UserId::Username(user.to_owned()));
+            ctx
+        };
+
+        for (user, status) in [
+            ("john", StatusCode::OK),
+            ("jane", StatusCode::OK),
+            ("john", StatusCode::TOO_MANY_REQUESTS),
+        ] {
+            let resp = service
+                .serve(ctx(user), Request::new(Body::empty()))
+                .await
+                .unwrap();
+            assert_eq!(resp.status(), status);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_concurrency_limit_reached() {
+        let service = RateLimitLayer::new(ConcurrentPolicy::max(0)).layer(service_fn(ok));
+        let resp = service
+            .serve(Context::default(), Request::new(Body::empty()))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    #[tokio::test]
+    async fn test_load_shed() {
+        let service =
+            RateLimitLayer::new(LoadShedPolicy::new(Duration::from_nanos(1)).max_shed_ratio(1.0))
+                .layer(service_fn(|req: Request| async move {
+                    tokio::time::sleep(Duration::from_millis(1)).await;
+                    ok(req).await
+                }));
+
+        let resp = service
+            .serve(Context::default(), Request::new(Body::empty()))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        // the latency of the first request exceeds the target by far,
+        // such that (almost) all requests are shed from now on
+        let mut shed = 0;
+        for _ in 0..4 {
+            let resp = service
+                .serve(Context::default(), Request::new(Body::empty()))
+                .await
+                .unwrap();
+            if resp.status() == StatusCode::SERVICE_UNAVAILABLE {
+                shed += 1;
+            }
+        }
+        assert!(shed >= 3);
+    }
+
+    #[tokio::test]
+    async fn test_keyed_concurrency_limit_client_ip() {
+        let (tx, rx) = tokio::sync::oneshot::channel::<()>();
+        let rx = Arc::new(tokio::sync::Mutex::new(Some(rx)));
+        let service = RateLimitLayer::new(KeyedConcurrentPolicy::new(1, ClientIpKey::new())).layer(
+            service_fn(move |req: Request| {
+                let rx = rx.clone();
+                async move {
+                    if req.uri().path() == "/slow" {
+                        let rx = rx.lock().await.take().unwrap();
+                        let _ = rx.await;
+                    }
+                    ok(req).await
+                }
+            }),
+        );
+
+        let slow = tokio::spawn({
+            let service = service.clone();
+            async move {
+                let req = Request::builder().uri("/slow").body(Body::empty()).unwrap();
+                service.serve(ctx_with_peer([10, 0, 0, 1]), req).await
+            }
+        });
+        while service
+            .serve(ctx_with_peer([10, 0, 0, 1]), Request::new(Body::empty()))
+            .await
+            .unwrap()
+            .status()
+            == StatusCode::OK
+        {
+            // wait for the slow request to be in flight
+            tokio::task::yield_now().await;
+        }
+
+        // other clients have their own limit
+        let resp = service
+            .serve(ctx_with_peer([10, 0, 0, 2]), Request::new(Body::empty()))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        tx.send(()).unwrap();
+        assert_eq!(slow.await.unwrap().unwrap().status(), StatusCode::OK);
+        let resp = service
+            .serve(ctx_with_peer([10, 0, 0, 1]), Request::new(Body::empty()))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+
+    #[test]
+    fn test_too_many_requests_retry_after_rounding() {
+        for (retry_after, expected) in [
+            (Duration::from_millis(1), "1"),
+            (Duration::from_secs(2), "2"),
+            (Duration::from_millis(2001), "3"),
+        ] {
+            let err = RateLimited::new(retry_after);
+            let resp: Response<Body> = too_many_requests(&err);
+            assert_eq!(resp.headers()[RETRY_AFTER], expected);
+        }
+    }
+}
@@ -0,0 +1,541 @@
+//! Middleware to redact sensitive data from captured http traffic.
+//!
+//! The [`RedactLayer`] does not modify the request or response that flows
+//! through the service stack. Instead it inserts a [`Redactor`] into the [`Context`],
+//! which is respected by the middleware that captures traffic, such as the
+//! [`RequestWriterService`] and [`ResponseWriterService`]. This way captures
+//! can be shared safely without leaking credentials or other secrets.
+//!
+//! The [`Redactor`] can also be used directly, e.g. by [`DefaultMakeSpan`]
+//! to redact the headers included in access logs.
+//!
+//! By default the [`Redactor`] masks the `Authorization`, `Proxy-Authorization`,
+//! `Cookie` and `Set-Cookie` headers. Fields within JSON bodies can be masked
+//! by a dot-separated path, where `*` matches any object key or array index
+//! and arrays are traversed transparently by key segments.
+//!
+//! [`RequestWriterService`]: crate::layer::traffic_writer::RequestWriterService
+//! [`ResponseWriterService`]: crate::layer::traffic_writer::ResponseWriterService
+//! [`DefaultMakeSpan`]: crate::layer::trace::DefaultMakeSpan
+//!
+//! # Example
+//!
+//! ```
+//! use rama_http::layer::redact::{RedactLayer, Redactor};
+//! use rama_http::layer::traffic_writer::RequestWriterLayer;
+//! use rama_http::{Body, Request, Response, header::AUTHORIZATION};
+//! use rama_http::dep::http_body_util::BodyExt;
+//! use rama_core::service::service_fn;
+//! use rama_core::{Context, Service, Layer};
+//! use rama_core::error::BoxError;
+//! use std::convert::Infallible;
+//!
+//! async fn handle(req: Request) -> Result<Response, Infallible> {
+//!     // the service itself still receives the original request
+//!     assert_eq!(req.headers()[AUTHORIZATION], "Bearer secret");
+//!     Ok(Response::new(Body::empty()))
+//! }
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), BoxError> {
+//! let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+//!
+//! let service = (
+//!     RedactLayer::new(Redactor::default().json_path("user.password")),
+//!     RequestWriterLayer::new(tx),
+//! )
+//!     .layer(service_fn(handle));
+//!
+//! let request = Request::builder()
+//!     .header(AUTHORIZATION, "Bearer secret")
+//!     .header("content-type", "application/json")
+//!     .body(Body::from(r#"{"user":{"name":"john","password":"hunter2"}}"#))
+//!     .unwrap();
+//! service.serve(Context::default(), request).await?;
+//!
+//! let captured: Request = rx.recv().await.unwrap();
+//! assert_eq!(captured.headers()[AUTHORIZATION], "[REDACTED]");
+//! let body = captured.into_body().collect().await.unwrap().to_bytes();
+//! assert_eq!(body, r#"{"user":{"name":"john","password":"[REDACTED]"}}"#);
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{
+    header::{self, CONTENT_LENGTH, CONTENT_TYPE},
+    Body, HeaderMap, HeaderName, HeaderValue, Request, Response,
+};
+use bytes::Bytes;
+use rama_core::{Context, Layer, Service};
+use rama_utils::macros::define_inner_service_accessors;
+use serde_json::Value;
+use std::{fmt, sync::Arc};
+
+/// The default mask used to replace redacted values.
+pub const DEFAULT_MASK: &str = "[REDACTED]";
+
+/// Configuration of what to redact from captured http traffic,
+/// and the logic to apply it.
+///
+/// See the [module docs](self) for more details.
+#[derive(Debug, Clone)]
+pub struct Redactor {
+    inner: Arc<RedactorConfig>,
+}
+
+#[derive(Debug, Clone)]
+struct RedactorConfig {
+    headers: Vec<HeaderName>,
+    json_paths: Vec<Vec<String>>,
+    mask: HeaderValue,
+}
+
+impl Default for Redactor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Redactor {
+    /// Create a new [`Redactor`] which masks the
+    /// `Authorization`, `Proxy-Authorization`, `Cookie` and `Set-Cookie` headers.
+    pub fn new() -> Self {
+        Self::empty().headers([
+            header::AUTHORIZATION,
+            header::PROXY_AUTHORIZATION,
+            header::COOKIE,
+            header::SET_COOKIE,
+        ])
+    }
+
+    /// Create a new [`Redactor`] which does not redact anything
+    /// until headers or json paths are added to it.
+    pub fn empty() -> Self {
+        Self {
+            inner: Arc::new(RedactorConfig {
+                headers: Vec::new(),
+                json_paths: Vec::new(),
+                mask: HeaderValue::from_static(DEFAULT_MASK),
+            }),
+        }
+    }
+
+    /// Add a header to be masked.
+    pub fn header(mut self, name: HeaderName) -> Self {
+        self.set_header(name);
+        self
+    }
+
+    /// Add a header to be masked.
+    pub fn set_header(&mut self, name: HeaderName) -> &mut Self {
+        let config = Arc::make_mut(&mut self.inner);
+        if !config.headers.contains(&name) {
+            config.headers.push(name);
+        }
+        self
+    }
+
+    /// Add multiple headers to be masked.
+    pub fn headers(mut self, names: impl IntoIterator<Item = HeaderName>) -> Self {
+        self.set_headers(names);
+        self
+    }
+
+    /// Add multiple headers to be masked.
+    pub fn set_headers(&mut self, names: impl IntoIterator<Item = HeaderName>) -> &mut Self {
+        for name in names {
+            self.set_header(name);
+        }
+        self
+    }
+
+    /// Add a dot-separated path (e.g. `user.password` or `items.*.token`)
+    /// of a JSON field to be masked.
+    pub fn json_path(mut self, path: impl AsRef<str>) -> Self {
+        self.set_json_path(path);
+        self
+    }
+
+    /// Add a dot-separated path (e.g. `user.password` or `items.*.token`)
+    /// of a JSON field to be masked.
+    pub fn set_json_path(&mut self, path: impl AsRef<str>) -> &mut Self {
+        let path: Vec<_> = path
+            .as_ref()
+            .split('.')
+            .filter(|segment| !segment.is_empty())
+            .map(ToOwned::to_owned)
+            .collect();
+        if !path.is_empty() {
+            Arc::make_mut(&mut self.inner).json_paths.push(path);
+        }
+        self
+    }
+
+    /// Set the mask used to replace redacted values.
+    ///
+    /// Defaults to [`DEFAULT_MASK`].
+    pub fn mask(mut self, mask: HeaderValue) -> Self {
+        self.set_mask(mask);
+        self
+    }
+
+    /// Set the mask used to replace redacted values.
+    ///
+    /// Defaults to [`DEFAULT_MASK`].
+    pub fn set_mask(&mut self, mask: HeaderValue) -> &mut Self {
+        Arc::make_mut(&mut self.inner).mask = mask;
+        self
+    }
+
+    /// Mask the configured headers in the given [`HeaderMap`].
+    pub fn redact_headers(&self, headers: &mut HeaderMap) {
+        for name in self.inner.headers.iter() {
+            if let header::Entry::Occupied(mut entry) = headers.entry(name) {
+                let mut mask = self.inner.mask.clone();
+                mask.set_sensitive(true);
+                entry.insert(mask);
+            }
+        }
+    }
+
+    /// Mask the configured JSON fields in the given body,
+    /// in case the headers indicate it is JSON content.
+    ///
+    /// The body is returned as-is if there is nothing to redact
+    /// or if it cannot be parsed as JSON.
+    pub fn redact_body(&self, headers: &HeaderMap, body: Bytes) -> Bytes {
+        if self.inner.json_paths.is_empty() || body.is_empty() || !is_json(headers) {
+            return body;
+        }
+
+        let mut value: Value = match serde_json::from_slice(&body) {
+            Ok(value) => value,
+            Err(err) => {
+                tracing::trace!(err = %err, "redact: failed to parse json body: keep as-is");
+                return body;
+            }
+        };
+
+        let mask = Value::String(String::from_utf8_lossy(self.inner.mask.as_bytes()).into_owned());
+        let mut redacted = false;
+        for path in self.inner.json_paths.iter() {
+            redacted |= redact_json_path(&mut value, path, &mask);
+        }
+        if !redacted {
+            return body;
+        }
+
+        match serde_json::to_vec(&value) {
+            Ok(bytes) => bytes.into(),
+            Err(err) => {
+                tracing::debug!(err = %err, "redact: failed to serialize redacted json body");
+                body
+            }
+        }
+    }
+
+    /// Redact the given (collected) request.
+    pub fn redact_request(
+        &self,
+        mut parts: crate::dep::http::request::Parts,
+        body: Bytes,
+    ) -> Request {
+        self.redact_headers(&mut parts.headers);
+        let body = self.redact_body(&parts.headers, body);
+        update_content_length(&mut parts.headers, &body);
+        Request::from_parts(parts, Body::from(body))
+    }
+
+    /// Redact the given (collected) response.
+    pub fn redact_response(
+        &self,
+        mut parts: crate::dep::http::response::Parts,
+        body: Bytes,
+    ) -> Response {
+        self.redact_headers(&mut parts.headers);
+        let body = self.redact_body(&parts.headers, body);
+        update_content_length(&mut parts.headers, &body);
+        Response::from_parts(parts, Body::from(body))
+    }
+}
+
+fn is_json(headers: &HeaderMap) -> bool {
+    headers
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<mime::Mime>().ok())
+        .map(|mime| {
+            mime.subtype() == mime::JSON || mime.suffix().is_some_and(|suffix| suffix == mime::JSON)
+        })
+        .unwrap_or_default()
+}
+
+fn update_content_length(headers: &mut HeaderMap, body: &Bytes) {
+    if headers.contains_key(CONTENT_LENGTH) {
+        headers.insert(CONTENT_LENGTH, HeaderValue::from(body.len()));
+    }
+}
+
+fn redact_json_path(value: &mut Value, path: &[String], mask: &Value) -> bool {
+    let Some((segment, rest)) = path.split_first() else {
+        *value = mask.clone();
+        return true;
+    };
+
+    match value {
+        Value::Object(map) => {
+            if segment == "*" {
+                map.values_mut().fold(false, |acc, value| {
+                    redact_json_path(value, rest, mask) | acc
+                })
+            } else {
+                map.get_mut(segment.as_str())
+                    .map(|value| redact_json_path(value, rest, mask))
+                    .unwrap_or_default()
+            }
+        }
+        Value::Array(items) => {
+            if segment == "*" {
+                items.iter_mut().fold(false, |acc, value| {
+                    redact_json_path(value, rest, mask) | acc
+                })
+            } else if let Ok(index) = segment.parse::<usize>() {
+                items
+                    .get_mut(index)
+                    .map(|value| redact_json_path(value, rest, mask))
+                    .unwrap_or_default()
+            } else {
+                // traverse arrays transparently for key segments
+                items.iter_mut().fold(false, |acc, value| {
+                    redact_json_path(value, path, mask) | acc
+                })
+            }
+        }
+        _ => false,
+    }
+}
+
+/// Layer that applies the [`RedactService`] middleware,
+/// making the [`Redactor`] available to traffic capturing middleware.
+///
+/// See the [module docs](self) for more details.
+#[derive(Debug, Clone, Default)]
+pub struct RedactLayer {
+    redactor: Redactor,
+}
+
+impl RedactLayer {
+    /// Create a new [`RedactLayer`] for the given [`Redactor`].
+    pub const fn new(redactor: Redactor) -> Self {
+        Self { redactor }
+    }
+}
+
+impl<S> Layer<S> for RedactLayer {
+    type Service = RedactService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RedactService {
+            inner,
+            redactor: self.redactor.clone(),
+        }
+    }
+}
+
+/// Middleware which inserts a [`Redactor`] into the [`Context`],
+/// such that traffic capturing middleware redacts what it records.
+///
+/// See the [module docs](self) for more details.
+pub struct RedactService<S> {
+    inner: S,
+    redactor: Redactor,
+}
+
+impl<S> RedactService<S> {
+    /// Create a new [`RedactService`] for the given [`Redactor`].
+    pub const fn new(inner: S, redactor: Redactor) -> Self {
+        Self { inner, redactor }
+    }
+
+    define_inner_service_accessors!();
+}
+
+impl<S: fmt::Debug> fmt::Debug for RedactService<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RedactService")
+            .field("inner", &self.inner)
+            .field("redactor", &self.redactor)
+            .finish()
+    }
+}
+
+impl<S: Clone> Clone for RedactService<S> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            redactor: self.redactor.clone(),
+        }
+    }
+}
+
+impl<State, S, ReqBody> Service<State, Request<ReqBody>> for RedactService<S>
+where
+    State: Clone + Send + Sync + 'static,
+    S: Service<State, Request<ReqBody>>,
+    ReqBody: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+
+    async fn serve(
+        &self,
+        mut ctx: Context<State>,
+        req: Request<ReqBody>,
+    ) -> Result<Self::Response, Self::Error> {
+        ctx.insert(self.redactor.clone());
+        self.inner.serve(ctx, req).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dep::{http_body, http_body_util::BodyExt};
+    use crate::layer::traffic_writer::{RequestWriterLayer, ResponseWriterLayer};
+    use rama_core::service::service_fn;
+    use std::convert::Infallible;
+
+    async fn body_string<B>(body: B) -> String
+    where
+        B: http_body::Body<Data = Bytes, Error: fmt::Debug>,
+    {
+        let bytes = body.collect().await.unwrap().to_bytes();
+        String::from_utf8(bytes.to_vec()).unwrap()
+    }
+
+    #[test]
+    fn test_redact_headers() {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::AUTHORIZATION, HeaderValue::from_static("Basic foo"));
+        headers.append(header::SET_COOKIE, HeaderValue::from_static("a=1"));
+        headers.append(header::SET_COOKIE, HeaderValue::from_static("b=2"));
+        headers.insert("x-api-key", HeaderValue::from_static("secret"));
+        headers.insert(header::ACCEPT, HeaderValue::from_static("*/*"));
+
+        Redactor::default()
+            .header(HeaderName::from_static("x-api-key"))
+            .redact_headers(&mut headers);
+
+        assert_eq!(headers[header::AUTHORIZATION], DEFAULT_MASK);
+        assert!(headers[header::AUTHORIZATION].is_sensitive());
+        assert_eq!(
+            headers
+                .get_all(header::SET_COOKIE)
+                .iter()
+                .collect::<Vec<_>>(),
+            vec![DEFAULT_MASK]
+        );
+        assert_eq!(headers["x-api-key"], DEFAULT_MASK);
+        assert_eq!(headers[header::ACCEPT], "*/*");
+        assert!(!headers.contains_key(header::COOKIE));
+    }
+
+    #[test]
+    fn test_redact_json_body() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            CONTENT_TYPE,
+            HeaderValue::from_static("application/json; charset=utf-8"),
+        );
+
+        let redactor = Redactor::empty()
+            .json_path("password")
+            .json_path("users.token")
+            .json_path("cards.*.number")
+            .json_path("missing.field")
+            .mask(HeaderValue::from_static("***"));
+
+        let body = Bytes::from_static(
+            br#"{"password":"p","users":[{"name":"a","token":"t1"},{"name":"b","token":"t2"}],"cards":{"visa":{"number":"4111"}}}"#,
+        );
+        let body = redactor.redact_body(&headers, body);
+        let value: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            value,
+            serde_json::json!({
+                "password": "***",
+                "users": [{"name": "a", "token": "***"}, {"name": "b", "token": "***"}],
+                "cards": {"visa": {"number": "***"}},
+            })
+        );
+    }
+
+    #[test]
+    fn test_redact_body_non_json_untouched() {
+        let redactor = Redactor::empty().json_path("password");
+
+        let mut headers = HeaderMap::new();
+        let body = Bytes::from_static(br#"{"password":"p"}"#);
+        assert_eq!(redactor.redact_body(&headers, body.clone()), body);
+
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+        let invalid = Bytes::from_static(b"password=p");
+        assert_eq!(redactor.redact_body(&headers, invalid.clone()), invalid);
+
+        headers.insert(
+            CONTENT_TYPE,
+            HeaderValue::from_static("application/problem+json"),
+        );
+        assert_ne!(redactor.redact_body(&headers, body.clone()), body);
+    }
+
+    #[tokio::test]
+    async fn test_redact_layer_traffic_writers() {
+        let (req_tx, mut req_rx) = tokio::sync::mpsc::unbounded_channel();
+        let (res_tx, mut res_rx) = tokio::sync::mpsc::unbounded_channel();
+
+        let service = (
+            RedactLayer::new(Redactor::default().json_path("token")),
+            ResponseWriterLayer::new(res_tx),
+            RequestWriterLayer::new(req_tx),
+        )
+            .layer(service_fn(|req: Request| async move {
+                assert_eq!(req.headers()[header::COOKIE], "session=abc");
+                assert_eq!(body_string(req.into_body()).await, r#"{"token":"foo"}"#);
+                Ok::<_, Infallible>(
+                    Response::builder()
+                        .header(header::SET_COOKIE, "session=def")
+                        .header(CONTENT_TYPE, "application/json")
+                        .header(CONTENT_LENGTH, "15")
+                        .body(Body::from(r#"{"token":"bar"}"#))
+                        .unwrap(),
+                )
+            }));
+
+        let req = Request::builder()
+            .header(header::COOKIE, "session=abc")
+            .header(CONTENT_TYPE, "application/json")
+            .body(Body::from(r#"{"token":"foo"}"#))
+            .unwrap();
+        let res = service.serve(Context::default(), req).await.unwrap();
+
+        // the original traffic is left untouched
+        assert_eq!(res.headers()[header::SET_COOKIE], "session=def");
+        assert_eq!(body_string(res.into_body()).await, r#"{"token":"bar"}"#);
+
+        let req = req_rx.recv().await.unwrap();
+        assert_eq!(req.headers()[header::COOKIE], DEFAULT_MASK);
+        assert_eq!(
+            body_string(req.into_body()).await,
+            r#"{"token":"[REDACTED]"}"#
+        );
+
+        let res = res_rx.recv().await.unwrap();
+        assert_eq!(res.headers()[header::SET_COOKIE], DEFAULT_MASK);
+        assert_eq!(res.headers()[CONTENT_LENGTH], "22");
+        assert_eq!(
+            body_string(res.into_body()).await,
+            r#"{"token":"[REDACTED]"}"#
+        );
+    }
+}
@@ -0,0 +1,740 @@
+//! Middleware that redirects requests to their canonical location.
+//!
+//! Commonly used at the edge of a deployment, these middlewares
+//! respond with a permanent redirect (`308 Permanent Redirect` by default)
+//! instead of calling the inner service, in case the request is not
+//! made to the canonical location of the resource:
+//!
+//! - [`RedirectToHttps`]: upgrade `http` requests to `https`;
+//! - [`RedirectTrailingSlash`]: add or remove the trailing slash of the path;
+//! - [`RedirectCanonicalHost`]: redirect between the `www` and apex domain.
+//!
+//! The status code can be changed to `301 Moved Permanently` (or any other
+//! redirection status code) using the `with_status_code` method of each layer.
+//!
+//! # Example
+//!
+//! ```
+//! use rama_http::layer::redirect::{
+//!     RedirectCanonicalHostLayer, RedirectToHttpsLayer, RedirectTrailingSlashLayer,
+//! };
+//! use rama_http::{header, Body, Request, Response, StatusCode};
+//! use rama_core::service::service_fn;
+//! use rama_core::{Context, Layer, Service};
+//! use rama_net::address::Domain;
+//! use std::convert::Infallible;
+//!
+//! # #[tokio::main]
+//! # async fn main() {
+//! let service = (
+//!     RedirectToHttpsLayer::new(),
+//!     RedirectCanonicalHostLayer::new(Domain::from_static("example.com")),
+//!     RedirectTrailingSlashLayer::remove(),
+//! )
+//!     .layer(service_fn(|_req: Request| async {
+//!         Ok::<_, Infallible>(Response::new(Body::empty()))
+//!     }));
+//!
+//! let req = Request::get("http://www.example.com/foo/?bar=baz")
+//!     .body(Body::empty())
+//!     .unwrap();
+//! let resp = service.serve(Context::default(), req).await.unwrap();
+//! assert_eq!(resp.status(), StatusCode::PERMANENT_REDIRECT);
+//! assert_eq!(
+//!     resp.headers()[header::LOCATION],
+//!     "https://www.example.com/foo/?bar=baz"
+//! );
+//! # }
+//! ```
+
+use crate::{header, HeaderValue, Request, Response, StatusCode, Uri};
+use rama_core::{Context, Layer, Service};
+use rama_net::address::{Domain, Host};
+use rama_net::http::RequestContext;
+use rama_net::Protocol;
+use rama_utils::macros::define_inner_service_accessors;
+use std::fmt;
+use std::net::IpAddr;
+
+/// Layer that applies the [`RedirectToHttps`] middleware.
+///
+/// See the [module docs](self) for more information.
+#[derive(Debug, Clone)]
+pub struct RedirectToHttpsLayer {
+    status: StatusCode,
+    https_port: u16,
+}
+
+impl RedirectToHttpsLayer {
+    /// Create a new [`RedirectToHttpsLayer`].
+    pub const fn new() -> Self {
+        Self {
+            status: StatusCode::PERMANENT_REDIRECT,
+            https_port: 443,
+        }
+    }
+
+    /// Set the status code used for the redirect,
+    /// `308 Permanent Redirect` by default.
+    ///
+    /// # Panics
+    ///
+    /// If `status` isn't a redirection status code (3xx).
+    pub fn with_status_code(mut self, status: StatusCode) -> Self {
+        self.status = redirect_status(status);
+        self
+    }
+
+    /// Set the status code used for the redirect,
+    /// `308 Permanent Redirect` by default.
+    ///
+    /// # Panics
+    ///
+    /// If `status` isn't a redirection status code (3xx).
+    pub fn set_status_code(&mut self, status: StatusCode) -> &mut Self {
+        self.status = redirect_status(status);
+        self
+    }
+
+    /// Set the port of the https server to redirect to, `443` by default.
+    pub const fn with_https_port(mut self, port: u16) -> Self {
+        self.https_port = port;
+        self
+    }
+
+    /// Set the port of the https server to redirect to, `443` by default.
+    pub fn set_https_port(&mut self, port: u16) -> &mut Self {
+        self.https_port = port;
+        self
+    }
+}
+
+impl Default for RedirectToHttpsLayer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S> Layer<S> for RedirectToHttpsLayer {
+    type Service = RedirectToHttps<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RedirectToHttps {
+            inner,
+            status: self.status,
+            https_port: self.https_port,
+        }
+    }
+}
+
+/// Middleware which redirects requests that are not made over a secure transport
+/// to the same location using the `https` scheme.
+///
+/// The protocol is detected using the [`RequestContext`],
+/// and thus takes forwarded information (e.g. `X-Forwarded-Proto`) into account
+/// when it is inserted in the [`Context`] by a layer such as the [`GetForwardedHeadersLayer`].
+///
+/// See the [module docs](self) for more information.
+///
+/// [`GetForwardedHeadersLayer`]: crate::layer::forwarded::GetForwardedHeadersLayer
+pub struct RedirectToHttps<S> {
+    inner: S,
+    status: StatusCode,
+    https_port: u16,
+}
+
+impl<S> RedirectToHttps<S> {
+    /// Create a new [`RedirectToHttps`] middleware.
+    pub const fn new(inner: S) -> Self {
+        Self {
+            inner,
+            status: StatusCode::PERMANENT_REDIRECT,
+            https_port: 443,
+        }
+    }
+
+    define_inner_service_accessors!();
+}
+
+impl<S: fmt::Debug> fmt::Debug for RedirectToHttps<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RedirectToHttps")
+            .field("inner", &self.inner)
+            .field("status", &self.status)
+            .field("https_port", &self.https_port)
+            .finish()
+    }
+}
+
+impl<S: Clone> Clone for RedirectToHttps<S> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            status: self.status,
+            https_port: self.https_port,
+        }
+    }
+}
+
+impl<State, S, ReqBody, ResBody> Service<State, Request<ReqBody>> for RedirectToHttps<S>
+where
+    State: Clone + Send + Sync + 'static,
+    S: Service<State, Request<ReqBody>, Response = Response<ResBody>>,
+    ReqBody: Send + 'static,
+    ResBody: Default + Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+
+    async fn serve(
+        &self,
+        ctx: Context<State>,
+        req: Request<ReqBody>,
+    ) -> Result<Self::Response, Self::Error> {
+        let request_ctx = match ctx.get::<RequestContext>() {
+            Some(request_ctx) => Some(request_ctx.clone()),
+            None => RequestContext::try_from((&ctx, &req)).ok(),
+        };
+        let Some(request_ctx) = request_ctx else {
+            tracing::debug!(uri = %req.uri(), "redirect to https: no authority found, skip");
+            return self.inner.serve(ctx, req).await;
+        };
+        if request_ctx.protocol.is_secure() {
+            return self.inner.serve(ctx, req).await;
+        }
+
+        let location = format!(
+            "https://{}{}",
+            fmt_authority(
+                request_ctx.authority.host(),
+                self.https_port,
+                Protocol::HTTPS.default_port()
+            ),
+            path_and_query(req.uri()),
+        );
+        tracing::trace!(uri = %req.uri(), %location, "redirect to https");
+        Ok(redirect(self.status, location))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TrailingSlash {
+    Add,
+    Remove,
+}
+
+/// Layer that applies the [`RedirectTrailingSlash`] middleware.
+///
+/// See the [module docs](self) for more information.
+#[derive(Debug, Clone)]
+pub struct RedirectTrailingSlashLayer {
+    status: StatusCode,
+    mode: TrailingSlash,
+}
+
+impl RedirectTrailingSlashLayer {
+    /// Create a new [`RedirectTrailingSlashLayer`] which redirects
+    /// requests to the path with a trailing slash added (e.g. `/foo` to `/foo/`).
+    ///
+    /// Paths of which the last segment contains a dot (e.g. `/style.css`)
+    /// are not redirected, as these typically refer to files.
+    pub const fn add() -> Self {
+        Self {
+            status: StatusCode::PERMANENT_REDIRECT,
+            mode: TrailingSlash::Add,
+        }
+    }
+
+    /// Create a new [`RedirectTrailingSlashLayer`] which redirects
+    /// requests to the path with the trailing slashes removed (e.g. `/foo/` to `/foo`).
+    pub const fn remove() -> Self {
+        Self {
+            status: StatusCode::PERMANENT_REDIRECT,
+            mode: TrailingSlash::Remove,
+        }
+    }
+
+    /// Set the status code used for the redirect,
+    /// `308 Permanent Redirect` by default.
+    ///
+    /// # Panics
+    ///
+    /// If `status` isn't a redirection status code (3xx).
+    pub fn with_status_code(mut self, status: StatusCode) -> Self {
+        self.status = redirect_status(status);
+        self
+    }
+
+    /// Set the status code used for the redirect,
+    /// `308 Permanent Redirect` by default.
+    ///
+    /// # Panics
+    ///
+    /// If `status` isn't a redirection status code (3xx).
+    pub fn set_status_code(&mut self, status: StatusCode) -> &mut Self {
+        self.status = redirect_status(status);
+        self
+    }
+}
+
+impl<S> Layer<S> for RedirectTrailingSlashLayer {
+    type Service = RedirectTrailingSlash<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RedirectTrailingSlash {
+            inner,
+            status: self.status,
+            mode: self.mode,
+        }
+    }
+}
+
+/// Middleware which redirects requests to the path with
+/// a trailing slash added or removed.
+///
+/// Redirects use a relative location, containing only the path and query.
+///
+/// See the [module docs](self) for more information.
+pub struct RedirectTrailingSlash<S> {
+    inner: S,
+    status: StatusCode,
+    mode: TrailingSlash,
+}
+
+impl<S> RedirectTrailingSlash<S> {
+    /// Create a new [`RedirectTrailingSlash`] middleware which redirects
+    /// requests to the path with a trailing slash added (e.g. `/foo` to `/foo/`).
+    ///
+    /// Paths of which the last segment contains a dot (e.g. `/style.css`)
+    /// are not redirected, as these typically refer to files.
+    pub const fn add(inner: S) -> Self {
+        Self {
+            inner,
+            status: StatusCode::PERMANENT_REDIRECT,
+            mode: TrailingSlash::Add,
+        }
+    }
+
+    /// Create a new [`RedirectTrailingSlash`] middleware which redirects
+    /// requests to the path with the trailing slashes removed (e.g. `/foo/` to `/foo`).
+    pub const fn remove(inner: S) -> Self {
+        Self {
+            inner,
+            status: StatusCode::PERMANENT_REDIRECT,
+            mode: TrailingSlash::Remove,
+        }
+    }
+
+    define_inner_service_accessors!();
+}
+
+impl<S: fmt::Debug> fmt::Debug for RedirectTrailingSlash<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RedirectTrailingSlash")
+            .field("inner", &self.inner)
+            .field("status", &self.status)
+            .field("mode", &self.mode)
+            .finish()
+    }
+}
+
+impl<S: Clone> Clone for RedirectTrailingSlash<S> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            status: self.status,
+            mode: self.mode,
+        }
+    }
+}
+
+impl<State, S, ReqBody, ResBody> Service<State, Request<ReqBody>> for RedirectTrailingSlash<S>
+where
+    State: Clone + Send + Sync + 'static,
+    S: Service<State, Request<ReqBody>, Response = Response<ResBody>>,
+    ReqBody: Send + 'static,
+    ResBody: Default + Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+
+    async fn serve(
+        &self,
+        ctx: Context<State>,
+        req: Request<ReqBody>,
+    ) -> Result<Self::Response, Self::Error> {
+        let Some(path) = redirect_trailing_slash_path(req.uri().path(), self.mode) else {
+            return self.inner.serve(ctx, req).await;
+        };
+
+        let location = match req.uri().query() {
+            Some(query) => format!("{path}?{query}"),
+            None => path,
+        };
+        tracing::trace!(uri = %req.uri(), %location, "redirect trailing slash");
+        Ok(redirect(self.status, location))
+    }
+}
+
+/// Returns the path to redirect to, if any.
+///
+/// Leading slashes are collapsed, such that the location
+/// can never be interpreted as a scheme-relative url (e.g. `//example.com`).
+fn redirect_trailing_slash_path(path: &str, mode: TrailingSlash) -> Option<String> {
+    let trimmed = path.trim_matches('/');
+    match mode {
+        TrailingSlash::Add => {
+            if path.ends_with('/')
+                || trimmed
+                    .rsplit('/')
+                    .next()
+                    .is_some_and(|segment| segment.contains('.'))
+            {
+                return None;
+            }
+            Some(format!("/{trimmed}/"))
+        }
+        TrailingSlash::Remove => {
+            if path == "/" || !path.ends_with('/') {
+                return None;
+            }
+            Some(format!("/{trimmed}"))
+        }
+    }
+}
+
+/// Layer that applies the [`RedirectCanonicalHost`] middleware.
+///
+/// See the [module docs](self) for more information.
+#[derive(Debug, Clone)]
+pub struct RedirectCanonicalHostLayer {
+    status: StatusCode,
+    host: Domain,
+}
+
+impl RedirectCanonicalHostLayer {
+    /// Create a new [`RedirectCanonicalHostLayer`] for the given canonical host.
+    ///
+    /// Requests made to the `www` subdomain of the canonical (apex) host,
+    /// or to the apex host of the canonical `www` host, are redirected.
+    /// E.g. for `example.com` requests for `www.example.com` are redirected to `example.com`,
+    /// while for `www.example.com` requests for `example.com` are redirected to `www.example.com`.
+    pub fn new(host: Domain) -> Self {
+        Self {
+            status: StatusCode::PERMANENT_REDIRECT,
+            host,
+        }
+    }
+
+    /// Set the status code used for the redirect,
+    /// `308 Permanent Redirect` by default.
+    ///
+    /// # Panics
+    ///
+    /// If `status` isn't a redirection status code (3xx).
+    pub fn with_status_code(mut self, status: StatusCode) -> Self {
+        self.status = redirect_status(status);
+        self
+    }
+
+    /// Set the status code used for the redirect,
+    /// `308 Permanent Redirect` by default.
+    ///
+    /// # Panics
+    ///
+    /// If `status` isn't a redirection status code (3xx).
+    pub fn set_status_code(&mut self, status: StatusCode) -> &mut Self {
+        self.status = redirect_status(status);
+        self
+    }
+}
+
+impl<S> Layer<S> for RedirectCanonicalHostLayer {
+    type Service = RedirectCanonicalHost<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RedirectCanonicalHost {
+            inner,
+            status: self.status,
+            host: self.host.clone(),
+        }
+    }
+}
+
+/// Middleware which redirects requests between the `www` and apex domain
+/// of the canonical host.
+///
+/// Requests for any other host are passed to the inner service as-is.
+///
+/// See the [module docs](self) for more information.
+pub struct RedirectCanonicalHost<S> {
+    inner: S,
+    status: StatusCode,
+    host: Domain,
+}
+
+impl<S> RedirectCanonicalHost<S> {
+    /// Create a new [`RedirectCanonicalHost`] middleware for the given canonical host.
+    ///
+    /// See [`RedirectCanonicalHostLayer::new`] for more information.
+    pub fn new(inner: S, host: Domain) -> Self {
+        Self {
+            inner,
+            status: StatusCode::PERMANENT_REDIRECT,
+            host,
+        }
+    }
+
+    define_inner_service_accessors!();
+}
+
+impl<S: fmt::Debug> fmt::Debug for RedirectCanonicalHost<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RedirectCanonicalHost")
+            .field("inner", &self.inner)
+            .field("status", &self.status)
+            .field("host", &self.host)
+            .finish()
+    }
+}
+
+impl<S: Clone> Clone for RedirectCanonicalHost<S> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            status: self.status,
+            host: self.host.clone(),
+        }
+    }
+}
+
+impl<S> RedirectCanonicalHost<S> {
+    /// Returns `true` in case the given domain is the `www` or apex
+    /// counterpart of the canonical host.
+    fn is_alias(&self, domain: &Domain) -> bool {
+        let (host, domain) = (
+            self.host.as_str().trim_end_matches('.'),
+            domain.as_str().trim_end_matches('.'),
+        );
+        match (strip_www(host), strip_www(domain)) {
+            (Some(apex), None) => apex.eq_ignore_ascii_case(domain),
+            (None, Some(apex)) => apex.eq_ignore_ascii_case(host),
+            _ => false,
+        }
+    }
+}
+
+fn strip_www(domain: &str) -> Option<&str> {
+    domain
+        .get(..4)
+        .filter(|prefix| prefix.eq_ignore_ascii_case("www."))
+        .map(|_| &domain[4..])
+}
+
+impl<State, S, ReqBody, ResBody> Service<State, Request<ReqBody>> for RedirectCanonicalHost<S>
+where
+    State: Clone + Send + Sync + 'static,
+    S: Service<State, Request<ReqBody>, Response = Response<ResBody>>,
+    ReqBody: Send + 'static,
+    ResBody: Default + Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+
+    async fn serve(
+        &self,
+        ctx: Context<State>,
+        req: Request<ReqBody>,
+    ) -> Result<Self::Response, Self::Error> {
+        let request_ctx = match ctx.get::<RequestContext>() {
+            Some(request_ctx) => Some(request_ctx.clone()),
+            None => RequestContext::try_from((&ctx, &req)).ok(),
+        };
+        let Some(request_ctx) = request_ctx else {
+            tracing::debug!(uri = %req.uri(), "redirect canonical host: no authority found, skip");
+            return self.inner.serve(ctx, req).await;
+        };
+        match request_ctx.authority.host() {
+            Host::Name(domain) if self.is_alias(domain) => (),
+            _ => return self.inner.serve(ctx, req).await,
+        }
+
+        let protocol = if request_ctx.protocol.is_secure() {
+            Protocol::HTTPS
+        } else {
+            Protocol::HTTP
+        };
+        let location = format!(
+            "{}://{}{}",
+            protocol.as_str(),
+            fmt_authority(
+                &Host::Name(self.host.clone()),
+                request_ctx.authority.port(),
+                protocol.default_port()
+            ),
+            path_and_query(req.uri()),
+        );
+        tracing::trace!(uri = %req.uri(), %location, "redirect canonical host");
+        Ok(redirect(self.status, location))
+    }
+}
+
+fn redirect_status(status: StatusCode) -> StatusCode {
+    assert!(status.is_redirection(), "not a redirection status code");
+    status
+}
+
+fn redirect<B: Default>(status: StatusCode, location: String) -> Response<B> {
+    let mut res = Response::default();
+    *res.status_mut() = status;
+    match HeaderValue::try_from(location) {
+        Ok(location) => {
+            res.headers_mut().insert(header::LOCATION, location);
+        }
+        Err(err) => {
+            tracing::debug!(error = %err, "redirect: invalid location");
+            *res.status_mut() = StatusCode::BAD_REQUEST;
+        }
+    }
+    res
+}
+
+fn path_and_query(uri: &Uri) -> &str {
+    uri.path_and_query()
+        .map(|path_and_query| path_and_query.as_str())
+        .filter(|path_and_query| path_and_query.starts_with('/'))
+        .unwrap_or("/")
+}
+
+fn fmt_authority(host: &Host, port: u16, default_port: u16) -> String {
+    let host = match host {
+        Host::Address(IpAddr::V6(ip)) => format!("[{ip}]"),
+        host => host.to_string(),
+    };
+    if port == default_port {
+        host
+    } else {
+        format!("{host}:{port}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Body;
+    use rama_core::service::service_fn;
+    use std::convert::Infallible;
+
+    async fn location<S>(service: &S, uri: &str) -> Option<String>
+    where
+        S: Service<(), Request, Response = Response, Error = Infallible>,
+    {
+        let req = Request::get(uri).body(Body::empty()).unwrap();
+        let resp = service.serve(Context::default(), req).await.unwrap();
+        if resp.status() == StatusCode::OK {
+            return None;
+        }
+        assert_eq!(resp.status(), StatusCode::PERMANENT_REDIRECT);
+        Some(
+            resp.headers()[header::LOCATION]
+                .to_str()
+                .unwrap()
+                .to_owned(),
+        )
+    }
+
+    fn ok() -> impl Service<(), Request, Response = Response, Error = Infallible> {
+        service_fn(|_req: Request| async { Ok::<_, Infallible>(Response::new(Body::empty())) })
+    }
+
+    #[tokio::test]
+    async fn test_redirect_to_https() {
+        let service = RedirectToHttpsLayer::new().layer(ok());
+        assert_eq!(
+            location(&service, "http://example.com/foo?bar=baz").await,
+            Some("https://example.com/foo?bar=baz".to_owned())
+        );
+        assert_eq!(
+            location(&service, "http://[::1]:8080").await,
+            Some("https://[::1]/".to_owned())
+        );
+        assert_eq!(location(&service, "https://example.com/foo").await, None);
+
+        let service = RedirectToHttpsLayer::new()
+            .with_https_port(8443)
+            .layer(ok());
+        assert_eq!(
+            location(&service, "http://example.com:8080/").await,
+            Some("https://example.com:8443/".to_owned())
+        );
+    }
+
+    #[test]
+    fn test_redirect_trailing_slash_path() {
+        for (path, mode, expected) in [
+            ("/", TrailingSlash::Add, None),
+            ("/foo", TrailingSlash::Add, Some("/foo/")),
+            ("/foo/", TrailingSlash::Add, None),
+            ("/foo/style.css", TrailingSlash::Add, None),
+            ("//evil", TrailingSlash::Add, Some("/evil/")),
+            ("/", TrailingSlash::Remove, None),
+            ("/foo", TrailingSlash::Remove, None),
+            ("/foo/", TrailingSlash::Remove, Some("/foo")),
+            ("/foo//", TrailingSlash::Remove, Some("/foo")),
+            (
+                "//example.com/",
+                TrailingSlash::Remove,
+                Some("/example.com"),
+            ),
+        ] {
+            assert_eq!(
+                redirect_trailing_slash_path(path, mode).as_deref(),
+                expected,
+                "{path} {mode:?}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_redirect_trailing_slash() {
+        let service = RedirectTrailingSlashLayer::add().layer(ok());
+        assert_eq!(
+            location(&service, "http://example.com/foo?bar=baz").await,
+            Some("/foo/?bar=baz".to_owned())
+        );
+
+        let service = RedirectTrailingSlashLayer::remove()
+            .with_status_code(StatusCode::MOVED_PERMANENTLY)
+            .layer(ok());
+        let req = Request::get("/foo/").body(Body::empty()).unwrap();
+        let resp = service.serve(Context::default(), req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::MOVED_PERMANENTLY);
+        assert_eq!(resp.headers()[header::LOCATION], "/foo");
+    }
+
+    #[tokio::test]
+    async fn test_redirect_canonical_host() {
+        let service =
+            RedirectCanonicalHostLayer::new(Domain::from_static("example.com")).layer(ok());
+        assert_eq!(
+            location(&service, "https://WWW.example.com/foo?bar=baz").await,
+            Some("https://example.com/foo?bar=baz".to_owned())
+        );
+        assert_eq!(
+            location(&service, "http://www.example.com:8080/").await,
+            Some("http://example.com:8080/".to_owned())
+        );
+        assert_eq!(location(&service, "https://example.com/").await, None);
+        assert_eq!(location(&service, "https://www.example.org/").await, None);
+        assert_eq!(location(&service, "https://api.example.com/").await, None);
+
+        let service =
+            RedirectCanonicalHostLayer::new(Domain::from_static("www.example.com")).layer(ok());
+        assert_eq!(
+            location(&service, "https://example.com/foo").await,
+            Some("https://www.example.com/foo".to_owned())
+        );
+        assert_eq!(location(&service, "https://www.example.com/").await, None);
+        assert_eq!(location(&service, "https://api.example.com/").await, None);
+    }
+}
@@ -63,7 +63,10 @@ use crate::{
     header::{HeaderName, HeaderValue},
     Request, Response,
 };
-use rama_core::{Context, Layer, Service};
+use rama_core::{
+    context::{LogFieldSet, LogFields},
+    Context, Layer, Service,
+};
 use rama_utils::macros::define_inner_service_accessors;
 use uuid::Uuid;
 
@@ -98,6 +101,12 @@ impl RequestId {
     }
 }
 
+impl LogFields for RequestId {
+    fn log_fields(&self, fields: &mut LogFieldSet) {
+        fields.record("request_id", String::from_utf8_lossy(self.0.as_bytes()));
+    }
+}
+
 impl From<HeaderValue> for RequestId {
     fn from(value: HeaderValue) -> Self {
         Self::new(value)
@@ -176,7 +185,8 @@ where
 /// header with the same name, then the header will be inserted.
 ///
 /// Additionally [`RequestId`] will be inserted into [`Request::extensions`] so other
-/// services can access it.
+/// services can access it. It is also inserted into the [`Context`],
+/// where it is picked up as a log field by the tracing and access log layers.
 pub struct SetRequestId<S, M> {
     inner: S,
     header_name: HeaderName,
@@ -244,7 +254,7 @@ where
 
     async fn serve(
         &self,
-        ctx: Context<State>,
+        mut ctx: Context<State>,
         mut req: Request<ReqBody>,
     ) -> Result<Self::Response, Self::Error> {
         if let Some(request_id) = req.headers().get(&self.header_name) {
@@ -257,6 +267,7 @@ where
             req.headers_mut()
                 .insert(self.header_name.clone(), request_id.0);
         }
+        ctx.maybe_insert(req.extensions().get::<RequestId>().cloned());
 
         self.inner.serve(ctx, req).await
     }
@@ -0,0 +1,188 @@
+//! Retry budget, to bound the amount of retries relative to the amount of requests.
+//!
+//! See [`RetryBudget`] for more details.
+
+use std::{
+    fmt,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// The amount of time slots in which the budget window is divided.
+const SLOTS: usize = 10;
+
+/// A retry budget, shared by all clones, limiting the amount of retries
+/// to a percentage of the (original) requests made within a time window,
+/// on top of a minimum amount of retries per second.
+///
+/// This avoids retry storms: when an upstream is down, retrying every
+/// failed request would only multiply the load on it, while a budget
+/// allows occasional failures to be retried without amplifying outages.
+///
+/// Use it with the [`ManagedPolicy`] via [`ManagedPolicy::with_budget`].
+///
+/// [`ManagedPolicy`]: super::ManagedPolicy
+/// [`ManagedPolicy::with_budget`]: super::ManagedPolicy::with_budget
+#[derive(Clone)]
+pub struct RetryBudget {
+    inner: Arc<Mutex<Bucket>>,
+}
+
+struct Bucket {
+    ttl: Duration,
+    min_per_sec: u32,
+    /// retries allowed per deposited request, scaled by 1000
+    retry_ratio: u64,
+    start: Instant,
+    /// the index of the time slot that is used for the current window
+    slot_index: u64,
+    /// deposits and withdrawals per time slot
+    slots: [(u64, u64); SLOTS],
+}
+
+impl fmt::Debug for RetryBudget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let bucket = self.inner.lock().unwrap();
+        f.debug_struct("RetryBudget")
+            .field("ttl", &bucket.ttl)
+            .field("min_per_sec", &bucket.min_per_sec)
+            .field("retry_ratio", &(bucket.retry_ratio as f64 / 1000.))
+            .finish()
+    }
+}
+
+impl Default for RetryBudget {
+    /// A budget allowing 20% of the requests within the last 10 seconds
+    /// to be retried, on top of 10 retries per second.
+    fn default() -> Self {
+        Self::new(Duration::from_secs(10), 10, 0.2)
+    }
+}
+
+impl RetryBudget {
+    /// Create a new [`RetryBudget`].
+    ///
+    /// - `ttl`: the time window in which requests are accounted,
+    ///   clamped to the range of 1 to 60 seconds;
+    /// - `min_per_sec`: the amount of retries per second that are always allowed;
+    /// - `retry_ratio`: the ratio of retries allowed per request, e.g. `0.1` to allow
+    ///   1 retry for every 10 requests, clamped to the range `0.0..=1000.0`
+    ///   and rounded down to a precision of `0.001` (`NaN` is treated as `0.0`).
+    pub fn new(ttl: Duration, min_per_sec: u32, retry_ratio: f32) -> Self {
+        let ttl = ttl.clamp(Duration::from_secs(1), Duration::from_secs(60));
+        let retry_ratio = (retry_ratio.clamp(0., 1000.) * 1000.) as u64;
+        Self {
+            inner: Arc::new(Mutex::new(Bucket {
+                ttl,
+                min_per_sec,
+                retry_ratio,
+                start: Instant::now(),
+                slot_index: 0,
+                slots: [(0, 0); SLOTS],
+            })),
+        }
+    }
+
+    /// Account for a request, increasing the amount of retries allowed.
+    pub fn deposit(&self) {
+        self.deposit_at(Instant::now())
+    }
+
+    /// Try to withdraw a retry from the budget,
+    /// returning `false` if the budget is exhausted.
+    pub fn withdraw(&self) -> bool {
+        self.withdraw_at(Instant::now())
+    }
+
+    pub(super) fn deposit_at(&self, now: Instant) {
+        let mut bucket = self.inner.lock().unwrap();
+        bucket.current_slot(now).0 += 1;
+    }
+
+    pub(super) fn withdraw_at(&self, now: Instant) -> bool {
+        let mut bucket = self.inner.lock().unwrap();
+        bucket.current_slot(now);
+
+        let (deposits, withdrawals) = bucket
+            .slots
+            .iter()
+            .fold((0, 0), |(d, w), (sd, sw)| (d + sd, w + sw));
+        let reserve = u64::from(bucket.min_per_sec) * bucket.ttl.as_secs();
+        let allowed = reserve + deposits * bucket.retry_ratio / 1000;
+        if withdrawals >= allowed {
+            return false;
+        }
+
+        bucket.current_slot(now).1 += 1;
+        true
+    }
+}
+
+impl Bucket {
+    /// Rotate the time slots, returning the (deposits, withdrawals) of the current slot.
+    fn current_slot(&mut self, now: Instant) -> &mut (u64, u64) {
+        let slot_duration = self.ttl / SLOTS as u32;
+        let index = (now.saturating_duration_since(self.start).as_nanos()
+            / slot_duration.as_nanos()) as u64;
+        // clear all slots which expired since the last time the bucket was used
+        let expired = index.saturating_sub(self.slot_index).min(SLOTS as u64);
+        for i in 1..=expired {
+            self.slots[((self.slot_index + i) % SLOTS as u64) as usize] = (0, 0);
+        }
+        self.slot_index = self.slot_index.max(index);
+        &mut self.slots[(self.slot_index % SLOTS as u64) as usize]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_retry_budget_reserve() {
+        let budget = RetryBudget::new(Duration::from_secs(1), 2, 0.);
+        let now = Instant::now();
+
+        assert!(budget.withdraw_at(now));
+        assert!(budget.withdraw_at(now));
+        assert!(!budget.withdraw_at(now));
+
+        // deposits do not help when the ratio is 0
+        budget.deposit_at(now);
+        assert!(!budget.withdraw_at(now));
+
+        // the reserve is available again once the window passed
+        let later = now + Duration::from_secs(1);
+        assert!(budget.withdraw_at(later));
+        assert!(budget.withdraw_at(later));
+        assert!(!budget.withdraw_at(later));
+    }
+
+    #[test]
+    fn test_retry_budget_ratio() {
+        let budget = RetryBudget::new(Duration::from_secs(10), 0, 0.5);
+        let now = Instant::now();
+
+        assert!(!budget.withdraw_at(now));
+        for _ in 0..4 {
+            budget.deposit_at(now);
+        }
+        assert!(budget.withdraw_at(now));
+        assert!(budget.withdraw_at(now));
+        assert!(!budget.withdraw_at(now));
+
+        let later = now + Duration::from_secs(5);
+        budget.deposit_at(later);
+        budget.deposit_at(later);
+        assert!(budget.withdraw_at(later));
+        assert!(!budget.withdraw_at(later));
+
+        // deposits and withdrawals expire with the window
+        let much_later = now + Duration::from_secs(11);
+        assert!(!budget.withdraw_at(much_later));
+        budget.deposit_at(much_later);
+        budget.deposit_at(much_later);
+        assert!(budget.withdraw_at(much_later));
+        assert!(!budget.withdraw_at(much_later));
+    }
+}
@@ -1,16 +1,19 @@
 use super::Retry;
+use crate::utils::ReplayConfig;
 use rama_core::Layer;
 use std::fmt;
 
 /// Retry requests based on a policy
 pub struct RetryLayer<P> {
     policy: P,
+    replay: ReplayConfig,
 }
 
 impl<P: fmt::Debug> fmt::Debug for RetryLayer<P> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         f.debug_struct("RetryLayer")
             .field("policy", &self.policy)
+            .field("replay", &self.replay)
             .finish()
     }
 }
@@ -19,6 +22,7 @@ impl<P: Clone> Clone for RetryLayer<P> {
     fn clone(&self) -> Self {
         Self {
             policy: self.policy.clone(),
+            replay: self.replay.clone(),
         }
     }
 }
@@ -26,7 +30,28 @@ impl<P: Clone> Clone for RetryLayer<P> {
 impl<P> RetryLayer<P> {
     /// Creates a new [`RetryLayer`] from a retry policy.
     pub const fn new(policy: P) -> Self {
-        RetryLayer { policy }
+        RetryLayer {
+            policy,
+            replay: ReplayConfig::new(),
+        }
+    }
+
+    /// Set the [`ReplayConfig`] used to buffer the request body,
+    /// such that it can be resent when retrying the request.
+    ///
+    /// See [`Retry::with_replay_config`] for more information.
+    pub fn with_replay_config(mut self, config: ReplayConfig) -> Self {
+        self.replay = config;
+        self
+    }
+
+    /// Set the [`ReplayConfig`] used to buffer the request body,
+    /// such that it can be resent when retrying the request.
+    ///
+    /// See [`Retry::with_replay_config`] for more information.
+    pub fn set_replay_config(&mut self, config: ReplayConfig) -> &mut Self {
+        self.replay = config;
+        self
     }
 }
 
@@ -38,6 +63,6 @@ where
 
     fn layer(&self, service: S) -> Self::Service {
         let policy = self.policy.clone();
-        Retry::new(policy, service)
+        Retry::new(policy, service).with_replay_config(self.replay.clone())
     }
 }
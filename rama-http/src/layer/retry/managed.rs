@@ -4,9 +4,9 @@
 //!
 //! [`Policy`]: super::Policy
 
-use super::{Policy, PolicyResult, RetryBody};
-use crate::{Request, Response};
-use rama_core::Context;
+use super::{Policy, PolicyResult, RetryBody, RetryBudget};
+use crate::{Method, Request, Response, StatusCode};
+use rama_core::{time::Clock, Context};
 use rama_utils::backoff::Backoff;
 use std::future::Future;
 
@@ -20,16 +20,26 @@ use std::future::Future;
 #[non_exhaustive]
 pub struct DoNotRetry;
 
+#[derive(Debug, Clone)]
+/// Internal marker added to the [`Context`] of a retried request,
+/// such that only the original request is deposited in the [`RetryBudget`].
+struct Retried;
+
 /// A managed retry [`Policy`],
 /// which allows for an easier interface to configure retrying requests.
 ///
 /// [`DoNotRetry`] can be added to the [`Context`] of a [`Request`]
 /// to signal that the request should not be retried, regardless
 /// of the retry functionality defined.
+///
+/// A [`RetryBudget`] can be added using [`ManagedPolicy::with_budget`],
+/// to limit the amount of retries made relative to the amount of requests,
+/// preventing retry storms when the upstream is (partially) down.
 pub struct ManagedPolicy<B = Undefined, C = Undefined, R = Undefined> {
     backoff: B,
     clone: C,
     retry: R,
+    budget: Option<RetryBudget>,
 }
 
 impl<B, C, R, State, Response, Error> Policy<State, Response, Error> for ManagedPolicy<B, C, R>
@@ -52,13 +62,27 @@ where
             return PolicyResult::Abort(result);
         }
 
-        let (ctx, result, retry) = self.retry.retry(ctx, result).await;
-        if retry && self.backoff.next_backoff().await {
-            PolicyResult::Retry { ctx, req }
-        } else {
+        let clock = ctx.clock();
+        if let Some(budget) = &self.budget {
+            if !ctx.contains::<Retried>() {
+                budget.deposit_at(clock.now());
+            }
+        }
+
+        let (mut ctx, result, retry) = self.retry.retry(ctx, result).await;
+        // the backoff is consulted first, such that no budget
+        // is withdrawn for retries that the backoff gives up on
+        if !retry || !self.backoff.next_backoff().await {
+            self.backoff.reset().await;
+            return PolicyResult::Abort(result);
+        }
+        if !self.withdraw_budget(&clock) {
+            tracing::debug!("retry budget exhausted: aborting retry");
             self.backoff.reset().await;
-            PolicyResult::Abort(result)
+            return PolicyResult::Abort(result);
         }
+        ctx.insert(Retried);
+        PolicyResult::Retry { ctx, req }
     }
 
     fn clone_input(
@@ -85,6 +109,7 @@ where
             .field("backoff", &self.backoff)
             .field("clone", &self.clone)
             .field("retry", &self.retry)
+            .field("budget", &self.budget)
             .finish()
     }
 }
@@ -100,6 +125,7 @@ where
             backoff: self.backoff.clone(),
             clone: self.clone.clone(),
             retry: self.retry.clone(),
+            budget: self.budget.clone(),
         }
     }
 }
@@ -110,6 +136,7 @@ impl Default for ManagedPolicy<Undefined, Undefined, Undefined> {
             backoff: Undefined,
             clone: Undefined,
             retry: Undefined,
+            budget: None,
         }
     }
 }
@@ -132,6 +159,7 @@ impl<C, R> ManagedPolicy<Undefined, C, R> {
             backoff,
             clone: self.clone,
             retry: self.retry,
+            budget: self.budget,
         }
     }
 }
@@ -144,6 +172,7 @@ impl<B, R> ManagedPolicy<B, Undefined, R> {
             backoff: self.backoff,
             clone,
             retry: self.retry,
+            budget: self.budget,
         }
     }
 }
@@ -156,10 +185,34 @@ impl<B, C> ManagedPolicy<B, C, Undefined> {
             backoff: self.backoff,
             clone: self.clone,
             retry,
+            budget: self.budget,
         }
     }
 }
 
+impl<B, C, R> ManagedPolicy<B, C, R> {
+    /// add a [`RetryBudget`] to this [`ManagedPolicy`],
+    /// limiting the amount of retries relative to the amount of requests.
+    pub fn with_budget(mut self, budget: RetryBudget) -> Self {
+        self.budget = Some(budget);
+        self
+    }
+
+    /// add a [`RetryBudget`] to this [`ManagedPolicy`],
+    /// limiting the amount of retries relative to the amount of requests.
+    pub fn set_budget(&mut self, budget: RetryBudget) -> &mut Self {
+        self.budget = Some(budget);
+        self
+    }
+
+    fn withdraw_budget(&self, clock: &Clock) -> bool {
+        self.budget
+            .as_ref()
+            .map(|budget| budget.withdraw_at(clock.now()))
+            .unwrap_or(true)
+    }
+}
+
 /// A trait that is used to umbrella-cover all possible
 /// implementation kinds for the retry rule functionality.
 pub trait RetryRule<S, R, E>: private::Sealed<(S, R, E)> + Send + Sync + 'static {
@@ -261,6 +314,165 @@ where
     }
 }
 
+#[derive(Debug, Clone)]
+/// A [`RetryRule`] which retries errors (e.g. connect failures)
+/// and responses with a retryable [`StatusCode`].
+///
+/// By default the following status codes are retried:
+///
+/// - `429 Too Many Requests`
+/// - `502 Bad Gateway`
+/// - `503 Service Unavailable`
+/// - `504 Gateway Timeout`
+pub struct RetryClassifier {
+    statuses: Vec<StatusCode>,
+    retry_errors: bool,
+}
+
+impl Default for RetryClassifier {
+    fn default() -> Self {
+        Self {
+            statuses: vec![
+                StatusCode::TOO_MANY_REQUESTS,
+                StatusCode::BAD_GATEWAY,
+                StatusCode::SERVICE_UNAVAILABLE,
+                StatusCode::GATEWAY_TIMEOUT,
+            ],
+            retry_errors: true,
+        }
+    }
+}
+
+impl RetryClassifier {
+    /// Create a new [`RetryClassifier`] with the default retryable status codes.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a new [`RetryClassifier`] which retries no status codes,
+    /// only errors, until status codes are added using [`RetryClassifier::with_status`].
+    pub fn empty() -> Self {
+        Self {
+            statuses: Vec::new(),
+            retry_errors: true,
+        }
+    }
+
+    /// Add a [`StatusCode`] to be retried.
+    pub fn with_status(mut self, status: StatusCode) -> Self {
+        self.set_status(status);
+        self
+    }
+
+    /// Add a [`StatusCode`] to be retried.
+    pub fn set_status(&mut self, status: StatusCode) -> &mut Self {
+        if !self.statuses.contains(&status) {
+            self.statuses.push(status);
+        }
+        self
+    }
+
+    /// Define whether or not errors (e.g. connect failures) are retried.
+    ///
+    /// Enabled by default.
+    pub fn retry_errors(mut self, retry: bool) -> Self {
+        self.retry_errors = retry;
+        self
+    }
+
+    /// Define whether or not errors (e.g. connect failures) are retried.
+    ///
+    /// Enabled by default.
+    pub fn set_retry_errors(&mut self, retry: bool) -> &mut Self {
+        self.retry_errors = retry;
+        self
+    }
+}
+
+impl<S, Body, E> RetryRule<S, Response<Body>, E> for RetryClassifier
+where
+    S: Clone + Send + Sync + 'static,
+    E: std::fmt::Debug + Send + Sync + 'static,
+    Body: Send + 'static,
+{
+    async fn retry(
+        &self,
+        ctx: Context<S>,
+        result: Result<Response<Body>, E>,
+    ) -> (Context<S>, Result<Response<Body>, E>, bool) {
+        let retry = match &result {
+            Ok(response) => {
+                let status = response.status();
+                let retry = self.statuses.contains(&status);
+                if retry {
+                    tracing::debug!("retrying http status code: {status} ({})", status.as_u16());
+                }
+                retry
+            }
+            Err(error) => {
+                if self.retry_errors {
+                    tracing::debug!("retrying error: {:?}", error);
+                }
+                self.retry_errors
+            }
+        };
+        (ctx, result, retry)
+    }
+}
+
+#[derive(Debug, Clone)]
+/// A [`CloneInput`] which only clones requests with an idempotent [`Method`]
+/// (`GET`, `HEAD`, `OPTIONS`, `TRACE`, `PUT` and `DELETE`)
+/// and a body no larger than the configured maximum size,
+/// such that non-idempotent or large requests are never retried.
+pub struct IdempotentClone {
+    max_body_size: usize,
+}
+
+impl Default for IdempotentClone {
+    /// Clone idempotent requests with a body of at most 64 KiB.
+    fn default() -> Self {
+        Self {
+            max_body_size: 64 * 1024,
+        }
+    }
+}
+
+impl IdempotentClone {
+    /// Create a new [`IdempotentClone`] which clones idempotent requests
+    /// with a body of at most `max_body_size` bytes.
+    pub fn new(max_body_size: usize) -> Self {
+        Self { max_body_size }
+    }
+}
+
+impl<S: Clone> CloneInput<S> for IdempotentClone {
+    fn clone_input(
+        &self,
+        ctx: &Context<S>,
+        req: &Request<RetryBody>,
+    ) -> Option<(Context<S>, Request<RetryBody>)> {
+        let idempotent = matches!(
+            *req.method(),
+            Method::GET
+                | Method::HEAD
+                | Method::OPTIONS
+                | Method::TRACE
+                | Method::PUT
+                | Method::DELETE
+        );
+        if !idempotent
+            || req
+                .body()
+                .len()
+                .map_or(true, |len| len > self.max_body_size as u64)
+        {
+            return None;
+        }
+        Some((ctx.clone(), req.clone()))
+    }
+}
+
 #[derive(Debug, Clone)]
 #[non_exhaustive]
 /// A type to represent the undefined default type,
@@ -288,6 +500,8 @@ mod private {
     pub trait Sealed<S> {}
 
     impl<S> Sealed<S> for Undefined {}
+    impl<S> Sealed<S> for RetryClassifier {}
+    impl<S> Sealed<S> for IdempotentClone {}
     impl<F, S> Sealed<(S,)> for F where
         F: Fn(&Context<S>, &Request<RetryBody>) -> Option<(Context<S>, Request<RetryBody>)>
             + Send
@@ -355,7 +569,7 @@ mod tests {
         let request = Request::builder()
             .method("GET")
             .uri("http://example.com")
-            .body(RetryBody::empty())
+            .body(RetryBody::default())
             .unwrap();
 
         let policy = ManagedPolicy::default();
@@ -389,7 +603,7 @@ mod tests {
         let req = Request::builder()
             .method("GET")
             .uri("http://example.com")
-            .body(RetryBody::empty())
+            .body(RetryBody::default())
             .unwrap();
 
         let policy = ManagedPolicy::default();
@@ -426,7 +640,7 @@ mod tests {
         let req = Request::builder()
             .method("GET")
             .uri("http://example.com")
-            .body(RetryBody::empty())
+            .body(RetryBody::default())
             .unwrap();
 
         fn clone_fn<S>(
@@ -455,7 +669,7 @@ mod tests {
         let req = Request::builder()
             .method("GET")
             .uri("http://example.com")
-            .body(RetryBody::empty())
+            .body(RetryBody::default())
             .unwrap();
 
         async fn retry_fn<S, R, E>(
@@ -496,7 +710,7 @@ mod tests {
         let req = Request::builder()
             .method("GET")
             .uri("http://example.com")
-            .body(RetryBody::empty())
+            .body(RetryBody::default())
             .unwrap();
 
         fn clone_fn<S>(
@@ -548,4 +762,130 @@ mod tests {
         .await;
         assert_retry(Context::default(), req, Err(()), &policy).await;
     }
+    #[tokio::test]
+    async fn test_policy_retry_classifier() {
+        let req = Request::builder()
+            .method("GET")
+            .uri("http://example.com")
+            .body(RetryBody::default())
+            .unwrap();
+
+        let policy = ManagedPolicy::new(RetryClassifier::new());
+
+        for status in [
+            StatusCode::TOO_MANY_REQUESTS,
+            StatusCode::BAD_GATEWAY,
+            StatusCode::SERVICE_UNAVAILABLE,
+            StatusCode::GATEWAY_TIMEOUT,
+        ] {
+            assert_retry(
+                Context::default(),
+                req.clone(),
+                Ok(status.into_response()),
+                &policy,
+            )
+            .await;
+        }
+        for status in [StatusCode::OK, StatusCode::INTERNAL_SERVER_ERROR] {
+            assert_abort(
+                Context::default(),
+                req.clone(),
+                Ok(status.into_response()),
+                &policy,
+            )
+            .await;
+        }
+        assert_retry(Context::default(), req.clone(), Err(()), &policy).await;
+
+        let policy = ManagedPolicy::new(
+            RetryClassifier::empty()
+                .with_status(StatusCode::INTERNAL_SERVER_ERROR)
+                .retry_errors(false),
+        );
+        assert_retry(
+            Context::default(),
+            req.clone(),
+            Ok(StatusCode::INTERNAL_SERVER_ERROR.into_response()),
+            &policy,
+        )
+        .await;
+        assert_abort(
+            Context::default(),
+            req.clone(),
+            Ok(StatusCode::SERVICE_UNAVAILABLE.into_response()),
+            &policy,
+        )
+        .await;
+        assert_abort(Context::default(), req, Err(()), &policy).await;
+    }
+
+    #[test]
+    fn test_policy_idempotent_clone() {
+        let policy = ManagedPolicy::default().with_clone(IdempotentClone::new(4));
+
+        fn request(method: Method, body: &'static str) -> Request<RetryBody> {
+            Request::builder()
+                .method(method)
+                .uri("http://example.com")
+                .body(RetryBody::from(bytes::Bytes::from(body)))
+                .unwrap()
+        }
+
+        let ctx = Context::default();
+        assert_clone_input_some(&ctx, &request(Method::GET, ""), &policy);
+        assert_clone_input_some(&ctx, &request(Method::PUT, "abcd"), &policy);
+        assert_clone_input_some(&ctx, &request(Method::DELETE, ""), &policy);
+        assert_clone_input_none(&ctx, &request(Method::PUT, "abcde"), &policy);
+        assert_clone_input_none(&ctx, &request(Method::POST, ""), &policy);
+        assert_clone_input_none(&ctx, &request(Method::PATCH, "a"), &policy);
+    }
+
+    #[tokio::test]
+    async fn test_policy_retry_budget() {
+        let req = Request::builder()
+            .method("GET")
+            .uri("http://example.com")
+            .body(RetryBody::default())
+            .unwrap();
+
+        let budget = RetryBudget::new(Duration::from_secs(10), 0, 1.);
+        let policy = ManagedPolicy::default().with_budget(budget);
+
+        // a single request allows a single retry
+        let ctx = match policy
+            .retry(Context::default(), req.clone(), Err::<Response, _>(()))
+            .await
+        {
+            PolicyResult::Retry { ctx, .. } => ctx,
+            PolicyResult::Abort(_) => panic!("expected retry"),
+        };
+        // retries are not deposited, so the budget is exhausted
+        assert_abort(ctx, req.clone(), Err(()), &policy).await;
+
+        // a new request deposits to the budget again
+        assert_retry(Context::default(), req, Err(()), &policy).await;
+    }
+
+    #[tokio::test]
+    async fn test_policy_retry_budget_not_withdrawn_when_backoff_gives_up() {
+        let req = Request::builder()
+            .method("GET")
+            .uri("http://example.com")
+            .body(RetryBody::default())
+            .unwrap();
+
+        let budget = RetryBudget::new(Duration::from_secs(10), 0, 1.);
+        let policy = ManagedPolicy::default().with_budget(budget.clone());
+
+        // the `()` backoff never allows a retry, so nothing is withdrawn
+        let give_up_policy = ManagedPolicy::default()
+            .with_backoff(())
+            .with_budget(budget);
+        assert_abort(Context::default(), req.clone(), Err(()), &give_up_policy).await;
+
+        // such that the retry deposited by that request is still available
+        let mut ctx = Context::default();
+        ctx.insert(Retried);
+        assert_retry(ctx, req, Err(()), &policy).await;
+    }
 }
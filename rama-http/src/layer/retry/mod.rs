@@ -1,7 +1,38 @@
 //! Middleware for retrying "failed" requests.
+//!
+//! # Example
+//!
+//! Retry idempotent requests which failed with a connect error or a
+//! retryable status code (e.g. `503`), using an exponential backoff
+//! and a [`RetryBudget`] to avoid retry storms:
+//!
+//! ```
+//! use rama_http::layer::retry::{
+//!     IdempotentClone, ManagedPolicy, RetryBudget, RetryClassifier, RetryLayer,
+//! };
+//! use rama_utils::{backoff::ExponentialBackoff, rng::HasherRng};
+//! use std::time::Duration;
+//!
+//! let backoff = ExponentialBackoff::new(
+//!     Duration::from_millis(50),
+//!     Duration::from_secs(2),
+//!     0.1,
+//!     HasherRng::default,
+//! )
+//! .unwrap();
+//!
+//! let layer = RetryLayer::new(
+//!     ManagedPolicy::default()
+//!         .with_backoff(backoff)
+//!         .with_clone(IdempotentClone::default())
+//!         .with_retry(RetryClassifier::new())
+//!         .with_budget(RetryBudget::default()),
+//! );
+//! # let _ = layer;
+//! ```
 
 use crate::dep::http_body::Body as HttpBody;
-use crate::dep::http_body_util::BodyExt;
+use crate::utils::{BodyNotReplayable, ReplayConfig};
 use crate::Request;
 use rama_core::error::BoxError;
 use rama_core::{Context, Service};
@@ -14,8 +45,11 @@ mod body;
 #[doc(inline)]
 pub use body::RetryBody;
 
+pub mod budget;
+pub use budget::RetryBudget;
+
 pub mod managed;
-pub use managed::ManagedPolicy;
+pub use managed::{IdempotentClone, ManagedPolicy, RetryClassifier};
 
 #[cfg(test)]
 mod tests;
@@ -28,6 +62,7 @@ pub use self::policy::{Policy, PolicyResult};
 /// A [`Policy`] classifies what is a "failed" response.
 pub struct Retry<P, S> {
     policy: P,
+    replay: ReplayConfig,
     inner: S,
 }
 
@@ -39,6 +74,7 @@ where
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("Retry")
             .field("policy", &self.policy)
+            .field("replay", &self.replay)
             .field("inner", &self.inner)
             .finish()
     }
@@ -52,6 +88,7 @@ where
     fn clone(&self) -> Self {
         Retry {
             policy: self.policy.clone(),
+            replay: self.replay.clone(),
             inner: self.inner.clone(),
         }
     }
@@ -64,10 +101,32 @@ impl<P, S> Retry<P, S> {
     pub const fn new(policy: P, service: S) -> Self {
         Retry {
             policy,
+            replay: ReplayConfig::new(),
             inner: service,
         }
     }
 
+    /// Set the [`ReplayConfig`] used to buffer the request body,
+    /// such that it can be resent when retrying the request.
+    ///
+    /// By default the request body is buffered in memory, without any limit.
+    /// Requests with a body too large to be buffered can only be sent once,
+    /// retrying such a request fails with a [`RetryError`] for which
+    /// [`RetryError::is_body_not_replayable`] returns `true`.
+    pub fn with_replay_config(mut self, config: ReplayConfig) -> Self {
+        self.replay = config;
+        self
+    }
+
+    /// Set the [`ReplayConfig`] used to buffer the request body,
+    /// such that it can be resent when retrying the request.
+    ///
+    /// See [`Self::with_replay_config`] for more information.
+    pub fn set_replay_config(&mut self, config: ReplayConfig) -> &mut Self {
+        self.replay = config;
+        self
+    }
+
     define_inner_service_accessors!();
 }
 
@@ -81,9 +140,18 @@ pub struct RetryError {
 #[derive(Debug)]
 enum RetryErrorKind {
     BodyConsume,
+    BodyNotReplayable,
     Service,
 }
 
+impl RetryError {
+    /// Returns `true` if the request could not be retried
+    /// because its body was too large to be buffered, see [`ReplayConfig`].
+    pub fn is_body_not_replayable(&self) -> bool {
+        matches!(self.kind, RetryErrorKind::BodyNotReplayable)
+    }
+}
+
 impl std::fmt::Display for RetryError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match &self.inner {
@@ -97,6 +165,7 @@ impl std::fmt::Display for RetryErrorKind {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             RetryErrorKind::BodyConsume => write!(f, "failed to consume body"),
+            RetryErrorKind::BodyNotReplayable => write!(f, "failed to retry request"),
             RetryErrorKind::Service => write!(f, "service error"),
         }
     }
@@ -125,13 +194,14 @@ where
     ) -> Result<Self::Response, Self::Error> {
         let mut ctx = ctx;
 
-        // consume body so we can clone the request if desired
+        // buffer body so we can clone the request if desired
         let (parts, body) = request.into_parts();
-        let body = body.collect().await.map_err(|e| RetryError {
-            kind: RetryErrorKind::BodyConsume,
-            inner: Some(e.into()),
-        })?;
-        let body = RetryBody::new(body.to_bytes());
+        let body = RetryBody::buffer(body, &self.replay)
+            .await
+            .map_err(|e| RetryError {
+                kind: RetryErrorKind::BodyConsume,
+                inner: Some(e),
+            })?;
         let mut request = Request::from_parts(parts, body);
 
         let mut cloned = self.policy.clone_input(&ctx, &request);
@@ -151,6 +221,13 @@ where
                             PolicyResult::Retry { ctx, req } => (ctx, req),
                         };
 
+                    if !cloned_req.body().is_replayable() {
+                        return Err(RetryError {
+                            kind: RetryErrorKind::BodyNotReplayable,
+                            inner: Some(BodyNotReplayable.into()),
+                        });
+                    }
+
                     cloned = self.policy.clone_input(&cloned_ctx, &cloned_req);
                     ctx = cloned_ctx;
                     request = cloned_req;
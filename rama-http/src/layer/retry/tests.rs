@@ -55,6 +55,56 @@ async fn retry_errors() {
     assert_eq!(error_counter.load(Ordering::Acquire), 1);
 }
 
+#[tokio::test]
+async fn retry_body_replay() {
+    struct Svc {
+        errored: AtomicBool,
+    }
+
+    impl Service<State, Request<RetryBody>> for Svc {
+        type Response = Response;
+        type Error = OpaqueError;
+
+        async fn serve(
+            &self,
+            _ctx: Context<State>,
+            req: Request<RetryBody>,
+        ) -> Result<Self::Response, Self::Error> {
+            let body = req.try_into_string().await?;
+            if self.errored.swap(true, Ordering::AcqRel) {
+                Ok(body.into_response())
+            } else {
+                Err(error!("retry me"))
+            }
+        }
+    }
+
+    let request = || Request::builder().body(crate::Body::from("hello")).unwrap();
+
+    #[cfg(feature = "replay-disk")]
+    {
+        let svc = RetryLayer::new(RetryErrors)
+            .with_replay_config(
+                ReplayConfig::new()
+                    .with_memory_limit(2)
+                    .with_spill_to_disk(true),
+            )
+            .layer(Svc {
+                errored: AtomicBool::new(false),
+            });
+        let resp = svc.serve(Context::default(), request()).await.unwrap();
+        assert_eq!(resp.try_into_string().await.unwrap(), "hello");
+    }
+
+    let svc = RetryLayer::new(RetryErrors)
+        .with_replay_config(ReplayConfig::new().with_memory_limit(2))
+        .layer(Svc {
+            errored: AtomicBool::new(false),
+        });
+    let err = svc.serve(Context::default(), request()).await.unwrap_err();
+    assert!(err.is_body_not_replayable());
+}
+
 #[tokio::test]
 async fn retry_limit() {
     struct Svc {
@@ -231,7 +281,7 @@ fn request(s: &'static str) -> Request<RetryBody> {
     Request::builder()
         .method("POST")
         .uri("http://localhost")
-        .body(RetryBody::new(s.into()))
+        .body(RetryBody::from(bytes::Bytes::from(s)))
         .unwrap()
 }
 
@@ -0,0 +1,624 @@
+//! Server sessions, identified by a signed session cookie.
+//!
+//! The [`SessionLayer`] loads the session of the client, identified by the id
+//! in its session cookie, from a [`SessionStore`] and exposes it as a [`Session`]
+//! handle in the [`Context`]. Once the inner service has responded, a modified
+//! session is saved in the store (and a new client gets its session cookie),
+//! while a destroyed session is deleted from the store and its cookie is removed.
+//!
+//! Only the (random) session id is stored in the cookie, signed using a server secret
+//! (HMAC-SHA256) such that session ids cannot be forged. The session data itself
+//! is kept server-side, in memory ([`MemorySessionStore`]) or in any
+//! key-value backend such as Redis (see [`KeyValueSessionStore`]).
+//!
+//! # Example
+//!
+//! ```
+//! use rama_http::layer::session::{MemorySessionStore, Session, SessionLayer};
+//! use rama_http::{header, Body, Request, Response};
+//! use rama_core::service::service_fn;
+//! use rama_core::{Context, Layer, Service};
+//! use std::convert::Infallible;
+//!
+//! # #[tokio::main]
+//! # async fn main() {
+//! let store = MemorySessionStore::new();
+//! let svc = SessionLayer::new(store.clone(), b"a secret of at least 32 bytes long!")
+//!     .layer(service_fn(|ctx: Context<()>, _req: Request| async move {
+//!         let session = ctx.get::<Session>().unwrap();
+//!         let visits = session.get::<u64>("visits").unwrap_or_default() + 1;
+//!         session.insert("visits", visits).unwrap();
+//!         Ok::<_, Infallible>(Response::new(Body::from(visits.to_string())))
+//!     }));
+//!
+//! let resp = svc.serve(Context::default(), Request::new(Body::empty())).await.unwrap();
+//! let cookie = resp.headers()[header::SET_COOKIE].to_str().unwrap();
+//! assert!(cookie.starts_with("session="));
+//! assert_eq!(store.len(), 1);
+//! # }
+//! ```
+
+use crate::headers::{Cookie, HeaderMapExt};
+use crate::utils::SetCookie;
+use crate::{header, HeaderValue, Request, Response, StatusCode};
+use base64::Engine as _;
+use rama_core::error::{ErrorContext, OpaqueError};
+use rama_core::{Context, Layer, Service};
+use rama_utils::macros::define_inner_service_accessors;
+use ring::hmac;
+use serde::{de::DeserializeOwned, Serialize};
+use std::{
+    fmt,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use uuid::Uuid;
+
+mod store;
+#[doc(inline)]
+pub use store::{KeyValueBackend, KeyValueSessionStore, MemorySessionStore, SessionStore};
+
+#[doc(inline)]
+pub use crate::utils::SameSite;
+
+const BASE64_URL: base64::engine::GeneralPurpose = base64::engine::general_purpose::URL_SAFE_NO_PAD;
+
+const DEFAULT_COOKIE_NAME: &str = "session";
+
+/// Default time a session is kept alive.
+pub const DEFAULT_SESSION_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// The data of a session, as stored in a [`SessionStore`].
+pub type SessionData = serde_json::Map<String, serde_json::Value>;
+
+#[derive(Clone)]
+/// Handle to the session of the current request,
+/// inserted in the [`Context`] by the [`SessionService`].
+///
+/// Changes made via this handle are saved in the [`SessionStore`]
+/// once the inner service has responded.
+pub struct Session {
+    state: Arc<Mutex<SessionState>>,
+}
+
+#[derive(Debug)]
+struct SessionState {
+    id: Option<String>,
+    data: SessionData,
+    status: SessionStatus,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SessionStatus {
+    Unchanged,
+    Changed,
+    Renewed,
+    Destroyed,
+}
+
+impl fmt::Debug for Session {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = self.state.lock().unwrap();
+        f.debug_struct("Session")
+            .field("data", &state.data)
+            .field("status", &state.status)
+            .finish()
+    }
+}
+
+impl Session {
+    fn new(id: Option<String>, data: SessionData) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(SessionState {
+                id,
+                data,
+                status: SessionStatus::Unchanged,
+            })),
+        }
+    }
+
+    /// Returns the value stored under the given key,
+    /// `None` if there is no such value or it cannot be deserialized into `T`.
+    pub fn get<T: DeserializeOwned>(&self, key: &str) -> Option<T> {
+        let state = self.state.lock().unwrap();
+        let value = state.data.get(key)?;
+        T::deserialize(value)
+            .inspect_err(|err| {
+                tracing::debug!(key, error = %err, "session: failed to deserialize value");
+            })
+            .ok()
+    }
+
+    /// Store the given value under the given key,
+    /// replacing any previous value.
+    pub fn insert<T: Serialize>(
+        &self,
+        key: impl Into<String>,
+        value: T,
+    ) -> Result<(), OpaqueError> {
+        let value = serde_json::to_value(value).context("session: serialize value")?;
+        let mut state = self.state.lock().unwrap();
+        state.data.insert(key.into(), value);
+        state.mark_changed();
+        Ok(())
+    }
+
+    /// Remove the value stored under the given key, returning it if it existed.
+    pub fn remove(&self, key: &str) -> Option<serde_json::Value> {
+        let mut state = self.state.lock().unwrap();
+        let value = state.data.remove(key)?;
+        state.mark_changed();
+        Some(value)
+    }
+
+    /// Remove all values from the session.
+    pub fn clear(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.data.clear();
+        state.mark_changed();
+    }
+
+    /// Returns `true` if no values are stored in the session.
+    pub fn is_empty(&self) -> bool {
+        self.state.lock().unwrap().data.is_empty()
+    }
+
+    /// Assign a new id to the session, keeping its data.
+    ///
+    /// Do this when the privileges of the session change (e.g. on login),
+    /// to prevent session fixation attacks.
+    pub fn renew(&self) {
+        let mut state = self.state.lock().unwrap();
+        if state.status != SessionStatus::Destroyed {
+            state.status = SessionStatus::Renewed;
+        }
+    }
+
+    /// Destroy the session, deleting it from the store and removing the session cookie.
+    pub fn destroy(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.data.clear();
+        state.status = SessionStatus::Destroyed;
+    }
+}
+
+impl SessionState {
+    fn mark_changed(&mut self) {
+        if self.status == SessionStatus::Unchanged {
+            self.status = SessionStatus::Changed;
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct SessionConfig {
+    cookie_name: String,
+    ttl: Duration,
+    same_site: SameSite,
+    secure: bool,
+}
+
+impl Default for SessionConfig {
+    fn default() -> Self {
+        Self {
+            cookie_name: DEFAULT_COOKIE_NAME.to_owned(),
+            ttl: DEFAULT_SESSION_TTL,
+            same_site: SameSite::Lax,
+            secure: true,
+        }
+    }
+}
+
+#[derive(Clone)]
+struct SessionKey(hmac::Key);
+
+impl fmt::Debug for SessionKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("SessionKey(..)")
+    }
+}
+
+impl SessionKey {
+    fn sign(&self, id: &str) -> String {
+        let signature = hmac::sign(&self.0, id.as_bytes());
+        format!("{id}.{}", BASE64_URL.encode(signature))
+    }
+
+    fn verify<'a>(&self, cookie_value: &'a str) -> Option<&'a str> {
+        let (id, signature) = cookie_value.rsplit_once('.')?;
+        let signature = BASE64_URL.decode(signature).ok()?;
+        hmac::verify(&self.0, id.as_bytes(), &signature).ok()?;
+        Some(id)
+    }
+}
+
+/// Layer that applies the [`SessionService`] middleware.
+///
+/// See the [module docs](self) for more details.
+pub struct SessionLayer<T> {
+    store: T,
+    key: SessionKey,
+    config: Arc<SessionConfig>,
+}
+
+impl<T: fmt::Debug> fmt::Debug for SessionLayer<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SessionLayer")
+            .field("store", &self.store)
+            .field("key", &self.key)
+            .field("config", &self.config)
+            .finish()
+    }
+}
+
+impl<T: Clone> Clone for SessionLayer<T> {
+    fn clone(&self) -> Self {
+        Self {
+            store: self.store.clone(),
+            key: self.key.clone(),
+            config: self.config.clone(),
+        }
+    }
+}
+
+impl<T> SessionLayer<T> {
+    /// Create a new [`SessionLayer`] storing the sessions in the given [`SessionStore`],
+    /// signing the session cookies using the given secret.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the secret is shorter than 32 bytes.
+    pub fn new(store: T, secret: impl AsRef<[u8]>) -> Self {
+        let secret = secret.as_ref();
+        assert!(
+            secret.len() >= 32,
+            "session secret has to be at least 32 bytes long"
+        );
+        Self {
+            store,
+            key: SessionKey(hmac::Key::new(hmac::HMAC_SHA256, secret)),
+            config: Arc::new(SessionConfig::default()),
+        }
+    }
+
+    /// Set the name of the session cookie.
+    ///
+    /// Defaults to `session`.
+    pub fn cookie_name(mut self, name: impl Into<String>) -> Self {
+        Arc::make_mut(&mut self.config).cookie_name = name.into();
+        self
+    }
+
+    /// Set the name of the session cookie.
+    ///
+    /// Defaults to `session`.
+    pub fn set_cookie_name(&mut self, name: impl Into<String>) -> &mut Self {
+        Arc::make_mut(&mut self.config).cookie_name = name.into();
+        self
+    }
+
+    /// Set how long a session is kept (in the store and as cookie),
+    /// since it was last modified.
+    ///
+    /// Defaults to [`DEFAULT_SESSION_TTL`] (24 hours).
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        Arc::make_mut(&mut self.config).ttl = ttl;
+        self
+    }
+
+    /// Set how long a session is kept (in the store and as cookie),
+    /// since it was last modified.
+    ///
+    /// Defaults to [`DEFAULT_SESSION_TTL`] (24 hours).
+    pub fn set_ttl(&mut self, ttl: Duration) -> &mut Self {
+        Arc::make_mut(&mut self.config).ttl = ttl;
+        self
+    }
+
+    /// Set the [`SameSite`] attribute of the cookie.
+    ///
+    /// Defaults to [`SameSite::Lax`].
+    pub fn same_site(mut self, same_site: SameSite) -> Self {
+        Arc::make_mut(&mut self.config).same_site = same_site;
+        self
+    }
+
+    /// Set the [`SameSite`] attribute of the cookie.
+    ///
+    /// Defaults to [`SameSite::Lax`].
+    pub fn set_same_site(&mut self, same_site: SameSite) -> &mut Self {
+        Arc::make_mut(&mut self.config).same_site = same_site;
+        self
+    }
+
+    /// Set whether the cookie is only to be sent over secure (https) connections.
+    ///
+    /// Defaults to `true`, only disable it for local development.
+    pub fn secure(mut self, secure: bool) -> Self {
+        Arc::make_mut(&mut self.config).secure = secure;
+        self
+    }
+
+    /// Set whether the cookie is only to be sent over secure (https) connections.
+    ///
+    /// Defaults to `true`, only disable it for local development.
+    pub fn set_secure(&mut self, secure: bool) -> &mut Self {
+        Arc::make_mut(&mut self.config).secure = secure;
+        self
+    }
+}
+
+impl<S, T: Clone> Layer<S> for SessionLayer<T> {
+    type Service = SessionService<S, T>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        SessionService {
+            inner,
+            store: self.store.clone(),
+            key: self.key.clone(),
+            config: self.config.clone(),
+        }
+    }
+}
+
+/// Middleware which manages the [`Session`] of the client.
+///
+/// See the [module docs](self) for more details.
+pub struct SessionService<S, T> {
+    inner: S,
+    store: T,
+    key: SessionKey,
+    config: Arc<SessionConfig>,
+}
+
+impl<S, T> SessionService<S, T> {
+    define_inner_service_accessors!();
+
+    fn cookie_header_value(&self, value: &str, max_age: Duration) -> Option<HeaderValue> {
+        SetCookie {
+            name: &self.config.cookie_name,
+            value,
+            max_age: Some(max_age),
+            http_only: true,
+            same_site: self.config.same_site,
+            secure: self.config.secure,
+        }
+        .header_value()
+    }
+
+    /// Save or delete the session (as modified by the inner service) in the store,
+    /// returning the cookie to set (if any).
+    async fn commit(&self, session: Session) -> Result<Option<HeaderValue>, OpaqueError>
+    where
+        T: SessionStore,
+    {
+        let (id, data, status) = {
+            let mut state = session.state.lock().unwrap();
+            (
+                state.id.take(),
+                std::mem::take(&mut state.data),
+                state.status,
+            )
+        };
+
+        match status {
+            SessionStatus::Unchanged => Ok(None),
+            SessionStatus::Destroyed => match id {
+                Some(id) => {
+                    self.store.delete(&id).await?;
+                    Ok(self.cookie_header_value("", Duration::ZERO))
+                }
+                None => Ok(None),
+            },
+            SessionStatus::Changed if id.is_none() && data.is_empty() => Ok(None),
+            SessionStatus::Changed | SessionStatus::Renewed => {
+                if status == SessionStatus::Renewed {
+                    if let Some(id) = id.as_deref() {
+                        self.store.delete(id).await?;
+                    }
+                }
+                let id = match id {
+                    Some(id) if status == SessionStatus::Changed => id,
+                    _ => new_session_id(),
+                };
+                self.store.save(&id, data, self.config.ttl).await?;
+                Ok(self.cookie_header_value(&self.key.sign(&id), self.config.ttl))
+            }
+        }
+    }
+}
+
+fn new_session_id() -> String {
+    format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple())
+}
+
+impl<S: fmt::Debug, T: fmt::Debug> fmt::Debug for SessionService<S, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SessionService")
+            .field("inner", &self.inner)
+            .field("store", &self.store)
+            .field("key", &self.key)
+            .field("config", &self.config)
+            .finish()
+    }
+}
+
+impl<S: Clone, T: Clone> Clone for SessionService<S, T> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            store: self.store.clone(),
+            key: self.key.clone(),
+            config: self.config.clone(),
+        }
+    }
+}
+
+impl<State, S, T, ReqBody, ResBody> Service<State, Request<ReqBody>> for SessionService<S, T>
+where
+    State: Clone + Send + Sync + 'static,
+    S: Service<State, Request<ReqBody>, Response = Response<ResBody>>,
+    T: SessionStore,
+    ReqBody: Send + 'static,
+    ResBody: Default + Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+
+    async fn serve(
+        &self,
+        mut ctx: Context<State>,
+        req: Request<ReqBody>,
+    ) -> Result<Self::Response, Self::Error> {
+        let id = req.headers().typed_get::<Cookie>().and_then(|cookie| {
+            cookie
+                .get(&self.config.cookie_name)
+                .and_then(|value| self.key.verify(value))
+                .map(ToOwned::to_owned)
+        });
+
+        let session = match id {
+            Some(id) => match self.store.load(&id).await {
+                Ok(Some(data)) => Session::new(Some(id), data),
+                Ok(None) => Session::new(None, SessionData::new()),
+                Err(err) => {
+                    tracing::error!(error = %err, "session: failed to load session from store");
+                    let mut res = Response::new(ResBody::default());
+                    *res.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+                    return Ok(res);
+                }
+            },
+            None => Session::new(None, SessionData::new()),
+        };
+        ctx.insert(session.clone());
+
+        let mut res = self.inner.serve(ctx, req).await?;
+
+        match self.commit(session).await {
+            Ok(Some(cookie)) => {
+                res.headers_mut().append(header::SET_COOKIE, cookie);
+            }
+            Ok(None) => (),
+            Err(err) => {
+                tracing::error!(error = %err, "session: failed to commit session to store");
+            }
+        }
+
+        Ok(res)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Body, BodyExtractExt};
+    use rama_core::service::service_fn;
+    use std::convert::Infallible;
+
+    const SECRET: &[u8] = b"0123456789abcdef0123456789abcdef";
+
+    fn svc(
+        store: MemorySessionStore,
+    ) -> impl Service<(), Request, Response = Response, Error = Infallible> {
+        SessionLayer::new(store, SECRET).layer(service_fn(
+            |ctx: Context<()>, req: Request| async move {
+                let session = ctx.get::<Session>().unwrap();
+                match req.uri().path() {
+                    "/login" => {
+                        session.insert("user", "john").unwrap();
+                        session.renew();
+                    }
+                    "/logout" => session.destroy(),
+                    "/visit" => {
+                        let visits = session.get::<u64>("visits").unwrap_or_default();
+                        session.insert("visits", visits + 1).unwrap();
+                    }
+                    _ => (),
+                }
+                let user = session.get::<String>("user").unwrap_or_default();
+                let visits = session.get::<u64>("visits").unwrap_or_default();
+                Ok(Response::new(Body::from(format!("{user}:{visits}"))))
+            },
+        ))
+    }
+
+    async fn request(
+        svc: &impl Service<(), Request, Response = Response, Error = Infallible>,
+        path: &str,
+        cookie: Option<&str>,
+    ) -> (String, Option<String>) {
+        let mut req = Request::builder().uri(path);
+        if let Some(cookie) = cookie {
+            req = req.header(header::COOKIE, cookie);
+        }
+        let res = svc
+            .serve(Context::default(), req.body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let cookie = res.headers().get(header::SET_COOKIE).map(|value| {
+            let value = value.to_str().unwrap();
+            assert!(value.contains("HttpOnly"));
+            value.split(';').next().unwrap().to_owned()
+        });
+        (res.try_into_string().await.unwrap(), cookie)
+    }
+
+    #[tokio::test]
+    async fn test_session_lifecycle() {
+        let store = MemorySessionStore::new();
+        let svc = svc(store.clone());
+
+        // no session is created for untouched sessions
+        assert_eq!(request(&svc, "/", None).await, (":0".to_owned(), None));
+        assert!(store.is_empty());
+
+        let (body, cookie) = request(&svc, "/visit", None).await;
+        assert_eq!(body, ":1");
+        let cookie = cookie.unwrap();
+        assert_eq!(store.len(), 1);
+
+        // modified sessions keep their id, with the cookie expiry being extended
+        assert_eq!(
+            request(&svc, "/visit", Some(&cookie)).await,
+            (":2".to_owned(), Some(cookie.clone()))
+        );
+        assert_eq!(
+            request(&svc, "/", Some(&cookie)).await,
+            (":2".to_owned(), None)
+        );
+
+        // a renewed session gets a new id
+        let (body, login_cookie) = request(&svc, "/login", Some(&cookie)).await;
+        assert_eq!(body, "john:2");
+        let login_cookie = login_cookie.unwrap();
+        assert_ne!(login_cookie, cookie);
+        assert_eq!(store.len(), 1);
+        assert_eq!(request(&svc, "/", Some(&cookie)).await.0, ":0");
+        assert_eq!(request(&svc, "/", Some(&login_cookie)).await.0, "john:2");
+
+        // a destroyed session is removed
+        let (body, logout_cookie) = request(&svc, "/logout", Some(&login_cookie)).await;
+        assert_eq!(body, ":0");
+        assert_eq!(logout_cookie.unwrap(), "session=");
+        assert!(store.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_session_forged_cookie() {
+        let store = MemorySessionStore::new();
+        let svc = svc(store.clone());
+
+        let (_, cookie) = request(&svc, "/login", None).await;
+        let cookie = cookie.unwrap();
+        assert_eq!(request(&svc, "/", Some(&cookie)).await.0, "john:0");
+
+        let (id, signature) = cookie.rsplit_once('.').unwrap();
+        for forged in [
+            id.to_owned(),
+            format!("{id}.{}", BASE64_URL.encode([0; 32])),
+            format!("{}x.{signature}", id),
+        ] {
+            assert_eq!(request(&svc, "/", Some(&forged)).await.0, ":0", "{forged}");
+        }
+    }
+}
@@ -0,0 +1,305 @@
+use super::SessionData;
+use rama_core::error::{ErrorContext, OpaqueError};
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::time::Instant;
+
+/// Default prefix of the keys used by the [`KeyValueSessionStore`].
+const DEFAULT_KEY_PREFIX: &str = "session:";
+
+/// A backend in which the [`SessionData`] is stored, by session id.
+///
+/// Implemented by the [`MemorySessionStore`], and by the [`KeyValueSessionStore`]
+/// for any [`KeyValueBackend`] (e.g. a Redis client).
+pub trait SessionStore: Send + Sync + 'static {
+    /// Load the data of the session with the given id,
+    /// returning `None` if there is no such session (anymore).
+    fn load<'a>(
+        &'a self,
+        id: &'a str,
+    ) -> impl Future<Output = Result<Option<SessionData>, OpaqueError>> + Send + 'a;
+
+    /// Store the data of the session with the given id,
+    /// which expires after the given ttl.
+    fn save<'a>(
+        &'a self,
+        id: &'a str,
+        data: SessionData,
+        ttl: Duration,
+    ) -> impl Future<Output = Result<(), OpaqueError>> + Send + 'a;
+
+    /// Delete the session with the given id.
+    fn delete<'a>(
+        &'a self,
+        id: &'a str,
+    ) -> impl Future<Output = Result<(), OpaqueError>> + Send + 'a;
+}
+
+impl<T: SessionStore> SessionStore for Arc<T> {
+    fn load<'a>(
+        &'a self,
+        id: &'a str,
+    ) -> impl Future<Output = Result<Option<SessionData>, OpaqueError>> + Send + 'a {
+        (**self).load(id)
+    }
+
+    fn save<'a>(
+        &'a self,
+        id: &'a str,
+        data: SessionData,
+        ttl: Duration,
+    ) -> impl Future<Output = Result<(), OpaqueError>> + Send + 'a {
+        (**self).save(id, data, ttl)
+    }
+
+    fn delete<'a>(
+        &'a self,
+        id: &'a str,
+    ) -> impl Future<Output = Result<(), OpaqueError>> + Send + 'a {
+        (**self).delete(id)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+/// A [`SessionStore`] keeping the sessions in memory.
+///
+/// Expired sessions are removed when loaded, or by calling
+/// [`MemorySessionStore::remove_expired`] (e.g. periodically).
+///
+/// Cloning the store shares the sessions, which are lost once the process exits.
+pub struct MemorySessionStore {
+    sessions: Arc<Mutex<HashMap<String, (SessionData, Instant)>>>,
+}
+
+impl MemorySessionStore {
+    /// Create a new empty [`MemorySessionStore`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the amount of sessions stored, including expired sessions not yet removed.
+    pub fn len(&self) -> usize {
+        self.sessions.lock().unwrap().len()
+    }
+
+    /// Returns `true` if no sessions are stored.
+    pub fn is_empty(&self) -> bool {
+        self.sessions.lock().unwrap().is_empty()
+    }
+
+    /// Remove all expired sessions.
+    pub fn remove_expired(&self) {
+        let now = Instant::now();
+        self.sessions
+            .lock()
+            .unwrap()
+            .retain(|_, (_, expires_at)| *expires_at > now);
+    }
+}
+
+impl SessionStore for MemorySessionStore {
+    fn load<'a>(
+        &'a self,
+        id: &'a str,
+    ) -> impl Future<Output = Result<Option<SessionData>, OpaqueError>> + Send + 'a {
+        let mut sessions = self.sessions.lock().unwrap();
+        let data = match sessions.get(id) {
+            Some((_, expires_at)) if *expires_at <= Instant::now() => {
+                sessions.remove(id);
+                None
+            }
+            Some((data, _)) => Some(data.clone()),
+            None => None,
+        };
+        std::future::ready(Ok(data))
+    }
+
+    fn save<'a>(
+        &'a self,
+        id: &'a str,
+        data: SessionData,
+        ttl: Duration,
+    ) -> impl Future<Output = Result<(), OpaqueError>> + Send + 'a {
+        self.sessions
+            .lock()
+            .unwrap()
+            .insert(id.to_owned(), (data, Instant::now() + ttl));
+        std::future::ready(Ok(()))
+    }
+
+    fn delete<'a>(
+        &'a self,
+        id: &'a str,
+    ) -> impl Future<Output = Result<(), OpaqueError>> + Send + 'a {
+        self.sessions.lock().unwrap().remove(id);
+        std::future::ready(Ok(()))
+    }
+}
+
+/// A key-value backend with expiring keys, such as Redis,
+/// which can be used as [`SessionStore`] by wrapping it in a [`KeyValueSessionStore`].
+pub trait KeyValueBackend: Send + Sync + 'static {
+    /// Get the value of the given key, `None` if it does not exist (anymore).
+    ///
+    /// e.g. `GET key` in Redis.
+    fn get<'a>(
+        &'a self,
+        key: &'a str,
+    ) -> impl Future<Output = Result<Option<Vec<u8>>, OpaqueError>> + Send + 'a;
+
+    /// Set the value of the given key, which expires after the given ttl.
+    ///
+    /// e.g. `SET key value PX ttl` in Redis.
+    fn set_with_ttl<'a>(
+        &'a self,
+        key: &'a str,
+        value: Vec<u8>,
+        ttl: Duration,
+    ) -> impl Future<Output = Result<(), OpaqueError>> + Send + 'a;
+
+    /// Delete the given key.
+    ///
+    /// e.g. `DEL key` in Redis.
+    fn delete<'a>(
+        &'a self,
+        key: &'a str,
+    ) -> impl Future<Output = Result<(), OpaqueError>> + Send + 'a;
+}
+
+#[derive(Debug, Clone)]
+/// A [`SessionStore`] storing the sessions as JSON in a [`KeyValueBackend`],
+/// using the session id with a prefix (`session:` by default) as key.
+pub struct KeyValueSessionStore<B> {
+    backend: B,
+    prefix: String,
+}
+
+impl<B> KeyValueSessionStore<B> {
+    /// Create a new [`KeyValueSessionStore`] using the given backend.
+    pub fn new(backend: B) -> Self {
+        Self {
+            backend,
+            prefix: DEFAULT_KEY_PREFIX.to_owned(),
+        }
+    }
+
+    /// Define the prefix of the keys, `session:` by default.
+    pub fn prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    /// Define the prefix of the keys, `session:` by default.
+    pub fn set_prefix(&mut self, prefix: impl Into<String>) -> &mut Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    fn key(&self, id: &str) -> String {
+        format!("{}{id}", self.prefix)
+    }
+}
+
+impl<B: KeyValueBackend> SessionStore for KeyValueSessionStore<B> {
+    async fn load<'a>(&'a self, id: &'a str) -> Result<Option<SessionData>, OpaqueError> {
+        let Some(value) = self.backend.get(&self.key(id)).await? else {
+            return Ok(None);
+        };
+        serde_json::from_slice(&value)
+            .map(Some)
+            .context("session store: decode session data")
+    }
+
+    async fn save<'a>(
+        &'a self,
+        id: &'a str,
+        data: SessionData,
+        ttl: Duration,
+    ) -> Result<(), OpaqueError> {
+        let value = serde_json::to_vec(&data).context("session store: encode session data")?;
+        self.backend.set_with_ttl(&self.key(id), value, ttl).await
+    }
+
+    async fn delete<'a>(&'a self, id: &'a str) -> Result<(), OpaqueError> {
+        self.backend.delete(&self.key(id)).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[tokio::test(start_paused = true)]
+    async fn test_memory_session_store_expiry() {
+        let store = MemorySessionStore::new();
+        let mut data = SessionData::new();
+        data.insert("user".to_owned(), json!("john"));
+
+        store
+            .save("a", data.clone(), Duration::from_secs(10))
+            .await
+            .unwrap();
+        store
+            .save("b", data.clone(), Duration::from_secs(20))
+            .await
+            .unwrap();
+        assert_eq!(store.load("a").await.unwrap(), Some(data.clone()));
+
+        tokio::time::advance(Duration::from_secs(10)).await;
+        assert_eq!(store.load("a").await.unwrap(), None);
+        assert_eq!(store.len(), 1);
+
+        tokio::time::advance(Duration::from_secs(10)).await;
+        store.remove_expired();
+        assert!(store.is_empty());
+    }
+
+    #[derive(Debug, Default)]
+    struct MockBackend(Mutex<HashMap<String, Vec<u8>>>);
+
+    impl KeyValueBackend for MockBackend {
+        async fn get<'a>(&'a self, key: &'a str) -> Result<Option<Vec<u8>>, OpaqueError> {
+            Ok(self.0.lock().unwrap().get(key).cloned())
+        }
+
+        async fn set_with_ttl<'a>(
+            &'a self,
+            key: &'a str,
+            value: Vec<u8>,
+            _ttl: Duration,
+        ) -> Result<(), OpaqueError> {
+            self.0.lock().unwrap().insert(key.to_owned(), value);
+            Ok(())
+        }
+
+        async fn delete<'a>(&'a self, key: &'a str) -> Result<(), OpaqueError> {
+            self.0.lock().unwrap().remove(key);
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_key_value_session_store() {
+        let store = KeyValueSessionStore::new(MockBackend::default()).prefix("s:");
+        let mut data = SessionData::new();
+        data.insert("count".to_owned(), json!(1));
+
+        store
+            .save("a", data.clone(), Duration::from_secs(10))
+            .await
+            .unwrap();
+        assert_eq!(
+            store.backend.0.lock().unwrap().get("s:a").unwrap(),
+            br#"{"count":1}"#
+        );
+        assert_eq!(store.load("a").await.unwrap(), Some(data));
+
+        store.delete("a").await.unwrap();
+        assert_eq!(store.load("a").await.unwrap(), None);
+    }
+}
@@ -0,0 +1,346 @@
+//! Session affinity for the load balancer, such that repeat clients hit the same upstream.
+//!
+//! Two kinds of affinity are supported:
+//!
+//! - cookie-based: the [`StickySessionLayer`] pins the requests of a client
+//!   to the upstream stored in a cookie, which it sets as soon as the
+//!   [`Balance`] service selected an upstream for the client;
+//! - ip-hash: the [`client_ip_hash`] policy selects the upstream
+//!   by consistent hashing of the client ip.
+//!
+//! In both cases the requests are served by another upstream in case the
+//! pinned upstream is marked as unhealthy, with the cookie updated accordingly.
+//!
+//! [`Balance`]: rama_core::service::balance::Balance
+//!
+//! # Example
+//!
+//! ```
+//! use rama_http::layer::sticky_session::StickySessionLayer;
+//! use rama_http::{header, Body, Request, Response};
+//! use rama_core::service::balance::{Balance, Upstream, UpstreamPool};
+//! use rama_core::service::service_fn;
+//! use rama_core::{Context, Service, Layer};
+//! use std::convert::Infallible;
+//!
+//! # #[tokio::main]
+//! # async fn main() {
+//! let pool = UpstreamPool::try_new(["a", "b"].map(|name| {
+//!     Upstream::new(
+//!         name,
+//!         service_fn(move |_: Request| async move {
+//!             Ok::<_, Infallible>(Response::new(Body::from(name)))
+//!         }),
+//!     )
+//! }))
+//! .unwrap();
+//!
+//! let service = StickySessionLayer::new().layer(Balance::round_robin(pool));
+//!
+//! let req = Request::builder().uri("/").body(Body::empty()).unwrap();
+//! let resp = service.serve(Context::default(), req).await.unwrap();
+//! let cookie = resp.headers()[header::SET_COOKIE].to_str().unwrap();
+//! assert!(cookie.starts_with("upstream="));
+//! # }
+//! ```
+
+use crate::headers::{Cookie, HeaderMapExt};
+use crate::utils::{client_ip, SetCookie};
+use crate::{header, HeaderValue, Request, Response};
+use rama_core::{
+    service::balance::{ConsistentHash, UpstreamAffinity},
+    Context, Layer, Service,
+};
+use rama_utils::macros::define_inner_service_accessors;
+use std::{fmt, net::IpAddr, sync::Arc};
+
+#[doc(inline)]
+pub use crate::utils::SameSite;
+
+const DEFAULT_COOKIE_NAME: &str = "upstream";
+
+/// The client ip hash policy, as a [`ConsistentHash`] balance policy.
+pub type ClientIpHash<State, Request> =
+    ConsistentHash<fn(&Context<State>, &Request) -> Option<IpAddr>>;
+
+/// Create a [`ConsistentHash`] balance policy selecting the upstream
+/// by the client ip of the [`Forwarded`] information,
+/// falling back to the peer address of the [`SocketInfo`].
+///
+/// Only use the [`Forwarded`] information of trusted proxies,
+/// as it is otherwise easily spoofed.
+///
+/// [`Forwarded`]: rama_net::forwarded::Forwarded
+/// [`SocketInfo`]: rama_net::stream::SocketInfo
+pub fn client_ip_hash<State, Request>() -> ClientIpHash<State, Request> {
+    ConsistentHash::new(client_ip::<State, Request>)
+}
+
+#[derive(Debug, Clone)]
+struct StickySessionConfig {
+    cookie_name: String,
+    same_site: SameSite,
+    secure: bool,
+}
+
+/// Layer that applies the [`StickySessionService`] middleware.
+///
+/// See the [module docs](self) for more details.
+#[derive(Debug, Clone)]
+pub struct StickySessionLayer {
+    config: Arc<StickySessionConfig>,
+}
+
+impl StickySessionLayer {
+    /// Create a new [`StickySessionLayer`], using the `upstream` cookie.
+    pub fn new() -> Self {
+        Self {
+            config: Arc::new(StickySessionConfig {
+                cookie_name: DEFAULT_COOKIE_NAME.to_owned(),
+                same_site: SameSite::Lax,
+                secure: false,
+            }),
+        }
+    }
+
+    /// Set the name of the cookie storing the pinned upstream.
+    pub fn cookie_name(mut self, name: impl Into<String>) -> Self {
+        Arc::make_mut(&mut self.config).cookie_name = name.into();
+        self
+    }
+
+    /// Set the name of the cookie storing the pinned upstream.
+    pub fn set_cookie_name(&mut self, name: impl Into<String>) -> &mut Self {
+        Arc::make_mut(&mut self.config).cookie_name = name.into();
+        self
+    }
+
+    /// Set the [`SameSite`] attribute of the cookie.
+    ///
+    /// Defaults to [`SameSite::Lax`].
+    pub fn same_site(mut self, same_site: SameSite) -> Self {
+        Arc::make_mut(&mut self.config).same_site = same_site;
+        self
+    }
+
+    /// Set the [`SameSite`] attribute of the cookie.
+    ///
+    /// Defaults to [`SameSite::Lax`].
+    pub fn set_same_site(&mut self, same_site: SameSite) -> &mut Self {
+        Arc::make_mut(&mut self.config).same_site = same_site;
+        self
+    }
+
+    /// Only send the cookie over secure (https) connections.
+    pub fn secure(mut self, secure: bool) -> Self {
+        Arc::make_mut(&mut self.config).secure = secure;
+        self
+    }
+
+    /// Only send the cookie over secure (https) connections.
+    pub fn set_secure(&mut self, secure: bool) -> &mut Self {
+        Arc::make_mut(&mut self.config).secure = secure;
+        self
+    }
+}
+
+impl Default for StickySessionLayer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S> Layer<S> for StickySessionLayer {
+    type Service = StickySessionService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        StickySessionService {
+            inner,
+            config: self.config.clone(),
+        }
+    }
+}
+
+/// Middleware which pins the requests of a client to an upstream using a cookie.
+///
+/// See the [module docs](self) for more details.
+pub struct StickySessionService<S> {
+    inner: S,
+    config: Arc<StickySessionConfig>,
+}
+
+impl<S> StickySessionService<S> {
+    define_inner_service_accessors!();
+
+    fn cookie_header_value(&self, key: &str) -> Option<HeaderValue> {
+        SetCookie {
+            name: &self.config.cookie_name,
+            value: key,
+            max_age: None,
+            http_only: true,
+            same_site: self.config.same_site,
+            secure: self.config.secure,
+        }
+        .header_value()
+    }
+}
+
+impl<S: fmt::Debug> fmt::Debug for StickySessionService<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("StickySessionService")
+            .field("inner", &self.inner)
+            .field("config", &self.config)
+            .finish()
+    }
+}
+
+impl<S: Clone> Clone for StickySessionService<S> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            config: self.config.clone(),
+        }
+    }
+}
+
+impl<State, S, ReqBody, ResBody> Service<State, Request<ReqBody>> for StickySessionService<S>
+where
+    State: Clone + Send + Sync + 'static,
+    S: Service<State, Request<ReqBody>, Response = Response<ResBody>>,
+    ReqBody: Send + 'static,
+    ResBody: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+
+    async fn serve(
+        &self,
+        mut ctx: Context<State>,
+        req: Request<ReqBody>,
+    ) -> Result<Self::Response, Self::Error> {
+        let pinned = req
+            .headers()
+            .typed_get::<Cookie>()
+            .and_then(|cookie| cookie.get(&self.config.cookie_name).map(ToOwned::to_owned));
+        let affinity = match pinned {
+            Some(key) => UpstreamAffinity::pinned(key),
+            None => UpstreamAffinity::new(),
+        };
+        ctx.insert(affinity.clone());
+
+        let mut res = self.inner.serve(ctx, req).await?;
+
+        if let Some(selected) = affinity.selected_key() {
+            if affinity.pinned_key() != Some(selected.as_str()) {
+                if let Some(cookie) = self.cookie_header_value(&selected) {
+                    res.headers_mut().append(header::SET_COOKIE, cookie);
+                }
+            }
+        }
+        Ok(res)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Body, BodyExtractExt};
+    use rama_core::service::{
+        balance::{Balance, Upstream, UpstreamPool},
+        service_fn, BoxService,
+    };
+    use rama_net::stream::SocketInfo;
+    use std::convert::Infallible;
+
+    type Upstreams = UpstreamPool<BoxService<(), Request, Response, Infallible>>;
+
+    fn pool() -> Upstreams {
+        UpstreamPool::try_new(["a", "b", "c"].map(|name| {
+            Upstream::new(
+                name,
+                service_fn(move |_: Request| async move {
+                    Ok::<_, Infallible>(Response::new(Body::from(name)))
+                })
+                .boxed(),
+            )
+        }))
+        .unwrap()
+    }
+
+    async fn get(
+        svc: &impl Service<(), Request, Response = Response, Error = Infallible>,
+        cookie: Option<&str>,
+    ) -> (String, Option<String>) {
+        let mut req = Request::builder().uri("/");
+        if let Some(cookie) = cookie {
+            req = req.header(header::COOKIE, cookie);
+        }
+        let resp = svc
+            .serve(Context::default(), req.body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let cookie = resp.headers().get(header::SET_COOKIE).map(|value| {
+            let value = value.to_str().unwrap();
+            value[..value.find(';').unwrap()].to_owned()
+        });
+        (resp.into_body().try_into_string().await.unwrap(), cookie)
+    }
+
+    #[tokio::test]
+    async fn test_sticky_session_cookie() {
+        let pool = pool();
+        let svc = StickySessionLayer::new().layer(Balance::round_robin(pool.clone()));
+
+        let (first, cookie) = get(&svc, None).await;
+        let cookie = cookie.unwrap();
+        assert_eq!(
+            cookie,
+            format!("upstream={}", pool.upstreams()[0].affinity_key())
+        );
+
+        for _ in 0..3 {
+            let (upstream, set_cookie) = get(&svc, Some(&cookie)).await;
+            assert_eq!(upstream, first);
+            assert!(set_cookie.is_none());
+        }
+
+        // failover to another upstream, pinning that one instead
+        pool.upstreams()[0].set_healthy(false);
+        let (upstream, set_cookie) = get(&svc, Some(&cookie)).await;
+        assert_ne!(upstream, first);
+        let set_cookie = set_cookie.unwrap();
+        assert_ne!(set_cookie, cookie);
+        let (again, _) = get(&svc, Some(&set_cookie)).await;
+        assert_eq!(again, upstream);
+    }
+
+    #[tokio::test]
+    async fn test_client_ip_hash() {
+        let pool = pool();
+        let svc = Balance::new(pool.clone(), client_ip_hash());
+
+        let serve = |ip: &'static str| {
+            let svc = svc.clone();
+            async move {
+                let mut ctx = Context::default();
+                ctx.insert(SocketInfo::new(None, format!("{ip}:1234").parse().unwrap()));
+                let req = Request::builder().uri("/").body(Body::empty()).unwrap();
+                let resp = svc.serve(ctx, req).await.unwrap();
+                resp.into_body().try_into_string().await.unwrap()
+            }
+        };
+
+        let first = serve("10.0.0.1").await;
+        for _ in 0..3 {
+            assert_eq!(serve("10.0.0.1").await, first);
+        }
+
+        let index = pool
+            .upstreams()
+            .iter()
+            .position(|upstream| upstream.id() == first)
+            .unwrap();
+        pool.upstreams()[index].set_healthy(false);
+        assert_ne!(serve("10.0.0.1").await, first);
+    }
+}
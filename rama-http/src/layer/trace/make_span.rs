@@ -1,4 +1,6 @@
+use crate::layer::redact::Redactor;
 use crate::Request;
+use std::borrow::Cow;
 use tracing::{Level, Span};
 
 use super::DEFAULT_MESSAGE_LEVEL;
@@ -30,12 +32,18 @@ where
 
 /// The default way [`Span`]s will be created for [`Trace`].
 ///
+/// The created spans declare an `extensions` field, which [`Trace`] records
+/// with the [`LogFields`] of the registered extensions found in the [`Context`].
+///
 /// [`Span`]: tracing::Span
 /// [`Trace`]: super::Trace
+/// [`LogFields`]: rama_core::context::LogFields
+/// [`Context`]: rama_core::Context
 #[derive(Debug, Clone)]
 pub struct DefaultMakeSpan {
     level: Level,
     include_headers: bool,
+    redactor: Option<Redactor>,
 }
 
 impl DefaultMakeSpan {
@@ -44,6 +52,7 @@ impl DefaultMakeSpan {
         Self {
             level: DEFAULT_MESSAGE_LEVEL,
             include_headers: false,
+            redactor: None,
         }
     }
 
@@ -86,6 +95,28 @@ impl DefaultMakeSpan {
         self.include_headers = include_headers;
         self
     }
+
+    /// Redact the request headers included on the [`Span`]
+    /// using the given [`Redactor`].
+    ///
+    /// By default headers are included as-is.
+    ///
+    /// [`Span`]: tracing::Span
+    pub fn redact(mut self, redactor: Redactor) -> Self {
+        self.redactor = Some(redactor);
+        self
+    }
+
+    /// Redact the request headers included on the [`Span`]
+    /// using the given [`Redactor`].
+    ///
+    /// By default headers are included as-is.
+    ///
+    /// [`Span`]: tracing::Span
+    pub fn set_redact(&mut self, redactor: Redactor) -> &mut Self {
+        self.redactor = Some(redactor);
+        self
+    }
 }
 
 impl Default for DefaultMakeSpan {
@@ -102,13 +133,22 @@ impl<B> MakeSpan<B> for DefaultMakeSpan {
         macro_rules! make_span {
             ($level:expr) => {
                 if self.include_headers {
+                    let headers = match self.redactor.as_ref() {
+                        Some(redactor) => {
+                            let mut headers = request.headers().clone();
+                            redactor.redact_headers(&mut headers);
+                            Cow::Owned(headers)
+                        }
+                        None => Cow::Borrowed(request.headers()),
+                    };
                     tracing::span!(
                         $level,
                         "request",
                         method = %request.method(),
                         uri = %request.uri(),
                         version = ?request.version(),
-                        headers = ?request.headers(),
+                        headers = ?headers,
+                        extensions = tracing::field::Empty,
                     )
                 } else {
                     tracing::span!(
@@ -117,6 +157,7 @@ impl<B> MakeSpan<B> for DefaultMakeSpan {
                         method = %request.method(),
                         uri = %request.uri(),
                         version = ?request.version(),
+                        extensions = tracing::field::Empty,
                     )
                 }
             }
@@ -8,6 +8,7 @@ use crate::layer::classify::{
     ClassifiedResponse, ClassifyResponse, GrpcErrorsAsFailures, MakeClassifier,
     ServerErrorsAsFailures, SharedClassifier,
 };
+use crate::layer::util::log_fields::register_default_log_fields;
 use crate::{Request, Response};
 use rama_core::{Context, Service};
 use rama_utils::macros::define_inner_service_accessors;
@@ -336,6 +337,11 @@ where
         let start = Instant::now();
 
         let span = self.make_span.make_span(&req);
+        register_default_log_fields();
+        let log_fields = ctx.log_fields();
+        if !log_fields.is_empty() {
+            span.record("extensions", tracing::field::display(&log_fields));
+        }
 
         let classifier = self.make_classifier.make_classifier(&req);
 
@@ -0,0 +1,535 @@
+//! Middleware to create a span per request and propagate it
+//! using the [W3C Trace Context] headers (`traceparent` and `tracestate`).
+//!
+//! The [`TraceContextLayer`] is used on the server side. For each incoming request it
+//! extracts the parent [`TraceContext`] from the request headers (or starts a new trace),
+//! creates a (child) [`TraceContext`] for the request, inserts it into the [`Context`]
+//! and serves the request within a [`tracing`] span which records the trace and span ids,
+//! such that the logs and spans of a request can be correlated with the rest of the trace,
+//! e.g. by an OpenTelemetry collector.
+//!
+//! The [`SetTraceContextLayer`] is used on the client side. It injects the
+//! `traceparent` and `tracestate` headers in outbound requests, as a child of the
+//! [`TraceContext`] found in the [`Context`]. As the [`Context`] of an inbound request is
+//! passed on to the client in a proxy, the outbound (proxied) requests are linked to
+//! the span of the inbound request, enabling distributed tracing through rama-based gateways.
+//!
+//! [W3C Trace Context]: https://www.w3.org/TR/trace-context/
+//!
+//! # Example
+//!
+//! ```
+//! use rama_http::layer::trace_context::{SetTraceContextLayer, TraceContext, TraceContextLayer};
+//! use rama_http::{Body, HeaderValue, Request, Response};
+//! use rama_core::service::service_fn;
+//! use rama_core::{Context, Service, Layer};
+//! use rama_core::error::BoxError;
+//! use std::convert::Infallible;
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), BoxError> {
+//! // the upstream client, injecting the trace context in outbound requests
+//! let client = SetTraceContextLayer::new().layer(service_fn(|req: Request| async move {
+//!     let traceparent = req.headers()["traceparent"].clone();
+//!     Ok::<_, Infallible>(Response::new(Body::from(traceparent.as_bytes().to_vec())))
+//! }));
+//!
+//! // the gateway, extracting the trace context from inbound requests
+//! let gateway = TraceContextLayer::new().layer(service_fn(move |ctx: Context<()>, req: Request| {
+//!     let client = client.clone();
+//!     async move { client.serve(ctx, req).await }
+//! }));
+//!
+//! let req = Request::builder()
+//!     .header("traceparent", "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01")
+//!     .body(Body::empty())?;
+//! let resp = gateway.serve(Context::default(), req).await?;
+//!
+//! // the proxied request is part of the same trace
+//! let traceparent = HeaderValue::from_bytes(&resp.into_body().collect().await?.to_bytes())?;
+//! let trace_context = TraceContext::from_traceparent(&traceparent).unwrap();
+//! assert_eq!(trace_context.trace_id(), "0af7651916cd43dd8448eb211c80319c");
+//! assert_ne!(trace_context.span_id(), "b7ad6b7169203331");
+//! # Ok(())
+//! # }
+//! # use rama_http::dep::http_body_util::BodyExt;
+//! ```
+
+use crate::{HeaderMap, HeaderName, HeaderValue, Request};
+use rama_core::{Context, Layer, Service};
+use rama_utils::macros::define_inner_service_accessors;
+use std::fmt;
+use tracing::Instrument;
+
+/// The `traceparent` header, as defined by the W3C Trace Context.
+pub const TRACEPARENT: HeaderName = HeaderName::from_static("traceparent");
+
+/// The `tracestate` header, as defined by the W3C Trace Context.
+pub const TRACESTATE: HeaderName = HeaderName::from_static("tracestate");
+
+/// The only trace flag defined by the W3C Trace Context.
+const FLAG_SAMPLED: u8 = 0x01;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// The [W3C Trace Context] of a request.
+///
+/// Inserted into the [`Context`] by the [`TraceContextLayer`],
+/// and used by the [`SetTraceContextLayer`] to propagate the trace.
+///
+/// [W3C Trace Context]: https://www.w3.org/TR/trace-context/
+pub struct TraceContext {
+    trace_id: u128,
+    span_id: u64,
+    parent_span_id: Option<u64>,
+    flags: u8,
+    trace_state: Option<HeaderValue>,
+}
+
+impl TraceContext {
+    /// Start a new (sampled) trace.
+    pub fn new_root() -> Self {
+        Self {
+            trace_id: random_u128(),
+            span_id: random_span_id(),
+            parent_span_id: None,
+            flags: FLAG_SAMPLED,
+            trace_state: None,
+        }
+    }
+
+    /// Create a child [`TraceContext`], part of the same trace,
+    /// with a new span id and this span as its parent.
+    pub fn child(&self) -> Self {
+        Self {
+            trace_id: self.trace_id,
+            span_id: random_span_id(),
+            parent_span_id: Some(self.span_id),
+            flags: self.flags,
+            trace_state: self.trace_state.clone(),
+        }
+    }
+
+    /// Parse the [`TraceContext`] from the `traceparent` and `tracestate` headers,
+    /// returning `None` if there is no (valid) `traceparent` header.
+    pub fn from_headers(headers: &HeaderMap) -> Option<Self> {
+        let mut values = headers.get_all(TRACEPARENT).iter();
+        let traceparent = values.next()?;
+        if values.next().is_some() {
+            // multiple traceparent headers are invalid
+            return None;
+        }
+        let mut trace_context = Self::from_traceparent(traceparent)?;
+
+        let mut trace_state = Vec::new();
+        for value in headers.get_all(TRACESTATE) {
+            if !trace_state.is_empty() {
+                trace_state.push(b',');
+            }
+            trace_state.extend_from_slice(value.as_bytes());
+        }
+        if !trace_state.is_empty() {
+            trace_context.trace_state = HeaderValue::from_bytes(&trace_state).ok();
+        }
+
+        Some(trace_context)
+    }
+
+    /// Parse the [`TraceContext`] from a `traceparent` header value,
+    /// returning `None` if it is not valid.
+    pub fn from_traceparent(value: &HeaderValue) -> Option<Self> {
+        let value = value.to_str().ok()?;
+        let mut parts = value.split('-');
+        let version = parse_hex::<1>(parts.next()?)?;
+        let trace_id = parse_hex::<16>(parts.next()?)?;
+        let span_id = parse_hex::<8>(parts.next()?)?;
+        let flags = parse_hex::<1>(parts.next()?)?;
+
+        match version[0] {
+            0xff => return None,
+            // version 00 has exactly four parts,
+            // future versions may define additional parts
+            0x00 if parts.next().is_some() => return None,
+            _ => (),
+        }
+
+        let trace_id = u128::from_be_bytes(trace_id);
+        let span_id = u64::from_be_bytes(span_id);
+        if trace_id == 0 || span_id == 0 {
+            return None;
+        }
+
+        Some(Self {
+            trace_id,
+            span_id,
+            parent_span_id: None,
+            flags: flags[0],
+            trace_state: None,
+        })
+    }
+
+    /// The trace id, as 32 lowercase hex characters.
+    pub fn trace_id(&self) -> String {
+        format!("{:032x}", self.trace_id)
+    }
+
+    /// The id of this span, as 16 lowercase hex characters.
+    pub fn span_id(&self) -> String {
+        format!("{:016x}", self.span_id)
+    }
+
+    /// The id of the parent span, as 16 lowercase hex characters,
+    /// `None` if this is the root span of a trace.
+    pub fn parent_span_id(&self) -> Option<String> {
+        self.parent_span_id.map(|id| format!("{id:016x}"))
+    }
+
+    /// Returns `true` if the trace is sampled (recorded) by the caller.
+    pub fn is_sampled(&self) -> bool {
+        self.flags & FLAG_SAMPLED != 0
+    }
+
+    /// The vendor specific `tracestate` of the trace, if any.
+    pub fn trace_state(&self) -> Option<&HeaderValue> {
+        self.trace_state.as_ref()
+    }
+
+    /// The `traceparent` header value for this span.
+    pub fn traceparent(&self) -> HeaderValue {
+        HeaderValue::try_from(format!(
+            "00-{:032x}-{:016x}-{:02x}",
+            self.trace_id, self.span_id, self.flags
+        ))
+        .expect("hex encoded traceparent to be a valid header value")
+    }
+
+    /// Insert the `traceparent` and `tracestate` headers for this span,
+    /// replacing any existing ones.
+    pub fn insert_headers(&self, headers: &mut HeaderMap) {
+        headers.insert(TRACEPARENT, self.traceparent());
+        match &self.trace_state {
+            Some(trace_state) => {
+                headers.insert(TRACESTATE, trace_state.clone());
+            }
+            None => {
+                headers.remove(TRACESTATE);
+            }
+        }
+    }
+}
+
+fn parse_hex<const N: usize>(s: &str) -> Option<[u8; N]> {
+    // uppercase hex is not allowed
+    if s.bytes().any(|b| b.is_ascii_uppercase()) {
+        return None;
+    }
+    let mut out = [0; N];
+    hex::decode_to_slice(s, &mut out).ok()?;
+    Some(out)
+}
+
+fn random_u128() -> u128 {
+    uuid::Uuid::new_v4().as_u128()
+}
+
+fn random_span_id() -> u64 {
+    loop {
+        let id = random_u128() as u64;
+        if id != 0 {
+            return id;
+        }
+    }
+}
+
+/// Layer that applies the [`TraceContextService`] middleware.
+///
+/// See the [module docs](self) for more details.
+#[derive(Debug, Clone, Default)]
+pub struct TraceContextLayer {
+    _priv: (),
+}
+
+impl TraceContextLayer {
+    /// Create a new [`TraceContextLayer`].
+    pub const fn new() -> Self {
+        Self { _priv: () }
+    }
+}
+
+impl<S> Layer<S> for TraceContextLayer {
+    type Service = TraceContextService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        TraceContextService { inner }
+    }
+}
+
+/// Middleware which extracts the [`TraceContext`] of incoming requests
+/// and serves them within a span of that trace.
+///
+/// See the [module docs](self) for more details.
+pub struct TraceContextService<S> {
+    inner: S,
+}
+
+impl<S> TraceContextService<S> {
+    /// Create a new [`TraceContextService`].
+    pub const fn new(inner: S) -> Self {
+        Self { inner }
+    }
+
+    define_inner_service_accessors!();
+}
+
+impl<S: fmt::Debug> fmt::Debug for TraceContextService<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TraceContextService")
+            .field("inner", &self.inner)
+            .finish()
+    }
+}
+
+impl<S: Clone> Clone for TraceContextService<S> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<State, S, ReqBody> Service<State, Request<ReqBody>> for TraceContextService<S>
+where
+    State: Clone + Send + Sync + 'static,
+    S: Service<State, Request<ReqBody>>,
+    ReqBody: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+
+    async fn serve(
+        &self,
+        mut ctx: Context<State>,
+        req: Request<ReqBody>,
+    ) -> Result<Self::Response, Self::Error> {
+        let trace_context = match TraceContext::from_headers(req.headers()) {
+            Some(parent) => parent.child(),
+            None => TraceContext::new_root(),
+        };
+
+        let span = tracing::info_span!(
+            "trace_context",
+            trace_id = %trace_context.trace_id(),
+            span_id = %trace_context.span_id(),
+            parent_span_id = tracing::field::Empty,
+            sampled = trace_context.is_sampled(),
+        );
+        if let Some(parent_span_id) = trace_context.parent_span_id() {
+            span.record("parent_span_id", parent_span_id);
+        }
+
+        ctx.insert(trace_context);
+        self.inner.serve(ctx, req).instrument(span).await
+    }
+}
+
+/// Layer that applies the [`SetTraceContext`] middleware.
+///
+/// See the [module docs](self) for more details.
+#[derive(Debug, Clone, Default)]
+pub struct SetTraceContextLayer {
+    _priv: (),
+}
+
+impl SetTraceContextLayer {
+    /// Create a new [`SetTraceContextLayer`].
+    pub const fn new() -> Self {
+        Self { _priv: () }
+    }
+}
+
+impl<S> Layer<S> for SetTraceContextLayer {
+    type Service = SetTraceContext<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        SetTraceContext { inner }
+    }
+}
+
+/// Middleware which injects the `traceparent` and `tracestate` headers
+/// in outbound requests, as a child of the [`TraceContext`] found in the [`Context`].
+///
+/// Requests are left untouched if there is no [`TraceContext`] in the [`Context`].
+///
+/// See the [module docs](self) for more details.
+pub struct SetTraceContext<S> {
+    inner: S,
+}
+
+impl<S> SetTraceContext<S> {
+    /// Create a new [`SetTraceContext`].
+    pub const fn new(inner: S) -> Self {
+        Self { inner }
+    }
+
+    define_inner_service_accessors!();
+}
+
+impl<S: fmt::Debug> fmt::Debug for SetTraceContext<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SetTraceContext")
+            .field("inner", &self.inner)
+            .finish()
+    }
+}
+
+impl<S: Clone> Clone for SetTraceContext<S> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<State, S, ReqBody> Service<State, Request<ReqBody>> for SetTraceContext<S>
+where
+    State: Clone + Send + Sync + 'static,
+    S: Service<State, Request<ReqBody>>,
+    ReqBody: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+
+    async fn serve(
+        &self,
+        ctx: Context<State>,
+        mut req: Request<ReqBody>,
+    ) -> Result<Self::Response, Self::Error> {
+        if let Some(trace_context) = ctx.get::<TraceContext>() {
+            let trace_context = trace_context.child();
+            tracing::trace!(
+                trace_id = %trace_context.trace_id(),
+                span_id = %trace_context.span_id(),
+                "inject trace context in outbound request",
+            );
+            trace_context.insert_headers(req.headers_mut());
+        }
+        self.inner.serve(ctx, req).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Body, Response};
+    use rama_core::service::service_fn;
+    use std::convert::Infallible;
+
+    #[test]
+    fn test_parse_traceparent() {
+        let trace_context = TraceContext::from_traceparent(&HeaderValue::from_static(
+            "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01",
+        ))
+        .unwrap();
+        assert_eq!(trace_context.trace_id(), "0af7651916cd43dd8448eb211c80319c");
+        assert_eq!(trace_context.span_id(), "b7ad6b7169203331");
+        assert!(trace_context.is_sampled());
+        assert_eq!(
+            trace_context.traceparent(),
+            "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01"
+        );
+
+        // future versions may have more parts
+        assert!(TraceContext::from_traceparent(&HeaderValue::from_static(
+            "01-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-00-foo",
+        ))
+        .is_some_and(|trace_context| !trace_context.is_sampled()));
+
+        for invalid in [
+            "",
+            "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331",
+            "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01-foo",
+            "ff-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01",
+            "00-0AF7651916CD43DD8448EB211C80319C-b7ad6b7169203331-01",
+            "00-00000000000000000000000000000000-b7ad6b7169203331-01",
+            "00-0af7651916cd43dd8448eb211c80319c-0000000000000000-01",
+            "00-0af7651916cd43dd8448eb211c8031-b7ad6b7169203331-01",
+            "00-0af7651916cd43dd8448eb211c80319c-b7ad6b716920333z-01",
+        ] {
+            assert!(
+                TraceContext::from_traceparent(&HeaderValue::from_static(invalid)).is_none(),
+                "{invalid}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_trace_context_headers() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            TRACEPARENT,
+            HeaderValue::from_static("00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01"),
+        );
+        headers.append(TRACESTATE, HeaderValue::from_static("a=1"));
+        headers.append(TRACESTATE, HeaderValue::from_static("b=2"));
+
+        let parent = TraceContext::from_headers(&headers).unwrap();
+        assert_eq!(parent.trace_state().unwrap(), "a=1,b=2");
+
+        let child = parent.child();
+        assert_eq!(child.trace_id(), parent.trace_id());
+        assert_ne!(child.span_id(), parent.span_id());
+        assert_eq!(child.parent_span_id(), Some(parent.span_id()));
+
+        let mut headers = HeaderMap::new();
+        child.insert_headers(&mut headers);
+        assert_eq!(headers[TRACEPARENT], child.traceparent());
+        assert_eq!(headers[TRACESTATE], "a=1,b=2");
+    }
+
+    #[tokio::test]
+    async fn test_trace_context_propagation() {
+        let client = SetTraceContextLayer::new().layer(service_fn(|req: Request| async move {
+            Ok::<_, Infallible>(Response::new(req.headers().clone()))
+        }));
+        let gateway =
+            TraceContextLayer::new().layer(service_fn(move |ctx: Context<()>, req: Request| {
+                let client = client.clone();
+                async move {
+                    let trace_context = ctx.get::<TraceContext>().unwrap().clone();
+                    let resp = client.serve(ctx, req).await?;
+                    Ok::<_, Infallible>((trace_context, resp.into_body()))
+                }
+            }));
+
+        // new trace
+        let (trace_context, headers) = gateway
+            .serve(Context::default(), Request::new(Body::empty()))
+            .await
+            .unwrap();
+        assert!(trace_context.parent_span_id().is_none());
+        let outbound = TraceContext::from_headers(&headers).unwrap();
+        assert_eq!(outbound.trace_id(), trace_context.trace_id());
+        assert_ne!(outbound.span_id(), trace_context.span_id());
+
+        // continued trace, inbound headers are replaced
+        let req = Request::builder()
+            .header(
+                TRACEPARENT,
+                "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-00",
+            )
+            .header(TRACESTATE, "vendor=x")
+            .body(Body::empty())
+            .unwrap();
+        let (trace_context, headers) = gateway.serve(Context::default(), req).await.unwrap();
+        assert_eq!(trace_context.trace_id(), "0af7651916cd43dd8448eb211c80319c");
+        assert_eq!(
+            trace_context.parent_span_id().as_deref(),
+            Some("b7ad6b7169203331")
+        );
+        assert!(!trace_context.is_sampled());
+        let outbound = TraceContext::from_headers(&headers).unwrap();
+        assert_eq!(outbound.trace_id(), "0af7651916cd43dd8448eb211c80319c");
+        assert_ne!(outbound.span_id(), "b7ad6b7169203331");
+        assert_ne!(outbound.span_id(), trace_context.span_id());
+        assert_eq!(headers[TRACESTATE], "vendor=x");
+    }
+}
@@ -0,0 +1,526 @@
+//! Middleware to account for the size of each request and response.
+//!
+//! The [`TrafficSizeLayer`] inserts a [`TrafficSize`] extension in the [`Context`]
+//! (and the response extensions), which counts the header and body bytes
+//! of the request and its response. The body bytes are counted as the
+//! body is consumed, so the sizes are only complete once the response
+//! body has been received completely.
+//!
+//! Proxy operators can use it to bill or budget per user. Place it
+//! as the outermost http layer to account for the bytes as they are
+//! sent on the wire, and optionally add a [`TrafficSizeLayer::content`]
+//! layer within the (de)compression layers to also account for
+//! the (decompressed) content bytes of the request and response bodies.
+//!
+//! Headers are accounted for as the size of their http/1.1 encoding,
+//! regardless of the http version used.
+//!
+//! # Example
+//!
+//! ```
+//! use rama_http::layer::traffic_size::{TrafficSize, TrafficSizeBody, TrafficSizeLayer};
+//! use rama_http::{Body, Request, Response};
+//! use rama_http::dep::http_body_util::BodyExt;
+//! use rama_core::service::service_fn;
+//! use rama_core::{Context, Service, Layer};
+//! use rama_core::error::BoxError;
+//! use std::convert::Infallible;
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), BoxError> {
+//! let service = TrafficSizeLayer::new().layer(service_fn(
+//!     |_req: Request<TrafficSizeBody<Body>>| async {
+//!         Ok::<_, Infallible>(Response::new(Body::from("hello")))
+//!     },
+//! ));
+//!
+//! let req = Request::builder()
+//!     .uri("/")
+//!     .body(Body::from("hi"))
+//!     .unwrap();
+//! let resp = service.serve(Context::default(), req).await?;
+//! let size = resp.extensions().get::<TrafficSize>().unwrap().clone();
+//! // body bytes are counted as the body is consumed
+//! resp.into_body().collect().await.unwrap();
+//!
+//! // "GET / HTTP/1.1\r\n\r\n"
+//! assert_eq!(size.request_header_bytes(), 18);
+//! // "HTTP/1.1 200 OK\r\n\r\n"
+//! assert_eq!(size.response_header_bytes(), 19);
+//! assert_eq!(size.response_body_bytes(), 5);
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::dep::http_body::{Body as HttpBody, Frame, SizeHint};
+use crate::{HeaderMap, Method, Request, Response};
+use futures_lite::ready;
+use pin_project_lite::pin_project;
+use rama_core::{Context, Layer, Service};
+use rama_utils::macros::define_inner_service_accessors;
+use std::{
+    fmt,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+    task::{self, Poll},
+};
+
+/// Size of a request and its response, in bytes.
+///
+/// Clones share the same counters, such that the sizes
+/// can be read once the response body has been consumed.
+///
+/// See the [module docs](self) for more details.
+#[derive(Debug, Clone, Default)]
+pub struct TrafficSize {
+    inner: Arc<TrafficSizeInner>,
+}
+
+#[derive(Debug, Default)]
+struct TrafficSizeInner {
+    request_header_bytes: AtomicU64,
+    request_body_bytes: AtomicU64,
+    request_content_bytes: AtomicU64,
+    response_header_bytes: AtomicU64,
+    response_body_bytes: AtomicU64,
+    response_content_bytes: AtomicU64,
+    content_tracked: AtomicBool,
+}
+
+impl TrafficSize {
+    /// Create a new [`TrafficSize`], with all sizes zero.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The size of the request head (request line and headers).
+    pub fn request_header_bytes(&self) -> u64 {
+        self.inner.request_header_bytes.load(Ordering::Relaxed)
+    }
+
+    /// The size of the request body (and trailers) as sent on the wire.
+    pub fn request_body_bytes(&self) -> u64 {
+        self.inner.request_body_bytes.load(Ordering::Relaxed)
+    }
+
+    /// The size of the (decompressed) request body content,
+    /// only tracked when a [`TrafficSizeLayer::content`] layer is used.
+    pub fn request_content_bytes(&self) -> Option<u64> {
+        self.content_tracked()
+            .then(|| self.inner.request_content_bytes.load(Ordering::Relaxed))
+    }
+
+    /// The total size of the request as sent on the wire.
+    pub fn request_bytes(&self) -> u64 {
+        self.request_header_bytes() + self.request_body_bytes()
+    }
+
+    /// The size of the response head (status line and headers).
+    pub fn response_header_bytes(&self) -> u64 {
+        self.inner.response_header_bytes.load(Ordering::Relaxed)
+    }
+
+    /// The size of the response body (and trailers) as sent on the wire.
+    pub fn response_body_bytes(&self) -> u64 {
+        self.inner.response_body_bytes.load(Ordering::Relaxed)
+    }
+
+    /// The size of the (uncompressed) response body content,
+    /// only tracked when a [`TrafficSizeLayer::content`] layer is used.
+    pub fn response_content_bytes(&self) -> Option<u64> {
+        self.content_tracked()
+            .then(|| self.inner.response_content_bytes.load(Ordering::Relaxed))
+    }
+
+    /// The total size of the response as sent on the wire.
+    pub fn response_bytes(&self) -> u64 {
+        self.response_header_bytes() + self.response_body_bytes()
+    }
+
+    /// The total size of the request and response as sent on the wire.
+    pub fn total_bytes(&self) -> u64 {
+        self.request_bytes() + self.response_bytes()
+    }
+
+    fn content_tracked(&self) -> bool {
+        self.inner.content_tracked.load(Ordering::Relaxed)
+    }
+
+    fn counter(&self, counter: Counter) -> &AtomicU64 {
+        match counter {
+            Counter::RequestBody => &self.inner.request_body_bytes,
+            Counter::RequestContent => &self.inner.request_content_bytes,
+            Counter::ResponseBody => &self.inner.response_body_bytes,
+            Counter::ResponseContent => &self.inner.response_content_bytes,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Counter {
+    RequestBody,
+    RequestContent,
+    ResponseBody,
+    ResponseContent,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Wire,
+    Content,
+}
+
+/// Layer that applies the [`TrafficSizeService`] middleware.
+///
+/// See the [module docs](self) for more details.
+#[derive(Debug, Clone)]
+pub struct TrafficSizeLayer {
+    mode: Mode,
+}
+
+impl TrafficSizeLayer {
+    /// Create a new [`TrafficSizeLayer`], accounting for the
+    /// header and body bytes as sent on the wire.
+    pub const fn new() -> Self {
+        Self { mode: Mode::Wire }
+    }
+
+    /// Create a new [`TrafficSizeLayer`], accounting for the (decompressed)
+    /// body content bytes, to be used within the (de)compression layers.
+    pub const fn content() -> Self {
+        Self {
+            mode: Mode::Content,
+        }
+    }
+}
+
+impl Default for TrafficSizeLayer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S> Layer<S> for TrafficSizeLayer {
+    type Service = TrafficSizeService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        TrafficSizeService {
+            inner,
+            mode: self.mode,
+        }
+    }
+}
+
+/// Middleware which accounts for the size of each request and response.
+///
+/// See the [module docs](self) for more details.
+pub struct TrafficSizeService<S> {
+    inner: S,
+    mode: Mode,
+}
+
+impl<S> TrafficSizeService<S> {
+    /// Create a new [`TrafficSizeService`], accounting for the
+    /// header and body bytes as sent on the wire.
+    pub const fn new(inner: S) -> Self {
+        Self {
+            inner,
+            mode: Mode::Wire,
+        }
+    }
+
+    /// Create a new [`TrafficSizeService`], accounting for the (decompressed)
+    /// body content bytes, to be used within the (de)compression layers.
+    pub const fn content(inner: S) -> Self {
+        Self {
+            inner,
+            mode: Mode::Content,
+        }
+    }
+
+    define_inner_service_accessors!();
+}
+
+impl<S: fmt::Debug> fmt::Debug for TrafficSizeService<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TrafficSizeService")
+            .field("inner", &self.inner)
+            .field("mode", &self.mode)
+            .finish()
+    }
+}
+
+impl<S: Clone> Clone for TrafficSizeService<S> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            mode: self.mode,
+        }
+    }
+}
+
+impl<State, S, ReqBody, ResBody> Service<State, Request<ReqBody>> for TrafficSizeService<S>
+where
+    State: Clone + Send + Sync + 'static,
+    S: Service<State, Request<TrafficSizeBody<ReqBody>>, Response = Response<ResBody>>,
+    ReqBody: Send + 'static,
+    ResBody: Send + 'static,
+{
+    type Response = Response<TrafficSizeBody<ResBody>>;
+    type Error = S::Error;
+
+    async fn serve(
+        &self,
+        mut ctx: Context<State>,
+        req: Request<ReqBody>,
+    ) -> Result<Self::Response, Self::Error> {
+        let size = ctx.get_or_insert_default::<TrafficSize>().clone();
+
+        let (request_counter, response_counter) = match self.mode {
+            Mode::Wire => {
+                size.inner
+                    .request_header_bytes
+                    .fetch_add(request_head_size(&req), Ordering::Relaxed);
+                (Counter::RequestBody, Counter::ResponseBody)
+            }
+            Mode::Content => {
+                size.inner.content_tracked.store(true, Ordering::Relaxed);
+                (Counter::RequestContent, Counter::ResponseContent)
+            }
+        };
+
+        let req = req.map(|body| TrafficSizeBody::new(body, size.clone(), request_counter));
+        let mut resp = self.inner.serve(ctx, req).await?;
+
+        if self.mode == Mode::Wire {
+            size.inner
+                .response_header_bytes
+                .fetch_add(response_head_size(&resp), Ordering::Relaxed);
+        }
+        resp.extensions_mut().insert(size.clone());
+        Ok(resp.map(|body| TrafficSizeBody::new(body, size, response_counter)))
+    }
+}
+
+/// Size of the http/1.1 encoded request line and headers.
+fn request_head_size<B>(req: &Request<B>) -> u64 {
+    let target = if req.method() == Method::CONNECT {
+        req.uri()
+            .authority()
+            .map(|a| a.as_str().len())
+            .unwrap_or_default()
+    } else {
+        req.uri()
+            .path_and_query()
+            .map(|pq| pq.as_str().len())
+            .unwrap_or(1)
+    };
+    // "{method} {target} HTTP/1.1\r\n"
+    let line = req.method().as_str().len() + 1 + target + 1 + 8 + 2;
+    (line + headers_size(req.headers())) as u64
+}
+
+/// Size of the http/1.1 encoded status line and headers.
+fn response_head_size<B>(resp: &Response<B>) -> u64 {
+    let reason = resp
+        .status()
+        .canonical_reason()
+        .map(str::len)
+        .unwrap_or_default();
+    // "HTTP/1.1 {code} {reason}\r\n"
+    let line = 8 + 1 + 3 + 1 + reason + 2;
+    (line + headers_size(resp.headers())) as u64
+}
+
+/// Size of the http/1.1 encoded headers, including the final empty line.
+fn headers_size(headers: &HeaderMap) -> usize {
+    headers
+        .iter()
+        // "{name}: {value}\r\n"
+        .map(|(name, value)| name.as_str().len() + 2 + value.len() + 2)
+        .sum::<usize>()
+        + 2
+}
+
+pin_project! {
+    /// Request and response body used by [`TrafficSizeService`],
+    /// counting the bytes of the body as it is consumed.
+    pub struct TrafficSizeBody<B> {
+        #[pin]
+        inner: B,
+        size: TrafficSize,
+        counter: Counter,
+    }
+}
+
+impl<B> TrafficSizeBody<B> {
+    fn new(inner: B, size: TrafficSize, counter: Counter) -> Self {
+        Self {
+            inner,
+            size,
+            counter,
+        }
+    }
+}
+
+impl<B: fmt::Debug> fmt::Debug for TrafficSizeBody<B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TrafficSizeBody")
+            .field("inner", &self.inner)
+            .field("size", &self.size)
+            .field("counter", &self.counter)
+            .finish()
+    }
+}
+
+impl<B> HttpBody for TrafficSizeBody<B>
+where
+    B: HttpBody,
+{
+    type Data = B::Data;
+    type Error = B::Error;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut task::Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let this = self.project();
+        let result = ready!(this.inner.poll_frame(cx));
+        if let Some(Ok(frame)) = &result {
+            let bytes = if let Some(data) = frame.data_ref() {
+                bytes::Buf::remaining(data)
+            } else if let Some(trailers) = frame.trailers_ref() {
+                headers_size(trailers)
+            } else {
+                0
+            };
+            this.size
+                .counter(*this.counter)
+                .fetch_add(bytes as u64, Ordering::Relaxed);
+        }
+        Poll::Ready(result)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dep::http_body_util::BodyExt;
+    use crate::{header::CONTENT_TYPE, Body, StatusCode};
+    use rama_core::service::service_fn;
+    use std::convert::Infallible;
+
+    #[tokio::test]
+    async fn test_traffic_size_wire() {
+        let service = TrafficSizeLayer::new().layer(service_fn(
+            |ctx: Context<()>, req: Request<TrafficSizeBody<Body>>| async move {
+                let body = req.into_body().collect().await.unwrap().to_bytes();
+                // the size is available to the inner services as well
+                let size = ctx.get::<TrafficSize>().unwrap();
+                assert_eq!(size.request_body_bytes(), 5);
+                assert_eq!(size.request_content_bytes(), None);
+                Ok::<_, Infallible>(
+                    Response::builder()
+                        .status(StatusCode::CREATED)
+                        .header(CONTENT_TYPE, "text/plain")
+                        .body(Body::from(body.repeat(2)))
+                        .unwrap(),
+                )
+            },
+        ));
+
+        let req = Request::builder()
+            .method(Method::POST)
+            .uri("http://example.com/foo?bar=baz")
+            .header("x-a", "b")
+            .body(Body::from("hello"))
+            .unwrap();
+        let resp = service.serve(Context::default(), req).await.unwrap();
+        let size = resp.extensions().get::<TrafficSize>().unwrap().clone();
+        resp.into_body().collect().await.unwrap();
+
+        // "POST /foo?bar=baz HTTP/1.1\r\nx-a: b\r\n\r\n"
+        assert_eq!(size.request_header_bytes(), 38);
+        assert_eq!(size.request_body_bytes(), 5);
+        assert_eq!(size.request_bytes(), 43);
+        // "HTTP/1.1 201 Created\r\ncontent-type: text/plain\r\n\r\n"
+        assert_eq!(size.response_header_bytes(), 50);
+        assert_eq!(size.response_body_bytes(), 10);
+        assert_eq!(size.response_bytes(), 60);
+        assert_eq!(size.total_bytes(), 103);
+    }
+
+    #[tokio::test]
+    async fn test_traffic_size_connect() {
+        let service = TrafficSizeLayer::new().layer(service_fn(
+            |_req: Request<TrafficSizeBody<Body>>| async move {
+                Ok::<_, Infallible>(Response::new(Body::empty()))
+            },
+        ));
+
+        let req = Request::builder()
+            .method(Method::CONNECT)
+            .uri("example.com:443")
+            .body(Body::empty())
+            .unwrap();
+        let resp = service.serve(Context::default(), req).await.unwrap();
+        let size = resp.extensions().get::<TrafficSize>().unwrap();
+
+        // "CONNECT example.com:443 HTTP/1.1\r\n\r\n"
+        assert_eq!(size.request_header_bytes(), 36);
+        assert_eq!(size.request_body_bytes(), 0);
+    }
+
+    #[cfg(feature = "compression")]
+    #[tokio::test]
+    async fn test_traffic_size_content() {
+        use crate::header::ACCEPT_ENCODING;
+        use crate::layer::compression::CompressionLayer;
+
+        let content = "hello ".repeat(100);
+        let service = (
+            TrafficSizeLayer::new(),
+            CompressionLayer::new(),
+            TrafficSizeLayer::content(),
+        )
+            .layer(service_fn({
+                let content = content.clone();
+                move |_req: Request<_>| {
+                    let content = content.clone();
+                    async move {
+                        Ok::<_, Infallible>(
+                            Response::builder()
+                                .header(CONTENT_TYPE, "text/plain")
+                                .body(Body::from(content))
+                                .unwrap(),
+                        )
+                    }
+                }
+            }));
+
+        let req = Request::builder()
+            .uri("/")
+            .header(ACCEPT_ENCODING, "gzip")
+            .body(Body::empty())
+            .unwrap();
+        let resp = service.serve(Context::default(), req).await.unwrap();
+        let size = resp.extensions().get::<TrafficSize>().unwrap().clone();
+        let body = resp.into_body().collect().await.unwrap().to_bytes();
+
+        assert_eq!(size.response_body_bytes(), body.len() as u64);
+        assert_eq!(size.response_content_bytes(), Some(content.len() as u64));
+        assert!(size.response_body_bytes() < content.len() as u64);
+        assert_eq!(size.request_content_bytes(), Some(0));
+    }
+}
@@ -0,0 +1,650 @@
+//! Middleware to track traffic statistics per destination host.
+//!
+//! The [`TrafficStatsLayer`] records for each request the destination host
+//! (as found in the [`RequestContext`]), the bytes sent and received,
+//! the time to first byte (TTFB) and whether it failed. These are aggregated
+//! in a ring of time buckets per host, such that [`TrafficStats::snapshot`]
+//! reports on a sliding window (by default the last hour, in buckets of a minute).
+//!
+//! The [`TrafficStats`] is also a [`Service`] which responds with the
+//! snapshot as JSON, ready to be mounted on an admin endpoint. With the
+//! `telemetry` feature enabled the snapshot can also be reported as metrics,
+//! using [`TrafficStats::register_metrics`].
+//!
+//! [`RequestContext`]: rama_net::http::RequestContext
+//!
+//! # Example
+//!
+//! ```
+//! use rama_http::layer::traffic_stats::{TrafficStats, TrafficStatsBody, TrafficStatsLayer};
+//! use rama_http::{Body, Request, Response};
+//! use rama_http::dep::http_body_util::BodyExt;
+//! use rama_core::service::service_fn;
+//! use rama_core::{Context, Service, Layer};
+//! use rama_core::error::BoxError;
+//! use std::convert::Infallible;
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), BoxError> {
+//! let stats = TrafficStats::default();
+//!
+//! let service = TrafficStatsLayer::new(stats.clone()).layer(service_fn(
+//!     |_req: Request<TrafficStatsBody<Body>>| async {
+//!         Ok::<_, Infallible>(Response::new(Body::from("hello")))
+//!     },
+//! ));
+//!
+//! let req = Request::builder()
+//!     .uri("http://example.com")
+//!     .body(Body::empty())
+//!     .unwrap();
+//! let resp = service.serve(Context::default(), req).await?;
+//! // bytes are counted as the body is consumed
+//! resp.into_body().collect().await.unwrap();
+//!
+//! let snapshot = stats.snapshot();
+//! assert_eq!(snapshot[0].host, "example.com");
+//! assert_eq!(snapshot[0].requests, 1);
+//! assert_eq!(snapshot[0].bytes_received, 5);
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::dep::http_body::{Body as HttpBody, Frame, SizeHint};
+use crate::response::Json;
+use crate::{IntoResponse, Request, Response};
+use futures_lite::ready;
+use pin_project_lite::pin_project;
+use rama_core::{Context, Layer, Service};
+use rama_net::http::RequestContext;
+use rama_utils::macros::define_inner_service_accessors;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    convert::Infallible,
+    fmt,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{self, Poll},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+/// The default duration of a single time bucket.
+pub const DEFAULT_BUCKET_DURATION: Duration = Duration::from_secs(60);
+
+/// The default amount of time buckets kept per host.
+pub const DEFAULT_BUCKET_COUNT: usize = 60;
+
+/// Host used for requests of which the destination could not be determined.
+const UNKNOWN_HOST: &str = "unknown";
+
+/// Shared traffic statistics, tracked per destination host.
+///
+/// See the [module docs](self) for more details.
+#[derive(Debug, Clone)]
+pub struct TrafficStats {
+    inner: Arc<TrafficStatsInner>,
+}
+
+#[derive(Debug)]
+struct TrafficStatsInner {
+    bucket_duration: Duration,
+    bucket_count: usize,
+    hosts: Mutex<HashMap<String, Vec<Bucket>>>,
+}
+
+#[derive(Debug, Clone, Default)]
+struct Bucket {
+    /// index of the time window this bucket is for
+    window: u64,
+    requests: u64,
+    errors: u64,
+    bytes_sent: u64,
+    bytes_received: u64,
+    ttfb_total: Duration,
+}
+
+/// Statistics of a single host, aggregated over the tracked time buckets.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HostTrafficStats {
+    /// The destination host.
+    pub host: String,
+    /// The amount of requests made to this host.
+    pub requests: u64,
+    /// The amount of requests which failed (error or 5xx status).
+    pub errors: u64,
+    /// The ratio of failed requests, between 0 and 1.
+    pub error_rate: f64,
+    /// The amount of (request body) bytes sent to this host.
+    pub bytes_sent: u64,
+    /// The amount of (response body) bytes received from this host.
+    pub bytes_received: u64,
+    /// The mean time to first byte (response head), in milliseconds.
+    pub mean_ttfb_ms: f64,
+}
+
+impl Default for TrafficStats {
+    fn default() -> Self {
+        Self::new(DEFAULT_BUCKET_DURATION, DEFAULT_BUCKET_COUNT)
+    }
+}
+
+impl TrafficStats {
+    /// Create a new [`TrafficStats`] with `bucket_count` time buckets
+    /// of `bucket_duration` each, making up the sliding window reported on.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the bucket duration is smaller than a second or the bucket count is zero.
+    pub fn new(bucket_duration: Duration, bucket_count: usize) -> Self {
+        assert!(
+            bucket_duration >= Duration::from_secs(1),
+            "traffic stats: bucket duration has to be at least one second"
+        );
+        assert!(
+            bucket_count > 0,
+            "traffic stats: at least one bucket is required"
+        );
+        Self {
+            inner: Arc::new(TrafficStatsInner {
+                bucket_duration,
+                bucket_count,
+                hosts: Mutex::new(HashMap::new()),
+            }),
+        }
+    }
+
+    /// Snapshot of the statistics per host within the current sliding window,
+    /// sorted by the total amount of bytes transferred (most first).
+    pub fn snapshot(&self) -> Vec<HostTrafficStats> {
+        let window = self.current_window();
+        let oldest = window.saturating_sub(self.inner.bucket_count as u64 - 1);
+
+        let hosts = self.inner.hosts.lock().unwrap();
+        let mut snapshot: Vec<_> = hosts
+            .iter()
+            .filter_map(|(host, buckets)| {
+                let total = buckets
+                    .iter()
+                    .filter(|bucket| bucket.window >= oldest && bucket.window <= window)
+                    .fold(Bucket::default(), |mut total, bucket| {
+                        total.requests += bucket.requests;
+                        total.errors += bucket.errors;
+                        total.bytes_sent += bucket.bytes_sent;
+                        total.bytes_received += bucket.bytes_received;
+                        total.ttfb_total += bucket.ttfb_total;
+                        total
+                    });
+                if total.requests == 0 {
+                    return None;
+                }
+                Some(HostTrafficStats {
+                    host: host.clone(),
+                    requests: total.requests,
+                    errors: total.errors,
+                    error_rate: total.errors as f64 / total.requests as f64,
+                    bytes_sent: total.bytes_sent,
+                    bytes_received: total.bytes_received,
+                    mean_ttfb_ms: total.ttfb_total.as_secs_f64() * 1000. / total.requests as f64,
+                })
+            })
+            .collect();
+        drop(hosts);
+
+        snapshot.sort_by(|a, b| {
+            (b.bytes_sent + b.bytes_received)
+                .cmp(&(a.bytes_sent + a.bytes_received))
+                .then_with(|| b.requests.cmp(&a.requests))
+                .then_with(|| a.host.cmp(&b.host))
+        });
+        snapshot
+    }
+
+    /// Record bytes transferred with the given host outside of the [`TrafficStatsLayer`],
+    /// e.g. for the traffic tunneled over an (http) `CONNECT` request.
+    pub fn record_bytes(&self, host: &str, bytes_sent: u64, bytes_received: u64) {
+        if bytes_sent == 0 && bytes_received == 0 {
+            return;
+        }
+        self.record(host, |bucket| {
+            bucket.bytes_sent += bytes_sent;
+            bucket.bytes_received += bytes_received;
+        })
+    }
+
+    /// Remove all tracked statistics.
+    pub fn clear(&self) {
+        self.inner.hosts.lock().unwrap().clear();
+    }
+
+    fn current_window(&self) -> u64 {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        now.as_secs() / self.inner.bucket_duration.as_secs()
+    }
+
+    fn record(&self, host: &str, f: impl FnOnce(&mut Bucket)) {
+        let window = self.current_window();
+        let bucket_count = self.inner.bucket_count;
+
+        let mut hosts = self.inner.hosts.lock().unwrap();
+        let buckets = match hosts.get_mut(host) {
+            Some(buckets) => buckets,
+            None => hosts
+                .entry(host.to_owned())
+                .or_insert_with(|| vec![Bucket::default(); bucket_count]),
+        };
+        let bucket = &mut buckets[(window % bucket_count as u64) as usize];
+        if bucket.window != window {
+            *bucket = Bucket {
+                window,
+                ..Default::default()
+            };
+        }
+        f(bucket)
+    }
+}
+
+impl<State> Service<State, Request> for TrafficStats
+where
+    State: Clone + Send + Sync + 'static,
+{
+    type Response = Response;
+    type Error = Infallible;
+
+    async fn serve(
+        &self,
+        _ctx: Context<State>,
+        _req: Request,
+    ) -> Result<Self::Response, Self::Error> {
+        Ok(Json(self.snapshot()).into_response())
+    }
+}
+
+#[cfg(feature = "telemetry")]
+/// The OpenTelemetry instruments registered by [`TrafficStats::register_metrics`].
+///
+/// The metrics are reported for as long as this value is kept alive.
+#[derive(Debug)]
+pub struct TrafficStatsMetrics {
+    _requests: rama_core::telemetry::opentelemetry::metrics::ObservableGauge<u64>,
+    _errors: rama_core::telemetry::opentelemetry::metrics::ObservableGauge<u64>,
+    _bytes_sent: rama_core::telemetry::opentelemetry::metrics::ObservableGauge<u64>,
+    _bytes_received: rama_core::telemetry::opentelemetry::metrics::ObservableGauge<u64>,
+    _mean_ttfb: rama_core::telemetry::opentelemetry::metrics::ObservableGauge<f64>,
+}
+
+#[cfg(feature = "telemetry")]
+impl TrafficStats {
+    /// Register observable gauges on the given [`Meter`] which report
+    /// the [`snapshot`] of each host (as the `http.request.host` attribute)
+    /// every time the metrics are collected.
+    ///
+    /// [`Meter`]: rama_core::telemetry::opentelemetry::metrics::Meter
+    /// [`snapshot`]: TrafficStats::snapshot
+    pub fn register_metrics(
+        &self,
+        meter: &rama_core::telemetry::opentelemetry::metrics::Meter,
+    ) -> TrafficStatsMetrics {
+        use rama_core::telemetry::opentelemetry::KeyValue;
+
+        fn host_attributes(stats: &HostTrafficStats) -> [KeyValue; 1] {
+            [KeyValue::new("http.request.host", stats.host.clone())]
+        }
+
+        macro_rules! u64_gauge {
+            ($name:literal, $description:literal, $field:ident) => {{
+                let traffic_stats = self.clone();
+                meter
+                    .u64_observable_gauge($name)
+                    .with_description($description)
+                    .with_callback(move |observer| {
+                        for stats in traffic_stats.snapshot() {
+                            observer.observe(stats.$field, &host_attributes(&stats));
+                        }
+                    })
+                    .init()
+            }};
+        }
+
+        let traffic_stats = self.clone();
+        TrafficStatsMetrics {
+            _requests: u64_gauge!(
+                "traffic.host.requests",
+                "Amount of requests made to the host within the tracked window.",
+                requests
+            ),
+            _errors: u64_gauge!(
+                "traffic.host.errors",
+                "Amount of failed requests made to the host within the tracked window.",
+                errors
+            ),
+            _bytes_sent: u64_gauge!(
+                "traffic.host.bytes_sent",
+                "Amount of bytes sent to the host within the tracked window.",
+                bytes_sent
+            ),
+            _bytes_received: u64_gauge!(
+                "traffic.host.bytes_received",
+                "Amount of bytes received from the host within the tracked window.",
+                bytes_received
+            ),
+            _mean_ttfb: meter
+                .f64_observable_gauge("traffic.host.ttfb")
+                .with_description("Mean time to first byte of the host within the tracked window.")
+                .with_unit("ms")
+                .with_callback(move |observer| {
+                    for stats in traffic_stats.snapshot() {
+                        observer.observe(stats.mean_ttfb_ms, &host_attributes(&stats));
+                    }
+                })
+                .init(),
+        }
+    }
+}
+
+/// Layer that applies the [`TrafficStatsService`] middleware.
+///
+/// See the [module docs](self) for more details.
+#[derive(Debug, Clone)]
+pub struct TrafficStatsLayer {
+    stats: TrafficStats,
+}
+
+impl TrafficStatsLayer {
+    /// Create a new [`TrafficStatsLayer`] recording into the given [`TrafficStats`].
+    pub const fn new(stats: TrafficStats) -> Self {
+        Self { stats }
+    }
+}
+
+impl<S> Layer<S> for TrafficStatsLayer {
+    type Service = TrafficStatsService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        TrafficStatsService {
+            inner,
+            stats: self.stats.clone(),
+        }
+    }
+}
+
+/// Middleware which records traffic statistics per destination host.
+///
+/// See the [module docs](self) for more details.
+pub struct TrafficStatsService<S> {
+    inner: S,
+    stats: TrafficStats,
+}
+
+impl<S> TrafficStatsService<S> {
+    /// Create a new [`TrafficStatsService`] recording into the given [`TrafficStats`].
+    pub const fn new(inner: S, stats: TrafficStats) -> Self {
+        Self { inner, stats }
+    }
+
+    define_inner_service_accessors!();
+}
+
+impl<S: fmt::Debug> fmt::Debug for TrafficStatsService<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TrafficStatsService")
+            .field("inner", &self.inner)
+            .field("stats", &self.stats)
+            .finish()
+    }
+}
+
+impl<S: Clone> Clone for TrafficStatsService<S> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            stats: self.stats.clone(),
+        }
+    }
+}
+
+impl<State, S, ReqBody, ResBody> Service<State, Request<ReqBody>> for TrafficStatsService<S>
+where
+    State: Clone + Send + Sync + 'static,
+    S: Service<State, Request<TrafficStatsBody<ReqBody>>, Response = Response<ResBody>>,
+    ReqBody: Send + 'static,
+    ResBody: Send + 'static,
+{
+    type Response = Response<TrafficStatsBody<ResBody>>;
+    type Error = S::Error;
+
+    async fn serve(
+        &self,
+        mut ctx: Context<State>,
+        req: Request<ReqBody>,
+    ) -> Result<Self::Response, Self::Error> {
+        let host: Arc<str> = ctx
+            .get_or_try_insert_with_ctx::<RequestContext, _>(|ctx| (ctx, &req).try_into())
+            .map(|request_ctx| request_ctx.authority.host().to_string().into())
+            .unwrap_or_else(|_| UNKNOWN_HOST.into());
+
+        let req = req.map(|body| {
+            TrafficStatsBody::new(body, self.stats.clone(), host.clone(), Direction::Sent)
+        });
+
+        let start = Instant::now();
+        let result = self.inner.serve(ctx, req).await;
+        let ttfb = start.elapsed();
+
+        let failed = match &result {
+            Ok(resp) => resp.status().is_server_error(),
+            Err(_) => true,
+        };
+        self.stats.record(&host, |bucket| {
+            bucket.requests += 1;
+            bucket.ttfb_total += ttfb;
+            if failed {
+                bucket.errors += 1;
+            }
+        });
+
+        result.map(|resp| {
+            resp.map(|body| {
+                TrafficStatsBody::new(body, self.stats.clone(), host, Direction::Received)
+            })
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Direction {
+    Sent,
+    Received,
+}
+
+/// Records the counted bytes once the body is dropped.
+struct BytesRecorder {
+    stats: TrafficStats,
+    host: Arc<str>,
+    direction: Direction,
+    bytes: u64,
+}
+
+impl Drop for BytesRecorder {
+    fn drop(&mut self) {
+        if self.bytes == 0 {
+            return;
+        }
+        let bytes = self.bytes;
+        let direction = self.direction;
+        self.stats.record(&self.host, |bucket| match direction {
+            Direction::Sent => bucket.bytes_sent += bytes,
+            Direction::Received => bucket.bytes_received += bytes,
+        });
+    }
+}
+
+pin_project! {
+    /// Request and response body used by [`TrafficStatsService`],
+    /// counting the bytes of the body as it is consumed.
+    pub struct TrafficStatsBody<B> {
+        #[pin]
+        inner: B,
+        recorder: BytesRecorder,
+    }
+}
+
+impl<B> TrafficStatsBody<B> {
+    fn new(inner: B, stats: TrafficStats, host: Arc<str>, direction: Direction) -> Self {
+        Self {
+            inner,
+            recorder: BytesRecorder {
+                stats,
+                host,
+                direction,
+                bytes: 0,
+            },
+        }
+    }
+}
+
+impl<B: fmt::Debug> fmt::Debug for TrafficStatsBody<B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TrafficStatsBody")
+            .field("inner", &self.inner)
+            .field("host", &self.recorder.host)
+            .field("bytes", &self.recorder.bytes)
+            .finish()
+    }
+}
+
+impl<B> HttpBody for TrafficStatsBody<B>
+where
+    B: HttpBody,
+{
+    type Data = B::Data;
+    type Error = B::Error;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut task::Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let this = self.project();
+        let result = ready!(this.inner.poll_frame(cx));
+        if let Some(Ok(frame)) = &result {
+            if let Some(data) = frame.data_ref() {
+                this.recorder.bytes += bytes::Buf::remaining(data) as u64;
+            }
+        }
+        Poll::Ready(result)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dep::http_body_util::BodyExt;
+    use crate::{Body, StatusCode};
+    use rama_core::service::service_fn;
+
+    fn request(uri: &str, body: &'static str) -> Request {
+        Request::builder().uri(uri).body(Body::from(body)).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_traffic_stats_per_host() {
+        let stats = TrafficStats::default();
+        let service = TrafficStatsLayer::new(stats.clone()).layer(service_fn(
+            |req: Request<TrafficStatsBody<Body>>| async move {
+                let host = req.uri().host().unwrap().to_owned();
+                let body = req.into_body().collect().await.unwrap().to_bytes();
+                Ok::<_, Infallible>(if host == "b.example.com" {
+                    Response::builder()
+                        .status(StatusCode::BAD_GATEWAY)
+                        .body(Body::empty())
+                        .unwrap()
+                } else {
+                    Response::new(Body::from(body.repeat(2)))
+                })
+            },
+        ));
+
+        for (uri, body) in [
+            ("http://a.example.com/foo", "hello"),
+            ("http://a.example.com/bar", "world!"),
+            ("http://b.example.com", ""),
+        ] {
+            let resp = service
+                .serve(Context::default(), request(uri, body))
+                .await
+                .unwrap();
+            resp.into_body().collect().await.unwrap();
+        }
+
+        let snapshot = stats.snapshot();
+        assert_eq!(snapshot.len(), 2);
+
+        assert_eq!(snapshot[0].host, "a.example.com");
+        assert_eq!(snapshot[0].requests, 2);
+        assert_eq!(snapshot[0].errors, 0);
+        assert_eq!(snapshot[0].error_rate, 0.);
+        assert_eq!(snapshot[0].bytes_sent, 11);
+        assert_eq!(snapshot[0].bytes_received, 22);
+
+        assert_eq!(snapshot[1].host, "b.example.com");
+        assert_eq!(snapshot[1].requests, 1);
+        assert_eq!(snapshot[1].errors, 1);
+        assert_eq!(snapshot[1].error_rate, 1.);
+        assert_eq!(snapshot[1].bytes_sent, 0);
+        assert_eq!(snapshot[1].bytes_received, 0);
+
+        stats.clear();
+        assert!(stats.snapshot().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_traffic_stats_inner_error() {
+        let stats = TrafficStats::default();
+        let service = TrafficStatsLayer::new(stats.clone()).layer(service_fn(
+            |_req: Request<TrafficStatsBody<Body>>| async move { Err::<Response, _>("boom") },
+        ));
+
+        assert!(service
+            .serve(Context::default(), request("http://example.com", ""))
+            .await
+            .is_err());
+
+        let snapshot = stats.snapshot();
+        assert_eq!(snapshot.len(), 1);
+        assert_eq!(snapshot[0].requests, 1);
+        assert_eq!(snapshot[0].errors, 1);
+    }
+
+    #[tokio::test]
+    async fn test_traffic_stats_json_endpoint() {
+        let stats = TrafficStats::default();
+        stats.record("example.com", |bucket| {
+            bucket.requests += 1;
+            bucket.bytes_received += 42;
+        });
+        stats.record_bytes("example.com", 1, 2);
+
+        let resp = stats
+            .serve(Context::default(), request("http://admin/stats", ""))
+            .await
+            .unwrap();
+        let body = resp.into_body().collect().await.unwrap().to_bytes();
+        let value: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(value[0]["host"], "example.com");
+        assert_eq!(value[0]["bytes_sent"], 1);
+        assert_eq!(value[0]["bytes_received"], 44);
+
+        let snapshot: Vec<HostTrafficStats> = serde_json::from_slice(&body).unwrap();
+        assert_eq!(snapshot, stats.snapshot());
+    }
+}
@@ -2,6 +2,7 @@ use super::WriterMode;
 use crate::dep::http_body;
 use crate::dep::http_body_util::BodyExt;
 use crate::io::write_http_request;
+use crate::layer::redact::Redactor;
 use crate::{Body, Request, Response};
 use bytes::Bytes;
 use rama_core::error::{BoxError, ErrorExt, OpaqueError};
@@ -293,7 +294,10 @@ where
                             .context("printer prepare: collect request body")
                     })?
                     .to_bytes();
-                let req = Request::from_parts(parts.clone(), Body::from(body_bytes.clone()));
+                let req = match ctx.get::<Redactor>() {
+                    Some(redactor) => redactor.redact_request(parts.clone(), body_bytes.clone()),
+                    None => Request::from_parts(parts.clone(), Body::from(body_bytes.clone())),
+                };
                 self.writer.write_request(req).await;
                 Request::from_parts(parts, Body::from(body_bytes))
             }
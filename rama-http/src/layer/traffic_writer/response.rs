@@ -2,6 +2,7 @@ use super::WriterMode;
 use crate::dep::http_body;
 use crate::dep::http_body_util::BodyExt;
 use crate::io::write_http_response;
+use crate::layer::redact::Redactor;
 use crate::{Body, Request, Response};
 use bytes::Bytes;
 use rama_core::error::{BoxError, ErrorContext, OpaqueError};
@@ -276,6 +277,7 @@ where
         req: Request<ReqBody>,
     ) -> Result<Self::Response, Self::Error> {
         let do_not_print_response: Option<DoNotWriteResponse> = ctx.get().cloned();
+        let redactor: Option<Redactor> = ctx.get().cloned();
         let resp = self.inner.serve(ctx, req).await.map_err(Into::into)?;
         let resp = match do_not_print_response {
             Some(_) => resp.map(Body::new),
@@ -287,8 +289,10 @@ where
                     .map_err(|err| OpaqueError::from_boxed(err.into()))
                     .context("printer prepare: collect response body")?
                     .to_bytes();
-                let resp: http::Response<Body> =
-                    Response::from_parts(parts.clone(), Body::from(body_bytes.clone()));
+                let resp: http::Response<Body> = match redactor {
+                    Some(redactor) => redactor.redact_response(parts.clone(), body_bytes.clone()),
+                    None => Response::from_parts(parts.clone(), Body::from(body_bytes.clone())),
+                };
                 self.writer.write_response(resp).await;
                 Response::from_parts(parts, Body::from(body_bytes))
             }
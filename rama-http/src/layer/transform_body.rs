@@ -0,0 +1,614 @@
+//! Middleware to transform response bodies while they are streamed.
+//!
+//! The [`TransformResponseBodyLayer`] applies a [`BodyTransformer`] to the bodies
+//! of the responses matching its content type filters, chunk by chunk, without buffering
+//! the whole body. This makes it possible to rewrite content
+//! (e.g. as part of a MITM proxy) without delaying the first bytes of the response.
+//!
+//! Transformers available out of the box:
+//!
+//! - [`RegexReplace`]: replace all matches of a regular expression;
+//! - [`HtmlInject`]: inject a snippet (e.g. a script tag) in html documents.
+//!
+//! Responses are passed through untouched in case their body is encoded
+//! (e.g. compressed), so make sure to decompress them first (e.g. using the
+//! [`DecompressionLayer`]) in case you want to transform those as well.
+//! The `Content-Length` header of transformed responses is removed,
+//! as the length of the body is not known upfront.
+//!
+//! [`DecompressionLayer`]: crate::layer::decompression::DecompressionLayer
+//!
+//! # Example
+//!
+//! ```
+//! use rama_http::layer::transform_body::{HtmlInject, TransformResponseBodyLayer};
+//! use rama_http::{dep::mime, header, Body, BodyExtractExt, Request, Response};
+//! use rama_core::service::service_fn;
+//! use rama_core::{Context, Layer, Service};
+//! use std::convert::Infallible;
+//!
+//! # #[tokio::main]
+//! # async fn main() {
+//! let service = TransformResponseBodyLayer::new(HtmlInject::script_tag("/inject.js"))
+//!     .with_content_type(mime::TEXT_HTML)
+//!     .layer(service_fn(|_req: Request| async {
+//!         Ok::<_, Infallible>(
+//!             Response::builder()
+//!                 .header(header::CONTENT_TYPE, "text/html; charset=utf-8")
+//!                 .body(Body::from("<html><head></head><body>hi</body></html>"))
+//!                 .unwrap(),
+//!         )
+//!     }));
+//!
+//! let resp = service
+//!     .serve(Context::default(), Request::new(Body::empty()))
+//!     .await
+//!     .unwrap();
+//! assert_eq!(
+//!     resp.into_body().try_into_string().await.unwrap(),
+//!     r#"<html><head><script src="/inject.js"></script></head><body>hi</body></html>"#,
+//! );
+//! # }
+//! ```
+
+use crate::dep::http_body::{Body as HttpBody, Frame};
+use crate::dep::mime::{self, Mime};
+use crate::{header, Body, HeaderMap, Method, Request, Response, StatusCode};
+use bytes::{Bytes, BytesMut};
+use futures_lite::ready;
+use pin_project_lite::pin_project;
+use rama_core::{error::BoxError, Context, Layer, Service};
+use rama_utils::macros::define_inner_service_accessors;
+use regex::bytes::Regex;
+use std::{
+    fmt,
+    pin::Pin,
+    task::{self, Poll},
+};
+
+/// A streaming transformer of (response) bodies,
+/// used by the [`TransformResponseBodyLayer`].
+///
+/// A transformer is cloned for each response it is applied to.
+pub trait BodyTransformer: Clone + Send + Sync + 'static {
+    /// Transform the next chunk of the body, returning the bytes to send.
+    ///
+    /// A transformer can hold back (the end of) a chunk, e.g. in case it might
+    /// be the start of a match which continues in the next chunk.
+    fn transform(&mut self, chunk: Bytes) -> Bytes;
+
+    /// Called once the end of the body is reached,
+    /// returning the bytes which were held back, if any.
+    fn finish(&mut self) -> Bytes {
+        Bytes::new()
+    }
+}
+
+#[derive(Debug, Clone)]
+/// A [`BodyTransformer`] which replaces all matches of a regular expression.
+///
+/// The replacement can refer to capture groups, using the same syntax
+/// as [`Regex::replace_all`] (e.g. `$1` or `${name}`).
+///
+/// As matches can span multiple chunks, the last bytes of each chunk are held back
+/// until the next chunk is received, up to the maximum match length.
+/// Matches longer than this length might not be replaced.
+pub struct RegexReplace {
+    regex: Regex,
+    replacement: Bytes,
+    max_match_len: usize,
+    buffer: BytesMut,
+}
+
+impl RegexReplace {
+    /// The default maximum length of a match.
+    pub const DEFAULT_MAX_MATCH_LEN: usize = 1024;
+
+    /// Create a new [`RegexReplace`] transformer.
+    pub fn new(regex: Regex, replacement: impl Into<Bytes>) -> Self {
+        Self {
+            regex,
+            replacement: replacement.into(),
+            max_match_len: Self::DEFAULT_MAX_MATCH_LEN,
+            buffer: BytesMut::new(),
+        }
+    }
+
+    /// Set the maximum length of a match, which is the amount of bytes held back
+    /// at the end of each chunk.
+    ///
+    /// Default is [`Self::DEFAULT_MAX_MATCH_LEN`].
+    pub fn with_max_match_len(mut self, len: usize) -> Self {
+        self.max_match_len = len;
+        self
+    }
+
+    /// Set the maximum length of a match, which is the amount of bytes held back
+    /// at the end of each chunk.
+    ///
+    /// Default is [`Self::DEFAULT_MAX_MATCH_LEN`].
+    pub fn set_max_match_len(&mut self, len: usize) -> &mut Self {
+        self.max_match_len = len;
+        self
+    }
+
+    /// Replace all matches starting before `safe`, returning the bytes up to the
+    /// end of the last match or `safe`, whichever comes last.
+    fn replace_until(&mut self, safe: usize) -> Bytes {
+        let mut output = BytesMut::with_capacity(self.buffer.len());
+        let mut last = 0;
+        for captures in self.regex.captures_iter(&self.buffer) {
+            let m = captures.get(0).expect("capture group 0 is always present");
+            if m.start() >= safe {
+                break;
+            }
+            let mut replaced = Vec::new();
+            captures.expand(&self.replacement, &mut replaced);
+            output.extend_from_slice(&self.buffer[last..m.start()]);
+            output.extend_from_slice(&replaced);
+            last = m.end();
+        }
+        let end = last.max(safe);
+        output.extend_from_slice(&self.buffer[last..end]);
+        let _ = self.buffer.split_to(end);
+        output.freeze()
+    }
+}
+
+impl BodyTransformer for RegexReplace {
+    fn transform(&mut self, chunk: Bytes) -> Bytes {
+        self.buffer.extend_from_slice(&chunk);
+        let safe = self.buffer.len().saturating_sub(self.max_match_len);
+        self.replace_until(safe)
+    }
+
+    fn finish(&mut self) -> Bytes {
+        let len = self.buffer.len();
+        self.replace_until(len)
+    }
+}
+
+#[derive(Debug, Clone)]
+/// A [`BodyTransformer`] which injects a snippet in an html document,
+/// prior to the first (case-insensitive) occurrence of a marker.
+///
+/// The body is passed through untouched in case the marker is not found.
+pub struct HtmlInject {
+    marker: Bytes,
+    snippet: Bytes,
+    buffer: BytesMut,
+    injected: bool,
+}
+
+impl HtmlInject {
+    /// Inject the snippet prior to the first occurrence of the given (ascii) marker.
+    pub fn before(marker: &str, snippet: impl Into<Bytes>) -> Self {
+        Self {
+            marker: Bytes::from(marker.to_ascii_lowercase()),
+            snippet: snippet.into(),
+            buffer: BytesMut::new(),
+            injected: false,
+        }
+    }
+
+    /// Inject the snippet at the end of the head element of the document.
+    pub fn head(snippet: impl Into<Bytes>) -> Self {
+        Self::before("</head>", snippet)
+    }
+
+    /// Inject the snippet at the end of the body element of the document.
+    pub fn body(snippet: impl Into<Bytes>) -> Self {
+        Self::before("</body>", snippet)
+    }
+
+    /// Inject a script tag loading the given source at the end of the head element
+    /// of the document.
+    pub fn script_tag(src: &str) -> Self {
+        Self::head(format!(
+            r#"<script src="{}"></script>"#,
+            escape_attribute(src)
+        ))
+    }
+
+    fn find_marker(&self) -> Option<usize> {
+        self.buffer
+            .windows(self.marker.len())
+            .position(|window| window.eq_ignore_ascii_case(&self.marker))
+    }
+}
+
+impl BodyTransformer for HtmlInject {
+    fn transform(&mut self, chunk: Bytes) -> Bytes {
+        if self.injected || self.marker.is_empty() {
+            return chunk;
+        }
+        self.buffer.extend_from_slice(&chunk);
+        match self.find_marker() {
+            Some(index) => {
+                self.injected = true;
+                let mut output = BytesMut::with_capacity(self.buffer.len() + self.snippet.len());
+                output.extend_from_slice(&self.buffer[..index]);
+                output.extend_from_slice(&self.snippet);
+                output.extend_from_slice(&self.buffer[index..]);
+                self.buffer.clear();
+                output.freeze()
+            }
+            None => {
+                // hold back the bytes which could be the start of the marker
+                let end = self.buffer.len().saturating_sub(self.marker.len() - 1);
+                self.buffer.split_to(end).freeze()
+            }
+        }
+    }
+
+    fn finish(&mut self) -> Bytes {
+        self.buffer.split().freeze()
+    }
+}
+
+fn escape_attribute(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('"', "&quot;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// Layer that applies the [`TransformResponseBody`] middleware.
+///
+/// See the [module docs](self) for more information.
+pub struct TransformResponseBodyLayer<T> {
+    transformer: T,
+    content_types: Vec<Mime>,
+}
+
+impl<T: fmt::Debug> fmt::Debug for TransformResponseBodyLayer<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TransformResponseBodyLayer")
+            .field("transformer", &self.transformer)
+            .field("content_types", &self.content_types)
+            .finish()
+    }
+}
+
+impl<T: Clone> Clone for TransformResponseBodyLayer<T> {
+    fn clone(&self) -> Self {
+        Self {
+            transformer: self.transformer.clone(),
+            content_types: self.content_types.clone(),
+        }
+    }
+}
+
+impl<T> TransformResponseBodyLayer<T> {
+    /// Create a new [`TransformResponseBodyLayer`], applying (a clone of)
+    /// the given transformer to the body of each matching response.
+    ///
+    /// By default all responses match, see [`Self::with_content_type`]
+    /// to only transform responses of specific content types.
+    pub fn new(transformer: T) -> Self {
+        Self {
+            transformer,
+            content_types: Vec::new(),
+        }
+    }
+
+    /// Only transform responses with the given content type,
+    /// in addition to the other content types configured.
+    ///
+    /// Content types are matched by type and subtype, ignoring their parameters,
+    /// and a subtype of `*` (e.g. `text/*`) matches all subtypes.
+    pub fn with_content_type(mut self, content_type: Mime) -> Self {
+        self.content_types.push(content_type);
+        self
+    }
+
+    /// Only transform responses with the given content type,
+    /// in addition to the other content types configured.
+    ///
+    /// See [`Self::with_content_type`] for more information.
+    pub fn set_content_type(&mut self, content_type: Mime) -> &mut Self {
+        self.content_types.push(content_type);
+        self
+    }
+}
+
+impl<S, T: Clone> Layer<S> for TransformResponseBodyLayer<T> {
+    type Service = TransformResponseBody<S, T>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        TransformResponseBody {
+            inner,
+            transformer: self.transformer.clone(),
+            content_types: self.content_types.clone(),
+        }
+    }
+}
+
+/// Middleware which transforms the bodies of the responses
+/// using a [`BodyTransformer`], while they are streamed.
+///
+/// See the [module docs](self) for more information.
+pub struct TransformResponseBody<S, T> {
+    inner: S,
+    transformer: T,
+    content_types: Vec<Mime>,
+}
+
+impl<S: fmt::Debug, T: fmt::Debug> fmt::Debug for TransformResponseBody<S, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TransformResponseBody")
+            .field("inner", &self.inner)
+            .field("transformer", &self.transformer)
+            .field("content_types", &self.content_types)
+            .finish()
+    }
+}
+
+impl<S: Clone, T: Clone> Clone for TransformResponseBody<S, T> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            transformer: self.transformer.clone(),
+            content_types: self.content_types.clone(),
+        }
+    }
+}
+
+impl<S, T> TransformResponseBody<S, T> {
+    /// Create a new [`TransformResponseBody`], transforming the bodies
+    /// of all responses using (a clone of) the given transformer.
+    pub fn new(inner: S, transformer: T) -> Self {
+        Self {
+            inner,
+            transformer,
+            content_types: Vec::new(),
+        }
+    }
+
+    define_inner_service_accessors!();
+
+    fn should_transform(&self, headers: &HeaderMap, status: StatusCode) -> bool {
+        if matches!(
+            status,
+            StatusCode::NO_CONTENT | StatusCode::PARTIAL_CONTENT | StatusCode::NOT_MODIFIED
+        ) {
+            return false;
+        }
+        if headers
+            .get(header::CONTENT_ENCODING)
+            .is_some_and(|encoding| encoding != "identity")
+        {
+            tracing::trace!("transform response body: skip encoded response body");
+            return false;
+        }
+        if self.content_types.is_empty() {
+            return true;
+        }
+        let Some(content_type) = headers
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse::<Mime>().ok())
+        else {
+            return false;
+        };
+        self.content_types.iter().any(|expected| {
+            content_type.type_() == expected.type_()
+                && (expected.subtype() == mime::STAR
+                    || content_type.subtype() == expected.subtype())
+        })
+    }
+}
+
+impl<State, S, T, ReqBody, ResBody> Service<State, Request<ReqBody>> for TransformResponseBody<S, T>
+where
+    State: Clone + Send + Sync + 'static,
+    S: Service<State, Request<ReqBody>, Response = Response<ResBody>>,
+    T: BodyTransformer,
+    ReqBody: Send + 'static,
+    ResBody: HttpBody<Data = Bytes, Error: Into<BoxError>> + Send + Sync + 'static,
+{
+    type Response = Response;
+    type Error = S::Error;
+
+    async fn serve(
+        &self,
+        ctx: Context<State>,
+        req: Request<ReqBody>,
+    ) -> Result<Self::Response, Self::Error> {
+        let is_head = req.method() == Method::HEAD;
+        let res = self.inner.serve(ctx, req).await?;
+        if is_head || !self.should_transform(res.headers(), res.status()) {
+            return Ok(res.map(Body::new));
+        }
+
+        let (mut parts, body) = res.into_parts();
+        parts.headers.remove(header::CONTENT_LENGTH);
+        let body = TransformBody::new(body, self.transformer.clone());
+        Ok(Response::from_parts(parts, Body::new(body)))
+    }
+}
+
+pin_project! {
+    /// Response body used by [`TransformResponseBody`],
+    /// transforming the body as it is consumed.
+    pub struct TransformBody<B, T> {
+        #[pin]
+        inner: B,
+        transformer: Option<T>,
+        trailers: Option<HeaderMap>,
+    }
+}
+
+impl<B, T> TransformBody<B, T> {
+    /// Create a new [`TransformBody`], transforming the given body
+    /// using the given transformer.
+    pub fn new(inner: B, transformer: T) -> Self {
+        Self {
+            inner,
+            transformer: Some(transformer),
+            trailers: None,
+        }
+    }
+}
+
+impl<B: fmt::Debug, T: fmt::Debug> fmt::Debug for TransformBody<B, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TransformBody")
+            .field("inner", &self.inner)
+            .field("transformer", &self.transformer)
+            .field("trailers", &self.trailers)
+            .finish()
+    }
+}
+
+impl<B, T> HttpBody for TransformBody<B, T>
+where
+    B: HttpBody<Data = Bytes, Error: Into<BoxError>>,
+    T: BodyTransformer,
+{
+    type Data = Bytes;
+    type Error = BoxError;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut task::Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let mut this = self.project();
+        loop {
+            let Some(transformer) = this.transformer.as_mut() else {
+                // the held back bytes were sent, only the trailers (if any) remain
+                return Poll::Ready(this.trailers.take().map(|t| Ok(Frame::trailers(t))));
+            };
+            match ready!(this.inner.as_mut().poll_frame(cx)) {
+                Some(Ok(frame)) => match frame.into_data() {
+                    Ok(data) => {
+                        let data = transformer.transform(data);
+                        if !data.is_empty() {
+                            return Poll::Ready(Some(Ok(Frame::data(data))));
+                        }
+                    }
+                    Err(frame) => {
+                        *this.trailers = frame.into_trailers().ok();
+                        let data = transformer.finish();
+                        *this.transformer = None;
+                        if !data.is_empty() {
+                            return Poll::Ready(Some(Ok(Frame::data(data))));
+                        }
+                    }
+                },
+                Some(Err(err)) => return Poll::Ready(Some(Err(err.into()))),
+                None => {
+                    let data = transformer.finish();
+                    *this.transformer = None;
+                    if !data.is_empty() {
+                        return Poll::Ready(Some(Ok(Frame::data(data))));
+                    }
+                }
+            }
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.transformer.is_none() && self.trailers.is_none()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::BodyExtractExt;
+    use rama_core::service::service_fn;
+    use std::convert::Infallible;
+
+    fn transform_chunks<T: BodyTransformer>(mut transformer: T, chunks: &[&str]) -> String {
+        let mut output = Vec::new();
+        for chunk in chunks {
+            output.extend_from_slice(
+                &transformer.transform(Bytes::copy_from_slice(chunk.as_bytes())),
+            );
+        }
+        output.extend_from_slice(&transformer.finish());
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn test_regex_replace() {
+        let transformer =
+            RegexReplace::new(Regex::new(r"foo(\d)").unwrap(), "bar$1").with_max_match_len(8);
+        assert_eq!(
+            transform_chunks(transformer.clone(), &["a foo1 b fo", "o2 c f", "oo3"]),
+            "a bar1 b bar2 c bar3"
+        );
+        assert_eq!(
+            transform_chunks(transformer, &["a very long chunk without matches", "!"]),
+            "a very long chunk without matches!"
+        );
+    }
+
+    #[test]
+    fn test_html_inject() {
+        let transformer = HtmlInject::body("<p>injected</p>");
+        assert_eq!(
+            transform_chunks(transformer.clone(), &["<html><body>hi</BO", "DY></html>"]),
+            "<html><body>hi<p>injected</p></BODY></html>"
+        );
+        assert_eq!(
+            transform_chunks(transformer, &["no ", "marker </bo"]),
+            "no marker </bo"
+        );
+        assert_eq!(
+            transform_chunks(HtmlInject::script_tag(r#"/a.js?x="y""#), &["<head></head>"]),
+            r#"<head><script src="/a.js?x=&quot;y&quot;"></script></head>"#
+        );
+    }
+
+    #[tokio::test]
+    async fn test_transform_response_body_layer() {
+        let service = TransformResponseBodyLayer::new(RegexReplace::new(
+            Regex::new("secret").unwrap(),
+            "******",
+        ))
+        .with_content_type(mime::TEXT_STAR)
+        .layer(service_fn(|req: Request| async move {
+            let content_type = req.uri().path().trim_start_matches('/').replace('-', "/");
+            let chunks: Vec<Result<_, Infallible>> =
+                vec![Ok("my sec"), Ok("ret is "), Ok("secret")];
+            Ok::<_, Infallible>(
+                Response::builder()
+                    .header(header::CONTENT_TYPE, content_type)
+                    .header(header::CONTENT_LENGTH, 19)
+                    .body(Body::from_stream(futures_lite::stream::iter(chunks)))
+                    .unwrap(),
+            )
+        }));
+
+        let resp = service
+            .serve(
+                Context::default(),
+                Request::get("/text-plain").body(Body::empty()).unwrap(),
+            )
+            .await
+            .unwrap();
+        assert!(resp.headers().get(header::CONTENT_LENGTH).is_none());
+        assert_eq!(
+            resp.into_body().try_into_string().await.unwrap(),
+            "my ****** is ******"
+        );
+
+        let resp = service
+            .serve(
+                Context::default(),
+                Request::get("/application-json")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(resp.headers()[header::CONTENT_LENGTH], "19");
+        assert_eq!(
+            resp.into_body().try_into_string().await.unwrap(),
+            "my secret is secret"
+        );
+    }
+}
@@ -0,0 +1,409 @@
+//! Middleware that rewrites the uri of requests,
+//! before they reach the router or reverse proxy.
+//!
+//! The rewrite is defined by a list of [`UriRewriteRule`]s, applied in order:
+//!
+//! - [`UriRewriteRule::strip_prefix`]: strip a path prefix, e.g. `/api/users` to `/users`;
+//! - [`UriRewriteRule::regex`]: replace the path using a regex,
+//!   with the replacement referencing its capture groups (e.g. `$1` or `${name}`);
+//! - [`UriRewriteRule::set_query_param`] and [`UriRewriteRule::remove_query_param`]:
+//!   manipulate the query parameters.
+//!
+//! Instead of rewriting the request, the [`UriRewriteLayer`] can also be configured
+//! to redirect the client to the rewritten uri, using [`UriRewriteLayer::redirect`].
+//!
+//! # Example
+//!
+//! ```
+//! use rama_http::layer::uri_rewrite::{UriRewriteLayer, UriRewriteRule};
+//! use rama_http::{Body, Request, Response};
+//! use rama_core::service::service_fn;
+//! use rama_core::{Context, Service, Layer};
+//! use std::convert::Infallible;
+//!
+//! # #[tokio::main]
+//! # async fn main() {
+//! let layer = UriRewriteLayer::new()
+//!     .with_rule(UriRewriteRule::strip_prefix("/api"))
+//!     .with_rule(UriRewriteRule::regex(r"^/v1/users/(\d+)$", "/users/$1").unwrap())
+//!     .with_rule(UriRewriteRule::remove_query_param("debug"));
+//!
+//! let service = layer.layer(service_fn(|req: Request| async move {
+//!     assert_eq!(req.uri(), "/users/42?lang=en");
+//!     Ok::<_, Infallible>(Response::new(Body::empty()))
+//! }));
+//!
+//! let req = Request::builder()
+//!     .uri("/api/v1/users/42?debug=1&lang=en")
+//!     .body(Body::empty())
+//!     .unwrap();
+//! service.serve(Context::default(), req).await.unwrap();
+//! # }
+//! ```
+
+use crate::{header, HeaderValue, Request, Response, StatusCode, Uri};
+use rama_core::{
+    error::{ErrorContext, OpaqueError},
+    Context, Layer, Service,
+};
+use rama_utils::macros::define_inner_service_accessors;
+use regex::Regex;
+use std::{fmt, sync::Arc};
+
+/// A single uri rewrite, applied by the [`UriRewriteLayer`].
+#[derive(Debug, Clone)]
+pub struct UriRewriteRule {
+    kind: RuleKind,
+}
+
+#[derive(Debug, Clone)]
+enum RuleKind {
+    StripPrefix(String),
+    Regex(Regex, String),
+    SetQueryParam(String, String),
+    RemoveQueryParam(String),
+}
+
+impl UriRewriteRule {
+    /// Strip the given prefix from the path, in case the path starts with it.
+    ///
+    /// The prefix is matched on full segments, such that
+    /// the prefix `/api` strips `/api/users` (to `/users`) but not `/apis`.
+    pub fn strip_prefix(prefix: impl AsRef<str>) -> Self {
+        Self {
+            kind: RuleKind::StripPrefix(format!("/{}", prefix.as_ref().trim_matches('/'))),
+        }
+    }
+
+    /// Replace the first match of the given regex in the path by the replacement,
+    /// which can reference the capture groups of the regex (e.g. `$1` or `${name}`).
+    pub fn regex(
+        pattern: impl AsRef<str>,
+        replacement: impl Into<String>,
+    ) -> Result<Self, OpaqueError> {
+        let regex = Regex::new(pattern.as_ref()).context("compile uri rewrite regex")?;
+        Ok(Self {
+            kind: RuleKind::Regex(regex, replacement.into()),
+        })
+    }
+
+    /// Set the query parameter, replacing all its existing values.
+    ///
+    /// The name and value are expected to be url-encoded already.
+    pub fn set_query_param(name: impl Into<String>, value: impl Into<String>) -> Self {
+        Self {
+            kind: RuleKind::SetQueryParam(name.into(), value.into()),
+        }
+    }
+
+    /// Remove all values of the query parameter.
+    pub fn remove_query_param(name: impl Into<String>) -> Self {
+        Self {
+            kind: RuleKind::RemoveQueryParam(name.into()),
+        }
+    }
+
+    fn apply(&self, path: &mut String, query: &mut Vec<String>) {
+        match &self.kind {
+            RuleKind::StripPrefix(prefix) => {
+                if prefix == "/" {
+                    return;
+                }
+                if let Some(rest) = path.strip_prefix(prefix.as_str()) {
+                    if rest.is_empty() {
+                        *path = "/".to_owned();
+                    } else if rest.starts_with('/') {
+                        *path = rest.to_owned();
+                    }
+                }
+            }
+            RuleKind::Regex(regex, replacement) => {
+                if let std::borrow::Cow::Owned(rewritten) =
+                    regex.replace(path, replacement.as_str())
+                {
+                    *path = rewritten;
+                }
+            }
+            RuleKind::SetQueryParam(name, value) => {
+                query.retain(|pair| query_param_name(pair) != name);
+                query.push(format!("{name}={value}"));
+            }
+            RuleKind::RemoveQueryParam(name) => {
+                query.retain(|pair| query_param_name(pair) != name);
+            }
+        }
+    }
+}
+
+fn query_param_name(pair: &str) -> &str {
+    pair.split_once('=').map_or(pair, |(name, _)| name)
+}
+
+#[derive(Debug, Clone, Default)]
+struct UriRewriteConfig {
+    rules: Vec<UriRewriteRule>,
+    redirect: Option<StatusCode>,
+}
+
+impl UriRewriteConfig {
+    /// Rewrite the uri, returning `None` in case it is unchanged.
+    fn rewrite(&self, uri: &Uri) -> Option<Uri> {
+        let mut path = uri.path().to_owned();
+        let mut query: Vec<_> = uri
+            .query()
+            .map(|query| {
+                query
+                    .split('&')
+                    .filter(|pair| !pair.is_empty())
+                    .map(ToOwned::to_owned)
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        for rule in &self.rules {
+            rule.apply(&mut path, &mut query);
+        }
+
+        let path_and_query = if query.is_empty() {
+            path
+        } else {
+            format!("{path}?{}", query.join("&"))
+        };
+        if uri
+            .path_and_query()
+            .map_or(path_and_query == "/", |pq| pq.as_str() == path_and_query)
+        {
+            return None;
+        }
+
+        let mut parts = uri.clone().into_parts();
+        parts.path_and_query = match path_and_query.parse() {
+            Ok(path_and_query) => Some(path_and_query),
+            Err(err) => {
+                tracing::warn!(%uri, %path_and_query, error = %err, "ignore invalid uri rewrite");
+                return None;
+            }
+        };
+        Uri::from_parts(parts).ok()
+    }
+}
+
+/// Layer that applies the [`UriRewrite`] middleware.
+///
+/// See the [module docs](self) for more details.
+#[derive(Debug, Clone, Default)]
+pub struct UriRewriteLayer {
+    config: Arc<UriRewriteConfig>,
+}
+
+impl UriRewriteLayer {
+    /// Create a new [`UriRewriteLayer`], without any rules.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a [`UriRewriteRule`].
+    ///
+    /// Rules are applied in the order they are added.
+    pub fn with_rule(mut self, rule: UriRewriteRule) -> Self {
+        Arc::make_mut(&mut self.config).rules.push(rule);
+        self
+    }
+
+    /// Add a [`UriRewriteRule`].
+    ///
+    /// Rules are applied in the order they are added.
+    pub fn set_rule(&mut self, rule: UriRewriteRule) -> &mut Self {
+        Arc::make_mut(&mut self.config).rules.push(rule);
+        self
+    }
+
+    /// Redirect the client to the rewritten uri using the given status code,
+    /// instead of rewriting the request.
+    ///
+    /// Requests of which the uri is not changed by the rules are served as-is.
+    ///
+    /// # Panics
+    ///
+    /// If `status_code` isn't a [redirection status code][mdn] (3xx).
+    ///
+    /// [mdn]: https://developer.mozilla.org/en-US/docs/Web/HTTP/Status#redirection_messages
+    pub fn redirect(mut self, status_code: StatusCode) -> Self {
+        self.set_redirect(status_code);
+        self
+    }
+
+    /// Redirect the client to the rewritten uri using the given status code,
+    /// instead of rewriting the request.
+    ///
+    /// Requests of which the uri is not changed by the rules are served as-is.
+    ///
+    /// # Panics
+    ///
+    /// If `status_code` isn't a [redirection status code][mdn] (3xx).
+    ///
+    /// [mdn]: https://developer.mozilla.org/en-US/docs/Web/HTTP/Status#redirection_messages
+    pub fn set_redirect(&mut self, status_code: StatusCode) -> &mut Self {
+        assert!(
+            status_code.is_redirection(),
+            "not a redirection status code"
+        );
+        Arc::make_mut(&mut self.config).redirect = Some(status_code);
+        self
+    }
+}
+
+impl<S> Layer<S> for UriRewriteLayer {
+    type Service = UriRewrite<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        UriRewrite {
+            inner,
+            config: self.config.clone(),
+        }
+    }
+}
+
+/// Middleware that rewrites the uri of requests.
+///
+/// See the [module docs](self) for more details.
+pub struct UriRewrite<S> {
+    inner: S,
+    config: Arc<UriRewriteConfig>,
+}
+
+impl<S> UriRewrite<S> {
+    define_inner_service_accessors!();
+}
+
+impl<S: fmt::Debug> fmt::Debug for UriRewrite<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("UriRewrite")
+            .field("inner", &self.inner)
+            .field("config", &self.config)
+            .finish()
+    }
+}
+
+impl<S: Clone> Clone for UriRewrite<S> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            config: self.config.clone(),
+        }
+    }
+}
+
+impl<State, S, ReqBody, ResBody> Service<State, Request<ReqBody>> for UriRewrite<S>
+where
+    State: Clone + Send + Sync + 'static,
+    S: Service<State, Request<ReqBody>, Response = Response<ResBody>>,
+    ReqBody: Send + 'static,
+    ResBody: Default + Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+
+    async fn serve(
+        &self,
+        ctx: Context<State>,
+        mut req: Request<ReqBody>,
+    ) -> Result<Self::Response, Self::Error> {
+        if let Some(uri) = self.config.rewrite(req.uri()) {
+            match self.config.redirect {
+                Some(status_code) => match HeaderValue::try_from(uri.to_string()) {
+                    Ok(location) => {
+                        let mut res = Response::new(ResBody::default());
+                        *res.status_mut() = status_code;
+                        res.headers_mut().insert(header::LOCATION, location);
+                        return Ok(res);
+                    }
+                    Err(err) => {
+                        tracing::warn!(%uri, error = %err, "uri rewrite: invalid redirect location");
+                    }
+                },
+                None => {
+                    tracing::trace!(from = %req.uri(), to = %uri, "rewrite request uri");
+                    *req.uri_mut() = uri;
+                }
+            }
+        }
+        self.inner.serve(ctx, req).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Body;
+    use rama_core::service::service_fn;
+    use std::convert::Infallible;
+
+    fn rewrite(layer: &UriRewriteLayer, uri: &str) -> Option<String> {
+        layer
+            .config
+            .rewrite(&uri.parse().unwrap())
+            .map(|uri| uri.to_string())
+    }
+
+    #[test]
+    fn test_strip_prefix() {
+        let layer = UriRewriteLayer::new().with_rule(UriRewriteRule::strip_prefix("/api/"));
+        assert_eq!(rewrite(&layer, "/api/users?x=1").unwrap(), "/users?x=1");
+        assert_eq!(rewrite(&layer, "/api").unwrap(), "/");
+        assert_eq!(
+            rewrite(&layer, "http://example.com/api/users").unwrap(),
+            "http://example.com/users"
+        );
+        assert!(rewrite(&layer, "/apis").is_none());
+        assert!(rewrite(&layer, "/users").is_none());
+    }
+
+    #[test]
+    fn test_regex() {
+        let layer = UriRewriteLayer::new().with_rule(
+            UriRewriteRule::regex(r"^/blog/(?<year>\d{4})/(.+)$", "/posts/$2?year=${year}")
+                .unwrap(),
+        );
+        assert_eq!(
+            rewrite(&layer, "/blog/2024/hello").unwrap(),
+            "/posts/hello?year=2024"
+        );
+        assert!(rewrite(&layer, "/blog/latest").is_none());
+        assert!(UriRewriteRule::regex("(", "").is_err());
+    }
+
+    #[test]
+    fn test_query_params() {
+        let layer = UriRewriteLayer::new()
+            .with_rule(UriRewriteRule::set_query_param("lang", "en"))
+            .with_rule(UriRewriteRule::remove_query_param("token"));
+        assert_eq!(
+            rewrite(&layer, "/?lang=fr&token=secret&lang=nl&page=2").unwrap(),
+            "/?page=2&lang=en"
+        );
+        assert!(rewrite(&layer, "/foo?lang=en").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_redirect() {
+        let svc = UriRewriteLayer::new()
+            .with_rule(UriRewriteRule::strip_prefix("/old"))
+            .redirect(StatusCode::MOVED_PERMANENTLY)
+            .layer(service_fn(|_: Request| async {
+                Ok::<_, Infallible>(Response::new(Body::empty()))
+            }));
+
+        let req = Request::builder()
+            .uri("/old/page?x=1")
+            .body(Body::empty())
+            .unwrap();
+        let resp = svc.serve(Context::default(), req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::MOVED_PERMANENTLY);
+        assert_eq!(resp.headers()[header::LOCATION], "/page?x=1");
+
+        let req = Request::builder().uri("/page").body(Body::empty()).unwrap();
+        let resp = svc.serve(Context::default(), req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+}
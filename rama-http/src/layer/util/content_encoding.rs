@@ -1,5 +1,6 @@
 //! Types and functions for handling content encoding.
 
+use super::quality::{quality_values, QValue};
 use rama_utils::macros::match_ignore_ascii_case_str;
 
 pub(crate) trait SupportedEncodings: Copy {
@@ -22,7 +23,7 @@ pub(crate) enum Encoding {
 
 impl Encoding {
     #[allow(dead_code)]
-    fn to_str(self) -> &'static str {
+    pub(crate) fn to_str(self) -> &'static str {
         match self {
             Encoding::Identity => "identity",
             Encoding::Gzip => "gzip",
@@ -48,7 +49,7 @@ impl Encoding {
         http::HeaderValue::from_static(self.to_str())
     }
 
-    fn parse(s: &str, _supported_encoding: impl SupportedEncodings) -> Option<Encoding> {
+    pub(crate) fn parse(s: &str, _supported_encoding: impl SupportedEncodings) -> Option<Encoding> {
         match_ignore_ascii_case_str! {
             match (s) {
                 "gzip" | "x-gzip" if _supported_encoding.gzip() => Some(Encoding::Gzip),
@@ -77,109 +78,23 @@ impl Encoding {
         accepted_encodings: impl Iterator<Item = (Encoding, QValue)>,
     ) -> Option<Self> {
         accepted_encodings
-            .filter(|(_, qvalue)| qvalue.0 > 0)
+            .filter(|(_, qvalue)| !qvalue.is_zero())
             .max_by_key(|&(encoding, qvalue)| (qvalue, encoding))
             .map(|(encoding, _)| encoding)
     }
 }
 
-// Allowed q-values are numbers between 0 and 1 with at most 3 digits in the fractional part. They
-// are presented here as an unsigned integer between 0 and 1000.
-#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
-pub(crate) struct QValue(u16);
-
-impl QValue {
-    #[inline]
-    fn one() -> Self {
-        Self(1000)
-    }
-
-    // Parse a q-value as specified in RFC 7231 section 5.3.1.
-    fn parse(s: &str) -> Option<Self> {
-        let mut c = s.chars();
-        // Parse "q=" (case-insensitively).
-        match c.next() {
-            Some('q' | 'Q') => (),
-            _ => return None,
-        };
-        match c.next() {
-            Some('=') => (),
-            _ => return None,
-        };
-
-        // Parse leading digit. Since valid q-values are between 0.000 and 1.000, only "0" and "1"
-        // are allowed.
-        let mut value = match c.next() {
-            Some('0') => 0,
-            Some('1') => 1000,
-            _ => return None,
-        };
-
-        // Parse optional decimal point.
-        match c.next() {
-            Some('.') => (),
-            None => return Some(Self(value)),
-            _ => return None,
-        };
-
-        // Parse optional fractional digits. The value of each digit is multiplied by `factor`.
-        // Since the q-value is represented as an integer between 0 and 1000, `factor` is `100` for
-        // the first digit, `10` for the next, and `1` for the digit after that.
-        let mut factor = 100;
-        loop {
-            match c.next() {
-                Some(n @ '0'..='9') => {
-                    // If `factor` is less than `1`, three digits have already been parsed. A
-                    // q-value having more than 3 fractional digits is invalid.
-                    if factor < 1 {
-                        return None;
-                    }
-                    // Add the digit's value multiplied by `factor` to `value`.
-                    value += factor * (n as u16 - '0' as u16);
-                }
-                None => {
-                    // No more characters to parse. Check that the value representing the q-value is
-                    // in the valid range.
-                    return if value <= 1000 {
-                        Some(Self(value))
-                    } else {
-                        None
-                    };
-                }
-                _ => return None,
-            };
-            factor /= 10;
-        }
-    }
-}
-
 // based on https://github.com/http-rs/accept-encoding
 #[allow(dead_code)]
 pub(crate) fn encodings<'a>(
     headers: &'a http::HeaderMap,
     supported_encoding: impl SupportedEncodings + 'a,
 ) -> impl Iterator<Item = (Encoding, QValue)> + 'a {
-    headers
-        .get_all(http::header::ACCEPT_ENCODING)
-        .iter()
-        .filter_map(|hval| hval.to_str().ok())
-        .flat_map(|s| s.split(','))
-        .filter_map(move |v| {
-            let mut v = v.splitn(2, ';');
-
-            let encoding = match Encoding::parse(v.next().unwrap().trim(), supported_encoding) {
-                Some(encoding) => encoding,
-                None => return None, // ignore unknown encodings
-            };
-
-            let qval = if let Some(qval) = v.next() {
-                QValue::parse(qval.trim())?
-            } else {
-                QValue::one()
-            };
-
-            Some((encoding, qval))
-        })
+    quality_values(headers, &http::header::ACCEPT_ENCODING).filter_map(move |(v, qval)| {
+        // ignore unknown encodings
+        let encoding = Encoding::parse(v, supported_encoding)?;
+        Some((encoding, qval))
+    })
 }
 
 #[cfg(all(test, feature = "compression"))]
@@ -0,0 +1,19 @@
+use crate::layer::request_id::RequestId;
+use rama_core::context::register_log_fields;
+use rama_net::user::UserId;
+use rama_ua::UserAgent;
+use std::sync::Once;
+
+/// Register the [`LogFields`] of the extensions known to this crate,
+/// as used by the layers which export the log fields found in the [`Context`].
+///
+/// [`LogFields`]: rama_core::context::LogFields
+/// [`Context`]: rama_core::Context
+pub(crate) fn register_default_log_fields() {
+    static REGISTER: Once = Once::new();
+    REGISTER.call_once(|| {
+        register_log_fields::<RequestId>();
+        register_log_fields::<UserId>();
+        register_log_fields::<UserAgent>();
+    });
+}
@@ -4,3 +4,5 @@
 pub(crate) mod compression;
 
 pub(crate) mod content_encoding;
+pub(crate) mod log_fields;
+pub(crate) mod quality;
@@ -0,0 +1,149 @@
+//! Quality values (q-values), shared by the content negotiation utilities,
+//! as specified in [RFC 9110, section 12.4.2](https://www.rfc-editor.org/rfc/rfc9110#section-12.4.2).
+
+use crate::{HeaderMap, HeaderName};
+
+// Allowed q-values are numbers between 0 and 1 with at most 3 digits in the fractional part. They
+// are presented here as an unsigned integer between 0 and 1000.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub(crate) struct QValue(u16);
+
+impl QValue {
+    #[inline]
+    pub(crate) fn one() -> Self {
+        Self(1000)
+    }
+
+    #[inline]
+    pub(crate) fn is_zero(&self) -> bool {
+        self.0 == 0
+    }
+
+    // Parse a q-value as specified in RFC 7231 section 5.3.1.
+    pub(crate) fn parse(s: &str) -> Option<Self> {
+        let mut c = s.chars();
+        // Parse "q=" (case-insensitively).
+        match c.next() {
+            Some('q' | 'Q') => (),
+            _ => return None,
+        };
+        match c.next() {
+            Some('=') => (),
+            _ => return None,
+        };
+
+        // Parse leading digit. Since valid q-values are between 0.000 and 1.000, only "0" and "1"
+        // are allowed.
+        let mut value = match c.next() {
+            Some('0') => 0,
+            Some('1') => 1000,
+            _ => return None,
+        };
+
+        // Parse optional decimal point.
+        match c.next() {
+            Some('.') => (),
+            None => return Some(Self(value)),
+            _ => return None,
+        };
+
+        // Parse optional fractional digits. The value of each digit is multiplied by `factor`.
+        // Since the q-value is represented as an integer between 0 and 1000, `factor` is `100` for
+        // the first digit, `10` for the next, and `1` for the digit after that.
+        let mut factor = 100;
+        loop {
+            match c.next() {
+                Some(n @ '0'..='9') => {
+                    // If `factor` is less than `1`, three digits have already been parsed. A
+                    // q-value having more than 3 fractional digits is invalid.
+                    if factor < 1 {
+                        return None;
+                    }
+                    // Add the digit's value multiplied by `factor` to `value`.
+                    value += factor * (n as u16 - '0' as u16);
+                }
+                None => {
+                    // No more characters to parse. Check that the value representing the q-value is
+                    // in the valid range.
+                    return if value <= 1000 {
+                        Some(Self(value))
+                    } else {
+                        None
+                    };
+                }
+                _ => return None,
+            };
+            factor /= 10;
+        }
+    }
+}
+
+/// Iterate over the (trimmed) values of a comma separated header (e.g. `Accept-Encoding`
+/// or `Accept-Language`), together with their q-value.
+///
+/// Values without a q-value get the default q-value of `1`,
+/// while values with an invalid q-value are ignored.
+pub(crate) fn quality_values<'a>(
+    headers: &'a HeaderMap,
+    name: &'a HeaderName,
+) -> impl Iterator<Item = (&'a str, QValue)> + 'a {
+    headers
+        .get_all(name)
+        .iter()
+        .filter_map(|hval| hval.to_str().ok())
+        .flat_map(|s| s.split(','))
+        .filter_map(|v| {
+            let mut v = v.splitn(2, ';');
+            let value = v.next().unwrap().trim();
+            if value.is_empty() {
+                return None;
+            }
+
+            let qval = if let Some(qval) = v.next() {
+                QValue::parse(qval.trim())?
+            } else {
+                QValue::one()
+            };
+
+            Some((value, qval))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{header::ACCEPT_LANGUAGE, HeaderValue};
+
+    #[test]
+    fn test_qvalue_parse() {
+        assert_eq!(QValue::parse("q=1"), Some(QValue(1000)));
+        assert_eq!(QValue::parse("Q=0.5"), Some(QValue(500)));
+        assert_eq!(QValue::parse("q=0.123"), Some(QValue(123)));
+        assert_eq!(QValue::parse("q=0"), Some(QValue(0)));
+        assert_eq!(QValue::parse("q=1.001"), None);
+        assert_eq!(QValue::parse("q=0.1234"), None);
+        assert_eq!(QValue::parse("q=2"), None);
+        assert_eq!(QValue::parse("x=1"), None);
+    }
+
+    #[test]
+    fn test_quality_values() {
+        let mut headers = HeaderMap::new();
+        headers.append(
+            ACCEPT_LANGUAGE,
+            HeaderValue::from_static("nl-BE, fr;q=0.8,,en ; q=0.5"),
+        );
+        headers.append(ACCEPT_LANGUAGE, HeaderValue::from_static("de;q=2, *;q=0"));
+
+        let values: Vec<_> = quality_values(&headers, &ACCEPT_LANGUAGE).collect();
+        assert_eq!(
+            values,
+            vec![
+                ("nl-BE", QValue(1000)),
+                ("fr", QValue(800)),
+                ("en", QValue(500)),
+                ("*", QValue(0)),
+            ]
+        );
+    }
+}
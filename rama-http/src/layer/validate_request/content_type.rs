@@ -0,0 +1,81 @@
+use super::{problem::problem_response, ValidateRequest};
+use crate::dep::http_body;
+use crate::headers::{ContentType, HeaderMapExt};
+use crate::{dep::mime::Mime, Body, Request, Response, StatusCode};
+use rama_core::Context;
+use std::sync::Arc;
+
+#[derive(Debug, Clone)]
+/// Type that performs validation of the Content-Type header.
+///
+/// Requests with a body are required to have a `Content-Type` matching
+/// `type/subtype` or `type/*`, as configured. Other requests are rejected
+/// using a `415 Unsupported Media Type` problem details response.
+///
+/// Requests without a body (a body which is known to be empty,
+/// according to its [size hint](http_body::Body::size_hint)) are always allowed through.
+///
+/// See [`JsonBody`] to also validate the (json) body of the request.
+///
+/// [`JsonBody`]: super::JsonBody
+pub struct ContentTypeHeader {
+    expected: Arc<Mime>,
+}
+
+impl ContentTypeHeader {
+    /// Create a new [`ContentTypeHeader`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `expected` is not in the form: `type/subtype` or `type/*`,
+    /// such as `application/json`.
+    pub fn new(expected: &str) -> Self {
+        Self {
+            expected: Arc::new(
+                expected
+                    .parse::<Mime>()
+                    .expect("value is not a valid media type"),
+            ),
+        }
+    }
+
+    pub(super) fn matches(&self, content_type: &Mime) -> bool {
+        content_type.type_() == self.expected.type_()
+            && (self.expected.subtype() == mime::STAR
+                || content_type.subtype() == self.expected.subtype())
+    }
+}
+
+/// Returns `true` in case the request (most likely) has a non-empty body.
+pub(super) fn has_body<B: http_body::Body>(req: &Request<B>) -> bool {
+    let body = req.body();
+    !body.is_end_stream() && body.size_hint().exact() != Some(0)
+}
+
+/// Returns the parsed Content-Type header of the request, if any.
+pub(super) fn content_type<B>(req: &Request<B>) -> Option<Mime> {
+    req.headers().typed_get::<ContentType>().map(Mime::from)
+}
+
+impl<S, B> ValidateRequest<S, B> for ContentTypeHeader
+where
+    S: Clone + Send + Sync + 'static,
+    B: http_body::Body + Send + 'static,
+{
+    type ResponseBody = Body;
+
+    async fn validate(
+        &self,
+        ctx: Context<S>,
+        req: Request<B>,
+    ) -> Result<(Context<S>, Request<B>), Response<Self::ResponseBody>> {
+        if !has_body(&req) || content_type(&req).is_some_and(|mime| self.matches(&mime)) {
+            return Ok((ctx, req));
+        }
+        Err(problem_response(
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            &format!("expected content type {}", self.expected),
+            &[],
+        ))
+    }
+}
@@ -0,0 +1,677 @@
+use super::{
+    content_type::{content_type, has_body},
+    problem::problem_response,
+    ValidateRequest, ValidationError,
+};
+use crate::dep::http_body::Body as _;
+use crate::dep::http_body_util::{BodyExt, LengthLimitError, Limited};
+use crate::{Body, Request, Response, StatusCode};
+use rama_core::error::{ErrorContext, OpaqueError};
+use rama_core::Context;
+use regex::Regex;
+use serde_json::{Map, Value};
+use std::{collections::HashMap, fmt, sync::Arc};
+
+/// The default maximum size of a json body which is buffered to validate it.
+const DEFAULT_MAX_BODY_SIZE: usize = 1024 * 1024;
+
+/// A validator of json (request) bodies, used by [`JsonBody`].
+///
+/// Implemented for [`JsonSchema`] and for closures
+/// `Fn(&Value) -> Result<(), Vec<ValidationError>>`.
+pub trait JsonValidator: Send + Sync + 'static {
+    /// Validate the json value, returning all errors found in case it is invalid.
+    fn validate_json(&self, value: &Value) -> Result<(), Vec<ValidationError>>;
+}
+
+impl<F> JsonValidator for F
+where
+    F: Fn(&Value) -> Result<(), Vec<ValidationError>> + Send + Sync + 'static,
+{
+    fn validate_json(&self, value: &Value) -> Result<(), Vec<ValidationError>> {
+        self(value)
+    }
+}
+
+/// Type that performs validation of json request bodies.
+///
+/// Requests with a body are rejected:
+///
+/// - using a `415 Unsupported Media Type` problem details response,
+///   in case their Content-Type is not `application/json` (or `application/*+json`);
+/// - using a `413 Payload Too Large` problem details response,
+///   in case the body exceeds the [maximum body size](JsonBody::with_max_body_size);
+/// - using a `400 Bad Request` problem details response,
+///   in case the body is not valid json or is rejected by the [`JsonValidator`],
+///   listing the validation errors.
+///
+/// The body is buffered in memory in order to validate it,
+/// and passed as such to the inner service.
+///
+/// # Example
+///
+/// ```
+/// use rama_http::layer::validate_request::{JsonBody, JsonSchema, ValidateRequestHeaderLayer, ValidationError};
+/// use serde_json::{json, Value};
+///
+/// let schema = JsonSchema::new(json!({
+///     "type": "object",
+///     "required": ["name"],
+///     "properties": {
+///         "name": { "type": "string", "minLength": 1 },
+///         "age": { "type": "integer", "minimum": 0 },
+///     },
+/// }))
+/// .unwrap();
+/// let schema_layer = ValidateRequestHeaderLayer::custom(JsonBody::new(schema));
+///
+/// let fn_layer = ValidateRequestHeaderLayer::custom(JsonBody::new(|value: &Value| {
+///     if value.get("name").is_some() {
+///         Ok(())
+///     } else {
+///         Err(vec![ValidationError::new("", "missing name")])
+///     }
+/// }));
+/// ```
+pub struct JsonBody<V> {
+    validator: Arc<V>,
+    max_body_size: usize,
+}
+
+impl<V> JsonBody<V> {
+    /// Create a new [`JsonBody`] validator using the given [`JsonValidator`].
+    pub fn new(validator: V) -> Self {
+        Self {
+            validator: Arc::new(validator),
+            max_body_size: DEFAULT_MAX_BODY_SIZE,
+        }
+    }
+
+    /// Set the maximum size of the body which is buffered in order to validate it.
+    ///
+    /// Requests with a larger body are rejected using a
+    /// `413 Payload Too Large` problem details response. Defaults to 1 MiB.
+    pub fn with_max_body_size(mut self, size: usize) -> Self {
+        self.max_body_size = size;
+        self
+    }
+
+    /// Set the maximum size of the body which is buffered in order to validate it.
+    ///
+    /// See [`Self::with_max_body_size`] for more information.
+    pub fn set_max_body_size(&mut self, size: usize) -> &mut Self {
+        self.max_body_size = size;
+        self
+    }
+}
+
+impl<V> Clone for JsonBody<V> {
+    fn clone(&self) -> Self {
+        Self {
+            validator: self.validator.clone(),
+            max_body_size: self.max_body_size,
+        }
+    }
+}
+
+impl<V: fmt::Debug> fmt::Debug for JsonBody<V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("JsonBody")
+            .field("validator", &self.validator)
+            .field("max_body_size", &self.max_body_size)
+            .finish()
+    }
+}
+
+fn payload_too_large() -> Response {
+    problem_response(
+        StatusCode::PAYLOAD_TOO_LARGE,
+        "request body is too large",
+        &[],
+    )
+}
+
+fn is_json(req: &Request) -> bool {
+    content_type(req).is_some_and(|mime| {
+        mime.type_() == mime::APPLICATION
+            && (mime.subtype() == mime::JSON || mime.suffix() == Some(mime::JSON))
+    })
+}
+
+impl<S, V> ValidateRequest<S, Body> for JsonBody<V>
+where
+    S: Clone + Send + Sync + 'static,
+    V: JsonValidator,
+{
+    type ResponseBody = Body;
+
+    async fn validate(
+        &self,
+        ctx: Context<S>,
+        req: Request,
+    ) -> Result<(Context<S>, Request), Response<Self::ResponseBody>> {
+        if !has_body(&req) {
+            return Ok((ctx, req));
+        }
+        if !is_json(&req) {
+            return Err(problem_response(
+                StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                "expected content type application/json",
+                &[],
+            ));
+        }
+
+        if req.body().size_hint().lower() > self.max_body_size as u64 {
+            return Err(payload_too_large());
+        }
+
+        let (parts, body) = req.into_parts();
+        let bytes = match Limited::new(body, self.max_body_size).collect().await {
+            Ok(collected) => collected.to_bytes(),
+            Err(err) if err.is::<LengthLimitError>() => return Err(payload_too_large()),
+            Err(err) => {
+                tracing::debug!(error = %err, "validate json body: failed to read body");
+                return Err(problem_response(
+                    StatusCode::BAD_REQUEST,
+                    "failed to read request body",
+                    &[],
+                ));
+            }
+        };
+
+        let value: Value = match serde_json::from_slice(&bytes) {
+            Ok(value) => value,
+            Err(err) => {
+                return Err(problem_response(
+                    StatusCode::BAD_REQUEST,
+                    &format!("invalid json: {err}"),
+                    &[],
+                ));
+            }
+        };
+        if let Err(errors) = self.validator.validate_json(&value) {
+            return Err(problem_response(
+                StatusCode::BAD_REQUEST,
+                "request body failed validation",
+                &errors,
+            ));
+        }
+
+        Ok((ctx, Request::from_parts(parts, Body::from(bytes))))
+    }
+}
+
+#[derive(Debug, Clone)]
+/// A [`JsonValidator`] using a [JSON Schema].
+///
+/// Only the following subset of the JSON Schema validation keywords is supported,
+/// other keywords are ignored:
+///
+/// - any value: `type`, `enum` and `const`;
+/// - objects: `properties`, `required`, `additionalProperties`,
+///   `minProperties` and `maxProperties`;
+/// - arrays: `items`, `minItems` and `maxItems`;
+/// - strings: `minLength`, `maxLength` and `pattern`;
+/// - numbers: `minimum`, `maximum`, `exclusiveMinimum` and `exclusiveMaximum`.
+///
+/// [JSON Schema]: https://json-schema.org/draft/2020-12/json-schema-validation
+pub struct JsonSchema {
+    schema: Arc<Value>,
+    patterns: Arc<HashMap<String, Regex>>,
+}
+
+impl JsonSchema {
+    /// Create a new [`JsonSchema`] from its json representation.
+    ///
+    /// Returns an error in case the schema contains an invalid `pattern`.
+    pub fn new(schema: Value) -> Result<Self, OpaqueError> {
+        let mut patterns = HashMap::new();
+        compile_patterns(&schema, &mut patterns)?;
+        Ok(Self {
+            schema: Arc::new(schema),
+            patterns: Arc::new(patterns),
+        })
+    }
+
+    /// The json representation of the schema.
+    pub fn schema(&self) -> &Value {
+        &self.schema
+    }
+}
+
+impl JsonValidator for JsonSchema {
+    fn validate_json(&self, value: &Value) -> Result<(), Vec<ValidationError>> {
+        let mut errors = Vec::new();
+        validate_schema(
+            &self.schema,
+            &self.patterns,
+            value,
+            &mut String::new(),
+            &mut errors,
+        );
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+/// Compile the `pattern` of the given (sub)schema(s), such that they can be reused.
+fn compile_patterns(
+    schema: &Value,
+    patterns: &mut HashMap<String, Regex>,
+) -> Result<(), OpaqueError> {
+    let Value::Object(schema) = schema else {
+        return Ok(());
+    };
+    if let Some(pattern) = schema.get("pattern").and_then(Value::as_str) {
+        if !patterns.contains_key(pattern) {
+            let re = Regex::new(pattern).context("json schema: invalid pattern")?;
+            patterns.insert(pattern.to_owned(), re);
+        }
+    }
+    if let Some(properties) = schema.get("properties").and_then(Value::as_object) {
+        for property_schema in properties.values() {
+            compile_patterns(property_schema, patterns)?;
+        }
+    }
+    for keyword in ["items", "additionalProperties"] {
+        if let Some(subschema) = schema.get(keyword) {
+            compile_patterns(subschema, patterns)?;
+        }
+    }
+    Ok(())
+}
+
+fn validate_schema(
+    schema: &Value,
+    patterns: &HashMap<String, Regex>,
+    value: &Value,
+    pointer: &mut String,
+    errors: &mut Vec<ValidationError>,
+) {
+    let schema = match schema {
+        Value::Bool(true) => return,
+        Value::Bool(false) => {
+            errors.push(ValidationError::new(
+                pointer.clone(),
+                "value is not allowed",
+            ));
+            return;
+        }
+        Value::Object(schema) => schema,
+        _ => return,
+    };
+
+    if let Some(ty) = schema.get("type") {
+        let matches = match ty {
+            Value::String(ty) => is_type(value, ty),
+            Value::Array(types) => types
+                .iter()
+                .filter_map(Value::as_str)
+                .any(|ty| is_type(value, ty)),
+            _ => true,
+        };
+        if !matches {
+            errors.push(ValidationError::new(
+                pointer.clone(),
+                format!("expected type {ty}, got {}", type_name(value)),
+            ));
+            return;
+        }
+    }
+    if let Some(Value::Array(values)) = schema.get("enum") {
+        if !values.contains(value) {
+            errors.push(ValidationError::new(
+                pointer.clone(),
+                "value is not one of the allowed values",
+            ));
+        }
+    }
+    if let Some(expected) = schema.get("const") {
+        if expected != value {
+            errors.push(ValidationError::new(
+                pointer.clone(),
+                format!("expected value {expected}"),
+            ));
+        }
+    }
+
+    match value {
+        Value::Object(object) => validate_object(schema, patterns, object, pointer, errors),
+        Value::Array(items) => {
+            check_len(
+                schema,
+                "minItems",
+                "maxItems",
+                items.len(),
+                "items",
+                pointer,
+                errors,
+            );
+            if let Some(items_schema) = schema.get("items") {
+                for (index, item) in items.iter().enumerate() {
+                    let len = pointer.len();
+                    pointer.push_str(&format!("/{index}"));
+                    validate_schema(items_schema, patterns, item, pointer, errors);
+                    pointer.truncate(len);
+                }
+            }
+        }
+        Value::String(s) => {
+            check_len(
+                schema,
+                "minLength",
+                "maxLength",
+                s.chars().count(),
+                "characters",
+                pointer,
+                errors,
+            );
+            if let Some(pattern) = schema.get("pattern").and_then(Value::as_str) {
+                // all patterns are compiled when creating the schema
+                if patterns.get(pattern).is_some_and(|re| !re.is_match(s)) {
+                    errors.push(ValidationError::new(
+                        pointer.clone(),
+                        format!("value does not match pattern {pattern}"),
+                    ));
+                }
+            }
+        }
+        Value::Number(n) => {
+            if let Some(n) = n.as_f64() {
+                validate_number(schema, n, pointer, errors);
+            }
+        }
+        Value::Bool(_) | Value::Null => (),
+    }
+}
+
+fn validate_object(
+    schema: &Map<String, Value>,
+    patterns: &HashMap<String, Regex>,
+    object: &Map<String, Value>,
+    pointer: &mut String,
+    errors: &mut Vec<ValidationError>,
+) {
+    check_len(
+        schema,
+        "minProperties",
+        "maxProperties",
+        object.len(),
+        "properties",
+        pointer,
+        errors,
+    );
+    if let Some(Value::Array(required)) = schema.get("required") {
+        for name in required.iter().filter_map(Value::as_str) {
+            if !object.contains_key(name) {
+                errors.push(ValidationError::new(
+                    pointer.clone(),
+                    format!("missing required property {name}"),
+                ));
+            }
+        }
+    }
+
+    let properties = schema.get("properties").and_then(Value::as_object);
+    let additional = schema.get("additionalProperties");
+    for (name, value) in object {
+        let property_schema = properties
+            .and_then(|properties| properties.get(name))
+            .or(additional);
+        if let Some(property_schema) = property_schema {
+            let len = pointer.len();
+            pointer.push('/');
+            pointer.push_str(&name.replace('~', "~0").replace('/', "~1"));
+            validate_schema(property_schema, patterns, value, pointer, errors);
+            pointer.truncate(len);
+        }
+    }
+}
+
+fn validate_number(
+    schema: &Map<String, Value>,
+    n: f64,
+    pointer: &str,
+    errors: &mut Vec<ValidationError>,
+) {
+    let bound = |keyword: &str| schema.get(keyword).and_then(Value::as_f64);
+    let mut check = |keyword: &str, violated: fn(f64, f64) -> bool, description: &str| {
+        if let Some(limit) = bound(keyword) {
+            if violated(n, limit) {
+                errors.push(ValidationError::new(
+                    pointer,
+                    format!("value must be {description} {limit}"),
+                ));
+            }
+        }
+    };
+    check("minimum", |n, limit| n < limit, "at least");
+    check("maximum", |n, limit| n > limit, "at most");
+    check("exclusiveMinimum", |n, limit| n <= limit, "greater than");
+    check("exclusiveMaximum", |n, limit| n >= limit, "less than");
+}
+
+fn check_len(
+    schema: &Map<String, Value>,
+    min_keyword: &str,
+    max_keyword: &str,
+    len: usize,
+    unit: &str,
+    pointer: &str,
+    errors: &mut Vec<ValidationError>,
+) {
+    if let Some(min) = schema.get(min_keyword).and_then(Value::as_u64) {
+        if (len as u64) < min {
+            errors.push(ValidationError::new(
+                pointer,
+                format!("expected at least {min} {unit}"),
+            ));
+        }
+    }
+    if let Some(max) = schema.get(max_keyword).and_then(Value::as_u64) {
+        if (len as u64) > max {
+            errors.push(ValidationError::new(
+                pointer,
+                format!("expected at most {max} {unit}"),
+            ));
+        }
+    }
+}
+
+fn is_type(value: &Value, ty: &str) -> bool {
+    match ty {
+        "integer" => value.as_i64().is_some() || value.as_u64().is_some(),
+        "number" => value.is_number(),
+        ty => type_name(value) == ty,
+    }
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layer::validate_request::ValidateRequestHeaderLayer;
+    use crate::{header, BodyExtractExt};
+    use rama_core::{service::service_fn, Layer, Service};
+    use serde_json::json;
+    use std::convert::Infallible;
+
+    fn schema() -> JsonSchema {
+        JsonSchema::new(json!({
+            "type": "object",
+            "required": ["name", "tags"],
+            "additionalProperties": false,
+            "properties": {
+                "name": { "type": "string", "minLength": 1, "pattern": "^[a-z]+$" },
+                "age": { "type": "integer", "minimum": 0, "exclusiveMaximum": 150 },
+                "tags": { "type": "array", "maxItems": 2, "items": { "enum": ["a", "b"] } },
+            },
+        }))
+        .unwrap()
+    }
+
+    fn errors(value: Value) -> Vec<String> {
+        let mut errors: Vec<_> = match schema().validate_json(&value) {
+            Ok(()) => vec![],
+            Err(errors) => errors.iter().map(|err| err.to_string()).collect(),
+        };
+        // property order depends on the features of serde_json
+        errors.sort();
+        errors
+    }
+
+    #[test]
+    fn test_json_schema() {
+        assert!(errors(json!({"name": "joe", "age": 3, "tags": ["a"]})).is_empty());
+        assert_eq!(
+            errors(json!({"name": "Joe", "age": 150, "tags": ["a", "c", "b"], "x": 1})),
+            vec![
+                "/age: value must be less than 150",
+                "/name: value does not match pattern ^[a-z]+$",
+                "/tags/1: value is not one of the allowed values",
+                "/tags: expected at most 2 items",
+                "/x: value is not allowed",
+            ]
+        );
+        assert_eq!(
+            errors(json!({"age": 1.5})),
+            vec![
+                "/age: expected type \"integer\", got number",
+                "missing required property name",
+                "missing required property tags",
+            ]
+        );
+        assert_eq!(
+            errors(json!([])),
+            vec!["expected type \"object\", got array"]
+        );
+    }
+
+    #[test]
+    fn test_json_schema_invalid_pattern() {
+        assert!(JsonSchema::new(json!({
+            "type": "object",
+            "properties": { "name": { "type": "string", "pattern": "([a-z]+" } },
+        }))
+        .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_json_body_validation() {
+        let service = ValidateRequestHeaderLayer::custom(JsonBody::new(schema())).layer(
+            service_fn(|req: Request| async move {
+                let body = req.into_body().try_into_string().await.unwrap();
+                Ok::<_, Infallible>(Response::new(Body::from(body)))
+            }),
+        );
+
+        let request = |content_type: &str, body: &'static str| {
+            Request::post("/")
+                .header(header::CONTENT_TYPE, content_type)
+                .header(header::CONTENT_LENGTH, body.len())
+                .body(Body::from(body))
+                .unwrap()
+        };
+
+        let res = service
+            .serve(
+                Context::default(),
+                request("application/json", r#"{"name":"joe","tags":[]}"#),
+            )
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(
+            res.into_body().try_into_string().await.unwrap(),
+            r#"{"name":"joe","tags":[]}"#
+        );
+
+        let res = service
+            .serve(Context::default(), request("text/plain", "joe"))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+        assert_eq!(
+            res.headers()[header::CONTENT_TYPE],
+            "application/problem+json"
+        );
+
+        let res = service
+            .serve(
+                Context::default(),
+                request("application/vnd.api+json", r#"{"name":""}"#),
+            )
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+        let problem: Value = res.into_body().try_into_json().await.unwrap();
+        assert_eq!(
+            problem,
+            json!({
+                "type": "about:blank",
+                "title": "Bad Request",
+                "status": 400,
+                "detail": "request body failed validation",
+                "errors": [
+                    {"pointer": "", "detail": "missing required property tags"},
+                    {"pointer": "/name", "detail": "expected at least 1 characters"},
+                    {"pointer": "/name", "detail": "value does not match pattern ^[a-z]+$"},
+                ],
+            })
+        );
+
+        let res = service
+            .serve(Context::default(), request("application/json", "{"))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+        let res = service
+            .serve(Context::default(), Request::new(Body::empty()))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let service =
+            ValidateRequestHeaderLayer::custom(JsonBody::new(schema()).with_max_body_size(8))
+                .layer(service_fn(|_req: Request| async move {
+                    Ok::<_, Infallible>(Response::new(Body::empty()))
+                }));
+        let res = service
+            .serve(
+                Context::default(),
+                request("application/json", r#"{"name":"joe","tags":[]}"#),
+            )
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+        let res = service
+            .serve(
+                Context::default(),
+                Request::post("/")
+                    .header(header::CONTENT_TYPE, "application/json")
+                    .body(Body::from_stream(futures_lite::stream::iter([
+                        Ok::<_, Infallible>(r#"{"name":"#),
+                        Ok(r#""joe","tags":[]}"#),
+                    ])))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    }
+}
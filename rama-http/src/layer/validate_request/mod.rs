@@ -47,6 +47,14 @@
 //! # }
 //! ```
 //!
+//! Requests with a body can be validated to have the expected Content-Type
+//! using [`ContentTypeHeader`], and their json body can be validated
+//! against a [`JsonSchema`] (or any other [`JsonValidator`]) using [`JsonBody`].
+//! Rejected requests get a `400 Bad Request` or `415 Unsupported Media Type`
+//! [problem details] (`application/problem+json`) response.
+//!
+//! [problem details]: https://www.rfc-editor.org/rfc/rfc9457
+//!
 //! Custom validation can be made by implementing [`ValidateRequest`]:
 //!
 //! ```
@@ -138,6 +146,10 @@
 //! ```
 
 mod accept_header;
+mod content_type;
+mod json_body;
+mod problem;
+mod request_target;
 mod validate;
 mod validate_fn;
 mod validate_request_header;
@@ -145,6 +157,14 @@ mod validate_request_header;
 #[doc(inline)]
 pub use accept_header::AcceptHeader;
 #[doc(inline)]
+pub use content_type::ContentTypeHeader;
+#[doc(inline)]
+pub use json_body::{JsonBody, JsonSchema, JsonValidator};
+#[doc(inline)]
+pub use problem::ValidationError;
+#[doc(inline)]
+pub use request_target::RequestTarget;
+#[doc(inline)]
 pub use validate::ValidateRequest;
 #[doc(inline)]
 pub use validate_fn::{BoxValidateRequestFn, ValidateRequestFn};
@@ -0,0 +1,86 @@
+use crate::{header, Body, HeaderValue, Response, StatusCode};
+use serde::Serialize;
+use std::fmt;
+
+/// Content type of the problem details responses, as defined in [RFC 9457].
+///
+/// [RFC 9457]: https://www.rfc-editor.org/rfc/rfc9457
+const APPLICATION_PROBLEM_JSON: &str = "application/problem+json";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+/// A single validation error of a (json) request body,
+/// listed as part of the `errors` of the problem details response.
+pub struct ValidationError {
+    pointer: String,
+    detail: String,
+}
+
+impl ValidationError {
+    /// Create a new [`ValidationError`] for the value found at the given [JSON pointer]
+    /// (e.g. `/user/name`, or the empty string for the root value).
+    ///
+    /// [JSON pointer]: https://www.rfc-editor.org/rfc/rfc6901
+    pub fn new(pointer: impl Into<String>, detail: impl Into<String>) -> Self {
+        Self {
+            pointer: pointer.into(),
+            detail: detail.into(),
+        }
+    }
+
+    /// The JSON pointer to the invalid value.
+    pub fn pointer(&self) -> &str {
+        &self.pointer
+    }
+
+    /// A human readable description of the error.
+    pub fn detail(&self) -> &str {
+        &self.detail
+    }
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.pointer.is_empty() {
+            f.write_str(&self.detail)
+        } else {
+            write!(f, "{}: {}", self.pointer, self.detail)
+        }
+    }
+}
+
+impl std::error::Error for ValidationError {}
+
+#[derive(Serialize)]
+struct Problem<'a> {
+    r#type: &'static str,
+    title: &'static str,
+    status: u16,
+    detail: &'a str,
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    errors: &'a [ValidationError],
+}
+
+/// Create a problem details response, as defined in [RFC 9457].
+///
+/// [RFC 9457]: https://www.rfc-editor.org/rfc/rfc9457
+pub(super) fn problem_response(
+    status: StatusCode,
+    detail: &str,
+    errors: &[ValidationError],
+) -> Response {
+    let problem = Problem {
+        r#type: "about:blank",
+        title: status.canonical_reason().unwrap_or_default(),
+        status: status.as_u16(),
+        detail,
+        errors,
+    };
+    let body = serde_json::to_vec(&problem).expect("problem details can be serialized");
+    let mut res = Response::new(Body::from(body));
+    *res.status_mut() = status;
+    res.headers_mut().insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static(APPLICATION_PROBLEM_JSON),
+    );
+    res
+}
@@ -1,4 +1,4 @@
-use super::{AcceptHeader, BoxValidateRequestFn, ValidateRequest};
+use super::{AcceptHeader, BoxValidateRequestFn, ContentTypeHeader, ValidateRequest};
 use crate::{Request, Response};
 use rama_core::{Context, Layer, Service};
 use rama_utils::macros::define_inner_service_accessors;
@@ -58,6 +58,29 @@ impl<ResBody> ValidateRequestHeaderLayer<AcceptHeader<ResBody>> {
     }
 }
 
+impl ValidateRequestHeaderLayer<ContentTypeHeader> {
+    /// Validate requests with a body have the expected Content-Type header.
+    ///
+    /// The `Content-Type` header is required to match `type/subtype` or `type/*`,
+    /// as configured, or the request is rejected using a
+    /// `415 Unsupported Media Type` problem details response.
+    ///
+    /// # Panics
+    ///
+    /// See `ContentTypeHeader::new` for when this method panics.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rama_http::layer::validate_request::{ContentTypeHeader, ValidateRequestHeaderLayer};
+    ///
+    /// let layer = ValidateRequestHeaderLayer::<ContentTypeHeader>::content_type("application/json");
+    /// ```
+    pub fn content_type(value: &str) -> Self {
+        Self::custom(ContentTypeHeader::new(value))
+    }
+}
+
 impl<T> ValidateRequestHeaderLayer<T> {
     /// Validate requests using a custom validator.
     pub fn custom(validate: T) -> Self {
@@ -338,6 +361,32 @@ mod tests {
         assert_eq!(res.status(), StatusCode::NOT_ACCEPTABLE);
     }
 
+    #[tokio::test]
+    async fn content_type_header() {
+        let service =
+            ValidateRequestHeaderLayer::content_type("application/json").layer(service_fn(echo));
+
+        for (content_type, body, status) in [
+            (
+                Some("application/json; charset=utf-8"),
+                "{}",
+                StatusCode::OK,
+            ),
+            (Some("text/plain"), "{}", StatusCode::UNSUPPORTED_MEDIA_TYPE),
+            (None, "{}", StatusCode::UNSUPPORTED_MEDIA_TYPE),
+            (None, "", StatusCode::OK),
+        ] {
+            let mut request = Request::post("/");
+            if let Some(content_type) = content_type {
+                request = request.header(header::CONTENT_TYPE, content_type);
+            }
+            let request = request.body(Body::from(body)).unwrap();
+
+            let res = service.serve(Context::default(), request).await.unwrap();
+            assert_eq!(res.status(), status, "{content_type:?}");
+        }
+    }
+
     async fn echo<B>(req: Request<B>) -> Result<Response<B>, BoxError> {
         Ok(Response::new(req.into_body()))
     }
@@ -0,0 +1,636 @@
+//! Middleware that verifies the signature of incoming webhook requests.
+//!
+//! The request body is collected and authenticated using HMAC-SHA256
+//! before it is passed to the inner service. Requests with a missing, malformed
+//! or invalid signature are rejected with a `401 Unauthorized` response,
+//! while requests with a body exceeding the [maximum body size] are rejected
+//! with a `413 Payload Too Large` response.
+//!
+//! Next to a generic HMAC-SHA256 scheme (signature in a configurable header),
+//! presets are available for [Stripe] and [GitHub] webhooks. Schemes that sign
+//! a timestamp also protect against replay attacks, by rejecting requests
+//! that fall outside of the configured tolerance window.
+//!
+//! Signatures are always compared in constant time.
+//!
+//! The current time used to verify timestamps is read from the [`Clock`]
+//! of the [`Context`], such that the tolerance window can be tested deterministically.
+//!
+//! [Stripe]: https://docs.stripe.com/webhooks#verify-manually
+//! [GitHub]: https://docs.github.com/en/webhooks/using-webhooks/validating-webhook-deliveries
+//! [maximum body size]: WebhookVerificationLayer::max_body_size
+//! [`Clock`]: rama_core::time::Clock
+//! [`Context`]: rama_core::Context
+//!
+//! # Example
+//!
+//! ```
+//! use rama_http::layer::webhook::WebhookVerificationLayer;
+//! use rama_http::{Body, Request, Response, StatusCode};
+//! use rama_core::service::service_fn;
+//! use rama_core::{Context, Layer, Service};
+//! use std::convert::Infallible;
+//!
+//! # #[tokio::main]
+//! # async fn main() {
+//! let service = WebhookVerificationLayer::github("my-secret")
+//!     .layer(service_fn(|_req: Request| async move {
+//!         Ok::<_, Infallible>(Response::new(Body::empty()))
+//!     }));
+//!
+//! // unsigned requests are rejected
+//! let resp = service
+//!     .serve(Context::default(), Request::new(Body::from("{}")))
+//!     .await
+//!     .unwrap();
+//! assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+//! # }
+//! ```
+
+use crate::dep::http_body_util::{BodyExt, LengthLimitError, Limited};
+use crate::{dep::http_body, Body, HeaderMap, HeaderName, Request, Response, StatusCode};
+use bytes::Bytes;
+use rama_core::{error::BoxError, Context, Layer, Service};
+use rama_utils::macros::define_inner_service_accessors;
+use ring::hmac;
+use std::{
+    fmt,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+const STRIPE_SIGNATURE: HeaderName = HeaderName::from_static("stripe-signature");
+const GITHUB_SIGNATURE: HeaderName = HeaderName::from_static("x-hub-signature-256");
+
+/// Default tolerance used for the replay window of timestamped signatures.
+pub const DEFAULT_TOLERANCE: Duration = Duration::from_secs(300);
+
+/// Default maximum size of the (buffered) request body: 1 MiB.
+pub const DEFAULT_MAX_BODY_SIZE: usize = 1024 * 1024;
+
+#[derive(Debug, Clone)]
+enum Scheme {
+    HmacSha256 {
+        signature_header: HeaderName,
+        signature_prefix: Option<&'static str>,
+        timestamp_header: Option<HeaderName>,
+    },
+    Stripe,
+    GitHub,
+}
+
+/// Layer that applies the [`WebhookVerificationService`] middleware,
+/// verifying the signature of incoming webhook requests.
+///
+/// See the [module docs](self) for more information.
+#[derive(Debug, Clone)]
+pub struct WebhookVerificationLayer {
+    scheme: Scheme,
+    secret: Bytes,
+    tolerance: Duration,
+    max_body_size: usize,
+}
+
+impl WebhookVerificationLayer {
+    /// Create a new [`WebhookVerificationLayer`] which expects
+    /// the hex-encoded HMAC-SHA256 signature of the request body in the given header.
+    pub fn hmac_sha256(signature_header: HeaderName, secret: impl Into<Bytes>) -> Self {
+        Self {
+            scheme: Scheme::HmacSha256 {
+                signature_header,
+                signature_prefix: None,
+                timestamp_header: None,
+            },
+            secret: secret.into(),
+            tolerance: DEFAULT_TOLERANCE,
+            max_body_size: DEFAULT_MAX_BODY_SIZE,
+        }
+    }
+
+    /// Create a new [`WebhookVerificationLayer`] for [Stripe] webhooks.
+    ///
+    /// Signatures are read from the `Stripe-Signature` header
+    /// and are expected to sign the `{timestamp}.{body}` payload.
+    ///
+    /// [Stripe]: https://docs.stripe.com/webhooks#verify-manually
+    pub fn stripe(secret: impl Into<Bytes>) -> Self {
+        Self {
+            scheme: Scheme::Stripe,
+            secret: secret.into(),
+            tolerance: DEFAULT_TOLERANCE,
+            max_body_size: DEFAULT_MAX_BODY_SIZE,
+        }
+    }
+
+    /// Create a new [`WebhookVerificationLayer`] for [GitHub] webhooks.
+    ///
+    /// Signatures are read from the `X-Hub-Signature-256` header,
+    /// which is formatted as `sha256={signature}`.
+    ///
+    /// GitHub does not sign a timestamp, and thus no replay window is enforced.
+    ///
+    /// [GitHub]: https://docs.github.com/en/webhooks/using-webhooks/validating-webhook-deliveries
+    pub fn github(secret: impl Into<Bytes>) -> Self {
+        Self {
+            scheme: Scheme::GitHub,
+            secret: secret.into(),
+            tolerance: DEFAULT_TOLERANCE,
+            max_body_size: DEFAULT_MAX_BODY_SIZE,
+        }
+    }
+
+    /// Set the prefix which precedes the signature in the signature header (e.g. `sha256=`).
+    ///
+    /// Only applies to the generic [`hmac_sha256`](Self::hmac_sha256) scheme.
+    pub fn signature_prefix(mut self, prefix: &'static str) -> Self {
+        self.set_signature_prefix(prefix);
+        self
+    }
+
+    /// Set the prefix which precedes the signature in the signature header (e.g. `sha256=`).
+    ///
+    /// Only applies to the generic [`hmac_sha256`](Self::hmac_sha256) scheme.
+    pub fn set_signature_prefix(&mut self, prefix: &'static str) -> &mut Self {
+        if let Scheme::HmacSha256 {
+            signature_prefix, ..
+        } = &mut self.scheme
+        {
+            *signature_prefix = Some(prefix);
+        }
+        self
+    }
+
+    /// Set the header containing the unix timestamp (in seconds) at which the request was signed.
+    ///
+    /// When set the signed payload is expected to be `{timestamp}.{body}`,
+    /// and requests outside of the [tolerance](Self::tolerance) window are rejected.
+    ///
+    /// Only applies to the generic [`hmac_sha256`](Self::hmac_sha256) scheme.
+    pub fn timestamp_header(mut self, header: HeaderName) -> Self {
+        self.set_timestamp_header(header);
+        self
+    }
+
+    /// Set the header containing the unix timestamp (in seconds) at which the request was signed.
+    ///
+    /// When set the signed payload is expected to be `{timestamp}.{body}`,
+    /// and requests outside of the [tolerance](Self::tolerance) window are rejected.
+    ///
+    /// Only applies to the generic [`hmac_sha256`](Self::hmac_sha256) scheme.
+    pub fn set_timestamp_header(&mut self, header: HeaderName) -> &mut Self {
+        if let Scheme::HmacSha256 {
+            timestamp_header, ..
+        } = &mut self.scheme
+        {
+            *timestamp_header = Some(header);
+        }
+        self
+    }
+
+    /// Set the maximum age (or clock skew) allowed for timestamped signatures.
+    ///
+    /// Defaults to [`DEFAULT_TOLERANCE`] (5 minutes).
+    pub fn tolerance(mut self, tolerance: Duration) -> Self {
+        self.tolerance = tolerance;
+        self
+    }
+
+    /// Set the maximum age (or clock skew) allowed for timestamped signatures.
+    ///
+    /// Defaults to [`DEFAULT_TOLERANCE`] (5 minutes).
+    pub fn set_tolerance(&mut self, tolerance: Duration) -> &mut Self {
+        self.tolerance = tolerance;
+        self
+    }
+
+    /// Set the maximum size of the request body, which is buffered in order to verify it.
+    ///
+    /// Requests with a larger body are rejected with a `413 Payload Too Large` response.
+    /// Defaults to [`DEFAULT_MAX_BODY_SIZE`] (1 MiB).
+    pub fn max_body_size(mut self, size: usize) -> Self {
+        self.max_body_size = size;
+        self
+    }
+
+    /// Set the maximum size of the request body, which is buffered in order to verify it.
+    ///
+    /// Requests with a larger body are rejected with a `413 Payload Too Large` response.
+    /// Defaults to [`DEFAULT_MAX_BODY_SIZE`] (1 MiB).
+    pub fn set_max_body_size(&mut self, size: usize) -> &mut Self {
+        self.max_body_size = size;
+        self
+    }
+}
+
+impl<S> Layer<S> for WebhookVerificationLayer {
+    type Service = WebhookVerificationService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        WebhookVerificationService {
+            inner,
+            scheme: self.scheme.clone(),
+            secret: self.secret.clone(),
+            tolerance: self.tolerance,
+            max_body_size: self.max_body_size,
+        }
+    }
+}
+
+/// Middleware that verifies the signature of incoming webhook requests.
+///
+/// See the [module docs](self) for more information.
+pub struct WebhookVerificationService<S> {
+    inner: S,
+    scheme: Scheme,
+    secret: Bytes,
+    tolerance: Duration,
+    max_body_size: usize,
+}
+
+impl<S> WebhookVerificationService<S> {
+    define_inner_service_accessors!();
+}
+
+impl<S: fmt::Debug> fmt::Debug for WebhookVerificationService<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WebhookVerificationService")
+            .field("inner", &self.inner)
+            .field("scheme", &self.scheme)
+            .field("tolerance", &self.tolerance)
+            .field("max_body_size", &self.max_body_size)
+            .finish()
+    }
+}
+
+impl<S: Clone> Clone for WebhookVerificationService<S> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            scheme: self.scheme.clone(),
+            secret: self.secret.clone(),
+            tolerance: self.tolerance,
+            max_body_size: self.max_body_size,
+        }
+    }
+}
+
+impl<S, State, ReqBody, ResBody> Service<State, Request<ReqBody>> for WebhookVerificationService<S>
+where
+    S: Service<State, Request, Response = Response<ResBody>>,
+    State: Clone + Send + Sync + 'static,
+    ReqBody: http_body::Body<Data: Send, Error: Into<BoxError>> + Send + 'static,
+    ResBody: Default + Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+
+    async fn serve(
+        &self,
+        ctx: Context<State>,
+        req: Request<ReqBody>,
+    ) -> Result<Self::Response, Self::Error> {
+        if req.body().size_hint().lower() > self.max_body_size as u64 {
+            tracing::debug!("webhook verification: request body too large: reject request");
+            return Ok(reject(StatusCode::PAYLOAD_TOO_LARGE));
+        }
+
+        let (parts, body) = req.into_parts();
+        let body = match Limited::new(body, self.max_body_size).collect().await {
+            Ok(collected) => collected.to_bytes(),
+            Err(err) if err.is::<LengthLimitError>() => {
+                tracing::debug!("webhook verification: request body too large: reject request");
+                return Ok(reject(StatusCode::PAYLOAD_TOO_LARGE));
+            }
+            Err(err) => {
+                tracing::debug!(
+                    error = %err,
+                    "webhook verification: failed to collect request body"
+                );
+                return Ok(reject(StatusCode::BAD_REQUEST));
+            }
+        };
+
+        let now = ctx.clock().system_now();
+        if let Err(reason) = self.verify(&parts.headers, &body, now) {
+            tracing::debug!(reason, "webhook verification failed: reject request");
+            return Ok(reject(StatusCode::UNAUTHORIZED));
+        }
+
+        let req = Request::from_parts(parts, Body::from(body));
+        self.inner.serve(ctx, req).await
+    }
+}
+
+impl<S> WebhookVerificationService<S> {
+    fn verify(
+        &self,
+        headers: &HeaderMap,
+        body: &[u8],
+        now: SystemTime,
+    ) -> Result<(), &'static str> {
+        match &self.scheme {
+            Scheme::HmacSha256 {
+                signature_header,
+                signature_prefix,
+                timestamp_header,
+            } => {
+                let signature = signature_header_str(headers, signature_header)?;
+                let signature = match signature_prefix {
+                    Some(prefix) => signature
+                        .strip_prefix(prefix)
+                        .ok_or("signature prefix missing")?,
+                    None => signature,
+                };
+                let signature = hex::decode(signature.trim()).map_err(|_| "invalid signature")?;
+                match timestamp_header {
+                    Some(timestamp_header) => {
+                        let timestamp = headers
+                            .get(timestamp_header)
+                            .ok_or("timestamp header missing")?
+                            .to_str()
+                            .map_err(|_| "timestamp header is not valid utf-8")?;
+                        self.verify_timestamp(timestamp, now)?;
+                        self.verify_signature(&[timestamp.as_bytes(), b".", body], &signature)
+                    }
+                    None => self.verify_signature(&[body], &signature),
+                }
+            }
+            Scheme::Stripe => {
+                let header = signature_header_str(headers, &STRIPE_SIGNATURE)?;
+                let mut timestamp = None;
+                let mut signatures = Vec::new();
+                for (key, value) in header.split(',').filter_map(|kv| kv.trim().split_once('=')) {
+                    match key {
+                        "t" => timestamp = Some(value),
+                        "v1" => signatures.push(value),
+                        _ => (),
+                    }
+                }
+                let timestamp = timestamp.ok_or("timestamp missing")?;
+                self.verify_timestamp(timestamp, now)?;
+                let payload = [timestamp.as_bytes(), b".", body];
+                // multiple signatures are present while a secret is being rolled
+                if signatures.iter().any(|signature| {
+                    hex::decode(signature)
+                        .ok()
+                        .map(|signature| self.verify_signature(&payload, &signature).is_ok())
+                        .unwrap_or_default()
+                }) {
+                    Ok(())
+                } else {
+                    Err("signature mismatch")
+                }
+            }
+            Scheme::GitHub => {
+                let signature = signature_header_str(headers, &GITHUB_SIGNATURE)?
+                    .strip_prefix("sha256=")
+                    .ok_or("signature prefix missing")?;
+                let signature = hex::decode(signature.trim()).map_err(|_| "invalid signature")?;
+                self.verify_signature(&[body], &signature)
+            }
+        }
+    }
+
+    fn verify_timestamp(&self, timestamp: &str, now: SystemTime) -> Result<(), &'static str> {
+        let timestamp: u64 = timestamp.trim().parse().map_err(|_| "invalid timestamp")?;
+        let now = now
+            .duration_since(UNIX_EPOCH)
+            .map_err(|_| "system time before unix epoch")?
+            .as_secs();
+        if now.abs_diff(timestamp) > self.tolerance.as_secs() {
+            return Err("timestamp outside of tolerance window");
+        }
+        Ok(())
+    }
+
+    fn verify_signature(&self, payload: &[&[u8]], signature: &[u8]) -> Result<(), &'static str> {
+        let key = hmac::Key::new(hmac::HMAC_SHA256, &self.secret);
+        // constant-time comparison
+        hmac::verify(&key, &payload.concat(), signature).map_err(|_| "signature mismatch")
+    }
+}
+
+fn reject<ResBody: Default>(status: StatusCode) -> Response<ResBody> {
+    let mut res = Response::new(ResBody::default());
+    *res.status_mut() = status;
+    res
+}
+
+fn signature_header_str<'a>(
+    headers: &'a HeaderMap,
+    name: &HeaderName,
+) -> Result<&'a str, &'static str> {
+    headers
+        .get(name)
+        .ok_or("signature header missing")?
+        .to_str()
+        .map_err(|_| "signature header is not valid utf-8")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rama_core::{service::service_fn, time::Clock};
+    use std::convert::Infallible;
+
+    const SECRET: &str = "whsec_test";
+
+    fn sign(payload: &[u8]) -> String {
+        let key = hmac::Key::new(hmac::HMAC_SHA256, SECRET.as_bytes());
+        hex::encode(hmac::sign(&key, payload))
+    }
+
+    fn now() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+    }
+
+    async fn serve(layer: WebhookVerificationLayer, req: Request) -> StatusCode {
+        serve_with_ctx(layer, Context::default(), req).await
+    }
+
+    async fn serve_with_ctx(
+        layer: WebhookVerificationLayer,
+        ctx: Context<()>,
+        req: Request,
+    ) -> StatusCode {
+        let service = layer.layer(service_fn(|req: Request| async move {
+            let body = req.into_body().collect().await.unwrap().to_bytes();
+            assert_eq!(body, "{\"hello\":\"world\"}");
+            Ok::<_, Infallible>(Response::new(Body::empty()))
+        }));
+        service.serve(ctx, req).await.unwrap().status()
+    }
+
+    const BODY: &str = "{\"hello\":\"world\"}";
+
+    #[tokio::test]
+    async fn test_hmac_sha256() {
+        let layer =
+            WebhookVerificationLayer::hmac_sha256(HeaderName::from_static("x-signature"), SECRET);
+
+        let req = Request::builder()
+            .header("x-signature", sign(BODY.as_bytes()))
+            .body(Body::from(BODY))
+            .unwrap();
+        assert_eq!(serve(layer.clone(), req).await, StatusCode::OK);
+
+        let req = Request::builder()
+            .header("x-signature", sign(b"tampered"))
+            .body(Body::from(BODY))
+            .unwrap();
+        assert_eq!(serve(layer.clone(), req).await, StatusCode::UNAUTHORIZED);
+
+        let req = Request::builder().body(Body::from(BODY)).unwrap();
+        assert_eq!(serve(layer, req).await, StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_max_body_size() {
+        let layer =
+            WebhookVerificationLayer::hmac_sha256(HeaderName::from_static("x-signature"), SECRET)
+                .max_body_size(8);
+
+        let req = Request::builder()
+            .header("x-signature", sign(BODY.as_bytes()))
+            .body(Body::from(BODY))
+            .unwrap();
+        assert_eq!(
+            serve(layer.clone(), req).await,
+            StatusCode::PAYLOAD_TOO_LARGE
+        );
+
+        // bodies without a known size are limited while they are collected
+        let req = Request::builder()
+            .header("x-signature", sign(BODY.as_bytes()))
+            .body(Body::from_stream(futures_lite::stream::iter([
+                Ok::<_, Infallible>(&BODY[..8]),
+                Ok(&BODY[8..]),
+            ])))
+            .unwrap();
+        assert_eq!(serve(layer, req).await, StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    #[test]
+    fn test_missing_timestamp_header() {
+        let service =
+            WebhookVerificationLayer::hmac_sha256(HeaderName::from_static("x-signature"), SECRET)
+                .timestamp_header(HeaderName::from_static("x-timestamp"))
+                .layer(());
+
+        let mut headers = HeaderMap::new();
+        headers.insert("x-signature", sign(BODY.as_bytes()).parse().unwrap());
+        assert_eq!(
+            service.verify(&headers, BODY.as_bytes(), SystemTime::now()),
+            Err("timestamp header missing")
+        );
+
+        headers.remove("x-signature");
+        assert_eq!(
+            service.verify(&headers, BODY.as_bytes(), SystemTime::now()),
+            Err("signature header missing")
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_hmac_sha256_with_prefix_and_timestamp() {
+        let layer =
+            WebhookVerificationLayer::hmac_sha256(HeaderName::from_static("x-signature"), SECRET)
+                .signature_prefix("sha256=")
+                .timestamp_header(HeaderName::from_static("x-timestamp"))
+                .tolerance(Duration::from_secs(60));
+
+        let mut ctx = Context::default();
+        ctx.insert(Clock::tokio());
+        let timestamp = ctx
+            .clock()
+            .system_now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+            .to_string();
+        let request = || {
+            Request::builder()
+                .header(
+                    "x-signature",
+                    format!("sha256={}", sign(format!("{timestamp}.{BODY}").as_bytes())),
+                )
+                .header("x-timestamp", &timestamp)
+                .body(Body::from(BODY))
+                .unwrap()
+        };
+
+        assert_eq!(
+            serve_with_ctx(layer.clone(), ctx.clone(), request()).await,
+            StatusCode::OK
+        );
+
+        // still within the tolerance window
+        tokio::time::advance(Duration::from_secs(60)).await;
+        assert_eq!(
+            serve_with_ctx(layer.clone(), ctx.clone(), request()).await,
+            StatusCode::OK
+        );
+
+        // the same request is no longer accepted once outside of the tolerance window
+        tokio::time::advance(Duration::from_secs(2)).await;
+        assert_eq!(
+            serve_with_ctx(layer, ctx, request()).await,
+            StatusCode::UNAUTHORIZED
+        );
+    }
+
+    #[tokio::test]
+    async fn test_stripe() {
+        let layer = WebhookVerificationLayer::stripe(SECRET);
+
+        let timestamp = now();
+        let signature = sign(format!("{timestamp}.{BODY}").as_bytes());
+        let req = Request::builder()
+            .header(
+                "stripe-signature",
+                format!("t={timestamp},v1={},v1={signature},v0=abc", sign(b"old")),
+            )
+            .body(Body::from(BODY))
+            .unwrap();
+        assert_eq!(serve(layer.clone(), req).await, StatusCode::OK);
+
+        let timestamp = now() - 600;
+        let signature = sign(format!("{timestamp}.{BODY}").as_bytes());
+        let req = Request::builder()
+            .header("stripe-signature", format!("t={timestamp},v1={signature}"))
+            .body(Body::from(BODY))
+            .unwrap();
+        assert_eq!(serve(layer.clone(), req).await, StatusCode::UNAUTHORIZED);
+
+        let req = Request::builder()
+            .header(
+                "stripe-signature",
+                format!("t={},v1={}", now(), sign(BODY.as_bytes())),
+            )
+            .body(Body::from(BODY))
+            .unwrap();
+        assert_eq!(serve(layer, req).await, StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_github() {
+        let layer = WebhookVerificationLayer::github(SECRET);
+
+        let req = Request::builder()
+            .header(
+                "x-hub-signature-256",
+                format!("sha256={}", sign(BODY.as_bytes())),
+            )
+            .body(Body::from(BODY))
+            .unwrap();
+        assert_eq!(serve(layer.clone(), req).await, StatusCode::OK);
+
+        let req = Request::builder()
+            .header("x-hub-signature-256", sign(BODY.as_bytes()))
+            .body(Body::from(BODY))
+            .unwrap();
+        assert_eq!(serve(layer, req).await, StatusCode::UNAUTHORIZED);
+    }
+}
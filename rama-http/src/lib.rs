@@ -21,8 +21,9 @@
 pub use ::rama_http_types::{
     header,
     response::{self, IntoResponse, Response},
-    Body, BodyDataStream, BodyExtractExt, BodyLimit, HeaderMap, HeaderName, HeaderValue, Method,
-    Request, Scheme, StatusCode, Uri, Version,
+    Body, BodyDataStream, BodyExtractExt, BodyLimit, HeaderMap, HeaderName, HeaderValue,
+    InformationalResponse, InformationalResponses, Method, OriginalHeaderOrder, Request, Scheme,
+    StatusCode, Uri, Version,
 };
 
 pub mod headers;
@@ -33,6 +34,8 @@ pub mod service;
 
 pub mod io;
 
+pub mod sse;
+
 pub mod utils;
 
 pub mod dep {
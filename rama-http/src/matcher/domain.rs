@@ -2,6 +2,7 @@ use crate::Request;
 use rama_core::{context::Extensions, Context};
 use rama_net::address::{Domain, Host};
 use rama_net::http::RequestContext;
+use std::collections::HashMap;
 
 #[derive(Debug, Clone)]
 /// Matcher based on the (sub)domain of the request's URI.
@@ -33,22 +34,8 @@ impl<State, Body> rama_core::matcher::Matcher<State, Request<Body>> for DomainMa
         ctx: &Context<State>,
         req: &Request<Body>,
     ) -> bool {
-        let host = match ctx.get::<RequestContext>() {
-            Some(req_ctx) => req_ctx.authority.host().clone(),
-            None => {
-                let req_ctx: RequestContext = match (ctx, req).try_into() {
-                    Ok(req_ctx) => req_ctx,
-                    Err(err) => {
-                        tracing::error!(error = %err, "DomainMatcher: failed to lazy-make the request ctx");
-                        return false;
-                    }
-                };
-                let host = req_ctx.authority.host().clone();
-                if let Some(ext) = ext {
-                    ext.insert(req_ctx);
-                }
-                host
-            }
+        let Some(host) = request_host("DomainMatcher", ext, ctx, req) else {
+            return false;
         };
         match host {
             Host::Name(domain) => {
@@ -67,3 +54,191 @@ impl<State, Body> rama_core::matcher::Matcher<State, Request<Body>> for DomainMa
         }
     }
 }
+
+/// Get the host of the request, from the [`RequestContext`],
+/// lazily creating and storing the [`RequestContext`] if not yet available.
+fn request_host<State, Body>(
+    matcher: &'static str,
+    ext: Option<&mut Extensions>,
+    ctx: &Context<State>,
+    req: &Request<Body>,
+) -> Option<Host> {
+    if let Some(req_ctx) = ctx.get::<RequestContext>() {
+        return Some(req_ctx.authority.host().clone());
+    }
+    let req_ctx: RequestContext = match (ctx, req).try_into() {
+        Ok(req_ctx) => req_ctx,
+        Err(err) => {
+            tracing::error!(error = %err, "{matcher}: failed to lazy-make the request ctx");
+            return None;
+        }
+    };
+    let host = req_ctx.authority.host().clone();
+    if let Some(ext) = ext {
+        ext.insert(req_ctx);
+    }
+    Some(host)
+}
+
+#[derive(Debug, Clone, Default)]
+/// Matcher based on a (large) set of domains of the request's URI,
+/// each matched either exactly or including its subdomains.
+///
+/// The domains are compiled into a trie of their labels,
+/// such that matching costs a single lookup per label of the request host,
+/// rather than a scan over all domains as a list of [`DomainMatcher`]s would.
+pub struct DomainSetMatcher {
+    root: DomainNode,
+    len: usize,
+}
+
+#[derive(Debug, Clone, Default)]
+struct DomainNode {
+    children: HashMap<Box<str>, DomainNode>,
+    exact: bool,
+    sub: bool,
+}
+
+impl DomainSetMatcher {
+    /// Create a new empty [`DomainSetMatcher`], matching no domain.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a domain to be matched exactly.
+    pub fn with_exact(mut self, domain: Domain) -> Self {
+        self.insert(&domain, false);
+        self
+    }
+
+    /// Add a domain to be matched exactly.
+    pub fn set_exact(&mut self, domain: Domain) -> &mut Self {
+        self.insert(&domain, false);
+        self
+    }
+
+    /// Add a domain to be matched including all its subdomains.
+    pub fn with_sub(mut self, domain: Domain) -> Self {
+        self.insert(&domain, true);
+        self
+    }
+
+    /// Add a domain to be matched including all its subdomains.
+    pub fn set_sub(&mut self, domain: Domain) -> &mut Self {
+        self.insert(&domain, true);
+        self
+    }
+
+    /// Returns the amount of domains in this set.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` in case no domains were added to this set.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns `true` if the given [`Domain`] is matched by this set.
+    pub fn contains(&self, domain: &Domain) -> bool {
+        let mut node = &self.root;
+        for label in labels(domain) {
+            match node.children.get(label.as_str()) {
+                Some(child) => node = child,
+                None => return false,
+            }
+            if node.sub {
+                return true;
+            }
+        }
+        node.exact
+    }
+
+    fn insert(&mut self, domain: &Domain, sub: bool) {
+        let mut node = &mut self.root;
+        for label in labels(domain) {
+            node = node.children.entry(label.into_boxed_str()).or_default();
+        }
+        if !node.exact && !node.sub {
+            self.len += 1;
+        }
+        if sub {
+            node.sub = true;
+        } else {
+            node.exact = true;
+        }
+    }
+}
+
+/// The lowercase labels of a domain, starting from the top-level domain.
+fn labels(domain: &Domain) -> impl Iterator<Item = String> + '_ {
+    domain
+        .as_str()
+        .trim_matches('.')
+        .rsplit('.')
+        .map(|label| label.to_ascii_lowercase())
+}
+
+impl<State, Body> rama_core::matcher::Matcher<State, Request<Body>> for DomainSetMatcher {
+    fn matches(
+        &self,
+        ext: Option<&mut Extensions>,
+        ctx: &Context<State>,
+        req: &Request<Body>,
+    ) -> bool {
+        match request_host("DomainSetMatcher", ext, ctx, req) {
+            Some(Host::Name(domain)) => self.contains(&domain),
+            Some(Host::Address(_)) => {
+                tracing::trace!("DomainSetMatcher: ignore request host address");
+                false
+            }
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_domain_set_contains() {
+        let set = DomainSetMatcher::new()
+            .with_exact(Domain::from_static("example.com"))
+            .with_sub(Domain::from_static("ads.example.org"))
+            .with_exact(Domain::from_static("Tracker.NET."));
+        assert_eq!(set.len(), 3);
+
+        for (domain, expected) in [
+            ("example.com", true),
+            ("EXAMPLE.com.", true),
+            ("www.example.com", false),
+            ("com", false),
+            ("ads.example.org", true),
+            ("foo.bar.ads.example.org", true),
+            ("example.org", false),
+            ("tracker.net", true),
+            ("a.tracker.net", false),
+        ] {
+            assert_eq!(
+                set.contains(&Domain::from_static(domain)),
+                expected,
+                "{domain}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_domain_set_matcher() {
+        use rama_core::matcher::Matcher;
+
+        let set = DomainSetMatcher::new().with_sub(Domain::from_static("example.com"));
+        let matches = |uri: &str| {
+            let req = Request::builder().uri(uri).body(()).unwrap();
+            set.matches(None, &Context::default(), &req)
+        };
+        assert!(matches("http://www.example.com/foo"));
+        assert!(!matches("http://example.org/foo"));
+        assert!(!matches("http://127.0.0.1/foo"));
+    }
+}
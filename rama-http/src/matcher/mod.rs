@@ -17,7 +17,7 @@ pub use method::MethodMatcher;
 
 mod domain;
 #[doc(inline)]
-pub use domain::DomainMatcher;
+pub use domain::{DomainMatcher, DomainSetMatcher};
 
 pub mod uri;
 pub use uri::UriMatcher;
@@ -28,7 +28,7 @@ pub use version::VersionMatcher;
 
 mod path;
 #[doc(inline)]
-pub use path::{PathMatcher, UriParams, UriParamsDeserializeError};
+pub use path::{PathMatcher, PathTrie, UriParams, UriParamsDeserializeError};
 
 mod header;
 #[doc(inline)]
@@ -4,6 +4,9 @@ use std::collections::HashMap;
 
 mod de;
 
+mod trie;
+pub use trie::PathTrie;
+
 #[derive(Debug, Clone, Default)]
 /// parameters that are inserted in the [`Context`],
 /// in case the [`PathMatcher`] found a match for the given [`Request`].
@@ -37,6 +40,24 @@ impl UriParams {
         }
     }
 
+    /// Add the params of an outer (e.g. nesting) route,
+    /// which do not conflict with the params of this route.
+    pub(crate) fn extend_outer(&mut self, outer: &UriParams) {
+        for (name, value) in outer.params.iter().flatten() {
+            if self.get(name).is_none() {
+                self.insert(name.clone(), value.clone());
+            }
+        }
+    }
+
+    /// Insert the captured glob value (without its leading slash) as the named parameter.
+    fn insert_glob_param(&mut self, name: &str) {
+        if let Some(glob) = self.glob.as_deref() {
+            let value = glob.trim_start_matches('/').to_owned();
+            self.insert(name.to_owned(), value);
+        }
+    }
+
     /// Some str slice will be returned in case a glob value was captured
     /// for the last part of the Path that was matched on.
     pub fn glob(&self) -> Option<&str> {
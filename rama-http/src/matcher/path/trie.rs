@@ -0,0 +1,328 @@
+use super::UriParams;
+use crate::Request;
+use rama_core::{context::Extensions, Context};
+use std::collections::HashMap;
+
+#[derive(Debug, Clone)]
+/// A set of path patterns, using the same syntax as the [`PathMatcher`],
+/// compiled into a trie of their segments, each pattern mapped to a value.
+///
+/// Unlike the [`PathMatcher`], a glob can be named (e.g. `/files/*path`),
+/// in which case the remainder of the path (without its leading slash)
+/// is captured as a parameter as well.
+///
+/// Finding the pattern matching a path costs a lookup per segment of the path,
+/// rather than a scan over all patterns as a list of [`PathMatcher`]s would.
+/// In case multiple patterns match, literal segments are preferred over
+/// parameters, and parameters over globs.
+///
+/// As a matcher it matches any of its patterns,
+/// inserting the [`UriParams`] of the matched pattern.
+///
+/// [`PathMatcher`]: super::PathMatcher
+pub struct PathTrie<T> {
+    root: Node<T>,
+    len: usize,
+}
+
+#[derive(Debug, Clone)]
+struct Node<T> {
+    literals: HashMap<Box<str>, Node<T>>,
+    param: Option<Box<Node<T>>>,
+    /// the value of the pattern ending at this node
+    value: Option<Leaf<T>>,
+    /// the value of the pattern ending with a glob at this node
+    glob: Option<Leaf<T>>,
+}
+
+#[derive(Debug, Clone)]
+struct Leaf<T> {
+    /// names of the params, in order of the param segments
+    params: Vec<Box<str>>,
+    /// name of the glob param, if any
+    glob: Option<Box<str>>,
+    value: T,
+}
+
+impl<T> Default for Node<T> {
+    fn default() -> Self {
+        Self {
+            literals: HashMap::new(),
+            param: None,
+            value: None,
+            glob: None,
+        }
+    }
+}
+
+impl<T> Default for PathTrie<T> {
+    fn default() -> Self {
+        Self {
+            root: Node::default(),
+            len: 0,
+        }
+    }
+}
+
+impl<T> PathTrie<T> {
+    /// Create a new empty [`PathTrie`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Insert a path pattern, returning the previous value of the pattern, if any.
+    ///
+    /// Patterns only differing in the names of their params are considered equal.
+    pub fn insert(&mut self, pattern: impl AsRef<str>, value: T) -> Option<T> {
+        let pattern = pattern.as_ref().trim().trim_matches('/');
+        let mut node = &mut self.root;
+        let mut params = Vec::new();
+        let mut glob = None;
+
+        let segments: Vec<_> = segments(pattern).collect();
+        for (index, segment) in segments.iter().enumerate() {
+            if let Some(name) = segment.strip_prefix(':') {
+                params.push(name.to_lowercase().into_boxed_str());
+                node = node.param.get_or_insert_with(Default::default);
+            } else if segment.starts_with('*') && index == segments.len() - 1 {
+                glob = Some(segment.trim_start_matches('*').to_lowercase());
+            } else {
+                node = node
+                    .literals
+                    .entry(segment.to_lowercase().into_boxed_str())
+                    .or_default();
+            }
+        }
+
+        let slot = if glob.is_some() {
+            &mut node.glob
+        } else {
+            &mut node.value
+        };
+        let glob = glob
+            .filter(|name| !name.is_empty())
+            .map(String::into_boxed_str);
+        let previous = slot.replace(Leaf {
+            params,
+            glob,
+            value,
+        });
+        if previous.is_none() {
+            self.len += 1;
+        }
+        previous.map(|leaf| leaf.value)
+    }
+
+    /// Find the value of the pattern matching the given path,
+    /// together with the [`UriParams`] captured for it.
+    pub fn find(&self, path: &str) -> Option<(&T, UriParams)> {
+        let path = path.trim().trim_matches('/');
+        let segments: Vec<_> = segments(path).collect();
+        let mut captured = Vec::new();
+        let (leaf, glob) = self.root.find(&segments, &mut captured)?;
+
+        let mut params = UriParams::default();
+        for (name, segment) in leaf.params.iter().zip(captured) {
+            let segment = percent_encoding::percent_decode(segment.as_bytes())
+                .decode_utf8()
+                .map(|s| s.to_string())
+                .unwrap_or_else(|_| segment.to_owned());
+            params.insert(name.to_string(), segment);
+        }
+        for segment in glob {
+            params.append_glob(segment);
+        }
+        if let Some(name) = leaf.glob.as_deref() {
+            params.insert_glob_param(name);
+        }
+        Some((&leaf.value, params))
+    }
+
+    /// Get a mutable reference to the value of the given path pattern,
+    /// as inserted by [`PathTrie::insert`].
+    pub fn get_mut(&mut self, pattern: impl AsRef<str>) -> Option<&mut T> {
+        let pattern = pattern.as_ref().trim().trim_matches('/');
+        let segments: Vec<_> = segments(pattern).collect();
+        let mut node = &mut self.root;
+        for (index, segment) in segments.iter().enumerate() {
+            if segment.starts_with(':') {
+                node = node.param.as_deref_mut()?;
+            } else if segment.starts_with('*') && index == segments.len() - 1 {
+                return node.glob.as_mut().map(|leaf| &mut leaf.value);
+            } else {
+                node = node.literals.get_mut(segment.to_lowercase().as_str())?;
+            }
+        }
+        node.value.as_mut().map(|leaf| &mut leaf.value)
+    }
+
+    /// Returns the amount of patterns in this trie.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` in case no patterns were inserted in this trie.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+/// The segments of a trimmed path, where the root path has no segments.
+fn segments(path: &str) -> impl Iterator<Item = &str> {
+    path.split('/').filter(move |_| !path.is_empty())
+}
+
+impl<T> Node<T> {
+    fn find<'p>(
+        &self,
+        segments: &'p [&'p str],
+        captured: &mut Vec<&'p str>,
+    ) -> Option<(&Leaf<T>, &'p [&'p str])> {
+        let Some((segment, rest)) = segments.split_first() else {
+            return self.value.as_ref().map(|leaf| (leaf, &[][..]));
+        };
+
+        if let Some(child) = self.literals.get(segment.to_lowercase().as_str()) {
+            if let Some(found) = child.find(rest, captured) {
+                return Some(found);
+            }
+        }
+
+        if let Some(child) = self.param.as_deref() {
+            if !segment.is_empty() {
+                captured.push(segment);
+                if let Some(found) = child.find(rest, captured) {
+                    return Some(found);
+                }
+                captured.pop();
+            }
+        }
+
+        self.glob.as_ref().map(|leaf| (leaf, segments))
+    }
+}
+
+impl<P: AsRef<str>, T> FromIterator<(P, T)> for PathTrie<T> {
+    fn from_iter<I: IntoIterator<Item = (P, T)>>(iter: I) -> Self {
+        let mut trie = Self::new();
+        for (pattern, value) in iter {
+            trie.insert(pattern, value);
+        }
+        trie
+    }
+}
+
+impl<State, Body, T> rama_core::matcher::Matcher<State, Request<Body>> for PathTrie<T>
+where
+    T: Send + Sync + 'static,
+{
+    fn matches(
+        &self,
+        ext: Option<&mut Extensions>,
+        _ctx: &Context<State>,
+        req: &Request<Body>,
+    ) -> bool {
+        match self.find(req.uri().path()) {
+            None => false,
+            Some((_, params)) => {
+                if let Some(ext) = ext {
+                    ext.insert(params);
+                }
+                true
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matcher::PathMatcher;
+
+    #[test]
+    fn test_path_trie_find() {
+        let mut trie = PathTrie::new();
+        trie.insert("/", "root");
+        trie.insert("/users", "users");
+        trie.insert("/users/:id", "user");
+        trie.insert("/users/me", "me");
+        trie.insert("/users/:id/posts/:post", "post");
+        trie.insert("/static/*", "static");
+        trie.insert("/*", "fallback");
+        assert_eq!(trie.len(), 7);
+
+        let (value, params) = trie.find("/").unwrap();
+        assert_eq!(*value, "root");
+        assert!(params.get("id").is_none());
+
+        assert_eq!(*trie.find("/USERS/").unwrap().0, "users");
+        assert_eq!(*trie.find("/users/me").unwrap().0, "me");
+
+        let (value, params) = trie.find("/users/42/posts/hello%20world").unwrap();
+        assert_eq!(*value, "post");
+        assert_eq!(params.get("id"), Some("42"));
+        assert_eq!(params.get("post"), Some("hello world"));
+
+        let (value, params) = trie.find("/static/css/main.css").unwrap();
+        assert_eq!(*value, "static");
+        assert_eq!(params.glob(), Some("/css/main.css"));
+
+        // backtrack from the literal and param branches to the root glob
+        let (value, params) = trie.find("/users/42/comments").unwrap();
+        assert_eq!(*value, "fallback");
+        assert_eq!(params.glob(), Some("/users/42/comments"));
+    }
+
+    #[test]
+    fn test_path_trie_insert_replace() {
+        let mut trie = PathTrie::new();
+        assert_eq!(trie.insert("/a/:x", 1), None);
+        assert_eq!(trie.insert("/a/:y", 2), Some(1));
+        assert_eq!(trie.len(), 1);
+        assert_eq!(trie.find("/a/b").unwrap().1.get("y"), Some("b"));
+        assert!(trie.find("/a").is_none());
+        assert!(trie.find("/a/b/c").is_none());
+
+        *trie.get_mut("/a/:z").unwrap() = 3;
+        assert_eq!(*trie.find("/a/b").unwrap().0, 3);
+        assert!(trie.get_mut("/a").is_none());
+    }
+
+    #[test]
+    fn test_path_trie_named_glob() {
+        let mut trie = PathTrie::new();
+        trie.insert("/users/:id/files/*path", ());
+        let (_, params) = trie.find("/users/1/files/a/b.txt").unwrap();
+        assert_eq!(params.get("id"), Some("1"));
+        assert_eq!(params.get("path"), Some("a/b.txt"));
+        assert_eq!(params.glob(), Some("/a/b.txt"));
+    }
+
+    #[test]
+    fn test_path_trie_matches_path_matcher() {
+        for (pattern, path) in [
+            ("/foo/:bar", "/foo/baz"),
+            ("/foo/:bar/*", "/foo/baz/a/b"),
+            ("/foo/*", "/foo"),
+            ("/foo/:bar", "/foo/"),
+            ("/", ""),
+            ("/Foo", "/foo"),
+        ] {
+            let mut trie = PathTrie::new();
+            trie.insert(pattern, ());
+            let expected = PathMatcher::new(pattern).matches_path(path).map(|params| {
+                (
+                    params.get("bar").map(ToOwned::to_owned),
+                    params.glob().map(ToOwned::to_owned),
+                )
+            });
+            let found = trie.find(path).map(|(_, params)| {
+                (
+                    params.get("bar").map(ToOwned::to_owned),
+                    params.glob().map(ToOwned::to_owned),
+                )
+            });
+            assert_eq!(found, expected, "{pattern} <> {path}");
+        }
+    }
+}
@@ -0,0 +1,554 @@
+//! Weighted canary (A/B) routing of requests between upstream variants.
+//!
+//! The [`Canary`] service routes each request to one of its [`Variant`]s, e.g. the
+//! stable and canary release of an upstream, such that a new release can be rolled
+//! out to a small share of the clients first:
+//!
+//! 1. requests matching a header or cookie predicate of a variant are routed to it,
+//!    e.g. to allow testers to opt in to the canary release using a header;
+//! 2. requests of a client that was assigned a variant before are routed to the same
+//!    variant, in case a [sticky cookie] is used to store the assignment,
+//!    for as long as the weight of that variant is not zero;
+//! 3. all other requests are assigned a variant according to the variant weights,
+//!    by hashing the client ip, such that a client is assigned the same variant
+//!    on each request, for as long as the weights remain the same.
+//!
+//! The client ip is taken from the [`Forwarded`] information if available, falling back
+//! to the peer address of the [`SocketInfo`]. Requests without any client ip are
+//! assigned a random variant. The name of the selected variant is inserted in the
+//! response extensions as [`SelectedVariant`].
+//!
+//! [sticky cookie]: Canary::with_sticky_cookie
+//! [`Forwarded`]: rama_net::forwarded::Forwarded
+//! [`SocketInfo`]: rama_net::stream::SocketInfo
+//!
+//! # Example
+//!
+//! ```
+//! use rama_http::service::canary::{Canary, SelectedVariant, Variant};
+//! use rama_http::{Body, HeaderName, HeaderValue, Request, Response};
+//! use rama_core::service::service_fn;
+//! use rama_core::{Context, Service};
+//! use std::convert::Infallible;
+//!
+//! # #[tokio::main]
+//! # async fn main() {
+//! let upstream = |name: &'static str| {
+//!     service_fn(move |_: Request| async move {
+//!         Ok::<_, Infallible>(Response::new(Body::from(name)))
+//!     })
+//!     .boxed()
+//! };
+//!
+//! let service = Canary::try_new([
+//!     Variant::new("stable", 95, upstream("stable")),
+//!     Variant::new("canary", 5, upstream("canary")).with_header(
+//!         HeaderName::from_static("x-canary"),
+//!         HeaderValue::from_static("1"),
+//!     ),
+//! ])
+//! .unwrap();
+//!
+//! let req = Request::builder()
+//!     .header("x-canary", "1")
+//!     .body(Body::empty())
+//!     .unwrap();
+//! let resp = service.serve(Context::default(), req).await.unwrap();
+//! assert_eq!(resp.extensions().get::<SelectedVariant>().unwrap().name(), "canary");
+//! # }
+//! ```
+
+use crate::headers::{Cookie, HeaderMapExt};
+use crate::utils::{client_ip, SameSite, SetCookie};
+use crate::{header, HeaderName, HeaderValue, Request, Response};
+use rama_core::{error::OpaqueError, Context, Service};
+use rama_utils::rng::{HasherRng, Rng};
+use std::{
+    collections::hash_map::DefaultHasher,
+    fmt,
+    hash::{Hash, Hasher},
+    sync::Arc,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// The name of the [`Variant`] which served the request,
+/// inserted in the response extensions by the [`Canary`] service.
+pub struct SelectedVariant(String);
+
+impl SelectedVariant {
+    /// The name of the selected [`Variant`].
+    pub fn name(&self) -> &str {
+        &self.0
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Predicate {
+    Header(HeaderName, HeaderValue),
+    Cookie(String, String),
+}
+
+impl Predicate {
+    fn matches<Body>(&self, req: &Request<Body>) -> bool {
+        match self {
+            Predicate::Header(name, value) => {
+                req.headers().get_all(name).iter().any(|v| v == value)
+            }
+            Predicate::Cookie(name, value) => req
+                .headers()
+                .typed_get::<Cookie>()
+                .is_some_and(|cookie| cookie.get(name) == Some(value.as_str())),
+        }
+    }
+}
+
+/// An upstream variant of a [`Canary`] service.
+pub struct Variant<S> {
+    name: String,
+    weight: u32,
+    service: S,
+    predicates: Vec<Predicate>,
+}
+
+impl<S> Variant<S> {
+    /// Create a new [`Variant`] with the given (unique) name and weight.
+    ///
+    /// The share of clients assigned to this variant is its weight relative to the
+    /// total weight of all variants. Variants with a zero weight are only routed to
+    /// for requests that match one of their predicates.
+    pub fn new(name: impl Into<String>, weight: u32, service: S) -> Self {
+        Self {
+            name: name.into(),
+            weight,
+            service,
+            predicates: Vec::new(),
+        }
+    }
+
+    /// Route requests with the given header value to this variant, regardless of the weights.
+    pub fn with_header(mut self, name: HeaderName, value: HeaderValue) -> Self {
+        self.predicates.push(Predicate::Header(name, value));
+        self
+    }
+
+    /// Route requests with the given header value to this variant, regardless of the weights.
+    pub fn set_header(&mut self, name: HeaderName, value: HeaderValue) -> &mut Self {
+        self.predicates.push(Predicate::Header(name, value));
+        self
+    }
+
+    /// Route requests with the given cookie value to this variant, regardless of the weights.
+    pub fn with_cookie(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.predicates
+            .push(Predicate::Cookie(name.into(), value.into()));
+        self
+    }
+
+    /// Route requests with the given cookie value to this variant, regardless of the weights.
+    pub fn set_cookie(&mut self, name: impl Into<String>, value: impl Into<String>) -> &mut Self {
+        self.predicates
+            .push(Predicate::Cookie(name.into(), value.into()));
+        self
+    }
+
+    /// The name of this [`Variant`].
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The weight of this [`Variant`].
+    pub fn weight(&self) -> u32 {
+        self.weight
+    }
+
+    /// Reference to the [`Service`] of this [`Variant`].
+    pub fn service(&self) -> &S {
+        &self.service
+    }
+}
+
+impl<S: fmt::Debug> fmt::Debug for Variant<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Variant")
+            .field("name", &self.name)
+            .field("weight", &self.weight)
+            .field("service", &self.service)
+            .field("predicates", &self.predicates)
+            .finish()
+    }
+}
+
+#[derive(Debug, Clone)]
+struct StickyCookie {
+    name: String,
+    same_site: SameSite,
+    secure: bool,
+}
+
+/// A [`Service`] routing each request to one of its [`Variant`]s,
+/// by predicate or weight, with a stable assignment per client.
+///
+/// See [the module docs](self) for more information.
+pub struct Canary<S> {
+    variants: Arc<[Variant<S>]>,
+    total_weight: u64,
+    sticky_cookie: Option<Arc<StickyCookie>>,
+}
+
+impl<S> Canary<S> {
+    /// Create a new [`Canary`] service from the given [`Variant`]s.
+    ///
+    /// Fails in case no variants are given, the total weight of the variants is zero,
+    /// or the variant names are not unique or contain other characters
+    /// than ASCII alphanumerics, `-` and `_`.
+    pub fn try_new(variants: impl IntoIterator<Item = Variant<S>>) -> Result<Self, OpaqueError> {
+        let variants: Arc<[Variant<S>]> = variants.into_iter().collect();
+        for (index, variant) in variants.iter().enumerate() {
+            if variant.name.is_empty()
+                || !variant
+                    .name
+                    .bytes()
+                    .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_')
+            {
+                return Err(OpaqueError::from_display(format!(
+                    "invalid canary variant name: {:?}",
+                    variant.name
+                )));
+            }
+            if variants[..index].iter().any(|v| v.name == variant.name) {
+                return Err(OpaqueError::from_display(format!(
+                    "duplicate canary variant name: {}",
+                    variant.name
+                )));
+            }
+        }
+        let total_weight = variants.iter().map(|v| v.weight as u64).sum();
+        if total_weight == 0 {
+            return Err(OpaqueError::from_display(
+                "canary requires at least one variant with a non-zero weight",
+            ));
+        }
+        Ok(Self {
+            variants,
+            total_weight,
+            sticky_cookie: None,
+        })
+    }
+
+    /// Store the assigned variant in a cookie with the given name,
+    /// such that the assignment of a client remains the same, even as the weights change.
+    ///
+    /// Not used by default.
+    pub fn with_sticky_cookie(mut self, name: impl Into<String>) -> Self {
+        self.set_sticky_cookie(name);
+        self
+    }
+
+    /// Store the assigned variant in a cookie with the given name,
+    /// such that the assignment of a client remains the same, even as the weights change.
+    ///
+    /// Not used by default.
+    pub fn set_sticky_cookie(&mut self, name: impl Into<String>) -> &mut Self {
+        let (same_site, secure) = self
+            .sticky_cookie
+            .as_ref()
+            .map_or((SameSite::Lax, false), |cookie| {
+                (cookie.same_site, cookie.secure)
+            });
+        self.sticky_cookie = Some(Arc::new(StickyCookie {
+            name: name.into(),
+            same_site,
+            secure,
+        }));
+        self
+    }
+
+    /// Set the [`SameSite`] attribute of the sticky cookie.
+    ///
+    /// Defaults to [`SameSite::Lax`].
+    pub fn with_sticky_cookie_same_site(mut self, same_site: SameSite) -> Self {
+        self.set_sticky_cookie_same_site(same_site);
+        self
+    }
+
+    /// Set the [`SameSite`] attribute of the sticky cookie.
+    ///
+    /// Defaults to [`SameSite::Lax`].
+    pub fn set_sticky_cookie_same_site(&mut self, same_site: SameSite) -> &mut Self {
+        if let Some(cookie) = self.sticky_cookie.as_mut() {
+            Arc::make_mut(cookie).same_site = same_site;
+        }
+        self
+    }
+
+    /// Only send the sticky cookie over secure (https) connections.
+    pub fn with_sticky_cookie_secure(mut self, secure: bool) -> Self {
+        self.set_sticky_cookie_secure(secure);
+        self
+    }
+
+    /// Only send the sticky cookie over secure (https) connections.
+    pub fn set_sticky_cookie_secure(&mut self, secure: bool) -> &mut Self {
+        if let Some(cookie) = self.sticky_cookie.as_mut() {
+            Arc::make_mut(cookie).secure = secure;
+        }
+        self
+    }
+
+    /// The [`Variant`]s of this [`Canary`] service.
+    pub fn variants(&self) -> &[Variant<S>] {
+        &self.variants
+    }
+
+    /// Select the index of the variant to route the request to,
+    /// and whether or not the client has to be (re)assigned to it using the sticky cookie.
+    fn select<State, Body>(&self, ctx: &Context<State>, req: &Request<Body>) -> (usize, bool) {
+        if let Some(index) = self
+            .variants
+            .iter()
+            .position(|v| v.predicates.iter().any(|p| p.matches(req)))
+        {
+            return (index, false);
+        }
+
+        if let Some(sticky_cookie) = self.sticky_cookie.as_deref() {
+            if let Some(index) = req.headers().typed_get::<Cookie>().and_then(|cookie| {
+                let name = cookie.get(&sticky_cookie.name)?;
+                self.variants
+                    .iter()
+                    .position(|v| v.name == name && v.weight > 0)
+            }) {
+                return (index, false);
+            }
+        }
+
+        let point = match client_ip(ctx, req) {
+            Some(ip) => {
+                // hashed using fixed keys, such that it is stable across restarts
+                let mut hasher = DefaultHasher::new();
+                ip.hash(&mut hasher);
+                hasher.finish() % self.total_weight
+            }
+            None => HasherRng::new().next_u64() % self.total_weight,
+        };
+        let mut cumulative = 0;
+        let index = self
+            .variants
+            .iter()
+            .position(|v| {
+                cumulative += v.weight as u64;
+                point < cumulative
+            })
+            .unwrap_or_default();
+        (index, self.sticky_cookie.is_some())
+    }
+
+    fn cookie_header_value(sticky_cookie: &StickyCookie, name: &str) -> Option<HeaderValue> {
+        SetCookie {
+            name: &sticky_cookie.name,
+            value: name,
+            max_age: None,
+            http_only: true,
+            same_site: sticky_cookie.same_site,
+            secure: sticky_cookie.secure,
+        }
+        .header_value()
+    }
+}
+
+impl<S> Clone for Canary<S> {
+    fn clone(&self) -> Self {
+        Self {
+            variants: self.variants.clone(),
+            total_weight: self.total_weight,
+            sticky_cookie: self.sticky_cookie.clone(),
+        }
+    }
+}
+
+impl<S: fmt::Debug> fmt::Debug for Canary<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Canary")
+            .field("variants", &self.variants)
+            .field("sticky_cookie", &self.sticky_cookie)
+            .finish()
+    }
+}
+
+impl<State, S, ReqBody, ResBody> Service<State, Request<ReqBody>> for Canary<S>
+where
+    State: Clone + Send + Sync + 'static,
+    S: Service<State, Request<ReqBody>, Response = Response<ResBody>>,
+    ReqBody: Send + 'static,
+    ResBody: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+
+    async fn serve(
+        &self,
+        ctx: Context<State>,
+        req: Request<ReqBody>,
+    ) -> Result<Self::Response, Self::Error> {
+        let (index, assign) = self.select(&ctx, &req);
+        let variant = &self.variants[index];
+        tracing::trace!(variant = %variant.name, "canary: route request to variant");
+
+        let mut res = variant.service.serve(ctx, req).await?;
+
+        res.extensions_mut()
+            .insert(SelectedVariant(variant.name.clone()));
+        if assign {
+            if let Some(cookie) = self
+                .sticky_cookie
+                .as_deref()
+                .and_then(|sticky_cookie| Self::cookie_header_value(sticky_cookie, &variant.name))
+            {
+                res.headers_mut().append(header::SET_COOKIE, cookie);
+            }
+        }
+        Ok(res)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Body;
+    use rama_core::service::{service_fn, BoxService};
+    use rama_net::stream::SocketInfo;
+    use std::{
+        convert::Infallible,
+        net::{Ipv4Addr, SocketAddr},
+    };
+
+    type Upstream = BoxService<(), Request, Response, Infallible>;
+
+    fn upstream(name: &'static str) -> Upstream {
+        service_fn(
+            move |_: Request| async move { Ok::<_, Infallible>(Response::new(Body::from(name))) },
+        )
+        .boxed()
+    }
+
+    fn canary(weights: [u32; 2]) -> Canary<Upstream> {
+        Canary::try_new([
+            Variant::new("stable", weights[0], upstream("stable")),
+            Variant::new("canary", weights[1], upstream("canary"))
+                .with_header(
+                    HeaderName::from_static("x-canary"),
+                    HeaderValue::from_static("1"),
+                )
+                .with_cookie("beta", "yes"),
+        ])
+        .unwrap()
+    }
+
+    async fn get(
+        svc: &Canary<Upstream>,
+        ip: Option<Ipv4Addr>,
+        header: Option<(&'static str, &'static str)>,
+    ) -> (String, Option<String>) {
+        let mut ctx = Context::default();
+        if let Some(ip) = ip {
+            ctx.insert(SocketInfo::new(None, SocketAddr::from((ip, 1234))));
+        }
+        let mut req = Request::builder().uri("/");
+        if let Some((name, value)) = header {
+            req = req.header(name, value);
+        }
+        let resp = svc
+            .serve(ctx, req.body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let cookie = resp.headers().get(header::SET_COOKIE).map(|value| {
+            let value = value.to_str().unwrap();
+            value[..value.find(';').unwrap()].to_owned()
+        });
+        let name = resp
+            .extensions()
+            .get::<SelectedVariant>()
+            .unwrap()
+            .name()
+            .to_owned();
+        (name, cookie)
+    }
+
+    #[test]
+    fn test_canary_try_new_invalid() {
+        assert!(Canary::<Upstream>::try_new([]).is_err());
+        assert!(Canary::try_new([Variant::new("a", 0, upstream("a"))]).is_err());
+        assert!(Canary::try_new([Variant::new("a b", 1, upstream("a"))]).is_err());
+        assert!(Canary::try_new([
+            Variant::new("a", 1, upstream("a")),
+            Variant::new("a", 1, upstream("a")),
+        ])
+        .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_canary_weights() {
+        let svc = canary([1, 0]);
+        for i in 0..20 {
+            let (name, _) = get(&svc, Some(Ipv4Addr::new(10, 0, 0, i)), None).await;
+            assert_eq!(name, "stable");
+            let (name, _) = get(&svc, None, None).await;
+            assert_eq!(name, "stable");
+        }
+
+        let svc = canary([1, 1]);
+        let mut canary_count = 0;
+        for i in 0..100 {
+            let ip = Some(Ipv4Addr::new(10, 0, 1, i));
+            let (first, cookie) = get(&svc, ip, None).await;
+            assert!(cookie.is_none());
+            // stable assignment per client
+            for _ in 0..3 {
+                assert_eq!(get(&svc, ip, None).await.0, first);
+            }
+            if first == "canary" {
+                canary_count += 1;
+            }
+        }
+        assert!((20..=80).contains(&canary_count), "{canary_count}");
+    }
+
+    #[tokio::test]
+    async fn test_canary_predicates() {
+        let svc = canary([1, 0]);
+        let ip = Some(Ipv4Addr::new(10, 0, 0, 1));
+        assert_eq!(get(&svc, ip, Some(("x-canary", "1"))).await.0, "canary");
+        assert_eq!(get(&svc, ip, Some(("x-canary", "0"))).await.0, "stable");
+        assert_eq!(
+            get(&svc, ip, Some(("cookie", "foo=bar; beta=yes"))).await.0,
+            "canary"
+        );
+        assert_eq!(get(&svc, ip, Some(("cookie", "beta=no"))).await.0, "stable");
+    }
+
+    #[tokio::test]
+    async fn test_canary_sticky_cookie() {
+        let svc = canary([0, 1]).with_sticky_cookie("variant");
+
+        let (name, cookie) = get(&svc, None, None).await;
+        assert_eq!(name, "canary");
+        assert_eq!(cookie.as_deref(), Some("variant=canary"));
+
+        // the assignment remains, even as the weights change
+        let svc = canary([1, 1]).with_sticky_cookie("variant");
+        for cookie in ["variant=stable", "variant=canary"] {
+            let (name, set_cookie) = get(&svc, None, Some(("cookie", cookie))).await;
+            assert_eq!(cookie, format!("variant={name}"));
+            assert!(set_cookie.is_none());
+        }
+
+        // unless the variant is rolled back or no longer exists
+        let svc = canary([1, 0]).with_sticky_cookie("variant");
+        for cookie in ["variant=canary", "variant=unknown"] {
+            assert_eq!(
+                get(&svc, None, Some(("cookie", cookie))).await,
+                ("stable".to_owned(), Some("variant=stable".to_owned()))
+            );
+        }
+    }
+}
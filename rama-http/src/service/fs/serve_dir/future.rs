@@ -8,7 +8,17 @@ use crate::{
 };
 use bytes::Bytes;
 use rama_core::{error::BoxError, Context, Service};
-use std::{convert::Infallible, io};
+use std::{
+    collections::VecDeque,
+    convert::Infallible,
+    io::{self, SeekFrom},
+    ops::RangeInclusive,
+};
+use tokio::{
+    fs::File,
+    io::{AsyncReadExt, AsyncSeekExt},
+};
+use uuid::Uuid;
 
 pub(super) async fn consume_open_file_result<State, ReqBody, ResBody, F>(
     open_file_result: Result<OpenFileOutput, std::io::Error>,
@@ -41,7 +51,22 @@ where
             Ok(response_with_status(StatusCode::PRECONDITION_FAILED))
         }
 
-        Ok(OpenFileOutput::NotModified) => Ok(response_with_status(StatusCode::NOT_MODIFIED)),
+        Ok(OpenFileOutput::NotModified {
+            etag,
+            last_modified,
+        }) => {
+            let mut res = response_with_status(StatusCode::NOT_MODIFIED);
+            if let Some(etag) = etag {
+                res.headers_mut()
+                    .insert(header::ETAG, etag.to_header_value());
+            }
+            if let Some(last_modified) = last_modified {
+                if let Ok(value) = HeaderValue::from_str(&last_modified.0.to_string()) {
+                    res.headers_mut().insert(header::LAST_MODIFIED, value);
+                }
+            }
+            Ok(res)
+        }
 
         Err(err) => {
             #[cfg(unix)]
@@ -116,7 +141,7 @@ fn build_response(output: FileOpened) -> Response {
     };
 
     let mut builder = Response::builder()
-        .header(header::CONTENT_TYPE, output.mime_header_value)
+        .header(header::CONTENT_TYPE, output.mime_header_value.clone())
         .header(header::ACCEPT_RANGES, "bytes");
 
     if let Some(encoding) = output
@@ -130,42 +155,55 @@ fn build_response(output: FileOpened) -> Response {
         builder = builder.header(header::LAST_MODIFIED, last_modified.0.to_string());
     }
 
+    if let Some(etag) = output.etag {
+        builder = builder.header(header::ETAG, etag.to_header_value());
+    }
+
     match output.maybe_range {
         Some(Ok(ranges)) => {
-            if let Some(range) = ranges.first() {
-                if ranges.len() > 1 {
-                    builder
-                        .header(header::CONTENT_RANGE, format!("bytes */{}", size))
-                        .status(StatusCode::RANGE_NOT_SATISFIABLE)
-                        .body(body_from_bytes(Bytes::from(
-                            "Cannot serve multipart range requests",
-                        )))
-                        .unwrap()
-                } else {
-                    let body = if let Some(file) = maybe_file {
-                        let range_size = range.end() - range.start() + 1;
-                        Body::new(
-                            AsyncReadBody::with_capacity_limited(
-                                file,
-                                output.chunk_size,
-                                range_size,
-                            )
+            if ranges.len() > MAX_RANGES {
+                builder
+                    .header(header::CONTENT_RANGE, format!("bytes */{}", size))
+                    .status(StatusCode::RANGE_NOT_SATISFIABLE)
+                    .body(body_from_bytes(Bytes::from("Too many ranges requested")))
+                    .unwrap()
+            } else if ranges.len() > 1 {
+                let byte_ranges =
+                    ByteRanges::new(ranges, size, &output.mime_header_value, output.chunk_size);
+                let content_type = byte_ranges.content_type();
+                let mut response = builder
+                    .header(header::CONTENT_LENGTH, byte_ranges.content_length())
+                    .status(StatusCode::PARTIAL_CONTENT)
+                    .body(match maybe_file {
+                        Some(file) => byte_ranges.into_body(file),
+                        None => empty_body(),
+                    })
+                    .unwrap();
+                // the content type of the file itself is part of each body part
+                response
+                    .headers_mut()
+                    .insert(header::CONTENT_TYPE, content_type);
+                response
+            } else if let Some(range) = ranges.first() {
+                let body = if let Some(file) = maybe_file {
+                    let range_size = range.end() - range.start() + 1;
+                    Body::new(
+                        AsyncReadBody::with_capacity_limited(file, output.chunk_size, range_size)
                             .boxed(),
-                        )
-                    } else {
-                        empty_body()
-                    };
-
-                    builder
-                        .header(
-                            header::CONTENT_RANGE,
-                            format!("bytes {}-{}/{}", range.start(), range.end(), size),
-                        )
-                        .header(header::CONTENT_LENGTH, range.end() - range.start() + 1)
-                        .status(StatusCode::PARTIAL_CONTENT)
-                        .body(body)
-                        .unwrap()
-                }
+                    )
+                } else {
+                    empty_body()
+                };
+
+                builder
+                    .header(
+                        header::CONTENT_RANGE,
+                        format!("bytes {}-{}/{}", range.start(), range.end(), size),
+                    )
+                    .header(header::CONTENT_LENGTH, range.end() - range.start() + 1)
+                    .status(StatusCode::PARTIAL_CONTENT)
+                    .body(body)
+                    .unwrap()
             } else {
                 builder
                     .header(header::CONTENT_RANGE, format!("bytes */{}", size))
@@ -199,6 +237,116 @@ fn build_response(output: FileOpened) -> Response {
     }
 }
 
+/// The maximum amount of ranges served for a single (multipart) range request,
+/// such that a client cannot make the server do excessive work for a single request.
+const MAX_RANGES: usize = 64;
+
+/// A `multipart/byteranges` response body, serving multiple ranges of a file.
+struct ByteRanges {
+    boundary: String,
+    /// The ranges with the headers of their part, in the order they were requested.
+    parts: VecDeque<(Bytes, RangeInclusive<u64>)>,
+    closing: Bytes,
+    chunk_size: usize,
+}
+
+impl ByteRanges {
+    fn new(
+        ranges: Vec<RangeInclusive<u64>>,
+        size: u64,
+        content_type: &HeaderValue,
+        chunk_size: usize,
+    ) -> Self {
+        let boundary = Uuid::new_v4().simple().to_string();
+        let content_type = String::from_utf8_lossy(content_type.as_bytes());
+        let parts = ranges
+            .into_iter()
+            .enumerate()
+            .map(|(index, range)| {
+                let head = format!(
+                    "{}--{boundary}\r\nContent-Type: {content_type}\r\nContent-Range: bytes {}-{}/{size}\r\n\r\n",
+                    if index == 0 { "" } else { "\r\n" },
+                    range.start(),
+                    range.end(),
+                );
+                (Bytes::from(head), range)
+            })
+            .collect();
+        let closing = Bytes::from(format!("\r\n--{boundary}--\r\n"));
+        Self {
+            boundary,
+            parts,
+            closing,
+            chunk_size,
+        }
+    }
+
+    fn content_type(&self) -> HeaderValue {
+        HeaderValue::try_from(format!("multipart/byteranges; boundary={}", self.boundary))
+            .expect("uuid boundary to be a valid header value")
+    }
+
+    fn content_length(&self) -> u64 {
+        self.parts
+            .iter()
+            .map(|(head, range)| head.len() as u64 + range.end() - range.start() + 1)
+            .sum::<u64>()
+            + self.closing.len() as u64
+    }
+
+    fn into_body(self, file: File) -> Body {
+        struct State {
+            file: File,
+            parts: VecDeque<(Bytes, RangeInclusive<u64>)>,
+            remaining: u64,
+            closing: Option<Bytes>,
+            chunk_size: usize,
+        }
+
+        let state = State {
+            file,
+            parts: self.parts,
+            remaining: 0,
+            closing: Some(self.closing),
+            chunk_size: self.chunk_size,
+        };
+        let stream = futures_lite::stream::unfold(state, |mut state| async move {
+            if state.remaining > 0 {
+                let mut buf = vec![0; state.chunk_size.min(state.remaining as usize)];
+                let result = match state.file.read(&mut buf).await {
+                    Ok(0) => Err(io::Error::from(io::ErrorKind::UnexpectedEof)),
+                    Ok(n) => {
+                        state.remaining -= n as u64;
+                        buf.truncate(n);
+                        Ok(Bytes::from(buf))
+                    }
+                    Err(err) => Err(err),
+                };
+                if result.is_err() {
+                    state.remaining = 0;
+                    state.parts.clear();
+                    state.closing = None;
+                }
+                return Some((result, state));
+            }
+
+            match state.parts.pop_front() {
+                Some((head, range)) => {
+                    if let Err(err) = state.file.seek(SeekFrom::Start(*range.start())).await {
+                        state.parts.clear();
+                        state.closing = None;
+                        return Some((Err(err), state));
+                    }
+                    state.remaining = range.end() - range.start() + 1;
+                    Some((Ok(head), state))
+                }
+                None => state.closing.take().map(|closing| (Ok(closing), state)),
+            }
+        });
+        Body::from_stream(stream)
+    }
+}
+
 fn body_from_bytes(bytes: Bytes) -> Body {
     Body::from(bytes)
 }
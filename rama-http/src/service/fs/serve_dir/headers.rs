@@ -1,7 +1,9 @@
 use crate::header::HeaderValue;
+use crate::layer::util::content_encoding::Encoding;
 use httpdate::HttpDate;
-use std::time::SystemTime;
+use std::{fs::Metadata, time::SystemTime};
 
+#[derive(Clone)]
 pub(super) struct LastModified(pub(super) HttpDate);
 
 impl From<SystemTime> for LastModified {
@@ -43,3 +45,135 @@ impl IfUnmodifiedSince {
             .map(|time| IfUnmodifiedSince(time.into()))
     }
 }
+
+/// A strong entity tag, derived from the metadata of the served file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) struct ETag(String);
+
+impl ETag {
+    /// Create an entity tag for the served file (variant),
+    /// `None` in case the modification time is not available.
+    pub(super) fn from_metadata(meta: &Metadata, encoding: Option<Encoding>) -> Option<ETag> {
+        let modified = meta
+            .modified()
+            .ok()?
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .ok()?;
+        let mut tag = format!("\"{:x}-{:x}", modified.as_nanos(), meta.len());
+        if let Some(encoding) = encoding.filter(|encoding| *encoding != Encoding::Identity) {
+            tag.push('-');
+            tag.push_str(encoding.to_str());
+        }
+        tag.push('"');
+        Some(ETag(tag))
+    }
+
+    pub(super) fn to_header_value(&self) -> HeaderValue {
+        HeaderValue::from_str(&self.0).expect("etag to be a valid header value")
+    }
+
+    /// Strong comparison, as defined in
+    /// <https://www.rfc-editor.org/rfc/rfc9110#section-8.8.3.2>.
+    fn strong_eq(&self, tag: &str) -> bool {
+        !tag.starts_with("W/") && self.0 == tag
+    }
+
+    /// Weak comparison, as defined in
+    /// <https://www.rfc-editor.org/rfc/rfc9110#section-8.8.3.2>.
+    fn weak_eq(&self, tag: &str) -> bool {
+        self.0 == tag.strip_prefix("W/").unwrap_or(tag)
+    }
+}
+
+/// A list of entity tags, as used by the `If-Match` and `If-None-Match` headers.
+enum ETagList {
+    Any,
+    Tags(Vec<String>),
+}
+
+impl ETagList {
+    fn from_header_value(value: &HeaderValue) -> Option<ETagList> {
+        let value = value.to_str().ok()?.trim();
+        if value == "*" {
+            return Some(ETagList::Any);
+        }
+        let tags: Vec<_> = value
+            .split(',')
+            .map(str::trim)
+            .filter(|tag| !tag.is_empty())
+            .map(ToOwned::to_owned)
+            .collect();
+        (!tags.is_empty()).then_some(ETagList::Tags(tags))
+    }
+}
+
+pub(super) struct IfMatch(ETagList);
+
+impl IfMatch {
+    /// Check if the supplied entity tag passes the precondition,
+    /// using the strong comparison function.
+    pub(super) fn precondition_passes(&self, etag: Option<&ETag>) -> bool {
+        match (&self.0, etag) {
+            (ETagList::Any, _) => etag.is_some(),
+            (ETagList::Tags(tags), Some(etag)) => tags.iter().any(|tag| etag.strong_eq(tag)),
+            (ETagList::Tags(_), None) => false,
+        }
+    }
+
+    /// Convert a header value into a IfMatch, invalid values are silently ignored
+    pub(super) fn from_header_value(value: &HeaderValue) -> Option<IfMatch> {
+        ETagList::from_header_value(value).map(IfMatch)
+    }
+}
+
+pub(super) struct IfNoneMatch(ETagList);
+
+impl IfNoneMatch {
+    /// Check if the supplied entity tag means the resource has been modified,
+    /// using the weak comparison function.
+    pub(super) fn is_modified(&self, etag: Option<&ETag>) -> bool {
+        match (&self.0, etag) {
+            (ETagList::Any, _) => etag.is_none(),
+            (ETagList::Tags(tags), Some(etag)) => !tags.iter().any(|tag| etag.weak_eq(tag)),
+            (ETagList::Tags(_), None) => true,
+        }
+    }
+
+    /// Convert a header value into a IfNoneMatch, invalid values are silently ignored
+    pub(super) fn from_header_value(value: &HeaderValue) -> Option<IfNoneMatch> {
+        ETagList::from_header_value(value).map(IfNoneMatch)
+    }
+}
+
+pub(super) enum IfRange {
+    ETag(String),
+    Date(HttpDate),
+}
+
+impl IfRange {
+    /// Check if the range request can be served, which is only the case
+    /// if the representation is unchanged, as defined in
+    /// <https://www.rfc-editor.org/rfc/rfc9110#section-13.1.5>.
+    pub(super) fn is_unchanged(
+        &self,
+        etag: Option<&ETag>,
+        last_modified: Option<&LastModified>,
+    ) -> bool {
+        match self {
+            IfRange::ETag(tag) => etag.is_some_and(|etag| etag.strong_eq(tag)),
+            IfRange::Date(date) => last_modified.is_some_and(|modified| modified.0 == *date),
+        }
+    }
+
+    /// Convert a header value into a IfRange, invalid values are silently ignored
+    pub(super) fn from_header_value(value: &HeaderValue) -> Option<IfRange> {
+        let value = value.to_str().ok()?.trim();
+        if value.starts_with('"') || value.starts_with("W/") {
+            Some(IfRange::ETag(value.to_owned()))
+        } else {
+            httpdate::parse_http_date(value)
+                .ok()
+                .map(|time| IfRange::Date(time.into()))
+        }
+    }
+}
@@ -27,6 +27,11 @@ const DEFAULT_CAPACITY: usize = 65536;
 ///
 /// The `Content-Type` will be guessed from the file extension.
 ///
+/// Responses contain `Last-Modified` and `ETag` headers, such that conditional
+/// requests (`If-Match`, `If-None-Match`, `If-Modified-Since`, `If-Unmodified-Since`)
+/// and (`If-Range` guarded) byte range requests are supported. Requests for
+/// multiple ranges are served as a single `multipart/byteranges` response.
+///
 /// An empty response with status `404 Not Found` will be returned if:
 ///
 /// - The file doesn't exist
@@ -1,8 +1,10 @@
 use super::{
-    headers::{IfModifiedSince, IfUnmodifiedSince, LastModified},
+    headers::{
+        ETag, IfMatch, IfModifiedSince, IfNoneMatch, IfRange, IfUnmodifiedSince, LastModified,
+    },
     ServeVariant,
 };
-use crate::layer::util::content_encoding::{Encoding, QValue};
+use crate::layer::util::{content_encoding::Encoding, quality::QValue};
 use crate::{header, HeaderValue, Method, Request, Uri};
 use http_range_header::RangeUnsatisfiableError;
 use std::{
@@ -16,10 +18,15 @@ use tokio::{fs::File, io::AsyncSeekExt};
 
 pub(super) enum OpenFileOutput {
     FileOpened(Box<FileOpened>),
-    Redirect { location: HeaderValue },
+    Redirect {
+        location: HeaderValue,
+    },
     FileNotFound,
     PreconditionFailed,
-    NotModified,
+    NotModified {
+        etag: Option<ETag>,
+        last_modified: Option<LastModified>,
+    },
 }
 
 pub(super) struct FileOpened {
@@ -29,6 +36,7 @@ pub(super) struct FileOpened {
     pub(super) maybe_encoding: Option<Encoding>,
     pub(super) maybe_range: Option<Result<Vec<RangeInclusive<u64>>, RangeUnsatisfiableError>>,
     pub(super) last_modified: Option<LastModified>,
+    pub(super) etag: Option<ETag>,
 }
 
 pub(super) enum FileRequestExtent {
@@ -44,15 +52,7 @@ pub(super) async fn open_file(
     range_header: Option<String>,
     buf_chunk_size: usize,
 ) -> io::Result<OpenFileOutput> {
-    let if_unmodified_since = req
-        .headers()
-        .get(header::IF_UNMODIFIED_SINCE)
-        .and_then(IfUnmodifiedSince::from_header_value);
-
-    let if_modified_since = req
-        .headers()
-        .get(header::IF_MODIFIED_SINCE)
-        .and_then(IfModifiedSince::from_header_value);
+    let conditions = Conditions::from_request(&req);
 
     let mime = match variant {
         ServeVariant::Directory {
@@ -87,14 +87,13 @@ pub(super) async fn open_file(
             file_metadata_with_fallback(path_to_file, negotiated_encodings).await?;
 
         let last_modified = meta.modified().ok().map(LastModified::from);
-        if let Some(output) = check_modified_headers(
-            last_modified.as_ref(),
-            if_unmodified_since,
-            if_modified_since,
-        ) {
+        let etag = ETag::from_metadata(&meta, maybe_encoding);
+        if let Some(output) = conditions.check(etag.as_ref(), last_modified.as_ref()) {
             return Ok(output);
         }
 
+        let range_header = range_header
+            .filter(|_| conditions.range_allowed(etag.as_ref(), last_modified.as_ref()));
         let maybe_range = try_parse_range(range_header.as_deref(), meta.len());
 
         Ok(OpenFileOutput::FileOpened(Box::new(FileOpened {
@@ -104,24 +103,24 @@ pub(super) async fn open_file(
             maybe_encoding,
             maybe_range,
             last_modified,
+            etag,
         })))
     } else {
         let (mut file, maybe_encoding) =
             open_file_with_fallback(path_to_file, negotiated_encodings).await?;
         let meta = file.metadata().await?;
         let last_modified = meta.modified().ok().map(LastModified::from);
-        if let Some(output) = check_modified_headers(
-            last_modified.as_ref(),
-            if_unmodified_since,
-            if_modified_since,
-        ) {
+        let etag = ETag::from_metadata(&meta, maybe_encoding);
+        if let Some(output) = conditions.check(etag.as_ref(), last_modified.as_ref()) {
             return Ok(output);
         }
 
+        let range_header = range_header
+            .filter(|_| conditions.range_allowed(etag.as_ref(), last_modified.as_ref()));
         let maybe_range = try_parse_range(range_header.as_deref(), meta.len());
         if let Some(Ok(ranges)) = maybe_range.as_ref() {
-            // if there is any other amount of ranges than 1 we'll return an
-            // unsatisfiable later as there isn't yet support for multipart ranges
+            // multiple ranges are served as a multipart body,
+            // which seeks to the start of each range itself
             if ranges.len() == 1 {
                 file.seek(SeekFrom::Start(*ranges[0].start())).await?;
             }
@@ -134,38 +133,87 @@ pub(super) async fn open_file(
             maybe_encoding,
             maybe_range,
             last_modified,
+            etag,
         })))
     }
 }
 
-fn check_modified_headers(
-    modified: Option<&LastModified>,
+/// The conditional request headers, evaluated in the order defined in
+/// <https://www.rfc-editor.org/rfc/rfc9110#section-13.2.2>.
+struct Conditions {
+    if_match: Option<IfMatch>,
     if_unmodified_since: Option<IfUnmodifiedSince>,
+    if_none_match: Option<IfNoneMatch>,
     if_modified_since: Option<IfModifiedSince>,
-) -> Option<OpenFileOutput> {
-    if let Some(since) = if_unmodified_since {
-        let precondition = modified
-            .as_ref()
-            .map(|time| since.precondition_passes(time))
-            .unwrap_or(false);
+    if_range: Option<IfRange>,
+}
 
+impl Conditions {
+    fn from_request(req: &Request) -> Self {
+        let headers = req.headers();
+        Self {
+            if_match: headers
+                .get(header::IF_MATCH)
+                .and_then(IfMatch::from_header_value),
+            if_unmodified_since: headers
+                .get(header::IF_UNMODIFIED_SINCE)
+                .and_then(IfUnmodifiedSince::from_header_value),
+            if_none_match: headers
+                .get(header::IF_NONE_MATCH)
+                .and_then(IfNoneMatch::from_header_value),
+            if_modified_since: headers
+                .get(header::IF_MODIFIED_SINCE)
+                .and_then(IfModifiedSince::from_header_value),
+            if_range: headers
+                .get(header::IF_RANGE)
+                .and_then(IfRange::from_header_value),
+        }
+    }
+
+    fn check(
+        &self,
+        etag: Option<&ETag>,
+        modified: Option<&LastModified>,
+    ) -> Option<OpenFileOutput> {
+        // If-Unmodified-Since is ignored when If-Match is present
+        let precondition = match (&self.if_match, &self.if_unmodified_since) {
+            (Some(if_match), _) => if_match.precondition_passes(etag),
+            (None, Some(since)) => modified
+                .map(|time| since.precondition_passes(time))
+                .unwrap_or(false),
+            (None, None) => true,
+        };
         if !precondition {
             return Some(OpenFileOutput::PreconditionFailed);
         }
-    }
 
-    if let Some(since) = if_modified_since {
-        let unmodified = modified
-            .as_ref()
-            .map(|time| !since.is_modified(time))
-            // no last_modified means its always modified
-            .unwrap_or(false);
+        // If-Modified-Since is ignored when If-None-Match is present
+        let unmodified = match (&self.if_none_match, &self.if_modified_since) {
+            (Some(if_none_match), _) => !if_none_match.is_modified(etag),
+            (None, Some(since)) => modified
+                .map(|time| !since.is_modified(time))
+                // no last_modified means its always modified
+                .unwrap_or(false),
+            (None, None) => false,
+        };
         if unmodified {
-            return Some(OpenFileOutput::NotModified);
+            return Some(OpenFileOutput::NotModified {
+                etag: etag.cloned(),
+                last_modified: modified.cloned(),
+            });
         }
+
+        None
     }
 
-    None
+    /// Returns `false` in case the range header has to be ignored,
+    /// as the representation was changed according to the If-Range header.
+    fn range_allowed(&self, etag: Option<&ETag>, modified: Option<&LastModified>) -> bool {
+        self.if_range
+            .as_ref()
+            .map(|if_range| if_range.is_unchanged(etag, modified))
+            .unwrap_or(true)
+    }
 }
 
 // Returns the preferred_encoding encoding and modifies the path extension
@@ -509,6 +509,73 @@ async fn read_partial_in_bounds() {
     assert_eq!(body, source);
 }
 
+#[tokio::test]
+async fn read_partial_multiple_ranges() {
+    let svc = ServeDir::new("..");
+
+    let req = Request::builder()
+        .uri("/README.md")
+        .header("Range", "bytes=0-9, 100-199")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.serve(Context::default(), req).await.unwrap();
+
+    let file_contents = std::fs::read("../README.md").unwrap();
+    assert_eq!(res.status(), StatusCode::PARTIAL_CONTENT);
+    assert_eq!(res.headers()["accept-ranges"], "bytes");
+    assert!(res.headers().get("content-range").is_none());
+
+    let content_type = res.headers()["content-type"].to_str().unwrap();
+    let boundary = content_type
+        .strip_prefix("multipart/byteranges; boundary=")
+        .unwrap()
+        .to_owned();
+    let content_length: usize = res.headers()["content-length"]
+        .to_str()
+        .unwrap()
+        .parse()
+        .unwrap();
+
+    let body = res.into_body().collect().await.unwrap().to_bytes();
+    assert_eq!(body.len(), content_length);
+
+    let mut expected = Vec::new();
+    for (index, (start, end)) in [(0, 9), (100, 199)].into_iter().enumerate() {
+        if index > 0 {
+            expected.extend_from_slice(b"\r\n");
+        }
+        expected.extend_from_slice(
+            format!(
+                "--{boundary}\r\nContent-Type: text/markdown\r\nContent-Range: bytes {start}-{end}/{}\r\n\r\n",
+                file_contents.len()
+            )
+            .as_bytes(),
+        );
+        expected.extend_from_slice(&file_contents[start..=end]);
+    }
+    expected.extend_from_slice(format!("\r\n--{boundary}--\r\n").as_bytes());
+    assert_eq!(body, Bytes::from(expected));
+}
+
+#[tokio::test]
+async fn read_partial_multiple_ranges_if_range_mismatch() {
+    let svc = ServeDir::new("..");
+
+    let req = Request::builder()
+        .uri("/README.md")
+        .header("Range", "bytes=0-9, 100-199")
+        .header("If-Range", "\"not-the-etag\"")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.serve(Context::default(), req).await.unwrap();
+
+    // the full file is served in case the validator does not match
+    let file_contents = std::fs::read("../README.md").unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let body = res.into_body().collect().await.unwrap().to_bytes();
+    assert_eq!(body, Bytes::from(file_contents));
+}
+
 #[tokio::test]
 async fn read_partial_accepts_out_of_bounds_range() {
     let svc = ServeDir::new("..");
@@ -653,6 +720,117 @@ async fn last_modified() {
     assert!(res.into_body().frame().await.is_none());
 }
 
+#[tokio::test]
+async fn etag() {
+    let svc = ServeDir::new("..");
+    let req = Request::builder()
+        .uri("/README.md")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.serve(Context::default(), req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let etag = res
+        .headers()
+        .get(header::ETAG)
+        .expect("Missing etag header!")
+        .clone();
+    assert!(etag.to_str().unwrap().starts_with('"'));
+
+    // -- If-None-Match
+
+    for if_none_match in [
+        etag.to_str().unwrap().to_owned(),
+        format!("W/{}", etag.to_str().unwrap()),
+        format!("\"foo\", {}", etag.to_str().unwrap()),
+        "*".to_owned(),
+    ] {
+        let req = Request::builder()
+            .uri("/README.md")
+            .header(header::IF_NONE_MATCH, &if_none_match)
+            // ignored in favour of If-None-Match
+            .header(header::IF_MODIFIED_SINCE, "Fri, 09 Aug 1996 14:21:40 GMT")
+            .body(Body::empty())
+            .unwrap();
+        let res = svc.serve(Context::default(), req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::NOT_MODIFIED, "{if_none_match}");
+        assert_eq!(res.headers()[header::ETAG], etag);
+        assert!(res.into_body().frame().await.is_none());
+    }
+
+    let req = Request::builder()
+        .uri("/README.md")
+        .header(header::IF_NONE_MATCH, "\"foo\"")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.serve(Context::default(), req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+
+    // -- If-Match
+
+    let req = Request::builder()
+        .uri("/README.md")
+        .header(header::IF_MATCH, &etag)
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.serve(Context::default(), req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+
+    for if_match in [
+        "\"foo\"".to_owned(),
+        // weak tags never match using the strong comparison
+        format!("W/{}", etag.to_str().unwrap()),
+    ] {
+        let req = Request::builder()
+            .uri("/README.md")
+            .header(header::IF_MATCH, &if_match)
+            .body(Body::empty())
+            .unwrap();
+        let res = svc.serve(Context::default(), req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::PRECONDITION_FAILED, "{if_match}");
+    }
+
+    // -- If-Range
+
+    let req = Request::builder()
+        .uri("/README.md")
+        .header(header::RANGE, "bytes=0-9")
+        .header(header::IF_RANGE, &etag)
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.serve(Context::default(), req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::PARTIAL_CONTENT);
+    assert_eq!(res.headers()[header::CONTENT_LENGTH], "10");
+
+    let req = Request::builder()
+        .uri("/README.md")
+        .header(header::RANGE, "bytes=0-9")
+        .header(header::IF_RANGE, "\"foo\"")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.serve(Context::default(), req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let body = res.into_body().collect().await.unwrap().to_bytes();
+    assert_eq!(body.as_ref(), include_bytes!("../../../../../README.md"));
+}
+
+#[tokio::test]
+async fn precompressed_etag() {
+    let svc = ServeDir::new("../test-files").precompressed_gzip();
+
+    let mut etags = Vec::new();
+    for accept_encoding in ["identity", "gzip"] {
+        let req = Request::builder()
+            .uri("/precompressed.txt")
+            .header("Accept-Encoding", accept_encoding)
+            .body(Body::empty())
+            .unwrap();
+        let res = svc.serve(Context::default(), req).await.unwrap();
+        etags.push(res.headers()[header::ETAG].clone());
+    }
+    assert_ne!(etags[0], etags[1]);
+    assert!(etags[1].to_str().unwrap().ends_with("-gzip\""));
+}
+
 #[tokio::test]
 async fn with_fallback_svc() {
     async fn fallback(req: Request) -> Result<Response, Infallible> {
@@ -1,5 +1,6 @@
 //! Http Services provided by Rama.
 
+pub mod canary;
 pub mod client;
 pub mod fs;
 pub mod redirect;
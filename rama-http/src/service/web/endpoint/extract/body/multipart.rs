@@ -0,0 +1,951 @@
+use crate::dep::mime::Mime;
+use crate::service::web::extract::FromRequest;
+use crate::utils::macros::define_http_rejection;
+use crate::{header, BodyDataStream, HeaderMap, HeaderName, HeaderValue, IntoResponse, Request};
+use bytes::{Bytes, BytesMut};
+use futures_lite::StreamExt;
+use rama_core::error::BoxError;
+use rama_http_types::{Body, Response, StatusCode};
+use std::fmt;
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+#[cfg(feature = "multipart-spool")]
+use std::{io, path::Path};
+
+/// Max size of the headers of a single part.
+const MAX_PART_HEADERS_SIZE: usize = 8 * 1024;
+
+#[cfg(feature = "multipart-spool")]
+/// Default size from which [`MultipartField::spool`] writes the field data to a temporary file.
+const DEFAULT_SPOOL_THRESHOLD: usize = 1024 * 1024;
+
+define_http_rejection! {
+    #[status = UNSUPPORTED_MEDIA_TYPE]
+    #[body = "Multipart requests must have `Content-Type: multipart/form-data` with a boundary"]
+    /// Rejection type for [`Multipart`]
+    /// used if the `Content-Type` header is missing, its value is not
+    /// `multipart/form-data` or it does not define a boundary.
+    pub struct InvalidMultipartContentType;
+}
+
+/// Streaming `multipart/form-data` parser,
+/// which yields the fields (parts) of the body one by one,
+/// without buffering the entire body in memory.
+///
+/// It can be used as an extractor in [`WebService`] handlers,
+/// or created directly from any [`Body`] (e.g. in an inspection layer)
+/// using [`Multipart::new`] or [`Multipart::from_request`].
+///
+/// By default no limits are applied, other than a max size of 8 KiB
+/// for the headers of each part. Use [`Multipart::max_field_size`],
+/// [`Multipart::max_fields`] and [`Multipart::max_size`] to limit
+/// the resources a single request can consume.
+///
+/// Large fields can be written to a temporary file using [`MultipartField::spool`],
+/// which requires the `multipart-spool` feature.
+///
+/// # Example
+///
+/// ```
+/// use rama_http::service::web::{WebService, extract::Multipart};
+///
+/// let service = WebService::<()>::default().post("/upload", |mut multipart: Multipart| async move {
+///     while let Some(field) = multipart.next_field().await.unwrap() {
+///         let name = field.name().unwrap_or_default().to_owned();
+///         let data = field.bytes().await.unwrap();
+///         println!("field {name}: {} bytes", data.len());
+///     }
+/// });
+/// ```
+///
+/// [`WebService`]: crate::service::web::WebService
+/// [`Multipart::from_request`]: FromRequest::from_request
+pub struct Multipart {
+    stream: BodyDataStream,
+    buffer: BytesMut,
+    /// `--boundary`
+    delimiter: Vec<u8>,
+    state: MultipartState,
+    eof: bool,
+
+    fields: usize,
+    field_size: u64,
+    total_size: u64,
+
+    max_fields: Option<usize>,
+    max_field_size: Option<u64>,
+    max_size: Option<u64>,
+    #[cfg(feature = "multipart-spool")]
+    spool_threshold: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum MultipartState {
+    /// Before the first delimiter.
+    Preamble,
+    /// Right after a delimiter, expecting either `\r\n` or `--`.
+    Delimiter,
+    /// Reading the data of a field.
+    Field,
+    /// After the last delimiter.
+    End,
+}
+
+impl fmt::Debug for Multipart {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut d = f.debug_struct("Multipart");
+        d.field("delimiter", &String::from_utf8_lossy(&self.delimiter))
+            .field("state", &self.state)
+            .field("fields", &self.fields)
+            .field("total_size", &self.total_size)
+            .field("max_fields", &self.max_fields)
+            .field("max_field_size", &self.max_field_size)
+            .field("max_size", &self.max_size);
+        #[cfg(feature = "multipart-spool")]
+        d.field("spool_threshold", &self.spool_threshold);
+        d.finish()
+    }
+}
+
+impl Multipart {
+    /// Create a new [`Multipart`] parser for the given body,
+    /// of which the parts are separated by the given boundary.
+    pub fn new(body: Body, boundary: impl AsRef<str>) -> Self {
+        let mut delimiter = b"--".to_vec();
+        delimiter.extend_from_slice(boundary.as_ref().as_bytes());
+        Self {
+            stream: body.into_data_stream(),
+            buffer: BytesMut::new(),
+            delimiter,
+            state: MultipartState::Preamble,
+            eof: false,
+            fields: 0,
+            field_size: 0,
+            total_size: 0,
+            max_fields: None,
+            max_field_size: None,
+            max_size: None,
+            #[cfg(feature = "multipart-spool")]
+            spool_threshold: DEFAULT_SPOOL_THRESHOLD,
+        }
+    }
+
+    /// Get the multipart boundary from the `Content-Type` header,
+    /// if it is a `multipart/form-data` content type with a boundary.
+    pub fn boundary(headers: &HeaderMap) -> Option<String> {
+        let mime: Mime = headers
+            .get(header::CONTENT_TYPE)?
+            .to_str()
+            .ok()?
+            .parse()
+            .ok()?;
+        if mime.type_() != mime::MULTIPART || mime.subtype() != mime::FORM_DATA {
+            return None;
+        }
+        mime.get_param(mime::BOUNDARY)
+            .map(|boundary| boundary.as_str().to_owned())
+    }
+
+    /// Limit the amount of fields, resulting in an error for any field beyond it.
+    pub fn max_fields(mut self, max: usize) -> Self {
+        self.max_fields = Some(max);
+        self
+    }
+
+    /// Limit the amount of fields, resulting in an error for any field beyond it.
+    pub fn set_max_fields(&mut self, max: usize) -> &mut Self {
+        self.max_fields = Some(max);
+        self
+    }
+
+    /// Limit the size (in bytes) of the data of a single field.
+    pub fn max_field_size(mut self, max: u64) -> Self {
+        self.max_field_size = Some(max);
+        self
+    }
+
+    /// Limit the size (in bytes) of the data of a single field.
+    pub fn set_max_field_size(&mut self, max: u64) -> &mut Self {
+        self.max_field_size = Some(max);
+        self
+    }
+
+    /// Limit the size (in bytes) of the entire multipart body.
+    pub fn max_size(mut self, max: u64) -> Self {
+        self.max_size = Some(max);
+        self
+    }
+
+    /// Limit the size (in bytes) of the entire multipart body.
+    pub fn set_max_size(&mut self, max: u64) -> &mut Self {
+        self.max_size = Some(max);
+        self
+    }
+
+    #[cfg(feature = "multipart-spool")]
+    /// Define the size (in bytes) from which [`MultipartField::spool`]
+    /// writes the field data to a temporary file instead of keeping it in memory.
+    ///
+    /// Defaults to 1 MiB.
+    pub fn spool_threshold(mut self, threshold: usize) -> Self {
+        self.spool_threshold = threshold;
+        self
+    }
+
+    #[cfg(feature = "multipart-spool")]
+    /// Define the size (in bytes) from which [`MultipartField::spool`]
+    /// writes the field data to a temporary file instead of keeping it in memory.
+    ///
+    /// Defaults to 1 MiB.
+    pub fn set_spool_threshold(&mut self, threshold: usize) -> &mut Self {
+        self.spool_threshold = threshold;
+        self
+    }
+
+    /// Returns the next field of the multipart body,
+    /// or `None` if all fields have been read.
+    ///
+    /// Data of the previous field which was not yet read is skipped.
+    pub async fn next_field(&mut self) -> Result<Option<MultipartField<'_>>, MultipartError> {
+        loop {
+            match self.state {
+                MultipartState::Preamble => {
+                    let pos = loop {
+                        if let Some(pos) = find(&self.buffer, &self.delimiter) {
+                            break pos;
+                        }
+                        // keep the tail which can still be the start of the delimiter
+                        let keep = self.delimiter.len() - 1;
+                        if self.buffer.len() > keep {
+                            let _ = self.buffer.split_to(self.buffer.len() - keep);
+                        }
+                        if !self.fill().await? {
+                            return Err(MultipartError::incomplete());
+                        }
+                    };
+                    let _ = self.buffer.split_to(pos + self.delimiter.len());
+                    self.state = MultipartState::Delimiter;
+                }
+                MultipartState::Delimiter => {
+                    while self.buffer.len() < 2 {
+                        if !self.fill().await? {
+                            return Err(MultipartError::incomplete());
+                        }
+                    }
+                    if self.buffer.starts_with(b"--") {
+                        self.state = MultipartState::End;
+                        continue;
+                    }
+                    if !self.buffer.starts_with(b"\r\n") {
+                        return Err(MultipartError::new(
+                            MultipartErrorKind::InvalidBoundary,
+                            "expected CRLF after boundary",
+                        ));
+                    }
+                    let _ = self.buffer.split_to(2);
+
+                    if self.max_fields.is_some_and(|max| self.fields >= max) {
+                        return Err(MultipartError::new(
+                            MultipartErrorKind::FieldsLimitExceeded,
+                            "too many fields",
+                        ));
+                    }
+                    let headers = self.read_part_headers().await?;
+                    self.fields += 1;
+                    self.field_size = 0;
+                    self.state = MultipartState::Field;
+                    return Ok(Some(MultipartField::new(self, headers)?));
+                }
+                MultipartState::Field => {
+                    // skip the data of the previous field which was not consumed
+                    while self.read_field_chunk().await?.is_some() {}
+                }
+                MultipartState::End => return Ok(None),
+            }
+        }
+    }
+
+    async fn read_part_headers(&mut self) -> Result<HeaderMap, MultipartError> {
+        let pos = loop {
+            if let Some(pos) = find(&self.buffer, b"\r\n\r\n") {
+                break pos;
+            }
+            if self.buffer.starts_with(b"\r\n") {
+                // part without any headers
+                let _ = self.buffer.split_to(2);
+                return Ok(HeaderMap::new());
+            }
+            if self.buffer.len() > MAX_PART_HEADERS_SIZE {
+                return Err(MultipartError::new(
+                    MultipartErrorKind::InvalidHeaders,
+                    "part headers too large",
+                ));
+            }
+            if !self.fill().await? {
+                return Err(MultipartError::incomplete());
+            }
+        };
+        if pos > MAX_PART_HEADERS_SIZE {
+            return Err(MultipartError::new(
+                MultipartErrorKind::InvalidHeaders,
+                "part headers too large",
+            ));
+        }
+
+        let raw = self.buffer.split_to(pos + 4);
+        let mut headers = HeaderMap::new();
+        for line in raw[..pos].split(|b| *b == b'\n') {
+            let line = line.strip_suffix(b"\r").unwrap_or(line);
+            if line.is_empty() {
+                continue;
+            }
+            let (name, value) = line
+                .iter()
+                .position(|b| *b == b':')
+                .map(|idx| (&line[..idx], &line[idx + 1..]))
+                .ok_or_else(|| {
+                    MultipartError::new(MultipartErrorKind::InvalidHeaders, "invalid part header")
+                })?;
+            let name = HeaderName::from_bytes(name.trim_ascii()).map_err(|err| {
+                MultipartError::with_source(MultipartErrorKind::InvalidHeaders, err)
+            })?;
+            let value = HeaderValue::from_bytes(value.trim_ascii()).map_err(|err| {
+                MultipartError::with_source(MultipartErrorKind::InvalidHeaders, err)
+            })?;
+            headers.append(name, value);
+        }
+        Ok(headers)
+    }
+
+    /// Read the next chunk of data of the current field,
+    /// returning `None` once the end of the field is reached.
+    async fn read_field_chunk(&mut self) -> Result<Option<Bytes>, MultipartError> {
+        if self.state != MultipartState::Field {
+            return Ok(None);
+        }
+
+        // the delimiter of a field is preceded by a CRLF, which is not part of the data
+        let delimiter_len = self.delimiter.len() + 2;
+        loop {
+            if let Some(pos) = find_delimiter(&self.buffer, &self.delimiter) {
+                if pos > 0 {
+                    let chunk = self.buffer.split_to(pos).freeze();
+                    self.record_field_data(chunk.len())?;
+                    return Ok(Some(chunk));
+                }
+                let _ = self.buffer.split_to(delimiter_len);
+                self.state = MultipartState::Delimiter;
+                return Ok(None);
+            }
+
+            // all but the tail, which can still be the start of the delimiter
+            if self.buffer.len() >= delimiter_len {
+                let chunk = self
+                    .buffer
+                    .split_to(self.buffer.len() - (delimiter_len - 1))
+                    .freeze();
+                self.record_field_data(chunk.len())?;
+                return Ok(Some(chunk));
+            }
+
+            if !self.fill().await? {
+                return Err(MultipartError::incomplete());
+            }
+        }
+    }
+
+    fn record_field_data(&mut self, size: usize) -> Result<(), MultipartError> {
+        self.field_size += size as u64;
+        if self.max_field_size.is_some_and(|max| self.field_size > max) {
+            return Err(MultipartError::new(
+                MultipartErrorKind::FieldSizeExceeded,
+                "field size limit exceeded",
+            ));
+        }
+        Ok(())
+    }
+
+    /// Read more data from the body into the buffer,
+    /// returning `false` if the end of the body is reached.
+    async fn fill(&mut self) -> Result<bool, MultipartError> {
+        if self.eof {
+            return Ok(false);
+        }
+        loop {
+            match self.stream.next().await {
+                Some(Ok(chunk)) => {
+                    if chunk.is_empty() {
+                        continue;
+                    }
+                    self.total_size += chunk.len() as u64;
+                    if self.max_size.is_some_and(|max| self.total_size > max) {
+                        return Err(MultipartError::new(
+                            MultipartErrorKind::SizeExceeded,
+                            "multipart size limit exceeded",
+                        ));
+                    }
+                    self.buffer.extend_from_slice(&chunk);
+                    return Ok(true);
+                }
+                Some(Err(err)) => {
+                    return Err(MultipartError::with_source(MultipartErrorKind::Body, err))
+                }
+                None => {
+                    self.eof = true;
+                    return Ok(false);
+                }
+            }
+        }
+    }
+}
+
+impl FromRequest for Multipart {
+    type Rejection = InvalidMultipartContentType;
+
+    async fn from_request(req: Request) -> Result<Self, Self::Rejection> {
+        let boundary = Self::boundary(req.headers()).ok_or(InvalidMultipartContentType)?;
+        Ok(Self::new(req.into_body(), boundary))
+    }
+}
+
+/// A single field (part) of a [`Multipart`] body.
+///
+/// The data of the field can be read chunk by chunk using [`MultipartField::chunk`],
+/// streamed into a writer using [`MultipartField::write_to`],
+/// or read all at once using [`MultipartField::bytes`], [`MultipartField::text`] or [`MultipartField::spool`].
+pub struct MultipartField<'a> {
+    multipart: &'a mut Multipart,
+    headers: HeaderMap,
+    name: Option<String>,
+    file_name: Option<String>,
+    content_type: Option<Mime>,
+}
+
+impl fmt::Debug for MultipartField<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MultipartField")
+            .field("headers", &self.headers)
+            .field("name", &self.name)
+            .field("file_name", &self.file_name)
+            .field("content_type", &self.content_type)
+            .finish()
+    }
+}
+
+impl<'a> MultipartField<'a> {
+    fn new(multipart: &'a mut Multipart, headers: HeaderMap) -> Result<Self, MultipartError> {
+        let (name, file_name) = match headers.get(header::CONTENT_DISPOSITION) {
+            Some(value) => {
+                let value = value.to_str().map_err(|err| {
+                    MultipartError::with_source(MultipartErrorKind::InvalidHeaders, err)
+                })?;
+                parse_content_disposition(value)
+            }
+            None => (None, None),
+        };
+        let content_type = headers
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok()?.parse().ok());
+        Ok(Self {
+            multipart,
+            headers,
+            name,
+            file_name,
+            content_type,
+        })
+    }
+
+    /// The name of the field, as defined in its `Content-Disposition` header.
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// The file name of the field, as defined in its `Content-Disposition` header.
+    ///
+    /// This is client input and should not be used as a path as-is.
+    pub fn file_name(&self) -> Option<&str> {
+        self.file_name.as_deref()
+    }
+
+    /// The content type of the field, if defined.
+    pub fn content_type(&self) -> Option<&Mime> {
+        self.content_type.as_ref()
+    }
+
+    /// The headers of the field.
+    pub fn headers(&self) -> &HeaderMap {
+        &self.headers
+    }
+
+    /// Read the next chunk of data of this field,
+    /// returning `None` once all data has been read.
+    pub async fn chunk(&mut self) -> Result<Option<Bytes>, MultipartError> {
+        self.multipart.read_field_chunk().await
+    }
+
+    /// Read all (remaining) data of this field into memory.
+    pub async fn bytes(mut self) -> Result<Bytes, MultipartError> {
+        let mut data = BytesMut::new();
+        while let Some(chunk) = self.chunk().await? {
+            data.extend_from_slice(&chunk);
+        }
+        Ok(data.freeze())
+    }
+
+    /// Read all (remaining) data of this field as UTF-8 text.
+    pub async fn text(self) -> Result<String, MultipartError> {
+        let data = self.bytes().await?;
+        String::from_utf8(data.into())
+            .map_err(|err| MultipartError::with_source(MultipartErrorKind::InvalidData, err))
+    }
+
+    #[cfg(feature = "multipart-spool")]
+    /// Read all (remaining) data of this field, keeping it in memory
+    /// unless it grows larger than the [spool threshold], in which case
+    /// all data is written to a temporary file instead.
+    ///
+    /// [spool threshold]: Multipart::spool_threshold
+    pub async fn spool(mut self) -> Result<MultipartFieldData, MultipartError> {
+        let threshold = self.multipart.spool_threshold;
+        let mut data = BytesMut::new();
+        while let Some(chunk) = self.chunk().await? {
+            data.extend_from_slice(&chunk);
+            if data.len() > threshold {
+                return self.spool_to_file(data.freeze()).await;
+            }
+        }
+        Ok(MultipartFieldData::Memory(data.freeze()))
+    }
+
+    /// Stream all (remaining) data of this field into the given writer,
+    /// chunk by chunk, returning the amount of bytes written.
+    ///
+    /// The writer is flushed once all data is written.
+    pub async fn write_to<W>(&mut self, writer: &mut W) -> Result<u64, MultipartError>
+    where
+        W: AsyncWrite + Unpin + ?Sized,
+    {
+        let mut len = 0;
+        while let Some(chunk) = self.chunk().await? {
+            len += chunk.len() as u64;
+            writer
+                .write_all(&chunk)
+                .await
+                .map_err(|err| MultipartError::with_source(MultipartErrorKind::Io, err))?;
+        }
+        writer
+            .flush()
+            .await
+            .map_err(|err| MultipartError::with_source(MultipartErrorKind::Io, err))?;
+        Ok(len)
+    }
+
+    #[cfg(feature = "multipart-spool")]
+    async fn spool_to_file(mut self, data: Bytes) -> Result<MultipartFieldData, MultipartError> {
+        let (file, path) = tempfile::NamedTempFile::new()
+            .map_err(|err| MultipartError::with_source(MultipartErrorKind::Io, err))?
+            .into_parts();
+        let mut file = tokio::fs::File::from_std(file);
+        file.write_all(&data)
+            .await
+            .map_err(|err| MultipartError::with_source(MultipartErrorKind::Io, err))?;
+        let len = data.len() as u64 + self.write_to(&mut file).await?;
+        Ok(MultipartFieldData::File(SpooledFile { path, len }))
+    }
+}
+
+#[cfg(feature = "multipart-spool")]
+#[derive(Debug)]
+/// The data of a [`MultipartField`], as read by [`MultipartField::spool`].
+pub enum MultipartFieldData {
+    /// The data is small enough to be kept in memory.
+    Memory(Bytes),
+    /// The data is written to a temporary file.
+    File(SpooledFile),
+}
+
+#[cfg(feature = "multipart-spool")]
+impl MultipartFieldData {
+    /// The size of the data in bytes.
+    pub fn len(&self) -> u64 {
+        match self {
+            Self::Memory(data) => data.len() as u64,
+            Self::File(file) => file.len(),
+        }
+    }
+
+    /// Returns `true` if there is no data.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(feature = "multipart-spool")]
+/// A temporary file containing the data of a [`MultipartField`].
+///
+/// The file is deleted when this value is dropped,
+/// unless it is moved elsewhere using [`SpooledFile::persist`].
+pub struct SpooledFile {
+    path: tempfile::TempPath,
+    len: u64,
+}
+
+#[cfg(feature = "multipart-spool")]
+impl fmt::Debug for SpooledFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SpooledFile")
+            .field("path", &self.path())
+            .field("len", &self.len)
+            .finish()
+    }
+}
+
+#[cfg(feature = "multipart-spool")]
+impl SpooledFile {
+    /// The path of the temporary file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The size of the file in bytes.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Returns `true` if the file is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Open the temporary file for reading.
+    pub async fn open(&self) -> io::Result<tokio::fs::File> {
+        tokio::fs::File::open(self.path()).await
+    }
+
+    /// Move the temporary file to the given path,
+    /// such that it is no longer deleted when dropped.
+    pub fn persist(self, path: impl AsRef<Path>) -> io::Result<()> {
+        self.path.persist(path).map_err(|err| err.error)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// The kind of a [`MultipartError`].
+pub enum MultipartErrorKind {
+    /// The body could not be read.
+    Body,
+    /// The body ended before the closing boundary.
+    Incomplete,
+    /// The boundary is not followed by a CRLF or closing `--`.
+    InvalidBoundary,
+    /// The headers of a part are invalid (or too large).
+    InvalidHeaders,
+    /// The data of a field is not valid for the requested format (e.g. UTF-8 text).
+    InvalidData,
+    /// The amount of fields exceeds the limit.
+    FieldsLimitExceeded,
+    /// The size of a field exceeds the limit.
+    FieldSizeExceeded,
+    /// The size of the multipart body exceeds the limit.
+    SizeExceeded,
+    /// The data could not be written (e.g. spooled to a temporary file).
+    Io,
+}
+
+/// Error returned while parsing a [`Multipart`] body.
+///
+/// It can be turned into a response directly, which uses
+/// `413 Payload Too Large` for exceeded size limits,
+/// `500 Internal Server Error` for I/O errors and `400 Bad Request` otherwise.
+pub struct MultipartError {
+    kind: MultipartErrorKind,
+    source: BoxError,
+}
+
+impl MultipartError {
+    fn new(kind: MultipartErrorKind, msg: &'static str) -> Self {
+        Self {
+            kind,
+            source: msg.into(),
+        }
+    }
+
+    fn with_source(kind: MultipartErrorKind, err: impl Into<BoxError>) -> Self {
+        Self {
+            kind,
+            source: err.into(),
+        }
+    }
+
+    fn incomplete() -> Self {
+        Self::new(
+            MultipartErrorKind::Incomplete,
+            "unexpected end of multipart body",
+        )
+    }
+
+    /// The kind of error.
+    pub fn kind(&self) -> MultipartErrorKind {
+        self.kind
+    }
+
+    /// The status code used when this error is turned into a response.
+    pub fn status(&self) -> StatusCode {
+        match self.kind {
+            MultipartErrorKind::FieldsLimitExceeded
+            | MultipartErrorKind::FieldSizeExceeded
+            | MultipartErrorKind::SizeExceeded => StatusCode::PAYLOAD_TOO_LARGE,
+            MultipartErrorKind::Io => StatusCode::INTERNAL_SERVER_ERROR,
+            _ => StatusCode::BAD_REQUEST,
+        }
+    }
+}
+
+impl fmt::Debug for MultipartError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MultipartError")
+            .field("kind", &self.kind)
+            .field("source", &self.source)
+            .finish()
+    }
+}
+
+impl fmt::Display for MultipartError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "multipart error ({:?}): {}", self.kind, self.source)
+    }
+}
+
+impl std::error::Error for MultipartError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(self.source.as_ref())
+    }
+}
+
+impl IntoResponse for MultipartError {
+    fn into_response(self) -> Response {
+        tracing::trace!(error = %self, "rejecting multipart request");
+        (self.status(), self.to_string()).into_response()
+    }
+}
+
+/// Find the first position of the needle in the haystack.
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+/// Find the position of the CRLF preceding the given delimiter.
+fn find_delimiter(haystack: &[u8], delimiter: &[u8]) -> Option<usize> {
+    let mut offset = 0;
+    while let Some(pos) = find(&haystack[offset..], b"\r\n") {
+        let start = offset + pos;
+        let rest = &haystack[start + 2..];
+        if rest.len() < delimiter.len() {
+            return None;
+        }
+        if rest.starts_with(delimiter) {
+            return Some(start);
+        }
+        offset = start + 1;
+    }
+    None
+}
+
+/// Parse the name and filename parameters of a `Content-Disposition` header value.
+fn parse_content_disposition(value: &str) -> (Option<String>, Option<String>) {
+    let mut name = None;
+    let mut file_name = None;
+    for param in value.split(';').skip(1) {
+        let Some((key, value)) = param.split_once('=') else {
+            continue;
+        };
+        let value = value.trim();
+        let value = value
+            .strip_prefix('"')
+            .and_then(|value| value.strip_suffix('"'))
+            .map(|value| value.replace("\\\"", "\"").replace("\\\\", "\\"))
+            .unwrap_or_else(|| value.to_owned());
+        match key.trim().to_ascii_lowercase().as_str() {
+            "name" => name = Some(value),
+            "filename" => file_name = Some(value),
+            _ => (),
+        }
+    }
+    (name, file_name)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::service::web::WebService;
+    use crate::{Method, StatusCode};
+    use rama_core::{Context, Service};
+    use std::convert::Infallible;
+
+    const BODY: &str = "preamble\r\n\
+        --xyz\r\n\
+        Content-Disposition: form-data; name=\"title\"\r\n\
+        \r\n\
+        hello world\r\n\
+        --xyz\r\n\
+        Content-Disposition: form-data; name=\"file\"; filename=\"a \\\"b\\\".txt\"\r\n\
+        Content-Type: text/plain\r\n\
+        \r\n\
+        line 1\r\n--xy\r\nline 2\r\n\
+        --xyz--\r\n";
+
+    fn chunked_body(data: &'static str, chunk_size: usize) -> Body {
+        let chunks: Vec<_> = data
+            .as_bytes()
+            .chunks(chunk_size)
+            .map(|chunk| Ok::<_, Infallible>(Bytes::copy_from_slice(chunk)))
+            .collect();
+        Body::from_stream(futures_lite::stream::iter(chunks))
+    }
+
+    #[tokio::test]
+    async fn test_multipart_fields() {
+        for chunk_size in [1, 2, 3, 7, 64, BODY.len()] {
+            let mut multipart = Multipart::new(chunked_body(BODY, chunk_size), "xyz");
+
+            let field = multipart.next_field().await.unwrap().unwrap();
+            assert_eq!(field.name(), Some("title"));
+            assert_eq!(field.file_name(), None);
+            assert_eq!(field.text().await.unwrap(), "hello world");
+
+            let field = multipart.next_field().await.unwrap().unwrap();
+            assert_eq!(field.name(), Some("file"));
+            assert_eq!(field.file_name(), Some("a \"b\".txt"));
+            assert_eq!(field.content_type(), Some(&mime::TEXT_PLAIN));
+            assert_eq!(
+                field.bytes().await.unwrap(),
+                "line 1\r\n--xy\r\nline 2",
+                "chunk size: {chunk_size}"
+            );
+
+            assert!(multipart.next_field().await.unwrap().is_none());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_multipart_skip_unread_fields() {
+        let mut multipart = Multipart::new(chunked_body(BODY, 5), "xyz");
+        let mut field = multipart.next_field().await.unwrap().unwrap();
+        let chunk = field.chunk().await.unwrap().unwrap();
+        assert!(
+            !chunk.is_empty() && "hello world".starts_with(std::str::from_utf8(&chunk).unwrap())
+        );
+        let field = multipart.next_field().await.unwrap().unwrap();
+        assert_eq!(field.name(), Some("file"));
+        assert!(multipart.next_field().await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_multipart_limits() {
+        let mut multipart = Multipart::new(chunked_body(BODY, 8), "xyz").max_fields(1);
+        let _ = multipart.next_field().await.unwrap().unwrap();
+        let err = multipart.next_field().await.unwrap_err();
+        assert_eq!(err.kind(), MultipartErrorKind::FieldsLimitExceeded);
+        assert_eq!(err.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+        let mut multipart = Multipart::new(chunked_body(BODY, 8), "xyz").max_field_size(5);
+        let field = multipart.next_field().await.unwrap().unwrap();
+        let err = field.bytes().await.unwrap_err();
+        assert_eq!(err.kind(), MultipartErrorKind::FieldSizeExceeded);
+
+        let mut multipart = Multipart::new(chunked_body(BODY, 8), "xyz").max_size(64);
+        let err = loop {
+            match multipart.next_field().await {
+                Ok(Some(field)) => {
+                    if let Err(err) = field.bytes().await {
+                        break err;
+                    }
+                }
+                Ok(None) => panic!("expected size limit error"),
+                Err(err) => break err,
+            }
+        };
+        assert_eq!(err.kind(), MultipartErrorKind::SizeExceeded);
+    }
+
+    #[tokio::test]
+    async fn test_multipart_incomplete() {
+        let body = "--xyz\r\nContent-Disposition: form-data; name=\"a\"\r\n\r\nabc";
+        let mut multipart = Multipart::new(chunked_body(body, 4), "xyz");
+        let field = multipart.next_field().await.unwrap().unwrap();
+        let err = field.bytes().await.unwrap_err();
+        assert_eq!(err.kind(), MultipartErrorKind::Incomplete);
+    }
+
+    #[cfg(feature = "multipart-spool")]
+    #[tokio::test]
+    async fn test_multipart_spool() {
+        let mut multipart = Multipart::new(chunked_body(BODY, 4), "xyz").spool_threshold(12);
+
+        let field = multipart.next_field().await.unwrap().unwrap();
+        match field.spool().await.unwrap() {
+            MultipartFieldData::Memory(data) => assert_eq!(data, "hello world"),
+            MultipartFieldData::File(_) => panic!("expected in-memory data"),
+        }
+
+        let field = multipart.next_field().await.unwrap().unwrap();
+        let file = match field.spool().await.unwrap() {
+            MultipartFieldData::Memory(_) => panic!("expected spooled file"),
+            MultipartFieldData::File(file) => file,
+        };
+        assert_eq!(file.len(), 20);
+        let data = tokio::fs::read(file.path()).await.unwrap();
+        assert_eq!(data, b"line 1\r\n--xy\r\nline 2");
+
+        let path = file.path().to_owned();
+        drop(file);
+        assert!(!path.exists());
+    }
+
+    #[tokio::test]
+    async fn test_multipart_write_to() {
+        let mut multipart = Multipart::new(chunked_body(BODY, 3), "xyz");
+        let _ = multipart.next_field().await.unwrap().unwrap();
+
+        let mut field = multipart.next_field().await.unwrap().unwrap();
+        let mut output = Vec::new();
+        assert_eq!(field.write_to(&mut output).await.unwrap(), 20);
+        assert_eq!(output, b"line 1\r\n--xy\r\nline 2");
+
+        let mut multipart = Multipart::new(chunked_body(BODY, 3), "xyz").max_field_size(5);
+        let mut field = multipart.next_field().await.unwrap().unwrap();
+        let err = field.write_to(&mut Vec::new()).await.unwrap_err();
+        assert_eq!(err.kind(), MultipartErrorKind::FieldSizeExceeded);
+    }
+
+    #[tokio::test]
+    async fn test_multipart_extractor() {
+        let service = WebService::default().post("/", |mut multipart: Multipart| async move {
+            let mut names = Vec::new();
+            while let Some(field) = multipart.next_field().await.unwrap() {
+                names.push(field.name().unwrap().to_owned());
+            }
+            assert_eq!(names, ["title", "file"]);
+        });
+
+        let req = Request::builder()
+            .uri("/")
+            .method(Method::POST)
+            .header("content-type", "multipart/form-data; boundary=xyz")
+            .body(BODY.into())
+            .unwrap();
+        let resp = service.serve(Context::default(), req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        let req = Request::builder()
+            .uri("/")
+            .method(Method::POST)
+            .header("content-type", "multipart/form-data")
+            .body(BODY.into())
+            .unwrap();
+        let resp = service.serve(Context::default(), req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    }
+}
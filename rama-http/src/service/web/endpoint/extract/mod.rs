@@ -2,7 +2,7 @@
 
 use crate::{dep::http::request::Parts, dep::mime, header, HeaderMap, IntoResponse};
 use rama_core::Context;
-use std::future::Future;
+use std::{convert::Infallible, future::Future};
 
 mod host;
 #[doc(inline)]
@@ -29,7 +29,13 @@ pub use typed_header::{TypedHeader, TypedHeaderRejection, TypedHeaderRejectionRe
 
 mod body;
 #[doc(inline)]
-pub use body::{Body, Bytes, Form, Json, Text};
+pub use body::{
+    Body, Bytes, Form, InvalidMultipartContentType, Json, Multipart, MultipartError,
+    MultipartErrorKind, MultipartField, Text,
+};
+#[cfg(feature = "multipart-spool")]
+#[doc(inline)]
+pub use body::{MultipartFieldData, SpooledFile};
 
 /// Types that can be created from request parts.
 ///
@@ -68,6 +74,66 @@ pub trait FromRequest: Sized + Send + Sync + 'static {
     ) -> impl Future<Output = Result<Self, Self::Rejection>> + Send;
 }
 
+/// Extract an optional value, which is `None` in case
+/// the extractor rejected the request, instead of responding with its rejection.
+impl<S, T> FromRequestContextRefPair<S> for Option<T>
+where
+    S: Clone + Send + Sync + 'static,
+    T: FromRequestContextRefPair<S>,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_context_ref_pair(
+        ctx: &Context<S>,
+        parts: &Parts,
+    ) -> Result<Self, Self::Rejection> {
+        Ok(T::from_request_context_ref_pair(ctx, parts).await.ok())
+    }
+}
+
+/// Extract the result of the extractor, such that the handler
+/// can respond to a rejection in its own way.
+impl<S, T> FromRequestContextRefPair<S> for Result<T, T::Rejection>
+where
+    S: Clone + Send + Sync + 'static,
+    T: FromRequestContextRefPair<S, Rejection: Send + Sync + 'static>,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_context_ref_pair(
+        ctx: &Context<S>,
+        parts: &Parts,
+    ) -> Result<Self, Self::Rejection> {
+        Ok(T::from_request_context_ref_pair(ctx, parts).await)
+    }
+}
+
+/// Extract an optional value, which is `None` in case
+/// the extractor rejected the request, instead of responding with its rejection.
+impl<T> FromRequest for Option<T>
+where
+    T: FromRequest,
+{
+    type Rejection = Infallible;
+
+    async fn from_request(req: crate::Request) -> Result<Self, Self::Rejection> {
+        Ok(T::from_request(req).await.ok())
+    }
+}
+
+/// Extract the result of the extractor, such that the handler
+/// can respond to a rejection in its own way.
+impl<T> FromRequest for Result<T, T::Rejection>
+where
+    T: FromRequest<Rejection: Send + Sync + 'static>,
+{
+    type Rejection = Infallible;
+
+    async fn from_request(req: crate::Request) -> Result<Self, Self::Rejection> {
+        Ok(T::from_request(req).await)
+    }
+}
+
 fn has_any_content_type(headers: &HeaderMap, expected_content_types: &[&mime::Mime]) -> bool {
     let content_type = if let Some(content_type) = headers.get(header::CONTENT_TYPE) {
         content_type
@@ -85,3 +151,63 @@ fn has_any_content_type(headers: &HeaderMap, expected_content_types: &[&mime::Mi
         .iter()
         .any(|ct| content_type.starts_with(ct.as_ref()))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::service::web::WebService;
+    use crate::{Body, Request, StatusCode};
+    use rama_core::Service;
+
+    #[derive(Debug, serde::Deserialize)]
+    struct Params {
+        name: String,
+    }
+
+    #[tokio::test]
+    async fn test_optional_extractor() {
+        let svc = WebService::default().get("/", |query: Option<Query<Params>>| async move {
+            match query {
+                Some(Query(params)) => params.name,
+                None => "anonymous".to_owned(),
+            }
+        });
+
+        for (uri, expected) in [("/?name=rama", "rama"), ("/?foo=bar", "anonymous")] {
+            let req = Request::get(uri).body(Body::empty()).unwrap();
+            let resp = svc.serve(Context::default(), req).await.unwrap();
+            assert_eq!(resp.status(), StatusCode::OK);
+            let body = crate::BodyExtractExt::try_into_string(resp.into_body())
+                .await
+                .unwrap();
+            assert_eq!(body, expected);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_result_extractor() {
+        let svc = WebService::default().post(
+            "/",
+            |json: Result<Json<Params>, body::JsonRejection>| async move {
+                match json {
+                    Ok(Json(params)) => (StatusCode::OK, params.name),
+                    Err(rejection) => (StatusCode::UNPROCESSABLE_ENTITY, rejection.body_text()),
+                }
+            },
+        );
+
+        let req = Request::post("/")
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(r#"{"name":"rama"}"#))
+            .unwrap();
+        let resp = svc.serve(Context::default(), req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        let req = Request::post("/")
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(r#"{"age":42}"#))
+            .unwrap();
+        let resp = svc.serve(Context::default(), req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    }
+}
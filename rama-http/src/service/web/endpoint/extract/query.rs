@@ -3,6 +3,7 @@ use crate::dep::http::request::Parts;
 use crate::utils::macros::define_http_rejection;
 use rama_core::Context;
 use serde::de::DeserializeOwned;
+use std::ops::{Deref, DerefMut};
 
 /// Extractor that deserializes query strings into some type.
 ///
@@ -46,3 +47,17 @@ where
         Ok(Query(params))
     }
 }
+
+impl<T> Deref for Query<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<T> DerefMut for Query<T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
@@ -1,11 +1,12 @@
 //! k8s web service
 
-use crate::{matcher::HttpMatcher, IntoResponse, Request, Response, StatusCode};
+use crate::{matcher::HttpMatcher, IntoResponse, Method, Request, Response, StatusCode};
 use rama_core::{
+    graceful::WeakShutdownGuard,
     service::{service_fn, BoxService},
     Context, Service,
 };
-use std::{convert::Infallible, fmt, marker::PhantomData, sync::Arc};
+use std::{borrow::Cow, convert::Infallible, fmt, marker::PhantomData, sync::Arc};
 
 use super::match_service;
 
@@ -162,6 +163,245 @@ where
     }
 }
 
+type HealthCheckFn = dyn Fn() -> bool + Send + Sync + 'static;
+
+#[derive(Clone)]
+struct HealthCheck {
+    name: Cow<'static, str>,
+    check: Arc<HealthCheckFn>,
+}
+
+/// Ready-made k8s health web service, exposing the
+/// `/livez`, `/readyz` and `/healthz` endpoints.
+///
+/// - `/livez`: fails in case any of the liveness checks fails;
+/// - `/readyz`: fails in case any of the readiness checks fails,
+///   or once the (graceful) shutdown signal is received,
+///   such that the workload is no longer routed to while it is draining;
+/// - `/healthz`: fails in case any of the above fails.
+///
+/// A failing endpoint responds with a 503 (Service Unavailable),
+/// and a 200 (OK) otherwise. Adding the `verbose` query parameter
+/// (e.g. `/readyz?verbose`) lists the result of each individual check.
+///
+/// # Example
+///
+/// ```
+/// use rama_core::graceful::Shutdown;
+/// use rama_http::service::web::k8s::K8sHealthService;
+/// use std::sync::{atomic::{AtomicBool, Ordering}, Arc};
+///
+/// # #[tokio::main]
+/// # async fn main() {
+/// let shutdown = Shutdown::default();
+/// let pool_healthy = Arc::new(AtomicBool::new(true));
+///
+/// let health = K8sHealthService::new()
+///     .with_shutdown_guard(shutdown.guard_weak())
+///     .with_readiness_check("upstream", move || pool_healthy.load(Ordering::Acquire));
+/// # }
+/// ```
+pub struct K8sHealthService {
+    shutdown_guard: Option<WeakShutdownGuard>,
+    liveness_checks: Vec<HealthCheck>,
+    readiness_checks: Vec<HealthCheck>,
+}
+
+impl fmt::Debug for K8sHealthService {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let names = |checks: &[HealthCheck]| -> Vec<String> {
+            checks.iter().map(|check| check.name.to_string()).collect()
+        };
+        f.debug_struct("K8sHealthService")
+            .field("shutdown_guard", &self.shutdown_guard.is_some())
+            .field("liveness_checks", &names(&self.liveness_checks))
+            .field("readiness_checks", &names(&self.readiness_checks))
+            .finish()
+    }
+}
+
+impl Clone for K8sHealthService {
+    fn clone(&self) -> Self {
+        Self {
+            shutdown_guard: self.shutdown_guard.clone(),
+            liveness_checks: self.liveness_checks.clone(),
+            readiness_checks: self.readiness_checks.clone(),
+        }
+    }
+}
+
+impl Default for K8sHealthService {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl K8sHealthService {
+    /// Create a new [`K8sHealthService`], which is always alive and ready.
+    pub fn new() -> Self {
+        Self {
+            shutdown_guard: None,
+            liveness_checks: Vec::new(),
+            readiness_checks: Vec::new(),
+        }
+    }
+
+    /// Mark the service as not ready once the shutdown signal
+    /// of the guard's [`Shutdown`] is received.
+    ///
+    /// Use a shutdown delay to give k8s the time to observe
+    /// the readiness change prior to the shutdown (draining) itself.
+    ///
+    /// [`Shutdown`]: rama_core::graceful::Shutdown
+    pub fn with_shutdown_guard(mut self, guard: WeakShutdownGuard) -> Self {
+        self.shutdown_guard = Some(guard);
+        self
+    }
+
+    /// Mark the service as not ready once the shutdown signal
+    /// of the guard's [`Shutdown`] is received.
+    ///
+    /// Use a shutdown delay to give k8s the time to observe
+    /// the readiness change prior to the shutdown (draining) itself.
+    ///
+    /// [`Shutdown`]: rama_core::graceful::Shutdown
+    pub fn set_shutdown_guard(&mut self, guard: WeakShutdownGuard) -> &mut Self {
+        self.shutdown_guard = Some(guard);
+        self
+    }
+
+    /// Add a named check, which fails the liveness endpoint when it returns `false`.
+    pub fn with_liveness_check<F>(mut self, name: impl Into<Cow<'static, str>>, check: F) -> Self
+    where
+        F: Fn() -> bool + Send + Sync + 'static,
+    {
+        self.set_liveness_check(name, check);
+        self
+    }
+
+    /// Add a named check, which fails the liveness endpoint when it returns `false`.
+    pub fn set_liveness_check<F>(
+        &mut self,
+        name: impl Into<Cow<'static, str>>,
+        check: F,
+    ) -> &mut Self
+    where
+        F: Fn() -> bool + Send + Sync + 'static,
+    {
+        self.liveness_checks.push(HealthCheck {
+            name: name.into(),
+            check: Arc::new(check),
+        });
+        self
+    }
+
+    /// Add a named check, which fails the readiness endpoint when it returns `false`.
+    pub fn with_readiness_check<F>(mut self, name: impl Into<Cow<'static, str>>, check: F) -> Self
+    where
+        F: Fn() -> bool + Send + Sync + 'static,
+    {
+        self.set_readiness_check(name, check);
+        self
+    }
+
+    /// Add a named check, which fails the readiness endpoint when it returns `false`.
+    pub fn set_readiness_check<F>(
+        &mut self,
+        name: impl Into<Cow<'static, str>>,
+        check: F,
+    ) -> &mut Self
+    where
+        F: Fn() -> bool + Send + Sync + 'static,
+    {
+        self.readiness_checks.push(HealthCheck {
+            name: name.into(),
+            check: Arc::new(check),
+        });
+        self
+    }
+
+    /// Returns `true` in case the shutdown signal was received.
+    async fn is_shutting_down(&self) -> bool {
+        match &self.shutdown_guard {
+            Some(guard) => futures_lite::future::poll_once(guard.shutdown_signal_triggered())
+                .await
+                .is_some(),
+            None => false,
+        }
+    }
+}
+
+impl<State> Service<State, Request> for K8sHealthService
+where
+    State: Clone + Send + Sync + 'static,
+{
+    type Response = Response;
+    type Error = Infallible;
+
+    async fn serve(&self, _: Context<State>, req: Request) -> Result<Self::Response, Self::Error> {
+        if req.method() != Method::GET && req.method() != Method::HEAD {
+            return Ok(StatusCode::NOT_FOUND.into_response());
+        }
+        let (endpoint, live, ready) = match req.uri().path() {
+            "/livez" => ("livez", true, false),
+            "/readyz" => ("readyz", false, true),
+            "/healthz" => ("healthz", true, true),
+            _ => return Ok(StatusCode::NOT_FOUND.into_response()),
+        };
+
+        let mut results = Vec::new();
+        if live {
+            results.extend(
+                self.liveness_checks
+                    .iter()
+                    .map(|check| (check.name.as_ref(), (check.check)())),
+            );
+        }
+        if ready {
+            if self.shutdown_guard.is_some() {
+                results.push(("shutdown", !self.is_shutting_down().await));
+            }
+            results.extend(
+                self.readiness_checks
+                    .iter()
+                    .map(|check| (check.name.as_ref(), (check.check)())),
+            );
+        }
+
+        let healthy = results.iter().all(|(_, ok)| *ok);
+        let status = if healthy {
+            StatusCode::OK
+        } else {
+            StatusCode::SERVICE_UNAVAILABLE
+        };
+
+        let verbose = req
+            .uri()
+            .query()
+            .is_some_and(|query| query.split('&').any(|param| param == "verbose"));
+        let body = if verbose {
+            let mut body = String::new();
+            for (name, ok) in results {
+                body.push_str(&format!(
+                    "[{}]{name} {}\n",
+                    if ok { '+' } else { '-' },
+                    if ok { "ok" } else { "failed" }
+                ));
+            }
+            body.push_str(&format!(
+                "{endpoint} check {}\n",
+                if healthy { "passed" } else { "failed" }
+            ));
+            body
+        } else if healthy {
+            "ok".to_owned()
+        } else {
+            format!("{endpoint} check failed")
+        };
+        Ok((status, body).into_response())
+    }
+}
+
 mod private {
     use super::*;
 
@@ -184,3 +424,81 @@ mod private {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Body, BodyExtractExt};
+    use rama_core::graceful::Shutdown;
+    use std::{
+        sync::atomic::{AtomicBool, Ordering},
+        time::Duration,
+    };
+
+    async fn get(service: &K8sHealthService, path: &str) -> (StatusCode, String) {
+        let req = Request::get(path).body(Body::empty()).unwrap();
+        let resp = service.serve(Context::default(), req).await.unwrap();
+        let status = resp.status();
+        (status, resp.into_body().try_into_string().await.unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_k8s_health_service() {
+        let upstream_healthy = Arc::new(AtomicBool::new(true));
+        let service = K8sHealthService::new()
+            .with_liveness_check("deadlock", || true)
+            .with_readiness_check("upstream", {
+                let upstream_healthy = upstream_healthy.clone();
+                move || upstream_healthy.load(Ordering::Acquire)
+            });
+
+        for path in ["/livez", "/readyz", "/healthz"] {
+            assert_eq!(get(&service, path).await, (StatusCode::OK, "ok".to_owned()));
+        }
+        assert_eq!(get(&service, "/foo").await.0, StatusCode::NOT_FOUND);
+
+        upstream_healthy.store(false, Ordering::Release);
+        assert_eq!(get(&service, "/livez").await.0, StatusCode::OK);
+        assert_eq!(
+            get(&service, "/readyz").await,
+            (
+                StatusCode::SERVICE_UNAVAILABLE,
+                "readyz check failed".to_owned()
+            )
+        );
+        assert_eq!(
+            get(&service, "/healthz?verbose").await,
+            (
+                StatusCode::SERVICE_UNAVAILABLE,
+                "[+]deadlock ok\n[-]upstream failed\nhealthz check failed\n".to_owned()
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn test_k8s_health_service_shutdown() {
+        let (tx, rx) = tokio::sync::oneshot::channel::<()>();
+        let shutdown = Shutdown::builder()
+            .with_signal(async move {
+                let _ = rx.await;
+            })
+            .with_delay(Duration::from_secs(60))
+            .build();
+        let service = K8sHealthService::new().with_shutdown_guard(shutdown.guard_weak());
+
+        assert_eq!(get(&service, "/readyz").await.0, StatusCode::OK);
+
+        tx.send(()).unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        assert_eq!(
+            get(&service, "/readyz?verbose").await,
+            (
+                StatusCode::SERVICE_UNAVAILABLE,
+                "[-]shutdown failed\nreadyz check failed\n".to_owned()
+            )
+        );
+        // a draining workload is still alive
+        assert_eq!(get(&service, "/livez").await.0, StatusCode::OK);
+    }
+}
@@ -8,6 +8,14 @@ mod endpoint;
 #[doc(inline)]
 pub use endpoint::{extract, EndpointServiceFn, IntoEndpointService};
 
+pub mod router;
+#[doc(inline)]
+pub use router::Router;
+
 pub mod k8s;
 #[doc(inline)]
-pub use k8s::{k8s_health, k8s_health_builder};
+pub use k8s::{k8s_health, k8s_health_builder, K8sHealthService};
+
+pub mod upstream_health;
+#[doc(inline)]
+pub use upstream_health::UpstreamHealthService;
@@ -0,0 +1,462 @@
+//! A web service router, dispatching requests by their path and method.
+//!
+//! Routes are path patterns using the [`PathTrie`] syntax:
+//!
+//! - literal segments, e.g. `/users`;
+//! - parameters, e.g. `/users/:id`;
+//! - a (named) wildcard as the last segment, capturing the remainder of the path,
+//!   e.g. `/files/*path`.
+//!
+//! The captured parameters are inserted in the [`Context`] as [`UriParams`],
+//! such that they can be extracted using the [`Path`] extractor.
+//! All routes are compiled into a [`PathTrie`], such that finding the route of a request
+//! does not depend on the amount of routes. In case multiple routes match a path,
+//! literal segments are preferred over parameters, and parameters over wildcards.
+//!
+//! Requests for a known route but using a method without a service for it,
+//! are responded to using `405 Method Not Allowed` (with the allowed methods in the
+//! `Allow` header), while requests for unknown routes are served by the fallback service.
+//! `HEAD` requests are served by the `GET` service, unless a `HEAD` service is defined.
+//!
+//! [`Path`]: super::extract::Path
+//!
+//! # Example
+//!
+//! ```
+//! use rama_http::service::web::{extract::Path, Router};
+//! use rama_http::{Body, Request, StatusCode};
+//! use rama_http::dep::http_body_util::BodyExt;
+//! use rama_core::{Context, Service};
+//!
+//! #[derive(Debug, serde::Deserialize)]
+//! struct FileParams {
+//!     id: u64,
+//!     path: String,
+//! }
+//!
+//! # #[tokio::main]
+//! # async fn main() {
+//! let api = Router::new()
+//!     .get("/users/:id/files/*path", |Path(params): Path<FileParams>| async move {
+//!         format!("file {} of user {}", params.path, params.id)
+//!     })
+//!     .delete("/users/:id", StatusCode::NO_CONTENT);
+//!
+//! let router = Router::new()
+//!     .nest("/api", api)
+//!     .fallback(StatusCode::IM_A_TEAPOT);
+//!
+//! let req = Request::get("/api/users/42/files/docs/a.txt").body(Body::empty()).unwrap();
+//! let resp = router.serve(Context::default(), req).await.unwrap();
+//! let body = resp.into_body().collect().await.unwrap().to_bytes();
+//! assert_eq!(body, "file docs/a.txt of user 42");
+//!
+//! let req = Request::post("/api/users/42").body(Body::empty()).unwrap();
+//! let resp = router.serve(Context::default(), req).await.unwrap();
+//! assert_eq!(resp.status(), StatusCode::METHOD_NOT_ALLOWED);
+//!
+//! let req = Request::get("/unknown").body(Body::empty()).unwrap();
+//! let resp = router.serve(Context::default(), req).await.unwrap();
+//! assert_eq!(resp.status(), StatusCode::IM_A_TEAPOT);
+//! # }
+//! ```
+
+use super::{service::NestedService, IntoEndpointService};
+use crate::{
+    header,
+    matcher::{PathTrie, UriParams},
+    HeaderValue, IntoResponse, Method, Request, Response, StatusCode,
+};
+use rama_core::{
+    service::{service_fn, BoxService},
+    Context, Service,
+};
+use std::{convert::Infallible, fmt, sync::Arc};
+
+type RouteService<State> = Arc<BoxService<State, Request, Response, Infallible>>;
+
+/// A web service router, dispatching requests by their path and method.
+///
+/// See the [module docs](self) for more details.
+pub struct Router<State> {
+    routes: Arc<PathTrie<MethodRoutes<State>>>,
+    fallback: RouteService<State>,
+}
+
+struct MethodRoutes<State> {
+    methods: Vec<(Method, RouteService<State>)>,
+    any: Option<RouteService<State>>,
+}
+
+impl<State> Clone for MethodRoutes<State> {
+    fn clone(&self) -> Self {
+        Self {
+            methods: self.methods.clone(),
+            any: self.any.clone(),
+        }
+    }
+}
+
+impl<State> MethodRoutes<State> {
+    fn new() -> Self {
+        Self {
+            methods: Vec::new(),
+            any: None,
+        }
+    }
+
+    fn set(&mut self, method: Option<Method>, service: RouteService<State>) {
+        match method {
+            Some(method) => {
+                self.methods.retain(|(m, _)| *m != method);
+                self.methods.push((method, service));
+            }
+            None => self.any = Some(service),
+        }
+    }
+
+    fn get(&self, method: &Method) -> Option<&RouteService<State>> {
+        let find = |method: &Method| {
+            self.methods
+                .iter()
+                .find_map(|(m, service)| (m == method).then_some(service))
+        };
+        find(method)
+            .or_else(|| {
+                if method == Method::HEAD {
+                    find(&Method::GET)
+                } else {
+                    None
+                }
+            })
+            .or(self.any.as_ref())
+    }
+
+    fn method_not_allowed(&self) -> Response {
+        let mut methods: Vec<_> = self.methods.iter().map(|(m, _)| m.as_str()).collect();
+        if methods.contains(&"GET") && !methods.contains(&"HEAD") {
+            methods.push("HEAD");
+        }
+        let mut res = StatusCode::METHOD_NOT_ALLOWED.into_response();
+        if let Ok(allow) = HeaderValue::try_from(methods.join(", ")) {
+            res.headers_mut().insert(header::ALLOW, allow);
+        }
+        res
+    }
+}
+
+impl<State> fmt::Debug for Router<State> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Router")
+            .field("routes", &self.routes.len())
+            .finish()
+    }
+}
+
+impl<State> Clone for Router<State> {
+    fn clone(&self) -> Self {
+        Self {
+            routes: self.routes.clone(),
+            fallback: self.fallback.clone(),
+        }
+    }
+}
+
+impl<State> Router<State>
+where
+    State: Clone + Send + Sync + 'static,
+{
+    /// Create a new [`Router`] without any routes,
+    /// responding with `404 Not Found` to all requests.
+    pub fn new() -> Self {
+        Self {
+            routes: Arc::new(PathTrie::new()),
+            fallback: Arc::new(
+                service_fn(|| async { Ok(StatusCode::NOT_FOUND.into_response()) }).boxed(),
+            ),
+        }
+    }
+
+    /// Add a route for the given method and path, using the given service.
+    ///
+    /// An existing service for the same method and path is replaced.
+    pub fn route<I, T>(self, method: Method, path: &str, service: I) -> Self
+    where
+        I: IntoEndpointService<State, T>,
+    {
+        self.add_route(Some(method), path, service)
+    }
+
+    /// Add a route for the given path, using the given service for any method
+    /// which has no service of its own.
+    pub fn any<I, T>(self, path: &str, service: I) -> Self
+    where
+        I: IntoEndpointService<State, T>,
+    {
+        self.add_route(None, path, service)
+    }
+
+    /// Add a GET route to the router, using the given service.
+    pub fn get<I, T>(self, path: &str, service: I) -> Self
+    where
+        I: IntoEndpointService<State, T>,
+    {
+        self.route(Method::GET, path, service)
+    }
+
+    /// Add a POST route to the router, using the given service.
+    pub fn post<I, T>(self, path: &str, service: I) -> Self
+    where
+        I: IntoEndpointService<State, T>,
+    {
+        self.route(Method::POST, path, service)
+    }
+
+    /// Add a PUT route to the router, using the given service.
+    pub fn put<I, T>(self, path: &str, service: I) -> Self
+    where
+        I: IntoEndpointService<State, T>,
+    {
+        self.route(Method::PUT, path, service)
+    }
+
+    /// Add a DELETE route to the router, using the given service.
+    pub fn delete<I, T>(self, path: &str, service: I) -> Self
+    where
+        I: IntoEndpointService<State, T>,
+    {
+        self.route(Method::DELETE, path, service)
+    }
+
+    /// Add a PATCH route to the router, using the given service.
+    pub fn patch<I, T>(self, path: &str, service: I) -> Self
+    where
+        I: IntoEndpointService<State, T>,
+    {
+        self.route(Method::PATCH, path, service)
+    }
+
+    /// Add a HEAD route to the router, using the given service.
+    pub fn head<I, T>(self, path: &str, service: I) -> Self
+    where
+        I: IntoEndpointService<State, T>,
+    {
+        self.route(Method::HEAD, path, service)
+    }
+
+    /// Add a OPTIONS route to the router, using the given service.
+    pub fn options<I, T>(self, path: &str, service: I) -> Self
+    where
+        I: IntoEndpointService<State, T>,
+    {
+        self.route(Method::OPTIONS, path, service)
+    }
+
+    /// Add a TRACE route to the router, using the given service.
+    pub fn trace<I, T>(self, path: &str, service: I) -> Self
+    where
+        I: IntoEndpointService<State, T>,
+    {
+        self.route(Method::TRACE, path, service)
+    }
+
+    /// Nest a service (e.g. another [`Router`]) under the given path prefix.
+    ///
+    /// The nested service receives the requests with the prefix removed from their path,
+    /// and with the parameters captured by the prefix available in the [`UriParams`].
+    pub fn nest<I, T>(self, prefix: &str, service: I) -> Self
+    where
+        I: IntoEndpointService<State, T>,
+    {
+        let prefix = prefix.trim_end_matches(['/', '*']);
+        let service: RouteService<State> =
+            Arc::new(NestedService(service.into_endpoint_service()).boxed());
+        self.add_route_service(None, prefix, service.clone())
+            .add_route_service(None, &format!("{prefix}/*"), service)
+    }
+
+    /// Use the given service for requests which match no route.
+    pub fn fallback<I, T>(mut self, service: I) -> Self
+    where
+        I: IntoEndpointService<State, T>,
+    {
+        self.fallback = Arc::new(service.into_endpoint_service().boxed());
+        self
+    }
+
+    fn add_route<I, T>(self, method: Option<Method>, path: &str, service: I) -> Self
+    where
+        I: IntoEndpointService<State, T>,
+    {
+        let service = Arc::new(service.into_endpoint_service().boxed());
+        self.add_route_service(method, path, service)
+    }
+
+    fn add_route_service(
+        mut self,
+        method: Option<Method>,
+        path: &str,
+        service: RouteService<State>,
+    ) -> Self {
+        let routes = Arc::make_mut(&mut self.routes);
+        match routes.get_mut(path) {
+            Some(methods) => methods.set(method, service),
+            None => {
+                let mut methods = MethodRoutes::new();
+                methods.set(method, service);
+                routes.insert(path, methods);
+            }
+        }
+        self
+    }
+}
+
+impl<State> Default for Router<State>
+where
+    State: Clone + Send + Sync + 'static,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<State> Service<State, Request> for Router<State>
+where
+    State: Clone + Send + Sync + 'static,
+{
+    type Response = Response;
+    type Error = Infallible;
+
+    async fn serve(
+        &self,
+        mut ctx: Context<State>,
+        req: Request,
+    ) -> Result<Self::Response, Self::Error> {
+        let Some((methods, mut params)) = self.routes.find(req.uri().path()) else {
+            return self.fallback.serve(ctx, req).await;
+        };
+        let Some(service) = methods.get(req.method()) else {
+            return Ok(methods.method_not_allowed());
+        };
+        if let Some(outer) = ctx.get::<UriParams>() {
+            params.extend_outer(outer);
+        }
+        ctx.insert(params);
+        service.serve(ctx, req).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::service::web::extract::Path;
+    use crate::{Body, BodyExtractExt};
+
+    async fn serve(router: &Router<()>, method: Method, uri: &str) -> (StatusCode, String) {
+        let req = Request::builder()
+            .method(method)
+            .uri(uri)
+            .body(Body::empty())
+            .unwrap();
+        let resp = router.serve(Context::default(), req).await.unwrap();
+        let status = resp.status();
+        (status, resp.into_body().try_into_string().await.unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_router_method_dispatch() {
+        let router = Router::new()
+            .get("/items", "list")
+            .post("/items", "create")
+            .any("/any", "any")
+            .get("/any", "get any");
+
+        assert_eq!(
+            serve(&router, Method::GET, "/items").await,
+            (StatusCode::OK, "list".to_owned())
+        );
+        assert_eq!(
+            serve(&router, Method::POST, "/items/").await,
+            (StatusCode::OK, "create".to_owned())
+        );
+        assert_eq!(
+            serve(&router, Method::HEAD, "/items").await.0,
+            StatusCode::OK
+        );
+        assert_eq!(
+            serve(&router, Method::PATCH, "/any").await,
+            (StatusCode::OK, "any".to_owned())
+        );
+        assert_eq!(
+            serve(&router, Method::GET, "/any").await,
+            (StatusCode::OK, "get any".to_owned())
+        );
+
+        let req = Request::delete("/items").body(Body::empty()).unwrap();
+        let resp = router.serve(Context::default(), req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::METHOD_NOT_ALLOWED);
+        assert_eq!(resp.headers()[header::ALLOW], "GET, POST, HEAD");
+
+        assert_eq!(
+            serve(&router, Method::GET, "/unknown").await.0,
+            StatusCode::NOT_FOUND
+        );
+    }
+
+    #[tokio::test]
+    async fn test_router_params_and_wildcards() {
+        let router = Router::new()
+            .get("/users/me", "me")
+            .get("/users/:id", |Path(id): Path<u64>| async move {
+                format!("user {id}")
+            })
+            .get("/static/*path", |ctx: Context<()>| async move {
+                let params = ctx.get::<UriParams>().unwrap();
+                format!("static {}", params.get("path").unwrap())
+            });
+
+        assert_eq!(serve(&router, Method::GET, "/users/me").await.1, "me");
+        assert_eq!(serve(&router, Method::GET, "/users/7").await.1, "user 7");
+        assert_eq!(
+            serve(&router, Method::GET, "/users/x").await.0,
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(
+            serve(&router, Method::GET, "/static/css/a.css").await.1,
+            "static css/a.css"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_router_nest() {
+        #[derive(serde::Deserialize)]
+        struct Params {
+            tenant: String,
+            id: u64,
+        }
+
+        let users = Router::new()
+            .get("/", "users")
+            .get("/:id", |Path(params): Path<Params>| async move {
+                format!("{}/{}", params.tenant, params.id)
+            })
+            .fallback("users fallback");
+        let router = Router::new()
+            .nest("/:tenant/users", users)
+            .fallback("root fallback");
+
+        assert_eq!(serve(&router, Method::GET, "/acme/users").await.1, "users");
+        assert_eq!(
+            serve(&router, Method::GET, "/acme/users/3?x=y").await.1,
+            "acme/3"
+        );
+        assert_eq!(
+            serve(&router, Method::GET, "/acme/users/3/more").await.1,
+            "users fallback"
+        );
+        assert_eq!(
+            serve(&router, Method::GET, "/acme/groups").await.1,
+            "root fallback"
+        );
+    }
+}
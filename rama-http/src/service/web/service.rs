@@ -169,7 +169,7 @@ where
     }
 }
 
-struct NestedService<S>(S);
+pub(super) struct NestedService<S>(pub(super) S);
 
 impl<S: fmt::Debug> fmt::Debug for NestedService<S> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -196,7 +196,10 @@ where
         req: Request,
     ) -> impl Future<Output = Result<Self::Response, Self::Error>> + Send + '_ {
         // get nested path
-        let path = ctx.get::<UriParams>().unwrap().glob().unwrap();
+        let path = ctx
+            .get::<UriParams>()
+            .and_then(UriParams::glob)
+            .unwrap_or("/");
 
         // set the nested path
         let (mut parts, body) = req.into_parts();
@@ -0,0 +1,110 @@
+//! upstream health status web service
+
+use crate::{response::Json, IntoResponse, Request, Response, StatusCode};
+use rama_core::{service::balance::UpstreamPool, Context, Service};
+use serde_json::json;
+use std::{convert::Infallible, fmt};
+
+/// Web service which responds with the [`UpstreamHealth`] of all
+/// upstreams of an [`UpstreamPool`] as JSON, ready to be mounted on a status endpoint.
+///
+/// Responds with a 503 (Service Unavailable) status in case none
+/// of the upstreams are healthy, and a 200 (OK) status otherwise.
+///
+/// [`UpstreamHealth`]: rama_core::service::balance::UpstreamHealth
+pub struct UpstreamHealthService<S> {
+    pool: UpstreamPool<S>,
+}
+
+impl<S> UpstreamHealthService<S> {
+    /// Create a new [`UpstreamHealthService`] for the given pool.
+    pub const fn new(pool: UpstreamPool<S>) -> Self {
+        Self { pool }
+    }
+}
+
+impl<S: fmt::Debug> fmt::Debug for UpstreamHealthService<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("UpstreamHealthService")
+            .field("pool", &self.pool)
+            .finish()
+    }
+}
+
+impl<S> Clone for UpstreamHealthService<S> {
+    fn clone(&self) -> Self {
+        Self {
+            pool: self.pool.clone(),
+        }
+    }
+}
+
+impl<State, S> Service<State, Request> for UpstreamHealthService<S>
+where
+    State: Clone + Send + Sync + 'static,
+    S: Send + Sync + 'static,
+{
+    type Response = Response;
+    type Error = Infallible;
+
+    async fn serve(
+        &self,
+        _ctx: Context<State>,
+        _req: Request,
+    ) -> Result<Self::Response, Self::Error> {
+        let health = self.pool.health();
+        let status = if health.iter().any(|upstream| upstream.healthy) {
+            StatusCode::OK
+        } else {
+            StatusCode::SERVICE_UNAVAILABLE
+        };
+        let upstreams: Vec<_> = health
+            .into_iter()
+            .map(|upstream| {
+                json!({
+                    "id": upstream.id,
+                    "healthy": upstream.healthy,
+                    "in_flight": upstream.in_flight,
+                    "consecutive_successes": upstream.consecutive_successes,
+                    "consecutive_failures": upstream.consecutive_failures,
+                    "last_error": upstream.last_error,
+                })
+            })
+            .collect();
+        Ok((status, Json(upstreams)).into_response())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{dep::http_body_util::BodyExt, Body};
+    use rama_core::service::balance::Upstream;
+
+    async fn get_status(svc: &UpstreamHealthService<()>) -> (StatusCode, serde_json::Value) {
+        let resp = svc
+            .serve(Context::default(), Request::new(Body::empty()))
+            .await
+            .unwrap();
+        let status = resp.status();
+        let body = resp.into_body().collect().await.unwrap().to_bytes();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_upstream_health_service() {
+        let pool = UpstreamPool::try_new([Upstream::new("a", ()), Upstream::new("b", ())]).unwrap();
+        let svc = UpstreamHealthService::new(pool.clone());
+
+        pool.upstreams()[0].set_healthy(false);
+        let (status, value) = get_status(&svc).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(value[0]["id"], "a");
+        assert_eq!(value[0]["healthy"], false);
+        assert_eq!(value[1]["healthy"], true);
+
+        pool.upstreams()[1].set_healthy(false);
+        let (status, _) = get_status(&svc).await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    }
+}
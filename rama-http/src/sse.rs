@@ -0,0 +1,544 @@
+//! Server-Sent Events (SSE) responses.
+//!
+//! An [`Sse`] response streams [`Event`]s produced by an async [`Stream`]
+//! to the client, encoded in the `text/event-stream` wire format as defined in
+//! [the HTML Living Standard](https://html.spec.whatwg.org/multipage/server-sent-events.html).
+//!
+//! # Example
+//!
+//! ```
+//! use rama_http::sse::{Event, KeepAlive, Sse};
+//! use rama_http::{header, IntoResponse, Response};
+//! use std::{convert::Infallible, time::Duration};
+//!
+//! fn events() -> Response {
+//!     let stream = futures_lite::stream::iter((0..3).map(|n| {
+//!         Ok::<_, Infallible>(Event::new().with_event("tick").with_data(n.to_string()))
+//!     }));
+//!     Sse::new(stream)
+//!         .with_keep_alive(KeepAlive::new().with_interval(Duration::from_secs(10)))
+//!         .into_response()
+//! }
+//!
+//! let resp = events();
+//! assert_eq!(resp.headers()[header::CONTENT_TYPE], "text/event-stream");
+//! ```
+
+use crate::dep::mime;
+use crate::{header, Body, HeaderValue, IntoResponse, Response};
+use bytes::{BufMut, Bytes, BytesMut};
+use futures_lite::Stream;
+use pin_project_lite::pin_project;
+use rama_core::error::BoxError;
+use rama_core::graceful::ShutdownGuard;
+use serde::Serialize;
+use std::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+use tokio::time::{Instant, Sleep};
+
+/// A single Server-Sent Event.
+///
+/// All fields are optional, an empty event is
+/// still sent to the client (and ignored by it).
+#[derive(Debug, Clone, Default)]
+pub struct Event {
+    id: Option<String>,
+    event: Option<String>,
+    data: Option<String>,
+    retry: Option<Duration>,
+    comment: Option<String>,
+}
+
+impl Event {
+    /// Create a new empty [`Event`].
+    pub const fn new() -> Self {
+        Self {
+            id: None,
+            event: None,
+            data: None,
+            retry: None,
+            comment: None,
+        }
+    }
+
+    /// Set the data of the event.
+    ///
+    /// Data containing newlines is sent as multiple `data` lines,
+    /// which the client joins back together.
+    pub fn with_data(mut self, data: impl Into<String>) -> Self {
+        self.data = Some(data.into());
+        self
+    }
+
+    /// Set the data of the event.
+    ///
+    /// Data containing newlines is sent as multiple `data` lines,
+    /// which the client joins back together.
+    pub fn set_data(&mut self, data: impl Into<String>) -> &mut Self {
+        self.data = Some(data.into());
+        self
+    }
+
+    /// Set the data of the event as the json encoding of the given value.
+    pub fn try_with_json_data<T: Serialize>(mut self, data: &T) -> Result<Self, serde_json::Error> {
+        self.data = Some(serde_json::to_string(data)?);
+        Ok(self)
+    }
+
+    /// Set the data of the event as the json encoding of the given value.
+    pub fn try_set_json_data<T: Serialize>(
+        &mut self,
+        data: &T,
+    ) -> Result<&mut Self, serde_json::Error> {
+        self.data = Some(serde_json::to_string(data)?);
+        Ok(self)
+    }
+
+    /// Set the name of the event, dispatched by the client as the event type.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the name contains a newline or carriage return.
+    pub fn with_event(mut self, event: impl Into<String>) -> Self {
+        self.event = Some(single_line("event", event.into()));
+        self
+    }
+
+    /// Set the name of the event, dispatched by the client as the event type.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the name contains a newline or carriage return.
+    pub fn set_event(&mut self, event: impl Into<String>) -> &mut Self {
+        self.event = Some(single_line("event", event.into()));
+        self
+    }
+
+    /// Set the id of the event, used by the client as `Last-Event-ID` when reconnecting.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the id contains a newline, carriage return or null character.
+    pub fn with_id(mut self, id: impl Into<String>) -> Self {
+        self.id = Some(event_id(id.into()));
+        self
+    }
+
+    /// Set the id of the event, used by the client as `Last-Event-ID` when reconnecting.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the id contains a newline, carriage return or null character.
+    pub fn set_id(&mut self, id: impl Into<String>) -> &mut Self {
+        self.id = Some(event_id(id.into()));
+        self
+    }
+
+    /// Set the time the client has to wait before reconnecting
+    /// in case the connection is lost.
+    pub fn with_retry(mut self, retry: Duration) -> Self {
+        self.retry = Some(retry);
+        self
+    }
+
+    /// Set the time the client has to wait before reconnecting
+    /// in case the connection is lost.
+    pub fn set_retry(&mut self, retry: Duration) -> &mut Self {
+        self.retry = Some(retry);
+        self
+    }
+
+    /// Set a comment for the event, ignored by the client.
+    ///
+    /// Comments containing newlines are sent as multiple comment lines.
+    pub fn with_comment(mut self, comment: impl Into<String>) -> Self {
+        self.comment = Some(comment.into());
+        self
+    }
+
+    /// Set a comment for the event, ignored by the client.
+    ///
+    /// Comments containing newlines are sent as multiple comment lines.
+    pub fn set_comment(&mut self, comment: impl Into<String>) -> &mut Self {
+        self.comment = Some(comment.into());
+        self
+    }
+
+    /// Encode the event in the `text/event-stream` wire format.
+    pub fn encode(&self) -> Bytes {
+        let mut buf = BytesMut::new();
+        if let Some(comment) = &self.comment {
+            for line in comment.lines() {
+                write_field(&mut buf, "", line);
+            }
+        }
+        if let Some(event) = &self.event {
+            write_field(&mut buf, "event", event);
+        }
+        if let Some(id) = &self.id {
+            write_field(&mut buf, "id", id);
+        }
+        if let Some(retry) = self.retry {
+            write_field(&mut buf, "retry", &retry.as_millis().to_string());
+        }
+        if let Some(data) = &self.data {
+            if data.is_empty() {
+                write_field(&mut buf, "data", "");
+            }
+            for line in data.lines() {
+                write_field(&mut buf, "data", line);
+            }
+        }
+        buf.put_u8(b'\n');
+        buf.freeze()
+    }
+}
+
+fn write_field(buf: &mut BytesMut, name: &str, value: &str) {
+    buf.put_slice(name.as_bytes());
+    buf.put_u8(b':');
+    if !value.is_empty() {
+        // a leading space is stripped by the client,
+        // so it is always added to preserve one which is part of the value
+        buf.put_u8(b' ');
+        buf.put_slice(value.as_bytes());
+    }
+    buf.put_u8(b'\n');
+}
+
+fn single_line(field: &str, value: String) -> String {
+    assert!(
+        !value.contains(['\n', '\r']),
+        "sse event {field} cannot contain newlines or carriage returns"
+    );
+    value
+}
+
+fn event_id(value: String) -> String {
+    assert!(
+        !value.contains('\0'),
+        "sse event id cannot contain null characters"
+    );
+    single_line("id", value)
+}
+
+/// Configuration of the keep-alive pings sent for an [`Sse`] stream.
+///
+/// A ping is a comment-only event, sent in case no event
+/// was sent during the configured interval, such that
+/// (proxy) connections are not closed because of inactivity.
+#[derive(Debug, Clone)]
+pub struct KeepAlive {
+    interval: Duration,
+    ping: Bytes,
+}
+
+impl Default for KeepAlive {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl KeepAlive {
+    /// Create a new [`KeepAlive`], sending an empty comment every 15 seconds.
+    pub fn new() -> Self {
+        Self {
+            interval: Duration::from_secs(15),
+            ping: Bytes::from_static(b":\n\n"),
+        }
+    }
+
+    /// Set the interval after which a ping is sent, in case no other event was sent.
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Set the interval after which a ping is sent, in case no other event was sent.
+    pub fn set_interval(&mut self, interval: Duration) -> &mut Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Set the comment text sent as a ping.
+    ///
+    /// Text containing newlines is sent as multiple comment lines.
+    pub fn with_text(mut self, text: impl Into<String>) -> Self {
+        self.ping = Event::new().with_comment(text).encode();
+        self
+    }
+
+    /// Set the comment text sent as a ping.
+    ///
+    /// Text containing newlines is sent as multiple comment lines.
+    pub fn set_text(&mut self, text: impl Into<String>) -> &mut Self {
+        self.ping = Event::new().with_comment(text).encode();
+        self
+    }
+}
+
+/// A Server-Sent Events response, streaming the [`Event`]s of the inner [`Stream`].
+///
+/// The response ends once the inner stream ends,
+/// or once the (optional) [`ShutdownGuard`] gets cancelled,
+/// which allows a graceful shutdown to complete even while clients are still connected.
+///
+/// See [the module docs](self) for an example.
+pub struct Sse<S> {
+    stream: S,
+    keep_alive: Option<KeepAlive>,
+    guard: Option<ShutdownGuard>,
+}
+
+impl<S> fmt::Debug for Sse<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Sse")
+            .field("stream", &format_args!("{}", std::any::type_name::<S>()))
+            .field("keep_alive", &self.keep_alive)
+            .field("guard", &self.guard)
+            .finish()
+    }
+}
+
+impl<S> Sse<S> {
+    /// Create a new [`Sse`] response streaming the events of the given stream.
+    pub fn new(stream: S) -> Self {
+        Self {
+            stream,
+            keep_alive: None,
+            guard: None,
+        }
+    }
+
+    /// Send keep-alive pings while no events are sent.
+    pub fn with_keep_alive(mut self, keep_alive: KeepAlive) -> Self {
+        self.keep_alive = Some(keep_alive);
+        self
+    }
+
+    /// Send keep-alive pings while no events are sent.
+    pub fn set_keep_alive(&mut self, keep_alive: KeepAlive) -> &mut Self {
+        self.keep_alive = Some(keep_alive);
+        self
+    }
+
+    /// Set the guard used to end the stream once a graceful shutdown is initiated.
+    pub fn with_guard(mut self, guard: ShutdownGuard) -> Self {
+        self.guard = Some(guard);
+        self
+    }
+
+    /// Maybe set the guard used to end the stream once a graceful shutdown is initiated.
+    ///
+    /// Useful in combination with [`rama_core::Context::guard`].
+    pub fn maybe_with_guard(mut self, guard: Option<ShutdownGuard>) -> Self {
+        self.guard = guard;
+        self
+    }
+
+    /// Set the guard used to end the stream once a graceful shutdown is initiated.
+    pub fn set_guard(&mut self, guard: ShutdownGuard) -> &mut Self {
+        self.guard = Some(guard);
+        self
+    }
+}
+
+impl<S, E> IntoResponse for Sse<S>
+where
+    S: Stream<Item = Result<Event, E>> + Send + 'static,
+    E: Into<BoxError>,
+{
+    fn into_response(self) -> Response {
+        let keep_alive = self.keep_alive.map(|keep_alive| KeepAliveTimer {
+            keep_alive,
+            sleep: None,
+        });
+        let shutdown = self
+            .guard
+            .map(|guard| -> ShutdownFuture { Box::pin(async move { guard.cancelled().await }) });
+        let body = Body::from_stream(SseStream {
+            stream: self.stream,
+            keep_alive,
+            shutdown,
+        });
+        (
+            [
+                (
+                    header::CONTENT_TYPE,
+                    HeaderValue::from_static(mime::TEXT_EVENT_STREAM.as_ref()),
+                ),
+                (header::CACHE_CONTROL, HeaderValue::from_static("no-cache")),
+            ],
+            body,
+        )
+            .into_response()
+    }
+}
+
+type ShutdownFuture = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+struct KeepAliveTimer {
+    keep_alive: KeepAlive,
+    // created lazily, such that the response can be created outside of a runtime
+    sleep: Option<Pin<Box<Sleep>>>,
+}
+
+impl KeepAliveTimer {
+    fn reset(&mut self) {
+        if let Some(sleep) = self.sleep.as_mut() {
+            sleep
+                .as_mut()
+                .reset(Instant::now() + self.keep_alive.interval);
+        }
+    }
+
+    fn poll_ping(&mut self, cx: &mut Context<'_>) -> Poll<Bytes> {
+        let interval = self.keep_alive.interval;
+        let sleep = self
+            .sleep
+            .get_or_insert_with(|| Box::pin(tokio::time::sleep(interval)));
+        std::task::ready!(sleep.as_mut().poll(cx));
+        self.reset();
+        Poll::Ready(self.keep_alive.ping.clone())
+    }
+}
+
+pin_project! {
+    struct SseStream<S> {
+        #[pin]
+        stream: S,
+        keep_alive: Option<KeepAliveTimer>,
+        shutdown: Option<ShutdownFuture>,
+    }
+}
+
+impl<S, E> Stream for SseStream<S>
+where
+    S: Stream<Item = Result<Event, E>>,
+    E: Into<BoxError>,
+{
+    type Item = Result<Bytes, BoxError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+
+        if let Some(shutdown) = this.shutdown.as_mut() {
+            if shutdown.as_mut().poll(cx).is_ready() {
+                tracing::trace!("sse stream: graceful shutdown initiated, ending stream");
+                *this.shutdown = None;
+                *this.keep_alive = None;
+                return Poll::Ready(None);
+            }
+        }
+
+        match this.stream.poll_next(cx) {
+            Poll::Ready(Some(Ok(event))) => {
+                if let Some(keep_alive) = this.keep_alive.as_mut() {
+                    keep_alive.reset();
+                }
+                Poll::Ready(Some(Ok(event.encode())))
+            }
+            Poll::Ready(Some(Err(err))) => Poll::Ready(Some(Err(err.into()))),
+            Poll::Ready(None) => Poll::Ready(None),
+            Poll::Pending => {
+                let Some(keep_alive) = this.keep_alive.as_mut() else {
+                    return Poll::Pending;
+                };
+                keep_alive.poll_ping(cx).map(|ping| Some(Ok(ping)))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::BodyExtractExt;
+    use futures_lite::StreamExt;
+    use rama_core::graceful::Shutdown;
+    use std::convert::Infallible;
+
+    #[test]
+    fn test_event_encode() {
+        let event = Event::new()
+            .with_comment("hello\nworld")
+            .with_event("update")
+            .with_id("42")
+            .with_retry(Duration::from_secs(3))
+            .with_data("line 1\nline 2");
+        assert_eq!(
+            event.encode(),
+            ": hello\n: world\nevent: update\nid: 42\nretry: 3000\ndata: line 1\ndata: line 2\n\n"
+        );
+
+        assert_eq!(Event::new().with_data("").encode(), "data:\n\n");
+        assert_eq!(
+            Event::new()
+                .try_with_json_data(&serde_json::json!({"a": 1}))
+                .unwrap()
+                .encode(),
+            "data: {\"a\":1}\n\n"
+        );
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_event_name_with_newline() {
+        let _ = Event::new().with_event("a\nb");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_sse_keep_alive() {
+        let (tx, rx) = tokio::sync::mpsc::unbounded_channel::<Result<Event, Infallible>>();
+        let stream = futures_lite::stream::unfold(rx, |mut rx| async move {
+            rx.recv().await.map(|item| (item, rx))
+        });
+
+        let resp = Sse::new(stream)
+            .with_keep_alive(
+                KeepAlive::new()
+                    .with_interval(Duration::from_secs(1))
+                    .with_text("ping"),
+            )
+            .into_response();
+        assert_eq!(resp.headers()[header::CACHE_CONTROL], "no-cache");
+
+        tokio::spawn(async move {
+            tx.send(Ok(Event::new().with_data("a"))).unwrap();
+            tokio::time::sleep(Duration::from_millis(2500)).await;
+            tx.send(Ok(Event::new().with_data("b"))).unwrap();
+        });
+
+        let body = resp.into_body().try_into_string().await.unwrap();
+        assert_eq!(body, "data: a\n\n: ping\n\n: ping\n\ndata: b\n\n");
+    }
+
+    #[tokio::test]
+    async fn test_sse_graceful_shutdown() {
+        let (tx, rx) = tokio::sync::oneshot::channel::<()>();
+        let shutdown = Shutdown::new(async move {
+            let _ = rx.await;
+        });
+
+        let stream = futures_lite::stream::once(Ok::<_, Infallible>(Event::new().with_data("a")))
+            .chain(futures_lite::stream::pending());
+        let resp = Sse::new(stream)
+            .with_guard(shutdown.guard())
+            .into_response();
+
+        let body = tokio::spawn(resp.into_body().try_into_string());
+        tokio::task::yield_now().await;
+        tx.send(()).unwrap();
+        shutdown
+            .shutdown_with_limit(Duration::from_secs(5))
+            .await
+            .unwrap();
+
+        assert_eq!(body.await.unwrap().unwrap(), "data: a\n\n");
+    }
+}
@@ -0,0 +1,14 @@
+use rama_core::Context;
+use rama_net::{forwarded::Forwarded, stream::SocketInfo};
+use std::net::IpAddr;
+
+/// Get the ip of the client, as found in the [`Forwarded`] information,
+/// falling back to the peer address of the [`SocketInfo`].
+///
+/// Only use the [`Forwarded`] information of trusted proxies,
+/// as it is otherwise easily spoofed.
+pub(crate) fn client_ip<State, Request>(ctx: &Context<State>, _req: &Request) -> Option<IpAddr> {
+    ctx.get::<Forwarded>()
+        .and_then(|forwarded| forwarded.client_ip())
+        .or_else(|| ctx.get::<SocketInfo>().map(|info| info.peer_addr().ip()))
+}
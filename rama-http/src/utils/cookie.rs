@@ -0,0 +1,97 @@
+use crate::HeaderValue;
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+/// The `SameSite` attribute of a cookie set by middleware,
+/// e.g. the CSRF, session or sticky session cookie.
+pub enum SameSite {
+    #[default]
+    /// Cookie is only sent for same-site requests.
+    Strict,
+    /// Cookie is also sent for top-level cross-site navigations (e.g. following a link).
+    Lax,
+    /// Cookie is sent for all requests, requires the cookie to be secure.
+    None,
+}
+
+impl SameSite {
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            SameSite::Strict => "Strict",
+            SameSite::Lax => "Lax",
+            SameSite::None => "None",
+        }
+    }
+}
+
+/// The attributes of a cookie set by middleware, used to create
+/// the value of its `Set-Cookie` header. The cookie is always set for path `/`.
+#[derive(Debug, Clone)]
+pub(crate) struct SetCookie<'a> {
+    pub(crate) name: &'a str,
+    pub(crate) value: &'a str,
+    pub(crate) max_age: Option<Duration>,
+    pub(crate) http_only: bool,
+    pub(crate) same_site: SameSite,
+    pub(crate) secure: bool,
+}
+
+impl SetCookie<'_> {
+    /// Create the `Set-Cookie` header value,
+    /// or `None` in case the name or value contain invalid characters.
+    pub(crate) fn header_value(&self) -> Option<HeaderValue> {
+        let mut cookie = format!("{}={}; Path=/", self.name, self.value);
+        if let Some(max_age) = self.max_age {
+            cookie.push_str(&format!("; Max-Age={}", max_age.as_secs()));
+        }
+        if self.http_only {
+            cookie.push_str("; HttpOnly");
+        }
+        cookie.push_str("; SameSite=");
+        cookie.push_str(self.same_site.as_str());
+        if self.secure {
+            cookie.push_str("; Secure");
+        }
+        HeaderValue::try_from(cookie).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_set_cookie_header_value() {
+        let cookie = SetCookie {
+            name: "session",
+            value: "abc",
+            max_age: Some(Duration::from_secs(60)),
+            http_only: true,
+            same_site: SameSite::Lax,
+            secure: true,
+        };
+        assert_eq!(
+            cookie.header_value().unwrap(),
+            "session=abc; Path=/; Max-Age=60; HttpOnly; SameSite=Lax; Secure"
+        );
+
+        let cookie = SetCookie {
+            name: "csrf",
+            value: "abc",
+            max_age: None,
+            http_only: false,
+            same_site: SameSite::Strict,
+            secure: false,
+        };
+        assert_eq!(
+            cookie.header_value().unwrap(),
+            "csrf=abc; Path=/; SameSite=Strict"
+        );
+
+        let cookie = SetCookie {
+            value: "a\nb",
+            ..cookie
+        };
+        assert!(cookie.header_value().is_none());
+    }
+}
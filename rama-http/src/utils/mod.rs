@@ -1,9 +1,21 @@
 //! Utilities for HTTP.
 
+mod client_ip;
+pub(crate) use client_ip::client_ip;
+
+mod cookie;
+#[doc(inline)]
+pub use cookie::SameSite;
+pub(crate) use cookie::SetCookie;
+
 mod header_value;
 #[doc(inline)]
 pub use header_value::{HeaderValueErr, HeaderValueGetter};
 
+mod replay_body;
+#[doc(inline)]
+pub use replay_body::{BodyNotReplayable, ReplayBody, ReplayConfig};
+
 #[doc(hidden)]
 #[macro_use]
 pub(crate) mod macros;
@@ -0,0 +1,626 @@
+use crate::dep::http_body::{self, Frame, SizeHint};
+use crate::dep::http_body_util::{combinators::UnsyncBoxBody, BodyExt};
+use crate::HeaderMap;
+use bytes::{Buf, Bytes, BytesMut};
+use rama_core::error::BoxError;
+use std::{
+    fmt,
+    pin::Pin,
+    sync::{Mutex, PoisonError},
+    task::{Context, Poll},
+};
+
+#[cfg(feature = "replay-disk")]
+use {
+    crate::dep::http_body_util::{BodyStream, StreamBody},
+    futures_lite::{Stream, StreamExt},
+    std::{future::Future, io, sync::Arc},
+    tokio::io::AsyncWriteExt,
+    tokio_util::io::ReaderStream,
+};
+
+#[derive(Debug, Clone, Default)]
+/// Configuration of how a [`ReplayBody`] buffers the body it wraps.
+///
+/// By default bodies are buffered in memory, without any limit.
+/// Once a [memory limit] is set, larger bodies are either spilled
+/// to a temporary file, in case [spilling to disk] is enabled,
+/// or are streamed as-is, in which case the body can only be sent once.
+/// The same goes for spilled bodies which exceed the [disk limit].
+///
+/// Spilling to disk requires the `replay-disk` feature.
+///
+/// [memory limit]: ReplayConfig::with_memory_limit
+/// [spilling to disk]: ReplayConfig::with_spill_to_disk
+/// [disk limit]: ReplayConfig::with_disk_limit
+pub struct ReplayConfig {
+    memory_limit: Option<usize>,
+    #[cfg(feature = "replay-disk")]
+    spill_to_disk: bool,
+    #[cfg(feature = "replay-disk")]
+    disk_limit: Option<u64>,
+}
+
+impl ReplayConfig {
+    /// Create a new [`ReplayConfig`], buffering bodies
+    /// in memory without any limit.
+    pub const fn new() -> Self {
+        Self {
+            memory_limit: None,
+            #[cfg(feature = "replay-disk")]
+            spill_to_disk: false,
+            #[cfg(feature = "replay-disk")]
+            disk_limit: None,
+        }
+    }
+
+    /// Set the maximum amount of bytes which are buffered in memory.
+    pub const fn with_memory_limit(mut self, limit: usize) -> Self {
+        self.memory_limit = Some(limit);
+        self
+    }
+
+    /// Set the maximum amount of bytes which are buffered in memory.
+    pub fn set_memory_limit(&mut self, limit: usize) -> &mut Self {
+        self.memory_limit = Some(limit);
+        self
+    }
+
+    #[cfg(feature = "replay-disk")]
+    /// Set whether bodies larger than the memory limit
+    /// are written to a temporary file, such that they remain replayable.
+    pub const fn with_spill_to_disk(mut self, spill: bool) -> Self {
+        self.spill_to_disk = spill;
+        self
+    }
+
+    #[cfg(feature = "replay-disk")]
+    /// Set whether bodies larger than the memory limit
+    /// are written to a temporary file, such that they remain replayable.
+    pub fn set_spill_to_disk(&mut self, spill: bool) -> &mut Self {
+        self.spill_to_disk = spill;
+        self
+    }
+
+    #[cfg(feature = "replay-disk")]
+    /// Set the maximum amount of bytes which are written to a temporary file
+    /// when [spilling to disk](Self::with_spill_to_disk).
+    ///
+    /// Bodies exceeding this limit are streamed as-is, and can thus only be sent once.
+    pub const fn with_disk_limit(mut self, limit: u64) -> Self {
+        self.disk_limit = Some(limit);
+        self
+    }
+
+    #[cfg(feature = "replay-disk")]
+    /// Set the maximum amount of bytes which are written to a temporary file
+    /// when [spilling to disk](Self::with_spill_to_disk).
+    ///
+    /// See [`Self::with_disk_limit`] for more information.
+    pub fn set_disk_limit(&mut self, limit: u64) -> &mut Self {
+        self.disk_limit = Some(limit);
+        self
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[non_exhaustive]
+/// Error returned when replaying a [`ReplayBody`] which
+/// was too large to be buffered and thus could only be sent once.
+pub struct BodyNotReplayable;
+
+impl fmt::Display for BodyNotReplayable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("body is not replayable: one-shot stream exceeded the buffer limit")
+    }
+}
+
+impl std::error::Error for BodyNotReplayable {}
+
+/// A request body which can be sent multiple times,
+/// e.g. to retry a request or to follow a redirect.
+///
+/// Created using [`ReplayBody::buffer`], according to a [`ReplayConfig`].
+/// The body is buffered in memory, or in a temporary file (with the `replay-disk` feature),
+/// and each clone
+/// replays the body from the start. Bodies which are too large to be buffered
+/// are streamed as-is instead: these can only be sent once, and their clones
+/// fail with [`BodyNotReplayable`] when polled, which can be checked upfront
+/// using [`ReplayBody::is_replayable`].
+///
+/// Trailers of the original body are buffered as well,
+/// and are sent after the data of each replay.
+///
+/// # Example
+///
+/// ```
+/// use rama_http::utils::{ReplayBody, ReplayConfig};
+/// use rama_http::{Body, BodyExtractExt};
+///
+/// # #[tokio::main]
+/// # async fn main() {
+/// let body = ReplayBody::buffer(Body::from("hello"), &ReplayConfig::new())
+///     .await
+///     .unwrap();
+/// assert!(body.is_replayable());
+///
+/// let replay = body.clone();
+/// assert_eq!(body.try_into_string().await.unwrap(), "hello");
+/// assert_eq!(replay.try_into_string().await.unwrap(), "hello");
+/// # }
+/// ```
+pub struct ReplayBody {
+    source: Source,
+    read: Read,
+    trailers: Option<HeaderMap>,
+}
+
+enum Source {
+    Memory(Option<Bytes>),
+    #[cfg(feature = "replay-disk")]
+    File {
+        path: Arc<tempfile::TempPath>,
+        len: u64,
+    },
+    OneShot,
+}
+
+enum Read {
+    Memory,
+    #[cfg(feature = "replay-disk")]
+    File(FileRead),
+    OneShot {
+        prefix: Option<Bytes>,
+        rest: OneShotBody,
+    },
+    NotReplayable,
+}
+
+#[cfg(feature = "replay-disk")]
+/// Read state of a body spilled to a temporary file,
+/// which is (re)opened for each replay.
+enum FileRead {
+    Idle,
+    Opening(Pin<Box<dyn Future<Output = io::Result<tokio::fs::File>> + Send + Sync>>),
+    Streaming(ReaderStream<tokio::fs::File>),
+}
+
+/// Remainder of a body which could not be buffered.
+///
+/// The mutex is only ever accessed mutably, it merely makes the body `Sync`.
+struct OneShotBody(Mutex<UnsyncBoxBody<Bytes, BoxError>>);
+
+impl OneShotBody {
+    fn poll_frame(&mut self, cx: &mut Context<'_>) -> Poll<Option<Result<Frame<Bytes>, BoxError>>> {
+        let body = self.0.get_mut().unwrap_or_else(PoisonError::into_inner);
+        http_body::Body::poll_frame(Pin::new(body), cx)
+    }
+}
+
+impl fmt::Debug for ReplayBody {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut d = f.debug_struct("ReplayBody");
+        match &self.source {
+            Source::Memory(bytes) => d.field("memory", bytes),
+            #[cfg(feature = "replay-disk")]
+            Source::File { path, len } => d.field("file", &path.to_path_buf()).field("len", len),
+            Source::OneShot => d.field("one_shot", &true),
+        };
+        d.finish()
+    }
+}
+
+impl Clone for ReplayBody {
+    fn clone(&self) -> Self {
+        match &self.source {
+            Source::Memory(bytes) => Self {
+                trailers: self.trailers.clone(),
+                ..Self::memory(bytes.clone())
+            },
+            #[cfg(feature = "replay-disk")]
+            Source::File { path, len } => Self {
+                source: Source::File {
+                    path: path.clone(),
+                    len: *len,
+                },
+                read: Read::File(FileRead::Idle),
+                trailers: self.trailers.clone(),
+            },
+            Source::OneShot => Self {
+                source: Source::OneShot,
+                read: Read::NotReplayable,
+                trailers: None,
+            },
+        }
+    }
+}
+
+impl Default for ReplayBody {
+    fn default() -> Self {
+        Self::memory(None)
+    }
+}
+
+impl From<Bytes> for ReplayBody {
+    fn from(bytes: Bytes) -> Self {
+        Self::memory(Some(bytes))
+    }
+}
+
+impl ReplayBody {
+    fn memory(bytes: Option<Bytes>) -> Self {
+        Self {
+            source: Source::Memory(bytes),
+            read: Read::Memory,
+            trailers: None,
+        }
+    }
+
+    /// Buffer the given body according to the [`ReplayConfig`],
+    /// such that it can be replayed if small enough.
+    ///
+    /// See [`ReplayBody`] for more information.
+    pub async fn buffer<B>(body: B, config: &ReplayConfig) -> Result<Self, BoxError>
+    where
+        B: http_body::Body<Data: Send + 'static, Error: Into<BoxError>> + Send + 'static,
+    {
+        let mut body = body
+            .map_frame(|frame| frame.map_data(|mut data| data.copy_to_bytes(data.remaining())))
+            .map_err(Into::into)
+            .boxed_unsync();
+
+        let mut buffer = BytesMut::new();
+        let mut trailers = None;
+        while let Some(frame) = body.frame().await {
+            let Some(data) = into_data(frame?, &mut trailers) else {
+                continue;
+            };
+            buffer.extend_from_slice(&data);
+            if config
+                .memory_limit
+                .map_or(true, |limit| buffer.len() <= limit)
+            {
+                continue;
+            }
+
+            #[cfg(feature = "replay-disk")]
+            if config.spill_to_disk {
+                return Self::spill(buffer.freeze(), body, config.disk_limit).await;
+            }
+            return Ok(Self::one_shot(Some(buffer.freeze()), body));
+        }
+
+        Ok(Self {
+            trailers,
+            ..Self::memory((!buffer.is_empty()).then(|| buffer.freeze()))
+        })
+    }
+
+    fn one_shot(prefix: Option<Bytes>, rest: UnsyncBoxBody<Bytes, BoxError>) -> Self {
+        Self {
+            source: Source::OneShot,
+            read: Read::OneShot {
+                prefix,
+                rest: OneShotBody(Mutex::new(rest)),
+            },
+            trailers: None,
+        }
+    }
+
+    #[cfg(feature = "replay-disk")]
+    async fn spill(
+        prefix: Bytes,
+        mut body: UnsyncBoxBody<Bytes, BoxError>,
+        disk_limit: Option<u64>,
+    ) -> Result<Self, BoxError> {
+        let exceeds = |len: u64| disk_limit.is_some_and(|limit| len > limit);
+        if exceeds(prefix.len() as u64) {
+            return Ok(Self::one_shot(Some(prefix), body));
+        }
+
+        let (file, path) = tempfile::NamedTempFile::new()?.into_parts();
+        let mut file = tokio::fs::File::from_std(file);
+
+        let mut len = prefix.len() as u64;
+        let mut trailers = None;
+        file.write_all(&prefix).await?;
+        while let Some(frame) = body.frame().await {
+            let Some(data) = into_data(frame?, &mut trailers) else {
+                continue;
+            };
+            if exceeds(len + data.len() as u64) {
+                // stream what was spilled so far, followed by the remainder of the body
+                file.flush().await?;
+                let spilled = spilled_stream(tokio::fs::File::open(&path).await?, path);
+                let rest =
+                    futures_lite::stream::once(Ok(Frame::data(data))).chain(BodyStream::new(body));
+                return Ok(Self::one_shot(
+                    None,
+                    StreamBody::new(spilled.chain(rest)).boxed_unsync(),
+                ));
+            }
+            len += data.len() as u64;
+            file.write_all(&data).await?;
+        }
+        file.flush().await?;
+
+        Ok(Self {
+            source: Source::File {
+                path: Arc::new(path),
+                len,
+            },
+            read: Read::File(FileRead::Idle),
+            trailers,
+        })
+    }
+
+    /// Returns `true` if this body can be sent more than once,
+    /// meaning its clones replay the same body.
+    pub fn is_replayable(&self) -> bool {
+        !matches!(self.source, Source::OneShot)
+    }
+
+    /// Clone this body, returning `None` in case it is not replayable.
+    pub fn try_clone(&self) -> Option<Self> {
+        self.is_replayable().then(|| self.clone())
+    }
+
+    /// Returns the length of this body in bytes,
+    /// or `None` in case it is not replayable.
+    pub fn len(&self) -> Option<u64> {
+        match &self.source {
+            Source::Memory(bytes) => {
+                Some(bytes.as_ref().map(|b| b.len() as u64).unwrap_or_default())
+            }
+            #[cfg(feature = "replay-disk")]
+            Source::File { len, .. } => Some(*len),
+            Source::OneShot => None,
+        }
+    }
+
+    /// Returns `true` if this body is known to be empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == Some(0)
+    }
+
+    /// Returns `true` if this body was spilled to a temporary file.
+    pub fn is_spilled(&self) -> bool {
+        match self.source {
+            #[cfg(feature = "replay-disk")]
+            Source::File { .. } => true,
+            Source::Memory(_) | Source::OneShot => false,
+        }
+    }
+
+    /// Turn this body into bytes, in case it is buffered in memory.
+    ///
+    /// The trailers of the body, if any, are dropped.
+    pub fn into_bytes(self) -> Option<Bytes> {
+        match self.source {
+            Source::Memory(bytes) => bytes,
+            #[cfg(feature = "replay-disk")]
+            Source::File { .. } => None,
+            Source::OneShot => None,
+        }
+    }
+}
+
+impl http_body::Body for ReplayBody {
+    type Data = Bytes;
+    type Error = BoxError;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let this = self.get_mut();
+        match &mut this.read {
+            Read::Memory => match &mut this.source {
+                Source::Memory(bytes) => match bytes.take() {
+                    Some(bytes) => Poll::Ready(Some(Ok(Frame::data(bytes)))),
+                    None => Poll::Ready(this.trailers.take().map(|t| Ok(Frame::trailers(t)))),
+                },
+                #[cfg(feature = "replay-disk")]
+                Source::File { .. } => unreachable!("memory read of file source"),
+                Source::OneShot => unreachable!("memory read of one-shot source"),
+            },
+            #[cfg(feature = "replay-disk")]
+            Read::File(file) => loop {
+                match file {
+                    FileRead::Idle => {
+                        let Source::File { path, .. } = &this.source else {
+                            unreachable!("file read of file source")
+                        };
+                        let path = path.to_path_buf();
+                        *file = FileRead::Opening(Box::pin(tokio::fs::File::open(path)));
+                    }
+                    FileRead::Opening(open) => match std::task::ready!(open.as_mut().poll(cx)) {
+                        Ok(opened) => *file = FileRead::Streaming(ReaderStream::new(opened)),
+                        Err(err) => return Poll::Ready(Some(Err(err.into()))),
+                    },
+                    FileRead::Streaming(stream) => {
+                        return match std::task::ready!(Pin::new(stream).poll_next(cx)) {
+                            Some(Ok(data)) => Poll::Ready(Some(Ok(Frame::data(data)))),
+                            Some(Err(err)) => Poll::Ready(Some(Err(err.into()))),
+                            None => {
+                                Poll::Ready(this.trailers.take().map(|t| Ok(Frame::trailers(t))))
+                            }
+                        };
+                    }
+                }
+            },
+            Read::OneShot { prefix, rest } => {
+                if let Some(prefix) = prefix.take() {
+                    return Poll::Ready(Some(Ok(Frame::data(prefix))));
+                }
+                rest.poll_frame(cx)
+            }
+            Read::NotReplayable => Poll::Ready(Some(Err(BodyNotReplayable.into()))),
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        match &self.read {
+            Read::Memory => matches!(self.source, Source::Memory(None)) && self.trailers.is_none(),
+            #[cfg(feature = "replay-disk")]
+            Read::File(_) => false,
+            Read::OneShot { .. } | Read::NotReplayable => false,
+        }
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        match (&self.source, &self.read) {
+            (Source::Memory(bytes), _) => {
+                SizeHint::with_exact(bytes.as_ref().map(|b| b.len() as u64).unwrap_or_default())
+            }
+            #[cfg(feature = "replay-disk")]
+            (Source::File { len, .. }, Read::File(FileRead::Idle)) => SizeHint::with_exact(*len),
+            _ => SizeHint::default(),
+        }
+    }
+}
+
+impl From<ReplayBody> for crate::Body {
+    fn from(body: ReplayBody) -> Self {
+        match body.source {
+            Source::Memory(Some(bytes)) if body.trailers.is_none() => bytes.into(),
+            Source::Memory(None) if body.trailers.is_none() => crate::Body::empty(),
+            _ => crate::Body::new(body),
+        }
+    }
+}
+
+/// Returns the data of the frame, storing its trailers otherwise.
+fn into_data(frame: Frame<Bytes>, trailers: &mut Option<HeaderMap>) -> Option<Bytes> {
+    match frame.into_data() {
+        Ok(data) => Some(data),
+        Err(frame) => {
+            if let Ok(frame_trailers) = frame.into_trailers() {
+                *trailers = Some(frame_trailers);
+            }
+            None
+        }
+    }
+}
+
+#[cfg(feature = "replay-disk")]
+/// Stream the data of the spilled file, which is deleted once the stream is dropped.
+fn spilled_stream(
+    file: tokio::fs::File,
+    path: tempfile::TempPath,
+) -> impl Stream<Item = Result<Frame<Bytes>, BoxError>> + Send + 'static {
+    ReaderStream::new(file).map(move |result| {
+        let _keep_until_read = &path;
+        result.map(Frame::data).map_err(Into::into)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::BodyExtractExt;
+
+    fn body() -> crate::Body {
+        crate::Body::from_stream(futures_lite::stream::iter(
+            ["hello", " ", "world"].map(|s| Ok::<_, BoxError>(Bytes::from_static(s.as_bytes()))),
+        ))
+    }
+
+    #[tokio::test]
+    async fn test_replay_body_memory() {
+        let body = ReplayBody::buffer(body(), &ReplayConfig::new())
+            .await
+            .unwrap();
+        assert!(body.is_replayable());
+        assert!(!body.is_spilled());
+        assert_eq!(body.len(), Some(11));
+
+        let replay = body.try_clone().unwrap();
+        assert_eq!(body.try_into_string().await.unwrap(), "hello world");
+        assert_eq!(replay.try_into_string().await.unwrap(), "hello world");
+    }
+
+    #[cfg(feature = "replay-disk")]
+    #[tokio::test]
+    async fn test_replay_body_spill_to_disk() {
+        let config = ReplayConfig::new()
+            .with_memory_limit(4)
+            .with_spill_to_disk(true);
+        let body = ReplayBody::buffer(body(), &config).await.unwrap();
+        assert!(body.is_replayable());
+        assert!(body.is_spilled());
+        assert_eq!(body.len(), Some(11));
+
+        let replay = body.clone();
+        assert_eq!(body.try_into_string().await.unwrap(), "hello world");
+        assert_eq!(replay.try_into_string().await.unwrap(), "hello world");
+    }
+
+    #[tokio::test]
+    async fn test_replay_body_trailers() {
+        fn body_with_trailers() -> crate::Body {
+            let mut trailers = HeaderMap::new();
+            trailers.insert("x-checksum", crate::HeaderValue::from_static("42"));
+            crate::Body::new(crate::dep::http_body_util::StreamBody::new(
+                futures_lite::stream::iter([
+                    Ok::<_, BoxError>(Frame::data(Bytes::from_static(b"hello world"))),
+                    Ok(Frame::trailers(trailers)),
+                ]),
+            ))
+        }
+
+        #[allow(unused_mut)]
+        let mut configs = vec![ReplayConfig::new()];
+        #[cfg(feature = "replay-disk")]
+        configs.push(
+            ReplayConfig::new()
+                .with_memory_limit(4)
+                .with_spill_to_disk(true),
+        );
+
+        for config in configs {
+            let body = ReplayBody::buffer(body_with_trailers(), &config)
+                .await
+                .unwrap();
+            assert_eq!(body.is_spilled(), config.memory_limit.is_some());
+
+            for body in [body.clone(), body] {
+                let collected = body.collect().await.unwrap();
+                assert_eq!(collected.trailers().unwrap()["x-checksum"], "42");
+                assert_eq!(collected.to_bytes(), "hello world");
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_replay_body_one_shot() {
+        let config = ReplayConfig::new().with_memory_limit(4);
+        let body = ReplayBody::buffer(body(), &config).await.unwrap();
+        assert!(!body.is_replayable());
+        assert!(body.try_clone().is_none());
+        assert_eq!(body.len(), None);
+
+        let replay = body.clone();
+        assert_eq!(body.try_into_string().await.unwrap(), "hello world");
+        let err = replay.collect().await.unwrap_err();
+        assert!(err.downcast_ref::<BodyNotReplayable>().is_some());
+    }
+
+    #[cfg(feature = "replay-disk")]
+    #[tokio::test]
+    async fn test_replay_body_spill_exceeds_disk_limit() {
+        let config = ReplayConfig::new()
+            .with_memory_limit(4)
+            .with_spill_to_disk(true)
+            .with_disk_limit(6);
+        let body = ReplayBody::buffer(body(), &config).await.unwrap();
+        assert!(!body.is_replayable());
+        assert!(!body.is_spilled());
+        assert_eq!(body.len(), None);
+
+        let replay = body.clone();
+        assert_eq!(body.try_into_string().await.unwrap(), "hello world");
+        let err = replay.collect().await.unwrap_err();
+        assert!(err.downcast_ref::<BodyNotReplayable>().is_some());
+    }
+}
@@ -0,0 +1,469 @@
+use super::Domain;
+use rama_core::error::{ErrorContext, OpaqueError};
+use std::{fmt, io::BufRead, sync::Arc};
+
+#[cfg(feature = "http")]
+use crate::{address::Host, http::RequestContext};
+#[cfg(feature = "http")]
+use rama_core::{context::Extensions, Context};
+#[cfg(feature = "http")]
+use rama_http_types::Request;
+
+/// Bits of the bloom filter per domain in the blocklist,
+/// resulting in a false positive rate of about 1% for the 4 hashes used.
+const BLOOM_BITS_PER_ENTRY: usize = 10;
+const BLOOM_HASHES: u64 = 4;
+
+/// Names of hosts files that are not meant to be blocked.
+const HOSTS_FILE_LOCAL_NAMES: &[&str] = &[
+    "localhost",
+    "localhost.localdomain",
+    "local",
+    "broadcasthost",
+    "ip6-localhost",
+    "ip6-loopback",
+];
+
+/// A compact and immutable set of blocked domains,
+/// built for lists of millions of domains using the [`DomainBlocklistBuilder`].
+///
+/// All domains are stored in a single sorted buffer (indexed by their labels in reverse,
+/// starting from the top-level domain), which is searched for the request domain and
+/// its parent domains. A bloom filter in front of that search rejects most domains which
+/// are not blocked at the cost of a couple of hashes.
+/// The memory used is about the size of the domains in the list,
+/// plus about 6 bytes per domain.
+///
+/// A domain is either blocked exactly, or including all its subdomains.
+///
+/// Cloning a [`DomainBlocklist`] is cheap, as its data is shared.
+/// With the `http` feature enabled it can be used as a [`Matcher`] on the request authority,
+/// e.g. to block requests using the [`HijackLayer`].
+///
+/// [`Matcher`]: rama_core::matcher::Matcher
+/// [`HijackLayer`]: rama_core::layer::HijackLayer
+///
+/// # Example
+///
+/// ```
+/// use rama_net::address::{Domain, DomainBlocklist};
+///
+/// let list = "
+/// # hosts file
+/// 0.0.0.0 tracker.example.com
+/// # adblock style
+/// ||ads.example.org^
+/// ";
+///
+/// let blocklist = DomainBlocklist::builder()
+///     .with_list(list.as_bytes())
+///     .unwrap()
+///     .build()
+///     .unwrap();
+///
+/// assert!(blocklist.contains(&Domain::from_static("tracker.example.com")));
+/// assert!(!blocklist.contains(&Domain::from_static("foo.tracker.example.com")));
+/// assert!(blocklist.contains(&Domain::from_static("foo.ads.example.org")));
+/// assert!(!blocklist.contains(&Domain::from_static("example.org")));
+/// ```
+#[derive(Clone)]
+pub struct DomainBlocklist {
+    inner: Arc<BlocklistInner>,
+}
+
+struct BlocklistInner {
+    /// the keys of all domains, concatenated in sorted order
+    keys: Box<str>,
+    /// the end offset of each key in `keys`
+    ends: Box<[u32]>,
+    /// bitset of the domains which block their subdomains as well
+    sub: Box<[u64]>,
+    bloom: Box<[u64]>,
+}
+
+impl DomainBlocklist {
+    /// Create a new [`DomainBlocklistBuilder`] to build a [`DomainBlocklist`].
+    pub fn builder() -> DomainBlocklistBuilder {
+        DomainBlocklistBuilder::new()
+    }
+
+    /// Returns the amount of domains in this blocklist.
+    pub fn len(&self) -> usize {
+        self.inner.ends.len()
+    }
+
+    /// Returns `true` in case this blocklist contains no domains.
+    pub fn is_empty(&self) -> bool {
+        self.inner.ends.is_empty()
+    }
+
+    /// Returns `true` if the given [`Domain`] is blocked,
+    /// either exactly or as a subdomain of a blocked domain.
+    pub fn contains(&self, domain: &Domain) -> bool {
+        if self.is_empty() {
+            return false;
+        }
+        let key = domain_key(domain.as_str());
+        let boundaries = key
+            .match_indices('.')
+            .map(|(index, _)| index)
+            .chain(std::iter::once(key.len()));
+        for end in boundaries {
+            let candidate = &key[..end];
+            if !self.inner.bloom_contains(candidate) {
+                continue;
+            }
+            if let Some(index) = self.inner.search(candidate) {
+                if end == key.len() || self.inner.is_sub(index) {
+                    return true;
+                }
+            }
+        }
+        false
+    }
+}
+
+impl BlocklistInner {
+    fn key(&self, index: usize) -> &str {
+        let start = match index {
+            0 => 0,
+            index => self.ends[index - 1] as usize,
+        };
+        &self.keys[start..self.ends[index] as usize]
+    }
+
+    fn search(&self, key: &str) -> Option<usize> {
+        let (mut low, mut high) = (0, self.ends.len());
+        while low < high {
+            let mid = low + (high - low) / 2;
+            match self.key(mid).cmp(key) {
+                std::cmp::Ordering::Less => low = mid + 1,
+                std::cmp::Ordering::Greater => high = mid,
+                std::cmp::Ordering::Equal => return Some(mid),
+            }
+        }
+        None
+    }
+
+    fn is_sub(&self, index: usize) -> bool {
+        self.sub[index / 64] & (1 << (index % 64)) != 0
+    }
+
+    fn bloom_contains(&self, key: &str) -> bool {
+        bloom_bits(key, self.bloom.len()).all(|bit| self.bloom[bit / 64] & (1 << (bit % 64)) != 0)
+    }
+}
+
+/// The bits of the bloom filter (of the given amount of words) set for a key,
+/// using double hashing of the 64-bit FNV-1a hash of the key.
+fn bloom_bits(key: &str, words: usize) -> impl Iterator<Item = usize> {
+    let hash = key.bytes().fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+    });
+    let (h1, h2) = (hash & 0xffff_ffff, (hash >> 32) | 1);
+    let bits = (words * 64) as u64;
+    (0..BLOOM_HASHES).map(move |i| (h1.wrapping_add(i.wrapping_mul(h2)) % bits) as usize)
+}
+
+/// The key of a domain: its lowercase labels in reverse order,
+/// such that the keys of its parent domains are prefixes of it.
+fn domain_key(domain: &str) -> String {
+    let mut key = String::with_capacity(domain.len());
+    for (index, label) in domain.trim_matches('.').rsplit('.').enumerate() {
+        if index > 0 {
+            key.push('.');
+        }
+        key.push_str(label);
+    }
+    key.make_ascii_lowercase();
+    key
+}
+
+impl fmt::Debug for DomainBlocklist {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DomainBlocklist")
+            .field("len", &self.len())
+            .field("bytes", &self.inner.keys.len())
+            .finish()
+    }
+}
+
+#[cfg(feature = "http")]
+impl<State, Body> rama_core::matcher::Matcher<State, Request<Body>> for DomainBlocklist {
+    fn matches(
+        &self,
+        ext: Option<&mut Extensions>,
+        ctx: &Context<State>,
+        req: &Request<Body>,
+    ) -> bool {
+        let host = match ctx.get::<RequestContext>() {
+            Some(req_ctx) => req_ctx.authority.host().clone(),
+            None => {
+                let req_ctx: RequestContext = match (ctx, req).try_into() {
+                    Ok(req_ctx) => req_ctx,
+                    Err(err) => {
+                        tracing::error!(error = %err, "DomainBlocklist: failed to lazy-make the request ctx");
+                        return false;
+                    }
+                };
+                let host = req_ctx.authority.host().clone();
+                if let Some(ext) = ext {
+                    ext.insert(req_ctx);
+                }
+                host
+            }
+        };
+        match host {
+            Host::Name(domain) => self.contains(&domain),
+            Host::Address(_) => false,
+        }
+    }
+}
+
+/// Builder of a [`DomainBlocklist`].
+///
+/// Domains can be added individually, or read from lists in the following formats,
+/// which can be mixed (even within the same list):
+///
+/// - hosts files (e.g. `0.0.0.0 example.com`): blocks the domains exactly,
+///   except for local names such as `localhost`;
+/// - adblock style (e.g. `||example.com^`): blocks the domain including its subdomains,
+///   any other adblock rule (e.g. with options or exceptions) is ignored;
+/// - plain domains (e.g. `example.com` or `*.example.com`): blocks the domain
+///   including its subdomains.
+///
+/// Empty lines and comments (starting with `#` or `!`) are ignored,
+/// as are lines which do not contain valid domains.
+#[derive(Debug, Clone, Default)]
+pub struct DomainBlocklistBuilder {
+    entries: Vec<(String, bool)>,
+}
+
+impl DomainBlocklistBuilder {
+    /// Create a new empty [`DomainBlocklistBuilder`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Block the given domain exactly.
+    pub fn with_exact(mut self, domain: Domain) -> Self {
+        self.entries.push((domain_key(domain.as_str()), false));
+        self
+    }
+
+    /// Block the given domain exactly.
+    pub fn set_exact(&mut self, domain: Domain) -> &mut Self {
+        self.entries.push((domain_key(domain.as_str()), false));
+        self
+    }
+
+    /// Block the given domain including all its subdomains.
+    pub fn with_sub(mut self, domain: Domain) -> Self {
+        self.entries.push((domain_key(domain.as_str()), true));
+        self
+    }
+
+    /// Block the given domain including all its subdomains.
+    pub fn set_sub(&mut self, domain: Domain) -> &mut Self {
+        self.entries.push((domain_key(domain.as_str()), true));
+        self
+    }
+
+    /// Block the domains of the given list,
+    /// see the [`DomainBlocklistBuilder`] for the supported formats.
+    pub fn with_list(mut self, reader: impl BufRead) -> Result<Self, OpaqueError> {
+        self.set_list(reader)?;
+        Ok(self)
+    }
+
+    /// Block the domains of the given list,
+    /// see the [`DomainBlocklistBuilder`] for the supported formats.
+    pub fn set_list(&mut self, reader: impl BufRead) -> Result<&mut Self, OpaqueError> {
+        for line in reader.lines() {
+            let line = line.context("read blocklist line")?;
+            self.parse_line(&line);
+        }
+        Ok(self)
+    }
+
+    fn parse_line(&mut self, line: &str) {
+        let line = line.split('#').next().unwrap_or_default().trim();
+        if line.is_empty() || line.starts_with('!') || line.starts_with('[') {
+            return;
+        }
+
+        if let Some(rule) = line.strip_prefix("||") {
+            match rule.strip_suffix('^').unwrap_or(rule).parse::<Domain>() {
+                Ok(domain) => {
+                    self.set_sub(domain);
+                }
+                Err(_) => tracing::trace!(%line, "blocklist: ignore unsupported adblock rule"),
+            }
+            return;
+        }
+
+        let mut tokens = line.split_whitespace();
+        let Some(first) = tokens.next() else {
+            return;
+        };
+        if first.parse::<std::net::IpAddr>().is_ok() {
+            for name in tokens {
+                if HOSTS_FILE_LOCAL_NAMES
+                    .iter()
+                    .any(|local| local.eq_ignore_ascii_case(name))
+                {
+                    continue;
+                }
+                if let Ok(domain) = name.parse::<Domain>() {
+                    self.set_exact(domain);
+                }
+            }
+            return;
+        }
+
+        match first.strip_prefix("*.").unwrap_or(first).parse::<Domain>() {
+            Ok(domain) if tokens.next().is_none() => {
+                self.set_sub(domain);
+            }
+            _ => tracing::trace!(%line, "blocklist: ignore invalid line"),
+        }
+    }
+
+    /// Build the [`DomainBlocklist`],
+    /// failing in case the domains exceed 4 GiB in total.
+    pub fn build(self) -> Result<DomainBlocklist, OpaqueError> {
+        let mut entries = self.entries;
+        entries.sort_unstable();
+        // merge duplicates, with sub entries covering exact ones
+        entries.dedup_by(|next, kept| {
+            if next.0 == kept.0 {
+                kept.1 |= next.1;
+                true
+            } else {
+                false
+            }
+        });
+
+        let size: usize = entries.iter().map(|(key, _)| key.len()).sum();
+        if u32::try_from(size).is_err() {
+            return Err(OpaqueError::from_display(
+                "blocklist domains exceed 4 GiB in total",
+            ));
+        }
+
+        let mut keys = String::with_capacity(size);
+        let mut ends = Vec::with_capacity(entries.len());
+        let mut sub = vec![0u64; entries.len().div_ceil(64)];
+        let mut bloom = vec![0u64; (entries.len() * BLOOM_BITS_PER_ENTRY).div_ceil(64).max(1)];
+        for (index, (key, is_sub)) in entries.iter().enumerate() {
+            keys.push_str(key);
+            ends.push(keys.len() as u32);
+            if *is_sub {
+                sub[index / 64] |= 1 << (index % 64);
+            }
+            for bit in bloom_bits(key, bloom.len()) {
+                bloom[bit / 64] |= 1 << (bit % 64);
+            }
+        }
+
+        Ok(DomainBlocklist {
+            inner: Arc::new(BlocklistInner {
+                keys: keys.into_boxed_str(),
+                ends: ends.into_boxed_slice(),
+                sub: sub.into_boxed_slice(),
+                bloom: bloom.into_boxed_slice(),
+            }),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn blocklist(list: &str) -> DomainBlocklist {
+        DomainBlocklist::builder()
+            .with_list(list.as_bytes())
+            .unwrap()
+            .build()
+            .unwrap()
+    }
+
+    fn contains(blocklist: &DomainBlocklist, domain: &str) -> bool {
+        blocklist.contains(&domain.parse().unwrap())
+    }
+
+    #[test]
+    fn test_blocklist_formats() {
+        let blocklist = blocklist(
+            "
+            [Adblock Plus 2.0]
+            ! adblock comment
+            # hosts comment
+            127.0.0.1 localhost
+            0.0.0.0 exact.example.com other.example.com # trailing comment
+            ||ads.example.org^
+            ||example.net^$third-party
+            @@||allowed.example.org^
+            *.wild.example
+            plain.example
+            not a domain
+            ",
+        );
+        assert_eq!(blocklist.len(), 5);
+
+        for (domain, expected) in [
+            ("localhost", false),
+            ("exact.example.com", true),
+            ("EXACT.example.com.", true),
+            ("other.example.com", true),
+            ("sub.exact.example.com", false),
+            ("example.com", false),
+            ("ads.example.org", true),
+            ("x.y.ads.example.org", true),
+            ("example.org", false),
+            ("example.net", false),
+            ("allowed.example.org", false),
+            ("wild.example", true),
+            ("a.wild.example", true),
+            ("plain.example", true),
+            ("a.plain.example", true),
+            ("example", false),
+        ] {
+            assert_eq!(contains(&blocklist, domain), expected, "{domain}");
+        }
+    }
+
+    #[test]
+    fn test_blocklist_duplicates() {
+        let blocklist = DomainBlocklist::builder()
+            .with_exact(Domain::from_static("example.com"))
+            .with_sub(Domain::from_static("example.com"))
+            .with_exact(Domain::from_static("Example.com"))
+            .build()
+            .unwrap();
+        assert_eq!(blocklist.len(), 1);
+        assert!(contains(&blocklist, "www.example.com"));
+    }
+
+    #[test]
+    fn test_blocklist_large() {
+        let mut builder = DomainBlocklist::builder();
+        for i in 0..100_000 {
+            builder.set_exact(format!("host-{i}.example.com").parse().unwrap());
+        }
+        let blocklist = builder.build().unwrap();
+        assert_eq!(blocklist.len(), 100_000);
+        assert!(contains(&blocklist, "host-0.example.com"));
+        assert!(contains(&blocklist, "host-99999.example.com"));
+        assert!(!contains(&blocklist, "host-100000.example.com"));
+        assert!(!contains(&blocklist, "example.com"));
+    }
+
+    #[test]
+    fn test_blocklist_empty() {
+        let blocklist = DomainBlocklist::builder().build().unwrap();
+        assert!(blocklist.is_empty());
+        assert!(!contains(&blocklist, "example.com"));
+    }
+}
@@ -22,3 +22,7 @@ pub use authority::Authority;
 mod proxy;
 #[doc(inline)]
 pub use proxy::ProxyAddress;
+
+mod blocklist;
+#[doc(inline)]
+pub use blocklist::{DomainBlocklist, DomainBlocklistBuilder};
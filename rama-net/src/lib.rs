@@ -27,7 +27,6 @@ pub(crate) mod proto;
 #[doc(inline)]
 pub use proto::Protocol;
 
-#[cfg(feature = "http")]
 pub mod transport;
 
 #[cfg(feature = "http")]
@@ -0,0 +1,418 @@
+//! Firewall-style middleware to accept or reject requests and connections
+//! based on the IP address of the peer.
+//!
+//! The IP address is taken from the [`Forwarded`] context if available,
+//! e.g. as inserted by the PROXY protocol (`HaProxy`) server layer or from the
+//! forwarded headers of a trusted proxy, and from the [`SocketInfo`] otherwise.
+//!
+//! The [`IpFilterRules`] used can be replaced at any time using the
+//! [`IpFilterHandle`] of the layer, without having to restart the server.
+//!
+//! # Example
+//!
+//! ```
+//! use rama_core::{service::service_fn, Context, Layer, Service};
+//! use rama_net::stream::{
+//!     layer::{IpFilterLayer, IpFilterRules},
+//!     SocketInfo,
+//! };
+//! use std::convert::Infallible;
+//!
+//! # #[tokio::main]
+//! # async fn main() {
+//! let rules = IpFilterRules::new()
+//!     .with_deny_list("# office\n10.0.0.0/8\n192.168.1.1".as_bytes())
+//!     .unwrap();
+//!
+//! let layer = IpFilterLayer::new(rules);
+//! let handle = layer.handle();
+//! let service = layer.layer(service_fn(|_: ()| async { Ok::<_, Infallible>(()) }));
+//!
+//! let mut ctx = Context::default();
+//! ctx.insert(SocketInfo::new(None, ([10, 1, 2, 3], 40000).into()));
+//! assert!(service.serve(ctx.clone(), ()).await.is_err());
+//!
+//! // lists can be reloaded at any time
+//! handle.set(IpFilterRules::new());
+//! assert!(service.serve(ctx, ()).await.is_ok());
+//! # }
+//! ```
+
+use crate::{
+    forwarded::Forwarded,
+    stream::{dep::ipnet::IpNet, matcher::ip::IntoIpNet, SocketInfo},
+};
+use arc_swap::ArcSwap;
+use rama_core::{
+    error::{BoxError, ErrorContext, OpaqueError},
+    Context, Layer, Service,
+};
+use rama_utils::macros::define_inner_service_accessors;
+use std::{fmt, io::BufRead, net::IpAddr, sync::Arc};
+
+#[derive(Debug, Clone, Default)]
+/// The allow and deny lists of CIDR networks used by the [`IpFilter`].
+///
+/// An IP address is rejected if it is contained in the deny list,
+/// or if the allow list is not empty and the IP address is not contained in it.
+/// All other IP addresses are accepted.
+///
+/// Requests and connections for which no IP address is known are rejected,
+/// unless [`IpFilterRules::set_allow_unknown`] is enabled.
+pub struct IpFilterRules {
+    allow: Vec<IpNet>,
+    deny: Vec<IpNet>,
+    allow_unknown: bool,
+}
+
+impl IpFilterRules {
+    /// Create new [`IpFilterRules`], which accept all known IP addresses.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add the network to the allow list.
+    pub fn with_allow(mut self, net: impl IntoIpNet) -> Self {
+        self.allow.push(net.into_ip_net());
+        self
+    }
+
+    /// Add the network to the allow list.
+    pub fn set_allow(&mut self, net: impl IntoIpNet) -> &mut Self {
+        self.allow.push(net.into_ip_net());
+        self
+    }
+
+    /// Add the networks of the list to the allow list.
+    ///
+    /// See [`IpFilterRules::set_allow_list`] for the supported format.
+    pub fn with_allow_list(mut self, reader: impl BufRead) -> Result<Self, OpaqueError> {
+        self.set_allow_list(reader)?;
+        Ok(self)
+    }
+
+    /// Add the networks of the list to the allow list.
+    ///
+    /// The list contains one CIDR network (e.g. `10.0.0.0/8`) or
+    /// IP address (e.g. `::1`) per line. Empty lines and
+    /// everything following a `#` are ignored.
+    pub fn set_allow_list(&mut self, reader: impl BufRead) -> Result<&mut Self, OpaqueError> {
+        parse_list(reader, &mut self.allow).context("parse allow list")?;
+        Ok(self)
+    }
+
+    /// Add the network to the deny list.
+    pub fn with_deny(mut self, net: impl IntoIpNet) -> Self {
+        self.deny.push(net.into_ip_net());
+        self
+    }
+
+    /// Add the network to the deny list.
+    pub fn set_deny(&mut self, net: impl IntoIpNet) -> &mut Self {
+        self.deny.push(net.into_ip_net());
+        self
+    }
+
+    /// Add the networks of the list to the deny list.
+    ///
+    /// See [`IpFilterRules::set_allow_list`] for the supported format.
+    pub fn with_deny_list(mut self, reader: impl BufRead) -> Result<Self, OpaqueError> {
+        self.set_deny_list(reader)?;
+        Ok(self)
+    }
+
+    /// Add the networks of the list to the deny list.
+    ///
+    /// See [`IpFilterRules::set_allow_list`] for the supported format.
+    pub fn set_deny_list(&mut self, reader: impl BufRead) -> Result<&mut Self, OpaqueError> {
+        parse_list(reader, &mut self.deny).context("parse deny list")?;
+        Ok(self)
+    }
+
+    /// Accept requests and connections for which no IP address is known.
+    pub fn with_allow_unknown(mut self, allow: bool) -> Self {
+        self.allow_unknown = allow;
+        self
+    }
+
+    /// Accept requests and connections for which no IP address is known.
+    pub fn set_allow_unknown(&mut self, allow: bool) -> &mut Self {
+        self.allow_unknown = allow;
+        self
+    }
+
+    /// Returns `true` if the (optional) IP address is accepted by these rules.
+    pub fn is_allowed(&self, ip: Option<IpAddr>) -> bool {
+        let Some(ip) = ip else {
+            return self.allow_unknown;
+        };
+        let ip = ip.to_canonical();
+        if self.deny.iter().any(|net| net.contains(&ip)) {
+            return false;
+        }
+        self.allow.is_empty() || self.allow.iter().any(|net| net.contains(&ip))
+    }
+}
+
+fn parse_list(reader: impl BufRead, nets: &mut Vec<IpNet>) -> Result<(), OpaqueError> {
+    for (index, line) in reader.lines().enumerate() {
+        let line = line.context("read line")?;
+        let line = line.split('#').next().unwrap_or_default().trim();
+        if line.is_empty() {
+            continue;
+        }
+        let net = match line.parse::<IpNet>() {
+            Ok(net) => net,
+            Err(_) => line.parse::<IpAddr>().map(IpNet::from).map_err(|_| {
+                OpaqueError::from_display(format!(
+                    "line {}: invalid CIDR network or IP address: {line}",
+                    index + 1
+                ))
+            })?,
+        };
+        nets.push(net);
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone)]
+/// Error returned by the [`IpFilter`] in case the peer IP address is rejected.
+pub struct IpRejected {
+    ip: Option<IpAddr>,
+}
+
+impl IpRejected {
+    /// The rejected IP address, `None` in case it was not known.
+    pub fn ip(&self) -> Option<IpAddr> {
+        self.ip
+    }
+}
+
+impl fmt::Display for IpRejected {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.ip {
+            Some(ip) => write!(f, "ip filter: ip address {ip} rejected"),
+            None => f.write_str("ip filter: unknown ip address rejected"),
+        }
+    }
+}
+
+impl std::error::Error for IpRejected {}
+
+#[derive(Clone)]
+/// Handle to replace the [`IpFilterRules`] used by
+/// an [`IpFilterLayer`] and all its [`IpFilter`] services.
+pub struct IpFilterHandle(Arc<ArcSwap<IpFilterRules>>);
+
+impl IpFilterHandle {
+    /// Replace the rules used for all future requests and connections.
+    pub fn set(&self, rules: IpFilterRules) {
+        self.0.store(Arc::new(rules))
+    }
+
+    /// Get the rules currently in use.
+    pub fn get(&self) -> Arc<IpFilterRules> {
+        self.0.load_full()
+    }
+}
+
+impl fmt::Debug for IpFilterHandle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("IpFilterHandle").field(&self.0).finish()
+    }
+}
+
+/// Layer that applies the [`IpFilter`] middleware.
+///
+/// See the [module docs](self) for more details.
+#[derive(Debug, Clone)]
+pub struct IpFilterLayer {
+    rules: IpFilterHandle,
+    trust_forwarded: bool,
+}
+
+impl IpFilterLayer {
+    /// Create a new [`IpFilterLayer`] using the given rules.
+    pub fn new(rules: IpFilterRules) -> Self {
+        Self {
+            rules: IpFilterHandle(Arc::new(ArcSwap::from_pointee(rules))),
+            trust_forwarded: true,
+        }
+    }
+
+    /// Get the [`IpFilterHandle`] to replace the rules of
+    /// this layer and the services created by it.
+    pub fn handle(&self) -> IpFilterHandle {
+        self.rules.clone()
+    }
+
+    /// Use the client IP of the [`Forwarded`] context, if available (enabled by default).
+    ///
+    /// Disable this to filter on the IP address of the peer socket only.
+    pub fn with_trust_forwarded(mut self, trust: bool) -> Self {
+        self.trust_forwarded = trust;
+        self
+    }
+
+    /// Use the client IP of the [`Forwarded`] context, if available (enabled by default).
+    ///
+    /// Disable this to filter on the IP address of the peer socket only.
+    pub fn set_trust_forwarded(&mut self, trust: bool) -> &mut Self {
+        self.trust_forwarded = trust;
+        self
+    }
+}
+
+impl<S> Layer<S> for IpFilterLayer {
+    type Service = IpFilter<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        IpFilter {
+            inner,
+            rules: self.rules.clone(),
+            trust_forwarded: self.trust_forwarded,
+        }
+    }
+}
+
+/// Middleware that rejects requests and connections using the [`IpFilterRules`].
+///
+/// Rejected requests and connections result in an [`IpRejected`] error.
+///
+/// See the [module docs](self) for more details.
+pub struct IpFilter<S> {
+    inner: S,
+    rules: IpFilterHandle,
+    trust_forwarded: bool,
+}
+
+impl<S> IpFilter<S> {
+    /// Create a new [`IpFilter`] using the given rules.
+    pub fn new(inner: S, rules: IpFilterRules) -> Self {
+        IpFilterLayer::new(rules).layer(inner)
+    }
+
+    /// Get the [`IpFilterHandle`] to replace the rules of this service.
+    pub fn handle(&self) -> IpFilterHandle {
+        self.rules.clone()
+    }
+
+    define_inner_service_accessors!();
+
+    fn client_ip<State>(&self, ctx: &Context<State>) -> Option<IpAddr> {
+        self.trust_forwarded
+            .then(|| ctx.get::<Forwarded>().and_then(|f| f.client_ip()))
+            .flatten()
+            .or_else(|| ctx.get::<SocketInfo>().map(|info| info.peer_addr().ip()))
+    }
+}
+
+impl<S: fmt::Debug> fmt::Debug for IpFilter<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("IpFilter")
+            .field("inner", &self.inner)
+            .field("rules", &self.rules)
+            .field("trust_forwarded", &self.trust_forwarded)
+            .finish()
+    }
+}
+
+impl<S: Clone> Clone for IpFilter<S> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            rules: self.rules.clone(),
+            trust_forwarded: self.trust_forwarded,
+        }
+    }
+}
+
+impl<State, Request, S> Service<State, Request> for IpFilter<S>
+where
+    State: Clone + Send + Sync + 'static,
+    Request: Send + 'static,
+    S: Service<State, Request, Error: Into<BoxError>>,
+{
+    type Response = S::Response;
+    type Error = BoxError;
+
+    async fn serve(
+        &self,
+        ctx: Context<State>,
+        req: Request,
+    ) -> Result<Self::Response, Self::Error> {
+        let ip = self.client_ip(&ctx);
+        if !self.rules.0.load().is_allowed(ip) {
+            tracing::debug!(?ip, "ip filter: reject request");
+            return Err(IpRejected { ip }.into());
+        }
+        self.inner.serve(ctx, req).await.map_err(Into::into)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::forwarded::ForwardedElement;
+    use rama_core::service::service_fn;
+    use std::convert::Infallible;
+
+    #[test]
+    fn test_ip_filter_rules() {
+        let rules = IpFilterRules::new()
+            .with_allow_list("10.0.0.0/8 # internal\n\n::1\n".as_bytes())
+            .unwrap()
+            .with_deny("10.1.0.0/16".parse::<IpNet>().unwrap());
+
+        assert!(rules.is_allowed(Some([10, 0, 0, 1].into())));
+        assert!(!rules.is_allowed(Some([10, 1, 0, 1].into())));
+        assert!(!rules.is_allowed(Some([192, 168, 0, 1].into())));
+        assert!(rules.is_allowed(Some("::1".parse().unwrap())));
+        // ipv4-mapped ipv6 addresses match their ipv4 networks
+        assert!(rules.is_allowed(Some("::ffff:10.0.0.1".parse().unwrap())));
+        assert!(!rules.is_allowed(None));
+        assert!(rules.clone().with_allow_unknown(true).is_allowed(None));
+
+        // no allow list accepts all but the denied addresses
+        let rules = IpFilterRules::new().with_deny([127, 0, 0, 1]);
+        assert!(!rules.is_allowed(Some([127, 0, 0, 1].into())));
+        assert!(rules.is_allowed(Some([127, 0, 0, 2].into())));
+    }
+
+    #[test]
+    fn test_ip_filter_rules_invalid_list() {
+        let err = IpFilterRules::new()
+            .with_deny_list("10.0.0.0/8\n10.0.0.0/33\n".as_bytes())
+            .unwrap_err();
+        assert!(err.to_string().contains("line 2"), "{err}");
+    }
+
+    #[tokio::test]
+    async fn test_ip_filter() {
+        let layer = IpFilterLayer::new(IpFilterRules::new().with_deny([1, 1, 1, 1]));
+        let handle = layer.handle();
+        let service = layer.layer(service_fn(|_: ()| async { Ok::<_, Infallible>(()) }));
+
+        let mut ctx = Context::default();
+        ctx.insert(SocketInfo::new(None, ([127, 0, 0, 1], 40000).into()));
+        assert!(service.serve(ctx.clone(), ()).await.is_ok());
+
+        // the forwarded client ip takes precedence over the peer address
+        ctx.insert(Forwarded::new(ForwardedElement::forwarded_for(
+            IpAddr::from([1, 1, 1, 1]),
+        )));
+        let err = service.serve(ctx.clone(), ()).await.unwrap_err();
+        let err = err.downcast_ref::<IpRejected>().unwrap();
+        assert_eq!(err.ip(), Some([1, 1, 1, 1].into()));
+
+        let peer_only = IpFilterLayer::new(handle.get().as_ref().clone())
+            .with_trust_forwarded(false)
+            .layer(service_fn(|_: ()| async { Ok::<_, Infallible>(()) }));
+        assert!(peer_only.serve(ctx.clone(), ()).await.is_ok());
+
+        // rules are reloaded
+        handle.set(IpFilterRules::new().with_allow([1, 1, 1, 1]));
+        assert!(service.serve(ctx, ()).await.is_ok());
+        assert!(service.serve(Context::default(), ()).await.is_err());
+    }
+}
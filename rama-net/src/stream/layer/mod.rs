@@ -9,6 +9,10 @@ pub use tracker::{
     OutgoingBytesTrackerLayer, OutgoingBytesTrackerService,
 };
 
+pub mod ip_filter;
+#[doc(inline)]
+pub use ip_filter::{IpFilter, IpFilterHandle, IpFilterLayer, IpFilterRules, IpRejected};
+
 #[cfg(feature = "http")]
 pub mod http;
 
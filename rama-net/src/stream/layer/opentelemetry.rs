@@ -3,6 +3,7 @@
 //! [`Layer`]: rama_core::Layer
 
 use crate::stream::SocketInfo;
+use crate::transport::TransportProtocol;
 use rama_core::telemetry::opentelemetry::semantic_conventions::resource::{
     SERVICE_NAME, SERVICE_VERSION,
 };
@@ -204,8 +205,9 @@ impl<S, F> NetworkMetricsService<S, F> {
             .attributes(2 + self.base_attributes.len(), ctx);
         attributes.extend(self.base_attributes.iter().cloned());
 
-        // client info
-        if let Some(socket_info) = ctx.get::<SocketInfo>() {
+        // client and connection info
+        let socket_info = ctx.get::<SocketInfo>();
+        if let Some(socket_info) = socket_info {
             let peer_addr = socket_info.peer_addr();
             attributes.push(KeyValue::new(
                 NETWORK_TYPE,
@@ -215,9 +217,13 @@ impl<S, F> NetworkMetricsService<S, F> {
                 },
             ));
         }
-
-        // connection info
-        attributes.push(KeyValue::new(NETWORK_TRANSPORT, "tcp")); // TODO: do not hardcode this once we support UDP
+        attributes.push(KeyValue::new(
+            NETWORK_TRANSPORT,
+            match socket_info.map(SocketInfo::protocol) {
+                Some(TransportProtocol::Udp) => "udp",
+                Some(TransportProtocol::Tcp) | None => "tcp",
+            },
+        ));
 
         attributes
     }
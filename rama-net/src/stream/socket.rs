@@ -1,3 +1,4 @@
+use crate::transport::TransportProtocol;
 use std::io::Result;
 use std::net::SocketAddr;
 
@@ -63,17 +64,29 @@ impl Socket for tokio::net::UdpSocket {
 
 #[derive(Debug, Clone)]
 /// Connected socket information.
+///
+/// Inserted in the [`Context`] by the transport layer (e.g. a tcp listener)
+/// for each accepted connection, such that services and layers can use
+/// the transport metadata without having to derive it themselves.
+///
+/// [`Context`]: rama_core::Context
 pub struct SocketInfo {
     local_addr: Option<SocketAddr>,
     peer_addr: SocketAddr,
+    interface: Option<String>,
+    protocol: TransportProtocol,
+    tcp_info: Option<TcpInfo>,
 }
 
 impl SocketInfo {
-    /// Create a new `SocketInfo`.
+    /// Create a new `SocketInfo` for a [`TransportProtocol::Tcp`] socket.
     pub const fn new(local_addr: Option<SocketAddr>, peer_addr: SocketAddr) -> Self {
         Self {
             local_addr,
             peer_addr,
+            interface: None,
+            protocol: TransportProtocol::Tcp,
+            tcp_info: None,
         }
     }
 
@@ -86,4 +99,118 @@ impl SocketInfo {
     pub fn peer_addr(&self) -> &SocketAddr {
         &self.peer_addr
     }
+
+    /// Set the name of the network interface the socket is bound to.
+    pub fn with_interface(mut self, interface: impl Into<String>) -> Self {
+        self.interface = Some(interface.into());
+        self
+    }
+
+    /// Set the name of the network interface the socket is bound to.
+    pub fn set_interface(&mut self, interface: impl Into<String>) -> &mut Self {
+        self.interface = Some(interface.into());
+        self
+    }
+
+    /// Get the name of the network interface the socket is bound to, if known.
+    pub fn interface(&self) -> Option<&str> {
+        self.interface.as_deref()
+    }
+
+    /// Set the [`TransportProtocol`] of the socket.
+    pub fn with_protocol(mut self, protocol: TransportProtocol) -> Self {
+        self.protocol = protocol;
+        self
+    }
+
+    /// Set the [`TransportProtocol`] of the socket.
+    pub fn set_protocol(&mut self, protocol: TransportProtocol) -> &mut Self {
+        self.protocol = protocol;
+        self
+    }
+
+    /// Get the [`TransportProtocol`] of the socket.
+    pub fn protocol(&self) -> TransportProtocol {
+        self.protocol
+    }
+
+    /// Set the [`TcpInfo`] observed for the socket.
+    pub fn with_tcp_info(mut self, tcp_info: TcpInfo) -> Self {
+        self.tcp_info = Some(tcp_info);
+        self
+    }
+
+    /// Set the [`TcpInfo`] observed for the socket.
+    pub fn set_tcp_info(&mut self, tcp_info: TcpInfo) -> &mut Self {
+        self.tcp_info = Some(tcp_info);
+        self
+    }
+
+    /// Get the [`TcpInfo`] observed for the socket, if available.
+    pub fn tcp_info(&self) -> Option<&TcpInfo> {
+        self.tcp_info.as_ref()
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+/// TCP-level signals observed for a connected socket.
+///
+/// These complement application level fingerprints (e.g. TLS and HTTP),
+/// as the values used by a peer are mostly determined by its OS network stack,
+/// which makes them useful for passive OS fingerprinting (in the spirit of `p0f`).
+///
+/// All values are optional, as which of them can be observed
+/// depends on the platform and the transport layer in use.
+///
+/// Signals which require observing the raw packets of the peer
+/// (e.g. its IP time-to-live or initial TCP window size) are not (yet) available.
+pub struct TcpInfo {
+    mss: Option<u32>,
+}
+
+impl TcpInfo {
+    /// Create a new empty [`TcpInfo`].
+    pub const fn new() -> Self {
+        Self { mss: None }
+    }
+
+    /// Set the TCP maximum segment size negotiated with the peer.
+    pub fn with_mss(mut self, mss: u32) -> Self {
+        self.mss = Some(mss);
+        self
+    }
+
+    /// Set the TCP maximum segment size negotiated with the peer.
+    pub fn set_mss(&mut self, mss: u32) -> &mut Self {
+        self.mss = Some(mss);
+        self
+    }
+
+    /// Get the TCP maximum segment size negotiated with the peer.
+    pub fn mss(&self) -> Option<u32> {
+        self.mss
+    }
+
+    /// Returns `true` if none of the TCP signals were observed.
+    pub fn is_empty(&self) -> bool {
+        self.mss.is_none()
+    }
+}
+
+#[derive(Debug, Clone)]
+/// [`SocketInfo`] of an established client connection.
+///
+/// Inserted in the [`Context`] by client connectors (e.g. a tcp connector),
+/// separate from the [`SocketInfo`], as in a proxy the latter is
+/// the information of the accepted (incoming) connection.
+///
+/// [`Context`]: rama_core::Context
+pub struct ClientSocketInfo(pub SocketInfo);
+
+impl std::ops::Deref for ClientSocketInfo {
+    type Target = SocketInfo;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
 }
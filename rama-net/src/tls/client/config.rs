@@ -1,5 +1,10 @@
-use super::{merge_client_hello_lists, ClientHelloExtension};
-use crate::tls::{CipherSuite, CompressionAlgorithm, DataEncoding, KeyLogIntent};
+use super::{merge_client_hello_lists, ClientHelloExtension, ServerCertPin};
+use crate::address::Host;
+use crate::tls::{
+    ApplicationProtocol, CipherSuite, CompressionAlgorithm, DataEncoding, KeyLogIntent,
+    ProtocolVersion,
+};
+use rama_core::error::{ErrorContext, OpaqueError};
 
 #[derive(Debug, Clone, Default)]
 /// Common API to configure a TLS Client
@@ -15,6 +20,21 @@ pub struct ClientConfig {
     /// - [`super::ClientHelloExtension::ApplicationLayerProtocolNegotiation`]
     /// - [`super::ClientHelloExtension::SupportedVersions`]
     pub extensions: Option<Vec<ClientHelloExtension>>,
+    /// optional supported versions by the client
+    ///
+    /// Takes priority over the versions defined
+    /// by [`super::ClientHelloExtension::SupportedVersions`].
+    pub protocol_versions: Option<Vec<ProtocolVersion>>,
+    /// optional ALPNs used for protocol negotiation with the server
+    ///
+    /// Takes priority over the ALPNs defined by
+    /// [`super::ClientHelloExtension::ApplicationLayerProtocolNegotiation`].
+    pub application_layer_protocol_negotiation: Option<Vec<ApplicationProtocol>>,
+    /// optionally define which server name (if any) is indicated by the client
+    ///
+    /// Takes priority over the server name defined by
+    /// [`super::ClientHelloExtension::ServerName`].
+    pub server_name: Option<ServerNameIndication>,
     /// optionally define how server should be verified by client
     pub server_verify_mode: Option<ServerVerifyMode>,
     /// optionally define raw (PEM-encoded) client auth certs
@@ -40,6 +60,18 @@ impl ClientConfig {
             (maybe_our_ext, None) => maybe_our_ext,
         };
 
+        if let Some(protocol_versions) = other.protocol_versions {
+            self.protocol_versions = Some(protocol_versions);
+        }
+
+        if let Some(alpn) = other.application_layer_protocol_negotiation {
+            self.application_layer_protocol_negotiation = Some(alpn);
+        }
+
+        if let Some(server_name) = other.server_name {
+            self.server_name = Some(server_name);
+        }
+
         if let Some(server_verify_mode) = other.server_verify_mode {
             self.server_verify_mode = Some(server_verify_mode);
         }
@@ -72,6 +104,65 @@ pub struct ClientAuthData {
     pub cert_chain: DataEncoding,
 }
 
+impl ClientAuthData {
+    /// Create [`ClientAuthData`] from the DER-encoded certificate chain
+    /// (leaf certificate first) and DER-encoded private key,
+    /// e.g. as loaded from a secret store, without having to write them to disk.
+    pub fn from_der(
+        cert_chain: impl IntoIterator<Item = Vec<u8>>,
+        private_key: Vec<u8>,
+    ) -> Result<Self, OpaqueError> {
+        let mut cert_chain: Vec<_> = cert_chain.into_iter().collect();
+        let cert_chain = match cert_chain.len() {
+            0 => {
+                return Err(OpaqueError::from_display(
+                    "client auth data: empty DER cert chain",
+                ))
+            }
+            1 => DataEncoding::Der(cert_chain.pop().unwrap()),
+            _ => DataEncoding::DerStack(cert_chain),
+        };
+        if private_key.is_empty() {
+            return Err(OpaqueError::from_display(
+                "client auth data: empty DER private key",
+            ));
+        }
+        Ok(Self {
+            private_key: DataEncoding::Der(private_key),
+            cert_chain,
+        })
+    }
+
+    /// Create [`ClientAuthData`] from the PEM-encoded certificate chain
+    /// (leaf certificate first) and PEM-encoded private key,
+    /// e.g. as loaded from a secret store, without having to write them to disk.
+    pub fn from_pem(
+        cert_chain: impl Into<String>,
+        private_key: impl Into<String>,
+    ) -> Result<Self, OpaqueError> {
+        Ok(Self {
+            private_key: DataEncoding::Pem(
+                private_key
+                    .into()
+                    .try_into()
+                    .context("client auth data: PEM private key")?,
+            ),
+            cert_chain: DataEncoding::Pem(
+                cert_chain
+                    .into()
+                    .try_into()
+                    .context("client auth data: PEM cert chain")?,
+            ),
+        })
+    }
+}
+
+impl From<ClientAuthData> for ClientAuth {
+    fn from(value: ClientAuthData) -> Self {
+        ClientAuth::Single(value)
+    }
+}
+
 #[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
 /// Mode of server verification by a (tls) client
 pub enum ServerVerifyMode {
@@ -81,6 +172,22 @@ pub enum ServerVerifyMode {
     Auto,
     /// Explicitly disable server verification (if possible)
     Disable,
+    /// Only accept a server presenting the (leaf) certificate matching the given pin,
+    /// regardless of the issuer or the (server) name(s) it is valid for.
+    Pinned(ServerCertPin),
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+/// Server Name Indication (SNI) policy of a (tls) client
+pub enum ServerNameIndication {
+    #[default]
+    /// Indicate the (domain) name of the server the client connects to
+    Auto,
+    /// Do not send the server name indication extension
+    Disable,
+    /// Indicate the given host, instead of the one the client connects to,
+    /// which is also the name the server (cert) is verified against
+    Custom(Host),
 }
 
 impl From<super::ClientHello> for ClientConfig {
@@ -94,3 +201,54 @@ impl From<super::ClientHello> for ClientConfig {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::address::Domain;
+
+    #[test]
+    fn test_client_config_merge() {
+        let mut config = ClientConfig {
+            protocol_versions: Some(vec![ProtocolVersion::TLSv1_2]),
+            application_layer_protocol_negotiation: Some(vec![ApplicationProtocol::HTTP_11]),
+            server_name: Some(ServerNameIndication::Disable),
+            ..Default::default()
+        };
+        config.merge(ClientConfig {
+            application_layer_protocol_negotiation: Some(vec![ApplicationProtocol::HTTP_2]),
+            server_name: Some(ServerNameIndication::Custom(Domain::example().into())),
+            ..Default::default()
+        });
+
+        assert_eq!(
+            config.protocol_versions,
+            Some(vec![ProtocolVersion::TLSv1_2])
+        );
+        assert_eq!(
+            config.application_layer_protocol_negotiation,
+            Some(vec![ApplicationProtocol::HTTP_2])
+        );
+        assert_eq!(
+            config.server_name,
+            Some(ServerNameIndication::Custom(Domain::example().into()))
+        );
+    }
+
+    #[test]
+    fn test_client_auth_data_from_der() {
+        let data = ClientAuthData::from_der([vec![1]], vec![2]).unwrap();
+        assert_eq!(data.cert_chain, DataEncoding::Der(vec![1]));
+        assert_eq!(data.private_key, DataEncoding::Der(vec![2]));
+
+        let data = ClientAuthData::from_der([vec![1], vec![3]], vec![2]).unwrap();
+        assert_eq!(
+            data.cert_chain,
+            DataEncoding::DerStack(vec![vec![1], vec![3]])
+        );
+
+        assert!(ClientAuthData::from_der([], vec![2]).is_err());
+        assert!(ClientAuthData::from_der([vec![1]], vec![]).is_err());
+        assert!(ClientAuthData::from_pem("", "key").is_err());
+    }
+}
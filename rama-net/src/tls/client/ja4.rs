@@ -0,0 +1,251 @@
+use super::{ClientHello, ClientHelloExtension};
+use crate::tls::{ExtensionId, ProtocolVersion};
+use sha2::{Digest, Sha256};
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+/// The [JA4] fingerprint of a [`ClientHello`],
+/// e.g. `t13d1516h2_8daaf6152771_e5627efa2ab1`.
+///
+/// The version is taken from the supported versions extension,
+/// and is `00` in case that extension is missing, as the legacy
+/// version field of the [`ClientHello`] is not preserved.
+///
+/// Note that a [`ClientHello`] created from a rustls client hello only contains
+/// a couple of its extensions, resulting in a fingerprint which differs
+/// from the one computed from the raw client hello.
+///
+/// [JA4]: https://github.com/FoxIO-LLC/ja4/blob/main/technical_details/JA4.md
+pub struct Ja4 {
+    quic: bool,
+    version: &'static str,
+    sni: bool,
+    cipher_count: usize,
+    extension_count: usize,
+    alpn: String,
+    cipher_hash: String,
+    extension_hash: String,
+}
+
+impl Ja4 {
+    /// Compute the [`Ja4`] fingerprint of a [`ClientHello`] received over TCP.
+    pub fn compute(hello: &ClientHello) -> Self {
+        Self::compute_inner(hello, false)
+    }
+
+    /// Compute the [`Ja4`] fingerprint of a [`ClientHello`] received over QUIC.
+    pub fn compute_quic(hello: &ClientHello) -> Self {
+        Self::compute_inner(hello, true)
+    }
+
+    fn compute_inner(hello: &ClientHello, quic: bool) -> Self {
+        let mut ciphers: Vec<u16> = hello
+            .cipher_suites()
+            .iter()
+            .map(|cs| u16::from(*cs))
+            .filter(|v| !is_grease(*v))
+            .collect();
+        let mut extensions: Vec<u16> = hello
+            .extensions()
+            .iter()
+            .map(|ext| u16::from(ext.id()))
+            .filter(|v| !is_grease(*v))
+            .collect();
+        let cipher_count = ciphers.len();
+        let extension_count = extensions.len();
+
+        let version = hello
+            .supported_versions()
+            .and_then(|versions| {
+                versions
+                    .iter()
+                    .filter(|v| !is_grease(u16::from(**v)))
+                    .max_by_key(|v| u16::from(**v))
+            })
+            .map(version_str)
+            .unwrap_or("00");
+
+        let alpn = hello
+            .ext_alpn()
+            .and_then(|protocols| protocols.first())
+            .map(|protocol| alpn_str(protocol.as_bytes()))
+            .unwrap_or_else(|| "00".to_owned());
+
+        ciphers.sort_unstable();
+        let cipher_hash = hash12(&join_hex(&ciphers), ciphers.is_empty());
+
+        extensions.retain(|id| {
+            *id != u16::from(ExtensionId::SERVER_NAME)
+                && *id != u16::from(ExtensionId::APPLICATION_LAYER_PROTOCOL_NEGOTIATION)
+        });
+        extensions.sort_unstable();
+        let mut extension_input = join_hex(&extensions);
+        let signature_algorithms: Vec<u16> = hello
+            .ext_signature_algorithms()
+            .unwrap_or_default()
+            .iter()
+            .map(|scheme| u16::from(*scheme))
+            .collect();
+        if !signature_algorithms.is_empty() {
+            extension_input.push('_');
+            extension_input.push_str(&join_hex(&signature_algorithms));
+        }
+        let extension_hash = hash12(&extension_input, extensions.is_empty());
+
+        Self {
+            quic,
+            version,
+            sni: hello
+                .extensions()
+                .iter()
+                .any(|ext| matches!(ext, ClientHelloExtension::ServerName(_))),
+            cipher_count,
+            extension_count,
+            alpn,
+            cipher_hash,
+            extension_hash,
+        }
+    }
+}
+
+impl fmt::Display for Ja4 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}{}{}{:02}{:02}{}_{}_{}",
+            if self.quic { 'q' } else { 't' },
+            self.version,
+            if self.sni { 'd' } else { 'i' },
+            self.cipher_count.min(99),
+            self.extension_count.min(99),
+            self.alpn,
+            self.cipher_hash,
+            self.extension_hash,
+        )
+    }
+}
+
+fn is_grease(value: u16) -> bool {
+    value & 0x0f0f == 0x0a0a && value >> 8 == value & 0xff
+}
+
+fn version_str(version: &ProtocolVersion) -> &'static str {
+    match version {
+        ProtocolVersion::SSLv2 => "s2",
+        ProtocolVersion::SSLv3 => "s3",
+        ProtocolVersion::TLSv1_0 => "10",
+        ProtocolVersion::TLSv1_1 => "11",
+        ProtocolVersion::TLSv1_2 => "12",
+        ProtocolVersion::TLSv1_3 => "13",
+        ProtocolVersion::DTLSv1_0 => "d1",
+        ProtocolVersion::DTLSv1_2 => "d2",
+        ProtocolVersion::DTLSv1_3 => "d3",
+        _ => "00",
+    }
+}
+
+fn alpn_str(protocol: &[u8]) -> String {
+    match (protocol.first(), protocol.last()) {
+        (Some(first), Some(last))
+            if first.is_ascii_alphanumeric() && last.is_ascii_alphanumeric() =>
+        {
+            format!("{}{}", *first as char, *last as char)
+        }
+        (Some(first), Some(last)) => {
+            let first = hex::encode([*first]);
+            let last = hex::encode([*last]);
+            format!("{}{}", &first[..1], &last[1..])
+        }
+        _ => "00".to_owned(),
+    }
+}
+
+fn join_hex(values: &[u16]) -> String {
+    values
+        .iter()
+        .map(|v| format!("{v:04x}"))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+fn hash12(input: &str, empty: bool) -> String {
+    if empty {
+        return "000000000000".to_owned();
+    }
+    let mut hash = hex::encode(Sha256::digest(input.as_bytes()));
+    hash.truncate(12);
+    hash
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        address::{Domain, Host},
+        tls::{ApplicationProtocol, CipherSuite, SignatureScheme, SupportedGroup},
+    };
+
+    #[test]
+    fn test_ja4() {
+        let hello = ClientHello {
+            cipher_suites: vec![
+                CipherSuite::from(0x0a0a),
+                CipherSuite::from(0x1301),
+                CipherSuite::from(0xc02b),
+                CipherSuite::from(0x1302),
+            ],
+            compression_algorithms: vec![],
+            extensions: vec![
+                ClientHelloExtension::Opaque {
+                    id: ExtensionId::from(0x1a1a),
+                    data: vec![],
+                },
+                ClientHelloExtension::ServerName(Some(Host::Name(Domain::from_static(
+                    "example.com",
+                )))),
+                ClientHelloExtension::SupportedGroups(vec![SupportedGroup::from(0x001d)]),
+                ClientHelloExtension::ApplicationLayerProtocolNegotiation(vec![
+                    ApplicationProtocol::HTTP_2,
+                    ApplicationProtocol::HTTP_11,
+                ]),
+                ClientHelloExtension::SignatureAlgorithms(vec![
+                    SignatureScheme::from(0x0403),
+                    SignatureScheme::from(0x0804),
+                ]),
+                ClientHelloExtension::SupportedVersions(vec![
+                    ProtocolVersion::from(0x3a3a),
+                    ProtocolVersion::TLSv1_3,
+                    ProtocolVersion::TLSv1_2,
+                ]),
+            ],
+        };
+
+        assert_eq!(
+            Ja4::compute(&hello).to_string(),
+            "t13d0305h2_5559582ccdc4_fbabbea27ee8"
+        );
+        assert!(Ja4::compute_quic(&hello)
+            .to_string()
+            .starts_with("q13d0305h2_"));
+    }
+
+    #[test]
+    fn test_ja4_empty() {
+        let hello = ClientHello {
+            cipher_suites: vec![],
+            compression_algorithms: vec![],
+            extensions: vec![],
+        };
+        assert_eq!(
+            Ja4::compute(&hello).to_string(),
+            "t00i000000_000000000000_000000000000"
+        );
+    }
+
+    #[test]
+    fn test_ja4_alpn_str() {
+        assert_eq!(alpn_str(b"h2"), "h2");
+        assert_eq!(alpn_str(b"http/1.1"), "h1");
+        assert_eq!(alpn_str(&[0xab, 0xcd]), "ad");
+    }
+}
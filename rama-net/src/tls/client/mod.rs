@@ -12,12 +12,25 @@ mod hello;
 #[doc(inline)]
 pub use hello::{ClientHello, ClientHelloExtension};
 
-#[cfg(feature = "boring")]
+mod ja4;
+#[doc(inline)]
+pub use ja4::Ja4;
+
+#[cfg(any(feature = "boring", feature = "fuzzing"))]
 mod parser;
+#[cfg(feature = "fuzzing")]
+#[doc(inline)]
+pub use parser::parse_client_hello;
 
 mod config;
 #[doc(inline)]
-pub use config::{ClientAuth, ClientAuthData, ClientConfig, ServerVerifyMode};
+pub use config::{
+    ClientAuth, ClientAuthData, ClientConfig, ServerNameIndication, ServerVerifyMode,
+};
+
+mod verify;
+#[doc(inline)]
+pub use verify::{ServerCertPin, ServerVerifyOutcome, ServerVerifyPolicy};
 
 use super::{ApplicationProtocol, ProtocolVersion};
 
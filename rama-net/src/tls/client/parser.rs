@@ -20,7 +20,11 @@ use rama_core::error::OpaqueError;
 use std::str;
 
 #[inline]
-pub(crate) fn parse_client_hello(i: &[u8]) -> Result<ClientHello, OpaqueError> {
+/// Parse the raw bytes of a tls client hello handshake message (without record header)
+/// into a [`ClientHello`].
+///
+/// Only exposed with the `fuzzing` feature enabled.
+pub fn parse_client_hello(i: &[u8]) -> Result<ClientHello, OpaqueError> {
     match parse_client_hello_inner(i) {
         Err(err) => Err(OpaqueError::from_display(format!(
             "parse client hello handshake message: {err:?}"
@@ -0,0 +1,246 @@
+use super::ServerVerifyMode;
+use crate::address::{Domain, Host};
+use rama_core::error::{ErrorContext, OpaqueError};
+use std::{fmt, str::FromStr};
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+/// A pin of a server (leaf) certificate,
+/// being the SHA-256 fingerprint of its DER encoding.
+///
+/// Can be parsed from its hex representation, with or without
+/// colon separators (e.g. as printed by `openssl x509 -fingerprint -sha256`).
+pub struct ServerCertPin([u8; 32]);
+
+impl ServerCertPin {
+    /// Create a new [`ServerCertPin`] from the SHA-256 fingerprint
+    /// of the DER encoded (leaf) certificate.
+    pub const fn from_sha256(fingerprint: [u8; 32]) -> Self {
+        Self(fingerprint)
+    }
+
+    /// Returns the SHA-256 fingerprint of this [`ServerCertPin`].
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+
+    /// Returns `true` if the given SHA-256 fingerprint matches this [`ServerCertPin`].
+    pub fn matches(&self, fingerprint: &[u8]) -> bool {
+        self.0[..] == *fingerprint
+    }
+}
+
+impl fmt::Debug for ServerCertPin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("ServerCertPin")
+            .field(&format_args!("{self}"))
+            .finish()
+    }
+}
+
+impl fmt::Display for ServerCertPin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, b) in self.0.iter().enumerate() {
+            if i > 0 {
+                f.write_str(":")?;
+            }
+            write!(f, "{b:02X}")?;
+        }
+        Ok(())
+    }
+}
+
+impl From<[u8; 32]> for ServerCertPin {
+    fn from(fingerprint: [u8; 32]) -> Self {
+        Self(fingerprint)
+    }
+}
+
+impl FromStr for ServerCertPin {
+    type Err = OpaqueError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let hex: Vec<u8> = s.bytes().filter(|b| *b != b':').collect();
+        if hex.len() != 64 {
+            return Err(OpaqueError::from_display(
+                "invalid server cert pin: expected 32 hex-encoded bytes",
+            ));
+        }
+        let mut fingerprint = [0; 32];
+        for (byte, pair) in fingerprint.iter_mut().zip(hex.chunks_exact(2)) {
+            let pair = std::str::from_utf8(pair).context("invalid server cert pin")?;
+            *byte = u8::from_str_radix(pair, 16).context("invalid server cert pin")?;
+        }
+        Ok(Self(fingerprint))
+    }
+}
+
+impl TryFrom<&str> for ServerCertPin {
+    type Error = OpaqueError;
+
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+/// A policy which defines the [`ServerVerifyMode`] per destination (server) host,
+/// e.g. to verify most upstream servers strictly, while accepting
+/// a pinned certificate for an internal server.
+///
+/// Rules are matched in the order they are added, and the first matching rule wins.
+/// Hosts that match no rule use the default mode of the policy, if any,
+/// or otherwise the mode configured in the tls connector data.
+///
+/// Tls connectors that support this policy record the applied mode
+/// as a [`ServerVerifyOutcome`] in the [`Context`].
+///
+/// [`Context`]: rama_core::Context
+pub struct ServerVerifyPolicy {
+    rules: Vec<(HostPattern, ServerVerifyMode)>,
+    default: Option<ServerVerifyMode>,
+}
+
+#[derive(Debug, Clone)]
+enum HostPattern {
+    Host(Host),
+    SubDomain(Domain),
+}
+
+impl HostPattern {
+    fn matches(&self, host: &Host) -> bool {
+        match (self, host) {
+            (HostPattern::Host(Host::Name(a)), Host::Name(b)) => a
+                .as_str()
+                .trim_end_matches('.')
+                .eq_ignore_ascii_case(b.as_str().trim_end_matches('.')),
+            (HostPattern::Host(a), b) => a == b,
+            (HostPattern::SubDomain(parent), Host::Name(domain)) => parent.is_parent_of(domain),
+            (HostPattern::SubDomain(_), Host::Address(_)) => false,
+        }
+    }
+}
+
+impl ServerVerifyPolicy {
+    /// Create a new [`ServerVerifyPolicy`] without any rules.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Use the given [`ServerVerifyMode`] for the given [`Host`] (exact match).
+    pub fn with_host(mut self, host: impl Into<Host>, mode: ServerVerifyMode) -> Self {
+        self.rules.push((HostPattern::Host(host.into()), mode));
+        self
+    }
+
+    /// Use the given [`ServerVerifyMode`] for the given [`Host`] (exact match).
+    pub fn set_host(&mut self, host: impl Into<Host>, mode: ServerVerifyMode) -> &mut Self {
+        self.rules.push((HostPattern::Host(host.into()), mode));
+        self
+    }
+
+    /// Use the given [`ServerVerifyMode`] for the given [`Domain`] and all its subdomains.
+    pub fn with_sub_domain(mut self, domain: Domain, mode: ServerVerifyMode) -> Self {
+        self.rules.push((HostPattern::SubDomain(domain), mode));
+        self
+    }
+
+    /// Use the given [`ServerVerifyMode`] for the given [`Domain`] and all its subdomains.
+    pub fn set_sub_domain(&mut self, domain: Domain, mode: ServerVerifyMode) -> &mut Self {
+        self.rules.push((HostPattern::SubDomain(domain), mode));
+        self
+    }
+
+    /// Use the given [`ServerVerifyMode`] for all hosts that match no other rule.
+    pub fn with_default(mut self, mode: ServerVerifyMode) -> Self {
+        self.default = Some(mode);
+        self
+    }
+
+    /// Use the given [`ServerVerifyMode`] for all hosts that match no other rule.
+    pub fn set_default(&mut self, mode: ServerVerifyMode) -> &mut Self {
+        self.default = Some(mode);
+        self
+    }
+
+    /// Returns the [`ServerVerifyMode`] to be used for the given [`Host`],
+    /// or `None` in case the connector's own configuration is to be used.
+    pub fn mode_for(&self, host: &Host) -> Option<ServerVerifyMode> {
+        self.rules
+            .iter()
+            .find_map(|(pattern, mode)| pattern.matches(host).then_some(*mode))
+            .or(self.default)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// The server verification applied by a tls connector
+/// to establish a connection with the given server host,
+/// added to the [`Context`] once the connection is established.
+///
+/// [`Context`]: rama_core::Context
+pub struct ServerVerifyOutcome {
+    /// The server host that was verified.
+    pub server_host: Host,
+    /// The mode used to verify the server.
+    pub mode: ServerVerifyMode,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_server_cert_pin_parse() {
+        let pin: ServerCertPin =
+            "00:11:22:33:44:55:66:77:88:99:aa:bb:cc:dd:ee:ff:00:11:22:33:44:55:66:77:88:99:AA:BB:CC:DD:EE:FF"
+                .parse()
+                .unwrap();
+        assert_eq!(pin.as_bytes()[..2], [0x00, 0x11]);
+        assert_eq!(pin.as_bytes()[31], 0xff);
+        assert_eq!(
+            pin.to_string(),
+            "00:11:22:33:44:55:66:77:88:99:AA:BB:CC:DD:EE:FF:00:11:22:33:44:55:66:77:88:99:AA:BB:CC:DD:EE:FF"
+        );
+
+        let pin2: ServerCertPin = pin.to_string().replace(':', "").parse().unwrap();
+        assert_eq!(pin, pin2);
+
+        for invalid in ["", "00:11", &"zz".repeat(32), &"0".repeat(65)] {
+            assert!(invalid.parse::<ServerCertPin>().is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn test_server_verify_policy() {
+        let pin = ServerCertPin::from_sha256([1; 32]);
+        let policy = ServerVerifyPolicy::new()
+            .with_host(
+                Domain::from_static("internal.example.com"),
+                ServerVerifyMode::Pinned(pin),
+            )
+            .with_sub_domain(Domain::from_static("example.com"), ServerVerifyMode::Auto)
+            .with_host(
+                Host::Address("127.0.0.1".parse().unwrap()),
+                ServerVerifyMode::Disable,
+            );
+
+        for (host, expected) in [
+            ("internal.example.com", Some(ServerVerifyMode::Pinned(pin))),
+            ("INTERNAL.example.com.", Some(ServerVerifyMode::Pinned(pin))),
+            ("example.com", Some(ServerVerifyMode::Auto)),
+            ("www.example.com", Some(ServerVerifyMode::Auto)),
+            ("example.org", None),
+            ("127.0.0.1", Some(ServerVerifyMode::Disable)),
+            ("127.0.0.2", None),
+        ] {
+            let host: Host = host.parse().unwrap();
+            assert_eq!(policy.mode_for(&host), expected, "{host}");
+        }
+
+        let policy = policy.with_default(ServerVerifyMode::Auto);
+        assert_eq!(
+            policy.mode_for(&"example.org".parse().unwrap()),
+            Some(ServerVerifyMode::Auto)
+        );
+    }
+}
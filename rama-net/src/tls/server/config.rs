@@ -1,7 +1,10 @@
+use rama_core::error::{ErrorContext, OpaqueError};
 use serde::{Deserialize, Serialize};
+use std::{net::IpAddr, path::PathBuf, str::FromStr};
 
+use super::ServerSignerData;
 use crate::{
-    address::Host,
+    address::{Domain, Host},
     tls::{ApplicationProtocol, DataEncoding, KeyLogIntent, ProtocolVersion},
 };
 
@@ -51,6 +54,9 @@ pub enum ServerAuth {
     SelfSigned(SelfSignedData),
     /// Single data provided by the configurator
     Single(ServerAuthData),
+    /// Single data provided by the configurator,
+    /// with the private key only available as a signer (e.g. HSM or KMS)
+    Signer(ServerSignerData),
     /// Issuer which provides certs on the fly
     CertIssuer(ServerCertIssuerData),
 }
@@ -68,6 +74,59 @@ pub struct ServerCertIssuerData {
     /// The max amount of certs to cache,
     /// a default value of 8096 is used if 0.
     pub max_cache_size: u64,
+    /// Where the CA of a [`ServerCertIssuerKind::SelfSigned`] issuer is persisted,
+    /// ignored for other kinds of issuers.
+    pub ca_storage: SelfSignedCaStorage,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+/// Storage of the CA generated for a [`ServerCertIssuerKind::SelfSigned`] issuer.
+///
+/// The CA is persisted by default, such that a restarted (MITM) proxy keeps issuing
+/// certs using the same CA, which its clients might already trust. A persisted CA
+/// which can no longer be used (e.g. because it expired) is replaced by a new one.
+/// The files of a CA are named after its subject, such that
+/// issuers with a different subject can share the same directory.
+pub enum SelfSignedCaStorage {
+    #[default]
+    /// Persist the CA in the OS-appropriate data directory of rama:
+    ///
+    /// - `$XDG_DATA_HOME/rama` or `~/.local/share/rama` on Linux (and other unix systems);
+    /// - `~/Library/Application Support/rama` on macOS;
+    /// - `%LOCALAPPDATA%\rama` on Windows.
+    DataDir,
+    /// Persist the CA in the given directory.
+    Dir(PathBuf),
+    /// Do not persist the CA, generating a new CA each time.
+    Disabled,
+}
+
+impl SelfSignedCaStorage {
+    /// The directory to persist the CA in,
+    /// `None` in case persistence is disabled or no data directory can be found.
+    pub fn dir(&self) -> Option<PathBuf> {
+        match self {
+            SelfSignedCaStorage::DataDir => os_data_dir().map(|dir| dir.join("rama")),
+            SelfSignedCaStorage::Dir(dir) => Some(dir.clone()),
+            SelfSignedCaStorage::Disabled => None,
+        }
+    }
+}
+
+fn os_data_dir() -> Option<PathBuf> {
+    let env_dir = |name| {
+        std::env::var_os(name)
+            .map(PathBuf::from)
+            .filter(|dir| dir.is_absolute())
+    };
+    if cfg!(windows) {
+        env_dir("LOCALAPPDATA").or_else(|| env_dir("APPDATA"))
+    } else if cfg!(target_os = "macos") {
+        env_dir("HOME").map(|home| home.join("Library").join("Application Support"))
+    } else {
+        env_dir("XDG_DATA_HOME")
+            .or_else(|| env_dir("HOME").map(|home| home.join(".local").join("share")))
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -99,9 +158,68 @@ pub struct SelfSignedData {
     pub common_name: Option<Host>,
     /// Subject Alternative Names (SAN) can be defined
     /// to create a cert which allows multiple hostnames or domains to be secured under one certificate.
+    ///
+    /// Each name is either a DNS name, which can be a wildcard (e.g. `*.example.com`),
+    /// or an IPv4/IPv6 address (e.g. `127.0.0.1`, `::1` or `[::1]`).
     pub subject_alternative_names: Option<Vec<String>>,
 }
 
+impl SelfSignedData {
+    /// Returns the [`SubjectAltName`] entries to be used for the (leaf) certificate:
+    /// the common name (`localhost` if not defined), followed by
+    /// the [`subject_alternative_names`], without duplicates.
+    ///
+    /// An error is returned if one of the [`subject_alternative_names`] is invalid.
+    ///
+    /// [`subject_alternative_names`]: Self::subject_alternative_names
+    pub fn subject_alt_names(&self) -> Result<Vec<SubjectAltName>, OpaqueError> {
+        let common_name = match &self.common_name {
+            Some(Host::Name(domain)) => SubjectAltName::Dns(domain.as_str().to_ascii_lowercase()),
+            Some(Host::Address(addr)) => SubjectAltName::Ip(*addr),
+            None => SubjectAltName::Dns("localhost".to_owned()),
+        };
+        let mut names = vec![common_name];
+        for name in self.subject_alternative_names.iter().flatten() {
+            let name: SubjectAltName = name.parse()?;
+            if !names.contains(&name) {
+                names.push(name);
+            }
+        }
+        Ok(names)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+/// A Subject Alternative Name (SAN) entry of a certificate.
+pub enum SubjectAltName {
+    /// A DNS name, which can be a wildcard (e.g. `*.example.com`).
+    ///
+    /// Stored in lowercase, as DNS names are case insensitive.
+    Dns(String),
+    /// An IPv4 or IPv6 address.
+    Ip(IpAddr),
+}
+
+impl FromStr for SubjectAltName {
+    type Err = OpaqueError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let ip = s
+            .strip_prefix('[')
+            .and_then(|s| s.strip_suffix(']'))
+            .unwrap_or(s);
+        if let Ok(ip) = ip.parse() {
+            return Ok(Self::Ip(ip));
+        }
+
+        let name = s.trim_end_matches('.').to_ascii_lowercase();
+        let domain = name.strip_prefix("*.").unwrap_or(&name);
+        Domain::try_from(domain.to_owned())
+            .with_context(|| format!("invalid subject alternative name: {s}"))?;
+        Ok(Self::Dns(name))
+    }
+}
+
 #[derive(Debug, Clone)]
 /// Raw private key and certificate data to facilitate server authentication.
 pub struct ServerAuthData {
@@ -126,3 +244,57 @@ pub enum ClientVerifyMode {
     /// PEM-encoded certificate chain containing the acceptable client certificates
     ClientAuth(DataEncoding),
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_self_signed_data_subject_alt_names() {
+        let data = SelfSignedData {
+            common_name: Some(Host::Name(Domain::from_static("example.com"))),
+            subject_alternative_names: Some(vec![
+                "*.example.com".to_owned(),
+                "Example.COM".to_owned(),
+                "api.example.org.".to_owned(),
+                "127.0.0.1".to_owned(),
+                "::1".to_owned(),
+                "[2001:db8::1]".to_owned(),
+            ]),
+            ..Default::default()
+        };
+        assert_eq!(
+            data.subject_alt_names().unwrap(),
+            vec![
+                SubjectAltName::Dns("example.com".to_owned()),
+                SubjectAltName::Dns("*.example.com".to_owned()),
+                SubjectAltName::Dns("api.example.org".to_owned()),
+                SubjectAltName::Ip("127.0.0.1".parse().unwrap()),
+                SubjectAltName::Ip("::1".parse().unwrap()),
+                SubjectAltName::Ip("2001:db8::1".parse().unwrap()),
+            ]
+        );
+
+        assert_eq!(
+            SelfSignedData::default().subject_alt_names().unwrap(),
+            vec![SubjectAltName::Dns("localhost".to_owned())]
+        );
+
+        for invalid in ["", "*", "foo.*.com", "*.*.com", "exa mple.com", "[::1"] {
+            assert!(invalid.parse::<SubjectAltName>().is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn test_self_signed_ca_storage_dir() {
+        assert_eq!(SelfSignedCaStorage::Disabled.dir(), None);
+        assert_eq!(
+            SelfSignedCaStorage::Dir(PathBuf::from("/var/lib/proxy")).dir(),
+            Some(PathBuf::from("/var/lib/proxy"))
+        );
+        if let Some(dir) = SelfSignedCaStorage::default().dir() {
+            assert!(dir.is_absolute());
+            assert!(dir.ends_with("rama"));
+        }
+    }
+}
@@ -0,0 +1,298 @@
+use serde::Serialize;
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+
+#[cfg(feature = "telemetry")]
+use rama_core::telemetry::opentelemetry::{global, metrics::Gauge, KeyValue};
+
+const DEFAULT_WARN_BEFORE: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// The expiry information of a (server or CA) certificate,
+/// to be monitored by a [`CertExpiryMonitor`].
+pub struct CertExpiry {
+    name: String,
+    not_after: SystemTime,
+    is_ca: bool,
+}
+
+impl CertExpiry {
+    /// Create a new [`CertExpiry`] for a server (leaf) certificate.
+    pub fn new(name: impl Into<String>, not_after: SystemTime) -> Self {
+        Self {
+            name: name.into(),
+            not_after,
+            is_ca: false,
+        }
+    }
+
+    /// Create a new [`CertExpiry`] for a (issuing) CA certificate.
+    pub fn new_ca(name: impl Into<String>, not_after: SystemTime) -> Self {
+        Self {
+            name: name.into(),
+            not_after,
+            is_ca: true,
+        }
+    }
+
+    /// The name of the certificate, e.g. its subject common name.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The moment after which the certificate is no longer valid.
+    pub fn not_after(&self) -> SystemTime {
+        self.not_after
+    }
+
+    /// Returns `true` if this is a (issuing) CA certificate.
+    pub fn is_ca(&self) -> bool {
+        self.is_ca
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+/// The state of a certificate monitored by a [`CertExpiryMonitor`].
+pub enum CertExpiryState {
+    /// The certificate is valid for longer than the warning threshold.
+    Valid,
+    /// The certificate expires within the warning threshold.
+    ExpiringSoon,
+    /// The certificate has expired.
+    Expired,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+/// A snapshot of the expiry status of a monitored certificate,
+/// e.g. to be exposed by an admin or health endpoint.
+pub struct CertExpiryStatus {
+    /// The name of the certificate.
+    pub name: String,
+    /// Whether or not this is a (issuing) CA certificate.
+    pub is_ca: bool,
+    /// The state of the certificate.
+    pub state: CertExpiryState,
+    /// Seconds until the certificate expires, negative if it has already expired.
+    pub expires_in_secs: i64,
+}
+
+/// Monitors the expiry of server certificates and their issuing CA,
+/// logging warnings when the expiry of one of them is near.
+///
+/// The monitor can be checked on demand ([`CertExpiryMonitor::status`]),
+/// used as a readiness condition ([`CertExpiryMonitor::is_ready`]),
+/// or run as a background checker ([`CertExpiryMonitor::run`]).
+/// When the `telemetry` feature is enabled, a check also records
+/// the remaining validity of each certificate as a gauge.
+///
+/// Cloning is cheap, and all clones share the same certificates.
+///
+/// # Example
+///
+/// ```
+/// use rama_net::tls::server::{CertExpiry, CertExpiryMonitor};
+/// use std::time::{Duration, SystemTime};
+///
+/// let monitor = CertExpiryMonitor::new().with_warn_before(Duration::from_secs(7 * 24 * 3600));
+/// monitor.register(CertExpiry::new(
+///     "example.com",
+///     SystemTime::now() + Duration::from_secs(90 * 24 * 3600),
+/// ));
+/// assert!(monitor.is_ready());
+///
+/// // e.g. to be spawned as a background task
+/// let _checker = monitor.clone().run(Duration::from_secs(3600));
+/// ```
+#[derive(Debug, Clone)]
+pub struct CertExpiryMonitor {
+    certs: Arc<Mutex<Vec<CertExpiry>>>,
+    warn_before: Duration,
+    #[cfg(feature = "telemetry")]
+    gauge: Gauge<f64>,
+}
+
+impl Default for CertExpiryMonitor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CertExpiryMonitor {
+    /// Create a new [`CertExpiryMonitor`] without any certificates,
+    /// warning for certificates which expire within 30 days.
+    pub fn new() -> Self {
+        Self {
+            certs: Default::default(),
+            warn_before: DEFAULT_WARN_BEFORE,
+            #[cfg(feature = "telemetry")]
+            gauge: global::meter("rama_net")
+                .f64_gauge("tls.server.cert_expires_in")
+                .with_description("Measures the time until a monitored tls certificate expires.")
+                .with_unit("s")
+                .init(),
+        }
+    }
+
+    /// Define the duration before expiry from which a certificate is reported as expiring soon.
+    ///
+    /// Defaults to 30 days.
+    pub fn with_warn_before(mut self, warn_before: Duration) -> Self {
+        self.warn_before = warn_before;
+        self
+    }
+
+    /// Define the duration before expiry from which a certificate is reported as expiring soon.
+    ///
+    /// Defaults to 30 days.
+    pub fn set_warn_before(&mut self, warn_before: Duration) -> &mut Self {
+        self.warn_before = warn_before;
+        self
+    }
+
+    /// Register a certificate to be monitored.
+    pub fn register(&self, cert: CertExpiry) {
+        self.certs.lock().unwrap().push(cert);
+    }
+
+    /// Stop monitoring all certificates with the given name,
+    /// e.g. because they have been replaced.
+    pub fn remove(&self, name: &str) {
+        self.certs.lock().unwrap().retain(|cert| cert.name != name);
+    }
+
+    /// Returns the current expiry status of all monitored certificates.
+    pub fn status(&self) -> Vec<CertExpiryStatus> {
+        self.status_at(SystemTime::now())
+    }
+
+    fn status_at(&self, now: SystemTime) -> Vec<CertExpiryStatus> {
+        self.certs
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|cert| {
+                let (state, expires_in_secs) = match cert.not_after.duration_since(now) {
+                    Ok(remaining) if remaining > self.warn_before => {
+                        (CertExpiryState::Valid, remaining.as_secs() as i64)
+                    }
+                    Ok(remaining) => (CertExpiryState::ExpiringSoon, remaining.as_secs() as i64),
+                    Err(err) => (CertExpiryState::Expired, -(err.duration().as_secs() as i64)),
+                };
+                CertExpiryStatus {
+                    name: cert.name.clone(),
+                    is_ca: cert.is_ca,
+                    state,
+                    expires_in_secs,
+                }
+            })
+            .collect()
+    }
+
+    /// Returns `true` if none of the monitored certificates have expired.
+    ///
+    /// Certificates expiring soon are still considered ready,
+    /// such that this can be used as a readiness condition.
+    pub fn is_ready(&self) -> bool {
+        self.status()
+            .iter()
+            .all(|status| status.state != CertExpiryState::Expired)
+    }
+
+    /// Check all monitored certificates, logging a warning for each certificate
+    /// that is expiring soon and an error for each certificate that has expired.
+    ///
+    /// Returns the status of all monitored certificates.
+    pub fn check(&self) -> Vec<CertExpiryStatus> {
+        let status = self.status();
+        for cert in &status {
+            match cert.state {
+                CertExpiryState::Valid => tracing::trace!(
+                    cert.name = %cert.name,
+                    cert.is_ca = cert.is_ca,
+                    cert.expires_in_secs = cert.expires_in_secs,
+                    "tls certificate is valid",
+                ),
+                CertExpiryState::ExpiringSoon => tracing::warn!(
+                    cert.name = %cert.name,
+                    cert.is_ca = cert.is_ca,
+                    cert.expires_in_secs = cert.expires_in_secs,
+                    "tls certificate is expiring soon",
+                ),
+                CertExpiryState::Expired => tracing::error!(
+                    cert.name = %cert.name,
+                    cert.is_ca = cert.is_ca,
+                    cert.expires_in_secs = cert.expires_in_secs,
+                    "tls certificate has expired",
+                ),
+            }
+            #[cfg(feature = "telemetry")]
+            self.gauge.record(
+                cert.expires_in_secs as f64,
+                &[
+                    KeyValue::new("cert.name", cert.name.clone()),
+                    KeyValue::new("cert.is_ca", cert.is_ca),
+                ],
+            );
+        }
+        status
+    }
+
+    /// Check all monitored certificates (see [`CertExpiryMonitor::check`])
+    /// at the given interval, forever.
+    ///
+    /// Meant to be spawned as a background task,
+    /// e.g. using a graceful [`Executor`].
+    ///
+    /// [`Executor`]: rama_core::rt::Executor
+    pub async fn run(self, interval: Duration) {
+        let mut interval = tokio::time::interval(interval);
+        loop {
+            interval.tick().await;
+            self.check();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+    #[test]
+    fn test_cert_expiry_monitor_status() {
+        let now = SystemTime::now();
+        let monitor = CertExpiryMonitor::new().with_warn_before(DAY * 7);
+        monitor.register(CertExpiry::new("valid", now + DAY * 30));
+        monitor.register(CertExpiry::new("soon", now + DAY * 3));
+        monitor.register(CertExpiry::new_ca("ca", now + DAY * 365));
+
+        let status = monitor.status_at(now);
+        assert_eq!(
+            status
+                .iter()
+                .map(|s| (s.name.as_str(), s.is_ca, s.state))
+                .collect::<Vec<_>>(),
+            vec![
+                ("valid", false, CertExpiryState::Valid),
+                ("soon", false, CertExpiryState::ExpiringSoon),
+                ("ca", true, CertExpiryState::Valid),
+            ]
+        );
+        assert_eq!(status[1].expires_in_secs, (DAY * 3).as_secs() as i64);
+        assert!(monitor.is_ready());
+
+        monitor.register(CertExpiry::new("expired", now - DAY));
+        let status = monitor.status_at(now);
+        assert_eq!(status[3].state, CertExpiryState::Expired);
+        assert_eq!(status[3].expires_in_secs, -(DAY.as_secs() as i64));
+        assert!(!monitor.is_ready());
+
+        monitor.remove("expired");
+        assert!(monitor.is_ready());
+        assert_eq!(monitor.check().len(), 3);
+    }
+}
@@ -3,6 +3,14 @@
 mod config;
 #[doc(inline)]
 pub use config::{
-    ClientVerifyMode, SelfSignedData, ServerAuth, ServerAuthData, ServerCertIssuerData,
-    ServerCertIssuerKind, ServerConfig,
+    ClientVerifyMode, SelfSignedCaStorage, SelfSignedData, ServerAuth, ServerAuthData,
+    ServerCertIssuerData, ServerCertIssuerKind, ServerConfig, SubjectAltName,
 };
+
+mod signer;
+#[doc(inline)]
+pub use signer::{KeySigner, ServerSignerData};
+
+mod expiry;
+#[doc(inline)]
+pub use expiry::{CertExpiry, CertExpiryMonitor, CertExpiryState, CertExpiryStatus};
@@ -0,0 +1,63 @@
+use rama_core::error::OpaqueError;
+use std::{fmt, sync::Arc};
+
+use crate::tls::{DataEncoding, SignatureScheme};
+
+/// A handle to a private key which is not available as raw key material,
+/// e.g. because it lives in a HSM (PKCS#11) or a cloud KMS.
+///
+/// Instead of loading the private key, the tls implementation
+/// calls back into the [`KeySigner`] each time a handshake
+/// requires a signature to be made with the server's private key.
+///
+/// Signing happens as part of the handshake and is therefore blocking.
+/// Implementations that require network I/O (e.g. a cloud KMS) should
+/// keep this in mind, e.g. by using a dedicated runtime or thread pool.
+pub trait KeySigner: fmt::Debug + Send + Sync + 'static {
+    /// The signature schemes supported by this key, in order of preference.
+    ///
+    /// These have to match the type of the (public) key of the leaf certificate.
+    fn signature_schemes(&self) -> &[SignatureScheme];
+
+    /// Sign the given message using the given signature scheme,
+    /// which is always one of the [`signature_schemes`] of this signer.
+    ///
+    /// The message is not yet hashed, as hashing (if any) is part of the signature scheme.
+    ///
+    /// [`signature_schemes`]: KeySigner::signature_schemes
+    fn sign(&self, scheme: SignatureScheme, message: &[u8]) -> Result<Vec<u8>, OpaqueError>;
+}
+
+impl<T: KeySigner> KeySigner for Arc<T> {
+    fn signature_schemes(&self) -> &[SignatureScheme] {
+        (**self).signature_schemes()
+    }
+
+    fn sign(&self, scheme: SignatureScheme, message: &[u8]) -> Result<Vec<u8>, OpaqueError> {
+        (**self).sign(scheme, message)
+    }
+}
+
+#[derive(Debug, Clone)]
+/// Server auth data for which the private key is only
+/// available as a [`KeySigner`], e.g. a HSM or KMS key handle.
+pub struct ServerSignerData {
+    /// signer of the (external) private key used by the server
+    pub signer: Arc<dyn KeySigner>,
+    /// certificate chain as a companion to the private key
+    pub cert_chain: DataEncoding,
+
+    /// `ocsp` is a DER-encoded OCSP response
+    pub ocsp: Option<Vec<u8>>,
+}
+
+impl ServerSignerData {
+    /// Create a new [`ServerSignerData`] for the given signer and certificate chain.
+    pub fn new(signer: impl KeySigner, cert_chain: DataEncoding) -> Self {
+        Self {
+            signer: Arc::new(signer),
+            cert_chain,
+            ocsp: None,
+        }
+    }
+}
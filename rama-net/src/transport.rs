@@ -1,12 +1,18 @@
 //! transport net logic
 //!
-//! See [`TransportContext`] for the centerpiece of this module.
+//! See `TransportContext` (requires the `http` feature) for the centerpiece of this module,
+//! and [`TransportProtocol`] for the protocol used on the transport layer.
 
+#[cfg(feature = "http")]
 use crate::http::RequestContext;
+#[cfg(feature = "http")]
 use crate::{address::Authority, Protocol};
+#[cfg(feature = "http")]
 use rama_core::{error::OpaqueError, Context};
+#[cfg(feature = "http")]
 use rama_http_types::{dep::http::request::Parts as HttpParts, Request, Version};
 
+#[cfg(feature = "http")]
 #[derive(Debug, Clone, PartialEq, Eq)]
 /// The context as relevant to the transport layer,
 /// often used when operating on Tcp/Udp/Tls.
@@ -26,7 +32,7 @@ pub struct TransportContext {
     pub authority: Authority,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 /// The protocol used for the transport layer.
 pub enum TransportProtocol {
     /// The `tcp` protocol.
@@ -35,6 +41,7 @@ pub enum TransportProtocol {
     Udp,
 }
 
+#[cfg(feature = "http")]
 /// Utility trait to support trait bounds where you wish
 /// to turn combined types into a [`TransportContext`],
 /// not expressible with [`Into`].
@@ -51,6 +58,7 @@ pub trait TryRefIntoTransportContext<State> {
     ) -> Result<TransportContext, Self::Error>;
 }
 
+#[cfg(feature = "http")]
 impl<State, Body> TryFrom<(&Context<State>, &Request<Body>)> for TransportContext {
     type Error = OpaqueError;
 
@@ -67,6 +75,7 @@ impl<State, Body> TryFrom<(&Context<State>, &Request<Body>)> for TransportContex
     }
 }
 
+#[cfg(feature = "http")]
 impl<State> TryFrom<(&Context<State>, &HttpParts)> for TransportContext {
     type Error = OpaqueError;
 
@@ -1,3 +1,5 @@
+use rama_core::context::{LogFieldSet, LogFields};
+
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 /// The identifier of a user.
 ///
@@ -13,6 +15,15 @@ pub enum UserId {
     Token(Vec<u8>),
 }
 
+impl LogFields for UserId {
+    fn log_fields(&self, fields: &mut LogFieldSet) {
+        // tokens are secrets and therefore never logged
+        if let UserId::Username(username) = self {
+            fields.record("user", username);
+        }
+    }
+}
+
 impl PartialEq<str> for UserId {
     fn eq(&self, other: &str) -> bool {
         match self {
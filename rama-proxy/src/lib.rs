@@ -258,6 +258,10 @@ pub use proxydb::{Proxy, ProxyDB, ProxyFilter, ProxyID, ProxyQueryPredicate, Str
 #[doc(inline)]
 pub use proxydb::layer::{ProxyDBLayer, ProxyDBService, ProxyFilterMode, UsernameFormatter};
 
+#[cfg(feature = "credential-rotation")]
+#[doc(inline)]
+pub use proxydb::{ProxyCredentialRotation, ProxyCredentialWatch};
+
 #[cfg(feature = "live-update")]
 #[doc(inline)]
 pub use proxydb::{proxy_db_updater, LiveUpdateProxyDB, LiveUpdateProxyDBSetter};
@@ -1,3 +1,5 @@
+#[cfg(feature = "credential-rotation")]
+use super::ProxyCredentialRotation;
 use super::{Proxy, ProxyDB, ProxyFilter, ProxyQueryPredicate};
 use rama_core::{
     error::{BoxError, ErrorContext, ErrorExt, OpaqueError},
@@ -30,6 +32,8 @@ pub struct ProxyDBService<S, D, P, F> {
     predicate: P,
     username_formatter: F,
     preserve: bool,
+    #[cfg(feature = "credential-rotation")]
+    credential_rotation: Option<ProxyCredentialRotation>,
 }
 
 #[derive(Debug, Clone, Default)]
@@ -58,14 +62,16 @@ where
     F: fmt::Debug,
 {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        f.debug_struct("ProxyDBService")
-            .field("inner", &self.inner)
+        let mut d = f.debug_struct("ProxyDBService");
+        d.field("inner", &self.inner)
             .field("db", &self.db)
             .field("mode", &self.mode)
             .field("predicate", &self.predicate)
             .field("username_formatter", &self.username_formatter)
-            .field("preserve", &self.preserve)
-            .finish()
+            .field("preserve", &self.preserve);
+        #[cfg(feature = "credential-rotation")]
+        d.field("credential_rotation", &self.credential_rotation);
+        d.finish()
     }
 }
 
@@ -84,6 +90,8 @@ where
             predicate: self.predicate.clone(),
             username_formatter: self.username_formatter.clone(),
             preserve: self.preserve,
+            #[cfg(feature = "credential-rotation")]
+            credential_rotation: self.credential_rotation.clone(),
         }
     }
 }
@@ -98,6 +106,8 @@ impl<S, D> ProxyDBService<S, D, bool, ()> {
             predicate: true,
             username_formatter: (),
             preserve: false,
+            #[cfg(feature = "credential-rotation")]
+            credential_rotation: None,
         }
     }
 }
@@ -141,6 +151,28 @@ impl<S, D, P, F> ProxyDBService<S, D, P, F> {
         self
     }
 
+    #[cfg(feature = "credential-rotation")]
+    /// Track the credentials of the selected proxies using the given [`ProxyCredentialRotation`],
+    /// inserting a [`ProxyCredentialWatch`] in the [`Context`] for each selected proxy,
+    /// which becomes stale once the credentials of that proxy rotate.
+    ///
+    /// [`ProxyCredentialWatch`]: crate::ProxyCredentialWatch
+    pub fn credential_rotation(mut self, rotation: ProxyCredentialRotation) -> Self {
+        self.credential_rotation = Some(rotation);
+        self
+    }
+
+    #[cfg(feature = "credential-rotation")]
+    /// Track the credentials of the selected proxies using the given [`ProxyCredentialRotation`],
+    /// inserting a [`ProxyCredentialWatch`] in the [`Context`] for each selected proxy,
+    /// which becomes stale once the credentials of that proxy rotate.
+    ///
+    /// [`ProxyCredentialWatch`]: crate::ProxyCredentialWatch
+    pub fn set_credential_rotation(&mut self, rotation: ProxyCredentialRotation) -> &mut Self {
+        self.credential_rotation = Some(rotation);
+        self
+    }
+
     /// Set a [`ProxyQueryPredicate`] that will be used
     /// to possibly filter out proxies that according to the filters are correct,
     /// but not according to the predicate.
@@ -152,6 +184,8 @@ impl<S, D, P, F> ProxyDBService<S, D, P, F> {
             predicate: p,
             username_formatter: self.username_formatter,
             preserve: self.preserve,
+            #[cfg(feature = "credential-rotation")]
+            credential_rotation: self.credential_rotation,
         }
     }
 
@@ -167,6 +201,8 @@ impl<S, D, P, F> ProxyDBService<S, D, P, F> {
             predicate: self.predicate,
             username_formatter: f,
             preserve: self.preserve,
+            #[cfg(feature = "credential-rotation")]
+            credential_rotation: self.credential_rotation,
         }
     }
 
@@ -219,7 +255,7 @@ where
                         .context("proxydb: select proxy: get transport context")
                 })?
                 .clone();
-            let transport_protocol = transport_ctx.protocol.clone();
+            let transport_protocol = transport_ctx.protocol;
 
             let proxy = self
                 .db
@@ -300,7 +336,14 @@ where
             ctx.insert(proxy_address);
 
             // insert the id of the selected proxy
-            ctx.insert(super::ProxyID::from(proxy.id.clone()));
+            let proxy_id = super::ProxyID::from(proxy.id.clone());
+            #[cfg(feature = "credential-rotation")]
+            if let Some(rotation) = &self.credential_rotation {
+                // observe the credential as stored in the db, as the formatted username may differ per request
+                ctx.insert(rotation.observe(&proxy_id, proxy.address.credential.as_ref()));
+            }
+            rama_core::context::register_log_fields::<super::ProxyID>();
+            ctx.insert(proxy_id);
 
             // insert the entire proxy also in there, for full "Context"
             ctx.insert(proxy);
@@ -342,6 +385,8 @@ pub struct ProxyDBLayer<D, P, F> {
     predicate: P,
     username_formatter: F,
     preserve: bool,
+    #[cfg(feature = "credential-rotation")]
+    credential_rotation: Option<ProxyCredentialRotation>,
 }
 
 impl<D, P, F> fmt::Debug for ProxyDBLayer<D, P, F>
@@ -351,13 +396,15 @@ where
     F: fmt::Debug,
 {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        f.debug_struct("ProxyDBLayer")
-            .field("db", &self.db)
+        let mut d = f.debug_struct("ProxyDBLayer");
+        d.field("db", &self.db)
             .field("mode", &self.mode)
             .field("predicate", &self.predicate)
             .field("username_formatter", &self.username_formatter)
-            .field("preserve", &self.preserve)
-            .finish()
+            .field("preserve", &self.preserve);
+        #[cfg(feature = "credential-rotation")]
+        d.field("credential_rotation", &self.credential_rotation);
+        d.finish()
     }
 }
 
@@ -374,6 +421,8 @@ where
             predicate: self.predicate.clone(),
             username_formatter: self.username_formatter.clone(),
             preserve: self.preserve,
+            #[cfg(feature = "credential-rotation")]
+            credential_rotation: self.credential_rotation.clone(),
         }
     }
 }
@@ -387,6 +436,8 @@ impl<D> ProxyDBLayer<D, bool, ()> {
             predicate: true,
             username_formatter: (),
             preserve: false,
+            #[cfg(feature = "credential-rotation")]
+            credential_rotation: None,
         }
     }
 }
@@ -411,6 +462,28 @@ impl<D, P, F> ProxyDBLayer<D, P, F> {
         self
     }
 
+    #[cfg(feature = "credential-rotation")]
+    /// Track the credentials of the selected proxies using the given [`ProxyCredentialRotation`],
+    /// inserting a [`ProxyCredentialWatch`] in the [`Context`] for each selected proxy,
+    /// which becomes stale once the credentials of that proxy rotate.
+    ///
+    /// [`ProxyCredentialWatch`]: crate::ProxyCredentialWatch
+    pub fn credential_rotation(mut self, rotation: ProxyCredentialRotation) -> Self {
+        self.credential_rotation = Some(rotation);
+        self
+    }
+
+    #[cfg(feature = "credential-rotation")]
+    /// Track the credentials of the selected proxies using the given [`ProxyCredentialRotation`],
+    /// inserting a [`ProxyCredentialWatch`] in the [`Context`] for each selected proxy,
+    /// which becomes stale once the credentials of that proxy rotate.
+    ///
+    /// [`ProxyCredentialWatch`]: crate::ProxyCredentialWatch
+    pub fn set_credential_rotation(&mut self, rotation: ProxyCredentialRotation) -> &mut Self {
+        self.credential_rotation = Some(rotation);
+        self
+    }
+
     /// Set a [`ProxyQueryPredicate`] that will be used
     /// to possibly filter out proxies that according to the filters are correct,
     /// but not according to the predicate.
@@ -421,6 +494,8 @@ impl<D, P, F> ProxyDBLayer<D, P, F> {
             predicate: p,
             username_formatter: self.username_formatter,
             preserve: self.preserve,
+            #[cfg(feature = "credential-rotation")]
+            credential_rotation: self.credential_rotation,
         }
     }
 
@@ -435,6 +510,8 @@ impl<D, P, F> ProxyDBLayer<D, P, F> {
             predicate: self.predicate,
             username_formatter: f,
             preserve: self.preserve,
+            #[cfg(feature = "credential-rotation")]
+            credential_rotation: self.credential_rotation,
         }
     }
 }
@@ -455,6 +532,8 @@ where
             predicate: self.predicate.clone(),
             username_formatter: self.username_formatter.clone(),
             preserve: self.preserve,
+            #[cfg(feature = "credential-rotation")]
+            credential_rotation: self.credential_rotation.clone(),
         }
     }
 }
@@ -639,6 +718,62 @@ mod tests {
         );
     }
 
+    #[cfg(feature = "credential-rotation")]
+    #[tokio::test]
+    async fn test_proxy_db_service_credential_rotation() {
+        let proxy = |address: &str| Proxy {
+            id: NonEmptyString::from_static("42"),
+            address: ProxyAddress::from_str(address).unwrap(),
+            tcp: true,
+            udp: true,
+            http: true,
+            https: true,
+            socks5: true,
+            socks5h: true,
+            datacenter: false,
+            residential: true,
+            mobile: true,
+            pool_id: None,
+            continent: None,
+            country: None,
+            state: None,
+            city: None,
+            carrier: None,
+            asn: None,
+        };
+
+        let rotation = ProxyCredentialRotation::new();
+        let service = |proxy: Proxy| {
+            ProxyDBLayer::new(Arc::new(proxy))
+                .filter_mode(ProxyFilterMode::Default)
+                .credential_rotation(rotation.clone())
+                .layer(service_fn(|ctx: Context<()>, _: Request| async move {
+                    Ok::<_, Infallible>(ctx.get::<crate::ProxyCredentialWatch>().unwrap().clone())
+                }))
+        };
+        let req = || {
+            Request::builder()
+                .uri("https://example.com")
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        let old_service = service(proxy("john:secret@12.34.12.34:8080"));
+        let old_watch = old_service.serve(Context::default(), req()).await.unwrap();
+        assert!(!old_watch.is_stale());
+
+        // same credentials, nothing to drain
+        let watch = old_service.serve(Context::default(), req()).await.unwrap();
+        assert!(!old_watch.is_stale());
+        assert!(!watch.is_stale());
+
+        let new_service = service(proxy("john:rotated@12.34.12.34:8080"));
+        let new_watch = new_service.serve(Context::default(), req()).await.unwrap();
+        assert!(old_watch.is_stale());
+        assert!(watch.is_stale());
+        assert!(!new_watch.is_stale());
+    }
+
     #[tokio::test]
     async fn test_proxy_db_single_proxy_with_username_formatter() {
         let proxy = Proxy {
@@ -1,3 +1,4 @@
+use rama_core::context::{LogFieldSet, LogFields};
 use rama_core::error::{BoxError, ErrorContext, OpaqueError};
 use rama_net::{asn::Asn, transport::TransportContext};
 use rama_utils::str::NonEmptyString;
@@ -23,6 +24,12 @@ pub use csv::{ProxyCsvRowReader, ProxyCsvRowReaderError, ProxyCsvRowReaderErrorK
 
 pub(super) mod layer;
 
+#[cfg(feature = "credential-rotation")]
+mod rotation;
+#[cfg(feature = "credential-rotation")]
+#[doc(inline)]
+pub use rotation::{ProxyCredentialRotation, ProxyCredentialWatch};
+
 mod str;
 #[doc(inline)]
 pub use str::StringFilter;
@@ -39,6 +46,12 @@ impl ProxyID {
     }
 }
 
+impl LogFields for ProxyID {
+    fn log_fields(&self, fields: &mut LogFieldSet) {
+        fields.record("proxy_id", self);
+    }
+}
+
 impl AsRef<str> for ProxyID {
     fn as_ref(&self) -> &str {
         self.0.as_ref()
@@ -0,0 +1,197 @@
+use super::ProxyID;
+use parking_lot::Mutex;
+use rama_net::user::ProxyCredential;
+use std::{collections::HashMap, fmt, sync::Arc};
+use tokio::sync::watch;
+
+/// Tracks the credentials in use per [`ProxyID`], such that connections
+/// established with outdated credentials can be drained gracefully
+/// once the credentials of their upstream proxy rotate.
+///
+/// Used by the [`ProxyDBService`] (see [`ProxyDBLayer::credential_rotation`]), which
+/// observes the credential of each selected proxy and inserts a [`ProxyCredentialWatch`]
+/// in the [`Context`]. Once a different credential is observed for the same proxy
+/// (e.g. because a new [`ProxyDB`] with rotated credentials was set),
+/// all watches created for the previous credential become stale,
+/// while new requests simply use the new credential.
+///
+/// Services which keep connections alive (e.g. a connection pool or tunnel)
+/// are expected to hold on to the [`ProxyCredentialWatch`] of the connection,
+/// and gracefully close it, once idle, when it became stale. The connection pool
+/// of the `ReverseProxyService` (rama-http-backend) does so for the connections
+/// established with a [`ProxyCredentialWatch`] in their [`Context`].
+///
+/// Cloning is cheap, and all clones share the same state.
+///
+/// [`ProxyDBService`]: crate::ProxyDBService
+/// [`ProxyDBLayer::credential_rotation`]: crate::ProxyDBLayer::credential_rotation
+/// [`ProxyDB`]: crate::ProxyDB
+/// [`Context`]: rama_core::Context
+#[derive(Debug, Clone, Default)]
+pub struct ProxyCredentialRotation {
+    proxies: Arc<Mutex<HashMap<ProxyID, CredentialEntry>>>,
+}
+
+#[derive(Debug)]
+struct CredentialEntry {
+    credential: Option<ProxyCredential>,
+    generation: watch::Sender<u64>,
+}
+
+impl ProxyCredentialRotation {
+    /// Create a new [`ProxyCredentialRotation`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Observe the credential in use for the proxy with the given [`ProxyID`],
+    /// returning a [`ProxyCredentialWatch`] which becomes stale as soon as
+    /// a different credential is observed, or the proxy is rotated manually.
+    pub fn observe(
+        &self,
+        id: &ProxyID,
+        credential: Option<&ProxyCredential>,
+    ) -> ProxyCredentialWatch {
+        let mut proxies = self.proxies.lock();
+        let entry = proxies
+            .entry(id.clone())
+            .or_insert_with(|| CredentialEntry {
+                credential: credential.cloned(),
+                generation: watch::Sender::new(0),
+            });
+        if entry.credential.as_ref() != credential {
+            tracing::debug!(proxy.id = %id, "proxy credential rotated: draining old connections");
+            entry.credential = credential.cloned();
+            entry.generation.send_modify(|generation| *generation += 1);
+        }
+        ProxyCredentialWatch::new(entry.generation.subscribe())
+    }
+
+    /// Mark all watches created so far for the proxy with the given [`ProxyID`] as stale,
+    /// e.g. because its credentials are known to be revoked.
+    pub fn rotate(&self, id: &ProxyID) {
+        if let Some(entry) = self.proxies.lock().get(id) {
+            entry.generation.send_modify(|generation| *generation += 1);
+        }
+    }
+
+    /// Mark all watches created so far as stale.
+    pub fn rotate_all(&self) {
+        for entry in self.proxies.lock().values() {
+            entry.generation.send_modify(|generation| *generation += 1);
+        }
+    }
+
+    /// Stop tracking the proxy with the given [`ProxyID`], e.g. because it was removed
+    /// from the [`ProxyDB`], marking all watches created for it as stale.
+    ///
+    /// [`ProxyDB`]: crate::ProxyDB
+    pub fn remove(&self, id: &ProxyID) {
+        if let Some(entry) = self.proxies.lock().remove(id) {
+            entry.generation.send_modify(|generation| *generation += 1);
+        }
+    }
+}
+
+/// A watch on the credential used to establish a connection to an upstream proxy,
+/// created by [`ProxyCredentialRotation::observe`].
+///
+/// Inserted in the [`Context`] by the [`ProxyDBService`] when it is configured
+/// with a [`ProxyCredentialRotation`].
+///
+/// [`Context`]: rama_core::Context
+/// [`ProxyDBService`]: crate::ProxyDBService
+#[derive(Clone)]
+pub struct ProxyCredentialWatch {
+    generation: u64,
+    rx: watch::Receiver<u64>,
+}
+
+impl fmt::Debug for ProxyCredentialWatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ProxyCredentialWatch")
+            .field("generation", &self.generation)
+            .field("stale", &self.is_stale())
+            .finish()
+    }
+}
+
+impl ProxyCredentialWatch {
+    fn new(rx: watch::Receiver<u64>) -> Self {
+        let generation = *rx.borrow();
+        Self { generation, rx }
+    }
+
+    /// Returns `true` if the credential watched has been rotated,
+    /// meaning the connection using it should be drained.
+    pub fn is_stale(&self) -> bool {
+        *self.rx.borrow() != self.generation
+    }
+
+    /// Wait until the credential watched has been rotated,
+    /// meaning the connection using it should be drained.
+    ///
+    /// Resolves immediately if the watch is already stale.
+    pub async fn stale(&self) {
+        let mut rx = self.rx.clone();
+        let generation = self.generation;
+        // an error means the rotation tracker was dropped,
+        // in which case the credential is as good as stale
+        let _ = rx.wait_for(|current| *current != generation).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rama_net::user::Basic;
+    use rama_utils::str::NonEmptyString;
+
+    fn credential(password: &'static str) -> ProxyCredential {
+        ProxyCredential::Basic(Basic::new("john", password))
+    }
+
+    #[tokio::test]
+    async fn test_credential_rotation_observe() {
+        let rotation = ProxyCredentialRotation::new();
+        let id = ProxyID::from(NonEmptyString::from_static("1"));
+        let other_id = ProxyID::from(NonEmptyString::from_static("2"));
+
+        let watch_a = rotation.observe(&id, Some(&credential("a")));
+        let watch_a2 = rotation.observe(&id, Some(&credential("a")));
+        let watch_other = rotation.observe(&other_id, None);
+        assert!(!watch_a.is_stale());
+        assert!(!watch_a2.is_stale());
+
+        let watch_b = rotation.observe(&id, Some(&credential("b")));
+        assert!(watch_a.is_stale());
+        assert!(watch_a2.is_stale());
+        assert!(!watch_b.is_stale());
+        assert!(!watch_other.is_stale());
+
+        watch_a.stale().await;
+
+        rotation.rotate(&other_id);
+        assert!(watch_other.is_stale());
+        assert!(!watch_b.is_stale());
+    }
+
+    #[tokio::test]
+    async fn test_credential_rotation_stale_wakes() {
+        let rotation = ProxyCredentialRotation::new();
+        let id = ProxyID::from(NonEmptyString::from_static("1"));
+
+        let watch = rotation.observe(&id, Some(&credential("a")));
+        let handle = tokio::spawn(async move { watch.stale().await });
+        tokio::task::yield_now().await;
+        assert!(!handle.is_finished());
+
+        rotation.rotate_all();
+        handle.await.unwrap();
+
+        let watch = rotation.observe(&id, Some(&credential("a")));
+        assert!(!watch.is_stale());
+        rotation.remove(&id);
+        assert!(watch.is_stale());
+    }
+}
@@ -0,0 +1,41 @@
+use crate::client::default_tcp_connect;
+use rama_core::{
+    error::{BoxError, ErrorContext},
+    service::balance::{HealthProbe, Upstream},
+    Context,
+};
+use rama_net::address::Authority;
+use std::future::Future;
+
+#[derive(Debug, Clone, Default)]
+#[non_exhaustive]
+/// A [`HealthProbe`] which considers an [`Upstream`] healthy
+/// if a tcp connection can be established with it.
+///
+/// The id of the upstream is used as the [`Authority`] to connect to,
+/// e.g. `example.com:8080`.
+pub struct TcpHealthProbe;
+
+impl TcpHealthProbe {
+    /// Create a new [`TcpHealthProbe`].
+    pub const fn new() -> Self {
+        Self
+    }
+}
+
+impl<S> HealthProbe<S> for TcpHealthProbe
+where
+    S: Send + Sync + 'static,
+{
+    fn probe(
+        &self,
+        upstream: &Upstream<S>,
+    ) -> impl Future<Output = Result<(), BoxError>> + Send + '_ {
+        let authority =
+            Authority::try_from(upstream.id()).context("parse upstream id as authority");
+        async move {
+            let (_stream, _addr) = default_tcp_connect(&Context::default(), authority?).await?;
+            Ok(())
+        }
+    }
+}
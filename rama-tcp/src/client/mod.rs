@@ -7,6 +7,10 @@ mod connect;
 #[doc(inline)]
 pub use connect::{default_tcp_connect, tcp_connect, TcpStreamConnector};
 
+mod health;
+#[doc(inline)]
+pub use health::TcpHealthProbe;
+
 #[cfg(feature = "http")]
 mod request;
 #[cfg(feature = "http")]
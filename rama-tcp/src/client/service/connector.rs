@@ -6,8 +6,10 @@ use rama_dns::{DnsResolver, HickoryDns};
 use rama_net::{
     address::ProxyAddress,
     client::EstablishedClientConnection,
+    stream::{ClientSocketInfo, SocketInfo},
     transport::{TransportProtocol, TryRefIntoTransportContext},
 };
+use std::net::SocketAddr;
 use tokio::net::TcpStream;
 
 use crate::client::connect::TcpStreamConnector;
@@ -93,6 +95,7 @@ where
             )
             .await
             .context("tcp connector: conncept to proxy")?;
+            ctx.insert(client_socket_info(&conn, addr));
             return Ok(EstablishedClientConnection {
                 ctx,
                 req,
@@ -129,6 +132,7 @@ where
         )
         .await
         .context("tcp connector: connect to server")?;
+        ctx.insert(client_socket_info(&conn, addr));
 
         Ok(EstablishedClientConnection {
             ctx,
@@ -138,3 +142,11 @@ where
         })
     }
 }
+
+fn client_socket_info(conn: &TcpStream, peer_addr: SocketAddr) -> ClientSocketInfo {
+    let mut info = SocketInfo::new(conn.local_addr().ok(), peer_addr);
+    if let Some(interface) = crate::utils::bound_device(conn) {
+        info.set_interface(interface);
+    }
+    ClientSocketInfo(info)
+}
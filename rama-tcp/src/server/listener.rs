@@ -2,7 +2,7 @@ use rama_core::graceful::ShutdownGuard;
 use rama_core::rt::Executor;
 use rama_core::Context;
 use rama_core::Service;
-use rama_net::stream::SocketInfo;
+use rama_net::stream::{SocketInfo, TcpInfo};
 use std::fmt;
 use std::pin::pin;
 use std::sync::Arc;
@@ -211,8 +211,7 @@ where
             let mut ctx = ctx.clone();
 
             tokio::spawn(async move {
-                let local_addr = socket.local_addr().ok();
-                ctx.insert(SocketInfo::new(local_addr, peer_addr));
+                ctx.insert(socket_info(&socket, peer_addr));
 
                 let _ = service.serve(ctx, socket).await;
             });
@@ -245,8 +244,7 @@ where
                             let mut ctx = ctx.clone();
 
                             guard.spawn_task(async move {
-                                let local_addr = socket.local_addr().ok();
-                                ctx.insert(SocketInfo::new(local_addr, peer_addr));
+                                ctx.insert(socket_info(&socket, peer_addr));
 
                                 let _ = service.serve(ctx, socket).await;
                             });
@@ -261,6 +259,30 @@ where
     }
 }
 
+fn socket_info(socket: &TcpStream, peer_addr: SocketAddr) -> SocketInfo {
+    let local_addr = socket.local_addr().ok();
+    let mut info = SocketInfo::new(local_addr, peer_addr);
+    if let Some(interface) = crate::utils::bound_device(socket) {
+        info.set_interface(interface);
+    }
+    match tcp_info(socket) {
+        Some(tcp_info) => info.with_tcp_info(tcp_info),
+        None => info,
+    }
+}
+
+/// Collect the TCP-level signals which can be observed for an accepted socket.
+#[cfg(all(unix, not(target_os = "redox")))]
+fn tcp_info(socket: &TcpStream) -> Option<TcpInfo> {
+    let mss = socket2::SockRef::from(socket).mss().ok()?;
+    Some(TcpInfo::new().with_mss(mss))
+}
+
+#[cfg(not(all(unix, not(target_os = "redox"))))]
+fn tcp_info(_socket: &TcpStream) -> Option<TcpInfo> {
+    None
+}
+
 async fn handle_accept_err(err: io::Error) {
     if crate::utils::is_connection_error(&err) {
         tracing::trace!(
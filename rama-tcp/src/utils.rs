@@ -16,3 +16,17 @@ pub fn is_connection_error(e: &io::Error) -> bool {
             | io::ErrorKind::Interrupted
     )
 }
+
+/// Name of the network interface the socket is bound to, if any.
+///
+/// Only available on platforms which expose `SO_BINDTODEVICE`.
+#[cfg(any(target_os = "android", target_os = "fuchsia", target_os = "linux"))]
+pub(crate) fn bound_device(socket: &tokio::net::TcpStream) -> Option<String> {
+    let device = socket2::SockRef::from(socket).device().ok()??;
+    Some(String::from_utf8_lossy(&device).into_owned())
+}
+
+#[cfg(not(any(target_os = "android", target_os = "fuchsia", target_os = "linux")))]
+pub(crate) fn bound_device(_socket: &tokio::net::TcpStream) -> Option<String> {
+    None
+}
@@ -7,7 +7,7 @@ use rama_core::{Context, Layer, Service};
 use rama_net::address::Host;
 use rama_net::client::{ConnectorService, EstablishedClientConnection};
 use rama_net::stream::Stream;
-use rama_net::tls::client::NegotiatedTlsParameters;
+use rama_net::tls::client::{NegotiatedTlsParameters, ServerVerifyOutcome, ServerVerifyPolicy};
 use rama_net::tls::ApplicationProtocol;
 use rama_net::transport::TryRefIntoTransportContext;
 use std::fmt;
@@ -19,6 +19,7 @@ use tokio_boring::SslStream;
 /// See [`TlsConnector`] for more information.
 pub struct TlsConnectorLayer<K = ConnectorKindAuto> {
     connector_data: Option<TlsConnectorData>,
+    server_verify_policy: Option<ServerVerifyPolicy>,
     kind: K,
 }
 
@@ -26,6 +27,7 @@ impl<K: fmt::Debug> std::fmt::Debug for TlsConnectorLayer<K> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("TlsConnectorLayer")
             .field("connector_data", &self.connector_data)
+            .field("server_verify_policy", &self.server_verify_policy)
             .field("kind", &self.kind)
             .finish()
     }
@@ -35,6 +37,7 @@ impl<K: Clone> Clone for TlsConnectorLayer<K> {
     fn clone(&self) -> Self {
         Self {
             connector_data: self.connector_data.clone(),
+            server_verify_policy: self.server_verify_policy.clone(),
             kind: self.kind.clone(),
         }
     }
@@ -61,6 +64,22 @@ impl<K> TlsConnectorLayer<K> {
         self.connector_data = Some(connector_data);
         self
     }
+
+    /// Attach a [`ServerVerifyPolicy`] to this [`TlsConnectorLayer`],
+    /// to define the server verification per destination host,
+    /// overwriting the mode defined in the [`TlsConnectorData`] for matching hosts.
+    pub fn with_server_verify_policy(mut self, policy: ServerVerifyPolicy) -> Self {
+        self.server_verify_policy = Some(policy);
+        self
+    }
+
+    /// Attach a [`ServerVerifyPolicy`] to this [`TlsConnectorLayer`],
+    /// to define the server verification per destination host,
+    /// overwriting the mode defined in the [`TlsConnectorData`] for matching hosts.
+    pub fn set_server_verify_policy(&mut self, policy: ServerVerifyPolicy) -> &mut Self {
+        self.server_verify_policy = Some(policy);
+        self
+    }
 }
 
 impl TlsConnectorLayer<ConnectorKindAuto> {
@@ -70,6 +89,7 @@ impl TlsConnectorLayer<ConnectorKindAuto> {
     pub fn auto() -> Self {
         Self {
             connector_data: None,
+            server_verify_policy: None,
             kind: ConnectorKindAuto,
         }
     }
@@ -81,6 +101,7 @@ impl TlsConnectorLayer<ConnectorKindSecure> {
     pub fn secure() -> Self {
         Self {
             connector_data: None,
+            server_verify_policy: None,
             kind: ConnectorKindSecure,
         }
     }
@@ -92,6 +113,7 @@ impl TlsConnectorLayer<ConnectorKindTunnel> {
     pub fn tunnel(host: Option<Host>) -> Self {
         Self {
             connector_data: None,
+            server_verify_policy: None,
             kind: ConnectorKindTunnel { host },
         }
     }
@@ -104,6 +126,7 @@ impl<K: Clone, S> Layer<S> for TlsConnectorLayer<K> {
         TlsConnector {
             inner,
             connector_data: self.connector_data.clone(),
+            server_verify_policy: self.server_verify_policy.clone(),
             kind: self.kind.clone(),
         }
     }
@@ -125,6 +148,7 @@ impl Default for TlsConnectorLayer<ConnectorKindAuto> {
 pub struct TlsConnector<S, K = ConnectorKindAuto> {
     inner: S,
     connector_data: Option<TlsConnectorData>,
+    server_verify_policy: Option<ServerVerifyPolicy>,
     kind: K,
 }
 
@@ -133,6 +157,7 @@ impl<S: fmt::Debug, K: fmt::Debug> fmt::Debug for TlsConnector<S, K> {
         f.debug_struct("TlsConnector")
             .field("inner", &self.inner)
             .field("connector_data", &self.connector_data)
+            .field("server_verify_policy", &self.server_verify_policy)
             .field("kind", &self.kind)
             .finish()
     }
@@ -143,6 +168,7 @@ impl<S: Clone, K: Clone> Clone for TlsConnector<S, K> {
         Self {
             inner: self.inner.clone(),
             connector_data: self.connector_data.clone(),
+            server_verify_policy: self.server_verify_policy.clone(),
             kind: self.kind.clone(),
         }
     }
@@ -154,6 +180,7 @@ impl<S, K> TlsConnector<S, K> {
         Self {
             inner,
             connector_data: None,
+            server_verify_policy: None,
             kind,
         }
     }
@@ -184,6 +211,22 @@ impl<S, K> TlsConnector<S, K> {
         self.connector_data = Some(connector_data);
         self
     }
+
+    /// Attach a [`ServerVerifyPolicy`] to this [`TlsConnector`],
+    /// to define the server verification per destination host,
+    /// overwriting the mode defined in the [`TlsConnectorData`] for matching hosts.
+    pub fn with_server_verify_policy(mut self, policy: ServerVerifyPolicy) -> Self {
+        self.server_verify_policy = Some(policy);
+        self
+    }
+
+    /// Attach a [`ServerVerifyPolicy`] to this [`TlsConnector`],
+    /// to define the server verification per destination host,
+    /// overwriting the mode defined in the [`TlsConnectorData`] for matching hosts.
+    pub fn set_server_verify_policy(&mut self, policy: ServerVerifyPolicy) -> &mut Self {
+        self.server_verify_policy = Some(policy);
+        self
+    }
 }
 
 impl<S> TlsConnector<S, ConnectorKindAuto> {
@@ -267,7 +310,8 @@ where
         let host = transport_ctx.authority.host().clone();
 
         let connector_data = ctx.get().cloned();
-        let (stream, negotiated_params) = self.handshake(connector_data, host, conn).await?;
+        let (stream, negotiated_params, verify_outcome) =
+            self.handshake(connector_data, host, conn).await?;
 
         tracing::trace!(
             authority = %transport_ctx.authority,
@@ -275,6 +319,7 @@ where
         );
 
         ctx.insert(negotiated_params);
+        ctx.insert(verify_outcome);
 
         Ok(EstablishedClientConnection {
             ctx,
@@ -325,8 +370,10 @@ where
         let host = transport_ctx.authority.host().clone();
 
         let connector_data = ctx.get().cloned();
-        let (conn, negotiated_params) = self.handshake(connector_data, host, conn).await?;
+        let (conn, negotiated_params, verify_outcome) =
+            self.handshake(connector_data, host, conn).await?;
         ctx.insert(negotiated_params);
+        ctx.insert(verify_outcome);
 
         Ok(EstablishedClientConnection {
             ctx,
@@ -381,8 +428,10 @@ where
         };
 
         let connector_data = ctx.get().cloned();
-        let (stream, negotiated_params) = self.handshake(connector_data, host, conn).await?;
+        let (stream, negotiated_params, verify_outcome) =
+            self.handshake(connector_data, host, conn).await?;
         ctx.insert(negotiated_params);
+        ctx.insert(verify_outcome);
 
         tracing::trace!("TlsConnector(tunnel): connection secured");
         Ok(EstablishedClientConnection {
@@ -402,27 +451,50 @@ impl<S, K> TlsConnector<S, K> {
         connector_data: Option<TlsConnectorData>,
         server_host: Host,
         stream: T,
-    ) -> Result<(SslStream<T>, NegotiatedTlsParameters), BoxError>
+    ) -> Result<(SslStream<T>, NegotiatedTlsParameters, ServerVerifyOutcome), BoxError>
     where
         T: Stream + Unpin,
     {
-        let client_config_data = match connector_data.as_ref().or(self.connector_data.as_ref()) {
-            Some(connector_data) => connector_data.try_to_build_config()?,
-            None => TlsConnectorData::new_http_auto()?.try_to_build_config()?,
+        let connector_data = match connector_data.or_else(|| self.connector_data.clone()) {
+            Some(connector_data) => connector_data,
+            None => TlsConnectorData::new_http_auto()?,
         };
-        let server_host = client_config_data.server_name.unwrap_or(server_host);
+        let server_host = connector_data.server_name().cloned().unwrap_or(server_host);
+        let connector_data = match self
+            .server_verify_policy
+            .as_ref()
+            .and_then(|policy| policy.mode_for(&server_host))
+        {
+            Some(mode) => connector_data.with_server_verify_mode(mode),
+            None => connector_data,
+        };
+        let verify_mode = connector_data.server_verify_mode();
+
+        let client_config_data = connector_data.try_to_build_config()?;
         let stream = tokio_boring::connect(
             client_config_data.config,
             server_host.to_string().as_str(),
             stream,
         )
         .await
-        .map_err(|err| match err.as_io_error() {
-            Some(err) => OpaqueError::from_display(err.to_string())
-                .context("boring ssl connector: connect")
-                .into_boxed(),
-            None => OpaqueError::from_display("boring ssl connector: connect").into_boxed(),
+        .map_err(|err| {
+            tracing::debug!(
+                server.host = %server_host,
+                ?verify_mode,
+                "boring ssl connector: handshake failed: {err}",
+            );
+            match err.as_io_error() {
+                Some(err) => OpaqueError::from_display(err.to_string())
+                    .context("boring ssl connector: connect")
+                    .into_boxed(),
+                None => OpaqueError::from_display("boring ssl connector: connect").into_boxed(),
+            }
         })?;
+        tracing::debug!(
+            server.host = %server_host,
+            ?verify_mode,
+            "boring ssl connector: server verified",
+        );
 
         let params = match stream.ssl().session() {
             Some(ssl_session) => {
@@ -450,7 +522,14 @@ impl<S, K> TlsConnector<S, K> {
             }
         };
 
-        Ok((stream, params))
+        Ok((
+            stream,
+            params,
+            ServerVerifyOutcome {
+                server_host,
+                mode: verify_mode,
+            },
+        ))
     }
 }
 
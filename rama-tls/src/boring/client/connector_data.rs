@@ -4,7 +4,10 @@ use boring::{
     hash::MessageDigest,
     pkey::{PKey, Private},
     rsa::Rsa,
-    ssl::{ConnectConfiguration, SslCurve, SslSignatureAlgorithm, SslVerifyMode, SslVersion},
+    ssl::{
+        ConnectConfiguration, SslAlert, SslCurve, SslSignatureAlgorithm, SslVerifyError,
+        SslVerifyMode, SslVersion,
+    },
     x509::{
         extension::{BasicConstraints, KeyUsage, SubjectKeyIdentifier},
         X509,
@@ -12,8 +15,8 @@ use boring::{
 };
 use rama_core::error::{ErrorContext, ErrorExt, OpaqueError};
 use rama_net::tls::{
-    client::{ClientAuth, ClientHelloExtension},
-    DataEncoding,
+    client::{ClientAuth, ClientHelloExtension, ServerNameIndication},
+    DataEncoding, ProtocolVersion,
 };
 use rama_net::tls::{openssl_cipher_list_str_from_cipher_list, ApplicationProtocol, KeyLogIntent};
 use rama_net::{address::Host, tls::client::ServerVerifyMode};
@@ -42,6 +45,7 @@ pub(super) struct ConnectConfigurationInput {
     pub(super) verify_algorithm_prefs: Option<Vec<SslSignatureAlgorithm>>,
     pub(super) server_verify_mode: Option<ServerVerifyMode>,
     pub(super) client_auth: Option<ConnectorConfigClientAuth>,
+    pub(super) enable_sni: Option<bool>,
 }
 
 #[derive(Debug, Clone)]
@@ -141,6 +145,21 @@ impl TlsConnectorData {
                 trace!("boring connector: server verify mode: disable");
                 cfg_builder.set_custom_verify_callback(SslVerifyMode::NONE, |_| Ok(()));
             }
+            ServerVerifyMode::Pinned(pin) => {
+                trace!(%pin, "boring connector: server verify mode: pinned");
+                cfg_builder.set_custom_verify_callback(SslVerifyMode::PEER, move |ssl| {
+                    let matches = ssl
+                        .peer_certificate()
+                        .and_then(|cert| cert.digest(MessageDigest::sha256()).ok())
+                        .map(|fingerprint| pin.matches(&fingerprint))
+                        .unwrap_or_default();
+                    if matches {
+                        Ok(())
+                    } else {
+                        Err(SslVerifyError::Invalid(SslAlert::BAD_CERTIFICATE))
+                    }
+                });
+            }
         }
 
         if let Some(auth) = self.connect_config_input.client_auth.as_ref() {
@@ -170,11 +189,16 @@ impl TlsConnectorData {
         }
 
         trace!("boring connector: build SSL connector config");
-        let cfg = cfg_builder
+        let mut cfg = cfg_builder
             .build()
             .configure()
             .context("create ssl connector configuration")?;
 
+        if let Some(enable_sni) = self.connect_config_input.enable_sni {
+            trace!("boring connector: set SNI: {enable_sni}");
+            cfg.set_use_server_name_indication(enable_sni);
+        }
+
         trace!(
             "boring connector: return SSL connector config for server: {:?}",
             self.server_name
@@ -185,6 +209,24 @@ impl TlsConnectorData {
         })
     }
 
+    /// Returns a copy of this [`TlsConnectorData`],
+    /// with the [`ServerVerifyMode`] overwritten by the given mode.
+    pub(super) fn with_server_verify_mode(&self, mode: ServerVerifyMode) -> TlsConnectorData {
+        let mut connect_config_input = self.connect_config_input.as_ref().clone();
+        connect_config_input.server_verify_mode = Some(mode);
+        TlsConnectorData {
+            connect_config_input: Arc::new(connect_config_input),
+            server_name: self.server_name.clone(),
+        }
+    }
+
+    /// Returns the [`ServerVerifyMode`] used by this [`TlsConnectorData`].
+    pub(super) fn server_verify_mode(&self) -> ServerVerifyMode {
+        self.connect_config_input
+            .server_verify_mode
+            .unwrap_or_default()
+    }
+
     /// Merge `self` together with the `other`, resulting in
     /// a new [`TlsConnectorData`], where any defined properties of `other`
     /// take priority over conflicting ones in `self`.
@@ -233,6 +275,10 @@ impl TlsConnectorData {
                     .client_auth
                     .clone()
                     .or_else(|| self.connect_config_input.client_auth.clone()),
+                enable_sni: other
+                    .connect_config_input
+                    .enable_sni
+                    .or(self.connect_config_input.enable_sni),
             }),
             server_name: other
                 .server_name
@@ -388,28 +434,7 @@ impl TryFrom<rama_net::tls::client::ClientConfig> for TlsConnectorData {
                 }
                 ClientHelloExtension::SupportedVersions(versions) => {
                     trace!("TlsConnectorData: builder: from std client config: supported versions: {:?}", versions);
-
-                    if let Some(min_ver) = versions.iter().min() {
-                        trace!(
-                            "TlsConnectorData: builder: from std client config: min version: {:?}",
-                            min_ver
-                        );
-                        min_ssl_version = Some((*min_ver).try_into().map_err(|v| {
-                            OpaqueError::from_display(format!("protocol version {v}"))
-                                .context("build boring ssl connector: min proto version")
-                        })?);
-                    }
-
-                    if let Some(max_ver) = versions.iter().max() {
-                        trace!(
-                            "TlsConnectorData: builder: from std client config: max version: {:?}",
-                            max_ver
-                        );
-                        max_ssl_version = Some((*max_ver).try_into().map_err(|v| {
-                            OpaqueError::from_display(format!("protocol version {v}"))
-                                .context("build boring ssl connector: max proto version")
-                        })?);
-                    }
+                    (min_ssl_version, max_ssl_version) = ssl_version_range(versions)?;
                 }
                 ClientHelloExtension::SignatureAlgorithms(schemes) => {
                     trace!("TlsConnectorData: builder: from std client config: signature algorithms: {:?}", schemes);
@@ -427,6 +452,40 @@ impl TryFrom<rama_net::tls::client::ClientConfig> for TlsConnectorData {
             }
         }
 
+        // explicit properties take priority over the extensions
+        if let Some(versions) = value.protocol_versions.as_deref() {
+            trace!(
+                "TlsConnectorData: builder: from std client config: protocol versions: {:?}",
+                versions
+            );
+            (min_ssl_version, max_ssl_version) = ssl_version_range(versions)?;
+        }
+        if let Some(alpn_list) = value.application_layer_protocol_negotiation.as_deref() {
+            trace!(
+                "TlsConnectorData: builder: from std client config: alpn (explicit): {:?}",
+                alpn_list
+            );
+            let mut buf = vec![];
+            for alpn in alpn_list {
+                alpn.encode_wire_format(&mut buf)
+                    .context("build (boring) ssl connector: encode alpn")?;
+            }
+            alpn_protos = Some(buf);
+        }
+        let enable_sni = match value.server_name {
+            None => None,
+            Some(ServerNameIndication::Auto) => {
+                server_name = None;
+                Some(true)
+            }
+            Some(ServerNameIndication::Disable) => Some(false),
+            Some(ServerNameIndication::Custom(host)) => {
+                trace!("TlsConnectorData: builder: from std client config: set custom server name: {host}");
+                server_name = Some(host);
+                Some(true)
+            }
+        };
+
         let client_auth = match value.client_auth {
             None => None,
             Some(ClientAuth::SelfSigned) => {
@@ -491,12 +550,48 @@ impl TryFrom<rama_net::tls::client::ClientConfig> for TlsConnectorData {
                 verify_algorithm_prefs,
                 server_verify_mode: value.server_verify_mode,
                 client_auth,
+                enable_sni,
             }),
             server_name,
         })
     }
 }
 
+/// Returns the min and max [`SslVersion`] of the given protocol versions.
+fn ssl_version_range(
+    versions: &[ProtocolVersion],
+) -> Result<(Option<SslVersion>, Option<SslVersion>), OpaqueError> {
+    let min_ssl_version = match versions.iter().min() {
+        Some(min_ver) => {
+            trace!(
+                "TlsConnectorData: builder: from std client config: min version: {:?}",
+                min_ver
+            );
+            Some((*min_ver).try_into().map_err(|v| {
+                OpaqueError::from_display(format!("protocol version {v}"))
+                    .context("build boring ssl connector: min proto version")
+            })?)
+        }
+        None => None,
+    };
+
+    let max_ssl_version = match versions.iter().max() {
+        Some(max_ver) => {
+            trace!(
+                "TlsConnectorData: builder: from std client config: max version: {:?}",
+                max_ver
+            );
+            Some((*max_ver).try_into().map_err(|v| {
+                OpaqueError::from_display(format!("protocol version {v}"))
+                    .context("build boring ssl connector: max proto version")
+            })?)
+        }
+        None => None,
+    };
+
+    Ok((min_ssl_version, max_ssl_version))
+}
+
 fn self_signed_client_auth() -> Result<(Vec<X509>, PKey<Private>), OpaqueError> {
     let rsa = Rsa::generate(4096).context("generate 4096 RSA key")?;
     let privkey = PKey::from_rsa(rsa).context("create private key from 4096 RSA key")?;
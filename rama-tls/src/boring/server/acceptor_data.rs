@@ -1,29 +1,29 @@
+use super::ca_storage::load_or_generate_ca;
+use super::key_signer::KeySignerMethod;
+use super::SelfSignedGenerator;
 use crate::boring::dep::boring::{
     asn1::Asn1Time,
-    bn::{BigNum, MsbOption},
-    hash::MessageDigest,
     nid::Nid,
     pkey::{PKey, Private},
-    rsa::Rsa,
-    x509::{
-        extension::{BasicConstraints, KeyUsage, SubjectKeyIdentifier},
-        X509NameBuilder, X509,
-    },
-};
-use boring::{
-    ssl::{NameType, SniError, SslAcceptorBuilder, SslRef},
-    x509::extension::{AuthorityKeyIdentifier, SubjectAlternativeName},
+    x509::{X509Ref, X509},
 };
+use boring::ssl::{NameType, SniError, SslAcceptorBuilder, SslRef};
 use moka::sync::Cache;
 use rama_core::error::{ErrorContext, OpaqueError};
 use rama_net::{
     address::{Domain, Host},
     tls::{
-        server::{ClientVerifyMode, SelfSignedData, ServerAuth, ServerCertIssuerKind},
+        server::{
+            CertExpiry, ClientVerifyMode, KeySigner, SelfSignedData, ServerAuth,
+            ServerCertIssuerKind,
+        },
         ApplicationProtocol, DataEncoding, KeyLogIntent, ProtocolVersion,
     },
 };
-use std::{sync::Arc, time::Duration};
+use std::{
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
 
 #[derive(Debug, Clone)]
 /// Internal data used as configuration/input for the [`super::TlsAcceptorService`].
@@ -60,6 +60,12 @@ enum TlsCertSourceKind {
         /// Cert Chain of the server
         cert_chain: Vec<X509>,
     },
+    InMemorySigner {
+        /// Signer of the (external) private key of the server
+        signer: Arc<dyn KeySigner>,
+        /// Cert Chain of the server
+        cert_chain: Vec<X509>,
+    },
     InMemoryIssuer {
         /// Cache for certs already issued
         cert_cache: Cache<Host, IssuedCert>,
@@ -87,17 +93,7 @@ impl TlsCertSource {
                 private_key,
                 cert_chain,
             } => {
-                for (i, ca_cert) in cert_chain.iter().enumerate() {
-                    if i == 0 {
-                        builder
-                            .set_certificate(ca_cert.as_ref())
-                            .context("build boring ssl acceptor: set Leaf CA certificate (x509)")?;
-                    } else {
-                        builder.add_extra_chain_cert(ca_cert.clone()).context(
-                            "build boring ssl acceptor: add extra chain certificate (x509)",
-                        )?;
-                    }
-                }
+                set_cert_chain(&mut builder, &cert_chain)?;
                 builder
                     .set_private_key(private_key.as_ref())
                     .context("build boring ssl acceptor: set private key")?;
@@ -105,6 +101,11 @@ impl TlsCertSource {
                     .check_private_key()
                     .context("build boring ssl acceptor: check private key")?;
             }
+            TlsCertSourceKind::InMemorySigner { signer, cert_chain } => {
+                set_cert_chain(&mut builder, &cert_chain)?;
+                // the private key is never loaded, all signing is delegated to the signer
+                builder.set_private_key_method(KeySignerMethod(signer));
+            }
             TlsCertSourceKind::InMemoryIssuer {
                 cert_cache,
                 ca_key,
@@ -158,6 +159,84 @@ impl TlsCertSource {
     }
 }
 
+impl TlsAcceptorData {
+    /// Create a new [`TlsAcceptorData`] from an already parsed private key and
+    /// (server) certificate chain, with the leaf certificate first,
+    /// bypassing the (PEM/DER) parsing of the _rama_ opiniated
+    /// [`rama_net::tls::server::ServerConfig`].
+    ///
+    /// Client authentication is disabled and all protocol versions are supported.
+    pub fn try_from_key_and_cert_chain(
+        private_key: PKey<Private>,
+        cert_chain: Vec<X509>,
+    ) -> Result<Self, OpaqueError> {
+        if cert_chain.is_empty() {
+            return Err(OpaqueError::from_display(
+                "boring/TlsAcceptorData: empty server cert chain",
+            ));
+        }
+        Ok(Self::from_cert_source_kind(TlsCertSourceKind::InMemory {
+            private_key,
+            cert_chain,
+        }))
+    }
+
+    /// Create a new [`TlsAcceptorData`] which issues server certificates on the fly,
+    /// using an already parsed CA certificate and its private key.
+    ///
+    /// Client authentication is disabled and all protocol versions are supported.
+    pub fn from_ca_key_and_cert(ca_key: PKey<Private>, ca_cert: X509) -> Self {
+        Self::from_cert_source_kind(TlsCertSourceKind::InMemoryIssuer {
+            cert_cache: issued_cert_cache(0),
+            ca_key,
+            ca_cert,
+        })
+    }
+
+    fn from_cert_source_kind(kind: TlsCertSourceKind) -> Self {
+        Self {
+            config: Arc::new(TlsConfig {
+                cert_source: TlsCertSource { kind },
+                alpn_protocols: None,
+                keylog_intent: KeyLogIntent::default(),
+                protocol_versions: None,
+                client_cert_chain: None,
+            }),
+        }
+    }
+
+    /// Set the ALPN protocols supported by the service's inner application service.
+    pub fn with_alpn_protocols(mut self, protocols: &[ApplicationProtocol]) -> Self {
+        self.set_alpn_protocols(protocols);
+        self
+    }
+
+    /// Set the ALPN protocols supported by the service's inner application service.
+    pub fn set_alpn_protocols(&mut self, protocols: &[ApplicationProtocol]) -> &mut Self {
+        Arc::make_mut(&mut self.config).alpn_protocols = Some(protocols.to_vec());
+        self
+    }
+
+    /// Returns the expiry information of the configured server certificate chain,
+    /// or of the issuing CA certificate in case certificates are issued on the fly,
+    /// e.g. to be registered in a [`CertExpiryMonitor`].
+    ///
+    /// [`CertExpiryMonitor`]: rama_net::tls::server::CertExpiryMonitor
+    pub fn cert_expiries(&self) -> Result<Vec<CertExpiry>, OpaqueError> {
+        match &self.config.cert_source.kind {
+            TlsCertSourceKind::InMemory { cert_chain, .. }
+            | TlsCertSourceKind::InMemorySigner { cert_chain, .. } => cert_chain
+                .iter()
+                .enumerate()
+                .map(|(i, cert)| x509_cert_expiry(cert, i > 0))
+                .collect(),
+            TlsCertSourceKind::InMemoryIssuer { ca_cert, .. } => {
+                Ok(vec![x509_cert_expiry(ca_cert, true)?])
+            }
+        }
+    }
+}
+
 impl TryFrom<rama_net::tls::server::ServerConfig> for TlsAcceptorData {
     type Error = OpaqueError;
 
@@ -197,23 +276,7 @@ impl TryFrom<rama_net::tls::server::ServerConfig> for TlsAcceptorData {
             }
             ServerAuth::Single(data) => {
                 // server TLS Certs
-                let cert_chain = match data.cert_chain {
-                    DataEncoding::Der(raw_data) => vec![X509::from_der(&raw_data[..]).context(
-                        "boring/TlsAcceptorData: parse x509 server cert from DER content",
-                    )?],
-                    DataEncoding::DerStack(raw_data_list) => raw_data_list
-                        .into_iter()
-                        .map(|raw_data| {
-                            X509::from_der(&raw_data[..]).context(
-                                "boring/TlsAcceptorData: parse x509 server cert from DER content",
-                            )
-                        })
-                        .collect::<Result<Vec<_>, _>>()?,
-                    DataEncoding::Pem(raw_data) => X509::stack_from_pem(raw_data.as_bytes())
-                        .context(
-                            "boring/TlsAcceptorData: parse x509 server cert chain from PEM content",
-                        )?,
-                };
+                let cert_chain = parse_cert_chain(data.cert_chain)?;
 
                 // server TLS key
                 let private_key = match data.private_key {
@@ -235,19 +298,18 @@ impl TryFrom<rama_net::tls::server::ServerConfig> for TlsAcceptorData {
                 }
             }
 
+            ServerAuth::Signer(data) => TlsCertSourceKind::InMemorySigner {
+                signer: data.signer,
+                cert_chain: parse_cert_chain(data.cert_chain)?,
+            },
+
             ServerAuth::CertIssuer(data) => {
-                let cert_cache = Cache::builder()
-                    .time_to_live(Duration::from_secs(60 * 60 * 24 * 89))
-                    .max_capacity(if data.max_cache_size == 0 {
-                        8096
-                    } else {
-                        data.max_cache_size
-                    })
-                    .build();
+                let cert_cache = issued_cert_cache(data.max_cache_size);
+                let ca_storage = data.ca_storage;
 
                 match data.kind {
                     ServerCertIssuerKind::SelfSigned(data) => {
-                        let (ca_cert, ca_key) = self_signed_server_ca(data)
+                        let (ca_cert, ca_key) = load_or_generate_ca(data, &ca_storage)
                             .context("boring/TlsAcceptorData: CA: self-signed ca")?;
                         TlsCertSourceKind::InMemoryIssuer {
                             cert_cache,
@@ -325,6 +387,51 @@ impl TryFrom<rama_net::tls::server::ServerConfig> for TlsAcceptorData {
     }
 }
 
+fn parse_cert_chain(cert_chain: DataEncoding) -> Result<Vec<X509>, OpaqueError> {
+    Ok(match cert_chain {
+        DataEncoding::Der(raw_data) => vec![X509::from_der(&raw_data[..])
+            .context("boring/TlsAcceptorData: parse x509 server cert from DER content")?],
+        DataEncoding::DerStack(raw_data_list) => raw_data_list
+            .into_iter()
+            .map(|raw_data| {
+                X509::from_der(&raw_data[..])
+                    .context("boring/TlsAcceptorData: parse x509 server cert from DER content")
+            })
+            .collect::<Result<Vec<_>, _>>()?,
+        DataEncoding::Pem(raw_data) => X509::stack_from_pem(raw_data.as_bytes())
+            .context("boring/TlsAcceptorData: parse x509 server cert chain from PEM content")?,
+    })
+}
+
+fn set_cert_chain(
+    builder: &mut SslAcceptorBuilder,
+    cert_chain: &[X509],
+) -> Result<(), OpaqueError> {
+    for (i, ca_cert) in cert_chain.iter().enumerate() {
+        if i == 0 {
+            builder
+                .set_certificate(ca_cert.as_ref())
+                .context("build boring ssl acceptor: set Leaf CA certificate (x509)")?;
+        } else {
+            builder
+                .add_extra_chain_cert(ca_cert.clone())
+                .context("build boring ssl acceptor: add extra chain certificate (x509)")?;
+        }
+    }
+    Ok(())
+}
+
+fn issued_cert_cache(max_cache_size: u64) -> Cache<Host, IssuedCert> {
+    Cache::builder()
+        .time_to_live(Duration::from_secs(60 * 60 * 24 * 89))
+        .max_capacity(if max_cache_size == 0 {
+            8096
+        } else {
+            max_cache_size
+        })
+        .build()
+}
+
 fn issue_cert_for_ca(
     host: Host,
     ca_cert: &X509,
@@ -334,28 +441,53 @@ fn issue_cert_for_ca(
         %host,
         "generate certs for host using in-memory ca cert"
     );
-    let (cert, key) = self_signed_server_auth_gen_cert(
-        &SelfSignedData {
-            organisation_name: Some(
-                ca_cert
-                    .subject_name()
-                    .entries_by_nid(Nid::ORGANIZATIONNAME)
-                    .next()
-                    .and_then(|entry| entry.data().as_utf8().ok())
-                    .map(|s| s.to_string())
-                    .unwrap_or_else(|| "Anonymous".to_owned()),
-            ),
-            common_name: Some(host.clone()),
-            subject_alternative_names: None,
-        },
-        ca_cert,
-        ca_key,
-    )
+    let (cert, key) = SelfSignedGenerator::new(SelfSignedData {
+        organisation_name: Some(
+            ca_cert
+                .subject_name()
+                .entries_by_nid(Nid::ORGANIZATIONNAME)
+                .next()
+                .and_then(|entry| entry.data().as_utf8().ok())
+                .map(|s| s.to_string())
+                .unwrap_or_else(|| "Anonymous".to_owned()),
+        ),
+        common_name: Some(host.clone()),
+        subject_alternative_names: None,
+    })
+    .generate_cert(ca_cert, ca_key)
     .with_context(|| format!("issue certs in memory for: {host:?}"))?;
 
     Ok(IssuedCert { cert, key })
 }
 
+fn x509_cert_expiry(cert: &X509Ref, is_ca: bool) -> Result<CertExpiry, OpaqueError> {
+    let name = cert
+        .subject_name()
+        .entries_by_nid(Nid::COMMONNAME)
+        .next()
+        .and_then(|entry| entry.data().as_utf8().ok())
+        .map(|s| s.to_string())
+        .unwrap_or_else(|| "Anonymous".to_owned());
+
+    let now = SystemTime::now();
+    let today = Asn1Time::days_from_now(0).context("x509 cert expiry: create ASN1Time for now")?;
+    let diff = today
+        .diff(cert.not_after())
+        .context("x509 cert expiry: compute time until not after")?;
+    let secs = i64::from(diff.days) * 24 * 60 * 60 + i64::from(diff.secs);
+    let not_after = if secs >= 0 {
+        now + Duration::from_secs(secs as u64)
+    } else {
+        now - Duration::from_secs(secs.unsigned_abs())
+    };
+
+    Ok(if is_ca {
+        CertExpiry::new_ca(name, not_after)
+    } else {
+        CertExpiry::new(name, not_after)
+    })
+}
+
 fn add_issued_cert_to_ssl_ref(
     host: Host,
     issued_cert: IssuedCert,
@@ -385,243 +517,10 @@ fn add_issued_cert_to_ssl_ref(
 fn self_signed_server_auth(
     data: SelfSignedData,
 ) -> Result<(Vec<X509>, PKey<Private>), OpaqueError> {
-    let (ca_cert, ca_privkey) = self_signed_server_auth_gen_ca(&data).context("self-signed CA")?;
-    let (cert, privkey) = self_signed_server_auth_gen_cert(&data, &ca_cert, &ca_privkey)
+    let generator = SelfSignedGenerator::new(data);
+    let (ca_cert, ca_privkey) = generator.generate_ca().context("self-signed CA")?;
+    let (cert, privkey) = generator
+        .generate_cert(&ca_cert, &ca_privkey)
         .context("self-signed cert using self-signed CA")?;
     Ok((vec![cert, ca_cert], privkey))
 }
-
-#[inline]
-fn self_signed_server_ca(data: SelfSignedData) -> Result<(X509, PKey<Private>), OpaqueError> {
-    self_signed_server_auth_gen_ca(&data)
-}
-
-fn self_signed_server_auth_gen_cert(
-    data: &SelfSignedData,
-    ca_cert: &X509,
-    ca_privkey: &PKey<Private>,
-) -> Result<(X509, PKey<Private>), OpaqueError> {
-    let rsa = Rsa::generate(4096).context("generate 4096 RSA key")?;
-    let privkey = PKey::from_rsa(rsa).context("create private key from 4096 RSA key")?;
-
-    let common_name = data
-        .common_name
-        .clone()
-        .unwrap_or(Host::Name(Domain::from_static("localhost")));
-
-    let mut x509_name = X509NameBuilder::new().context("create x509 name builder")?;
-    x509_name
-        .append_entry_by_nid(
-            Nid::ORGANIZATIONNAME,
-            data.organisation_name.as_deref().unwrap_or("Anonymous"),
-        )
-        .context("append organisation name to x509 name builder")?;
-    for subject_alt_name in data.subject_alternative_names.iter().flatten() {
-        x509_name
-            .append_entry_by_nid(Nid::SUBJECT_ALT_NAME, subject_alt_name.as_ref())
-            .context("append subject alt name to x509 name builder")?;
-    }
-    x509_name
-        .append_entry_by_nid(Nid::COMMONNAME, common_name.to_string().as_str())
-        .context("append common name to x509 name builder")?;
-    let x509_name = x509_name.build();
-
-    let mut cert_builder = X509::builder().context("create x509 (cert) builder")?;
-    cert_builder
-        .set_version(2)
-        .context("x509 cert builder: set version = 2")?;
-    let serial_number = {
-        let mut serial = BigNum::new().context("x509 cert builder: create big num (serial")?;
-        serial
-            .rand(159, MsbOption::MAYBE_ZERO, false)
-            .context("x509 cert builder: randomise serial number (big num)")?;
-        serial
-            .to_asn1_integer()
-            .context("x509 cert builder: convert serial to ASN1 integer")?
-    };
-    cert_builder
-        .set_serial_number(&serial_number)
-        .context("x509 cert builder: set serial number")?;
-    cert_builder
-        .set_issuer_name(ca_cert.subject_name())
-        .context("x509 cert builder: set issuer name")?;
-    cert_builder
-        .set_pubkey(&privkey)
-        .context("x509 cert builder: set pub key")?;
-    cert_builder
-        .set_subject_name(&x509_name)
-        .context("x509 cert builder: set subject name")?;
-    cert_builder
-        .set_pubkey(&privkey)
-        .context("x509 cert builder: set public key using private key (ref)")?;
-    let not_before =
-        Asn1Time::days_from_now(0).context("x509 cert builder: create ASN1Time for today")?;
-    cert_builder
-        .set_not_before(&not_before)
-        .context("x509 cert builder: set not before to today")?;
-    let not_after = Asn1Time::days_from_now(90)
-        .context("x509 cert builder: create ASN1Time for 90 days in future")?;
-    cert_builder
-        .set_not_after(&not_after)
-        .context("x509 cert builder: set not after to 90 days in future")?;
-
-    cert_builder
-        .append_extension(
-            BasicConstraints::new()
-                .build()
-                .context("x509 cert builder: build basic constraints")?,
-        )
-        .context("x509 cert builder: add basic constraints as x509 extension")?;
-    cert_builder
-        .append_extension(
-            KeyUsage::new()
-                .critical()
-                .non_repudiation()
-                .digital_signature()
-                .key_encipherment()
-                .build()
-                .context("x509 cert builder: create key usage")?,
-        )
-        .context("x509 cert builder: add key usage x509 extension")?;
-
-    let mut subject_alt_name = SubjectAlternativeName::new();
-    match common_name {
-        Host::Name(domain) => {
-            subject_alt_name.dns(domain.as_str());
-        }
-        Host::Address(addr) => {
-            subject_alt_name.ip(addr.to_string().as_str());
-        }
-    }
-    let subject_alt_name = subject_alt_name
-        .build(&cert_builder.x509v3_context(Some(ca_cert), None))
-        .context("x509 cert builder: build subject alt name")?;
-
-    cert_builder
-        .append_extension(subject_alt_name)
-        .context("x509 cert builder: add subject alt name")?;
-
-    let subject_key_identifier = SubjectKeyIdentifier::new()
-        .build(&cert_builder.x509v3_context(Some(ca_cert), None))
-        .context("x509 cert builder: build subject key id")?;
-    cert_builder
-        .append_extension(subject_key_identifier)
-        .context("x509 cert builder: add subject key id x509 extension")?;
-
-    let auth_key_identifier = AuthorityKeyIdentifier::new()
-        .keyid(false)
-        .issuer(false)
-        .build(&cert_builder.x509v3_context(Some(ca_cert), None))
-        .context("x509 cert builder: build auth key id")?;
-    cert_builder
-        .append_extension(auth_key_identifier)
-        .context("x509 cert builder: set auth key id extension")?;
-
-    cert_builder
-        .sign(ca_privkey, MessageDigest::sha256())
-        .context("x509 cert builder: sign cert")?;
-
-    let cert = cert_builder.build();
-
-    Ok((cert, privkey))
-}
-
-fn self_signed_server_auth_gen_ca(
-    data: &SelfSignedData,
-) -> Result<(X509, PKey<Private>), OpaqueError> {
-    let rsa = Rsa::generate(4096).context("generate 4096 RSA key")?;
-    let privkey = PKey::from_rsa(rsa).context("create private key from 4096 RSA key")?;
-
-    let common_name = data
-        .common_name
-        .clone()
-        .unwrap_or(Host::Name(Domain::from_static("localhost")));
-
-    let mut x509_name = X509NameBuilder::new().context("create x509 name builder")?;
-    x509_name
-        .append_entry_by_nid(
-            Nid::ORGANIZATIONNAME,
-            data.organisation_name.as_deref().unwrap_or("Anonymous"),
-        )
-        .context("append organisation name to x509 name builder")?;
-    for subject_alt_name in data.subject_alternative_names.iter().flatten() {
-        x509_name
-            .append_entry_by_nid(Nid::SUBJECT_ALT_NAME, subject_alt_name.as_ref())
-            .context("append subject alt name to x509 name builder")?;
-    }
-    x509_name
-        .append_entry_by_nid(Nid::COMMONNAME, common_name.to_string().as_str())
-        .context("append common name to x509 name builder")?;
-    let x509_name = x509_name.build();
-
-    let mut ca_cert_builder = X509::builder().context("create x509 (cert) builder")?;
-    ca_cert_builder
-        .set_version(2)
-        .context("x509 cert builder: set version = 2")?;
-    let serial_number = {
-        let mut serial = BigNum::new().context("x509 cert builder: create big num (serial")?;
-        serial
-            .rand(159, MsbOption::MAYBE_ZERO, false)
-            .context("x509 cert builder: randomise serial number (big num)")?;
-        serial
-            .to_asn1_integer()
-            .context("x509 cert builder: convert serial to ASN1 integer")?
-    };
-    ca_cert_builder
-        .set_serial_number(&serial_number)
-        .context("x509 cert builder: set serial number")?;
-    ca_cert_builder
-        .set_subject_name(&x509_name)
-        .context("x509 cert builder: set subject name")?;
-    ca_cert_builder
-        .set_issuer_name(&x509_name)
-        .context("x509 cert builder: set issuer (self-signed")?;
-    ca_cert_builder
-        .set_pubkey(&privkey)
-        .context("x509 cert builder: set public key using private key (ref)")?;
-    let not_before =
-        Asn1Time::days_from_now(0).context("x509 cert builder: create ASN1Time for today")?;
-    ca_cert_builder
-        .set_not_before(&not_before)
-        .context("x509 cert builder: set not before to today")?;
-    let not_after = Asn1Time::days_from_now(90)
-        .context("x509 cert builder: create ASN1Time for 90 days in future")?;
-    ca_cert_builder
-        .set_not_after(&not_after)
-        .context("x509 cert builder: set not after to 90 days in future")?;
-
-    ca_cert_builder
-        .append_extension(
-            BasicConstraints::new()
-                .critical()
-                .ca()
-                .build()
-                .context("x509 cert builder: build basic constraints")?,
-        )
-        .context("x509 cert builder: add basic constraints as x509 extension")?;
-    ca_cert_builder
-        .append_extension(
-            KeyUsage::new()
-                .critical()
-                .key_cert_sign()
-                .crl_sign()
-                .build()
-                .context("x509 cert builder: create key usage")?,
-        )
-        .context("x509 cert builder: add key usage x509 extension")?;
-
-    let subject_key_identifier = SubjectKeyIdentifier::new()
-        .build(&ca_cert_builder.x509v3_context(None, None))
-        .context("x509 cert builder: build subject key id")?;
-    ca_cert_builder
-        .append_extension(subject_key_identifier)
-        .context("x509 cert builder: add subject key id x509 extension")?;
-
-    ca_cert_builder
-        .sign(&privkey, MessageDigest::sha256())
-        .context("x509 cert builder: sign cert")?;
-
-    let cert = ca_cert_builder.build();
-
-    Ok((cert, privkey))
-}
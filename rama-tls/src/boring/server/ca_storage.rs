@@ -0,0 +1,277 @@
+use super::SelfSignedGenerator;
+use crate::boring::dep::boring::{
+    asn1::Asn1Time,
+    hash::{hash, MessageDigest},
+    nid::Nid,
+    pkey::{PKey, Private},
+    x509::{X509Ref, X509},
+};
+use rama_core::error::{ErrorContext, OpaqueError};
+use rama_net::tls::server::{SelfSignedCaStorage, SelfSignedData};
+use std::{
+    fmt::Write as _,
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+/// Minimum remaining validity (in days) of a persisted CA for it to be reused.
+const MIN_REMAINING_VALIDITY_DAYS: u32 = 1;
+
+/// Load the self-signed CA persisted in the given storage,
+/// or generate (and persist) a new one in case there is none that can be used.
+///
+/// A persisted CA is only replaced in case it is missing, (about to be) expired
+/// or issued for another subject. Any other error (e.g. a CA which cannot be read
+/// or parsed) is returned, such that a CA which might be trusted is never overwritten.
+///
+/// Failing to persist the CA is not fatal, as the generated CA can still be used,
+/// even though it will not survive a restart.
+pub(super) fn load_or_generate_ca(
+    data: SelfSignedData,
+    storage: &SelfSignedCaStorage,
+) -> Result<(X509, PKey<Private>), OpaqueError> {
+    let generator = SelfSignedGenerator::new(data);
+    let Some(dir) = storage.dir() else {
+        return generator.generate_ca();
+    };
+    let (cert_path, key_path) = ca_file_paths(&dir, &generator)?;
+
+    match load_ca(&cert_path, &key_path, &generator)? {
+        StoredCa::Usable(cert, key) => {
+            tracing::debug!(path = %cert_path.display(), "reuse persisted self-signed CA");
+            return Ok((cert, key));
+        }
+        StoredCa::Replace(reason) => {
+            tracing::debug!(
+                path = %cert_path.display(),
+                %reason,
+                "persisted self-signed CA cannot be used: generate a new one",
+            );
+        }
+    }
+
+    let (cert, key) = generator.generate_ca()?;
+    if let Err(err) = store_ca(&dir, &cert_path, &key_path, &cert, &key) {
+        tracing::warn!(
+            dir = %dir.display(),
+            error = %err,
+            "failed to persist self-signed CA: a new CA will be generated on restart",
+        );
+    }
+    Ok((cert, key))
+}
+
+/// The paths of the CA cert and key within the storage dir.
+///
+/// The file names are derived from the subject of the CA, such that
+/// issuers with a different subject do not overwrite each other's CA.
+fn ca_file_paths(
+    dir: &Path,
+    generator: &SelfSignedGenerator,
+) -> Result<(PathBuf, PathBuf), OpaqueError> {
+    let subject = format!(
+        "O={}\nCN={}",
+        generator.organisation_name(),
+        generator.common_name()
+    );
+    let digest = hash(MessageDigest::sha256(), subject.as_bytes())
+        .context("hash subject of self-signed CA")?;
+    let key = digest.iter().take(8).fold(String::new(), |mut key, b| {
+        let _ = write!(key, "{b:02x}");
+        key
+    });
+    Ok((
+        dir.join(format!("mitm-ca-{key}.crt.pem")),
+        dir.join(format!("mitm-ca-{key}.key.pem")),
+    ))
+}
+
+/// A CA found in storage.
+enum StoredCa {
+    /// The CA can be (re)used.
+    Usable(X509, PKey<Private>),
+    /// The CA has to be replaced by a new one, for the given reason.
+    Replace(&'static str),
+}
+
+/// Load the persisted CA.
+fn load_ca(
+    cert_path: &Path,
+    key_path: &Path,
+    generator: &SelfSignedGenerator,
+) -> Result<StoredCa, OpaqueError> {
+    let (cert_pem, key_pem) = match (fs::read(cert_path), fs::read(key_path)) {
+        (Ok(cert_pem), Ok(key_pem)) => (cert_pem, key_pem),
+        (Err(err), _) | (_, Err(err)) if err.kind() == io::ErrorKind::NotFound => {
+            return Ok(StoredCa::Replace("not (completely) persisted"))
+        }
+        (Err(err), _) | (_, Err(err)) => {
+            return Err(OpaqueError::from_std(err).context("read persisted CA"))
+        }
+    };
+
+    let cert = X509::from_pem(&cert_pem).context("parse persisted CA cert from PEM")?;
+    let key = PKey::private_key_from_pem(&key_pem).context("parse persisted CA key from PEM")?;
+
+    let cert_public_key = cert
+        .public_key()
+        .context("get public key of persisted CA cert")?
+        .public_key_to_der()
+        .context("encode public key of persisted CA cert as DER")?;
+    let public_key = key
+        .public_key_to_der()
+        .context("encode public key of persisted CA key as DER")?;
+    if cert_public_key != public_key {
+        return Err(OpaqueError::from_display(
+            "persisted CA key does not match its cert",
+        ));
+    }
+
+    let min_not_after = Asn1Time::days_from_now(MIN_REMAINING_VALIDITY_DAYS)
+        .context("create ASN1Time for minimum remaining validity")?;
+    let diff = min_not_after
+        .diff(cert.not_after())
+        .context("compute remaining validity of persisted CA cert")?;
+    if diff.days < 0 || (diff.days == 0 && diff.secs < 0) {
+        return Ok(StoredCa::Replace("expired (or about to expire)"));
+    }
+
+    let common_name = generator.common_name().to_string();
+    if subject_entry(&cert, Nid::ORGANIZATIONNAME).as_deref() != Some(generator.organisation_name())
+        || subject_entry(&cert, Nid::COMMONNAME).as_deref() != Some(common_name.as_str())
+    {
+        return Ok(StoredCa::Replace("issued for another subject"));
+    }
+
+    Ok(StoredCa::Usable(cert, key))
+}
+
+fn subject_entry(cert: &X509Ref, nid: Nid) -> Option<String> {
+    cert.subject_name()
+        .entries_by_nid(nid)
+        .next()
+        .and_then(|entry| entry.data().as_utf8().ok())
+        .map(|s| s.to_string())
+}
+
+fn store_ca(
+    dir: &Path,
+    cert_path: &Path,
+    key_path: &Path,
+    cert: &X509,
+    key: &PKey<Private>,
+) -> Result<(), OpaqueError> {
+    fs::create_dir_all(dir).context("create CA storage dir")?;
+
+    let cert_pem = cert.to_pem().context("encode CA cert as PEM")?;
+    let key_pem = key
+        .private_key_to_pem_pkcs8()
+        .context("encode CA key as PEM")?;
+
+    // the key is written first, such that an interrupted store
+    // leaves no cert behind without a matching key
+    write_file_atomic(key_path, &key_pem, true).context("write CA key")?;
+    write_file_atomic(cert_path, &cert_pem, false).context("write CA cert")?;
+
+    tracing::info!(path = %cert_path.display(), "persisted self-signed CA");
+    Ok(())
+}
+
+/// Write the file by renaming a temporary file, such that the file is never partially written.
+fn write_file_atomic(path: &Path, data: &[u8], private: bool) -> io::Result<()> {
+    let mut tmp_path = PathBuf::from(path);
+    tmp_path.set_extension("pem.tmp");
+
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    if private {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    #[cfg(not(unix))]
+    let _ = private;
+
+    let mut file = options.open(&tmp_path)?;
+    file.write_all(data)?;
+    file.sync_all()?;
+    drop(file);
+
+    fs::rename(&tmp_path, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn data(organisation_name: &str) -> SelfSignedData {
+        SelfSignedData {
+            organisation_name: Some(organisation_name.to_owned()),
+            ..Default::default()
+        }
+    }
+
+    fn paths(dir: &Path, data: SelfSignedData) -> (PathBuf, PathBuf) {
+        ca_file_paths(dir, &SelfSignedGenerator::new(data)).unwrap()
+    }
+
+    #[test]
+    fn test_load_or_generate_ca_persisted() {
+        let dir = tempfile::tempdir().unwrap();
+        let ca_dir = dir.path().join("ca");
+        let storage = SelfSignedCaStorage::Dir(ca_dir.clone());
+        let (cert_path, key_path) = paths(&ca_dir, data("a"));
+
+        let (cert, _) = load_or_generate_ca(data("a"), &storage).unwrap();
+        let (reused_cert, _) = load_or_generate_ca(data("a"), &storage).unwrap();
+        assert_eq!(cert.to_der().unwrap(), reused_cert.to_der().unwrap());
+
+        // issuers with another subject use their own CA
+        let (other_cert, _) = load_or_generate_ca(data("b"), &storage).unwrap();
+        assert_ne!(cert.to_der().unwrap(), other_cert.to_der().unwrap());
+        let (reused_cert, _) = load_or_generate_ca(data("a"), &storage).unwrap();
+        assert_eq!(cert.to_der().unwrap(), reused_cert.to_der().unwrap());
+
+        // a partially persisted CA is replaced
+        fs::remove_file(&key_path).unwrap();
+        let (new_cert, _) = load_or_generate_ca(data("a"), &storage).unwrap();
+        assert_ne!(cert.to_der().unwrap(), new_cert.to_der().unwrap());
+
+        // while a corrupted one is an error
+        fs::write(&cert_path, b"garbage").unwrap();
+        assert!(load_or_generate_ca(data("a"), &storage).is_err());
+        assert_eq!(fs::read(&cert_path).unwrap(), b"garbage");
+    }
+
+    #[test]
+    fn test_load_or_generate_ca_subject_mismatch() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = SelfSignedCaStorage::Dir(dir.path().to_owned());
+
+        let (cert, _) = load_or_generate_ca(data("a"), &storage).unwrap();
+
+        // move the CA of "a" to the files of "b"
+        let (cert_path_a, key_path_a) = paths(dir.path(), data("a"));
+        let (cert_path_b, key_path_b) = paths(dir.path(), data("b"));
+        fs::rename(cert_path_a, &cert_path_b).unwrap();
+        fs::rename(key_path_a, key_path_b).unwrap();
+
+        let (new_cert, _) = load_or_generate_ca(data("b"), &storage).unwrap();
+        assert_ne!(cert.to_der().unwrap(), new_cert.to_der().unwrap());
+        assert_eq!(
+            subject_entry(&new_cert, Nid::ORGANIZATIONNAME).as_deref(),
+            Some("b")
+        );
+        assert_eq!(fs::read(cert_path_b).unwrap(), new_cert.to_pem().unwrap());
+    }
+
+    #[test]
+    fn test_load_or_generate_ca_disabled() {
+        let (a, _) =
+            load_or_generate_ca(SelfSignedData::default(), &SelfSignedCaStorage::Disabled).unwrap();
+        let (b, _) =
+            load_or_generate_ca(SelfSignedData::default(), &SelfSignedCaStorage::Disabled).unwrap();
+        assert_ne!(a.to_der().unwrap(), b.to_der().unwrap());
+    }
+}
@@ -0,0 +1,64 @@
+use crate::boring::dep::boring::ssl::{
+    PrivateKeyMethod, PrivateKeyMethodError, SslRef, SslSignatureAlgorithm,
+};
+use rama_net::tls::server::KeySigner;
+use rama_net::tls::SignatureScheme;
+use std::sync::Arc;
+
+/// A boring [`PrivateKeyMethod`] which delegates all signing to a [`KeySigner`],
+/// such that the private key itself never has to be loaded.
+pub(super) struct KeySignerMethod(pub(super) Arc<dyn KeySigner>);
+
+impl PrivateKeyMethod for KeySignerMethod {
+    fn sign(
+        &self,
+        _ssl: &mut SslRef,
+        input: &[u8],
+        signature_algorithm: SslSignatureAlgorithm,
+        output: &mut [u8],
+    ) -> Result<usize, PrivateKeyMethodError> {
+        let scheme = SignatureScheme::try_from(signature_algorithm).map_err(|_| {
+            tracing::error!("boring: key signer: unknown signature algorithm requested");
+            PrivateKeyMethodError::FAILURE
+        })?;
+        if !self.0.signature_schemes().contains(&scheme) {
+            tracing::error!(?scheme, "boring: key signer: unsupported signature scheme");
+            return Err(PrivateKeyMethodError::FAILURE);
+        }
+
+        let signature = self.0.sign(scheme, input).map_err(|err| {
+            tracing::error!(error = %err, ?scheme, "boring: key signer: sign failed");
+            PrivateKeyMethodError::FAILURE
+        })?;
+        let output = output.get_mut(..signature.len()).ok_or_else(|| {
+            tracing::error!(
+                len = signature.len(),
+                "boring: key signer: signature exceeds max signature length"
+            );
+            PrivateKeyMethodError::FAILURE
+        })?;
+        output.copy_from_slice(&signature);
+        Ok(signature.len())
+    }
+
+    fn decrypt(
+        &self,
+        _ssl: &mut SslRef,
+        _input: &[u8],
+        _output: &mut [u8],
+    ) -> Result<usize, PrivateKeyMethodError> {
+        // only used by (non-forward secret) RSA key exchange, which is not supported
+        tracing::error!("boring: key signer: decrypt is not supported");
+        Err(PrivateKeyMethodError::FAILURE)
+    }
+
+    fn complete(
+        &self,
+        _ssl: &mut SslRef,
+        _output: &mut [u8],
+    ) -> Result<usize, PrivateKeyMethodError> {
+        // sign never returns `PrivateKeyMethodError::RETRY`,
+        // so there is never an operation pending completion
+        Err(PrivateKeyMethodError::FAILURE)
+    }
+}
@@ -10,10 +10,17 @@
 //!   Spawns a mini handmade http server, as well as a TLS termination proxy, forwarding the
 //!   plain text stream to the first.
 
+mod ca_storage;
+mod key_signer;
+
 mod acceptor_data;
 #[doc(inline)]
 pub use acceptor_data::TlsAcceptorData;
 
+mod self_signed;
+#[doc(inline)]
+pub use self_signed::{SelfSignedGenerator, SelfSignedKeyType, DEFAULT_SELF_SIGNED_VALIDITY};
+
 mod service;
 #[doc(inline)]
 pub use service::TlsAcceptorService;
@@ -0,0 +1,370 @@
+use crate::boring::dep::boring::{
+    asn1::Asn1Time,
+    bn::{BigNum, MsbOption},
+    ec::{EcGroup, EcKey},
+    hash::MessageDigest,
+    nid::Nid,
+    pkey::{PKey, Private},
+    rsa::Rsa,
+    x509::{
+        extension::{
+            AuthorityKeyIdentifier, BasicConstraints, KeyUsage, SubjectAlternativeName,
+            SubjectKeyIdentifier,
+        },
+        X509Name, X509NameBuilder, X509,
+    },
+};
+use rama_core::error::{ErrorContext, OpaqueError};
+use rama_net::{
+    address::{Domain, Host},
+    tls::server::{SelfSignedData, SubjectAltName},
+};
+use std::{
+    fmt,
+    str::FromStr,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// The default validity of generated certificates: 90 days.
+pub const DEFAULT_SELF_SIGNED_VALIDITY: Duration = Duration::from_secs(60 * 60 * 24 * 90);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+/// The type of private key generated by the [`SelfSignedGenerator`].
+pub enum SelfSignedKeyType {
+    /// RSA key of 2048 bits
+    Rsa2048,
+    #[default]
+    /// RSA key of 4096 bits
+    Rsa4096,
+    /// ECDSA key using the P-256 (prime256v1) curve
+    EcdsaP256,
+    /// ECDSA key using the P-384 (secp384r1) curve
+    EcdsaP384,
+}
+
+impl SelfSignedKeyType {
+    fn is_rsa(self) -> bool {
+        matches!(self, Self::Rsa2048 | Self::Rsa4096)
+    }
+
+    fn generate(self) -> Result<PKey<Private>, OpaqueError> {
+        match self {
+            Self::Rsa2048 | Self::Rsa4096 => {
+                let bits = if self == Self::Rsa2048 { 2048 } else { 4096 };
+                let rsa =
+                    Rsa::generate(bits).with_context(|| format!("generate {bits} RSA key"))?;
+                PKey::from_rsa(rsa)
+                    .with_context(|| format!("create private key from {bits} RSA key"))
+            }
+            Self::EcdsaP256 | Self::EcdsaP384 => {
+                let nid = if self == Self::EcdsaP256 {
+                    Nid::X9_62_PRIME256V1
+                } else {
+                    Nid::SECP384R1
+                };
+                let group = EcGroup::from_curve_name(nid).context("create EC group")?;
+                let ec_key = EcKey::generate(&group).context("generate EC key")?;
+                PKey::from_ec_key(ec_key).context("create private key from EC key")
+            }
+        }
+    }
+}
+
+impl fmt::Display for SelfSignedKeyType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Rsa2048 => "rsa2048",
+            Self::Rsa4096 => "rsa4096",
+            Self::EcdsaP256 => "ecdsa-p256",
+            Self::EcdsaP384 => "ecdsa-p384",
+        })
+    }
+}
+
+impl FromStr for SelfSignedKeyType {
+    type Err = OpaqueError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "rsa2048" | "rsa-2048" => Ok(Self::Rsa2048),
+            "rsa" | "rsa4096" | "rsa-4096" => Ok(Self::Rsa4096),
+            "ecdsa" | "ecdsa-p256" | "p256" | "p-256" => Ok(Self::EcdsaP256),
+            "ecdsa-p384" | "p384" | "p-384" => Ok(Self::EcdsaP384),
+            _ => Err(OpaqueError::from_display(format!(
+                "unknown self-signed key type: {s}"
+            ))),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+/// Generator of self-signed CA certificates and
+/// the (leaf) server certificates issued by such a CA.
+///
+/// Used internally by the [`TlsAcceptorData`] for self-signed [`ServerAuth`],
+/// and exposed so the same certificates can be generated ahead of time.
+///
+/// [`TlsAcceptorData`]: super::TlsAcceptorData
+/// [`ServerAuth`]: rama_net::tls::server::ServerAuth
+pub struct SelfSignedGenerator {
+    data: SelfSignedData,
+    key_type: SelfSignedKeyType,
+    validity: Duration,
+}
+
+impl SelfSignedGenerator {
+    /// Create a new [`SelfSignedGenerator`] for the given [`SelfSignedData`].
+    pub fn new(data: SelfSignedData) -> Self {
+        Self {
+            data,
+            key_type: SelfSignedKeyType::default(),
+            validity: DEFAULT_SELF_SIGNED_VALIDITY,
+        }
+    }
+
+    /// Define the [`SelfSignedKeyType`] of the generated private keys.
+    ///
+    /// Defaults to [`SelfSignedKeyType::Rsa4096`].
+    pub fn key_type(mut self, key_type: SelfSignedKeyType) -> Self {
+        self.key_type = key_type;
+        self
+    }
+
+    /// Define the [`SelfSignedKeyType`] of the generated private keys.
+    ///
+    /// Defaults to [`SelfSignedKeyType::Rsa4096`].
+    pub fn set_key_type(&mut self, key_type: SelfSignedKeyType) -> &mut Self {
+        self.key_type = key_type;
+        self
+    }
+
+    /// Define how long generated certificates remain valid, starting from now.
+    ///
+    /// Defaults to [`DEFAULT_SELF_SIGNED_VALIDITY`].
+    pub fn validity(mut self, validity: Duration) -> Self {
+        self.validity = validity;
+        self
+    }
+
+    /// Define how long generated certificates remain valid, starting from now.
+    ///
+    /// Defaults to [`DEFAULT_SELF_SIGNED_VALIDITY`].
+    pub fn set_validity(&mut self, validity: Duration) -> &mut Self {
+        self.validity = validity;
+        self
+    }
+
+    /// Generate a self-signed CA certificate and its private key.
+    pub fn generate_ca(&self) -> Result<(X509, PKey<Private>), OpaqueError> {
+        let privkey = self.key_type.generate()?;
+        let x509_name = self.x509_name()?;
+
+        let mut ca_cert_builder = X509::builder().context("create x509 (cert) builder")?;
+        ca_cert_builder
+            .set_version(2)
+            .context("x509 cert builder: set version = 2")?;
+        ca_cert_builder
+            .set_serial_number(&random_serial_number()?)
+            .context("x509 cert builder: set serial number")?;
+        ca_cert_builder
+            .set_subject_name(&x509_name)
+            .context("x509 cert builder: set subject name")?;
+        ca_cert_builder
+            .set_issuer_name(&x509_name)
+            .context("x509 cert builder: set issuer (self-signed")?;
+        ca_cert_builder
+            .set_pubkey(&privkey)
+            .context("x509 cert builder: set public key using private key (ref)")?;
+        let (not_before, not_after) = self.validity_period()?;
+        ca_cert_builder
+            .set_not_before(&not_before)
+            .context("x509 cert builder: set not before to today")?;
+        ca_cert_builder
+            .set_not_after(&not_after)
+            .context("x509 cert builder: set not after")?;
+
+        ca_cert_builder
+            .append_extension(
+                BasicConstraints::new()
+                    .critical()
+                    .ca()
+                    .build()
+                    .context("x509 cert builder: build basic constraints")?,
+            )
+            .context("x509 cert builder: add basic constraints as x509 extension")?;
+        ca_cert_builder
+            .append_extension(
+                KeyUsage::new()
+                    .critical()
+                    .key_cert_sign()
+                    .crl_sign()
+                    .build()
+                    .context("x509 cert builder: create key usage")?,
+            )
+            .context("x509 cert builder: add key usage x509 extension")?;
+
+        let subject_key_identifier = SubjectKeyIdentifier::new()
+            .build(&ca_cert_builder.x509v3_context(None, None))
+            .context("x509 cert builder: build subject key id")?;
+        ca_cert_builder
+            .append_extension(subject_key_identifier)
+            .context("x509 cert builder: add subject key id x509 extension")?;
+
+        ca_cert_builder
+            .sign(&privkey, MessageDigest::sha256())
+            .context("x509 cert builder: sign cert")?;
+
+        let cert = ca_cert_builder.build();
+
+        Ok((cert, privkey))
+    }
+
+    /// Generate a (leaf) server certificate and its private key,
+    /// issued by the given CA certificate and its private key.
+    pub fn generate_cert(
+        &self,
+        ca_cert: &X509,
+        ca_privkey: &PKey<Private>,
+    ) -> Result<(X509, PKey<Private>), OpaqueError> {
+        let privkey = self.key_type.generate()?;
+        let x509_name = self.x509_name()?;
+
+        let mut cert_builder = X509::builder().context("create x509 (cert) builder")?;
+        cert_builder
+            .set_version(2)
+            .context("x509 cert builder: set version = 2")?;
+        cert_builder
+            .set_serial_number(&random_serial_number()?)
+            .context("x509 cert builder: set serial number")?;
+        cert_builder
+            .set_issuer_name(ca_cert.subject_name())
+            .context("x509 cert builder: set issuer name")?;
+        cert_builder
+            .set_subject_name(&x509_name)
+            .context("x509 cert builder: set subject name")?;
+        cert_builder
+            .set_pubkey(&privkey)
+            .context("x509 cert builder: set public key using private key (ref)")?;
+        let (not_before, not_after) = self.validity_period()?;
+        cert_builder
+            .set_not_before(&not_before)
+            .context("x509 cert builder: set not before to today")?;
+        cert_builder
+            .set_not_after(&not_after)
+            .context("x509 cert builder: set not after")?;
+
+        cert_builder
+            .append_extension(
+                BasicConstraints::new()
+                    .build()
+                    .context("x509 cert builder: build basic constraints")?,
+            )
+            .context("x509 cert builder: add basic constraints as x509 extension")?;
+        let mut key_usage = KeyUsage::new();
+        key_usage.critical().non_repudiation().digital_signature();
+        if self.key_type.is_rsa() {
+            // only RSA keys can be used for key transport,
+            // ECDSA keys are limited to signatures
+            key_usage.key_encipherment();
+        }
+        cert_builder
+            .append_extension(
+                key_usage
+                    .build()
+                    .context("x509 cert builder: create key usage")?,
+            )
+            .context("x509 cert builder: add key usage x509 extension")?;
+
+        let mut subject_alt_name = SubjectAlternativeName::new();
+        for name in self.data.subject_alt_names()? {
+            match name {
+                SubjectAltName::Dns(name) => {
+                    subject_alt_name.dns(&name);
+                }
+                SubjectAltName::Ip(addr) => {
+                    subject_alt_name.ip(addr.to_string().as_str());
+                }
+            }
+        }
+        let subject_alt_name = subject_alt_name
+            .build(&cert_builder.x509v3_context(Some(ca_cert), None))
+            .context("x509 cert builder: build subject alt name")?;
+
+        cert_builder
+            .append_extension(subject_alt_name)
+            .context("x509 cert builder: add subject alt name")?;
+
+        let subject_key_identifier = SubjectKeyIdentifier::new()
+            .build(&cert_builder.x509v3_context(Some(ca_cert), None))
+            .context("x509 cert builder: build subject key id")?;
+        cert_builder
+            .append_extension(subject_key_identifier)
+            .context("x509 cert builder: add subject key id x509 extension")?;
+
+        let auth_key_identifier = AuthorityKeyIdentifier::new()
+            .keyid(false)
+            .issuer(false)
+            .build(&cert_builder.x509v3_context(Some(ca_cert), None))
+            .context("x509 cert builder: build auth key id")?;
+        cert_builder
+            .append_extension(auth_key_identifier)
+            .context("x509 cert builder: set auth key id extension")?;
+
+        cert_builder
+            .sign(ca_privkey, MessageDigest::sha256())
+            .context("x509 cert builder: sign cert")?;
+
+        let cert = cert_builder.build();
+
+        Ok((cert, privkey))
+    }
+
+    /// The common name (CN) used in the subject of the generated certificates.
+    pub(super) fn common_name(&self) -> Host {
+        self.data
+            .common_name
+            .clone()
+            .unwrap_or(Host::Name(Domain::from_static("localhost")))
+    }
+
+    /// The organisation name (O) used in the subject of the generated certificates.
+    pub(super) fn organisation_name(&self) -> &str {
+        self.data
+            .organisation_name
+            .as_deref()
+            .unwrap_or("Anonymous")
+    }
+
+    fn x509_name(&self) -> Result<X509Name, OpaqueError> {
+        let mut x509_name = X509NameBuilder::new().context("create x509 name builder")?;
+        x509_name
+            .append_entry_by_nid(Nid::ORGANIZATIONNAME, self.organisation_name())
+            .context("append organisation name to x509 name builder")?;
+        x509_name
+            .append_entry_by_nid(Nid::COMMONNAME, self.common_name().to_string().as_str())
+            .context("append common name to x509 name builder")?;
+        Ok(x509_name.build())
+    }
+
+    fn validity_period(&self) -> Result<(Asn1Time, Asn1Time), OpaqueError> {
+        let not_before =
+            Asn1Time::days_from_now(0).context("x509 cert builder: create ASN1Time for today")?;
+        let not_after = SystemTime::now()
+            .checked_add(self.validity)
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            .context("x509 cert builder: compute not after timestamp")?;
+        let not_after = Asn1Time::from_unix(not_after.as_secs() as _)
+            .context("x509 cert builder: create ASN1Time for not after")?;
+        Ok((not_before, not_after))
+    }
+}
+
+fn random_serial_number() -> Result<crate::boring::dep::boring::asn1::Asn1Integer, OpaqueError> {
+    let mut serial = BigNum::new().context("x509 cert builder: create big num (serial")?;
+    serial
+        .rand(159, MsbOption::MAYBE_ZERO, false)
+        .context("x509 cert builder: randomise serial number (big num)")?;
+    serial
+        .to_asn1_integer()
+        .context("x509 cert builder: convert serial to ASN1 integer")
+}
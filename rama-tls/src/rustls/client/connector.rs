@@ -9,7 +9,7 @@ use rama_core::{Context, Layer, Service};
 use rama_net::address::Host;
 use rama_net::client::{ConnectorService, EstablishedClientConnection};
 use rama_net::stream::Stream;
-use rama_net::tls::client::NegotiatedTlsParameters;
+use rama_net::tls::client::{NegotiatedTlsParameters, ServerVerifyOutcome, ServerVerifyPolicy};
 use rama_net::tls::ApplicationProtocol;
 use rama_net::transport::TryRefIntoTransportContext;
 use std::fmt;
@@ -21,6 +21,7 @@ use tokio::io::{AsyncRead, AsyncWrite};
 /// See [`TlsConnector`] for more information.
 pub struct TlsConnectorLayer<K = ConnectorKindAuto> {
     connector_data: Option<TlsConnectorData>,
+    server_verify_policy: Option<ServerVerifyPolicy>,
     kind: K,
 }
 
@@ -28,6 +29,7 @@ impl<K: fmt::Debug> std::fmt::Debug for TlsConnectorLayer<K> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("TlsConnectorLayer")
             .field("connector_data", &self.connector_data)
+            .field("server_verify_policy", &self.server_verify_policy)
             .field("kind", &self.kind)
             .finish()
     }
@@ -37,6 +39,7 @@ impl<K: Clone> Clone for TlsConnectorLayer<K> {
     fn clone(&self) -> Self {
         Self {
             connector_data: self.connector_data.clone(),
+            server_verify_policy: self.server_verify_policy.clone(),
             kind: self.kind.clone(),
         }
     }
@@ -63,6 +66,22 @@ impl<K> TlsConnectorLayer<K> {
         self.connector_data = Some(connector_data);
         self
     }
+
+    /// Attach a [`ServerVerifyPolicy`] to this [`TlsConnectorLayer`],
+    /// to define the server verification per destination host,
+    /// overwriting the mode defined in the [`TlsConnectorData`] for matching hosts.
+    pub fn with_server_verify_policy(mut self, policy: ServerVerifyPolicy) -> Self {
+        self.server_verify_policy = Some(policy);
+        self
+    }
+
+    /// Attach a [`ServerVerifyPolicy`] to this [`TlsConnectorLayer`],
+    /// to define the server verification per destination host,
+    /// overwriting the mode defined in the [`TlsConnectorData`] for matching hosts.
+    pub fn set_server_verify_policy(&mut self, policy: ServerVerifyPolicy) -> &mut Self {
+        self.server_verify_policy = Some(policy);
+        self
+    }
 }
 
 impl TlsConnectorLayer<ConnectorKindAuto> {
@@ -72,6 +91,7 @@ impl TlsConnectorLayer<ConnectorKindAuto> {
     pub fn auto() -> Self {
         Self {
             connector_data: None,
+            server_verify_policy: None,
             kind: ConnectorKindAuto,
         }
     }
@@ -83,6 +103,7 @@ impl TlsConnectorLayer<ConnectorKindSecure> {
     pub fn secure() -> Self {
         Self {
             connector_data: None,
+            server_verify_policy: None,
             kind: ConnectorKindSecure,
         }
     }
@@ -94,6 +115,7 @@ impl TlsConnectorLayer<ConnectorKindTunnel> {
     pub fn tunnel(host: Option<Host>) -> Self {
         Self {
             connector_data: None,
+            server_verify_policy: None,
             kind: ConnectorKindTunnel { host },
         }
     }
@@ -106,6 +128,7 @@ impl<K: Clone, S> Layer<S> for TlsConnectorLayer<K> {
         TlsConnector {
             inner,
             connector_data: self.connector_data.clone(),
+            server_verify_policy: self.server_verify_policy.clone(),
             kind: self.kind.clone(),
         }
     }
@@ -127,6 +150,7 @@ impl Default for TlsConnectorLayer<ConnectorKindAuto> {
 pub struct TlsConnector<S, K = ConnectorKindAuto> {
     inner: S,
     connector_data: Option<TlsConnectorData>,
+    server_verify_policy: Option<ServerVerifyPolicy>,
     kind: K,
 }
 
@@ -135,6 +159,7 @@ impl<S: fmt::Debug, K: fmt::Debug> fmt::Debug for TlsConnector<S, K> {
         f.debug_struct("TlsConnector")
             .field("inner", &self.inner)
             .field("connector_data", &self.connector_data)
+            .field("server_verify_policy", &self.server_verify_policy)
             .field("kind", &self.kind)
             .finish()
     }
@@ -145,6 +170,7 @@ impl<S: Clone, K: Clone> Clone for TlsConnector<S, K> {
         Self {
             inner: self.inner.clone(),
             connector_data: self.connector_data.clone(),
+            server_verify_policy: self.server_verify_policy.clone(),
             kind: self.kind.clone(),
         }
     }
@@ -156,6 +182,7 @@ impl<S, K> TlsConnector<S, K> {
         Self {
             inner,
             connector_data: None,
+            server_verify_policy: None,
             kind,
         }
     }
@@ -186,6 +213,22 @@ impl<S, K> TlsConnector<S, K> {
         self.connector_data = Some(connector_data);
         self
     }
+
+    /// Attach a [`ServerVerifyPolicy`] to this [`TlsConnector`],
+    /// to define the server verification per destination host,
+    /// overwriting the mode defined in the [`TlsConnectorData`] for matching hosts.
+    pub fn with_server_verify_policy(mut self, policy: ServerVerifyPolicy) -> Self {
+        self.server_verify_policy = Some(policy);
+        self
+    }
+
+    /// Attach a [`ServerVerifyPolicy`] to this [`TlsConnector`],
+    /// to define the server verification per destination host,
+    /// overwriting the mode defined in the [`TlsConnectorData`] for matching hosts.
+    pub fn set_server_verify_policy(&mut self, policy: ServerVerifyPolicy) -> &mut Self {
+        self.server_verify_policy = Some(policy);
+        self
+    }
 }
 
 impl<S> TlsConnector<S, ConnectorKindAuto> {
@@ -274,7 +317,8 @@ where
         );
 
         let connector_data = ctx.get().cloned();
-        let (stream, negotiated_params) = self.handshake(connector_data, server_host, conn).await?;
+        let (stream, negotiated_params, verify_outcome) =
+            self.handshake(connector_data, server_host, conn).await?;
 
         tracing::trace!(
             authority = %transport_ctx.authority,
@@ -283,6 +327,7 @@ where
         );
 
         ctx.insert(negotiated_params);
+        ctx.insert(verify_outcome);
 
         Ok(EstablishedClientConnection {
             ctx,
@@ -333,8 +378,10 @@ where
         let server_host = transport_ctx.authority.host().clone();
 
         let connector_data = ctx.get().cloned();
-        let (conn, negotiated_params) = self.handshake(connector_data, server_host, conn).await?;
+        let (conn, negotiated_params, verify_outcome) =
+            self.handshake(connector_data, server_host, conn).await?;
         ctx.insert(negotiated_params);
+        ctx.insert(verify_outcome);
 
         Ok(EstablishedClientConnection {
             ctx,
@@ -389,8 +436,10 @@ where
         };
 
         let connector_data = ctx.get().cloned();
-        let (conn, negotiated_params) = self.handshake(connector_data, server_host, conn).await?;
+        let (conn, negotiated_params, verify_outcome) =
+            self.handshake(connector_data, server_host, conn).await?;
         ctx.insert(negotiated_params);
+        ctx.insert(verify_outcome);
 
         tracing::trace!("TlsConnector(tunnel): connection secured");
         Ok(EstablishedClientConnection {
@@ -410,21 +459,45 @@ impl<S, K> TlsConnector<S, K> {
         connector_data: Option<TlsConnectorData>,
         server_host: Host,
         stream: T,
-    ) -> Result<(TlsStream<T>, NegotiatedTlsParameters), BoxError>
+    ) -> Result<(TlsStream<T>, NegotiatedTlsParameters, ServerVerifyOutcome), BoxError>
     where
         T: Stream + Unpin,
     {
-        let client_config_data = match connector_data.as_ref().or(self.connector_data.as_ref()) {
-            Some(connector_data) => connector_data.try_to_build_config()?,
-            None => TlsConnectorData::new_http_auto()?.try_to_build_config()?,
+        let connector_data = match connector_data.or_else(|| self.connector_data.clone()) {
+            Some(connector_data) => connector_data,
+            None => TlsConnectorData::new_http_auto()?,
+        };
+        let server_host = connector_data.server_name().cloned().unwrap_or(server_host);
+        let connector_data = match self
+            .server_verify_policy
+            .as_ref()
+            .and_then(|policy| policy.mode_for(&server_host))
+        {
+            Some(mode) => connector_data.with_server_verify_mode(mode),
+            None => connector_data,
         };
-        let server_name = rustls_pki_types::ServerName::try_from(
-            client_config_data.server_name.unwrap_or(server_host),
-        )?;
+        let verify_mode = connector_data.server_verify_mode();
+
+        let client_config_data = connector_data.try_to_build_config()?;
+        let server_name = rustls_pki_types::ServerName::try_from(server_host.clone())?;
 
         let connector = RustlsConnector::from(Arc::new(client_config_data.config));
 
-        let stream = connector.connect(server_name, stream).await?;
+        let stream = connector
+            .connect(server_name, stream)
+            .await
+            .inspect_err(|err| {
+                tracing::debug!(
+                    server.host = %server_host,
+                    ?verify_mode,
+                    "rustls connector: handshake failed: {err}",
+                );
+            })?;
+        tracing::debug!(
+            server.host = %server_host,
+            ?verify_mode,
+            "rustls connector: server verified",
+        );
 
         let (_, conn_data_ref) = stream.get_ref();
 
@@ -438,7 +511,14 @@ impl<S, K> TlsConnector<S, K> {
                 .map(ApplicationProtocol::from),
         };
 
-        Ok((stream, params))
+        Ok((
+            stream,
+            params,
+            ServerVerifyOutcome {
+                server_host,
+                mode: verify_mode,
+            },
+        ))
     }
 }
 
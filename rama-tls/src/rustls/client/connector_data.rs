@@ -1,14 +1,15 @@
 use crate::rustls::dep::pemfile;
 use crate::rustls::dep::pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer};
 use crate::rustls::dep::rcgen::{self, KeyPair};
-use crate::rustls::dep::rustls::client::danger::ServerCertVerifier;
 use crate::rustls::dep::rustls::RootCertStore;
 use crate::rustls::dep::rustls::{ClientConfig, SupportedProtocolVersion, ALL_VERSIONS};
 use crate::rustls::key_log::KeyLogFile;
-use crate::rustls::verify::NoServerCertVerifier;
+use crate::rustls::verify::{NoServerCertVerifier, PinnedServerCertVerifier};
 use rama_core::error::{ErrorContext, OpaqueError};
 use rama_net::address::Host;
-use rama_net::tls::client::{ClientAuth, ClientHelloExtension, ServerVerifyMode};
+use rama_net::tls::client::{
+    ClientAuth, ClientHelloExtension, ServerNameIndication, ServerVerifyMode,
+};
 use rama_net::tls::{ApplicationProtocol, DataEncoding};
 use std::io::BufReader;
 use std::sync::{Arc, OnceLock};
@@ -30,7 +31,8 @@ struct ClientConfigInput {
     client_auth: Option<(Vec<CertificateDer<'static>>, PrivateKeyDer<'static>)>,
     key_logger: Option<String>,
     alpn_protos: Option<Vec<Vec<u8>>>,
-    cert_verifier: Option<Arc<dyn ServerCertVerifier>>,
+    server_verify_mode: Option<ServerVerifyMode>,
+    enable_sni: Option<bool>,
 }
 
 impl TlsConnectorData {
@@ -122,10 +124,35 @@ impl TlsConnectorData {
             client_config.alpn_protocols = alpn_protos;
         }
 
-        if let Some(cert_verifier) = self.client_config_input.cert_verifier.clone() {
-            client_config
-                .dangerous()
-                .set_certificate_verifier(cert_verifier);
+        if let Some(enable_sni) = self.client_config_input.enable_sni {
+            trace!(enable_sni, "rustls: tls connector data: set SNI");
+            client_config.enable_sni = enable_sni;
+        }
+
+        match self
+            .client_config_input
+            .server_verify_mode
+            .unwrap_or_default()
+        {
+            ServerVerifyMode::Auto => (), // = default
+            ServerVerifyMode::Disable => {
+                trace!("rustls: tls connector data: disable server cert verification");
+                client_config
+                    .dangerous()
+                    .set_certificate_verifier(Arc::new(NoServerCertVerifier::default()));
+            }
+            ServerVerifyMode::Pinned(pin) => {
+                trace!(%pin, "rustls: tls connector data: pin server cert");
+                let verifier = PinnedServerCertVerifier::new(
+                    pin,
+                    client_config
+                        .crypto_provider()
+                        .signature_verification_algorithms,
+                );
+                client_config
+                    .dangerous()
+                    .set_certificate_verifier(Arc::new(verifier));
+            }
         }
 
         Ok(ClientConfigData {
@@ -134,6 +161,16 @@ impl TlsConnectorData {
         })
     }
 
+    /// Try to build the [`ClientConfig`] defined by this [`TlsConnectorData`].
+    ///
+    /// Useful for transports which drive the tls handshake themselves,
+    /// such as QUIC, instead of it being done by the [`TlsConnector`].
+    ///
+    /// [`TlsConnector`]: super::TlsConnector
+    pub fn try_to_build_client_config(&self) -> Result<ClientConfig, OpaqueError> {
+        self.try_to_build_config().map(|data| data.config)
+    }
+
     /// Merge `self` together with the `other`, resulting in
     /// a new [`TlsConnectorData`], where any defined properties of `other`
     /// take priority over conflicting ones in `self`.
@@ -166,11 +203,14 @@ impl TlsConnectorData {
                     .alpn_protos
                     .clone()
                     .or_else(|| self.client_config_input.alpn_protos.clone()),
-                cert_verifier: other
+                server_verify_mode: other
                     .client_config_input
-                    .cert_verifier
-                    .clone()
-                    .or_else(|| self.client_config_input.cert_verifier.clone()),
+                    .server_verify_mode
+                    .or(self.client_config_input.server_verify_mode),
+                enable_sni: other
+                    .client_config_input
+                    .enable_sni
+                    .or(self.client_config_input.enable_sni),
             }),
             server_name: other
                 .server_name
@@ -178,6 +218,25 @@ impl TlsConnectorData {
                 .or_else(|| self.server_name.clone()),
         }
     }
+
+    /// Returns a copy of this [`TlsConnectorData`],
+    /// with the [`ServerVerifyMode`] overwritten by the given mode.
+    pub(super) fn with_server_verify_mode(&self, mode: ServerVerifyMode) -> TlsConnectorData {
+        self.merge(&TlsConnectorData {
+            client_config_input: Arc::new(ClientConfigInput {
+                server_verify_mode: Some(mode),
+                ..Default::default()
+            }),
+            server_name: None,
+        })
+    }
+
+    /// Returns the [`ServerVerifyMode`] used by this [`TlsConnectorData`].
+    pub(super) fn server_verify_mode(&self) -> ServerVerifyMode {
+        self.client_config_input
+            .server_verify_mode
+            .unwrap_or_default()
+    }
 }
 
 impl TlsConnectorData {
@@ -200,7 +259,7 @@ impl TryFrom<rama_net::tls::client::ClientConfig> for TlsConnectorData {
     type Error = OpaqueError;
 
     fn try_from(value: rama_net::tls::client::ClientConfig) -> Result<Self, Self::Error> {
-        let protocol_versions = value.extensions.iter().flatten().find_map(|ext| {
+        let mut protocol_versions = value.extensions.iter().flatten().find_map(|ext| {
             if let ClientHelloExtension::SupportedVersions(versions) = ext {
                 Some(
                     versions
@@ -265,15 +324,6 @@ impl TryFrom<rama_net::tls::client::ClientConfig> for TlsConnectorData {
             }
         };
 
-        let cert_verifier: Option<Arc<dyn ServerCertVerifier>> =
-            match value.server_verify_mode.unwrap_or_default() {
-                ServerVerifyMode::Auto => None, // = default
-                ServerVerifyMode::Disable => {
-                    trace!("rustls: tls connector data: disable server cert verification");
-                    Some(Arc::new(NoServerCertVerifier::default()))
-                }
-            };
-
         let mut alpn_protos = None;
         let mut server_name = None;
 
@@ -292,6 +342,31 @@ impl TryFrom<rama_net::tls::client::ClientConfig> for TlsConnectorData {
             }
         }
 
+        // explicit properties take priority over the extensions
+        if let Some(versions) = value.protocol_versions.as_deref() {
+            protocol_versions = Some(
+                versions
+                    .iter()
+                    .filter_map(|v| (*v).try_into().ok())
+                    .collect(),
+            );
+        }
+        if let Some(alpns) = value.application_layer_protocol_negotiation.as_deref() {
+            alpn_protos = Some(alpns.iter().map(|p| p.as_bytes().to_vec()).collect());
+        }
+        let enable_sni = match value.server_name {
+            None => None,
+            Some(ServerNameIndication::Auto) => {
+                server_name = None;
+                Some(true)
+            }
+            Some(ServerNameIndication::Disable) => Some(false),
+            Some(ServerNameIndication::Custom(host)) => {
+                server_name = Some(host);
+                Some(true)
+            }
+        };
+
         // return the created client config, all good if you reach here
         Ok(TlsConnectorData {
             client_config_input: Arc::new(ClientConfigInput {
@@ -303,7 +378,8 @@ impl TryFrom<rama_net::tls::client::ClientConfig> for TlsConnectorData {
                     .unwrap_or_default()
                     .into_file_path(),
                 alpn_protos,
-                cert_verifier,
+                server_verify_mode: value.server_verify_mode,
+                enable_sni,
             }),
             server_name,
         })
@@ -343,3 +419,35 @@ fn self_signed_client_auth(
         PrivatePkcs8KeyDer::from(client_key_der.secret_pkcs8_der().to_owned()).into(),
     ))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rama_net::tls::client::{ClientAuthData, ClientConfig};
+
+    #[test]
+    fn test_client_auth_from_memory() {
+        let certified_key = rcgen::generate_simple_self_signed(vec!["client".to_owned()]).unwrap();
+        let cert_der = certified_key.cert.der().to_vec();
+
+        for auth_data in [
+            ClientAuthData::from_der([cert_der.clone()], certified_key.key_pair.serialize_der())
+                .unwrap(),
+            ClientAuthData::from_pem(
+                certified_key.cert.pem(),
+                certified_key.key_pair.serialize_pem(),
+            )
+            .unwrap(),
+        ] {
+            let data = TlsConnectorData::try_from(ClientConfig {
+                client_auth: Some(auth_data.into()),
+                ..Default::default()
+            })
+            .unwrap();
+            let cert_chain = data.client_auth_cert_chain().unwrap();
+            assert_eq!(cert_chain.len(), 1);
+            assert_eq!(cert_chain[0].as_ref(), cert_der.as_slice());
+            data.try_to_build_config().unwrap();
+        }
+    }
+}
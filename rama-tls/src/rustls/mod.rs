@@ -4,6 +4,9 @@ pub mod client;
 pub mod server;
 pub mod verify;
 
+#[cfg(feature = "test-certs")]
+pub mod test_certs;
+
 mod key_log;
 
 pub mod dep {
@@ -1,12 +1,18 @@
+use super::key_signer::KeySignerSigningKey;
 use crate::rustls::dep::pemfile;
 use crate::rustls::dep::pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer};
 use crate::rustls::dep::rcgen::{self, KeyPair};
-use crate::rustls::dep::rustls::{self, server::WebPkiClientVerifier, RootCertStore};
+use crate::rustls::dep::rustls::{
+    self,
+    server::{ClientHello, ResolvesServerCert, WebPkiClientVerifier},
+    sign::CertifiedKey,
+    RootCertStore,
+};
 use crate::rustls::key_log::KeyLogFile;
 use rama_core::error::{ErrorContext, OpaqueError};
 use rama_net::address::{Domain, Host};
-use rama_net::tls::server::{ClientVerifyMode, SelfSignedData, ServerAuth};
-use rama_net::tls::DataEncoding;
+use rama_net::tls::server::{ClientVerifyMode, SelfSignedData, ServerAuth, SubjectAltName};
+use rama_net::tls::{ApplicationProtocol, DataEncoding};
 use std::io::BufReader;
 use std::sync::Arc;
 
@@ -37,6 +43,42 @@ impl TlsAcceptorData {
     pub fn take_server_cert_chain(&mut self) -> Option<Vec<CertificateDer<'static>>> {
         self.server_cert_chain.take()
     }
+
+    /// Create a new [`TlsAcceptorData`] from an already loaded [`CertifiedKey`],
+    /// e.g. with a signing key backed by an HSM, bypassing the (PEM/DER) parsing
+    /// of the _rama_ opiniated [`rama_net::tls::server::ServerConfig`].
+    ///
+    /// Client authentication is disabled and all protocol versions are supported.
+    pub fn from_certified_key(certified_key: Arc<CertifiedKey>) -> Self {
+        let server_config =
+            rustls::ServerConfig::builder_with_protocol_versions(rustls::ALL_VERSIONS)
+                .with_no_client_auth()
+                .with_cert_resolver(Arc::new(SingleCertResolver(certified_key)));
+        server_config.into()
+    }
+
+    /// Set the ALPN protocols supported by the service's inner application service.
+    pub fn with_alpn_protocols(mut self, protocols: &[ApplicationProtocol]) -> Self {
+        self.set_alpn_protocols(protocols);
+        self
+    }
+
+    /// Set the ALPN protocols supported by the service's inner application service.
+    pub fn set_alpn_protocols(&mut self, protocols: &[ApplicationProtocol]) -> &mut Self {
+        Arc::make_mut(&mut self.server_config).alpn_protocols =
+            protocols.iter().map(|p| p.as_bytes().to_vec()).collect();
+        self
+    }
+}
+
+#[derive(Debug)]
+/// Resolves to the same [`CertifiedKey`] for every client.
+struct SingleCertResolver(Arc<CertifiedKey>);
+
+impl ResolvesServerCert for SingleCertResolver {
+    fn resolve(&self, _client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        Some(self.0.clone())
+    }
 }
 
 impl From<rustls::ServerConfig> for TlsAcceptorData {
@@ -133,23 +175,7 @@ impl TryFrom<rama_net::tls::server::ServerConfig> for TlsAcceptorData {
             }
             ServerAuth::Single(data) => {
                 // server TLS Certs
-                let cert_chain = match data.cert_chain {
-                    DataEncoding::Der(raw_data) => vec![CertificateDer::from(raw_data)],
-                    DataEncoding::DerStack(raw_data_list) => raw_data_list
-                        .into_iter()
-                        .map(CertificateDer::from)
-                        .collect(),
-                    DataEncoding::Pem(raw_data) => {
-                        let mut pem = BufReader::new(raw_data.as_bytes());
-                        let mut cert_chain = Vec::new();
-                        for cert in pemfile::certs(&mut pem) {
-                            cert_chain.push(
-                                cert.context("rustls/TlsAcceptorData: parse tls server cert")?,
-                            );
-                        }
-                        cert_chain
-                    }
-                };
+                let cert_chain = parse_cert_chain(data.cert_chain)?;
 
                 if value.expose_server_cert {
                     server_cert_chain = Some(cert_chain.clone());
@@ -186,6 +212,19 @@ impl TryFrom<rama_net::tls::server::ServerConfig> for TlsAcceptorData {
                 .context("rustls/TlsAcceptorData: build base rustls ServerConfig")?
             }
 
+            ServerAuth::Signer(data) => {
+                let cert_chain = parse_cert_chain(data.cert_chain)?;
+                if value.expose_server_cert {
+                    server_cert_chain = Some(cert_chain.clone());
+                }
+
+                // the private key is never loaded, all signing is delegated to the signer
+                let mut certified_key =
+                    CertifiedKey::new(cert_chain, Arc::new(KeySignerSigningKey(data.signer)));
+                certified_key.ocsp = data.ocsp;
+                builder.with_cert_resolver(Arc::new(SingleCertResolver(Arc::new(certified_key))))
+            }
+
             ServerAuth::CertIssuer { .. } => {
                 return Err(OpaqueError::from_display("CertIssuer not supported for Rustls (open an PR with a patch to add support for it if you want this or use boring instead)"));
             }
@@ -213,9 +252,29 @@ impl TryFrom<rama_net::tls::server::ServerConfig> for TlsAcceptorData {
     }
 }
 
+fn parse_cert_chain(cert_chain: DataEncoding) -> Result<Vec<CertificateDer<'static>>, OpaqueError> {
+    Ok(match cert_chain {
+        DataEncoding::Der(raw_data) => vec![CertificateDer::from(raw_data)],
+        DataEncoding::DerStack(raw_data_list) => raw_data_list
+            .into_iter()
+            .map(CertificateDer::from)
+            .collect(),
+        DataEncoding::Pem(raw_data) => {
+            let mut pem = BufReader::new(raw_data.as_bytes());
+            let mut cert_chain = Vec::new();
+            for cert in pemfile::certs(&mut pem) {
+                cert_chain.push(cert.context("rustls/TlsAcceptorData: parse tls server cert")?);
+            }
+            cert_chain
+        }
+    })
+}
+
 fn self_signed_server_auth(
     data: SelfSignedData,
 ) -> Result<(Vec<CertificateDer<'static>>, PrivateKeyDer<'static>), OpaqueError> {
+    let subject_alt_names = rcgen_subject_alt_names(&data)?;
+
     // Create an issuer CA cert.
     let alg = &rcgen::PKCS_ECDSA_P256_SHA256;
     let ca_key_pair = KeyPair::generate_for(alg).context("self-signed: generate ca key pair")?;
@@ -247,9 +306,9 @@ fn self_signed_server_auth(
 
     let server_key_pair =
         KeyPair::generate_for(alg).context("self-signed: create server key pair")?;
-    let mut server_ee_params =
-        rcgen::CertificateParams::new(data.subject_alternative_names.unwrap_or_default())
-            .context("self-signed: create server EE params")?;
+    let mut server_ee_params = rcgen::CertificateParams::new(Vec::new())
+        .context("self-signed: create server EE params")?;
+    server_ee_params.subject_alt_names = subject_alt_names;
     server_ee_params.is_ca = rcgen::IsCa::NoCa;
     server_ee_params.extended_key_usages = vec![rcgen::ExtendedKeyUsagePurpose::ServerAuth];
     let server_cert = server_ee_params
@@ -265,3 +324,115 @@ fn self_signed_server_auth(
         PrivatePkcs8KeyDer::from(server_key_der.secret_pkcs8_der().to_owned()).into(),
     ))
 }
+
+/// Turn the subject alternative names of the [`SelfSignedData`]
+/// into [`rcgen::SanType`] entries of the SAN extension.
+pub(crate) fn rcgen_subject_alt_names(
+    data: &SelfSignedData,
+) -> Result<Vec<rcgen::SanType>, OpaqueError> {
+    data.subject_alt_names()?
+        .into_iter()
+        .map(|name| match name {
+            SubjectAltName::Dns(name) => rcgen::Ia5String::try_from(name)
+                .map(rcgen::SanType::DnsName)
+                .context("create DNS subject alt name"),
+            SubjectAltName::Ip(addr) => Ok(rcgen::SanType::IpAddress(addr)),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_certified_key() {
+        let (cert_chain, key_der) = self_signed_server_auth(SelfSignedData::default()).unwrap();
+        let signing_key = rustls::crypto::aws_lc_rs::sign::any_supported_type(&key_der).unwrap();
+        let certified_key = Arc::new(CertifiedKey::new(cert_chain, signing_key));
+
+        let data = TlsAcceptorData::from_certified_key(certified_key)
+            .with_alpn_protocols(&[ApplicationProtocol::HTTP_2, ApplicationProtocol::HTTP_11]);
+        assert_eq!(
+            data.server_config().alpn_protocols,
+            vec![b"h2".to_vec(), b"http/1.1".to_vec()]
+        );
+        assert!(data.server_cert_chain().is_none());
+    }
+
+    #[derive(Debug)]
+    struct SoftwareSigner {
+        key: Arc<dyn rustls::sign::SigningKey>,
+        schemes: Vec<rama_net::tls::SignatureScheme>,
+        calls: std::sync::atomic::AtomicUsize,
+    }
+
+    impl rama_net::tls::server::KeySigner for SoftwareSigner {
+        fn signature_schemes(&self) -> &[rama_net::tls::SignatureScheme] {
+            &self.schemes
+        }
+
+        fn sign(
+            &self,
+            scheme: rama_net::tls::SignatureScheme,
+            message: &[u8],
+        ) -> Result<Vec<u8>, OpaqueError> {
+            self.calls.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            self.key
+                .choose_scheme(&[scheme.into()])
+                .context("unsupported scheme")?
+                .sign(message)
+                .context("sign message")
+        }
+    }
+
+    fn transfer(from: &mut rustls::Connection, to: &mut rustls::Connection) {
+        let mut buf = Vec::new();
+        while from.wants_write() {
+            from.write_tls(&mut buf).unwrap();
+        }
+        let mut rd = &buf[..];
+        while !rd.is_empty() {
+            to.read_tls(&mut rd).unwrap();
+        }
+        to.process_new_packets().unwrap();
+    }
+
+    #[test]
+    fn test_server_auth_signer_handshake() {
+        let (cert_chain, key_der) = self_signed_server_auth(SelfSignedData::default()).unwrap();
+        let signer = Arc::new(SoftwareSigner {
+            key: rustls::crypto::aws_lc_rs::sign::any_supported_type(&key_der).unwrap(),
+            schemes: vec![rama_net::tls::SignatureScheme::ECDSA_NISTP256_SHA256],
+            calls: Default::default(),
+        });
+
+        let mut root_store = RootCertStore::empty();
+        root_store.add(cert_chain[1].clone()).unwrap();
+        let data = TlsAcceptorData::try_from(rama_net::tls::server::ServerConfig::new(
+            ServerAuth::Signer(rama_net::tls::server::ServerSignerData::new(
+                signer.clone(),
+                DataEncoding::DerStack(cert_chain.iter().map(|cert| cert.to_vec()).collect()),
+            )),
+        ))
+        .unwrap();
+
+        let client_config = rustls::ClientConfig::builder()
+            .with_root_certificates(root_store)
+            .with_no_client_auth();
+        let mut client: rustls::Connection =
+            rustls::ClientConnection::new(Arc::new(client_config), "localhost".try_into().unwrap())
+                .unwrap()
+                .into();
+        let mut server: rustls::Connection =
+            rustls::ServerConnection::new(data.server_config.clone())
+                .unwrap()
+                .into();
+
+        while client.is_handshaking() || server.is_handshaking() {
+            transfer(&mut client, &mut server);
+            transfer(&mut server, &mut client);
+        }
+        assert_eq!(signer.calls.load(std::sync::atomic::Ordering::SeqCst), 1);
+    }
+}
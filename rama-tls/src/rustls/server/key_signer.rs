@@ -0,0 +1,69 @@
+use crate::rustls::dep::rustls::{
+    self,
+    sign::{Signer, SigningKey},
+};
+use rama_net::tls::server::KeySigner;
+use rama_net::tls::SignatureScheme;
+use std::sync::Arc;
+
+#[derive(Debug)]
+/// A rustls [`SigningKey`] which delegates all signing to a [`KeySigner`],
+/// such that the private key itself never has to be loaded.
+pub(super) struct KeySignerSigningKey(pub(super) Arc<dyn KeySigner>);
+
+impl SigningKey for KeySignerSigningKey {
+    fn choose_scheme(&self, offered: &[rustls::SignatureScheme]) -> Option<Box<dyn Signer>> {
+        self.0
+            .signature_schemes()
+            .iter()
+            .copied()
+            .find(|scheme| offered.contains(&(*scheme).into()))
+            .map(|scheme| {
+                Box::new(KeySignerSigner {
+                    signer: self.0.clone(),
+                    scheme,
+                }) as Box<dyn Signer>
+            })
+    }
+
+    fn algorithm(&self) -> rustls::SignatureAlgorithm {
+        match self.0.signature_schemes().first() {
+            Some(
+                SignatureScheme::RSA_PKCS1_SHA1
+                | SignatureScheme::RSA_PKCS1_SHA256
+                | SignatureScheme::RSA_PKCS1_SHA384
+                | SignatureScheme::RSA_PKCS1_SHA512
+                | SignatureScheme::RSA_PSS_SHA256
+                | SignatureScheme::RSA_PSS_SHA384
+                | SignatureScheme::RSA_PSS_SHA512,
+            ) => rustls::SignatureAlgorithm::RSA,
+            Some(
+                SignatureScheme::ECDSA_SHA1_Legacy
+                | SignatureScheme::ECDSA_NISTP256_SHA256
+                | SignatureScheme::ECDSA_NISTP384_SHA384
+                | SignatureScheme::ECDSA_NISTP521_SHA512,
+            ) => rustls::SignatureAlgorithm::ECDSA,
+            Some(SignatureScheme::ED25519) => rustls::SignatureAlgorithm::ED25519,
+            Some(SignatureScheme::ED448) => rustls::SignatureAlgorithm::ED448,
+            _ => rustls::SignatureAlgorithm::Unknown(0),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct KeySignerSigner {
+    signer: Arc<dyn KeySigner>,
+    scheme: SignatureScheme,
+}
+
+impl Signer for KeySignerSigner {
+    fn sign(&self, message: &[u8]) -> Result<Vec<u8>, rustls::Error> {
+        self.signer
+            .sign(self.scheme, message)
+            .map_err(|err| rustls::Error::General(format!("key signer: {err}")))
+    }
+
+    fn scheme(&self) -> rustls::SignatureScheme {
+        self.scheme.into()
+    }
+}
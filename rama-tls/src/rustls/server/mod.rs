@@ -21,6 +21,10 @@ mod layer;
 #[doc(inline)]
 pub use layer::TlsAcceptorLayer;
 
+mod key_signer;
+
 mod acceptor_data;
+#[cfg(feature = "test-certs")]
+pub(crate) use acceptor_data::rcgen_subject_alt_names;
 #[doc(inline)]
 pub use acceptor_data::TlsAcceptorData;
@@ -0,0 +1,261 @@
+//! Deterministic certificates, to be used as test fixtures.
+//!
+//! Certificates generated by the self-signed [`ServerAuth`] are random,
+//! which makes them unfit for golden-file tests of tls behaviour and fingerprints.
+//! The [`TestCerts`] generated here are instead derived from a seed:
+//! the same seed (and [`SelfSignedData`]) always results in the exact same
+//! (DER-encoded) keys and certificates.
+//!
+//! These certificates are meant for tests only, given that anyone
+//! who knows the seed can derive the private keys.
+//!
+//! # Example
+//!
+//! ```
+//! use rama_tls::rustls::test_certs::TestCerts;
+//! use rama_net::tls::server::{SelfSignedData, ServerAuth, ServerConfig};
+//!
+//! let data = SelfSignedData {
+//!     common_name: Some("example.com".parse().unwrap()),
+//!     subject_alternative_names: Some(vec!["example.com".to_owned()]),
+//!     ..Default::default()
+//! };
+//! let certs = TestCerts::generate(b"my-golden-test", data.clone()).unwrap();
+//! assert_eq!(certs, TestCerts::generate(b"my-golden-test", data).unwrap());
+//!
+//! let server_config = ServerConfig::new(ServerAuth::Single(certs.server_auth_data()));
+//! ```
+//!
+//! [`ServerAuth`]: rama_net::tls::server::ServerAuth
+
+use crate::rustls::dep::pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer};
+use crate::rustls::dep::rcgen::{self, KeyPair, SerialNumber};
+use crate::rustls::server::rcgen_subject_alt_names;
+use rama_core::error::{ErrorContext, OpaqueError};
+use rama_net::address::{Domain, Host};
+use rama_net::tls::client::ServerCertPin;
+use rama_net::tls::server::{SelfSignedData, ServerAuthData};
+use rama_net::tls::DataEncoding;
+use sha2::{Digest, Sha256};
+
+/// PKCS#8 (v1) prefix of an Ed25519 private key, to be followed by the 32 byte seed.
+const ED25519_PKCS8_PREFIX: [u8; 16] = [
+    0x30, 0x2e, 0x02, 0x01, 0x00, 0x30, 0x05, 0x06, 0x03, 0x2b, 0x65, 0x70, 0x04, 0x22, 0x04, 0x20,
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// A CA and server certificate (and their keys),
+/// deterministically generated from a seed.
+///
+/// See [the module docs](self) for more information.
+pub struct TestCerts {
+    ca_cert: CertificateDer<'static>,
+    ca_key: Vec<u8>,
+    server_cert: CertificateDer<'static>,
+    server_key: Vec<u8>,
+}
+
+impl TestCerts {
+    /// Generate a CA and server certificate from the given seed.
+    ///
+    /// Ed25519 keys are derived from the seed, and as Ed25519 signatures
+    /// are deterministic, so are the signed certificates. Serial numbers
+    /// are derived from the seed as well, while the validity period
+    /// is fixed (from 2024 until 2124).
+    pub fn generate(seed: &[u8], data: SelfSignedData) -> Result<Self, OpaqueError> {
+        let subject_alt_names = rcgen_subject_alt_names(&data)?;
+        let alg = &rcgen::PKCS_ED25519;
+
+        let ca_key = seeded_key(seed, b"ca-key");
+        let ca_key_pair = KeyPair::from_pkcs8_der_and_sign_algo(&ca_key.as_slice().into(), alg)
+            .context("test certs: create ca key pair")?;
+
+        let common_name = data
+            .common_name
+            .clone()
+            .unwrap_or(Host::Name(Domain::from_static("localhost")));
+
+        let mut ca_params =
+            rcgen::CertificateParams::new(Vec::new()).context("test certs: create ca params")?;
+        ca_params.distinguished_name.push(
+            rcgen::DnType::OrganizationName,
+            data.organisation_name
+                .unwrap_or_else(|| "Anonymous".to_owned()),
+        );
+        ca_params
+            .distinguished_name
+            .push(rcgen::DnType::CommonName, common_name.to_string().as_str());
+        ca_params.is_ca = rcgen::IsCa::Ca(rcgen::BasicConstraints::Unconstrained);
+        ca_params.key_usages = vec![
+            rcgen::KeyUsagePurpose::KeyCertSign,
+            rcgen::KeyUsagePurpose::DigitalSignature,
+            rcgen::KeyUsagePurpose::CrlSign,
+        ];
+        ca_params.serial_number = Some(seeded_serial(seed, b"ca-serial"));
+        set_validity(&mut ca_params);
+        let ca_cert = ca_params
+            .self_signed(&ca_key_pair)
+            .context("test certs: create ca cert")?;
+
+        let server_key = seeded_key(seed, b"server-key");
+        let server_key_pair =
+            KeyPair::from_pkcs8_der_and_sign_algo(&server_key.as_slice().into(), alg)
+                .context("test certs: create server key pair")?;
+        let mut server_params = rcgen::CertificateParams::new(Vec::new())
+            .context("test certs: create server params")?;
+        server_params.subject_alt_names = subject_alt_names;
+        server_params
+            .distinguished_name
+            .push(rcgen::DnType::CommonName, common_name.to_string().as_str());
+        server_params.is_ca = rcgen::IsCa::NoCa;
+        server_params.extended_key_usages = vec![rcgen::ExtendedKeyUsagePurpose::ServerAuth];
+        server_params.serial_number = Some(seeded_serial(seed, b"server-serial"));
+        set_validity(&mut server_params);
+        let server_cert = server_params
+            .signed_by(&server_key_pair, &ca_cert, &ca_key_pair)
+            .context("test certs: sign server cert")?;
+
+        Ok(Self {
+            ca_cert: ca_cert.into(),
+            ca_key,
+            server_cert: server_cert.into(),
+            server_key,
+        })
+    }
+
+    /// The (self-signed) CA certificate, e.g. to be trusted by a test client.
+    pub fn ca_cert(&self) -> &CertificateDer<'static> {
+        &self.ca_cert
+    }
+
+    /// The private key of the CA certificate.
+    pub fn ca_private_key(&self) -> PrivateKeyDer<'static> {
+        PrivatePkcs8KeyDer::from(self.ca_key.clone()).into()
+    }
+
+    /// The server (leaf) certificate, signed by the CA.
+    pub fn server_cert(&self) -> &CertificateDer<'static> {
+        &self.server_cert
+    }
+
+    /// The server certificate chain: the server certificate followed by the CA certificate.
+    pub fn server_cert_chain(&self) -> Vec<CertificateDer<'static>> {
+        vec![self.server_cert.clone(), self.ca_cert.clone()]
+    }
+
+    /// The private key of the server certificate.
+    pub fn server_private_key(&self) -> PrivateKeyDer<'static> {
+        PrivatePkcs8KeyDer::from(self.server_key.clone()).into()
+    }
+
+    /// The [`ServerCertPin`] of the server certificate,
+    /// e.g. to pin it in a test client.
+    pub fn server_cert_pin(&self) -> ServerCertPin {
+        ServerCertPin::from_sha256(Sha256::digest(self.server_cert.as_ref()).into())
+    }
+
+    /// The server certificate chain and private key as [`ServerAuthData`],
+    /// such that they can be used by any of the rama tls backends.
+    pub fn server_auth_data(&self) -> ServerAuthData {
+        ServerAuthData {
+            private_key: DataEncoding::Der(self.server_key.clone()),
+            cert_chain: DataEncoding::DerStack(
+                self.server_cert_chain()
+                    .into_iter()
+                    .map(|cert| cert.to_vec())
+                    .collect(),
+            ),
+            ocsp: None,
+        }
+    }
+}
+
+/// Derive 32 bytes, unique for the given seed and label.
+fn derive(seed: &[u8], label: &[u8]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(b"rama-tls-test-certs");
+    hasher.update((label.len() as u64).to_be_bytes());
+    hasher.update(label);
+    hasher.update(seed);
+    hasher.finalize().into()
+}
+
+/// Create an (Ed25519) PKCS#8 DER-encoded private key, unique for the given seed and label.
+fn seeded_key(seed: &[u8], label: &[u8]) -> Vec<u8> {
+    let mut der = ED25519_PKCS8_PREFIX.to_vec();
+    der.extend_from_slice(&derive(seed, label));
+    der
+}
+
+fn seeded_serial(seed: &[u8], label: &[u8]) -> SerialNumber {
+    let mut serial = derive(seed, label)[..16].to_vec();
+    // serial numbers have to be positive and non-zero
+    serial[0] = (serial[0] & 0x7f) | 0x01;
+    SerialNumber::from(serial)
+}
+
+fn set_validity(params: &mut rcgen::CertificateParams) {
+    params.not_before = rcgen::date_time_ymd(2024, 1, 1);
+    params.not_after = rcgen::date_time_ymd(2124, 1, 1);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_certs_are_deterministic() {
+        let data = SelfSignedData {
+            common_name: Some(Host::Name(Domain::from_static("example.com"))),
+            subject_alternative_names: Some(vec!["example.com".to_owned()]),
+            ..Default::default()
+        };
+
+        let a = TestCerts::generate(b"seed", data.clone()).unwrap();
+        let b = TestCerts::generate(b"seed", data.clone()).unwrap();
+        assert_eq!(a, b);
+        assert_eq!(a.server_cert_pin(), b.server_cert_pin());
+
+        let c = TestCerts::generate(b"other seed", data).unwrap();
+        assert_ne!(a.ca_cert(), c.ca_cert());
+        assert_ne!(a.server_cert(), c.server_cert());
+        assert_ne!(a.server_private_key(), c.server_private_key());
+        assert_ne!(a.server_cert_pin(), c.server_cert_pin());
+    }
+
+    #[test]
+    fn test_certs_subject_alt_names() {
+        use crate::rustls::dep::pki_types::ServerName;
+        use crate::rustls::dep::rustls::{client::verify_server_name, server::ParsedCertificate};
+
+        let data = SelfSignedData {
+            common_name: Some(Host::Name(Domain::from_static("example.com"))),
+            subject_alternative_names: Some(vec![
+                "*.example.com".to_owned(),
+                "127.0.0.1".to_owned(),
+                "[::1]".to_owned(),
+            ]),
+            ..Default::default()
+        };
+        let certs = TestCerts::generate(b"seed", data).unwrap();
+        let cert = ParsedCertificate::try_from(certs.server_cert()).unwrap();
+
+        for name in ["example.com", "www.example.com", "127.0.0.1", "::1"] {
+            let name = ServerName::try_from(name).unwrap();
+            verify_server_name(&cert, &name).unwrap();
+        }
+        for name in ["a.b.example.com", "example.org", "127.0.0.2"] {
+            let name = ServerName::try_from(name).unwrap();
+            assert!(verify_server_name(&cert, &name).is_err(), "{name:?}");
+        }
+    }
+
+    #[test]
+    fn test_certs_usable_as_server_config() {
+        let certs = TestCerts::generate(b"seed", SelfSignedData::default()).unwrap();
+        crate::rustls::dep::rustls::ServerConfig::builder()
+            .with_no_client_auth()
+            .with_single_cert(certs.server_cert_chain(), certs.server_private_key())
+            .unwrap();
+    }
+}
@@ -5,10 +5,14 @@
 use crate::rustls::dep::{
     pki_types::{CertificateDer, ServerName, UnixTime},
     rustls::{
+        self,
         client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
-        DigitallySignedStruct, SignatureScheme,
+        crypto::WebPkiSupportedAlgorithms,
+        CertificateError, DigitallySignedStruct, SignatureScheme,
     },
 };
+use rama_net::tls::client::ServerCertPin;
+use sha2::{Digest, Sha256};
 
 /// Cert verifier that does not verify the server certificate.
 #[derive(Debug)]
@@ -76,3 +80,63 @@ impl ServerCertVerifier for NoServerCertVerifier {
         ]
     }
 }
+
+/// Cert verifier that only accepts a server certificate matching a [`ServerCertPin`],
+/// regardless of its issuer or the (server) name(s) it is valid for.
+///
+/// The handshake signatures are still verified, proving the server owns the pinned certificate.
+#[derive(Debug)]
+pub struct PinnedServerCertVerifier {
+    pin: ServerCertPin,
+    algorithms: WebPkiSupportedAlgorithms,
+}
+
+impl PinnedServerCertVerifier {
+    /// Create a new [`PinnedServerCertVerifier`] for the given [`ServerCertPin`],
+    /// using the given algorithms to verify the handshake signatures.
+    pub fn new(pin: ServerCertPin, algorithms: WebPkiSupportedAlgorithms) -> Self {
+        Self { pin, algorithms }
+    }
+}
+
+impl ServerCertVerifier for PinnedServerCertVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let fingerprint = Sha256::digest(end_entity.as_ref());
+        if self.pin.matches(&fingerprint[..]) {
+            Ok(ServerCertVerified::assertion())
+        } else {
+            Err(rustls::Error::InvalidCertificate(
+                CertificateError::ApplicationVerificationFailure,
+            ))
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls12_signature(message, cert, dss, &self.algorithms)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls13_signature(message, cert, dss, &self.algorithms)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.algorithms.supported_schemes()
+    }
+}
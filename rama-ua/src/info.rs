@@ -1,4 +1,5 @@
 use super::parse_http_user_agent_header;
+use rama_core::context::{LogFieldSet, LogFields};
 use rama_core::error::OpaqueError;
 use rama_utils::macros::match_ignore_ascii_case_str;
 use serde::{Deserialize, Deserializer, Serialize};
@@ -161,6 +162,20 @@ impl UserAgent {
     }
 }
 
+impl LogFields for UserAgent {
+    fn log_fields(&self, fields: &mut LogFieldSet) {
+        if let Some(info) = self.info() {
+            fields.record("ua_kind", info.kind);
+        }
+        if let Some(platform) = self.platform() {
+            fields.record("ua_platform", platform);
+        }
+        fields
+            .record("http_agent", self.http_agent())
+            .record("tls_agent", self.tls_agent());
+    }
+}
+
 impl FromStr for UserAgent {
     type Err = Infallible;
 
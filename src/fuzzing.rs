@@ -0,0 +1,261 @@
+//! Entry points to fuzz the parsers of rama.
+//!
+//! Each `fuzz_*` function feeds arbitrary input to one of the parsers
+//! (and the accessors of what it parsed), such that it can be called
+//! as-is from a fuzz target, e.g. using [cargo-fuzz](https://github.com/rust-fuzz/cargo-fuzz):
+//!
+//! ```ignore
+//! #![no_main]
+//!
+//! libfuzzer_sys::fuzz_target!(|data: &[u8]| {
+//!     rama::fuzzing::fuzz_haproxy_header(data);
+//! });
+//! ```
+//!
+//! None of these functions should ever panic, whatever the input.
+//!
+//! The [`corpus`] module provides valid inputs for each of these
+//! entry points, to be used as the initial corpus of the fuzzer.
+//!
+//! This module is only available with the `fuzzing` feature enabled.
+
+use crate::{
+    cli::args::RequestArgsBuilder,
+    context::Extensions,
+    net::tls::client::parse_client_hello,
+    proxy::{
+        haproxy::protocol::{v1, HeaderResult},
+        ProxyFilterUsernameParser,
+    },
+    username::parse_username,
+};
+
+/// Parse the input as a PROXY protocol (v1 or v2) header,
+/// including the TLVs of a v2 header.
+pub fn fuzz_haproxy_header(data: &[u8]) {
+    match HeaderResult::parse(data) {
+        HeaderResult::V1(Ok(v1::Header { addresses, .. })) => {
+            let _ = format!("{addresses:?}");
+        }
+        HeaderResult::V2(Ok(header)) => {
+            let _ = header.address_family();
+            for tlv in header.tlvs() {
+                let _ = tlv.map(|tlv| tlv.to_owned());
+            }
+        }
+        HeaderResult::V1(Err(_)) | HeaderResult::V2(Err(_)) => (),
+    }
+}
+
+/// Parse the input (lossy decoded as utf-8) as a proxy username,
+/// with the proxy filter configuration encoded in its labels.
+pub fn fuzz_username_labels(data: &[u8]) {
+    let username = String::from_utf8_lossy(data);
+    let mut ext = Extensions::new();
+    let _ = parse_username(&mut ext, ProxyFilterUsernameParser::default(), username);
+}
+
+/// Parse the input as the request items of the `rama http` command,
+/// using a newline to separate the arguments.
+pub fn fuzz_request_args(data: &[u8]) {
+    let args = String::from_utf8_lossy(data);
+    let mut builder = RequestArgsBuilder::new();
+    for arg in args.split('\n') {
+        builder.parse_arg(arg.to_owned());
+    }
+    let _ = builder.build();
+}
+
+/// Parse the input as a tls client hello handshake message (without record header),
+/// and extract the fingerprint relevant data from it.
+pub fn fuzz_client_hello(data: &[u8]) {
+    let Ok(hello) = parse_client_hello(data) else {
+        return;
+    };
+    let _ = hello.cipher_suites().len();
+    let _ = hello.compression_algorithms().len();
+    for extension in hello.extensions() {
+        let _ = extension.id();
+    }
+    let _ = hello.ext_server_name().map(|host| host.to_string());
+    let _ = hello.ext_supported_groups();
+    let _ = hello.ext_ec_point_formats();
+    let _ = hello.ext_signature_algorithms();
+    let _ = hello.ext_alpn();
+    let _ = hello.supported_versions();
+}
+
+pub mod corpus {
+    //! Valid inputs for the entry points of the [`fuzzing`](super) module,
+    //! to be used as the initial corpus of a fuzzer.
+
+    use crate::proxy::haproxy::protocol::v2;
+    use std::{fs, io, path::Path};
+
+    /// Valid inputs for [`fuzz_haproxy_header`](super::fuzz_haproxy_header).
+    pub fn haproxy_headers() -> Vec<Vec<u8>> {
+        let mut corpus = vec![
+            b"PROXY TCP4 127.0.0.1 192.168.1.1 80 443\r\n".to_vec(),
+            b"PROXY TCP6 ::1 2001:db8::1 8080 443\r\n".to_vec(),
+            b"PROXY UNKNOWN\r\n".to_vec(),
+        ];
+        let addresses: v2::Addresses =
+            v2::IPv4::new([127, 0, 0, 1], [192, 168, 1, 1], 80, 443).into();
+        let header = v2::Builder::with_addresses(
+            v2::Version::Two | v2::Command::Proxy,
+            v2::Protocol::Stream,
+            addresses,
+        )
+        .write_tlv(v2::Type::Authority, b"example.com")
+        .and_then(|builder| builder.write_tlv(v2::Type::NoOp, &[42]))
+        .and_then(|builder| builder.build())
+        .expect("build valid PROXY v2 header");
+        corpus.push(header);
+        corpus
+    }
+
+    /// Valid inputs for [`fuzz_username_labels`](super::fuzz_username_labels).
+    pub fn username_labels() -> Vec<Vec<u8>> {
+        [
+            "john",
+            "john-country-us",
+            "john-residential-mobile-country-be-city-brussels",
+            "john-pool-a-pool-b-continent-europe-asn-7018",
+            "john-id-42-carrier-comcast-state-ny",
+        ]
+        .iter()
+        .map(|s| s.as_bytes().to_vec())
+        .collect()
+    }
+
+    /// Valid inputs for [`fuzz_request_args`](super::fuzz_request_args).
+    pub fn request_args() -> Vec<Vec<u8>> {
+        [
+            ":8080",
+            "example.com/path?q=1",
+            "POST\nhttps://example.com\nfoo=bar\nnum:=42\nx-id:1",
+            "PUT\nexample.com\nlist:=[1,2]\nq==search\naccept:application/json",
+        ]
+        .iter()
+        .map(|s| s.as_bytes().to_vec())
+        .collect()
+    }
+
+    /// Valid inputs for [`fuzz_client_hello`](super::fuzz_client_hello).
+    pub fn client_hellos() -> Vec<Vec<u8>> {
+        vec![
+            client_hello(&[], &[]),
+            client_hello(
+                &[0x13, 0x01, 0x13, 0x02, 0xc0, 0x2f],
+                &[
+                    // server name: example.com
+                    0x00, 0x00, 0x00, 0x10, 0x00, 0x0e, 0x00, 0x00, 0x0b, b'e', b'x', b'a', b'm',
+                    b'p', b'l', b'e', b'.', b'c', b'o', b'm',
+                    // supported groups: x25519, secp256r1
+                    0x00, 0x0a, 0x00, 0x06, 0x00, 0x04, 0x00, 0x1d, 0x00, 0x17,
+                    // alpn: h2, http/1.1
+                    0x00, 0x10, 0x00, 0x0e, 0x00, 0x0c, 0x02, b'h', b'2', 0x08, b'h', b't', b't',
+                    b'p', b'/', b'1', b'.', b'1',
+                    // supported versions: tls 1.3, tls 1.2
+                    0x00, 0x2b, 0x00, 0x05, 0x04, 0x03, 0x04, 0x03, 0x03,
+                ],
+            ),
+        ]
+    }
+
+    fn client_hello(cipher_suites: &[u8], extensions: &[u8]) -> Vec<u8> {
+        let mut hello = vec![0x03, 0x03];
+        hello.extend_from_slice(&[0; 32]); // random
+        hello.push(0); // session id
+        hello.extend_from_slice(&(cipher_suites.len() as u16).to_be_bytes());
+        hello.extend_from_slice(cipher_suites);
+        hello.extend_from_slice(&[1, 0]); // null compression
+        if !extensions.is_empty() {
+            hello.extend_from_slice(&(extensions.len() as u16).to_be_bytes());
+            hello.extend_from_slice(extensions);
+        }
+        hello
+    }
+
+    /// Write the corpus of all entry points into the given directory,
+    /// using a sub directory per entry point (e.g. `<dir>/haproxy_header/0`).
+    pub fn write_corpus(dir: impl AsRef<Path>) -> io::Result<()> {
+        let dir = dir.as_ref();
+        for (name, corpus) in [
+            ("haproxy_header", haproxy_headers()),
+            ("username_labels", username_labels()),
+            ("request_args", request_args()),
+            ("client_hello", client_hellos()),
+        ] {
+            let target_dir = dir.join(name);
+            fs::create_dir_all(&target_dir)?;
+            for (index, input) in corpus.iter().enumerate() {
+                fs::write(target_dir.join(index.to_string()), input)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_corpus_is_valid() {
+        for input in corpus::haproxy_headers() {
+            assert!(
+                matches!(
+                    HeaderResult::parse(&input),
+                    HeaderResult::V1(Ok(_)) | HeaderResult::V2(Ok(_))
+                ),
+                "{input:?}"
+            );
+        }
+        for input in corpus::username_labels() {
+            let mut ext = Extensions::new();
+            parse_username(
+                &mut ext,
+                ProxyFilterUsernameParser::default(),
+                std::str::from_utf8(&input).unwrap(),
+            )
+            .unwrap();
+        }
+        for input in corpus::request_args() {
+            let mut builder = RequestArgsBuilder::new();
+            for arg in std::str::from_utf8(&input).unwrap().split('\n') {
+                builder.parse_arg(arg.to_owned());
+            }
+            builder.build().unwrap();
+        }
+        for input in corpus::client_hellos() {
+            parse_client_hello(&input).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_fuzz_entry_points_do_not_panic() {
+        let inputs: [&[u8]; 6] = [
+            b"",
+            b"\0",
+            b"PROXY TCP4 999.0.0.1",
+            b"\r\n\r\n\0\r\nQUIT\n\x21\x11\xff\xff",
+            b"john-country-",
+            &[0x03, 0x03, 0xff],
+        ];
+        for input in inputs {
+            fuzz_haproxy_header(input);
+            fuzz_username_labels(input);
+            fuzz_request_args(input);
+            fuzz_client_hello(input);
+        }
+    }
+
+    #[test]
+    fn test_write_corpus() {
+        let dir = tempfile::tempdir().unwrap();
+        corpus::write_corpus(dir.path()).unwrap();
+        let input = std::fs::read(dir.path().join("client_hello").join("1")).unwrap();
+        assert_eq!(input, corpus::client_hellos()[1]);
+    }
+}
@@ -7,8 +7,8 @@
 pub use ::rama_http::{
     dep, header, headers, io, matcher,
     response::{self, IntoResponse, Response},
-    service, Body, BodyDataStream, BodyExtractExt, BodyLimit, HeaderMap, HeaderName, HeaderValue,
-    Method, Request, Scheme, StatusCode, Uri, Version,
+    service, sse, Body, BodyDataStream, BodyExtractExt, BodyLimit, HeaderMap, HeaderName,
+    HeaderValue, Method, Request, Scheme, StatusCode, Uri, Version,
 };
 
 pub mod layer {
@@ -27,4 +27,4 @@ pub mod layer {
 
 #[cfg(feature = "http-full")]
 #[doc(inline)]
-pub use ::rama_http_backend::{client, server};
+pub use ::rama_http_backend::{client, reverse_proxy, server, websocket};
@@ -331,5 +331,8 @@ pub use ::rama_ua as ua;
 #[cfg(feature = "cli")]
 pub mod cli;
 
+#[cfg(feature = "fuzzing")]
+pub mod fuzzing;
+
 #[doc(inline)]
 pub use ::rama_utils as utils;
@@ -17,6 +17,7 @@ async fn test_help_ip() {
     assert!(lines.contains("rama ip service"));
     assert!(lines.contains("Usage:"));
     assert!(lines.contains("Options:"));
+    assert!(lines.contains("--query"));
 }
 
 #[tokio::test]
@@ -38,3 +39,43 @@ async fn test_help_http() {
     assert!(lines.contains("rama http :3000"));
     assert!(lines.contains("Options:"));
 }
+
+#[tokio::test]
+#[ignore]
+async fn test_help_speed() {
+    let lines = utils::RamaService::run(vec!["help", "speed"]).unwrap();
+    assert!(lines.contains("rama speed test"));
+    assert!(lines.contains("Usage:"));
+    assert!(lines.contains("Arguments:"));
+    assert!(lines.contains("Options:"));
+}
+
+#[tokio::test]
+#[ignore]
+async fn test_help_cert() {
+    let lines = utils::RamaService::run(vec!["help", "cert"]).unwrap();
+    assert!(lines.contains("rama cert generator"));
+    assert!(lines.contains("Usage:"));
+    assert!(lines.contains("Options:"));
+    assert!(lines.contains("--key-type"));
+}
+
+#[tokio::test]
+#[ignore]
+async fn test_help_tcp() {
+    let lines = utils::RamaService::run(vec!["help", "tcp"]).unwrap();
+    assert!(lines.contains("rama tcp client"));
+    assert!(lines.contains("Usage:"));
+    assert!(lines.contains("Arguments:"));
+    assert!(lines.contains("Options:"));
+}
+
+#[tokio::test]
+#[ignore]
+async fn test_help_reverse_proxy() {
+    let lines = utils::RamaService::run(vec!["help", "reverse-proxy"]).unwrap();
+    assert!(lines.contains("rama reverse proxy"));
+    assert!(lines.contains("Usage:"));
+    assert!(lines.contains("Arguments:"));
+    assert!(lines.contains("Options:"));
+}